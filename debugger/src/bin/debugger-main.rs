@@ -1,10 +1,7 @@
-use std::rc::Rc;
-use std::cell::RefCell;
-
 use clap::{Arg, App};
 
 use debugger::interface::Interface;
-use dustbox::debug::Debugger;
+use dustbox::debug::{Debugger, MachineRunner};
 
 fn main() {
     let matches = App::new("dustbox-disasm")
@@ -21,8 +18,9 @@ fn main() {
         debugger.load_executable(&filename);
     }
 
-    let app = Rc::new(RefCell::new(debugger));
+    // the machine runs on its own thread, so that the gui stays responsive
+    let runner = MachineRunner::new(debugger);
 
-    let mut gui = Interface::default(app);
+    let mut gui = Interface::default(runner);
     gui.main();
 }
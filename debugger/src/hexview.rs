@@ -0,0 +1,104 @@
+use std::rc::Rc;
+use std::sync::Arc;
+
+use gtk;
+use gtk::prelude::*;
+
+use dustbox::debug::{MemoryView, BYTES_PER_ROW, parse_hex_byte};
+
+use crate::interface::Session;
+
+/// model column of the address label, followed by one column per byte and the ascii column
+const ADDRESS_COLUMN: u32 = 0;
+const ASCII_COLUMN: u32 = BYTES_PER_ROW as u32 + 1;
+
+/// hex editor bound to the emulated memory. the memory view is refreshed on the runner thread
+pub struct HexView {
+    session: Rc<Session>,
+    store: gtk::ListStore,
+}
+
+impl HexView {
+    /// sets up the columns of `tree`, with the byte cells writing edits through to memory
+    pub fn attach(tree: &gtk::TreeView, session: &Rc<Session>) -> Self {
+        let types = vec![gtk::Type::String; BYTES_PER_ROW + 2];
+        let store = gtk::ListStore::new(&types);
+        tree.set_model(Some(&store));
+
+        append_column(tree, "Address", ADDRESS_COLUMN);
+        for i in 0..BYTES_PER_ROW {
+            let column = i as u32 + 1;
+            let cell = append_column(tree, &format!("{:X}", i), column);
+            cell.set_property_editable(true);
+
+            let session = Rc::clone(session);
+            cell.connect_edited(move |_, path, text| {
+                let row = match path.get_indices().first() {
+                    Some(row) => *row as usize,
+                    None => return,
+                };
+                let value = match parse_hex_byte(text) {
+                    Some(value) => value,
+                    None => {
+                        println!("invalid hex byte: {}", text);
+                        return;
+                    }
+                };
+                let memory = Arc::clone(&session.memory);
+                session.runner.inspect(move |app| {
+                    memory.lock().unwrap().write(&mut app.machine.mmu, row * BYTES_PER_ROW + i, value);
+                });
+                session.request_views();
+            });
+        }
+        append_column(tree, "ASCII", ASCII_COLUMN);
+
+        HexView {
+            session: Rc::clone(session),
+            store,
+        }
+    }
+
+    /// redraws the view from the last refresh of the memory view
+    pub fn update_store(&self) {
+        let view = self.session.memory.lock().unwrap();
+        self.store.clear();
+        let columns: Vec<u32> = (0..=ASCII_COLUMN).collect();
+        for row in 0..view.rows() {
+            let mut values = vec![view.row_address(row)];
+            for i in row * BYTES_PER_ROW..(row + 1) * BYTES_PER_ROW {
+                values.push(byte_markup(&view, i));
+            }
+            values.push(markup_escape(&view.row_ascii(row)));
+
+            let values: Vec<&dyn ToValue> = values.iter().map(|v| v as &dyn ToValue).collect();
+            self.store.insert_with_values(None, &columns, &values);
+        }
+    }
+}
+
+/// bytes changed since the last step are highlighted
+fn byte_markup(view: &MemoryView, index: usize) -> String {
+    let v = view.byte(index);
+    if view.is_changed(index) {
+        format!("<span color=\"#cf8c0b\">{:02X}</span>", v)
+    } else {
+        format!("{:02X}", v)
+    }
+}
+
+fn append_column(tree: &gtk::TreeView, title: &str, model_column: u32) -> gtk::CellRendererText {
+    let cell = gtk::CellRendererText::new();
+    cell.set_property_family(Some("monospace"));
+    let column = gtk::TreeViewColumn::new();
+    column.set_title(title);
+    column.pack_start(&cell, true);
+    column.add_attribute(&cell, "markup", model_column as i32);
+    tree.append_column(&column);
+    cell
+}
+
+/// escapes the characters that have a meaning in pango markup
+fn markup_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
@@ -4,6 +4,8 @@ use std;
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::io::prelude::*;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::time::Duration;
 use std::thread;
 
@@ -16,24 +18,123 @@ use gdk::prelude::*;
 use gdk_pixbuf;
 use cairo;
 
-use dustbox::cpu::{CPU, R};
-use dustbox::gpu::VideoModeBlock;
-use dustbox::gpu::ColorSpace;
+use dustbox::cpu::{CPU, R, RegisterState};
+use dustbox::gpu::{ColorSpace, VideoFrame};
 use dustbox::gpu::ColorSpace::RGB;
+use dustbox::gpu::InspectImage;
 
-use dustbox::debug::Debugger;
+use dustbox::debug::{Debugger, MachineRunner, MemoryView, RunnerCommand, RunnerEvent};
+
+use crate::hexview::HexView;
+
+/// width in bytes of a rendered VGA plane
+const PLANE_WIDTH: u32 = 80;
+
+/// number of rows shown in the hex view
+const HEX_ROWS: usize = 16;
+
+/// milliseconds between polls for events from the runner thread
+const POLL_INTERVAL: u32 = 10;
+
+/// the machine state shown in the views, read on the runner thread
+struct ViewState {
+    regs: RegisterState,
+    prev_regs: RegisterState,
+    disasm: String,
+    frame: VideoFrame,
+    palette: InspectImage,
+    planes: Vec<InspectImage>,
+    text_page: String,
+}
+
+impl ViewState {
+    fn read(app: &mut Debugger) -> Self {
+        let disasm = app.disasm_n_instructions_to_text(20);
+        let gpu = app.machine.gpu();
+        let page = gpu.active_page(&app.machine.mmu);
+        let text_page = match gpu.text_page(&app.machine.mmu, page) {
+            Some(rows) => format!("page {}:\n{}", page, rows.join("\n")),
+            None => format!("mode {:02X} is not a text mode", gpu.mode.mode),
+        };
+        let state = ViewState {
+            regs: app.machine.cpu.regs.clone(),
+            prev_regs: app.prev_regs.clone(),
+            disasm,
+            frame: gpu.render_frame(&app.machine.mmu),
+            palette: gpu.render_palette(16),
+            planes: (0..4).map(|plane| gpu.render_plane(&app.machine.mmu, plane, PLANE_WIDTH)).collect(),
+            text_page,
+        };
+
+        // save previous values for next update
+        app.prev_regs = app.machine.cpu.regs.clone();
+        state
+    }
+}
+
+/// the machine running on the runner thread, and the hex view memory shared with it
+pub struct Session {
+    pub runner: MachineRunner,
+    pub memory: Arc<Mutex<MemoryView>>,
+    views: Sender<ViewState>,
+}
+
+impl Session {
+    pub fn send(&self, command: RunnerCommand) {
+        self.runner.send(command);
+    }
+
+    /// executes a debugger command, as typed in the command input
+    pub fn exec(&self, cmd: &str) {
+        self.send(RunnerCommand::Exec(cmd.to_owned()));
+    }
+
+    /// remembers the memory contents, so that the changes made by the next step are highlighted
+    pub fn mark_step(&self) {
+        let memory = Arc::clone(&self.memory);
+        self.runner.inspect(move |app| memory.lock().unwrap().mark_step(&app.machine.mmu));
+    }
+
+    /// asks the runner thread for the machine state, answered to the main loop
+    pub fn request_views(&self) {
+        let memory = Arc::clone(&self.memory);
+        let views = self.views.clone();
+        self.runner.inspect(move |app| {
+            memory.lock().unwrap().refresh(&app.machine.mmu);
+            let _ = views.send(ViewState::read(app));
+        });
+    }
+}
 
 pub struct Interface {
-    app: Rc<RefCell<Debugger>>,
+    session: Rc<Session>,
+    views: Rc<Receiver<ViewState>>,
     builder: Rc<RefCell<gtk::Builder>>,
 }
 
 impl Interface {
-    pub fn default(app: Rc<RefCell<Debugger>>) -> Self {
+    pub fn default(runner: MachineRunner) -> Self {
         gtk::init().unwrap_or_else(|_| panic!("Failed to initialize GTK."));
 
+        let (sender, views) = channel();
+        let memory = Arc::new(Mutex::new(MemoryView::new(0, 0, HEX_ROWS)));
+        {
+            // start the hex view at CS:IP
+            let memory = Arc::clone(&memory);
+            runner.inspect(move |app| {
+                let cs = app.machine.cpu.get_r16(R::CS);
+                let ip = app.machine.cpu.regs.ip;
+                memory.lock().unwrap().goto(cs, ip, &app.machine.mmu);
+            });
+        }
+
         Self {
-            app,
+            session: Rc::new(Session {
+                runner,
+                memory,
+                views: sender,
+            }),
+            views: Rc::new(views),
             builder: Rc::new(RefCell::new(gtk::Builder::new_from_string(
                 include_str!("interface.glade"),
             ))),
@@ -70,9 +171,9 @@ impl Interface {
             .borrow()
             .get_object("button_dump_memory")
             .unwrap();
-        let disasm_text: gtk::TextView = self.builder
+        let button_run_live: gtk::ToggleButton = self.builder
             .borrow()
-            .get_object("disasm_text")
+            .get_object("button_run_live")
             .unwrap();
         let input_command: gtk::Entry = self.builder
             .borrow()
@@ -80,17 +181,62 @@ impl Interface {
             .unwrap();
         input_command.set_placeholder_text(Some("Enter command (or type help)"));
 
+        let hex_address: gtk::Entry = self.builder
+            .borrow()
+            .get_object("hex_address")
+            .unwrap();
+        hex_address.set_placeholder_text(Some("Go to address (seg:off)"));
+        let hex_view: gtk::TreeView = self.builder
+            .borrow()
+            .get_object("hex_view")
+            .unwrap();
+        let hexview = HexView::attach(&hex_view, &self.session);
+
+        // last machine state read from the runner thread
+        let state: Rc<RefCell<Option<ViewState>>> = Rc::new(RefCell::new(None));
+
         let canvas: gtk::DrawingArea = self.builder
             .borrow()
             .get_object("canvas")
             .unwrap();
         {
-            let app = Rc::clone(&self.app);
+            let state = Rc::clone(&state);
             canvas.connect_draw(move |_, ctx| {
-                let app = app.borrow();
-                let frame = app.machine.gpu().render_frame(&app.machine.mmu);
-                draw_canvas(ctx, frame.data, &frame.mode);
-                ctx.paint();
+                if let Some(state) = &*state.borrow() {
+                    draw_canvas(ctx, &state.frame);
+                    ctx.paint();
+                }
+                Inhibit(false)
+            });
+        }
+
+        let palette_canvas: gtk::DrawingArea = self.builder
+            .borrow()
+            .get_object("palette_canvas")
+            .unwrap();
+        {
+            let state = Rc::clone(&state);
+            palette_canvas.connect_draw(move |_, ctx| {
+                if let Some(state) = &*state.borrow() {
+                    draw_inspect_image(ctx, &state.palette, 0., 0.);
+                }
+                Inhibit(false)
+            });
+        }
+
+        let plane_canvas: gtk::DrawingArea = self.builder
+            .borrow()
+            .get_object("plane_canvas")
+            .unwrap();
+        {
+            let state = Rc::clone(&state);
+            plane_canvas.connect_draw(move |_, ctx| {
+                if let Some(state) = &*state.borrow() {
+                    for (plane, img) in state.planes.iter().enumerate() {
+                        let x = plane as f64 * f64::from(PLANE_WIDTH + 4);
+                        draw_inspect_image(ctx, img, x, 0.);
+                    }
+                }
                 Inhibit(false)
             });
         }
@@ -100,6 +246,14 @@ impl Interface {
             .borrow()
             .get_object("file_quit")
             .unwrap();
+        let file_load_project: gtk::MenuItem = self.builder
+            .borrow()
+            .get_object("file_load_project")
+            .unwrap();
+        let file_save_project: gtk::MenuItem = self.builder
+            .borrow()
+            .get_object("file_save_project")
+            .unwrap();
         let help_about: gtk::MenuItem = self.builder
             .borrow()
             .get_object("help_about")
@@ -111,6 +265,23 @@ impl Interface {
             gtk::main_quit();
         });
 
+        {
+            let session = Rc::clone(&self.session);
+            file_load_project.connect_activate(move |_| {
+                session.exec("project load");
+
+                // update disasm, showing the loaded comments and names
+                session.request_views();
+            });
+        }
+
+        {
+            let session = Rc::clone(&self.session);
+            file_save_project.connect_activate(move |_| {
+                session.exec("project save");
+            });
+        }
+
         {
             let window = window.clone();
             help_about.connect_activate(move |_| {
@@ -128,147 +299,135 @@ impl Interface {
         }
 
         {
-            // update disasm
-            let app = Rc::clone(&self.app);
+            // polls the runner thread, updating the views with the machine state
+            let session = Rc::clone(&self.session);
+            let views = Rc::clone(&self.views);
             let builder = Rc::clone(&self.builder);
-            let text = app.borrow_mut().disasm_n_instructions_to_text(20);
-            if let Some(buffer) = disasm_text.get_buffer() {
-                buffer.set_text(text.as_str())
-            }
-
-            {
-                let mut app = app.borrow_mut();
-                update_registers(&mut app, &builder);
-                update_canvas(&builder);
-            }
+            let state = Rc::clone(&state);
+            let button_run_live = button_run_live.clone();
+            gtk::timeout_add(POLL_INTERVAL, move || {
+                while let Some(event) = session.runner.try_recv() {
+                    match event {
+                        RunnerEvent::FrameReady(frame) => {
+                            if let Some(state) = &mut *state.borrow_mut() {
+                                state.frame = frame;
+                            }
+                            update_canvas(&builder);
+                            session.request_views();
+                        }
+                        RunnerEvent::BreakpointHit(_, _) | RunnerEvent::Paused => {
+                            button_run_live.set_active(false);
+                            session.request_views();
+                        }
+                        RunnerEvent::FatalError(msg) => {
+                            println!("{}", msg);
+                            button_run_live.set_active(false);
+                            session.request_views();
+                        }
+                    }
+                }
+                while let Ok(view_state) = views.try_recv() {
+                    update_registers(&view_state, &builder);
+                    update_disasm(&view_state, &builder);
+                    update_video_views(&view_state, &builder);
+                    hexview.update_store();
+                    *state.borrow_mut() = Some(view_state);
+                    update_canvas(&builder);
+                }
+                Continue(true)
+            });
         }
+        self.session.request_views();
 
         {
-            let app = Rc::clone(&self.app);
-            let builder = Rc::clone(&self.builder);
-            let disasm_text = disasm_text.clone();
-
+            let session = Rc::clone(&self.session);
             button_step_into.connect_clicked(move |_| {
-                let mut app = app.borrow_mut();
-
-                app.machine.cpu.fatal_error = false;
-                app.exec_command("step into 1");
-
-                // update disasm
-                let text = app.disasm_n_instructions_to_text(20);
-                if let Some(buffer) = disasm_text.get_buffer() {
-                    buffer.set_text(text.as_str())
-                }
-
-                update_registers(&mut app, &builder);
-                canvas.queue_draw();
+                session.mark_step();
+                session.send(RunnerCommand::Step(1));
             });
         }
 
         {
-            let app = Rc::clone(&self.app);
-            let builder = Rc::clone(&self.builder);
-            let disasm_text = disasm_text.clone();
-
+            let session = Rc::clone(&self.session);
             button_step_over.connect_clicked(move |_| {
-                let mut app = app.borrow_mut();
-
-                app.machine.cpu.fatal_error = false;
-                app.exec_command("step over 1");
-
-                // update disasm
-                let text = app.disasm_n_instructions_to_text(20);
-                if let Some(buffer) = disasm_text.get_buffer() {
-                    buffer.set_text(text.as_str())
-                }
-
-                update_registers(&mut app, &builder);
-                update_canvas(&builder);
+                session.mark_step();
+                session.exec("step over 1");
+                session.request_views();
             });
         }
 
         {
-            let app = Rc::clone(&self.app);
-            let builder = Rc::clone(&self.builder);
-            let disasm_text = disasm_text.clone();
-
+            let session = Rc::clone(&self.session);
             button_run_to_breakpoint.connect_clicked(move |_| {
-                let mut app = app.borrow_mut();
-
-                app.machine.cpu.fatal_error = false;
-
-                // run until bp is reached or 1M instructions was executed
-                app.exec_command("step into 6_000_000");
-
-                // update disasm
-                let text = app.disasm_n_instructions_to_text(20);
-                if let Some(buffer) = disasm_text.get_buffer() {
-                    buffer.set_text(text.as_str())
-                }
-
-                update_registers(&mut app, &builder);
-                update_canvas(&builder);
+                // run until bp is reached or 6M instructions was executed
+                session.mark_step();
+                session.send(RunnerCommand::Step(6_000_000));
             });
         }
 
         {
-            let app = Rc::clone(&self.app);
-            let builder = Rc::clone(&self.builder);
-            let disasm_text = disasm_text.clone();
-
+            let session = Rc::clone(&self.session);
             button_run.connect_clicked(move |_| {
-                let mut app = app.borrow_mut();
-                app.machine.cpu.fatal_error = false;
-
                 // runs & draws 1 frame
-                app.machine.execute_frame();
-
-                // update disasm
-                let text = app.disasm_n_instructions_to_text(20);
-                if let Some(buffer) = disasm_text.get_buffer() {
-                    buffer.set_text(text.as_str())
-                }
-
-                update_registers(&mut app, &builder);
-                update_canvas(&builder);
+                session.mark_step();
+                session.runner.inspect(|app| {
+                    app.machine.cpu.fatal_error = false;
+                    app.run_frame();
+                });
+                session.request_views();
             });
         }
 
         {
-            let app = Rc::clone(&self.app);
+            let session = Rc::clone(&self.session);
             button_list_breakpoints.connect_clicked(move |_| {
-                let mut app = app.borrow_mut();
-                app.exec_command("bp list");
+                session.exec("bp list");
             });
         }
 
         {
-            let app = Rc::clone(&self.app);
+            let session = Rc::clone(&self.session);
             button_dump_memory.connect_clicked(move |_| {
-                let mut app = app.borrow_mut();
-                app.exec_command("bindump cs:0x0000 0xFFFF emu_mem.bin");
+                session.exec("bindump cs:0x0000 0xFFFF emu_mem.bin");
             });
         }
 
         {
-            let app = Rc::clone(&self.app);
-            let builder = Rc::clone(&self.builder);
+            let session = Rc::clone(&self.session);
+            hex_address.connect_activate(move |entry| {
+                let text = entry.get_text().unwrap().to_string();
+                let memory = Arc::clone(&session.memory);
+                session.runner.inspect(move |app| {
+                    match app.parse_segment_offset(&text) {
+                        Ok((segment, offset)) => memory.lock().unwrap().goto(segment, offset, &app.machine.mmu),
+                        Err(e) => println!("parse error: {}", e),
+                    }
+                });
+                session.request_views();
+            });
+        }
+
+        {
+            let session = Rc::clone(&self.session);
+            button_run_live.connect_toggled(move |button| {
+                // the runner executes one frame at a time until paused, sending each frame to the poll loop
+                if button.get_active() {
+                    session.mark_step();
+                    session.send(RunnerCommand::Run);
+                } else {
+                    session.send(RunnerCommand::Pause);
+                }
+            });
+        }
 
+        {
+            let session = Rc::clone(&self.session);
             window.connect_key_press_event(move |_, key| {
                 if let key::Return = key.get_keyval() as u32 {
                     let search_word = input_command.get_text().unwrap();
-                    let mut app = app.borrow_mut();
-                    app.exec_command(&search_word);
+                    session.exec(&search_word);
                     input_command.set_text("");
-
-                    // update disasm
-                    let text = app.disasm_n_instructions_to_text(20);
-                    if let Some(buffer) = disasm_text.get_buffer() {
-                        buffer.set_text(text.as_str())
-                    }
-
-                    update_registers(&mut app, &builder);
-                    update_canvas(&builder);
+                    session.request_views();
                 }
                 Inhibit(false)
             });
@@ -286,36 +445,56 @@ impl Interface {
 }
 
 /// render video frame to canvas `c`
-fn draw_canvas(c: &cairo::Context, buf: Vec<ColorSpace>, mode: &VideoModeBlock) {
-    if buf.is_empty() {
+fn draw_canvas(c: &cairo::Context, frame: &VideoFrame) {
+    if frame.data.is_empty() {
         // println!("draw_canvas: no buffer to draw!");
         return;
     }
 
+    let pixbuf = gdk_pixbuf::Pixbuf::new_from_mut_slice(
+        frame.data.clone(),
+        gdk_pixbuf::Colorspace::Rgb,
+        true,
+        8,
+        frame.width as i32,
+        frame.height as i32,
+        frame.stride as i32);
+    c.set_source_pixbuf(&pixbuf, 0., 0.);
+}
+
+/// draws `img` to canvas `c` at `x`, `y`
+fn draw_inspect_image(c: &cairo::Context, img: &InspectImage, x: f64, y: f64) {
+    let (width, height) = (img.width, img.height);
+    let pixbuf = colors_to_pixbuf(&img.data, width, height);
+    c.set_source_pixbuf(&pixbuf, x, y);
+    c.rectangle(x, y, f64::from(width), f64::from(height));
+    c.fill();
+}
+
+fn colors_to_pixbuf(buf: &[ColorSpace], width: u32, height: u32) -> gdk_pixbuf::Pixbuf {
     let mut bytes_buf: Vec<u8> = Vec::new();
 
     for col in buf {
-        if let RGB(r, g, b) = col {
+        if let RGB(r, g, b) = *col {
             bytes_buf.push(r);
             bytes_buf.push(g);
             bytes_buf.push(b);
         }
     }
 
-    let pixbuf = gdk_pixbuf::Pixbuf::new_from_mut_slice(
+    gdk_pixbuf::Pixbuf::new_from_mut_slice(
         bytes_buf,
         gdk_pixbuf::Colorspace::Rgb,
         false,
         8,
-        mode.swidth as i32,
-        mode.sheight as i32,
-        mode.swidth as i32 * 3);
-    c.set_source_pixbuf(&pixbuf, 0., 0.);
+        width as i32,
+        height as i32,
+        width as i32 * 3)
 }
 
-fn u16_as_register_str(app: &Debugger, r: R) -> String {
-    let v = app.machine.cpu.get_r16(r);
-    let prev = app.prev_regs.get_r16(r);
+fn u16_as_register_str(state: &ViewState, r: R) -> String {
+    let v = state.regs.get_r16(r);
+    let prev = state.prev_regs.get_r16(r);
     if v == prev {
         format!("<span font_desc=\"mono\">{:04X}</span>", v)
     } else {
@@ -334,8 +513,31 @@ fn update_canvas(builder: &Rc<RefCell<gtk::Builder>>) {
     canvas.queue_draw();
 }
 
+fn update_disasm(state: &ViewState, builder: &Rc<RefCell<gtk::Builder>>) {
+    let disasm_text: gtk::TextView = builder
+            .borrow()
+            .get_object("disasm_text")
+            .unwrap();
+    if let Some(buffer) = disasm_text.get_buffer() {
+        buffer.set_text(state.disasm.as_str())
+    }
+}
+
+fn update_video_views(state: &ViewState, builder: &Rc<RefCell<gtk::Builder>>) {
+    let builder = builder.borrow();
+    let palette_canvas: gtk::DrawingArea = builder.get_object("palette_canvas").unwrap();
+    let plane_canvas: gtk::DrawingArea = builder.get_object("plane_canvas").unwrap();
+    let text_page: gtk::TextView = builder.get_object("text_page").unwrap();
+    palette_canvas.queue_draw();
+    plane_canvas.queue_draw();
+
+    if let Some(buffer) = text_page.get_buffer() {
+        buffer.set_text(state.text_page.as_str())
+    }
+}
+
 fn update_registers(
-    app: &mut Debugger,
+    state: &ViewState,
     builder: &Rc<RefCell<gtk::Builder>>,
 ) {
     let builder = builder.borrow();
@@ -344,38 +546,38 @@ fn update_registers(
     let cx_value: gtk::Label = builder.get_object("cx_value").unwrap();
     let dx_value: gtk::Label = builder.get_object("dx_value").unwrap();
 
-    ax_value.set_markup(&u16_as_register_str(app, R::AX));
-    bx_value.set_markup(&u16_as_register_str(app, R::BX));
-    cx_value.set_markup(&u16_as_register_str(app, R::CX));
-    dx_value.set_markup(&u16_as_register_str(app, R::DX));
+    ax_value.set_markup(&u16_as_register_str(state, R::AX));
+    bx_value.set_markup(&u16_as_register_str(state, R::BX));
+    cx_value.set_markup(&u16_as_register_str(state, R::CX));
+    dx_value.set_markup(&u16_as_register_str(state, R::DX));
 
     let si_value: gtk::Label = builder.get_object("si_value").unwrap();
     let di_value: gtk::Label = builder.get_object("di_value").unwrap();
     let bp_value: gtk::Label = builder.get_object("bp_value").unwrap();
     let sp_value: gtk::Label = builder.get_object("sp_value").unwrap();
 
-    si_value.set_markup(&u16_as_register_str(app, R::SI));
-    di_value.set_markup(&u16_as_register_str(app, R::DI));
-    bp_value.set_markup(&u16_as_register_str(app, R::BP));
-    sp_value.set_markup(&u16_as_register_str(app, R::SP));
+    si_value.set_markup(&u16_as_register_str(state, R::SI));
+    di_value.set_markup(&u16_as_register_str(state, R::DI));
+    bp_value.set_markup(&u16_as_register_str(state, R::BP));
+    sp_value.set_markup(&u16_as_register_str(state, R::SP));
 
     let ds_value: gtk::Label = builder.get_object("ds_value").unwrap();
     let cs_value: gtk::Label = builder.get_object("cs_value").unwrap();
     let es_value: gtk::Label = builder.get_object("es_value").unwrap();
     let fs_value: gtk::Label = builder.get_object("fs_value").unwrap();
 
-    ds_value.set_markup(&u16_as_register_str(app, R::DS));
-    cs_value.set_markup(&u16_as_register_str(app, R::CS));
-    es_value.set_markup(&u16_as_register_str(app, R::ES));
-    fs_value.set_markup(&u16_as_register_str(app, R::FS));
+    ds_value.set_markup(&u16_as_register_str(state, R::DS));
+    cs_value.set_markup(&u16_as_register_str(state, R::CS));
+    es_value.set_markup(&u16_as_register_str(state, R::ES));
+    fs_value.set_markup(&u16_as_register_str(state, R::FS));
 
     let gs_value: gtk::Label = builder.get_object("gs_value").unwrap();
     let ss_value: gtk::Label = builder.get_object("ss_value").unwrap();
     let ip_value: gtk::Label = builder.get_object("ip_value").unwrap();
 
-    gs_value.set_markup(&u16_as_register_str(app, R::GS));
-    ss_value.set_markup(&u16_as_register_str(app, R::SS));
-    ip_value.set_markup(&u16_as_register_str(app, R::IP));
+    gs_value.set_markup(&u16_as_register_str(state, R::GS));
+    ss_value.set_markup(&u16_as_register_str(state, R::SS));
+    ip_value.set_markup(&u16_as_register_str(state, R::IP));
 
     // XXX: color changes for flag changes too
     let c_flag: gtk::CheckButton = builder.get_object("c_flag").unwrap();
@@ -387,15 +589,12 @@ fn update_registers(
     let d_flag: gtk::CheckButton = builder.get_object("d_flag").unwrap();
     let i_flag: gtk::CheckButton = builder.get_object("i_flag").unwrap();
 
-    c_flag.set_active(app.machine.cpu.regs.flags.carry);
-    z_flag.set_active(app.machine.cpu.regs.flags.zero);
-    s_flag.set_active(app.machine.cpu.regs.flags.sign);
-    o_flag.set_active(app.machine.cpu.regs.flags.overflow);
-    a_flag.set_active(app.machine.cpu.regs.flags.adjust);
-    p_flag.set_active(app.machine.cpu.regs.flags.parity);
-    d_flag.set_active(app.machine.cpu.regs.flags.direction);
-    i_flag.set_active(app.machine.cpu.regs.flags.interrupt);
-
-    // save previous values for next update
-    app.prev_regs = app.machine.cpu.regs.clone();
+    c_flag.set_active(state.regs.flags.carry());
+    z_flag.set_active(state.regs.flags.zero());
+    s_flag.set_active(state.regs.flags.sign());
+    o_flag.set_active(state.regs.flags.overflow());
+    a_flag.set_active(state.regs.flags.adjust());
+    p_flag.set_active(state.regs.flags.parity());
+    d_flag.set_active(state.regs.flags.direction);
+    i_flag.set_active(state.regs.flags.interrupt);
 }
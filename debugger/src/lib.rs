@@ -1 +1,2 @@
-pub mod interface;
\ No newline at end of file
+pub mod hexview;
+pub mod interface;
@@ -1,8 +1,12 @@
 use chrono::prelude::*;
 
 use dustbox::machine::Machine;
-use dustbox::cpu::{Decoder};
-use dustbox::debug::ProgramTracer;
+use dustbox::cpu::{Decoder, InstructionInfo, R};
+use dustbox::debug::{InterruptAnnotator, ProgramTracer};
+use dustbox::format::ExeFile;
+use dustbox::hex::hex_bytes;
+use dustbox::memory::MemoryAddress;
+use dustbox::string::{parse_number_string, right_pad};
 use dustbox::tools;
 
 use clap::{Arg, App};
@@ -17,58 +21,169 @@ fn main() {
             .arg(Arg::with_name("flat")
                 .long("flat")
                 .help("Show a flat disassembly listing (no tracing)"))
+            .arg(Arg::with_name("nasm")
+                .long("nasm")
+                .help("Output traced program as nasm source, same as --format nasm"))
+            .arg(Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["text", "json", "nasm", "lst", "dot"])
+                .help("Output format. json and lst are flat listings, dot is the call graph of the traced program"))
+            .arg(Arg::with_name("origin")
+                .long("origin")
+                .takes_value(true)
+                .help("Offset to load a .com or raw binary at (default 0x100)"))
+            .arg(Arg::with_name("start")
+                .long("start")
+                .takes_value(true)
+                .help("Offset of the first instruction of a flat listing (default entry point)"))
+            .arg(Arg::with_name("end")
+                .long("end")
+                .takes_value(true)
+                .help("Offset where a flat listing stops (default end of program)"))
+            .arg(Arg::with_name("symbols")
+                .long("symbols")
+                .takes_value(true)
+                .help("Name addresses using a .map or .toml symbols file"))
             .arg(Arg::with_name("timestamp")
                 .long("timestamp")
                 .help("Include a timestamp in the output"))
             .get_matches();
 
     let filename = matches.value_of("INPUT").unwrap();
-    println!("; Source {}", filename);
-    if matches.is_present("timestamp") {
-        // disabled by default for reproducibility
-        println!("; Generated {}", Local::now().to_rfc2822());
+    let format = match matches.value_of("format") {
+        Some(format) => format,
+        None if matches.is_present("nasm") => "nasm",
+        None => "text",
+    };
+    let offset_arg = |name| matches.value_of(name).map(|v| match parse_number_string(v) {
+        Ok(n) if n <= 0xFFFF => n as u16,
+        _ => panic!("invalid --{} {}", name, v),
+    });
+
+    // json and dot output has no comments
+    let comments = format != "json" && format != "dot";
+    if comments {
+        println!("; Source {}", filename);
+        if matches.is_present("timestamp") {
+            // disabled by default for reproducibility
+            println!("; Generated {}", Local::now().to_rfc2822());
+        }
+        println!();
     }
-    println!();
 
-    if matches.is_present("flat") {
-        flat_disassembly(filename);
-    } else {
-        trace_disassembly(filename);
+    let mut machine = load_program(filename, offset_arg("origin"), comments);
+    if let Some(symbols) = matches.value_of("symbols") {
+        match machine.load_symbols(symbols) {
+            Ok(n) if comments => println!("; {} symbols from {}", n, symbols),
+            Ok(_) => {}
+            Err(err) => panic!("failed to read {}: {}", symbols, err),
+        }
+    }
+
+    let (start, end) = (offset_arg("start"), offset_arg("end"));
+    let flat = matches.is_present("flat") || start.is_some() || end.is_some();
+    match format {
+        "json" => {
+            let records: Vec<_> = flat_instructions(&mut machine, start, end).iter().map(InstructionInfo::record).collect();
+            println!("{}", serde_json::to_string_pretty(&records).unwrap());
+        }
+        "lst" => lst_listing(&flat_instructions(&mut machine, start, end)),
+        "text" if flat => {
+            let ops = flat_instructions(&mut machine, start, end);
+            flat_disassembly(&machine, &ops);
+        }
+        "dot" => {
+            let mut tracer = ProgramTracer::default();
+            tracer.trace_execution(&mut machine);
+            print!("{}", tracer.present_call_graph(&mut machine));
+        }
+        _ => trace_disassembly(&mut machine, format == "nasm"),
     }
 }
 
-fn flat_disassembly(filename: &str) {
-    let mut machine = Machine::deterministic();
-    match tools::read_binary(filename) {
-        Ok(data) => machine.load_executable(&data, 0x085F),
+/// loads a .com or .exe program, describing the exe header if `comments` is set.
+/// a .com program or raw binary is loaded at offset `origin` of its segment, by default 0x100
+fn load_program(filename: &str, origin: Option<u16>, comments: bool) -> Machine {
+    let data = match tools::read_binary(filename) {
+        Ok(data) => data,
         Err(err) => panic!("failed to read {}: {}", filename, err),
+    };
+    let is_exe = data.len() >= 2 && data[0] == b'M' && data[1] == b'Z';
+    if is_exe && comments {
+        if let Ok(exe) = ExeFile::from_data(&data) {
+            println!("; EXE entry point {:04X}:{:04X}, stack {:04X}:{:04X}, {} relocations",
+                exe.header.cs, exe.header.ip, exe.header.ss, exe.header.sp, exe.relocs.len());
+        }
     }
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&data, 0x085F);
+    if let Some(origin) = origin {
+        if is_exe {
+            panic!("--origin only applies to .com programs and raw binaries");
+        }
+        let cs = machine.cpu.get_r16(R::CS);
+        machine.mmu.write(cs, origin, &data);
+        machine.cpu.regs.ip = origin;
+        machine.rom_base = machine.cpu.get_memory_address();
+    }
+    machine
+}
 
+/// decodes the instructions from offset `start` (default the entry point) to offset `end`
+/// (default the end of the program) in the code segment
+fn flat_instructions(machine: &mut Machine, start: Option<u16>, end: Option<u16>) -> Vec<InstructionInfo> {
     let mut decoder = Decoder::default();
     let mut ma = machine.cpu.get_memory_address();
+    if let Some(start) = start {
+        ma = MemoryAddress::RealSegmentOffset(ma.segment(), start);
+    }
+    let end = match end {
+        Some(end) => MemoryAddress::RealSegmentOffset(ma.segment(), end).value(),
+        None => machine.rom_base.value() + machine.rom_length as u32,
+    };
 
-    let mut rom_end = machine.rom_base;
-    rom_end.add_offset(machine.rom_length as u16);
-
-    println!("; starting flat disassembly at {}", ma);
-
-    loop {
+    let mut ops = Vec::new();
+    while ma.value() < end {
         let op = decoder.get_instruction_info(&mut machine.mmu, ma.segment(), ma.offset());
-        println!("{}", op);
         ma.inc_n(op.bytes.len() as u16);
-        if ma.value() >= rom_end.value() {
-            break;
+        ops.push(op);
+    }
+    ops
+}
+
+fn flat_disassembly(machine: &Machine, ops: &[InstructionInfo]) {
+    let mut annotator = InterruptAnnotator::default();
+    if let Some(op) = ops.first() {
+        println!("; starting flat disassembly at {:04X}:{:04X}", op.segment, op.offset);
+    }
+
+    for op in ops {
+        if let Some(name) = machine.symbols.name_at(((op.segment << 4) + op.offset) as u32) {
+            println!("{}:", name);
+        }
+        let text = machine.symbols.instruction_info_text(op);
+        match annotator.annotate(op) {
+            Some(note) => println!("{}; {}", right_pad(&text, 68), note),
+            None => println!("{}", text),
         }
     }
 }
 
-fn trace_disassembly(filename: &str) {
-    let mut machine = Machine::deterministic();
-    match tools::read_binary(filename) {
-        Ok(data) => machine.load_executable(&data, 0x085F),
-        Err(err) => panic!("failed to read {}: {}", filename, err),
+/// prints a listing in the format of nasm -l: line number, offset, bytes and source
+fn lst_listing(ops: &[InstructionInfo]) {
+    for (line, op) in ops.iter().enumerate() {
+        println!("{:6} {:08X} {} {}", line + 1, op.offset, right_pad(&hex_bytes(&op.bytes), 18), op.instruction);
     }
+}
+
+fn trace_disassembly(machine: &mut Machine, nasm: bool) {
+    println!("; starting tracing disassembly at {}", MemoryAddress::RealSegmentOffset(machine.cpu.get_r16(R::CS), machine.cpu.regs.ip));
     let mut tracer = ProgramTracer::default();
-    tracer.trace_execution(&mut machine);
-    println!("{}", tracer.present_trace(&mut machine));
+    tracer.trace_execution(machine);
+    if nasm {
+        println!("{}", tracer.present_nasm(machine));
+    } else {
+        println!("{}", tracer.present_trace(machine));
+    }
 }
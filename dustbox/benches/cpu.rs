@@ -20,6 +20,55 @@ fn exec_simple_loop(c: &mut Criterion) {
     c.bench_function("execute small jmp short loop", move |b| b.iter(|| machine.execute_instruction()));
 }
 
+fn exec_memory_loop(c: &mut Criterion) {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB9, 0xFF, 0xFF,               // mov cx,0xffff
+        0xBB, 0x00, 0x20,               // mov bx,0x2000
+        0x26, 0x81, 0x47, 0x10, 0x34, 0x12, // add word [es:bx+0x10],0x1234
+        0x8B, 0x47, 0x10,               // mov ax,[bx+0x10]
+        0xD1, 0xE0,                     // shl ax,1
+        0xE2, 0xF3,                     // loop 0x106
+        0xEB, 0xEB,                     // jmp short 0x100
+    ];
+
+    machine.load_executable(&code, 0x085F);
+
+    c.bench_function("execute memory access loop", move |b| b.iter(|| machine.execute_instruction()));
+}
+
+fn exec_arith_loop(c: &mut Criterion) {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB8, 0x34, 0x12,               // mov ax,0x1234
+        0xBB, 0x07, 0x00,               // mov bx,0x7
+        0xF7, 0xE3,                     // mul bx
+        0xF7, 0xF3,                     // div bx
+        0x35, 0xFF, 0x00,               // xor ax,0xff
+        0xD1, 0xC8,                     // ror ax,1
+        0xEB, 0xEF,                     // jmp short 0x100
+    ];
+
+    machine.load_executable(&code, 0x085F);
+
+    c.bench_function("execute arithmetic loop", move |b| b.iter(|| machine.execute_instruction()));
+}
+
+fn exec_string_loop(c: &mut Criterion) {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xBE, 0x00, 0x10,               // mov si,0x1000
+        0xBF, 0x00, 0x20,               // mov di,0x2000
+        0xB9, 0x00, 0x01,               // mov cx,0x100
+        0xF3, 0xA4,                     // rep movsb
+        0xEB, 0xF3,                     // jmp short 0x100
+    ];
+
+    machine.load_executable(&code, 0x085F);
+
+    c.bench_function("execute rep movsb loop", move |b| b.iter(|| machine.execute_instruction()));
+}
+
 fn disasm_small_prog(c: &mut Criterion) {
     let mut machine = Machine::deterministic();
     let code: Vec<u8> = vec![
@@ -37,5 +86,5 @@ fn disasm_small_prog(c: &mut Criterion) {
     c.bench_function("disasm small prog", move |b| b.iter(|| machine.cpu.decoder.disassemble_block_to_str(&mut machine.mmu, 0x85F, 0x100, 8)));
 }
 
-criterion_group!(benches, exec_simple_loop, disasm_small_prog);
+criterion_group!(benches, exec_simple_loop, exec_memory_loop, exec_arith_loop, exec_string_loop, disasm_small_prog);
 criterion_main!(benches);
@@ -0,0 +1,125 @@
+// instructions per second of representative workloads, and the decode and render paths
+
+#[macro_use]
+extern crate criterion;
+
+extern crate dustbox;
+
+use criterion::{Criterion, Throughput};
+
+use dustbox::cpu::Decoder;
+use dustbox::machine::Machine;
+
+/// instructions executed per iteration of the execute benchmarks
+const INSTRUCTIONS: usize = 10_000;
+
+/// instructions decoded per iteration of the decode benchmark
+const DECODED: usize = 100;
+
+/// returns a machine running `code` as a .com program, after executing its first `setup` instructions
+fn load(code: &[u8], setup: usize) -> Machine {
+    let mut machine = Machine::deterministic();
+    machine.load_executable(code, 0x085F);
+    machine.execute_instructions(setup);
+    machine
+}
+
+fn execute(c: &mut Criterion) {
+    let mut group = c.benchmark_group("execute");
+    group.throughput(Throughput::Elements(INSTRUCTIONS as u64));
+
+    let mut machine = load(&[
+        0xB8, 0x34, 0x12,               // mov ax,0x1234
+        0x01, 0xC3,                     // add bx,ax
+        0x31, 0xD8,                     // xor ax,bx
+        0x21, 0xC1,                     // and cx,ax
+        0xD1, 0xE3,                     // shl bx,1
+        0x40,                           // inc ax
+        0xEB, 0xF5,                     // jmp short 0x103
+    ], 0);
+    group.bench_function("alu loop", move |b| b.iter(|| machine.execute_instructions(INSTRUCTIONS)));
+
+    let mut machine = load(&[
+        0xBE, 0x00, 0x10,               // mov si,0x1000
+        0xBF, 0x00, 0x20,               // mov di,0x2000
+        0xB9, 0x00, 0x01,               // mov cx,0x100
+        0xAD,                           // lodsw
+        0xAB,                           // stosw
+        0xE2, 0xFC,                     // loop 0x109
+        0xEB, 0xF1,                     // jmp short 0x100
+    ], 0);
+    group.bench_function("memory copy loop", move |b| b.iter(|| machine.execute_instructions(INSTRUCTIONS)));
+
+    let mut machine = load(&[
+        0xB8, 0x13, 0x00,               // mov ax,0x13
+        0xCD, 0x10,                     // int 0x10
+        0xB8, 0x00, 0xA0,               // mov ax,0xa000
+        0x8E, 0xC0,                     // mov es,ax
+        0x31, 0xFF,                     // xor di,di
+        0x26, 0x88, 0x05,               // mov [es:di],al
+        0x47,                           // inc di
+        0xFE, 0xC0,                     // inc al
+        0xEB, 0xF8,                     // jmp short 0x10c
+    ], 6);
+    group.bench_function("mode 13h plot loop", move |b| b.iter(|| machine.execute_instructions(INSTRUCTIONS)));
+
+    let mut machine = load(&[
+        0xB8, 0x41, 0x0E,               // mov ax,0xe41
+        0xCD, 0x10,                     // int 0x10
+        0xEB, 0xF9,                     // jmp short 0x100
+    ], 0);
+    group.bench_function("teletype text output", move |b| b.iter(|| machine.execute_instructions(INSTRUCTIONS)));
+
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(DECODED as u64));
+
+    // a sequence of 4 instructions, repeated to fill the decoded block
+    let sequence = [
+        0x80, 0x3E, 0x31, 0x10, 0x00,   // cmp byte [0x1031],0x0
+        0x26, 0x81, 0x47, 0x10, 0x34, 0x12, // add word [es:bx+0x10],0x1234
+        0x66, 0x0F, 0xB7, 0xC3,         // movzx eax,bx
+        0xF3, 0xA4,                     // rep movsb
+    ];
+    let code: Vec<u8> = sequence.iter().cycle().take(sequence.len() * DECODED / 4).cloned().collect();
+    let mut machine = load(&code, 0);
+    let mut decoder = Decoder::default();
+    group.bench_function("decode block", move |b| b.iter(|| decoder.decode_to_block(&mut machine.mmu, 0x085F, 0x0100, DECODED)));
+
+    group.finish();
+}
+
+fn render(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render");
+
+    // fills the text screen through teletype output
+    let mut text = load(&[
+        0xB8, 0x41, 0x0E,               // mov ax,0xe41
+        0xCD, 0x10,                     // int 0x10
+        0xEB, 0xF9,                     // jmp short 0x100
+    ], 0);
+    text.execute_instructions(4 * 80 * 25);
+    group.bench_function("text mode frame", move |b| b.iter(|| text.gpu().render_frame(&text.mmu)));
+
+    let mut graphics = load(&[
+        0xB8, 0x13, 0x00,               // mov ax,0x13
+        0xCD, 0x10,                     // int 0x10
+        0xB8, 0x00, 0xA0,               // mov ax,0xa000
+        0x8E, 0xC0,                     // mov es,ax
+        0x31, 0xFF,                     // xor di,di
+        0x26, 0x88, 0x05,               // mov [es:di],al
+        0x47,                           // inc di
+        0xFE, 0xC0,                     // inc al
+        0xEB, 0xF8,                     // jmp short 0x10c
+    ], 6);
+    graphics.execute_instructions(4 * 320 * 200);
+    group.bench_function("mode 13h frame", move |b| b.iter(|| graphics.gpu().render_frame(&graphics.mmu)));
+
+    group.finish();
+}
+
+criterion_group!(benches, execute, decode, render);
+criterion_main!(benches);
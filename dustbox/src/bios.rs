@@ -1,16 +1,24 @@
 // https://wiki.osdev.org/BIOS
 // dosbox-x: src/hardware/bios.cpp
 
+use chrono::{NaiveTime, Timelike};
+use log::{debug, warn};
+
+use crate::cpu::{CPU, R, FLAG_CF};
+use crate::machine::Component;
 use crate::memory::{MMU, MemoryAddress};
 
 #[derive(Clone)]
 pub struct BIOS {
+    /// cycles left of the INT 15h AH=86h wait in progress
+    wait_cycles: Option<u64>,
 }
 
 impl BIOS {
     pub const DATA_SEG: u16           = 0x0040; // bios data segment, 256 byte at 000400 to 0004FF
 
     pub const DATA_INITIAL_MODE: u16  = 0x0010;
+    pub const DATA_MEMORY_SIZE: u16   = 0x0013; // word, conventional memory in KB
     pub const DATA_CURRENT_MODE: u16  = 0x0049;
     pub const DATA_NB_COLS: u16       = 0x004A;
     pub const DATA_PAGE_SIZE: u16     = 0x004C;
@@ -21,6 +29,8 @@ impl BIOS {
     pub const DATA_CRTC_ADDRESS: u16  = 0x0063;
     pub const DATA_CURRENT_MSR: u16   = 0x0065;
     pub const DATA_CURRENT_PAL: u16   = 0x0066;
+    pub const DATA_TIMER_TICKS: u16   = 0x006C; // dword, timer ticks since midnight
+    pub const DATA_MIDNIGHT: u16      = 0x0070; // byte, nonzero if midnight passed since last read
     pub const DATA_NB_ROWS: u16       = 0x0084;
     pub const DATA_CHAR_HEIGHT: u16   = 0x0085;
     pub const DATA_VIDEO_CTL: u16     = 0x0087;
@@ -32,9 +42,28 @@ impl BIOS {
 
     const ROM_SEG: u16                = 0xF000; // bios rom segment, 64k at F_0000 to F_FFFF
     const ROM_EQUIPMENT_WORD: u16     = 0x0410;
+    const ROM_CONFIG_TABLE: u16       = 0xE6F5; // System Configuration Table
+    const ROM_INT08: u16              = 0xFEA5; // INT 08 handler, `timer_tick` is called on entry
+    const ROM_CALLBACKS: u16          = 0x1000; // default interrupt handlers, CALLBACK_SIZE bytes each
+
+    /// size of the default interrupt handlers: a callback to the high level handler, and IRET
+    pub const CALLBACK_SIZE: u16      = 5;
+
+    /// callback number of `timer_tick`, the interrupts use their number
+    pub const CALLBACK_TIMER_TICK: u16 = 0x100;
+
+    /// interrupts handled by the BIOS: the timer tick and its user hook, memory size and system services
+    pub const INTERRUPTS: &[u8] = &[0x08, 0x12, 0x15, 0x1C];
+
+    /// timer ticks in 24 hours, at 18.2065 Hz
+    const TICKS_PER_DAY: u32          = 0x0018_00B0;
+
+    /// PIT input clock divided by the 65536 divisor gives the tick rate
+    const PIT_HZ: u64                 = 1_193_182;
 
     pub fn default() -> Self {
         BIOS {
+            wait_cycles: None,
         }
     }
 
@@ -43,12 +72,59 @@ impl BIOS {
         self.write_configuration_data_table(&mut mmu);
     }
 
+    /// returns the offset in the BIOS segment of the default handler of interrupt `int`
+    pub fn default_handler(int: u8) -> u16 {
+        BIOS::ROM_CALLBACKS + u16::from(int) * BIOS::CALLBACK_SIZE
+    }
+
+    /// returns the instruction invoking high level handler `number`
+    fn callback(number: u16) -> [u8; 4] {
+        [0xFE, 0x38, number as u8, (number >> 8) as u8]
+    }
+
     fn init_ivt(&mut self, mmu: &mut MMU) {
         const IRET: u8 = 0xCF;
-        for irq in 0..0xFF {
-            self.write_ivt_entry(mmu, irq, BIOS::ROM_SEG, u16::from(irq));
-            mmu.write_u8(BIOS::ROM_SEG, u16::from(irq), IRET);
+        for irq in 0..=0xFF {
+            let offset = BIOS::default_handler(irq);
+            self.write_ivt_entry(mmu, irq, BIOS::ROM_SEG, offset);
+            mmu.write(BIOS::ROM_SEG, offset, &BIOS::callback(u16::from(irq)));
+            mmu.write_u8(BIOS::ROM_SEG, offset + 4, IRET);
+        }
+
+        // INT 08 - IRQ0 - SYSTEM TIMER, counts the tick and chains to INT 1C
+        mmu.write(BIOS::ROM_SEG, BIOS::ROM_INT08, &BIOS::callback(BIOS::CALLBACK_TIMER_TICK));
+        let int08 = [
+            0xCD, 0x1C,     // int 0x1c
+            0x50,           // push ax
+            0xB0, 0x20,     // mov al,0x20
+            0xE6, 0x20,     // out 0x20,al  ; EOI
+            0x58,           // pop ax
+            IRET,
+        ];
+        mmu.write(BIOS::ROM_SEG, BIOS::ROM_INT08 + 4, &int08);
+        self.write_ivt_entry(mmu, 0x08, BIOS::ROM_SEG, BIOS::ROM_INT08);
+    }
+
+    /// initializes the timer tick count with the time of day `time`
+    pub fn init_timer_ticks(&self, mmu: &mut MMU, time: NaiveTime) {
+        mmu.write_u32(BIOS::DATA_SEG, BIOS::DATA_TIMER_TICKS, BIOS::ticks_of_time(time));
+    }
+
+    /// returns the timer tick count at the time of day `time`
+    pub fn ticks_of_time(time: NaiveTime) -> u32 {
+        // one tick is generated every 54.9254ms
+        let nanos = u64::from(time.num_seconds_from_midnight()) * 1_000_000_000 + u64::from(time.nanosecond().min(999_999_999));
+        (u128::from(nanos) * u128::from(BIOS::PIT_HZ) / (65536 * 1_000_000_000)) as u32
+    }
+
+    /// called by the INT 08 handler, counts the timer ticks since midnight
+    pub fn timer_tick(&self, mmu: &mut MMU) {
+        let mut ticks = mmu.read_u32(BIOS::DATA_SEG, BIOS::DATA_TIMER_TICKS) + 1;
+        if ticks >= BIOS::TICKS_PER_DAY {
+            ticks = 0;
+            mmu.write_u8(BIOS::DATA_SEG, BIOS::DATA_MIDNIGHT, 1);
         }
+        mmu.write_u32(BIOS::DATA_SEG, BIOS::DATA_TIMER_TICKS, ticks);
     }
 
     fn write_ivt_entry(&self, mmu: &mut MMU, number: u8, seg: u16, offset: u16) {
@@ -60,7 +136,7 @@ impl BIOS {
 
     /// initializes the Configuration Data Table
     fn write_configuration_data_table(&self, mmu: &mut MMU) {
-        let mut addr = MemoryAddress::RealSegmentOffset(BIOS::ROM_SEG, 0xE6F5);
+        let mut addr = MemoryAddress::RealSegmentOffset(BIOS::ROM_SEG, BIOS::ROM_CONFIG_TABLE);
         mmu.write_u16_inc(&mut addr, 8);          // table size
         mmu.write_u8_inc(&mut addr, 0xFC);        // model: AT
         mmu.write_u8_inc(&mut addr, 0);           // submodel
@@ -73,3 +149,82 @@ impl BIOS {
         mmu.write_u16(BIOS::ROM_SEG, BIOS::ROM_EQUIPMENT_WORD, 0x0021);
     }
 }
+
+impl Component for BIOS {
+    /// handles BIOS interrupts 0x12 and 0x15
+    fn int(&mut self, int: u8, cpu: &mut CPU, mmu: &mut MMU) -> bool {
+        if int == 0x12 {
+            // BIOS - GET MEMORY SIZE
+            // Return:
+            // AX = kilobytes of contiguous memory starting at absolute address 00000h
+            cpu.set_r16(R::AX, mmu.read_u16(BIOS::DATA_SEG, BIOS::DATA_MEMORY_SIZE));
+            return true;
+        }
+        if int != 0x15 {
+            return false;
+        }
+        let mut success = true;
+        match cpu.get_r8(R::AH) {
+            0x4F => {
+                // KEYBOARD - KEYBOARD INTERCEPT (AT model 3x9,XT2,XT286,CONV,PS)
+                // AL = hardware scan code
+                // Return:
+                // CF set: AL = scan code to be processed
+                // CF clear: scan code should be ignored
+                // programs hook INT 15 to translate or swallow the scan code, by default it is processed as is
+                mmu.set_flag(FLAG_CF, true);
+                return true;
+            }
+            0x86 => {
+                // BIOS - WAIT (AT,PS)
+                // CX:DX = interval in microseconds
+                // Return:
+                // CF clear if successful (wait interval elapsed)
+                let remaining = *self.wait_cycles.get_or_insert_with(|| {
+                    let us = u64::from(cpu.get_r16(R::CX)) << 16 | u64::from(cpu.get_r16(R::DX));
+                    let cycles = us * cpu.clock_hz as u64 / 1_000_000;
+                    debug!(target: "int", "BIOS - WAIT {} us, {} cycles", us, cycles);
+                    cycles
+                });
+                if remaining > 0 {
+                    // idles a millisecond at a time with interrupts enabled, running the callback
+                    // again until the wait is over, so that the timer interrupts happen meanwhile
+                    let step = remaining.min(cpu.clock_hz as u64 / 1000).max(1);
+                    self.wait_cycles = Some(remaining - step);
+                    cpu.cycle_count = cpu.cycle_count.wrapping_add(step as usize);
+                    cpu.regs.flags.interrupt = true;
+                    cpu.regs.ip = cpu.regs.ip.wrapping_sub(BIOS::callback(0).len() as u16);
+                    return true;
+                }
+                self.wait_cycles = None;
+                cpu.set_r8(R::AH, 0);
+            }
+            0x88 => {
+                // SYSTEM - GET EXTENDED MEMORY SIZE (286+)
+                // Return:
+                // CF clear if successful
+                // AX = number of contiguous KB starting at absolute address 100000h
+                let extended = mmu.memory.data.len().saturating_sub(0x10_0000) / 1024;
+                cpu.set_r16(R::AX, extended.min(0xFFFF) as u16);
+            }
+            0xC0 => {
+                // SYSTEM - GET CONFIGURATION (XT >1986/1/10,AT mdl 3x9,CONV,XT286,PS)
+                // Return:
+                // CF clear if successful
+                // ES:BX -> ROM table
+                // AH = status (00h)
+                cpu.set_r16(R::ES, BIOS::ROM_SEG);
+                cpu.set_r16(R::BX, BIOS::ROM_CONFIG_TABLE);
+                cpu.set_r8(R::AH, 0);
+            }
+            _ => {
+                warn!(target: "int", "int error: unknown INT 15, AH={:02X}, AL={:02X}", cpu.get_r8(R::AH), cpu.get_r8(R::AL));
+                // AH = 86h: function not supported
+                cpu.set_r8(R::AH, 0x86);
+                success = false;
+            }
+        }
+        mmu.set_flag(FLAG_CF, !success);
+        true
+    }
+}
@@ -0,0 +1,67 @@
+use chrono::prelude::*;
+use chrono::Duration;
+
+#[cfg(test)]
+#[path = "./clock_test.rs"]
+mod clock_test;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// the virtual time source of the machine. time of day is derived from the executed cpu cycles,
+/// so the timed components agree with each other and deterministic runs are reproducible
+#[derive(Clone, Debug)]
+pub struct Clock {
+    /// time of day when the machine was started
+    start: NaiveDateTime,
+
+    /// emulated time since start, in nanoseconds
+    elapsed_nanos: u64,
+
+    /// fraction of a nanosecond carried between calls to `advance`, so that rounding doesn't drift
+    remainder: u128,
+}
+
+impl Default for Clock {
+    /// returns a clock starting at a fixed time, for deterministic runs
+    fn default() -> Self {
+        Self::starting_at(NaiveDate::from_ymd(2000, 1, 1).and_hms(0, 0, 0))
+    }
+}
+
+impl Clock {
+    pub fn starting_at(start: NaiveDateTime) -> Self {
+        Clock {
+            start,
+            elapsed_nanos: 0,
+            remainder: 0,
+        }
+    }
+
+    /// returns a clock starting at the host local time
+    pub fn local() -> Self {
+        Self::starting_at(Local::now().naive_local())
+    }
+
+    /// progresses the clock by `cycles` cpu cycles at `clock_hz`
+    pub fn advance(&mut self, cycles: usize, clock_hz: usize) {
+        let scaled = cycles as u128 * NANOS_PER_SEC + self.remainder;
+        let clock_hz = clock_hz.max(1) as u128;
+        self.elapsed_nanos += (scaled / clock_hz) as u64;
+        self.remainder = scaled % clock_hz;
+    }
+
+    /// returns the emulated time since start
+    pub fn elapsed(&self) -> std::time::Duration {
+        std::time::Duration::from_nanos(self.elapsed_nanos)
+    }
+
+    /// returns the current date and time
+    pub fn now(&self) -> NaiveDateTime {
+        self.start + Duration::nanoseconds(self.elapsed_nanos as i64)
+    }
+
+    /// sets the current date and time, such as by DOS. the clock keeps running from `now`
+    pub fn set_now(&mut self, now: NaiveDateTime) {
+        self.start = now - Duration::nanoseconds(self.elapsed_nanos as i64);
+    }
+}
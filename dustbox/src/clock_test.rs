@@ -0,0 +1,16 @@
+use std::time::Duration;
+
+use chrono::NaiveDate;
+
+use crate::clock::Clock;
+
+#[test]
+fn derives_time_from_cycles() {
+    let mut clock = Clock::starting_at(NaiveDate::from_ymd(1994, 12, 31).and_hms(23, 59, 59));
+    // 3 Hz clock gives a third of a second per cycle, without drifting
+    for _ in 0..3 {
+        clock.advance(1, 3);
+    }
+    assert_eq!(Duration::from_secs(1), clock.elapsed());
+    assert_eq!(NaiveDate::from_ymd(1995, 1, 1).and_hms(0, 0, 0), clock.now());
+}
@@ -1,14 +1,371 @@
 // https://wiki.osdev.org/CMOS
 // dosbox-x: src/hardware/cmos.cpp
+//
+// MC146818 real-time clock and CMOS RAM, accessed by selecting a register
+// with port 0070 and reading or writing it with port 0071
+
+use std::ops::RangeInclusive;
+
+use chrono::prelude::*;
+use chrono::Duration;
+use log::trace;
+
+use crate::clock::Clock;
+use crate::cpu::{CPU, R, FLAG_CF};
+use crate::machine::Component;
+use crate::memory::MMU;
+
+#[cfg(test)]
+#[path = "./cmos_test.rs"]
+mod cmos_test;
+
+const REG_SECONDS: u8   = 0x00;
+const REG_MINUTES: u8   = 0x02;
+const REG_HOURS: u8     = 0x04;
+const REG_WEEKDAY: u8   = 0x06;
+const REG_DAY: u8       = 0x07;
+const REG_MONTH: u8     = 0x08;
+const REG_YEAR: u8      = 0x09;
+const REG_A: u8         = 0x0A;
+const REG_B: u8         = 0x0B;
+const REG_C: u8         = 0x0C;
+const REG_D: u8         = 0x0D;
+const REG_CENTURY: u8   = 0x32;
+
+/// status register B: SET, halts clock updates
+const B_SET: u8         = 0b1000_0000;
+/// status register B: PIE, periodic interrupt enable
+const B_PIE: u8         = 0b0100_0000;
+/// status register B: UIE, update-ended interrupt enable
+const B_UIE: u8         = 0b0001_0000;
+/// status register B: DM, binary instead of BCD values
+const B_BINARY: u8      = 0b0000_0100;
+/// status register B: 24-hour mode
+const B_24H: u8         = 0b0000_0010;
+
+/// status register C: IRQF, an enabled interrupt is pending
+const C_IRQF: u8        = 0b1000_0000;
+/// status register C: PF, periodic interrupt
+const C_PF: u8          = 0b0100_0000;
+/// status register C: UF, update-ended interrupt
+const C_UF: u8          = 0b0001_0000;
+
+/// status register D: VRT, valid RAM and time
+const D_VRT: u8         = 0b1000_0000;
+
+/// the time base of the periodic interrupt, in Hz
+const RTC_HZ: u64 = 32_768;
 
 #[derive(Clone)]
 pub struct CMOS {
+    /// register selected by port 0070
+    index: u8,
+
+    /// non-time registers and CMOS RAM
+    ram: [u8; 0x80],
+
+    /// the current clock time
+    pub time: NaiveDateTime,
+
+    /// difference between the clock time and the machine clock, changed when the time is set
+    offset: Duration,
+
+    /// cpu cycles since the last periodic interrupt
+    periodic_cycles: u64,
+}
+
+impl Component for CMOS {
+    fn io_ports(&self) -> Vec<RangeInclusive<u16>> {
+        vec![0x0070..=0x0071]
+    }
+
+    fn interrupts(&self) -> Vec<u8> {
+        vec![0x1A, 0x70]
+    }
+
+    fn in_u8(&mut self, port: u16) -> Option<u8> {
+        match port {
+            0x0071 => Some(self.read_register(self.index)),
+            _ => None
+        }
+    }
+
+    fn out_u8(&mut self, port: u16, data: u8) -> bool {
+        match port {
+            // bit 7 disables NMI, which is not emulated
+            0x0070 => self.index = data & 0x7F,
+            0x0071 => self.write_register(self.index, data),
+            _ => return false
+        }
+        true
+    }
+
+    fn int(&mut self, int: u8, cpu: &mut CPU, mmu: &mut MMU) -> bool {
+        if int == 0x70 {
+            // IRQ 8 - REAL-TIME CLOCK INTERRUPT
+            // acknowledges the interrupt by reading status register C
+            self.read_register(REG_C);
+            return true;
+        }
+        if int != 0x1A {
+            return false;
+        }
+        match cpu.get_r8(R::AH) {
+            0x02 => {
+                // TIME - GET REAL-TIME CLOCK TIME (AT,XT286,PS)
+                // Return:
+                // CF clear if successful
+                // CH = hour (BCD)
+                // CL = minutes (BCD)
+                // DH = seconds (BCD)
+                // DL = daylight savings flag (00h standard time, 01h daylight time)
+                cpu.set_r8(R::CH, to_bcd(self.time.hour() as u8));
+                cpu.set_r8(R::CL, to_bcd(self.time.minute() as u8));
+                cpu.set_r8(R::DH, to_bcd(self.time.second() as u8));
+                cpu.set_r8(R::DL, 0);
+            }
+            0x03 => {
+                // TIME - SET REAL-TIME CLOCK TIME (AT,XT286,PS)
+                // CH = hour (BCD)
+                // CL = minutes (BCD)
+                // DH = seconds (BCD)
+                // DL = daylight savings flag (00h standard time, 01h daylight time)
+                let hour = u32::from(from_bcd(cpu.get_r8(R::CH)));
+                let minute = u32::from(from_bcd(cpu.get_r8(R::CL)));
+                let second = u32::from(from_bcd(cpu.get_r8(R::DH)));
+                if let Some(time) = NaiveTime::from_hms_opt(hour, minute, second) {
+                    self.set_time(self.time.date().and_time(time));
+                }
+            }
+            0x04 => {
+                // TIME - GET REAL-TIME CLOCK DATE (AT,XT286,PS)
+                // Return:
+                // CF clear if successful
+                // CH = century (BCD)
+                // CL = year (BCD)
+                // DH = month (BCD)
+                // DL = day (BCD)
+                cpu.set_r8(R::CH, to_bcd((self.time.year() / 100) as u8));
+                cpu.set_r8(R::CL, to_bcd((self.time.year() % 100) as u8));
+                cpu.set_r8(R::DH, to_bcd(self.time.month() as u8));
+                cpu.set_r8(R::DL, to_bcd(self.time.day() as u8));
+            }
+            0x05 => {
+                // TIME - SET REAL-TIME CLOCK DATE (AT,XT286,PS)
+                // CH = century (BCD)
+                // CL = year (BCD)
+                // DH = month (BCD)
+                // DL = day (BCD)
+                let year = i32::from(from_bcd(cpu.get_r8(R::CH))) * 100 + i32::from(from_bcd(cpu.get_r8(R::CL)));
+                let month = u32::from(from_bcd(cpu.get_r8(R::DH)));
+                let day = u32::from(from_bcd(cpu.get_r8(R::DL)));
+                if let Some(date) = NaiveDate::from_ymd_opt(year, month, day) {
+                    self.set_time(date.and_time(self.time.time()));
+                }
+            }
+            _ => return false
+        }
+        mmu.set_flag(FLAG_CF, false);
+        true
+    }
 }
 
 impl CMOS {
+    /// returns a CMOS with the clock set to a fixed time, for deterministic runs
     pub fn default() -> Self {
-        // XXX see CMOS_Init in dosbox-x
-        CMOS {
+        let mut cmos = CMOS {
+            index: 0,
+            ram: [0; 0x80],
+            time: NaiveDate::from_ymd(2000, 1, 1).and_hms(0, 0, 0),
+            offset: Duration::zero(),
+            periodic_cycles: 0,
+        };
+        cmos.ram[REG_A as usize] = 0x26; // 32.768 kHz time base, 1024 Hz periodic rate
+        cmos.ram[REG_B as usize] = B_24H;
+        cmos.ram[REG_D as usize] = D_VRT;
+
+        cmos.set_memory_size(640, 3072);
+        cmos
+    }
+
+    /// sets the memory configuration reported in CMOS RAM, in KB
+    pub fn set_memory_size(&mut self, conventional_kb: u16, extended_kb: u16) {
+        self.ram[0x15] = conventional_kb as u8;
+        self.ram[0x16] = (conventional_kb >> 8) as u8;
+        // extended memory, as found by POST and as configured
+        for reg in &[0x17, 0x30] {
+            self.ram[*reg] = extended_kb as u8;
+            self.ram[*reg + 1] = (extended_kb >> 8) as u8;
+        }
+    }
+
+    /// makes the clock show the time of `clock`, discarding any time set by programs
+    pub fn reset_time(&mut self, clock: &Clock) {
+        self.offset = Duration::zero();
+        self.time = clock.now();
+    }
+
+    /// sets the clock time, it keeps running from `time` along with the machine clock
+    pub fn set_time(&mut self, time: NaiveDateTime) {
+        self.offset = self.offset + (time - self.time);
+        self.time = time;
+    }
+
+    /// returns the cpu cycles at `clock_hz` that pass before the periodic interrupt is raised,
+    /// None if it is disabled
+    pub fn cycles_until_irq(&self, clock_hz: usize) -> Option<usize> {
+        let rate = self.periodic_rate();
+        if rate == 0 || self.ram[REG_B as usize] & B_PIE == 0 {
+            return None;
+        }
+        let period = ((clock_hz as u64) << (rate - 1)) / RTC_HZ;
+        Some(period.max(1).saturating_sub(self.periodic_cycles) as usize)
+    }
+
+    /// returns the rate selected in status register A, 0 if the periodic interrupt is off
+    fn periodic_rate(&self) -> u8 {
+        // rates 1 and 2 are the same as 8 and 9
+        match self.ram[REG_A as usize] & 0x0F {
+            0 => 0,
+            1 => 8,
+            2 => 9,
+            n => n,
         }
     }
+
+    /// follows the machine `clock`, and progresses the periodic interrupt by `cycles` cpu cycles at `clock_hz`.
+    /// returns true when it raised IRQ 8
+    pub fn update(&mut self, clock: &Clock, cycles: usize, clock_hz: usize) -> bool {
+        let clock_hz = clock_hz as u64;
+        let mut flags = 0;
+
+        if self.ram[REG_B as usize] & B_SET == 0 {
+            let time = clock.now() + self.offset;
+            if time.timestamp() != self.time.timestamp() {
+                flags |= C_UF;
+            }
+            self.time = time;
+        } else {
+            // the clock is halted while being set
+            self.offset = self.time - clock.now();
+        }
+
+        let rate = self.periodic_rate();
+        if rate != 0 {
+            let period = ((clock_hz << (rate - 1)) / RTC_HZ).max(1);
+            self.periodic_cycles += cycles as u64;
+            if self.periodic_cycles >= period {
+                self.periodic_cycles %= period;
+                flags |= C_PF;
+            }
+        }
+
+        if flags == 0 {
+            return false;
+        }
+        let b = self.ram[REG_B as usize];
+        let enabled = (b & B_PIE != 0 && flags & C_PF != 0) || (b & B_UIE != 0 && flags & C_UF != 0);
+        let c = &mut self.ram[REG_C as usize];
+        *c |= flags;
+        if enabled && *c & C_IRQF == 0 {
+            *c |= C_IRQF;
+            trace!(target: "io", "cmos irq 8, status c {:02X}", *c);
+            return true;
+        }
+        false
+    }
+
+    /// reads register `reg`, in the format selected by status register B
+    fn read_register(&mut self, reg: u8) -> u8 {
+        let value = match reg {
+            REG_SECONDS => self.time.second() as u8,
+            REG_MINUTES => self.time.minute() as u8,
+            REG_HOURS => {
+                let hour = self.time.hour() as u8;
+                if self.ram[REG_B as usize] & B_24H == 0 {
+                    // 12-hour mode, bit 7 is set for pm
+                    let pm = if hour >= 12 { 0x80 } else { 0 };
+                    let hour = match hour % 12 { 0 => 12, h => h };
+                    return self.encode(hour) | pm;
+                }
+                hour
+            }
+            REG_WEEKDAY => self.time.weekday().number_from_sunday() as u8,
+            REG_DAY => self.time.day() as u8,
+            REG_MONTH => self.time.month() as u8,
+            REG_YEAR => (self.time.year() % 100) as u8,
+            REG_CENTURY => (self.time.year() / 100) as u8,
+            REG_C => {
+                // reading status register C clears the pending interrupt flags
+                let c = self.ram[REG_C as usize];
+                self.ram[REG_C as usize] = 0;
+                return c;
+            }
+            _ => return self.ram[(reg & 0x7F) as usize],
+        };
+        self.encode(value)
+    }
+
+    /// writes register `reg`, in the format selected by status register B
+    fn write_register(&mut self, reg: u8, data: u8) {
+        trace!(target: "io", "cmos write register {:02X} = {:02X}", reg, data);
+        let time = match reg {
+            REG_SECONDS => self.time.with_second(u32::from(self.decode(data))),
+            REG_MINUTES => self.time.with_minute(u32::from(self.decode(data))),
+            REG_HOURS => {
+                let mut hour = self.decode(data & 0x7F);
+                if self.ram[REG_B as usize] & B_24H == 0 {
+                    hour %= 12;
+                    if data & 0x80 != 0 {
+                        hour += 12;
+                    }
+                }
+                self.time.with_hour(u32::from(hour))
+            }
+            REG_WEEKDAY => None, // derived from the date
+            REG_DAY => self.time.with_day(u32::from(self.decode(data))),
+            REG_MONTH => self.time.with_month(u32::from(self.decode(data))),
+            REG_YEAR => self.time.with_year(self.time.year() / 100 * 100 + i32::from(self.decode(data))),
+            REG_CENTURY => self.time.with_year(i32::from(self.decode(data)) * 100 + self.time.year() % 100),
+            REG_A => {
+                // bit 7, update in progress, is read-only
+                self.ram[REG_A as usize] = data & 0x7F;
+                None
+            }
+            REG_C | REG_D => None, // read-only
+            _ => {
+                self.ram[(reg & 0x7F) as usize] = data;
+                None
+            }
+        };
+        if let Some(time) = time {
+            self.set_time(time);
+        }
+    }
+
+    /// converts `value` to BCD unless binary mode is selected
+    fn encode(&self, value: u8) -> u8 {
+        if self.ram[REG_B as usize] & B_BINARY != 0 {
+            value
+        } else {
+            to_bcd(value)
+        }
+    }
+
+    /// converts `value` from BCD unless binary mode is selected
+    fn decode(&self, value: u8) -> u8 {
+        if self.ram[REG_B as usize] & B_BINARY != 0 {
+            value
+        } else {
+            from_bcd(value)
+        }
+    }
+}
+
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
 }
@@ -0,0 +1,63 @@
+use crate::clock::Clock;
+use crate::cmos::CMOS;
+use crate::machine::Component;
+
+#[test]
+fn can_read_bcd_time_registers() {
+    let mut clock = Clock::default();
+    let mut cmos = CMOS::default();
+    cmos.set_time(chrono::NaiveDate::from_ymd(1994, 12, 31).and_hms(23, 59, 58));
+
+    let read = |cmos: &mut CMOS, reg: u8| {
+        cmos.out_u8(0x70, reg);
+        cmos.in_u8(0x71).unwrap()
+    };
+    assert_eq!(0x58, read(&mut cmos, 0x00));
+    assert_eq!(0x59, read(&mut cmos, 0x02));
+    assert_eq!(0x23, read(&mut cmos, 0x04));
+    assert_eq!(0x07, read(&mut cmos, 0x06)); // saturday
+    assert_eq!(0x31, read(&mut cmos, 0x07));
+    assert_eq!(0x12, read(&mut cmos, 0x08));
+    assert_eq!(0x94, read(&mut cmos, 0x09));
+    assert_eq!(0x19, read(&mut cmos, 0x32));
+
+    // two seconds later it is a new year
+    let clock_hz = 1000;
+    clock.advance(2 * clock_hz, clock_hz);
+    assert!(!cmos.update(&clock, 2 * clock_hz, clock_hz));
+    assert_eq!(0x00, read(&mut cmos, 0x00));
+    assert_eq!(0x01, read(&mut cmos, 0x07));
+    assert_eq!(0x95, read(&mut cmos, 0x09));
+
+    // binary mode
+    cmos.out_u8(0x70, 0x0B);
+    cmos.out_u8(0x71, 0b0000_0110);
+    assert_eq!(95, read(&mut cmos, 0x09));
+    cmos.out_u8(0x70, 0x02);
+    cmos.out_u8(0x71, 42);
+    assert_eq!(42, read(&mut cmos, 0x02));
+}
+
+#[test]
+fn can_raise_periodic_irq8() {
+    let clock = Clock::default();
+    let mut cmos = CMOS::default();
+    let clock_hz = 1_048_576;
+
+    // disabled by default
+    assert!(!cmos.update(&clock, clock_hz / 1024, clock_hz));
+
+    // enable the periodic interrupt at the default 1024 Hz
+    cmos.out_u8(0x70, 0x0B);
+    cmos.out_u8(0x71, 0b0100_0010);
+    cmos.out_u8(0x70, 0x0C);
+    cmos.in_u8(0x71);
+    assert!(!cmos.update(&clock, clock_hz / 1024 - 1, clock_hz));
+    assert!(cmos.update(&clock, 1, clock_hz));
+
+    // no new irq until status register C is read
+    assert!(!cmos.update(&clock, clock_hz / 1024, clock_hz));
+    cmos.out_u8(0x70, 0x0C);
+    assert_eq!(0b1100_0000, cmos.in_u8(0x71).unwrap());
+    assert!(cmos.update(&clock, clock_hz / 1024, clock_hz));
+}
@@ -0,0 +1,34 @@
+use crate::codepage::{Codepage, CountryInfo};
+
+#[test]
+fn can_convert_codepages() {
+    assert_eq!("£|¢", Codepage::CP437.to_utf8(&[0x9C, 0x7C, 0x9B]));
+    assert_eq!('ø', Codepage::CP850.u8_as_char(0x9B));
+    assert_eq!('ø', Codepage::CP865.u8_as_char(0x9B));
+    assert_eq!('Ê', Codepage::CP850.u8_as_char(0xD2));
+    assert_eq!(Some(0x9D), Codepage::CP865.char_as_u8('Ø'));
+    assert_eq!(Some(Codepage::CP850), Codepage::from_number(850));
+    assert_eq!(None, Codepage::from_number(1252));
+}
+
+#[test]
+fn can_upper_case_in_codepage() {
+    assert_eq!(b'A', Codepage::CP437.to_upper(b'a'));
+    assert_eq!(0x9A, Codepage::CP437.to_upper(0x81)); // ü -> Ü
+    assert_eq!(0x9B, Codepage::CP437.to_upper(0x9B)); // ¢ has no upper case
+    assert_eq!(0x9D, Codepage::CP850.to_upper(0x9B)); // ø -> Ø
+    assert_eq!(0x90, Codepage::CP850.to_upper(0x82)); // é -> É
+    assert_eq!(0x9D, Codepage::CP865.to_upper(0x9B)); // ø -> Ø
+    assert_eq!(0xA0, Codepage::CP437.to_upper(0xA0)); // Á is not in code page 437
+    assert_eq!(0x80, Codepage::CP437.upper_case_table().len());
+}
+
+#[test]
+fn can_encode_country_info() {
+    let data = CountryInfo::for_code(44).unwrap().to_bytes(Codepage::CP437, (0x0070, 0x00F0));
+    assert_eq!(CountryInfo::LENGTH, data.len());
+    assert_eq!(&[0x01, 0x00, 0x9C, 0x00], &data[0..4]);   // d/m/y, "£"
+    assert_eq!(&[b',', 0, b'.', 0, b'/', 0, b':', 0], &data[7..15]);
+    assert_eq!(&[0xF0, 0x00, 0x70, 0x00], &data[0x12..0x16]);
+    assert_eq!(None, CountryInfo::for_code(999));
+}
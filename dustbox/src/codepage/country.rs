@@ -0,0 +1,92 @@
+use crate::codepage::Codepage;
+
+/// order of day, month and year in dates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DateFormat {
+    /// m d y
+    USA = 0,
+
+    /// d m y
+    Europe = 1,
+
+    /// y m d
+    Japan = 2,
+}
+
+/// country specific formats, returned by INT 21/AH=38h
+#[derive(Clone, Debug, PartialEq)]
+pub struct CountryInfo {
+    /// international telephone prefix
+    pub code: u16,
+    pub date_format: DateFormat,
+    pub currency_symbol: &'static str,
+    pub thousands_separator: char,
+    pub decimal_separator: char,
+    pub date_separator: char,
+    pub time_separator: char,
+
+    /// bit 0 = symbol follows value, bit 1 = space between value and symbol,
+    /// bit 2 = symbol replaces decimal point
+    pub currency_format: u8,
+    pub currency_digits: u8,
+
+    /// 24-hour clock, else 12-hour
+    pub clock_24h: bool,
+    pub list_separator: char,
+}
+
+/// returns a country info entry
+#[allow(clippy::too_many_arguments)]
+const fn country(code: u16, date_format: DateFormat, currency_symbol: &'static str, thousands_separator: char, decimal_separator: char, date_separator: char, time_separator: char, currency_format: u8, currency_digits: u8, clock_24h: bool, list_separator: char) -> CountryInfo {
+    CountryInfo { code, date_format, currency_symbol, thousands_separator, decimal_separator, date_separator, time_separator, currency_format, currency_digits, clock_24h, list_separator }
+}
+
+impl CountryInfo {
+    /// length of the country info in memory
+    pub const LENGTH: usize = 34;
+
+    /// returns the formats of a country
+    pub fn for_code(code: u16) -> Option<Self> {
+        use DateFormat::*;
+        let info = match code {
+            1 => country(1, USA, "$", ',', '.', '-', ':', 0, 2, false, ','),
+            2 => country(2, Japan, "$", ' ', ',', '-', ':', 3, 2, true, ';'),      // Canadian-French
+            31 => country(31, Europe, "f", '.', ',', '-', ':', 2, 2, true, ';'),   // Netherlands
+            33 => country(33, Europe, "F", ' ', ',', '/', ':', 3, 2, true, ';'),   // France
+            34 => country(34, Europe, "Pts", '.', ',', '/', ':', 3, 0, true, ';'), // Spain
+            39 => country(39, Europe, "Lit.", '.', ',', '/', '.', 2, 0, true, ';'), // Italy
+            44 => country(44, Europe, "£", ',', '.', '/', ':', 0, 2, true, ','),   // United Kingdom
+            45 => country(45, Europe, "kr", '.', ',', '-', '.', 2, 2, true, ';'),  // Denmark
+            46 => country(46, Japan, "Kr", ' ', ',', '-', '.', 3, 2, true, ';'),   // Sweden
+            47 => country(47, Europe, "Kr", '.', ',', '.', ':', 2, 2, true, ';'),  // Norway
+            49 => country(49, Europe, "DM", '.', ',', '.', ':', 3, 2, true, ';'),  // Germany
+            81 => country(81, Japan, "¥", ',', '.', '-', ':', 0, 0, true, ','),    // Japan
+            _ => return None,
+        };
+        Some(info)
+    }
+
+    /// returns the country info as stored by DOS 3+, with the case map routine at `case_map` (segment, offset)
+    /// http://www.ctyme.com/intr/rb-2773.htm#Table1399
+    pub fn to_bytes(&self, codepage: Codepage, case_map: (u16, u16)) -> Vec<u8> {
+        let asciiz = |s: &str, len: usize| {
+            let mut v: Vec<u8> = s.chars().map(|c| codepage.char_as_u8(c).unwrap_or(b'?')).take(len - 1).collect();
+            v.resize(len, 0);
+            v
+        };
+        let mut data = Vec::with_capacity(Self::LENGTH);
+        data.extend_from_slice(&(self.date_format as u16).to_le_bytes());
+        data.extend(asciiz(self.currency_symbol, 5));
+        for &c in &[self.thousands_separator, self.decimal_separator, self.date_separator, self.time_separator] {
+            data.extend(asciiz(&c.to_string(), 2));
+        }
+        data.push(self.currency_format);
+        data.push(self.currency_digits);
+        data.push(self.clock_24h as u8);
+        data.extend_from_slice(&case_map.1.to_le_bytes());
+        data.extend_from_slice(&case_map.0.to_le_bytes());
+        data.extend(asciiz(&self.list_separator.to_string(), 2));
+        data.resize(Self::LENGTH, 0);
+        data
+    }
+}
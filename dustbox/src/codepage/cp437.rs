@@ -71,7 +71,7 @@ pub fn u8_as_char(b: u8) -> char {
         0x37 => '7', // 0037 - DIGIT SEVEN
         0x38 => '8', // 0038 - DIGIT EIGHT
         0x39 => '9', // 0039 - DIGIT NINE
-        0x3a => ',', // 003a - COLON
+        0x3a => ':', // 003a - COLON
         0x3b => ';', // 003b - SEMICOLON
         0x3c => '<', // 003c - LESS-THAN SIGN
         0x3d => '=', // 003d - EQUALS SIGN
@@ -141,7 +141,7 @@ pub fn u8_as_char(b: u8) -> char {
         0x79 => 'y', // 0079 - LATIN SMALL LETTER Y
         0x7a => 'z', // 007a - LATIN SMALL LETTER Z
         0x7b => '{', // 007b - LEFT CURLY BRACKET
-        0x7c => '-', // 007c - VERTICAL LINE
+        0x7c => '|', // 007c - VERTICAL LINE
         0x7d => '}', // 007d - RIGHT CURLY BRACKET
         0x7e => '~', // 007e - TILDE
         0x7f => '⌂', // 2302 - DELETE
@@ -229,9 +229,9 @@ pub fn u8_as_char(b: u8) -> char {
         0xcc => '╠', // 2560 - BOX DRAWINGS DOUBLE VERTICAL AND RIGHT
         0xcd => '═', // 2550 - BOX DRAWINGS DOUBLE HORIZONTAL
         0xce => '╬', // 256c - BOX DRAWINGS DOUBLE VERTICAL AND HORIZONTAL
-        0xcf => '╶', // 2567 - BOX DRAWINGS UP SINGLE AND HORIZONTAL DOUBLE
+        0xcf => '╧', // 2567 - BOX DRAWINGS UP SINGLE AND HORIZONTAL DOUBLE
 
-        0xd0 => '╸', // 2568 - BOX DRAWINGS UP DOUBLE AND HORIZONTAL SINGLE
+        0xd0 => '╨', // 2568 - BOX DRAWINGS UP DOUBLE AND HORIZONTAL SINGLE
         0xd1 => '╤', // 2564 - BOX DRAWINGS DOWN SINGLE AND HORIZONTAL DOUBLE
         0xd2 => '╥', // 2565 - BOX DRAWINGS DOWN DOUBLE AND HORIZONTAL SINGLE
         0xd3 => '╙', // 2559 - BOX DRAWINGS UP DOUBLE AND RIGHT SINGLE
@@ -248,9 +248,9 @@ pub fn u8_as_char(b: u8) -> char {
         0xde => '▐', // 2590 - RIGHT HALF BLOCK
         0xdf => '▀', // 2580 - UPPER HALF BLOCK
 
-        0xe0 => 'ʱ', // 03b1 - GREEK SMALL LETTER ALPHA
+        0xe0 => 'α', // 03b1 - GREEK SMALL LETTER ALPHA
         0xe1 => 'ß', // 00df - LATIN SMALL LETTER SHARP S
-        0xe2 => 'γ', // 0393 - GREEK CAPITAL LETTER GAMMA
+        0xe2 => 'Γ', // 0393 - GREEK CAPITAL LETTER GAMMA
         0xe3 => 'π', // 03c0 - GREEK SMALL LETTER PI
         0xe4 => 'Σ', // 03a3 - GREEK CAPITAL LETTER SIGMA
         0xe5 => 'σ', // 03c3 - GREEK SMALL LETTER SIGMA
@@ -0,0 +1,61 @@
+use crate::codepage::cp437;
+
+pub fn to_utf8(v: &[u8]) -> String {
+    v.iter().map(|b| u8_as_char(*b)).collect()
+}
+
+/// converts byte to a symbol in code page 850 ("Multilingual Latin 1"), presented as a utf8 char.
+/// only the symbols that differ from code page 437 are listed
+/// https://en.wikipedia.org/wiki/Code_page_850
+pub fn u8_as_char(b: u8) -> char {
+    match b {
+        0x9b => 'ø', // 00f8 - LATIN SMALL LETTER O WITH STROKE
+        0x9d => 'Ø', // 00d8 - LATIN CAPITAL LETTER O WITH STROKE
+        0x9e => '×', // 00d7 - MULTIPLICATION SIGN
+        0xa9 => '®', // 00ae - REGISTERED SIGN
+        0xb5 => 'Á', // 00c1 - LATIN CAPITAL LETTER A WITH ACUTE
+        0xb6 => 'Â', // 00c2 - LATIN CAPITAL LETTER A WITH CIRCUMFLEX
+        0xb7 => 'À', // 00c0 - LATIN CAPITAL LETTER A WITH GRAVE
+        0xb8 => '©', // 00a9 - COPYRIGHT SIGN
+        0xbd => '¢', // 00a2 - CENT SIGN
+        0xbe => '¥', // 00a5 - YEN SIGN
+        0xc6 => 'ã', // 00e3 - LATIN SMALL LETTER A WITH TILDE
+        0xc7 => 'Ã', // 00c3 - LATIN CAPITAL LETTER A WITH TILDE
+        0xcf => '¤', // 00a4 - CURRENCY SIGN
+        0xd0 => 'ð', // 00f0 - LATIN SMALL LETTER ETH
+        0xd1 => 'Ð', // 00d0 - LATIN CAPITAL LETTER ETH
+        0xd2 => 'Ê', // 00ca - LATIN CAPITAL LETTER E WITH CIRCUMFLEX
+        0xd3 => 'Ë', // 00cb - LATIN CAPITAL LETTER E WITH DIAERESIS
+        0xd4 => 'È', // 00c8 - LATIN CAPITAL LETTER E WITH GRAVE
+        0xd5 => 'ı', // 0131 - LATIN SMALL LETTER DOTLESS I
+        0xd6 => 'Í', // 00cd - LATIN CAPITAL LETTER I WITH ACUTE
+        0xd7 => 'Î', // 00ce - LATIN CAPITAL LETTER I WITH CIRCUMFLEX
+        0xd8 => 'Ï', // 00cf - LATIN CAPITAL LETTER I WITH DIAERESIS
+        0xdd => '¦', // 00a6 - BROKEN BAR
+        0xde => 'Ì', // 00cc - LATIN CAPITAL LETTER I WITH GRAVE
+        0xe0 => 'Ó', // 00d3 - LATIN CAPITAL LETTER O WITH ACUTE
+        0xe2 => 'Ô', // 00d4 - LATIN CAPITAL LETTER O WITH CIRCUMFLEX
+        0xe3 => 'Ò', // 00d2 - LATIN CAPITAL LETTER O WITH GRAVE
+        0xe4 => 'õ', // 00f5 - LATIN SMALL LETTER O WITH TILDE
+        0xe5 => 'Õ', // 00d5 - LATIN CAPITAL LETTER O WITH TILDE
+        0xe7 => 'þ', // 00fe - LATIN SMALL LETTER THORN
+        0xe8 => 'Þ', // 00de - LATIN CAPITAL LETTER THORN
+        0xe9 => 'Ú', // 00da - LATIN CAPITAL LETTER U WITH ACUTE
+        0xea => 'Û', // 00db - LATIN CAPITAL LETTER U WITH CIRCUMFLEX
+        0xeb => 'Ù', // 00d9 - LATIN CAPITAL LETTER U WITH GRAVE
+        0xec => 'ý', // 00fd - LATIN SMALL LETTER Y WITH ACUTE
+        0xed => 'Ý', // 00dd - LATIN CAPITAL LETTER Y WITH ACUTE
+        0xee => '¯', // 00af - MACRON
+        0xef => '´', // 00b4 - ACUTE ACCENT
+        0xf0 => '\u{ad}', // 00ad - SOFT HYPHEN
+        0xf2 => '‗', // 2017 - DOUBLE LOW LINE
+        0xf3 => '¾', // 00be - VULGAR FRACTION THREE QUARTERS
+        0xf4 => '¶', // 00b6 - PILCROW SIGN
+        0xf5 => '§', // 00a7 - SECTION SIGN
+        0xf7 => '¸', // 00b8 - CEDILLA
+        0xf9 => '¨', // 00a8 - DIAERESIS
+        0xfb => '¹', // 00b9 - SUPERSCRIPT ONE
+        0xfc => '³', // 00b3 - SUPERSCRIPT THREE
+        _ => cp437::u8_as_char(b),
+    }
+}
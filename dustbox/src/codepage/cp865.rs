@@ -0,0 +1,17 @@
+use crate::codepage::cp437;
+
+pub fn to_utf8(v: &[u8]) -> String {
+    v.iter().map(|b| u8_as_char(*b)).collect()
+}
+
+/// converts byte to a symbol in code page 865 ("Nordic"), presented as a utf8 char.
+/// only the symbols that differ from code page 437 are listed
+/// https://en.wikipedia.org/wiki/Code_page_865
+pub fn u8_as_char(b: u8) -> char {
+    match b {
+        0x9b => 'ø', // 00f8 - LATIN SMALL LETTER O WITH STROKE
+        0x9d => 'Ø', // 00d8 - LATIN CAPITAL LETTER O WITH STROKE
+        0xaf => '¤', // 00a4 - CURRENCY SIGN
+        _ => cp437::u8_as_char(b),
+    }
+}
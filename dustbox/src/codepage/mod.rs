@@ -1 +1,84 @@
 pub mod cp437;
+pub mod cp850;
+pub mod cp865;
+
+pub use self::country::*;
+mod country;
+
+#[cfg(test)]
+#[path = "./codepage_test.rs"]
+mod codepage_test;
+
+/// a DOS code page, mapping bytes to displayed symbols
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+pub enum Codepage {
+    /// United States
+    #[default]
+    #[serde(rename = "437")]
+    CP437,
+
+    /// Multilingual Latin 1
+    #[serde(rename = "850")]
+    CP850,
+
+    /// Nordic
+    #[serde(rename = "865")]
+    CP865,
+}
+
+impl Codepage {
+    pub fn from_number(number: u16) -> Option<Self> {
+        match number {
+            437 => Some(Codepage::CP437),
+            850 => Some(Codepage::CP850),
+            865 => Some(Codepage::CP865),
+            _ => None,
+        }
+    }
+
+    pub fn number(self) -> u16 {
+        match self {
+            Codepage::CP437 => 437,
+            Codepage::CP850 => 850,
+            Codepage::CP865 => 865,
+        }
+    }
+
+    /// converts byte to a symbol in the code page, presented as a utf8 char
+    pub fn u8_as_char(self, b: u8) -> char {
+        match self {
+            Codepage::CP437 => cp437::u8_as_char(b),
+            Codepage::CP850 => cp850::u8_as_char(b),
+            Codepage::CP865 => cp865::u8_as_char(b),
+        }
+    }
+
+    pub fn to_utf8(self, v: &[u8]) -> String {
+        v.iter().map(|b| self.u8_as_char(*b)).collect()
+    }
+
+    /// returns the byte for a symbol, if the code page has it
+    pub fn char_as_u8(self, c: char) -> Option<u8> {
+        if c.is_ascii() {
+            return Some(c as u8);
+        }
+        (0x80..=0xFF).find(|&b| self.u8_as_char(b) == c)
+    }
+
+    /// returns the upper case version of a character in the code page
+    pub fn to_upper(self, b: u8) -> u8 {
+        if b < 0x80 {
+            return b.to_ascii_uppercase();
+        }
+        let mut upper = self.u8_as_char(b).to_uppercase();
+        match (upper.next(), upper.next()) {
+            (Some(c), None) => self.char_as_u8(c).unwrap_or(b),
+            _ => b,
+        }
+    }
+
+    /// returns the upper case versions of characters 80h to FFh
+    pub fn upper_case_table(self) -> Vec<u8> {
+        (0x80..=0xFF).map(|b| self.to_upper(b)).collect()
+    }
+}
@@ -0,0 +1,348 @@
+// structured description of decoded instructions: the kind and access of each operand,
+// the registers read and written and the memory accessed
+
+use crate::cpu::{AMode, BranchTarget, Instruction, InstructionInfo, Op, Parameter, RepeatMode, Segment, R};
+
+#[cfg(test)]
+#[path = "./analysis_test.rs"]
+mod analysis_test;
+
+/// how an operand or memory is accessed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    None,
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    pub fn is_read(self) -> bool {
+        self == Access::Read || self == Access::ReadWrite
+    }
+
+    pub fn is_write(self) -> bool {
+        self == Access::Write || self == Access::ReadWrite
+    }
+
+    /// returns the access of both `self` and `other`
+    pub fn merge(self, other: Access) -> Access {
+        match (self.is_read() || other.is_read(), self.is_write() || other.is_write()) {
+            (false, false) => Access::None,
+            (true, false) => Access::Read,
+            (false, true) => Access::Write,
+            (true, true) => Access::ReadWrite,
+        }
+    }
+}
+
+/// a memory operand
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryOperand {
+    /// the segment register used, taking segment prefixes and bp based addressing into account
+    pub segment: R,
+
+    /// base and index registers
+    pub registers: Vec<R>,
+
+    pub displacement: i32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OperandKind {
+    Register(R),
+    SegmentRegister(R),
+    FpuRegister(R),
+    ControlRegister(R),
+    Immediate(u32),
+    FarPointer(u16, u16),
+    Memory(MemoryOperand),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Operand {
+    pub kind: OperandKind,
+
+    /// size in bits
+    pub size: u8,
+
+    pub access: Access,
+}
+
+/// the operands and the registers and memory used by an instruction, see `InstructionInfo::analysis`
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstructionAnalysis {
+    pub operands: Vec<Operand>,
+
+    /// registers read, explicitly or implicitly, including the registers used for addressing
+    pub reads: Vec<R>,
+
+    /// registers written, explicitly or implicitly
+    pub writes: Vec<R>,
+
+    /// access of memory operands, the stack and string instructions
+    pub memory: Access,
+
+    /// target of a direct jump, call or loop
+    pub branch_target: Option<BranchTarget>,
+}
+
+impl InstructionAnalysis {
+    /// returns true if `r` or a register overlapping it is read
+    pub fn reads_register(&self, r: R) -> bool {
+        self.reads.iter().any(|x| overlaps(*x, r))
+    }
+
+    /// returns true if `r` or a register overlapping it is written
+    pub fn writes_register(&self, r: R) -> bool {
+        self.writes.iter().any(|x| overlaps(*x, r))
+    }
+}
+
+/// returns the 32-bit register containing `r`, or `r` itself for the other registers
+pub fn container(r: R) -> R {
+    match r {
+        R::AL | R::AH | R::AX => R::EAX,
+        R::CL | R::CH | R::CX => R::ECX,
+        R::DL | R::DH | R::DX => R::EDX,
+        R::BL | R::BH | R::BX => R::EBX,
+        R::SP => R::ESP,
+        R::BP => R::EBP,
+        R::SI => R::ESI,
+        R::DI => R::EDI,
+        _ => r,
+    }
+}
+
+/// returns true if `a` and `b` share any bits, such as AL and AX, but not AL and AH
+pub fn overlaps(a: R, b: R) -> bool {
+    if container(a) != container(b) {
+        return false;
+    }
+    let high = |r| r == R::AH || r == R::CH || r == R::DH || r == R::BH;
+    let low = |r| r == R::AL || r == R::CL || r == R::DL || r == R::BL;
+    !(high(a) && low(b) || low(a) && high(b))
+}
+
+/// returns the analysis of the decoded instruction `info`
+pub fn analyze(info: &InstructionInfo) -> InstructionAnalysis {
+    let op = &info.instruction;
+    let mut a = InstructionAnalysis {
+        operands: Vec::new(),
+        reads: Vec::new(),
+        writes: Vec::new(),
+        memory: Access::None,
+        branch_target: info.branch_targets().first().cloned(),
+    };
+
+    let (dst_access, src_access) = operand_access(op);
+    let params = [(&op.params.dst, dst_access), (&op.params.src, src_access), (&op.params.src2, Access::Read)];
+    for (param, access) in params.iter() {
+        if let Some(operand) = operand(op, param, *access) {
+            match &operand.kind {
+                OperandKind::Register(r) | OperandKind::SegmentRegister(r) | OperandKind::FpuRegister(r) |
+                OperandKind::ControlRegister(r) => {
+                    if access.is_read() {
+                        a.reads.push(*r);
+                    }
+                    if access.is_write() {
+                        a.writes.push(*r);
+                    }
+                }
+                OperandKind::Memory(mem) => {
+                    a.reads.push(mem.segment);
+                    a.reads.extend_from_slice(&mem.registers);
+                    a.memory = a.memory.merge(*access);
+                }
+                OperandKind::Immediate(_) | OperandKind::FarPointer(_, _) => {}
+            }
+            a.operands.push(operand);
+        }
+    }
+    implicit_access(op, &mut a);
+
+    a.reads = unique(&a.reads);
+    a.writes = unique(&a.writes);
+    a
+}
+
+/// returns `regs` without duplicates, in order of first appearance
+fn unique(regs: &[R]) -> Vec<R> {
+    let mut res = Vec::new();
+    for r in regs {
+        if !res.contains(r) {
+            res.push(*r);
+        }
+    }
+    res
+}
+
+/// returns the access of the dst and src operands of `op`
+fn operand_access(op: &Instruction) -> (Access, Access) {
+    match op.command {
+        // lea only computes the address
+        Op::Lea16 | Op::Lea32 => (Access::Write, Access::None),
+
+        Op::Mov8 | Op::Mov16 | Op::Mov32 | Op::Movsx16 | Op::Movsx32 | Op::Movzx16 | Op::Movzx32 |
+        Op::Lds | Op::Les | Op::Lar16 | Op::Bsf16 | Op::Bsf32 | Op::Bsr16 | Op::Bsr32 |
+        Op::Pop16 | Op::Pop32 | Op::In8 | Op::In16 | Op::Sldt | Op::Smsw |
+        Op::Seto | Op::Setno | Op::Setc | Op::Setnc | Op::Setz | Op::Setnz | Op::Setna | Op::Seta |
+        Op::Sets | Op::Setns | Op::Setpe | Op::Setpo | Op::Setl | Op::Setnl | Op::Setng | Op::Setg |
+        Op::Fst | Op::Fstp | Op::Fist | Op::Fistp | Op::Fisttp | Op::Fstsw | Op::Fnstcw => (Access::Write, Access::Read),
+
+        // 3 operand imul: dst = src * src2
+        Op::Imul16 | Op::Imul32 if op.params.src2 != Parameter::None => (Access::Write, Access::Read),
+
+        // 1 operand mul and div, the result goes to ax and dx
+        Op::Mul8 | Op::Mul16 | Op::Mul32 | Op::Div8 | Op::Div16 | Op::Div32 |
+        Op::Idiv8 | Op::Idiv16 | Op::Idiv32 | Op::Imul8 => (Access::Read, Access::Read),
+        Op::Imul16 | Op::Imul32 if op.params.src == Parameter::None => (Access::Read, Access::Read),
+
+        Op::Cmp8 | Op::Cmp16 | Op::Cmp32 | Op::Test8 | Op::Test16 | Op::Test32 |
+        Op::Bt16 | Op::Bt32 | Op::Bound | Op::Push16 | Op::Push32 | Op::Out8 | Op::Out16 |
+        Op::Int | Op::RetImm16 | Op::Enter |
+        Op::Fld | Op::Fild | Op::Fldcw | Op::Fcom | Op::Fcomp | Op::Ficom | Op::Ficomp => (Access::Read, Access::Read),
+
+        Op::Xchg8 | Op::Xchg16 | Op::Xchg32 | Op::Xadd8 | Op::Xadd16 | Op::Xadd32 => (Access::ReadWrite, Access::ReadWrite),
+
+        _ if op.is_branch() => (Access::Read, Access::Read),
+        _ => (Access::ReadWrite, Access::Read),
+    }
+}
+
+/// returns the operand of `param`, None if there is no operand
+fn operand(op: &Instruction, param: &Parameter, access: Access) -> Option<Operand> {
+    let memory = |size, amode: Option<&AMode>, displacement: i32| {
+        let registers = match amode {
+            Some(amode) => amode_registers(amode),
+            None => Vec::new(),
+        };
+        let segment = match op.segment_prefix {
+            Segment::Default if amode.is_some_and(AMode::uses_stack_segment) => R::SS,
+            seg => seg.as_register(),
+        };
+        (OperandKind::Memory(MemoryOperand { segment, registers, displacement }), size)
+    };
+    let (kind, size) = match *param {
+        Parameter::None => return None,
+        Parameter::Reg8(r) => (OperandKind::Register(r), 8),
+        Parameter::Reg16(r) => (OperandKind::Register(r), 16),
+        Parameter::Reg32(r) => (OperandKind::Register(r), 32),
+        Parameter::SReg16(r) => (OperandKind::SegmentRegister(r), 16),
+        Parameter::FPR80(r) => (OperandKind::FpuRegister(r), 80),
+        Parameter::CReg32(r) => (OperandKind::ControlRegister(r), 32),
+        Parameter::Imm8(v) => (OperandKind::Immediate(u32::from(v)), 8),
+        Parameter::ImmS8(v) => (OperandKind::Immediate(v as u32), 8),
+        Parameter::Imm16(v) => (OperandKind::Immediate(u32::from(v)), 16),
+        Parameter::Imm32(v) => (OperandKind::Immediate(v), 32),
+        Parameter::Ptr16Imm(seg, off) => (OperandKind::FarPointer(seg, off), 32),
+
+        Parameter::Ptr8(_, off) => memory(8, None, i32::from(off)),
+        Parameter::Ptr8Amode(_, ref amode) => memory(8, Some(amode), 0),
+        Parameter::Ptr8AmodeS8(_, ref amode, d) => memory(8, Some(amode), i32::from(d)),
+        Parameter::Ptr8AmodeS16(_, ref amode, d) => memory(8, Some(amode), i32::from(d)),
+        Parameter::Ptr8AmodeS32(_, ref amode, d) => memory(8, Some(amode), d),
+
+        Parameter::Ptr16(_, off) => memory(16, None, i32::from(off)),
+        Parameter::Ptr16Amode(_, ref amode) => memory(16, Some(amode), 0),
+        Parameter::Ptr16AmodeS8(_, ref amode, d) => memory(16, Some(amode), i32::from(d)),
+        Parameter::Ptr16AmodeS16(_, ref amode, d) => memory(16, Some(amode), i32::from(d)),
+        Parameter::Ptr16AmodeS32(_, ref amode, d) => memory(16, Some(amode), d),
+
+        Parameter::Ptr32(_, off) => memory(32, None, i32::from(off)),
+        Parameter::Ptr32Amode(_, ref amode) => memory(32, Some(amode), 0),
+        Parameter::Ptr32AmodeS8(_, ref amode, d) => memory(32, Some(amode), i32::from(d)),
+        Parameter::Ptr32AmodeS16(_, ref amode, d) => memory(32, Some(amode), i32::from(d)),
+        Parameter::Ptr32AmodeS32(_, ref amode, d) => memory(32, Some(amode), d),
+    };
+    Some(Operand { kind, size, access })
+}
+
+/// returns the base and index registers of `amode`
+fn amode_registers(amode: &AMode) -> Vec<R> {
+    match *amode {
+        AMode::BXSI => vec![R::BX, R::SI],
+        AMode::BXDI => vec![R::BX, R::DI],
+        AMode::BPSI => vec![R::BP, R::SI],
+        AMode::BPDI => vec![R::BP, R::DI],
+        AMode::SI => vec![R::SI],
+        AMode::DI => vec![R::DI],
+        AMode::BP => vec![R::BP],
+        AMode::BX => vec![R::BX],
+        AMode::EAX => vec![R::EAX],
+        AMode::ECX => vec![R::ECX],
+        AMode::EDX => vec![R::EDX],
+        AMode::EBX => vec![R::EBX],
+        AMode::ESP => vec![R::ESP],
+        AMode::EBP => vec![R::EBP],
+        AMode::ESI => vec![R::ESI],
+        AMode::EDI => vec![R::EDI],
+        AMode::SIB(base, index, _) => base.into_iter().chain(index).collect(),
+    }
+}
+
+/// adds the registers and memory used by `op` without being operands
+fn implicit_access(op: &Instruction, a: &mut InstructionAnalysis) {
+    let (reads, writes, memory): (&[R], &[R], Access) = match op.command {
+        Op::Mul8 | Op::Imul8 => (&[R::AL], &[R::AX], Access::None),
+        Op::Mul16 | Op::Imul16 if op.params.src == Parameter::None => (&[R::AX], &[R::AX, R::DX], Access::None),
+        Op::Mul32 | Op::Imul32 if op.params.src == Parameter::None => (&[R::EAX], &[R::EAX, R::EDX], Access::None),
+        Op::Div8 | Op::Idiv8 => (&[R::AX], &[R::AX], Access::None),
+        Op::Div16 | Op::Idiv16 => (&[R::AX, R::DX], &[R::AX, R::DX], Access::None),
+        Op::Div32 | Op::Idiv32 => (&[R::EAX, R::EDX], &[R::EAX, R::EDX], Access::None),
+        Op::Cbw => (&[R::AL], &[R::AX], Access::None),
+        Op::Cwd16 => (&[R::AX], &[R::DX], Access::None),
+        Op::Cwde32 => (&[R::AX], &[R::EAX], Access::None),
+        Op::Aaa | Op::Aas | Op::Aam | Op::Aad => (&[R::AX], &[R::AX], Access::None),
+        Op::Daa | Op::Das => (&[R::AL], &[R::AL], Access::None),
+        Op::Lahf => (&[], &[R::AH], Access::None),
+        Op::Sahf => (&[R::AH], &[], Access::None),
+        Op::Salc => (&[], &[R::AL], Access::None),
+        Op::Xlatb => (&[R::BX, R::AL, R::DS], &[R::AL], Access::Read),
+        Op::Lds => (&[], &[R::DS], Access::None),
+        Op::Les => (&[], &[R::ES], Access::None),
+
+        Op::Push16 | Op::Push32 | Op::Pushf | Op::CallNear => (&[R::SP, R::SS], &[R::SP], Access::Write),
+        Op::CallFar => (&[R::SP, R::SS, R::CS], &[R::SP, R::CS], Access::Write),
+        Op::Pop16 | Op::Pop32 | Op::Popf | Op::Retn | Op::RetImm16 => (&[R::SP, R::SS], &[R::SP], Access::Read),
+        Op::Retf | Op::Iret => (&[R::SP, R::SS], &[R::SP, R::CS], Access::Read),
+        Op::Int | Op::Into => (&[R::SP, R::SS, R::CS], &[R::SP, R::CS], Access::ReadWrite),
+        Op::Pusha16 | Op::Pushad32 => (&[R::AX, R::CX, R::DX, R::BX, R::SP, R::BP, R::SI, R::DI, R::SS], &[R::SP], Access::Write),
+        Op::Popa16 | Op::Popad32 => (&[R::SP, R::SS], &[R::AX, R::CX, R::DX, R::BX, R::SP, R::BP, R::SI, R::DI], Access::Read),
+        Op::Enter => (&[R::SP, R::BP, R::SS], &[R::SP, R::BP], Access::ReadWrite),
+        Op::Leave => (&[R::BP, R::SS], &[R::SP, R::BP], Access::Read),
+        Op::JmpFar if matches!(op.params.dst, Parameter::Ptr16Imm(_, _)) => (&[], &[R::CS], Access::None),
+        Op::JmpFar => (&[], &[R::CS], Access::Read),
+
+        Op::Loop | Op::Loope | Op::Loopne => (&[R::CX], &[R::CX], Access::None),
+        Op::Jcxz => (&[R::CX], &[], Access::None),
+
+        Op::Movsb | Op::Movsw | Op::Movsd => (&[R::SI, R::DI, R::ES], &[R::SI, R::DI], Access::ReadWrite),
+        Op::Cmpsb | Op::Cmpsw | Op::Cmpsd => (&[R::SI, R::DI, R::ES], &[R::SI, R::DI], Access::Read),
+        Op::Lodsb => (&[R::SI], &[R::SI, R::AL], Access::Read),
+        Op::Lodsw => (&[R::SI], &[R::SI, R::AX], Access::Read),
+        Op::Lodsd => (&[R::SI], &[R::SI, R::EAX], Access::Read),
+        Op::Stosb => (&[R::DI, R::AL, R::ES], &[R::DI], Access::Write),
+        Op::Stosw => (&[R::DI, R::AX, R::ES], &[R::DI], Access::Write),
+        Op::Stosd => (&[R::DI, R::EAX, R::ES], &[R::DI], Access::Write),
+        Op::Scasb => (&[R::DI, R::AL, R::ES], &[R::DI], Access::Read),
+        Op::Scasw => (&[R::DI, R::AX, R::ES], &[R::DI], Access::Read),
+        Op::Scasd => (&[R::DI, R::EAX, R::ES], &[R::DI], Access::Read),
+        Op::Insb | Op::Insw | Op::Insd => (&[R::DI, R::DX, R::ES], &[R::DI], Access::Write),
+        Op::Outsb | Op::Outsw | Op::Outsd => (&[R::SI, R::DX], &[R::SI], Access::Read),
+        _ => (&[], &[], Access::None),
+    };
+    a.reads.extend_from_slice(reads);
+    a.writes.extend_from_slice(writes);
+    a.memory = a.memory.merge(memory);
+
+    // the source of string instructions is addressed by ds, unless overridden
+    if matches!(op.command, Op::Movsb | Op::Movsw | Op::Movsd | Op::Cmpsb | Op::Cmpsw | Op::Cmpsd |
+        Op::Lodsb | Op::Lodsw | Op::Lodsd | Op::Outsb | Op::Outsw | Op::Outsd) {
+        a.reads.push(op.segment_prefix.as_register());
+    }
+    if op.repeat != RepeatMode::None {
+        a.reads.push(R::CX);
+        a.writes.push(R::CX);
+    }
+}
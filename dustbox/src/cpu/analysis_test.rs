@@ -0,0 +1,84 @@
+use crate::cpu::analysis::{overlaps, Access, MemoryOperand, OperandKind};
+use crate::cpu::{BranchTarget, InstructionInfo, R};
+use crate::machine::Machine;
+
+/// decodes the instructions of `code` loaded at 085F:0100
+fn decode(code: &[u8]) -> Vec<InstructionInfo> {
+    let mut machine = Machine::deterministic();
+    machine.load_executable(code, 0x085F);
+    let mut offset = 0x100;
+    let mut ops = Vec::new();
+    while offset < 0x100 + code.len() as u16 {
+        let op = machine.cpu.decoder.get_instruction_info(&mut machine.mmu, 0x085F, offset);
+        offset += op.bytes.len() as u16;
+        ops.push(op);
+    }
+    ops
+}
+
+#[test]
+fn can_analyze_operands() {
+    let ops = decode(&[
+        0x8B, 0x46, 0xFE,   // mov ax,[bp-0x2]
+        0x26, 0x01, 0x07,   // add [es:bx],ax
+        0x8D, 0x38,         // lea di,[bx+si]
+    ]);
+
+    let a = ops[0].analysis();
+    assert_eq!(OperandKind::Register(R::AX), a.operands[0].kind);
+    assert_eq!(Access::Write, a.operands[0].access);
+    assert_eq!(OperandKind::Memory(MemoryOperand { segment: R::SS, registers: vec![R::BP], displacement: -2 }), a.operands[1].kind);
+    assert_eq!(16, a.operands[1].size);
+    assert_eq!(vec![R::SS, R::BP], a.reads);
+    assert_eq!(vec![R::AX], a.writes);
+    assert_eq!(Access::Read, a.memory);
+
+    let a = ops[1].analysis();
+    assert_eq!(vec![R::ES, R::BX, R::AX], a.reads);
+    assert!(a.writes.is_empty());
+    assert_eq!(Access::ReadWrite, a.memory);
+
+    let a = ops[2].analysis();
+    assert_eq!(vec![R::DS, R::BX, R::SI], a.reads);
+    assert_eq!(vec![R::DI], a.writes);
+    assert_eq!(Access::None, a.memory);
+}
+
+#[test]
+fn can_analyze_implicit_registers() {
+    let ops = decode(&[
+        0xF7, 0xE3,         // mul bx
+        0xF3, 0xA5,         // rep movsw
+        0x50,               // push ax
+        0xE2, 0xFB,         // loop 0x102
+    ]);
+
+    let a = ops[0].analysis();
+    assert!(a.reads_register(R::BX) && a.reads_register(R::AL));
+    assert!(a.writes_register(R::DX) && a.writes_register(R::AH));
+    assert!(!a.writes_register(R::BX));
+
+    let a = ops[1].analysis();
+    assert!(a.reads_register(R::CX) && a.writes_register(R::CX));
+    assert!(a.writes_register(R::SI) && a.writes_register(R::DI));
+    assert!(a.reads_register(R::DS) && a.reads_register(R::ES));
+    assert_eq!(Access::ReadWrite, a.memory);
+
+    let a = ops[2].analysis();
+    assert_eq!(vec![R::AX, R::SP, R::SS], a.reads);
+    assert_eq!(vec![R::SP], a.writes);
+    assert_eq!(Access::Write, a.memory);
+
+    let a = ops[3].analysis();
+    assert!(a.writes_register(R::CX));
+    assert_eq!(Some(BranchTarget { segment: 0x085F, offset: 0x102 }), a.branch_target);
+}
+
+#[test]
+fn can_tell_overlapping_registers() {
+    assert!(overlaps(R::AL, R::AX));
+    assert!(overlaps(R::EAX, R::AH));
+    assert!(!overlaps(R::AL, R::AH));
+    assert!(!overlaps(R::AX, R::BX));
+    assert!(overlaps(R::DS, R::DS));
+}
@@ -0,0 +1,1343 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::cpu::encoder::Encoder;
+use crate::cpu::instruction::{Instruction, RepeatMode};
+use crate::cpu::op::Op;
+use crate::cpu::parameter::Parameter;
+use crate::cpu::register::{R, AMode, r8, r16, r32, sr, fpr};
+use crate::cpu::segment::Segment;
+use crate::cpu::decoder::AddressSize;
+
+#[cfg(test)]
+#[path = "./assembler_test.rs"]
+mod assembler_test;
+
+/// max number of passes used to resolve label offsets
+const MAX_PASSES: usize = 16;
+
+#[derive(Debug)]
+pub struct AssembleError {
+    /// 1-based line number of the source text
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+/// assembles NASM-like source text to machine code, starting at offset 0 unless an "org"
+/// directive is used. panics on errors, use `assemble_at` to handle them
+pub fn assemble(text: &str) -> Vec<u8> {
+    match assemble_at(text, 0) {
+        Ok(code) => code,
+        Err(e) => panic!("assemble failed: {}", e),
+    }
+}
+
+/// assembles NASM-like source text to machine code located at `origin`.
+///
+/// supports one instruction per line, "label:" definitions, ";" comments,
+/// byte/word/dword/short/near/far operand modifiers and the org, bits, db, dw and dd directives
+pub fn assemble_at(text: &str, origin: u16) -> Result<Vec<u8>, AssembleError> {
+    let statements = parse(text)?;
+
+    // labels are resolved with the offsets of the previous pass, until they are stable
+    let mut labels = HashMap::new();
+    for _ in 0..MAX_PASSES {
+        let (_, next) = assemble_pass(&statements, origin, &labels, false)?;
+        if next == labels {
+            let (code, _) = assemble_pass(&statements, origin, &labels, true)?;
+            return Ok(code);
+        }
+        labels = next;
+    }
+    Err(AssembleError { line: 0, msg: "label offsets did not settle".to_owned() })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Size {
+    Byte,
+    Word,
+    Dword,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Distance {
+    Default,
+    Short,
+    Near,
+    Far,
+}
+
+#[derive(Clone, Debug)]
+enum Term {
+    Number(i64),
+    Label(String),
+    /// "$", the offset of the current instruction
+    Here,
+}
+
+/// a sum of terms, the bool is true for subtracted terms
+#[derive(Clone, Debug)]
+struct Expr(Vec<(bool, Term)>);
+
+#[derive(Clone, Debug)]
+enum Operand {
+    Reg(Parameter),
+    Imm(Option<Size>, Expr),
+    Mem(Option<Size>, Segment, Vec<(R, u8)>, Expr),
+    Far(Expr, Expr),
+}
+
+/// an operand with expressions evaluated
+#[derive(Clone, Debug)]
+enum Arg {
+    Reg(Parameter),
+    Imm(Option<Size>, i64),
+    Mem(Option<Size>, Segment, Vec<(R, u8)>, i64),
+    Far(u16, u16),
+}
+
+#[derive(Debug)]
+enum DataItem {
+    Bytes(Vec<u8>),
+    Value(Expr),
+}
+
+#[derive(Debug)]
+enum Kind {
+    Empty,
+    Org(Expr),
+    Data(Size, Vec<DataItem>),
+    Instruction {
+        mnemonic: String,
+        lock: bool,
+        repeat: RepeatMode,
+        segment: Segment,
+        distance: Distance,
+        operands: Vec<Operand>,
+    },
+}
+
+#[derive(Debug)]
+struct Statement {
+    line: usize,
+    labels: Vec<String>,
+    kind: Kind,
+}
+
+fn parse(text: &str) -> Result<Vec<Statement>, AssembleError> {
+    let mut statements = Vec::new();
+    let mut defined: Vec<String> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let statement = parse_line(i + 1, line).map_err(|msg| AssembleError { line: i + 1, msg })?;
+        for label in &statement.labels {
+            if defined.contains(label) {
+                return Err(AssembleError { line: i + 1, msg: format!("label {} is already defined", label) });
+            }
+            defined.push(label.clone());
+        }
+        statements.push(statement);
+    }
+    Ok(statements)
+}
+
+fn parse_line(line: usize, text: &str) -> Result<Statement, String> {
+    let mut rest = strip_comment(text).trim();
+    let mut labels = Vec::new();
+
+    // "label:" definitions
+    loop {
+        let len = rest.find(|c: char| !is_label_char(c)).unwrap_or(rest.len());
+        if len == 0 || !rest[len..].starts_with(':') || segment_from_name(&rest[..len]).is_some() {
+            break;
+        }
+        labels.push(rest[..len].to_owned());
+        rest = rest[len + 1..].trim_start();
+    }
+
+    let mut lock = false;
+    let mut repeat = RepeatMode::None;
+    let mut segment = Segment::Default;
+    let mut mnemonic = String::new();
+    while !rest.is_empty() {
+        let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let word = rest[..len].to_lowercase();
+        rest = rest[len..].trim_start();
+        match word.as_ref() {
+            "lock" => lock = true,
+            "rep" => repeat = RepeatMode::Rep,
+            "repe" | "repz" => repeat = RepeatMode::Repe,
+            "repne" | "repnz" => repeat = RepeatMode::Repne,
+            _ => match segment_from_name(&word) {
+                Some(seg) => segment = seg,
+                None => {
+                    mnemonic = word;
+                    break;
+                }
+            },
+        }
+    }
+
+    let kind = match mnemonic.as_ref() {
+        "" => {
+            if lock || repeat != RepeatMode::None || segment != Segment::Default {
+                return Err("prefix without instruction".to_owned());
+            }
+            Kind::Empty
+        }
+        "org" => Kind::Org(parse_expr(rest)?),
+        "bits" => {
+            if rest != "16" {
+                return Err(format!("unsupported bits {}", rest));
+            }
+            Kind::Empty
+        }
+        "db" | "dw" | "dd" => {
+            let size = match mnemonic.as_ref() {
+                "db" => Size::Byte,
+                "dw" => Size::Word,
+                _ => Size::Dword,
+            };
+            let mut items = Vec::new();
+            for item in split_operands(rest) {
+                match quoted(item) {
+                    Some(s) => items.push(DataItem::Bytes(s.bytes().collect())),
+                    None => items.push(DataItem::Value(parse_expr(item)?)),
+                }
+            }
+            Kind::Data(size, items)
+        }
+        _ => {
+            let mut distance = Distance::Default;
+            let mut operands = Vec::new();
+            if !rest.is_empty() {
+                for s in split_operands(rest) {
+                    operands.push(parse_operand(s, &mut distance)?);
+                }
+            }
+            Kind::Instruction { mnemonic, lock, repeat, segment, distance, operands }
+        }
+    };
+    Ok(Statement { line, labels, kind })
+}
+
+/// removes a ";" comment, ignoring ";" in quoted strings
+fn strip_comment(s: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == ';' => return &s[..i],
+            None => {}
+        }
+    }
+    s
+}
+
+/// splits operands on commas outside of brackets and quotes
+fn split_operands(s: &str) -> Vec<&str> {
+    let mut res = Vec::new();
+    let mut quote = None;
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '\'' | '"' => quote = Some(c),
+                '[' | '(' => depth += 1,
+                ']' | ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    res.push(s[start..i].trim());
+                    start = i + 1;
+                }
+                _ => {}
+            },
+        }
+    }
+    res.push(s[start..].trim());
+    res
+}
+
+fn is_label_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '@'
+}
+
+/// returns the content of a quoted string
+fn quoted(s: &str) -> Option<&str> {
+    if s.len() >= 2 && (s.starts_with('\'') && s.ends_with('\'') || s.starts_with('"') && s.ends_with('"')) {
+        Some(&s[1..s.len() - 1])
+    } else {
+        None
+    }
+}
+
+fn segment_from_name(name: &str) -> Option<Segment> {
+    match name.to_lowercase().as_ref() {
+        "cs" => Some(Segment::CS),
+        "ds" => Some(Segment::DS),
+        "es" => Some(Segment::ES),
+        "fs" => Some(Segment::FS),
+        "gs" => Some(Segment::GS),
+        "ss" => Some(Segment::SS),
+        _ => None,
+    }
+}
+
+fn register_from_name(name: &str) -> Option<Parameter> {
+    let name = name.to_lowercase();
+    let name = match name.as_ref() {
+        "st" => "st0".to_owned(),
+        _ if name.starts_with("st(") && name.ends_with(')') => format!("st{}", &name[3..name.len() - 1]),
+        _ => name,
+    };
+    for i in 0..8 {
+        if name == r8(i).to_string() {
+            return Some(Parameter::Reg8(r8(i)));
+        }
+        if name == r16(i).to_string() {
+            return Some(Parameter::Reg16(r16(i)));
+        }
+        if name == r32(i).to_string() {
+            return Some(Parameter::Reg32(r32(i)));
+        }
+        if name == fpr(i).to_string() {
+            return Some(Parameter::FPR80(fpr(i)));
+        }
+        if i < 6 && name == sr(i).to_string() {
+            return Some(Parameter::SReg16(sr(i)));
+        }
+    }
+    None
+}
+
+fn parse_operand(s: &str, distance: &mut Distance) -> Result<Operand, String> {
+    let mut s = s.trim();
+    let mut size = None;
+    loop {
+        let len = s.find(char::is_whitespace).unwrap_or(s.len());
+        match s[..len].to_lowercase().as_ref() {
+            "byte" => size = Some(Size::Byte),
+            "word" => size = Some(Size::Word),
+            "dword" => size = Some(Size::Dword),
+            "short" => *distance = Distance::Short,
+            "near" => *distance = Distance::Near,
+            "far" => *distance = Distance::Far,
+            _ => break,
+        }
+        s = s[len..].trim_start();
+    }
+    if s.is_empty() {
+        return Err("missing operand".to_owned());
+    }
+
+    if let Some(open) = s.find('[') {
+        if !s.ends_with(']') {
+            return Err(format!("invalid memory operand {}", s));
+        }
+        // segment override as "es:[bx]" or "[es:bx]"
+        let mut seg = Segment::Default;
+        let outer = s[..open].trim().trim_end_matches(':').trim();
+        if !outer.is_empty() {
+            seg = segment_from_name(outer).ok_or_else(|| format!("invalid segment {}", outer))?;
+        }
+        let mut inner = s[open + 1..s.len() - 1].trim();
+        if let Some(pos) = inner.find(':') {
+            seg = segment_from_name(inner[..pos].trim()).ok_or_else(|| format!("invalid segment {}", &inner[..pos]))?;
+            inner = inner[pos + 1..].trim();
+        }
+        let mut regs = Vec::new();
+        let mut terms = Vec::new();
+        for (negate, term) in split_terms(inner)? {
+            let (reg, scale) = match term.find('*') {
+                Some(pos) => {
+                    let (a, b) = (term[..pos].trim(), term[pos + 1..].trim());
+                    match (register_from_name(a), register_from_name(b)) {
+                        (Some(r), None) => (Some(r), parse_number(b)),
+                        (None, Some(r)) => (Some(r), parse_number(a)),
+                        _ => return Err(format!("invalid scaled index {}", term)),
+                    }
+                }
+                None => (register_from_name(term), Some(1)),
+            };
+            match reg {
+                Some(Parameter::Reg16(r)) | Some(Parameter::Reg32(r)) if !negate => match scale {
+                    Some(scale @ 1) | Some(scale @ 2) | Some(scale @ 4) | Some(scale @ 8) => regs.push((r, scale as u8)),
+                    _ => return Err(format!("invalid scale in {}", term)),
+                },
+                Some(_) => return Err(format!("invalid register in memory operand {}", s)),
+                None => terms.push((negate, parse_term(term)?)),
+            }
+        }
+        return Ok(Operand::Mem(size, seg, regs, Expr(terms)));
+    }
+
+    if let Some(reg) = register_from_name(s) {
+        return Ok(Operand::Reg(reg));
+    }
+    if quoted(s).is_none() {
+        if let Some(pos) = s.find(':') {
+            return Ok(Operand::Far(parse_expr(&s[..pos])?, parse_expr(&s[pos + 1..])?));
+        }
+    }
+    Ok(Operand::Imm(size, parse_expr(s)?))
+}
+
+/// splits "a+b-c" into signed terms
+fn split_terms(s: &str) -> Result<Vec<(bool, &str)>, String> {
+    let mut res = Vec::new();
+    let mut negate = false;
+    let mut start = 0;
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == '+' || c == '-' => {
+                let term = s[start..i].trim();
+                if !term.is_empty() {
+                    res.push((negate, term));
+                    negate = false;
+                } else if !res.is_empty() && start != 0 {
+                    return Err(format!("invalid expression {}", s));
+                }
+                if c == '-' {
+                    negate = !negate;
+                }
+                start = i + 1;
+            }
+            None => {}
+        }
+    }
+    let term = s[start..].trim();
+    if term.is_empty() {
+        return Err(format!("invalid expression {}", s));
+    }
+    res.push((negate, term));
+    Ok(res)
+}
+
+fn parse_expr(s: &str) -> Result<Expr, String> {
+    let mut terms = Vec::new();
+    for (negate, term) in split_terms(s.trim())? {
+        terms.push((negate, parse_term(term)?));
+    }
+    Ok(Expr(terms))
+}
+
+fn parse_term(s: &str) -> Result<Term, String> {
+    if s == "$" {
+        return Ok(Term::Here);
+    }
+    if let Some(q) = quoted(s) {
+        let mut v = 0;
+        for (i, b) in q.bytes().enumerate().take(4) {
+            v |= i64::from(b) << (i * 8);
+        }
+        return Ok(Term::Number(v));
+    }
+    if s.starts_with(|c: char| c.is_ascii_digit()) {
+        return match parse_number(s) {
+            Some(v) => Ok(Term::Number(v)),
+            None => Err(format!("invalid number {}", s)),
+        };
+    }
+    if !s.is_empty() && s.chars().all(is_label_char) {
+        return Ok(Term::Label(s.to_owned()));
+    }
+    Err(format!("invalid expression {}", s))
+}
+
+/// parses a number as "0x1F", "1Fh", "0b101" or "31"
+fn parse_number(s: &str) -> Option<i64> {
+    let x = s.replace("_", "").to_lowercase();
+    if let Some(hex) = x.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(hex) = x.strip_suffix('h') {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = x.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()
+    } else {
+        x.parse::<i64>().ok()
+    }
+}
+
+/// state while assembling one pass over the statements
+struct Pass<'a> {
+    labels: &'a HashMap<String, u16>,
+    final_pass: bool,
+    /// offset of the current statement
+    here: u16,
+}
+
+impl Pass<'_> {
+    fn eval(&self, expr: &Expr) -> Result<i64, String> {
+        let mut sum = 0;
+        for (negate, term) in &expr.0 {
+            let v = match term {
+                Term::Number(v) => *v,
+                Term::Here => i64::from(self.here),
+                Term::Label(name) => match self.labels.get(name) {
+                    Some(v) => i64::from(*v),
+                    // forward references use the current offset until the label is known
+                    None if !self.final_pass => i64::from(self.here),
+                    None => return Err(format!("undefined label {}", name)),
+                },
+            };
+            sum = if *negate { sum - v } else { sum + v };
+        }
+        Ok(sum)
+    }
+
+    fn eval_operand(&self, operand: &Operand) -> Result<Arg, String> {
+        Ok(match operand {
+            Operand::Reg(r) => Arg::Reg(r.clone()),
+            Operand::Imm(size, expr) => Arg::Imm(*size, self.eval(expr)?),
+            Operand::Mem(size, seg, regs, expr) => Arg::Mem(*size, *seg, regs.clone(), self.eval(expr)?),
+            Operand::Far(seg, off) => Arg::Far(to_u16(self.eval(seg)?)?, to_u16(self.eval(off)?)?),
+        })
+    }
+}
+
+fn assemble_pass(statements: &[Statement], origin: u16, labels: &HashMap<String, u16>, final_pass: bool)
+    -> Result<(Vec<u8>, HashMap<String, u16>), AssembleError> {
+
+    let encoder = Encoder::new();
+    let mut pass = Pass { labels, final_pass, here: origin };
+    let mut code = Vec::new();
+    let mut found = HashMap::new();
+    for statement in statements {
+        let err = |msg| AssembleError { line: statement.line, msg };
+        for label in &statement.labels {
+            found.insert(label.clone(), pass.here);
+        }
+        let data = match statement.kind {
+            Kind::Empty => vec!(),
+            Kind::Org(ref expr) => {
+                if !code.is_empty() {
+                    return Err(err("org must be used before any code".to_owned()));
+                }
+                pass.here = to_u16(pass.eval(expr).map_err(err)?).map_err(err)?;
+                for label in &statement.labels {
+                    found.insert(label.clone(), pass.here);
+                }
+                vec!()
+            }
+            Kind::Data(size, ref items) => {
+                let mut data = Vec::new();
+                for item in items {
+                    match item {
+                        DataItem::Bytes(bytes) if size == Size::Byte => data.extend(bytes),
+                        DataItem::Bytes(bytes) => {
+                            // strings in dw and dd are padded to the data size
+                            let n = if size == Size::Word { 2 } else { 4 };
+                            let mut bytes = bytes.clone();
+                            while bytes.len() % n != 0 {
+                                bytes.push(0);
+                            }
+                            data.extend(bytes);
+                        }
+                        DataItem::Value(expr) => {
+                            let v = pass.eval(expr).map_err(err)?;
+                            match immediate(size, v).map_err(err)? {
+                                Parameter::Imm8(v) => data.push(v),
+                                Parameter::Imm16(v) => data.extend(&v.to_le_bytes()),
+                                Parameter::Imm32(v) => data.extend(&v.to_le_bytes()),
+                                _ => unreachable!(),
+                            }
+                        }
+                    }
+                }
+                data
+            }
+            Kind::Instruction { ref mnemonic, lock, repeat, segment, distance, ref operands } => {
+                let mut args = Vec::new();
+                for operand in operands {
+                    args.push(pass.eval_operand(operand).map_err(err)?);
+                }
+                let mut op = build_instruction(&encoder, mnemonic, &args, distance, pass.here).map_err(err)?;
+                op.lock = lock;
+                op.repeat = repeat;
+                if segment != Segment::Default {
+                    op.segment_prefix = segment;
+                }
+                match encoder.encode_at(&op, pass.here) {
+                    Ok(data) => data,
+                    Err(e) if final_pass => return Err(err(format!("{}", e))),
+                    // branch targets may be out of range until all labels are known
+                    Err(_) => vec!(0; 2),
+                }
+            }
+        };
+        pass.here = pass.here.wrapping_add(data.len() as u16);
+        code.extend(data);
+    }
+    Ok((code, found))
+}
+
+fn to_u16(v: i64) -> Result<u16, String> {
+    if v < i64::from(i16::MIN) || v > i64::from(u16::MAX) {
+        return Err(format!("value {} out of range", v));
+    }
+    Ok(v as u16)
+}
+
+fn immediate(size: Size, v: i64) -> Result<Parameter, String> {
+    match size {
+        Size::Byte if v >= i64::from(i8::MIN) && v <= i64::from(u8::MAX) => Ok(Parameter::Imm8(v as u8)),
+        Size::Word if v >= i64::from(i16::MIN) && v <= i64::from(u16::MAX) => Ok(Parameter::Imm16(v as u16)),
+        Size::Dword if v >= i64::from(i32::MIN) && v <= i64::from(u32::MAX) => Ok(Parameter::Imm32(v as u32)),
+        _ => Err(format!("value {} out of range", v)),
+    }
+}
+
+/// returns a signed byte immediate if the value fits, as used by the sign-extending encodings
+fn immediate_s8(size: Size, v: i64) -> Result<Parameter, String> {
+    if size != Size::Byte && v >= i64::from(i8::MIN) && v <= i64::from(i8::MAX) {
+        Ok(Parameter::ImmS8(v as i8))
+    } else {
+        immediate(size, v)
+    }
+}
+
+fn register_size(p: &Parameter) -> Option<Size> {
+    match p {
+        Parameter::Reg8(_) => Some(Size::Byte),
+        Parameter::Reg16(_) | Parameter::SReg16(_) => Some(Size::Word),
+        Parameter::Reg32(_) => Some(Size::Dword),
+        _ => None,
+    }
+}
+
+/// returns the operation size from the register operands, or from a size modifier
+fn operation_size(args: &[Arg]) -> Result<Size, String> {
+    let mut size = None;
+    for arg in args {
+        if let Arg::Reg(p) = arg {
+            if let Some(s) = register_size(p) {
+                match size {
+                    Some(prev) if prev != s => return Err("operand size mismatch".to_owned()),
+                    _ => size = Some(s),
+                }
+            }
+        }
+    }
+    if let Some(size) = size {
+        return Ok(size);
+    }
+    for arg in args {
+        match arg {
+            Arg::Mem(Some(s), _, _, _) | Arg::Imm(Some(s), _) => return Ok(*s),
+            _ => {}
+        }
+    }
+    Err("operation size not specified".to_owned())
+}
+
+fn sized(ops: [Op; 3], size: Size) -> Op {
+    let [op8, op16, op32] = ops;
+    match size {
+        Size::Byte => op8,
+        Size::Word => op16,
+        Size::Dword => op32,
+    }
+}
+
+/// memory operand forms, before the operand size is applied
+enum Memory {
+    Direct(u16),
+    Amode(AMode),
+    S8(AMode, i8),
+    S16(AMode, i16),
+    S32(AMode, i32),
+}
+
+/// returns the Parameter of a memory operand, and true if it needs 32-bit addressing
+fn memory(size: Size, seg: Segment, regs: &[(R, u8)], disp: i64) -> Result<(Parameter, bool), String> {
+    let is32 = regs.iter().any(|(r, _)| is_reg32(*r));
+    let mem = if regs.is_empty() {
+        Memory::Direct(to_u16(disp)?)
+    } else if is32 {
+        memory32(regs, disp)?
+    } else {
+        memory16(regs, disp)?
+    };
+    let p = match (size, mem) {
+        (Size::Byte, Memory::Direct(v)) => Parameter::Ptr8(seg, v),
+        (Size::Byte, Memory::Amode(a)) => Parameter::Ptr8Amode(seg, a),
+        (Size::Byte, Memory::S8(a, v)) => Parameter::Ptr8AmodeS8(seg, a, v),
+        (Size::Byte, Memory::S16(a, v)) => Parameter::Ptr8AmodeS16(seg, a, v),
+        (Size::Byte, Memory::S32(a, v)) => Parameter::Ptr8AmodeS32(seg, a, v),
+        (Size::Word, Memory::Direct(v)) => Parameter::Ptr16(seg, v),
+        (Size::Word, Memory::Amode(a)) => Parameter::Ptr16Amode(seg, a),
+        (Size::Word, Memory::S8(a, v)) => Parameter::Ptr16AmodeS8(seg, a, v),
+        (Size::Word, Memory::S16(a, v)) => Parameter::Ptr16AmodeS16(seg, a, v),
+        (Size::Word, Memory::S32(a, v)) => Parameter::Ptr16AmodeS32(seg, a, v),
+        (Size::Dword, Memory::Direct(v)) => Parameter::Ptr32(seg, v),
+        (Size::Dword, Memory::Amode(a)) => Parameter::Ptr32Amode(seg, a),
+        (Size::Dword, Memory::S8(a, v)) => Parameter::Ptr32AmodeS8(seg, a, v),
+        (Size::Dword, Memory::S16(a, v)) => Parameter::Ptr32AmodeS16(seg, a, v),
+        (Size::Dword, Memory::S32(a, v)) => Parameter::Ptr32AmodeS32(seg, a, v),
+    };
+    Ok((p, is32))
+}
+
+fn is_reg32(r: R) -> bool {
+    matches!(r, R::EAX | R::ECX | R::EDX | R::EBX | R::ESP | R::EBP | R::ESI | R::EDI)
+}
+
+fn memory16(regs: &[(R, u8)], disp: i64) -> Result<Memory, String> {
+    let mut base = None;
+    let mut index = None;
+    for (r, scale) in regs {
+        match (r, scale) {
+            (R::BX, 1) | (R::BP, 1) if base.is_none() => base = Some(*r),
+            (R::SI, 1) | (R::DI, 1) if index.is_none() => index = Some(*r),
+            _ => return Err("invalid effective address".to_owned()),
+        }
+    }
+    let amode = match (base, index) {
+        (Some(R::BX), Some(R::SI)) => AMode::BXSI,
+        (Some(R::BX), Some(R::DI)) => AMode::BXDI,
+        (Some(R::BP), Some(R::SI)) => AMode::BPSI,
+        (Some(R::BP), Some(R::DI)) => AMode::BPDI,
+        (None, Some(R::SI)) => AMode::SI,
+        (None, Some(R::DI)) => AMode::DI,
+        (Some(R::BP), None) => AMode::BP,
+        _ => AMode::BX,
+    };
+    let disp = to_u16(disp)? as i16;
+    Ok(if disp == 0 && amode != AMode::BP {
+        Memory::Amode(amode)
+    } else if disp >= i16::from(i8::MIN) && disp <= i16::from(i8::MAX) {
+        Memory::S8(amode, disp as i8)
+    } else {
+        Memory::S16(amode, disp)
+    })
+}
+
+fn memory32(regs: &[(R, u8)], disp: i64) -> Result<Memory, String> {
+    let mut base = None;
+    let mut index = None;
+    for (r, scale) in regs {
+        if !is_reg32(*r) {
+            return Err("invalid effective address".to_owned());
+        }
+        if *scale == 1 && base.is_none() {
+            base = Some(*r);
+        } else if index.is_none() {
+            index = Some((*r, *scale));
+        } else {
+            return Err("invalid effective address".to_owned());
+        }
+    }
+    // esp can only be a base
+    if let (Some(b), Some((R::ESP, 1))) = (base, index) {
+        base = Some(R::ESP);
+        index = Some((b, 1));
+    }
+    if let Some((R::ESP, _)) = index {
+        return Err("esp can't be used as index".to_owned());
+    }
+    if disp < i64::from(i32::MIN) || disp > i64::from(u32::MAX) {
+        return Err(format!("displacement {} out of range", disp));
+    }
+    let disp = disp as i32;
+    let amode = match (base, index) {
+        (Some(b), None) => match b {
+            R::EAX => AMode::EAX,
+            R::ECX => AMode::ECX,
+            R::EDX => AMode::EDX,
+            R::EBX => AMode::EBX,
+            R::ESP => AMode::ESP,
+            R::EBP => AMode::EBP,
+            R::ESI => AMode::ESI,
+            _ => AMode::EDI,
+        },
+        // index without base is always encoded with a 32-bit displacement
+        (None, Some((i, scale))) => return Ok(Memory::S32(AMode::SIB(None, Some(i), scale), disp)),
+        (b, Some((i, scale))) => AMode::SIB(b, Some(i), scale),
+        (None, None) => unreachable!(),
+    };
+    let needs_disp = matches!(amode, AMode::EBP | AMode::SIB(Some(R::EBP), _, _));
+    Ok(if disp == 0 && !needs_disp {
+        Memory::Amode(amode)
+    } else if disp >= i32::from(i8::MIN) && disp <= i32::from(i8::MAX) {
+        Memory::S8(amode, disp as i8)
+    } else {
+        Memory::S32(amode, disp)
+    })
+}
+
+fn plain_op(mnemonic: &str) -> Option<Op> {
+    Some(match mnemonic {
+        "aaa" => Op::Aaa,
+        "aas" => Op::Aas,
+        "daa" => Op::Daa,
+        "das" => Op::Das,
+        "pusha" => Op::Pusha16,
+        "pushad" => Op::Pushad32,
+        "popa" => Op::Popa16,
+        "popad" => Op::Popad32,
+        "insb" => Op::Insb,
+        "insw" => Op::Insw,
+        "insd" => Op::Insd,
+        "outsb" => Op::Outsb,
+        "outsw" => Op::Outsw,
+        "outsd" => Op::Outsd,
+        "nop" => Op::Nop,
+        "cbw" => Op::Cbw,
+        "cwde" => Op::Cwde32,
+        "cwd" => Op::Cwd16,
+        "wait" | "fwait" => Op::Fwait,
+        "pushf" => Op::Pushf,
+        "popf" => Op::Popf,
+        "sahf" => Op::Sahf,
+        "lahf" => Op::Lahf,
+        "movsb" => Op::Movsb,
+        "movsw" => Op::Movsw,
+        "movsd" => Op::Movsd,
+        "cmpsb" => Op::Cmpsb,
+        "cmpsw" => Op::Cmpsw,
+        "cmpsd" => Op::Cmpsd,
+        "stosb" => Op::Stosb,
+        "stosw" => Op::Stosw,
+        "stosd" => Op::Stosd,
+        "lodsb" => Op::Lodsb,
+        "lodsw" => Op::Lodsw,
+        "lodsd" => Op::Lodsd,
+        "scasb" => Op::Scasb,
+        "scasw" => Op::Scasw,
+        "scasd" => Op::Scasd,
+        "leave" => Op::Leave,
+        "into" => Op::Into,
+        "iret" => Op::Iret,
+        "salc" => Op::Salc,
+        "xlat" | "xlatb" => Op::Xlatb,
+        "hlt" => Op::Hlt,
+        "cmc" => Op::Cmc,
+        "clc" => Op::Clc,
+        "stc" => Op::Stc,
+        "cli" => Op::Cli,
+        "sti" => Op::Sti,
+        "cld" => Op::Cld,
+        "std" => Op::Std,
+        "fchs" => Op::Fchs,
+        "fabs" => Op::Fabs,
+        "ftst" => Op::Ftst,
+        "fld1" => Op::Fld1,
+        "fldl2t" => Op::Fldl2t,
+        "fldl2e" => Op::Fldl2e,
+        "fldpi" => Op::Fldpi,
+        "fldz" => Op::Fldz,
+        "fpatan" => Op::Fpatan,
+        "fsqrt" => Op::Fsqrt,
+        "fsincos" => Op::Fsincos,
+        "frndint" => Op::Frndint,
+        "fsin" => Op::Fsin,
+        "fcos" => Op::Fcos,
+        "finit" | "fninit" => Op::Finit,
+        _ => return None,
+    })
+}
+
+fn jcc_op(mnemonic: &str) -> Option<Op> {
+    Some(match mnemonic {
+        "jo" => Op::Jo,
+        "jno" => Op::Jno,
+        "jc" | "jb" | "jnae" => Op::Jc,
+        "jnc" | "jnb" | "jae" => Op::Jnc,
+        "jz" | "je" => Op::Jz,
+        "jnz" | "jne" => Op::Jnz,
+        "jna" | "jbe" => Op::Jna,
+        "ja" | "jnbe" => Op::Ja,
+        "js" => Op::Js,
+        "jns" => Op::Jns,
+        "jpe" | "jp" => Op::Jpe,
+        "jpo" | "jnp" => Op::Jpo,
+        "jl" | "jnge" => Op::Jl,
+        "jnl" | "jge" => Op::Jnl,
+        "jng" | "jle" => Op::Jng,
+        "jg" | "jnle" => Op::Jg,
+        "loop" => Op::Loop,
+        "loope" | "loopz" => Op::Loope,
+        "loopne" | "loopnz" => Op::Loopne,
+        "jcxz" => Op::Jcxz,
+        _ => return None,
+    })
+}
+
+fn setcc_op(mnemonic: &str) -> Option<Op> {
+    Some(match mnemonic {
+        "seto" => Op::Seto,
+        "setno" => Op::Setno,
+        "setc" | "setb" | "setnae" => Op::Setc,
+        "setnc" | "setnb" | "setae" => Op::Setnc,
+        "setz" | "sete" => Op::Setz,
+        "setnz" | "setne" => Op::Setnz,
+        "setna" | "setbe" => Op::Setna,
+        "seta" | "setnbe" => Op::Seta,
+        "sets" => Op::Sets,
+        "setns" => Op::Setns,
+        "setpe" | "setp" => Op::Setpe,
+        "setpo" | "setnp" => Op::Setpo,
+        "setl" | "setnge" => Op::Setl,
+        "setnl" | "setge" => Op::Setnl,
+        "setng" | "setle" => Op::Setng,
+        "setg" | "setnle" => Op::Setg,
+        _ => return None,
+    })
+}
+
+fn sized_ops(mnemonic: &str) -> Option<[Op; 3]> {
+    Some(match mnemonic {
+        "add" => [Op::Add8, Op::Add16, Op::Add32],
+        "or" => [Op::Or8, Op::Or16, Op::Or32],
+        "adc" => [Op::Adc8, Op::Adc16, Op::Adc32],
+        "sbb" => [Op::Sbb8, Op::Sbb16, Op::Sbb32],
+        "and" => [Op::And8, Op::And16, Op::And32],
+        "sub" => [Op::Sub8, Op::Sub16, Op::Sub32],
+        "xor" => [Op::Xor8, Op::Xor16, Op::Xor32],
+        "cmp" => [Op::Cmp8, Op::Cmp16, Op::Cmp32],
+        "mov" => [Op::Mov8, Op::Mov16, Op::Mov32],
+        "test" => [Op::Test8, Op::Test16, Op::Test32],
+        "xchg" => [Op::Xchg8, Op::Xchg16, Op::Xchg32],
+        "xadd" => [Op::Xadd8, Op::Xadd16, Op::Xadd32],
+        "cmpxchg" => [Op::Cmpxchg8, Op::Cmpxchg16, Op::Cmpxchg32],
+        "not" => [Op::Not8, Op::Not16, Op::Not32],
+        "neg" => [Op::Neg8, Op::Neg16, Op::Neg32],
+        "mul" => [Op::Mul8, Op::Mul16, Op::Mul32],
+        "imul" => [Op::Imul8, Op::Imul16, Op::Imul32],
+        "div" => [Op::Div8, Op::Div16, Op::Div32],
+        "idiv" => [Op::Idiv8, Op::Idiv16, Op::Idiv32],
+        "inc" => [Op::Inc8, Op::Inc16, Op::Inc32],
+        "dec" => [Op::Dec8, Op::Dec16, Op::Dec32],
+        "rol" => [Op::Rol8, Op::Rol16, Op::Rol32],
+        "ror" => [Op::Ror8, Op::Ror16, Op::Ror32],
+        "rcl" => [Op::Rcl8, Op::Rcl16, Op::Rcl32],
+        "rcr" => [Op::Rcr8, Op::Rcr16, Op::Rcr32],
+        "shl" | "sal" => [Op::Shl8, Op::Shl16, Op::Shl32],
+        "shr" => [Op::Shr8, Op::Shr16, Op::Shr32],
+        "sar" => [Op::Sar8, Op::Sar16, Op::Sar32],
+        _ => return None,
+    })
+}
+
+fn fpu_op(mnemonic: &str) -> Option<Op> {
+    Some(match mnemonic {
+        "fadd" => Op::Fadd,
+        "fmul" => Op::Fmul,
+        "fcom" => Op::Fcom,
+        "fcomp" => Op::Fcomp,
+        "fsub" => Op::Fsub,
+        "fsubr" => Op::Fsubr,
+        "fdiv" => Op::Fdiv,
+        "fdivr" => Op::Fdivr,
+        "fld" => Op::Fld,
+        "fxch" => Op::Fxch,
+        "fst" => Op::Fst,
+        "fstp" => Op::Fstp,
+        "fldcw" => Op::Fldcw,
+        "fnstcw" => Op::Fnstcw,
+        "ficomp" => Op::Ficomp,
+        "fild" => Op::Fild,
+        "fisttp" => Op::Fisttp,
+        "fist" => Op::Fist,
+        "fistp" => Op::Fistp,
+        "ffree" => Op::Ffree,
+        "faddp" => Op::Faddp,
+        "fimul" => Op::Fimul,
+        "ficom" => Op::Ficom,
+        "fsubrp" => Op::Fsubrp,
+        "fsubp" => Op::Fsubp,
+        "fidiv" => Op::Fidiv,
+        "fdivp" => Op::Fdivp,
+        _ => return None,
+    })
+}
+
+/// builds the Instruction from the mnemonic and the evaluated operands, located at `here`
+fn build_instruction(encoder: &Encoder, mnemonic: &str, args: &[Arg], distance: Distance, here: u16) -> Result<Instruction, String> {
+    let mut b = Builder { address_size: AddressSize::_16bit, segment: Segment::Default };
+    let mut op = b.build(encoder, mnemonic, args, distance, here)?;
+    op.address_size = b.address_size;
+    op.segment_prefix = b.segment;
+    Ok(op)
+}
+
+/// collects the prefixes needed by the memory operands of an instruction
+struct Builder {
+    address_size: AddressSize,
+    segment: Segment,
+}
+
+impl Builder {
+    fn expect_args(args: &[Arg], n: usize, mnemonic: &str) -> Result<(), String> {
+        if args.len() != n {
+            return Err(format!("{} expects {} operands", mnemonic, n));
+        }
+        Ok(())
+    }
+
+    /// returns the register or memory operand with the given size
+    fn rm(&mut self, arg: &Arg, size: Size) -> Result<Parameter, String> {
+        match arg {
+            Arg::Reg(p) if register_size(p) == Some(size) && !matches!(p, Parameter::SReg16(_)) => Ok(p.clone()),
+            Arg::Mem(s, seg, regs, disp) => {
+                if let Some(s) = s {
+                    if *s != size {
+                        return Err("operand size mismatch".to_owned());
+                    }
+                }
+                let (p, is32) = memory(size, *seg, regs, *disp)?;
+                if is32 {
+                    self.address_size = AddressSize::_32bit;
+                }
+                if *seg != Segment::Default {
+                    self.segment = *seg;
+                }
+                Ok(p)
+            }
+            _ => Err(format!("invalid operand {:?}", arg)),
+        }
+    }
+
+    /// returns the memory operand, using its size modifier if set
+    fn mem(&mut self, arg: &Arg, default: Size) -> Result<Parameter, String> {
+        match arg {
+            Arg::Mem(size, _, _, _) => self.rm(arg, size.unwrap_or(default)),
+            _ => Err(format!("expected memory operand, found {:?}", arg)),
+        }
+    }
+
+    fn reg(arg: &Arg, size: Size) -> Result<Parameter, String> {
+        match arg {
+            Arg::Reg(p) if register_size(p) == Some(size) && !matches!(p, Parameter::SReg16(_)) => Ok(p.clone()),
+            _ => Err(format!("expected register, found {:?}", arg)),
+        }
+    }
+
+    fn imm8(arg: &Arg) -> Result<Parameter, String> {
+        match arg {
+            Arg::Imm(_, v) => immediate(Size::Byte, *v),
+            _ => Err(format!("expected immediate, found {:?}", arg)),
+        }
+    }
+
+    fn target(arg: &Arg) -> Result<Parameter, String> {
+        match arg {
+            Arg::Imm(_, v) => Ok(Parameter::Imm16(to_u16(*v)?)),
+            _ => Err(format!("expected branch target, found {:?}", arg)),
+        }
+    }
+
+    fn build(&mut self, encoder: &Encoder, mnemonic: &str, args: &[Arg], distance: Distance, here: u16) -> Result<Instruction, String> {
+        if let Some(op) = plain_op(mnemonic) {
+            Builder::expect_args(args, 0, mnemonic)?;
+            return Ok(Instruction::new(op));
+        }
+        if let Some(op) = jcc_op(mnemonic) {
+            Builder::expect_args(args, 1, mnemonic)?;
+            return Ok(Instruction::new1(op, Builder::target(&args[0])?));
+        }
+        if let Some(op) = setcc_op(mnemonic) {
+            Builder::expect_args(args, 1, mnemonic)?;
+            return Ok(Instruction::new1(op, self.rm(&args[0], Size::Byte)?));
+        }
+        if let Some(op) = fpu_op(mnemonic) {
+            Builder::expect_args(args, 1, mnemonic)?;
+            let dst = match &args[0] {
+                Arg::Reg(p @ Parameter::FPR80(_)) => p.clone(),
+                arg => {
+                    let default = if op == Op::Fldcw || op == Op::Fnstcw { Size::Word } else { Size::Dword };
+                    self.mem(arg, default)?
+                }
+            };
+            return Ok(Instruction::new1(op, dst));
+        }
+        if let Some(ops) = sized_ops(mnemonic) {
+            return self.build_sized(mnemonic, ops, args);
+        }
+
+        match mnemonic {
+            "fstsw" | "fnstsw" => {
+                Builder::expect_args(args, 1, mnemonic)?;
+                Ok(Instruction::new1(Op::Fstsw, Builder::reg(&args[0], Size::Word)?))
+            }
+            "push" | "pop" => {
+                Builder::expect_args(args, 1, mnemonic)?;
+                let push = mnemonic == "push";
+                let (op16, op32) = if push { (Op::Push16, Op::Push32) } else { (Op::Pop16, Op::Pop32) };
+                match &args[0] {
+                    Arg::Reg(p @ Parameter::Reg16(_)) | Arg::Reg(p @ Parameter::SReg16(_)) => Ok(Instruction::new1(op16, p.clone())),
+                    Arg::Reg(p @ Parameter::Reg32(_)) => Ok(Instruction::new1(op32, p.clone())),
+                    Arg::Imm(size, v) if push && *size != Some(Size::Dword) => {
+                        let imm = match size {
+                            Some(Size::Word) => immediate(Size::Word, *v)?,
+                            _ => immediate_s8(Size::Word, *v)?,
+                        };
+                        Ok(Instruction::new1(Op::Push16, imm))
+                    }
+                    arg @ Arg::Mem(size, _, _, _) => {
+                        let op = if *size == Some(Size::Dword) { op32 } else { op16 };
+                        Ok(Instruction::new1(op, self.mem(arg, Size::Word)?))
+                    }
+                    arg => Err(format!("invalid operand {:?}", arg)),
+                }
+            }
+            "lea" | "les" | "lds" | "bound" => {
+                Builder::expect_args(args, 2, mnemonic)?;
+                let (op, dst) = match (mnemonic, &args[0]) {
+                    ("lea", Arg::Reg(p @ Parameter::Reg32(_))) => (Op::Lea32, p.clone()),
+                    ("lea", arg) => (Op::Lea16, Builder::reg(arg, Size::Word)?),
+                    ("les", arg) => (Op::Les, Builder::reg(arg, Size::Word)?),
+                    ("lds", arg) => (Op::Lds, Builder::reg(arg, Size::Word)?),
+                    (_, arg) => (Op::Bound, Builder::reg(arg, Size::Word)?),
+                };
+                Ok(Instruction::new2(op, dst, self.mem(&args[1], Size::Word)?))
+            }
+            "movzx" | "movsx" => {
+                Builder::expect_args(args, 2, mnemonic)?;
+                let zero = mnemonic == "movzx";
+                let (op, dst) = match &args[0] {
+                    Arg::Reg(p @ Parameter::Reg16(_)) => (if zero { Op::Movzx16 } else { Op::Movsx16 }, p.clone()),
+                    Arg::Reg(p @ Parameter::Reg32(_)) => (if zero { Op::Movzx32 } else { Op::Movsx32 }, p.clone()),
+                    arg => return Err(format!("invalid operand {:?}", arg)),
+                };
+                let src = match &args[1] {
+                    Arg::Reg(p @ Parameter::Reg8(_)) | Arg::Reg(p @ Parameter::Reg16(_)) => p.clone(),
+                    arg @ Arg::Mem(Some(size), _, _, _) => self.rm(arg, *size)?,
+                    _ => return Err("operation size not specified".to_owned()),
+                };
+                Ok(Instruction::new2(op, dst, src))
+            }
+            "in" | "out" => {
+                Builder::expect_args(args, 2, mnemonic)?;
+                let (acc, port) = if mnemonic == "in" { (&args[0], &args[1]) } else { (&args[1], &args[0]) };
+                let (op8, op16) = if mnemonic == "in" { (Op::In8, Op::In16) } else { (Op::Out8, Op::Out16) };
+                let (op, acc) = match acc {
+                    Arg::Reg(p @ Parameter::Reg8(R::AL)) => (op8, p.clone()),
+                    Arg::Reg(p @ Parameter::Reg16(R::AX)) => (op16, p.clone()),
+                    arg => return Err(format!("invalid operand {:?}", arg)),
+                };
+                let port = match port {
+                    Arg::Reg(p @ Parameter::Reg16(R::DX)) => p.clone(),
+                    arg => Builder::imm8(arg)?,
+                };
+                if mnemonic == "in" {
+                    Ok(Instruction::new2(op, acc, port))
+                } else {
+                    Ok(Instruction::new2(op, port, acc))
+                }
+            }
+            "int" => {
+                Builder::expect_args(args, 1, mnemonic)?;
+                Ok(Instruction::new1(Op::Int, Builder::imm8(&args[0])?))
+            }
+            "aam" | "aad" => {
+                let op = if mnemonic == "aam" { Op::Aam } else { Op::Aad };
+                match args.len() {
+                    0 => Ok(Instruction::new1(op, Parameter::Imm8(10))),
+                    _ => {
+                        Builder::expect_args(args, 1, mnemonic)?;
+                        Ok(Instruction::new1(op, Builder::imm8(&args[0])?))
+                    }
+                }
+            }
+            "enter" => {
+                Builder::expect_args(args, 2, mnemonic)?;
+                let size = match &args[0] {
+                    Arg::Imm(_, v) => immediate(Size::Word, *v)?,
+                    arg => return Err(format!("invalid operand {:?}", arg)),
+                };
+                Ok(Instruction::new2(Op::Enter, size, Builder::imm8(&args[1])?))
+            }
+            "ret" | "retn" | "retf" => {
+                let op = if mnemonic == "retf" { Op::Retf } else { Op::Retn };
+                match args.len() {
+                    0 => Ok(Instruction::new(op)),
+                    _ => {
+                        Builder::expect_args(args, 1, mnemonic)?;
+                        match &args[0] {
+                            Arg::Imm(_, v) => Ok(Instruction::new1(op, immediate(Size::Word, *v)?)),
+                            arg => Err(format!("invalid operand {:?}", arg)),
+                        }
+                    }
+                }
+            }
+            "jmp" | "call" => {
+                Builder::expect_args(args, 1, mnemonic)?;
+                let jmp = mnemonic == "jmp";
+                let (near, far) = if jmp { (Op::JmpNear, Op::JmpFar) } else { (Op::CallNear, Op::CallFar) };
+                match &args[0] {
+                    Arg::Far(seg, off) => Ok(Instruction::new1(far, Parameter::Ptr16Imm(*seg, *off))),
+                    arg @ Arg::Imm(_, _) => {
+                        let target = Builder::target(arg)?;
+                        if jmp && distance != Distance::Near {
+                            let short = Instruction::new1(Op::JmpShort, target.clone());
+                            if distance == Distance::Short || encoder.encode_at(&short, here).is_ok() {
+                                return Ok(short);
+                            }
+                        }
+                        Ok(Instruction::new1(near, target))
+                    }
+                    arg => {
+                        let op = if distance == Distance::Far { far } else { near };
+                        Ok(Instruction::new1(op, self.rm_or_mem16(arg)?))
+                    }
+                }
+            }
+            "sldt" | "smsw" => {
+                Builder::expect_args(args, 1, mnemonic)?;
+                let op = if mnemonic == "sldt" { Op::Sldt } else { Op::Smsw };
+                Ok(Instruction::new1(op, self.rm_or_mem16(&args[0])?))
+            }
+            "arpl" => {
+                // r/m16, r16
+                Builder::expect_args(args, 2, mnemonic)?;
+                Ok(Instruction::new2(Op::Arpl, self.rm_or_mem16(&args[0])?, Builder::reg(&args[1], Size::Word)?))
+            }
+            "bt" | "bts" | "btr" | "btc" => {
+                // r/m16, r16 or imm8
+                Builder::expect_args(args, 2, mnemonic)?;
+                let (op16, op32) = match mnemonic {
+                    "bt" => (Op::Bt16, Op::Bt32),
+                    "bts" => (Op::Bts16, Op::Bts32),
+                    "btr" => (Op::Btr16, Op::Btr32),
+                    _ => (Op::Btc16, Op::Btc32),
+                };
+                let (op, size) = match operation_size(args).unwrap_or(Size::Word) {
+                    Size::Byte => return Err("operand size mismatch".to_owned()),
+                    Size::Word => (op16, Size::Word),
+                    Size::Dword => (op32, Size::Dword),
+                };
+                let dst = self.rm(&args[0], size)?;
+                let src = match &args[1] {
+                    Arg::Imm(_, _) => Builder::imm8(&args[1])?,
+                    arg => Builder::reg(arg, size)?,
+                };
+                Ok(Instruction::new2(op, dst, src))
+            }
+            "bsf" | "bsr" => {
+                // r16, r/m16
+                Builder::expect_args(args, 2, mnemonic)?;
+                let (op, size) = match (mnemonic, operation_size(args)?) {
+                    ("bsf", Size::Word) => (Op::Bsf16, Size::Word),
+                    ("bsf", Size::Dword) => (Op::Bsf32, Size::Dword),
+                    (_, Size::Word) => (Op::Bsr16, Size::Word),
+                    (_, Size::Dword) => (Op::Bsr32, Size::Dword),
+                    _ => return Err("operand size mismatch".to_owned()),
+                };
+                Ok(Instruction::new2(op, Builder::reg(&args[0], size)?, self.rm(&args[1], size)?))
+            }
+            "bswap" => {
+                Builder::expect_args(args, 1, mnemonic)?;
+                Ok(Instruction::new1(Op::Bswap32, Builder::reg(&args[0], Size::Dword)?))
+            }
+            "lar" => {
+                // r16, r/m16
+                Builder::expect_args(args, 2, mnemonic)?;
+                Ok(Instruction::new2(Op::Lar16, Builder::reg(&args[0], Size::Word)?, self.rm_or_mem16(&args[1])?))
+            }
+            "shld" | "shrd" => {
+                Builder::expect_args(args, 3, mnemonic)?;
+                let op = if mnemonic == "shld" { Op::Shld } else { Op::Shrd };
+                Ok(Instruction::new3(op, self.rm_or_mem16(&args[0])?, Builder::reg(&args[1], Size::Word)?, Builder::imm8(&args[2])?))
+            }
+            _ => Err(format!("unknown instruction {}", mnemonic)),
+        }
+    }
+
+    /// returns a 16-bit register or memory operand
+    fn rm_or_mem16(&mut self, arg: &Arg) -> Result<Parameter, String> {
+        match arg {
+            Arg::Mem(_, _, _, _) => self.mem(arg, Size::Word),
+            _ => self.rm(arg, Size::Word),
+        }
+    }
+
+    /// builds the instructions with 8, 16 and 32-bit forms
+    fn build_sized(&mut self, mnemonic: &str, ops: [Op; 3], args: &[Arg]) -> Result<Instruction, String> {
+        match mnemonic {
+            "not" | "neg" | "mul" | "div" | "idiv" | "inc" | "dec" => {
+                Builder::expect_args(args, 1, mnemonic)?;
+                let size = operation_size(args)?;
+                Ok(Instruction::new1(sized(ops, size), self.rm(&args[0], size)?))
+            }
+            "imul" if args.len() != 2 => {
+                if args.len() == 1 {
+                    let size = operation_size(args)?;
+                    return Ok(Instruction::new1(sized(ops, size), self.rm(&args[0], size)?));
+                }
+                Builder::expect_args(args, 3, mnemonic)?;
+                let size = operation_size(&args[..2])?;
+                let imm = match &args[2] {
+                    Arg::Imm(_, v) => immediate_s8(size, *v)?,
+                    arg => return Err(format!("invalid operand {:?}", arg)),
+                };
+                if size == Size::Byte {
+                    return Err("operand size mismatch".to_owned());
+                }
+                Ok(Instruction::new3(sized(ops, size), Builder::reg(&args[0], size)?, self.rm(&args[1], size)?, imm))
+            }
+            "imul" => {
+                // imul r16, r/m16 or imul r16, imm
+                let size = operation_size(args)?;
+                if size == Size::Byte {
+                    return Err("operand size mismatch".to_owned());
+                }
+                let dst = Builder::reg(&args[0], size)?;
+                match &args[1] {
+                    Arg::Imm(_, v) => Ok(Instruction::new3(sized(ops, size), dst.clone(), dst, immediate_s8(size, *v)?)),
+                    arg => Ok(Instruction::new2(sized(ops, size), dst, self.rm(arg, size)?)),
+                }
+            }
+            "rol" | "ror" | "rcl" | "rcr" | "shl" | "sal" | "shr" | "sar" => {
+                Builder::expect_args(args, 2, mnemonic)?;
+                let size = operation_size(&args[..1])?;
+                let count = match &args[1] {
+                    Arg::Reg(Parameter::Reg8(R::CL)) => Parameter::Reg8(R::CL),
+                    arg => Builder::imm8(arg)?,
+                };
+                Ok(Instruction::new2(sized(ops, size), self.rm(&args[0], size)?, count))
+            }
+            _ => {
+                // two operand forms: add, or, adc, sbb, and, sub, xor, cmp, mov, test, xchg, xadd, cmpxchg
+                Builder::expect_args(args, 2, mnemonic)?;
+                if let (Arg::Reg(Parameter::SReg16(_)), _) | (_, Arg::Reg(Parameter::SReg16(_))) = (&args[0], &args[1]) {
+                    if mnemonic != "mov" {
+                        return Err("invalid use of segment register".to_owned());
+                    }
+                    return match (&args[0], &args[1]) {
+                        (Arg::Reg(dst @ Parameter::SReg16(_)), src) => Ok(Instruction::new2(Op::Mov16, dst.clone(), self.rm_or_mem16(src)?)),
+                        (dst, Arg::Reg(src @ Parameter::SReg16(_))) => Ok(Instruction::new2(Op::Mov16, self.rm_or_mem16(dst)?, src.clone())),
+                        _ => unreachable!(),
+                    };
+                }
+                let size = operation_size(args)?;
+                let op = sized(ops, size);
+                let dst = self.rm(&args[0], size)?;
+                let src = match &args[1] {
+                    Arg::Imm(_, v) => match mnemonic {
+                        "mov" | "test" => immediate(size, *v)?,
+                        "xchg" | "xadd" | "cmpxchg" => return Err(format!("invalid operand for {}", mnemonic)),
+                        _ => immediate_s8(size, *v)?,
+                    },
+                    arg @ Arg::Reg(_) => Builder::reg(arg, size)?,
+                    arg @ Arg::Mem(_, _, _, _) if dst.is_reg() && mnemonic != "xadd" && mnemonic != "cmpxchg" => self.rm(arg, size)?,
+                    arg => return Err(format!("invalid operand {:?}", arg)),
+                };
+                Ok(Instruction::new2(op, dst, src))
+            }
+        }
+    }
+}
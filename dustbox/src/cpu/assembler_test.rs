@@ -0,0 +1,133 @@
+use pretty_assertions::assert_eq;
+
+use crate::cpu::{assemble, assemble_at};
+use crate::cpu::R;
+use crate::machine::Machine;
+
+#[test]
+fn can_assemble_instructions() {
+    let cases: Vec<(&str, Vec<u8>)> = vec![
+        ("mov ax, 0x1234", vec!(0xB8, 0x34, 0x12)),
+        ("mov bl, 5", vec!(0xB3, 0x05)),
+        ("mov ds, ax", vec!(0x8E, 0xD8)),
+        ("add bx, 1", vec!(0x83, 0xC3, 0x01)),
+        ("add al, 10h", vec!(0x04, 0x10)),
+        ("cmp cx, 0x1000", vec!(0x81, 0xF9, 0x00, 0x10)),
+        ("int 0x21", vec!(0xCD, 0x21)),
+        ("push cs", vec!(0x0E)),
+        ("pop ds", vec!(0x1F)),
+        ("push -1", vec!(0x6A, 0xFF)),
+        ("rep movsb", vec!(0xF3, 0xA4)),
+        ("shl ax, 1", vec!(0xD1, 0xE0)),
+        ("shr bx, cl", vec!(0xD3, 0xEB)),
+        ("rol dl, 4", vec!(0xC0, 0xC2, 0x04)),
+        ("inc si", vec!(0x46)),
+        ("dec byte [bx]", vec!(0xFE, 0x0F)),
+        ("in al, dx", vec!(0xEC)),
+        ("out 0x20, al", vec!(0xE6, 0x20)),
+        ("ret 4", vec!(0xC2, 0x04, 0x00)),
+        ("retf", vec!(0xCB)),
+        ("jmp 0x1234:0x5678", vec!(0xEA, 0x78, 0x56, 0x34, 0x12)),
+        ("call [bx]", vec!(0xFF, 0x17)),
+        ("movzx bx, byte [si]", vec!(0x0F, 0xB6, 0x1C)),
+        ("test32: test eax, ecx", vec!(0x66, 0x85, 0xC8)),
+        ("setae al", vec!(0x0F, 0x93, 0xC0)),
+        ("bts word [0x1ae], 0xf", vec!(0x0F, 0xBA, 0x2E, 0xAE, 0x01, 0x0F)),
+        ("btc eax, ebx", vec!(0x66, 0x0F, 0xBB, 0xD8)),
+        ("bsr cx, [si]", vec!(0x0F, 0xBD, 0x0C)),
+        ("xadd [bx], al", vec!(0x0F, 0xC0, 0x07)),
+        ("cmpxchg ecx, edx", vec!(0x66, 0x0F, 0xB1, 0xD1)),
+        ("bswap esi", vec!(0x66, 0x0F, 0xCE)),
+    ];
+    for (text, bytes) in cases {
+        assert_eq!(bytes, assemble(text), "{}", text);
+    }
+}
+
+#[test]
+fn can_assemble_memory_operands() {
+    let cases: Vec<(&str, Vec<u8>)> = vec![
+        ("mov [bx+si+4], al", vec!(0x88, 0x40, 0x04)),
+        ("mov word [bp-2], 0x1234", vec!(0xC7, 0x46, 0xFE, 0x34, 0x12)),
+        ("mov al, [bp]", vec!(0x8A, 0x46, 0x00)),
+        ("mov [es:di], ax", vec!(0x26, 0x89, 0x05)),
+        ("mov ax, cs:[0x200]", vec!(0x2E, 0xA1, 0x00, 0x02)),
+        ("add dx, [di+0x1000]", vec!(0x03, 0x95, 0x00, 0x10)),
+        ("mov eax, [ebx+ecx*4+8]", vec!(0x66, 0x67, 0x8B, 0x44, 0x8B, 0x08)),
+        ("lea si, [bx+di]", vec!(0x8D, 0x31)),
+    ];
+    for (text, bytes) in cases {
+        assert_eq!(bytes, assemble(text), "{}", text);
+    }
+}
+
+#[test]
+fn can_assemble_labels() {
+    let code = assemble("
+        org 0x100
+    start:
+        mov cx, 3
+    again:
+        dec cx          ; count down
+        jnz again
+        jmp done
+        nop
+    done:
+        ret");
+    assert_eq!(vec!(0xB9, 0x03, 0x00, 0x49, 0x75, 0xFD, 0xEB, 0x01, 0x90, 0xC3), code);
+}
+
+#[test]
+fn can_assemble_near_forward_branch() {
+    // the branch grows to the near form once the label offset is known
+    let text = format!("jz target\n{}target: ret", "db 0\n".repeat(200));
+    let code = assemble_at(&text, 0x100).unwrap();
+    assert_eq!(vec!(0x0F, 0x84, 0xC8, 0x00), code[0..4].to_vec());
+    assert_eq!(205, code.len());
+}
+
+#[test]
+fn can_assemble_data() {
+    let code = assemble("
+        org 0x100
+        mov dx, msg
+        mov cx, len
+    msg: db 'Hi;$', 13, 10
+    len: dw $ - msg");
+    assert_eq!(vec!(0xBA, 0x06, 0x01, 0xB9, 0x0C, 0x01, 0x48, 0x69, 0x3B, 0x24, 0x0D, 0x0A, 0x06, 0x00), code);
+}
+
+#[test]
+fn reports_errors_with_line_number() {
+    let err = assemble_at("nop\njmp missing", 0).unwrap_err();
+    assert_eq!("line 2: undefined label missing", format!("{}", err));
+
+    let err = assemble_at("mov [bx], 1", 0).unwrap_err();
+    assert_eq!("line 1: operation size not specified", format!("{}", err));
+
+    let err = assemble_at("mov ax, bl", 0).unwrap_err();
+    assert_eq!("line 1: operand size mismatch", format!("{}", err));
+
+    let err = assemble_at("frobnicate ax", 0).unwrap_err();
+    assert_eq!("line 1: unknown instruction frobnicate", format!("{}", err));
+
+    let text = format!("back: {}loop back", "db 0\n".repeat(200));
+    assert!(assemble_at(&text, 0).is_err());
+}
+
+#[test]
+fn can_execute_assembled_code() {
+    let code = assemble("
+        org 0x100
+        xor ax, ax
+        mov cx, 10
+    next:
+        add ax, cx
+        loop next
+        hlt");
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(22);
+    assert_eq!(55, machine.cpu.get_r16(R::AX));
+    assert_eq!(0, machine.cpu.get_r16(R::CX));
+}
@@ -1,14 +1,16 @@
 use std::num::Wrapping;
 
+use log::{trace, warn};
+
+use crate::cpu::CpuModel;
 use crate::cpu::instruction::{Instruction, InstructionInfo, ModRegRm, RepeatMode};
 use crate::cpu::parameter::{Parameter, ParameterSet};
 use crate::cpu::op::{Op, Invalid};
-use crate::cpu::register::{R, r8, r16, r32, sr, fpr};
+use crate::cpu::register::{R, AMode, r8, r16, r32, sr, fpr};
 use crate::cpu::segment::Segment;
-use crate::memory::{MMU, MemoryAddress};
-
-/// if enabled, prints decoded instructions each time they are being decoded
-const DEBUG_DECODER: bool = false;
+use crate::hex::hex_bytes;
+use crate::memory::{MMU, MemoryAddress, CODE_PAGE_SIZE};
+use crate::string::right_pad;
 
 #[cfg(test)]
 #[path = "./decoder_test.rs"]
@@ -28,15 +30,97 @@ pub enum AddressSize {
     _16bit, _32bit,
 }
 
+/// a decoded memory operand using 32-bit addressing
+enum Address32 {
+    Disp(u32),
+    Amode(AMode),
+    AmodeS8(AMode, i8),
+    AmodeS32(AMode, i32),
+}
+
+impl Address32 {
+    fn ptr8(self, seg: Segment) -> Parameter {
+        match self {
+            // real mode offsets are 16-bit
+            Address32::Disp(v) => Parameter::Ptr8(seg, v as u16),
+            Address32::Amode(amode) => Parameter::Ptr8Amode(seg, amode),
+            Address32::AmodeS8(amode, imm) => Parameter::Ptr8AmodeS8(seg, amode, imm),
+            Address32::AmodeS32(amode, imm) => Parameter::Ptr8AmodeS32(seg, amode, imm),
+        }
+    }
+
+    fn ptr16(self, seg: Segment) -> Parameter {
+        match self {
+            Address32::Disp(v) => Parameter::Ptr16(seg, v as u16),
+            Address32::Amode(amode) => Parameter::Ptr16Amode(seg, amode),
+            Address32::AmodeS8(amode, imm) => Parameter::Ptr16AmodeS8(seg, amode, imm),
+            Address32::AmodeS32(amode, imm) => Parameter::Ptr16AmodeS32(seg, amode, imm),
+        }
+    }
+
+    fn ptr32(self, seg: Segment) -> Parameter {
+        match self {
+            Address32::Disp(v) => Parameter::Ptr32(seg, v as u16),
+            Address32::Amode(amode) => Parameter::Ptr32Amode(seg, amode),
+            Address32::AmodeS8(amode, imm) => Parameter::Ptr32AmodeS8(seg, amode, imm),
+            Address32::AmodeS32(amode, imm) => Parameter::Ptr32AmodeS32(seg, amode, imm),
+        }
+    }
+}
+
+/// longest possible x86 instruction, in bytes
+const MAX_INSTRUCTION_LENGTH: u32 = 15;
+
+/// number of entries in the direct-mapped decode cache, must be a power of two
+const CACHE_SIZE: usize = 0x1_0000;
+
+#[derive(Clone)]
+struct CachedInstruction {
+    /// physical address of the instruction
+    addr: u32,
+    segment: u16,
+    offset: u16,
+    instruction: Instruction,
+}
+
 #[derive(Clone, Default)]
 pub struct Decoder {
     current_seg: u16,
 
     /// starting instruction decoding offset
     current_offset: u16,
+
+    /// decoded instructions indexed by physical address, invalidated on writes to their memory pages
+    cache: Vec<Option<CachedInstruction>>,
+
+    /// decode opcodes as this cpu does, including its undocumented aliases.
+    /// None decodes the opcodes of all models up to the 486
+    model: Option<CpuModel>,
 }
 
 impl Decoder {
+    /// selects the cpu whose opcodes are decoded, see `model`
+    pub fn set_model(&mut self, model: Option<CpuModel>) {
+        self.model = model;
+        self.cache.clear();
+    }
+
+    pub fn model(&self) -> Option<CpuModel> {
+        self.model
+    }
+
+    fn is_8086(&self) -> bool {
+        self.model == Some(CpuModel::I8086)
+    }
+
+    /// the 8086 to 286 decode the sreg field of mov to/from sreg with 2 bits
+    fn sreg_index(&self, reg: u8) -> u8 {
+        match self.model {
+            Some(CpuModel::I8086) | Some(CpuModel::I80186) | Some(CpuModel::I80286) => reg & 3,
+            _ => reg,
+        }
+    }
+
     /// decodes given seg::offset into Vec with `n` InstructionInfo's
     pub fn decode_to_block(&mut self, mut mmu: &mut MMU, seg: u16, offset: u16, n: usize) -> Vec<InstructionInfo> {
         let mut ops: Vec<InstructionInfo> = Vec::new();
@@ -57,9 +141,7 @@ impl Decoder {
     /// decodes op at seg:offset into a InstructionInfo
     pub fn get_instruction_info(&mut self, mut mmu: &mut MMU, seg: u16, offset: u16) -> InstructionInfo {
         let instr = self.get_instruction(&mut mmu, seg, offset);
-        if DEBUG_DECODER {
-            println!("get_instruction_info at {}: {}", MemoryAddress::RealSegmentOffset(seg, offset), instr);
-        }
+        trace!(target: "cpu", "get_instruction_info at {}: {}", MemoryAddress::RealSegmentOffset(seg, offset), instr);
         InstructionInfo {
             segment: seg as usize,
             offset: offset as usize,
@@ -70,27 +152,86 @@ impl Decoder {
 
     /// decodes op at seg:offset into a Instruction
     pub fn get_instruction(&mut self, mut mmu: &mut MMU, segment: u16, offset: u16) -> Instruction {
+        for page in mmu.memory.take_invalidated_code_pages() {
+            self.invalidate_page(page);
+        }
+        // the physical address, wrapping at 1 MB while the A20 gate is disabled
+        let addr = MemoryAddress::RealSegmentOffset(segment, offset).value() & mmu.memory.a20_mask();
+        if self.cache.is_empty() {
+            self.cache = vec![None; CACHE_SIZE];
+        }
+        let slot = addr as usize & (CACHE_SIZE - 1);
+        if let Some(cached) = &self.cache[slot] {
+            // jump targets are decoded relative to the segment, so the address must match
+            if cached.addr == addr && cached.segment == segment && cached.offset == offset {
+                return cached.instruction.clone();
+            }
+        }
+
         self.current_seg = segment;
         self.current_offset = offset;
         let mut op = Instruction::new(Op::Uninitialized);
         self.decode(&mut mmu, &mut op);
+
+        mmu.memory.mark_code(addr, op.length as usize);
+        self.cache[slot] = Some(CachedInstruction {
+            addr,
+            segment,
+            offset,
+            instruction: op.clone(),
+        });
         op
     }
 
+    /// drops all cached instructions, such as when the A20 gate changes the memory they were read from
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+
+    /// returns the number of cached instructions
+    pub fn cached_instructions(&self) -> usize {
+        self.cache.iter().filter(|c| c.is_some()).count()
+    }
+
+    /// drops cached instructions overlapping `page`
+    fn invalidate_page(&mut self, page: u32) {
+        let start = (page * CODE_PAGE_SIZE).saturating_sub(MAX_INSTRUCTION_LENGTH - 1);
+        let end = (page + 1) * CODE_PAGE_SIZE;
+        trace!(target: "cpu", "decoder: invalidating cache {:06X}-{:06X}", start, end);
+        if self.cache.is_empty() {
+            return;
+        }
+        for addr in start..end {
+            let slot = addr as usize & (CACHE_SIZE - 1);
+            if let Some(cached) = &self.cache[slot] {
+                if cached.addr == addr {
+                    self.cache[slot] = None;
+                }
+            }
+        }
+    }
+
     /// decodes the next instruction
     #[cfg_attr(feature = "cargo-clippy", allow(clippy::cyclomatic_complexity))]
     fn decode(&mut self, mut mmu: &mut MMU, mut op: &mut Instruction) {
         let start_offset = self.current_offset;
-        let b = self.read_u8(mmu);
-        if DEBUG_DECODER {
-            // println!("decode op {:04X}: {}", start_offset, op);
+        let mut b = self.read_u8(mmu);
+        if self.is_8086() {
+            // opcodes given a meaning by the 186 are aliases of their neighbours on the 8086:
+            // 60-6F are jcc 70-7F, C0/C1 are ret C2/C3, C8/C9 are retf CA/CB and F1 is lock
+            b = match b {
+                0x60..=0x6F => b | 0x10,
+                0xC0 | 0xC1 | 0xC8 | 0xC9 => b | 0x02,
+                0xF1 => 0xF0,
+                _ => b,
+            };
         }
 
         match b {
             0x00 => {
                 // add r/m8, r8
                 op.command = Op::Add8;
-                op.params = self.rm8_r8(&mut mmu, op.segment_prefix);
+                op.params = self.rm8_r8(&mut mmu, op);
             }
             0x01 => {
                 // add r/m16, r16
@@ -100,7 +241,7 @@ impl Decoder {
             0x02 => {
                 // add r8, r/m8
                 op.command = Op::Add8;
-                op.params = self.r8_rm8(&mut mmu, op.segment_prefix);
+                op.params = self.r8_rm8(&mut mmu, op);
             }
             0x03 => {
                 // add r16, r/m16
@@ -131,18 +272,18 @@ impl Decoder {
             }
             0x06 => {
                 // push es
-                op.command = Op::Push16;
+                op.command = sreg_stack_op(op, Op::Push16, Op::Push32);
                 op.params.dst = Parameter::SReg16(R::ES);
             }
             0x07 => {
                 // pop es
-                op.command = Op::Pop16;
+                op.command = sreg_stack_op(op, Op::Pop16, Op::Pop32);
                 op.params.dst = Parameter::SReg16(R::ES);
             }
             0x08 => {
                 // or r/m8, r8
                 op.command = Op::Or8;
-                op.params = self.rm8_r8(&mut mmu, op.segment_prefix);
+                op.params = self.rm8_r8(&mut mmu, op);
             }
             0x09 => {
                 // or r/m16, r16
@@ -152,12 +293,12 @@ impl Decoder {
             0x0A => {
                 // or r8, r/m8
                 op.command = Op::Or8;
-                op.params = self.r8_rm8(&mut mmu, op.segment_prefix);
+                op.params = self.r8_rm8(&mut mmu, op);
             }
             0x0B => {
                 // or r16, r/m16
-                op.command = Op::Or16;
-                op.params = self.r16_rm16(&mut mmu, op);
+                // or r32, r/m32
+                self.prefixed_16_32_r_rm(mmu, op, Op::Or16, Op::Or32)
             }
             0x0C => {
                 // or AL, imm8
@@ -183,7 +324,12 @@ impl Decoder {
             }
             0x0E => {
                 // push cs
-                op.command = Op::Push16;
+                op.command = sreg_stack_op(op, Op::Push16, Op::Push32);
+                op.params.dst = Parameter::SReg16(R::CS);
+            }
+            0x0F if self.is_8086() => {
+                // pop cs
+                op.command = Op::Pop16;
                 op.params.dst = Parameter::SReg16(R::CS);
             }
             0x0F => {
@@ -197,11 +343,50 @@ impl Decoder {
                             _ => Op::Invalid(vec!(b, b2), Invalid::Reg(x.reg)),
                         };
                     }
+                    0x01 => {
+                        let x = self.read_mod_reg_rm(mmu);
+                        op.params.dst = self.rm16(mmu, op, x.rm, x.md);
+                        op.command = match x.reg {
+                            4 => Op::Smsw, // smsw r/m16
+                            _ => Op::Invalid(vec!(b, b2), Invalid::Reg(x.reg)),
+                        };
+                    }
                     0x02 => {
                         // lar r16, r16/m16
                         op.command = Op::Lar16;
                         op.params = self.r16_rm16(&mut mmu, op);
                     }
+                    0x08 | 0x09 | 0x0B | 0x30 | 0x31 | 0x32 | 0xA2 => {
+                        // invd, wbinvd (486), ud2, wrmsr, rdtsc, rdmsr, cpuid (pentium)
+                        op.command = Op::Invalid(vec!(b, b2), Invalid::Unsupported);
+                    }
+                    0x40..=0x4F => {
+                        // cmovcc r16, r/m16 (pentium pro)
+                        op.params = self.r16_rm16(&mut mmu, op);
+                        op.command = Op::Invalid(vec!(b, b2), Invalid::Unsupported);
+                    }
+                    0x20 => {
+                        // mov r32, cr0 (the mod field is ignored)
+                        let x = self.read_mod_reg_rm(mmu);
+                        if x.reg == 0 {
+                            op.command = Op::Mov32;
+                            op.params.dst = Parameter::Reg32(r32(x.rm));
+                            op.params.src = Parameter::CReg32(R::CR0);
+                            op.op_size = OperandSize::_32bit;
+                        } else {
+                            op.command = Op::Invalid(vec!(b, b2), Invalid::Reg(x.reg));
+                        }
+                    }
+                    0x80 => {
+                        // jo rel16
+                        op.command = Op::Jo;
+                        op.params.dst = Parameter::Imm16(self.read_rel16(mmu));
+                    }
+                    0x81 => {
+                        // jno rel16
+                        op.command = Op::Jno;
+                        op.params.dst = Parameter::Imm16(self.read_rel16(mmu));
+                    }
                     0x82 => {
                         // jc rel16
                         op.command = Op::Jc;
@@ -232,11 +417,26 @@ impl Decoder {
                         op.command = Op::Ja;
                         op.params.dst = Parameter::Imm16(self.read_rel16(mmu));
                     }
+                    0x88 => {
+                        // js rel16
+                        op.command = Op::Js;
+                        op.params.dst = Parameter::Imm16(self.read_rel16(mmu));
+                    }
                     0x89 => {
                         // jns rel16
                         op.command = Op::Jns;
                         op.params.dst = Parameter::Imm16(self.read_rel16(mmu));
                     }
+                    0x8A => {
+                        // jpe rel16
+                        op.command = Op::Jpe;
+                        op.params.dst = Parameter::Imm16(self.read_rel16(mmu));
+                    }
+                    0x8B => {
+                        // jpo rel16
+                        op.command = Op::Jpo;
+                        op.params.dst = Parameter::Imm16(self.read_rel16(mmu));
+                    }
                     0x8C => {
                         // jl rel16
                         op.command = Op::Jl;
@@ -257,38 +457,43 @@ impl Decoder {
                         op.command = Op::Jg;
                         op.params.dst = Parameter::Imm16(self.read_rel16(mmu));
                     }
-                    0x92 => {
-                        // setc r/m8
-                        let x = self.read_mod_reg_rm(mmu);
-                        op.command = Op::Setc;
-                        op.params.dst = self.rm8(&mut mmu, op.segment_prefix, x.rm, x.md);
-                    }
-                    0x95 => {
-                        // setnz r/m8
-                        let x = self.read_mod_reg_rm(mmu);
-                        op.command = Op::Setnz;
-                        op.params.dst = self.rm8(&mut mmu, op.segment_prefix, x.rm, x.md);
-                    }
-                    0x9F => {
-                        // setg r/m8
+                    0x90..=0x9F => {
+                        // setcc r/m8, the reg field is ignored
                         let x = self.read_mod_reg_rm(mmu);
-                        op.command = Op::Setg;
-                        op.params.dst = self.rm8(&mut mmu, op.segment_prefix, x.rm, x.md);
+                        op.command = match b2 {
+                            0x90 => Op::Seto,
+                            0x91 => Op::Setno,
+                            0x92 => Op::Setc,
+                            0x93 => Op::Setnc,
+                            0x94 => Op::Setz,
+                            0x95 => Op::Setnz,
+                            0x96 => Op::Setna,
+                            0x97 => Op::Seta,
+                            0x98 => Op::Sets,
+                            0x99 => Op::Setns,
+                            0x9A => Op::Setpe,
+                            0x9B => Op::Setpo,
+                            0x9C => Op::Setl,
+                            0x9D => Op::Setnl,
+                            0x9E => Op::Setng,
+                            _ => Op::Setg,
+                        };
+                        op.params.dst = self.rm8(&mut mmu, op, x.rm, x.md);
                     }
                     0xA0 => {
                         // push fs
-                        op.command = Op::Push16;
+                        op.command = sreg_stack_op(op, Op::Push16, Op::Push32);
                         op.params.dst = Parameter::SReg16(R::FS);
                     }
                     0xA1 => {
                         // pop fs
-                        op.command = Op::Pop16;
+                        op.command = sreg_stack_op(op, Op::Pop16, Op::Pop32);
                         op.params.dst = Parameter::SReg16(R::FS);
                     }
                     0xA3 => {
                         // bt r/m16, r16
-                        op.command = Op::Bt;
-                        op.params = self.rm16_r16(&mut mmu, op);
+                        // bt r/m32, r32
+                        self.prefixed_16_32_rm_r(mmu, op, Op::Bt16, Op::Bt32)
                     }
                     0xA4 =>{
                         // shld r/m16, r16, imm8
@@ -298,14 +503,19 @@ impl Decoder {
                     }
                     0xA8 => {
                         // push gs
-                        op.command = Op::Push16;
+                        op.command = sreg_stack_op(op, Op::Push16, Op::Push32);
                         op.params.dst = Parameter::SReg16(R::GS);
                     }
                     0xA9 => {
                         // pop gs
-                        op.command = Op::Pop16;
+                        op.command = sreg_stack_op(op, Op::Pop16, Op::Pop32);
                         op.params.dst = Parameter::SReg16(R::GS);
                     }
+                    0xAB => {
+                        // bts r/m16, r16
+                        // bts r/m32, r32
+                        self.prefixed_16_32_rm_r(mmu, op, Op::Bts16, Op::Bts32)
+                    }
                     0xAC => {
                         // shrd r/m16, r16, imm8
                         op.command = Op::Shrd;
@@ -317,17 +527,32 @@ impl Decoder {
                         // imul r32, r/m32
                         self.prefixed_16_32_r_rm(&mut mmu, &mut op, Op::Imul16, Op::Imul32)
                     }
+                    0xB0 => {
+                        // cmpxchg r/m8, r8
+                        op.command = Op::Cmpxchg8;
+                        op.params = self.rm8_r8(mmu, op);
+                    }
+                    0xB1 => {
+                        // cmpxchg r/m16, r16
+                        // cmpxchg r/m32, r32
+                        self.prefixed_16_32_rm_r(mmu, op, Op::Cmpxchg16, Op::Cmpxchg32)
+                    }
+                    0xB3 => {
+                        // btr r/m16, r16
+                        // btr r/m32, r32
+                        self.prefixed_16_32_rm_r(mmu, op, Op::Btr16, Op::Btr32)
+                    }
                     0xB6 => {
                         match op.op_size {
                             OperandSize::_16bit => {
                                 // movzx r16, r/m8
                                 op.command = Op::Movzx16;
-                                op.params = self.r16_rm8(&mut mmu, op.segment_prefix);
+                                op.params = self.r16_rm8(&mut mmu, op);
                             }
                             OperandSize::_32bit => {
                                 // movzx r32, r/m8
                                 op.command = Op::Movzx32;
-                                op.params = self.r32_rm8(&mut mmu, op.segment_prefix);
+                                op.params = self.r32_rm8(&mut mmu, op);
                             }
                         }
                     }
@@ -342,28 +567,53 @@ impl Decoder {
                         }
                     }
                     0xBA => {
-                        // bts r/m16, imm8
+                        // bt, bts, btr, btc r/m16, imm8
+                        // bt, bts, btr, btc r/m32, imm8
                         let x = self.read_mod_reg_rm(mmu);
-                        op.command = Op::Bts;
-                        op.params.dst = self.rm16(&mut mmu, op, x.rm, x.md);
-                        op.params.src = Parameter::Imm8(self.read_u8(mmu));
+                        if x.reg < 4 {
+                            op.command = Op::Invalid(vec!(b, b2), Invalid::Reg(x.reg));
+                        } else {
+                            let ops = [(Op::Bt16, Op::Bt32), (Op::Bts16, Op::Bts32), (Op::Btr16, Op::Btr32), (Op::Btc16, Op::Btc32)];
+                            let (op16, op32) = ops[usize::from(x.reg - 4)].clone();
+                            match op.op_size {
+                                OperandSize::_16bit => {
+                                    op.command = op16;
+                                    op.params.dst = self.rm16(&mut mmu, op, x.rm, x.md);
+                                }
+                                OperandSize::_32bit => {
+                                    op.command = op32;
+                                    op.params.dst = self.rm32(&mut mmu, op, x.rm, x.md);
+                                }
+                            }
+                            op.params.src = Parameter::Imm8(self.read_u8(mmu));
+                        }
+                    }
+                    0xBB => {
+                        // btc r/m16, r16
+                        // btc r/m32, r32
+                        self.prefixed_16_32_rm_r(mmu, op, Op::Btc16, Op::Btc32)
                     }
                     0xBC => {
                         // bsf r16, r/m16
-                        op.command = Op::Bsf;
-                        op.params = self.r16_rm16(&mut mmu, op);
+                        // bsf r32, r/m32
+                        self.prefixed_16_32_r_rm(mmu, op, Op::Bsf16, Op::Bsf32)
+                    }
+                    0xBD => {
+                        // bsr r16, r/m16
+                        // bsr r32, r/m32
+                        self.prefixed_16_32_r_rm(mmu, op, Op::Bsr16, Op::Bsr32)
                     }
                     0xBE => {
                         match op.op_size {
                             OperandSize::_16bit => {
                                 // movsx r16, r/m8
                                 op.command = Op::Movsx16;
-                                op.params = self.r16_rm8(&mut mmu, op.segment_prefix);
+                                op.params = self.r16_rm8(&mut mmu, op);
                             }
                             OperandSize::_32bit => {
                                 // movsx r32, r/m8
                                 op.command = Op::Movsx32;
-                                op.params = self.r32_rm8(&mut mmu, op.segment_prefix);
+                                op.params = self.r32_rm8(&mut mmu, op);
                             }
                         }
                     }
@@ -377,13 +627,34 @@ impl Decoder {
                             }
                         }
                     }
+                    0xC0 => {
+                        // xadd r/m8, r8
+                        op.command = Op::Xadd8;
+                        op.params = self.rm8_r8(mmu, op);
+                    }
+                    0xC1 => {
+                        // xadd r/m16, r16
+                        // xadd r/m32, r32
+                        self.prefixed_16_32_rm_r(mmu, op, Op::Xadd16, Op::Xadd32)
+                    }
+                    0xC8..=0xCF => {
+                        match op.op_size {
+                            // the result of bswap r16 is undefined
+                            OperandSize::_16bit => op.command = Op::Invalid(vec!(b, b2), Invalid::Op),
+                            OperandSize::_32bit => {
+                                // bswap r32
+                                op.command = Op::Bswap32;
+                                op.params.dst = Parameter::Reg32(r32(b2 & 7));
+                            }
+                        }
+                    }
                     _ => op.command = Op::Invalid(vec!(b, b2), Invalid::Op),
                 }
             }
             0x10 => {
                 // adc r/m8, r8
                 op.command = Op::Adc8;
-                op.params = self.rm8_r8(&mut mmu, op.segment_prefix);
+                op.params = self.rm8_r8(&mut mmu, op);
             }
             0x11 => {
                 // adc r/m16, r16
@@ -393,12 +664,12 @@ impl Decoder {
             0x12 => {
                 // adc r8, r/m8
                 op.command = Op::Adc8;
-                op.params = self.r8_rm8(&mut mmu, op.segment_prefix);
+                op.params = self.r8_rm8(&mut mmu, op);
             }
             0x13 => {
                 // adc r16, r/m16
-                op.command = Op::Adc16;
-                op.params = self.r16_rm16(&mut mmu, op);
+                // adc r32, r/m32
+                self.prefixed_16_32_r_rm(mmu, op, Op::Adc16, Op::Adc32)
             }
             0x14 => {
                 // adc al, imm8
@@ -424,18 +695,18 @@ impl Decoder {
             }
             0x16 => {
                 // push ss
-                op.command = Op::Push16;
+                op.command = sreg_stack_op(op, Op::Push16, Op::Push32);
                 op.params.dst = Parameter::SReg16(R::SS);
             }
             0x17 => {
                 // pop ss
-                op.command = Op::Pop16;
+                op.command = sreg_stack_op(op, Op::Pop16, Op::Pop32);
                 op.params.dst = Parameter::SReg16(R::SS);
             }
             0x18 => {
                 // sbb r/m8, r8
                 op.command = Op::Sbb8;
-                op.params = self.rm8_r8(&mut mmu, op.segment_prefix);
+                op.params = self.rm8_r8(&mut mmu, op);
             }
             0x19 => {
                 // sbb r/m16, r16
@@ -445,12 +716,12 @@ impl Decoder {
             0x1A => {
                 // sbb r8, r/m8
                 op.command = Op::Sbb8;
-                op.params = self.r8_rm8(&mut mmu, op.segment_prefix);
+                op.params = self.r8_rm8(&mut mmu, op);
             }
             0x1B => {
                 // sbb r16, r/m16
-                op.command = Op::Sbb16;
-                op.params = self.r16_rm16(&mut mmu, op);
+                // sbb r32, r/m32
+                self.prefixed_16_32_r_rm(mmu, op, Op::Sbb16, Op::Sbb32)
             }
             0x1C => {
                 // sbb al, imm8
@@ -476,18 +747,18 @@ impl Decoder {
             }
             0x1E => {
                 // push ds
-                op.command = Op::Push16;
+                op.command = sreg_stack_op(op, Op::Push16, Op::Push32);
                 op.params.dst = Parameter::SReg16(R::DS);
             }
             0x1F => {
                 // pop ds
-                op.command = Op::Pop16;
+                op.command = sreg_stack_op(op, Op::Pop16, Op::Pop32);
                 op.params.dst = Parameter::SReg16(R::DS);
             }
             0x20 => {
                 // and r/m8, r8
                 op.command = Op::And8;
-                op.params = self.rm8_r8(&mut mmu, op.segment_prefix);
+                op.params = self.rm8_r8(&mut mmu, op);
             }
             0x21 => {
                 // and r/m16, r16
@@ -497,12 +768,12 @@ impl Decoder {
             0x22 => {
                 // and r8, r/m8
                 op.command = Op::And8;
-                op.params = self.r8_rm8(&mut mmu, op.segment_prefix);
+                op.params = self.r8_rm8(&mut mmu, op);
             }
             0x23 => {
                 // and r16, r/m16
-                op.command = Op::And16;
-                op.params = self.r16_rm16(&mut mmu, op);
+                // and r32, r/m32
+                self.prefixed_16_32_r_rm(mmu, op, Op::And16, Op::And32)
             }
             0x24 => {
                 // and AL, imm8
@@ -537,7 +808,7 @@ impl Decoder {
             0x28 => {
                 // sub r/m8, r8
                 op.command = Op::Sub8;
-                op.params = self.rm8_r8(&mut mmu, op.segment_prefix);
+                op.params = self.rm8_r8(&mut mmu, op);
             }
             0x29 => {
                 // sub r/m16, r16
@@ -547,7 +818,7 @@ impl Decoder {
             0x2A => {
                 // sub r8, r/m8
                 op.command = Op::Sub8;
-                op.params = self.r8_rm8(&mut mmu, op.segment_prefix);
+                op.params = self.r8_rm8(&mut mmu, op);
             }
             0x2B => {
                 // sub r16, r/m16
@@ -587,7 +858,7 @@ impl Decoder {
             0x30 => {
                 // xor r/m8, r8
                 op.command = Op::Xor8;
-                op.params = self.rm8_r8(&mut mmu, op.segment_prefix);
+                op.params = self.rm8_r8(&mut mmu, op);
             }
             0x31 => {
                 // xor r/m16, r16
@@ -597,7 +868,7 @@ impl Decoder {
             0x32 => {
                 // xor r8, r/m8
                 op.command = Op::Xor8;
-                op.params = self.r8_rm8(&mut mmu, op.segment_prefix);
+                op.params = self.r8_rm8(&mut mmu, op);
             }
             0x33 => {
                 // xor r16, r/m16
@@ -637,7 +908,7 @@ impl Decoder {
             0x38 => {
                 // cmp r/m8, r8
                 op.command = Op::Cmp8;
-                op.params = self.rm8_r8(&mut mmu, op.segment_prefix);
+                op.params = self.rm8_r8(&mut mmu, op);
             }
             0x39 => {
                 // cmp r/m16, r16
@@ -647,7 +918,7 @@ impl Decoder {
             0x3A => {
                 // cmp r8, r/m8
                 op.command = Op::Cmp8;
-                op.params = self.r8_rm8(&mut mmu, op.segment_prefix);
+                op.params = self.r8_rm8(&mut mmu, op);
             }
             0x3B => {
                 // cmp r16, r/m16
@@ -818,9 +1089,15 @@ impl Decoder {
                 }
             },
             0x6C => op.command = Op::Insb,
-            0x6D => op.command = Op::Insw,
+            0x6D => op.command = match op.op_size {
+                OperandSize::_16bit => Op::Insw,
+                OperandSize::_32bit => Op::Insd,
+            },
             0x6E => op.command = Op::Outsb,
-            0x6F => op.command = Op::Outsw,
+            0x6F => op.command = match op.op_size {
+                OperandSize::_16bit => Op::Outsw,
+                OperandSize::_32bit => Op::Outsd,
+            },
             0x70 => {
                 // jo rel8
                 op.command = Op::Jo;
@@ -905,7 +1182,7 @@ impl Decoder {
                 // <arithmetic> r/m8, imm8
                 // 0x82 is unrecognized by objdump & ndisasm, but alias to 0x80 on pre Pentium 4:s according to ref.x86asm.net
                 let x = self.read_mod_reg_rm(mmu);
-                op.params.dst = self.rm8(&mut mmu, op.segment_prefix, x.rm, x.md);
+                op.params.dst = self.rm8(&mut mmu, op, x.rm, x.md);
                 op.params.src = Parameter::Imm8(self.read_u8(mmu));
                 op.command = match x.reg {
                     0 => Op::Add8,
@@ -997,7 +1274,7 @@ impl Decoder {
             0x84 => {
                 // test r/m8, r8
                 op.command = Op::Test8;
-                op.params = self.rm8_r8(&mut mmu, op.segment_prefix);
+                op.params = self.rm8_r8(&mut mmu, op);
             }
             0x85 => {
                 // test r/m16, r16
@@ -1008,17 +1285,17 @@ impl Decoder {
             0x86 => {
                 // xchg r/m8, r8
                 op.command = Op::Xchg8;
-                op.params = self.rm8_r8(&mut mmu, op.segment_prefix);
+                op.params = self.rm8_r8(&mut mmu, op);
             }
             0x87 => {
                 // xchg r/m16, r16
-                op.command = Op::Xchg16;
-                op.params = self.rm16_r16(&mut mmu, op);
+                // xchg r/m32, r32
+                self.prefixed_16_32_rm_r(mmu, op, Op::Xchg16, Op::Xchg32)
             }
             0x88 => {
                 // mov r/m8, r8
                 op.command = Op::Mov8;
-                op.params = self.rm8_r8(&mut mmu, op.segment_prefix);
+                op.params = self.rm8_r8(&mut mmu, op);
             }
             0x89 => {
                 // mov r/m16, r16
@@ -1028,7 +1305,7 @@ impl Decoder {
             0x8A => {
                 // mov r8, r/m8
                 op.command = Op::Mov8;
-                op.params = self.r8_rm8(&mut mmu, op.segment_prefix);
+                op.params = self.r8_rm8(&mut mmu, op);
             }
             0x8B => {
                 // mov r16, r/m16
@@ -1037,24 +1314,56 @@ impl Decoder {
             }
             0x8C => {
                 // mov r/m16, sreg
-                op.command = Op::Mov16;
-                op.params = self.rm16_sreg(&mut mmu, op);
-            }
-            0x8D => {
-                // lea r16, m
-                op.command = Op::Lea16;
-                op.params = self.r16_m16(&mut mmu, op);
+                let x = self.read_mod_reg_rm(mmu);
+                let reg = self.sreg_index(x.reg);
+                if reg > 5 {
+                    op.command = Op::Invalid(vec!(b), Invalid::Reg(x.reg));
+                } else {
+                    op.command = Op::Mov16;
+                    op.params.dst = self.rm16(mmu, op, x.rm, x.md);
+                    op.params.src = Parameter::SReg16(sr(reg));
+                }
             }
+            0x8D => match op.op_size {
+                OperandSize::_16bit => {
+                    // lea r16, m
+                    op.command = Op::Lea16;
+                    op.params = self.r16_m16(&mut mmu, op);
+                }
+                OperandSize::_32bit => {
+                    // lea r32, m
+                    op.command = Op::Lea32;
+                    op.params = self.r32_rm16(mmu, op);
+                }
+            },
             0x8E => {
                 // mov sreg, r/m16
-                op.command = Op::Mov16;
-                op.params = self.sreg_rm16(&mut mmu, op);
+                let x = self.read_mod_reg_rm(mmu);
+                let reg = self.sreg_index(x.reg);
+                if reg > 5 {
+                    op.command = Op::Invalid(vec!(b), Invalid::Reg(x.reg));
+                } else {
+                    op.command = Op::Mov16;
+                    op.params.dst = Parameter::SReg16(sr(reg));
+                    op.params.src = self.rm16(mmu, op, x.rm, x.md);
+                }
             }
             0x8F => {
                 let x = self.read_mod_reg_rm(mmu);
-                op.params.dst = self.rm16(&mut mmu, op, x.rm, x.md);
+                let pop = match op.op_size {
+                    OperandSize::_16bit => {
+                        op.params.dst = self.rm16(mmu, op, x.rm, x.md);
+                        Op::Pop16 // pop r/m16
+                    }
+                    OperandSize::_32bit => {
+                        op.params.dst = self.rm32(mmu, op, x.rm, x.md);
+                        Op::Pop32 // pop r/m32
+                    }
+                };
                 op.command = match x.reg {
-                    0 => Op::Pop16, // pop r/m16
+                    0 => pop,
+                    // the 8086 ignores the reg field
+                    _ if self.is_8086() => pop,
                     _ => Op::Invalid(vec!(b), Invalid::FPUOp),
                 };
             }
@@ -1082,10 +1391,9 @@ impl Decoder {
             0x99 => op.command = Op::Cwd16,
             0x9A => {
                 // call ptr16:16
+                // call ptr16:32
                 op.command = Op::CallFar;
-                let imm = self.read_u16(mmu);
-                let seg = self.read_u16(mmu);
-                op.params.dst = Parameter::Ptr16Imm(seg, imm);
+                op.params.dst = self.read_far_pointer(mmu, op);
             }
             0x9B => op.command = Op::Fwait,
             0x9C => op.command = Op::Pushf,
@@ -1096,39 +1404,39 @@ impl Decoder {
                 // mov AL, [moffs8]
                 op.command = Op::Mov8;
                 op.params.dst = Parameter::Reg8(R::AL);
-                op.params.src = Parameter::Ptr8(op.segment_prefix, self.read_u16(mmu));
+                op.params.src = Parameter::Ptr8(op.segment_prefix, self.read_moffs(mmu, op));
             }
             0xA1 => match op.op_size {
                 OperandSize::_16bit => {
                     // mov AX, [moffs16]
                     op.command = Op::Mov16;
                     op.params.dst = Parameter::Reg16(R::AX);
-                    op.params.src = Parameter::Ptr16(op.segment_prefix, self.read_u16(mmu));
+                    op.params.src = Parameter::Ptr16(op.segment_prefix, self.read_moffs(mmu, op));
                 }
                 OperandSize::_32bit => {
                     // mov EAX, [moffs32]
                     op.command = Op::Mov32;
                     op.params.dst = Parameter::Reg32(R::EAX);
-                    op.params.src = Parameter::Ptr32(op.segment_prefix, self.read_u16(mmu));
+                    op.params.src = Parameter::Ptr32(op.segment_prefix, self.read_moffs(mmu, op));
                 }
             },
             0xA2 => {
                 // mov [moffs8], AL
                 op.command = Op::Mov8;
-                op.params.dst = Parameter::Ptr8(op.segment_prefix, self.read_u16(mmu));
+                op.params.dst = Parameter::Ptr8(op.segment_prefix, self.read_moffs(mmu, op));
                 op.params.src = Parameter::Reg8(R::AL);
             }
             0xA3 => match op.op_size {
                 OperandSize::_16bit => {
                     // mov [moffs16], AX
                     op.command = Op::Mov16;
-                    op.params.dst = Parameter::Ptr16(op.segment_prefix, self.read_u16(mmu));
+                    op.params.dst = Parameter::Ptr16(op.segment_prefix, self.read_moffs(mmu, op));
                     op.params.src = Parameter::Reg16(R::AX);
                 }
                 OperandSize::_32bit => {
                     // mov [moffs32], EAX
                     op.command = Op::Mov32;
-                    op.params.dst = Parameter::Ptr32(op.segment_prefix, self.read_u16(mmu));
+                    op.params.dst = Parameter::Ptr32(op.segment_prefix, self.read_moffs(mmu, op));
                     op.params.src = Parameter::Reg32(R::EAX);
                 }
             },
@@ -1138,7 +1446,10 @@ impl Decoder {
                 OperandSize::_32bit => Op::Movsd,
             },
             0xA6 => op.command = Op::Cmpsb,
-            0xA7 => op.command = Op::Cmpsw,
+            0xA7 => op.command = match op.op_size {
+                OperandSize::_16bit => Op::Cmpsw,
+                OperandSize::_32bit => Op::Cmpsd,
+            },
             0xA8 => {
                 // test AL, imm8
                 op.command = Op::Test8;
@@ -1172,7 +1483,10 @@ impl Decoder {
                 OperandSize::_32bit => Op::Lodsd,
             },
             0xAE => op.command = Op::Scasb,
-            0xAF => op.command = Op::Scasw,
+            0xAF => op.command = match op.op_size {
+                OperandSize::_16bit => Op::Scasw,
+                OperandSize::_32bit => Op::Scasd,
+            },
             0xB0..=0xB7 => {
                 // mov r8, u8
                 op.command = Op::Mov8;
@@ -1203,10 +1517,11 @@ impl Decoder {
                     3 => Op::Rcr8,
                     4 => Op::Shl8,
                     5 => Op::Shr8,
+                    6 => Op::Shl8, // undocumented alias
                     7 => Op::Sar8,
                     _ => Op::Invalid(vec!(b), Invalid::Reg(x.reg)),
                 };
-                op.params.dst = self.rm8(&mut mmu, op.segment_prefix, x.rm, x.md);
+                op.params.dst = self.rm8(&mut mmu, op, x.rm, x.md);
                 op.params.src = Parameter::Imm8(self.read_u8(mmu));
             }
             0xC1 => {
@@ -1221,6 +1536,7 @@ impl Decoder {
                             3 => Op::Rcr16,
                             4 => Op::Shl16,
                             5 => Op::Shr16,
+                            6 => Op::Shl16, // undocumented alias
                             7 => Op::Sar16,
                             _ => Op::Invalid(vec!(b), Invalid::Reg(x.reg)),
                         };
@@ -1236,6 +1552,7 @@ impl Decoder {
                             3 => Op::Rcr32,
                             4 => Op::Shl32,
                             5 => Op::Shr32,
+                            6 => Op::Shl32, // undocumented alias
                             7 => Op::Sar32,
                             _ => Op::Invalid(vec!(b), Invalid::Reg(x.reg)),
                         };
@@ -1262,10 +1579,11 @@ impl Decoder {
             }
             0xC6 => {
                 let x = self.read_mod_reg_rm(mmu);
-                op.params.dst = self.rm8(&mut mmu, op.segment_prefix, x.rm, x.md);
+                op.params.dst = self.rm8(&mut mmu, op, x.rm, x.md);
                 op.params.src = Parameter::Imm8(self.read_u8(mmu));
                 op.command = match x.reg {
                     0 => Op::Mov8, // mov r/m8, imm8
+                    _ if self.is_8086() => Op::Mov8, // the 8086 ignores the reg field
                     _ => Op::Invalid(vec!(b, x.u8()), Invalid::Reg(x.reg)),
                 };
             }
@@ -1277,6 +1595,7 @@ impl Decoder {
                         op.params.src = Parameter::Imm16(self.read_u16(mmu));
                         op.command = match x.reg {
                             0 => Op::Mov16, // mov r/m16, imm16
+                            _ if self.is_8086() => Op::Mov16, // the 8086 ignores the reg field
                             _ => Op::Invalid(vec!(b, x.u8()), Invalid::Reg(x.reg)),
                         };
                     }
@@ -1324,27 +1643,50 @@ impl Decoder {
                     3 => Op::Rcr8,
                     4 => Op::Shl8,
                     5 => Op::Shr8,
+                    6 if self.is_8086() => Op::Setmo8,
+                    6 => Op::Shl8, // undocumented alias
                     7 => Op::Sar8,
                     _ => Op::Invalid(vec!(b, x.u8()), Invalid::Reg(x.reg)),
                 };
-                op.params.dst = self.rm8(&mut mmu, op.segment_prefix, x.rm, x.md);
+                op.params.dst = self.rm8(&mut mmu, op, x.rm, x.md);
                 op.params.src = Parameter::Imm8(1);
             }
             0xD1 => {
                 // bit shift word by 1
+                // bit shift dword by 1
                 let x = self.read_mod_reg_rm(mmu);
-                op.command = match x.reg {
-                    0 => Op::Rol16,
-                    1 => Op::Ror16,
-                    2 => Op::Rcl16,
-                    3 => Op::Rcr16,
-                    4 => Op::Shl16,
-                    5 => Op::Shr16,
-                    7 => Op::Sar16,
-                    _ => Op::Invalid(vec!(b), Invalid::Reg(x.reg)),
-                };
-                op.params.dst = self.rm16(&mut mmu, op, x.rm, x.md);
-                op.params.src = Parameter::Imm16(1);
+                match op.op_size {
+                    OperandSize::_16bit => {
+                        op.command = match x.reg {
+                            0 => Op::Rol16,
+                            1 => Op::Ror16,
+                            2 => Op::Rcl16,
+                            3 => Op::Rcr16,
+                            4 => Op::Shl16,
+                            5 => Op::Shr16,
+                            6 if self.is_8086() => Op::Setmo16,
+                            6 => Op::Shl16, // undocumented alias
+                            7 => Op::Sar16,
+                            _ => Op::Invalid(vec!(b), Invalid::Reg(x.reg)),
+                        };
+                        op.params.dst = self.rm16(mmu, op, x.rm, x.md);
+                    }
+                    OperandSize::_32bit => {
+                        op.command = match x.reg {
+                            0 => Op::Rol32,
+                            1 => Op::Ror32,
+                            2 => Op::Rcl32,
+                            3 => Op::Rcr32,
+                            4 => Op::Shl32,
+                            5 => Op::Shr32,
+                            6 => Op::Shl32, // undocumented alias
+                            7 => Op::Sar32,
+                            _ => Op::Invalid(vec!(b), Invalid::Reg(x.reg)),
+                        };
+                        op.params.dst = self.rm32(mmu, op, x.rm, x.md);
+                    }
+                }
+                op.params.src = Parameter::Imm8(1);
             }
             0xD2 => {
                 // bit shift byte by CL
@@ -1356,26 +1698,49 @@ impl Decoder {
                     3 => Op::Rcr8,
                     4 => Op::Shl8,
                     5 => Op::Shr8,
+                    6 if self.is_8086() => Op::Setmo8,
+                    6 => Op::Shl8, // undocumented alias
                     7 => Op::Sar8,
                     _ => Op::Invalid(vec!(b), Invalid::Reg(x.reg)),
                 };
-                op.params.dst = self.rm8(&mut mmu, op.segment_prefix, x.rm, x.md);
+                op.params.dst = self.rm8(&mut mmu, op, x.rm, x.md);
                 op.params.src = Parameter::Reg8(R::CL);
             }
             0xD3 => {
                 // bit shift word by CL
+                // bit shift dword by CL
                 let x = self.read_mod_reg_rm(mmu);
-                op.command = match x.reg {
-                    0 => Op::Rol16,
-                    1 => Op::Ror16,
-                    2 => Op::Rcl16,
-                    3 => Op::Rcr16,
-                    4 => Op::Shl16,
-                    5 => Op::Shr16,
-                    7 => Op::Sar16,
-                    _ => Op::Invalid(vec!(b), Invalid::Reg(x.reg)),
-                };
-                op.params.dst = self.rm16(&mut mmu, op, x.rm, x.md);
+                match op.op_size {
+                    OperandSize::_16bit => {
+                        op.command = match x.reg {
+                            0 => Op::Rol16,
+                            1 => Op::Ror16,
+                            2 => Op::Rcl16,
+                            3 => Op::Rcr16,
+                            4 => Op::Shl16,
+                            5 => Op::Shr16,
+                            6 if self.is_8086() => Op::Setmo16,
+                            6 => Op::Shl16, // undocumented alias
+                            7 => Op::Sar16,
+                            _ => Op::Invalid(vec!(b), Invalid::Reg(x.reg)),
+                        };
+                        op.params.dst = self.rm16(mmu, op, x.rm, x.md);
+                    }
+                    OperandSize::_32bit => {
+                        op.command = match x.reg {
+                            0 => Op::Rol32,
+                            1 => Op::Ror32,
+                            2 => Op::Rcl32,
+                            3 => Op::Rcr32,
+                            4 => Op::Shl32,
+                            5 => Op::Shr32,
+                            6 => Op::Shl32, // undocumented alias
+                            7 => Op::Sar32,
+                            _ => Op::Invalid(vec!(b), Invalid::Reg(x.reg)),
+                        };
+                        op.params.dst = self.rm32(mmu, op, x.rm, x.md);
+                    }
+                }
                 op.params.src = Parameter::Reg8(R::CL);
             }
             0xD4 => {
@@ -1422,7 +1787,7 @@ impl Decoder {
                         op.params.dst = self.rmf32(mmu, op, x.rm, x.md);
                     }
                     _ => {
-                        println!("XXX unhandled D8 reg {:?}", x);
+                        warn!(target: "cpu", "XXX unhandled D8 reg {:?}", x);
                         op.command = Op::Invalid(vec!(b, x.u8()), Invalid::FPUOp);
                     }
                 }
@@ -1461,7 +1826,7 @@ impl Decoder {
                             op.params.dst = self.rmf16(mmu, op, x.rm, x.md);
                         }
                         _ => {
-                            println!("XXX unhandled D9 md012 reg {:?}", x);
+                            warn!(target: "cpu", "XXX unhandled D9 md012 reg {:?}", x);
                             op.command = Op::Invalid(vec!(b, x.u8()), Invalid::FPUOp);
                         }
                     }
@@ -1482,7 +1847,7 @@ impl Decoder {
                             1 => op.command = Op::Fabs, // { md: 3, reg: 4, rm: 1 }
                             4 => op.command = Op::Ftst, // { md: 3, reg: 4, rm: 4 }
                             _ => {
-                                println!("XXX unhandled D9 md3 reg4 rm {:?}", x);
+                                warn!(target: "cpu", "XXX unhandled D9 md3 reg4 rm {:?}", x);
                                 op.command = Op::Invalid(vec!(b, x.u8()), Invalid::FPUOp);
                             }
                         }
@@ -1493,14 +1858,14 @@ impl Decoder {
                             3 => op.command = Op::Fldpi,    // { md: 3, reg: 5, rm: 3 }
                             6 => op.command = Op::Fldz,     // { md: 3, reg: 5, rm: 6 }
                             _ => {
-                                println!("XXX unhandled D9 md3 reg5 rm {:?}", x);
+                                warn!(target: "cpu", "XXX unhandled D9 md3 reg5 rm {:?}", x);
                                 op.command = Op::Invalid(vec!(b, x.u8()), Invalid::FPUOp);
                             }
                         }
                         6 => match x.rm {
                             3 => op.command = Op::Fpatan, // { md: 3, reg: 6, rm: 3 }
                             _ => {
-                                println!("XXX unhandled D9 md3 reg5 rm {:?}", x);
+                                warn!(target: "cpu", "XXX unhandled D9 md3 reg5 rm {:?}", x);
                                 op.command = Op::Invalid(vec!(b, x.u8()), Invalid::FPUOp);
                             }
                         }
@@ -1511,17 +1876,17 @@ impl Decoder {
                             6 => op.command = Op::Fsin,     // { md: 3, reg: 7, rm: 6 }
                             7 => op.command = Op::Fcos,     // { md: 3, reg: 7, rm: 7 }
                             _ => {
-                                println!("XXX unhandled D9 md3 reg7 rm {:?}", x);
+                                warn!(target: "cpu", "XXX unhandled D9 md3 reg7 rm {:?}", x);
                                 op.command = Op::Invalid(vec!(b, x.u8()), Invalid::FPUOp);
                             }
                         }
                         _ => {
-                            println!("XXX unhandled D9 md3 reg {:?}", x);
+                            warn!(target: "cpu", "XXX unhandled D9 md3 reg {:?}", x);
                             op.command = Op::Invalid(vec!(b, x.u8()), Invalid::FPUOp);
                         }
                     }
                     _ => {
-                        println!("XXX unhandled D9 md {:?}", x);
+                        warn!(target: "cpu", "XXX unhandled D9 md {:?}", x);
                         op.command = Op::Invalid(vec!(b, x.u8()), Invalid::FPUOp);
                     }
                 }
@@ -1535,7 +1900,7 @@ impl Decoder {
                         op.params.dst = self.rmf32(mmu, op, x.rm, x.md);
                     }
                     _ => {
-                        println!("XXX unhandled DA md {:?}", x);
+                        warn!(target: "cpu", "XXX unhandled DA md {:?}", x);
                         op.command = Op::Invalid(vec!(b, x.u8()), Invalid::FPUOp);
                     }
                 }
@@ -1565,7 +1930,7 @@ impl Decoder {
                         }
                     }
                     _ => {
-                        println!("XXX unhandled DB reg {:?}", x);
+                        warn!(target: "cpu", "XXX unhandled DB reg {:?}", x);
                         op.command = Op::Invalid(vec!(b, x.u8()), Invalid::FPUOp);
                     }
                 }
@@ -1589,7 +1954,7 @@ impl Decoder {
                 // DCE5              fsubr to st5       dos-software-decoding/demo-fpu/zud/zud_final.com
 
                 let x = self.read_mod_reg_rm(mmu);
-                println!("XXX DC {:?}", x);
+                warn!(target: "cpu", "XXX DC {:?}", x);
 
                 op.command = Op::Invalid(vec!(b, x.u8()), Invalid::FPUOp);
             }
@@ -1613,7 +1978,7 @@ impl Decoder {
                         op.params.dst = self.rmf32(mmu, op, x.rm, x.md); // XXX m64fp
                     }
                     _ => {
-                        println!("XXX unhandled DD reg {:?}", x);
+                        warn!(target: "cpu", "XXX unhandled DD reg {:?}", x);
                         op.command = Op::Invalid(vec!(b, x.u8()), Invalid::FPUOp);
                     }
                 }
@@ -1662,7 +2027,7 @@ impl Decoder {
                         op.params.dst = self.rmf16(mmu, op, x.rm, x.md);
                     }
                     _ => {
-                        println!("XXX unhandled DE reg {:?}", x);
+                        warn!(target: "cpu", "XXX unhandled DE reg {:?}", x);
                         op.command = Op::Invalid(vec!(b, x.u8()), Invalid::FPUOp);
                     }
                 }
@@ -1689,7 +2054,7 @@ impl Decoder {
                             op.command = Op::Fstsw;
                             op.params.dst = Parameter::Reg16(R::AX);
                         } else {
-                            println!("XXX unhandled DF reg4 {:?}", x);
+                            warn!(target: "cpu", "XXX unhandled DF reg4 {:?}", x);
                             op.command = Op::Invalid(vec!(b, x.u8()), Invalid::FPUOp);
                         }
                     }
@@ -1704,7 +2069,7 @@ impl Decoder {
                         op.params.dst = self.rmf32(mmu, op, x.rm, x.md); // XXX 64-bit
                     }
                     _ => {
-                        println!("XXX unhandled DF reg {:?}", x);
+                        warn!(target: "cpu", "XXX unhandled DF reg {:?}", x);
                         op.command = Op::Invalid(vec!(b, x.u8()), Invalid::FPUOp);
                     }
                 }
@@ -1762,10 +2127,9 @@ impl Decoder {
             }
             0xEA => {
                 // jmp far ptr16:16
+                // jmp far ptr16:32
                 op.command = Op::JmpFar;
-                let imm = self.read_u16(mmu);
-                let seg = self.read_u16(mmu);
-                op.params.dst = Parameter::Ptr16Imm(seg, imm);
+                op.params.dst = self.read_far_pointer(mmu, op);
             }
             0xEB => {
                 // jmp short rel8
@@ -1808,10 +2172,23 @@ impl Decoder {
                 op.params.dst = Parameter::Imm8(1);
             }
             0xF2 => {
-                // repne (cmps, scas) prefix
-                op.repeat = RepeatMode::Repne;
+                // repne (cmps, scas) prefix, acts as rep for the other string instructions
                 self.decode(&mut mmu, &mut op);
                 op.length += 1;
+                match op.command {
+                    Op::Insb | Op::Insw | Op::Insd |
+                    Op::Outsb | Op::Outsw | Op::Outsd |
+                    Op::Movsb | Op::Movsw | Op::Movsd |
+                    Op::Stosb | Op::Stosw | Op::Stosd |
+                    Op::Lodsb | Op::Lodsw | Op::Lodsd => {
+                        op.repeat = RepeatMode::Rep;
+                    }
+                    Op::Cmpsb | Op::Cmpsw | Op::Cmpsd |
+                    Op::Scasb | Op::Scasw | Op::Scasd => {
+                        op.repeat = RepeatMode::Repne;
+                    }
+                    _ => {} // the prefix is ignored
+                }
                 return;
             }
             0xF3 => {
@@ -1819,15 +2196,15 @@ impl Decoder {
                 self.decode(&mut mmu, &mut op);
                 op.length += 1;
                 match op.command {
-                    Op::Insb | Op::Insw |
-                    Op::Outsb | Op::Outsw |
+                    Op::Insb | Op::Insw | Op::Insd |
+                    Op::Outsb | Op::Outsw | Op::Outsd |
                     Op::Movsb | Op::Movsw | Op::Movsd |
                     Op::Stosb | Op::Stosw | Op::Stosd |
                     Op::Lodsb | Op::Lodsw | Op::Lodsd => {
                         op.repeat = RepeatMode::Rep;
                     }
-                    Op::Cmpsb | Op::Cmpsw |
-                    Op::Scasb | Op::Scasw => {
+                    Op::Cmpsb | Op::Cmpsw | Op::Cmpsd |
+                    Op::Scasb | Op::Scasw | Op::Scasd => {
                         op.repeat = RepeatMode::Repe;
                     }
                     _ => op.command = Op::Invalid(vec!(b), Invalid::Op), // XXX should encode the instruction bytes after 0xf3 prefix
@@ -1839,7 +2216,7 @@ impl Decoder {
             0xF6 => {
                 // <math> r/m8
                 let x = self.read_mod_reg_rm(mmu);
-                op.params.dst = self.rm8(&mut mmu, op.segment_prefix, x.rm, x.md);
+                op.params.dst = self.rm8(&mut mmu, op, x.rm, x.md);
                 match x.reg {
                     0 | 1 => {
                         // test r/m8, imm8
@@ -1901,10 +2278,16 @@ impl Decoder {
             0xFB => op.command = Op::Sti,
             0xFC => op.command = Op::Cld,
             0xFD => op.command = Op::Std,
+            0xFE if mmu.read_u8(self.current_seg, self.current_offset) == 0x38 => {
+                // callback imm16, dustbox specific
+                self.current_offset = self.current_offset.wrapping_add(1);
+                op.command = Op::Callback;
+                op.params.dst = Parameter::Imm16(self.read_u16(mmu));
+            }
             0xFE => {
                 // r/m8
                 let x = self.read_mod_reg_rm(mmu);
-                op.params.dst = self.rm8(&mut mmu, op.segment_prefix, x.rm, x.md);
+                op.params.dst = self.rm8(&mut mmu, op, x.rm, x.md);
                 op.command = match x.reg {
                     // NOTE: 2 is a deprecated but valid encoding, example:
                     // https://www.pouet.net/prod.php?which=65203
@@ -1928,17 +2311,24 @@ impl Decoder {
                             4 => Op::JmpNear,
                             5 => Op::JmpFar,
                             6 => Op::Push16,
+                            7 if self.is_8086() => Op::Push16,
                             _ => Op::Invalid(vec!(b, x.u8()), Invalid::Reg(x.reg)),
                         };
                     }
                     OperandSize::_32bit => {
-                        op.params.dst = self.rm32(&mut mmu, op, x.rm, x.md);
+                        // call far m16:32 and jmp far m16:32 address the pointer as m16
+                        op.params.dst = match x.reg {
+                            3 | 5 => self.rm16(mmu, op, x.rm, x.md),
+                            _ => self.rm32(mmu, op, x.rm, x.md),
+                        };
                         op.command = match x.reg {
                             0 => Op::Inc32,
                             1 => Op::Dec32,
+                            3 => Op::CallFar,
+                            5 => Op::JmpFar,
                             6 => Op::Push32,
                             _ => {
-                                println!("XXX FF 32bit {:?}", x);
+                                warn!(target: "cpu", "XXX FF 32bit {:?}", x);
                                 Op::Invalid(vec!(b, x.u8()), Invalid::Reg(x.reg))
                             }
                         };
@@ -1948,9 +2338,7 @@ impl Decoder {
         }
         // calculate instruction length
         op.length = (Wrapping(u16::from(op.length)) + Wrapping(self.current_offset) - Wrapping(start_offset)).0 as u8;
-        if DEBUG_DECODER {
-            println!("{:04X}: decoded {}", start_offset, op);
-        }
+        trace!(target: "cpu", "{:04X}: decoded {}", start_offset, op);
     }
 
     fn prefixed_16_32_rm_r(&mut self, mut mmu: &mut MMU, op: &mut Instruction, op16: Op, op32: Op) {
@@ -1979,9 +2367,59 @@ impl Decoder {
         }
     }
 
+    /// decode the memory operand of a modrm byte using 32-bit addressing (mod 0-2),
+    /// reading the SIB byte and displacement if present
+    fn address32(&mut self, mmu: &MMU, rm: u8, md: u8) -> Address32 {
+        let amode = if rm == 4 {
+            let sib = self.read_u8(mmu);
+            let scale = 1 << (sib >> 6);
+            let index = match (sib >> 3) & 7 {
+                4 => None,
+                n => Some(r32(n)),
+            };
+            let base = match sib & 7 {
+                5 if md == 0 => None,
+                n => Some(r32(n)),
+            };
+            match (base, index) {
+                (None, None) => None,
+                (Some(base), None) => Some(AddressSize::_32bit.amode_from(base.index() as u8)),
+                (base, index) => Some(AMode::SIB(base, index, scale)),
+            }
+        } else if rm == 5 && md == 0 {
+            None
+        } else {
+            Some(AddressSize::_32bit.amode_from(rm))
+        };
+
+        match (amode, md) {
+            // [u32]
+            (None, _) => Address32::Disp(self.read_u32(mmu)),
+            // [amode+s32], base-less SIB always has a 32-bit displacement
+            (Some(amode @ AMode::SIB(None, _, _)), _) |
+            (Some(amode), 2) => Address32::AmodeS32(amode, self.read_u32(mmu) as i32),
+            // [amode]
+            (Some(amode), 0) => Address32::Amode(amode),
+            // [amode+s8]
+            (Some(amode), _) => Address32::AmodeS8(amode, self.read_s8(mmu)),
+        }
+    }
+
+    /// reads a moffs operand, sized by the address size
+    fn read_moffs(&mut self, mmu: &MMU, op: &Instruction) -> u16 {
+        match op.address_size {
+            AddressSize::_16bit => self.read_u16(mmu),
+            // real mode offsets are 16-bit
+            AddressSize::_32bit => self.read_u32(mmu) as u16,
+        }
+    }
+
     /// decode rm8
-    fn rm8(&mut self, mmu: &mut MMU, seg: Segment, rm: u8, md: u8) -> Parameter {
-        let adr_size = AddressSize::_16bit;
+    fn rm8(&mut self, mmu: &mut MMU, op: &Instruction, rm: u8, md: u8) -> Parameter {
+        let seg = op.segment_prefix;
+        if op.address_size == AddressSize::_32bit && md != 3 {
+            return self.address32(mmu, rm, md).ptr8(seg);
+        }
         match md {
             0 => {
                 if rm == 6 {
@@ -1989,13 +2427,13 @@ impl Decoder {
                     Parameter::Ptr8(seg, self.read_u16(mmu))
                 } else {
                     // [amode]
-                    Parameter::Ptr8Amode(seg, adr_size.amode_from(rm))
+                    Parameter::Ptr8Amode(seg, op.address_size.amode_from(rm))
                 }
             }
             // [amode+s8]
-            1 => Parameter::Ptr8AmodeS8(seg, adr_size.amode_from(rm), self.read_s8(mmu)),
+            1 => Parameter::Ptr8AmodeS8(seg, op.address_size.amode_from(rm), self.read_s8(mmu)),
             // [amode+s16]
-            2 => Parameter::Ptr8AmodeS16(seg, adr_size.amode_from(rm), self.read_s16(mmu)),
+            2 => Parameter::Ptr8AmodeS16(seg, op.address_size.amode_from(rm), self.read_s16(mmu)),
             // reg
             3 => Parameter::Reg8(r8(rm)),
             _ => unreachable!(),
@@ -2004,6 +2442,9 @@ impl Decoder {
 
     /// decode rm16
     fn rm16(&mut self, mmu: &mut MMU, op: &Instruction, rm: u8, md: u8) -> Parameter {
+        if op.address_size == AddressSize::_32bit && md != 3 {
+            return self.address32(mmu, rm, md).ptr16(op.segment_prefix);
+        }
         match md {
             0 => {
                 if rm == 6 {
@@ -2026,6 +2467,9 @@ impl Decoder {
 
     /// decode rm32
     fn rm32(&mut self, mmu: &mut MMU, op: &Instruction, rm: u8, md: u8) -> Parameter {
+        if op.address_size == AddressSize::_32bit && md != 3 {
+            return self.address32(mmu, rm, md).ptr32(op.segment_prefix);
+        }
         match md {
             0 => {
                 if rm == 6 {
@@ -2048,6 +2492,9 @@ impl Decoder {
 
     /// decode rm as 16-bit fpu op argument
     fn rmf16(&mut self, mmu: &mut MMU, op: &Instruction, rm: u8, md: u8) -> Parameter {
+        if op.address_size == AddressSize::_32bit && md != 3 {
+            return self.address32(mmu, rm, md).ptr16(op.segment_prefix);
+        }
         match md {
             0 => {
                 if rm == 6 {
@@ -2070,6 +2517,9 @@ impl Decoder {
 
     /// decode rm as 32-bit fpu op argument
     fn rmf32(&mut self, mmu: &mut MMU, op: &Instruction, rm: u8, md: u8) -> Parameter {
+        if op.address_size == AddressSize::_32bit && md != 3 {
+            return self.address32(mmu, rm, md).ptr32(op.segment_prefix);
+        }
         match md {
             0 => {
                 if rm == 6 {
@@ -2091,61 +2541,40 @@ impl Decoder {
     }
 
     /// decode r8, r/m8
-    fn r8_rm8(&mut self, mut mmu: &mut MMU, seg: Segment) -> ParameterSet {
+    fn r8_rm8(&mut self, mut mmu: &mut MMU, op: &Instruction) -> ParameterSet {
         let x = self.read_mod_reg_rm(mmu);
         ParameterSet {
             dst: Parameter::Reg8(r8(x.reg)),
-            src: self.rm8(&mut mmu, seg, x.rm, x.md),
+            src: self.rm8(&mut mmu, op, x.rm, x.md),
             src2: Parameter::None,
         }
     }
 
     /// decode r/m8, r8
-    fn rm8_r8(&mut self, mut mmu: &mut MMU, seg: Segment) -> ParameterSet {
+    fn rm8_r8(&mut self, mut mmu: &mut MMU, op: &Instruction) -> ParameterSet {
         let x = self.read_mod_reg_rm(mmu);
         ParameterSet {
-            dst: self.rm8(&mut mmu, seg, x.rm, x.md),
+            dst: self.rm8(&mut mmu, op, x.rm, x.md),
             src: Parameter::Reg8(r8(x.reg)),
             src2: Parameter::None,
         }
     }
-
-    /// decode Sreg, r/m16
-    fn sreg_rm16(&mut self, mut mmu: &mut MMU, op: &Instruction) -> ParameterSet {
-        let x = self.read_mod_reg_rm(mmu);
-        ParameterSet {
-            dst: Parameter::SReg16(sr(x.reg)),
-            src: self.rm16(&mut mmu, op, x.rm, x.md),
-            src2: Parameter::None,
-        }
-    }
-
-    /// decode r/m16, Sreg
-    fn rm16_sreg(&mut self, mut mmu: &mut MMU, op: &Instruction) -> ParameterSet {
-        let x = self.read_mod_reg_rm(mmu);
-        ParameterSet {
-            dst: self.rm16(&mut mmu, op, x.rm, x.md),
-            src: Parameter::SReg16(sr(x.reg)),
-            src2: Parameter::None,
-        }
-    }
-
     /// decode r16, r/m8 (movzx)
-    fn r16_rm8(&mut self, mut mmu: &mut MMU, seg: Segment) -> ParameterSet {
+    fn r16_rm8(&mut self, mut mmu: &mut MMU, op: &Instruction) -> ParameterSet {
         let x = self.read_mod_reg_rm(mmu);
         ParameterSet {
             dst: Parameter::Reg16(r16(x.reg)),
-            src: self.rm8(&mut mmu, seg, x.rm, x.md),
+            src: self.rm8(&mut mmu, op, x.rm, x.md),
             src2: Parameter::None,
         }
     }
 
     /// decode r32, r/m8 (movzx)
-    fn r32_rm8(&mut self, mut mmu: &mut MMU, seg: Segment) -> ParameterSet {
+    fn r32_rm8(&mut self, mut mmu: &mut MMU, op: &Instruction) -> ParameterSet {
         let x = self.read_mod_reg_rm(mmu);
         ParameterSet {
             dst: Parameter::Reg32(r32(x.reg)),
-            src: self.rm8(&mut mmu, seg, x.rm, x.md),
+            src: self.rm8(&mut mmu, op, x.rm, x.md),
             src2: Parameter::None,
         }
     }
@@ -2184,7 +2613,7 @@ impl Decoder {
     fn r16_m16(&mut self, mut mmu: &mut MMU, op: &Instruction) -> ParameterSet {
         let x = self.read_mod_reg_rm(mmu);
         if x.md == 3 {
-            println!("r16_m16 error: invalid encoding, ip={:04X}", self.current_offset);
+            warn!(target: "cpu", "r16_m16 error: invalid encoding, ip={:04X}", self.current_offset);
         }
         ParameterSet {
             dst: Parameter::Reg16(r16(x.reg)),
@@ -2216,15 +2645,11 @@ impl Decoder {
     fn read_mod_reg_rm(&mut self, mmu: &MMU) -> ModRegRm {
         let b = mmu.read_u8(self.current_seg, self.current_offset);
         self.current_offset = self.current_offset.wrapping_add(1);
-        let res = ModRegRm {
+        ModRegRm {
             md: b >> 6, // high 2 bits
             reg: (b >> 3) & 7, // mid 3 bits
             rm: b & 7, // low 3 bits
-        };
-        if DEBUG_DECODER {
-            // println!("read_mod_reg_rm byte {:02X} = mod {}, reg {}, rm {}", b, res.md, res.reg, res.rm);
         }
-        res
     }
 
     fn read_rel8(&mut self, mmu: &MMU) -> u16 {
@@ -2259,6 +2684,17 @@ impl Decoder {
         u32::from(hi) << 16 | u32::from(lo)
     }
 
+    /// reads the ptr16:16 or ptr16:32 operand of a far call or jmp. offsets above 0xFFFF
+    /// fault in real mode, and are truncated
+    fn read_far_pointer(&mut self, mmu: &MMU, op: &Instruction) -> Parameter {
+        let offset = match op.op_size {
+            OperandSize::_16bit => self.read_u16(mmu),
+            OperandSize::_32bit => self.read_u32(mmu) as u16,
+        };
+        let seg = self.read_u16(mmu);
+        Parameter::Ptr16Imm(seg, offset)
+    }
+
     fn read_s16(&mut self, mmu: &MMU) -> i16 {
         self.read_u16(mmu) as i16
     }
@@ -2269,6 +2705,14 @@ impl Decoder {
     }
 }
 
+/// push or pop of a segment register, moving a dword with the 32-bit operand size
+fn sreg_stack_op(op: &Instruction, op16: Op, op32: Op) -> Op {
+    match op.op_size {
+        OperandSize::_16bit => op16,
+        OperandSize::_32bit => op32,
+    }
+}
+
 pub fn instruction_info_to_str(ops: &[InstructionInfo]) -> String {
     let mut lines = Vec::new();
     for op in ops {
@@ -2284,3 +2728,43 @@ pub fn instructions_to_str(ops: &[Instruction]) -> String {
     }
     lines.join("\n")
 }
+
+/// segment where `disasm_bytes` places the code while decoding
+const DISASM_SEGMENT: u16 = 0x1000;
+
+/// disassembles 16-bit code starting at offset 0 into lines of offset, hex bytes and instruction,
+/// like ndisasm. bytes that does not decode to a valid instruction are shown as db
+pub fn disasm_bytes(bytes: &[u8]) -> Vec<String> {
+    let mut mmu = MMU::default();
+    mmu.write(DISASM_SEGMENT, 0, bytes);
+    let mut decoder = Decoder::default();
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let (length, text) = disasm_at(&mut decoder, &mut mmu, bytes, offset);
+        lines.push(format!("{:08X}  {} {}", offset, right_pad(&hex_bytes(&bytes[offset..offset + length]), 16), text));
+        offset += length;
+    }
+    lines
+}
+
+/// returns the disassembly of the first instruction in `bytes`, without offset and hex bytes
+pub fn disasm_first_instr(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return String::new();
+    }
+    let mut mmu = MMU::default();
+    mmu.write(DISASM_SEGMENT, 0, bytes);
+    disasm_at(&mut Decoder::default(), &mut mmu, bytes, 0).1
+}
+
+/// returns the length and text of the instruction at `offset`, or a db of the byte
+fn disasm_at(decoder: &mut Decoder, mmu: &mut MMU, bytes: &[u8], offset: usize) -> (usize, String) {
+    let instr = decoder.get_instruction(mmu, DISASM_SEGMENT, offset as u16);
+    let length = instr.length as usize;
+    if instr.command.is_valid() && length > 0 && offset + length <= bytes.len() {
+        (length, instr.to_string())
+    } else {
+        (1, format!("db 0x{:02X}", bytes[offset]))
+    }
+}
@@ -1,5 +1,6 @@
 use pretty_assertions::assert_eq;
 
+use crate::cpu::{disasm_bytes, disasm_first_instr, BranchTarget, CpuModel};
 use crate::machine::Machine;
 
 #[test]
@@ -25,6 +26,25 @@ fn can_disassemble_basic() {
                res);
 }
 
+#[test]
+fn can_disassemble_bytes_with_db_fallback() {
+    let code: Vec<u8> = vec![
+        0xB4, 0x09,       // mov ah,0x9
+        0xE8, 0xFB, 0xFF, // call 0x0
+        0x0F, 0xFF,       // invalid
+        0xBA, 0x0B,       // mov dx, truncated
+    ];
+    assert_eq!("00000000  B409             Mov8     ah, 0x09
+00000002  E8FBFF           CallNear 0x0000
+00000005  0F               db 0x0F
+00000006  FF               db 0xFF
+00000007  BA               db 0xBA
+00000008  0B               db 0x0B", disasm_bytes(&code).join("\n"));
+
+    assert_eq!("Mov8     ah, 0x09", disasm_first_instr(&code));
+    assert_eq!("db 0x0F", disasm_first_instr(&code[5..]));
+}
+
 #[test]
 fn can_disassemble_lea() {
     let mut machine = Machine::deterministic();
@@ -53,6 +73,34 @@ fn can_disassemble_segment_prefixed() {
                res);
 }
 
+#[test]
+fn can_describe_instruction_records() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xF3, 0x26, 0xA4,       // rep movsb [es:di], byte [es:si]
+        0xE8, 0xFA, 0xFF,       // call 0x100
+        0xEA, 0x00, 0x01, 0x00, 0xF0, // jmp 0xf000:0x100
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    let op = machine.cpu.decoder.get_instruction_info(&mut machine.mmu, 0x85F, 0x100);
+    let record = op.record();
+    assert_eq!(0x86F0, record.address);
+    assert_eq!(vec![0xF3, 0x26, 0xA4], record.bytes);
+    assert_eq!(vec!["Rep", "es"], record.prefixes);
+    assert_eq!("Movsb", record.mnemonic);
+    assert!(record.targets.is_empty());
+
+    let op = machine.cpu.decoder.get_instruction_info(&mut machine.mmu, 0x85F, 0x103);
+    let record = op.record();
+    assert_eq!("CallNear", record.mnemonic);
+    assert_eq!(vec!["0x0100"], record.operands);
+    assert_eq!(vec![BranchTarget { segment: 0x85F, offset: 0x100 }], record.targets);
+
+    let op = machine.cpu.decoder.get_instruction_info(&mut machine.mmu, 0x85F, 0x106);
+    assert_eq!(vec![BranchTarget { segment: 0xF000, offset: 0x100 }], op.branch_targets());
+}
+
 #[test]
 fn can_disassemble_values() {
     let mut machine = Machine::deterministic();
@@ -628,3 +676,74 @@ fn can_disassemble_finit() {
     assert_eq!("[085F:0100] DBE3             Finit
 [085F:0102] D9E4             Ftst", res);
 }
+
+#[test]
+fn can_disassemble_32bit_addressing() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0x67, 0x8B, 0x04, 0x88,                         // mov ax,[eax+ecx*4]
+        0x67, 0x8A, 0x44, 0x24, 0x04,                   // mov al,[esp+0x4]
+        0x67, 0x66, 0x8B, 0x1D, 0x34, 0x12, 0x00, 0x00, // mov ebx,[dword 0x1234]
+        0x67, 0xC6, 0x84, 0x8B, 0x00, 0x01, 0x00, 0x00, 0x7F, // mov byte [ebx+ecx*4+0x100],0x7f
+        0x67, 0x8B, 0x04, 0xCD, 0x00, 0x02, 0x00, 0x00, // mov ax,[ecx*8+0x200]
+        0x66, 0x67, 0x8D, 0x44, 0x48, 0x02,             // lea eax,[eax+ecx*2+0x2]
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    let res = machine.cpu.decoder.disassemble_block_to_str(&mut machine.mmu, 0x85F, 0x100, 6);
+    assert_eq!("[085F:0100] 678B0488         Mov16    ax, word [ds:eax+ecx*4]
+[085F:0104] 678A442404       Mov8     al, byte [ds:esp+0x04]
+[085F:0109] 67668B1D34120000 Mov32    ebx, dword [ds:0x1234]
+[085F:0111] 67C6848B000100007F Mov8     byte [ds:ebx+ecx*4+0x00000100], 0x7F
+[085F:011A] 678B04CD00020000 Mov16    ax, word [ds:ecx*8+0x00000200]
+[085F:0122] 66678D444802     Lea32    eax, word [ds:eax+ecx*2+0x02]", res);
+}
+
+#[test]
+fn can_disassemble_8086_aliases() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0x64, 0x02,       // jz 0x104           ; alias of 0x74
+        0xD0, 0xF0,       // setmo al
+        0x8E, 0xF0,       // mov ss,ax          ; the sreg field is 2 bits
+        0xC6, 0xC8, 0x12, // mov al,0x12        ; the reg field is ignored
+        0xC1,             // ret                ; alias of 0xC3
+        0x0F,             // pop cs
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    machine.cpu.decoder.set_model(Some(CpuModel::I8086));
+    let res = machine.cpu.decoder.disassemble_block_to_str(&mut machine.mmu, 0x85F, 0x100, 6);
+    assert_eq!("[085F:0100] 6402             Jz       0x0104
+[085F:0102] D0F0             Setmo8   al, 0x01
+[085F:0104] 8EF0             Mov16    ss, ax
+[085F:0106] C6C812           Mov8     al, 0x12
+[085F:0109] C1               Retn
+[085F:010A] 0F               Pop16    cs",
+               res);
+
+    // later models decode 0xD0 /6 as shl
+    machine.cpu.decoder.set_model(Some(CpuModel::I80386));
+    let res = machine.cpu.decoder.disassemble_block_to_str(&mut machine.mmu, 0x85F, 0x102, 1);
+    assert_eq!("[085F:0102] D0F0             Shl8     al, 0x01", res);
+}
+
+#[test]
+fn can_invalidate_cached_instructions_wrapping_at_1mb() {
+    let mut machine = Machine::deterministic();
+    assert!(!machine.mmu.memory.a20());
+    // with the A20 gate disabled, FFFF:0020 is 0000:0010
+    machine.mmu.write(0x0000, 0x0010, &[0x40]); // inc ax
+    assert_eq!("Inc16    ax", machine.cpu.decoder.get_instruction(&mut machine.mmu, 0xFFFF, 0x0020).to_string());
+    machine.mmu.write(0x0000, 0x0010, &[0x48]); // dec ax
+    assert_eq!("Dec16    ax", machine.cpu.decoder.get_instruction(&mut machine.mmu, 0xFFFF, 0x0020).to_string());
+
+    // the high memory area is decoded once the gate is enabled
+    machine.mmu.memory.set_a20(true);
+    machine.cpu.decoder.clear_cache();
+    machine.mmu.write(0xFFFF, 0x0020, &[0x43]); // inc bx
+    assert_eq!("Inc16    bx", machine.cpu.decoder.get_instruction(&mut machine.mmu, 0xFFFF, 0x0020).to_string());
+
+    machine.cpu.decoder.set_model(Some(CpuModel::I8086));
+    assert_eq!(0, machine.cpu.decoder.cached_instructions());
+}
@@ -1,9 +1,10 @@
 use std::fmt;
 
-use crate::cpu::instruction::{Instruction, ModRegRm};
-use crate::cpu::parameter::{Parameter, ParameterSet};
+use crate::cpu::instruction::{Instruction, ModRegRm, RepeatMode};
+use crate::cpu::parameter::Parameter;
 use crate::cpu::segment::Segment;
-use crate::cpu::register::R;
+use crate::cpu::register::{R, AMode};
+use crate::cpu::decoder::{OperandSize, AddressSize};
 use crate::cpu::op::{Op};
 
 #[cfg(test)]
@@ -39,25 +40,34 @@ impl Encoder {
         }
     }
 
+    /// encodes a sequence of instructions, the first one located at offset 0
     pub fn encode_vec(&self, ops: &[Instruction]) -> Result<Vec<u8>, EncodeError> {
         let mut out = vec!();
         for op in ops {
-            let enc = self.encode(op);
-            if let Ok(data) = enc {
-                out.extend(data);
-            } else {
-                return enc;
-            }
+            let data = self.encode_at(op, out.len() as u16)?;
+            out.extend(data);
         }
         Ok(out)
     }
 
-    /// encodes Instruction to a valid byte sequence
+    /// encodes Instruction to a valid byte sequence, located at offset 0
     pub fn encode(&self, op: &Instruction) -> Result<Vec<u8>, EncodeError> {
+        self.encode_at(op, 0)
+    }
+
+    /// encodes Instruction to a valid byte sequence, located at `offset`.
+    /// the decoder resolves relative branches to their target offset, so the
+    /// location is needed to encode them back
+    pub fn encode_at(&self, op: &Instruction, offset: u16) -> Result<Vec<u8>, EncodeError> {
         let mut out = vec!();
         if op.lock {
             out.push(0xF0);
         }
+        match op.repeat {
+            RepeatMode::None => {},
+            RepeatMode::Rep | RepeatMode::Repe => out.push(0xF3),
+            RepeatMode::Repne => out.push(0xF2),
+        }
         match op.segment_prefix {
             Segment::Default => {},
             Segment::ES => out.push(0x26),
@@ -67,24 +77,57 @@ impl Encoder {
             Segment::FS => out.push(0x64),
             Segment::GS => out.push(0x65),
         }
+        let control_register = matches!(op.params.src, Parameter::CReg32(_));
+        if (op.op_size == OperandSize::_32bit || Encoder::is_32bit(&op.command)) && !control_register {
+            out.push(0x66); // Operand-size override prefix
+        }
+        if Encoder::address32(op) {
+            out.push(0x67); // Address-size override prefix
+        }
 
+        let data = self.encode_command(op, offset.wrapping_add(out.len() as u16))?;
+        out.extend(data);
+        Ok(out)
+    }
+
+    /// encodes the opcode and operands, `offset` is the location of the opcode
+    fn encode_command(&self, op: &Instruction, offset: u16) -> Result<Vec<u8>, EncodeError> {
+        let mut out = vec!();
         match op.command {
             Op::Daa => out.push(0x27),
             Op::Das => out.push(0x2F),
             Op::Aaa => out.push(0x37),
             Op::Aas => out.push(0x3F),
-            Op::Bsf => {
-                // bsf r16, r/m16
-                out.push(0x0F);
-                out.push(0xBC);
-                out.extend(self.encode_r_rm(&op.params));
-            }
-            Op::Bt => {
-                // bt r/m16, r16
-                out.push(0x0F);
-                out.push(0xA3); // XXX BT r/m16, imm8   form is 0xBA
-                out.extend(self.encode_rm_r(&op.params));
-            }
+            Op::Pusha16 | Op::Pushad32 => out.push(0x60),
+            Op::Popa16 | Op::Popad32 => out.push(0x61),
+            Op::Insb => out.push(0x6C),
+            Op::Insw | Op::Insd => out.push(0x6D),
+            Op::Outsb => out.push(0x6E),
+            Op::Outsw | Op::Outsd => out.push(0x6F),
+            Op::Nop => out.push(0x90),
+            Op::Cbw | Op::Cwde32 => out.push(0x98),
+            Op::Cwd16 => out.push(0x99),
+            Op::Fwait => out.push(0x9B),
+            Op::Pushf => out.push(0x9C),
+            Op::Popf => out.push(0x9D),
+            Op::Sahf => out.push(0x9E),
+            Op::Lahf => out.push(0x9F),
+            Op::Movsb => out.push(0xA4),
+            Op::Movsw | Op::Movsd => out.push(0xA5),
+            Op::Cmpsb => out.push(0xA6),
+            Op::Cmpsw | Op::Cmpsd => out.push(0xA7),
+            Op::Stosb => out.push(0xAA),
+            Op::Stosw | Op::Stosd => out.push(0xAB),
+            Op::Lodsb => out.push(0xAC),
+            Op::Lodsw | Op::Lodsd => out.push(0xAD),
+            Op::Scasb => out.push(0xAE),
+            Op::Scasw | Op::Scasd => out.push(0xAF),
+            Op::Leave => out.push(0xC9),
+            Op::Into => out.push(0xCE),
+            Op::Iret => out.push(0xCF),
+            Op::Salc => out.push(0xD6),
+            Op::Xlatb => out.push(0xD7),
+            Op::Hlt => out.push(0xF4),
             Op::Cmc => out.push(0xF5),
             Op::Clc => out.push(0xF8),
             Op::Stc => out.push(0xF9),
@@ -92,65 +135,145 @@ impl Encoder {
             Op::Sti => out.push(0xFB),
             Op::Cld => out.push(0xFC),
             Op::Std => out.push(0xFD),
-            Op::Cbw => out.push(0x98),
-            Op::Cwd16 => out.push(0x99),
-            Op::Sahf => out.push(0x9E),
-            Op::Lahf => out.push(0x9F),
-            Op::Nop => out.push(0x90),
-            Op::Salc => out.push(0xD6),
-            Op::Xlatb => out.push(0xD7),
-            Op::Cmpsb => out.push(0xA6),
-            Op::Cmpsw => out.push(0xA7),
+            Op::Aam => {
+                // D4 ib: aam imm8
+                out.push(0xD4);
+                out.extend(self.encode_imm8(&op.params.dst)?);
+            }
             Op::Aad => {
-                if let Parameter::Imm8(imm) = op.params.dst {
-                    out.push(0xD5);
-                    out.push(imm);
-                } else {
-                    unreachable!();
-                }
+                // D5 ib: aad imm8
+                out.push(0xD5);
+                out.extend(self.encode_imm8(&op.params.dst)?);
             }
-            Op::Aam => {
-                if let Parameter::Imm8(imm) = op.params.dst {
-                    out.push(0xD4);
-                    out.push(imm);
+            Op::Arpl => {
+                // 63 /r: arpl r/m16, r16
+                out.push(0x63);
+                out.extend(self.encode_rm_r(op)?);
+            }
+            Op::Bound => {
+                // 62 /r: bound r16, m16&16
+                out.push(0x62);
+                out.extend(self.encode_r_rm(op)?);
+            }
+            Op::Bsf16 | Op::Bsf32 | Op::Bsr16 | Op::Bsr32 => {
+                // 0F BC /r: bsf r16, r/m16
+                // 0F BD /r: bsr r16, r/m16
+                out.push(0x0F);
+                out.push(match op.command {
+                    Op::Bsf16 | Op::Bsf32 => 0xBC,
+                    _ => 0xBD,
+                });
+                out.extend(self.encode_r_rm(op)?);
+            }
+            Op::Bt16 | Op::Bt32 | Op::Bts16 | Op::Bts32 | Op::Btr16 | Op::Btr32 | Op::Btc16 | Op::Btc32 => {
+                // 0F A3 /r: bt r/m16, r16      0F BA /4 ib: bt r/m16, imm8
+                // 0F AB /r: bts r/m16, r16     0F BA /5 ib: bts r/m16, imm8
+                // 0F B3 /r: btr r/m16, r16     0F BA /6 ib: btr r/m16, imm8
+                // 0F BB /r: btc r/m16, r16     0F BA /7 ib: btc r/m16, imm8
+                let (op_r, reg) = match op.command {
+                    Op::Bt16 | Op::Bt32 => (0xA3, 4),
+                    Op::Bts16 | Op::Bts32 => (0xAB, 5),
+                    Op::Btr16 | Op::Btr32 => (0xB3, 6),
+                    _ => (0xBB, 7),
+                };
+                out.push(0x0F);
+                if let Parameter::Imm8(_) = op.params.src {
+                    out.push(0xBA);
+                    out.extend(self.encode_rm(op, &op.params.dst, reg)?);
+                    out.extend(self.encode_imm8(&op.params.src)?);
                 } else {
-                    unreachable!();
+                    out.push(op_r);
+                    out.extend(self.encode_rm_r(op)?);
                 }
             }
-            Op::Dec8 | Op::Inc8 => {
-                // 0xFE: r/m8
-                out.push(0xFE);
-                out.extend(self.encode_rm(&op.params.dst, Encoder::feff_index(&op.command)));
+            Op::Lar16 => {
+                // 0F 02 /r: lar r16, r/m16
+                out.extend(&[0x0F, 0x02]);
+                out.extend(self.encode_r_rm(op)?);
+            }
+            Op::Sldt => {
+                // 0F 00 /0: sldt r/m16
+                out.extend(&[0x0F, 0x00]);
+                out.extend(self.encode_rm(op, &op.params.dst, 0)?);
+            }
+            Op::Smsw => {
+                // 0F 01 /4: smsw r/m16
+                out.extend(&[0x0F, 0x01]);
+                out.extend(self.encode_rm(op, &op.params.dst, 4)?);
+            }
+            Op::Seto | Op::Setno | Op::Setc | Op::Setnc | Op::Setz | Op::Setnz | Op::Setna | Op::Seta |
+            Op::Sets | Op::Setns | Op::Setpe | Op::Setpo | Op::Setl | Op::Setnl | Op::Setng | Op::Setg => {
+                // 0F 90-9F /0: setcc r/m8, in the order of the jcc opcodes
+                out.push(0x0F);
+                out.push(match op.command {
+                    Op::Seto => 0x90,
+                    Op::Setno => 0x91,
+                    Op::Setc => 0x92,
+                    Op::Setnc => 0x93,
+                    Op::Setz => 0x94,
+                    Op::Setnz => 0x95,
+                    Op::Setna => 0x96,
+                    Op::Seta => 0x97,
+                    Op::Sets => 0x98,
+                    Op::Setns => 0x99,
+                    Op::Setpe => 0x9A,
+                    Op::Setpo => 0x9B,
+                    Op::Setl => 0x9C,
+                    Op::Setnl => 0x9D,
+                    Op::Setng => 0x9E,
+                    _ => 0x9F,
+                });
+                out.extend(self.encode_rm(op, &op.params.dst, 0)?);
+            }
+            Op::Shld => {
+                // 0F A4 /r ib: shld r/m16, r16, imm8
+                out.extend(&[0x0F, 0xA4]);
+                out.extend(self.encode_rm_r(op)?);
+                out.extend(self.encode_imm8(&op.params.src2)?);
+            }
+            Op::Shrd => {
+                // 0F AC /r ib: shrd r/m16, r16, imm8
+                out.extend(&[0x0F, 0xAC]);
+                out.extend(self.encode_rm_r(op)?);
+                out.extend(self.encode_imm8(&op.params.src2)?);
+            }
+            Op::Les | Op::Lds => {
+                // C4 /r: les r16, m16:16
+                // C5 /r: lds r16, m16:16
+                out.push(if op.command == Op::Les { 0xC4 } else { 0xC5 });
+                out.extend(self.encode_r_rm(op)?);
             }
-            Op::Dec16 | Op::Inc16 => {
-                if let Parameter::Reg16(ref r) = op.params.dst {
-                    match op.command {
-                        Op::Inc16 => out.push(0x40 | r.index() as u8), // 0x40...0x47: inc r16
-                        Op::Dec16 => out.push(0x48 | r.index() as u8), // 0x48...0x4F: dec r16
-                        // 0x50...0x57: push r16
-                        // 0x58...0x5F: pop r16
-                        _ => panic!("unhandled {:?}", op.command),
+            Op::Lea16 | Op::Lea32 => {
+                // 8D /r: lea r16, m
+                out.push(0x8D);
+                out.extend(self.encode_r_rm(op)?);
+            }
+            Op::Enter => {
+                // C8 iw ib: enter imm16, imm8
+                out.push(0xC8);
+                out.extend(self.encode_imm(&op.params.dst)?);
+                out.extend(self.encode_imm8(&op.params.src)?);
+            }
+            Op::Retn | Op::Retf | Op::RetImm16 => {
+                // C2 iw: ret [near] imm16
+                // C3: ret [near]
+                // CA iw: ret [far] imm16
+                // CB: ret [far]
+                let far = op.command == Op::Retf;
+                match op.params.dst {
+                    Parameter::None => out.push(if far { 0xCB } else { 0xC3 }),
+                    Parameter::Imm16(_) => {
+                        out.push(if far { 0xCA } else { 0xC2 });
+                        out.extend(self.encode_imm(&op.params.dst)?);
                     }
-                } else {
-                    // 0xFF: // 0xFF: r/m16
-                    out.push(0xFF);
-                    out.extend(self.encode_rm(&op.params.dst, Encoder::feff_index(&op.command)));
+                    _ => return Err(EncodeError::UnhandledParameter(op.params.dst.clone())),
                 }
             }
-            Op::Dec32 | Op::Inc32 => {
-                out.push(0x66); // REX.W (Operand-size override prefix)
-                if let Parameter::Reg32(ref r) = op.params.dst {
-                    match op.command {
-                        Op::Inc32 => out.push(0x40 | r.index() as u8), // 0x40...0x47: inc r32
-                        Op::Dec32 => out.push(0x48 | r.index() as u8), // 0x48...0x4F: dec r32
-                        // 0x50...0x57: push r16
-                        // 0x58...0x5F: pop r16
-                        _ => panic!("unhandled {:?}", op.command),
-                    }
+            Op::Callback => {
+                if let Parameter::Imm16(imm) = op.params.dst {
+                    out.extend(&[0xFE, 0x38, imm as u8, (imm >> 8) as u8]);
                 } else {
-                    // 0xFF: // 0xFF: r/m16
-                    out.push(0xFF);
-                    out.extend(self.encode_rm(&op.params.dst, Encoder::feff_index(&op.command)));
+                    return Err(EncodeError::UnhandledParameter(op.params.dst.clone()));
                 }
             }
             Op::Int => {
@@ -167,682 +290,629 @@ impl Encoder {
                     return Err(EncodeError::UnhandledParameter(op.params.dst.clone()));
                 }
             }
-            /*
-            Op::Cmp16 => {
-                // 0x39: cmp r/m16, r16
-                // 0x3B: cmp r16, r/m16
-                // 0x3D: cmp AX, imm16
-                // 0x81: <arithmetic> r/m16, imm16
-                // 0x83: <arithmetic> r/m16, imm8
-            }
-            */
-            Op::Xchg8 => {
-                // 86 /r           XCHG r/m8, r8
-                out.push(0x86);
-                out.extend(self.encode_rm_r(&op.params));
-            }
-            Op::Xchg16 => {
-                // XXX if both are registers and one is AX, use 1-byte encoding
-                if let Parameter::Reg16(r1) = op.params.dst {
-                    if let Parameter::Reg16(r2) = op.params.src {
-                        if r1 == R::AX {
-                            out.push(0x90 + r2.index() as u8);
-                            return Ok(out);
-                        } else if r2 == R::AX {
-                            out.push(0x90 + r1.index() as u8);
-                            return Ok(out);
-                        }
+            Op::In8 | Op::In16 => {
+                // E4 ib: in AL, imm8
+                // E5 ib: in AX, imm8
+                // EC: in AL, DX
+                // ED: in AX, DX
+                let w = if op.command == Op::In8 { 0 } else { 1 };
+                match op.params.src {
+                    Parameter::Imm8(imm) => {
+                        out.push(0xE4 | w);
+                        out.push(imm);
                     }
+                    Parameter::Reg16(R::DX) => out.push(0xEC | w),
+                    _ => return Err(EncodeError::UnhandledParameter(op.params.src.clone())),
                 }
-
-                // 87 /r           XCHG r/m16, r16
-                out.push(0x87);
-                out.extend(self.encode_rm_r(&op.params));
             }
-            Op::Lea16 => {
-                 out.push(0x8D);
-                 out.extend(self.encode_r_rm(&op.params)); // XXX 16-bit ver?!
-                // lea r16, m        di, [bx]  = 0b11_1111
-            }
-            Op::Shld => {
-                // shld r/m16, r16, imm8
-                out.push(0x0F);
-                out.push(0xA4);
-                out.extend(self.encode_rm_r_imm(&op.params));
+            Op::Out8 | Op::Out16 => {
+                // E6 ib: out imm8, AL
+                // E7 ib: out imm8, AX
+                // EE: out DX, AL
+                // EF: out DX, AX
+                let w = if op.command == Op::Out8 { 0 } else { 1 };
+                match op.params.dst {
+                    Parameter::Imm8(imm) => {
+                        out.push(0xE6 | w);
+                        out.push(imm);
+                    }
+                    Parameter::Reg16(R::DX) => out.push(0xEE | w),
+                    _ => return Err(EncodeError::UnhandledParameter(op.params.dst.clone())),
+                }
             }
-            Op::Shrd => {
-                out.push(0x0F);
-                out.push(0xAC);
-                out.extend(self.encode_rm_r_imm(&op.params));
+            Op::Jo | Op::Jno | Op::Jc | Op::Jnc | Op::Jz | Op::Jnz | Op::Jna | Op::Ja |
+            Op::Js | Op::Jns | Op::Jpe | Op::Jpo | Op::Jl | Op::Jnl | Op::Jng | Op::Jg => {
+                // 70+cc rel8: jcc short
+                // 0F 80+cc rel16: jcc near
+                let cc = Encoder::jcc_index(&op.command);
+                match self.encode_rel8(op, offset, 0x70 + cc) {
+                    Ok(data) => out.extend(data),
+                    Err(_) => out.extend(self.encode_rel16(op, offset, &[0x0F, 0x80 + cc])?),
+                }
             }
-            Op::Movsx16 => {
-                // MOVSX r16, r/m8
-                out.push(0x0F);
-                if let Parameter::Reg16(r) = op.params.dst {
-                    out.push(0xBE);
-                    out.extend(self.encode_rm(&op.params.src, r.u8()));
+            Op::Loopne => out.extend(self.encode_rel8(op, offset, 0xE0)?),
+            Op::Loope => out.extend(self.encode_rel8(op, offset, 0xE1)?),
+            Op::Loop => out.extend(self.encode_rel8(op, offset, 0xE2)?),
+            Op::Jcxz => out.extend(self.encode_rel8(op, offset, 0xE3)?),
+            Op::JmpShort => out.extend(self.encode_rel8(op, offset, 0xEB)?),
+            Op::CallNear | Op::JmpNear => {
+                if let Parameter::Imm16(_) = op.params.dst {
+                    // E8 rel16: call near
+                    // E9 rel16: jmp near
+                    let opcode = if op.command == Op::CallNear { 0xE8 } else { 0xE9 };
+                    out.extend(self.encode_rel16(op, offset, &[opcode])?);
                 } else {
-                    return Err(EncodeError::UnhandledParameter(op.params.dst.clone()));
+                    // FF /2: call near r/m16
+                    // FF /4: jmp near r/m16
+                    out.push(0xFF);
+                    out.extend(self.encode_rm(op, &op.params.dst, Encoder::feff_index(&op.command))?);
                 }
             }
-            Op::Movsx32 => {
-                // MOVSX r32, r/m8
-                // MOVSX r32, r/m16
-                out.push(0x66); // REX.W (Operand-size override prefix)
-                out.push(0x0F);
-                if let Parameter::Reg32(r) = op.params.dst {
-                    match op.params.src {
-                        Parameter::Reg8(_) => {
-                            out.push(0xBE);
-                            out.extend(self.encode_rm(&op.params.src, r.u8()));
-                        }
-                        Parameter::Reg16(_) => {
-                            out.push(0xBF);
-                            out.extend(self.encode_rm(&op.params.src, r.u8()));
-                        }
-                        _ => return Err(EncodeError::UnhandledParameter(op.params.src.clone())),
+            Op::CallFar | Op::JmpFar => {
+                if let Parameter::Ptr16Imm(seg, imm) = op.params.dst {
+                    // 9A iw iw: call far ptr16:16
+                    // EA iw iw: jmp far ptr16:16
+                    // 66 9A id iw: call far ptr16:32
+                    out.push(if op.command == Op::CallFar { 0x9A } else { 0xEA });
+                    match op.op_size {
+                        OperandSize::_16bit => out.extend(&imm.to_le_bytes()),
+                        OperandSize::_32bit => out.extend(&u32::from(imm).to_le_bytes()),
                     }
+                    out.extend(&seg.to_le_bytes());
                 } else {
-                    return Err(EncodeError::UnhandledParameter(op.params.dst.clone()));
+                    // FF /3: call far m16:16
+                    // FF /5: jmp far m16:16
+                    // 66 FF /3: call far m16:32
+                    out.push(0xFF);
+                    out.extend(self.encode_rm(op, &op.params.dst, Encoder::feff_index(&op.command))?);
                 }
             }
-            Op::Movzx16 => {
-                // MOVZX r16, r/m8
-                out.push(0x0F);
-                if let Parameter::Reg16(r) = op.params.dst {
-                    out.push(0xB6);
-                    out.extend(self.encode_rm(&op.params.src, r.u8()));
-                } else {
-                    return Err(EncodeError::UnhandledParameter(op.params.dst.clone()));
-                }
+            Op::Dec8 | Op::Inc8 => {
+                // 0xFE: r/m8
+                out.push(0xFE);
+                out.extend(self.encode_rm(op, &op.params.dst, Encoder::feff_index(&op.command))?);
             }
-            Op::Movzx32 => {
-                // MOVZX r32, r/m8
-                // MOVZX r32, r/m16
-                out.push(0x66); // REX.W (Operand-size override prefix)
-                out.push(0x0F);
-                if let Parameter::Reg32(r) = op.params.dst {
-                    match op.params.src {
-                        Parameter::Reg8(_) => {
-                            out.push(0xB6);
-                            out.extend(self.encode_rm(&op.params.src, r.u8()));
-                        }
-                        Parameter::Reg16(_) => {
-                            out.push(0xB7);
-                            out.extend(self.encode_rm(&op.params.src, r.u8()));
-                        }
-                        _ => return Err(EncodeError::UnhandledParameter(op.params.src.clone())),
+            Op::Dec16 | Op::Inc16 | Op::Dec32 | Op::Inc32 => {
+                match op.params.dst {
+                    Parameter::Reg16(r) | Parameter::Reg32(r) => match op.command {
+                        Op::Inc16 | Op::Inc32 => out.push(0x40 | r.u8()), // 0x40...0x47: inc r16
+                        _ => out.push(0x48 | r.u8()),                     // 0x48...0x4F: dec r16
+                    },
+                    _ => {
+                        // 0xFF: r/m16
+                        out.push(0xFF);
+                        out.extend(self.encode_rm(op, &op.params.dst, Encoder::feff_index(&op.command))?);
                     }
-                } else {
-                    return Err(EncodeError::UnhandledParameter(op.params.dst.clone()));
                 }
             }
-            Op::Mov8 => {
+            Op::Push16 | Op::Push32 => {
                 match op.params.dst {
-                    Parameter::Reg8(r) => {
-                        if r == R::AL {
-                            if let Parameter::Ptr8(_, imm16) = op.params.src {
-                                // 0xA0: mov AL, [moffs8]
-                                out.push(0xA0);
-                                out.push(imm16 as u8);
-                                out.push((imm16 >> 8) as u8);
-                                return Ok(out);
-                            }
-                        }
-                        if let Parameter::Imm8(i) = op.params.src {
-                            // 0xB0...0xB7: mov r8, u8
-                            out.push(0xB0 | r as u8);
-                            out.push(i as u8);
-                        } else if op.params.src.is_ptr() {
-                            // 0x8A: mov r8, r/m8
-                            out.push(0x8A);
-                            out.extend(self.encode_r_rm(&op.params));
-                        } else {
-                            // 0x88: mov r/m8, r8
-                            out.push(0x88);
-                            out.extend(self.encode_rm_r(&op.params));
-                        }
+                    // 0x50...0x57: push r16
+                    Parameter::Reg16(r) | Parameter::Reg32(r) => out.push(0x50 | r.u8()),
+                    Parameter::SReg16(r) => match r {
+                        R::ES => out.push(0x06),
+                        R::CS => out.push(0x0E),
+                        R::SS => out.push(0x16),
+                        R::DS => out.push(0x1E),
+                        R::FS => out.extend(&[0x0F, 0xA0]),
+                        R::GS => out.extend(&[0x0F, 0xA8]),
+                        _ => return Err(EncodeError::UnhandledParameter(op.params.dst.clone())),
+                    },
+                    Parameter::Imm16(_) => {
+                        // 0x68: push imm16
+                        out.push(0x68);
+                        out.extend(self.encode_imm(&op.params.dst)?);
                     }
-                    Parameter::Ptr8(_, _) |
-                    Parameter::Ptr8Amode(_, _) |
-                    Parameter::Ptr8AmodeS8(_, _, _) |
-                    Parameter::Ptr8AmodeS16(_, _, _) => {
-                        if let Parameter::Ptr8(_, imm16) = op.params.dst {
-                            if let Parameter::Reg8(r) =  op.params.src {
-                                if r == R::AL {
-                                    // 0xA2: mov [moffs8], AL
-                                    out.push(0xA2);
-                                    out.push(imm16 as u8);
-                                    out.push((imm16 >> 8) as u8);
-                                    return Ok(out);
-                                }
-                            }
-                        }
-
-                        // 0x88: mov r/m8, r8
-                        out.push(0x88);
-                        out.extend(self.encode_rm_r(&op.params));
+                    Parameter::ImmS8(_) => {
+                        // 0x6A: push imm8
+                        out.push(0x6A);
+                        out.extend(self.encode_imm(&op.params.dst)?);
                     }
                     _ => {
-                        return Err(EncodeError::UnhandledParameter(op.params.dst.clone()));
+                        // FF /6: push r/m16
+                        out.push(0xFF);
+                        out.extend(self.encode_rm(op, &op.params.dst, Encoder::feff_index(&op.command))?);
                     }
                 }
             }
-            Op::Mov16 => {
-                //0x89: mov r/m16, r16
-                //0x8B: mov r16, r/m16
-                //0x8C: mov r/m16, sreg
-                //0x8E: mov sreg, r/m16
-                //0xC7: mov r/m16, imm16    reg = 0 for MOV.
-
-                if op.params.src.is_imm() {
-                    if let Parameter::Reg16(ref r) = op.params.dst {
-                        //0xB8...0xBF: mov r16, u16
-                        out.push(0xB8 | r.index() as u8);
-                        if let Parameter::Imm16(imm16) = op.params.src {
-                            out.push(imm16 as u8);
-                            out.push((imm16 >> 8) as u8);
-                        } else {
-                            return Err(EncodeError::UnhandledParameter(op.params.src.clone()));
-                        }
-                    } else {
-                        return Err(EncodeError::UnhandledParameter(op.params.dst.clone()));
+            Op::Pop16 | Op::Pop32 => {
+                match op.params.dst {
+                    // 0x58...0x5F: pop r16
+                    Parameter::Reg16(r) | Parameter::Reg32(r) => out.push(0x58 | r.u8()),
+                    Parameter::SReg16(r) => match r {
+                        R::ES => out.push(0x07),
+                        R::SS => out.push(0x17),
+                        R::DS => out.push(0x1F),
+                        R::FS => out.extend(&[0x0F, 0xA1]),
+                        R::GS => out.extend(&[0x0F, 0xA9]),
+                        _ => return Err(EncodeError::UnhandledParameter(op.params.dst.clone())),
+                    },
+                    _ => {
+                        // 8F /0: pop r/m16
+                        out.push(0x8F);
+                        out.extend(self.encode_rm(op, &op.params.dst, 0)?);
                     }
-                    /*
-                } else if op.params.src.is_ptr() {
-                    // 0x8A: mov r8, r/m8
-                    out.push(0x8A);
-                    out.extend(self.encode_r8_rm8(&op.params));
-                } else {
-                    // 0x88: mov r/m8, r8
-                    out.push(0x88);
-                    out.extend(self.encode_rm8_r8(&op.params));
-                }*/
-                } else {
-                    return Err(EncodeError::UnhandledParameter(op.params.dst.clone()));
                 }
             }
-            Op::Mov32 => {
-                // XXX TODO handle more forms
-                out.push(0x66); // REX.W (Operand-size override prefix)
-                if op.params.src.is_imm() {
-                    if let Parameter::Reg32(ref r) = op.params.dst {
-                        //0x66 0xB8...0xBF: mov r32, u32
-                        out.push(0xB8 | r.index() as u8);
-                        if let Parameter::Imm32(imm32) = op.params.src {
-                            out.push(imm32 as u8);
-                            out.push((imm32 >> 8) as u8);
-                            out.push((imm32 >> 16) as u8);
-                            out.push((imm32 >> 24) as u8);
-                        } else {
-                            return Err(EncodeError::UnhandledParameter(op.params.src.clone()));
-                        }
+            Op::Xchg8 | Op::Xchg16 | Op::Xchg32 => {
+                if op.command != Op::Xchg8 && Encoder::is_accumulator(&op.params.dst) &&
+                    op.params.src.is_reg() && !Encoder::is_accumulator(&op.params.src) {
+                    // 90+rw: xchg AX, r16
+                    // NOTE: "xchg ax,ax" is an alias of "nop"
+                    out.push(0x90 | Encoder::reg_index(&op.params.src)?);
+                } else {
+                    // 86 /r: xchg r/m8, r8
+                    // 87 /r: xchg r/m16, r16
+                    out.push(if op.command == Op::Xchg8 { 0x86 } else { 0x87 });
+                    if op.params.src.is_reg() {
+                        out.extend(self.encode_rm_r(op)?);
                     } else {
-                        return Err(EncodeError::UnhandledParameter(op.params.dst.clone()));
+                        out.extend(self.encode_r_rm(op)?);
                     }
-                } else {
-                    return Err(EncodeError::UnhandledParameter(op.params.dst.clone()));
-                }
-            }
-            Op::And8 | Op::Or8 | Op::Add8 | Op::Adc8 | Op::Sub8 | Op::Sbb8 | Op::Cmp8 | Op::Xor8 => {
-                match self.arith_instr8(op) {
-                    Ok(data) => out.extend(data),
-                    Err(why) => return Err(why),
-                }
-            }
-            Op::Test8 | Op::Not8 | Op::Neg8 | Op::Mul8 | Op::Imul8 | Op::Div8 | Op::Idiv8 => {
-                match self.math_instr8(op) {
-                    Ok(data) => out.extend(data),
-                    Err(why) => return Err(why),
-                }
-            }
-            Op::Rol8 | Op::Ror8 | Op::Rcl8 | Op::Rcr8 | Op::Shl8 | Op::Shr8 | Op::Sar8 => {
-                match self.bitshift_instr8(op) {
-                    Ok(data) => out.extend(data),
-                    Err(why) => return Err(why),
                 }
             }
-            Op::Rol16 | Op::Ror16 | Op::Rcl16 | Op::Rcr16 | Op::Shl16 | Op::Shr16 | Op::Sar16 => {
-                match self.bitshift_instr16(op) {
-                    Ok(data) => out.extend(data),
-                    Err(why) => return Err(why),
-                }
-            }
-            Op::Rol32 | Op::Ror32 | Op::Rcl32 | Op::Rcr32 | Op::Shl32 | Op::Shr32 | Op::Sar32 => {
-                match self.bitshift_instr32(op) {
-                    Ok(data) => out.extend(data),
-                    Err(why) => return Err(why),
-                }
+            Op::Xadd8 | Op::Xadd16 | Op::Xadd32 | Op::Cmpxchg8 | Op::Cmpxchg16 | Op::Cmpxchg32 => {
+                // 0F B0 /r: cmpxchg r/m8, r8
+                // 0F B1 /r: cmpxchg r/m16, r16
+                // 0F C0 /r: xadd r/m8, r8
+                // 0F C1 /r: xadd r/m16, r16
+                out.push(0x0F);
+                out.push(match op.command {
+                    Op::Cmpxchg8 => 0xB0,
+                    Op::Cmpxchg16 | Op::Cmpxchg32 => 0xB1,
+                    Op::Xadd8 => 0xC0,
+                    _ => 0xC1,
+                });
+                out.extend(self.encode_rm_r(op)?);
             }
-            Op::And16 | Op::Or16 | Op::Add16 | Op::Adc16 | Op::Sub16 | Op::Sbb16 | Op::Cmp16 | Op::Xor16 => {
-                match self.arith_instr16(op) {
-                    Ok(data) => out.extend(data),
-                    Err(why) => return Err(why),
-                }
+            Op::Bswap32 => {
+                // 0F C8+rd: bswap r32
+                out.push(0x0F);
+                out.push(0xC8 | Encoder::reg_index(&op.params.dst)?);
             }
-            Op::And32 | Op::Or32 | Op::Add32 | Op::Adc32 | Op::Sub32 | Op::Sbb32 | Op::Cmp32 | Op::Xor32 => {
-                match self.arith_instr32(op) {
-                    Ok(data) => out.extend(data),
-                    Err(why) => return Err(why),
-                }
+            Op::Movsx16 | Op::Movsx32 | Op::Movzx16 | Op::Movzx32 => {
+                // 0F B6 /r: movzx r16, r/m8
+                // 0F B7 /r: movzx r32, r/m16
+                // 0F BE /r: movsx r16, r/m8
+                // 0F BF /r: movsx r32, r/m16
+                let base = match op.command {
+                    Op::Movzx16 | Op::Movzx32 => 0xB6,
+                    _ => 0xBE,
+                };
+                out.push(0x0F);
+                out.push(if Encoder::is_8bit(&op.params.src) { base } else { base + 1 });
+                out.extend(self.encode_r_rm(op)?);
             }
-            Op::Test16 | Op::Not16 | Op::Neg16 | Op::Div16 | Op::Idiv16 | Op::Mul16 | Op::Imul16 => {
-                match self.math_instr16(op) {
-                    Ok(data) => out.extend(data),
-                    Err(why) => return Err(why),
-                }
+            Op::Mov8 | Op::Mov16 | Op::Mov32 => out.extend(self.mov_instr(op)?),
+            Op::Add8 | Op::Or8 | Op::Adc8 | Op::Sbb8 | Op::And8 | Op::Sub8 | Op::Xor8 | Op::Cmp8 |
+            Op::Add16 | Op::Or16 | Op::Adc16 | Op::Sbb16 | Op::And16 | Op::Sub16 | Op::Xor16 | Op::Cmp16 |
+            Op::Add32 | Op::Or32 | Op::Adc32 | Op::Sbb32 | Op::And32 | Op::Sub32 | Op::Xor32 | Op::Cmp32 => {
+                out.extend(self.arith_instr(op)?);
             }
-            Op::Test32 | Op::Not32 | Op::Neg32 | Op::Div32 | Op::Idiv32 | Op::Mul32 | Op::Imul32 => {
-                match self.math_instr32(op) {
-                    Ok(data) => out.extend(data),
-                    Err(why) => return Err(why),
-                }
+            Op::Test8 | Op::Not8 | Op::Neg8 | Op::Mul8 | Op::Imul8 | Op::Div8 | Op::Idiv8 |
+            Op::Test16 | Op::Not16 | Op::Neg16 | Op::Mul16 | Op::Imul16 | Op::Div16 | Op::Idiv16 |
+            Op::Test32 | Op::Not32 | Op::Neg32 | Op::Mul32 | Op::Imul32 | Op::Div32 | Op::Idiv32 => {
+                out.extend(self.math_instr(op)?);
             }
-            Op::Push16 => {
-                if let Parameter::Imm16(imm16) = op.params.dst {
-                    // 0x68: push imm16
-                    out.push(0x68);
-                    out.push(imm16 as u8);
-                    out.push((imm16 >> 8) as u8);
-                } else {
-                    return Err(EncodeError::UnhandledParameter(op.params.dst.clone()));
-                }
+            Op::Rol8 | Op::Ror8 | Op::Rcl8 | Op::Rcr8 | Op::Shl8 | Op::Shr8 | Op::Sar8 |
+            Op::Rol16 | Op::Ror16 | Op::Rcl16 | Op::Rcr16 | Op::Shl16 | Op::Shr16 | Op::Sar16 |
+            Op::Rol32 | Op::Ror32 | Op::Rcl32 | Op::Rcr32 | Op::Shl32 | Op::Shr32 | Op::Sar32 |
+            Op::Setmo8 | Op::Setmo16 => {
+                out.extend(self.bitshift_instr(op)?);
             }
-            Op::Pop16 => {
-                if let Parameter::Reg16(r) = op.params.dst {
-                    out.push(0x58 + r.index() as u8);
-                } else {
-                    return Err(EncodeError::UnhandledParameter(op.params.dst.clone()));
-                }
+            Op::Fadd | Op::Fmul | Op::Fcom | Op::Fcomp | Op::Fsub | Op::Fsubr | Op::Fdiv | Op::Fdivr |
+            Op::Fld | Op::Fxch | Op::Fst | Op::Fstp | Op::Fldcw | Op::Fnstcw |
+            Op::Ficomp | Op::Fild | Op::Fisttp | Op::Fist | Op::Fistp | Op::Ffree |
+            Op::Faddp | Op::Fimul | Op::Ficom | Op::Fsubrp | Op::Fsubp | Op::Fidiv | Op::Fdivp => {
+                out.extend(self.fpu_instr(op)?);
             }
-            Op::Popf => out.push(0x9D),
-            Op::Loop => {
-                if let Parameter::Imm16(_imm16) = op.params.dst {
-                    // XXX param should be untouched S8 !!!
-                    out.push(0xE2);
-                    panic!("need rel offset to encode loop");
-                } else {
+            Op::Fchs => out.extend(&[0xD9, 0xE0]),
+            Op::Fabs => out.extend(&[0xD9, 0xE1]),
+            Op::Ftst => out.extend(&[0xD9, 0xE4]),
+            Op::Fld1 => out.extend(&[0xD9, 0xE8]),
+            Op::Fldl2t => out.extend(&[0xD9, 0xE9]),
+            Op::Fldl2e => out.extend(&[0xD9, 0xEA]),
+            Op::Fldpi => out.extend(&[0xD9, 0xEB]),
+            Op::Fldz => out.extend(&[0xD9, 0xEE]),
+            Op::Fpatan => out.extend(&[0xD9, 0xF3]),
+            Op::Fsqrt => out.extend(&[0xD9, 0xFA]),
+            Op::Fsincos => out.extend(&[0xD9, 0xFB]),
+            Op::Frndint => out.extend(&[0xD9, 0xFC]),
+            Op::Fsin => out.extend(&[0xD9, 0xFE]),
+            Op::Fcos => out.extend(&[0xD9, 0xFF]),
+            Op::Finit => out.extend(&[0xDB, 0xE3]),
+            Op::Fstsw => {
+                // DF E0: fnstsw AX
+                if op.params.dst != Parameter::Reg16(R::AX) {
                     return Err(EncodeError::UnhandledParameter(op.params.dst.clone()));
                 }
+                out.extend(&[0xDF, 0xE0]);
             }
-            _ => {
+            Op::Uninitialized | Op::Invalid(_, _) => {
                 return Err(EncodeError::UnhandledOp(op.command.clone()));
             }
         }
         Ok(out)
     }
 
-    fn arith_instr8(&self, ins: &Instruction) -> Result<Vec<u8>, EncodeError> {
+    fn mov_instr(&self, ins: &Instruction) -> Result<Vec<u8>, EncodeError> {
         let mut out = vec!();
-        let idx = match ins.command {
-            Op::Add8 => 0x00,
-            Op::Or8  => 0x08,
-            Op::Adc8 => 0x10,
-            Op::Sbb8 => 0x18,
-            Op::And8 => 0x20,
-            Op::Sub8 => 0x28,
-            Op::Xor8 => 0x30,
-            Op::Cmp8 => 0x38,
-            _ => panic!("unhandled {:?}", ins.command),
-        };
+        let w = if ins.command == Op::Mov8 { 0 } else { 1 };
 
-        if let Parameter::Reg8(r) = ins.params.dst {
-            if r == R::AL {
-                if let Parameter::Imm8(imm) = ins.params.src {
-                    // 0x0C: or AL, imm8
-                    // 0x1C: sbb al, imm8
-                    // 0x24: and AL, imm8
-                    // 0x34: xor AL, imm8
-                    // 0x3C: cmp AL, imm8
-                    out.push(idx + 4);
-                    out.push(imm);
-                    return Ok(out);
-                }
-            }
+        // 0x8C: mov r/m16, sreg
+        // 0x8E: mov sreg, r/m16
+        if let Parameter::SReg16(r) = ins.params.dst {
+            out.push(0x8E);
+            out.extend(self.encode_rm(ins, &ins.params.src, r.u8())?);
+            return Ok(out);
         }
-        match ins.params.dst {
-            Parameter::Reg8(r) => {
-                if let Parameter::Imm8(i) = ins.params.src {
-                    // 0x80: <arithmetic> r/m8, imm8
-                    out.push(0x80);
-                    // md 3 = register adressing
-                    let mrr = ModRegRm{md: 3, rm: r.index() as u8, reg: self.arith_index(&ins.command)};
-                    out.push(mrr.u8());
-                    out.push(i);
-                } else if ins.params.src.is_ptr() {
-                    // 0x0A: or r8, r/m8
-                    // 0x1A: sbb r8, r/m8
-                    // 0x22: and r8, r/m8
-                    // 0x32: xor r8, r/m8
-                    // 0x3A: cmp r8, r/m8
-                    out.push(idx + 2);
-                    out.extend(self.encode_r_rm(&ins.params));
-                } else {
-                    // 0x08: or r/m8, r8
-                    // 0x10: adc r/m8, r8
-                    // 0x18: sbb r/m8, r8
-                    // 0x20: and r/m8, r8
-                    // 0x28: sub r/m8, r8
-                    // 0x30: xor r/m8, r8
-                    // 0x38: cmp r/m8, r8
-                    out.push(idx);
-                    out.extend(self.encode_rm_r(&ins.params));
-                }
-                Ok(out)
-            }
-            Parameter::Ptr8(_, _) |
-            Parameter::Ptr8Amode(_, _) |
-            Parameter::Ptr8AmodeS8(_, _, _) |
-            Parameter::Ptr8AmodeS16(_, _, _) => {
-                // 0x08: or r/m8, r8
-                // 0x10: adc r/m8, r8
-                // 0x20: and r/m8, r8
-                // 0x30: xor r/m8, r8
-                // 0x38: cmp r/m8, r8
-                out.push(idx);
-                out.extend(self.encode_rm_r(&ins.params));
-                Ok(out)
-            }
-            _ => Err(EncodeError::UnhandledParameter(ins.params.dst.clone())),
+        if let Parameter::SReg16(r) = ins.params.src {
+            out.push(0x8C);
+            out.extend(self.encode_rm(ins, &ins.params.dst, r.u8())?);
+            return Ok(out);
         }
-    }
 
-    fn arith_instr16(&self, ins: &Instruction) -> Result<Vec<u8>, EncodeError> {
-        let mut out = vec!();
-        let idx = match ins.command {
-            Op::Add16 => 0x01,
-            Op::Or16  => 0x09,
-            Op::Adc16 => 0x11,
-            Op::Sbb16 => 0x19,
-            Op::And16 => 0x21,
-            Op::Sub16 => 0x29,
-            Op::Xor16 => 0x31,
-            Op::Cmp16 => 0x39,
-            _ => panic!("unhandled {:?}", ins.command),
-        };
+        // 0F 20: mov r32, cr0
+        if let Parameter::CReg32(r) = ins.params.src {
+            out.extend(&[0x0F, 0x20]);
+            out.extend(self.encode_rm(ins, &ins.params.dst, r.u8())?);
+            return Ok(out);
+        }
 
-        if let Parameter::Reg16(r) = ins.params.dst {
-            if r == R::AX {
-                if let Parameter::Imm16(i) = ins.params.src {
-                    // 0x3D: CMP AX, imm16
-                    out.push(idx + 4);
-                    out.push(i as u8);
-                    out.push((i >> 8) as u8);
-                    return Ok(out);
-                }
+        // 0xA0: mov AL, [moffs8]
+        // 0xA1: mov AX, [moffs16]
+        // 0xA2: mov [moffs8], AL
+        // 0xA3: mov [moffs16], AX
+        if Encoder::is_accumulator(&ins.params.dst) {
+            if let Some(moffs) = Encoder::moffs(&ins.params.src) {
+                out.push(0xA0 | w);
+                out.extend(self.encode_moffs(ins, moffs));
+                return Ok(out);
             }
         }
-        match ins.params.dst {
-            Parameter::Reg16(r) => {
-                if let Parameter::Imm16(i) = ins.params.src {
-                    // 0x81: <arithmetic> r/m16, imm16
-                    out.push(0x81);
-                    // md 3 = register adressing
-                    let mrr = ModRegRm{md: 3, rm: r.index() as u8, reg: self.arith_index(&ins.command)};
-                    out.push(mrr.u8());
-                    out.push(i as u8);
-                    out.push((i >> 8) as u8);
-                } else if ins.params.src.is_ptr() {
-                    // 0x3B: CMP r16, r/m16
-                    out.push(idx + 2);
-                    out.extend(self.encode_r_rm(&ins.params));
-                } else {
-                    // 0x39: CMP r/m16, r16
-                    out.push(idx);
-                    out.extend(self.encode_rm_r(&ins.params));
-                }
-                Ok(out)
+        if Encoder::is_accumulator(&ins.params.src) {
+            if let Some(moffs) = Encoder::moffs(&ins.params.dst) {
+                out.push(0xA2 | w);
+                out.extend(self.encode_moffs(ins, moffs));
+                return Ok(out);
             }
-            Parameter::Ptr16(_, _) |
-            Parameter::Ptr16Amode(_, _) |
-            Parameter::Ptr16AmodeS8(_, _, _) |
-            Parameter::Ptr16AmodeS16(_, _, _) => {
-                // 0x39: CMP r/m16, r16
-                out.push(idx);
-                out.extend(self.encode_rm_r(&ins.params));
-                Ok(out)
+        }
+
+        if ins.params.src.is_imm() {
+            match ins.params.dst {
+                Parameter::Reg8(r) | Parameter::Reg16(r) | Parameter::Reg32(r) => {
+                    // 0xB0...0xB7: mov r8, u8
+                    // 0xB8...0xBF: mov r16, u16
+                    out.push(0xB0 | w << 3 | r.u8());
+                }
+                _ => {
+                    // 0xC6: mov r/m8, imm8
+                    // 0xC7: mov r/m16, imm16
+                    out.push(0xC6 | w);
+                    out.extend(self.encode_rm(ins, &ins.params.dst, 0)?);
+                }
             }
-            _ => Err(EncodeError::UnhandledParameter(ins.params.dst.clone())),
+            out.extend(self.encode_imm(&ins.params.src)?);
+        } else if ins.params.src.is_ptr() {
+            // 0x8A: mov r8, r/m8
+            // 0x8B: mov r16, r/m16
+            out.push(0x8A | w);
+            out.extend(self.encode_r_rm(ins)?);
+        } else {
+            // 0x88: mov r/m8, r8
+            // 0x89: mov r/m16, r16
+            out.push(0x88 | w);
+            out.extend(self.encode_rm_r(ins)?);
         }
+        Ok(out)
     }
 
-    fn arith_instr32(&self, ins: &Instruction) -> Result<Vec<u8>, EncodeError> {
+    fn arith_instr(&self, ins: &Instruction) -> Result<Vec<u8>, EncodeError> {
         let mut out = vec!();
+        let idx = self.arith_index(&ins.command);
+        let w = if Encoder::is_8bit(&ins.params.dst) { 0 } else { 1 };
 
-        // same encoding as 16-bit versions but with 32bit prefix
-        let idx = match ins.command {
-            Op::Add32 => 0x01,
-            Op::Or32  => 0x09,
-            Op::Adc32 => 0x11,
-            Op::Sbb32 => 0x19,
-            Op::And32 => 0x21,
-            Op::Sub32 => 0x29,
-            Op::Xor32 => 0x31,
-            Op::Cmp32 => 0x39,
-            _ => panic!("unhandled {:?}", ins.command),
-        };
-
-        out.push(0x66); // REX.W (Operand-size override prefix)
-
-        if let Parameter::Reg32(r) = ins.params.dst {
-            if r == R::EAX {
-                if let Parameter::Imm32(i) = ins.params.src {
-                    // 0x3D: CMP EAX, imm16
-                    out.push(idx + 4);
-                    out.push(i as u8);         // byte 0
-                    out.push((i >> 8) as u8);  // byte 1
-                    out.push((i >> 16) as u8); // byte 2
-                    out.push((i >> 24) as u8); // byte 3
-                    return Ok(out);
-                }
+        match ins.params.src {
+            Parameter::ImmS8(_) => {
+                // 0x83: <arithmetic> r/m16, imm8
+                out.push(0x83);
+                out.extend(self.encode_rm(ins, &ins.params.dst, idx)?);
             }
-        }
-        match ins.params.dst {
-            Parameter::Reg32(r) => {
-                if let Parameter::Imm32(i) = ins.params.src {
-                    // 0x81: <arithmetic> r/m32, imm32
-                    out.push(0x81);
-                    // md 3 = register adressing
-                    let mrr = ModRegRm{md: 3, rm: r.index() as u8, reg: self.arith_index(&ins.command)};
-                    out.push(mrr.u8());
-                    out.push(i as u8);         // byte 0
-                    out.push((i >> 8) as u8);  // byte 1
-                    out.push((i >> 16) as u8); // byte 2
-                    out.push((i >> 24) as u8); // byte 3
-                } else if ins.params.src.is_ptr() {
-                    // 0x3B: CMP r32, r/m32
-                    out.push(idx + 2);
-                    out.extend(self.encode_r_rm(&ins.params));
+            Parameter::Imm8(_) | Parameter::Imm16(_) | Parameter::Imm32(_) => {
+                if Encoder::is_accumulator(&ins.params.dst) {
+                    // 0x04: add AL, imm8
+                    // 0x05: add AX, imm16
+                    out.push(idx << 3 | 4 | w);
                 } else {
-                    // 0x39: CMP r/m32, r32
-                    out.push(idx);
-                    out.extend(self.encode_rm_r(&ins.params));
+                    // 0x80: <arithmetic> r/m8, imm8
+                    // 0x81: <arithmetic> r/m16, imm16
+                    out.push(0x80 | w);
+                    out.extend(self.encode_rm(ins, &ins.params.dst, idx)?);
                 }
-                Ok(out)
             }
-            /*
-            Parameter::Ptr16(_, _) |
-            Parameter::Ptr16Amode(_, _) |
-            Parameter::Ptr16AmodeS8(_, _, _) |
-            Parameter::Ptr16AmodeS16(_, _, _) => {
-                // 0x39: CMP r/m16, r16
-                out.push(idx);
-                out.extend(self.encode_rm_r(&ins.params));
-                Ok(out)
+            _ => {
+                if ins.params.src.is_ptr() {
+                    // 0x02: add r8, r/m8
+                    // 0x03: add r16, r/m16
+                    out.push(idx << 3 | 2 | w);
+                    out.extend(self.encode_r_rm(ins)?);
+                } else {
+                    // 0x00: add r/m8, r8
+                    // 0x01: add r/m16, r16
+                    out.push(idx << 3 | w);
+                    out.extend(self.encode_rm_r(ins)?);
+                }
+                return Ok(out);
             }
-            */
-            _ => Err(EncodeError::UnhandledParameter(ins.params.dst.clone())),
         }
+        out.extend(self.encode_imm(&ins.params.src)?);
+        Ok(out)
     }
 
-    fn math_instr8(&self, ins: &Instruction) -> Result<Vec<u8>, EncodeError> {
-        // XXX 0xD2: bit shift byte by CL
+    fn math_instr(&self, ins: &Instruction) -> Result<Vec<u8>, EncodeError> {
         let mut out = vec!();
-        match ins.params.dst {
-            Parameter::Reg8(r) => {
-                if ins.command == Op::Test8 {
-                    if let Parameter::Imm8(i) = ins.params.src {
-                        if r == R::AL {
-                            // 0xA8: test AL, imm8
-                            out.push(0xA8);
-                        } else {
-                            // 0xF6: test r/m8, imm8
-                            out.push(0xF6);
-                            out.push(ModRegRm::rm_reg(r.index() as u8, self.math_index(&ins.command)));
-                        }
-                        out.push(i);
+        let w = if Encoder::is_8bit(&ins.params.dst) { 0 } else { 1 };
+
+        match ins.command {
+            Op::Test8 | Op::Test16 | Op::Test32 => {
+                if ins.params.src.is_imm() {
+                    if Encoder::is_accumulator(&ins.params.dst) {
+                        // 0xA8: test AL, imm8
+                        // 0xA9: test AX, imm16
+                        out.push(0xA8 | w);
                     } else {
-                        // 0x84: test r/m8, r8
-                        out.push(0x84);
-                        out.extend(self.encode_rm_r(&ins.params));
+                        // 0xF6 /0: test r/m8, imm8
+                        // 0xF7 /0: test r/m16, imm16
+                        out.push(0xF6 | w);
+                        out.extend(self.encode_rm(ins, &ins.params.dst, 0)?);
                     }
+                    out.extend(self.encode_imm(&ins.params.src)?);
                 } else {
-                    // 0xF6: <math> r/m8.  reg = instruction
-                    out.push(0xF6);
-                    out.push(ModRegRm::rm_reg(r.index() as u8, self.math_index(&ins.command)));
+                    // 0x84: test r/m8, r8
+                    // 0x85: test r/m16, r16
+                    out.push(0x84 | w);
+                    if ins.params.src.is_reg() {
+                        out.extend(self.encode_rm_r(ins)?);
+                    } else {
+                        out.extend(self.encode_r_rm(ins)?);
+                    }
                 }
-                Ok(out)
+                return Ok(out);
             }
-            Parameter::Ptr8(_, _) |
-            Parameter::Ptr8Amode(_, _) |
-            Parameter::Ptr8AmodeS8(_, _, _) |
-            Parameter::Ptr8AmodeS16(_, _, _) => {
-                if ins.command == Op::Test8 {
-                    // 0x84: test r/m8, r8
-                    out.push(0x84);
-                    out.extend(self.encode_rm_r(&ins.params));
-                } else {
-                    // 0xF6: not r/m8
-                    out.push(0xF6);
-                    out.extend(self.encode_rm(&ins.params.dst, Encoder::f6_index(&ins.command)));
+            Op::Imul16 | Op::Imul32 => {
+                match ins.params.src2 {
+                    Parameter::ImmS8(_) => {
+                        // 3 operand form: 6B /r ib
+                        out.push(0x6B);
+                        out.extend(self.encode_r_rm(ins)?);
+                        out.extend(self.encode_imm(&ins.params.src2)?);
+                        return Ok(out);
+                    }
+                    Parameter::Imm16(_) | Parameter::Imm32(_) => {
+                        // 3 operand form: 69 /r iw
+                        out.push(0x69);
+                        out.extend(self.encode_r_rm(ins)?);
+                        out.extend(self.encode_imm(&ins.params.src2)?);
+                        return Ok(out);
+                    }
+                    _ => {}
+                }
+                if !ins.params.src.is_none() {
+                    // 2 operand form: 0F AF /r
+                    out.extend(&[0x0F, 0xAF]);
+                    out.extend(self.encode_r_rm(ins)?);
+                    return Ok(out);
                 }
-                Ok(out)
             }
-            _ => Err(EncodeError::UnexpectedDstType(ins.params.dst.clone())),
+            _ => {}
         }
+
+        // 1 operand form: F6 /r, F7 /r
+        out.push(0xF6 | w);
+        out.extend(self.encode_rm(ins, &ins.params.dst, self.math_index(&ins.command))?);
+        Ok(out)
     }
 
-    fn math_instr16(&self, ins: &Instruction) -> Result<Vec<u8>, EncodeError> {
+    fn bitshift_instr(&self, ins: &Instruction) -> Result<Vec<u8>, EncodeError> {
         let mut out = vec!();
-
-        match ins.params.src2 {
-            Parameter::ImmS8(v) => {
-                // 3 operand form: 6B /r ib
-                out.push(0x6B);
-                out.extend(self.encode_r_rm(&ins.params));
-                out.push(v as u8);
-                return Ok(out);
+        let w = if Encoder::is_8bit(&ins.params.dst) { 0 } else { 1 };
+        let idx = self.bitshift_index(&ins.command);
+        match ins.params.src {
+            Parameter::Imm8(1) | Parameter::Imm16(1) => {
+                // 0xD0: bit shift byte by 1
+                // 0xD1: bit shift word by 1
+                out.push(0xD0 | w);
+                out.extend(self.encode_rm(ins, &ins.params.dst, idx)?);
             }
-            _ => {}
+            Parameter::Reg8(R::CL) => {
+                // 0xD2: bit shift byte by CL
+                // 0xD3: bit shift word by CL
+                out.push(0xD2 | w);
+                out.extend(self.encode_rm(ins, &ins.params.dst, idx)?);
+            }
+            Parameter::Imm8(imm) => {
+                // 0xC0: r/m8, byte imm8
+                // 0xC1: r/m16, byte imm8
+                out.push(0xC0 | w);
+                out.extend(self.encode_rm(ins, &ins.params.dst, idx)?);
+                out.push(imm);
+            }
+            _ => return Err(EncodeError::UnhandledParameter(ins.params.src.clone())),
         }
+        Ok(out)
+    }
 
-        if ins.command != Op::Test16 {
-            match ins.params.src {
-                Parameter::Reg16(_) => {
-                    // 2 operand form: 0F AF /r
-                    out.push(0x0F);
-                    out.push(0xAF);
-                    out.extend(self.encode_r_rm(&ins.params));
-                    return Ok(out);
-                }
-                _ => {}
-            }
+    fn fpu_instr(&self, ins: &Instruction) -> Result<Vec<u8>, EncodeError> {
+        let mut out = vec!();
+        let st = matches!(ins.params.dst, Parameter::FPR80(_));
+        let word = Encoder::is_16bit(&ins.params.dst);
+        let (opcode, reg) = match ins.command {
+            // D8 /r m32fp, D8 C0+i st(i)
+            Op::Fadd => (0xD8, 0),
+            Op::Fmul => (0xD8, 1),
+            Op::Fcom => (0xD8, 2),
+            Op::Fcomp => (0xD8, 3),
+            Op::Fsub => (0xD8, 4),
+            Op::Fsubr => (0xD8, 5),
+            Op::Fdiv => (0xD8, 6),
+            Op::Fdivr => (0xD8, 7),
+
+            Op::Fld => (0xD9, 0),
+            Op::Fxch => (0xD9, 1),
+            Op::Fst if st => (0xDD, 2),
+            Op::Fst => (0xD9, 2),
+            Op::Fstp if st => (0xDD, 3),
+            Op::Fstp => (0xD9, 3),
+            Op::Fldcw => (0xD9, 5),
+            Op::Fnstcw => (0xD9, 7),
+
+            Op::Ficomp if word => (0xDE, 3),
+            Op::Ficomp => (0xDA, 3),
+
+            Op::Fild if word => (0xDF, 0),
+            Op::Fild => (0xDB, 0),
+            Op::Fisttp => (0xDB, 1),
+            Op::Fist if word || st => (0xDF, 2),
+            Op::Fist => (0xDB, 2),
+            Op::Fistp if word => (0xDF, 3),
+            Op::Fistp => (0xDB, 3),
+
+            Op::Ffree => (0xDD, 0),
+
+            Op::Faddp => (0xDE, 0),
+            Op::Fimul => (0xDE, 1),
+            Op::Ficom => (0xDE, 2),
+            Op::Fsubrp => (0xDE, 4),
+            Op::Fsubp => (0xDE, 5),
+            Op::Fidiv => (0xDE, 6),
+            Op::Fdivp => (0xDE, 7),
+            _ => return Err(EncodeError::UnhandledOp(ins.command.clone())),
+        };
+        out.push(opcode);
+        out.extend(self.encode_rm(ins, &ins.params.dst, reg)?);
+        Ok(out)
+    }
+
+    /// encodes a short relative branch to the target offset in dst
+    fn encode_rel8(&self, ins: &Instruction, offset: u16, opcode: u8) -> Result<Vec<u8>, EncodeError> {
+        let target = Encoder::branch_target(ins)?;
+        let rel = target.wrapping_sub(offset.wrapping_add(2)) as i16;
+        if rel < i16::from(i8::MIN) || rel > i16::from(i8::MAX) {
+            return Err(EncodeError::Text(format!("{:04X}: branch target {:04X} is out of short range", offset, target)));
         }
+        Ok(vec!(opcode, rel as u8))
+    }
+
+    /// encodes a near relative branch to the target offset in dst
+    fn encode_rel16(&self, ins: &Instruction, offset: u16, opcode: &[u8]) -> Result<Vec<u8>, EncodeError> {
+        let target = Encoder::branch_target(ins)?;
+        let rel = target.wrapping_sub(offset.wrapping_add(opcode.len() as u16 + 2));
+        let mut out = opcode.to_vec();
+        out.extend(&rel.to_le_bytes());
+        Ok(out)
+    }
 
+    fn branch_target(ins: &Instruction) -> Result<u16, EncodeError> {
         match ins.params.dst {
-            Parameter::Reg16(r) => {
-                if ins.command == Op::Test16 {
-                    if let Parameter::Imm16(i) = ins.params.src {
-                        if r == R::AX {
-                            out.push(0xA9); // test AX, imm16
-                        } else {
-                            out.push(0xF7); // test r/m16, imm16
-                            out.push(ModRegRm::rm_reg(r.index() as u8, self.math_index(&ins.command)));
-                        }
-                        out.push(i as u8);
-                        out.push((i >> 8) as u8);
-                    } else {
-                        out.push(0x85); // test r/m16, r16
-                        out.extend(self.encode_rm_r(&ins.params));
-                    }
-                } else {
-                    // 1 operand form: F7 /5
-                    out.push(0xF7);
-                    out.push(ModRegRm::rm_reg(r.index() as u8, self.math_index(&ins.command)));
-                }
-                Ok(out)
-            }
-            _ => Err(EncodeError::UnexpectedDstType(ins.params.dst.clone())),
+            Parameter::Imm16(target) => Ok(target),
+            _ => Err(EncodeError::UnhandledParameter(ins.params.dst.clone())),
         }
     }
 
-    fn math_instr32(&self, ins: &Instruction) -> Result<Vec<u8>, EncodeError> {
-        let mut out = vec!();
-
-        out.push(0x66); // REX.W (Operand-size override prefix)
+    /// returns true for ops that need the operand-size override prefix
+    fn is_32bit(op: &Op) -> bool {
+        matches!(*op,
+            Op::Adc32 | Op::Add32 | Op::And32 | Op::Cmp32 | Op::Or32 | Op::Sbb32 | Op::Sub32 | Op::Xor32 |
+            Op::Dec32 | Op::Inc32 | Op::Div32 | Op::Idiv32 | Op::Mul32 | Op::Imul32 |
+            Op::Neg32 | Op::Not32 | Op::Test32 | Op::Xchg32 |
+            Op::Rcl32 | Op::Rcr32 | Op::Rol32 | Op::Ror32 | Op::Sar32 | Op::Shl32 | Op::Shr32 |
+            Op::Mov32 | Op::Movsx32 | Op::Movzx32 | Op::Lea32 | Op::Cwde32 |
+            Op::Push32 | Op::Pop32 | Op::Pushad32 | Op::Popad32 |
+            Op::Bsf32 | Op::Bsr32 | Op::Bt32 | Op::Btc32 | Op::Btr32 | Op::Bts32 |
+            Op::Bswap32 | Op::Cmpxchg32 | Op::Xadd32 |
+            Op::Cmpsd | Op::Insd | Op::Lodsd | Op::Movsd | Op::Outsd | Op::Scasd | Op::Stosd)
+    }
 
-        match ins.params.src2 {
-            Parameter::ImmS8(v) => {
-                // 3 operand form: 6B /r ib
-                out.push(0x6B);
-                out.extend(self.encode_r_rm(&ins.params));
-                out.push(v as u8);
-                return Ok(out);
-            }
-            _ => {}
+    /// returns true if the instruction needs the address-size override prefix
+    fn address32(ins: &Instruction) -> bool {
+        if ins.address_size == AddressSize::_32bit {
+            return true;
         }
+        [&ins.params.dst, &ins.params.src, &ins.params.src2].iter().any(|p| match p {
+            Parameter::Ptr8AmodeS32(_, _, _) |
+            Parameter::Ptr16AmodeS32(_, _, _) |
+            Parameter::Ptr32AmodeS32(_, _, _) => true,
+            Parameter::Ptr8Amode(_, amode) |
+            Parameter::Ptr16Amode(_, amode) |
+            Parameter::Ptr32Amode(_, amode) |
+            Parameter::Ptr8AmodeS8(_, amode, _) |
+            Parameter::Ptr16AmodeS8(_, amode, _) |
+            Parameter::Ptr32AmodeS8(_, amode, _) => amode.is_32bit(),
+            _ => false,
+        })
+    }
 
-        if ins.command != Op::Test32 {
-            match ins.params.src {
-                Parameter::Reg32(_) => {
-                    // 2 operand form: 0F AF /r
-                    out.push(0x0F);
-                    out.push(0xAF);
-                    out.extend(self.encode_r_rm(&ins.params));
-                    return Ok(out);
-                }
-                _ => {}
-            }
+    fn is_accumulator(p: &Parameter) -> bool {
+        matches!(*p,
+            Parameter::Reg8(R::AL) | Parameter::Reg16(R::AX) | Parameter::Reg32(R::EAX))
+    }
+
+    fn is_8bit(p: &Parameter) -> bool {
+        matches!(*p,
+            Parameter::Reg8(_) |
+            Parameter::Ptr8(_, _) |
+            Parameter::Ptr8Amode(_, _) |
+            Parameter::Ptr8AmodeS8(_, _, _) |
+            Parameter::Ptr8AmodeS16(_, _, _) |
+            Parameter::Ptr8AmodeS32(_, _, _))
+    }
+
+    fn is_16bit(p: &Parameter) -> bool {
+        matches!(*p,
+            Parameter::Reg16(_) |
+            Parameter::Ptr16(_, _) |
+            Parameter::Ptr16Amode(_, _) |
+            Parameter::Ptr16AmodeS8(_, _, _) |
+            Parameter::Ptr16AmodeS16(_, _, _) |
+            Parameter::Ptr16AmodeS32(_, _, _))
+    }
+
+    /// returns the offset of a direct memory operand
+    fn moffs(p: &Parameter) -> Option<u16> {
+        match *p {
+            Parameter::Ptr8(_, v) | Parameter::Ptr16(_, v) | Parameter::Ptr32(_, v) => Some(v),
+            _ => None,
         }
+    }
 
-        match ins.params.dst {
-            Parameter::Reg32(r) => {
-                if ins.command == Op::Test32 {
-                    if let Parameter::Imm32(i) = ins.params.src {
-                        if r == R::EAX {
-                            out.push(0xA9); // test eAX, imm32
-                        } else {
-                            out.push(0xF7); // test r/m32, imm32
-                            out.push(ModRegRm::rm_reg(r.index() as u8, self.math_index(&ins.command)));
-                        }
-                        out.push(i as u8);
-                        out.push((i >> 8) as u8);
-                    } else {
-                        out.push(0x85); // test r/m32, r32
-                        out.extend(self.encode_rm_r(&ins.params));
-                    }
-                } else {
-                    // 1 operand form: F7 /5
-                    out.push(0xF7);
-                    out.push(ModRegRm::rm_reg(r.index() as u8, self.math_index(&ins.command)));
-                }
-                Ok(out)
-            }
-            _ => Err(EncodeError::UnexpectedDstType(ins.params.dst.clone())),
+    fn encode_moffs(&self, ins: &Instruction, moffs: u16) -> Vec<u8> {
+        if Encoder::address32(ins) {
+            u32::from(moffs).to_le_bytes().to_vec()
+        } else {
+            moffs.to_le_bytes().to_vec()
         }
     }
 
-    /// used for 0xF6 encodings
-    fn f6_index(op: &Op) -> u8 {
+    /// used for 0x70 and 0x0F 0x80 encodings
+    fn jcc_index(op: &Op) -> u8 {
         match *op {
-            Op::Test8 => 0,
-            Op::Not8  => 2,
-            Op::Neg8  => 3,
-            Op::Mul8  => 4,
-            Op::Imul8 => 5,
-            Op::Div8  => 6,
-            Op::Idiv8 => 7,
-            _ => panic!("f6_index {:?}", op),
+            Op::Jo  => 0x0,
+            Op::Jno => 0x1,
+            Op::Jc  => 0x2,
+            Op::Jnc => 0x3,
+            Op::Jz  => 0x4,
+            Op::Jnz => 0x5,
+            Op::Jna => 0x6,
+            Op::Ja  => 0x7,
+            Op::Js  => 0x8,
+            Op::Jns => 0x9,
+            Op::Jpe => 0xA,
+            Op::Jpo => 0xB,
+            Op::Jl  => 0xC,
+            Op::Jnl => 0xD,
+            Op::Jng => 0xE,
+            Op::Jg  => 0xF,
+            _ => panic!("jcc_index {:?}", op),
         }
     }
 
@@ -852,104 +922,14 @@ impl Encoder {
             Op::Inc8 | Op::Inc16 | Op::Inc32 => 0,
             Op::Dec8 | Op::Dec16 | Op::Dec32 => 1,
             Op::CallNear => 2,
-            // 3 => call far
+            Op::CallFar => 3,
             Op::JmpNear => 4,
-            // 5 => jmp far
-            Op::Push16 => 6,
+            Op::JmpFar => 5,
+            Op::Push16 | Op::Push32 => 6,
             _ => panic!("feff_index {:?}", op),
         }
     }
 
-    fn bitshift_instr8(&self, ins: &Instruction) -> Result<Vec<u8>, EncodeError> {
-        let mut out = vec!();
-        match ins.params.dst {
-            Parameter::Reg8(r) => {
-                match ins.params.src {
-                    Parameter::Imm8(i) => {
-                        // md 3 = register adressing
-                        // XXX ModRegRm.rm really should use enum AMode, not like AMode is now. naming there is wrong
-                        let mrr = ModRegRm{md: 3, rm: r.index() as u8, reg: self.bitshift_index(&ins.command)};
-                        if i == 1 {
-                            // 0xD0 = bit shift byte by 1
-                            out.push(0xD0);
-                            out.push(mrr.u8());
-                        } else {
-                            // 0xC0 = r/m8, byte imm8
-                            out.push(0xC0);
-                            out.push(mrr.u8());
-                            out.push(i as u8);
-                        }
-                    }
-                    _ => {
-                        // 0xD2: bit shift byte by CL
-                        panic!("bitshift_instr8 {:?}", ins);
-                    }
-                }
-                Ok(out)
-            }
-            _ => Err(EncodeError::UnexpectedDstType(ins.params.dst.clone())),
-        }
-    }
-
-    fn bitshift_instr16(&self, ins: &Instruction) -> Result<Vec<u8>, EncodeError> {
-        let mut out = vec!();
-        match ins.params.dst {
-            Parameter::Reg16(r) => {
-                match ins.params.src {
-                    Parameter::Imm8(i) => {
-                        // md 3 = register adressing
-                        let mrr = ModRegRm{md: 3, rm: r.index() as u8, reg: self.bitshift_index(&ins.command)};
-                        if i == 1 {
-                            // 0xD1 = bit shift byte by 1
-                            out.push(0xD1);
-                            out.push(mrr.u8());
-                        } else {
-                            // 0xC1 = r/m16, byte imm8
-                            out.push(0xC1);
-                            out.push(mrr.u8());
-                            out.push(i as u8);
-                        }
-                    }
-                    _ => {
-                        panic!("bitshift_instr16 {}: {:?}", ins, ins);
-                    }
-                }
-                Ok(out)
-            }
-            _ => Err(EncodeError::UnexpectedDstType(ins.params.dst.clone())),
-        }
-    }
-
-    fn bitshift_instr32(&self, ins: &Instruction) -> Result<Vec<u8>, EncodeError> {
-        let mut out = vec!();
-        match ins.params.dst {
-            Parameter::Reg32(r) => {
-                out.push(0x66); // REX.W (Operand-size override prefix)
-                match ins.params.src {
-                    Parameter::Imm8(i) => {
-                        // md 3 = register adressing
-                        let mrr = ModRegRm{md: 3, rm: r.index() as u8, reg: self.bitshift_index(&ins.command)};
-                        if i == 1 {
-                            // 0xD1 = bit shift byte by 1
-                            out.push(0xD1);
-                            out.push(mrr.u8());
-                        } else {
-                            // 0xC1 = r/m16, byte imm8
-                            out.push(0xC1);
-                            out.push(mrr.u8());
-                            out.push(i as u8);
-                        }
-                    }
-                    _ => {
-                        panic!("bitshift_instr32 {}: {:?}", ins, ins);
-                    }
-                }
-                Ok(out)
-            }
-            _ => Err(EncodeError::UnexpectedDstType(ins.params.dst.clone())),
-        }
-    }
-
     fn bitshift_index(&self, op: &Op) -> u8 {
         match *op {
             Op::Rol8 | Op::Rol16 | Op::Rol32 => 0,
@@ -958,6 +938,7 @@ impl Encoder {
             Op::Rcr8 | Op::Rcr16 | Op::Rcr32 => 3,
             Op::Shl8 | Op::Shl16 | Op::Shl32 => 4,
             Op::Shr8 | Op::Shr16 | Op::Shr32 => 5,
+            Op::Setmo8 | Op::Setmo16 => 6,
             Op::Sar8 | Op::Sar16 | Op::Sar32 => 7,
             _ => panic!("bitshift_index {:?}", op),
         }
@@ -977,6 +958,7 @@ impl Encoder {
         }
     }
 
+    /// used for 0xF6 and 0xF7 encodings
     fn math_index(&self, op: &Op) -> u8 {
         match *op {
             Op::Test8 | Op::Test16 | Op::Test32 => 0,
@@ -990,80 +972,141 @@ impl Encoder {
         }
     }
 
-    fn encode_r_rm(&self, params: &ParameterSet) -> Vec<u8> {
-        match params.dst {
-            Parameter::Reg8(ref r) |
-            Parameter::Reg16(ref r) => self.encode_rm(&params.src, r.index() as u8),
-            _ => unreachable!(),
+    /// returns the modrm reg field value of a register parameter
+    fn reg_index(p: &Parameter) -> Result<u8, EncodeError> {
+        match *p {
+            Parameter::Reg8(r) |
+            Parameter::Reg16(r) |
+            Parameter::Reg32(r) |
+            Parameter::SReg16(r) => Ok(r.u8()),
+            _ => Err(EncodeError::UnhandledParameter(p.clone())),
         }
     }
 
-    fn encode_rm_r(&self, params: &ParameterSet) -> Vec<u8> {
-        match params.src {
-            Parameter::Reg8(ref r) |
-            Parameter::Reg16(ref r) |
-            Parameter::Reg32(ref r) =>
-                self.encode_rm(&params.dst, r.index() as u8),
-            _ => panic!("unexpected parameter type: {:?}", params.src),
-        }
+    /// encodes dst as reg and src as r/m
+    fn encode_r_rm(&self, ins: &Instruction) -> Result<Vec<u8>, EncodeError> {
+        self.encode_rm(ins, &ins.params.src, Encoder::reg_index(&ins.params.dst)?)
     }
 
-    fn encode_rm_r_imm(&self, params: &ParameterSet) -> Vec<u8> {
-        let mut out = Vec::new();
-        // shld r/m16, r16, imm8
-        out.extend(match params.src {
-            Parameter::Reg8(ref r) |
-            Parameter::Reg16(ref r) => self.encode_rm(&params.dst, r.index() as u8),
-            _ => panic!("unexpected parameter type: {:?}", params.src),
-        });
-        if let Parameter::Imm8(imm) = params.src2 {
-            out.push(imm);
-        } else {
-            unreachable!();
-        }
-        out
+    /// encodes dst as r/m and src as reg
+    fn encode_rm_r(&self, ins: &Instruction) -> Result<Vec<u8>, EncodeError> {
+        self.encode_rm(ins, &ins.params.dst, Encoder::reg_index(&ins.params.src)?)
     }
 
-    fn encode_rm(&self, dst: &Parameter, reg: u8) -> Vec<u8> {
+    /// encodes the modrm byte, sib byte and displacement of the r/m parameter
+    fn encode_rm(&self, ins: &Instruction, rm: &Parameter, reg: u8) -> Result<Vec<u8>, EncodeError> {
         let mut out = Vec::new();
-        match *dst {
+        match *rm {
+            Parameter::Reg8(r) |
+            Parameter::Reg16(r) |
+            Parameter::Reg32(r) |
+            Parameter::FPR80(r) => {
+                out.push(ModRegRm{md: 3, rm: r.u8(), reg}.u8());
+            }
             Parameter::Ptr8(_, imm16) |
-            Parameter::Ptr16(_, imm16) => {
-                out.push(ModRegRm{md: 0, rm: 6, reg}.u8());
-                out.push(imm16 as u8);
-                out.push((imm16 >> 8) as u8);
+            Parameter::Ptr16(_, imm16) |
+            Parameter::Ptr32(_, imm16) => {
+                if Encoder::address32(ins) {
+                    // [u32]
+                    out.push(ModRegRm{md: 0, rm: 5, reg}.u8());
+                } else {
+                    // [u16]
+                    out.push(ModRegRm{md: 0, rm: 6, reg}.u8());
+                }
+                out.extend(self.encode_moffs(ins, imm16));
             }
             Parameter::Ptr8Amode(_, ref amode) |
-            Parameter::Ptr16Amode(_, ref amode) => {
-                out.push(ModRegRm{md: 0, rm: amode.index() as u8, reg}.u8());
-            }
+            Parameter::Ptr16Amode(_, ref amode) |
+            Parameter::Ptr32Amode(_, ref amode) => match *amode {
+                // [bp], [ebp] and [ebp+index] can only be encoded with a displacement
+                AMode::BP | AMode::EBP | AMode::SIB(Some(R::EBP), _, _) => {
+                    out.extend(self.encode_amode(amode, 1, reg)?);
+                    out.push(0);
+                }
+                _ => out.extend(self.encode_amode(amode, 0, reg)?),
+            },
             Parameter::Ptr8AmodeS8(_, ref amode, imm) |
-            Parameter::Ptr16AmodeS8(_, ref amode, imm) => {
-                out.push(ModRegRm{md: 1, rm: amode.index() as u8, reg}.u8());
+            Parameter::Ptr16AmodeS8(_, ref amode, imm) |
+            Parameter::Ptr32AmodeS8(_, ref amode, imm) => {
+                out.extend(self.encode_amode(amode, 1, reg)?);
                 out.push(imm as u8);
-            },
+            }
             Parameter::Ptr8AmodeS16(_, ref amode, imm16) |
-            Parameter::Ptr16AmodeS16(_, ref amode, imm16) => {
-                out.push(ModRegRm{md: 2, rm: amode.index() as u8, reg}.u8());
-                out.push(imm16 as u8);
-                out.push((imm16 >> 8) as u8);
+            Parameter::Ptr16AmodeS16(_, ref amode, imm16) |
+            Parameter::Ptr32AmodeS16(_, ref amode, imm16) => {
+                if amode.is_32bit() || Encoder::address32(ins) {
+                    return Err(EncodeError::UnhandledParameter(rm.clone()));
+                }
+                out.extend(self.encode_amode(amode, 2, reg)?);
+                out.extend(&imm16.to_le_bytes());
             }
-            Parameter::Reg8(ref r) |
-            Parameter::Reg16(ref r) |
-            Parameter::Reg32(ref r) => {
-                out.push(ModRegRm{md: 3, rm: r.index() as u8, reg}.u8());
+            Parameter::Ptr8AmodeS32(_, ref amode, imm32) |
+            Parameter::Ptr16AmodeS32(_, ref amode, imm32) |
+            Parameter::Ptr32AmodeS32(_, ref amode, imm32) => {
+                if !amode.is_32bit() {
+                    return Err(EncodeError::UnhandledParameter(rm.clone()));
+                }
+                match *amode {
+                    // [index*scale+s32] has no base register, marked by md 0
+                    AMode::SIB(None, _, _) => out.extend(self.encode_amode(amode, 0, reg)?),
+                    _ => out.extend(self.encode_amode(amode, 2, reg)?),
+                }
+                out.extend(&imm32.to_le_bytes());
             }
-            _ => panic!("encode_rm: unhandled md {:?}", dst),
+            _ => return Err(EncodeError::UnhandledParameter(rm.clone())),
         }
-        out
+        Ok(out)
     }
 
-    fn encode_imm8(&self, param: &Parameter) -> Vec<u8> {
+    /// encodes the modrm byte, and sib byte if needed, of a memory operand using mod `md`
+    fn encode_amode(&self, amode: &AMode, md: u8, reg: u8) -> Result<Vec<u8>, EncodeError> {
         let mut out = Vec::new();
-        if let Parameter::Imm8(imm) = *param {
-            out.push(imm as u8);
-            return out;
+        match *amode {
+            AMode::SIB(base, index, scale) => {
+                let ss = match scale {
+                    1 => 0,
+                    2 => 1,
+                    4 => 2,
+                    8 => 3,
+                    _ => return Err(EncodeError::Text(format!("invalid sib scale {}", scale))),
+                };
+                let index = match index {
+                    Some(R::ESP) => return Err(EncodeError::Text("esp can't be used as sib index".to_owned())),
+                    Some(r) => r.u8(),
+                    None => 4,
+                };
+                let base = match base {
+                    Some(r) => r.u8(),
+                    None if md == 0 => 5,
+                    None => return Err(EncodeError::Text(format!("sib without base needs a 32-bit displacement: {}", amode))),
+                };
+                out.push(ModRegRm{md, rm: 4, reg}.u8());
+                out.push(ss << 6 | index << 3 | base);
+            }
+            // [esp] needs a sib byte
+            AMode::ESP => {
+                out.push(ModRegRm{md, rm: 4, reg}.u8());
+                out.push(0x24);
+            }
+            _ => out.push(ModRegRm{md, rm: amode.index() as u8, reg}.u8()),
+        }
+        Ok(out)
+    }
+
+    fn encode_imm8(&self, param: &Parameter) -> Result<Vec<u8>, EncodeError> {
+        match *param {
+            Parameter::Imm8(imm) => Ok(vec!(imm)),
+            _ => Err(EncodeError::UnhandledParameter(param.clone())),
+        }
+    }
+
+    fn encode_imm(&self, param: &Parameter) -> Result<Vec<u8>, EncodeError> {
+        match *param {
+            Parameter::Imm8(imm) => Ok(vec!(imm)),
+            Parameter::ImmS8(imm) => Ok(vec!(imm as u8)),
+            Parameter::Imm16(imm) => Ok(imm.to_le_bytes().to_vec()),
+            Parameter::Imm32(imm) => Ok(imm.to_le_bytes().to_vec()),
+            _ => Err(EncodeError::UnhandledParameter(param.clone())),
         }
-        panic!("not imm8 {:?}", param);
     }
 }
@@ -11,9 +11,12 @@ use crate::cpu::parameter::Parameter;
 use crate::cpu::instruction::Instruction;
 use crate::cpu::op::Op;
 use crate::cpu::register::{R, AMode};
-use crate::cpu::decoder::OperandSize;
+use crate::cpu::decoder::{OperandSize, disasm_first_instr};
+use crate::cpu::decoder::Decoder;
+use crate::memory::MMU;
 use crate::machine::Machine;
 use crate::hex::hex_bytes;
+#[cfg(feature = "ndisasm")]
 use crate::ndisasm::ndisasm_first_instr;
 
 #[test] #[ignore] // expensive test
@@ -39,18 +42,18 @@ fn can_encode_random_seq() {
         if op.instruction.command.is_valid() {
             // - if successful, try to encode. all valid decodings should be mapped for valid
             //   encoding for implemented ops (this should find all missing cases)
-            if let Ok(enc) = encoder.encode(&op.instruction) {
+            if let Ok(enc) = encoder.encode_at(&op.instruction, 0x100) {
                 let in_bytes = Vec::from_iter(code[0..enc.len()].iter().cloned());
                 if enc != in_bytes {
-                    let ndisasm_of_input = ndisasm_first_instr(&in_bytes).unwrap();
-                    let ndisasm_of_encode = ndisasm_first_instr(&enc).unwrap();
-                    if ndisasm_of_input != ndisasm_of_encode {
-                        panic!("encoding resulted in wrong sequence.\n\ninput  {:?}\noutput {:?}\ninstr {:?}\nndisasm of\ninput '{}'\nencode '{}'",
+                    let disasm_of_input = disasm_first_instr(&in_bytes);
+                    let disasm_of_encode = disasm_first_instr(&enc);
+                    if disasm_of_input != disasm_of_encode {
+                        panic!("encoding resulted in wrong sequence.\n\ninput  {:?}\noutput {:?}\ninstr {:?}\ndisasm of\ninput '{}'\nencode '{}'",
                             hex_bytes(&in_bytes),
                             hex_bytes(&enc),
                             op.instruction,
-                            ndisasm_of_input,
-                            ndisasm_of_encode);
+                            disasm_of_input,
+                            disasm_of_encode);
                     }
                 }
 
@@ -266,6 +269,52 @@ fn can_encode_xor32() {
     assert_encdec(&op, "xor ebx,0xffeeddaa", vec!(0x66, 0x81, 0xF3, 0xAA, 0xDD, 0xEE, 0xFF));
 }
 
+#[test]
+fn can_encode_bit_tests() {
+    let op = Instruction::new2(Op::Btr16, Parameter::Reg16(R::AX), Parameter::Reg16(R::DX));
+    assert_encdec(&op, "btr ax,dx", vec!(0x0F, 0xB3, 0xD0));
+
+    let op = Instruction::new2(Op::Btc16, Parameter::Reg16(R::AX), Parameter::Imm8(4));
+    assert_encdec(&op, "btc ax,byte 0x4", vec!(0x0F, 0xBA, 0xF8, 0x04));
+
+    let mut op = Instruction::new2(Op::Bsr32, Parameter::Reg32(R::EDX), Parameter::Reg32(R::EAX));
+    op.op_size = OperandSize::_32bit;
+    assert_encdec(&op, "bsr edx,eax", vec!(0x66, 0x0F, 0xBD, 0xD0));
+}
+
+#[test]
+fn can_encode_486_instructions() {
+    let op = Instruction::new2(Op::Xadd16, Parameter::Reg16(R::BX), Parameter::Reg16(R::CX));
+    assert_encdec(&op, "xadd bx,cx", vec!(0x0F, 0xC1, 0xCB));
+
+    let op = Instruction::new2(Op::Cmpxchg8, Parameter::Ptr8(Segment::Default, 0x1234), Parameter::Reg8(R::DL));
+    assert_encdec(&op, "cmpxchg [0x1234],dl", vec!(0x0F, 0xB0, 0x16, 0x34, 0x12));
+
+    let mut op = Instruction::new1(Op::Bswap32, Parameter::Reg32(R::EDX));
+    op.op_size = OperandSize::_32bit;
+    assert_encdec(&op, "bswap edx", vec!(0x66, 0x0F, 0xCA));
+}
+
+#[test]
+fn can_encode_far_transfers_with_operand_size() {
+    let mut op = Instruction::new1(Op::CallFar, Parameter::Ptr16Imm(0x085F, 0x010B));
+    op.op_size = OperandSize::_32bit;
+    assert_encdec(&op, "call dword 0x85f:0x10b", vec!(0x66, 0x9A, 0x0B, 0x01, 0x00, 0x00, 0x5F, 0x08));
+
+    let mut op = Instruction::new1(Op::Push32, Parameter::SReg16(R::DS));
+    op.op_size = OperandSize::_32bit;
+    assert_encdec(&op, "o32 push ds", vec!(0x66, 0x1E));
+}
+
+#[test]
+fn can_encode_setcc() {
+    let op = Instruction::new1(Op::Setpo, Parameter::Reg8(R::BL));
+    assert_encdec(&op, "setpo bl", vec!(0x0F, 0x9B, 0xC3));
+
+    let op = Instruction::new1(Op::Setng, Parameter::Ptr8(Segment::Default, 0x1234));
+    assert_encdec(&op, "setng [0x1234]", vec!(0x0F, 0x9E, 0x06, 0x34, 0x12));
+}
+
 #[test]
 fn can_encode_or8() {
     // AL, imm8
@@ -495,6 +544,18 @@ fn can_encode_shrd() {
     assert_encdec(&op, "shrd bx,di,0x8", vec!(0x0F, 0xAC, 0xFB, 0x08));
 }
 
+#[test]
+fn can_encode_smsw() {
+    let op = Instruction::new1(Op::Smsw, Parameter::Reg16(R::AX));
+    assert_encdec(&op, "smsw ax", vec!(0x0F, 0x01, 0xE0));
+}
+
+#[test]
+fn can_encode_mov_from_cr0() {
+    let op = Instruction::new2(Op::Mov32, Parameter::Reg32(R::ECX), Parameter::CReg32(R::CR0));
+    assert_encdec(&op, "mov ecx,cr0", vec!(0x0F, 0x20, 0xC1));
+}
+
 #[test]
 fn can_encode_shr8() {
     let op = Instruction::new2(Op::Shr8, Parameter::Reg8(R::AH), Parameter::Imm8(0xFF));
@@ -631,10 +692,99 @@ fn can_encode_sar16() {
     assert_encdec(&op, "sar bx,byte 0x30", vec!(0xC1, 0xFB, 0x30));
 }
 
+#[test]
+fn can_encode_relative_branches() {
+    // targets are absolute, the encoding is relative to the end of the instruction at 0x100
+    let op = Instruction::new1(Op::Loop, Parameter::Imm16(0x0100));
+    assert_encdec(&op, "loop 0x0", vec!(0xE2, 0xFE));
+
+    let op = Instruction::new1(Op::Jz, Parameter::Imm16(0x0110));
+    assert_encdec(&op, "jz 0x10", vec!(0x74, 0x0E));
+
+    let op = Instruction::new1(Op::Jz, Parameter::Imm16(0x0300));
+    assert_encdec(&op, "jz near 0x200", vec!(0x0F, 0x84, 0xFC, 0x01));
+
+    let op = Instruction::new1(Op::CallNear, Parameter::Imm16(0x0000));
+    assert_encdec(&op, "call 0xff00", vec!(0xE8, 0xFD, 0xFE));
+
+    let op = Instruction::new1(Op::JmpShort, Parameter::Imm16(0x0090));
+    assert_encdec(&op, "jmp short 0xff90", vec!(0xEB, 0x8E));
+}
+
+#[test]
+fn cant_encode_loop_out_of_range() {
+    let encoder = Encoder::new();
+    let op = Instruction::new1(Op::Loop, Parameter::Imm16(0x0200));
+    assert!(encoder.encode_at(&op, 0x100).is_err());
+}
+
+#[test]
+fn can_encode_test32() {
+    let mut op = Instruction::new2(Op::Test32, Parameter::Reg32(R::EBX), Parameter::Reg32(R::ECX));
+    op.op_size = OperandSize::_32bit;
+    assert_encdec(&op, "test ebx,ecx", vec!(0x66, 0x85, 0xCB));
+}
+
+#[test]
+fn can_encode_cmpsw() {
+    let op = Instruction::new(Op::Cmpsw);
+    assert_encdec(&op, "cmpsw", vec!(0xA7));
+}
+
+#[test]
+fn can_round_trip_op_table() {
+    // decodes each opcode with a selection of operand bytes, encodes the result
+    // and checks that it decodes to the same instruction again
+    const SEG: u16 = 0x1000;
+    const OFFSET: u16 = 0x0100;
+    let prefixes: [&[u8]; 4] = [&[], &[0x66], &[0x67], &[0x66, 0x67]];
+    let operands: [[u8; 6]; 8] = [
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        [0xC1, 0x12, 0x34, 0x56, 0x78, 0x9A],
+        [0x46, 0x80, 0x34, 0x56, 0x78, 0x9A],
+        [0x87, 0x34, 0x12, 0x56, 0x78, 0x9A],
+        [0x1E, 0x34, 0x12, 0x56, 0x78, 0x9A],
+        [0x2C, 0x88, 0x34, 0x56, 0x78, 0x9A],
+        [0x54, 0x24, 0xF0, 0x56, 0x78, 0x9A],
+        [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+    ];
+    let mut opcodes: Vec<Vec<u8>> = (0..=0xFFu8).filter(|b| *b != 0x0F).map(|b| vec!(b)).collect();
+    opcodes.extend((0..=0xFFu8).map(|b| vec!(0x0F, b)));
+
+    let encoder = Encoder::new();
+    let mut mmu = MMU::default();
+    let mut decoder = Decoder::default();
+    for prefix in &prefixes {
+        for opcode in &opcodes {
+            for operand in &operands {
+                let mut data = prefix.to_vec();
+                data.extend(opcode);
+                data.extend(operand);
+                mmu.write(SEG, OFFSET, &data);
+                let first = decoder.get_instruction(&mut mmu, SEG, OFFSET);
+                match first.command {
+                    Op::Invalid(_, _) | Op::Uninitialized => continue,
+                    _ => {}
+                }
+                let code = match encoder.encode_at(&first, OFFSET) {
+                    Ok(code) => code,
+                    Err(why) => panic!("{} decoded as {:?}, failed to encode: {}", hex_bytes(&data), first, why),
+                };
+                mmu.write(SEG, OFFSET, &code);
+                let mut second = decoder.get_instruction(&mut mmu, SEG, OFFSET);
+                assert_eq!(code.len(), second.length as usize, "{} encoded as {}", first, hex_bytes(&code));
+                second.length = first.length;
+                assert_eq!(first, second, "{} decoded as {}, encoded as {}", hex_bytes(&data), first, hex_bytes(&code));
+            }
+        }
+    }
+}
+
 // TODO make this into a macro to retain caller line numbers in the asserts
+#[cfg_attr(not(feature = "ndisasm"), allow(unused_variables))]
 fn assert_encdec(op :&Instruction, expected_ndisasm: &str, expected_bytes: Vec<u8>) {
     let encoder = Encoder::new();
-    let code = encoder.encode(&op).unwrap();
+    let code = encoder.encode_at(&op, 0x100).unwrap();
     assert_eq!(expected_bytes, code, "encoded byte sequence does not match expected bytes");
 
     let mut want_op = op.clone();
@@ -646,5 +796,6 @@ fn assert_encdec(op :&Instruction, expected_ndisasm: &str, expected_bytes: Vec<u
     want_op.length = decoded_op.length; // len is not known by Instruction::new()
     assert_eq!(&want_op, decoded_op, "decoded resulting op from instruction encode does not match input op");
 
+    #[cfg(feature = "ndisasm")]
     assert_eq!(expected_ndisasm.to_owned(), ndisasm_first_instr(&code).unwrap(), "disasm of encoded byte sequence does not match expected ndisasm output");
 }
@@ -5,25 +5,101 @@
 mod flag_test;
 
 /// https://en.wikipedia.org/wiki/FLAGS_register
-#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[derive(Copy, Clone, Debug, Default)]
 pub struct Flags {
     // ____ O_I_ SZ_A _P_C
-    pub carry: bool, // 0: carry flag
-    reserved1: bool, // 1: reserved, always 1 in EFLAGS
-    pub parity: bool, // 2: parity flag
+    carry: bool, // 0: carry flag
+    parity: bool, // 2: parity flag
     reserved3: bool,
-    pub adjust: bool, // 4: adjust flag
+    adjust: bool, // 4: adjust flag
     reserved5: bool,
-    pub zero: bool, // 6: zero flag
-    pub sign: bool, // 7: sign flag
+    zero: bool, // 6: zero flag
+    sign: bool, // 7: sign flag
     pub trap: bool, // 8: trap flag (single step)
     pub interrupt: bool, // 9: interrupt flag
     pub direction: bool, // 10: direction flag (control with cld, std)
-    pub overflow: bool, // 11: overflow flag
+    overflow: bool, // 11: overflow flag
     iopl12: bool, // 12: I/O privilege level (286+ only), always 1 on 8086 and 186
     iopl13: bool, // 13 --""---
     nested_task: bool, // 14: Nested task flag (286+ only), always 1 on 8086 and 186
-    reserved15: bool, // 15: Reserved, always 1 on 8086 and 186, always 0 on later models
+    // 15: reserved, always 1 on 8086 and 186, always 0 on later models, see `CPU::flags_image`
+
+    /// the last arithmetic operation, its flags are computed on demand
+    lazy: Option<LazyFlags>,
+
+    /// always compute flags right away (deterministic mode)
+    pub eager: bool,
+}
+
+impl PartialEq for Flags {
+    fn eq(&self, other: &Self) -> bool {
+        self.u16() == other.u16()
+    }
+}
+
+/// the kind of operation recorded for lazy flag evaluation
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LazyOp {
+    Add,
+    Sub,
+    /// like Add, but CF is not affected
+    Inc,
+    /// like Sub, but CF is not affected
+    Dec,
+    /// and, or, xor, test: CF and OF cleared, AF not affected
+    Logic,
+}
+
+/// operands and result of the last arithmetic operation.
+/// each flag returns None when it is not affected by the operation
+#[derive(Copy, Clone, Debug)]
+struct LazyFlags {
+    op: LazyOp,
+    bits: u32,
+    res: u64,
+    src: u64,
+    dst: u64,
+}
+
+impl LazyFlags {
+    fn msb(&self) -> u64 {
+        1 << (self.bits - 1)
+    }
+
+    fn carry(&self) -> Option<bool> {
+        match self.op {
+            LazyOp::Add | LazyOp::Sub => Some(self.res & (1 << self.bits) != 0),
+            LazyOp::Logic => Some(false),
+            LazyOp::Inc | LazyOp::Dec => None,
+        }
+    }
+
+    fn overflow(&self) -> Option<bool> {
+        Some(match self.op {
+            LazyOp::Add | LazyOp::Inc => (self.res ^ self.src) & (self.res ^ self.dst) & self.msb() != 0,
+            LazyOp::Sub | LazyOp::Dec => (self.dst ^ self.src) & (self.dst ^ self.res) & self.msb() != 0,
+            LazyOp::Logic => false,
+        })
+    }
+
+    fn sign(&self) -> Option<bool> {
+        Some(self.res & self.msb() != 0)
+    }
+
+    fn zero(&self) -> Option<bool> {
+        Some(self.res.trailing_zeros() >= self.bits)
+    }
+
+    fn parity(&self) -> Option<bool> {
+        Some(PARITY_LOOKUP[(self.res & 0xFF) as usize] != 0)
+    }
+
+    fn adjust(&self) -> Option<bool> {
+        match self.op {
+            LazyOp::Logic => None,
+            _ => Some((self.res ^ self.src ^ self.dst) & 0x10 != 0),
+        }
+    }
 }
 
 // XXX make use of flag mask
@@ -60,7 +136,6 @@ impl Flags {
     pub fn new() -> Self {
         Flags {
             carry: false, // bit 0
-            reserved1: false,
             parity: false,
             reserved3: false,
             adjust: false,
@@ -74,7 +149,8 @@ impl Flags {
             iopl12: false,
             iopl13: false,
             nested_task: false,
-            reserved15: false, // bit 15
+            lazy: None,
+            eager: false,
         }
     }
 
@@ -84,8 +160,87 @@ impl Flags {
         f
     }
 
+    /// records an arithmetic operation, its flags are computed when read
+    pub fn set_lazy(&mut self, op: LazyOp, bits: u32, res: u64, src: u64, dst: u64) {
+        if op != LazyOp::Add && op != LazyOp::Sub {
+            // the unaffected flags are kept from the previous operation
+            self.resolve();
+        }
+        self.lazy = Some(LazyFlags{op, bits, res, src, dst});
+        if self.eager {
+            self.resolve();
+        }
+    }
+
+    /// computes the flags of the recorded operation
+    pub fn resolve(&mut self) {
+        if let Some(lazy) = self.lazy.take() {
+            self.carry = lazy.carry().unwrap_or(self.carry);
+            self.parity = lazy.parity().unwrap_or(self.parity);
+            self.adjust = lazy.adjust().unwrap_or(self.adjust);
+            self.zero = lazy.zero().unwrap_or(self.zero);
+            self.sign = lazy.sign().unwrap_or(self.sign);
+            self.overflow = lazy.overflow().unwrap_or(self.overflow);
+        }
+    }
+
+    pub fn carry(&self) -> bool {
+        self.lazy.and_then(|l| l.carry()).unwrap_or(self.carry)
+    }
+
+    pub fn parity(&self) -> bool {
+        self.lazy.and_then(|l| l.parity()).unwrap_or(self.parity)
+    }
+
+    pub fn adjust(&self) -> bool {
+        self.lazy.and_then(|l| l.adjust()).unwrap_or(self.adjust)
+    }
+
+    pub fn zero(&self) -> bool {
+        self.lazy.and_then(|l| l.zero()).unwrap_or(self.zero)
+    }
+
+    pub fn sign(&self) -> bool {
+        self.lazy.and_then(|l| l.sign()).unwrap_or(self.sign)
+    }
+
+    pub fn overflow(&self) -> bool {
+        self.lazy.and_then(|l| l.overflow()).unwrap_or(self.overflow)
+    }
+
+    pub fn set_carry(&mut self, b: bool) {
+        self.resolve();
+        self.carry = b;
+    }
+
+    pub fn set_parity(&mut self, b: bool) {
+        self.resolve();
+        self.parity = b;
+    }
+
+    pub fn set_adjust(&mut self, b: bool) {
+        self.resolve();
+        self.adjust = b;
+    }
+
+    pub fn set_zero(&mut self, b: bool) {
+        self.resolve();
+        self.zero = b;
+    }
+
+    pub fn set_sign(&mut self, b: bool) {
+        self.resolve();
+        self.sign = b;
+    }
+
+    pub fn set_overflow(&mut self, b: bool) {
+        self.resolve();
+        self.overflow = b;
+    }
+
     /// sets sign, zero, parity flags according to `b`
     pub fn set_szp(&mut self, b: bool) {
+        self.resolve();
         self.sign = b;
         self.zero = b;
         self.parity = b;
@@ -94,42 +249,50 @@ impl Flags {
     /// Set equal to the most-significant bit of the result,
     /// which is the sign bit of a signed integer.
     /// (0 indicates a positive value and 1 indicates a negative value.)
-    pub fn set_sign_u8(&mut self, v: usize) {
+    pub fn set_sign_u8(&mut self, v: u64) {
+        self.resolve();
         self.sign = v & 0x80 != 0;
     }
 
-    pub fn set_sign_u16(&mut self, v: usize) {
+    pub fn set_sign_u16(&mut self, v: u64) {
+        self.resolve();
         self.sign = v & 0x8000 != 0;
     }
 
-    pub fn set_sign_u32(&mut self, v: usize) {
+    pub fn set_sign_u32(&mut self, v: u64) {
+        self.resolve();
         self.sign = v & 0x8000_0000 != 0;
     }
 
     /// Set if the least-significant byte of the result contains an
     /// even number of 1 bits; cleared otherwise.
-    pub fn set_parity(&mut self, v: usize) {
+    pub fn set_parity_from(&mut self, v: u64) {
+        self.resolve();
         // TODO later: rework flag register to be a u16 directly, use FLAG_PF
-        self.parity = PARITY_LOOKUP[v & 0xFF] != 0
+        self.parity = PARITY_LOOKUP[(v & 0xFF) as usize] != 0
     }
 
     /// Zero flag — Set if the result is zero; cleared otherwise.
-    pub fn set_zero_u8(&mut self, v: usize) {
+    pub fn set_zero_u8(&mut self, v: u64) {
+        self.resolve();
         self.zero = v.trailing_zeros() >= 8;
     }
 
-    pub fn set_zero_u16(&mut self, v: usize) {
+    pub fn set_zero_u16(&mut self, v: u64) {
+        self.resolve();
         self.zero = v.trailing_zeros() >= 16;
     }
 
-    pub fn set_zero_u32(&mut self, v: usize) {
+    pub fn set_zero_u32(&mut self, v: u64) {
+        self.resolve();
         self.zero = v.trailing_zeros() >= 32;
     }
 
     /// Set if an arithmetic operation generates a carry or a borrow out
     /// of bit 3 of the result; cleared otherwise. This flag is used in
     /// binary-coded decimal (BCD) arithmetic.
-    pub fn set_adjust(&mut self, res: usize, v1: usize, v2: usize) {
+    pub fn set_adjust_from(&mut self, res: u64, v1: u64, v2: u64) {
+        self.resolve();
         self.adjust = (res ^ (v1 ^ v2)) & 0x10 != 0;
     }
 
@@ -137,64 +300,73 @@ impl Flags {
     /// small a negative number (excluding the sign-bit) to fit in the
     /// destination operand; cleared otherwise. This flag indicates an
     /// overflow condition for signed-integer (two’s complement) arithmetic.
-    pub fn set_overflow_add_u8(&mut self, res: usize, v1: usize, v2: usize) {
+    pub fn set_overflow_add_u8(&mut self, res: u64, v1: u64, v2: u64) {
+        self.resolve();
         self.overflow = (res ^ v1) & (res ^ v2) & 0x80 != 0;
     }
 
-    pub fn set_overflow_add_u16(&mut self, res: usize, v1: usize, v2: usize) {
+    pub fn set_overflow_add_u16(&mut self, res: u64, v1: u64, v2: u64) {
+        self.resolve();
         self.overflow = (res ^ v1) & (res ^ v2) & 0x8000 != 0;
     }
 
-    pub fn set_overflow_add_u32(&mut self, res: usize, v1: usize, v2: usize) {
+    pub fn set_overflow_add_u32(&mut self, res: u64, v1: u64, v2: u64) {
+        self.resolve();
         self.overflow = (res ^ v1) & (res ^ v2) & 0x8000_0000 != 0;
     }
 
-    pub fn set_overflow_sub_u8(&mut self, res: usize, v1: usize, v2: usize) {
+    pub fn set_overflow_sub_u8(&mut self, res: u64, v1: u64, v2: u64) {
+        self.resolve();
         self.overflow = (v2 ^ v1) & (v2 ^ res) & 0x80 != 0;
     }
 
-    pub fn set_overflow_sub_u16(&mut self, res: usize, v1: usize, v2: usize) {
+    pub fn set_overflow_sub_u16(&mut self, res: u64, v1: u64, v2: u64) {
+        self.resolve();
         self.overflow = (v2 ^ v1) & (v2 ^ res) & 0x8000 != 0;
     }
 
-    pub fn set_overflow_sub_u32(&mut self, res: usize, v1: usize, v2: usize) {
+    pub fn set_overflow_sub_u32(&mut self, res: u64, v1: u64, v2: u64) {
+        self.resolve();
         self.overflow = (v2 ^ v1) & (v2 ^ res) & 0x8000_0000 != 0;
     }
 
     /// Set if an arithmetic operation generates a carry or a borrow out of
     /// the most-significant bit of the result; cleared otherwise. This flag
     /// indicates an overflow condition for unsigned-integer arithmetic.
-    pub fn set_carry_u8(&mut self, res: usize) {
+    pub fn set_carry_u8(&mut self, res: u64) {
+        self.resolve();
         self.carry = res & 0x100 != 0;
     }
 
-    pub fn set_carry_u16(&mut self, res: usize) {
+    pub fn set_carry_u16(&mut self, res: u64) {
+        self.resolve();
         self.carry = res & 0x1_0000 != 0;
     }
 
-    pub fn set_carry_u32(&mut self, res: usize) {
+    pub fn set_carry_u32(&mut self, res: u64) {
+        self.resolve();
         self.carry = res & 0x1_0000_0000 != 0;
     }
 
     /// initializes the flags with a packed u16
     pub fn set_u16(&mut self, val: u16) {
+        self.lazy = None;
         self.carry       = val & 0x1 != 0;
-        //self.reserved1   = val & 0x2 != 0;
         self.parity      = val & 0x4 != 0;
         self.adjust      = val & 0x10 != 0;
         self.zero        = val & 0x40 != 0;
         self.sign        = val & 0x80 != 0;
         self.trap        = val & 0x100 != 0;
-        //self.interrupt   = val & 0x200 != 0;
+        self.interrupt   = val & 0x200 != 0;
         self.direction   = val & 0x400 != 0;
         self.overflow    = val & 0x800 != 0;
-        //self.iopl12      = val & 0x1000 != 0;
-        //self.iopl13      = val & 0x2000 != 0;
-        //self.nested_task = val & 0x4000 != 0;
+        self.iopl12      = val & 0x1000 != 0;
+        self.iopl13      = val & 0x2000 != 0;
+        self.nested_task = val & 0x4000 != 0;
     }
 
-    pub fn carry_val(&self) -> usize {
-        if self.carry {
+    pub fn carry_val(&self) -> u64 {
+        if self.carry() {
             1
         } else {
             0
@@ -202,7 +374,7 @@ impl Flags {
     }
 
     pub fn carry_numeric(&self) -> String {
-        format!("{}", if self.carry {
+        format!("{}", if self.carry() {
             1
         } else {
             0
@@ -210,7 +382,7 @@ impl Flags {
     }
 
     pub fn zero_numeric(&self) -> String {
-        format!("{}", if self.zero {
+        format!("{}", if self.zero() {
             1
         } else {
             0
@@ -218,11 +390,11 @@ impl Flags {
     }
 
     pub fn sign_numeric(&self) -> String {
-        format!("{}", if self.sign { 1 } else { 0 })
+        format!("{}", if self.sign() { 1 } else { 0 })
     }
 
     pub fn overflow_numeric(&self) -> String {
-        format!("{}", if self.overflow {
+        format!("{}", if self.overflow() {
             1
         } else {
             0
@@ -230,7 +402,7 @@ impl Flags {
     }
 
     pub fn adjust_numeric(&self) -> String {
-        format!("{}", if self.adjust {
+        format!("{}", if self.adjust() {
             1
         } else {
             0
@@ -238,7 +410,7 @@ impl Flags {
     }
 
     pub fn parity_numeric(&self) -> String {
-        format!("{}", if self.parity {
+        format!("{}", if self.parity() {
             1
         } else {
             0
@@ -261,25 +433,22 @@ impl Flags {
         })
     }
 
-    /// returns the FLAGS register
+    /// returns the FLAGS register as on the 386, bit 1 is always set
     pub fn u16(&self) -> u16 {
-        let mut val = 0 as u16;
-        if self.carry {
+        let mut val = 1 << 1;
+        if self.carry() {
             val |= 1;
         }
-        if self.reserved1 {
-            val |= 1 << 1;
-        }
-        if self.parity {
+        if self.parity() {
             val |= 1 << 2;
         }
-        if self.adjust {
+        if self.adjust() {
             val |= 1 << 4;
         }
-        if self.zero {
+        if self.zero() {
             val |= 1 << 6;
         }
-        if self.sign {
+        if self.sign() {
             val |= 1 << 7;
         }
         if self.trap {
@@ -291,7 +460,7 @@ impl Flags {
         if self.direction {
             val |= 1 << 10;
         }
-        if self.overflow {
+        if self.overflow() {
             val |= 1 << 11;
         }
         if self.iopl12 {
@@ -307,3 +476,167 @@ impl Flags {
     }
 }
 
+
+/// returns a mask covering the low `bits` bits
+fn bit_mask(bits: u32) -> u64 {
+    (1 << bits) - 1
+}
+
+/// returns bit `n` of `v`
+fn bit(v: u64, n: u32) -> bool {
+    (v >> n) & 1 != 0
+}
+
+/// Shift and rotate operations, returning the result and updating the flags.
+/// Operands are `bits` (8, 16 or 32) wide, and `count` is the raw count operand.
+/// A masked count of 0 leaves the flags untouched.
+/// Overflow is only defined by Intel for a count of 1; for larger counts
+/// it is set as by a single step on the original operands, like the
+/// hardware recorded in flag_test_shifts.txt.
+impl Flags {
+    /// sets sign, zero, parity from a shift result and clears adjust
+    fn set_shift_szap(&mut self, bits: u32, res: u64) {
+        self.sign = bit(res, bits - 1);
+        self.zero = res == 0;
+        self.set_parity_from(res);
+        self.adjust = false;
+    }
+
+    /// shl, sal
+    pub fn shl(&mut self, bits: u32, op1: u32, count: u32) -> u32 {
+        let count = count & 0x1F;
+        if count == 0 {
+            return op1;
+        }
+        self.resolve();
+        let op1 = u64::from(op1);
+        let res = (op1 << count) & bit_mask(bits);
+        // the last bit shifted out, 0 once everything is shifted out
+        self.carry = count <= bits && bit(op1, bits - count);
+        self.overflow = bit(op1, bits - 1) ^ bit(op1, bits - 2);
+        self.set_shift_szap(bits, res);
+        res as u32
+    }
+
+    pub fn shr(&mut self, bits: u32, op1: u32, count: u32) -> u32 {
+        let count = count & 0x1F;
+        if count == 0 {
+            return op1;
+        }
+        self.resolve();
+        let op1 = u64::from(op1);
+        let res = op1 >> count;
+        self.carry = bit(op1, count - 1);
+        self.overflow = bit(op1, bits - 1);
+        self.set_shift_szap(bits, res);
+        res as u32
+    }
+
+    pub fn sar(&mut self, bits: u32, op1: u32, count: u32) -> u32 {
+        let count = count & 0x1F;
+        if count == 0 {
+            return op1;
+        }
+        self.resolve();
+        // sign extend op1 to 64 bits, so shifting by more than `bits` fills with the sign
+        let op1 = ((u64::from(op1) << (64 - bits)) as i64) >> (64 - bits);
+        let res = (op1 >> count) as u64 & bit_mask(bits);
+        self.carry = (op1 >> (count - 1)) & 1 != 0;
+        self.overflow = false;
+        self.set_shift_szap(bits, res);
+        res as u32
+    }
+
+    pub fn rol(&mut self, bits: u32, op1: u32, count: u32) -> u32 {
+        let count = count & 0x1F;
+        if count == 0 {
+            return op1;
+        }
+        self.resolve();
+        // a rotation by a multiple of `bits` still updates the flags
+        let count = count % bits;
+        let op1 = u64::from(op1);
+        let res = ((op1 << count) | (op1 >> (bits - count))) & bit_mask(bits);
+        self.carry = bit(res, 0);
+        self.overflow = bit(op1, bits - 1) ^ bit(op1, bits - 2);
+        res as u32
+    }
+
+    pub fn ror(&mut self, bits: u32, op1: u32, count: u32) -> u32 {
+        let count = count & 0x1F;
+        if count == 0 {
+            return op1;
+        }
+        self.resolve();
+        let count = count % bits;
+        let op1 = u64::from(op1);
+        let res = ((op1 >> count) | (op1 << (bits - count))) & bit_mask(bits);
+        self.carry = bit(res, bits - 1);
+        self.overflow = bit(op1, 0) ^ bit(op1, bits - 1);
+        res as u32
+    }
+
+    /// rotate `bits` + 1 bits (CF, op1) left
+    pub fn rcl(&mut self, bits: u32, op1: u32, count: u32) -> u32 {
+        let count = (count & 0x1F) % (bits + 1);
+        if count == 0 {
+            return op1;
+        }
+        self.resolve();
+        let op1 = u64::from(op1);
+        let v = self.carry_val() << bits | op1;
+        let v = ((v << count) | (v >> (bits + 1 - count))) & bit_mask(bits + 1);
+        let res = v & bit_mask(bits);
+        self.carry = bit(v, bits);
+        self.overflow = bit(op1, bits - 1) ^ bit(op1, bits - 2);
+        res as u32
+    }
+
+    /// rotate `bits` + 1 bits (CF, op1) right
+    pub fn rcr(&mut self, bits: u32, op1: u32, count: u32) -> u32 {
+        let count = (count & 0x1F) % (bits + 1);
+        if count == 0 {
+            return op1;
+        }
+        self.resolve();
+        let op1 = u64::from(op1);
+        self.overflow = self.carry ^ bit(op1, bits - 1);
+        let v = self.carry_val() << bits | op1;
+        let v = ((v >> count) | (v << (bits + 1 - count))) & bit_mask(bits + 1);
+        let res = v & bit_mask(bits);
+        self.carry = bit(v, bits);
+        res as u32
+    }
+
+    /// shifts `op1` left, filling in bits from `op2`. `bits` is 16 or 32
+    pub fn shld(&mut self, bits: u32, op1: u32, op2: u32, count: u32) -> u32 {
+        let count = count & 0x1F;
+        if count == 0 {
+            return op1;
+        }
+        self.resolve();
+        // for count > 16 the P6 shifts op1:op2:op1
+        let v = u128::from(op1) << (2 * bits) | u128::from(op2) << bits | u128::from(op1);
+        let res = (v >> (2 * bits - count)) as u64 & bit_mask(bits);
+        self.carry = (v >> (3 * bits - count)) & 1 != 0;
+        self.overflow = bit(u64::from(op1), bits - 1) ^ bit(u64::from(op1), bits - 2);
+        self.set_shift_szap(bits, res);
+        res as u32
+    }
+
+    /// shifts `op1` right, filling in bits from `op2`. `bits` is 16 or 32
+    pub fn shrd(&mut self, bits: u32, op1: u32, op2: u32, count: u32) -> u32 {
+        let count = count & 0x1F;
+        if count == 0 {
+            return op1;
+        }
+        self.resolve();
+        // for count > 16 the P6 shifts op1:op2:op1
+        let v = u128::from(op1) << (2 * bits) | u128::from(op2) << bits | u128::from(op1);
+        let res = (v >> count) as u64 & bit_mask(bits);
+        self.carry = (v >> (count - 1)) & 1 != 0;
+        self.overflow = bit(u64::from(op1), bits - 1) ^ bit(u64::from(op2), 0);
+        self.set_shift_szap(bits, res);
+        res as u32
+    }
+}
@@ -1,8 +1,278 @@
-use crate::cpu::flag::Flags;
+use crate::cpu::flag::{Flags, LazyOp};
+use crate::cpu::R;
+use crate::machine::Machine;
 
 #[test]
 fn can_pack_unpack_flags() {
     let mut flags = Flags::new();
     flags.set_u16(0xFFFF);
-    assert_eq!(0x0DD5, flags.u16());
+    assert_eq!(0x7FD7, flags.u16());
+
+    flags.set_u16(0x0000);
+    assert_eq!(0x0002, flags.u16()); // reserved bit 1 is always set
+}
+
+#[test]
+fn can_evaluate_flags_lazily() {
+    // op, bits, dst, src
+    let ops = [
+        (LazyOp::Add, 8, 0xFF, 0x01_u64),
+        (LazyOp::Inc, 8, 0x7F, 0x01),
+        (LazyOp::Sub, 16, 0x0000, 0x0001),
+        (LazyOp::Dec, 16, 0x8000, 0x0001),
+        (LazyOp::Logic, 16, 0x1234, 0x00FF),
+        (LazyOp::Inc, 32, 0xFFFF_FFFF, 0x0000_0001),
+        (LazyOp::Add, 32, 0x7FFF_FFFF, 0x0000_0001),
+        (LazyOp::Logic, 8, 0x0F, 0xF0),
+        (LazyOp::Dec, 8, 0x01, 0x01),
+    ];
+    let mut lazy = Flags::new();
+    let mut eager = Flags::new();
+    eager.eager = true;
+    for &(op, bits, dst, src) in &ops {
+        let res = match op {
+            LazyOp::Add | LazyOp::Inc => dst + src,
+            LazyOp::Sub | LazyOp::Dec => dst.wrapping_sub(src),
+            LazyOp::Logic => dst & src,
+        };
+        lazy.set_lazy(op, bits, res, src, dst);
+        eager.set_lazy(op, bits, res, src, dst);
+        assert_eq!(eager.u16(), lazy.u16(), "{:?}{} {:X} {:X}", op, bits, dst, src);
+    }
+    // dec 1 gives ZF, PF and keeps CF from the inc 0xFFFF_FFFF
+    assert_eq!(0x0046, lazy.u16());
+    lazy.resolve();
+    assert_eq!(0x0046, lazy.u16());
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Shift {
+    Shl, Shr, Sar, Rol, Ror, Rcl, Rcr, Shld, Shrd,
+}
+
+/// returns the flags with all of `carry`, `overflow`, `sign`, `zero`, `parity` set to `b`
+fn flags_with(b: bool) -> Flags {
+    let mut flags = Flags::new();
+    flags.set_carry(b);
+    flags.set_overflow(b);
+    flags.set_szp(b);
+    flags
+}
+
+fn run(op: Shift, flags: &mut Flags, bits: u32, op1: u32, op2: u32, count: u32) -> u32 {
+    match op {
+        Shift::Shl => flags.shl(bits, op1, count),
+        Shift::Shr => flags.shr(bits, op1, count),
+        Shift::Sar => flags.sar(bits, op1, count),
+        Shift::Rol => flags.rol(bits, op1, count),
+        Shift::Ror => flags.ror(bits, op1, count),
+        Shift::Rcl => flags.rcl(bits, op1, count),
+        Shift::Rcr => flags.rcr(bits, op1, count),
+        Shift::Shld => flags.shld(bits, op1, op2, count),
+        Shift::Shrd => flags.shrd(bits, op1, op2, count),
+    }
+}
+
+#[test]
+fn can_shift_and_rotate_known_values() {
+    // op, bits, op1, op2, carry in, count => result, carry, overflow
+    let table = [
+        (Shift::Shl, 16, 0x8001, 0, false, 17, 0x0000, false, true),
+        (Shift::Shl, 32, 0x4000_0000, 0, false, 2, 0x0000_0000, true, true),
+        (Shift::Shr, 8, 0x80, 0, false, 2, 0x20, false, true),
+        (Shift::Shr, 16, 0x8000, 0, false, 1, 0x4000, false, true),
+        (Shift::Sar, 8, 0x80, 0, false, 9, 0xFF, true, false),
+        (Shift::Rol, 8, 0x81, 0, false, 8, 0x81, true, true),
+        (Shift::Ror, 32, 0x0000_0001, 0, false, 1, 0x8000_0000, true, true),
+        (Shift::Rcl, 16, 0x4000, 0, false, 1, 0x8000, false, true),
+        (Shift::Rcr, 8, 0x01, 0, true, 1, 0x80, true, true),
+        (Shift::Shld, 16, 0x8000, 0x0001, false, 17, 0x0003, false, true),
+        (Shift::Shrd, 16, 0x0001, 0x8000, false, 17, 0xC000, false, false),
+        // count 1, where OF is defined
+        (Shift::Shl, 8, 0x81, 0, false, 1, 0x02, true, true),
+        (Shift::Shl, 8, 0x40, 0, false, 1, 0x80, false, true),
+        (Shift::Shr, 8, 0x01, 0, false, 1, 0x00, true, false),
+        (Shift::Sar, 16, 0x8001, 0, false, 1, 0xC000, true, false),
+        (Shift::Rol, 16, 0x8000, 0, false, 1, 0x0001, true, true),
+        (Shift::Ror, 8, 0x01, 0, false, 1, 0x80, true, true),
+        (Shift::Rcl, 8, 0x80, 0, false, 1, 0x00, true, true),
+        (Shift::Rcr, 16, 0x0001, 0, false, 1, 0x0000, true, false),
+        (Shift::Shld, 16, 0x8000, 0x8000, false, 1, 0x0001, true, true),
+        (Shift::Shrd, 32, 0x0000_0001, 0x0000_0001, false, 1, 0x8000_0000, true, true),
+        // a count of 0, also after masking to 5 bits, leaves the flags alone
+        (Shift::Shl, 16, 0x1234, 0, true, 0, 0x1234, true, true),
+        (Shift::Shr, 8, 0xFF, 0, false, 32, 0xFF, false, false),
+    ];
+    for &(op, bits, op1, op2, carry, count, res, cf, of) in &table {
+        let mut flags = flags_with(carry);
+        assert_eq!(res, run(op, &mut flags, bits, op1, op2, count), "{:?}{} {:X} {}", op, bits, op1, count);
+        assert_eq!(cf, flags.carry(), "carry {:?}{} {:X} {}", op, bits, op1, count);
+        assert_eq!(of, flags.overflow(), "overflow {:?}{} {:X} {}", op, bits, op1, count);
+    }
+}
+
+/// op1 values of the recorded sweep
+const SWEEP_OP1: [u32; 4] = [0x0000_0001, 0x8000_8081, 0x1234_5678, 0xAAAA_AAAA];
+
+/// op2 values of the recorded sweep, only used by shld and shrd
+const SWEEP_OP2: [u32; 2] = [0x0000_0000, 0x9ABC_DEF0];
+
+/// CF, PF, ZF, SF and OF
+const SWEEP_FLAGS: u16 = 0x08C5;
+
+/// calls `f` with each (op, bits, op1, op2, carry in) of the recorded sweep, in file order
+fn for_each_sweep(mut f: impl FnMut(Shift, u32, u32, u32, bool)) {
+    let ops = [Shift::Shl, Shift::Shr, Shift::Sar, Shift::Rol, Shift::Ror, Shift::Rcl, Shift::Rcr, Shift::Shld, Shift::Shrd];
+    for &op in &ops {
+        let (sizes, op2s): (&[u32], &[u32]) = match op {
+            Shift::Shld | Shift::Shrd => (&[16, 32], &SWEEP_OP2),
+            _ => (&[8, 16, 32], &SWEEP_OP2[..1]),
+        };
+        for &bits in sizes {
+            for &op1 in &SWEEP_OP1 {
+                for &op2 in op2s {
+                    for &carry in &[false, true] {
+                        f(op, bits, op1, op2, carry);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn width_mask(bits: u32) -> u32 {
+    match bits {
+        8 => 0xFF,
+        16 => 0xFFFF,
+        _ => 0xFFFF_FFFF,
+    }
+}
+
+#[test]
+fn can_shift_and_rotate_all_counts() {
+    // recorded on hardware by record_shift_and_rotate_flags, one line per
+    // "op bits op1 op2 carry" followed by "result:flags" for counts 0 to 32
+    let recorded = include_str!("./flag_test_shifts.txt");
+    let mut lines = recorded.lines().filter(|l| !l.starts_with('#'));
+    for_each_sweep(|op, bits, op1, op2, carry| {
+        let op1 = op1 & width_mask(bits);
+        let op2 = op2 & width_mask(bits);
+        let line = lines.next().expect("recorded table is too short");
+        let mut fields = line.split_whitespace();
+        let head: Vec<&str> = fields.by_ref().take(5).collect();
+        assert_eq!(format!("{:?} {} {:08X} {:08X} {}", op, bits, op1, op2, carry as u8), head.join(" "));
+        for count in 0..=32 {
+            let (res, want) = fields.next().unwrap().split_at(8);
+            let res = u32::from_str_radix(res, 16).unwrap();
+            let want = u16::from_str_radix(&want[1..], 16).unwrap();
+            let mut flags = flags_with(carry);
+            assert_eq!(res, run(op, &mut flags, bits, op1, op2, count), "{:?}{} {:X} {:X} {} {}", op, bits, op1, op2, carry, count);
+            assert_eq!(want, flags.u16() & SWEEP_FLAGS, "flags of {:?}{} {:X} {:X} {} {}", op, bits, op1, op2, carry, count);
+        }
+    });
+    assert_eq!(None, lines.next(), "recorded table is too long");
+}
+
+/// runs the shift or rotate on the host cpu, returning the result and flags
+#[cfg(target_arch = "x86_64")]
+fn host_shift(op: Shift, bits: u32, op1: u32, op2: u32, carry: bool, count: u32) -> (u32, u16) {
+    use std::arch::asm;
+    let mut v = op1 as u64;
+    let mut f: u64 = if carry { 0x0002 | u64::from(SWEEP_FLAGS) } else { 0x0002 };
+    macro_rules! host {
+        ($insn:literal, $size:literal) => {
+            unsafe {
+                asm!(concat!("push {f}; popfq; ", $insn, " {v:", $size, "}, cl; pushfq; pop {f}"),
+                    v = inout(reg) v, f = inout(reg) f, in("cl") count as u8)
+            }
+        };
+        ($insn:literal, $size:literal, double) => {
+            unsafe {
+                asm!(concat!("push {f}; popfq; ", $insn, " {v:", $size, "}, {o:", $size, "}, cl; pushfq; pop {f}"),
+                    v = inout(reg) v, f = inout(reg) f, o = in(reg) op2 as u64, in("cl") count as u8)
+            }
+        };
+    }
+    macro_rules! sizes {
+        ($insn:literal) => {
+            match bits {
+                8 => host!($insn, "l"),
+                16 => host!($insn, "x"),
+                _ => host!($insn, "e"),
+            }
+        };
+        ($insn:literal, double) => {
+            match bits {
+                16 => host!($insn, "x", double),
+                _ => host!($insn, "e", double),
+            }
+        };
+    }
+    match op {
+        Shift::Shl => sizes!("shl"),
+        Shift::Shr => sizes!("shr"),
+        Shift::Sar => sizes!("sar"),
+        Shift::Rol => sizes!("rol"),
+        Shift::Ror => sizes!("ror"),
+        Shift::Rcl => sizes!("rcl"),
+        Shift::Rcr => sizes!("rcr"),
+        Shift::Shld => sizes!("shld", double),
+        Shift::Shrd => sizes!("shrd", double),
+    }
+    (v as u32 & width_mask(bits), f as u16 & SWEEP_FLAGS)
+}
+
+/// regenerates flag_test_shifts.txt on a x86 host, run with `cargo test -- --ignored record_shift`
+#[test]
+#[ignore]
+#[cfg(target_arch = "x86_64")]
+fn record_shift_and_rotate_flags() {
+    use std::fmt::Write;
+    let mut out = String::from("# recorded by flag_test::record_shift_and_rotate_flags, do not edit\n");
+    for_each_sweep(|op, bits, op1, op2, carry| {
+        let op1 = op1 & width_mask(bits);
+        let op2 = op2 & width_mask(bits);
+        write!(out, "{:?} {} {:08X} {:08X} {}", op, bits, op1, op2, carry as u8).unwrap();
+        for count in 0..=32 {
+            let (res, flags) = host_shift(op, bits, op1, op2, carry, count);
+            write!(out, " {:08X}:{:03X}", res, flags).unwrap();
+        }
+        out.push('\n');
+    });
+    std::fs::write(concat!(env!("CARGO_MANIFEST_DIR"), "/src/cpu/flag_test_shifts.txt"), out).unwrap();
+}
+
+#[test]
+fn can_set_arithmetic_flags_of_edge_cases() {
+    // CF, PF, AF, ZF, SF and OF
+    const MASK: u16 = 0x08D5;
+    // opcode of "op al,imm8", carry in, al, imm8 => al, flags (as set by a 386)
+    let table = [
+        (0x04, false, 0x7F, 0x01, 0x80, 0x0890),    // add: OF, signed 127 + 1
+        (0x2C, false, 0x80, 0x01, 0x7F, 0x0810),    // sub: OF, signed -128 - 1
+        (0x04, false, 0x08, 0x08, 0x10, 0x0010),    // add: AF, carry out of the low nibble
+        (0x04, false, 0x0F, 0x10, 0x1F, 0x0000),    // add: no AF
+        (0x14, true, 0x00, 0x7F, 0x80, 0x0890),     // adc: OF and AF from the carry in
+        (0x14, true, 0x7F, 0xFF, 0x7F, 0x0011),     // adc: CF and AF, no OF
+        (0x14, true, 0xFF, 0x00, 0x00, 0x0055),     // adc: CF from the carry in
+        (0x1C, true, 0x00, 0xFF, 0x00, 0x0055),     // sbb: CF, borrow of 0xFF + 1
+        (0x1C, true, 0x80, 0x7F, 0x00, 0x0854),     // sbb: OF, signed -128 - 127 - 1
+        (0x1C, true, 0x7F, 0xFF, 0x7F, 0x0011),     // sbb: CF, no OF
+        (0x1C, true, 0x10, 0x00, 0x0F, 0x0014),     // sbb: AF from the borrow in
+        (0x1C, true, 0x00, 0x00, 0xFF, 0x0095),     // sbb: CF from the borrow in
+        (0x1C, false, 0x05, 0x03, 0x02, 0x0000),
+    ];
+    for &(opcode, carry, al, imm, res, flags) in &table {
+        let code: Vec<u8> = vec![
+            if carry { 0xF9 } else { 0xF8 },       // stc / clc
+            0xB0, al,                               // mov al,imm8
+            opcode, imm,                            // op al,imm8
+        ];
+        let mut machine = Machine::deterministic();
+        machine.load_executable(&code, 0x085F);
+        machine.execute_instructions(3);
+        let msg = format!("{:02X} {:02X}, {:02X}, cf {}", opcode, al, imm, carry);
+        assert_eq!(res, machine.cpu.get_r8(R::AL), "{}", msg);
+        assert_eq!(flags, machine.cpu.regs.flags.u16() & MASK, "{}", msg);
+    }
 }
@@ -1,8 +1,9 @@
 use std::fmt;
 
 use crate::cpu::Segment;
+use crate::cpu::analysis::{self, InstructionAnalysis};
 use crate::cpu::Op;
-use crate::cpu::{Parameter, ParameterSet};
+use crate::cpu::{Parameter, ParameterSet, R};
 use crate::cpu::{OperandSize, AddressSize};
 use crate::hex::hex_bytes;
 use crate::string::right_pad;
@@ -73,6 +74,25 @@ impl Instruction {
         self.command == Op::JmpShort || self.command == Op::JmpNear || self.command == Op::JmpFar
     }
 
+    /// returns true for instructions that may transfer control: jumps, calls, returns, loops and interrupts
+    pub fn is_branch(&self) -> bool {
+        matches!(self.command,
+            Op::CallNear | Op::CallFar | Op::JmpNear | Op::JmpShort | Op::JmpFar |
+            Op::Retn | Op::Retf | Op::RetImm16 | Op::Iret | Op::Int | Op::Into |
+            Op::Loop | Op::Loope | Op::Loopne |
+            Op::Ja | Op::Jc | Op::Jcxz | Op::Jg | Op::Jl |
+            Op::Jna | Op::Jnc | Op::Jng | Op::Jnl | Op::Jno | Op::Jns | Op::Jnz |
+            Op::Jo | Op::Jpe | Op::Jpo | Op::Js | Op::Jz)
+    }
+
+    /// mov ss and pop ss hold off the single-step trap until after the next instruction
+    pub fn inhibits_trap(&self) -> bool {
+        match self.params.dst {
+            Parameter::SReg16(R::SS) => self.command == Op::Mov16 || self.command == Op::Pop16 || self.command == Op::Pop32,
+            _ => false,
+        }
+    }
+
     fn op_size_from_op(op: &Op) -> OperandSize {
         match *op {
             Op::Mov32 | Op::Inc32 | Op::Dec32 => OperandSize::_32bit,
@@ -152,6 +172,84 @@ impl fmt::Display for InstructionInfo {
     }
 }
 
+impl InstructionInfo {
+    /// returns the targets of direct jumps, calls and loops, conditional or not
+    pub fn branch_targets(&self) -> Vec<BranchTarget> {
+        if !self.instruction.is_branch() {
+            return Vec::new();
+        }
+        match self.instruction.params.dst {
+            Parameter::Imm16(offset) if self.instruction.command != Op::Int => {
+                vec![BranchTarget { segment: self.segment as u16, offset }]
+            }
+            Parameter::Ptr16Imm(segment, offset) => vec![BranchTarget { segment, offset }],
+            _ => Vec::new(),
+        }
+    }
+
+    /// returns the operands, registers and memory used by the instruction
+    pub fn analysis(&self) -> InstructionAnalysis {
+        analysis::analyze(self)
+    }
+
+    /// returns the instruction as a record for other tools to consume
+    pub fn record(&self) -> InstructionRecord {
+        let op = &self.instruction;
+        let mut prefixes = Vec::new();
+        if op.lock {
+            prefixes.push("lock".to_owned());
+        }
+        if op.repeat != RepeatMode::None {
+            prefixes.push(op.repeat.as_str().to_owned());
+        }
+        if op.segment_prefix != Segment::Default {
+            prefixes.push(op.segment_prefix.as_str().to_owned());
+        }
+        let operands = [&op.params.dst, &op.params.src, &op.params.src2].iter()
+            .filter(|p| ***p != Parameter::None)
+            .map(|p| format!("{}", p))
+            .collect();
+        InstructionRecord {
+            segment: self.segment as u16,
+            offset: self.offset as u16,
+            address: ((self.segment << 4) + self.offset) as u32,
+            bytes: self.bytes.clone(),
+            prefixes,
+            mnemonic: format!("{}", op.command),
+            operands,
+            targets: self.branch_targets(),
+        }
+    }
+}
+
+/// address of a direct branch
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BranchTarget {
+    pub segment: u16,
+    pub offset: u16,
+}
+
+/// a decoded instruction, as written by `dustbox-disasm --format json`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct InstructionRecord {
+    pub segment: u16,
+    pub offset: u16,
+
+    /// linear address
+    pub address: u32,
+
+    pub bytes: Vec<u8>,
+
+    /// lock, repeat and segment prefixes
+    pub prefixes: Vec<String>,
+
+    pub mnemonic: String,
+    pub operands: Vec<String>,
+
+    /// targets of direct branches, the next instruction is not included
+    pub targets: Vec<BranchTarget>,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum RepeatMode {
     None,
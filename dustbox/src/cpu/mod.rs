@@ -24,19 +24,21 @@ mod op;
 pub use self::encoder::*;
 mod encoder;
 
+pub use self::assembler::*;
+mod assembler;
+
+pub mod analysis;
+
 use std::u8;
 use std::num::Wrapping;
+use std::str::FromStr;
+
+use log::{debug, log_enabled, trace, warn, Level};
 
 use crate::machine::{DEBUG_MARK_STACK, STACK_MARKER};
 use crate::memory::{MMU, MemoryAddress};
 
-/// prints diagnostics if writes to memory close to SS:SP occurs
-const DEBUG_PARAMS_TOUCHING_STACK: bool = false;
-
-/// prints diagnostics of stack usage (push / pop)
-const DEBUG_STACK: bool = false;
-
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum Exception {
     // http://wiki.osdev.org/Interrupt_Vector_Table
     DIV0 = 0,    // Divide by 0
@@ -49,6 +51,51 @@ pub enum Exception {
     PF = 14,     // Page fault
 }
 
+/// the emulated processor generation
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd, Deserialize)]
+pub enum CpuModel {
+    #[default]
+    #[serde(rename = "8086")]
+    I8086,
+    #[serde(rename = "186")]
+    I80186,
+    #[serde(rename = "286")]
+    I80286,
+    #[serde(rename = "386")]
+    I80386,
+    #[serde(rename = "486")]
+    I80486,
+}
+
+impl CpuModel {
+    /// returns the clock speed of a typical machine with this cpu
+    pub fn clock_hz(self) -> usize {
+        match self {
+            CpuModel::I8086 => 5_000_000, // Intel 8086: 0.330 MIPS at 5.000 MHz
+            CpuModel::I80186 => 8_000_000,
+            CpuModel::I80286 => 12_000_000,
+            CpuModel::I80386 => 25_000_000,
+            CpuModel::I80486 => 33_000_000,
+        }
+    }
+}
+
+impl FromStr for CpuModel {
+    type Err = String;
+
+    /// parses "8086", "186", "286", "386" or "486"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_start_matches("80").trim_start_matches('i') {
+            "8086" | "86" => Ok(CpuModel::I8086),
+            "186" => Ok(CpuModel::I80186),
+            "286" => Ok(CpuModel::I80286),
+            "386" => Ok(CpuModel::I80386),
+            "486" => Ok(CpuModel::I80486),
+            _ => Err(format!("unknown cpu model {}", s)),
+        }
+    }
+}
+
 pub struct CPU {
     pub instruction_count: usize,
     pub cycle_count: usize,
@@ -64,6 +111,9 @@ pub struct CPU {
 
     pub decoder: Decoder,
     pub clock_hz: usize,
+
+    /// the emulated cpu, decides the flag image seen by PUSHF, POPF and IRET
+    pub model: CpuModel,
 }
 
 impl CPU {
@@ -75,13 +125,15 @@ impl CPU {
             fatal_error: false,
             deterministic: false,
             decoder: Decoder::default(),
-            clock_hz: 5_000_000, // Intel 8086: 0.330 MIPS at 5.000 MHz
+            clock_hz: CpuModel::I8086.clock_hz(),
+            model: CpuModel::I8086,
         }
     }
 
     pub fn deterministic() -> Self {
         let mut res = Self::default();
         res.deterministic = true;
+        res.regs.flags.eager = true;
         res
     }
 
@@ -109,8 +161,37 @@ impl CPU {
         self.regs.set_r32(r, val);
     }
 
-    pub fn execute_interrupt(&mut self, mmu: &mut MMU, int: u8) {
+    /// returns the FLAGS register as pushed by the cpu model: bits 12-15 are always set on
+    /// the 8086 and 186, and always clear in real mode on the 286
+    pub fn flags_image(&self) -> u16 {
         let flags = self.regs.flags.u16();
+        match self.model {
+            CpuModel::I8086 | CpuModel::I80186 => flags | 0xF000,
+            CpuModel::I80286 => flags & 0x0FFF,
+            CpuModel::I80386 | CpuModel::I80486 => flags & 0x7FFF,
+        }
+    }
+
+    /// sets the FLAGS register from a popped value, IOPL and NT can only be changed on the 386 and later
+    pub fn set_flags_image(&mut self, val: u16) {
+        let val = match self.model {
+            CpuModel::I80386 | CpuModel::I80486 => val,
+            _ => val & 0x0FFF,
+        };
+        self.regs.flags.set_u16(val);
+    }
+
+    /// returns CR0 as read in real mode: the unused bits of the 286 machine status word read
+    /// as ones, the 386 reports a 387 (ET), as does the 486 with its builtin fpu
+    pub fn cr0(&self) -> u32 {
+        match self.model {
+            CpuModel::I80386 | CpuModel::I80486 => 0x0000_0010,
+            _ => 0xFFF0,
+        }
+    }
+
+    pub fn execute_interrupt(&mut self, mmu: &mut MMU, int: u8) {
+        let flags = self.flags_image();
         self.push16(mmu, flags);
         mmu.flags_address = MemoryAddress::RealSegmentOffset(self.get_r16(R::SS), self.get_r16(R::SP));
 
@@ -128,63 +209,49 @@ impl CPU {
         self.set_r16(R::CS, cs);
     }
 
-    pub fn exception(&mut self, which: &Exception, error: usize) {
-        /*
-        #define CPU_INT_SOFTWARE    0x1
-        #define CPU_INT_EXCEPTION   0x2
-        #define CPU_INT_HAS_ERROR   0x4
-        #define CPU_INT_NOIOPLCHECK 0x8
-        */
-        println!("Exception {:?}, error {}", which, error);
-
-        // CPU_Interrupt(which,CPU_INT_EXCEPTION | ((which>=8) ? CPU_INT_HAS_ERROR : 0),reg_eip);
+    /// delivers the exception `which` through the interrupt vector table, raised by the instruction
+    /// of `len` bytes before ip. faults return to that instruction, so it is restarted once the
+    /// handler has fixed the cause. the divide error of the 8086 and 186 returns after it
+    pub fn exception(&mut self, mmu: &mut MMU, which: &Exception, len: u8) {
+        let fault = match which {
+            Exception::DIV0 => !matches!(self.model, CpuModel::I8086 | CpuModel::I80186),
+            _ => true,
+        };
+        if fault {
+            self.regs.ip = self.regs.ip.wrapping_sub(u16::from(len));
+        }
+        debug!(target: "cpu", "[{}] exception {:?}", self.get_memory_address(), which);
+        self.execute_interrupt(mmu, *which as u8);
     }
 
-    pub fn cmp8(&mut self, dst: usize, src: usize) {
+    pub fn cmp8(&mut self, dst: u64, src: u64) {
         let res = (Wrapping(dst) - Wrapping(src)).0;
 
         // The CF, OF, SF, ZF, AF, and PF flags are set according to the result.
-        self.regs.flags.set_carry_u8(res);
-        self.regs.flags.set_overflow_sub_u8(res, src, dst);
-        self.regs.flags.set_sign_u8(res);
-        self.regs.flags.set_zero_u8(res);
-        self.regs.flags.set_adjust(res, src, dst);
-        self.regs.flags.set_parity(res);
+        self.regs.flags.set_lazy(LazyOp::Sub, 8, res, src, dst);
     }
 
-    pub fn cmp16(&mut self, dst: usize, src: usize) {
+    pub fn cmp16(&mut self, dst: u64, src: u64) {
         let res = (Wrapping(dst) - Wrapping(src)).0;
 
         // The CF, OF, SF, ZF, AF, and PF flags are set according to the result.
-        self.regs.flags.set_carry_u16(res);
-        self.regs.flags.set_overflow_sub_u16(res, src, dst);
-        self.regs.flags.set_sign_u16(res);
-        self.regs.flags.set_zero_u16(res);
-        self.regs.flags.set_adjust(res, src, dst);
-        self.regs.flags.set_parity(res);
+        self.regs.flags.set_lazy(LazyOp::Sub, 16, res, src, dst);
     }
 
-    pub fn cmp32(&mut self, dst: usize, src: usize) {
+    pub fn cmp32(&mut self, dst: u64, src: u64) {
         let res = (Wrapping(dst) - Wrapping(src)).0;
 
         // The CF, OF, SF, ZF, AF, and PF flags are set according to the result.
-        self.regs.flags.set_carry_u32(res);
-        self.regs.flags.set_overflow_sub_u32(res, src, dst);
-        self.regs.flags.set_sign_u32(res);
-        self.regs.flags.set_zero_u32(res);
-        self.regs.flags.set_adjust(res, src, dst);
-        self.regs.flags.set_parity(res);
+        self.regs.flags.set_lazy(LazyOp::Sub, 32, res, src, dst);
     }
 
     pub fn push16(&mut self, mmu: &mut MMU, data: u16) {
         let sp = (Wrapping(self.get_r16(R::SP)) - Wrapping(2)).0;
         self.set_r16(R::SP, sp);
         let ss = self.get_r16(R::SS);
-        if DEBUG_STACK {
-            println!("[{}] push16 {:04X} to {:04X}:{:04X}", self.get_memory_address(), data, ss, sp);
-        }
+        trace!(target: "cpu", "[{}] push16 {:04X} to {:04X}:{:04X}", self.get_memory_address(), data, ss, sp);
         if DEBUG_MARK_STACK && data == STACK_MARKER {
-            println!("[{}] push16 {:04X} to {:04X}:{:04X} STACK MARKER", self.get_memory_address(), data, ss, sp);
+            warn!(target: "cpu", "[{}] push16 {:04X} to {:04X}:{:04X} STACK MARKER", self.get_memory_address(), data, ss, sp);
         }
         mmu.write_u16(ss, sp, data);
     }
@@ -193,9 +260,7 @@ impl CPU {
         let sp = (Wrapping(self.get_r16(R::SP)) - Wrapping(4)).0;
         self.set_r16(R::SP, sp);
         let ss = self.get_r16(R::SS);
-        if DEBUG_STACK {
-            println!("[{}] push32 {:04X} to {:04X}:{:04X}", self.get_memory_address(), data, ss, sp);
-        }
+        trace!(target: "cpu", "[{}] push32 {:04X} to {:04X}:{:04X}", self.get_memory_address(), data, ss, sp);
         mmu.write_u32(ss, sp, data);
     }
 
@@ -203,9 +268,7 @@ impl CPU {
         let ss = self.get_r16(R::SS);
         let sp = self.get_r16(R::SP);
         let data = mmu.read_u16(ss, self.get_r16(R::SP));
-        if DEBUG_STACK {
-            println!("[{}] pop16 {:04X} from {:04X}:{:04X}", self.get_memory_address(), data, ss, sp);
-        }
+        trace!(target: "cpu", "[{}] pop16 {:04X} from {:04X}:{:04X}", self.get_memory_address(), data, ss, sp);
         let sp = (Wrapping(sp) + Wrapping(2)).0;
         self.set_r16(R::SP, sp);
         data
@@ -215,9 +278,7 @@ impl CPU {
         let ss = self.get_r16(R::SS);
         let sp = self.get_r16(R::SP);
         let data = mmu.read_u32(ss, sp);
-        if DEBUG_STACK {
-            println!("[{}] pop32 {:04X} from {:04X}:{:04X}", self.get_memory_address(), data, ss, sp);
-        }
+        trace!(target: "cpu", "[{}] pop32 {:04X} from {:04X}:{:04X}", self.get_memory_address(), data, ss, sp);
         let sp = (Wrapping(sp) + Wrapping(4)).0;
         self.set_r16(R::SP, sp);
         data
@@ -270,115 +331,141 @@ impl CPU {
     }
 
     /// returns "segment, offset" pair
-    fn get_amode_addr(&self, amode: &AMode) -> (u16, u16) {
-        match *amode {
-            AMode::BX  => (self.get_r16(R::DS), self.get_r16(R::BX)),
-            AMode::BP  => (self.get_r16(R::SS), self.get_r16(R::BP)),
-            AMode::SI  => (self.get_r16(R::DS), self.get_r16(R::SI)),
-            AMode::DI  => (self.get_r16(R::DS), self.get_r16(R::DI)),
-            AMode::BXSI => (self.get_r16(R::DS), self.get_r16(R::BX).wrapping_add(self.get_r16(R::SI))),
-            AMode::BXDI => (self.get_r16(R::DS), self.get_r16(R::BX).wrapping_add(self.get_r16(R::DI))),
-            AMode::BPSI => (self.get_r16(R::SS), self.get_r16(R::BP).wrapping_add(self.get_r16(R::SI))),
-            AMode::BPDI => (self.get_r16(R::SS), self.get_r16(R::BP).wrapping_add(self.get_r16(R::DI))),
-            _ => panic!("xxx"),
-        }
+    fn get_amode_addr(&self, seg: Segment, amode: &AMode) -> (u16, u16) {
+        (self.amode_segment(seg, amode), self.amode(amode) as u16)
     }
 
-    /// used by lds, les
+    /// returns the segment and offset of the far pointer in memory at `p`, used by lds, les and far call/jmp
     pub fn read_segment_selector(&self, mmu: &MMU, p: &Parameter) -> (u16, u16) {
-        let (segment, offset) = match *p {
+        let (segment, offset) = self.segment_selector_address(p);
+        let o_val = mmu.read_u16(segment, offset);
+        let s_val = mmu.read_u16(segment, offset.wrapping_add(2));
+        (s_val, o_val)
+    }
+
+    /// reads the m16:32 far pointer at `p`, returning segment and offset. offsets
+    /// above 0xFFFF fault in real mode, and are truncated
+    pub fn read_segment_selector32(&self, mmu: &MMU, p: &Parameter) -> (u16, u16) {
+        let (segment, offset) = self.segment_selector_address(p);
+        let o_val = mmu.read_u32(segment, offset) as u16;
+        let s_val = mmu.read_u16(segment, offset.wrapping_add(4));
+        (s_val, o_val)
+    }
+
+    fn segment_selector_address(&self, p: &Parameter) -> (u16, u16) {
+        match *p {
             Parameter::Ptr16(seg, imm) => (self.segment(seg), imm),
-            Parameter::Ptr16Amode(_, ref amode) => self.get_amode_addr(amode),
-            Parameter::Ptr16AmodeS8(_, ref amode, imms) => {
-                let (seg, off) = self.get_amode_addr(amode);
+            Parameter::Ptr16Amode(seg, ref amode) => self.get_amode_addr(seg, amode),
+            Parameter::Ptr16AmodeS8(seg, ref amode, imms) => {
+                let (seg, off) = self.get_amode_addr(seg, amode);
                 (seg, (i32::from(off) + i32::from(imms)) as u16)
             }
-            /*
-            Parameter::Ptr16AmodeS16(_, ref amode, imms) => {
-                let (seg, off) = self.get_amode_addr(amode);
+            Parameter::Ptr16AmodeS16(seg, ref amode, imms) => {
+                let (seg, off) = self.get_amode_addr(seg, amode);
                 (seg, (i32::from(off) + i32::from(imms)) as u16)
             }
-            */
+            Parameter::Ptr16AmodeS32(seg, ref amode, imms) => {
+                let (seg, off) = self.get_amode_addr(seg, amode);
+                (seg, (Wrapping(off) + Wrapping(imms as u16)).0)
+            }
             _ => panic!("unhandled parameter {:?}", p),
-        };
-
-        let o_val = mmu.read_u16(segment, offset);
-        let s_val = mmu.read_u16(segment, offset + 2);
-        (s_val, o_val)
+        }
     }
 
     /// returns the address of pointer, used by LEA
-    pub fn read_parameter_address(&mut self, p: &Parameter) -> usize {
+    pub fn read_parameter_address(&mut self, p: &Parameter) -> u64 {
         match *p {
             Parameter::Ptr16Amode(_, ref amode) => self.amode(amode),
-            Parameter::Ptr16AmodeS8(_, ref amode, imm) => (Wrapping(self.amode(amode)) + Wrapping(imm as usize)).0,
-            Parameter::Ptr16AmodeS16(_, ref amode, imm) => (Wrapping(self.amode(amode)) + Wrapping(imm as usize)).0,
-            Parameter::Ptr16(_, imm) => imm as usize,
+            Parameter::Ptr16AmodeS8(_, ref amode, imm) => (Wrapping(self.amode(amode)) + Wrapping(imm as u64)).0,
+            Parameter::Ptr16AmodeS16(_, ref amode, imm) => (Wrapping(self.amode(amode)) + Wrapping(imm as u64)).0,
+            Parameter::Ptr16AmodeS32(_, ref amode, imm) => (Wrapping(self.amode(amode) as u32) + Wrapping(imm as u32)).0 as u64,
+            Parameter::Ptr16(_, imm) => imm as u64,
             _ => panic!("unhandled parameter: {:?} at {:06X}", p, self.get_address()),
         }
     }
 
-    pub fn read_parameter_imm(&self, p: &Parameter) -> usize {
+    pub fn read_parameter_imm(&self, p: &Parameter) -> u64 {
         match *p {
-            Parameter::Imm8(imm) => imm as usize,
-            Parameter::Imm16(imm) => imm as usize,
-            Parameter::ImmS8(imm) => imm as usize,
+            Parameter::Imm8(imm) => imm as u64,
+            Parameter::Imm16(imm) => imm as u64,
+            Parameter::ImmS8(imm) => imm as u64,
             _ => panic!("read_parameter_imm only allows imm-type params: {:?}", p),
         }
     }
 
-    pub fn read_parameter_value(&mut self, mmu: &MMU, p: &Parameter) -> usize {
+    pub fn read_parameter_value(&mut self, mmu: &MMU, p: &Parameter) -> u64 {
         match *p {
-            Parameter::Imm8(imm) => imm as usize,
-            Parameter::Imm16(imm) => imm as usize,
-            Parameter::Imm32(imm) => imm as usize,
-            Parameter::ImmS8(imm) => imm as usize,
-            Parameter::Reg8(r) => self.get_r8(r) as usize,
-            Parameter::Reg16(r) => self.get_r16(r) as usize,
-            Parameter::Reg32(r) => self.get_r32(r) as usize,
-            Parameter::SReg16(sr) => self.get_r16(sr) as usize,
-            Parameter::Ptr8(seg, imm) => mmu.read_u8(self.segment(seg), imm) as usize,
+            Parameter::Imm8(imm) => imm as u64,
+            Parameter::Imm16(imm) => imm as u64,
+            Parameter::Imm32(imm) => imm as u64,
+            Parameter::ImmS8(imm) => imm as u64,
+            Parameter::Reg8(r) => self.get_r8(r) as u64,
+            Parameter::Reg16(r) => self.get_r16(r) as u64,
+            Parameter::Reg32(r) => self.get_r32(r) as u64,
+            Parameter::SReg16(sr) => self.get_r16(sr) as u64,
+            Parameter::CReg32(_) => u64::from(self.cr0()),
+            Parameter::Ptr8(seg, imm) => mmu.read_u8(self.segment(seg), imm) as u64,
             Parameter::Ptr8Amode(seg, ref amode) => {
-                let seg = self.segment(seg);
+                let seg = self.amode_segment(seg, amode);
                 let offset = self.amode(amode) as u16;
-                mmu.read_u8(seg, offset) as usize
+                mmu.read_u8(seg, offset) as u64
             }
             Parameter::Ptr8AmodeS8(seg, ref amode, imm) => {
-                let seg = self.segment(seg);
+                let seg = self.amode_segment(seg, amode);
                 let offset = (Wrapping(self.amode(amode) as u16) + Wrapping(imm as u16)).0;
-                mmu.read_u8(seg, offset) as usize
+                mmu.read_u8(seg, offset) as u64
             }
             Parameter::Ptr8AmodeS16(seg, ref amode, imm) => {
-                let seg = self.segment(seg);
+                let seg = self.amode_segment(seg, amode);
+                let offset = (Wrapping(self.amode(amode) as u16) + Wrapping(imm as u16)).0;
+                mmu.read_u8(seg, offset) as u64
+            }
+            Parameter::Ptr8AmodeS32(seg, ref amode, imm) => {
+                let seg = self.amode_segment(seg, amode);
                 let offset = (Wrapping(self.amode(amode) as u16) + Wrapping(imm as u16)).0;
-                mmu.read_u8(seg, offset) as usize
+                mmu.read_u8(seg, offset) as u64
             }
-            Parameter::Ptr16(seg, imm) => mmu.read_u16(self.segment(seg), imm) as usize,
+            Parameter::Ptr16(seg, imm) => mmu.read_u16(self.segment(seg), imm) as u64,
             Parameter::Ptr16Amode(seg, ref amode) => {
-                let seg = self.segment(seg);
+                let seg = self.amode_segment(seg, amode);
                 let offset = self.amode(amode) as u16;
-                mmu.read_u16(seg, offset) as usize
+                mmu.read_u16(seg, offset) as u64
             }
             Parameter::Ptr16AmodeS8(seg, ref amode, imm) => {
-                let seg = self.segment(seg);
+                let seg = self.amode_segment(seg, amode);
                 let offset = (Wrapping(self.amode(amode) as u16) + Wrapping(imm as u16)).0;
-                mmu.read_u16(seg, offset) as usize
+                mmu.read_u16(seg, offset) as u64
             }
             Parameter::Ptr16AmodeS16(seg, ref amode, imm) => {
-                let seg = self.segment(seg);
+                let seg = self.amode_segment(seg, amode);
                 let offset = (Wrapping(self.amode(amode) as u16) + Wrapping(imm as u16)).0;
-                mmu.read_u16(seg, offset) as usize
+                mmu.read_u16(seg, offset) as u64
             }
-            Parameter::Ptr32(seg, offset) => mmu.read_u32(self.segment(seg), offset) as usize,
+            Parameter::Ptr16AmodeS32(seg, ref amode, imm) => {
+                let seg = self.amode_segment(seg, amode);
+                let offset = (Wrapping(self.amode(amode) as u16) + Wrapping(imm as u16)).0;
+                mmu.read_u16(seg, offset) as u64
+            }
+            Parameter::Ptr32(seg, offset) => mmu.read_u32(self.segment(seg), offset) as u64,
             Parameter::Ptr32Amode(seg, ref amode) => {
-                let seg = self.segment(seg);
+                let seg = self.amode_segment(seg, amode);
                 let offset = self.amode(amode) as u16;
-                mmu.read_u32(seg, offset) as usize
+                mmu.read_u32(seg, offset) as u64
             }
             Parameter::Ptr32AmodeS8(seg, ref amode, imm) => {
-                let seg = self.segment(seg);
+                let seg = self.amode_segment(seg, amode);
                 let offset = (Wrapping(self.amode(amode) as u16) + Wrapping(imm as u16)).0;
-                mmu.read_u32(seg, offset) as usize
+                mmu.read_u32(seg, offset) as u64
+            }
+            Parameter::Ptr32AmodeS16(seg, ref amode, imm) => {
+                let seg = self.amode_segment(seg, amode);
+                let offset = (Wrapping(self.amode(amode) as u16) + Wrapping(imm as u16)).0;
+                mmu.read_u32(seg, offset) as u64
+            }
+            Parameter::Ptr32AmodeS32(seg, ref amode, imm) => {
+                let seg = self.amode_segment(seg, amode);
+                let offset = (Wrapping(self.amode(amode) as u16) + Wrapping(imm as u16)).0;
+                mmu.read_u32(seg, offset) as u64
             }
             _ => {
                 let (seg, off) = self.get_address_pair();
@@ -396,19 +483,25 @@ impl CPU {
                 mmu.write_u8(seg, offset, data);
             }
             Parameter::Ptr8Amode(seg, ref amode) => {
-                let seg = self.segment(seg);
+                let seg = self.amode_segment(seg, amode);
                 let offset = self.amode(amode) as u16;
                 self.debug_write_u8(seg, offset, data);
                 mmu.write_u8(seg, offset, data);
             }
             Parameter::Ptr8AmodeS8(seg, ref amode, imm) => {
-                let seg = self.segment(seg);
+                let seg = self.amode_segment(seg, amode);
                 let offset = (Wrapping(self.amode(amode) as u16) + Wrapping(imm as u16)).0;
                 self.debug_write_u8(seg, offset, data);
                 mmu.write_u8(seg, offset, data);
             }
             Parameter::Ptr8AmodeS16(seg, ref amode, imm) => {
-                let seg = self.segment(seg);
+                let seg = self.amode_segment(seg, amode);
+                let offset = (Wrapping(self.amode(amode) as u16) + Wrapping(imm as u16)).0;
+                self.debug_write_u8(seg, offset, data);
+                mmu.write_u8(seg, offset, data);
+            }
+            Parameter::Ptr8AmodeS32(seg, ref amode, imm) => {
+                let seg = self.amode_segment(seg, amode);
                 let offset = (Wrapping(self.amode(amode) as u16) + Wrapping(imm as u16)).0;
                 self.debug_write_u8(seg, offset, data);
                 mmu.write_u8(seg, offset, data);
@@ -432,19 +525,25 @@ impl CPU {
                 mmu.write_u16(seg, offset, data);
             }
             Parameter::Ptr16Amode(seg, ref amode) => {
-                let seg = self.segment(seg);
+                let seg = self.amode_segment(seg, amode);
                 let offset = self.amode(amode) as u16;
                 self.debug_write_u16(seg, offset, data);
                 mmu.write_u16(seg, offset, data);
             }
             Parameter::Ptr16AmodeS8(seg, ref amode, imm) => {
-                let seg = self.segment(seg);
+                let seg = self.amode_segment(seg, amode);
                 let offset = (Wrapping(self.amode(amode) as u16) + Wrapping(imm as u16)).0;
                 self.debug_write_u16(seg, offset, data);
                 mmu.write_u16(seg, offset, data);
             }
             Parameter::Ptr16AmodeS16(seg, ref amode, imm) => {
-                let seg = self.segment(seg);
+                let seg = self.amode_segment(seg, amode);
+                let offset = (Wrapping(self.amode(amode) as u16) + Wrapping(imm as u16)).0;
+                self.debug_write_u16(seg, offset, data);
+                mmu.write_u16(seg, offset, data);
+            }
+            Parameter::Ptr16AmodeS32(seg, ref amode, imm) => {
+                let seg = self.amode_segment(seg, amode);
                 let offset = (Wrapping(self.amode(amode) as u16) + Wrapping(imm as u16)).0;
                 self.debug_write_u16(seg, offset, data);
                 mmu.write_u16(seg, offset, data);
@@ -462,29 +561,35 @@ impl CPU {
                 mmu.write_u32(seg, offset, data);
             }
             Parameter::Ptr32Amode(seg, ref amode) => {
-                let seg = self.segment(seg);
+                let seg = self.amode_segment(seg, amode);
                 let offset = self.amode(amode);
                 self.debug_write_u32(seg, offset as u16, data);
                 mmu.write_u32(seg, offset as u16, data);
             }
             Parameter::Ptr32AmodeS8(seg, ref amode, imm) => {
-                let seg = self.segment(seg);
+                let seg = self.amode_segment(seg, amode);
                 let offset = (Wrapping(self.amode(amode) as u16) + Wrapping(imm as u16)).0;
                 self.debug_write_u32(seg, offset as u16, data);
                 mmu.write_u32(seg, offset, data);
             }
             Parameter::Ptr32AmodeS16(seg, ref amode, imm) => {
-                let seg = self.segment(seg);
+                let seg = self.amode_segment(seg, amode);
                 let offset = (Wrapping(self.amode(amode) as u16) + Wrapping(imm as u16)).0;
                 self.debug_write_u32(seg, offset as u16, data);
                 mmu.write_u32(seg, offset, data);
             }
+            Parameter::Ptr32AmodeS32(seg, ref amode, imm) => {
+                let seg = self.amode_segment(seg, amode);
+                let offset = (Wrapping(self.amode(amode) as u16) + Wrapping(imm as u16)).0;
+                self.debug_write_u32(seg, offset, data);
+                mmu.write_u32(seg, offset, data);
+            }
             _ => panic!("unhandled type {:?} at {:06X}", p, self.get_address()),
         }
     }
 
     fn debug_write_u8(&self, seg: u16, off: u16, data: u8) {
-        if !DEBUG_PARAMS_TOUCHING_STACK {
+        if !log_enabled!(target: "cpu", Level::Trace) {
             return;
         }
         let pos = MemoryAddress::RealSegmentOffset(seg, off).value() as isize;
@@ -493,12 +598,12 @@ impl CPU {
         let dist = (pos - stack.value() as isize).abs();
         if dist < 256 {
             // XXX points to the instruction AFTER the one to blame
-            println!("[{}] debug_write_u8 {:04X}:{:04X} = {:02X} ... stack {} (dist {})", code, seg, off, data, stack, dist);
+            trace!(target: "cpu", "[{}] debug_write_u8 {:04X}:{:04X} = {:02X} ... stack {} (dist {})", code, seg, off, data, stack, dist);
         }
     }
 
     fn debug_write_u16(&self, seg: u16, off: u16, data: u16) {
-        if !DEBUG_PARAMS_TOUCHING_STACK {
+        if !log_enabled!(target: "cpu", Level::Trace) {
             return;
         }
         let pos = MemoryAddress::RealSegmentOffset(seg, off).value() as isize;
@@ -507,12 +612,12 @@ impl CPU {
         let dist = (pos - stack.value() as isize).abs();
         if dist < 256 {
             // XXX points to the instruction AFTER the one to blame
-            println!("[{}] debug_write_u16 {:04X}:{:04X} = {:04X} ... stack {} (dist {})", code, seg, off, data, stack, dist);
+            trace!(target: "cpu", "[{}] debug_write_u16 {:04X}:{:04X} = {:04X} ... stack {} (dist {})", code, seg, off, data, stack, dist);
         }
     }
 
     fn debug_write_u32(&self, seg: u16, off: u16, data: u32) {
-        if !DEBUG_PARAMS_TOUCHING_STACK {
+        if !log_enabled!(target: "cpu", Level::Trace) {
             return;
         }
         let pos = MemoryAddress::RealSegmentOffset(seg, off).value() as isize;
@@ -521,7 +626,7 @@ impl CPU {
         let dist = (pos - stack.value() as isize).abs();
         if dist < 256 {
              // XXX points to the instruction AFTER the one to blame
-            println!("[{}] debug_write_u32 {:04X}:{:04X} = {:08X} ... stack {} (dist {})", code, seg, off, data, stack, dist);
+            trace!(target: "cpu", "[{}] debug_write_u32 {:04X}:{:04X} = {:08X} ... stack {} (dist {})", code, seg, off, data, stack, dist);
         }
     }
 
@@ -530,40 +635,60 @@ impl CPU {
         self.get_r16(seg.as_register())
     }
 
-    pub fn amode(&self, amode: &AMode) -> usize {
+    /// returns the value of the segment register used to address memory through `amode`,
+    /// which is SS for BP, EBP and ESP based addressing unless `seg` overrides it
+    fn amode_segment(&self, seg: Segment, amode: &AMode) -> u16 {
+        match seg {
+            Segment::Default if amode.uses_stack_segment() => self.get_r16(R::SS),
+            seg => self.segment(seg),
+        }
+    }
+
+    pub fn amode(&self, amode: &AMode) -> u64 {
         match *amode {
-            AMode::BXSI => (Wrapping(self.get_r16(R::BX)) + Wrapping(self.get_r16(R::SI))).0 as usize,
-            AMode::BXDI => (Wrapping(self.get_r16(R::BX)) + Wrapping(self.get_r16(R::DI))).0 as usize,
-            AMode::BPSI => (Wrapping(self.get_r16(R::BP)) + Wrapping(self.get_r16(R::SI))).0 as usize,
-            AMode::BPDI => (Wrapping(self.get_r16(R::BP)) + Wrapping(self.get_r16(R::DI))).0 as usize,
-            AMode::SI => self.get_r16(R::SI) as usize,
-            AMode::DI => self.get_r16(R::DI) as usize,
-            AMode::BP => self.get_r16(R::BP) as usize,
-            AMode::BX => self.get_r16(R::BX) as usize,
-
-            AMode::EAX => self.get_r32(R::EAX) as usize,
-            AMode::ECX => self.get_r32(R::ECX) as usize,
-            AMode::EDX => self.get_r32(R::EDX) as usize,
-            AMode::EBX => self.get_r32(R::EBX) as usize,
-            AMode::ESP => self.get_r32(R::ESP) as usize,
-            AMode::EBP => self.get_r32(R::EBP) as usize,
-            AMode::ESI => self.get_r32(R::ESI) as usize,
-            AMode::EDI => self.get_r32(R::EDI) as usize,
+            AMode::BXSI => (Wrapping(self.get_r16(R::BX)) + Wrapping(self.get_r16(R::SI))).0 as u64,
+            AMode::BXDI => (Wrapping(self.get_r16(R::BX)) + Wrapping(self.get_r16(R::DI))).0 as u64,
+            AMode::BPSI => (Wrapping(self.get_r16(R::BP)) + Wrapping(self.get_r16(R::SI))).0 as u64,
+            AMode::BPDI => (Wrapping(self.get_r16(R::BP)) + Wrapping(self.get_r16(R::DI))).0 as u64,
+            AMode::SI => self.get_r16(R::SI) as u64,
+            AMode::DI => self.get_r16(R::DI) as u64,
+            AMode::BP => self.get_r16(R::BP) as u64,
+            AMode::BX => self.get_r16(R::BX) as u64,
+
+            AMode::EAX => self.get_r32(R::EAX) as u64,
+            AMode::ECX => self.get_r32(R::ECX) as u64,
+            AMode::EDX => self.get_r32(R::EDX) as u64,
+            AMode::EBX => self.get_r32(R::EBX) as u64,
+            AMode::ESP => self.get_r32(R::ESP) as u64,
+            AMode::EBP => self.get_r32(R::EBP) as u64,
+            AMode::ESI => self.get_r32(R::ESI) as u64,
+            AMode::EDI => self.get_r32(R::EDI) as u64,
+            AMode::SIB(base, index, scale) => {
+                let base = match base {
+                    Some(r) => self.get_r32(r),
+                    None => 0,
+                };
+                let index = match index {
+                    Some(r) => self.get_r32(r).wrapping_mul(u32::from(scale)),
+                    None => 0,
+                };
+                base.wrapping_add(index) as u64
+            }
         }
     }
 
     /// used by aaa, aas
     pub fn adjb(&mut self, param1: i8, param2: i8) {
-        if self.regs.flags.adjust || (self.get_r8(R::AL) & 0xf) > 9 {
+        if self.regs.flags.adjust() || (self.get_r8(R::AL) & 0xf) > 9 {
             let al = (i16::from(self.get_r8(R::AL)) + i16::from(param1)) as u8;
             let ah = (i16::from(self.get_r8(R::AH)) + i16::from(param2)) as u8;
             self.set_r8(R::AL, al);
             self.set_r8(R::AH, ah);
-            self.regs.flags.adjust = true;
-            self.regs.flags.carry = true;
+            self.regs.flags.set_adjust(true);
+            self.regs.flags.set_carry(true);
         } else {
-            self.regs.flags.adjust = false;
-            self.regs.flags.carry = false;
+            self.regs.flags.set_adjust(false);
+            self.regs.flags.set_carry(false);
         }
         let al = self.get_r8(R::AL);
         self.set_r8(R::AL, al & 0x0F);
@@ -572,27 +697,27 @@ impl CPU {
     /// used by daa, das
     pub fn adj4(&mut self, param1: i16, param2: i16) {
         let mut al = self.get_r8(R::AL);
-        if ((al & 0x0F) > 0x09) || self.regs.flags.adjust {
-            if (al > 0x99) || self.regs.flags.carry {
+        if ((al & 0x0F) > 0x09) || self.regs.flags.adjust() {
+            if (al > 0x99) || self.regs.flags.carry() {
                 al = (i16::from(al) + param2) as u8;
-                self.regs.flags.carry = true;
+                self.regs.flags.set_carry(true);
             } else {
-                self.regs.flags.carry = false;
+                self.regs.flags.set_carry(false);
             }
             al = (i16::from(al) + param1) as u8;
-            self.regs.flags.adjust = true;
+            self.regs.flags.set_adjust(true);
         } else {
-            if (al > 0x99) || self.regs.flags.carry {
+            if (al > 0x99) || self.regs.flags.carry() {
                 al = (i16::from(al) + param2) as u8;
-                self.regs.flags.carry = true;
+                self.regs.flags.set_carry(true);
             } else {
-                self.regs.flags.carry = false;
+                self.regs.flags.set_carry(false);
             }
-            self.regs.flags.adjust = false;
+            self.regs.flags.set_adjust(false);
         }
         self.set_r8(R::AL, al);
-        self.regs.flags.sign = al & 0x80 != 0;
-        self.regs.flags.zero = al == 0;
-        self.regs.flags.set_parity(al as usize);
+        self.regs.flags.set_sign(al & 0x80 != 0);
+        self.regs.flags.set_zero(al == 0);
+        self.regs.flags.set_parity_from(al as u64);
     }
 }
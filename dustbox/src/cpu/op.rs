@@ -1,7 +1,41 @@
 use std::fmt;
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum Op {
+/// declares `Op` with the given fieldless variants followed by `Invalid`, and `OpIndex` numbering
+/// them, which indexes the instruction dispatch table
+macro_rules! ops {
+    ($($(#[$attr:meta])* $op:ident),* $(,)?) => {
+        #[derive(Clone, Debug, PartialEq)]
+        pub enum Op {
+            $($(#[$attr])* $op,)*
+
+            /// Invalid encoding. XXX also used for unhandled encodings atm
+            Invalid(Vec<u8>, Invalid),
+        }
+
+        /// the variants of `Op` without their data
+        #[derive(Clone, Copy)]
+        #[repr(u16)]
+        enum OpIndex {
+            $($op,)*
+            Invalid,
+        }
+
+        impl Op {
+            /// number of variants, the size of the instruction dispatch table
+            pub const COUNT: usize = OpIndex::Invalid as usize + 1;
+
+            /// returns the variant number, used to index the instruction dispatch table
+            pub fn index(&self) -> usize {
+                match self {
+                    $(Op::$op => OpIndex::$op as usize,)*
+                    Op::Invalid(..) => OpIndex::Invalid as usize,
+                }
+            }
+        }
+    };
+}
+
+ops!(
     /// ASCII Adjust After Addition
     Aaa,
 
@@ -25,14 +59,31 @@ pub enum Op {
     Bound,
 
     /// Bit Scan Forward
-    Bsf,
+    Bsf16, Bsf32,
+
+    /// Bit Scan Reverse
+    Bsr16, Bsr32,
+
+    /// Byte Swap (486)
+    Bswap32,
 
     /// Bit Test
-    Bt,
+    Bt16, Bt32,
+
+    /// Bit Test and Complement
+    Btc16, Btc32,
 
-    Bts,
+    /// Bit Test and Reset
+    Btr16, Btr32,
+
+    /// Bit Test and Set
+    Bts16, Bts32,
     CallNear, CallFar,
 
+    /// invokes high level handler `dst` of the emulator, the FE 38 imm16 opcode
+    /// placed in the BIOS by dustbox
+    Callback,
+
     /// Convert Byte to Word
     Cbw,
 
@@ -49,7 +100,10 @@ pub enum Op {
     Cmc,
 
     Cmp8, Cmp16, Cmp32,
-    Cmpsb, Cmpsw,
+    Cmpsb, Cmpsw, Cmpsd,
+
+    /// Compare and Exchange (486)
+    Cmpxchg8, Cmpxchg16, Cmpxchg32,
 
     /// Convert Word to Doubleword
     Cwd16, Cwde32,
@@ -75,7 +129,7 @@ pub enum Op {
     Inc8, Inc16, Inc32,
 
     /// Input from Port to String
-    Insb, Insw,
+    Insb, Insw, Insd,
 
     Int,
     Into,
@@ -146,6 +200,7 @@ pub enum Op {
     /// Load Effective Address
     /// Computes the effective address of the source operand and stores it in the destination operand.
     Lea16,
+    Lea32,
 
     Leave,
 
@@ -185,7 +240,7 @@ pub enum Op {
     Not8, Not16, Not32,
     Or8, Or16, Or32,
     Out8, Out16,
-    Outsb, Outsw,
+    Outsb, Outsw, Outsd,
     Pop16, Pop32,
 
     /// Pop DI, SI, BP, BX, DX, CX, and AX.
@@ -238,7 +293,11 @@ pub enum Op {
     /// Integer Subtraction with Borrow
     Sbb8, Sbb16, Sbb32,
 
-    Scasb, Scasw,
+    Scasb, Scasw, Scasd,
+
+    /// seta: Set byte if above (CF=0 and ZF=0).
+    /// alias setnbe: Set byte if not below or equal (CF=0 and ZF=0).
+    Seta,
 
     /// setc: Set byte if carry (CF=1).
     /// alias setb: Set byte if below (CF=1).
@@ -248,10 +307,58 @@ pub enum Op {
     /// alias setnle: Set byte if not less or equal (ZF=0 and SF=OF).
     Setg,
 
+    /// setl: Set byte if less (SF≠OF).
+    /// alias setnge: Set byte if not greater or equal (SF≠OF).
+    Setl,
+
+    /// setna: Set byte if not above (CF=1 or ZF=1).
+    /// alias setbe: Set byte if below or equal (CF=1 or ZF=1).
+    Setna,
+
+    /// setnc: Set byte if not carry (CF=0).
+    /// alias setnb: Set byte if not below (CF=0).
+    Setnc,
+
+    /// setng: Set byte if not greater (ZF=1 or SF≠OF).
+    /// alias setle: Set byte if less or equal (ZF=1 or SF≠OF).
+    Setng,
+
+    /// setnl: Set byte if not less (SF=OF).
+    /// alias setge: Set byte if greater or equal (SF=OF).
+    Setnl,
+
+    /// setno: Set byte if not overflow (OF=0).
+    Setno,
+
+    /// setns: Set byte if not sign (SF=0).
+    Setns,
+
     /// setnz: Set byte if not zero (ZF=0).
     /// alias setne: Set byte if not equal (ZF=0).
     Setnz,
 
+    /// seto: Set byte if overflow (OF=1).
+    Seto,
+
+    /// setpe: Set byte if parity even (PF=1).
+    /// alias setp: Set byte if parity (PF=1).
+    Setpe,
+
+    /// setpo: Set byte if parity odd (PF=0).
+    /// alias setnp: Set byte if not parity (PF=0).
+    Setpo,
+
+    /// sets: Set byte if sign (SF=1).
+    Sets,
+
+    /// setz: Set byte if zero (ZF=1).
+    /// alias sete: Set byte if equal (ZF=1).
+    Setz,
+
+    /// "setmo" sets `dst` to all ones if `src` is not zero, the undocumented
+    /// D0-D3 /6 opcodes of the 8086 (an alias of shl on later models)
+    Setmo8, Setmo16,
+
     /// Multiply `dst` by 2, `src` times (alias sal)
     Shl8,
     /// Multiply `dst` by 2, `src` times (alias sal)
@@ -269,6 +376,9 @@ pub enum Op {
 
     Sldt,
 
+    /// Store Machine Status Word
+    Smsw,
+
     // Set Carry Flag
     Stc,
 
@@ -282,6 +392,9 @@ pub enum Op {
     Sub8, Sub16, Sub32,
     Test8, Test16, Test32,
 
+    /// Exchange and Add (486)
+    Xadd8, Xadd16, Xadd32,
+
     /// Exchange Register/Memory with Register
     Xchg8, Xchg16, Xchg32,
 
@@ -393,10 +506,7 @@ pub enum Op {
 
     /// Initial state
     Uninitialized,
-
-    /// Invalid encoding. XXX also used for unhandled encodings atm
-    Invalid(Vec<u8>, Invalid),
-}
+);
 
 impl fmt::Display for Op {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -433,4 +543,8 @@ pub enum Invalid {
 
     /// unimplemented / invalid FPU instr
     FPUOp,
+
+    /// an opcode the emulated cpus lack, such as ud2, cpuid, rdtsc or cmovcc.
+    /// raises the invalid opcode exception, as they do
+    Unsupported,
 }
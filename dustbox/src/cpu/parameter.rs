@@ -40,6 +40,8 @@ pub enum Parameter {
     Reg32(R),
     /// 80-bit fpu register
     FPR80(R),
+    /// 32-bit control register
+    CReg32(R),
 
     Imm8(u8),                           // byte 0x80
     ImmS8(i8),                          // byte +0x3f
@@ -51,16 +53,19 @@ pub enum Parameter {
     Ptr8Amode(Segment, AMode),          // byte [amode], like "byte [bx]"
     Ptr8AmodeS8(Segment, AMode, i8),    // byte [amode+s8], like "byte [bp-0x20]"
     Ptr8AmodeS16(Segment, AMode, i16),  // byte [amode+s16], like "byte [bp-0x2020]"
+    Ptr8AmodeS32(Segment, AMode, i32),  // byte [amode+s32], like "byte [ebp-0x20202020]"
 
     Ptr16(Segment, u16),                // word [u16], like "word [0x4040]"
     Ptr16Amode(Segment, AMode),         // word [amode], like "word [bx]"
     Ptr16AmodeS8(Segment, AMode, i8),   // word [amode+s8], like "word [bp-0x20]"
     Ptr16AmodeS16(Segment, AMode, i16), // word [amode+s16], like "word [bp-0x2020]"
+    Ptr16AmodeS32(Segment, AMode, i32), // word [amode+s32], like "word [ebp-0x20202020]"
 
     Ptr32(Segment, u16),                // dword [u16], like "dword [0x4040]"
     Ptr32Amode(Segment, AMode),         // dword [amode], like "dword [bx]"
     Ptr32AmodeS8(Segment, AMode, i8),   // dword [amode+s8], like "dword [bp-0x20]"
     Ptr32AmodeS16(Segment, AMode, i16), // dword [amode+s16], like "dword [bp-0x2020]"
+    Ptr32AmodeS32(Segment, AMode, i32), // dword [amode+s32], like "dword [ebp-0x20202020]"
     None,
 }
 
@@ -71,7 +76,8 @@ impl fmt::Display for Parameter {
             Parameter::Reg16(ref r) |
             Parameter::Reg32(ref r) |
             Parameter::SReg16(ref r) |
-            Parameter::FPR80(ref r) => write!(f, "{}", r),
+            Parameter::FPR80(ref r) |
+            Parameter::CReg32(ref r) => write!(f, "{}", r),
 
             Parameter::Imm8(imm) => write!(f, "0x{:02X}", imm),
             Parameter::Imm16(imm) => write!(f, "0x{:04X}", imm),
@@ -113,6 +119,18 @@ impl fmt::Display for Parameter {
                     imm
                 }
             ),
+            Parameter::Ptr8AmodeS32(seg, ref amode, imm) => write!(
+                f,
+                "byte [{}:{}{}0x{:08X}]",
+                seg,
+                amode,
+                if imm < 0 { "-" } else { "+" },
+                if imm < 0 {
+                    (Wrapping(0) - Wrapping(imm)).0
+                } else {
+                    imm
+                }
+            ),
             Parameter::Ptr16(seg, v) => write!(f, "word [{}:0x{:04X}]", seg, v),
             Parameter::Ptr16Amode(seg, ref amode) => write!(f, "word [{}:{}]", seg, amode),
             Parameter::Ptr16AmodeS8(seg, ref amode, imm) => write!(
@@ -139,6 +157,18 @@ impl fmt::Display for Parameter {
                     imm
                 }
             ),
+            Parameter::Ptr16AmodeS32(seg, ref amode, imm) => write!(
+                f,
+                "word [{}:{}{}0x{:08X}]",
+                seg,
+                amode,
+                if imm < 0 { "-" } else { "+" },
+                if imm < 0 {
+                    (Wrapping(0) - Wrapping(imm)).0
+                } else {
+                    imm
+                }
+            ),
             Parameter::Ptr32(seg, v) => write!(f, "dword [{}:0x{:04X}]", seg, v),
             Parameter::Ptr32Amode(seg, ref amode) => write!(f, "dword [{}:{}]", seg, amode),
             Parameter::Ptr32AmodeS8(seg, ref amode, imm) => write!(
@@ -165,6 +195,18 @@ impl fmt::Display for Parameter {
                     imm
                 }
             ),
+            Parameter::Ptr32AmodeS32(seg, ref amode, imm) => write!(
+                f,
+                "dword [{}:{}{}0x{:08X}]",
+                seg,
+                amode,
+                if imm < 0 { "-" } else { "+" },
+                if imm < 0 {
+                    (Wrapping(0) - Wrapping(imm)).0
+                } else {
+                    imm
+                }
+            ),
             Parameter::None => write!(f, ""),
         }
     }
@@ -191,7 +233,14 @@ impl Parameter {
             Parameter::Ptr8AmodeS16(_, _, _) |
             Parameter::Ptr16Amode(_, _) |
             Parameter::Ptr16AmodeS8(_, _, _) |
-            Parameter::Ptr16AmodeS16(_, _, _) => true,
+            Parameter::Ptr16AmodeS16(_, _, _) |
+            Parameter::Ptr8AmodeS32(_, _, _) |
+            Parameter::Ptr16AmodeS32(_, _, _) |
+            Parameter::Ptr32(_, _) |
+            Parameter::Ptr32Amode(_, _) |
+            Parameter::Ptr32AmodeS8(_, _, _) |
+            Parameter::Ptr32AmodeS16(_, _, _) |
+            Parameter::Ptr32AmodeS32(_, _, _) => true,
             _ => false,
         }
     }
@@ -1,6 +1,8 @@
 use std::convert::From;
 use std::fmt;
 
+use log::error;
+
 use crate::cpu::flag::Flags;
 use crate::cpu::decoder::AddressSize;
 
@@ -58,6 +60,7 @@ pub enum R {
     IP,                                     // 16-bit ip
     EAX, ECX, EDX, EBX, ESP, EBP, ESI, EDI, // 32-bit gpr
     ST0, ST1, ST2, ST3, ST4, ST5, ST6, ST7, // 80-bit fpu registers
+    CR0,                                    // 32-bit control register
 }
 
 impl fmt::Display for R {
@@ -105,6 +108,8 @@ impl fmt::Display for R {
             R::ST5 => "st5",
             R::ST6 => "st6",
             R::ST7 => "st7",
+
+            R::CR0 => "cr0",
         };
         write!(f, "{}", s)
     }
@@ -113,14 +118,14 @@ impl fmt::Display for R {
 impl R {
     pub fn index(self) -> usize {
           match self {
-            R::AL | R::AX | R::EAX | R::ES => 0,
-            R::CL | R::CX | R::ECX | R::CS => 1,
-            R::DL | R::DX | R::EDX | R::SS => 2,
-            R::BL | R::BX | R::EBX | R::DS => 3,
-            R::AH | R::SP | R::ESP | R::FS => 4,
-            R::CH | R::BP | R::EBP | R::GS => 5,
-            R::DH | R::SI | R::ESI => 6,
-            R::BH | R::DI | R::EDI => 7,
+            R::AL | R::AX | R::EAX | R::ES | R::ST0 | R::CR0 => 0,
+            R::CL | R::CX | R::ECX | R::CS | R::ST1 => 1,
+            R::DL | R::DX | R::EDX | R::SS | R::ST2 => 2,
+            R::BL | R::BX | R::EBX | R::DS | R::ST3 => 3,
+            R::AH | R::SP | R::ESP | R::FS | R::ST4 => 4,
+            R::CH | R::BP | R::EBP | R::GS | R::ST5 => 5,
+            R::DH | R::SI | R::ESI | R::ST6 => 6,
+            R::BH | R::DI | R::EDI | R::ST7 => 7,
             _ => unreachable!(),
         }
     }
@@ -222,6 +227,9 @@ pub enum AMode {
 
     // 32-bit addressing modes
     EAX, ECX, EDX, EBX, ESP, EBP, ESI, EDI,
+
+    /// 32-bit addressing with SIB byte: optional base, optional index, scale (1, 2, 4 or 8)
+    SIB(Option<R>, Option<R>, u8),
 }
 
 impl fmt::Display for AMode {
@@ -244,6 +252,15 @@ impl fmt::Display for AMode {
             AMode::EBP => "ebp",
             AMode::ESI => "esi",
             AMode::EDI => "edi",
+            AMode::SIB(base, index, scale) => {
+                return match (base, index) {
+                    (Some(base), Some(index)) if *scale == 1 => write!(f, "{}+{}", base, index),
+                    (Some(base), Some(index)) => write!(f, "{}+{}*{}", base, index, scale),
+                    (Some(base), None) => write!(f, "{}", base),
+                    (None, Some(index)) => write!(f, "{}*{}", index, scale),
+                    (None, None) => Ok(()),
+                };
+            }
         };
         write!(f, "{}", s)
     }
@@ -260,8 +277,22 @@ impl AMode {
             AMode::DI | AMode::EBP => 5,
             AMode::BP | AMode::ESI => 6,
             AMode::BX | AMode::EDI => 7,
+            AMode::SIB(_, _, _) => 4,
         }
     }
+
+    /// returns true if the addressing mode defaults to the SS segment, which
+    /// is the case when BP, EBP or ESP is the base register
+    pub fn uses_stack_segment(&self) -> bool {
+        matches!(*self, AMode::BP | AMode::BPSI | AMode::BPDI | AMode::EBP | AMode::ESP |
+            AMode::SIB(Some(R::EBP), _, _) | AMode::SIB(Some(R::ESP), _, _))
+    }
+
+    /// returns true for the addressing modes that need 32-bit addressing
+    pub fn is_32bit(&self) -> bool {
+        !matches!(*self, AMode::BXSI | AMode::BXDI | AMode::BPSI | AMode::BPDI |
+            AMode::SI | AMode::DI | AMode::BP | AMode::BX)
+    }
 }
 
 impl AddressSize {
@@ -371,7 +402,7 @@ impl RegisterState {
             R::DS => self.sreg16[3] = val,
             R::FS => self.sreg16[4] = val,
             R::GS => self.sreg16[5] = val,
-            _ => println!("FATAL INVALID r16 value {}", r), //unreachable!(),
+            _ => error!(target: "cpu", "FATAL INVALID r16 value {}", r), //unreachable!(),
           }
     }
 
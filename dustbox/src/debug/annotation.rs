@@ -0,0 +1,309 @@
+use std::collections::BTreeMap;
+
+use crate::cpu::{InstructionInfo, Op, Parameter, R};
+use crate::debug::SymbolTable;
+use crate::memory::MemoryAddress;
+use crate::string::right_pad;
+
+#[cfg(test)]
+#[path = "./annotation_test.rs"]
+mod annotation_test;
+
+/// tracks the AH and AL values assigned by a linear sequence of instructions,
+/// in order to describe the services called by interrupts
+#[derive(Default)]
+pub struct InterruptAnnotator {
+    ah: Option<u8>,
+    al: Option<u8>,
+}
+
+impl InterruptAnnotator {
+    /// returns a description of the interrupt call in `ii`, and learns register assignments
+    pub fn annotate(&mut self, ii: &InstructionInfo) -> Option<String> {
+        let op = &ii.instruction;
+        match (&op.command, &op.params.dst, &op.params.src) {
+            (Op::Int, Parameter::Imm8(v), _) => {
+                let res = int_desc(*v, self.ah, self.al);
+                // the called service may return values in AX
+                self.forget();
+                return Some(res);
+            }
+            (Op::Mov8, Parameter::Reg8(R::AH), Parameter::Imm8(v)) => {
+                self.ah = Some(*v);
+                return None;
+            }
+            (Op::Mov8, Parameter::Reg8(R::AL), Parameter::Imm8(v)) => {
+                self.al = Some(*v);
+                return None;
+            }
+            (Op::Mov16, Parameter::Reg16(R::AX), Parameter::Imm16(v)) => {
+                self.ah = Some((v >> 8) as u8);
+                self.al = Some(*v as u8);
+                return None;
+            }
+            (Op::Xor16, Parameter::Reg16(R::AX), Parameter::Reg16(R::AX)) |
+            (Op::Sub16, Parameter::Reg16(R::AX), Parameter::Reg16(R::AX)) => {
+                self.ah = Some(0);
+                self.al = Some(0);
+                return None;
+            }
+            (Op::Xor8, Parameter::Reg8(R::AH), Parameter::Reg8(R::AH)) => {
+                self.ah = Some(0);
+                return None;
+            }
+            _ => {}
+        }
+        if changes_ax(ii) {
+            self.forget();
+        }
+        None
+    }
+
+    fn forget(&mut self) {
+        self.ah = None;
+        self.al = None;
+    }
+}
+
+/// returns true if `ii` might change AX, or leaves the linear instruction flow
+fn changes_ax(ii: &InstructionInfo) -> bool {
+    let op = &ii.instruction;
+    match op.command {
+        Op::Cmp8 | Op::Cmp16 | Op::Cmp32 | Op::Test8 | Op::Test16 | Op::Test32 => return false,
+        Op::Aaa | Op::Aad | Op::Aam | Op::Aas | Op::Daa | Op::Das | Op::Cbw | Op::Cwde32 |
+        Op::Mul8 | Op::Mul16 | Op::Mul32 | Op::Imul8 | Op::Imul16 | Op::Imul32 |
+        Op::Div8 | Op::Div16 | Op::Div32 | Op::Idiv8 | Op::Idiv16 | Op::Idiv32 |
+        Op::In8 | Op::In16 | Op::Lodsb | Op::Lodsw | Op::Lodsd | Op::Lahf | Op::Salc | Op::Xlatb |
+        Op::Popa16 | Op::Popad32 |
+        Op::CallNear | Op::CallFar | Op::Retn | Op::Retf | Op::RetImm16 | Op::Iret |
+        Op::JmpShort | Op::JmpNear | Op::JmpFar => return true,
+        _ => {}
+    }
+    let is_ax = |p: &Parameter| match *p {
+        Parameter::Reg8(r) => r == R::AL || r == R::AH,
+        Parameter::Reg16(r) => r == R::AX,
+        Parameter::Reg32(r) => r == R::EAX,
+        _ => false,
+    };
+    is_ax(&op.params.dst) || (is_ax(&op.params.src) && (op.command == Op::Xchg8 || op.command == Op::Xchg16 || op.command == Op::Xchg32))
+}
+
+/// returns a disassembly listing of `ops`, with interrupt calls described, known addresses named
+/// and `comments` shown by their flat address
+pub fn annotated_listing(ops: &[InstructionInfo], symbols: &SymbolTable, comments: &BTreeMap<u32, String>) -> String {
+    let mut annotator = InterruptAnnotator::default();
+    let mut lines = Vec::new();
+    for ii in ops {
+        let addr = MemoryAddress::RealSegmentOffset(ii.segment as u16, ii.offset as u16).value();
+        if let Some(name) = symbols.name_at(addr) {
+            lines.push(format!("{}:", name));
+        }
+        let text = symbols.instruction_info_text(ii);
+        // user comments take precedence over the interrupt descriptions
+        let note = annotator.annotate(ii);
+        match comments.get(&addr).or(note.as_ref()) {
+            Some(note) => lines.push(format!("{}; {}", right_pad(&text, 68), note)),
+            None => lines.push(text),
+        }
+    }
+    lines.join("\n")
+}
+
+/// describes the service called by interrupt `int`, using the AH and AL values if known
+pub fn int_desc(int: u8, ah: Option<u8>, al: Option<u8>) -> String {
+    let ah = match (int, ah) {
+        (0x20, _) => 0,
+        (0x33, _) => 0,
+        (_, Some(ah)) => ah,
+        (_, None) => return format!("{}: unknown function", int_category(int)),
+    };
+    let desc = match int {
+        0x10 => match ah {
+            0x00 => match al {
+                Some(al) => return format!("video: set {} mode (0x{:02X})", video_mode_desc(al), al),
+                None => "set video mode",
+            },
+            0x01 => "set text-mode cursor shape",
+            0x02 => "set cursor position",
+            0x03 => "get cursor position and size",
+            0x05 => "select active display page",
+            0x06 => "scroll up",
+            0x07 => "scroll down",
+            0x08 => "read character and attribute at cursor position",
+            0x09 => "write character and attribute at cursor position",
+            0x0A => "write character only at cursor position",
+            0x0B => "set background/border color",
+            0x0C => "write graphics pixel",
+            0x0D => "read graphics pixel",
+            0x0E => "teletype output",
+            0x0F => "get current video mode",
+            0x10 => match al {
+                Some(0x00) => "set single palette register",
+                Some(0x10) => "set individual DAC register",
+                Some(0x12) => "VIDEO - SET BLOCK OF DAC REGISTERS (VGA/MCGA)",
+                Some(0x15) => "read individual DAC register",
+                Some(0x17) => "read block of DAC registers",
+                _ => return format!("video: unrecognized AH = 10, AL = {}", hex_or_unknown(al)),
+            },
+            0x11 => "character generator",
+            0x12 => "alternate function select",
+            0x13 => "write string (row=DH, col=DL)",
+            0x1A => "get/set display combination code",
+            0x1C => match al {
+                Some(0x00) => "get video state buffer size",
+                Some(0x01) => "save video state",
+                Some(0x02) => "restore video state",
+                _ => return format!("video: unrecognized AH = 1C, AL = {}", hex_or_unknown(al)),
+            },
+            _ => "",
+        },
+        0x13 => match ah {
+            0x00 => "reset disk system",
+            0x01 => "get status of last operation",
+            0x02 => "read sectors into memory",
+            0x03 => "write disk sectors",
+            0x08 => "get drive parameters",
+            0x15 => "get disk type",
+            _ => "",
+        },
+        0x15 => match ah {
+            0x4F => "keyboard intercept",
+            0x86 => "wait",
+            0x88 => "get extended memory size",
+            0xC0 => "get configuration",
+            _ => "",
+        },
+        0x16 => match ah {
+            0x00 => "read scancode (blocking)",
+            0x01 => "read scancode (non-blocking)",
+            0x02 => "get shift flags",
+            0x10 => "get enhanced keystroke (blocking)",
+            0x11 => "check for enhanced keystroke (non-blocking)",
+            0x12 => "get extended shift states",
+            _ => "",
+        },
+        0x1A => match ah {
+            0x00 => "get system time",
+            0x01 => "set system time",
+            0x02 => "get real-time clock time",
+            0x03 => "set real-time clock time",
+            0x04 => "get real-time clock date",
+            0x05 => "set real-time clock date",
+            _ => "",
+        },
+        0x20 => "terminate program with return code 0",
+        0x21 => match ah {
+            0x01 => "read character from stdin, with echo",
+            0x02 => "write character in DL to stdout",
+            0x06 => "write character in DL to DIRECT CONSOLE OUTPUT",
+            0x07 => "direct character input, without echo",
+            0x08 => "read character from stdin, without echo",
+            0x09 => "write $-terminated string at DS:DX to stdout",
+            0x0A => "buffered input",
+            0x0B => "get stdin status",
+            0x0C => "flush buffer and read stdin",
+            0x0E => "select default drive",
+            0x19 => "get current default drive",
+            0x1A => "set disk transfer area address",
+            0x1B => "get allocation information for default drive",
+            0x1C => "get allocation information for specific drive",
+            0x25 => "set interrupt vector",
+            0x2A => "get system date",
+            0x2B => "set system date",
+            0x2C => "get system time",
+            0x2D => "set system time",
+            0x30 => "get dos version",
+            0x31 => "terminate and stay resident",
+            0x33 => "get/set ctrl-break checking",
+            0x35 => "get interrupt vector",
+            0x36 => "get free disk space",
+            0x39 => "create subdirectory",
+            0x3A => "remove subdirectory",
+            0x3B => "set current directory",
+            0x3C => "create or truncate file",
+            0x3D => "open file",
+            0x3E => "close file",
+            0x3F => "read from file or device",
+            0x40 => "write to file or device",
+            0x41 => "delete file",
+            0x42 => "set current file position",
+            0x43 => "get/set file attributes",
+            0x44 => "ioctl",
+            0x47 => "get current directory",
+            0x48 => "allocate memory",
+            0x49 => "free memory",
+            0x4A => "resize memory block",
+            0x4B => "load and/or execute program",
+            0x4C => "terminate program with return code in AL",
+            0x4D => "get return code",
+            0x4E => "find first matching file",
+            0x4F => "find next matching file",
+            0x56 => "rename file",
+            0x57 => "get/set file date and time",
+            0x62 => "get psp address",
+            0x71 => "long filename functions",
+            _ => "",
+        },
+        0x2F => match (ah, al) {
+            (0x43, Some(0x00)) => "XMS installation check",
+            (0x43, Some(0x10)) => "get XMS driver address",
+            _ => "",
+        },
+        0x33 => match al {
+            Some(0x00) => "reset driver and read status",
+            Some(0x01) => "show mouse cursor",
+            Some(0x02) => "hide mouse cursor",
+            Some(0x03) => "get position and button status",
+            Some(0x04) => "set mouse cursor position",
+            Some(0x07) => "set horizontal cursor range",
+            Some(0x08) => "set vertical cursor range",
+            Some(0x0C) => "define interrupt subroutine parameters",
+            _ => return format!("mouse: unrecognized AL = {}", hex_or_unknown(al)),
+        },
+        _ => return format!("XXX int_desc unrecognized {:02X}", int),
+    };
+    if desc.is_empty() {
+        format!("{}: unrecognized AH = {:02X}", int_category(int), ah)
+    } else {
+        format!("{}: {}", int_category(int), desc)
+    }
+}
+
+fn int_category(int: u8) -> &'static str {
+    match int {
+        0x10 => "video",
+        0x13 => "disk",
+        0x15 => "system",
+        0x16 => "keyboard",
+        0x1A => "pit",
+        0x20 | 0x21 => "dos",
+        0x2F => "multiplex",
+        0x33 => "mouse",
+        _ => "int",
+    }
+}
+
+fn hex_or_unknown(v: Option<u8>) -> String {
+    match v {
+        Some(v) => format!("{:02X}", v),
+        None => "??".to_owned(),
+    }
+}
+
+fn video_mode_desc(mode: u8) -> &'static str {
+    match mode {
+        0x00 => "40x25 text",
+        0x01 => "40x25x16 text",
+        0x02 => "80x25 text",
+        0x03 => "80x25x16 text",
+        0x04 => "320x200x4",
+        0x06 => "640x200x2",
+        0x0D => "320x200x16",
+        0x0E => "640x200x16",
+        0x10 => "640x350x16",
+        0x11 => "640x480x2",
+        0x12 => "640x480x16",
+        0x13 => "320x200x256",
+        _ => "unrecognized"
+    }
+}
@@ -0,0 +1,29 @@
+use std::collections::BTreeMap;
+
+use crate::debug::{annotated_listing, SymbolTable};
+use crate::machine::Machine;
+
+#[test]
+fn can_annotate_interrupt_calls() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB8, 0x00, 0x3D,   // mov ax,0x3d00
+        0xBA, 0x00, 0x02,   // mov dx,0x200
+        0xCD, 0x21,         // int 0x21
+        0xCD, 0x21,         // int 0x21
+        0xB4, 0x0E,         // mov ah,0xe
+        0xCD, 0x10,         // int 0x10
+        0x31, 0xC0,         // xor ax,ax
+        0xCD, 0x16,         // int 0x16
+    ];
+    machine.load_executable(&code, 0x085F);
+    let ops = machine.cpu.decoder.decode_to_block(&mut machine.mmu, 0x085F, 0x100, 8);
+    assert_eq!("[085F:0100] B8003D           Mov16    ax, 0x3D00
+[085F:0103] BA0002           Mov16    dx, 0x0200
+[085F:0106] CD21             Int      0x21                          ; dos: open file
+[085F:0108] CD21             Int      0x21                          ; dos: unknown function
+[085F:010A] B40E             Mov8     ah, 0x0E
+[085F:010C] CD10             Int      0x10                          ; video: teletype output
+[085F:010E] 31C0             Xor16    ax, ax
+[085F:0110] CD16             Int      0x16                          ; keyboard: read scancode (blocking)", annotated_listing(&ops, &SymbolTable::default(), &BTreeMap::new()));
+}
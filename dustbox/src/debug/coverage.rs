@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::ops::Range;
+
+#[cfg(test)]
+#[path = "./coverage_test.rs"]
+mod coverage_test;
+
+/// max length of a drcov basic block
+const MAX_BLOCK_SIZE: u32 = 0xFFFF;
+
+/// a memory range reported as a module in drcov output, such as the loaded program
+pub struct CoverageModule {
+    pub name: String,
+    pub base: u32,
+    pub end: u32,
+}
+
+/// records the executed instructions, for code coverage reports
+#[derive(Default)]
+pub struct Coverage {
+    /// length of executed instructions, by flat address
+    instructions: BTreeMap<u32, u8>,
+}
+
+impl Coverage {
+    /// marks the `length` bytes at flat address `addr` as an executed instruction
+    pub fn record(&mut self, addr: u32, length: u8) {
+        self.instructions.insert(addr, length);
+    }
+
+    /// returns the number of distinct executed instructions
+    pub fn instruction_count(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// returns the executed address ranges in ascending order, with adjacent instructions
+    /// merged into basic blocks
+    pub fn blocks(&self) -> Vec<Range<u32>> {
+        let mut res: Vec<Range<u32>> = Vec::new();
+        for (addr, len) in &self.instructions {
+            let end = addr + u32::from(*len);
+            match res.last_mut() {
+                Some(last) if last.end >= *addr && end - last.start <= MAX_BLOCK_SIZE => last.end = last.end.max(end),
+                _ => res.push(*addr..end),
+            }
+        }
+        res
+    }
+
+    /// returns one byte per byte in `range`, set to 1 if it was executed
+    pub fn byte_map(&self, range: Range<u32>) -> Vec<u8> {
+        let mut map = vec![0u8; (range.end - range.start) as usize];
+        for block in self.blocks() {
+            for addr in block.start.max(range.start)..block.end.min(range.end) {
+                map[(addr - range.start) as usize] = 1;
+            }
+        }
+        map
+    }
+
+    /// writes the basic blocks in drcov format, as read by the IDA and Ghidra coverage plugins.
+    /// block offsets are relative to the base of the first module holding them,
+    /// blocks outside of all modules are left out
+    pub fn write_drcov<W: Write>(&self, w: &mut W, modules: &[CoverageModule]) -> io::Result<()> {
+        let mut entries = Vec::new();
+        for block in self.blocks() {
+            if let Some(id) = modules.iter().position(|m| block.start >= m.base && block.start < m.end) {
+                entries.push((block.start - modules[id].base, block.end - block.start, id as u16));
+            }
+        }
+        writeln!(w, "DRCOV VERSION: 2")?;
+        writeln!(w, "DRCOV FLAVOR: dustbox")?;
+        writeln!(w, "Module Table: version 2, count {}", modules.len())?;
+        writeln!(w, "Columns: id, base, end, entry, checksum, timestamp, path")?;
+        for (id, m) in modules.iter().enumerate() {
+            writeln!(w, "{:3}, 0x{:016x}, 0x{:016x}, 0x{:016x}, 0x{:08x}, 0x{:08x}, {}", id, m.base, m.end, 0, 0, 0, m.name)?;
+        }
+        writeln!(w, "BB Table: {} bbs", entries.len())?;
+        for (start, size, id) in entries {
+            w.write_all(&start.to_le_bytes())?;
+            w.write_all(&(size as u16).to_le_bytes())?;
+            w.write_all(&id.to_le_bytes())?;
+        }
+        Ok(())
+    }
+}
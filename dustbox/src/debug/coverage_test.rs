@@ -0,0 +1,65 @@
+use std::fs;
+
+use tempfile::tempdir;
+
+use crate::debug::{Coverage, CoverageModule};
+use crate::machine::Machine;
+
+#[test]
+fn can_merge_coverage_blocks() {
+    let mut coverage = Coverage::default();
+    coverage.record(0x1000, 3);
+    coverage.record(0x1003, 2);
+    coverage.record(0x1003, 2);
+    coverage.record(0x1010, 1);
+    assert_eq!(3, coverage.instruction_count());
+    assert_eq!(vec![0x1000..0x1005, 0x1010..0x1011], coverage.blocks());
+    assert_eq!(vec![0, 1, 1, 1, 1, 1, 0, 0], coverage.byte_map(0x0FFF..0x1007));
+}
+
+#[test]
+fn can_write_drcov() {
+    let mut coverage = Coverage::default();
+    coverage.record(0x8700, 2);
+    coverage.record(0xF0021, 1);
+    let modules = [
+        CoverageModule{name: "PROG.COM".to_owned(), base: 0x85F0, end: 0x8710},
+        CoverageModule{name: "memory".to_owned(), base: 0, end: 0x10_0000},
+    ];
+    let mut out = Vec::new();
+    coverage.write_drcov(&mut out, &modules).unwrap();
+
+    let header = "DRCOV VERSION: 2
+DRCOV FLAVOR: dustbox
+Module Table: version 2, count 2
+Columns: id, base, end, entry, checksum, timestamp, path
+  0, 0x00000000000085f0, 0x0000000000008710, 0x0000000000000000, 0x00000000, 0x00000000, PROG.COM
+  1, 0x0000000000000000, 0x0000000000100000, 0x0000000000000000, 0x00000000, 0x00000000, memory
+BB Table: 2 bbs
+";
+    assert_eq!(header.as_bytes(), &out[..header.len()]);
+    assert_eq!(vec![
+        0x10, 0x01, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00,
+        0x21, 0x00, 0x0F, 0x00, 0x01, 0x00, 0x01, 0x00,
+    ], &out[header.len()..]);
+}
+
+#[test]
+fn can_record_program_coverage() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("out.cov");
+
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xEB, 0x01,         // jmp short 0x103
+        0x90,               // nop
+        0xB4, 0x4C,         // mov ah,0x4C
+        0xCD, 0x21,         // int 0x21
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.enable_coverage();
+    machine.execute_instructions(3);
+
+    machine.save_coverage(path.to_str().unwrap()).unwrap();
+    assert_eq!(vec![1, 1, 0, 1, 1, 1, 1], fs::read(&path).unwrap());
+}
@@ -1,13 +1,17 @@
+use std::collections::BTreeMap;
+use std::fs;
 use std::time::Instant;
 use std::num::ParseIntError;
-use std::io::Error as IoError;
+use std::io::{self, Error as IoError};
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
 use crate::machine::Machine;
-use crate::cpu::{R, RegisterState, Decoder};
+use crate::cpu::{R, RegisterState, Decoder, assemble_at};
 use crate::memory::MemoryAddress;
-use crate::debug::{Breakpoints, MemoryBreakpoints};
+use crate::debug::{annotated_listing, program_hash, project_path, AddressText, Breakpoints, IoBreakpoint, MemoryAccess, MemoryBreakpoints, Project, StackGuardAction, Watchpoint};
 use crate::string::parse_number_string;
+use crate::tools::read_binary;
 
 #[cfg(test)]
 #[path = "./debugger_test.rs"]
@@ -21,19 +25,40 @@ pub struct Debugger {
     /// break when IP reach these addresses
     ip_breakpoints: Breakpoints,
 
-    /// break when memory change on these addresses
+    /// break when these memory ranges are accessed
     memory_breakpoints: MemoryBreakpoints,
+
+    /// CS:IP of the last executed instruction
+    last_ip: (u16, u16),
+
+    /// path and hash of the loaded program
+    program: Option<(String, String)>,
+
+    /// user comments by flat address
+    comments: BTreeMap<u32, String>,
+
+    /// user assigned symbol names by flat address
+    renames: BTreeMap<u32, String>,
 }
 
 impl Debugger {
     pub fn default() -> Self {
-        let machine = Machine::default();
+        let mut machine = Machine::default();
+        machine.break_on_int3 = true;
+        #[cfg(feature = "jit")]
+        {
+            machine.jit.disabled = true;
+        }
         Debugger {
             prev_regs: machine.register_snapshot(),
             machine,
             last_program: None,
             ip_breakpoints: Breakpoints::default(),
             memory_breakpoints: MemoryBreakpoints::default(),
+            last_ip: (0, 0),
+            program: None,
+            comments: BTreeMap::new(),
+            renames: BTreeMap::new(),
         }
     }
 
@@ -43,33 +68,79 @@ impl Debugger {
     }
 
     fn should_break(&mut self) -> bool {
+        for w in self.machine.mmu.take_code_writes() {
+            println!(
+                "Warning: code at {:06X} was modified from {:02X} to {:02X}, ip = {:04X}:{:04X}",
+                w.addr,
+                w.old,
+                w.new,
+                self.machine.cpu.get_r16(R::CS),
+                self.machine.cpu.regs.ip
+            );
+        }
         if self.machine.cpu.fatal_error {
             return true;
         }
         if self.is_ip_at_breakpoint() {
+            let name = match self.machine.symbols.describe(self.machine.cpu.get_address()) {
+                Some(name) => format!(" ({})", name),
+                None => String::new(),
+            };
             println!(
-                "Breakpoint reached, ip = {:04X}:{:04X}",
+                "Breakpoint reached, ip = {:04X}:{:04X}{}",
                 self.machine.cpu.get_r16(R::CS),
-                self.machine.cpu.regs.ip
+                self.machine.cpu.regs.ip,
+                name
             );
             return true;
         }
-        for addr in self.memory_breakpoints.get() {
-            let val = self.machine.mmu.memory.read_u8(addr);
-            if self.memory_breakpoints.has_changed(addr, val) {
-                println!("Value at memory breakpoint has changed. {:06X} = {:02X}", addr, val);
-                return true;
+        let io_hits = self.machine.io_breakpoints.take_hits();
+        for hit in &io_hits {
+            println!("I/O breakpoint: {}, by instruction at {:04X}:{:04X}", hit, self.last_ip.0, self.last_ip.1);
+        }
+        if !io_hits.is_empty() {
+            return true;
+        }
+        let hits = self.memory_breakpoints.check(&self.machine.mmu, self.machine.cpu.get_address());
+        for hit in &hits {
+            match hit.access {
+                MemoryAccess::Execute => println!(
+                    "Memory breakpoint {} reached, ip = {:04X}:{:04X}",
+                    hit.watchpoint,
+                    self.machine.cpu.get_r16(R::CS),
+                    self.machine.cpu.regs.ip
+                ),
+                _ => println!(
+                    "Memory breakpoint {}: {} at {:06X}, value {:X} -> {:X}, by instruction at {:04X}:{:04X}",
+                    hit.watchpoint,
+                    hit.access,
+                    hit.addr,
+                    hit.old,
+                    hit.new,
+                    self.last_ip.0,
+                    self.last_ip.1
+                ),
             }
         }
-        false
+        !hits.is_empty()
+    }
+
+    /// executes one instruction, tracking the accesses to watched memory
+    fn execute_instruction(&mut self) {
+        self.last_ip = (self.machine.cpu.get_r16(R::CS), self.machine.cpu.regs.ip);
+        self.memory_breakpoints.before_instruction(&self.machine.mmu);
+        self.machine.execute_instruction();
     }
 
-    pub fn step_into(&mut self, cnt: usize) {
+    /// executes up to `cnt` instructions, returns true if stopped at a breakpoint or fatal error
+    pub fn step_into(&mut self, cnt: usize) -> bool {
         let start = Instant::now();
         let mut done = 0;
+        let mut stopped = false;
         for _ in 0..cnt {
-            self.machine.execute_instruction();
+            self.execute_instruction();
             if self.should_break() {
+                stopped = true;
                 break;
             }
             done += 1;
@@ -82,6 +153,24 @@ impl Debugger {
             done,
             ms
         );
+        stopped
+    }
+
+    /// executes instructions until the next vertical retrace like `Machine::execute_frame`,
+    /// returns true if stopped at a breakpoint or fatal error
+    pub fn run_frame(&mut self) -> bool {
+        let budget = self.machine.cpu.clock_hz / self.machine.gpu().refresh_rate.max(1);
+        let frame_count = self.machine.gpu().frame_count;
+        let start = self.machine.cpu.cycle_count;
+        loop {
+            self.execute_instruction();
+            if self.should_break() {
+                return true;
+            }
+            if self.machine.gpu().frame_count != frame_count || self.machine.cpu.cycle_count.wrapping_sub(start) >= budget {
+                return false;
+            }
+        }
     }
 
     pub fn step_over(&mut self) {
@@ -94,7 +183,7 @@ impl Debugger {
         let mut cnt = 0;
         loop {
             cnt += 1;
-            self.machine.execute_instruction();
+            self.execute_instruction();
             if self.should_break() {
                 break;
             }
@@ -113,26 +202,22 @@ impl Debugger {
 
     pub fn disasm_n_instructions_to_text(&mut self, n: usize) -> String {
         let mut decoder = Decoder::default();
-        decoder.disassemble_block_to_str(&mut self.machine.mmu, self.machine.cpu.get_r16(R::CS), self.machine.cpu.regs.ip, n)
+        let ops = decoder.decode_to_block(&mut self.machine.mmu, self.machine.cpu.get_r16(R::CS), self.machine.cpu.regs.ip, n);
+        annotated_listing(&ops, &self.machine.symbols, &self.comments)
     }
 
-    pub fn dump_memory(&self, filename: &str, base: u32, len: u32) -> Result<usize, IoError> {
-        use std::path::Path;
-        use std::fs::File;
-        use std::io::Write;
-
-        let path = Path::new(filename);
-        let mut file = match File::create(&path) {
-            Err(why) => return Err(why),
-            Ok(file) => file,
-        };
+    /// writes `len` bytes of memory from `seg:off` to `filename`, returns the number of bytes written
+    pub fn dump_memory(&self, filename: &str, seg: u16, off: u16, len: usize) -> Result<usize, IoError> {
+        let data = self.machine.dump_memory(seg, off, len);
+        fs::write(filename, &data)?;
+        Ok(data.len())
+    }
 
-        let base = base as usize;
-        let len = len as usize;
-        if let Err(why) = file.write(&self.machine.mmu.memory.data[base..base + len]) {
-            return Err(why);
-        }
-        Ok(0)
+    /// writes the video memory of the current mode to `filename`, returns the number of bytes written
+    pub fn dump_video_memory(&self, filename: &str) -> Result<usize, IoError> {
+        let data = self.machine.dump_video_memory();
+        fs::write(filename, &data)?;
+        Ok(data.len())
     }
 
     pub fn exec_command(&mut self, cmd: &str) {
@@ -150,18 +235,36 @@ impl Debugger {
                 println!("reset                            - resets the cpu");
                 println!("instcount                        - show number of instructions executed");
                 println!("reg                              - show register values");
-                println!("bp add <seg:off>                 - add breakpoint");
-                println!("bp remove <seg:off>              - remove breakpoint");
+                println!("symbols <file>                   - load symbols from a .map or .toml file");
+                println!("symbols                          - show symbols");
+                println!("rename <seg:off|symbol> <name>   - name an address");
+                println!("comment add <seg:off> <text>     - add comment to the disassembly");
+                println!("comment remove <seg:off>         - remove comment");
+                println!("comment list                     - show comments");
+                println!("project save [file]              - save breakpoints, comments and names of the program");
+                println!("project load [file]              - load a saved project of the program");
+                println!("bp add <seg:off|symbol>          - add breakpoint");
+                println!("bp remove <seg:off|symbol>       - remove breakpoint");
                 println!("bp list                          - show breakpoints");
                 println!("bp clear                         - clear breakpoints");
-                println!("membp add <seg:off>              - add memory breakpoint");
+                println!("membp add <seg:off> [len] [rwx] [old->new] - add memory breakpoint");
                 println!("membp remove <seg:off>           - remove memory breakpoint");
                 println!("membp list                       - show memory breakpoints");
                 println!("membp clear                      - clear memory breakpoints");
+                println!("portbp add <port> [rw]           - add breakpoint on reads and/or writes to i/o port");
+                println!("portbp remove <port>             - remove i/o port breakpoint");
+                println!("intbp add <int> [ah] [al]        - add breakpoint on interrupt, optionally for function AH/AL");
+                println!("intbp remove <int>               - remove interrupt breakpoint");
+                println!("portbp|intbp list                - show i/o and interrupt breakpoints");
+                println!("portbp|intbp clear               - clear i/o and interrupt breakpoints");
+                println!("int3 on|off                      - stop on INT 3 not hooked by the program (default on)");
+                println!("stackguard on [depth]|off        - stop on stack underflow, overflow past depth bytes or collision with the program");
                 println!("flat                             - show current address as flat value");
                 println!("disasm                           - disasm instruction");
+                println!("asm <seg:off> <instruction>      - assemble instruction to memory");
                 println!("hexdump <seg:off> <len>          - dumps len bytes of memory at given offset to the console");
                 println!("bindump <seg:off> <len> <file>   - writes memory dump to file");
+                println!("viddump <file>                   - writes video memory of the current mode to file");
                 println!("exit                             - exit");
             }
             "step" => {
@@ -209,8 +312,8 @@ impl Debugger {
                     match parts[1] {
                         "help" => {
                             println!("Available breakpoint commands:");
-                            println!("  bp add <seg:off>     add breakpoint");
-                            println!("  bp remove <seg:off>  remove breakpoint");
+                            println!("  bp add <seg:off>     add breakpoint, at an address or symbol");
+                            println!("  bp remove <seg:off>  remove breakpoint, at an address or symbol");
                             println!("  bp clear             clears all breakpoints");
                             println!("  bp list              list all breakpoints");
                         }
@@ -258,28 +361,35 @@ impl Debugger {
                     match parts[1] {
                         "help" => {
                             println!("Available memory breakpoint commands:");
-                            println!("  membp add <seg:off>     add breakpoint");
-                            println!("  membp remove <seg:off>  remove breakpoint");
-                            println!("  membp clear             clears all breakpoints");
-                            println!("  membp list              list all breakpoints");
+                            println!("  membp add <seg:off> [len] [rwx] [old->new]  add breakpoint on len bytes (default 1), for");
+                            println!("                                              reads, writes and/or execution (default w).");
+                            println!("                                              old and new are hex values or *");
+                            println!("  membp remove <seg:off>                      remove breakpoint");
+                            println!("  membp clear                                 clears all breakpoints");
+                            println!("  membp list                                  list all breakpoints");
                         }
                         "add" | "set" => {
-                            match self.parse_segment_offset_pair(&parts[2]) {
-                                Ok(bp) => {
-                                    if self.memory_breakpoints.add(bp).is_some() {
-                                        println!("Breakpoint added: {:06X}", bp);
+                            match self.parse_watchpoint(&parts[2..]) {
+                                Ok(wp) => {
+                                    let desc = format!("{}", wp);
+                                    if self.memory_breakpoints.add(wp).is_some() {
+                                        self.memory_breakpoints.install(&mut self.machine.mmu);
+                                        println!("Memory breakpoint added: {}", desc);
                                     } else {
                                         println!("Breakpoint was already added");
                                     }
                                 }
-                                Err(e) => println!("parse error: {:?}", e),
+                                Err(e) => println!("parse error: {}", e),
                             }
                         }
                         "del" | "delete" | "remove" => {
                             match self.parse_segment_offset_pair(&parts[2]) {
                                 Ok(bp) => {
                                     match self.memory_breakpoints.remove(bp) {
-                                        Some(_) => println!("Memory breakpoint removed: {:06X}", bp),
+                                        Some(_) => {
+                                            self.memory_breakpoints.install(&mut self.machine.mmu);
+                                            println!("Memory breakpoint removed: {:06X}", bp);
+                                        }
                                         None => println!("Breakpoint not found, so not removed!"),
                                     }
                                 }
@@ -288,18 +398,95 @@ impl Debugger {
                         }
                         "clear" => {
                             self.memory_breakpoints.clear();
+                            self.memory_breakpoints.install(&mut self.machine.mmu);
                         }
                         "list" => {
                             let list = self.memory_breakpoints.get();
                             let strs: Vec<String> =
-                                list.iter().map(|b| format!("{:06X}", b)).collect();
-                            let formatted_list = strs.join(" ");
+                                list.iter().map(|b| format!("{}", b)).collect();
+                            let formatted_list = strs.join(", ");
                             println!("Memory breakpoints: {}", formatted_list);
                         }
                         _ => println!("unknown breakpoint subcommand: {}", parts[1]),
                     }
                 }
             }
+            "portbp" | "intbp" => {
+                if parts.len() < 2 {
+                    println!("{}: not enough arguments", parts[0]);
+                } else {
+                    match parts[1] {
+                        "add" | "set" => {
+                            match self.parse_io_breakpoint(parts[0], &parts[2..]) {
+                                Ok(bp) => {
+                                    let desc = format!("{}", bp);
+                                    if self.machine.io_breakpoints.add(bp) {
+                                        println!("Breakpoint added: {}", desc);
+                                    } else {
+                                        println!("Breakpoint was already added");
+                                    }
+                                }
+                                Err(e) => println!("parse error: {}", e),
+                            }
+                        }
+                        "del" | "delete" | "remove" => {
+                            match parts.get(2).map(|s| self.parse_register_hex_string(s)) {
+                                Some(Ok(v)) => {
+                                    let removed = if parts[0] == "portbp" {
+                                        self.machine.io_breakpoints.remove_port(v as u16)
+                                    } else {
+                                        self.machine.io_breakpoints.remove_interrupt(v as u8)
+                                    };
+                                    println!("Removed {} breakpoints", removed);
+                                }
+                                Some(Err(e)) => println!("parse error: {:?}", e),
+                                None => println!("{}: not enough arguments", parts[0]),
+                            }
+                        }
+                        "clear" => {
+                            self.machine.io_breakpoints.clear();
+                        }
+                        "list" => {
+                            let strs: Vec<String> = self.machine.io_breakpoints.get().iter().map(|b| format!("{}", b)).collect();
+                            println!("I/O breakpoints: {}", strs.join(", "));
+                        }
+                        _ => println!("unknown breakpoint subcommand: {}", parts[1]),
+                    }
+                }
+            }
+            "int3" => {
+                match parts.get(1) {
+                    Some(&"on") => self.machine.break_on_int3 = true,
+                    Some(&"off") => self.machine.break_on_int3 = false,
+                    _ => {}
+                }
+                println!("Stop on INT 3: {}", if self.machine.break_on_int3 { "on" } else { "off" });
+            }
+            "stackguard" => {
+                let config = &mut self.machine.stack_guard.config;
+                match parts.get(1) {
+                    Some(&"on") => {
+                        config.enabled = true;
+                        config.action = StackGuardAction::Break;
+                        if let Some(depth) = parts.get(2) {
+                            match parse_number_string(depth) {
+                                Ok(v) => config.max_depth = Some(v as u16),
+                                Err(e) => println!("parse error: {:?}", e),
+                            }
+                        }
+                    }
+                    Some(&"off") => config.enabled = false,
+                    _ => {}
+                }
+                let depth = match config.max_depth {
+                    Some(depth) => format!(", max depth {} bytes", depth),
+                    None => String::new(),
+                };
+                println!("Stack guard: {}{}", if config.enabled { "on" } else { "off" }, depth);
+                if let Some((ss, top)) = self.machine.stack_guard.base() {
+                    println!("Top of stack: {:04X}:{:04X}", ss, top);
+                }
+            }
             "flat" => {
                 self.show_flat_address();
             }
@@ -307,7 +494,28 @@ impl Debugger {
                 let mut decoder = Decoder::default();
                 let op = decoder.get_instruction_info(&mut self.machine.mmu, self.machine.cpu.get_r16(R::CS), self.machine.cpu.regs.ip);
                 println!("{:?}", op);
-                println!("{}", op);
+                println!("{}", self.machine.symbols.instruction_info_text(&op));
+            }
+            "a" | "asm" => {
+                // asm <seg:off> <instruction>
+                if parts.len() < 3 {
+                    println!("asm: not enough arguments");
+                    return;
+                }
+                let (segment, offset) = match self.parse_segment_offset(parts[1]) {
+                    Ok(p) => p,
+                    Err(e) => {
+                        println!("parse error: {:?}", e);
+                        return;
+                    }
+                };
+                match assemble_at(&parts[2..].join(" "), offset) {
+                    Ok(code) => {
+                        self.machine.mmu.write(segment, offset, &code);
+                        println!("Assembled {} bytes to {:04X}:{:04X}", code.len(), segment, offset);
+                    }
+                    Err(e) => println!("asm error: {}", e),
+                }
             }
             "load" => {
                 if parts.len() < 2 {
@@ -321,6 +529,87 @@ impl Debugger {
                     self.last_program = Option::Some(path);
                 }
             }
+            "symbols" => {
+                if parts.len() < 2 {
+                    for (addr, name) in self.machine.symbols.iter() {
+                        println!("{:06X} {}", addr, name);
+                    }
+                } else {
+                    let path = parts[1..].join(" ").trim().to_string();
+                    match self.machine.load_symbols(&path) {
+                        Ok(n) => {
+                            self.apply_renames();
+                            println!("Loaded {} symbols from {}", n, path)
+                        }
+                        Err(e) => println!("Failed to load symbols from {}: {}", path, e),
+                    }
+                }
+            }
+            "rename" => {
+                // rename <seg:off|symbol> <name>
+                if parts.len() < 3 {
+                    println!("rename: not enough arguments");
+                    return;
+                }
+                match self.parse_segment_offset_pair(parts[1]) {
+                    Ok(addr) => {
+                        self.renames.insert(addr, parts[2].to_owned());
+                        self.machine.symbols.rename(addr, parts[2]);
+                        println!("Renamed {:06X} to {}", addr, parts[2]);
+                    }
+                    Err(e) => println!("parse error: {:?}", e),
+                }
+            }
+            "comment" => {
+                if parts.len() < 2 {
+                    println!("comment: not enough arguments");
+                    return;
+                }
+                match parts[1] {
+                    "add" | "set" if parts.len() > 3 => {
+                        match self.parse_segment_offset_pair(parts[2]) {
+                            Ok(addr) => {
+                                self.comments.insert(addr, parts[3..].join(" "));
+                                println!("Comment added: {:06X}", addr);
+                            }
+                            Err(e) => println!("parse error: {:?}", e),
+                        }
+                    }
+                    "del" | "delete" | "remove" if parts.len() > 2 => {
+                        match self.parse_segment_offset_pair(parts[2]) {
+                            Ok(addr) => match self.comments.remove(&addr) {
+                                Some(_) => println!("Comment removed: {:06X}", addr),
+                                None => println!("Comment not found, so not removed!"),
+                            },
+                            Err(e) => println!("parse error: {:?}", e),
+                        }
+                    }
+                    "list" => {
+                        for (addr, text) in &self.comments {
+                            println!("{:06X} {}", addr, text);
+                        }
+                    }
+                    _ => println!("unknown comment subcommand: {}", cmd),
+                }
+            }
+            "project" => {
+                let path = if parts.len() > 2 {
+                    Some(parts[2..].join(" "))
+                } else {
+                    None
+                };
+                match parts.get(1) {
+                    Some(&"save") => match self.save_project(path.as_deref()) {
+                        Ok(path) => println!("Saved project to {}", path.display()),
+                        Err(e) => println!("Failed to save project: {}", e),
+                    },
+                    Some(&"load") => match self.load_project(path.as_deref()) {
+                        Ok(path) => println!("Loaded project from {}", path.display()),
+                        Err(e) => println!("Failed to load project: {}", e),
+                    },
+                    _ => println!("project: expected save or load"),
+                }
+            }
             "hexdump" => {
                 // show dump of memory at <seg:off> <length>
                 if parts.len() < 3 {
@@ -367,27 +656,36 @@ impl Debugger {
                     return;
                 }
 
-                let pos: u32;
-                let length: u32;
-
-                match self.parse_segment_offset_pair(&parts[1]) {
-                    Ok(p) => pos = p,
+                let (seg, off) = match self.parse_segment_offset(&parts[1]) {
+                    Ok(p) => p,
                     Err(e) => {
                         println!("parse error: {:?}", e);
                         return;
                     }
-                }
-                match parse_number_string(&parts[2]) {
-                    Ok(n) => length = n,
+                };
+                let length = match parse_number_string(&parts[2]) {
+                    Ok(n) => n as usize,
                     Err(e) => {
                         println!("length parse error: {}", e);
                         return;
                     }
-                }
+                };
                 let filename = parts[3].trim();
-                println!("Writing memory dump {}, len {:04X} to {}", parts[1], length, filename);
-                if let Err(why) = self.dump_memory(filename, pos, length) {
-                    println!("Dump memory failed: {}", why);
+                match self.dump_memory(filename, seg, off, length) {
+                    Ok(n) => println!("Wrote {:04X} bytes from {:04X}:{:04X} to {}", n, seg, off, filename),
+                    Err(why) => println!("Dump memory failed: {}", why),
+                }
+            }
+            "viddump" => {
+                // viddump <file>
+                if parts.len() < 2 {
+                    println!("viddump: not enough arguments");
+                    return;
+                }
+                let filename = parts[1].trim();
+                match self.dump_video_memory(filename) {
+                    Ok(n) => println!("Wrote {:04X} bytes of video memory to {}", n, filename),
+                    Err(why) => println!("Dump video memory failed: {}", why),
                 }
             }
             "r" | "run" => {
@@ -400,12 +698,94 @@ impl Debugger {
         }
     }
 
-    /// Loads a .com or .exe file
+    /// Loads a .com or .exe file, and the saved project of the program if there is one
     pub fn load_executable(&mut self, filename: &str) {
         self.machine.hard_reset();
         if let Some(e) = self.machine.load_executable_file(filename) {
             panic!("error {}", e);
         };
+        self.program = match read_binary(filename) {
+            Ok(data) => Some((filename.to_owned(), program_hash(&data))),
+            Err(_) => None,
+        };
+        if let Some((_, hash)) = &self.program {
+            let path = project_path(filename, hash);
+            if path.exists() {
+                match self.load_project(None) {
+                    Ok(path) => println!("Loaded project from {}", path.display()),
+                    Err(e) => println!("Failed to load project {}: {}", path.display(), e),
+                }
+            }
+        }
+    }
+
+    /// returns the breakpoints, comments and symbol names of the loaded program
+    pub fn project(&self) -> Project {
+        let hash = match &self.program {
+            Some((_, hash)) => hash.as_str(),
+            None => "",
+        };
+        let mut project = Project::new(hash);
+        project.breakpoints = self.ip_breakpoints.get();
+        project.watchpoints = self.memory_breakpoints.get();
+        project.io_breakpoints = self.machine.io_breakpoints.get().to_vec();
+        project.comments = self.comments.iter().map(|(addr, text)| AddressText{addr: *addr, text: text.clone()}).collect();
+        project.symbols = self.renames.iter().map(|(addr, text)| AddressText{addr: *addr, text: text.clone()}).collect();
+        project
+    }
+
+    /// replaces the breakpoints, comments and symbol names with the ones of `project`
+    pub fn apply_project(&mut self, project: Project) {
+        self.ip_breakpoints.clear();
+        for bp in project.breakpoints {
+            self.ip_breakpoints.add(bp);
+        }
+        self.memory_breakpoints.clear();
+        for wp in project.watchpoints {
+            self.memory_breakpoints.add(wp);
+        }
+        self.memory_breakpoints.install(&mut self.machine.mmu);
+        self.machine.io_breakpoints.clear();
+        for bp in project.io_breakpoints {
+            self.machine.io_breakpoints.add(bp);
+        }
+        self.comments = project.comments.into_iter().map(|c| (c.addr, c.text)).collect();
+        self.renames = project.symbols.into_iter().map(|c| (c.addr, c.text)).collect();
+        self.apply_renames();
+    }
+
+    /// saves the project of the loaded program to `path`, or to the default project file
+    pub fn save_project(&self, path: Option<&str>) -> io::Result<PathBuf> {
+        let path = self.project_file(path)?;
+        self.project().save(&path)?;
+        Ok(path)
+    }
+
+    /// loads the project of the loaded program from `path`, or from the default project file
+    pub fn load_project(&mut self, path: Option<&str>) -> io::Result<PathBuf> {
+        let path = self.project_file(path)?;
+        let project = Project::load(&path)?;
+        if let Some((_, hash)) = &self.program {
+            if project.hash != *hash {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("project is for program {}, loaded program is {}", project.hash, hash)));
+            }
+        }
+        self.apply_project(project);
+        Ok(path)
+    }
+
+    fn project_file(&self, path: Option<&str>) -> io::Result<PathBuf> {
+        match (path, &self.program) {
+            (Some(path), _) => Ok(Path::new(path).to_path_buf()),
+            (None, Some((program, hash))) => Ok(project_path(program, hash)),
+            (None, None) => Err(io::Error::new(io::ErrorKind::NotFound, "no program loaded")),
+        }
+    }
+
+    fn apply_renames(&mut self) {
+        for (addr, name) in &self.renames {
+            self.machine.symbols.rename(*addr, name);
+        }
     }
 
     fn show_flat_address(&mut self) {
@@ -422,6 +802,9 @@ impl Debugger {
 
     /// parses segment:offset pair to an integer
     fn parse_segment_offset_pair(&self, s: &str) -> Result<u32, ParseIntError> {
+        if let Some(addr) = self.machine.symbols.lookup(s) {
+            return Ok(addr);
+        }
         let x = &s.replace("_", "");
         match x.find(':') {
             Some(pos) => {
@@ -445,6 +828,84 @@ impl Debugger {
         }
     }
 
+    /// parses segment:offset pair, or a flat address or symbol relative to CS
+    pub fn parse_segment_offset(&self, s: &str) -> Result<(u16, u16), ParseIntError> {
+        let x = &s.replace("_", "");
+        if self.machine.symbols.lookup(s).is_none() {
+            if let Some(pos) = x.find(':') {
+                let segment = self.parse_register_hex_string(&x[0..pos])?;
+                let offset = self.parse_register_hex_string(&x[pos+1..])?;
+                return Ok((segment as u16, offset as u16));
+            }
+        }
+        let addr = self.parse_segment_offset_pair(s)?;
+        let cs = self.machine.cpu.get_r16(R::CS);
+        Ok((cs, addr.wrapping_sub(u32::from(cs) << 4) as u16))
+    }
+
+    /// parses the arguments of "membp add": <seg:off> [len] [rwx] [old->new]
+    fn parse_watchpoint(&self, args: &[&str]) -> Result<Watchpoint, String> {
+        if args.is_empty() {
+            return Err("address not provided".to_owned());
+        }
+        let addr = self.parse_segment_offset_pair(args[0]).map_err(|e| format!("{}: {}", args[0], e))?;
+        let mut wp = Watchpoint::new(addr, 1);
+        for arg in &args[1..] {
+            if let Some(pos) = arg.find("->") {
+                wp.old = self.parse_watch_value(&arg[..pos])?;
+                wp.new = self.parse_watch_value(&arg[pos + 2..])?;
+            } else if !wp.set_access(arg) {
+                match self.parse_register_hex_string(arg) {
+                    Ok(len) if len > 0 => wp.length = len as u32,
+                    _ => return Err(format!("unrecognized argument {}", arg)),
+                }
+            }
+        }
+        Ok(wp)
+    }
+
+    /// parses the arguments of "portbp add": <port> [rw], or "intbp add": <int> [ah] [al]
+    fn parse_io_breakpoint(&self, cmd: &str, args: &[&str]) -> Result<IoBreakpoint, String> {
+        let values: Vec<Result<usize, String>> = args.iter()
+            .map(|s| self.parse_register_hex_string(s).map_err(|e| format!("{}: {}", s, e)))
+            .collect();
+        if cmd == "portbp" {
+            let port = match values.first() {
+                Some(v) => v.clone()? as u16,
+                None => return Err("port not provided".to_owned()),
+            };
+            let access = args.get(1).cloned().unwrap_or("rw");
+            if access.is_empty() || !access.chars().all(|c| c == 'r' || c == 'w') {
+                return Err(format!("unrecognized access {}", access));
+            }
+            Ok(IoBreakpoint::Port { port, read: access.contains('r'), write: access.contains('w') })
+        } else {
+            let int = match values.first() {
+                Some(v) => v.clone()? as u8,
+                None => return Err("interrupt not provided".to_owned()),
+            };
+            let ah = match values.get(1) {
+                Some(v) => Some(v.clone()? as u8),
+                None => None,
+            };
+            let al = match values.get(2) {
+                Some(v) => Some(v.clone()? as u8),
+                None => None,
+            };
+            Ok(IoBreakpoint::Interrupt { int, ah, al })
+        }
+    }
+
+    fn parse_watch_value(&self, s: &str) -> Result<Option<u32>, String> {
+        if s == "*" {
+            return Ok(None);
+        }
+        match self.parse_register_hex_string(s) {
+            Ok(v) => Ok(Some(v as u32)),
+            Err(e) => Err(format!("{}: {}", s, e)),
+        }
+    }
+
     /// parses hex string or register name to a integer
     fn parse_register_hex_string(&self, s: &str) -> Result<usize, ParseIntError> {
         let x = &s.replace("_", "");
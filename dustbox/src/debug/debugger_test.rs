@@ -1,3 +1,7 @@
+use std::fs;
+
+use tempfile::tempdir;
+
 use crate::debug::Debugger;
 use crate::cpu::R;
 
@@ -22,6 +26,109 @@ fn test_parse_segment_offset_pair() {
     assert_eq!(0x873F, dbg.parse_segment_offset_pair("873F").unwrap());
 }
 
+#[test]
+fn test_parse_symbol_address() {
+    let mut dbg = Debugger::default();
+    dbg.machine.symbols.insert(0x8731, "main");
+    assert_eq!(0x8731, dbg.parse_segment_offset_pair("main").unwrap());
+    assert!(dbg.parse_segment_offset_pair("missing").is_err());
+}
+
+
+#[test]
+fn test_memory_breakpoint_stops_after_write() {
+    let code: Vec<u8> = vec![
+        0xB8, 0x34, 0x12,   // mov ax,0x1234
+        0xA3, 0x00, 0x02,   // mov [0x200],ax
+        0x90,               // nop
+        0x90,               // nop
+    ];
+    let mut dbg = Debugger::default();
+    dbg.machine.load_executable(&code, 0x085F);
+    dbg.exec_command("membp add 085F:0201 1 w *->12");
+    dbg.step_into(10);
+    assert_eq!(0x0106, dbg.machine.cpu.regs.ip);
+
+    // condition is not met
+    let mut dbg = Debugger::default();
+    dbg.machine.load_executable(&code, 0x085F);
+    dbg.exec_command("membp add 085F:0200 2 w 0->1");
+    dbg.step_into(3);
+    assert_eq!(0x0107, dbg.machine.cpu.regs.ip);
+}
+
+#[test]
+fn test_io_breakpoints() {
+    let code: Vec<u8> = vec![
+        0xE4, 0x61,         // in al,0x61
+        0xB4, 0x30,         // mov ah,0x30
+        0xCD, 0x21,         // int 0x21
+        0x90,               // nop
+    ];
+    let mut dbg = Debugger::default();
+    dbg.machine.load_executable(&code, 0x085F);
+    dbg.exec_command("portbp add 61 r");
+    dbg.exec_command("intbp add 21 30");
+    dbg.step_into(10);
+    assert_eq!(0x0102, dbg.machine.cpu.regs.ip);
+
+    dbg.step_into(10);
+    // breaks when the interrupt was handled
+    assert_eq!(0x0106, dbg.machine.cpu.regs.ip);
+}
+
+#[test]
+fn test_int3_stops_execution() {
+    let code: Vec<u8> = vec![
+        0xCC,               // int3
+        0x90,               // nop
+        0x90,               // nop
+    ];
+    let mut dbg = Debugger::default();
+    dbg.machine.load_executable(&code, 0x085F);
+    assert!(dbg.step_into(10));
+    // stopped after returning from the default handler
+    assert_eq!(0x0101, dbg.machine.cpu.regs.ip);
+
+    let mut dbg = Debugger::default();
+    dbg.machine.load_executable(&code, 0x085F);
+    dbg.exec_command("int3 off");
+    dbg.step_into(3);
+    assert_eq!(0x0102, dbg.machine.cpu.regs.ip);
+}
+
+#[test]
+fn test_asm_command() {
+    let code: Vec<u8> = vec![
+        0x90,               // nop
+        0x90,               // nop
+    ];
+    let mut dbg = Debugger::default();
+    dbg.machine.load_executable(&code, 0x085F);
+    dbg.exec_command("asm CS:0100 mov ax, 0x1234");
+    dbg.exec_command("asm 085F:0103 jmp 0x0100");
+    assert_eq!(vec![0xB8, 0x34, 0x12, 0xEB, 0xFB], dbg.machine.mmu.read(0x085F, 0x0100, 5));
+    dbg.step_into(2);
+    assert_eq!(0x1234, dbg.machine.cpu.get_r16(R::AX));
+    assert_eq!(0x0100, dbg.machine.cpu.regs.ip);
+}
+
+#[test]
+fn test_dump_commands() {
+    let dir = tempdir().unwrap();
+    let code: Vec<u8> = vec![
+        0xB8, 0x34, 0x12,   // mov ax,0x1234
+    ];
+    let mut dbg = Debugger::default();
+    dbg.machine.load_executable(&code, 0x085F);
+    let bin = dir.path().join("code.bin");
+    dbg.exec_command(&format!("bindump CS:0100 3 {}", bin.to_str().unwrap()));
+    assert_eq!(code, fs::read(&bin).unwrap());
+
+    let vid = dir.path().join("video.bin");
+    dbg.exec_command(&format!("viddump {}", vid.to_str().unwrap()));
+    assert_eq!(0x2_0000, fs::read(&vid).unwrap().len());
+}
 
 #[test]
 fn test_dis_toml_file() {
@@ -52,3 +159,52 @@ fn test_dis_toml_file() {
     println!("{:#?}", decoded);
 
 }
+
+#[test]
+fn test_project_survives_restart() {
+    let dir = tempdir().unwrap();
+    let program = dir.path().join("prog.com");
+    fs::write(&program, [
+        0xB8, 0x34, 0x12,   // mov ax,0x1234
+        0xCD, 0x20,         // int 0x20
+    ]).unwrap();
+    let program = program.to_str().unwrap();
+
+    let mut dbg = Debugger::default();
+    dbg.load_executable(program);
+    dbg.exec_command("bp add CS:0103");
+    dbg.exec_command("portbp add 3C9 w");
+    dbg.exec_command("comment add CS:0100 set up the counter");
+    dbg.exec_command("rename CS:0103 quit");
+    dbg.exec_command("project save");
+
+    let mut dbg = Debugger::default();
+    dbg.load_executable(program);
+    assert_eq!(dbg.project().breakpoints.len(), 1);
+    assert_eq!(dbg.project().io_breakpoints.len(), 1);
+    let text = dbg.disasm_n_instructions_to_text(2);
+    assert!(text.contains("; set up the counter"), "{}", text);
+    assert!(text.contains("quit:"), "{}", text);
+
+    dbg.step_into(5);
+    assert_eq!(0x0103, dbg.machine.cpu.regs.ip);
+}
+
+#[test]
+fn test_stack_guard_command() {
+    let code: Vec<u8> = vec![
+        0x50,               // push ax
+        0x50,               // push ax
+        0x50,               // push ax
+        0x90,               // nop
+    ];
+    let mut dbg = Debugger::default();
+    dbg.machine.load_executable(&code, 0x085F);
+    dbg.exec_command("stackguard on 4");
+    assert!(dbg.step_into(10));
+    // 6 bytes in use, including the return address of the .com program
+    assert_eq!(0x0102, dbg.machine.cpu.regs.ip);
+
+    dbg.exec_command("stackguard off");
+    assert!(!dbg.machine.stack_guard.config.enabled);
+}
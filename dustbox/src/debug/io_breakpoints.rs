@@ -0,0 +1,152 @@
+use std::fmt;
+use std::mem;
+
+use crate::debug::int_desc;
+
+#[cfg(test)]
+#[path = "./io_breakpoints_test.rs"]
+mod io_breakpoints_test;
+
+/// a breakpoint on i/o port accesses or interrupt calls
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum IoBreakpoint {
+    /// reads (in) and/or writes (out) to `port`
+    Port { port: u16, read: bool, write: bool },
+
+    /// calls to interrupt `int`, optionally only for function AH and sub-function AL
+    Interrupt { int: u8, ah: Option<u8>, al: Option<u8> },
+}
+
+impl fmt::Display for IoBreakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IoBreakpoint::Port { port, read, write } => write!(
+                f,
+                "port {:04X} {}{}",
+                port,
+                if read { "r" } else { "-" },
+                if write { "w" } else { "-" }
+            ),
+            IoBreakpoint::Interrupt { int, ah, al } => {
+                write!(f, "int {:02X}", int)?;
+                if let Some(ah) = ah {
+                    write!(f, " AH={:02X}", ah)?;
+                }
+                if let Some(al) = al {
+                    write!(f, " AL={:02X}", al)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// a triggered i/o port or interrupt breakpoint
+#[derive(Clone, Debug, PartialEq)]
+pub enum IoBreak {
+    PortRead { port: u16, data: u8 },
+    PortWrite { port: u16, data: u8 },
+    Interrupt { int: u8, ax: u16 },
+}
+
+impl fmt::Display for IoBreak {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IoBreak::PortRead { port, data } => write!(f, "in {:04X} = {:02X}", port, data),
+            IoBreak::PortWrite { port, data } => write!(f, "out {:04X} = {:02X}", port, data),
+            IoBreak::Interrupt { int, ax } => write!(
+                f,
+                "int {:02X}, AX={:04X} ({})",
+                int,
+                ax,
+                int_desc(int, Some((ax >> 8) as u8), Some(ax as u8))
+            ),
+        }
+    }
+}
+
+/// breakpoints checked by the machine on i/o port accesses and interrupt calls
+#[derive(Default)]
+pub struct IoBreakpoints {
+    breakpoints: Vec<IoBreakpoint>,
+
+    /// triggered breakpoints since last call to `take_hits`
+    hits: Vec<IoBreak>,
+}
+
+impl IoBreakpoints {
+    pub fn add(&mut self, bp: IoBreakpoint) -> bool {
+        if self.breakpoints.contains(&bp) {
+            return false;
+        }
+        self.breakpoints.push(bp);
+        true
+    }
+
+    /// removes the breakpoints on `port`, returning the number removed
+    pub fn remove_port(&mut self, port: u16) -> usize {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|bp| match *bp {
+            IoBreakpoint::Port { port: p, .. } => p != port,
+            _ => true,
+        });
+        len - self.breakpoints.len()
+    }
+
+    /// removes the breakpoints on interrupt `int`, returning the number removed
+    pub fn remove_interrupt(&mut self, int: u8) -> usize {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|bp| match *bp {
+            IoBreakpoint::Interrupt { int: i, .. } => i != int,
+            _ => true,
+        });
+        len - self.breakpoints.len()
+    }
+
+    pub fn get(&self) -> &[IoBreakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.breakpoints.is_empty()
+    }
+
+    /// returns the triggered breakpoints since last call
+    pub fn take_hits(&mut self) -> Vec<IoBreak> {
+        mem::take(&mut self.hits)
+    }
+
+    /// checks a read of `data` from `port`
+    pub fn port_read(&mut self, port: u16, data: u8) {
+        if self.breakpoints.iter().any(|bp| matches!(*bp, IoBreakpoint::Port { port: p, read: true, .. } if p == port)) {
+            self.hits.push(IoBreak::PortRead { port, data });
+        }
+    }
+
+    /// checks a write of `data` to `port`
+    pub fn port_write(&mut self, port: u16, data: u8) {
+        if self.breakpoints.iter().any(|bp| matches!(*bp, IoBreakpoint::Port { port: p, write: true, .. } if p == port)) {
+            self.hits.push(IoBreak::PortWrite { port, data });
+        }
+    }
+
+    /// checks a call to interrupt `int` with register AX = `ax`
+    pub fn interrupt(&mut self, int: u8, ax: u16) {
+        let ah = (ax >> 8) as u8;
+        let al = ax as u8;
+        let hit = self.breakpoints.iter().any(|bp| match *bp {
+            IoBreakpoint::Interrupt { int: i, ah: bp_ah, al: bp_al } => {
+                i == int && bp_ah.is_none_or(|v| v == ah) && bp_al.is_none_or(|v| v == al)
+            }
+            _ => false,
+        });
+        if hit {
+            self.hits.push(IoBreak::Interrupt { int, ax });
+        }
+    }
+}
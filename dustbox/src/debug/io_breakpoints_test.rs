@@ -0,0 +1,40 @@
+use crate::debug::io_breakpoints::{IoBreak, IoBreakpoint, IoBreakpoints};
+
+#[test]
+fn io_breakpoints_match_port_direction() {
+    let mut bps = IoBreakpoints::default();
+    bps.add(IoBreakpoint::Port { port: 0x60, read: true, write: false });
+    bps.add(IoBreakpoint::Port { port: 0x3C9, read: false, write: true });
+
+    bps.port_read(0x60, 0x1C);
+    bps.port_write(0x60, 0xF4);
+    bps.port_read(0x3C9, 0x3F);
+    bps.port_write(0x3C9, 0x10);
+    assert_eq!(vec![
+        IoBreak::PortRead { port: 0x60, data: 0x1C },
+        IoBreak::PortWrite { port: 0x3C9, data: 0x10 },
+    ], bps.take_hits());
+    assert_eq!(0, bps.take_hits().len());
+
+    assert_eq!(1, bps.remove_port(0x60));
+    bps.port_read(0x60, 0x1C);
+    assert_eq!(0, bps.take_hits().len());
+}
+
+#[test]
+fn io_breakpoints_match_interrupt_function() {
+    let mut bps = IoBreakpoints::default();
+    bps.add(IoBreakpoint::Interrupt { int: 0x21, ah: Some(0x3D), al: None });
+    bps.add(IoBreakpoint::Interrupt { int: 0x10, ah: None, al: None });
+
+    bps.interrupt(0x21, 0x3C00);
+    bps.interrupt(0x21, 0x3D02);
+    bps.interrupt(0x10, 0x0013);
+    bps.interrupt(0x16, 0x0000);
+    let hits = bps.take_hits();
+    assert_eq!(vec![
+        IoBreak::Interrupt { int: 0x21, ax: 0x3D02 },
+        IoBreak::Interrupt { int: 0x10, ax: 0x0013 },
+    ], hits);
+    assert_eq!("int 21, AX=3D02 (dos: open file)", format!("{}", hits[0]));
+}
@@ -1,62 +1,235 @@
-use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use crate::memory::{MemoryWatcher, MMU};
 
 #[cfg(test)]
 #[path = "./memory_breakpoints_test.rs"]
 mod memory_breakpoints_test;
 
+/// max number of accesses logged during one instruction
+const MAX_LOGGED_ACCESSES: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MemoryAccess {
+    Read,
+    Write,
+    Execute,
+}
+
+impl fmt::Display for MemoryAccess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MemoryAccess::Read => write!(f, "read"),
+            MemoryAccess::Write => write!(f, "write"),
+            MemoryAccess::Execute => write!(f, "execute"),
+        }
+    }
+}
+
+/// a watched memory range
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Watchpoint {
+    pub addr: u32,
+
+    /// number of watched bytes. the watched value is the first 1, 2 or 4 of them
+    pub length: u32,
+
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+
+    /// if set, writes only trigger when the value was `old` before the instruction
+    pub old: Option<u32>,
+
+    /// if set, writes only trigger when the value is `new` after the instruction
+    pub new: Option<u32>,
+}
+
+impl Watchpoint {
+    /// returns a watchpoint triggered by writes to `length` bytes at `addr`
+    pub fn new(addr: u32, length: u32) -> Self {
+        Watchpoint {
+            addr,
+            length: length.max(1),
+            read: false,
+            write: true,
+            execute: false,
+            old: None,
+            new: None,
+        }
+    }
+
+    /// sets the triggering accesses from a combination of "r", "w" and "x"
+    pub fn set_access(&mut self, s: &str) -> bool {
+        if s.is_empty() || !s.chars().all(|c| c == 'r' || c == 'w' || c == 'x') {
+            return false;
+        }
+        self.read = s.contains('r');
+        self.write = s.contains('w');
+        self.execute = s.contains('x');
+        true
+    }
+
+    pub fn contains(&self, addr: u32) -> bool {
+        addr >= self.addr && addr < self.addr + self.length
+    }
+
+    /// returns the little-endian value of the watched bytes
+    pub fn value(&self, mmu: &MMU) -> u32 {
+        let size = match self.length {
+            1 => 1,
+            2 | 3 => 2,
+            _ => 4,
+        };
+        // bulk reads are not seen by the watchers
+        mmu.memory.read(self.addr, size).iter().rev().fold(0, |acc, b| acc << 8 | u32::from(*b))
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:06X} len {} {}{}{}",
+            self.addr,
+            self.length,
+            if self.read { "r" } else { "-" },
+            if self.write { "w" } else { "-" },
+            if self.execute { "x" } else { "-" }
+        )?;
+        if self.old.is_some() || self.new.is_some() {
+            let show = |v: Option<u32>| match v {
+                Some(v) => format!("{:X}", v),
+                None => "*".to_owned(),
+            };
+            write!(f, " {}->{}", show(self.old), show(self.new))?;
+        }
+        Ok(())
+    }
+}
+
+/// a triggered watchpoint
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchHit {
+    pub watchpoint: Watchpoint,
+    pub access: MemoryAccess,
+
+    /// the accessed address
+    pub addr: u32,
+
+    /// the watched value before and after the instruction
+    pub old: u32,
+    pub new: u32,
+}
+
+/// the addresses accessed by the current instruction
+#[derive(Default)]
+struct AccessLog {
+    reads: Vec<u32>,
+    writes: Vec<u32>,
+}
+
+impl MemoryWatcher for AccessLog {
+    fn read_u8(&mut self, addr: u32) {
+        if self.reads.len() < MAX_LOGGED_ACCESSES {
+            self.reads.push(addr);
+        }
+    }
+
+    fn write_u8(&mut self, addr: u32, _data: u8) {
+        if self.writes.len() < MAX_LOGGED_ACCESSES {
+            self.writes.push(addr);
+        }
+    }
+}
+
+/// a list of memory ranges for the debugger to break on when accessed.
+/// accesses are observed through the MMU, so they are seen regardless of the instruction
 #[derive(Default)]
 pub struct MemoryBreakpoints {
-    breakpoints: Vec<u32>,
+    watchpoints: Vec<Watchpoint>,
+
+    /// watched values before the current instruction
+    values: Vec<u32>,
 
-    /// tracks previous memory values to find changes
-    map: HashMap<u32, u8>,
+    log: Arc<Mutex<AccessLog>>,
 }
 
-/// a list of addresses for the debugger to break on when memory content changes
 impl MemoryBreakpoints {
-     pub fn default() -> Self {
-        MemoryBreakpoints {
-            breakpoints: vec![0; 0],
-            map: HashMap::new(),
+    pub fn add(&mut self, wp: Watchpoint) -> Option<u32> {
+        if self.watchpoints.contains(&wp) {
+            return None;
         }
+        let addr = wp.addr;
+        self.watchpoints.push(wp);
+        Some(addr)
     }
 
-    pub fn add(&mut self, bp: u32) -> Option<u32> {
-        if self.breakpoints.iter().find(|&&x|x == bp).is_none() {
-            self.breakpoints.push(bp);
-            Some(bp)
-        } else {
+    /// removes the watchpoints starting at `addr`
+    pub fn remove(&mut self, addr: u32) -> Option<u32> {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|wp| wp.addr != addr);
+        if self.watchpoints.len() == len {
             None
+        } else {
+            Some(addr)
         }
     }
 
-    pub fn remove(&mut self, bp: u32) -> Option<u32> {
-        // TODO later: simplify when https://github.com/rust-lang/rust/issues/40062 is stable
-        match self.breakpoints.iter().position(|x| *x == bp) {
-            Some(pos) => {
-                self.breakpoints.remove(pos);
-                Some(bp)
-            },
-            None => None,
-        }
-    }
-
-    /// returns a Vec with breakpoints sorted ascending
-    pub fn get(&self) -> Vec<u32> {
-        let mut sorted = self.breakpoints.clone();
-        sorted.sort();
+    /// returns a Vec with watchpoints sorted by ascending address
+    pub fn get(&self) -> Vec<Watchpoint> {
+        let mut sorted = self.watchpoints.clone();
+        sorted.sort_by_key(|wp| wp.addr);
         sorted
     }
 
     pub fn clear(&mut self) {
-        self.breakpoints.clear();
+        self.watchpoints.clear();
     }
 
-    /// returns true if memory value has changed since last check
-    pub fn has_changed(&mut self, address: u32, val: u8) -> bool {
-        let t = self.map.entry(address).or_insert(val);
-        let old = *t;
-        *t = val;
-        old != val
+    /// registers the watched ranges with `mmu`, replacing any previous watches
+    pub fn install(&self, mmu: &mut MMU) {
+        mmu.clear_watches();
+        for wp in &self.watchpoints {
+            if wp.read || wp.write {
+                mmu.register_watch(wp.addr..=wp.addr + wp.length - 1, self.log.clone());
+            }
+        }
+    }
+
+    /// remembers the watched values before executing an instruction
+    pub fn before_instruction(&mut self, mmu: &MMU) {
+        let mut log = self.log.lock().unwrap();
+        log.reads.clear();
+        log.writes.clear();
+        self.values = self.watchpoints.iter().map(|wp| wp.value(mmu)).collect();
+    }
+
+    /// returns the watchpoints triggered by the executed instruction, or by reaching `ip` (flat address)
+    pub fn check(&mut self, mmu: &MMU, ip: u32) -> Vec<WatchHit> {
+        let log = mem::take(&mut *self.log.lock().unwrap());
+        let mut hits = Vec::new();
+        for (i, wp) in self.watchpoints.iter().enumerate() {
+            let new = wp.value(mmu);
+            let old = self.values.get(i).cloned().unwrap_or(new);
+            let mut hit = |access, addr| hits.push(WatchHit{watchpoint: wp.clone(), access, addr, old, new});
+
+            if wp.execute && wp.contains(ip) {
+                hit(MemoryAccess::Execute, ip);
+            }
+            if wp.read {
+                if let Some(addr) = log.reads.iter().find(|addr| wp.contains(**addr)) {
+                    hit(MemoryAccess::Read, *addr);
+                }
+            }
+            if wp.write && wp.old.is_none_or(|v| v == old) && wp.new.is_none_or(|v| v == new) {
+                if let Some(addr) = log.writes.iter().find(|addr| wp.contains(**addr)) {
+                    hit(MemoryAccess::Write, *addr);
+                }
+            }
+        }
+        hits
     }
 }
@@ -1,21 +1,83 @@
-use crate::debug::memory_breakpoints::MemoryBreakpoints;
+use crate::debug::memory_breakpoints::{MemoryAccess, MemoryBreakpoints, Watchpoint};
+use crate::memory::MMU;
 
 #[test]
 fn sorted_memory_breakpoints() {
     let mut bps = MemoryBreakpoints::default();
-    bps.add(3);
-    bps.add(1);
-    bps.add(2);
+    bps.add(Watchpoint::new(3, 1));
+    bps.add(Watchpoint::new(1, 1));
+    bps.add(Watchpoint::new(2, 1));
 
-    assert_eq!(vec![1,2,3], bps.get());
+    let addrs: Vec<u32> = bps.get().iter().map(|wp| wp.addr).collect();
+    assert_eq!(vec![1,2,3], addrs);
 }
 
 #[test]
-fn memory_breakpoints_has_changed() {
+fn memory_breakpoints_observe_writes() {
+    let mut mmu = MMU::default();
     let mut bps = MemoryBreakpoints::default();
+    bps.add(Watchpoint::new(0x1234, 2));
+    bps.install(&mut mmu);
 
-    assert_eq!(false, bps.has_changed(0x1234, 1));
-    assert_eq!(false, bps.has_changed(0x1234, 1));
-    assert_eq!(true, bps.has_changed(0x1234, 2));
-    assert_eq!(false, bps.has_changed(0x1234, 2));
+    bps.before_instruction(&mmu);
+    mmu.memory.write_u8(0x1236, 1);
+    assert_eq!(0, bps.check(&mmu, 0).len());
+
+    bps.before_instruction(&mmu);
+    mmu.memory.write_u16(0x1234, 0x1122);
+    let hits = bps.check(&mmu, 0);
+    assert_eq!(1, hits.len());
+    assert_eq!(MemoryAccess::Write, hits[0].access);
+    assert_eq!(0x1234, hits[0].addr);
+    assert_eq!(0x0000, hits[0].old);
+    assert_eq!(0x1122, hits[0].new);
+
+    // bulk writes are observed too
+    bps.before_instruction(&mmu);
+    mmu.memory.write(0x1230, &[0; 8]);
+    assert_eq!(1, bps.check(&mmu, 0).len());
+}
+
+#[test]
+fn memory_breakpoints_value_conditions() {
+    let mut mmu = MMU::default();
+    let mut bps = MemoryBreakpoints::default();
+    let mut wp = Watchpoint::new(0x2000, 1);
+    wp.old = Some(0x01);
+    wp.new = Some(0x02);
+    bps.add(wp);
+    bps.install(&mut mmu);
+
+    for (val, expected) in &[(0x01, 0), (0x03, 0), (0x01, 0), (0x02, 1), (0x02, 0)] {
+        bps.before_instruction(&mmu);
+        mmu.memory.write_u8(0x2000, *val);
+        assert_eq!(*expected, bps.check(&mmu, 0).len());
+    }
+}
+
+#[test]
+fn memory_breakpoints_observe_reads_and_execution() {
+    let mut mmu = MMU::default();
+    let mut bps = MemoryBreakpoints::default();
+    let mut wp = Watchpoint::new(0x3000, 0x10);
+    assert_eq!(true, wp.set_access("rx"));
+    assert_eq!(false, wp.set_access("q"));
+    bps.add(wp);
+    bps.install(&mut mmu);
+
+    bps.before_instruction(&mmu);
+    mmu.memory.write_u8(0x3004, 0xFF);
+    assert_eq!(0, bps.check(&mmu, 0).len());
+
+    bps.before_instruction(&mmu);
+    mmu.memory.read_u8(0x300F);
+    let hits = bps.check(&mmu, 0);
+    assert_eq!(1, hits.len());
+    assert_eq!(MemoryAccess::Read, hits[0].access);
+    assert_eq!(0x300F, hits[0].addr);
+
+    bps.before_instruction(&mmu);
+    let hits = bps.check(&mmu, 0x3008);
+    assert_eq!(1, hits.len());
+    assert_eq!(MemoryAccess::Execute, hits[0].access);
 }
@@ -0,0 +1,116 @@
+use crate::memory::MMU;
+
+#[cfg(test)]
+#[path = "./memory_view_test.rs"]
+mod memory_view_test;
+
+/// number of bytes shown on each row of the hex view
+pub const BYTES_PER_ROW: usize = 16;
+
+/// a window of memory shown in a hex view, tracking the bytes changed since the last step
+pub struct MemoryView {
+    pub segment: u16,
+    pub offset: u16,
+    rows: usize,
+
+    /// bytes as of the last refresh
+    data: Vec<u8>,
+
+    /// bytes as of the last call to `mark_step`
+    prev: Vec<u8>,
+}
+
+impl MemoryView {
+    pub fn new(segment: u16, offset: u16, rows: usize) -> Self {
+        MemoryView {
+            segment,
+            offset,
+            rows,
+            data: Vec::new(),
+            prev: Vec::new(),
+        }
+    }
+
+    /// number of visible rows
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// number of visible bytes
+    pub fn len(&self) -> usize {
+        self.rows * BYTES_PER_ROW
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    /// moves the view to `segment:offset`, forgetting the change history
+    pub fn goto(&mut self, segment: u16, offset: u16, mmu: &MMU) {
+        self.segment = segment;
+        self.offset = offset;
+        self.data = self.read(mmu);
+        self.prev = self.data.clone();
+    }
+
+    /// re-reads the visible bytes from memory
+    pub fn refresh(&mut self, mmu: &MMU) {
+        self.data = self.read(mmu);
+        if self.prev.len() != self.data.len() {
+            self.prev = self.data.clone();
+        }
+    }
+
+    /// remembers the current bytes as the base for change highlighting, call before each step
+    pub fn mark_step(&mut self, mmu: &MMU) {
+        self.refresh(mmu);
+        self.prev = self.data.clone();
+    }
+
+    /// returns the offset of byte `index` in the view
+    pub fn offset_of(&self, index: usize) -> u16 {
+        self.offset.wrapping_add(index as u16)
+    }
+
+    /// returns byte `index` as of the last refresh
+    pub fn byte(&self, index: usize) -> u8 {
+        self.data[index]
+    }
+
+    /// returns true if byte `index` changed since the last step
+    pub fn is_changed(&self, index: usize) -> bool {
+        self.data[index] != self.prev[index]
+    }
+
+    /// writes `value` to byte `index` in the view. user edits are not highlighted as changes
+    pub fn write(&mut self, mmu: &mut MMU, index: usize, value: u8) {
+        mmu.write_u8(self.segment, self.offset_of(index), value);
+        self.data[index] = value;
+        self.prev[index] = value;
+    }
+
+    /// returns the "SSSS:OOOO" address label of `row`
+    pub fn row_address(&self, row: usize) -> String {
+        format!("{:04X}:{:04X}", self.segment, self.offset_of(row * BYTES_PER_ROW))
+    }
+
+    /// returns the printable characters of `row`, with other bytes shown as '.'
+    pub fn row_ascii(&self, row: usize) -> String {
+        self.data[row * BYTES_PER_ROW..(row + 1) * BYTES_PER_ROW].iter()
+            .map(|&b| if (0x20..0x7F).contains(&b) { b as char } else { '.' })
+            .collect()
+    }
+
+    fn read(&self, mmu: &MMU) -> Vec<u8> {
+        (0..self.len()).map(|i| mmu.read_u8(self.segment, self.offset_of(i))).collect()
+    }
+}
+
+/// parses a hex byte as typed in the hex view
+pub fn parse_hex_byte(s: &str) -> Option<u8> {
+    let s = s.trim();
+    if s.is_empty() || s.len() > 2 {
+        return None;
+    }
+    u8::from_str_radix(s, 16).ok()
+}
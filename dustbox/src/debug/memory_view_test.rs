@@ -0,0 +1,45 @@
+use crate::debug::{MemoryView, parse_hex_byte};
+use crate::memory::MMU;
+
+#[test]
+fn can_track_changed_bytes() {
+    let mut mmu = MMU::default();
+    mmu.write(0x1000, 0x0100, b"Hello\x00");
+
+    let mut view = MemoryView::new(0x1000, 0x0100, 2);
+    view.goto(0x1000, 0x0100, &mmu);
+    assert_eq!(32, view.len());
+    assert_eq!("1000:0100", view.row_address(0));
+    assert_eq!("1000:0110", view.row_address(1));
+    assert_eq!("Hello...........", view.row_ascii(0));
+
+    view.mark_step(&mmu);
+    mmu.write_u8(0x1000, 0x0101, b'a');
+    view.refresh(&mmu);
+    assert_eq!(b'a', view.byte(1));
+    assert!(view.is_changed(1));
+    assert!(!view.is_changed(0));
+
+    // changes are relative to the last step
+    view.mark_step(&mmu);
+    assert!(!view.is_changed(1));
+}
+
+#[test]
+fn can_edit_bytes() {
+    let mut mmu = MMU::default();
+    let mut view = MemoryView::new(0x2000, 0xFFF8, 1);
+    view.goto(0x2000, 0xFFF8, &mmu);
+
+    // the view wraps around within the segment
+    assert_eq!(0x0002, view.offset_of(10));
+    view.write(&mut mmu, 10, 0xCC);
+    assert_eq!(0xCC, mmu.read_u8(0x2000, 0x0002));
+    assert!(!view.is_changed(10));
+
+    assert_eq!(Some(0xCC), parse_hex_byte("cc"));
+    assert_eq!(Some(0x07), parse_hex_byte(" 7"));
+    assert_eq!(None, parse_hex_byte("100"));
+    assert_eq!(None, parse_hex_byte("zz"));
+    assert_eq!(None, parse_hex_byte(""));
+}
@@ -6,8 +6,32 @@ mod breakpoints;
 pub use self::memory_breakpoints::*;
 mod memory_breakpoints;
 
+pub use self::io_breakpoints::*;
+mod io_breakpoints;
+
+pub use self::stack_guard::*;
+mod stack_guard;
+
+pub use self::annotation::*;
+mod annotation;
+
+pub use self::symbols::*;
+mod symbols;
+
 pub use self::tracer::*;
 mod tracer;
 
+pub use self::coverage::*;
+mod coverage;
+
+pub use self::memory_view::*;
+mod memory_view;
+
+pub use self::project::*;
+mod project;
+
 pub use self::debugger::*;
 mod debugger;
+
+pub use self::runner::*;
+mod runner;
@@ -0,0 +1,87 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::debug::{IoBreakpoint, Watchpoint};
+
+#[cfg(test)]
+#[path = "./project_test.rs"]
+mod project_test;
+
+/// directory next to the program where its project files are stored
+const PROJECT_DIR: &str = ".dustbox";
+
+/// the debugger state of a program, saved to disk so that reverse engineering sessions survive restarts
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Project {
+    /// hash of the program binary the project belongs to
+    pub hash: String,
+
+    /// flat addresses of the IP breakpoints
+    #[serde(default)]
+    pub breakpoints: Vec<u32>,
+
+    #[serde(default)]
+    pub watchpoints: Vec<Watchpoint>,
+
+    #[serde(default)]
+    pub io_breakpoints: Vec<IoBreakpoint>,
+
+    /// user comments, shown in the disassembly
+    #[serde(default)]
+    pub comments: Vec<AddressText>,
+
+    /// user assigned symbol names, replacing the loaded ones
+    #[serde(default)]
+    pub symbols: Vec<AddressText>,
+}
+
+/// a text attached to a flat address
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AddressText {
+    pub addr: u32,
+    pub text: String,
+}
+
+impl Project {
+    pub fn new(hash: &str) -> Self {
+        Project {
+            hash: hash.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// writes the project to `path`, creating its directory if needed
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            if !dir.as_os_str().is_empty() {
+                fs::create_dir_all(dir)?;
+            }
+        }
+        let text = toml::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, text)
+    }
+}
+
+/// returns the hash identifying a program binary (64-bit FNV-1a, as hex)
+pub fn program_hash(data: &[u8]) -> String {
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    for b in data {
+        hash ^= u64::from(*b);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// returns the default project file of the program at `program_path` with hash `hash`,
+/// such as "GAME/.dustbox/0123456789abcdef.toml"
+pub fn project_path(program_path: &str, hash: &str) -> PathBuf {
+    let dir = Path::new(program_path).parent().unwrap_or_else(|| Path::new(""));
+    dir.join(PROJECT_DIR).join(format!("{}.toml", hash))
+}
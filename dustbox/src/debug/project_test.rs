@@ -0,0 +1,47 @@
+use tempfile::tempdir;
+
+use crate::debug::{program_hash, project_path, AddressText, IoBreakpoint, Project, Watchpoint};
+
+#[test]
+fn can_save_and_load_project() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("projects").join("prog.toml");
+
+    let mut project = Project::new("0123456789abcdef");
+    project.breakpoints = vec![0x8731, 0x8740];
+    let mut wp = Watchpoint::new(0x8800, 2);
+    wp.set_access("rw");
+    wp.new = Some(0x1234);
+    project.watchpoints = vec![wp];
+    project.io_breakpoints = vec![
+        IoBreakpoint::Port{port: 0x3C9, read: false, write: true},
+        IoBreakpoint::Interrupt{int: 0x21, ah: Some(0x4C), al: None},
+    ];
+    project.comments = vec![AddressText{addr: 0x8731, text: "draws the \"logo\"".to_owned()}];
+    project.symbols = vec![AddressText{addr: 0x8740, text: "main_loop".to_owned()}];
+
+    project.save(&path).unwrap();
+    assert_eq!(project, Project::load(&path).unwrap());
+}
+
+#[test]
+fn can_load_sparse_project() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("prog.toml");
+    std::fs::write(&path, "hash = \"abc\"\nbreakpoints = [34609]\n").unwrap();
+
+    let project = Project::load(&path).unwrap();
+    assert_eq!("abc", project.hash);
+    assert_eq!(vec![0x8731], project.breakpoints);
+    assert!(project.watchpoints.is_empty());
+    assert!(project.comments.is_empty());
+}
+
+#[test]
+fn can_hash_program() {
+    assert_eq!("cbf29ce484222325", program_hash(&[]));
+    assert_eq!("af63dc4c8601ec8c", program_hash(b"a"));
+    assert_eq!(
+        std::path::Path::new("games/.dustbox/af63dc4c8601ec8c.toml"),
+        project_path("games/GAME.EXE", "af63dc4c8601ec8c"));
+}
@@ -0,0 +1,237 @@
+// runs a machine on a worker thread, controlled through a message channel
+// so that the embedding application (such as the GUI debugger) doesn't block
+
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use crate::cpu::R;
+use crate::debug::Debugger;
+use crate::gpu::VideoFrame;
+use crate::tools::read_binary;
+
+#[cfg(test)]
+#[path = "./runner_test.rs"]
+mod runner_test;
+
+/// commands sent to the worker thread of a `MachineRunner`, handled in order
+pub enum RunnerCommand {
+    /// runs one video frame at a time until paused, or a breakpoint or fatal error is reached
+    Run,
+
+    /// stops running
+    Pause,
+
+    /// executes up to `n` instructions, stopping at breakpoints
+    Step(usize),
+
+    /// loads the program at host path
+    Load(String),
+
+    /// executes a debugger command, as typed in the debugger
+    Exec(String),
+
+    /// calls the function with the debugger, to read or modify the machine state
+    Inspect(Box<dyn FnOnce(&mut Debugger) + Send>),
+
+    /// ends the worker thread
+    Quit,
+}
+
+/// events sent from the worker thread of a `MachineRunner`
+pub enum RunnerEvent {
+    /// a frame was executed while running
+    FrameReady(VideoFrame),
+
+    /// execution stopped at a breakpoint, at CS:IP
+    BreakpointHit(u16, u16),
+
+    /// execution stopped at a fatal error, or a program failed to load
+    FatalError(String),
+
+    /// a run, step or load command has finished
+    Paused,
+}
+
+/// owns a machine on a worker thread
+pub struct MachineRunner {
+    commands: Sender<RunnerCommand>,
+    events: Receiver<RunnerEvent>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MachineRunner {
+    /// starts a worker thread owning `debugger`
+    pub fn new(debugger: Debugger) -> Self {
+        Self::spawn(move || debugger)
+    }
+
+    /// starts a worker thread owning the debugger returned by `create`, which is called on the worker thread
+    pub fn spawn<F>(create: F) -> Self
+    where
+        F: FnOnce() -> Debugger + Send + 'static,
+    {
+        let (commands, command_receiver) = channel();
+        let (event_sender, events) = channel();
+        let thread = thread::spawn(move || {
+            let mut worker = Worker {
+                debugger: create(),
+                events: event_sender,
+                running: false,
+            };
+            worker.run(&command_receiver);
+        });
+        MachineRunner {
+            commands,
+            events,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn send(&self, command: RunnerCommand) {
+        if self.commands.send(command).is_err() {
+            warn!(target: "cpu", "machine runner: worker thread has stopped");
+        }
+    }
+
+    /// calls `f` with the debugger on the worker thread, after the previously sent commands
+    pub fn inspect<F>(&self, f: F)
+    where
+        F: FnOnce(&mut Debugger) + Send + 'static,
+    {
+        self.send(RunnerCommand::Inspect(Box::new(f)));
+    }
+
+    /// calls `f` with the debugger on the worker thread and waits for the result
+    pub fn query<T, F>(&self, f: F) -> Option<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Debugger) -> T + Send + 'static,
+    {
+        let (sender, receiver) = channel();
+        self.inspect(move |debugger| {
+            let _ = sender.send(f(debugger));
+        });
+        receiver.recv().ok()
+    }
+
+    /// returns the next event without blocking, for polling from the main loop of a GUI
+    pub fn try_recv(&self) -> Option<RunnerEvent> {
+        self.events.try_recv().ok()
+    }
+
+    /// waits up to `timeout` for the next event
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<RunnerEvent> {
+        match self.events.recv_timeout(timeout) {
+            Ok(event) => Some(event),
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => None,
+        }
+    }
+}
+
+impl Drop for MachineRunner {
+    fn drop(&mut self) {
+        let _ = self.commands.send(RunnerCommand::Quit);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct Worker {
+    debugger: Debugger,
+    events: Sender<RunnerEvent>,
+    running: bool,
+}
+
+impl Worker {
+    fn run(&mut self, commands: &Receiver<RunnerCommand>) {
+        loop {
+            if self.running {
+                while let Ok(command) = commands.try_recv() {
+                    if !self.handle(command) {
+                        return;
+                    }
+                }
+                if !self.running {
+                    continue;
+                }
+                let start = Instant::now();
+                let stopped = self.debugger.run_frame();
+                let frame = self.debugger.machine.gpu().render_frame(&self.debugger.machine.mmu);
+                if self.events.send(RunnerEvent::FrameReady(frame)).is_err() {
+                    return;
+                }
+                if stopped {
+                    self.running = false;
+                    self.stopped(true);
+                } else {
+                    // paced to the refresh rate of the emulated display
+                    let frame_time = Duration::from_secs(1) / self.debugger.machine.gpu().refresh_rate.max(1) as u32;
+                    if let Some(left) = frame_time.checked_sub(start.elapsed()) {
+                        thread::sleep(left);
+                    }
+                }
+            } else {
+                match commands.recv() {
+                    Ok(command) => if !self.handle(command) {
+                        return;
+                    },
+                    Err(_) => return,
+                }
+            }
+        }
+    }
+
+    /// returns false when the worker should end
+    fn handle(&mut self, command: RunnerCommand) -> bool {
+        match command {
+            RunnerCommand::Run => {
+                self.debugger.machine.cpu.fatal_error = false;
+                self.running = true;
+            }
+            RunnerCommand::Pause => {
+                self.running = false;
+                self.stopped(false);
+            }
+            RunnerCommand::Step(n) => {
+                self.running = false;
+                self.debugger.machine.cpu.fatal_error = false;
+                let stopped = self.debugger.step_into(n);
+                self.stopped(stopped);
+            }
+            RunnerCommand::Load(path) => {
+                self.running = false;
+                match read_binary(&path) {
+                    Ok(_) => {
+                        self.debugger.load_executable(&path);
+                        self.stopped(false);
+                    }
+                    Err(e) => {
+                        let _ = self.events.send(RunnerEvent::FatalError(format!("failed to load {}: {}", path, e)));
+                    }
+                }
+            }
+            RunnerCommand::Exec(cmd) => self.debugger.exec_command(&cmd),
+            RunnerCommand::Inspect(f) => f(&mut self.debugger),
+            RunnerCommand::Quit => return false,
+        }
+        true
+    }
+
+    /// reports why execution stopped
+    fn stopped(&self, at_breakpoint: bool) {
+        let cpu = &self.debugger.machine.cpu;
+        let (cs, ip) = (cpu.get_r16(R::CS), cpu.regs.ip);
+        let event = if cpu.fatal_error {
+            RunnerEvent::FatalError(format!("cpu stopped at {:04X}:{:04X}", cs, ip))
+        } else if at_breakpoint {
+            RunnerEvent::BreakpointHit(cs, ip)
+        } else {
+            RunnerEvent::Paused
+        };
+        let _ = self.events.send(event);
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+use crate::cpu::R;
+use crate::debug::{Debugger, MachineRunner, RunnerCommand, RunnerEvent};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn loop_program() -> MachineRunner {
+    MachineRunner::spawn(|| {
+        let code: Vec<u8> = vec![
+            0x40,               // inc ax
+            0xEB, 0xFD,         // jmp short 0x100
+        ];
+        let mut dbg = Debugger::default();
+        dbg.machine.load_executable(&code, 0x085F);
+        dbg
+    })
+}
+
+#[test]
+fn can_step_and_inspect_on_worker_thread() {
+    let runner = loop_program();
+    runner.send(RunnerCommand::Step(3));
+    match runner.recv_timeout(TIMEOUT) {
+        Some(RunnerEvent::Paused) => {}
+        _ => panic!("expected paused event"),
+    }
+    assert_eq!(Some((2, 0x0101)), runner.query(|dbg| (dbg.machine.cpu.get_r16(R::AX), dbg.machine.cpu.regs.ip)));
+
+    runner.inspect(|dbg| dbg.machine.cpu.set_r16(R::BX, 0x5678));
+    assert_eq!(Some(0x5678), runner.query(|dbg| dbg.machine.cpu.get_r16(R::BX)));
+}
+
+#[test]
+fn can_run_until_breakpoint() {
+    let runner = loop_program();
+    runner.send(RunnerCommand::Run);
+    match runner.recv_timeout(TIMEOUT) {
+        Some(RunnerEvent::FrameReady(frame)) => assert_eq!(frame.stride * frame.height as usize, frame.data.len()),
+        _ => panic!("expected frame"),
+    }
+    runner.send(RunnerCommand::Pause);
+    loop {
+        match runner.recv_timeout(TIMEOUT) {
+            Some(RunnerEvent::FrameReady(_)) => {}
+            Some(RunnerEvent::Paused) => break,
+            _ => panic!("expected paused event"),
+        }
+    }
+
+    runner.send(RunnerCommand::Exec("bp add 085F:0101".to_owned()));
+    runner.send(RunnerCommand::Run);
+    loop {
+        match runner.recv_timeout(TIMEOUT) {
+            Some(RunnerEvent::FrameReady(_)) => {}
+            Some(RunnerEvent::BreakpointHit(cs, ip)) => {
+                assert_eq!((0x085F, 0x0101), (cs, ip));
+                break;
+            }
+            _ => panic!("expected breakpoint"),
+        }
+    }
+
+    runner.send(RunnerCommand::Load("missing.com".to_owned()));
+    match runner.recv_timeout(TIMEOUT) {
+        Some(RunnerEvent::FatalError(msg)) => assert!(msg.starts_with("failed to load missing.com")),
+        _ => panic!("expected fatal error"),
+    }
+}
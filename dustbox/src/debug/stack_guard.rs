@@ -0,0 +1,169 @@
+use std::fmt;
+use std::mem;
+use std::ops::Range;
+
+#[cfg(test)]
+#[path = "./stack_guard_test.rs"]
+mod stack_guard_test;
+
+/// what the machine does when the stack guard triggers
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum StackGuardAction {
+    /// logs a warning and continues
+    #[serde(rename = "warn")]
+    Warn,
+
+    /// logs a warning and stops execution, breaking into the debugger
+    #[serde(rename = "break")]
+    Break,
+}
+
+/// settings of the stack guard, a debug aid watching the stack of the loaded program
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StackGuardConfig {
+    pub enabled: bool,
+
+    /// triggers when more than `max_depth` bytes are in use
+    pub max_depth: Option<u16>,
+
+    pub action: StackGuardAction,
+}
+
+impl Default for StackGuardConfig {
+    fn default() -> Self {
+        StackGuardConfig {
+            enabled: false,
+            max_depth: None,
+            action: StackGuardAction::Warn,
+        }
+    }
+}
+
+/// a stack condition found by the stack guard
+#[derive(Clone, Debug, PartialEq)]
+pub enum StackEvent {
+    /// SP is above the top of the stack, more was popped than pushed
+    Underflow { ss: u16, sp: u16, top: u16 },
+
+    /// more than the max depth is in use
+    Overflow { ss: u16, sp: u16, depth: u32 },
+
+    /// SS:SP points into the PSP or the loaded program, pushes overwrite it
+    Collision { ss: u16, sp: u16 },
+}
+
+impl fmt::Display for StackEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StackEvent::Underflow { ss, sp, top } => write!(f, "stack underflow, SS:SP = {:04X}:{:04X} is above the top of stack {:04X}", ss, sp, top),
+            StackEvent::Overflow { ss, sp, depth } => write!(f, "stack overflow, SS:SP = {:04X}:{:04X} with {} bytes in use", ss, sp, depth),
+            StackEvent::Collision { ss, sp } => write!(f, "stack collision, SS:SP = {:04X}:{:04X} points into the program", ss, sp),
+        }
+    }
+}
+
+/// tracks the stack of the loaded program from its initial SS:SP, and reports when the stack
+/// underflows, grows past the max depth, or reaches the PSP or program code.
+/// each condition is reported once, until the stack has recovered
+#[derive(Default)]
+pub struct StackGuard {
+    pub config: StackGuardConfig,
+
+    /// SS and SP of the empty stack at program start, or at the last change of SS
+    base: Option<(u16, u16)>,
+
+    /// flat addresses of the PSP and the loaded program
+    protected: Range<u32>,
+
+    /// SP at the last check
+    sp: u16,
+
+    /// bytes in use on the stack, negative on underflow
+    depth: i32,
+
+    /// set when SS was changed by the last instruction
+    switched: bool,
+
+    /// the condition reported last, if it persists
+    reported: Option<StackEvent>,
+}
+
+impl StackGuard {
+    pub fn new(config: StackGuardConfig) -> Self {
+        StackGuard {
+            config,
+            ..StackGuard::default()
+        }
+    }
+
+    /// starts tracking the stack of a program loaded at `protected`, with the empty stack at `ss`:`top`
+    pub fn arm(&mut self, ss: u16, top: u16, protected: Range<u32>) {
+        self.base = Some((ss, top));
+        self.protected = protected;
+        self.sp = top;
+        self.depth = 0;
+        self.switched = false;
+        self.reported = None;
+    }
+
+    /// returns the SS and SP of the empty stack, the depth is measured from
+    pub fn base(&self) -> Option<(u16, u16)> {
+        self.base
+    }
+
+    /// checks the stack at `ss`:`sp`, returning a newly found condition
+    pub fn check(&mut self, ss: u16, sp: u16) -> Option<StackEvent> {
+        let event = self.find(ss, sp);
+        let new = match (&event, &self.reported) {
+            (Some(e), Some(r)) => mem::discriminant(e) != mem::discriminant(r),
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        self.reported = event.clone();
+        if new {
+            event
+        } else {
+            None
+        }
+    }
+
+    fn find(&mut self, ss: u16, sp: u16) -> Option<StackEvent> {
+        let (base_ss, top) = self.base?;
+        if ss != base_ss {
+            // the program switches to its own stack
+            self.base = Some((ss, sp));
+            self.sp = sp;
+            self.depth = 0;
+            self.switched = true;
+            return None;
+        }
+        // SP moves in small steps, the direction of each step tells pushes from pops across the segment wrap
+        self.depth += i32::from(self.sp.wrapping_sub(sp) as i16);
+        self.sp = sp;
+        if self.switched {
+            self.switched = false;
+            if self.depth < 0 {
+                // SP is usually loaded by the instruction following the load of SS
+                self.base = Some((ss, sp));
+                self.depth = 0;
+            }
+            return None;
+        }
+        if self.depth < 0 {
+            return Some(StackEvent::Underflow { ss, sp, top });
+        }
+        if self.depth == 0 {
+            return None;
+        }
+        let flat = (u32::from(ss) << 4) + u32::from(sp);
+        if self.protected.contains(&flat) {
+            return Some(StackEvent::Collision { ss, sp });
+        }
+        let depth = self.depth as u32;
+        match self.config.max_depth {
+            Some(max) if depth > u32::from(max) => Some(StackEvent::Overflow { ss, sp, depth }),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use crate::debug::stack_guard::{StackEvent, StackGuard, StackGuardConfig};
+
+#[test]
+fn stack_guard_reports_underflow_once() {
+    let mut guard = StackGuard::default();
+    guard.arm(0x085F, 0x0000, 0x85F0..0x8800);
+    assert_eq!(None, guard.check(0x085F, 0xFFFE));
+    assert_eq!(None, guard.check(0x085F, 0xFF00));
+    assert_eq!(None, guard.check(0x085F, 0x0000));
+
+    assert_eq!(Some(StackEvent::Underflow { ss: 0x085F, sp: 0x0002, top: 0x0000 }), guard.check(0x085F, 0x0002));
+    assert_eq!(None, guard.check(0x085F, 0x0004));
+
+    // reported again after the stack recovered
+    assert_eq!(None, guard.check(0x085F, 0xFFFE));
+    assert!(guard.check(0x085F, 0x0002).is_some());
+}
+
+#[test]
+fn stack_guard_reports_overflow_and_collision() {
+    let mut guard = StackGuard::new(StackGuardConfig {
+        enabled: true,
+        max_depth: Some(0x100),
+        ..StackGuardConfig::default()
+    });
+    guard.arm(0x085F, 0x0000, 0x85F0..0x8800);
+    assert_eq!(None, guard.check(0x085F, 0xFF00));
+    assert_eq!(Some(StackEvent::Overflow { ss: 0x085F, sp: 0xFEFE, depth: 0x102 }), guard.check(0x085F, 0xFEFE));
+
+    let mut sp = 0xFEFE;
+    while sp > 0x0300 {
+        sp -= 0x100;
+        assert_eq!(None, guard.check(0x085F, sp));
+    }
+
+    // 085F:0200 is inside the loaded program
+    assert_eq!(Some(StackEvent::Collision { ss: 0x085F, sp: 0x0200 }), guard.check(0x085F, 0x0200));
+    assert_eq!("stack collision, SS:SP = 085F:0200 points into the program", format!("{}", StackEvent::Collision { ss: 0x085F, sp: 0x0200 }));
+}
+
+#[test]
+fn stack_guard_follows_stack_switch() {
+    let mut guard = StackGuard::default();
+    guard.arm(0x085F, 0x0000, 0x85F0..0x8800);
+
+    // mov ss,ax after mov sp,0x400, followed by a push
+    assert_eq!(None, guard.check(0x2000, 0x0400));
+    assert_eq!(None, guard.check(0x2000, 0x03FE));
+    assert_eq!(Some((0x2000, 0x0400)), guard.base());
+    assert!(guard.check(0x2000, 0x0400).is_none());
+
+    // mov ss,ax followed by mov sp,0x400
+
+    let mut guard = StackGuard::default();
+    guard.arm(0x085F, 0x0000, 0x85F0..0x8800);
+    guard.check(0x2000, 0x0200);
+    guard.check(0x2000, 0x0400);
+    assert_eq!(Some((0x2000, 0x0400)), guard.base());
+    assert!(guard.check(0x2000, 0x0402).is_some());
+}
@@ -0,0 +1,168 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::cpu::{Instruction, InstructionInfo, Parameter};
+use crate::memory::MemoryAddress;
+
+#[cfg(test)]
+#[path = "./symbols_test.rs"]
+mod symbols_test;
+
+/// named addresses of the loaded program, used to present addresses by name
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable {
+    /// symbol name by flat address, the first name wins when several share an address
+    names: BTreeMap<u32, String>,
+
+    /// flat address by symbol name
+    addresses: HashMap<String, u32>,
+}
+
+impl SymbolTable {
+    /// loads a WLINK/TLINK .map file, or a .toml file of "seg:off" = "name" pairs.
+    /// segments are relative to `load_segment`, like in the linker output
+    pub fn from_file<P: AsRef<Path>>(path: P, load_segment: u16) -> io::Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let is_toml = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("toml"));
+        if is_toml {
+            Self::parse_toml(&text, load_segment)
+        } else {
+            Ok(Self::parse_map(&text, load_segment))
+        }
+    }
+
+    /// parses the public symbols listed in a WLINK or TLINK map file
+    pub fn parse_map(text: &str, load_segment: u16) -> Self {
+        let mut table = SymbolTable::default();
+        for line in text.lines() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            // TLINK marks absolute and unused symbols with "Abs" and "Idle"
+            let name = match tokens.len() {
+                2 => tokens[1],
+                3 if tokens[1] == "Abs" || tokens[1] == "Idle" => tokens[2],
+                _ => continue,
+            };
+            // WLINK marks unreferenced symbols with "*" and library symbols with "+"
+            let address = tokens[0].trim_end_matches(['*', '+']);
+            if let Some((seg, off)) = parse_segment_offset(address) {
+                table.insert(MemoryAddress::RealSegmentOffset(seg.wrapping_add(load_segment), off).value(), name);
+            }
+        }
+        table
+    }
+
+    /// parses a toml table of "seg:off" = "name" pairs. a key without segment is an offset into `load_segment`
+    pub fn parse_toml(text: &str, load_segment: u16) -> io::Result<Self> {
+        let value: toml::Value = text.parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut table = SymbolTable::default();
+        if let Some(entries) = value.as_table() {
+            for (key, name) in entries {
+                let (seg, off) = match parse_segment_offset(key).or_else(|| parse_hex(key).map(|off| (0, off))) {
+                    Some(pair) => pair,
+                    None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid symbol address {}", key))),
+                };
+                let name = match name.as_str() {
+                    Some(name) => name,
+                    None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("symbol name for {} is not a string", key))),
+                };
+                table.insert(MemoryAddress::RealSegmentOffset(seg.wrapping_add(load_segment), off).value(), name);
+            }
+        }
+        Ok(table)
+    }
+
+    pub fn insert(&mut self, addr: u32, name: &str) {
+        self.names.entry(addr).or_insert_with(|| name.to_owned());
+        self.addresses.insert(name.to_owned(), addr);
+    }
+
+    /// names the symbol at `addr`, replacing a previous name of the address
+    pub fn rename(&mut self, addr: u32, name: &str) {
+        if let Some(old) = self.names.insert(addr, name.to_owned()) {
+            if self.addresses.get(&old) == Some(&addr) {
+                self.addresses.remove(&old);
+            }
+        }
+        self.addresses.insert(name.to_owned(), addr);
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// returns the symbols sorted by address
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &str)> {
+        self.names.iter().map(|(addr, name)| (*addr, name.as_str()))
+    }
+
+    /// returns the name of the symbol at flat address `addr`
+    pub fn name_at(&self, addr: u32) -> Option<&str> {
+        self.names.get(&addr).map(|name| name.as_str())
+    }
+
+    /// returns the flat address of symbol `name`
+    pub fn lookup(&self, name: &str) -> Option<u32> {
+        self.addresses.get(name).cloned()
+    }
+
+    /// describes `addr` as "name" or "name+0x12", relative to the closest preceding symbol
+    pub fn describe(&self, addr: u32) -> Option<String> {
+        let (start, name) = self.names.range(..=addr).next_back()?;
+        match addr - start {
+            0 => Some(name.to_owned()),
+            delta if delta <= 0xFFFF => Some(format!("{}+0x{:X}", name, delta)),
+            _ => None,
+        }
+    }
+
+    /// returns `op` decoded in segment `seg` as text, showing a known branch target by name
+    pub fn instruction_text(&self, op: &Instruction, seg: u16) -> String {
+        self.name_branch_target(format!("{}", op), op, seg)
+    }
+
+    /// returns `ii` as text, showing a known branch target by name
+    pub fn instruction_info_text(&self, ii: &InstructionInfo) -> String {
+        self.name_branch_target(format!("{}", ii), &ii.instruction, ii.segment as u16)
+    }
+
+    fn name_branch_target(&self, text: String, op: &Instruction, seg: u16) -> String {
+        if self.is_empty() {
+            return text;
+        }
+        if !op.is_branch() || op.is_ret() {
+            return text;
+        }
+        let target = match op.params.dst {
+            Parameter::Imm16(off) => MemoryAddress::RealSegmentOffset(seg, off),
+            Parameter::Ptr16Imm(seg, off) => MemoryAddress::RealSegmentOffset(seg, off),
+            _ => return text,
+        };
+        let raw = format!("{}", op.params.dst);
+        match self.name_at(target.value()) {
+            Some(name) if text.ends_with(&raw) => format!("{}{}", &text[..text.len() - raw.len()], name),
+            _ => text,
+        }
+    }
+}
+
+/// parses a "SSSS:OOOO" hex address
+fn parse_segment_offset(s: &str) -> Option<(u16, u16)> {
+    let pos = s.find(':')?;
+    Some((parse_hex(&s[..pos])?, parse_hex(&s[pos + 1..])?))
+}
+
+/// parses a 16-bit hex value, with optional "0x" prefix
+fn parse_hex(s: &str) -> Option<u16> {
+    let s = s.trim_start_matches("0x").trim_start_matches("0X");
+    if s.is_empty() || s.len() > 4 {
+        return None;
+    }
+    u16::from_str_radix(s, 16).ok()
+}
@@ -0,0 +1,93 @@
+use crate::cpu::Decoder;
+use crate::debug::SymbolTable;
+use crate::machine::Machine;
+
+#[test]
+fn can_parse_tlink_map() {
+    let map = "
+ Start  Stop   Length Name               Class
+
+ 00000H 0001FH 00020H _TEXT              CODE
+ 00020H 0002FH 00010H _DATA              DATA
+
+Detailed map of segments
+
+ 0000:0000 0020 C=CODE   S=_TEXT          G=(none)  M=HELLO.C   ACBP=28
+
+  Address         Publics by Name
+
+ 0000:0010       _main
+ 0002:0000       _message
+ 0000:0000  Abs  __AHINCR
+
+  Address         Publics by Value
+
+ 0000:0000  Abs  __AHINCR
+ 0000:0010       _main
+ 0002:0000       _message
+
+Program entry point at 0000:0000
+";
+    let symbols = SymbolTable::parse_map(map, 0x086F);
+    assert_eq!(3, symbols.len());
+    assert_eq!(Some("_main"), symbols.name_at(0x8700));
+    assert_eq!(Some(0x8710), symbols.lookup("_message"));
+    assert_eq!(Some("__AHINCR"), symbols.name_at(0x86F0));
+}
+
+#[test]
+fn can_parse_wlink_map() {
+    let map = "
+Segment                Class          Group          Address         Size
+=======                =====          =====          =======         ====
+
+_TEXT                  CODE           AUTO           0000:0000       00000022
+
+Address        Symbol
+=======        ======
+
+Module: hello.obj(hello.c)
+0000:0000      main_
+0000:0012*     unused_
+0002:0004+     printf_
+";
+    let symbols = SymbolTable::parse_map(map, 0x086F);
+    assert_eq!(3, symbols.len());
+    assert_eq!(Some(0x86F0), symbols.lookup("main_"));
+    assert_eq!(Some("unused_"), symbols.name_at(0x8702));
+    assert_eq!(Some("printf_"), symbols.name_at(0x8714));
+}
+
+#[test]
+fn can_parse_toml_symbols() {
+    let symbols = SymbolTable::parse_toml("
+0x0100 = \"start\"
+\"0000:0108\" = \"print\"
+", 0x085F).unwrap();
+    assert_eq!(Some(0x86F0), symbols.lookup("start"));
+    assert_eq!(Some("print"), symbols.name_at(0x86F8));
+    assert_eq!(Some("print+0x3".to_owned()), symbols.describe(0x86FB));
+    assert_eq!(None, symbols.describe(0x86EF));
+
+    assert!(SymbolTable::parse_toml("\"xyz\" = \"start\"", 0x085F).is_err());
+}
+
+#[test]
+fn can_name_branch_targets() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xE8, 0x05, 0x00,   // call 0x108
+        0xEB, 0xFE,         // jmp short 0x103
+        0x90,               // nop
+        0x90,               // nop
+        0x90,               // nop
+        0xC3,               // ret
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.symbols = SymbolTable::parse_toml("0x0108 = \"print\"", 0x085F).unwrap();
+
+    let mut decoder = Decoder::default();
+    let ops = decoder.decode_to_block(&mut machine.mmu, 0x085F, 0x0100, 2);
+    assert_eq!("[085F:0100] E80500           CallNear print", machine.symbols.instruction_info_text(&ops[0]));
+    assert_eq!("[085F:0103] EBFE             JmpShort 0x0103", machine.symbols.instruction_info_text(&ops[1]));
+}
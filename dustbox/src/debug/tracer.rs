@@ -1,9 +1,12 @@
 use std::cmp;
+use std::collections::HashMap;
 use std::num::Wrapping;
 
 use crate::machine::Machine;
+use crate::debug::{int_desc, SymbolTable};
 use crate::cpu::{Decoder, RepeatMode, InstructionInfo, RegisterState, R, Op, Invalid, Parameter, Segment};
-use crate::memory::MemoryAddress;
+use crate::cpu::analysis;
+use crate::memory::{CodeWrite, MemoryAddress};
 use crate::string::right_pad;
 
 #[cfg(test)]
@@ -34,6 +37,9 @@ pub struct ProgramTracer {
 
     /// traced $-strings in memory which can be decoded in final pass
     dollar_strings: Vec<MemoryAddress>,
+
+    /// self-modifying code writes observed while running the program
+    code_writes: Vec<CodeWrite>,
 }
 
 #[derive(Default)]
@@ -164,6 +170,44 @@ impl Ord for SeenSource {
 }
 
 
+/// a line of nasm output, starting at `offset`
+struct NasmLine {
+    offset: u16,
+    kind: NasmLineKind,
+}
+
+enum NasmLineKind {
+    Instr,
+    Bytes(Vec<u8>),
+    Word(u16),
+    DollarString(Vec<u8>),
+}
+
+/// a function found by the tracer, see `ProgramTracer::functions`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TracedFunction {
+    pub entry: MemoryAddress,
+    pub name: String,
+
+    /// start of each instruction reachable from `entry` without following calls
+    pub instructions: Vec<MemoryAddress>,
+
+    /// entries of the functions called or tail-jumped to
+    pub calls: Vec<MemoryAddress>,
+
+    pub loops: Vec<TracedLoop>,
+}
+
+/// a loop, found as a branch back to an earlier instruction of the same function
+#[derive(Clone, Debug, PartialEq)]
+pub struct TracedLoop {
+    /// first instruction of the loop
+    pub head: MemoryAddress,
+
+    /// the branch back to `head`
+    pub tail: MemoryAddress,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 enum GuessedDataType {
     InstrStart,
@@ -233,9 +277,16 @@ impl ProgramTracer {
             dirty_regs: DirtyRegisters::default(),
             annotations: Vec::new(),
             dollar_strings: Vec::new(),
+            code_writes: Vec::new(),
         }
     }
 
+    /// adds writes to code bytes, as returned by `MMU::take_code_writes`,
+    /// in order to mark self-modifying code in the presented trace
+    pub fn learn_code_writes(&mut self, writes: &[CodeWrite]) {
+        self.code_writes.extend_from_slice(writes);
+    }
+
     /// traces all discovered paths of the program by static analysis
     pub fn trace_execution(&mut self, machine: &mut Machine) {
         // init known register values at program start
@@ -249,8 +300,6 @@ impl ProgramTracer {
         let ma = MemoryAddress::RealSegmentOffset(machine.cpu.get_r16(R::CS), machine.cpu.regs.ip);
         self.seen_addresses.push(SeenAddress{ma, visited: false, sources: SeenSources::default()});
 
-        println!("; starting tracing disassembly at {}", ma);
-
         loop {
             self.trace_unvisited_address(machine);
             if !self.has_unvisited_code_addresses() {
@@ -316,46 +365,48 @@ impl ProgramTracer {
             }
         }
 
-        // find all unvisited offsets
+        // find all unvisited offsets. unknown bytes are shown in the segment of the preceding code
+        let accounted: HashMap<u32, u16> = self.accounted_bytes.iter()
+            .map(|ab| (ab.address.value(), ab.address.segment()))
+            .collect();
         let mut unaccounted_bytes = vec![];
         let mut block = Vec::new();
         let mut block_start = MemoryAddress::Unset;
         let mut block_last = MemoryAddress::Unset;
-        for ofs in (machine.rom_base.offset() as usize)..(machine.rom_base.offset() as usize + machine.rom_length) {
-            let adr = MemoryAddress::RealSegmentOffset(machine.rom_base.segment(), ofs as u16);
-
-            let mut found = false;
-            for ab in &self.accounted_bytes {
-                if ab.address == adr {
-                    found = true;
-                    break;
-                }
+        let mut seg = machine.rom_base.segment();
+        let rom_start = machine.rom_base.value();
+        for phys in rom_start..rom_start + machine.rom_length as u32 {
+            if let Some(code_seg) = accounted.get(&phys) {
+                seg = *code_seg;
+                continue;
+            }
+            if phys < u32::from(seg) << 4 || phys - (u32::from(seg) << 4) > 0xFFFF {
+                seg = (phys >> 4) as u16;
+            }
+            let adr = MemoryAddress::RealSegmentOffset(seg, (phys - (u32::from(seg) << 4)) as u16);
+            if  DEBUG_TRACER {
+                eprintln!("address is unaccounted {}", adr);
             }
-            if !found {
-                if  DEBUG_TRACER {
-                    eprintln!("address is unaccounted {}", adr);
-                }
-
-                // determine if last byte was in this range
-                if let MemoryAddress::RealSegmentOffset(_seg, off) = block_last {
-                    if off != adr.offset().wrapping_sub(1) && !block.is_empty() {
-                        unaccounted_bytes.push(GuessedDataAddress{kind: GuessedDataType::UnknownBytes(block.clone()), address: block_start});
-                        block.clear();
-                    }
-                }
-                if block.is_empty() {
-                    block_start = adr;
-                }
-                block_last = adr;
-
-                let val = machine.mmu.read_u8(adr.segment(), adr.offset());
-                block.push(val);
 
-                if block.len() >= 4 {
+            // determine if last byte was in this range
+            if let MemoryAddress::RealSegmentOffset(last_seg, _) = block_last {
+                if (last_seg != seg || block_last.value() + 1 != phys) && !block.is_empty() {
                     unaccounted_bytes.push(GuessedDataAddress{kind: GuessedDataType::UnknownBytes(block.clone()), address: block_start});
                     block.clear();
                 }
             }
+            if block.is_empty() {
+                block_start = adr;
+            }
+            block_last = adr;
+
+            let val = machine.mmu.memory.read_u8(phys);
+            block.push(val);
+
+            if block.len() >= 4 {
+                unaccounted_bytes.push(GuessedDataAddress{kind: GuessedDataType::UnknownBytes(block.clone()), address: block_start});
+                block.clear();
+            }
         }
 
         if !block.is_empty() {
@@ -441,15 +492,26 @@ impl ProgramTracer {
 
                     let mut tail = self.render_xref(ab.address);
 
-                    let decor = self.annotate_instruction(&ii);
+                    let mut decor = self.annotate_instruction(&ii);
+                    let start = ab.address.value();
+                    let end = start + u32::from(ii.instruction.length);
+                    if self.code_writes.iter().any(|w| w.addr >= start && w.addr < end) {
+                        if !decor.is_empty() {
+                            decor.push_str(" | ");
+                        }
+                        decor.push_str("self-modified");
+                    }
                     if decor != "" {
                         tail.push_str(&format!("; {}", decor));
                     }
 
+                    if let Some(name) = machine.symbols.name_at(start) {
+                        res.push_str(&format!("{}:\n", name));
+                    }
+                    let iis = machine.symbols.instruction_info_text(&ii);
                     if tail != "" {
-                        res.push_str(&format!("{}{}", right_pad(&format!("{}", ii), 68), tail));
+                        res.push_str(&format!("{}{}", right_pad(&iis, 68), tail));
                     } else {
-                        let iis = format!("{}", ii);
                         res.push_str(&iis);
                     }
                     res.push('\n');
@@ -493,6 +555,281 @@ impl ProgramTracer {
         res
     }
 
+    /// presents the traced program as nasm source, assembling back to the original binary
+    /// (the relocated load image for .exe files).
+    /// relative branches refer to generated labels, other instructions are emitted as bytes
+    pub fn present_nasm(&mut self, machine: &mut Machine) -> String {
+        let seg = machine.rom_base.segment();
+        let start = u32::from(machine.rom_base.offset());
+        let end = start + machine.rom_length as u32;
+
+        let labels = self.nasm_label_candidates(seg, start, end, &machine.symbols);
+        let lines = self.nasm_lines(machine, start, end, &labels);
+        let defined: HashMap<u16, String> = lines.iter()
+            .filter_map(|l| labels.get(&l.offset).map(|name| (l.offset, name.clone())))
+            .collect();
+
+        let mut res = format!("bits 16\norg 0x{:04X}\n", start);
+        let mut decoder = Decoder::default();
+        for line in &lines {
+            if let Some(name) = defined.get(&line.offset) {
+                res.push_str(&format!("\n{}:\n", name));
+            }
+            let (text, comment) = match &line.kind {
+                NasmLineKind::Instr => {
+                    let ii = decoder.get_instruction_info(&mut machine.mmu, seg, line.offset);
+                    let label = match ii.instruction.params.dst {
+                        Parameter::Imm16(dst) => defined.get(&dst),
+                        _ => None,
+                    };
+                    let branch = label.and_then(|label| nasm_branch(&ii.bytes, label));
+                    let text = match branch {
+                        Some(text) => text,
+                        None => nasm_db(&ii.bytes),
+                    };
+                    let mut comment = format!("{}", ii.instruction);
+                    let decor = self.annotate_instruction(&ii);
+                    if !decor.is_empty() {
+                        comment.push_str(&format!(" ; {}", decor));
+                    }
+                    (text, comment)
+                }
+                NasmLineKind::Bytes(v) => (nasm_db(v), String::new()),
+                NasmLineKind::Word(v) => (format!("dw 0x{:04X}", v), String::new()),
+                NasmLineKind::DollarString(v) => (nasm_string(v), String::new()),
+            };
+            if comment.is_empty() {
+                res.push_str(&format!("    {}\n", text));
+            } else {
+                res.push_str(&format!("    {}; {}\n", right_pad(&text, 40), comment));
+            }
+        }
+
+        // memory accesses outside of the program
+        let mut outside: Vec<&SeenAddress> = self.seen_addresses.iter()
+            .filter(|dst| dst.ma.segment() == seg && !dst.sources.sources.is_empty() && dst.sources.only_memory_access())
+            .filter(|dst| u32::from(dst.ma.offset()) < start || u32::from(dst.ma.offset()) >= end)
+            .collect();
+        outside.sort_by_key(|dst| dst.ma.offset());
+        outside.dedup_by_key(|dst| dst.ma.offset());
+        if !outside.is_empty() {
+            res.push('\n');
+        }
+        for dst in outside {
+            res.push_str(&format!("data_{:04X} equ 0x{:04X}\n", dst.ma.offset(), dst.ma.offset()));
+        }
+        res
+    }
+
+    /// returns label names for the referenced addresses between `start` and `end`,
+    /// preferring names from `symbols`
+    fn nasm_label_candidates(&self, seg: u16, start: u32, end: u32, symbols: &SymbolTable) -> HashMap<u16, String> {
+        let mut labels = HashMap::new();
+        for dst in &self.seen_addresses {
+            let ofs = match segment_offset(seg, dst.ma) {
+                Some(ofs) => ofs,
+                None => continue,
+            };
+            if u32::from(ofs) < start || u32::from(ofs) >= end || dst.sources.sources.is_empty() {
+                continue;
+            }
+            let name = if dst.sources.has_code() {
+                if dst.sources.sources.iter().any(|s| s.kind == AddressUsageKind::Call) {
+                    format!("sub_{:04X}", ofs)
+                } else {
+                    format!("loc_{:04X}", ofs)
+                }
+            } else {
+                format!("data_{:04X}", ofs)
+            };
+            labels.insert(ofs, name);
+        }
+        let base = u32::from(seg) << 4;
+        for (addr, name) in symbols.iter() {
+            if addr >= base + start && addr < base + end {
+                labels.insert((addr - base) as u16, nasm_identifier(name));
+            }
+        }
+        labels
+    }
+
+    /// splits the program into lines of code and data. data is split on labels
+    fn nasm_lines(&self, machine: &mut Machine, start: u32, end: u32, labels: &HashMap<u16, String>) -> Vec<NasmLine> {
+        let seg = machine.rom_base.segment();
+        let mut instr_len = HashMap::new();
+        let mut strings = HashMap::new();
+        let mut decoder = Decoder::default();
+        for ab in &self.accounted_bytes {
+            let offset = match segment_offset(seg, ab.address) {
+                Some(offset) => offset,
+                None => continue,
+            };
+            match &ab.kind {
+                GuessedDataType::InstrStart => {
+                    let ii = decoder.get_instruction_info(&mut machine.mmu, ab.address.segment(), ab.address.offset());
+                    instr_len.insert(offset, u32::from(ii.instruction.length));
+                }
+                GuessedDataType::DollarStringStart(v, _) => {
+                    strings.insert(offset, v.len() as u32);
+                }
+                _ => {}
+            }
+        }
+        let word_access: Vec<u16> = self.seen_addresses.iter()
+            .filter(|dst| dst.sources.sources.iter().any(|s| s.kind == AddressUsageKind::MemoryWord))
+            .filter_map(|dst| segment_offset(seg, dst.ma))
+            .collect();
+
+        let mut lines = Vec::new();
+        let mut pos = start;
+        while pos < end {
+            let offset = pos as u16;
+            if let Some(len) = instr_len.get(&offset) {
+                if pos + len <= end {
+                    lines.push(NasmLine{offset, kind: NasmLineKind::Instr});
+                    pos += len;
+                    continue;
+                }
+            }
+
+            // data continues until next label or instruction
+            let mut data_end = pos + 1;
+            while data_end < end && !labels.contains_key(&(data_end as u16)) && !instr_len.contains_key(&(data_end as u16)) {
+                data_end += 1;
+            }
+            let kind = if let Some(len) = strings.get(&offset) {
+                data_end = data_end.min(pos + len);
+                NasmLineKind::DollarString(machine.mmu.read(seg, offset, (data_end - pos) as usize))
+            } else if word_access.contains(&offset) && data_end - pos >= 2 {
+                data_end = pos + 2;
+                NasmLineKind::Word(machine.mmu.read_u16(seg, offset))
+            } else {
+                data_end = data_end.min(pos + 8);
+                NasmLineKind::Bytes(machine.mmu.read(seg, offset, (data_end - pos) as usize))
+            };
+            lines.push(NasmLine{offset, kind});
+            pos = data_end;
+        }
+        lines
+    }
+
+    /// returns the functions of the traced program: the entry point, call targets and
+    /// traced code starting with a "push bp, mov bp,sp" prologue
+    pub fn functions(&self, machine: &mut Machine) -> Vec<TracedFunction> {
+        let mut decoder = Decoder::default();
+        let visited: HashMap<u32, InstructionInfo> = self.visited_addresses.iter()
+            .map(|ma| (ma.value(), decoder.get_instruction_info(&mut machine.mmu, ma.segment(), ma.offset())))
+            .collect();
+
+        // the tracer does not execute the program, so CS:IP is still the entry point
+        let start = MemoryAddress::RealSegmentOffset(machine.cpu.get_r16(R::CS), machine.cpu.regs.ip);
+        let mut entries = vec![start];
+        for dst in &self.seen_addresses {
+            if dst.sources.sources.iter().any(|s| s.kind == AddressUsageKind::Call) {
+                entries.push(dst.ma);
+            }
+        }
+        for ma in &self.visited_addresses {
+            let bytes = &visited[&ma.value()].bytes;
+            if *bytes == [0x55] {
+                // push bp, followed by mov bp,sp
+                let mut next = *ma;
+                next.inc_n(1);
+                if let Some(ii) = visited.get(&next.value()) {
+                    if ii.bytes == [0x8B, 0xEC] || ii.bytes == [0x89, 0xE5] {
+                        entries.push(*ma);
+                    }
+                }
+            }
+        }
+        let mut unique: Vec<MemoryAddress> = Vec::new();
+        for ma in entries {
+            if visited.contains_key(&ma.value()) && !unique.iter().any(|e| e.value() == ma.value()) {
+                unique.push(ma);
+            }
+        }
+        unique.sort_by_key(|ma| ma.value());
+
+        unique.iter().map(|entry| {
+            let name = match machine.symbols.name_at(entry.value()) {
+                Some(name) => name.to_owned(),
+                None if entry.value() == start.value() => "start".to_owned(),
+                None if entry.segment() == machine.rom_base.segment() => format!("sub_{:04X}", entry.offset()),
+                None => format!("sub_{:04X}_{:04X}", entry.segment(), entry.offset()),
+            };
+            let is_entry = |ma: MemoryAddress| ma.value() != entry.value() && unique.iter().any(|e| e.value() == ma.value());
+
+            // walk the instructions reachable from the entry, without following calls
+            let mut func = TracedFunction { entry: *entry, name, instructions: Vec::new(), calls: Vec::new(), loops: Vec::new() };
+            let mut queue = vec![*entry];
+            let mut branches = Vec::new();
+            while let Some(ma) = queue.pop() {
+                if func.instructions.iter().any(|i| i.value() == ma.value()) {
+                    continue;
+                }
+                let ii = match visited.get(&ma.value()) {
+                    Some(ii) => ii,
+                    None => continue,
+                };
+                func.instructions.push(ma);
+                let op = &ii.instruction;
+                if let Some(target) = ii.analysis().branch_target {
+                    let target = MemoryAddress::RealSegmentOffset(target.segment, target.offset);
+                    if op.command == Op::CallNear || op.command == Op::CallFar || is_entry(target) {
+                        if !func.calls.iter().any(|c| c.value() == target.value()) {
+                            func.calls.push(target);
+                        }
+                    } else {
+                        branches.push((ma, target));
+                        queue.push(target);
+                    }
+                }
+                let terminates = op.command == Op::Int && op.params.dst == Parameter::Imm8(0x20);
+                if !op.is_ret() && !op.is_unconditional_jmp() && op.command != Op::Iret && !terminates {
+                    let mut next = ma;
+                    next.inc_n(ii.bytes.len() as u16);
+                    if !is_entry(next) {
+                        queue.push(next);
+                    }
+                }
+            }
+            func.instructions.sort_by_key(|ma| ma.value());
+            for (tail, head) in branches {
+                if head.value() <= tail.value() && func.instructions.iter().any(|i| i.value() == head.value()) {
+                    func.loops.push(TracedLoop { head, tail });
+                }
+            }
+            func.loops.sort_by_key(|l| (l.head.value(), l.tail.value()));
+            func
+        }).collect()
+    }
+
+    /// presents the call graph of the traced program in graphviz DOT format
+    pub fn present_call_graph(&self, machine: &mut Machine) -> String {
+        let functions = self.functions(machine);
+        let mut res = "digraph calls {\n    node [shape=box];\n".to_owned();
+        for func in &functions {
+            let mut label = format!("{}\\n{}", func.name, func.entry);
+            match func.loops.len() {
+                0 => {}
+                1 => label.push_str("\\n1 loop"),
+                n => label.push_str(&format!("\\n{} loops", n)),
+            }
+            res.push_str(&format!("    \"{}\" [label=\"{}\"];\n", func.name, label));
+        }
+        for func in &functions {
+            for call in &func.calls {
+                let callee = match functions.iter().find(|f| f.entry.value() == call.value()) {
+                    Some(f) => f.name.clone(),
+                    None => format!("{}", call),
+                };
+                res.push_str(&format!("    \"{}\" -> \"{}\";\n", func.name, callee));
+            }
+        }
+        res.push_str("}\n");
+        res
+    }
+
     /// returns true if anyone called to given MemoryAddress
     fn is_call_dst(&self, ma: MemoryAddress) -> bool {
         if let Some(sources) = self.get_sources_for_address(ma) {
@@ -693,7 +1030,8 @@ impl ProgramTracer {
                     match kind {
                         Invalid::Op => eprintln!("ERROR: invalid/unhandled op {}", ii.instruction),
                         Invalid::FPUOp => eprintln!("ERROR: invalid/unhandled FPU op {}", ii.instruction),
-                        Invalid::Reg(_) => eprintln!("ERROR: invalid/unhandled reg op {}", ii.instruction)
+                        Invalid::Reg(_) => eprintln!("ERROR: invalid/unhandled reg op {}", ii.instruction),
+                        Invalid::Unsupported => eprintln!("ERROR: unsupported op {}", ii.instruction),
                     }
                 },
                 Op::RetImm16 => panic!("FIXME handle {}", ii.instruction),
@@ -703,7 +1041,9 @@ impl ProgramTracer {
                         Parameter::Imm16(imm) => self.learn_address(ma.segment(), imm, ma, AddressUsageKind::Jump),
                         Parameter::Reg16(_) => {}, // ignore "jmp bx"
                         Parameter::Ptr16(_, _) => {}, // ignore "jmp [0x4422]"
-                        Parameter::Ptr16Imm(_, _) => {}, // ignore "jmp far 0xFFFF:0x0000"
+                        Parameter::Ptr16Imm(seg, imm) => if is_in_rom(machine, seg, imm) {
+                            self.learn_address(seg, imm, ma, AddressUsageKind::Jump)
+                        }, // ignores "jmp far 0xFFFF:0x0000"
                         Parameter::Ptr16Amode(_, _) => {}, // ignore "2EFF27            jmp [cs:bx]"
                         Parameter::Ptr16AmodeS8(_, _, _) => {}, // ignore "jmp [di+0x10]
                         Parameter::Ptr16AmodeS16(_, _, _) => {}, // ignore "jmp [si+0x662C]"
@@ -727,7 +1067,9 @@ impl ProgramTracer {
                     Parameter::Imm16(imm) => self.learn_address(ma.segment(), imm, ma, AddressUsageKind::Call),
                     Parameter::Reg16(_) => {}, // ignore "call bp"
                     Parameter::Ptr16(_, _) => {}, // ignore "call [0x4422]"
-                    Parameter::Ptr16Imm(_, _) => {} // ignore "call 0x4422:0x3050"
+                    Parameter::Ptr16Imm(seg, imm) => if is_in_rom(machine, seg, imm) {
+                        self.learn_address(seg, imm, ma, AddressUsageKind::Call)
+                    },
                     Parameter::Ptr16Amode(_, _) => {}, // ignore "FF1F              call far [bx]"
                     Parameter::Ptr16AmodeS8(_, _, _) => {}, // ignore "call [di+0x10]
                     Parameter::Ptr16AmodeS16(_, _, _) => {}, // ignore "call [bx-0x67A0]"
@@ -962,22 +1304,27 @@ impl ProgramTracer {
                 Op::Imul8 | Op::Imul16 |
                 Op::Idiv8 | Op::Idiv16 |
                 Op::Shl8 | Op::Shl16 | Op::Shld |
+                Op::Setmo8 | Op::Setmo16 |
                 Op::Shr8 | Op::Shr16 | Op::Shrd => {
                     // NOTE: several of these instructions could be simulated,
-                    // but for now just mark dst registers as dirty.
-                    match ii.instruction.params.dst {
-                        Parameter::Reg8(r) | Parameter::Reg16(r) | Parameter::SReg16(r) => {
+                    // but for now just mark the written registers as dirty.
+                    for r in ii.analysis().writes {
+                        if r.is_gpr() {
+                            // 8-bit registers dirty their 16-bit register
+                            self.dirty_regs.dirty_r(analysis::container(r));
+                        } else if matches!(r, R::ES | R::CS | R::SS | R::DS | R::FS | R::GS) {
                             self.dirty_regs.dirty_r(r);
-                            self.annotations.push(TraceAnnotation{ma, note: format!("{} is dirty", r)});
+                        } else {
+                            continue;
                         }
-                        _ => {}
+                        self.annotations.push(TraceAnnotation{ma, note: format!("{} is dirty", r)});
                     }
                 }
                 _ => {}
             }
             ma.inc_n(u16::from(ii.instruction.length));
 
-            if (ma.offset() as isize - machine.rom_base.offset() as isize) >= machine.rom_length as isize {
+            if ma.value() >= machine.rom_base.value() + machine.rom_length as u32 {
                 eprintln!("ERROR: breaking because we reached end of file at {} (indicates incorrect parsing)", ma);
                 break;
             }
@@ -985,15 +1332,6 @@ impl ProgramTracer {
         self.mark_address_visited(start_ma);
     }
 
-    fn video_mode_desc(&self, mode: u8) -> &str {
-        match mode {
-            0x03 => "80x25x16 text",
-            0x11 => "640x480x2",
-            0x13 => "320x200x256",
-            _ => "unrecognized"
-        }
-    }
-
     /// describe out port (write)
     fn out_desc(&self, port: u16) -> &str {
         match port {
@@ -1034,58 +1372,80 @@ impl ProgramTracer {
     }
 
     fn int_desc(&self, int: u8) -> String {
-        let al = self.regs.get_r8(R::AL);
-        let ah = self.regs.get_r8(R::AH);
-        match int {
-            0x10 => { // video
-                match ah {
-                    0x00 => format!("video: set {} mode (0x{:02X})", self.video_mode_desc(al), al),
-                    0x02 => String::from("video: set cursor position"),
-                    0x06 => String::from("video: scroll up"),
-                    0x07 => String::from("video: scroll down"),
-                    0x10 => match al {
-                        0x12 => String::from("video: VIDEO - SET BLOCK OF DAC REGISTERS (VGA/MCGA)"),
-                        _ => format!("video: unrecognized AH = 10, AL = {:02X}", al)
-                    }
-                    0x13 => String::from("video: write string (row=DH, col=DL)"),
-                    _ => format!("video: unrecognized AH = {:02X}", ah)
-                }
-            }
-            0x16 => { // keyboard
-                match ah {
-                    0x00 => String::from("keyboard: read scancode (blocking)"),
-                    0x01 => String::from("keyboard: read scancode (non-blocking)"),
-                    _ => format!("keyboard: unrecognized AH = {:02X}", ah)
-                }
-            }
-            0x1A => { // pit timer
-                match ah {
-                    0x00 => String::from("pit: get system time"),
-                    _ => format!("pit: unrecognized AH = {:02X}", ah)
-                }
-            }
-            0x20 => {
-                String::from("dos: terminate program with return code 0")
-            }
-            0x21 => { // DOS
-                match ah {
-                    0x02 => String::from("dos: write character in DL to stdout"),
-                    0x06 => String::from("dos: write character in DL to DIRECT CONSOLE OUTPUT"),
-                    0x09 => String::from("dos: write $-terminated string at DS:DX to stdout"),
-                    0x4C => String::from("dos: terminate program with return code in AL"),
-                    _ => format!("dos: unrecognized AH = {:02X}", ah)
-                }
-            }
-            0x33 => { // mouse
-                let al = self.regs.get_r8(R::AL);
-                match al {
-                     0x03 => String::from("mouse: get position and button status"),
-                     _ => format!("mouse: unrecognized AL = {:02X}", al)
-                }
-            }
-            _ => {
-                format!("XXX int_desc unrecognized {:02X}", int)
-            },
+        // XXX dirty state is not tracked separately for AH and AL
+        int_desc(int, Some(self.regs.get_r8(R::AH)), Some(self.regs.get_r8(R::AL)))
+    }
+}
+
+/// returns the offset of `ma` relative to `seg`, if reachable from it
+fn segment_offset(seg: u16, ma: MemoryAddress) -> Option<u16> {
+    let base = u32::from(seg) << 4;
+    if ma.value() < base || ma.value() - base > 0xFFFF {
+        return None;
+    }
+    Some((ma.value() - base) as u16)
+}
+
+/// returns true if `seg:offset` is inside the loaded program
+fn is_in_rom(machine: &Machine, seg: u16, offset: u16) -> bool {
+    let addr = MemoryAddress::RealSegmentOffset(seg, offset).value();
+    addr >= machine.rom_base.value() && addr < machine.rom_base.value() + machine.rom_length as u32
+}
+
+/// returns nasm syntax for the relative branch encoded in `bytes`, to `label`
+fn nasm_branch(bytes: &[u8], label: &str) -> Option<String> {
+    const JCC: [&str; 16] = [
+        "jo", "jno", "jc", "jnc", "jz", "jnz", "jna", "ja",
+        "js", "jns", "jpe", "jpo", "jl", "jnl", "jng", "jg",
+    ];
+    let op = match *bytes {
+        [b @ 0x70..=0x7F, _] => format!("{} short", JCC[(b & 0xF) as usize]),
+        [0x0F, b @ 0x80..=0x8F, _, _] => format!("{} near", JCC[(b & 0xF) as usize]),
+        [0xE0, _] => "loopne".to_owned(),
+        [0xE1, _] => "loope".to_owned(),
+        [0xE2, _] => "loop".to_owned(),
+        [0xE3, _] => "jcxz".to_owned(),
+        [0xE8, _, _] => "call".to_owned(),
+        [0xE9, _, _] => "jmp near".to_owned(),
+        [0xEB, _] => "jmp short".to_owned(),
+        _ => return None,
+    };
+    Some(format!("{} {}", op, label))
+}
+
+fn nasm_db(bytes: &[u8]) -> String {
+    let v: Vec<String> = bytes.iter().map(|b| format!("0x{:02X}", b)).collect();
+    format!("db {}", v.join(", "))
+}
+
+/// returns a db directive with printable characters quoted
+fn nasm_string(bytes: &[u8]) -> String {
+    let mut parts = Vec::new();
+    let mut quoted = String::new();
+    for b in bytes {
+        if *b >= 0x20 && *b < 0x7F && *b != b'\'' {
+            quoted.push(*b as char);
+            continue;
+        }
+        if !quoted.is_empty() {
+            parts.push(format!("'{}'", quoted));
+            quoted.clear();
         }
+        parts.push(format!("0x{:02X}", b));
+    }
+    if !quoted.is_empty() {
+        parts.push(format!("'{}'", quoted));
+    }
+    format!("db {}", parts.join(", "))
+}
+
+/// returns `name` as a valid nasm label
+fn nasm_identifier(name: &str) -> String {
+    let mut res: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "_$#@~.?".contains(c) { c } else { '_' })
+        .collect();
+    if res.starts_with(|c: char| c.is_ascii_digit() || c == '$' || c == '#') {
+        res.insert(0, '_');
     }
+    res
 }
@@ -1,5 +1,7 @@
 use crate::machine::Machine;
 use crate::debug::ProgramTracer;
+#[cfg(feature = "ndisasm")]
+use crate::ndisasm::nasm_assemble;
 
 use std::fmt;
 
@@ -476,6 +478,123 @@ fn trace_data_ref() {
 }
 
 
+#[test]
+fn trace_exe_far_call() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        // header
+        b'M', b'Z',
+        0x33, 0x00,             // bytes in last page
+        0x01, 0x00,             // pages
+        0x01, 0x00,             // relocations
+        0x02, 0x00,             // header paragraphs
+        0x00, 0x00,             // min extra paragraphs
+        0xFF, 0xFF,             // max extra paragraphs
+        0x00, 0x00,             // ss
+        0x00, 0x01,             // sp
+        0x00, 0x00,             // checksum
+        0x00, 0x00,             // ip
+        0x00, 0x00,             // cs
+        0x1C, 0x00,             // relocation table offset
+        0x00, 0x00,             // overlay number
+        0x03, 0x00, 0x00, 0x00, // relocation 0000:0003
+
+        // program
+        0x9A, 0x00, 0x00, 0x01, 0x00, // call 0x1:0x0
+        0xB4, 0x4C,             // mov ah,0x4c
+        0xCD, 0x21,             // int 0x21
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0xB0, 0x01,             // mov al,0x1
+        0xCB,                   // retf
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    let mut tracer = ProgramTracer::default();
+    tracer.trace_execution(&mut machine);
+    let res = tracer.present_trace(&mut machine);
+    ass_eq("[086F:0000] 9A00007008       CallFar  0870:0000
+[086F:0005] B44C             Mov8     ah, 0x4C                      ; ah = 0x4C
+[086F:0007] CD21             Int      0x21                          ; dos: terminate program with return code in AL | dirty all regs
+[086F:0009] 00000000         db       0x00, 0x00, 0x00, 0x00
+[086F:000D] 000000           db       0x00, 0x00, 0x00
+[0870:0000] B001             Mov8     al, 0x01                      ; xref: call@086F:0000; al = 0x01
+[0870:0002] CB               Retf
+
+", &res);
+}
+
+/// a program with code, data and labels of each kind
+fn nasm_program() -> Vec<u8> {
+    vec![
+        0x2E, 0xA1, 0x23, 0x01, // mov ax,[cs:0x123]
+        0x2E, 0xA2, 0x00, 0x02, // mov [cs:0x200],al
+        0xBA, 0x1C, 0x01,   // mov dx,0x11c
+        0xB4, 0x09,         // mov ah,0x9
+        0xCD, 0x21,         // int 0x21
+        0xE8, 0x04, 0x00,   // call 0x116
+        0xB4, 0x4C,         // mov ah,0x4c
+        0xCD, 0x21,         // int 0x21
+        0xB1, 0x03,         // mov cl,0x3
+        0x48,               // dec ax
+        0x75, 0xFD,         // jnz 0x118
+        0xC3,               // ret
+        b'H', b'e', b'l', b'l', b'o', b'$',
+        0xFF,
+        0x34, 0x12,
+    ]
+}
+
+#[test]
+fn trace_nasm_output() {
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&nasm_program(), 0x085F);
+
+    let mut tracer = ProgramTracer::default();
+    tracer.trace_execution(&mut machine);
+    let res = tracer.present_nasm(&mut machine);
+    ass_eq("bits 16
+org 0x0100
+    db 0x2E, 0xA1, 0x23, 0x01               ; Mov16    ax, word [cs:0x0123]
+    db 0x2E, 0xA2, 0x00, 0x02               ; Mov8     byte [cs:0x0200], al
+    db 0xBA, 0x1C, 0x01                     ; Mov16    dx, 0x011C ; dx = 0x011C
+    db 0xB4, 0x09                           ; Mov8     ah, 0x09 ; ah = 0x09
+    db 0xCD, 0x21                           ; Int      0x21 ; dos: write $-terminated string at DS:DX to stdout | dirty all regs
+    call sub_0116                           ; CallNear 0x0116
+    db 0xB4, 0x4C                           ; Mov8     ah, 0x4C ; ah = 0x4C
+    db 0xCD, 0x21                           ; Int      0x21 ; dos: terminate program with return code in AL | dirty all regs
+
+sub_0116:
+    db 0xB1, 0x03                           ; Mov8     cl, 0x03 ; cl = 0x03
+
+loc_0118:
+    db 0x48                                 ; Dec16    ax ; ax = 0x4BFF
+    jnz short loc_0118                      ; Jnz      0x0118
+    db 0xC3                                 ; Retn
+
+data_011C:
+    db 'Hello$'
+    db 0xFF
+
+data_0123:
+    dw 0x1234
+
+data_0200 equ 0x0200
+", &res);
+}
+
+#[test]
+#[cfg(feature = "ndisasm")]
+fn trace_nasm_output_assembles_to_original() {
+    let code = nasm_program();
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&code, 0x085F);
+
+    let mut tracer = ProgramTracer::default();
+    tracer.trace_execution(&mut machine);
+    let res = tracer.present_nasm(&mut machine);
+    assert_eq!(code, nasm_assemble(&res).unwrap());
+}
+
 /*
 // BUG:
 games-com-commercial/Pente (1984)(Michael Leach)/pente.com
@@ -529,3 +648,42 @@ ERROR: breaking because we reached end of file at 085F:0210 (indicates incorrect
 ERROR: breaking because we reached end of file at 085F:0214 (indicates incorrect parsing)
 */
 
+
+#[test]
+fn trace_call_graph() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xE8, 0x03, 0x00,   // call 0x106
+        0xCD, 0x20,         // int 0x20
+        0x90,               // nop
+        0x55,               // push bp
+        0x89, 0xE5,         // mov bp,sp
+        0xB9, 0x03, 0x00,   // mov cx,0x3
+        0x49,               // dec cx
+        0x75, 0xFD,         // jnz 0x10c
+        0xE9, 0x00, 0x00,   // jmp 0x112
+        0x5D,               // pop bp
+        0xC3,               // ret
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    let mut tracer = ProgramTracer::default();
+    tracer.trace_execution(&mut machine);
+    let functions = tracer.functions(&mut machine);
+    assert_eq!(2, functions.len());
+    assert_eq!("start", functions[0].name);
+    assert_eq!(2, functions[0].instructions.len());
+    assert_eq!("sub_0106", functions[1].name);
+    assert_eq!(8, functions[1].instructions.len());
+    assert_eq!(1, functions[1].loops.len());
+    assert_eq!(0x010C, functions[1].loops[0].head.offset());
+    assert_eq!(0x010D, functions[1].loops[0].tail.offset());
+
+    ass_eq("digraph calls {
+    node [shape=box];
+    \"start\" [label=\"start\\n085F:0100\"];
+    \"sub_0106\" [label=\"sub_0106\\n085F:0106\\n1 loop\"];
+    \"start\" -> \"sub_0106\";
+}
+", &tracer.present_call_graph(&mut machine));
+}
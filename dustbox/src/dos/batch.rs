@@ -0,0 +1,128 @@
+// batch files run by the shell: their lines, parameters and labels, and the parsing of IF and FOR
+
+#[cfg(test)]
+#[path = "./batch_test.rs"]
+mod batch_test;
+
+/// a running batch file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Batch {
+    lines: Vec<String>,
+
+    /// index of the next line to run
+    next: usize,
+
+    /// %0 (the batch file name) to %9, followed by the parameters brought in by SHIFT
+    params: Vec<String>,
+}
+
+impl Batch {
+    /// returns the batch file with contents `text`, started as `name` with arguments `args`
+    pub fn new(text: &str, name: &str, args: &str) -> Self {
+        let mut params = vec![name.to_owned()];
+        params.extend(args.split_whitespace().map(String::from));
+        Batch {
+            lines: text.lines().map(String::from).collect(),
+            next: 0,
+            params,
+        }
+    }
+
+    /// returns the next line to run, None at the end of the file
+    pub fn next_line(&mut self) -> Option<String> {
+        let line = self.lines.get(self.next)?.clone();
+        self.next += 1;
+        Some(line)
+    }
+
+    /// continues after label `label`, without case. returns false if there is no such label
+    pub fn goto(&mut self, label: &str) -> bool {
+        let label = label.trim_start_matches(':');
+        let found = self.lines.iter().position(|line| {
+            let line = line.trim_start();
+            line.starts_with(':') && line[1..].split_whitespace().next().is_some_and(|l| l.eq_ignore_ascii_case(label))
+        });
+        match found {
+            Some(pos) => {
+                self.next = pos + 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// returns the parameters %0 to %9, missing ones are empty
+    pub fn params(&self) -> Vec<String> {
+        (0..10).map(|i| self.params.get(i).cloned().unwrap_or_default()).collect()
+    }
+
+    /// moves each parameter down by one, %1 becomes %0
+    pub fn shift(&mut self) {
+        if !self.params.is_empty() {
+            self.params.remove(0);
+        }
+    }
+}
+
+/// the condition of an IF command
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    /// the return code of the last program is this number or above
+    ErrorLevel(u8),
+
+    /// file name `name` exists
+    Exist(String),
+
+    /// both strings are the same, with case
+    Equal(String, String),
+}
+
+/// parses the arguments of "IF [NOT] condition command", returns the condition, if it is negated,
+/// and the command
+pub fn parse_if(args: &str) -> Option<(Condition, bool, &str)> {
+    let (word, rest) = split_word(args);
+    let (negated, args) = if word.eq_ignore_ascii_case("NOT") { (true, rest) } else { (false, args.trim_start()) };
+    let (word, rest) = split_word(args);
+    if word.eq_ignore_ascii_case("ERRORLEVEL") {
+        let (level, command) = split_word(rest);
+        return Some((Condition::ErrorLevel(level.parse().ok()?), negated, command));
+    }
+    if word.eq_ignore_ascii_case("EXIST") {
+        let (name, command) = split_word(rest);
+        return Some((Condition::Exist(name.to_owned()), negated, command));
+    }
+    // the strings of "a==b" may be separated from "==" by blanks
+    let pos = args.find("==")?;
+    let (right, command) = split_word(&args[pos + 2..]);
+    Some((Condition::Equal(args[..pos].trim().to_owned(), right.to_owned()), negated, command))
+}
+
+/// parses the arguments of "FOR %v IN (set) DO command", returns the variable name, the words of the set
+/// and the command
+pub fn parse_for(args: &str) -> Option<(char, Vec<String>, &str)> {
+    let (var, rest) = split_word(args);
+    let mut chars = var.chars();
+    let var = match (chars.next(), chars.next(), chars.next()) {
+        (Some('%'), Some(c), None) => c,
+        _ => return None,
+    };
+    let (word, rest) = split_word(rest);
+    if !word.eq_ignore_ascii_case("IN") {
+        return None;
+    }
+    let rest = rest.strip_prefix('(')?;
+    let end = rest.find(')')?;
+    let set = rest[..end].split([' ', '\t', ',', ';']).filter(|w| !w.is_empty()).map(String::from).collect();
+    let (word, command) = split_word(&rest[end + 1..]);
+    if !word.eq_ignore_ascii_case("DO") || command.is_empty() {
+        return None;
+    }
+    Some((var, set, command))
+}
+
+/// returns the first word of `text` and the text following it, without leading blanks
+pub fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    let end = text.find(char::is_whitespace).unwrap_or(text.len());
+    (&text[..end], text[end..].trim_start())
+}
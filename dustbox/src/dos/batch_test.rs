@@ -0,0 +1,41 @@
+use crate::dos::batch::{parse_for, parse_if, Batch, Condition};
+
+#[test]
+fn batch_jumps_to_labels() {
+    let mut batch = Batch::new("@echo off\r\ngoto Start\r\n:skipped\r\necho no\r\n:START\r\necho yes\r\n", "GO.BAT", "");
+    assert_eq!(Some("@echo off".to_owned()), batch.next_line());
+    assert!(batch.goto("start"));
+    assert_eq!(Some("echo yes".to_owned()), batch.next_line());
+    assert_eq!(None, batch.next_line());
+    assert!(batch.goto(":SKIPPED"));
+    assert_eq!(Some("echo no".to_owned()), batch.next_line());
+    assert!(!batch.goto("missing"));
+}
+
+#[test]
+fn batch_shifts_parameters() {
+    let mut batch = Batch::new("", "setup", "  sb 220  5 ");
+    let params = batch.params();
+    assert_eq!(10, params.len());
+    assert_eq!(vec!["setup", "sb", "220", "5", ""], params[..5].to_vec());
+    batch.shift();
+    assert_eq!(vec!["sb", "220", "5", ""], batch.params()[..4].to_vec());
+}
+
+#[test]
+fn parses_if_conditions() {
+    assert_eq!(Some((Condition::ErrorLevel(2), false, "goto end")), parse_if("errorlevel 2 goto end"));
+    assert_eq!(Some((Condition::Exist("C:\\DOOM.WAD".to_owned()), true, "echo missing")), parse_if("NOT EXIST C:\\DOOM.WAD echo missing"));
+    assert_eq!(Some((Condition::Equal("sb".to_owned(), "sb".to_owned()), false, "set card=sb")), parse_if("sb == sb set card=sb"));
+    assert_eq!(Some((Condition::Equal("\"\"".to_owned(), "\"\"".to_owned()), true, "goto usage")), parse_if("not \"\" == \"\" goto usage"));
+    assert_eq!(None, parse_if("errorlevel x goto end"));
+    assert_eq!(None, parse_if("a = b echo"));
+}
+
+#[test]
+fn parses_for_commands() {
+    let set = vec!["*.txt".to_owned(), "a".to_owned(), "b".to_owned()];
+    assert_eq!(Some(('f', set, "type %f")), parse_for("%f in (*.txt a,b) do type %f"));
+    assert_eq!(None, parse_for("%f in (a b) type %f"));
+    assert_eq!(None, parse_for("f in (a) do echo f"));
+}
@@ -0,0 +1,267 @@
+use log::warn;
+
+use crate::bios::BIOS;
+use crate::memory::MMU;
+
+#[cfg(test)]
+#[path = "./console_test.rs"]
+mod console_test;
+
+/// result of passing a console output byte through `AnsiFilter`
+#[derive(Debug, PartialEq)]
+pub enum AnsiOutput {
+    /// characters to display
+    Text(Vec<u8>),
+
+    /// a complete escape sequence, such as ESC[2J
+    Sequence(Vec<u8>),
+
+    /// the byte is part of an unfinished escape sequence
+    Pending,
+}
+
+/// max length of an escape sequence, longer sequences are displayed as text
+const MAX_SEQUENCE_LENGTH: usize = 32;
+
+/// separates ANSI escape sequences from console output, as done by ANSI.SYS
+#[derive(Clone, Default)]
+pub struct AnsiFilter {
+    sequence: Vec<u8>,
+}
+
+impl AnsiFilter {
+    pub fn push(&mut self, b: u8) -> AnsiOutput {
+        match self.sequence.len() {
+            0 if b == 0x1B => {
+                self.sequence.push(b);
+                return AnsiOutput::Pending;
+            }
+            0 => return AnsiOutput::Text(vec![b]),
+            1 if b != b'[' => {
+                self.sequence.push(b);
+                return AnsiOutput::Text(self.sequence.split_off(0));
+            }
+            _ => {}
+        }
+        self.sequence.push(b);
+        if self.sequence.len() > 2 && (0x40..=0x7E).contains(&b) {
+            AnsiOutput::Sequence(self.sequence.split_off(0))
+        } else if self.sequence.len() >= MAX_SEQUENCE_LENGTH {
+            AnsiOutput::Text(self.sequence.split_off(0))
+        } else {
+            AnsiOutput::Pending
+        }
+    }
+}
+
+/// text attribute of the console: light gray on black
+const DEFAULT_ATTRIBUTE: u8 = 0x07;
+
+/// maps ANSI color numbers (black, red, green, yellow, blue, magenta, cyan, white) to CGA colors
+const ANSI_COLORS: [u8; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
+
+/// the DOS console device, writing to the text mode video buffer.
+/// interprets ANSI escape sequences if ANSI.SYS is loaded
+#[derive(Clone)]
+pub struct Console {
+    /// set when ANSI.SYS is loaded
+    pub ansi: Option<AnsiFilter>,
+
+    /// attribute of written characters, changed by ESC[...m
+    attribute: u8,
+
+    /// row and column saved by ESC[s
+    saved_cursor: (u16, u16),
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Console {
+            ansi: None,
+            attribute: DEFAULT_ATTRIBUTE,
+            saved_cursor: (0, 0),
+        }
+    }
+}
+
+/// position and size of the active text page, read from the BIOS data area
+struct TextScreen {
+    segment: u16,
+    start: u16,
+    page: u8,
+    cols: u16,
+    rows: u16,
+}
+
+impl TextScreen {
+    /// returns None in graphic modes
+    fn current(mmu: &MMU) -> Option<Self> {
+        let segment = match mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_CURRENT_MODE) {
+            0..=3 => 0xB800,
+            7 => 0xB000,
+            _ => return None,
+        };
+        Some(TextScreen {
+            segment,
+            start: mmu.read_u16(BIOS::DATA_SEG, BIOS::DATA_CURRENT_START),
+            page: mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_CURRENT_PAGE),
+            cols: mmu.read_u16(BIOS::DATA_SEG, BIOS::DATA_NB_COLS),
+            rows: u16::from(mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_NB_ROWS)) + 1,
+        })
+    }
+
+    fn offset(&self, row: u16, col: u16) -> u16 {
+        self.start + (row * self.cols + col) * 2
+    }
+
+    /// returns row, column
+    fn cursor(&self, mmu: &MMU) -> (u16, u16) {
+        let ofs = BIOS::DATA_CURSOR_POS + u16::from(self.page) * 2;
+        (u16::from(mmu.read_u8(BIOS::DATA_SEG, ofs + 1)), u16::from(mmu.read_u8(BIOS::DATA_SEG, ofs)))
+    }
+
+    fn set_cursor(&self, mmu: &mut MMU, row: u16, col: u16) {
+        let ofs = BIOS::DATA_CURSOR_POS + u16::from(self.page) * 2;
+        mmu.write_u8(BIOS::DATA_SEG, ofs, col.min(self.cols - 1) as u8);
+        mmu.write_u8(BIOS::DATA_SEG, ofs + 1, row.min(self.rows - 1) as u8);
+    }
+
+    /// fills from (row, col) to the end position (inclusive) with blanks
+    fn clear(&self, mmu: &mut MMU, from: (u16, u16), to: (u16, u16), attr: u8) {
+        let (start, end) = (self.offset(from.0, from.1), self.offset(to.0, to.1));
+        for offset in (start..=end).step_by(2) {
+            mmu.write_u8(self.segment, offset, b' ');
+            mmu.write_u8(self.segment, offset + 1, attr);
+        }
+    }
+
+    fn scroll_up(&self, mmu: &mut MMU, attr: u8) {
+        let line = self.cols * 2;
+        let data = mmu.read(self.segment, self.offset(1, 0), usize::from(line * (self.rows - 1)));
+        mmu.write(self.segment, self.start, &data);
+        self.clear(mmu, (self.rows - 1, 0), (self.rows - 1, self.cols - 1), attr);
+    }
+}
+
+impl Console {
+    pub fn ansi_installed(&self) -> bool {
+        self.ansi.is_some()
+    }
+
+    /// clears the text screen and moves the cursor to the top left corner
+    pub fn clear_screen(&self, mmu: &mut MMU) {
+        if let Some(screen) = TextScreen::current(mmu) {
+            screen.clear(mmu, (0, 0), (screen.rows - 1, screen.cols - 1), self.attribute);
+            screen.set_cursor(mmu, 0, 0);
+        }
+    }
+
+    /// writes a byte to the screen. returns the byte, or the sequence it completes
+    pub fn write(&mut self, mmu: &mut MMU, b: u8) -> AnsiOutput {
+        let output = match &mut self.ansi {
+            None => AnsiOutput::Text(vec![b]),
+            Some(ansi) => ansi.push(b),
+        };
+        if let Some(screen) = TextScreen::current(mmu) {
+            match &output {
+                AnsiOutput::Text(text) => {
+                    for &c in text {
+                        self.put_char(mmu, &screen, c);
+                    }
+                }
+                AnsiOutput::Sequence(sequence) => self.execute(mmu, &screen, sequence),
+                AnsiOutput::Pending => {}
+            }
+        }
+        output
+    }
+
+    fn put_char(&mut self, mmu: &mut MMU, screen: &TextScreen, c: u8) {
+        let (mut row, mut col) = screen.cursor(mmu);
+        match c {
+            0x07 => {} // bell
+            0x08 => col = col.saturating_sub(1),
+            b'\r' => col = 0,
+            b'\n' => row += 1,
+            b'\t' => {
+                for _ in col % 8..8 {
+                    self.put_char(mmu, screen, b' ');
+                }
+                return;
+            }
+            _ => {
+                let offset = screen.offset(row, col);
+                mmu.write_u8(screen.segment, offset, c);
+                if self.ansi_installed() {
+                    // without ANSI.SYS the attribute on screen is kept, as with INT 10/AH=0Eh
+                    mmu.write_u8(screen.segment, offset + 1, self.attribute);
+                }
+                col += 1;
+            }
+        }
+        if col >= screen.cols {
+            col = 0;
+            row += 1;
+        }
+        if row >= screen.rows {
+            screen.scroll_up(mmu, self.attribute);
+            row = screen.rows - 1;
+        }
+        screen.set_cursor(mmu, row, col);
+    }
+
+    /// executes a ESC[ sequence
+    /// http://www.ctyme.com/intr/rb-2727.htm
+    fn execute(&mut self, mmu: &mut MMU, screen: &TextScreen, sequence: &[u8]) {
+        let command = sequence[sequence.len() - 1];
+        let params: Vec<Option<u16>> = String::from_utf8_lossy(&sequence[2..sequence.len() - 1])
+            .split(';')
+            .map(|p| p.parse().ok())
+            .collect();
+        let param = |i: usize, default: u16| params.get(i).cloned().flatten().unwrap_or(default);
+        let (row, col) = screen.cursor(mmu);
+        let (last_row, last_col) = (screen.rows - 1, screen.cols - 1);
+        match command {
+            b'A' => screen.set_cursor(mmu, row.saturating_sub(param(0, 1)), col),
+            b'B' => screen.set_cursor(mmu, row + param(0, 1), col),
+            b'C' => screen.set_cursor(mmu, row, col + param(0, 1)),
+            b'D' => screen.set_cursor(mmu, row, col.saturating_sub(param(0, 1))),
+            b'H' | b'f' => screen.set_cursor(mmu, param(0, 1).max(1) - 1, param(1, 1).max(1) - 1),
+            b'J' => match param(0, 0) {
+                0 => screen.clear(mmu, (row, col), (last_row, last_col), self.attribute),
+                1 => screen.clear(mmu, (0, 0), (row, col), self.attribute),
+                _ => {
+                    screen.clear(mmu, (0, 0), (last_row, last_col), self.attribute);
+                    screen.set_cursor(mmu, 0, 0);
+                }
+            },
+            b'K' => match param(0, 0) {
+                0 => screen.clear(mmu, (row, col), (row, last_col), self.attribute),
+                1 => screen.clear(mmu, (row, 0), (row, col), self.attribute),
+                _ => screen.clear(mmu, (row, 0), (row, last_col), self.attribute),
+            },
+            b'm' => {
+                for i in 0..params.len() {
+                    self.select_graphic_rendition(param(i, 0));
+                }
+            }
+            b's' => self.saved_cursor = (row, col),
+            b'u' => screen.set_cursor(mmu, self.saved_cursor.0, self.saved_cursor.1),
+            _ => warn!(target: "dos", "XXX ANSI.SYS: unhandled sequence ESC{}", String::from_utf8_lossy(&sequence[1..])),
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, n: u16) {
+        let attr = self.attribute;
+        self.attribute = match n {
+            0 => DEFAULT_ATTRIBUTE,
+            1 => attr | 0x08,                                   // bold
+            5 => attr | 0x80,                                   // blink
+            7 => (attr & 0x88) | ((attr & 0x07) << 4) | ((attr & 0x70) >> 4), // reverse
+            8 => (attr & 0xF0) | ((attr & 0x70) >> 4),          // concealed
+            30..=37 => (attr & 0xF8) | ANSI_COLORS[usize::from(n - 30)],
+            40..=47 => (attr & 0x8F) | (ANSI_COLORS[usize::from(n - 40)] << 4),
+            _ => attr,
+        };
+    }
+}
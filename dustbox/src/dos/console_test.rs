@@ -0,0 +1,63 @@
+use crate::bios::BIOS;
+use crate::dos::{AnsiFilter, AnsiOutput, Console};
+use crate::machine::Machine;
+use crate::memory::MMU;
+
+#[test]
+fn can_filter_ansi_sequences() {
+    let mut ansi = AnsiFilter::default();
+    let mut text = Vec::new();
+    let mut sequences = Vec::new();
+    for &b in b"a\x1B[1;31mb\x1B[2Jc\x1Bd" {
+        match ansi.push(b) {
+            AnsiOutput::Text(t) => text.extend(t),
+            AnsiOutput::Sequence(s) => sequences.push(s),
+            AnsiOutput::Pending => {}
+        }
+    }
+    assert_eq!(b"abc\x1Bd".to_vec(), text);
+    assert_eq!(vec![b"\x1B[1;31m".to_vec(), b"\x1B[2J".to_vec()], sequences);
+}
+
+fn write(console: &mut Console, mmu: &mut MMU, text: &[u8]) {
+    for &b in text {
+        console.write(mmu, b);
+    }
+}
+
+/// returns character and attribute at row, col of the 80x25 text screen
+fn screen_cell(mmu: &MMU, row: u16, col: u16) -> (u8, u8) {
+    let offset = (row * 80 + col) * 2;
+    (mmu.read_u8(0xB800, offset), mmu.read_u8(0xB800, offset + 1))
+}
+
+#[test]
+fn can_write_ansi_sequences_to_text_screen() {
+    let mut machine = Machine::deterministic();
+    let mut console = Console { ansi: Some(AnsiFilter::default()), ..Console::default() };
+    write(&mut console, &mut machine.mmu, b"\x1B[2J\x1B[1;31mA\x1B[44m\x1B[3;5HB\x1B[0m\x1B[2DC");
+    assert_eq!((b'A', 0x0C), screen_cell(&machine.mmu, 0, 0));
+    assert_eq!((b'B', 0x1C), screen_cell(&machine.mmu, 2, 4));
+    assert_eq!((b'C', 0x07), screen_cell(&machine.mmu, 2, 3));
+    assert_eq!(2, machine.mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_CURSOR_POS + 1));
+    assert_eq!(4, machine.mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_CURSOR_POS));
+
+    write(&mut console, &mut machine.mmu, b"\x1B[K");
+    assert_eq!((b' ', 0x07), screen_cell(&machine.mmu, 2, 4));
+}
+
+#[test]
+fn can_scroll_text_screen() {
+    let mut machine = Machine::deterministic();
+    let mut console = Console::default();
+    write(&mut console, &mut machine.mmu, b"first\r\n\x1B[2J");
+    assert_eq!(b'f', screen_cell(&machine.mmu, 0, 0).0);
+    // without ANSI.SYS the escape sequence is displayed
+    assert_eq!(0x1B, screen_cell(&machine.mmu, 1, 0).0);
+
+    for _ in 0..24 {
+        write(&mut console, &mut machine.mmu, b"\r\n");
+    }
+    assert_eq!(0x1B, screen_cell(&machine.mmu, 0, 0).0);
+    assert_eq!(24, machine.mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_CURSOR_POS + 1));
+}
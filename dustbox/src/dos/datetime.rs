@@ -0,0 +1,37 @@
+use std::time::SystemTime;
+
+use chrono::prelude::*;
+
+#[cfg(test)]
+#[path = "./datetime_test.rs"]
+mod datetime_test;
+
+/// returns `date` in the packed DOS format used by file timestamps:
+/// bits 15-9 year - 1980, bits 8-5 month, bits 4-0 day
+pub fn pack_dos_date(date: NaiveDate) -> u16 {
+    let year = (date.year() - 1980).clamp(0, 127) as u16;
+    (year << 9) | ((date.month() as u16) << 5) | date.day() as u16
+}
+
+/// returns `time` in the packed DOS format used by file timestamps:
+/// bits 15-11 hour, bits 10-5 minute, bits 4-0 second / 2
+pub fn pack_dos_time(time: NaiveTime) -> u16 {
+    ((time.hour() as u16) << 11) | ((time.minute() as u16) << 5) | (time.second() as u16 / 2)
+}
+
+/// returns the date and time of a packed DOS file timestamp, or None if it is invalid
+pub fn unpack_dos_date_time(date: u16, time: u16) -> Option<NaiveDateTime> {
+    let date = NaiveDate::from_ymd_opt(1980 + i32::from(date >> 9), u32::from((date >> 5) & 0xF), u32::from(date & 0x1F))?;
+    let time = NaiveTime::from_hms_opt(u32::from(time >> 11), u32::from((time >> 5) & 0x3F), u32::from(time & 0x1F) * 2)?;
+    Some(date.and_time(time))
+}
+
+/// returns a host file time as local time
+pub fn host_local_time(time: SystemTime) -> NaiveDateTime {
+    DateTime::<Local>::from(time).naive_local()
+}
+
+/// returns the host file time of a local time
+pub fn local_host_time(time: NaiveDateTime) -> Option<SystemTime> {
+    Local.from_local_datetime(&time).earliest().map(SystemTime::from)
+}
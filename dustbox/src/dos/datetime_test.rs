@@ -0,0 +1,17 @@
+use chrono::NaiveDate;
+
+use crate::dos::{pack_dos_date, pack_dos_time, unpack_dos_date_time, host_local_time, local_host_time};
+
+#[test]
+fn can_pack_file_timestamps() {
+    let t = NaiveDate::from_ymd(1994, 12, 31).and_hms(23, 59, 58);
+    let (date, time) = (pack_dos_date(t.date()), pack_dos_time(t.time()));
+    assert_eq!(0x1D9F, date);
+    assert_eq!(0xBF7D, time);
+    assert_eq!(Some(t), unpack_dos_date_time(date, time));
+
+    assert_eq!(None, unpack_dos_date_time(0x1D9F, 0xC000)); // hour 24
+
+    let host = local_host_time(t).unwrap();
+    assert_eq!(t, host_local_time(host));
+}
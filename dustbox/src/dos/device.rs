@@ -0,0 +1,162 @@
+use std::io;
+
+use crate::memory::MMU;
+
+#[cfg(test)]
+#[path = "./device_test.rs"]
+mod device_test;
+
+/// the device is a character device
+pub const ATTR_CHARACTER: u16 = 0x8000;
+
+/// the device supports IOCTL
+pub const ATTR_IOCTL: u16 = 0x4000;
+
+/// character device: the device is the clock device
+pub const ATTR_CLOCK: u16 = 0x0008;
+
+/// character device: the device is the NUL device
+pub const ATTR_NUL: u16 = 0x0004;
+
+/// character device: the device is the standard output
+pub const ATTR_STDOUT: u16 = 0x0002;
+
+/// character device: the device is the standard input
+pub const ATTR_STDIN: u16 = 0x0001;
+
+/// marks the last driver of the chain, in the offset of `DeviceHeader.next`
+pub const END_OF_CHAIN: u16 = 0xFFFF;
+
+// IOCTL device information word of a handle, returned by INT 21/AX=4400h
+// http://www.ctyme.com/intr/rb-2820.htm#Table1423
+
+/// the handle refers to a character device, else to a file
+pub const INFO_CHARACTER: u16 = 0x0080;
+
+/// character device: end of file on input not reached. file: the file has not been written
+pub const INFO_NOT_EOF: u16 = 0x0040;
+
+/// character device: binary (raw) mode, input is not checked for ^C and ^Z
+pub const INFO_BINARY: u16 = 0x0020;
+
+/// character device: output is written with INT 29h
+pub const INFO_SPECIAL: u16 = 0x0010;
+
+/// character device: the device is the standard output
+pub const INFO_STDOUT: u16 = 0x0002;
+
+/// character device: the device is the standard input
+pub const INFO_STDIN: u16 = 0x0001;
+
+/// file: bits 0-5 hold the drive number, 0 = A:
+pub const INFO_DRIVE_MASK: u16 = 0x003F;
+
+/// header in front of each DOS device driver, linking the driver chain
+/// http://www.ctyme.com/intr/rb-2990.htm#Table1642
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceHeader {
+    /// segment and offset of the next driver, offset FFFFh if last driver
+    pub next: (u16, u16),
+
+    pub attributes: u16,
+
+    /// offset of the strategy routine
+    pub strategy: u16,
+
+    /// offset of the interrupt routine
+    pub interrupt: u16,
+
+    /// blank padded device name for character devices, number of units for block devices
+    pub name: [u8; 8],
+}
+
+impl DeviceHeader {
+    pub const LENGTH: usize = 18;
+
+    /// returns a character device header, last in the chain
+    pub fn character(name: &str, attributes: u16, routine: u16) -> Self {
+        let mut padded = [b' '; 8];
+        for (dst, src) in padded.iter_mut().zip(name.bytes()) {
+            *dst = src.to_ascii_uppercase();
+        }
+        DeviceHeader {
+            next: (END_OF_CHAIN, END_OF_CHAIN),
+            attributes: attributes | ATTR_CHARACTER,
+            strategy: routine,
+            interrupt: routine,
+            name: padded,
+        }
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() < Self::LENGTH {
+            return None;
+        }
+        let word = |i: usize| u16::from(data[i]) | u16::from(data[i + 1]) << 8;
+        let mut name = [0; 8];
+        name.copy_from_slice(&data[10..18]);
+        Some(DeviceHeader {
+            next: (word(2), word(0)),
+            attributes: word(4),
+            strategy: word(6),
+            interrupt: word(8),
+            name,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(Self::LENGTH);
+        for word in &[self.next.1, self.next.0, self.attributes, self.strategy, self.interrupt] {
+            data.extend_from_slice(&word.to_le_bytes());
+        }
+        data.extend_from_slice(&self.name);
+        data
+    }
+
+    pub fn read(mmu: &MMU, segment: u16, offset: u16) -> Self {
+        Self::from_bytes(&mmu.read(segment, offset, Self::LENGTH)).unwrap()
+    }
+
+    pub fn write(&self, mmu: &mut MMU, segment: u16, offset: u16) {
+        mmu.write(segment, offset, &self.to_bytes());
+    }
+
+    pub fn is_character_device(&self) -> bool {
+        self.attributes & ATTR_CHARACTER != 0
+    }
+
+    pub fn is_last(&self) -> bool {
+        self.next.1 == END_OF_CHAIN
+    }
+
+    /// returns the device name without padding, or the number of units of a block device
+    pub fn name(&self) -> String {
+        if self.is_character_device() {
+            self.name.iter().map(|&b| b as char).collect::<String>().trim_end().to_owned()
+        } else {
+            format!("{} units", self.name[0])
+        }
+    }
+}
+
+/// parses the device headers of a .SYS driver file, returns the offset of each header in the file
+pub fn parse_driver(data: &[u8]) -> io::Result<Vec<(u16, DeviceHeader)>> {
+    let mut headers = Vec::new();
+    let mut offset = 0;
+    loop {
+        let header = match data.get(offset as usize..).and_then(DeviceHeader::from_bytes) {
+            Some(header) => header,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("device header at {:04X} is outside of the file", offset))),
+        };
+        let next = header.next.1;
+        let last = header.is_last();
+        headers.push((offset, header));
+        if last {
+            return Ok(headers);
+        }
+        if headers.iter().any(|(o, _)| *o == next) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "device headers form a loop"));
+        }
+        offset = next;
+    }
+}
@@ -0,0 +1,32 @@
+use crate::dos::{parse_driver, DeviceHeader, ATTR_CHARACTER, END_OF_CHAIN};
+
+#[test]
+fn can_parse_device_headers() {
+    // two character devices in one driver file, the first links to the second at offset 0x20
+    let mut data = vec![
+        0x20, 0x00, 0x00, 0x00, 0x00, 0x80, 0x12, 0x00, 0x13, 0x00, b'D', b'E', b'V', b'1', b' ', b' ', b' ', b' ',
+    ];
+    data.resize(0x20, 0xCB);
+    data.extend_from_slice(&[
+        0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x80, 0x12, 0x00, 0x13, 0x00, b'D', b'E', b'V', b'2', b' ', b' ', b' ', b' ',
+    ]);
+
+    let headers = parse_driver(&data).unwrap();
+    assert_eq!(2, headers.len());
+    assert_eq!(0x20, headers[1].0);
+    assert_eq!("DEV1", headers[0].1.name());
+    assert_eq!("DEV2", headers[1].1.name());
+    assert_eq!(ATTR_CHARACTER, headers[0].1.attributes);
+    assert_eq!(0x0012, headers[0].1.strategy);
+    assert!(headers[1].1.is_last());
+    assert_eq!(&data[..DeviceHeader::LENGTH], DeviceHeader::from_bytes(&data).unwrap().to_bytes().as_slice());
+
+    let header = DeviceHeader::character("con", 0, 0x0100);
+    assert_eq!((END_OF_CHAIN, END_OF_CHAIN), header.next);
+    assert_eq!(*b"CON     ", header.name);
+
+    // truncated files and headers pointing at themselves are rejected
+    assert!(parse_driver(&data[..0x10]).is_err());
+    data[0] = 0x00;
+    assert!(parse_driver(&data).is_err());
+}
@@ -1,17 +1,45 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
+
 use chrono::prelude::*;
+use log::{debug, info, warn};
+use rand::RngCore;
 
-use crate::cpu::R;
-use crate::codepage::cp437;
+use crate::bios::BIOS;
+use crate::clock::Clock;
+use crate::cpu::{R, FLAG_CF, FLAG_ZF};
+use crate::codepage::{cp437, Codepage, CountryInfo};
 use crate::cpu::CPU;
 use crate::memory::MMU;
 use crate::memory::MemoryAddress;
 use crate::hex::hex_bytes;
 use crate::string::bytes_to_ascii;
 use crate::machine::Component;
+use crate::storage::{path_drive, DiskGeometry, MountKind, MountTable};
+use crate::dos::device::*;
+use crate::dos::console::{AnsiFilter, AnsiOutput, Console};
+use crate::dos::datetime::{host_local_time, local_host_time, pack_dos_date, pack_dos_time, unpack_dos_date_time};
+use crate::dos::version::DosVersion;
+use crate::dos::lfn::{file_time, find_entry, list_dir, wildcard_match, DirEntry, FILE_ATTR_DIRECTORY, FILE_ATTR_HIDDEN, FILE_ATTR_SYSTEM};
+
+/// a program started by INT 21/AH=4Bh, for the machine to load
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecRequest {
+    /// host path of the program
+    pub path: PathBuf,
+
+    /// full DOS name of the program, such as "C:\GAMES\DOOM.EXE"
+    pub name: String,
+
+    /// command tail, such as " -warp 1"
+    pub tail: String,
+
+    /// segment of the environment given to the program, 0 for the one of the parent
+    pub env_segment: u16,
+}
 
 #[derive(Clone)]
 pub struct DOS {
@@ -22,6 +50,54 @@ pub struct DOS {
     pub file_handles: HashMap<u16, PathBuf>,
 
     pub psp_segment: u16,
+
+    /// host directories and disk images mounted as DOS drives
+    pub drives: MountTable,
+
+    /// characters written to standard output
+    pub stdout: Vec<u8>,
+
+    /// input of the standard input, when redirected from a buffer instead of the keyboard
+    stdin: Option<VecDeque<u8>>,
+
+    /// return code of the terminated program
+    pub exit_code: Option<u8>,
+
+    /// return code of the last program started by EXEC, returned by INT 21/AH=4Dh
+    pub child_exit_code: u8,
+
+    /// program started by EXEC, taken by the machine
+    exec_request: Option<ExecRequest>,
+
+    /// the CON device
+    console: Console,
+
+    /// segment where the next device driver is loaded
+    next_driver_segment: u16,
+
+    /// active code page, used for console output
+    pub codepage: Codepage,
+
+    /// country code, selecting the formats returned by INT 21/AH=38h
+    pub country: u16,
+
+    /// the current directory, such as "C:\GAMES". names without drive are relative to the program directory
+    pub current_dir: String,
+
+    /// remaining entries of the long filename searches, by search handle
+    find_searches: HashMap<u16, Vec<DirEntry>>,
+
+    /// DOS version reported by INT 21/AH=30h
+    pub version: DosVersion,
+
+    /// versions reported to specific programs by file name, such as "TC.EXE", as set by SETVER.EXE
+    pub setver: HashMap<String, DosVersion>,
+
+    /// extended ^C/^Break checking, set by INT 21/AX=3301h
+    break_check: bool,
+
+    /// IOCTL device information of the standard handles: stdin, stdout, stderr, stdaux and stdprn
+    std_device_info: [u16; 5],
 }
 
 impl DOS {
@@ -30,7 +106,726 @@ impl DOS {
             program_path: String::new(),
             file_handles: HashMap::new(),
             psp_segment: 0,
+            drives: MountTable::default(),
+            stdout: Vec::new(),
+            stdin: None,
+            exit_code: None,
+            child_exit_code: 0,
+            exec_request: None,
+            console: Console::default(),
+            next_driver_segment: Self::DRIVER_SEG,
+            codepage: Codepage::default(),
+            country: 1,
+            current_dir: String::new(),
+            find_searches: HashMap::new(),
+            version: DosVersion::default(),
+            setver: HashMap::new(),
+            break_check: false,
+            std_device_info: Self::STD_DEVICE_INFO,
+        }
+    }
+
+    /// IOCTL device information of the standard handles: CON, CON, CON, AUX and PRN
+    const STD_DEVICE_INFO: [u16; 5] = [
+        INFO_CHARACTER | INFO_NOT_EOF | INFO_SPECIAL | INFO_STDOUT | INFO_STDIN,
+        INFO_CHARACTER | INFO_NOT_EOF | INFO_SPECIAL | INFO_STDOUT | INFO_STDIN,
+        INFO_CHARACTER | INFO_NOT_EOF | INFO_SPECIAL | INFO_STDOUT | INFO_STDIN,
+        INFO_CHARACTER | INFO_NOT_EOF,
+        INFO_CHARACTER | INFO_NOT_EOF,
+    ];
+
+    /// interrupts handled by DOS: divide error, terminate, the function dispatcher, ctrl-break and
+    /// critical error handlers, TSR, fast console output and the multiplex interrupt
+    pub const INTERRUPTS: &[u8] = &[0x00, 0x20, 0x21, 0x23, 0x24, 0x27, 0x29, 0x2F];
+
+    /// segment of the DOS kernel data: list of lists and built-in device drivers
+    pub const DATA_SEG: u16 = 0x0070;
+
+    /// offset of the list of lists, returned by INT 21/AH=52h
+    pub const LIST_OF_LISTS: u16 = 0x0026;
+
+    /// offset of the NUL device header, first in the driver chain
+    pub const NUL_DEVICE: u16 = Self::LIST_OF_LISTS + 0x22;
+
+    /// offset of the emulated ANSI.SYS device header
+    const ANSI_DEVICE: u16 = 0x00C0;
+
+    /// offset of the strategy and interrupt routine of the built-in drivers
+    const DRIVER_ROUTINE: u16 = 0x00E0;
+
+    /// offset of the case map routine, upper casing AL for characters 80h to FFh
+    const CASE_MAP: u16 = 0x00F0;
+
+    /// offset of the upper case table: table size (word) followed by upper case of characters 80h to FFh
+    const UPPER_CASE_TABLE: u16 = 0x0100;
+
+    /// offset of the (empty) table of DBCS lead byte ranges
+    const DBCS_TABLE: u16 = 0x0190;
+
+    /// offset of the media ID byte returned by INT 21/AH=1Bh and 1Ch
+    const MEDIA_ID: u16 = 0x0198;
+
+    /// offset of the routine issuing INT 23h on ^C / ^Break, restarting the interrupted INT 21h call
+    /// unless the handler returns with RETF and CF set
+    const BREAK_ROUTINE: u16 = 0x01A0;
+
+    /// offset of the routine issuing INT 24h on a critical error, acting on the action returned in AL
+    const CRITICAL_ROUTINE: u16 = 0x01C0;
+
+    /// offset of the data of the break and critical error routines: SP before INT 23h (word),
+    /// AX, DI, BP and SI passed to INT 24h (words) and the action returned by INT 24h (byte)
+    const HANDLER_DATA: u16 = 0x0210;
+
+    /// first and last segment where device drivers are loaded, below the program environment
+    const DRIVER_SEG: u16 = 0x0100;
+    const DRIVER_SEG_END: u16 = 0x02E3;
+
+    /// writes the list of lists, and the chain of built-in device drivers: NUL, CON, AUX, PRN and CLOCK$
+    /// http://www.ctyme.com/intr/rb-2983.htm
+    pub fn init_drivers(&mut self, mmu: &mut MMU) {
+        let lol = Self::LIST_OF_LISTS;
+        mmu.write_u16(Self::DATA_SEG, lol - 2, 0);          // segment of first memory control block
+        for offset in &[0x00, 0x04, 0x12, 0x16, 0x1A] {
+            // DOS parameter blocks, file tables, disk buffers, current directories and FCBs are not emulated
+            mmu.write_u16(Self::DATA_SEG, lol + offset, 0xFFFF);
+            mmu.write_u16(Self::DATA_SEG, lol + offset + 2, 0xFFFF);
+        }
+        mmu.write_u16(Self::DATA_SEG, lol + 0x10, 512);     // maximum bytes per sector of any block device
+        mmu.write_u16(Self::DATA_SEG, lol + 0x1E, 0);       // number of protected FCBs
+        mmu.write_u8(Self::DATA_SEG, lol + 0x20, 0);        // number of block devices
+        mmu.write_u8(Self::DATA_SEG, lol + 0x21, 5);        // LASTDRIVE
+
+        let devices = [
+            ("NUL", ATTR_NUL),
+            ("CON", ATTR_STDIN | ATTR_STDOUT),
+            ("AUX", 0),
+            ("PRN", 0),
+            ("CLOCK$", ATTR_CLOCK),
+        ];
+        for (i, (name, attributes)) in devices.iter().enumerate() {
+            let offset = Self::NUL_DEVICE + (i * DeviceHeader::LENGTH) as u16;
+            let mut header = DeviceHeader::character(name, *attributes, Self::DRIVER_ROUTINE);
+            if i + 1 < devices.len() {
+                header.next = (Self::DATA_SEG, offset + DeviceHeader::LENGTH as u16);
+            }
+            header.write(mmu, Self::DATA_SEG, offset);
+            match *name {
+                "CON" => self.set_list_pointer(mmu, 0x0C, offset),
+                "CLOCK$" => self.set_list_pointer(mmu, 0x08, offset),
+                _ => {}
+            }
+        }
+        mmu.write_u8(Self::DATA_SEG, Self::DRIVER_ROUTINE, 0xCB); // retf
+        self.init_handler_routines(mmu);
+    }
+
+    /// writes the routines calling the ^C / ^Break (INT 23h) and critical error (INT 24h) handlers of
+    /// the program. they are entered with the frame of the interrupted INT 21h call on the stack, and
+    /// restart the call by jumping to the INT 21h handler
+    fn init_handler_routines(&self, mmu: &mut MMU) {
+        let [data_lo, data_hi] = Self::HANDLER_DATA.to_le_bytes();
+        let [int21_lo, int21_hi] = BIOS::default_handler(0x21).to_le_bytes();
+        let [int23_lo, int23_hi] = BIOS::default_handler(0x23).to_le_bytes();
+        let break_routine = [
+            0x2E, 0x89, 0x26, data_lo, data_hi, // mov [cs:HANDLER_DATA],sp
+            0xCD, 0x23,                         // int 0x23
+            0x2E, 0x3B, 0x26, data_lo, data_hi, // cmp sp,[cs:HANDLER_DATA]
+            0x74, 0x09,                         // je restart    ; returned with IRET or RETF 2
+            0x44,                               // inc sp        ; returned with RETF, drop the flags
+            0x44,                               // inc sp
+            0x73, 0x05,                         // jnc restart
+            0xEA, int23_lo, int23_hi, 0x00, 0xF0, // jmp 0xf000:int23 ; terminate, as the default handler
+            0xEA, int21_lo, int21_hi, 0x00, 0xF0, // restart: jmp 0xf000:int21
+        ];
+        mmu.write(Self::DATA_SEG, Self::BREAK_ROUTINE, &break_routine);
+
+        let param = |n: u16| (Self::HANDLER_DATA + n).to_le_bytes();
+        let ([ax_lo, ax_hi], [di_lo, di_hi], [bp_lo, bp_hi], [si_lo, si_hi]) = (param(2), param(4), param(6), param(8));
+        let [act_lo, act_hi] = param(0x0A);
+        let critical_routine = [
+            0x50,                                       // push ax
+            0x57,                                       // push di
+            0x55,                                       // push bp
+            0x56,                                       // push si
+            0x2E, 0xA1, ax_lo, ax_hi,                   // mov ax,[cs:HANDLER_DATA+2]
+            0x2E, 0x8B, 0x3E, di_lo, di_hi,             // mov di,[cs:HANDLER_DATA+4]
+            0x2E, 0x8B, 0x2E, bp_lo, bp_hi,             // mov bp,[cs:HANDLER_DATA+6]
+            0x2E, 0x8B, 0x36, si_lo, si_hi,             // mov si,[cs:HANDLER_DATA+8]
+            0xCD, 0x24,                                 // int 0x24
+            0x2E, 0xA2, act_lo, act_hi,                 // mov [cs:HANDLER_DATA+10],al
+            0x5E,                                       // pop si
+            0x5D,                                       // pop bp
+            0x5F,                                       // pop di
+            0x58,                                       // pop ax
+            0x2E, 0x80, 0x3E, act_lo, act_hi, 0x01,     // cmp byte [cs:HANDLER_DATA+10],0x1
+            0x74, 0x21,                                 // je retry
+            0x2E, 0x80, 0x3E, act_lo, act_hi, 0x02,     // cmp byte [cs:HANDLER_DATA+10],0x2
+            0x74, 0x14,                                 // je abort
+            0x2E, 0x80, 0x3E, act_lo, act_hi, 0x00,     // cmp byte [cs:HANDLER_DATA+10],0x0
+            0x74, 0x07,                                 // je ignore
+            0xB8, 0x53, 0x00,                           // mov ax,0x53   ; fail: error 53h, fail on INT 24
+            0xF9,                                       // stc
+            0xCA, 0x02, 0x00,                           // retf 0x2
+            0x31, 0xC0,                                 // ignore: xor ax,ax
+            0xCA, 0x02, 0x00,                           // retf 0x2
+            0xB8, 0x00, 0x4C,                           // abort: mov ax,0x4c00
+            0xCD, 0x21,                                 // int 0x21
+            0xEA, int21_lo, int21_hi, 0x00, 0xF0,       // retry: jmp 0xf000:int21
+        ];
+        mmu.write(Self::DATA_SEG, Self::CRITICAL_ROUTINE, &critical_routine);
+    }
+
+    /// pushes an interrupt frame returning to `offset` of the DOS data segment, entered by the IRET
+    /// ending the current INT 21h call. the frame of the call stays on the stack below it
+    fn enter_routine(&self, cpu: &mut CPU, mmu: &mut MMU, offset: u16) {
+        let (ss, sp) = (cpu.get_r16(R::SS), cpu.get_r16(R::SP));
+        let flags = mmu.read_u16(ss, sp.wrapping_add(4));
+        cpu.push16(mmu, flags);
+        cpu.push16(mmu, Self::DATA_SEG);
+        cpu.push16(mmu, offset);
+    }
+
+    /// returns the program started by EXEC since the last call
+    pub fn take_exec_request(&mut self) -> Option<ExecRequest> {
+        self.exec_request.take()
+    }
+
+    /// returns true if INT 21h function `ah` checks for ^C / ^Break: the character I/O functions,
+    /// and all functions with extended break checking
+    pub fn checks_break(&self, ah: u8) -> bool {
+        match ah {
+            0x06 | 0x07 => false,
+            0x01..=0x0C => true,
+            0x33 | 0x50 | 0x51 | 0x62 | 0x64 => false,
+            _ => self.break_check,
+        }
+    }
+
+    /// handles ^C / ^Break detected at the start of an INT 21h call: echoes ^C and calls the INT 23h handler
+    /// of the program, restarting the call when it returns
+    pub fn control_break(&mut self, cpu: &mut CPU, mmu: &mut MMU) {
+        for &b in b"^C\r\n" {
+            self.write_console(mmu, b);
+        }
+        self.enter_routine(cpu, mmu, Self::BREAK_ROUTINE);
+    }
+
+    /// reports the critical error `error` (such as 0Bh read fault) on drive `drive` (0 = A:) to the INT 24h
+    /// handler of the program. the action it returns decides if the current INT 21h call is retried,
+    /// ignored, failed with error 53h, or the program is aborted
+    fn critical_error(&mut self, cpu: &mut CPU, mmu: &mut MMU, error: u8, drive: u8, write: bool) {
+        // AH = data area error, fail, retry and ignore allowed, and the write bit
+        let ah = 0x06 | 0x08 | 0x10 | 0x20 | write as u8;
+        mmu.write_u16(Self::DATA_SEG, Self::HANDLER_DATA + 2, u16::from(ah) << 8 | u16::from(drive));
+        mmu.write_u16(Self::DATA_SEG, Self::HANDLER_DATA + 4, u16::from(error));
+        // BP:SI -> device driver header
+        mmu.write_u16(Self::DATA_SEG, Self::HANDLER_DATA + 6, Self::DATA_SEG);
+        mmu.write_u16(Self::DATA_SEG, Self::HANDLER_DATA + 8, Self::NUL_DEVICE);
+        self.enter_routine(cpu, mmu, Self::CRITICAL_ROUTINE);
+    }
+
+    /// selects the code page, and writes the case map routine and upper case table for it
+    pub fn set_codepage(&mut self, mmu: &mut MMU, codepage: Codepage) {
+        self.codepage = codepage;
+        let table_base = Self::UPPER_CASE_TABLE + 2 - 0x80;
+        let case_map = [
+            0x3C, 0x80,                                         // cmp al,0x80
+            0x72, 0x07,                                         // jc 0x7
+            0x53,                                               // push bx
+            0xBB, table_base as u8, (table_base >> 8) as u8,    // mov bx,table_base
+            0x2E, 0xD7,                                         // cs xlatb
+            0x5B,                                               // pop bx
+            0xCB,                                               // retf
+        ];
+        mmu.write(Self::DATA_SEG, Self::CASE_MAP, &case_map);
+        mmu.write_u16(Self::DATA_SEG, Self::UPPER_CASE_TABLE, 0x80);
+        mmu.write(Self::DATA_SEG, Self::UPPER_CASE_TABLE + 2, &codepage.upper_case_table());
+        mmu.write_u16(Self::DATA_SEG, Self::DBCS_TABLE, 0);
+    }
+
+    /// returns the formats of the current country
+    fn country_info(&self) -> CountryInfo {
+        CountryInfo::for_code(self.country).unwrap_or_else(|| CountryInfo::for_code(1).unwrap())
+    }
+
+    /// points the far pointer at `field` of the list of lists to a built-in driver at `offset`
+    fn set_list_pointer(&self, mmu: &mut MMU, field: u16, offset: u16) {
+        mmu.write_u16(Self::DATA_SEG, Self::LIST_OF_LISTS + field, offset);
+        mmu.write_u16(Self::DATA_SEG, Self::LIST_OF_LISTS + field + 2, Self::DATA_SEG);
+    }
+
+    /// loads a device driver and links it into the chain after NUL, as done by DEVICE= in CONFIG.SYS.
+    /// "ANSI.SYS" is emulated, other names are .SYS files on the host.
+    /// the driver code is not run, DOS services are handled natively
+    pub fn load_driver(&mut self, mmu: &mut MMU, name: &str) -> io::Result<()> {
+        let (segment, headers) = if name.eq_ignore_ascii_case("ANSI.SYS") {
+            if self.console.ansi_installed() {
+                return Ok(());
+            }
+            self.console.ansi = Some(AnsiFilter::default());
+            let header = DeviceHeader::character("CON", ATTR_IOCTL | ATTR_STDIN | ATTR_STDOUT, Self::DRIVER_ROUTINE);
+            header.write(mmu, Self::DATA_SEG, Self::ANSI_DEVICE);
+            self.set_list_pointer(mmu, 0x0C, Self::ANSI_DEVICE);
+            (Self::DATA_SEG, vec![Self::ANSI_DEVICE])
+        } else {
+            let data = fs::read(name)?;
+            let headers = parse_driver(&data)?;
+            let paragraphs = data.len().div_ceil(16);
+            if usize::from(self.next_driver_segment) + paragraphs > usize::from(Self::DRIVER_SEG_END) {
+                return Err(io::Error::new(io::ErrorKind::OutOfMemory, format!("no memory left for {} ({} bytes)", name, data.len())));
+            }
+            let segment = self.next_driver_segment;
+            mmu.write(segment, 0, &data);
+            self.next_driver_segment += paragraphs as u16;
+            (segment, headers.into_iter().map(|(offset, _)| offset).collect())
+        };
+
+        // the headers of the driver are inserted between NUL and the following driver
+        let mut nul = DeviceHeader::read(mmu, Self::DATA_SEG, Self::NUL_DEVICE);
+        for (i, &offset) in headers.iter().enumerate() {
+            let mut header = DeviceHeader::read(mmu, segment, offset);
+            header.next = match headers.get(i + 1) {
+                Some(&next) => (segment, next),
+                None => nul.next,
+            };
+            header.write(mmu, segment, offset);
+        }
+        nul.next = (segment, headers[0]);
+        nul.write(mmu, Self::DATA_SEG, Self::NUL_DEVICE);
+        Ok(())
+    }
+
+    /// returns the segment, offset and header of each driver in the chain, starting with NUL
+    pub fn drivers(&self, mmu: &MMU) -> Vec<(u16, u16, DeviceHeader)> {
+        let mut drivers = Vec::new();
+        let (mut segment, mut offset) = (Self::DATA_SEG, Self::NUL_DEVICE);
+        // the chain is in emulated memory, so a program may have broken it
+        while drivers.len() < 256 {
+            let header = DeviceHeader::read(mmu, segment, offset);
+            let next = header.next;
+            let last = header.is_last();
+            drivers.push((segment, offset, header));
+            if last {
+                break;
+            }
+            segment = next.0;
+            offset = next.1;
+        }
+        drivers
+    }
+
+    /// writes a character to the console, escape sequences are handled if ANSI.SYS is loaded
+    pub(crate) fn write_console(&mut self, mmu: &mut MMU, b: u8) {
+        match self.console.write(mmu, b) {
+            AnsiOutput::Text(text) => {
+                for b in text {
+                    print!("{}", self.codepage.u8_as_char(b));
+                    self.stdout.push(b);
+                }
+            }
+            AnsiOutput::Sequence(sequence) => {
+                // the host terminal interprets the sequence
+                print!("{}", String::from_utf8_lossy(&sequence));
+            }
+            AnsiOutput::Pending => {}
+        }
+    }
+
+    /// clears the text screen, as done by CLS
+    pub(crate) fn clear_console(&mut self, mmu: &mut MMU) {
+        self.console.clear_screen(mmu);
+    }
+
+    /// redirects the standard input from `data`, as with "PROGRAM < INPUT.TXT"
+    pub fn set_stdin(&mut self, data: Vec<u8>) {
+        self.stdin = Some(data.into());
+        // the handle refers to a file
+        self.std_device_info[0] = u16::from(self.current_drive()) & INFO_DRIVE_MASK;
+    }
+
+    /// returns the next character of the redirected standard input, ^Z at end of file.
+    /// returns None if the input is not redirected
+    pub(crate) fn read_stdin(&mut self) -> Option<u8> {
+        self.stdin.as_mut().map(|input| input.pop_front().unwrap_or(0x1A))
+    }
+
+    /// returns true if a character can be read from standard input
+    fn stdin_ready(&self) -> bool {
+        self.stdin.as_ref().is_some_and(|input| !input.is_empty())
+    }
+
+    /// reads a line from the redirected standard input into the buffer of INT 21/AH=0Ah at `seg`:`offset`
+    fn read_stdin_line(&mut self, mmu: &mut MMU, seg: u16, offset: u16) {
+        let max = mmu.read_u8(seg, offset);
+        if max == 0 {
+            return;
+        }
+        let mut line: Vec<u8> = Vec::new();
+        while let Some(input) = &mut self.stdin {
+            let b = match input.pop_front() {
+                Some(b) => b,
+                None => break,
+            };
+            match b {
+                b'\r' => {
+                    if input.front() == Some(&b'\n') {
+                        input.pop_front();
+                    }
+                    break;
+                }
+                b'\n' => break,
+                0x08 => {
+                    if line.pop().is_some() {
+                        for &c in b"\x08 \x08" {
+                            self.write_console(mmu, c);
+                        }
+                    }
+                }
+                // characters beyond the buffer size are dropped
+                _ if line.len() + 1 >= usize::from(max) => {}
+                _ => {
+                    line.push(b);
+                    self.write_console(mmu, b);
+                }
+            }
+        }
+        self.write_console(mmu, b'\r');
+        line.push(b'\r');
+        mmu.write_u8(seg, offset + 1, (line.len() - 1) as u8);
+        mmu.write(seg, offset + 2, &line);
+    }
+
+    /// returns the host path of a DOS filename. "C:\DIR\FILE" is looked up in the directory mounted as C:,
+    /// other names are relative to the current directory, or to the directory of the loaded program.
+    /// each part of the path matches a host file by its long or short name, without case
+    pub fn host_path(&self, filename: &str) -> PathBuf {
+        let full = self.full_path(filename);
+        let mut chars = full.chars();
+        let (mut path, rest) = match (chars.next(), chars.next()) {
+            (Some(drive), Some(':')) => {
+                match self.drives.get(drive) {
+                    Some(drive) => (drive.root.clone(), &full[2..]),
+                    None => (self.program_dir(), &full[2..]),
+                }
+            }
+            _ => (self.program_dir(), full.as_str()),
+        };
+        for part in rest.split('\\').filter(|p| !p.is_empty()) {
+            path = find_entry(&path, part).unwrap_or_else(|| path.join(part));
+        }
+        path
+    }
+
+    /// returns the directory of the loaded program
+    fn program_dir(&self) -> PathBuf {
+        Path::new(&self.program_path).parent().unwrap_or_else(|| Path::new("")).to_path_buf()
+    }
+
+    /// returns `filename` relative to the current directory, with "." and ".." resolved
+    pub(crate) fn full_path(&self, filename: &str) -> String {
+        let has_drive = filename.len() >= 2 && filename.as_bytes()[1] == b':';
+        let path = if has_drive || self.current_dir.is_empty() {
+            filename.to_owned()
+        } else if filename.starts_with('\\') {
+            let current = self.current_dir.as_bytes();
+            if current.len() >= 2 && current[1] == b':' {
+                format!("{}{}", &self.current_dir[..2], filename)
+            } else {
+                filename.to_owned()
+            }
+        } else {
+            format!("{}\\{}", self.current_dir.trim_end_matches('\\'), filename)
+        };
+
+        let (drive, rest) = if path.len() >= 2 && path.as_bytes()[1] == b':' {
+            (&path[..2], &path[2..])
+        } else {
+            ("", path.as_str())
+        };
+        let mut parts: Vec<&str> = Vec::new();
+        for part in rest.split('\\').filter(|p| !p.is_empty() && *p != ".") {
+            if part == ".." {
+                parts.pop();
+            } else {
+                parts.push(part);
+            }
+        }
+        if drive.is_empty() {
+            parts.join("\\")
+        } else {
+            format!("{}\\{}", drive, parts.join("\\"))
+        }
+    }
+
+    /// returns the current drive number, 0 = A:. the directory of the loaded program is drive C:
+    pub fn current_drive(&self) -> u8 {
+        path_drive(&self.current_dir).map_or(2, |drive| drive as u8 - b'A')
+    }
+
+    /// makes drive `number` the current drive, 0 = A:. unmounted drives are ignored
+    pub(crate) fn select_drive(&mut self, number: u8) {
+        if number == self.current_drive() {
+            return;
+        }
+        if let Some(drive) = self.drives.get_number(number) {
+            self.current_dir = format!("{}:\\", drive.mount.drive.to_ascii_uppercase());
+        }
+    }
+
+    /// returns the drive number of drive `dl`, as passed to the DOS disk functions (0 = default, 1 = A:)
+    fn drive_number(&self, dl: u8) -> u8 {
+        if dl == 0 { self.current_drive() } else { dl - 1 }
+    }
+
+    /// returns the disk layout of drive `dl`, as passed to the DOS disk functions (0 = default, 1 = A:)
+    fn drive_geometry(&self, dl: u8) -> Option<DiskGeometry> {
+        let number = self.drive_number(dl);
+        match self.drives.get_number(number) {
+            Some(drive) => Some(drive.geometry),
+            None if number == self.current_drive() && self.current_dir.is_empty() => Some(DiskGeometry::directory()),
+            None => None,
+        }
+    }
+
+    /// DOS 2+ - CHDIR - SET CURRENT DIRECTORY, and its long filename version.
+    /// returns the error code on failure
+    pub(crate) fn change_dir(&mut self, name: &str) -> Result<(), u16> {
+        let full = self.full_path(name);
+        if self.host_path(&full).is_dir() {
+            self.current_dir = full;
+            Ok(())
+        } else {
+            Err(0x0003) // path not found
+        }
+    }
+
+    /// returns the IOCTL device information of `handle`, or None if the handle is not open
+    fn device_info(&self, handle: u16) -> Option<u16> {
+        if let Some(info) = self.std_device_info.get(handle as usize) {
+            return Some(*info);
+        }
+        let path = self.get_path_from_handle(handle)?;
+        let drive = self.drives.drives().iter()
+            .find(|d| path.starts_with(&d.root))
+            .map_or_else(|| self.current_drive(), |d| d.number());
+        Some(u16::from(drive) & INFO_DRIVE_MASK)
+    }
+
+    /// DOS 2+ - IOCTL, INT 21/AH=44h
+    /// http://www.ctyme.com/intr/rb-2820.htm
+    fn ioctl(&mut self, cpu: &mut CPU, mmu: &mut MMU) {
+        let handle = cpu.get_r16(R::BX);
+        let res = match cpu.get_r8(R::AL) {
+            0x00 => {
+                // GET DEVICE INFORMATION
+                // BX = handle
+                // Return: CF clear if successful, DX = device information word (see #01423)
+                // CF set on error, AX = error code (01h,05h,06h)
+                self.device_info(handle).map(|info| cpu.set_r16(R::DX, info)).ok_or(0x0006)
+            }
+            0x01 => {
+                // SET DEVICE INFORMATION
+                // BX = handle (must refer to character device)
+                // DX = device information word (see #01423), DH must be zero for DOS version prior to 6.x
+                // Return: CF clear if successful, CF set on error, AX = error code (01h,05h,06h,0Dh)
+                let dx = cpu.get_r16(R::DX);
+                match self.device_info(handle) {
+                    None => Err(0x0006),
+                    Some(info) if info & INFO_CHARACTER == 0 => Err(0x0001),
+                    Some(_) if dx >> 8 != 0 && self.version.major < 6 => Err(0x000D),
+                    Some(info) => {
+                        // only the mode bits can be changed, the rest describes the device
+                        let mode = INFO_BINARY;
+                        self.std_device_info[handle as usize] = (info & !mode) | (dx & mode);
+                        Ok(())
+                    }
+                }
+            }
+            0x06 | 0x07 => {
+                // GET INPUT STATUS (06h) or GET OUTPUT STATUS (07h)
+                // BX = handle
+                // Return: CF clear if successful, AL = status, 00h not ready or at EOF, FFh ready
+                // CF set on error, AX = error code (01h,05h,06h)
+                let input = cpu.get_r8(R::AL) == 0x06;
+                self.device_info(handle).map(|info| {
+                    let ready = if !input {
+                        true
+                    } else if handle == 0 && self.stdin.is_some() {
+                        self.stdin_ready()
+                    } else {
+                        // console input is read through the BIOS, DOS has no characters buffered
+                        info & INFO_CHARACTER == 0 || info & INFO_STDIN == 0
+                    };
+                    cpu.set_r8(R::AL, if ready { 0xFF } else { 0x00 });
+                }).ok_or(0x0006)
+            }
+            0x08 => {
+                // CHECK IF BLOCK DEVICE REMOVABLE
+                // BL = drive number (00h = default, 01h = A:, etc)
+                // Return: CF clear if successful, AX = 0000h if removable, 0001h if fixed
+                // CF set on error, AX = error code (01h,0Fh)
+                let bl = cpu.get_r8(R::BL);
+                self.drive_geometry(bl).map(|_| {
+                    let removable = match self.drives.get_number(self.drive_number(bl)) {
+                        Some(drive) => drive.mount.kind() == MountKind::Floppy,
+                        None => false,
+                    };
+                    cpu.set_r16(R::AX, if removable { 0 } else { 1 });
+                }).ok_or(0x000F)
+            }
+            al => {
+                warn!(target: "dos", "int21 (dos) error: ioctl ah=44, al={:02X}", al);
+                Err(0x0001)
+            }
+        };
+        match res {
+            Ok(()) => mmu.set_flag(FLAG_CF, false),
+            Err(code) => {
+                cpu.set_r16(R::AX, code);
+                mmu.set_flag(FLAG_CF, true);
+            }
+        }
+    }
+
+    /// WINDOWS95 - LONG FILENAME FUNCTIONS, INT 21/AH=71h
+    /// http://www.ctyme.com/intr/rb-2939.htm
+    fn long_filename(&mut self, cpu: &mut CPU, mmu: &mut MMU) {
+        let (ds, dx) = (cpu.get_r16(R::DS), cpu.get_r16(R::DX));
+        let (es, di) = (cpu.get_r16(R::ES), cpu.get_r16(R::DI));
+        let res = match cpu.get_r8(R::AL) {
+            0x3B => {
+                // CHANGE DIRECTORY
+                // DS:DX -> ASCIZ directory name (may include drive)
+                let name = self.codepage.to_utf8(&mmu.readz(ds, dx));
+                self.change_dir(&name)
+            }
+            0x4E => {
+                // FIND FIRST MATCHING FILE
+                // CL = allowable-attributes mask, CH = required-attributes mask
+                // SI = date/time format (0000h = 64-bit file time, 0001h = DOS date/time)
+                // DS:DX -> ASCIZ filespec (both "*" and "*.*" match any filename)
+                // ES:DI -> FindData record
+                // Return: CF clear if successful, AX = filefind handle, CX = Unicode conversion flags
+                let spec = self.codepage.to_utf8(&mmu.readz(ds, dx));
+                let (dir, pattern) = match spec.rfind(['\\', ':']) {
+                    Some(pos) => (&spec[..=pos], &spec[pos + 1..]),
+                    None => ("", spec.as_str()),
+                };
+                let (allowed, required) = (cpu.get_r8(R::CL), cpu.get_r8(R::CH));
+                let mut entries: Vec<DirEntry> = list_dir(&self.host_path(dir))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|e| e.attributes & (FILE_ATTR_HIDDEN | FILE_ATTR_SYSTEM | FILE_ATTR_DIRECTORY) & !allowed == 0)
+                    .filter(|e| e.attributes & required == required)
+                    .filter(|e| wildcard_match(pattern, &e.long_name) || wildcard_match(pattern, &e.short_name))
+                    .collect();
+                if entries.is_empty() {
+                    Err(0x0002) // file not found
+                } else {
+                    let handle = (1..=0xFFFF).find(|h| !self.find_searches.contains_key(h)).unwrap();
+                    let first = entries.remove(0);
+                    self.write_find_data(mmu, es, di, &first, cpu.get_r16(R::SI));
+                    entries.reverse();
+                    self.find_searches.insert(handle, entries);
+                    cpu.set_r16(R::AX, handle);
+                    cpu.set_r16(R::CX, 0);
+                    Ok(())
+                }
+            }
+            0x4F => {
+                // FIND NEXT MATCHING FILE
+                // BX = filefind handle (from AX=714Eh), SI = date/time format, ES:DI -> FindData record
+                // Return: CF clear if successful, CX = Unicode conversion flags
+                let handle = cpu.get_r16(R::BX);
+                match self.find_searches.get_mut(&handle).map(|entries| entries.pop()) {
+                    Some(Some(entry)) => {
+                        self.write_find_data(mmu, es, di, &entry, cpu.get_r16(R::SI));
+                        cpu.set_r16(R::CX, 0);
+                        Ok(())
+                    }
+                    Some(None) => Err(0x0012), // no more files
+                    None => Err(0x0006),       // invalid handle
+                }
+            }
+            0x6C => {
+                // CREATE OR OPEN FILE
+                // BX = access mode and sharing flags, CX = attributes
+                // DX = action: bit 0 open, bit 1 truncate if file exists, bit 4 create if file does not exist
+                // DS:SI -> ASCIZ filename
+                // Return: CF clear if successful, AX = file handle, CX = action taken (1 opened, 2 created, 3 replaced)
+                let name = self.codepage.to_utf8(&mmu.readz(ds, cpu.get_r16(R::SI)));
+                let path = self.host_path(&name);
+                let action = cpu.get_r16(R::DX);
+                let res = if path.exists() {
+                    match action & 0x03 {
+                        0x00 => Err(0x0050), // file exists
+                        0x01 => Ok(1),
+                        _ => fs::OpenOptions::new().write(true).truncate(true).open(&path).map(|_| 3).map_err(|_| 0x0005),
+                    }
+                } else if action & 0x10 != 0 {
+                    File::create(&path).map(|_| 2).map_err(|_| 0x0005)
+                } else {
+                    Err(0x0002) // file not found
+                };
+                res.map(|taken| {
+                    debug!(target: "dos", "LONG FILENAME - CREATE OR OPEN FILE {}, action {:04X}", path.display(), action);
+                    let handle = self.open_existing_file(path);
+                    cpu.set_r16(R::AX, handle);
+                    cpu.set_r16(R::CX, taken);
+                })
+            }
+            0xA1 => {
+                // TERMINATE DIRECTORY SEARCH
+                // BX = filefind handle
+                match self.find_searches.remove(&cpu.get_r16(R::BX)) {
+                    Some(_) => Ok(()),
+                    None => Err(0x0006), // invalid handle
+                }
+            }
+            al => {
+                warn!(target: "dos", "XXX DOS - LONG FILENAME FUNCTION AX={:04X}", 0x7100 | u16::from(al));
+                Err(0x7100) // function not supported
+            }
+        };
+        match res {
+            Ok(()) => mmu.set_flag(FLAG_CF, false),
+            Err(code) => {
+                cpu.set_r16(R::AX, code);
+                mmu.set_flag(FLAG_CF, true);
+            }
+        }
+    }
+
+    /// writes the long filename FindData record of `entry` to `seg:offset`.
+    /// `format` 0 gives 64-bit file times, 1 gives DOS date and time
+    fn write_find_data(&self, mmu: &mut MMU, seg: u16, offset: u16, entry: &DirEntry, format: u16) {
+        let mut data = vec![0u8; 0x13E];
+        data[0x00] = entry.attributes;
+        let time: [u8; 8] = if format == 1 {
+            let local = host_local_time(entry.modified);
+            let packed = u32::from(pack_dos_time(local.time())) | (u32::from(pack_dos_date(local.date())) << 16);
+            u64::from(packed).to_le_bytes()
+        } else {
+            file_time(entry.modified).to_le_bytes()
+        };
+        for field in &[0x04, 0x0C, 0x14] {
+            data[*field..*field + 8].copy_from_slice(&time);
+        }
+        data[0x1C..0x20].copy_from_slice(&((entry.size >> 32) as u32).to_le_bytes());
+        data[0x20..0x24].copy_from_slice(&(entry.size as u32).to_le_bytes());
+
+        let encode = |name: &str| -> Vec<u8> {
+            name.chars().map(|c| self.codepage.char_as_u8(c).unwrap_or(b'_')).collect()
+        };
+        let long = encode(&entry.long_name);
+        let len = long.len().min(259);
+        data[0x2C..0x2C + len].copy_from_slice(&long[..len]);
+        if entry.short_name != entry.long_name {
+            // the short name is left empty if it is the same as the long name
+            let short = encode(&entry.short_name);
+            data[0x130..0x130 + short.len()].copy_from_slice(&short);
         }
+        mmu.write(seg, offset, &data);
     }
 
     /// returns a new file handle
@@ -50,34 +845,387 @@ impl DOS {
     fn get_path_from_handle(&self, handle: u16) -> Option<&PathBuf> {
         self.file_handles.get(&handle)
     }
+
+    /// handles the INT 21h date and time functions AH=2Ah to 2Dh, against the machine clock.
+    /// returns false for other functions
+    pub fn clock_int(&mut self, cpu: &mut CPU, mmu: &mut MMU, clock: &mut Clock) -> bool {
+        let now = clock.now();
+        match cpu.get_r8(R::AH) {
+            0x2A => {
+                // DOS 1+ - GET SYSTEM DATE
+                // Return: CX = year (1980-2099), DH = month, DL = day, AL = day of week (00h=Sunday)
+                cpu.set_r16(R::CX, now.year() as u16);
+                cpu.set_r8(R::DH, now.month() as u8);
+                cpu.set_r8(R::DL, now.day() as u8);
+                cpu.set_r8(R::AL, now.weekday().num_days_from_sunday() as u8);
+            }
+            0x2B => {
+                // DOS 1+ - SET SYSTEM DATE
+                // CX = year (1980-2099), DH = month, DL = day
+                // Return: AL = 00h successful, FFh invalid date, system date unchanged
+                let year = i32::from(cpu.get_r16(R::CX));
+                let date = if (1980..=2099).contains(&year) {
+                    NaiveDate::from_ymd_opt(year, u32::from(cpu.get_r8(R::DH)), u32::from(cpu.get_r8(R::DL)))
+                } else {
+                    None
+                };
+                match date {
+                    Some(date) => {
+                        clock.set_now(date.and_time(now.time()));
+                        cpu.set_r8(R::AL, 0x00);
+                    }
+                    None => cpu.set_r8(R::AL, 0xFF),
+                }
+            }
+            0x2C => {
+                // DOS 1+ - GET SYSTEM TIME
+                // Return: CH = hour, CL = minute, DH = second, DL = 1/100 seconds
+                cpu.set_r8(R::CH, now.hour() as u8);
+                cpu.set_r8(R::CL, now.minute() as u8);
+                cpu.set_r8(R::DH, now.second() as u8);
+                cpu.set_r8(R::DL, (now.nanosecond().min(999_999_999) / 10_000_000) as u8);
+            }
+            0x2D => {
+                // DOS 1+ - SET SYSTEM TIME
+                // CH = hour, CL = minute, DH = second, DL = 1/100 seconds
+                // Return: AL = 00h successful, FFh invalid time, system time unchanged
+                let centis = u32::from(cpu.get_r8(R::DL));
+                let time = if centis < 100 {
+                    NaiveTime::from_hms_milli_opt(u32::from(cpu.get_r8(R::CH)), u32::from(cpu.get_r8(R::CL)), u32::from(cpu.get_r8(R::DH)), centis * 10)
+                } else {
+                    None
+                };
+                match time {
+                    Some(time) => {
+                        clock.set_now(now.date().and_time(time));
+                        // the BIOS tick count follows the new time of day
+                        mmu.write_u32(BIOS::DATA_SEG, BIOS::DATA_TIMER_TICKS, BIOS::ticks_of_time(time));
+                        cpu.set_r8(R::AL, 0x00);
+                    }
+                    None => cpu.set_r8(R::AL, 0xFF),
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// handles INT 21h AH=5Ah, naming the file with values from `rng`. returns false for other functions
+    pub fn temp_file_int(&mut self, cpu: &mut CPU, mmu: &mut MMU, rng: &mut dyn RngCore) -> bool {
+        if cpu.get_r8(R::AH) != 0x5A {
+            return false;
+        }
+        // DOS 3+ - CREATE TEMPORARY FILE
+        // CX = file attributes
+        // DS:DX -> ASCIZ path ending with a backslash, followed by 13 bytes for the file name
+        // Return: CF clear if successful, AX = file handle, DS:DX -> full path name of the file
+        let (ds, dx) = (cpu.get_r16(R::DS), cpu.get_r16(R::DX));
+        let mut dir = mmu.readz(ds, dx);
+        if !dir.is_empty() && !dir.ends_with(b"\\") && !dir.ends_with(b":") {
+            dir.push(b'\\');
+        }
+        for _ in 0..16 {
+            let mut name = dir.clone();
+            name.extend_from_slice(format!("{:08X}", rng.next_u32()).as_bytes());
+            let path = self.host_path(&self.codepage.to_utf8(&name));
+            if path.exists() {
+                continue;
+            }
+            match File::create(&path) {
+                Ok(_) => {
+                    debug!(target: "dos", "CREATE TEMPORARY FILE {}", path.display());
+                    name.push(0);
+                    mmu.write(ds, dx, &name);
+                    let handle = self.open_existing_file(path);
+                    cpu.set_r16(R::AX, handle);
+                    mmu.set_flag(FLAG_CF, false);
+                }
+                Err(_) => {
+                    cpu.set_r16(R::AX, 0x0003); // path not found
+                    mmu.set_flag(FLAG_CF, true);
+                }
+            }
+            return true;
+        }
+        cpu.set_r16(R::AX, 0x0005); // access denied
+        mmu.set_flag(FLAG_CF, true);
+        true
+    }
+
+    /// DOS 2+ - GET/SET FILE'S LAST-WRITTEN DATE AND TIME, using the host file modification time
+    fn file_date_time(&mut self, cpu: &mut CPU, mmu: &mut MMU) {
+        let handle = cpu.get_r16(R::BX);
+        let path = match self.get_path_from_handle(handle) {
+            Some(path) => path.clone(),
+            None => {
+                // CF set on error, AX = error code (01h,06h)
+                cpu.set_r16(R::AX, 0x0006); // invalid handle
+                mmu.set_flag(FLAG_CF, true);
+                return;
+            }
+        };
+        match cpu.get_r8(R::AL) {
+            0x00 => {
+                // AX = 5700h GET FILE'S LAST-WRITTEN DATE AND TIME
+                // Return: CF clear if successful, CX = file's time, DX = file's date
+                match fs::metadata(&path).and_then(|m| m.modified()) {
+                    Ok(modified) => {
+                        let time = host_local_time(modified);
+                        cpu.set_r16(R::CX, pack_dos_time(time.time()));
+                        cpu.set_r16(R::DX, pack_dos_date(time.date()));
+                        mmu.set_flag(FLAG_CF, false);
+                    }
+                    Err(e) => {
+                        warn!(target: "dos", "GET FILE'S LAST-WRITTEN DATE AND TIME {}: {}", path.display(), e);
+                        cpu.set_r16(R::AX, 0x0005); // access denied
+                        mmu.set_flag(FLAG_CF, true);
+                    }
+                }
+            }
+            0x01 => {
+                // AX = 5701h SET FILE'S LAST-WRITTEN DATE AND TIME
+                // CX = new time, DX = new date
+                // Return: CF clear if successful, CF set on error and AX = error code (01h,06h)
+                let time = unpack_dos_date_time(cpu.get_r16(R::DX), cpu.get_r16(R::CX)).and_then(local_host_time);
+                let res = match time {
+                    Some(time) => fs::OpenOptions::new().write(true).open(&path).and_then(|f| f.set_modified(time)),
+                    None => Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid date or time")),
+                };
+                match res {
+                    Ok(()) => mmu.set_flag(FLAG_CF, false),
+                    Err(e) => {
+                        warn!(target: "dos", "SET FILE'S LAST-WRITTEN DATE AND TIME {}: {}", path.display(), e);
+                        cpu.set_r16(R::AX, 0x0005); // access denied
+                        mmu.set_flag(FLAG_CF, true);
+                    }
+                }
+            }
+            _ => {
+                warn!(target: "dos", "int21 (dos) error: file date and time ah=57, al={:02X}", cpu.get_r8(R::AL));
+                cpu.set_r16(R::AX, 0x0001); // function number invalid
+                mmu.set_flag(FLAG_CF, true);
+            }
+        }
+    }
+
+    /// DOS 4+ - GET EXTENDED COUNTRY INFORMATION, and the DOS 4+ character capitalization functions
+    /// http://www.ctyme.com/intr/rb-3163.htm
+    fn extended_country_info(&mut self, cpu: &mut CPU, mmu: &mut MMU) {
+        let (es, di) = (cpu.get_r16(R::ES), cpu.get_r16(R::DI));
+        let (ds, dx) = (cpu.get_r16(R::DS), cpu.get_r16(R::DX));
+        let al = cpu.get_r8(R::AL);
+        match al {
+            0x01 => {
+                // GET GENERAL INTERNATIONALIZATION INFO
+                // BX = code page (FFFFh global code page), DX = country ID (FFFFh current country)
+                // CX = size of buffer, ES:DI -> country information buffer
+                let country = match dx {
+                    0xFFFF => self.country,
+                    code => code,
+                };
+                let info = match CountryInfo::for_code(country) {
+                    Some(info) => info,
+                    None => {
+                        cpu.set_r16(R::AX, 0x0002);
+                        cpu.regs.flags.set_carry(true);
+                        return;
+                    }
+                };
+                let mut data = vec![0x01];
+                data.extend_from_slice(&((CountryInfo::LENGTH + 4) as u16).to_le_bytes());
+                data.extend_from_slice(&country.to_le_bytes());
+                data.extend_from_slice(&self.codepage.number().to_le_bytes());
+                data.extend(info.to_bytes(self.codepage, (Self::DATA_SEG, Self::CASE_MAP)));
+                data.truncate(usize::from(cpu.get_r16(R::CX)));
+                mmu.write(es, di, &data);
+                cpu.set_r16(R::CX, data.len() as u16);
+            }
+            0x02 | 0x04 | 0x07 => {
+                // GET POINTER TO UPPERCASE TABLE (02h), FILENAME UPPERCASE TABLE (04h) or DBCS LEAD BYTE TABLE (07h)
+                // ES:DI -> buffer receiving the id byte and a far pointer to the table
+                let table = if al == 0x07 {
+                    Self::DBCS_TABLE
+                } else {
+                    Self::UPPER_CASE_TABLE
+                };
+                mmu.write_u8(es, di, al);
+                mmu.write_u16(es, di + 1, table);
+                mmu.write_u16(es, di + 3, Self::DATA_SEG);
+                cpu.set_r16(R::CX, 5);
+            }
+            0x20 => {
+                // CAPITALIZE CHARACTER
+                // DL = character to capitalize
+                // Return: DL = capitalized character
+                let dl = cpu.get_r8(R::DL);
+                cpu.set_r8(R::DL, self.codepage.to_upper(dl));
+            }
+            0x21 | 0x22 => {
+                // CAPITALIZE STRING (21h), DS:DX -> string, CX = length
+                // CAPITALIZE ASCIZ STRING (22h), DS:DX -> ASCIZ string
+                let mut offset = dx;
+                let mut remaining = cpu.get_r16(R::CX);
+                loop {
+                    let b = mmu.read_u8(ds, offset);
+                    if (al == 0x21 && remaining == 0) || (al == 0x22 && b == 0) {
+                        break;
+                    }
+                    mmu.write_u8(ds, offset, self.codepage.to_upper(b));
+                    offset = offset.wrapping_add(1);
+                    remaining = remaining.wrapping_sub(1);
+                }
+            }
+            0x23 => {
+                // DETERMINE IF CHARACTER REPRESENTS YES/NO RESPONSE
+                // DL = character
+                // Return: AX = 0000h no, 0001h yes, 0002h neither
+                let answer = match self.codepage.to_upper(cpu.get_r8(R::DL)) {
+                    b'Y' => 1,
+                    b'N' => 0,
+                    _ => 2,
+                };
+                cpu.set_r16(R::AX, answer);
+            }
+            _ => {
+                warn!(target: "dos", "int21 (dos) error: extended country info ah=65, al={:02X}", al);
+                cpu.set_r16(R::AX, 0x0001); // function number invalid
+                cpu.regs.flags.set_carry(true);
+                return;
+            }
+        }
+        cpu.regs.flags.set_carry(false);
+    }
+
+    /// handles the DOS multiplex interrupt 0x2F for ids not claimed by a registered component.
+    /// installation checks of unclaimed ids report "not installed"
+    fn multiplex(&mut self, cpu: &mut CPU) -> bool {
+        match cpu.get_r16(R::AX) {
+            0x1500 => {
+                // CD-ROM - MSCDEX - INSTALLATION CHECK
+                // Return: BX = number of CD-ROM drive letters used
+                cpu.set_r16(R::BX, 0);
+            }
+            0x1600 => {
+                // MS Windows - WINDOWS ENHANCED MODE INSTALLATION CHECK
+                // Return: AL = 00h if Windows is not running
+                cpu.set_r8(R::AL, 0);
+            }
+            0x1680 => {
+                // MS Windows, DPMI - RELEASE CURRENT VIRTUAL MACHINE TIME-SLICE
+                // Return: AL = 80h (unchanged) if the call is not supported
+            }
+            0x1687 => {
+                // DPMI 0.9+ - INSTALLATION CHECK AND GET ENTRY POINT
+                // Return: AX = 0000h if installed
+            }
+            0x1A00 => {
+                // DOS 4.0+ ANSI.SYS - INSTALLATION CHECK
+                // Return: AL = FFh if installed
+                if self.console.ansi_installed() {
+                    cpu.set_r8(R::AL, 0xFF);
+                }
+            }
+            0x4A01 => {
+                // DOS 5+ - QUERY FREE HMA SPACE
+                // Return: BX = number of bytes available in HMA, ES:DI -> start of available HMA area
+                cpu.set_r16(R::BX, 0);
+                cpu.set_r16(R::ES, 0xFFFF);
+                cpu.set_r16(R::DI, 0xFFFF);
+            }
+            _ if cpu.get_r8(R::AL) == 0x00 => {
+                // installation check of print spooler (AH=01h), network redirector (AH=11h),
+                // XMS (AH=43h) and others
+                // Return: AL = 00h not installed, OK to install
+            }
+            _ => {
+                warn!(target: "dos", "int2f (dos) error: unknown ax={:04X}", cpu.get_r16(R::AX));
+                return false;
+            }
+        }
+        true
+    }
 }
 
 impl Component for DOS {
-    /// handles DOS interrupts 0x20 and 0x21
+    /// handles DOS interrupts 0x00, 0x20, 0x21, 0x23, 0x24, 0x29 and 0x2F
     fn int(&mut self, int: u8, cpu: &mut CPU, mmu: &mut MMU) -> bool {
+        if int == 0x00 {
+            // DIVIDE ERROR, not hooked by the program
+            // the default handler of DOS terminates the program
+            for &b in b"\r\nDivide overflow\r\n" {
+                self.write_console(mmu, b);
+            }
+            info!(target: "dos", "INT 00 - DIVIDE OVERFLOW, TERMINATE PROGRAM");
+            // the program failed, so don't report success to the parent or the batch file
+            self.exit_code = Some(0xFF);
+            cpu.fatal_error = true; // stops execution
+            return true;
+        }
         if int == 0x20 {
             // DOS 1+ - TERMINATE PROGRAM
             // NOTE: Windows overloads INT 20
-            println!("INT 20 - TERMINATE PROGRAM");
+            info!(target: "dos", "INT 20 - TERMINATE PROGRAM");
+            self.exit_code = Some(0);
+            cpu.fatal_error = true; // stops execution
+            return true;
+        }
+        if int == 0x29 {
+            // DOS 2+ - FAST CONSOLE OUTPUT
+            // AL = character to display
+            self.write_console(mmu, cpu.get_r8(R::AL));
+            return true;
+        }
+        if int == 0x2F {
+            return self.multiplex(cpu);
+        }
+        if int == 0x23 {
+            // DOS 1+ - CONTROL-C/CONTROL-BREAK HANDLER
+            // the default handler terminates the program
+            info!(target: "dos", "INT 23 - CONTROL-BREAK, TERMINATE PROGRAM");
+            self.exit_code = Some(0);
             cpu.fatal_error = true; // stops execution
             return true;
         }
+        if int == 0x24 {
+            // DOS 1+ - CRITICAL ERROR HANDLER
+            // AH = type and processing flags, AL = drive number, DI low byte = error code
+            // Return: AL = action code: 00h ignore, 01h retry, 02h abort, 03h fail (DOS 3.1+)
+            // the default handler fails the call, instead of asking "Abort, Retry, Fail?"
+            info!(target: "dos", "INT 24 - CRITICAL ERROR {:02X} ON DRIVE {}:, FAIL", cpu.get_r16(R::DI) as u8, (b'A' + cpu.get_r8(R::AL)) as char);
+            cpu.set_r8(R::AL, 0x03);
+            return true;
+        }
         if int != 0x21 {
             return false;
         }
         match cpu.get_r8(R::AH) {
             0x00 => {
                 // DOS 1+ - TERMINATE PROGRAM
-                println!("DOS 1+ - TERMINATE PROGRAM");
+                info!(target: "dos", "DOS 1+ - TERMINATE PROGRAM");
+                self.exit_code = Some(0);
                 cpu.fatal_error = true; // XXX just to stop debugger.run() function
             }
+            0x01 | 0x07 | 0x08 => {
+                // DOS 1+ - READ CHARACTER FROM STANDARD INPUT, WITH ECHO (01h)
+                // DOS 1+ - DIRECT CHARACTER INPUT, WITHOUT ECHO (07h)
+                // DOS 1+ - CHARACTER INPUT WITHOUT ECHO (08h)
+                // Return:
+                // AL = character read from standard input
+                match self.read_stdin() {
+                    Some(b) => {
+                        if cpu.get_r8(R::AH) == 0x01 {
+                            self.write_console(mmu, b);
+                        }
+                        cpu.set_r8(R::AL, b);
+                    }
+                    None => warn!(target: "dos", "XXX DOS - CHARACTER INPUT, ah={:02X}", cpu.get_r8(R::AH)),
+                }
+            }
             0x02 => {
                 // DOS 1+ - WRITE CHARACTER TO STANDARD OUTPUT
                 // DL = character to write
                 let dl = cpu.get_r8(R::DL);
-
-                // XXX set with video functions
-                print!("{}", cp437::u8_as_char(dl));
+                self.write_console(mmu, dl);
                 // Return:
                 // AL = last character output (despite the official docs which state
                 // nothing is returned) (at least DOS 2.1-7.0)
@@ -91,27 +1239,23 @@ impl Component for DOS {
                 // which is always the screen under DOS 1.x, but may be redirected
                 // under DOS 2+
 
-                // XXX set with video functions
                 let dl = cpu.get_r8(R::DL);
                 if dl != 0xFF {
-                    print!("{}", cp437::u8_as_char(dl));
-
-                    // XXX instead, we should WRITE to a "dos_stdout" stream
+                    self.write_console(mmu, dl);
+                    // Return:
+                    // AL = character output (despite official docs which
+                    // state nothing is returned) (at least DOS 2.1-7.0)
+                    cpu.set_r8(R::AL, dl);
                 } else {
-                    // see dosbox-x/src/dos/dos.cpp:484
-                    // happens in ../dos-software-decoding/games-com-commercial/Blort\ \(1987\)\(Hennsoft\)/blort.com
-                    // println!("XXX dl is 0xFF, TODO read input?");
+                    // DOS 1+ - DIRECT CONSOLE INPUT
+                    // Return:
+                    // ZF set if no character available and AL = 00h
+                    // ZF clear if character available and AL = character read
+                    let ready = self.stdin_ready();
+                    let b = if ready { self.read_stdin().unwrap_or(0) } else { 0 };
+                    cpu.set_r8(R::AL, b);
+                    mmu.set_flag(FLAG_ZF, !ready);
                 }
-                // Return:
-                // AL = character output (despite official docs which
-                // state nothing is returned) (at least DOS 2.1-7.0)
-                cpu.set_r8(R::AL, dl);
-            }
-            0x07 => {
-                // DOS 1+ - DIRECT CHARACTER INPUT, WITHOUT ECHO
-                // Return:
-                // AL = character read from standard input
-                println!("XXX DOS 1+ - DIRECT CHARACTER INPUT, WITHOUT ECHO");
             }
             0x09 => {
                 // DOS 1+ - WRITE STRING TO STANDARD OUTPUT
@@ -131,18 +1275,28 @@ impl Component for DOS {
                     if b as char == '$' {
                         break;
                     }
-                    print!("{}", cp437::u8_as_char(b));
-                    // machine.gpu_mut.write_char(&mut machine.mmu, b as u16, 0, 0, 1, false);
+                    self.write_console(mmu, b);
                 }
                 //cpu.set_r8(R::AL, b'$');
             }
+            0x0A => {
+                // DOS 1+ - BUFFERED INPUT
+                // DS:DX -> buffer: maximum characters including the final CR (byte),
+                // number of characters read excluding the CR (byte, set on return), characters
+                let (ds, dx) = (cpu.get_r16(R::DS), cpu.get_r16(R::DX));
+                if self.stdin.is_some() {
+                    self.read_stdin_line(mmu, ds, dx);
+                } else {
+                    warn!(target: "dos", "XXX DOS 1+ - BUFFERED INPUT to {:04X}:{:04X}", ds, dx);
+                }
+            }
             0x0B => {
                 // DOS 1+ - GET STDIN STATUS
                 // Return:
                 // AL = status
                 // 00h if no character available
                 // FFh if character is available
-                println!("XXX DOS 1+ - GET STDIN STATUS");
+                cpu.set_r8(R::AL, if self.stdin_ready() { 0xFF } else { 0x00 });
             }
             0x0C => {
                 // DOS 1+ - FLUSH BUFFER AND READ STANDARD INPUT
@@ -167,11 +1321,17 @@ impl Component for DOS {
                     _ => {},
                 }
             }
+            0x0E => {
+                // DOS 1+ - SELECT DEFAULT DRIVE
+                // DL = new default drive (00h = A:, 01h = B:, etc)
+                // Return: AL = number of potentially valid drive letters
+                self.select_drive(cpu.get_r8(R::DL));
+                cpu.set_r8(R::AL, self.drives.last_drive());
+            }
             0x19 => {
                 // DOS 1+ - GET CURRENT DEFAULT DRIVE
                 // Return: AL = drive (00h = A:, 01h = B:, etc)
-                println!("XXX DOS - GET CURRENT DEFAULT DRIVE");
-                cpu.set_r8(R::AL, 0); // XXX drive = A:
+                cpu.set_r8(R::AL, self.current_drive());
             }
             0x1A => {
                 // DOS 1+ - SET DISK TRANSFER AREA ADDRESS
@@ -179,7 +1339,29 @@ impl Component for DOS {
                 // Notes: The DTA is set to PSP:0080h when a program is started.
                 let seg = cpu.get_r16(R::DS);
                 let off = cpu.get_r16(R::DX);
-                println!("XXX DOS - SET DISK TRANSFER AREA ADDRESS {:04X}:{:04X}", seg, off);
+                warn!(target: "dos", "XXX DOS - SET DISK TRANSFER AREA ADDRESS {:04X}:{:04X}", seg, off);
+            }
+            0x1B | 0x1C => {
+                // DOS 1+ - GET ALLOCATION INFORMATION FOR DEFAULT DRIVE (AH=1Bh)
+                // DOS 1+ - GET ALLOCATION INFORMATION FOR SPECIFIC DRIVE (AH=1Ch)
+                // DL = drive (00h = default, 01h = A:, etc), for AH=1Ch
+                // Return:
+                // AL = sectors per cluster (allocation unit), or FFh if invalid drive
+                // CX = bytes per sector
+                // DX = total number of clusters
+                // DS:BX -> media ID byte
+                let dl = if cpu.get_r8(R::AH) == 0x1B { 0 } else { cpu.get_r8(R::DL) };
+                match self.drive_geometry(dl) {
+                    Some(geometry) => {
+                        mmu.write_u8(Self::DATA_SEG, Self::MEDIA_ID, geometry.media_descriptor);
+                        cpu.set_r8(R::AL, geometry.sectors_per_cluster as u8);
+                        cpu.set_r16(R::CX, geometry.bytes_per_sector);
+                        cpu.set_r16(R::DX, geometry.total_clusters);
+                        cpu.set_r16(R::DS, Self::DATA_SEG);
+                        cpu.set_r16(R::BX, Self::MEDIA_ID);
+                    }
+                    None => cpu.set_r8(R::AL, 0xFF),
+                }
             }
             0x25 => {
                 // DOS 1+ - SET INTERRUPT VECTOR
@@ -188,30 +1370,25 @@ impl Component for DOS {
                 let int = cpu.get_r8(R::AL);
                 mmu.write_vec(u16::from(int), MemoryAddress::LongSegmentOffset(seg, off));
             }
-            0x2C => {
-                // DOS 1+ - GET SYSTEM TIME
-                if cpu.deterministic {
-                    cpu.set_r16(R::CX, 0);
-                    cpu.set_r16(R::DX, 0);
-                } else {
-                    let now = chrono::Local::now();
-                    let centi_sec = now.nanosecond() / 1000_0000; // nanosecond to 1/100 sec
-                    cpu.set_r8(R::CH, now.hour() as u8);    // hour
-                    cpu.set_r8(R::CL, now.minute() as u8);  // minute
-                    cpu.set_r8(R::DH, now.second() as u8);  // second
-                    cpu.set_r8(R::DL, centi_sec as u8);     // 1/100 second
-                }
-            }
             0x2F => {
                 // DOS 2+ - GET DISK TRANSFER AREA ADDRESS
                 // Return: ES:BX -> current DTA
-                println!("XXX DOS - GET DISK TRANSFER AREA ADDRESS");
+                warn!(target: "dos", "XXX DOS - GET DISK TRANSFER AREA ADDRESS");
             }
             0x30 => {
                 // DOS 2+ - GET DOS VERSION
-                cpu.set_r8(R::AL, 5); // major version number
-                cpu.set_r8(R::AH, 0); // minor version number
-                cpu.set_r8(R::BH, 0xFF); // indicates MS-DOS
+                // AL = what to return in BH (00h OEM number, 01h version flag)
+                // Return:
+                // AL = major version number, AH = minor version number
+                // BL:CX = 24-bit user serial number
+                // BH = MS-DOS OEM number, or version flag (08h DOS is in ROM, 10h DOS is in HMA)
+                let version = self.version.for_program(&self.setver, &self.program_path);
+                let bh = if cpu.get_r8(R::AL) == 0x01 && version.major >= 5 { 0x00 } else { 0xFF };
+                cpu.set_r8(R::AL, version.major);
+                cpu.set_r8(R::AH, version.minor);
+                cpu.set_r8(R::BH, bh);
+                cpu.set_r8(R::BL, 0);
+                cpu.set_r16(R::CX, 0);
             }
             0x31 => {
                 // DOS 2+ - TERMINATE AND STAY RESIDENT
@@ -220,21 +1397,54 @@ impl Component for DOS {
                 // Return: Never
                 let code = cpu.get_r8(R::AL);
                 let paragraphs = cpu.get_r16(R::DX);
-                println!("XXX DOS - TERMINATE AND STAY RESIDENT, code:{:02X}, paragraphs:{:04X}", code, paragraphs);
+                warn!(target: "dos", "XXX DOS - TERMINATE AND STAY RESIDENT, code:{:02X}, paragraphs:{:04X}", code, paragraphs);
                 cpu.fatal_error = true;
             }
             0x33 => {
-                // DOS 2+ - EXTENDED BREAK CHECKING
-                // AL = subfunction
-                // 00h get current extended break state
-                // Return:
-                // DL = current state, 00h = off, 01h = on
-                // 01h set state of extended ^C/^Break checking
-                // DL = new state
-                // 00h off, check only on character I/O functions
-                // 01h on, check on all DOS functions
-                let al = cpu.get_r8(R::AL);
-                println!("XXX DOS - EXTENDED BREAK CHECKING, al:{:02X}", al);
+                match cpu.get_r8(R::AL) {
+                    0x00 => {
+                        // DOS 2+ - EXTENDED BREAK CHECKING - GET STATE
+                        // Return: DL = current state, 00h = off, 01h = on
+                        cpu.set_r8(R::DL, self.break_check as u8);
+                    }
+                    0x01 => {
+                        // DOS 2+ - EXTENDED BREAK CHECKING - SET STATE
+                        // DL = new state, 00h = off (check only on character I/O functions), 01h = on (check on all DOS functions)
+                        self.break_check = cpu.get_r8(R::DL) & 1 != 0;
+                    }
+                    0x02 => {
+                        // DOS 4+ - GET AND SET EXTENDED CONTROL-BREAK CHECKING STATE
+                        // DL = new state
+                        // Return: DL = old state
+                        let old = self.break_check;
+                        self.break_check = cpu.get_r8(R::DL) & 1 != 0;
+                        cpu.set_r8(R::DL, old as u8);
+                    }
+                    0x05 => {
+                        // DOS 4+ - GET BOOT DRIVE
+                        // Return: DL = boot drive (1 = A:, ...)
+                        cpu.set_r8(R::DL, 3);
+                    }
+                    0x06 => {
+                        // DOS 5+ - GET TRUE VERSION NUMBER, not changed by SETVER
+                        // Return:
+                        // BL = major version, BH = minor version, DL = revision (bits 2-0)
+                        // DH = version flags (bit 3: DOS is in ROM, bit 4: DOS is in HMA)
+                        // AL = FFh if the true version is less than 5.0
+                        if self.version.major >= 5 {
+                            cpu.set_r8(R::BL, self.version.major);
+                            cpu.set_r8(R::BH, self.version.minor);
+                            cpu.set_r16(R::DX, 0);
+                        } else {
+                            cpu.set_r8(R::AL, 0xFF);
+                        }
+                    }
+                    al => {
+                        // Return: AL = FFh for unsupported subfunctions
+                        warn!(target: "dos", "int21 (dos) error: break checking ah=33, al={:02X}", al);
+                        cpu.set_r8(R::AL, 0xFF);
+                    }
+                }
             }
             0x35 => {
                 // DOS 2+ - GET INTERRUPT VECTOR
@@ -243,6 +1453,72 @@ impl Component for DOS {
                 cpu.set_r16(R::ES, seg);
                 cpu.set_r16(R::BX, off);
             }
+            0x36 => {
+                // DOS 2+ - GET FREE DISK SPACE
+                // DL = drive number (00h = default, 01h = A:, etc)
+                // Return:
+                // AX = FFFFh if invalid drive
+                // else
+                // AX = sectors per cluster
+                // BX = number of free clusters
+                // CX = bytes per sector
+                // DX = total clusters on drive
+                match self.drive_geometry(cpu.get_r8(R::DL)) {
+                    Some(geometry) => {
+                        cpu.set_r16(R::AX, geometry.sectors_per_cluster);
+                        cpu.set_r16(R::BX, geometry.free_clusters);
+                        cpu.set_r16(R::CX, geometry.bytes_per_sector);
+                        cpu.set_r16(R::DX, geometry.total_clusters);
+                    }
+                    None => cpu.set_r16(R::AX, 0xFFFF),
+                }
+            }
+            0x38 => {
+                // DOS 2+ - GET COUNTRY-SPECIFIC INFORMATION
+                // DOS 3+ - SET COUNTRY CODE (DX = FFFFh)
+                // AL = 00h get current-country info
+                // AL = 01h-FEh specific country with code < 255
+                // AL = FFh specific country with code >= 255, BX = 16-bit country code
+                // DS:DX -> buffer for returned info
+                // Return:
+                // CF set on error, AX = error code (02h)
+                // CF clear if successful, BX = country code
+                let code = match cpu.get_r8(R::AL) {
+                    0x00 => self.country,
+                    0xFF => cpu.get_r16(R::BX),
+                    al => u16::from(al),
+                };
+                match CountryInfo::for_code(code) {
+                    Some(info) => {
+                        let dx = cpu.get_r16(R::DX);
+                        if dx == 0xFFFF {
+                            self.country = code;
+                        } else {
+                            mmu.write(cpu.get_r16(R::DS), dx, &info.to_bytes(self.codepage, (Self::DATA_SEG, Self::CASE_MAP)));
+                        }
+                        cpu.set_r16(R::BX, code);
+                        cpu.regs.flags.set_carry(false);
+                    }
+                    None => {
+                        warn!(target: "dos", "XXX DOS - GET COUNTRY-SPECIFIC INFORMATION, unknown country {}", code);
+                        cpu.set_r16(R::AX, 0x0002); // file not found
+                        cpu.regs.flags.set_carry(true);
+                    }
+                }
+            }
+            0x3B => {
+                // DOS 2+ - CHDIR - SET CURRENT DIRECTORY
+                // DS:DX -> ASCIZ pathname to become current directory (max 64 bytes)
+                // Return: CF clear if successful, CF set on error and AX = error code (03h)
+                let name = self.codepage.to_utf8(&mmu.readz(cpu.get_r16(R::DS), cpu.get_r16(R::DX)));
+                match self.change_dir(&name) {
+                    Ok(()) => mmu.set_flag(FLAG_CF, false),
+                    Err(code) => {
+                        cpu.set_r16(R::AX, code);
+                        mmu.set_flag(FLAG_CF, true);
+                    }
+                }
+            }
             0x3D => {
                 // DOS 2+ - OPEN - OPEN EXISTING FILE
                 let mode = cpu.get_r8(R::AL); // access and sharing modes (see #01402)
@@ -254,17 +1530,17 @@ impl Component for DOS {
                 let filename = cp437::to_utf8(&data);
 
                 // XXX need to find file match with varying case
-                let to_load = Path::new(&self.program_path).parent().unwrap().join(filename);
+                let to_load = self.host_path(&filename);
                 if to_load.exists() {
-                    println!("OPEN - OPEN EXISTING FILE {}, mode {:02X}, attr {:02X}", to_load.display(), mode, attr);
+                    debug!(target: "dos", "OPEN - OPEN EXISTING FILE {}, mode {:02X}, attr {:02X}", to_load.display(), mode, attr);
                     // CF clear if successful and AX = file handle
                     let handle = self.open_existing_file(to_load);
-                    cpu.regs.flags.carry = false;
+                    cpu.regs.flags.set_carry(false);
                     cpu.set_r16(R::AX, handle);
                 } else {
                     // CF set on error and AX = error code (01h,02h,03h,04h,05h,0Ch,56h) (see #01680 at AH=59h)
-                    println!("OPEN - OPEN EXISTING FILE {} - NOT FOUND", to_load.display());
-                    cpu.regs.flags.carry = true;
+                    debug!(target: "dos", "OPEN - OPEN EXISTING FILE {} - NOT FOUND", to_load.display());
+                    cpu.regs.flags.set_carry(true);
                     cpu.set_r16(R::AX, 0x0002); // 2 = "file not found"
                 }
             }
@@ -272,14 +1548,14 @@ impl Component for DOS {
                 // DOS 2+ - CLOSE - CLOSE FILE
                 let handle = cpu.get_r16(R::BX); // file handle
                 if let Some(_) = self.get_path_from_handle(handle) {
-                    println!("CLOSE - CLOSE FILE, handle {:04X}", handle);
+                    debug!(target: "dos", "CLOSE - CLOSE FILE, handle {:04X}", handle);
                     self.file_handles.remove(&handle);
                     // CF clear if successful and AX destroyed
-                    cpu.regs.flags.carry = false;
+                    cpu.regs.flags.set_carry(false);
                 } else {
                     // CF set on error and AX = error code (06h) (see #01680 at AH=59h/BX=0000h)
-                    cpu.regs.flags.carry = true;
-                    println!("XXX - ignoring close unknown handle {}", handle);
+                    cpu.regs.flags.set_carry(true);
+                    warn!(target: "dos", "XXX - ignoring close unknown handle {}", handle);
                 }
             }
             0x3F => {
@@ -289,27 +1565,37 @@ impl Component for DOS {
                 // DS:DX -> buffer for data
                 let ds = cpu.get_r16(R::DS);
                 let dx = cpu.get_r16(R::DX);
-                println!("READ - READ FROM FILE OR DEVICE, handle {:04X}, len {}, buffer at {:04X}:{:04X}", handle, len, ds, dx);
-
-                if let Some(path) = self.get_path_from_handle(handle) {
-                    if let Ok(f) = File::open(path) {
-                        // read up to `len` bytes
-                        let mut buf = vec![0u8; len];
-                        let mut handle = f.take(len as u64);
-                        match handle.read(&mut buf) {
-                            Ok(read_bytes) => {
-                                // XXX 3. write N bytes to DS:DX
-                                mmu.write(ds, dx, &buf);
-
-                                // XXX set AX to number of bytes that was read
-                                cpu.regs.flags.carry = false;
-                                cpu.set_r16(R::AX, read_bytes as u16);
-                                if read_bytes != len {
-                                    println!("--- wanted {} bytes, read {} bytes", len, read_bytes);
-                                }
+                debug!(target: "dos", "READ - READ FROM FILE OR DEVICE, handle {:04X}, len {}, buffer at {:04X}:{:04X}", handle, len, ds, dx);
+
+                if handle == 0 && self.stdin.is_some() {
+                    let mut buf = Vec::with_capacity(len);
+                    if let Some(input) = &mut self.stdin {
+                        let n = len.min(input.len());
+                        buf.extend(input.drain(..n));
+                    }
+                    mmu.write(ds, dx, &buf);
+                    mmu.set_flag(FLAG_CF, false);
+                    cpu.set_r16(R::AX, buf.len() as u16);
+                } else if let Some(path) = self.get_path_from_handle(handle).cloned() {
+                    // read up to `len` bytes
+                    let mut buf = vec![0u8; len];
+                    match File::open(&path).and_then(|f| f.take(len as u64).read(&mut buf)) {
+                        Ok(read_bytes) => {
+                            // XXX 3. write N bytes to DS:DX
+                            mmu.write(ds, dx, &buf);
+
+                            // XXX set AX to number of bytes that was read
+                            cpu.regs.flags.set_carry(false);
+                            cpu.set_r16(R::AX, read_bytes as u16);
+                            if read_bytes != len {
+                                debug!(target: "dos", "--- wanted {} bytes, read {} bytes", len, read_bytes);
                             }
-                            Err(e) => panic!(e),
-                        };
+                        }
+                        Err(e) => {
+                            warn!(target: "dos", "READ FROM FILE {}: {}", path.display(), e);
+                            let drive = self.device_info(handle).unwrap_or(0) & INFO_DRIVE_MASK;
+                            self.critical_error(cpu, mmu, 0x0B, drive as u8, false); // read fault
+                        }
                     }
                 }
             }
@@ -335,14 +1621,20 @@ impl Component for DOS {
                 let ds = cpu.get_r16(R::DS);
                 let dx = cpu.get_r16(R::DX);
                 let count = cpu.get_r16(R::CX);
-                println!("XXX DOS - WRITE TO FILE OR DEVICE, handle={:04X}, count={:04X}, data from {:04X}:{:04X}",
+                warn!(target: "dos", "XXX DOS - WRITE TO FILE OR DEVICE, handle={:04X}, count={:04X}, data from {:04X}:{:04X}",
                         cpu.get_r16(R::BX),
                         count,
                         ds,
                         dx);
 
                 let data = mmu.read(ds, dx, count as usize);
-                println!("  -- DATA: {} {}", hex_bytes(&data), bytes_to_ascii(&data));
+                debug!(target: "dos", "  -- DATA: {} {}", hex_bytes(&data), bytes_to_ascii(&data));
+                if cpu.get_r16(R::BX) == 1 {
+                    // standard output
+                    for b in data {
+                        self.write_console(mmu, b);
+                    }
+                }
             }
             0x43 => {
                 match cpu.get_r8(R::AL) {
@@ -352,38 +1644,13 @@ impl Component for DOS {
                         // AL = 80h XMS driver installed
                         // AL <> 80h no driver
                         cpu.set_r8(R::AL, 0); // signals that XMS is not installed
-                        println!("XXX DOS - XMS INSTALLATION CHECK");
-                    }
-                    _ => println!("int21 (dos) error: xms ah=43, al={:02X}",
-                        cpu.get_r8(R::AL)),
-                }
-            }
-            0x44 => {
-                match cpu.get_r8(R::AL) {
-                    0x00 => {
-                        // DOS 2+ - IOCTL - GET DEVICE INFORMATION
-                        // BX = handle
-                        // Return:
-                        // CF clear if successful
-                        // DX = device information word (see #01423)
-                        // CF set on error
-                        // AX = error code (01h,05h,06h) (see #01680 at AH=59h/BX=0000h)
-                        println!("XXX DOS - IOCTL - GET DEVICE INFORMATION, handle={:04X}",  cpu.get_r16(R::BX))
+                        warn!(target: "dos", "XXX DOS - XMS INSTALLATION CHECK");
                     }
-                    0x01 => {
-                        // DOS 2+ - IOCTL - SET DEVICE INFORMATION
-                        // BX = handle (must refer to character device)
-                        // DX = device information word (see #01423)
-                        // (DH must be zero for DOS version prior to 6.x)
-                        // Return:
-                        // CF clear if successful / set on error
-                        // AX = error code (01h,05h,06h,0Dh) (see #01680 at AH=59h/BX=0000h)
-                        println!("XXX DOS - IOCTL - SET DEVICE INFORMATION, handle={:04X}, device:{:04X}",  cpu.get_r16(R::BX),  cpu.get_r16(R::DX));
-                    }
-                    _ => println!("int21 (dos) error: ioctl ah=44, al={:02X}",
+                    _ => warn!(target: "dos", "int21 (dos) error: xms ah=43, al={:02X}",
                         cpu.get_r8(R::AL)),
                 }
             }
+            0x44 => self.ioctl(cpu, mmu),
             0x47 => {
                 // DOS 2+ - CWD - GET CURRENT DIRECTORY
                 // DL = drive number (00h = default, 01h = A:, etc)
@@ -396,7 +1663,7 @@ impl Component for DOS {
                 // AX = error code (0Fh) (see #01680 at AH=59h/BX=0000h)
                 let ds = cpu.get_r16(R::DS);
                 let si = cpu.get_r16(R::SI);
-                println!("XXX DOS - CWD - GET CURRENT DIRECTORY. dl={:02X}, DS:SI={:04X}:{:04X}",
+                warn!(target: "dos", "XXX DOS - CWD - GET CURRENT DIRECTORY. dl={:02X}, DS:SI={:04X}:{:04X}",
                     cpu.get_r8(R::DL), ds, si);
             }
             0x48 => {
@@ -408,13 +1675,13 @@ impl Component for DOS {
                 // CF set on error
                 // AX = error code (07h,08h) (see #01680 at AH=59h/BX=0000h)
                 // BX = size of largest available block
-                println!("XXX impl DOS 2+ - ALLOCATE MEMORY. bx={:04X}",
+                warn!(target: "dos", "XXX impl DOS 2+ - ALLOCATE MEMORY. bx={:04X}",
                         cpu.get_r16(R::BX));
 
                 // SIGNAL FAILURE
                 cpu.set_r16(R::AX, 0x0008); // out of memory
                 cpu.set_r16(R::BX, 0x0000);
-                cpu.regs.flags.carry = true;
+                cpu.regs.flags.set_carry(true);
             }
             0x49 => {
                 // DOS 2+ - FREE MEMORY
@@ -423,9 +1690,9 @@ impl Component for DOS {
                 // CF clear if successful
                 // CF set on error
                 // AX = error code (07h,09h) (see #01680 at AH=59h/BX=0000h)
-                println!("XXX impl DOS 2+ - FREE MEMORY. es={:04X}",
+                warn!(target: "dos", "XXX impl DOS 2+ - FREE MEMORY. es={:04X}",
                         cpu.get_r16(R::ES));
-                cpu.regs.flags.carry = false; // fake success
+                cpu.regs.flags.set_carry(false); // fake success
             }
             0x4A => {
                 // DOS 2+ - RESIZE MEMORY BLOCK
@@ -436,10 +1703,10 @@ impl Component for DOS {
                 // CF set on error
                 // AX = error code (07h,08h,09h) (see #01680 at AH=59h/BX=0000h)
                 // BX = maximum paragraphs available for specified memory block
-                println!("XXX impl DOS 2+ - RESIZE MEMORY BLOCK. bx={:04X}, es={:04X}",
+                warn!(target: "dos", "XXX impl DOS 2+ - RESIZE MEMORY BLOCK. bx={:04X}, es={:04X}",
                         cpu.get_r16(R::BX),
                         cpu.get_r16(R::ES));
-                cpu.regs.flags.carry = false; // fake success
+                cpu.regs.flags.set_carry(false); // fake success
             }
             0x4B => {
                 // DOS 2+ - EXEC - LOAD AND/OR EXECUTE PROGRAM
@@ -464,7 +1731,33 @@ impl Component for DOS {
 
                 let mode = cpu.get_r8(R::AL);
                 let name = mmu.read_asciiz(cpu.get_r16(R::DS), cpu.get_r16(R::DX));
-                println!("XXX DOS - EXEC - LOAD AND/OR EXECUTE PROGRAM {}, mode {:02X}", name, mode);
+                if mode != 0x00 {
+                    warn!(target: "dos", "XXX DOS - EXEC - LOAD AND/OR EXECUTE PROGRAM {}, mode {:02X}", name, mode);
+                    return true;
+                }
+                let path = self.host_path(&name);
+                let file_name = name.rsplit(['\\', ':']).next().unwrap_or(&name);
+                // COMMAND.COM is the built-in shell, started for "COMMAND /C" and the like
+                if !file_name.eq_ignore_ascii_case("COMMAND.COM") && !path.is_file() {
+                    cpu.set_r16(R::AX, 0x0002); // file not found
+                    mmu.set_flag(FLAG_CF, true);
+                    return true;
+                }
+                // parameter block: environment segment, then a far pointer to the command tail
+                let (es, bx) = (cpu.get_r16(R::ES), cpu.get_r16(R::BX));
+                let env_segment = mmu.read_u16(es, bx);
+                let (tail_offset, tail_segment) = (mmu.read_u16(es, bx.wrapping_add(2)), mmu.read_u16(es, bx.wrapping_add(4)));
+                let length = mmu.read_u8(tail_segment, tail_offset).min(126);
+                let tail = mmu.read(tail_segment, tail_offset.wrapping_add(1), usize::from(length));
+                let tail = String::from_utf8_lossy(&tail).into_owned();
+                info!(target: "dos", "DOS - EXEC - LOAD AND EXECUTE PROGRAM {}{}", name, tail);
+                self.exec_request = Some(ExecRequest {
+                    path,
+                    name: self.full_path(&name).to_ascii_uppercase(),
+                    tail,
+                    env_segment,
+                });
+                mmu.set_flag(FLAG_CF, false);
             }
             0x4C => {
                 // DOS 2+ - EXIT - TERMINATE WITH RETURN CODE
@@ -474,7 +1767,8 @@ impl Component for DOS {
                 // all open files are closed and all memory belonging to the process is freed. All
                 // network file locks should be removed before calling this function
                 let al = cpu.get_r8(R::AL);
-                println!("DOS - TERMINATE WITH RETURN CODE {:02X}", al);
+                info!(target: "dos", "DOS - TERMINATE WITH RETURN CODE {:02X}", al);
+                self.exit_code = Some(al);
                 cpu.fatal_error = true; // XXX just to stop debugger.run() function
             }
             0x4D => {
@@ -487,19 +1781,58 @@ impl Component for DOS {
                 // 03h terminate and stay resident (INT 21/AH=31h or INT 27)
                 // AL = return code
                 // CF clear
-                println!("XXX DOS 2+ - GET RETURN CODE");
+                cpu.set_r16(R::AX, u16::from(self.child_exit_code));
+                mmu.set_flag(FLAG_CF, false);
             }
             0x50 => {
                 // DOS 2+ internal - SET CURRENT PROCESS ID (SET PSP ADDRESS)
                 // BX = segment of PSP for new process
                 let bx = cpu.get_r16(R::BX);
-                println!("XXX DOS 2+ - SET CURRENT PROCESS ID, bx={:04X}", bx);
+                warn!(target: "dos", "XXX DOS 2+ - SET CURRENT PROCESS ID, bx={:04X}", bx);
             }
             0x51 => {
                 // DOS 2+ internal - GET CURRENT PROCESS ID (GET PSP ADDRESS)
                 // Return: BX = segment of PSP for current process
-                println!("XXX DOS - GET CURRENT PROCESS ID");
+                warn!(target: "dos", "XXX DOS - GET CURRENT PROCESS ID");
+            }
+            0x52 => {
+                // DOS 2+ internal - SYSVARS - GET LIST OF LISTS
+                // Return: ES:BX -> DOS list of lists
+                cpu.set_r16(R::ES, Self::DATA_SEG);
+                cpu.set_r16(R::BX, Self::LIST_OF_LISTS);
+            }
+            0x65 => self.extended_country_info(cpu, mmu),
+            0x71 => self.long_filename(cpu, mmu),
+            0x66 => {
+                match cpu.get_r8(R::AL) {
+                    0x01 => {
+                        // DOS 3.3+ - GET GLOBAL CODE PAGE TABLE
+                        // Return: CF clear if successful, BX = active code page, DX = system code page
+                        cpu.set_r16(R::BX, self.codepage.number());
+                        cpu.set_r16(R::DX, self.codepage.number());
+                        cpu.regs.flags.set_carry(false);
+                    }
+                    0x02 => {
+                        // DOS 3.3+ - SET GLOBAL CODE PAGE TABLE
+                        // BX = active code page, DX = system code page
+                        // Return: CF set on error, AX = error code (02h)
+                        match Codepage::from_number(cpu.get_r16(R::BX)) {
+                            Some(codepage) => {
+                                self.set_codepage(mmu, codepage);
+                                cpu.regs.flags.set_carry(false);
+                            }
+                            None => {
+                                warn!(target: "dos", "XXX DOS - SET GLOBAL CODE PAGE TABLE, unsupported code page {}", cpu.get_r16(R::BX));
+                                cpu.set_r16(R::AX, 0x0002);
+                                cpu.regs.flags.set_carry(true);
+                            }
+                        }
+                    }
+                    _ => warn!(target: "dos", "int21 (dos) error: code page ah=66, al={:02X}",
+                        cpu.get_r8(R::AL)),
+                }
             }
+            0x57 => self.file_date_time(cpu, mmu),
             0x59 => {
                 match cpu.get_r16(R::BX) {
                     0x0000 => {
@@ -511,14 +1844,14 @@ impl Component for DOS {
                         // CH = error locus (see #01684)
                         // ES:DI may be pointer (see #01681, #01680)
                         // CL, DX, SI, BP, and DS destroyed
-                        println!("XXX DOS - GET EXTENDED ERROR INFORMATION");
+                        warn!(target: "dos", "XXX DOS - GET EXTENDED ERROR INFORMATION");
                     }
-                    _ => println!("int21 (dos) error: unknown ah=59, bx={:04X}",
+                    _ => warn!(target: "dos", "int21 (dos) error: unknown ah=59, bx={:04X}",
                         cpu.get_r16(R::BX)),
                 }
             }
             _ => {
-                println!("int21 (dos) error: unknown ah={:02X}, ax={:04X}",
+                warn!(target: "dos", "int21 (dos) error: unknown ah={:02X}, ax={:04X}",
                         cpu.get_r8(R::AH),
                         cpu.get_r16(R::AX));
                 return false;
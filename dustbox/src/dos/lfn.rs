@@ -0,0 +1,158 @@
+// Windows95 long filename support: short name generation and directory listings
+// http://www.ctyme.com/intr/rb-2939.htm
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[cfg(test)]
+#[path = "./lfn_test.rs"]
+mod lfn_test;
+
+pub const FILE_ATTR_READ_ONLY: u8 = 0x01;
+pub const FILE_ATTR_HIDDEN: u8    = 0x02;
+pub const FILE_ATTR_SYSTEM: u8    = 0x04;
+pub const FILE_ATTR_DIRECTORY: u8 = 0x10;
+pub const FILE_ATTR_ARCHIVE: u8   = 0x20;
+
+/// characters allowed in long names, but not in short names
+const SHORT_NAME_INVALID: &str = "+,;=[]";
+
+/// a file or directory of a host directory, as seen by DOS
+#[derive(Clone, Debug, PartialEq)]
+pub struct DirEntry {
+    /// the host file name
+    pub long_name: String,
+
+    /// the generated 8.3 name
+    pub short_name: String,
+
+    pub attributes: u8,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+/// returns true if `name` is a valid 8.3 name, such as "FILE.TXT" or "readme"
+pub fn is_short_name(name: &str) -> bool {
+    let (base, ext) = match name.rfind('.') {
+        Some(pos) => (&name[..pos], &name[pos + 1..]),
+        None => (name, ""),
+    };
+    let valid = |s: &str| s.chars().all(|c| c.is_ascii() && c > ' ' && c != '.' && !SHORT_NAME_INVALID.contains(c) && !"\"*/:<>?\\|".contains(c));
+    !base.is_empty() && base.len() <= 8 && ext.len() <= 3 && valid(base) && valid(ext)
+}
+
+/// returns the 8.3 name of `long_name` such as "LONGFI~1.TXT", unique among `taken` (upper case names)
+pub fn short_name(long_name: &str, taken: &[String]) -> String {
+    if is_short_name(long_name) {
+        return long_name.to_ascii_uppercase();
+    }
+    let clean = |s: &str| -> String {
+        s.chars()
+            .filter(|c| *c != ' ' && *c != '.')
+            .map(|c| if !c.is_ascii() || SHORT_NAME_INVALID.contains(c) { '_' } else { c.to_ascii_uppercase() })
+            .collect()
+    };
+    let trimmed = long_name.trim_start_matches('.');
+    let (base, ext) = match trimmed.rfind('.') {
+        Some(pos) => (clean(&trimmed[..pos]), clean(&trimmed[pos + 1..])),
+        None => (clean(trimmed), String::new()),
+    };
+    let base = if base.is_empty() { "_".to_owned() } else { base };
+    let ext: String = ext.chars().take(3).collect();
+
+    for n in 1.. {
+        let tail = format!("~{}", n);
+        let prefix: String = base.chars().take(8 - tail.len()).collect();
+        let name = if ext.is_empty() {
+            format!("{}{}", prefix, tail)
+        } else {
+            format!("{}{}.{}", prefix, tail, ext)
+        };
+        if !taken.contains(&name) {
+            return name;
+        }
+    }
+    unreachable!();
+}
+
+/// returns the entries of host directory `dir` sorted by name, with unique short names
+pub fn list_dir(dir: &Path) -> io::Result<Vec<DirEntry>> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .collect();
+    names.sort();
+
+    // names that are already valid 8.3 names keep them
+    let mut taken: Vec<String> = names.iter().filter(|n| is_short_name(n)).map(|n| n.to_ascii_uppercase()).collect();
+    let mut entries = Vec::with_capacity(names.len());
+    for long_name in names {
+        let meta = match fs::metadata(dir.join(&long_name)) {
+            Ok(meta) => meta,
+            Err(_) => continue,
+        };
+        let short = if is_short_name(&long_name) {
+            long_name.to_ascii_uppercase()
+        } else {
+            let name = short_name(&long_name, &taken);
+            taken.push(name.clone());
+            name
+        };
+        let mut attributes = if meta.is_dir() { FILE_ATTR_DIRECTORY } else { FILE_ATTR_ARCHIVE };
+        if meta.permissions().readonly() {
+            attributes |= FILE_ATTR_READ_ONLY;
+        }
+        if long_name.starts_with('.') {
+            attributes |= FILE_ATTR_HIDDEN;
+        }
+        entries.push(DirEntry {
+            long_name,
+            short_name: short,
+            attributes,
+            size: if meta.is_dir() { 0 } else { meta.len() },
+            modified: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        });
+    }
+    Ok(entries)
+}
+
+/// returns the host path of entry `name` in host directory `dir`, matching its long or short name without case
+pub fn find_entry(dir: &Path, name: &str) -> Option<PathBuf> {
+    let exact = dir.join(name);
+    if exact.exists() {
+        return Some(exact);
+    }
+    list_dir(dir).ok()?
+        .into_iter()
+        .find(|e| e.long_name.eq_ignore_ascii_case(name) || e.short_name.eq_ignore_ascii_case(name))
+        .map(|e| dir.join(e.long_name))
+}
+
+/// returns true if `name` matches the wildcard `pattern`, without case. as with long names,
+/// "*" matches any characters including dots, "?" any single character and "*.*" anything
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    if pattern == "*.*" {
+        return true;
+    }
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    matches(&pattern, &name)
+}
+
+fn matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|i| matches(&pattern[1..], &name[i..])),
+        Some('?') => !name.is_empty() && matches(&pattern[1..], &name[1..]),
+        Some(c) => name.first() == Some(c) && matches(&pattern[1..], &name[1..]),
+    }
+}
+
+/// returns `time` as a Windows FILETIME, the number of 100 ns intervals since 1601-01-01
+pub fn file_time(time: SystemTime) -> u64 {
+    const EPOCH_DIFF_SECS: u64 = 11_644_473_600;
+    let since_epoch = time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    (since_epoch.as_secs() + EPOCH_DIFF_SECS) * 10_000_000 + u64::from(since_epoch.subsec_nanos() / 100)
+}
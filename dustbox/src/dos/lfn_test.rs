@@ -0,0 +1,57 @@
+use std::fs;
+
+use tempfile::tempdir;
+
+use crate::dos::{find_entry, is_short_name, list_dir, short_name, wildcard_match, FILE_ATTR_ARCHIVE, FILE_ATTR_DIRECTORY};
+
+#[test]
+fn can_generate_short_names() {
+    assert!(is_short_name("FILE.TXT"));
+    assert!(is_short_name("readme"));
+    assert!(!is_short_name("long file.txt"));
+    assert!(!is_short_name("archive.tar.gz"));
+    assert!(!is_short_name("A+B.TXT"));
+
+    assert_eq!("README", short_name("readme", &[]));
+    assert_eq!("LONGFI~1.TXT", short_name("Long File Name.txt", &[]));
+    assert_eq!("LONGFI~2.TXT", short_name("long file name.txt", &["LONGFI~1.TXT".to_owned()]));
+    assert_eq!("ARCHIV~1.GZ", short_name("archive.tar.gz", &[]));
+    assert_eq!("A_B~1.TXT", short_name("a+b.txt", &[]));
+    assert_eq!("GITIGN~1", short_name(".gitignore", &[]));
+
+    let taken: Vec<String> = (1..10).map(|n| format!("LONGFI~{}.TXT", n)).collect();
+    assert_eq!("LONGF~10.TXT", short_name("long file name.txt", &taken));
+}
+
+#[test]
+fn can_match_wildcards() {
+    assert!(wildcard_match("*.*", "README"));
+    assert!(wildcard_match("*", "archive.tar.gz"));
+    assert!(wildcard_match("*.gz", "ARCHIVE.TAR.GZ"));
+    assert!(wildcard_match("file?.txt", "FILE1.TXT"));
+    assert!(!wildcard_match("file?.txt", "FILE.TXT"));
+    assert!(!wildcard_match("*.txt", "file.doc"));
+}
+
+#[test]
+fn can_list_directory_with_short_names() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("Long File Name.txt"), b"abc").unwrap();
+    fs::write(dir.path().join("long file name 2.txt"), b"").unwrap();
+    fs::create_dir(dir.path().join("Saved Games")).unwrap();
+
+    let entries = list_dir(dir.path()).unwrap();
+    let names: Vec<(&str, &str)> = entries.iter().map(|e| (e.long_name.as_str(), e.short_name.as_str())).collect();
+    assert_eq!(vec![
+        ("Long File Name.txt", "LONGFI~1.TXT"),
+        ("Saved Games", "SAVEDG~1"),
+        ("long file name 2.txt", "LONGFI~2.TXT"),
+    ], names);
+    assert_eq!(FILE_ATTR_ARCHIVE, entries[0].attributes);
+    assert_eq!(3, entries[0].size);
+    assert_eq!(FILE_ATTR_DIRECTORY, entries[1].attributes);
+
+    assert_eq!(Some(dir.path().join("Saved Games")), find_entry(dir.path(), "savedg~1"));
+    assert_eq!(Some(dir.path().join("Long File Name.txt")), find_entry(dir.path(), "LONG FILE NAME.TXT"));
+    assert_eq!(None, find_entry(dir.path(), "missing"));
+}
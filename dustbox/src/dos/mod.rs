@@ -2,3 +2,24 @@
 
 pub use self::dos::*;
 mod dos;
+
+pub use self::device::*;
+mod device;
+
+pub use self::console::*;
+mod console;
+
+pub use self::datetime::*;
+mod datetime;
+
+pub use self::lfn::*;
+mod lfn;
+
+pub use self::version::*;
+mod version;
+
+pub use self::shell::*;
+mod shell;
+
+pub use self::batch::*;
+mod batch;
@@ -0,0 +1,650 @@
+// a minimal COMMAND.COM: reads command lines from the keyboard or the redirected standard input,
+// runs the built-in commands on the mounted drives, and starts programs and batch files
+
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::dos::DOS;
+use crate::dos::batch::{parse_for, parse_if, split_word, Batch, Condition};
+use crate::dos::datetime::host_local_time;
+use crate::dos::lfn::{list_dir, wildcard_match, DirEntry, FILE_ATTR_DIRECTORY, FILE_ATTR_HIDDEN};
+use crate::memory::MMU;
+
+#[cfg(test)]
+#[path = "./shell_test.rs"]
+mod shell_test;
+
+/// segment of the program environment, as set in the PSP
+pub const ENV_SEG: u16 = 0x02E3;
+
+/// bytes available to the environment, up to the PSP of the program
+const ENV_SIZE: usize = (0x0329 - ENV_SEG as usize) * 16;
+
+/// bytes of the environment kept for the program name following the variables
+const ENV_PROGRAM_NAME: usize = 80;
+
+/// what the machine does after a step of the shell
+#[derive(Debug, PartialEq)]
+pub enum ShellAction {
+    /// the shell keeps running
+    None,
+
+    /// starts the program at host path `path`, named `name` in DOS, with command tail `tail`
+    Run { path: PathBuf, name: String, tail: String },
+
+    /// leaves the shell with a return code, stopping the machine or returning to the program that started it
+    Exit(u8),
+}
+
+pub struct Shell {
+    /// the command line being typed
+    line: Vec<u8>,
+
+    /// the prompt is shown before the next character is read
+    prompt: bool,
+
+    /// the last character read was a carriage return, a following line feed is skipped
+    carriage_return: bool,
+
+    /// environment variables in the order they were set, such as ("PATH", "C:\DOS")
+    env: Vec<(String, String)>,
+
+    /// the running batch files, each started by CALL from the one before it
+    batches: Vec<Batch>,
+
+    /// expanded commands to run before the next line, such as those of FOR
+    pending: VecDeque<String>,
+
+    /// batch file commands are displayed before they run, turned off by ECHO OFF
+    echo: bool,
+
+    /// the shell exits once its batch files and commands are done, as COMMAND /C does
+    exit_when_done: bool,
+
+    /// PAUSE waits for a key
+    paused: bool,
+
+    /// return code of the last program
+    pub errorlevel: u8,
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Shell {
+            line: Vec::new(),
+            prompt: true,
+            carriage_return: false,
+            env: vec![("COMSPEC".to_owned(), "C:\\COMMAND.COM".to_owned())],
+            batches: Vec::new(),
+            pending: VecDeque::new(),
+            echo: true,
+            exit_when_done: false,
+            paused: false,
+            errorlevel: 0,
+        }
+    }
+}
+
+impl Shell {
+    /// callback number of `step`
+    pub const CALLBACK: u16 = 0x101;
+
+    /// the resident part of the shell, a .COM program calling `step` in a loop
+    pub const PROGRAM: [u8; 6] = [
+        0xFE, 0x38, Shell::CALLBACK as u8, (Shell::CALLBACK >> 8) as u8, // callback CALLBACK
+        0xEB, 0xFA,                                                        // jmp short 0x100
+    ];
+
+    /// returns a shell with environment variables `vars`, such as "PATH=C:\DOS"
+    pub fn with_environment(vars: &[String]) -> Self {
+        let env = vars.iter()
+            .filter_map(|var| var.find('=').map(|pos| (var[..pos].to_owned(), var[pos + 1..].to_owned())))
+            .collect();
+        Shell { env, ..Shell::default() }
+    }
+
+    /// runs command line `line`, then exits with the return code of the last program
+    pub fn run_command(&mut self, line: &str) {
+        let line = self.expand(line, None);
+        self.pending.push_back(line);
+        self.exit_when_done = true;
+    }
+
+    /// runs the batch file at host path `path`, started as `name` with arguments `args`,
+    /// then exits with the return code of the last program
+    pub fn run_batch(&mut self, path: &Path, name: &str, args: &str) -> io::Result<()> {
+        let data = fs::read(path)?;
+        self.batches.push(Batch::new(&String::from_utf8_lossy(&data), name, args));
+        self.exit_when_done = true;
+        Ok(())
+    }
+
+    /// returns true if the next step reads a character of the command line
+    pub fn reads_input(&self) -> bool {
+        self.paused || (self.pending.is_empty() && self.batches.is_empty() && !self.exit_when_done)
+    }
+
+    /// runs the next command of the batch file, or handles character `input` of the command line
+    pub fn step(&mut self, dos: &mut DOS, mmu: &mut MMU, input: Option<u8>) -> ShellAction {
+        if self.paused {
+            if input.is_some() {
+                self.paused = false;
+                print(dos, mmu, "\n");
+            }
+            return ShellAction::None;
+        }
+        if let Some(line) = self.pending.pop_front() {
+            return self.execute(dos, mmu, &line);
+        }
+        if let Some(batch) = self.batches.last_mut() {
+            let line = match batch.next_line() {
+                Some(line) => line,
+                None => {
+                    self.batches.pop();
+                    if self.batches.is_empty() {
+                        self.echo = true;
+                    }
+                    return ShellAction::None;
+                }
+            };
+            let params = batch.params();
+            let line = self.expand(&line, Some(&params));
+            let line = line.trim_start();
+            let silent = line.starts_with('@');
+            let line = line.trim_start_matches('@');
+            if self.echo && !silent && !line.trim().is_empty() && !line.starts_with(':') {
+                self.print_prompt(dos, mmu);
+                print(dos, mmu, &format!("{}\n", line));
+            }
+            return self.execute(dos, mmu, line);
+        }
+        if self.exit_when_done {
+            return ShellAction::Exit(self.errorlevel);
+        }
+        if self.prompt {
+            self.prompt = false;
+            self.print_prompt(dos, mmu);
+        }
+        let b = match input {
+            Some(b) => b,
+            None => return ShellAction::None,
+        };
+        let carriage_return = std::mem::replace(&mut self.carriage_return, b == b'\r');
+        match b {
+            b'\n' if carriage_return => {}
+            b'\r' | b'\n' => {
+                print(dos, mmu, "\n");
+                self.prompt = true;
+                let line = String::from_utf8_lossy(&std::mem::take(&mut self.line)).into_owned();
+                let line = self.expand(&line, None);
+                return self.execute(dos, mmu, &line);
+            }
+            0x08 => {
+                if self.line.pop().is_some() {
+                    print(dos, mmu, "\x08 \x08");
+                }
+            }
+            // end of the redirected standard input
+            0x1A if self.line.is_empty() => return ShellAction::Exit(0),
+            0x00 | 0x1A | 0x1B => {}
+            _ => {
+                self.line.push(b);
+                dos.write_console(mmu, b);
+            }
+        }
+        ShellAction::None
+    }
+
+    /// writes the environment of the program named `program`, such as "C:\GAMES\DOOM.EXE"
+    pub fn write_environment(&self, mmu: &mut MMU, program: &str) {
+        let vars: Vec<String> = self.env.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+        write_environment(mmu, &vars, program);
+    }
+
+    /// writes command tail `tail` to the PSP at `psp_segment`
+    pub fn write_command_tail(mmu: &mut MMU, psp_segment: u16, tail: &str) {
+        let tail: Vec<u8> = tail.bytes().take(126).collect();
+        mmu.write_u8(psp_segment, 0x80, tail.len() as u8);
+        mmu.write(psp_segment, 0x81, &tail);
+        mmu.write_u8(psp_segment, 0x81 + tail.len() as u16, b'\r');
+    }
+
+    /// returns the value of environment variable `name`, without case
+    pub fn var(&self, name: &str) -> Option<&str> {
+        self.env.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    /// sets environment variable `name`, an empty value removes it. fails if the environment is full
+    pub fn set_var(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let name = name.to_ascii_uppercase();
+        let size: usize = self.env.iter().filter(|(n, _)| *n != name).map(|(n, v)| n.len() + v.len() + 2).sum();
+        if !value.is_empty() && size + name.len() + value.len() + 2 > ENV_SIZE - ENV_PROGRAM_NAME {
+            return Err("Out of environment space".to_owned());
+        }
+        match self.env.iter().position(|(n, _)| *n == name) {
+            Some(pos) if value.is_empty() => {
+                self.env.remove(pos);
+            }
+            Some(pos) => self.env[pos].1 = value.to_owned(),
+            None if value.is_empty() => {}
+            None => self.env.push((name, value.to_owned())),
+        }
+        Ok(())
+    }
+
+    /// returns `line` with the environment variables such as %PATH% replaced by their values, and
+    /// in batch files the parameters %0 to %9 by `params`. unknown variables are removed, %% gives %
+    fn expand(&self, line: &str, params: Option<&[String]>) -> String {
+        let mut expanded = String::new();
+        let mut rest = line;
+        while let Some(start) = rest.find('%') {
+            expanded.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            if let Some(stripped) = after.strip_prefix('%') {
+                expanded.push('%');
+                rest = stripped;
+                continue;
+            }
+            if let (Some(params), Some(digit)) = (params, after.chars().next().and_then(|c| c.to_digit(10))) {
+                expanded.push_str(params.get(digit as usize).map_or("", String::as_str));
+                rest = &after[1..];
+                continue;
+            }
+            match after.find('%') {
+                // "%f in" of a FOR command typed at the prompt is not a variable
+                Some(end) if end > 0 && !after[..end].contains(char::is_whitespace) => {
+                    expanded.push_str(self.var(&after[..end]).unwrap_or(""));
+                    rest = &after[end + 1..];
+                }
+                _ => {
+                    expanded.push('%');
+                    rest = after;
+                }
+            }
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+
+    fn print_prompt(&self, dos: &mut DOS, mmu: &mut MMU) {
+        let dir = if dos.current_dir.is_empty() { "C:\\" } else { dos.current_dir.as_str() };
+        let dir = if dir.len() == 2 { format!("{}\\", dir) } else { dir.to_owned() };
+        print(dos, mmu, &format!("\n{}>", dir));
+    }
+
+    /// runs expanded command line `line`
+    fn execute(&mut self, dos: &mut DOS, mmu: &mut MMU, line: &str) -> ShellAction {
+        let line = line.trim();
+        // labels are the targets of GOTO
+        if line.is_empty() || line.starts_with(':') {
+            return ShellAction::None;
+        }
+        // built-in commands end at a delimiter, such as "CD.." and "ECHO."
+        let end = line.find(|c: char| c.is_whitespace() || "./\\=".contains(c)).unwrap_or(line.len());
+        let command = line[..end].to_ascii_uppercase();
+        let rest = &line[end..];
+        let args = rest.strip_prefix(|c: char| c.is_whitespace() || c == '=').unwrap_or(rest);
+        match command.as_str() {
+            "CALL" => {
+                let args = args.trim_start();
+                let end = args.find(char::is_whitespace).unwrap_or(args.len());
+                if end > 0 {
+                    return self.run(dos, mmu, &args[..end], &args[end..], true);
+                }
+            }
+            "CD" | "CHDIR" => self.cd(dos, mmu, args.trim()),
+            "CLS" => dos.clear_console(mmu),
+            "COPY" => self.copy(dos, mmu, args),
+            "DIR" => self.dir(dos, mmu, args),
+            "ECHO" => self.echo(dos, mmu, args),
+            "EXIT" => return ShellAction::Exit(if self.exit_when_done { self.errorlevel } else { 0 }),
+            "FOR" => self.for_each(dos, mmu, args),
+            "GOTO" => self.goto(dos, mmu, split_word(args).0),
+            "IF" => return self.if_command(dos, mmu, args),
+            "PATH" => self.path(dos, mmu, args.trim()),
+            "PAUSE" => {
+                print(dos, mmu, "Press any key to continue . . .");
+                self.paused = true;
+            }
+            "REM" => {}
+            "SET" => self.set(dos, mmu, args.trim()),
+            "SHIFT" => {
+                if let Some(batch) = self.batches.last_mut() {
+                    batch.shift();
+                }
+            }
+            "TYPE" => self.type_file(dos, mmu, args.trim()),
+            "VER" => print(dos, mmu, &format!("\ndustbox DOS version {}\n", dos.version)),
+            _ => {
+                let end = line.find(char::is_whitespace).unwrap_or(line.len());
+                let (program, tail) = line.split_at(end);
+                if program.len() == 2 && program.ends_with(':') {
+                    self.select_drive(dos, mmu, program);
+                } else {
+                    return self.run(dos, mmu, program, tail, false);
+                }
+            }
+        }
+        ShellAction::None
+    }
+
+    /// starts program or batch file `name`, looked for in the current directory and in PATH.
+    /// a batch file started by another one replaces it, unless `call` is set
+    fn run(&mut self, dos: &mut DOS, mmu: &mut MMU, name: &str, tail: &str, call: bool) -> ShellAction {
+        let (path, full_name) = match self.find_program(dos, name) {
+            Some(found) => found,
+            None => {
+                print(dos, mmu, "Bad command or file name\n");
+                return ShellAction::None;
+            }
+        };
+        if full_name.ends_with(".BAT") {
+            match fs::read(&path) {
+                Ok(data) => {
+                    let batch = Batch::new(&String::from_utf8_lossy(&data), name, tail);
+                    if !call {
+                        self.batches.pop();
+                    }
+                    self.batches.push(batch);
+                }
+                Err(e) => print(dos, mmu, &format!("{}\n", e)),
+            }
+            return ShellAction::None;
+        }
+        ShellAction::Run { path, name: full_name, tail: tail.to_owned() }
+    }
+
+    /// continues the batch file after label `label`
+    fn goto(&mut self, dos: &mut DOS, mmu: &mut MMU, label: &str) {
+        let found = match self.batches.last_mut() {
+            Some(batch) => batch.goto(label),
+            None => return,
+        };
+        if !found {
+            print(dos, mmu, "Label not found\n");
+            self.batches.pop();
+        }
+    }
+
+    /// runs the command of "IF [NOT] condition command" if the condition holds
+    fn if_command(&mut self, dos: &mut DOS, mmu: &mut MMU, args: &str) -> ShellAction {
+        let (condition, negated, command) = match parse_if(args) {
+            Some(parsed) => parsed,
+            None => {
+                print(dos, mmu, "Syntax error\n");
+                return ShellAction::None;
+            }
+        };
+        let holds = match condition {
+            Condition::ErrorLevel(level) => self.errorlevel >= level,
+            Condition::Exist(name) => !matching_files(dos, &name).is_empty(),
+            Condition::Equal(left, right) => left == right,
+        };
+        if holds == negated {
+            return ShellAction::None;
+        }
+        self.execute(dos, mmu, command)
+    }
+
+    /// runs the command of "FOR %v IN (set) DO command" for each word of the set,
+    /// wildcards giving the matching files
+    fn for_each(&mut self, dos: &mut DOS, mmu: &mut MMU, args: &str) {
+        let (var, set, command) = match parse_for(args) {
+            Some(parsed) => parsed,
+            None => return print(dos, mmu, "Syntax error\n"),
+        };
+        let mut items = Vec::new();
+        for word in set {
+            if word.contains(['*', '?']) {
+                items.extend(matching_files(dos, &word));
+            } else {
+                items.push(word);
+            }
+        }
+        let var = format!("%{}", var);
+        // the commands run in order, before the next line of the batch file
+        for item in items.iter().rev() {
+            self.pending.push_front(command.replace(&var, item));
+        }
+    }
+
+    /// returns the host path and the full DOS name of program `name`. a name without
+    /// extension is looked for as .COM, .EXE and .BAT
+    fn find_program(&self, dos: &DOS, name: &str) -> Option<(PathBuf, String)> {
+        let name = name.to_ascii_uppercase();
+        let file_name = name.rsplit(['\\', ':']).next().unwrap_or(&name);
+        let extensions: &[&str] = match file_name.rfind('.') {
+            Some(pos) if [".COM", ".EXE", ".BAT"].contains(&&file_name[pos..]) => &[""],
+            Some(_) => return None,
+            None => &[".COM", ".EXE", ".BAT"],
+        };
+        let mut dirs = vec![String::new()];
+        if file_name.len() == name.len() {
+            dirs.extend(self.var("PATH").unwrap_or("").split(';').filter(|d| !d.is_empty()).map(|d| format!("{}\\", d.trim_end_matches('\\'))));
+        }
+        for dir in &dirs {
+            for extension in extensions {
+                let dos_name = format!("{}{}{}", dir, name, extension);
+                let path = dos.host_path(&dos_name);
+                if path.is_file() {
+                    return Some((path, dos.full_path(&dos_name).to_ascii_uppercase()));
+                }
+            }
+        }
+        None
+    }
+
+    fn select_drive(&mut self, dos: &mut DOS, mmu: &mut MMU, drive: &str) {
+        let letter = drive.as_bytes()[0].to_ascii_uppercase();
+        if !letter.is_ascii_uppercase() || dos.drives.get(char::from(letter)).is_none() {
+            print(dos, mmu, "Invalid drive specification\n");
+            return;
+        }
+        dos.select_drive(letter - b'A');
+    }
+
+    fn cd(&mut self, dos: &mut DOS, mmu: &mut MMU, dir: &str) {
+        if dir.is_empty() {
+            let current = if dos.current_dir.is_empty() { "C:\\".to_owned() } else { dos.current_dir.clone() };
+            print(dos, mmu, &format!("{}\n", current));
+        } else if dos.change_dir(&dir.to_ascii_uppercase()).is_err() {
+            print(dos, mmu, "Invalid directory\n");
+        }
+    }
+
+    fn dir(&mut self, dos: &mut DOS, mmu: &mut MMU, args: &str) {
+        // switches such as /W and /P are ignored
+        let arg = args.split_whitespace().find(|a| !a.starts_with('/')).unwrap_or("");
+        let (dir, pattern) = split_pattern(dos, arg);
+        let entries: Vec<DirEntry> = list_dir(&dos.host_path(dir))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|e| e.attributes & FILE_ATTR_HIDDEN == 0 && wildcard_match(pattern, &e.short_name))
+            .collect();
+        let full = dos.full_path(dir).to_ascii_uppercase();
+        print(dos, mmu, &format!("\n Directory of {}\n\n", if full.is_empty() { "C:\\" } else { &full }));
+        if entries.is_empty() {
+            print(dos, mmu, "File not found\n");
+            return;
+        }
+        let mut bytes = 0;
+        for entry in &entries {
+            let (base, ext) = match entry.short_name.rfind('.') {
+                Some(pos) => (&entry.short_name[..pos], &entry.short_name[pos + 1..]),
+                None => (entry.short_name.as_str(), ""),
+            };
+            let size = if entry.attributes & FILE_ATTR_DIRECTORY != 0 {
+                "<DIR>     ".to_owned()
+            } else {
+                bytes += entry.size;
+                entry.size.to_string()
+            };
+            let modified = host_local_time(entry.modified);
+            let line = format!("{:<8} {:<3} {:>10} {}\n", base, ext, size, modified.format("%m-%d-%y  %H:%M"));
+            print(dos, mmu, &line);
+        }
+        print(dos, mmu, &format!("{:>9} file(s) {:>14} bytes\n", entries.len(), bytes));
+    }
+
+    fn type_file(&mut self, dos: &mut DOS, mmu: &mut MMU, name: &str) {
+        if name.is_empty() {
+            print(dos, mmu, "Required parameter missing\n");
+            return;
+        }
+        match fs::read(dos.host_path(name)) {
+            Ok(data) => {
+                // the text ends at ^Z
+                for &b in data.iter().take_while(|&&b| b != 0x1A) {
+                    dos.write_console(mmu, b);
+                }
+            }
+            Err(_) => print(dos, mmu, "File not found\n"),
+        }
+    }
+
+    fn copy(&mut self, dos: &mut DOS, mmu: &mut MMU, args: &str) {
+        let args: Vec<&str> = args.split_whitespace().filter(|a| !a.starts_with('/')).collect();
+        let (source, target) = match args.as_slice() {
+            [source] => (*source, ""),
+            [source, target] => (*source, *target),
+            [] => return print(dos, mmu, "Required parameter missing\n"),
+            _ => return print(dos, mmu, "Too many parameters\n"),
+        };
+        let (dir, pattern) = split_pattern(dos, source);
+        let source_dir = dos.host_path(dir);
+        let entries: Vec<DirEntry> = list_dir(&source_dir)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|e| e.attributes & (FILE_ATTR_DIRECTORY | FILE_ATTR_HIDDEN) == 0 && wildcard_match(pattern, &e.short_name))
+            .collect();
+        if entries.is_empty() {
+            return print(dos, mmu, "File not found\n");
+        }
+        let target_path = dos.host_path(target);
+        let to_dir = target.is_empty() || target_path.is_dir();
+        if !to_dir && entries.len() > 1 {
+            return print(dos, mmu, "Cannot copy multiple files to a file\n");
+        }
+        let mut copied = 0;
+        for entry in &entries {
+            let from = source_dir.join(&entry.long_name);
+            let to = if to_dir { target_path.join(&entry.long_name) } else { target_path.clone() };
+            if from == to {
+                print(dos, mmu, "File cannot be copied onto itself\n");
+                continue;
+            }
+            print(dos, mmu, &format!("{}\n", entry.short_name));
+            match fs::copy(&from, &to) {
+                Ok(_) => copied += 1,
+                Err(e) => print(dos, mmu, &format!("{}\n", e)),
+            }
+        }
+        print(dos, mmu, &format!("{:>9} file(s) copied\n", copied));
+    }
+
+    fn echo(&mut self, dos: &mut DOS, mmu: &mut MMU, args: &str) {
+        let text = args.trim_start_matches('.');
+        match args.trim().to_ascii_uppercase().as_str() {
+            "" if !args.starts_with('.') => {
+                let state = if self.echo { "on" } else { "off" };
+                print(dos, mmu, &format!("ECHO is {}\n", state));
+            }
+            "ON" => self.echo = true,
+            "OFF" => self.echo = false,
+            _ => print(dos, mmu, &format!("{}\n", text)),
+        }
+    }
+
+    fn set(&mut self, dos: &mut DOS, mmu: &mut MMU, args: &str) {
+        if args.is_empty() {
+            let vars: String = self.env.iter().map(|(n, v)| format!("{}={}\n", n, v)).collect();
+            return print(dos, mmu, &vars);
+        }
+        match args.find('=') {
+            Some(pos) => {
+                if let Err(e) = self.set_var(args[..pos].trim(), &args[pos + 1..]) {
+                    print(dos, mmu, &format!("{}\n", e));
+                }
+            }
+            None => print(dos, mmu, "Syntax error\n"),
+        }
+    }
+
+    fn path(&mut self, dos: &mut DOS, mmu: &mut MMU, args: &str) {
+        let result = match args {
+            "" => {
+                let path = self.var("PATH").map_or("No Path".to_owned(), |p| format!("PATH={}", p));
+                return print(dos, mmu, &format!("{}\n", path));
+            }
+            ";" => self.set_var("PATH", ""),
+            _ => self.set_var("PATH", &args.to_ascii_uppercase()),
+        };
+        if let Err(e) = result {
+            print(dos, mmu, &format!("{}\n", e));
+        }
+    }
+}
+
+/// writes `text` to the console, with "\n" as carriage return and line feed
+fn print(dos: &mut DOS, mmu: &mut MMU, text: &str) {
+    for b in text.bytes() {
+        if b == b'\n' {
+            dos.write_console(mmu, b'\r');
+        }
+        dos.write_console(mmu, b);
+    }
+}
+
+/// returns the names of the files matching `pattern`, such as "GAMES\*.EXE", with its directory
+fn matching_files(dos: &DOS, pattern: &str) -> Vec<String> {
+    let (dir, pattern) = split_pattern(dos, pattern);
+    list_dir(&dos.host_path(dir))
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|e| e.attributes & (FILE_ATTR_DIRECTORY | FILE_ATTR_HIDDEN) == 0 && wildcard_match(pattern, &e.short_name))
+        .map(|e| format!("{}{}", dir, e.short_name))
+        .collect()
+}
+
+/// returns the variables of the environment at `segment`, such as "PATH=C:\DOS"
+pub fn read_environment(mmu: &MMU, segment: u16) -> Vec<String> {
+    let mut vars = Vec::new();
+    let mut offset = 0;
+    while offset < 0x8000 {
+        let var = mmu.readz(segment, offset);
+        if var.is_empty() {
+            break;
+        }
+        offset += var.len() as u16 + 1;
+        vars.push(String::from_utf8_lossy(&var).into_owned());
+    }
+    vars
+}
+
+/// writes the environment with variables `vars` of the program named `program`
+pub fn write_environment(mmu: &mut MMU, vars: &[String], program: &str) {
+    let mut block = Vec::new();
+    for var in vars {
+        block.extend_from_slice(var.as_bytes());
+        block.push(0);
+    }
+    // DOS 3+ appends the number of strings and the program name
+    block.extend_from_slice(&[0, 1, 0]);
+    block.extend(program.bytes().take(ENV_PROGRAM_NAME - 1));
+    block.push(0);
+    mmu.write(ENV_SEG, 0, &block);
+}
+
+/// splits `arg` into a directory and a file name pattern, such as "GAMES\" and "*.EXE".
+/// a directory gives all its files
+fn split_pattern<'a>(dos: &DOS, arg: &'a str) -> (&'a str, &'a str) {
+    if arg.is_empty() || dos.host_path(arg).is_dir() {
+        return (arg, "*.*");
+    }
+    match arg.rfind(['\\', ':']) {
+        Some(pos) => (&arg[..=pos], &arg[pos + 1..]),
+        None => ("", arg),
+    }
+}
@@ -0,0 +1,178 @@
+use std::fs;
+use std::path::Path;
+
+use tempfile::tempdir;
+
+use crate::dos::{Shell, ShellAction, DOS};
+use crate::machine::Machine;
+use crate::memory::MMU;
+use crate::storage::Mount;
+
+fn dos_with_drive(dir: &Path) -> DOS {
+    let mut dos = DOS::default();
+    dos.drives.mount(&Mount::new('C', dir.to_path_buf())).unwrap();
+    dos.current_dir = "C:\\".to_owned();
+    dos
+}
+
+/// runs command line `line`, returns the action and the console output
+fn run(shell: &mut Shell, dos: &mut DOS, mmu: &mut MMU, line: &str) -> (ShellAction, String) {
+    dos.stdout.clear();
+    let action = shell.execute(dos, mmu, line);
+    (action, String::from_utf8_lossy(&dos.stdout).into_owned())
+}
+
+#[test]
+fn shell_runs_builtin_commands() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("readme.txt"), b"hello\r\n\x1Ajunk").unwrap();
+    fs::create_dir(dir.path().join("games")).unwrap();
+    let mut dos = dos_with_drive(dir.path());
+    let mut mmu = Machine::deterministic().mmu;
+    let mut shell = Shell::default();
+
+    let (_, listing) = run(&mut shell, &mut dos, &mut mmu, "dir");
+    assert!(listing.contains(" Directory of C:\\\r\n"));
+    assert!(listing.contains("GAMES        <DIR>      "));
+    assert!(listing.contains("README   TXT         12 "));
+    assert!(listing.contains("        2 file(s)             12 bytes\r\n"));
+    assert_eq!("hello\r\n", run(&mut shell, &mut dos, &mut mmu, "type readme.txt").1);
+
+    let (_, copied) = run(&mut shell, &mut dos, &mut mmu, "copy readme.txt games");
+    assert_eq!("README.TXT\r\n        1 file(s) copied\r\n", copied);
+    assert!(dir.path().join("games").join("readme.txt").is_file());
+
+    run(&mut shell, &mut dos, &mut mmu, "cd games");
+    assert_eq!("C:\\GAMES", dos.current_dir);
+    assert!(run(&mut shell, &mut dos, &mut mmu, "dir *.txt").1.contains("README   TXT"));
+    assert_eq!("File not found\r\n", run(&mut shell, &mut dos, &mut mmu, "copy *.exe").1);
+    run(&mut shell, &mut dos, &mut mmu, "cd..");
+    assert_eq!("C:\\", dos.current_dir);
+    assert_eq!("Invalid directory\r\n", run(&mut shell, &mut dos, &mut mmu, "cd missing").1);
+    assert_eq!("Invalid drive specification\r\n", run(&mut shell, &mut dos, &mut mmu, "d:").1);
+    assert_eq!("\r\n", run(&mut shell, &mut dos, &mut mmu, "echo.").1);
+    assert_eq!(ShellAction::Exit(0), run(&mut shell, &mut dos, &mut mmu, "exit").0);
+}
+
+#[test]
+fn shell_finds_programs_and_batch_files() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("hello.com"), [0xCD, 0x20]).unwrap();
+    fs::write(dir.path().join("start.bat"), b"@echo off\r\necho starting\r\ndoom -warp 1\r\n").unwrap();
+    fs::create_dir(dir.path().join("games")).unwrap();
+    fs::write(dir.path().join("games").join("doom.exe"), b"MZ").unwrap();
+    let mut dos = dos_with_drive(dir.path());
+    let mut mmu = Machine::deterministic().mmu;
+    let mut shell = Shell::default();
+
+    let hello = ShellAction::Run { path: dir.path().join("hello.com"), name: "C:\\HELLO.COM".to_owned(), tail: String::new() };
+    assert_eq!(hello, run(&mut shell, &mut dos, &mut mmu, "hello").0);
+    assert_eq!(hello, run(&mut shell, &mut dos, &mut mmu, "c:\\HELLO.COM").0);
+    assert_eq!((ShellAction::None, "Bad command or file name\r\n".to_owned()), run(&mut shell, &mut dos, &mut mmu, "doom"));
+    assert_eq!(ShellAction::None, run(&mut shell, &mut dos, &mut mmu, "readme.txt").0);
+
+    // the batch file runs a command in each step, programs are looked for in PATH
+    run(&mut shell, &mut dos, &mut mmu, "path c:\\games");
+    assert_eq!(ShellAction::None, run(&mut shell, &mut dos, &mut mmu, "start").0);
+    assert!(!shell.reads_input());
+    dos.stdout.clear();
+    assert_eq!(ShellAction::None, shell.step(&mut dos, &mut mmu, None));
+    assert_eq!(ShellAction::None, shell.step(&mut dos, &mut mmu, None));
+    let doom = ShellAction::Run {
+        path: dir.path().join("games").join("doom.exe"),
+        name: "C:\\GAMES\\DOOM.EXE".to_owned(),
+        tail: " -warp 1".to_owned(),
+    };
+    assert_eq!(doom, shell.step(&mut dos, &mut mmu, None));
+    assert_eq!(ShellAction::None, shell.step(&mut dos, &mut mmu, None));
+    assert!(shell.reads_input());
+    assert_eq!("starting\r\n", String::from_utf8_lossy(&dos.stdout));
+}
+
+#[test]
+fn shell_keeps_environment_variables() {
+    let mut shell = Shell::default();
+    let mut mmu = Machine::deterministic().mmu;
+    shell.set_var("game", "doom").unwrap();
+    assert_eq!(Some("doom"), shell.var("GAME"));
+    assert_eq!("doom % x", shell.expand("%Game% %% %UNKNOWN%x", None));
+    assert!(shell.set_var("LONG", &"x".repeat(1100)).is_err());
+
+    shell.write_environment(&mut mmu, "C:\\DOOM.EXE");
+    let env = b"COMSPEC=C:\\COMMAND.COM\0GAME=doom\0\0\x01\0C:\\DOOM.EXE\0";
+    assert_eq!(env.to_vec(), mmu.read(0x02E3, 0, env.len()));
+
+    shell.set_var("GAME", "").unwrap();
+    assert_eq!(None, shell.var("GAME"));
+
+    let params: Vec<String> = ["GO.BAT", "sb"].iter().map(|p| p.to_string()).collect();
+    assert_eq!("GO.BAT sb % 100%", shell.expand("%0 %1 %2%% 100%", Some(&params)));
+    assert_eq!("%1 %f in", shell.expand("%1 %f in", None));
+}
+
+/// runs the steps of `shell` until it reads input or exits, returns the programs started and the console output
+fn run_steps(shell: &mut Shell, dos: &mut DOS, mmu: &mut MMU) -> (Vec<ShellAction>, String) {
+    dos.stdout.clear();
+    let mut actions = Vec::new();
+    while !shell.reads_input() {
+        match shell.step(dos, mmu, None) {
+            ShellAction::None => {}
+            ShellAction::Exit(code) => {
+                actions.push(ShellAction::Exit(code));
+                break;
+            }
+            action => actions.push(action),
+        }
+    }
+    (actions, String::from_utf8_lossy(&dos.stdout).into_owned())
+}
+
+#[test]
+fn shell_runs_batch_file_commands() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("game.exe"), b"MZ").unwrap();
+    fs::write(dir.path().join("a.dat"), b"").unwrap();
+    fs::write(dir.path().join("b.dat"), b"").unwrap();
+    fs::write(dir.path().join("start.bat"), b"@echo off
+if \"%1\"==\"\" goto usage
+call setup.bat %1
+if errorlevel 1 goto failed
+for %%f in (*.dat x) do echo %%f
+shift
+game %0 %1
+goto end
+:usage
+echo usage: start card
+goto end
+:failed
+echo setup failed
+:end
+").unwrap();
+    fs::write(dir.path().join("setup.bat"), b"echo setup %1
+set CARD=%1
+").unwrap();
+    let mut dos = dos_with_drive(dir.path());
+    let mut mmu = Machine::deterministic().mmu;
+    let game = dir.path().join("game.exe");
+
+    let mut shell = Shell::default();
+    shell.run_batch(&dir.path().join("start.bat"), "START.BAT", "").unwrap();
+    assert_eq!((vec![ShellAction::Exit(0)], "usage: start card\r\n".to_owned()), run_steps(&mut shell, &mut dos, &mut mmu));
+
+    let mut shell = Shell::default();
+    shell.run_batch(&dir.path().join("start.bat"), "START.BAT", "sb 220").unwrap();
+    let (actions, output) = run_steps(&mut shell, &mut dos, &mut mmu);
+    let run = ShellAction::Run { path: game.clone(), name: "C:\\GAME.EXE".to_owned(), tail: " sb 220".to_owned() };
+    assert_eq!(vec![run, ShellAction::Exit(0)], actions);
+    assert_eq!("setup sb\r\nA.DAT\r\nB.DAT\r\nx\r\n", output);
+    assert_eq!(Some("sb"), shell.var("CARD"));
+
+    let mut shell = Shell { errorlevel: 3, ..Shell::default() };
+    shell.run_batch(&dir.path().join("start.bat"), "START.BAT", "sb").unwrap();
+    assert_eq!((vec![ShellAction::Exit(3)], "setup sb\r\nsetup failed\r\n".to_owned()), run_steps(&mut shell, &mut dos, &mut mmu));
+
+    let mut shell = Shell::with_environment(&["GAME=game".to_owned()]);
+    shell.run_command("if not exist missing.dat %GAME% -nosound");
+    let run = ShellAction::Run { path: game, name: "C:\\GAME.EXE".to_owned(), tail: " -nosound".to_owned() };
+    assert_eq!(vec![run, ShellAction::Exit(0)], run_steps(&mut shell, &mut dos, &mut mmu).0);
+}
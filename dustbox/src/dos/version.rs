@@ -0,0 +1,76 @@
+// DOS version reported to programs, with per program versions as set by SETVER.EXE
+// http://www.ctyme.com/intr/rb-2711.htm
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::Path;
+
+#[cfg(test)]
+#[path = "./version_test.rs"]
+mod version_test;
+
+/// a DOS version number, such as 5.00 or 3.30
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct DosVersion {
+    pub major: u8,
+    pub minor: u8,
+}
+
+impl Default for DosVersion {
+    fn default() -> Self {
+        DosVersion::new(5, 0)
+    }
+}
+
+impl DosVersion {
+    pub fn new(major: u8, minor: u8) -> Self {
+        DosVersion { major, minor }
+    }
+
+    /// parses a version such as "6.22" or "3.3". as written by DOS, "3.3" is 3.30
+    pub fn parse(s: &str) -> Option<Self> {
+        let (major, minor) = match s.find('.') {
+            Some(pos) => (&s[..pos], &s[pos + 1..]),
+            None => (s, "0"),
+        };
+        let major = major.parse::<u8>().ok()?;
+        let minor_value = minor.parse::<u8>().ok()?;
+        let minor = match minor.len() {
+            1 => minor_value * 10,
+            2 => minor_value,
+            _ => return None,
+        };
+        if major == 0 || major > 9 {
+            return None;
+        }
+        Some(DosVersion::new(major, minor))
+    }
+
+    /// returns the version reported to the program at host path `program`: the version
+    /// set for its file name in `setver`, or `self`
+    pub fn for_program(self, setver: &HashMap<String, DosVersion>, program: &str) -> Self {
+        let name = match Path::new(program).file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => return self,
+        };
+        setver.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(&name))
+            .map_or(self, |(_, v)| *v)
+    }
+}
+
+impl TryFrom<String> for DosVersion {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        DosVersion::parse(&s).ok_or_else(|| format!("invalid DOS version {}", s))
+    }
+}
+
+impl fmt::Display for DosVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:02}", self.major, self.minor)
+    }
+}
@@ -0,0 +1,23 @@
+use std::collections::HashMap;
+
+use crate::dos::DosVersion;
+
+#[test]
+fn can_parse_dos_version() {
+    assert_eq!(Some(DosVersion::new(6, 22)), DosVersion::parse("6.22"));
+    assert_eq!(Some(DosVersion::new(3, 30)), DosVersion::parse("3.3"));
+    assert_eq!(Some(DosVersion::new(5, 0)), DosVersion::parse("5"));
+    assert_eq!(None, DosVersion::parse("3.300"));
+    assert_eq!(None, DosVersion::parse("x.1"));
+    assert_eq!(None, DosVersion::parse("0.5"));
+    assert_eq!("3.30", DosVersion::new(3, 30).to_string());
+}
+
+#[test]
+fn can_set_version_for_program() {
+    let mut setver = HashMap::new();
+    setver.insert("TC.EXE".to_owned(), DosVersion::new(3, 30));
+    let version = DosVersion::default();
+    assert_eq!(DosVersion::new(3, 30), version.for_program(&setver, "/home/user/tc/tc.exe"));
+    assert_eq!(version, version.for_program(&setver, "/home/user/tc/tcc.exe"));
+}
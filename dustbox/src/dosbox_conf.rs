@@ -0,0 +1,288 @@
+// reads the common settings of dosbox .conf files, so that existing game setups can be tried:
+// cycles and cputype of [cpu], machine and memsize of [dosbox], and the mount, imgmount,
+// drive change, cd and program lines of [autoexec]. other settings are ignored
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::cpu::CpuModel;
+use crate::gpu::SvgaChipset;
+use crate::machine::MachineConfig;
+use crate::storage::{Mount, MountKind};
+
+#[cfg(test)]
+#[path = "./dosbox_conf_test.rs"]
+mod dosbox_conf_test;
+
+/// what a dosbox .conf file sets besides the machine configuration
+#[derive(Debug, Default, PartialEq)]
+pub struct DosboxConf {
+    /// the program started by the [autoexec] section, such as "C:\GAMES\DOOM"
+    pub program: Option<String>,
+
+    /// the current directory when the program starts, such as "C:\GAMES"
+    pub current_dir: Option<String>,
+
+    /// set by "cycles = max", the emulation runs as fast as the host allows
+    pub unlimited: bool,
+
+    /// settings and autoexec commands without an equivalent, to tell the user about
+    pub unsupported: Vec<String>,
+}
+
+impl DosboxConf {
+    /// reads the dosbox .conf file `filename` into `config`
+    pub fn from_file(filename: &str, config: &mut MachineConfig) -> io::Result<Self> {
+        let text = fs::read_to_string(filename)?;
+        Self::parse(&text, config).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// maps the settings of a dosbox .conf file onto `config`, such as:
+    ///
+    /// ```ini
+    /// [cpu]
+    /// cputype=386
+    /// cycles=fixed 12000
+    ///
+    /// [autoexec]
+    /// mount c ~/dos
+    /// c:
+    /// cd games
+    /// doom.exe
+    /// ```
+    pub fn parse(text: &str, config: &mut MachineConfig) -> Result<Self, String> {
+        let mut conf = DosboxConf::default();
+        let mut autoexec = Autoexec::default();
+        let mut section = String::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].trim().to_ascii_lowercase();
+                continue;
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if section == "autoexec" {
+                autoexec.command(line, config, &mut conf)?;
+                continue;
+            }
+            let (key, value) = match line.find('=') {
+                Some(pos) => (line[..pos].trim().to_ascii_lowercase(), line[pos + 1..].trim()),
+                None => continue,
+            };
+            match (section.as_str(), key.as_str()) {
+                ("cpu", "cycles") => conf.cycles(value, config)?,
+                ("cpu", "cputype") => conf.cputype(value, config),
+                ("dosbox", "machine") => conf.machine(value, config),
+                ("dosbox", "memsize") => {
+                    let mb: u16 = value.parse().map_err(|_| format!("invalid memsize {}", value))?;
+                    // the first MB is conventional and upper memory
+                    config.extended_kb = (u32::from(mb.max(1) - 1) * 1024).min(0xFFFF) as u16;
+                }
+                _ => {}
+            }
+        }
+        conf.current_dir = autoexec.program.as_ref().map(|_| autoexec.current_dir.clone());
+        conf.program = autoexec.program;
+        Ok(conf)
+    }
+
+    /// cycles=3000, "fixed 3000", "auto", "auto 3000 limit 50000" or "max"
+    fn cycles(&mut self, value: &str, config: &mut MachineConfig) -> Result<(), String> {
+        let value = value.to_ascii_lowercase();
+        let words: Vec<&str> = value.split_whitespace().collect();
+        let cycles = match words.as_slice() {
+            ["max", ..] => {
+                self.unlimited = true;
+                return Ok(());
+            }
+            // the real mode cycles of auto, if set, are used throughout
+            ["auto", rest @ ..] => match rest.iter().find(|w| w.parse::<usize>().is_ok()) {
+                Some(n) => n,
+                None => return Ok(()),
+            },
+            ["fixed", n] | [n] => n,
+            [] => return Ok(()),
+            _ => return Err(format!("invalid cycles {}", value)),
+        };
+        // dosbox executes `cycles` instructions per millisecond, and each instruction takes one cycle here
+        let cycles: usize = cycles.parse().map_err(|_| format!("invalid cycles {}", value))?;
+        config.cpu_hz = Some(cycles * 1000);
+        Ok(())
+    }
+
+    /// cputype=auto, 386, 386_slow, 486_slow, pentium_slow and the like
+    fn cputype(&mut self, value: &str, config: &mut MachineConfig) {
+        let value = value.to_ascii_lowercase();
+        if value == "auto" {
+            return;
+        }
+        match value.split('_').next().unwrap() {
+            "386" => config.cpu = CpuModel::I80386,
+            "486" => config.cpu = CpuModel::I80486,
+            _ => {
+                self.unsupported.push(format!("cputype={}, using a 486", value));
+                config.cpu = CpuModel::I80486;
+            }
+        }
+    }
+
+    /// machine=svga_s3, svga_et4000, vgaonly and the like
+    fn machine(&mut self, value: &str, config: &mut MachineConfig) {
+        let value = value.to_ascii_lowercase();
+        config.svga = match value.as_str() {
+            "svga_s3" | "vesa_nolfb" | "vesa_oldvbe" => SvgaChipset::S3Trio,
+            "svga_et4000" => SvgaChipset::ET4000,
+            "vgaonly" => SvgaChipset::None,
+            _ => {
+                self.unsupported.push(format!("machine={}, using plain VGA", value));
+                SvgaChipset::None
+            }
+        };
+    }
+}
+
+/// state of the [autoexec] section, as its commands are read
+struct Autoexec {
+    /// the current directory, such as "C:\GAMES"
+    current_dir: String,
+
+    /// the last program started
+    program: Option<String>,
+}
+
+impl Default for Autoexec {
+    fn default() -> Self {
+        Autoexec {
+            current_dir: "Z:\\".to_owned(),
+            program: None,
+        }
+    }
+}
+
+impl Autoexec {
+    fn command(&mut self, line: &str, config: &mut MachineConfig, conf: &mut DosboxConf) -> Result<(), String> {
+        let line = line.trim_start_matches('@').trim();
+        let args = split_args(line);
+        let command = match args.first() {
+            Some(command) => command.to_ascii_lowercase(),
+            None => return Ok(()),
+        };
+        match command.as_str() {
+            "mount" | "imgmount" => {
+                let (drive, path) = match (args.get(1), args.get(2)) {
+                    (Some(drive), Some(path)) if drive.len() == 1 && !path.starts_with('-') => (drive.chars().next().unwrap(), path),
+                    _ if args.iter().any(|a| a.eq_ignore_ascii_case("-u")) => {
+                        conf.unsupported.push(line.to_owned());
+                        return Ok(());
+                    }
+                    _ => return Err(format!("invalid {} command: {}", command, line)),
+                };
+                let mut mount = Mount::new(drive, expand_home(path));
+                if command == "imgmount" {
+                    mount.kind = match option(&args, "-t").map(|t| t.to_ascii_lowercase()).as_deref() {
+                        Some("iso") | Some("cdrom") => Some(MountKind::Iso),
+                        Some("floppy") => Some(MountKind::Floppy),
+                        Some(t) => {
+                            conf.unsupported.push(format!("imgmount -t {}", t));
+                            return Ok(());
+                        }
+                        None => None,
+                    };
+                } else {
+                    mount.kind = Some(MountKind::Directory);
+                }
+                config.mounts.retain(|m| !m.drive.eq_ignore_ascii_case(&drive));
+                config.mounts.push(mount);
+            }
+            _ if command.len() == 2 && command.ends_with(':') => {
+                self.current_dir = format!("{}\\", command.to_ascii_uppercase());
+            }
+            _ if command == "cd" || command.starts_with("cd\\") || command.starts_with("cd.") => {
+                let dir = if command == "cd" { args.get(1).map_or("", String::as_str) } else { &line[2..] };
+                self.current_dir = join_path(&self.current_dir, dir);
+            }
+            "rem" | "echo" | "cls" | "exit" | "pause" | "set" | "path" | "keyb" | "config" | "cycles" | "core" => {
+                if !matches!(command.as_str(), "rem" | "echo" | "cls" | "exit" | "pause") {
+                    conf.unsupported.push(line.to_owned());
+                }
+            }
+            _ => {
+                // "loadhigh" and "lh" run the program following them
+                let args = if command == "loadhigh" || command == "lh" { &args[1..] } else { &args[..] };
+                if let Some(program) = args.first() {
+                    if args.len() > 1 {
+                        conf.unsupported.push(format!("arguments of {}", line));
+                    }
+                    if let Some(previous) = self.program.replace(join_path(&self.current_dir, program)) {
+                        conf.unsupported.push(format!("{}, only the last program is started", previous));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// splits a command line into words, keeping quoted words together
+fn split_args(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    args.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        args.push(word);
+    }
+    args
+}
+
+/// returns the value following `name` in `args`, such as "iso" of "-t iso"
+fn option<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    let pos = args.iter().position(|a| a.eq_ignore_ascii_case(name))?;
+    args.get(pos + 1).map(String::as_str)
+}
+
+/// expands a leading "~" to the home directory of the user, as dosbox does
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest.trim_start_matches(['/', '\\'])),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// returns DOS path `path` relative to directory `dir`, such as "C:\GAMES" and "DOOM" giving "C:\GAMES\DOOM"
+fn join_path(dir: &str, path: &str) -> String {
+    let path = path.replace('/', "\\");
+    let (mut full, rest) = if path.len() >= 2 && path.as_bytes()[1] == b':' {
+        (format!("{}\\", path[..2].to_ascii_uppercase()), path[2..].to_owned())
+    } else if path.starts_with('\\') {
+        (dir[..3].to_owned(), path)
+    } else {
+        (dir.to_owned(), path)
+    };
+    for part in rest.split('\\').filter(|p| !p.is_empty() && *p != ".") {
+        if part == ".." {
+            if let Some(pos) = full.trim_end_matches('\\').rfind('\\') {
+                full.truncate(pos + 1);
+            }
+        } else {
+            if !full.ends_with('\\') {
+                full.push('\\');
+            }
+            full.push_str(&part.to_ascii_uppercase());
+        }
+    }
+    full
+}
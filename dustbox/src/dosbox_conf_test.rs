@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+
+use crate::cpu::CpuModel;
+use crate::dosbox_conf::{join_path, split_args, DosboxConf};
+use crate::gpu::SvgaChipset;
+use crate::machine::MachineConfig;
+use crate::storage::MountKind;
+
+#[test]
+fn can_map_dosbox_conf() {
+    let text = "
+[sdl]
+fullscreen=true
+
+[dosbox]
+machine=svga_et4000
+memsize=8
+
+[cpu]
+core=auto
+cputype=pentium_slow
+cycles=fixed 12000
+
+[autoexec]
+# Lines in this section will be run at startup.
+@echo off
+mount c \"/home/user/dos games\" -freesize 200
+imgmount d /home/user/game.cue -t cdrom
+c:
+cd \\games\\doom
+keyb gr
+doom.exe -warp 1 1
+exit
+";
+    let mut config = MachineConfig::default();
+    let conf = DosboxConf::parse(text, &mut config).unwrap();
+    assert_eq!(SvgaChipset::ET4000, config.svga);
+    assert_eq!(7 * 1024, config.extended_kb);
+    assert_eq!(CpuModel::I80486, config.cpu);
+    assert_eq!(Some(12_000_000), config.cpu_hz);
+
+    assert_eq!(2, config.mounts.len());
+    assert_eq!('c', config.mounts[0].drive);
+    assert_eq!(PathBuf::from("/home/user/dos games"), config.mounts[0].path);
+    assert_eq!(Some(MountKind::Directory), config.mounts[0].kind);
+    assert_eq!(Some(MountKind::Iso), config.mounts[1].kind);
+
+    assert_eq!(Some("C:\\GAMES\\DOOM\\DOOM.EXE".to_owned()), conf.program);
+    assert_eq!(Some("C:\\GAMES\\DOOM".to_owned()), conf.current_dir);
+    assert!(!conf.unlimited);
+    assert_eq!(vec![
+        "cputype=pentium_slow, using a 486".to_owned(),
+        "keyb gr".to_owned(),
+        "arguments of doom.exe -warp 1 1".to_owned(),
+    ], conf.unsupported);
+}
+
+#[test]
+fn can_map_dosbox_cycles() {
+    let cycles = |value: &str| {
+        let mut config = MachineConfig::default();
+        DosboxConf::parse(&format!("[cpu]\ncycles={}", value), &mut config).map(|conf| (config.cpu_hz, conf.unlimited))
+    };
+    assert_eq!(Ok((Some(3_000_000), false)), cycles("3000"));
+    assert_eq!(Ok((Some(3_000_000), false)), cycles("auto 3000 limit 50000"));
+    assert_eq!(Ok((None, false)), cycles("auto"));
+    assert_eq!(Ok((None, true)), cycles("max 90%"));
+    assert!(cycles("fast").is_err());
+    assert!(cycles("fixed").is_err());
+}
+
+#[test]
+fn can_join_dos_paths() {
+    assert_eq!("C:\\GAMES", join_path("C:\\", "games"));
+    assert_eq!("C:\\DOOM", join_path("C:\\GAMES", "..\\doom"));
+    assert_eq!("C:\\", join_path("C:\\GAMES", ".."));
+    assert_eq!("C:\\DOOM", join_path("C:\\GAMES", "\\doom"));
+    assert_eq!("D:\\SETUP.EXE", join_path("C:\\GAMES", "d:setup.exe"));
+    assert_eq!(vec!["mount", "c", "/my dos", "-t", "dir"], split_args("mount c \"/my dos\"  -t dir"));
+}
@@ -0,0 +1,2324 @@
+// instruction handlers, dispatched by `Machine::execute` through a table indexed by `Op`
+
+use std::mem;
+use std::num::Wrapping;
+
+use log::{debug, warn};
+
+use crate::cpu::{Op, Instruction, Exception, Parameter, R, AddressSize, OperandSize, LazyOp, CpuModel};
+use crate::machine::{Machine, DEBUG_MARK_STACK, STACK_MARKER};
+use crate::memory::MemoryAddress;
+
+/// executes a decoded instruction
+pub type OpHandler = fn(&mut Machine, &Instruction);
+
+/// returns a table of instruction handlers, indexed by `Op::index`
+pub fn dispatch_table() -> Vec<OpHandler> {
+    let mut table: Vec<OpHandler> = vec![Machine::op_unhandled; Op::COUNT];
+    table[Op::Aaa.index()] = Machine::op_aaa;
+    table[Op::Aad.index()] = Machine::op_aad;
+    table[Op::Aam.index()] = Machine::op_aam;
+    table[Op::Aas.index()] = Machine::op_aas;
+    table[Op::Adc8.index()] = Machine::op_adc8;
+    table[Op::Adc16.index()] = Machine::op_adc16;
+    table[Op::Add8.index()] = Machine::op_add8;
+    table[Op::Add16.index()] = Machine::op_add16;
+    table[Op::Add32.index()] = Machine::op_add32;
+    table[Op::And8.index()] = Machine::op_and8;
+    table[Op::And16.index()] = Machine::op_and16;
+    table[Op::Arpl.index()] = Machine::op_arpl;
+    table[Op::Bsf16.index()] = Machine::op_bsf16;
+    table[Op::Bsf32.index()] = Machine::op_bsf32;
+    table[Op::Bsr16.index()] = Machine::op_bsr16;
+    table[Op::Bsr32.index()] = Machine::op_bsr32;
+    table[Op::Bswap32.index()] = Machine::op_bswap32;
+    table[Op::Bt16.index()] = Machine::op_bt16;
+    table[Op::Bt32.index()] = Machine::op_bt32;
+    table[Op::Btc16.index()] = Machine::op_btc16;
+    table[Op::Btc32.index()] = Machine::op_btc32;
+    table[Op::Btr16.index()] = Machine::op_btr16;
+    table[Op::Btr32.index()] = Machine::op_btr32;
+    table[Op::Bts16.index()] = Machine::op_bts16;
+    table[Op::Bts32.index()] = Machine::op_bts32;
+    table[Op::Bound.index()] = Machine::op_bound;
+    table[Op::CallNear.index()] = Machine::op_call_near;
+    table[Op::CallFar.index()] = Machine::op_call_far;
+    table[Op::Cbw.index()] = Machine::op_cbw;
+    table[Op::Clc.index()] = Machine::op_clc;
+    table[Op::Cld.index()] = Machine::op_cld;
+    table[Op::Cli.index()] = Machine::op_cli;
+    table[Op::Cmc.index()] = Machine::op_cmc;
+    table[Op::Cmp8.index()] = Machine::op_cmp8;
+    table[Op::Cmp16.index()] = Machine::op_cmp16;
+    table[Op::Cmp32.index()] = Machine::op_cmp32;
+    table[Op::Cmpsb.index()] = Machine::op_cmpsb;
+    table[Op::Cmpsw.index()] = Machine::op_cmpsw;
+    table[Op::Cmpsd.index()] = Machine::op_cmpsd;
+    table[Op::Cmpxchg8.index()] = Machine::op_cmpxchg8;
+    table[Op::Cmpxchg16.index()] = Machine::op_cmpxchg16;
+    table[Op::Cmpxchg32.index()] = Machine::op_cmpxchg32;
+    table[Op::Cwd16.index()] = Machine::op_cwd16;
+    table[Op::Cwde32.index()] = Machine::op_cwde32;
+    table[Op::Daa.index()] = Machine::op_daa;
+    table[Op::Das.index()] = Machine::op_das;
+    table[Op::Dec8.index()] = Machine::op_dec8;
+    table[Op::Dec16.index()] = Machine::op_dec16;
+    table[Op::Dec32.index()] = Machine::op_dec32;
+    table[Op::Div8.index()] = Machine::op_div8;
+    table[Op::Div16.index()] = Machine::op_div16;
+    table[Op::Div32.index()] = Machine::op_div32;
+    table[Op::Enter.index()] = Machine::op_enter;
+    table[Op::Hlt.index()] = Machine::op_hlt;
+    table[Op::Idiv8.index()] = Machine::op_idiv8;
+    table[Op::Idiv16.index()] = Machine::op_idiv16;
+    table[Op::Idiv32.index()] = Machine::op_idiv32;
+    table[Op::Imul8.index()] = Machine::op_imul8;
+    table[Op::Imul16.index()] = Machine::op_imul16;
+    table[Op::Imul32.index()] = Machine::op_imul32;
+    table[Op::In8.index()] = Machine::op_in8;
+    table[Op::In16.index()] = Machine::op_in16;
+    table[Op::Inc8.index()] = Machine::op_inc8;
+    table[Op::Inc16.index()] = Machine::op_inc16;
+    table[Op::Inc32.index()] = Machine::op_inc32;
+    table[Op::Insb.index()] = Machine::op_insb;
+    table[Op::Insw.index()] = Machine::op_insw;
+    table[Op::Insd.index()] = Machine::op_insd;
+    table[Op::Callback.index()] = Machine::op_callback;
+    table[Op::Int.index()] = Machine::op_int;
+    table[Op::Into.index()] = Machine::op_into;
+    table[Op::Ja.index()] = Machine::op_ja;
+    table[Op::Jc.index()] = Machine::op_jc;
+    table[Op::Jcxz.index()] = Machine::op_jcxz;
+    table[Op::Jg.index()] = Machine::op_jg;
+    table[Op::Jl.index()] = Machine::op_jl;
+    table[Op::JmpFar.index()] = Machine::op_jmp_far;
+    table[Op::JmpNear.index()] = Machine::op_jmp_near;
+    table[Op::JmpShort.index()] = Machine::op_jmp_near;
+    table[Op::Jna.index()] = Machine::op_jna;
+    table[Op::Jnc.index()] = Machine::op_jnc;
+    table[Op::Jng.index()] = Machine::op_jng;
+    table[Op::Jnl.index()] = Machine::op_jnl;
+    table[Op::Jno.index()] = Machine::op_jno;
+    table[Op::Jns.index()] = Machine::op_jns;
+    table[Op::Jnz.index()] = Machine::op_jnz;
+    table[Op::Jo.index()] = Machine::op_jo;
+    table[Op::Jpe.index()] = Machine::op_jpe;
+    table[Op::Jpo.index()] = Machine::op_jpo;
+    table[Op::Js.index()] = Machine::op_js;
+    table[Op::Jz.index()] = Machine::op_jz;
+    table[Op::Lahf.index()] = Machine::op_lahf;
+    table[Op::Lds.index()] = Machine::op_lds;
+    table[Op::Lea16.index()] = Machine::op_lea16;
+    table[Op::Lea32.index()] = Machine::op_lea32;
+    table[Op::Leave.index()] = Machine::op_leave;
+    table[Op::Les.index()] = Machine::op_les;
+    table[Op::Lodsb.index()] = Machine::op_lodsb;
+    table[Op::Lodsw.index()] = Machine::op_lodsw;
+    table[Op::Lodsd.index()] = Machine::op_lodsd;
+    table[Op::Loop.index()] = Machine::op_loop;
+    table[Op::Loope.index()] = Machine::op_loope;
+    table[Op::Loopne.index()] = Machine::op_loopne;
+    table[Op::Mov8.index()] = Machine::op_mov8;
+    table[Op::Mov16.index()] = Machine::op_mov16;
+    table[Op::Mov32.index()] = Machine::op_mov32;
+    table[Op::Movsb.index()] = Machine::op_movsb;
+    table[Op::Movsw.index()] = Machine::op_movsw;
+    table[Op::Movsd.index()] = Machine::op_movsd;
+    table[Op::Movsx16.index()] = Machine::op_movsx16;
+    table[Op::Movsx32.index()] = Machine::op_movsx32;
+    table[Op::Movzx16.index()] = Machine::op_movzx16;
+    table[Op::Movzx32.index()] = Machine::op_movzx32;
+    table[Op::Mul8.index()] = Machine::op_mul8;
+    table[Op::Mul16.index()] = Machine::op_mul16;
+    table[Op::Mul32.index()] = Machine::op_mul32;
+    table[Op::Neg8.index()] = Machine::op_neg8;
+    table[Op::Neg16.index()] = Machine::op_neg16;
+    table[Op::Neg32.index()] = Machine::op_neg32;
+    table[Op::Nop.index()] = Machine::op_nop;
+    table[Op::Not8.index()] = Machine::op_not8;
+    table[Op::Not16.index()] = Machine::op_not16;
+    table[Op::Or8.index()] = Machine::op_or8;
+    table[Op::Or16.index()] = Machine::op_or16;
+    table[Op::Out8.index()] = Machine::op_out8;
+    table[Op::Out16.index()] = Machine::op_out16;
+    table[Op::Outsb.index()] = Machine::op_outsb;
+    table[Op::Outsw.index()] = Machine::op_outsw;
+    table[Op::Outsd.index()] = Machine::op_outsd;
+    table[Op::Pop16.index()] = Machine::op_pop16;
+    table[Op::Pop32.index()] = Machine::op_pop32;
+    table[Op::Popa16.index()] = Machine::op_popa16;
+    table[Op::Popad32.index()] = Machine::op_popad32;
+    table[Op::Popf.index()] = Machine::op_popf;
+    table[Op::Push16.index()] = Machine::op_push16;
+    table[Op::Push32.index()] = Machine::op_push32;
+    table[Op::Pusha16.index()] = Machine::op_pusha16;
+    table[Op::Pushad32.index()] = Machine::op_pushad32;
+    table[Op::Pushf.index()] = Machine::op_pushf;
+    table[Op::Rcl8.index()] = Machine::op_rcl8;
+    table[Op::Rcl16.index()] = Machine::op_rcl16;
+    table[Op::Rcl32.index()] = Machine::op_rcl32;
+    table[Op::Rcr8.index()] = Machine::op_rcr8;
+    table[Op::Rcr16.index()] = Machine::op_rcr16;
+    table[Op::Rcr32.index()] = Machine::op_rcr32;
+    table[Op::Iret.index()] = Machine::op_iret;
+    table[Op::Retf.index()] = Machine::op_retf;
+    table[Op::Retn.index()] = Machine::op_retn;
+    table[Op::Rol8.index()] = Machine::op_rol8;
+    table[Op::Rol16.index()] = Machine::op_rol16;
+    table[Op::Rol32.index()] = Machine::op_rol32;
+    table[Op::Ror8.index()] = Machine::op_ror8;
+    table[Op::Ror16.index()] = Machine::op_ror16;
+    table[Op::Ror32.index()] = Machine::op_ror32;
+    table[Op::Sahf.index()] = Machine::op_sahf;
+    table[Op::Salc.index()] = Machine::op_salc;
+    table[Op::Sar8.index()] = Machine::op_sar8;
+    table[Op::Sar16.index()] = Machine::op_sar16;
+    table[Op::Sar32.index()] = Machine::op_sar32;
+    table[Op::Sbb8.index()] = Machine::op_sbb8;
+    table[Op::Sbb16.index()] = Machine::op_sbb16;
+    table[Op::Scasb.index()] = Machine::op_scasb;
+    table[Op::Scasw.index()] = Machine::op_scasw;
+    table[Op::Scasd.index()] = Machine::op_scasd;
+    table[Op::Seta.index()] = Machine::op_seta;
+    table[Op::Setc.index()] = Machine::op_setc;
+    table[Op::Setg.index()] = Machine::op_setg;
+    table[Op::Setl.index()] = Machine::op_setl;
+    table[Op::Setna.index()] = Machine::op_setna;
+    table[Op::Setnc.index()] = Machine::op_setnc;
+    table[Op::Setng.index()] = Machine::op_setng;
+    table[Op::Setnl.index()] = Machine::op_setnl;
+    table[Op::Setno.index()] = Machine::op_setno;
+    table[Op::Setns.index()] = Machine::op_setns;
+    table[Op::Setnz.index()] = Machine::op_setnz;
+    table[Op::Seto.index()] = Machine::op_seto;
+    table[Op::Setpe.index()] = Machine::op_setpe;
+    table[Op::Setpo.index()] = Machine::op_setpo;
+    table[Op::Sets.index()] = Machine::op_sets;
+    table[Op::Setz.index()] = Machine::op_setz;
+    table[Op::Setmo8.index()] = Machine::op_setmo8;
+    table[Op::Setmo16.index()] = Machine::op_setmo16;
+    table[Op::Shl8.index()] = Machine::op_shl8;
+    table[Op::Shl16.index()] = Machine::op_shl16;
+    table[Op::Shl32.index()] = Machine::op_shl32;
+    table[Op::Shld.index()] = Machine::op_shld;
+    table[Op::Shr8.index()] = Machine::op_shr8;
+    table[Op::Shr16.index()] = Machine::op_shr16;
+    table[Op::Shr32.index()] = Machine::op_shr32;
+    table[Op::Shrd.index()] = Machine::op_shrd;
+    table[Op::Sldt.index()] = Machine::op_sldt;
+    table[Op::Smsw.index()] = Machine::op_smsw;
+    table[Op::Stc.index()] = Machine::op_stc;
+    table[Op::Std.index()] = Machine::op_std;
+    table[Op::Sti.index()] = Machine::op_sti;
+    table[Op::Stosb.index()] = Machine::op_stosb;
+    table[Op::Stosw.index()] = Machine::op_stosw;
+    table[Op::Stosd.index()] = Machine::op_stosd;
+    table[Op::Sub8.index()] = Machine::op_sub8;
+    table[Op::Sub16.index()] = Machine::op_sub16;
+    table[Op::Sub32.index()] = Machine::op_sub32;
+    table[Op::Test8.index()] = Machine::op_test8;
+    table[Op::Test16.index()] = Machine::op_test16;
+    table[Op::Xadd8.index()] = Machine::op_xadd8;
+    table[Op::Xadd16.index()] = Machine::op_xadd16;
+    table[Op::Xadd32.index()] = Machine::op_xadd32;
+    table[Op::Xchg8.index()] = Machine::op_xchg8;
+    table[Op::Xchg16.index()] = Machine::op_xchg16;
+    table[Op::Xchg32.index()] = Machine::op_xchg32;
+    table[Op::Xlatb.index()] = Machine::op_xlatb;
+    table[Op::Xor8.index()] = Machine::op_xor8;
+    table[Op::Xor16.index()] = Machine::op_xor16;
+    table[Op::Xor32.index()] = Machine::op_xor32;
+    table
+}
+
+impl Machine {
+    fn op_aaa(&mut self, _op: &Instruction) {
+        let v = if self.cpu.get_r8(R::AL) > 0xf9 {
+            2
+         } else {
+            1
+        };
+        self.cpu.adjb(6, v);
+    }
+
+    fn op_aad(&mut self, op: &Instruction) {
+        // one parameter
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+
+        let mut ax = u16::from(self.cpu.get_r8(R::AH)) * op1;
+        ax += u16::from(self.cpu.get_r8(R::AL));
+        let al = ax as u8;
+        self.cpu.set_r16(R::AX, al as u16);
+        self.cpu.regs.flags.set_sign(al >= 0x80);
+        self.cpu.regs.flags.set_zero(al == 0);
+        self.cpu.regs.flags.set_parity_from(al as u64);
+    }
+
+    fn op_aam(&mut self, op: &Instruction) {
+        let imm8 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u8;
+        if imm8 == 0 {
+            return self.cpu.exception(&mut self.mmu, &Exception::DIV0, op.length);
+        }
+        let al = self.cpu.get_r8(R::AL);
+        self.cpu.set_r8(R::AH, al / imm8);
+        self.cpu.set_r8(R::AL, al % imm8);
+        // The SF, ZF, and PF flags are set according to the resulting binary value in the AL register
+        let al = self.cpu.get_r8(R::AL);
+        self.cpu.regs.flags.set_sign(al & 0x80 != 0);
+        self.cpu.regs.flags.set_zero(al == 0);
+        self.cpu.regs.flags.set_parity_from(al as u64);
+    }
+
+    fn op_aas(&mut self, _op: &Instruction) {
+        let v = if self.cpu.get_r8(R::AL) < 6 {
+            -2
+        } else {
+            -1
+        };
+        self.cpu.adjb(-6, v);
+    }
+
+    fn op_adc8(&mut self, op: &Instruction) {
+        // two parameters (dst=reg)
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let carry = if self.cpu.regs.flags.carry() { 1 } else { 0 };
+        let res = (Wrapping(dst) + Wrapping(src) + Wrapping(carry)).0;
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, (res & 0xFF) as u8);
+
+        // The OF, SF, ZF, AF, CF, and PF flags are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Add, 8, res, src, dst);
+    }
+
+    fn op_adc16(&mut self, op: &Instruction) {
+        // two parameters (dst=reg)
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let carry = if self.cpu.regs.flags.carry() { 1 } else { 0 };
+        let res = (Wrapping(dst) + Wrapping(src) + Wrapping(carry)).0;
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, (res & 0xFFFF) as u16);
+
+        // The OF, SF, ZF, AF, CF, and PF flags are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Add, 16, res, src, dst);
+    }
+
+    fn op_add8(&mut self, op: &Instruction) {
+        // two parameters (dst=reg)
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u8;
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u8;
+        let res = src as u64 + dst as u64;
+        self.cpu.regs.flags.set_lazy(LazyOp::Add, 8, res, src as u64, dst as u64);
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
+    }
+
+    fn op_add16(&mut self, op: &Instruction) {
+        // two parameters (dst=reg)
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u16;
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        let res = src as u64 + dst as u64;
+        self.cpu.regs.flags.set_lazy(LazyOp::Add, 16, res, src as u64, dst as u64);
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
+    }
+
+    fn op_add32(&mut self, op: &Instruction) {
+        // two parameters (dst=reg)
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let res = src as u64 + dst as u64;
+        self.cpu.regs.flags.set_lazy(LazyOp::Add, 32, res, src as u64, dst as u64);
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u32);
+    }
+
+    fn op_and8(&mut self, op: &Instruction) {
+        // two parameters (dst=reg)
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let res = dst & src;
+
+        // The OF and CF flags are cleared; the SF, ZF, and PF flags
+        // are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Logic, 8, res, 0, 0);
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
+    }
+
+    fn op_and16(&mut self, op: &Instruction) {
+        // two parameters (dst=reg)
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let res = dst & src;
+
+        // The OF and CF flags are cleared; the SF, ZF, and PF flags
+        // are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Logic, 16, res, 0, 0);
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
+    }
+
+    fn op_arpl(&mut self, op: &Instruction) {
+        warn!(target: "cpu", "XXX impl {}", op);
+        /*
+        // NOTE: RPL is the low two bits of the address
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let mut dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        if dst & 3 < src & 3 {
+            self.cpu.regs.flags.set_zero(true);
+            dst = (dst & 0xFFFC) + (src & 3);
+            self.cpu.write_parameter_u16(&mut self.mmu, op.segment, &op.params.dst, (dst & 0xFFFF) as u16);
+        } else {
+            self.cpu.regs.flags.set_zero(false);
+        }
+        */
+    }
+
+    fn op_bsf16(&mut self, op: &Instruction) {
+        self.bit_scan(op, 16, false);
+    }
+
+    fn op_bsf32(&mut self, op: &Instruction) {
+        self.bit_scan(op, 32, false);
+    }
+
+    fn op_bsr16(&mut self, op: &Instruction) {
+        self.bit_scan(op, 16, true);
+    }
+
+    fn op_bsr32(&mut self, op: &Instruction) {
+        self.bit_scan(op, 32, true);
+    }
+
+    fn op_bswap32(&mut self, op: &Instruction) {
+        if !self.require_486(op) {
+            return;
+        }
+        let val = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, val.swap_bytes());
+    }
+
+    fn op_bt16(&mut self, op: &Instruction) {
+        self.bit_test(op, 16, None);
+    }
+
+    fn op_bt32(&mut self, op: &Instruction) {
+        self.bit_test(op, 32, None);
+    }
+
+    fn op_btc16(&mut self, op: &Instruction) {
+        self.bit_test(op, 16, Some(|base, mask| base ^ mask));
+    }
+
+    fn op_btc32(&mut self, op: &Instruction) {
+        self.bit_test(op, 32, Some(|base, mask| base ^ mask));
+    }
+
+    fn op_btr16(&mut self, op: &Instruction) {
+        self.bit_test(op, 16, Some(|base, mask| base & !mask));
+    }
+
+    fn op_btr32(&mut self, op: &Instruction) {
+        self.bit_test(op, 32, Some(|base, mask| base & !mask));
+    }
+
+    fn op_bts16(&mut self, op: &Instruction) {
+        self.bit_test(op, 16, Some(|base, mask| base | mask));
+    }
+
+    fn op_bts32(&mut self, op: &Instruction) {
+        self.bit_test(op, 32, Some(|base, mask| base | mask));
+    }
+
+    fn op_bound(&mut self, op: &Instruction) {
+        // XXX throw BR exception if out of bounds
+        warn!(target: "cpu", "XXX impl {}", op);
+    }
+
+    fn op_call_near(&mut self, op: &Instruction) {
+        let old_ip = self.cpu.regs.ip;
+        let temp_ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        self.cpu.push16(&mut self.mmu, old_ip);
+        self.cpu.regs.ip = temp_ip as u16;
+    }
+
+    fn op_call_far(&mut self, op: &Instruction) {
+        let (seg, offs) = self.far_target(op);
+        let old_seg = self.cpu.regs.get_r16(R::CS);
+        let old_ip = self.cpu.regs.ip;
+        match op.op_size {
+            OperandSize::_16bit => {
+                self.cpu.push16(&mut self.mmu, old_seg);
+                self.cpu.push16(&mut self.mmu, old_ip);
+            }
+            OperandSize::_32bit => {
+                // cs is pushed zero extended
+                self.cpu.push32(&mut self.mmu, u32::from(old_seg));
+                self.cpu.push32(&mut self.mmu, u32::from(old_ip));
+            }
+        }
+        self.cpu.regs.set_r16(R::CS, seg);
+        self.cpu.regs.ip = offs;
+    }
+
+    fn op_cbw(&mut self, _op: &Instruction) {
+        let ah = if self.cpu.get_r8(R::AL) & 0x80 != 0 {
+            0xFF
+        } else {
+            0x00
+        };
+        self.cpu.set_r8(R::AH, ah);
+    }
+
+    fn op_clc(&mut self, _op: &Instruction) {
+        self.cpu.regs.flags.set_carry(false);
+    }
+
+    fn op_cld(&mut self, _op: &Instruction) {
+        self.cpu.regs.flags.direction = false;
+    }
+
+    fn op_cli(&mut self, _op: &Instruction) {
+        self.cpu.regs.flags.interrupt = false;
+    }
+
+    fn op_cmc(&mut self, _op: &Instruction) {
+        self.cpu.regs.flags.set_carry(!self.cpu.regs.flags.carry());
+    }
+
+    fn op_cmp8(&mut self, op: &Instruction) {
+        // two parameters
+        // Modify status flags in the same manner as the SUB instruction
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        self.cpu.cmp8(dst, src);
+    }
+
+    fn op_cmp16(&mut self, op: &Instruction) {
+        // two parameters
+        // Modify status flags in the same manner as the SUB instruction
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        self.cpu.cmp16(dst, src);
+    }
+
+    fn op_cmp32(&mut self, op: &Instruction) {
+        // two parameters
+        // Modify status flags in the same manner as the SUB instruction
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        self.cpu.cmp32(dst, src);
+    }
+
+    fn op_cmpsb(&mut self, op: &Instruction) {
+        // no parameters
+        // Compare byte at address DS:(E)SI with byte at address ES:(E)DI
+        // The DS segment may be overridden with a segment override prefix, but the ES segment cannot be overridden.
+        let src = self.mmu.read_u8(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI)) as u64;
+        let dst = self.mmu.read_u8(self.cpu.get_r16(R::ES), self.cpu.get_r16(R::DI)) as u64;
+        self.cpu.cmp8(dst, src);
+
+        self.step_string_reg(op, R::SI, 1);
+        self.step_string_reg(op, R::DI, 1);
+    }
+
+    fn op_cmpsw(&mut self, op: &Instruction) {
+        // no parameters
+        // Compare word at address DS:(E)SI with word at address ES:(E)DI
+        // The DS segment may be overridden with a segment override prefix, but the ES segment cannot be overridden.
+        let src = self.mmu.read_u16(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI)) as u64;
+        let dst = self.mmu.read_u16(self.cpu.get_r16(R::ES), self.cpu.get_r16(R::DI)) as u64;
+        self.cpu.cmp16(dst, src);
+
+        self.step_string_reg(op, R::SI, 2);
+        self.step_string_reg(op, R::DI, 2);
+    }
+
+    fn op_cmpsd(&mut self, op: &Instruction) {
+        // no parameters
+        // Compare dword at address DS:(E)SI with dword at address ES:(E)DI
+        // The DS segment may be overridden with a segment override prefix, but the ES segment cannot be overridden.
+        let src = self.mmu.read_u32(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI)) as u64;
+        let dst = self.mmu.read_u32(self.cpu.get_r16(R::ES), self.cpu.get_r16(R::DI)) as u64;
+        self.cpu.cmp32(dst, src);
+        self.step_string_reg(op, R::SI, 4);
+        self.step_string_reg(op, R::DI, 4);
+    }
+
+    fn op_cmpxchg8(&mut self, op: &Instruction) {
+        self.compare_exchange(op, 8);
+    }
+
+    fn op_cmpxchg16(&mut self, op: &Instruction) {
+        self.compare_exchange(op, 16);
+    }
+
+    fn op_cmpxchg32(&mut self, op: &Instruction) {
+        self.compare_exchange(op, 32);
+    }
+
+    fn op_cwd16(&mut self, _op: &Instruction) {
+        // DX:AX ← sign-extend of AX.
+        let dx = if self.cpu.get_r16(R::AX) & 0x8000 != 0 {
+            0xFFFF
+        } else {
+            0
+        };
+        self.cpu.set_r16(R::DX, dx);
+    }
+
+    fn op_cwde32(&mut self, _op: &Instruction) {
+        // EAX ← sign-extend of AX.
+        let ax = self.cpu.get_r16(R::AX) as i16;
+        self.cpu.set_r32(R::EAX, ax as u32);
+    }
+
+    fn op_daa(&mut self, _op: &Instruction) {
+        self.cpu.adj4(6, 0x60);
+    }
+
+    fn op_das(&mut self, _op: &Instruction) {
+        self.cpu.adj4(-6, -0x60);
+    }
+
+    fn op_dec8(&mut self, op: &Instruction) {
+        // single parameter (dst)
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let src = 1;
+        let res = dst.wrapping_sub(src);
+
+        // The CF flag is not affected. The OF, SF, ZF, AF,
+        // and PF flags are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Dec, 8, res, src, dst);
+
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
+    }
+
+    fn op_dec16(&mut self, op: &Instruction) {
+        // single parameter (dst)
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let src = 1;
+        let res = dst.wrapping_sub(src);
+
+        // The CF flag is not affected. The OF, SF, ZF, AF,
+        // and PF flags are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Dec, 16, res, src, dst);
+
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
+    }
+
+    fn op_dec32(&mut self, op: &Instruction) {
+        // single parameter (dst)
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let src = 1;
+        let res = dst.wrapping_sub(src);
+
+        // The CF flag is not affected. The OF, SF, ZF, AF,
+        // and PF flags are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Dec, 32, res, src, dst);
+
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u32);
+    }
+
+    fn op_div8(&mut self, op: &Instruction) {
+        // Unsigned divide AX by r/m8, with result stored in AL ← Quotient, AH ← Remainder.
+        let ax = self.cpu.get_r16(R::AX) as u16;
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        if op1 == 0 {
+            return self.cpu.exception(&mut self.mmu, &Exception::DIV0, op.length);
+        }
+        let quotient = ax / op1;
+        let remainder = (ax % op1) as u8;
+        let quo8 = (quotient & 0xFF) as u8;
+        if quotient > 0xFF {
+            return self.cpu.exception(&mut self.mmu, &Exception::DIV0, op.length);
+        }
+        self.cpu.set_r8(R::AH, remainder);
+        self.cpu.set_r8(R::AL, quo8);
+    }
+
+    fn op_div16(&mut self, op: &Instruction) {
+        // Unsigned divide DX:AX by r/m16, with result stored in AX ← Quotient, DX ← Remainder.
+        let num = (u32::from(self.cpu.get_r16(R::DX)) << 16) + u32::from(self.cpu.get_r16(R::AX)); // DX:AX
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        if op1 == 0 {
+            return self.cpu.exception(&mut self.mmu, &Exception::DIV0, op.length);
+        }
+        let remainder = (num % op1) as u16;
+        let quotient = num / op1;
+        let quo16 = (quotient & 0xFFFF) as u16;
+        if quotient != u32::from(quo16) {
+            return self.cpu.exception(&mut self.mmu, &Exception::DIV0, op.length);
+        }
+        self.cpu.set_r16(R::DX, remainder);
+        self.cpu.set_r16(R::AX, quo16);
+    }
+
+    fn op_div32(&mut self, op: &Instruction) {
+        // Unsigned divide EDX:EAX by r/m32, with result stored in EAX ← Quotient, EDX ← Remainder.
+        let num = (u64::from(self.cpu.get_r32(R::EDX)) << 32) + u64::from(self.cpu.get_r32(R::EAX)); // EDX:EAX
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        if op1 == 0 {
+            return self.cpu.exception(&mut self.mmu, &Exception::DIV0, op.length);
+        }
+        let remainder = (num % op1) as u32;
+        let quotient = num / op1;
+        let quo32 = quotient as u32;
+        if quotient != u64::from(quo32) {
+            return self.cpu.exception(&mut self.mmu, &Exception::DIV0, op.length);
+        }
+        self.cpu.set_r32(R::EDX, remainder);
+        self.cpu.set_r32(R::EAX, quo32);
+    }
+
+    fn op_enter(&mut self, op: &Instruction) {
+        // Make Stack Frame for Procedure Parameters
+        // Create a stack frame with optional nested pointers for a procedure.
+        // XXX test this
+        let alloc_size = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        let mut nesting_level = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+
+        nesting_level &= 0x1F; // XXX "mod 32" says docs
+        let bp = self.cpu.get_r16(R::BP);
+        self.cpu.push16(&mut self.mmu, bp);
+        let frame_temp = self.cpu.get_r16(R::SP);
+
+        if nesting_level != 0 {
+            for i in 0..nesting_level {
+                let bp = self.cpu.get_r16(R::BP) - 2;
+                self.cpu.set_r16(R::BP, bp);
+                let val = self.mmu.read_u16(self.cpu.get_r16(R::SS), self.cpu.get_r16(R::BP));
+                debug!(target: "cpu", "XXX ENTER: pushing {} = {:04X}", i, val);
+                self.cpu.push16(&mut self.mmu, val);
+            }
+            self.cpu.push16(&mut self.mmu, frame_temp);
+        }
+
+        self.cpu.set_r16(R::BP, frame_temp);
+        let sp = self.cpu.get_r16(R::SP) - alloc_size;
+        self.cpu.set_r16(R::SP, sp);
+    }
+
+    fn op_hlt(&mut self, _op: &Instruction) {
+        // println!("XXX impl {}", op);
+        // self.fatal_error = true;
+    }
+
+    fn op_idiv8(&mut self, op: &Instruction) {
+        let ax = self.cpu.get_r16(R::AX) as i16; // dividend
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as i8;
+        if op1 == 0 {
+            return self.cpu.exception(&mut self.mmu, &Exception::DIV0, op.length);
+        }
+        let rem = ax.wrapping_rem(i16::from(op1)) as i8;
+        let quo = ax.checked_div(i16::from(op1)).unwrap_or(i16::MAX);
+        let quo8s = (quo & 0xFF) as i8;
+        if quo != i16::from(quo8s) {
+            return self.cpu.exception(&mut self.mmu, &Exception::DIV0, op.length);
+        }
+        self.cpu.set_r8(R::AL, quo as u8);
+        self.cpu.set_r8(R::AH, rem as u8);
+        // The CF, OF, SF, ZF, AF, and PF flags are undefined.
+    }
+
+    fn op_idiv16(&mut self, op: &Instruction) {
+        let dividend = ((u32::from(self.cpu.get_r16(R::DX)) << 16) | u32::from(self.cpu.get_r16(R::AX))) as i32; // DX:AX
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as i16;
+        if op1 == 0 {
+            return self.cpu.exception(&mut self.mmu, &Exception::DIV0, op.length);
+        }
+        let quo = dividend.checked_div(i32::from(op1)).unwrap_or(i32::MAX);
+        let rem = dividend.wrapping_rem(i32::from(op1)) as i16;
+        let quo16s = quo as i16;
+        if quo != i32::from(quo16s) {
+            return self.cpu.exception(&mut self.mmu, &Exception::DIV0, op.length);
+        }
+        self.cpu.set_r16(R::AX, quo16s as u16);
+        self.cpu.set_r16(R::DX, rem as u16);
+        // The CF, OF, SF, ZF, AF, and PF flags are undefined.
+    }
+
+    fn op_idiv32(&mut self, op: &Instruction) {
+        let dividend = ((u64::from(self.cpu.get_r32(R::EDX)) << 32) | u64::from(self.cpu.get_r32(R::EAX))) as i64; // EDX:EAX
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as i32;
+        if op1 == 0 {
+            return self.cpu.exception(&mut self.mmu, &Exception::DIV0, op.length);
+        }
+        let quo = dividend.checked_div(i64::from(op1)).unwrap_or(i64::MAX);
+        let rem = dividend.wrapping_rem(i64::from(op1)) as i32;
+        let quo32s = quo as i32;
+        if quo != i64::from(quo32s) {
+            return self.cpu.exception(&mut self.mmu, &Exception::DIV0, op.length);
+        }
+        self.cpu.set_r32(R::EAX, quo32s as u32);
+        self.cpu.set_r32(R::EDX, rem as u32);
+        // The CF, OF, SF, ZF, AF, and PF flags are undefined.
+    }
+
+    fn op_imul8(&mut self, op: &Instruction) {
+        // NOTE: only 1-parameter imul8 instruction exists
+        // IMUL r/m8               : AX← AL ∗ r/m byte.
+
+        let f1 = self.cpu.get_r8(R::AL) as i8;
+        let f2 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as i8;
+        let ax = (i16::from(f1) * i16::from(f2)) as u16; // product
+        self.cpu.set_r16(R::AX, ax);
+
+        if (ax & 0xFF80) == 0xFF80 || (ax & 0xFF80) == 0x0000 {
+            self.cpu.regs.flags.set_carry(false);
+            self.cpu.regs.flags.set_overflow(false);
+        } else {
+            self.cpu.regs.flags.set_carry(true);
+            self.cpu.regs.flags.set_overflow(true);
+        }
+    }
+
+    fn op_imul16(&mut self, op: &Instruction) {
+        match op.params.count() {
+            1 => {
+                // IMUL r/m16               : DX:AX ← AX ∗ r/m word.
+                let a = self.cpu.get_r16(R::AX);
+                let b = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as i16;
+                let temps = (a as i16) as isize * b as isize;
+                self.cpu.set_r16(R::AX, temps as u16);
+                self.cpu.set_r16(R::DX, (temps >> 16) as u16);
+
+                let tempi = temps as u32;
+                if (tempi & 0xFFFF_8000) == 0xFFFF_8000 || (tempi & 0xFFFF_8000) == 0x0000_0000 {
+                    self.cpu.regs.flags.set_carry(false);
+                    self.cpu.regs.flags.set_overflow(false);
+                } else {
+                    self.cpu.regs.flags.set_carry(true);
+                    self.cpu.regs.flags.set_overflow(true);
+                }
+            }
+            2 => {
+                // IMUL r16, r/m16          : word register ← word register ∗ r/m16.
+                let a = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+                let b = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as i16;
+                let temps = (a as i16) as isize * b as isize;
+                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, temps as u16);
+
+                let tempi = temps as u32;
+                if (tempi & 0xFFFF_8000) == 0xFFFF_8000 || (tempi & 0xFFFF_8000) == 0x0000_0000 {
+                    self.cpu.regs.flags.set_carry(false);
+                    self.cpu.regs.flags.set_overflow(false);
+                } else {
+                    self.cpu.regs.flags.set_carry(true);
+                    self.cpu.regs.flags.set_overflow(true);
+                }
+            }
+            3 => {
+                // IMUL r16, r/m16, imm8    : word register ← r/m16 ∗ sign-extended immediate byte.
+                // IMUL r16, r/m16, imm16   : word register ← r/m16 ∗ immediate word.
+                let a = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+                let b = self.cpu.read_parameter_value(&self.mmu, &op.params.src2) as i16;
+                let temps = (a as i16) as isize * b as isize;
+                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, temps as u16);
+
+                let tempi = temps as u32;
+                if (tempi & 0xFFFF_8000) == 0xFFFF_8000 || (tempi & 0xFFFF_8000) == 0x0000_0000 {
+                    self.cpu.regs.flags.set_carry(false);
+                    self.cpu.regs.flags.set_overflow(false);
+                } else {
+                    self.cpu.regs.flags.set_carry(true);
+                    self.cpu.regs.flags.set_overflow(true);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn op_imul32(&mut self, op: &Instruction) {
+        let tmp: i64;
+        match op.params.count() {
+            1 => {
+                // IMUL r/m32               : EDX:EAX ← EAX ∗ r/m32.
+                let a = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as i32;
+                tmp = (self.cpu.get_r32(R::EAX) as i32) as i64 * a as i64;
+                self.cpu.set_r32(R::EAX, tmp as u32);
+                self.cpu.set_r32(R::EDX, (tmp >> 32) as u32);
+            }
+            2 => {
+                // IMUL r32, r/m32          : doubleword register ← doubleword register ∗ r/m32.
+                let a = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+                let b = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+                tmp = a as i64 * b as i64;
+                self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, tmp as u32);
+            }
+            3 => {
+                // IMUL r32, r/m32, imm8     : doubleword register ← r/m32 ∗ sign- extended immediate byte.
+                // IMUL r32, r/m32, imm32    : doubleword register ← r/m32 ∗ immediate doubleword.
+                let a = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+                let b = self.cpu.read_parameter_value(&self.mmu, &op.params.src2);
+                tmp = b as i64 * a as i64;
+                self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, tmp as u32);
+            }
+            _ => unreachable!(),
+        }
+        if tmp != (tmp as i32) as i64 {
+            self.cpu.regs.flags.set_carry(true);
+            self.cpu.regs.flags.set_overflow(true);
+        } else {
+            self.cpu.regs.flags.set_carry(false);
+            self.cpu.regs.flags.set_overflow(false);
+        }
+    }
+
+    fn op_in8(&mut self, op: &Instruction) {
+        // two parameters (dst=AL)
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let data = self.in_u8(src as u16);
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, data);
+    }
+
+    fn op_in16(&mut self, op: &Instruction) {
+        // two parameters (dst=AX)
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let data = self.in_u16(src as u16);
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, data);
+    }
+
+    fn op_inc8(&mut self, op: &Instruction) {
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let src = 1;
+        let res = dst.wrapping_add(src);
+
+        // The OF, SF, ZF, AF, and PF flags are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Inc, 8, res, src, dst);
+
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
+    }
+
+    fn op_inc16(&mut self, op: &Instruction) {
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let src = 1;
+        let res = dst.wrapping_add(src);
+
+        // The OF, SF, ZF, AF, and PF flags are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Inc, 16, res, src, dst);
+
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
+    }
+
+    fn op_inc32(&mut self, op: &Instruction) {
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let src = 1;
+        let res = dst.wrapping_add(src);
+
+        // The OF, SF, ZF, AF, and PF flags are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Inc, 32, res, src, dst);
+
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u32);
+    }
+
+    fn op_insb(&mut self, op: &Instruction) {
+        // Input byte from I/O port specified in DX into memory location specified in ES:DI.
+        // The ES segment cannot be overridden with a segment override prefix.
+        let dx = self.cpu.get_r16(R::DX);
+        let data = self.in_u8(dx);
+        self.mmu.write_u8(self.cpu.get_r16(R::ES), self.cpu.get_r16(R::DI), data);
+        self.step_string_reg(op, R::DI, 1);
+    }
+
+    fn op_insw(&mut self, op: &Instruction) {
+        // Input word from I/O port specified in DX into memory location specified in ES:DI.
+        // The ES segment cannot be overridden with a segment override prefix.
+        let dx = self.cpu.get_r16(R::DX);
+        let data = self.in_u16(dx);
+        self.mmu.write_u16(self.cpu.get_r16(R::ES), self.cpu.get_r16(R::DI), data);
+        self.step_string_reg(op, R::DI, 2);
+    }
+
+    fn op_insd(&mut self, op: &Instruction) {
+        // Input dword from I/O port specified in DX into memory location specified in ES:DI.
+        // The ES segment cannot be overridden with a segment override prefix.
+        let dx = self.cpu.get_r16(R::DX);
+        let data = self.in_u32(dx);
+        self.mmu.write_u32(self.cpu.get_r16(R::ES), self.cpu.get_r16(R::DI), data);
+        self.step_string_reg(op, R::DI, 4);
+    }
+
+    fn op_callback(&mut self, op: &Instruction) {
+        // usually run by execute_instruction together with the following instruction
+        let number = self.cpu.read_parameter_imm(&op.params.dst);
+        self.handle_callback(number as u16);
+    }
+
+    fn op_int(&mut self, op: &Instruction) {
+        let int = self.cpu.read_parameter_imm(&op.params.dst);
+        self.cpu.execute_interrupt(&mut self.mmu, int as u8);
+    }
+
+    fn op_into(&mut self, _op: &Instruction) {
+        // INT 4 if overflow
+        if self.cpu.regs.flags.overflow() {
+            self.cpu.execute_interrupt(&mut self.mmu, 4);
+        }
+    }
+
+    fn op_ja(&mut self, op: &Instruction) {
+        if !self.cpu.regs.flags.carry() & !self.cpu.regs.flags.zero() {
+            self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        }
+    }
+
+    fn op_jc(&mut self, op: &Instruction) {
+        if self.cpu.regs.flags.carry() {
+            self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        }
+    }
+
+    fn op_jcxz(&mut self, op: &Instruction) {
+        if self.cpu.get_r16(R::CX) == 0 {
+            self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        }
+    }
+
+    fn op_jg(&mut self, op: &Instruction) {
+        if !self.cpu.regs.flags.zero() & self.cpu.regs.flags.sign() == self.cpu.regs.flags.overflow() {
+            self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        }
+    }
+
+    fn op_jl(&mut self, op: &Instruction) {
+        if self.cpu.regs.flags.sign() != self.cpu.regs.flags.overflow() {
+            self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        }
+    }
+
+    fn op_jmp_far(&mut self, op: &Instruction) {
+        let (seg, offs) = self.far_target(op);
+        self.cpu.set_r16(R::CS, seg);
+        self.cpu.regs.ip = offs;
+    }
+
+    fn op_jmp_near(&mut self, op: &Instruction) {
+        self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+    }
+
+    fn op_jna(&mut self, op: &Instruction) {
+        if self.cpu.regs.flags.carry() | self.cpu.regs.flags.zero() {
+            self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        }
+    }
+
+    fn op_jnc(&mut self, op: &Instruction) {
+        if !self.cpu.regs.flags.carry() {
+            self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        }
+    }
+
+    fn op_jng(&mut self, op: &Instruction) {
+        if self.cpu.regs.flags.zero() | self.cpu.regs.flags.sign() != self.cpu.regs.flags.overflow() {
+            self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        }
+    }
+
+    fn op_jnl(&mut self, op: &Instruction) {
+        if self.cpu.regs.flags.sign() == self.cpu.regs.flags.overflow() {
+            self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        }
+    }
+
+    fn op_jno(&mut self, op: &Instruction) {
+        if !self.cpu.regs.flags.overflow() {
+            self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        }
+    }
+
+    fn op_jns(&mut self, op: &Instruction) {
+        if !self.cpu.regs.flags.sign() {
+            self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        }
+    }
+
+    fn op_jnz(&mut self, op: &Instruction) {
+        if !self.cpu.regs.flags.zero() {
+            self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        }
+    }
+
+    fn op_jo(&mut self, op: &Instruction) {
+        if self.cpu.regs.flags.overflow() {
+            self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        }
+    }
+
+    fn op_jpe(&mut self, op: &Instruction) {
+        if self.cpu.regs.flags.parity() {
+            self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        }
+    }
+
+    fn op_jpo(&mut self, op: &Instruction) {
+         if !self.cpu.regs.flags.parity() {
+            self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        }
+    }
+
+    fn op_js(&mut self, op: &Instruction) {
+        if self.cpu.regs.flags.sign() {
+            self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        }
+    }
+
+    fn op_jz(&mut self, op: &Instruction) {
+        if self.cpu.regs.flags.zero() {
+            self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        }
+    }
+
+    fn op_lahf(&mut self, _op: &Instruction) {
+        // Load: AH ← EFLAGS(SF:ZF:0:AF:0:PF:1:CF).
+        let mut val = 0 as u8;
+        if self.cpu.regs.flags.carry() {
+            val |= 1;
+        }
+        val |= 1 << 1;
+        if self.cpu.regs.flags.parity() {
+            val |= 1 << 2;
+        }
+        if self.cpu.regs.flags.adjust() {
+            val |= 1 << 4;
+        }
+        if self.cpu.regs.flags.zero() {
+            val |= 1 << 6;
+        }
+        if self.cpu.regs.flags.sign() {
+            val |= 1 << 7;
+        }
+        self.cpu.set_r8(R::AH, val);
+    }
+
+    fn op_lds(&mut self, op: &Instruction) {
+        let (segment, offset) = self.cpu.read_segment_selector(&self.mmu, &op.params.src);
+        self.cpu.set_r16(R::DS, segment);
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, offset);
+    }
+
+    fn op_lea16(&mut self, op: &Instruction) {
+        let src = self.cpu.read_parameter_address(&op.params.src) as u16;
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, src);
+    }
+
+    fn op_lea32(&mut self, op: &Instruction) {
+        let src = match op.address_size {
+            // 16-bit effective addresses are zero extended
+            AddressSize::_16bit => u32::from(self.cpu.read_parameter_address(&op.params.src) as u16),
+            AddressSize::_32bit => self.cpu.read_parameter_address(&op.params.src) as u32,
+        };
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, src);
+    }
+
+    fn op_leave(&mut self, _op: &Instruction) {
+        // High Level Procedure Exit
+        // Set SP to BP, then pop BP.
+        // XXX test this
+        let bp = self.cpu.get_r16(R::BP);
+        self.cpu.set_r16(R::SP, bp);
+        let bp = self.cpu.pop16(&mut self.mmu);
+        self.cpu.set_r16(R::BP, bp);
+    }
+
+    fn op_les(&mut self, op: &Instruction) {
+        let (segment, offset) = self.cpu.read_segment_selector(&self.mmu, &op.params.src);
+        self.cpu.set_r16(R::ES, segment);
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, offset);
+    }
+
+    fn op_lodsb(&mut self, op: &Instruction) {
+        // no arguments
+        // The DS segment may be over-ridden with a segment override prefix.
+        let val = self.mmu.read_u8(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI));
+
+        self.cpu.set_r8(R::AL, val);
+        self.step_string_reg(op, R::SI, 1);
+    }
+
+    fn op_lodsw(&mut self, op: &Instruction) {
+        // no arguments
+        // The DS segment may be over-ridden with a segment override prefix.
+        let val = self.mmu.read_u16(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI));
+
+        self.cpu.set_r16(R::AX, val);
+        self.step_string_reg(op, R::SI, 2);
+    }
+
+    fn op_lodsd(&mut self, op: &Instruction) {
+        // no arguments
+        // The DS segment may be over-ridden with a segment override prefix.
+        let val = self.mmu.read_u32(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI));
+
+        self.cpu.set_r32(R::EAX, val);
+        self.step_string_reg(op, R::SI, 4);
+    }
+
+    fn op_loop(&mut self, op: &Instruction) {
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        let cx = self.cpu.get_r16(R::CX).wrapping_sub(1);
+        self.cpu.set_r16(R::CX, cx);
+        if cx != 0 {
+            self.cpu.regs.ip = dst;
+        }
+    }
+
+    fn op_loope(&mut self, op: &Instruction) {
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        let cx = self.cpu.get_r16(R::CX).wrapping_sub(1);
+        self.cpu.set_r16(R::CX, cx);
+        if cx != 0 && self.cpu.regs.flags.zero() {
+            self.cpu.regs.ip = dst;
+        }
+    }
+
+    fn op_loopne(&mut self, op: &Instruction) {
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        let cx = self.cpu.get_r16(R::CX).wrapping_sub(1);
+        self.cpu.set_r16(R::CX, cx);
+        if cx != 0 && !self.cpu.regs.flags.zero() {
+            self.cpu.regs.ip = dst;
+        }
+    }
+
+    fn op_mov8(&mut self, op: &Instruction) {
+        // two arguments (dst=reg)
+        let data = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u8;
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, data);
+    }
+
+    fn op_mov16(&mut self, op: &Instruction) {
+        // two arguments (dst=reg)
+        let data = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u16;
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, data);
+    }
+
+    fn op_mov32(&mut self, op: &Instruction) {
+        // two arguments (dst=reg)
+        if let Parameter::CReg32(_) = op.params.src {
+            if self.cpu.model < CpuModel::I80386 {
+                return self.cpu.exception(&mut self.mmu, &Exception::UD, op.length);
+            }
+        }
+        let data = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, data);
+    }
+
+    fn op_movsb(&mut self, op: &Instruction) {
+        // move byte from address DS:(E)SI to ES:(E)DI.
+        // The DS segment may be overridden with a segment override prefix, but the ES segment cannot be overridden.
+        let val = self.mmu.read_u8(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI));
+        self.step_string_reg(op, R::SI, 1);
+        let es = self.cpu.get_r16(R::ES);
+        let di = self.cpu.get_r16(R::DI);
+        self.mmu.write_u8(es, di, val);
+        self.step_string_reg(op, R::DI, 1);
+    }
+
+    fn op_movsw(&mut self, op: &Instruction) {
+        // move word from address DS:(E)SI to ES:(E)DI.
+        // The DS segment may be overridden with a segment override prefix, but the ES segment cannot be overridden.
+        let val = self.mmu.read_u16(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI));
+        self.step_string_reg(op, R::SI, 2);
+        let es = self.cpu.get_r16(R::ES);
+        let di = self.cpu.get_r16(R::DI);
+        self.mmu.write_u16(es, di, val);
+        self.step_string_reg(op, R::DI, 2);
+    }
+
+    fn op_movsd(&mut self, op: &Instruction) {
+        // move dword from address DS:(E)SI to ES:(E)DI
+        // The DS segment may be overridden with a segment override prefix, but the ES segment cannot be overridden.
+        let val = self.mmu.read_u32(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI));
+        self.step_string_reg(op, R::SI, 4);
+        let es = self.cpu.get_r16(R::ES);
+        let di = self.cpu.get_r16(R::DI);
+        self.mmu.write_u32(es, di, val);
+        self.step_string_reg(op, R::DI, 4);
+    }
+
+    fn op_movsx16(&mut self, op: &Instruction) {
+        // 80386+
+        // moves a signed value into a register and sign-extends it with 1.
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u8;
+
+        let mut data = u16::from(src);
+        if src & 0x80 != 0 {
+            data += 0xFF00;
+        }
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, data);
+    }
+
+    fn op_movsx32(&mut self, op: &Instruction) {
+        // 80386+
+        // moves a signed value into a register and sign-extends it with 1.
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u16;
+
+        let mut data = u32::from(src);
+        match op.params.src {
+            Parameter::Reg8(_) | Parameter::Ptr8AmodeS8(_, _, _) | Parameter::Ptr8AmodeS16(_, _, _) =>
+            if src & 0x80 != 0 {
+                data += 0xFFFF_FF00;
+            }
+            Parameter::Reg16(_) => if src & 0x8000 != 0 {
+                data += 0xFFFF_0000;
+            }
+            _ => panic!("unexpected Movsx32 src arg {:?}", op.params.src),
+        }
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, data);
+    }
+
+    fn op_movzx16(&mut self, op: &Instruction) {
+        // 80386+
+        // moves an unsigned value into a register and zero-extends it.
+        let val = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u16;
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, val);
+    }
+
+    fn op_movzx32(&mut self, op: &Instruction) {
+        // 80386+
+        // moves an unsigned value into a register and zero-extends it.
+        let val = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, val);
+    }
+
+    fn op_mul8(&mut self, op: &Instruction) {
+        // Unsigned multiply (AX ← AL ∗ r/m8).
+        let al = self.cpu.get_r8(R::AL) as u64;
+        let arg1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let ax = al.wrapping_mul(arg1) as u16;
+        self.cpu.set_r16(R::AX, ax);
+        // The OF and CF flags are set to 0 if the upper half of the
+        // result is 0; otherwise, they are set to 1.
+        // The SF, ZF, AF, and PF flags are undefined.
+        if ax & 0xFF00 != 0 {
+            self.cpu.regs.flags.set_carry(true);
+            self.cpu.regs.flags.set_overflow(true);
+        } else {
+            self.cpu.regs.flags.set_carry(false);
+            self.cpu.regs.flags.set_overflow(false);
+        }
+    }
+
+    fn op_mul16(&mut self, op: &Instruction) {
+        // Unsigned multiply (DX:AX ← AX ∗ r/m16).
+        let src = self.cpu.get_r16(R::AX) as u64;
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let res = dst.wrapping_mul(src);
+
+        self.cpu.set_r16(R::AX, res as u16);
+        let dx = (res >> 16) as u16;
+        self.cpu.set_r16(R::DX, dx);
+
+        self.cpu.regs.flags.set_carry(dx != 0);
+        self.cpu.regs.flags.set_overflow(dx != 0);
+    }
+
+    fn op_mul32(&mut self, op: &Instruction) {
+        // Unsigned multiply (EDX:EAX ← EAX ∗ r/m32)
+        let src = self.cpu.get_r32(R::EAX) as u64;
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let res = dst.wrapping_mul(src);
+
+        self.cpu.set_r32(R::EAX, res as u32);
+        let edx = (res >> 32) as u32;
+        self.cpu.set_r32(R::EDX, edx);
+
+        self.cpu.regs.flags.set_carry(edx != 0);
+        self.cpu.regs.flags.set_overflow(edx != 0);
+    }
+
+    fn op_neg8(&mut self, op: &Instruction) {
+        // Two's Complement Negation
+        // one argument
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let src: u8 = 0;
+        let res = src.wrapping_sub(dst as u8) as u64;
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
+
+        self.cpu.regs.flags.set_carry(dst != 0);
+        // The OF, SF, ZF, AF, and PF flags are set according to the result.
+        self.cpu.regs.flags.set_overflow(res == 0x80);
+        self.cpu.regs.flags.set_sign_u8(res);
+        self.cpu.regs.flags.set_zero_u8(res);
+        self.cpu.regs.flags.set_adjust_from(res, src as u64, dst);
+        self.cpu.regs.flags.set_parity_from(res);
+    }
+
+    fn op_neg16(&mut self, op: &Instruction) {
+        // one argument
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let src: u16 = 0;
+        let res = src.wrapping_sub(dst as u16) as u64;
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
+
+        self.cpu.regs.flags.set_carry(dst != 0);
+        // The OF, SF, ZF, AF, and PF flags are set according to the result.
+        self.cpu.regs.flags.set_overflow(res == 0x8000);
+        self.cpu.regs.flags.set_sign_u16(res);
+        self.cpu.regs.flags.set_zero_u16(res);
+        self.cpu.regs.flags.set_adjust_from(res, src as u64, dst);
+        self.cpu.regs.flags.set_parity_from(res);
+    }
+
+    fn op_neg32(&mut self, op: &Instruction) {
+        // one argument
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let src: u32 = 0;
+        let res = src.wrapping_sub(dst as u32) as u64;
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u32);
+
+        self.cpu.regs.flags.set_carry(dst != 0);
+        // The OF, SF, ZF, AF, and PF flags are set according to the result.
+        self.cpu.regs.flags.set_overflow(res == 0x8000_0000);
+        self.cpu.regs.flags.set_sign_u32(res);
+        self.cpu.regs.flags.set_zero_u32(res);
+        self.cpu.regs.flags.set_adjust_from(res, src as u64, dst);
+        self.cpu.regs.flags.set_parity_from(res);
+    }
+
+    fn op_nop(&mut self, _op: &Instruction) {
+    }
+
+    fn op_not8(&mut self, op: &Instruction) {
+        // one arguments (dst)
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let res = !dst;
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, (res & 0xFF) as u8);
+        // Flags Affected: None
+    }
+
+    fn op_not16(&mut self, op: &Instruction) {
+        // one arguments (dst)
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let res = !dst;
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, (res & 0xFFFF) as u16);
+        // Flags Affected: None
+    }
+
+    fn op_or8(&mut self, op: &Instruction) {
+        // two arguments (dst=AL)
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let res = dst | src;
+        // The OF and CF flags are cleared; the SF, ZF, and PF flags
+        // are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Logic, 8, res, 0, 0);
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, (res & 0xFF) as u8);
+    }
+
+    fn op_or16(&mut self, op: &Instruction) {
+        // two arguments (dst=AX)
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let res = dst | src;
+        // The OF and CF flags are cleared; the SF, ZF, and PF flags
+        // are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Logic, 16, res, 0, 0);
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, (res & 0xFFFF) as u16);
+    }
+
+    fn op_out8(&mut self, op: &Instruction) {
+        // two arguments
+        let addr = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        let val = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u8;
+        self.out_u8(addr, val);
+    }
+
+    fn op_out16(&mut self, op: &Instruction) {
+        // two arguments
+        let addr = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        let val = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u16;
+        self.out_u16(addr, val);
+    }
+
+    fn op_outsb(&mut self, op: &Instruction) {
+        // Output byte from memory location specified in DS:(E)SI or RSI to I/O port specified in DX.
+        // no arguments
+        let val = self.mmu.read_u8(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI));
+        let port = self.cpu.get_r16(R::DX);
+        self.out_u8(port, val);
+        self.step_string_reg(op, R::SI, 1);
+    }
+
+    fn op_outsw(&mut self, op: &Instruction) {
+        // Output word from memory location specified in DS:(E)SI or RSI to I/O port specified in DX**.
+        // no arguments
+        let val = self.mmu.read_u16(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI));
+        let port = self.cpu.get_r16(R::DX);
+        self.out_u16(port, val);
+        self.step_string_reg(op, R::SI, 2);
+    }
+
+    fn op_outsd(&mut self, op: &Instruction) {
+        // Output dword from memory location specified in DS:(E)SI to I/O port specified in DX.
+        // no arguments
+        let val = self.mmu.read_u32(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI));
+        let port = self.cpu.get_r16(R::DX);
+        self.out_u32(port, val);
+        self.step_string_reg(op, R::SI, 4);
+    }
+
+    fn op_pop16(&mut self, op: &Instruction) {
+        // one arguments (dst)
+        let data = self.cpu.pop16(&mut self.mmu);
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, data);
+    }
+
+    fn op_pop32(&mut self, op: &Instruction) {
+        // one arguments (dst)
+        let data = self.cpu.pop32(&mut self.mmu);
+        if let Parameter::SReg16(_) = op.params.dst {
+            // pop sreg discards the upper word
+            self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, data as u16);
+        } else {
+            self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, data);
+        }
+    }
+
+    fn op_popa16(&mut self, _op: &Instruction) {
+        let di = self.cpu.pop16(&mut self.mmu);
+        self.cpu.set_r16(R::DI, di);
+        let si = self.cpu.pop16(&mut self.mmu);
+        self.cpu.set_r16(R::SI, si);
+        let bp = self.cpu.pop16(&mut self.mmu);
+        self.cpu.set_r16(R::BP, bp);
+        let sp = self.cpu.get_r16(R::SP).wrapping_add(2); // skip next word of stack
+        self.cpu.set_r16(R::SP, sp);
+        let bx = self.cpu.pop16(&mut self.mmu);
+        self.cpu.set_r16(R::BX, bx);
+        let dx = self.cpu.pop16(&mut self.mmu);
+        self.cpu.set_r16(R::DX, dx);
+        let cx = self.cpu.pop16(&mut self.mmu);
+        self.cpu.set_r16(R::CX, cx);
+        let ax = self.cpu.pop16(&mut self.mmu);
+        self.cpu.set_r16(R::AX, ax);
+    }
+
+    fn op_popad32(&mut self, _op: &Instruction) {
+        let edi = self.cpu.pop32(&mut self.mmu);
+        self.cpu.set_r32(R::EDI, edi);
+        let esi = self.cpu.pop32(&mut self.mmu);
+        self.cpu.set_r32(R::ESI, esi);
+        let ebp = self.cpu.pop32(&mut self.mmu);
+        self.cpu.set_r32(R::EBP, ebp);
+        let sp = self.cpu.get_r16(R::SP).wrapping_add(4); // skip next dword of stack
+        self.cpu.set_r16(R::SP, sp);
+        let ebx = self.cpu.pop32(&mut self.mmu);
+        self.cpu.set_r32(R::EBX, ebx);
+        let edx = self.cpu.pop32(&mut self.mmu);
+        self.cpu.set_r32(R::EDX, edx);
+        let ecx = self.cpu.pop32(&mut self.mmu);
+        self.cpu.set_r32(R::ECX, ecx);
+        let eax = self.cpu.pop32(&mut self.mmu);
+        self.cpu.set_r32(R::EAX, eax);
+    }
+
+    fn op_popf(&mut self, _op: &Instruction) {
+        let data = self.cpu.pop16(&mut self.mmu);
+        self.cpu.set_flags_image(data);
+    }
+
+    fn op_push16(&mut self, op: &Instruction) {
+        // single parameter (dst)
+        let mut data = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+        if op.params.dst == Parameter::Reg16(R::SP) && matches!(self.cpu.model, CpuModel::I8086 | CpuModel::I80186) {
+            // the 8086 and 186 push the decremented sp
+            data = data.wrapping_sub(2);
+        }
+        self.cpu.push16(&mut self.mmu, data);
+    }
+
+    fn op_push32(&mut self, op: &Instruction) {
+        // single parameter (dst), push sreg pushes the selector zero extended
+        let data = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        self.cpu.push32(&mut self.mmu, data);
+    }
+
+    fn op_pusha16(&mut self, _op: &Instruction) {
+        let ax = self.cpu.get_r16(R::AX);
+        let cx = self.cpu.get_r16(R::CX);
+        let dx = self.cpu.get_r16(R::DX);
+        let bx = self.cpu.get_r16(R::BX);
+        let sp = self.cpu.get_r16(R::SP);
+        let bp = self.cpu.get_r16(R::BP);
+        let si = self.cpu.get_r16(R::SI);
+        let di = self.cpu.get_r16(R::DI);
+        self.cpu.push16(&mut self.mmu, ax);
+        self.cpu.push16(&mut self.mmu, cx);
+        self.cpu.push16(&mut self.mmu, dx);
+        self.cpu.push16(&mut self.mmu, bx);
+        self.cpu.push16(&mut self.mmu, sp);
+        self.cpu.push16(&mut self.mmu, bp);
+        self.cpu.push16(&mut self.mmu, si);
+        self.cpu.push16(&mut self.mmu, di);
+    }
+
+    fn op_pushad32(&mut self, _op: &Instruction) {
+        let eax = self.cpu.get_r32(R::EAX);
+        let ecx = self.cpu.get_r32(R::ECX);
+        let edx = self.cpu.get_r32(R::EDX);
+        let ebx = self.cpu.get_r32(R::EBX);
+        let esp = self.cpu.get_r32(R::ESP);
+        let ebp = self.cpu.get_r32(R::EBP);
+        let esi = self.cpu.get_r32(R::ESI);
+        let edi = self.cpu.get_r32(R::EDI);
+        self.cpu.push32(&mut self.mmu, eax);
+        self.cpu.push32(&mut self.mmu, ecx);
+        self.cpu.push32(&mut self.mmu, edx);
+        self.cpu.push32(&mut self.mmu, ebx);
+        self.cpu.push32(&mut self.mmu, esp);
+        self.cpu.push32(&mut self.mmu, ebp);
+        self.cpu.push32(&mut self.mmu, esi);
+        self.cpu.push32(&mut self.mmu, edi);
+    }
+
+    fn op_pushf(&mut self, _op: &Instruction) {
+        let data = self.cpu.flags_image();
+        self.cpu.push16(&mut self.mmu, data);
+    }
+
+    fn op_rcl8(&mut self, op: &Instruction) {
+        // Rotate 9 bits (CF, r/m8) left `src` times.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.rcl(8, op1, count);
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
+    }
+
+    fn op_rcl16(&mut self, op: &Instruction) {
+        // Rotate 17 bits (CF, r/m16) left `src` times.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.rcl(16, op1, count);
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
+    }
+
+    fn op_rcl32(&mut self, op: &Instruction) {
+        // Rotate 33 bits (CF, r/m32) left `src` times.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.rcl(32, op1, count);
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res);
+    }
+
+    fn op_rcr8(&mut self, op: &Instruction) {
+        // Rotate 9 bits (CF, r/m8) right `src` times.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.rcr(8, op1, count);
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
+    }
+
+    fn op_rcr16(&mut self, op: &Instruction) {
+        // Rotate 17 bits (CF, r/m16) right `src` times.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.rcr(16, op1, count);
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
+    }
+
+    fn op_rcr32(&mut self, op: &Instruction) {
+        // Rotate 33 bits (CF, r/m32) right `src` times.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.rcr(32, op1, count);
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res);
+    }
+
+    fn op_iret(&mut self, op: &Instruction) {
+        let (ip, cs) = self.pop_far_return(op);
+        self.cpu.regs.ip = ip;
+        self.cpu.set_r16(R::CS, cs);
+        let flags = match op.op_size {
+            OperandSize::_16bit => self.cpu.pop16(&mut self.mmu),
+            // iretd, the upper word of EFLAGS is not emulated
+            OperandSize::_32bit => self.cpu.pop32(&mut self.mmu) as u16,
+        };
+        self.cpu.set_flags_image(flags);
+        self.mmu.flags_address = MemoryAddress::Unset;
+    }
+
+    fn op_retf(&mut self, op: &Instruction) {
+        let (ip, cs) = self.pop_far_return(op);
+        self.cpu.regs.ip = ip;
+        self.cpu.set_r16(R::CS, cs);
+        if op.params.count() == 1 {
+            // 1 argument: pop imm16 bytes from stack, after the return address
+            let imm16 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+            let sp = self.cpu.get_r16(R::SP).wrapping_add(imm16);
+            self.cpu.set_r16(R::SP, sp);
+        }
+    }
+
+    fn op_retn(&mut self, op: &Instruction) {
+        let val = self.cpu.pop16(&mut self.mmu);
+        if DEBUG_MARK_STACK && val == STACK_MARKER {
+            warn!(target: "cpu", "[{}] WARNING: stack marker was popped after {} instr. execution ended. (can be valid where small app just return to DOS with a 'ret', but can also indicate memory corruption)",
+                self.cpu.get_memory_address(), self.cpu.instruction_count);
+            self.cpu.fatal_error = true;
+        }
+        // println!("Retn, ip from {:04X} to {:04X}", self.cpu.regs.ip, val);
+        self.cpu.regs.ip = val;
+        if op.params.count() == 1 {
+            // 1 argument: pop imm16 bytes from stack
+            let imm16 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
+            let sp = self.cpu.get_r16(R::SP).wrapping_add(imm16);
+            self.cpu.set_r16(R::SP, sp);
+        }
+    }
+
+    fn op_rol8(&mut self, op: &Instruction) {
+        // Rotate 8 bits of `dst` left `src` times.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.rol(8, op1, count);
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
+    }
+
+    fn op_rol16(&mut self, op: &Instruction) {
+        // Rotate 16 bits of `dst` left `src` times.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.rol(16, op1, count);
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
+    }
+
+    fn op_rol32(&mut self, op: &Instruction) {
+        // Rotate 32 bits of `dst` left `src` times.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.rol(32, op1, count);
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res);
+    }
+
+    fn op_ror8(&mut self, op: &Instruction) {
+        // Rotate 8 bits of `dst` right `src` times.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.ror(8, op1, count);
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
+    }
+
+    fn op_ror16(&mut self, op: &Instruction) {
+        // Rotate 16 bits of `dst` right `src` times.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.ror(16, op1, count);
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
+    }
+
+    fn op_ror32(&mut self, op: &Instruction) {
+        // Rotate 32 bits of `dst` right `src` times.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.ror(32, op1, count);
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res);
+    }
+
+    fn op_sahf(&mut self, _op: &Instruction) {
+        // Loads the SF, ZF, AF, PF, and CF flags of the EFLAGS register with values
+        // from the corresponding bits in the AH register (bits 7, 6, 4, 2, and 0, respectively).
+        let ah = self.cpu.get_r8(R::AH);
+        self.cpu.regs.flags.set_carry(ah & 0x1 != 0); // bit 0
+        self.cpu.regs.flags.set_parity(ah & 0x4 != 0); // bit 2
+        self.cpu.regs.flags.set_adjust(ah & 0x10 != 0); // bit 4
+        self.cpu.regs.flags.set_zero(ah & 0x40 != 0); // bit 6
+        self.cpu.regs.flags.set_sign(ah & 0x80 != 0); // bit 7
+    }
+
+    fn op_salc(&mut self, _op: &Instruction) {
+        let al = if self.cpu.regs.flags.carry() {
+            0xFF
+        } else {
+            0
+        };
+        self.cpu.set_r8(R::AL, al);
+    }
+
+    fn op_sar8(&mut self, op: &Instruction) {
+        // Signed divide r/m8 by 2, `src` times.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.sar(8, op1, count);
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
+    }
+
+    fn op_sar16(&mut self, op: &Instruction) {
+        // Signed divide r/m16 by 2, `src` times.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.sar(16, op1, count);
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
+    }
+
+    fn op_sar32(&mut self, op: &Instruction) {
+        // Signed divide r/m32 by 2, `src` times.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.sar(32, op1, count);
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res);
+    }
+
+    fn op_sbb8(&mut self, op: &Instruction) {
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let cf = if self.cpu.regs.flags.carry() { 1 } else { 0 };
+        let res = (Wrapping(dst) - (Wrapping(src) + Wrapping(cf))).0;
+
+        // The OF, SF, ZF, AF, PF, and CF flags are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Sub, 8, res, src, dst);
+
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
+    }
+
+    fn op_sbb16(&mut self, op: &Instruction) {
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let cf = if self.cpu.regs.flags.carry() { 1 } else { 0 };
+        let res = (Wrapping(dst) - (Wrapping(src) + Wrapping(cf))).0;
+
+        // The OF, SF, ZF, AF, PF, and CF flags are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Sub, 16, res, src, dst);
+
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
+    }
+
+    fn op_scasb(&mut self, op: &Instruction) {
+        // Compare AL with byte at ES:(E)DI then set status flags.
+        // ES cannot be overridden with a segment override prefix.
+        let src = self.cpu.get_r8(R::AL);
+        let dst = self.mmu.read_u8(self.cpu.get_r16(R::ES), self.cpu.get_r16(R::DI));
+        self.cpu.cmp8(dst as u64, src as u64);
+        self.step_string_reg(op, R::DI, 1);
+    }
+
+    fn op_scasw(&mut self, op: &Instruction) {
+        // Compare AX with word at ES:(E)DI or RDI then set status flags.
+        // ES cannot be overridden with a segment override prefix.
+        let src = self.cpu.get_r16(R::AX);
+        let dst = self.mmu.read_u16(self.cpu.get_r16(R::ES), self.cpu.get_r16(R::DI));
+        self.cpu.cmp16(dst as u64, src as u64);
+        self.step_string_reg(op, R::DI, 2);
+    }
+
+    fn op_scasd(&mut self, op: &Instruction) {
+        // Compare EAX with dword at ES:(E)DI then set status flags.
+        // ES cannot be overridden with a segment override prefix.
+        let src = self.cpu.get_r32(R::EAX);
+        let dst = self.mmu.read_u32(self.cpu.get_r16(R::ES), self.cpu.get_r16(R::DI));
+        self.cpu.cmp32(dst as u64, src as u64);
+        self.step_string_reg(op, R::DI, 4);
+    }
+
+    fn op_seta(&mut self, op: &Instruction) {
+        let cond = !self.cpu.regs.flags.carry() && !self.cpu.regs.flags.zero();
+        self.set_condition(op, cond);
+    }
+
+    fn op_setc(&mut self, op: &Instruction) {
+        let cond = self.cpu.regs.flags.carry();
+        self.set_condition(op, cond);
+    }
+
+    fn op_setg(&mut self, op: &Instruction) {
+        let cond = !self.cpu.regs.flags.zero() && self.cpu.regs.flags.sign() == self.cpu.regs.flags.overflow();
+        self.set_condition(op, cond);
+    }
+
+    fn op_setl(&mut self, op: &Instruction) {
+        let cond = self.cpu.regs.flags.sign() != self.cpu.regs.flags.overflow();
+        self.set_condition(op, cond);
+    }
+
+    fn op_setna(&mut self, op: &Instruction) {
+        let cond = self.cpu.regs.flags.carry() || self.cpu.regs.flags.zero();
+        self.set_condition(op, cond);
+    }
+
+    fn op_setnc(&mut self, op: &Instruction) {
+        let cond = !self.cpu.regs.flags.carry();
+        self.set_condition(op, cond);
+    }
+
+    fn op_setng(&mut self, op: &Instruction) {
+        let cond = self.cpu.regs.flags.zero() || self.cpu.regs.flags.sign() != self.cpu.regs.flags.overflow();
+        self.set_condition(op, cond);
+    }
+
+    fn op_setnl(&mut self, op: &Instruction) {
+        let cond = self.cpu.regs.flags.sign() == self.cpu.regs.flags.overflow();
+        self.set_condition(op, cond);
+    }
+
+    fn op_setno(&mut self, op: &Instruction) {
+        let cond = !self.cpu.regs.flags.overflow();
+        self.set_condition(op, cond);
+    }
+
+    fn op_setns(&mut self, op: &Instruction) {
+        let cond = !self.cpu.regs.flags.sign();
+        self.set_condition(op, cond);
+    }
+
+    fn op_setnz(&mut self, op: &Instruction) {
+        let cond = !self.cpu.regs.flags.zero();
+        self.set_condition(op, cond);
+    }
+
+    fn op_seto(&mut self, op: &Instruction) {
+        let cond = self.cpu.regs.flags.overflow();
+        self.set_condition(op, cond);
+    }
+
+    fn op_setpe(&mut self, op: &Instruction) {
+        let cond = self.cpu.regs.flags.parity();
+        self.set_condition(op, cond);
+    }
+
+    fn op_setpo(&mut self, op: &Instruction) {
+        let cond = !self.cpu.regs.flags.parity();
+        self.set_condition(op, cond);
+    }
+
+    fn op_sets(&mut self, op: &Instruction) {
+        let cond = self.cpu.regs.flags.sign();
+        self.set_condition(op, cond);
+    }
+
+    fn op_setz(&mut self, op: &Instruction) {
+        let cond = self.cpu.regs.flags.zero();
+        self.set_condition(op, cond);
+    }
+
+    fn op_setmo8(&mut self, op: &Instruction) {
+        // flags as for "or r/m8, 0xFF", unchanged if count is 0
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        if count != 0 {
+            self.cpu.regs.flags.set_lazy(LazyOp::Logic, 8, 0xFF, 0, 0);
+            self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, 0xFF);
+        }
+    }
+
+    fn op_setmo16(&mut self, op: &Instruction) {
+        // flags as for "or r/m16, 0xFFFF", unchanged if count is 0
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        if count != 0 {
+            self.cpu.regs.flags.set_lazy(LazyOp::Logic, 16, 0xFFFF, 0, 0);
+            self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, 0xFFFF);
+        }
+    }
+
+    fn op_shl8(&mut self, op: &Instruction) {
+        // Multiply r/m8 by 2, `src` times. (alias: sal)
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.shl(8, op1, count);
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
+    }
+
+    fn op_shl16(&mut self, op: &Instruction) {
+        // Multiply r/m16 by 2, `src` times. (alias: sal)
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.shl(16, op1, count);
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
+    }
+
+    fn op_shl32(&mut self, op: &Instruction) {
+        // Multiply r/m32 by 2, `src` times. (alias: sal)
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.shl(32, op1, count);
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res);
+    }
+
+    fn op_shld(&mut self, op: &Instruction) {
+        // Shift `dst` left `src2` times, shifting in bits from `src`.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let op2 = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src2) as u32;
+        let res = self.cpu.regs.flags.shld(16, op1, op2, count);
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
+    }
+
+    fn op_shr8(&mut self, op: &Instruction) {
+        // Unsigned divide r/m8 by 2, `src` times.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.shr(8, op1, count);
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
+    }
+
+    fn op_shr16(&mut self, op: &Instruction) {
+        // Unsigned divide r/m16 by 2, `src` times.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.shr(16, op1, count);
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
+    }
+
+    fn op_shr32(&mut self, op: &Instruction) {
+        // Unsigned divide r/m32 by 2, `src` times.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let res = self.cpu.regs.flags.shr(32, op1, count);
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res);
+    }
+
+    fn op_shrd(&mut self, op: &Instruction) {
+        // Shift `dst` right `src2` times, shifting in bits from `src`.
+        let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
+        let op2 = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
+        let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src2) as u32;
+        let res = self.cpu.regs.flags.shrd(16, op1, op2, count);
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
+    }
+
+    fn op_sldt(&mut self, op: &Instruction) {
+        warn!(target: "cpu", "XXX impl {}", op);
+    }
+
+    fn op_smsw(&mut self, op: &Instruction) {
+        if matches!(self.cpu.model, CpuModel::I8086 | CpuModel::I80186) {
+            return self.cpu.exception(&mut self.mmu, &Exception::UD, op.length);
+        }
+        let msw = self.cpu.cr0() as u16;
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, msw);
+    }
+
+    fn op_stc(&mut self, _op: &Instruction) {
+        self.cpu.regs.flags.set_carry(true);
+    }
+
+    fn op_std(&mut self, _op: &Instruction) {
+        self.cpu.regs.flags.direction = true;
+    }
+
+    fn op_sti(&mut self, _op: &Instruction) {
+        self.cpu.regs.flags.interrupt = true;
+    }
+
+    fn op_stosb(&mut self, op: &Instruction) {
+        // no parameters
+        // store AL at ES:(E)DI
+        // The ES segment cannot be overridden with a segment override prefix.
+        let al = self.cpu.get_r8(R::AL);
+        let es = self.cpu.get_r16(R::ES);
+        let di = self.cpu.get_r16(R::DI);
+        self.mmu.write_u8(es, di, al);
+        self.step_string_reg(op, R::DI, 1);
+    }
+
+    fn op_stosw(&mut self, op: &Instruction) {
+        // no parameters
+        // store AX at address ES:(E)DI
+        // The ES segment cannot be overridden with a segment override prefix.
+        let ax = self.cpu.get_r16(R::AX);
+        let es = self.cpu.get_r16(R::ES);
+        let di = self.cpu.get_r16(R::DI);
+        self.mmu.write_u16(es, di, ax);
+        self.step_string_reg(op, R::DI, 2);
+    }
+
+    fn op_stosd(&mut self, op: &Instruction) {
+        // no parameters
+        // store EAX at address ES:(E)DI
+        // The ES segment cannot be overridden with a segment override prefix.
+        let eax = self.cpu.get_r32(R::EAX);
+        let es = self.cpu.get_r16(R::ES);
+        let di = self.cpu.get_r16(R::DI);
+        self.mmu.write_u32(es, di, eax);
+        self.step_string_reg(op, R::DI, 4);
+    }
+
+    fn op_sub8(&mut self, op: &Instruction) {
+        // two parameters (dst=reg)
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let res = dst.wrapping_sub(src);
+
+        // The OF, SF, ZF, AF, PF, and CF flags are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Sub, 8, res, src, dst);
+
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
+    }
+
+    fn op_sub16(&mut self, op: &Instruction) {
+        // two parameters (dst=reg)
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let res = dst.wrapping_sub(src);
+
+        // The OF, SF, ZF, AF, PF, and CF flags are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Sub, 16, res, src, dst);
+
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
+    }
+
+    fn op_sub32(&mut self, op: &Instruction) {
+        // two parameters (dst=reg)
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let res = dst.wrapping_sub(src);
+
+        // The OF, SF, ZF, AF, PF, and CF flags are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Sub, 32, res, src, dst);
+
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u32);
+    }
+
+    fn op_test8(&mut self, op: &Instruction) {
+        // two parameters
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let res = dst & src;
+        // set SF, ZF, PF according to result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Logic, 8, res, 0, 0);
+    }
+
+    fn op_test16(&mut self, op: &Instruction) {
+        // two parameters
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let res = dst & src;
+        // set SF, ZF, PF according to result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Logic, 16, res, 0, 0);
+    }
+
+    fn op_xadd8(&mut self, op: &Instruction) {
+        self.exchange_add(op, 8);
+    }
+
+    fn op_xadd16(&mut self, op: &Instruction) {
+        self.exchange_add(op, 16);
+    }
+
+    fn op_xadd32(&mut self, op: &Instruction) {
+        self.exchange_add(op, 32);
+    }
+
+    fn op_xchg8(&mut self, op: &Instruction) {
+        // two parameters (registers)
+        let mut src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let mut dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        mem::swap(&mut src, &mut dst);
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, dst as u8);
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.src, src as u8);
+    }
+
+    fn op_xchg16(&mut self, op: &Instruction) {
+        // two parameters (registers)
+        let mut src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let mut dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        mem::swap(&mut src, &mut dst);
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, dst as u16);
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.src, src as u16);
+    }
+
+    fn op_xchg32(&mut self, op: &Instruction) {
+        // two parameters (registers)
+        let mut src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let mut dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        mem::swap(&mut src, &mut dst);
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, dst as u32);
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.src, src as u32);
+    }
+
+    fn op_xlatb(&mut self, op: &Instruction) {
+        // no parameters
+        // Set AL to memory byte DS:[(E)BX + unsigned AL].
+        // The DS segment may be overridden with a segment override prefix.
+        let al = self.mmu.read_u8(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::BX) + u16::from(self.cpu.get_r8(R::AL)));
+        self.cpu.set_r8(R::AL, al);
+    }
+
+    fn op_xor8(&mut self, op: &Instruction) {
+        // two parameters (dst=reg)
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let res = dst ^ src;
+
+        // The OF and CF flags are cleared; the SF, ZF,
+        // and PF flags are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Logic, 8, res, 0, 0);
+
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
+    }
+
+    fn op_xor16(&mut self, op: &Instruction) {
+        // two parameters (dst=reg)
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let res = dst ^ src;
+
+        // The OF and CF flags are cleared; the SF, ZF,
+        // and PF flags are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Logic, 16, res, 0, 0);
+
+        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
+    }
+
+    fn op_xor32(&mut self, op: &Instruction) {
+        // two parameters (dst=reg)
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let res = dst ^ src;
+
+        // The OF and CF flags are cleared; the SF, ZF,
+        // and PF flags are set according to the result.
+        self.cpu.regs.flags.set_lazy(LazyOp::Logic, 32, res, 0, 0);
+
+        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u32);
+    }
+
+    fn op_unhandled(&mut self, op: &Instruction) {
+        let (seg, off) = self.cpu.get_address_pair();
+        warn!(target: "cpu", "execute error: unhandled '{}' at {:04X}:{:04X} (flat {:06X})",
+                 op,
+                 seg,
+                 off,
+                 self.cpu.get_address());
+    }
+}
+
+impl Machine {
+    /// writes the `width` bit operand `p`
+    fn write_sized(&mut self, op: &Instruction, p: &Parameter, width: u32, val: u64) {
+        match width {
+            8 => self.cpu.write_parameter_u8(&mut self.mmu, p, val as u8),
+            16 => self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, p, val as u16),
+            _ => self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, p, val as u32),
+        }
+    }
+
+    /// returns the segment and offset of the target of a far call or jmp
+    fn far_target(&self, op: &Instruction) -> (u16, u16) {
+        match op.params.dst {
+            Parameter::Ptr16Imm(seg, offs) => (seg, offs),
+            // to the offset and segment stored at mem
+            _ => match op.op_size {
+                OperandSize::_16bit => self.cpu.read_segment_selector(&self.mmu, &op.params.dst),
+                OperandSize::_32bit => self.cpu.read_segment_selector32(&self.mmu, &op.params.dst),
+            },
+        }
+    }
+
+    /// pops the ip and cs of retf and iret, as dwords with the 32-bit operand size
+    fn pop_far_return(&mut self, op: &Instruction) -> (u16, u16) {
+        match op.op_size {
+            OperandSize::_16bit => {
+                let ip = self.cpu.pop16(&mut self.mmu);
+                (ip, self.cpu.pop16(&mut self.mmu))
+            }
+            OperandSize::_32bit => {
+                let ip = self.cpu.pop32(&mut self.mmu) as u16;
+                (ip, self.cpu.pop32(&mut self.mmu) as u16)
+            }
+        }
+    }
+
+    /// raises #UD and returns false if the cpu lacks the 486 instruction `op`
+    fn require_486(&mut self, op: &Instruction) -> bool {
+        if self.cpu.model < CpuModel::I80486 {
+            self.cpu.exception(&mut self.mmu, &Exception::UD, op.length);
+            return false;
+        }
+        true
+    }
+
+    /// xadd: dst = dst + src and src = the old dst, flags as for add
+    fn exchange_add(&mut self, op: &Instruction, width: u32) {
+        if !self.require_486(op) {
+            return;
+        }
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let res = src + dst;
+        self.cpu.regs.flags.set_lazy(LazyOp::Add, width, res, src, dst);
+        self.write_sized(op, &op.params.src, width, dst);
+        self.write_sized(op, &op.params.dst, width, res);
+    }
+
+    /// cmpxchg: compares the accumulator with dst, flags as for cmp. if equal, src is
+    /// written to dst, else dst is loaded into the accumulator
+    fn compare_exchange(&mut self, op: &Instruction, width: u32) {
+        if !self.require_486(op) {
+            return;
+        }
+        let acc = match width {
+            8 => Parameter::Reg8(R::AL),
+            16 => Parameter::Reg16(R::AX),
+            _ => Parameter::Reg32(R::EAX),
+        };
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let val = self.cpu.read_parameter_value(&self.mmu, &acc);
+        let res = (Wrapping(val) - Wrapping(dst)).0;
+        self.cpu.regs.flags.set_lazy(LazyOp::Sub, width, res, dst, val);
+        if val == dst {
+            self.write_sized(op, &op.params.dst, width, src);
+        } else {
+            self.write_sized(op, &acc, width, dst);
+        }
+    }
+
+    /// bsf, bsr: the index of the lowest (or highest, if `reverse`) set bit of src.
+    /// dst is left unchanged and ZF set if src is 0
+    fn bit_scan(&mut self, op: &Instruction, width: u32, reverse: bool) {
+        let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        if src == 0 {
+            self.cpu.regs.flags.set_zero(true);
+            return;
+        }
+        let index = if reverse {
+            63 - src.leading_zeros()
+        } else {
+            src.trailing_zeros()
+        };
+        self.write_sized(op, &op.params.dst, width, u64::from(index));
+        self.cpu.regs.flags.set_zero(false);
+    }
+
+    /// bt, bts, btr, btc: copies the selected bit of dst to CF, then writes back
+    /// dst modified by `update`, if any. the bit offset is taken modulo `width`
+    fn bit_test(&mut self, op: &Instruction, width: u32, update: Option<fn(u64, u64) -> u64>) {
+        let base = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
+        let offset = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
+        let mask = 1 << (offset & u64::from(width - 1));
+        self.cpu.regs.flags.set_carry(base & mask != 0);
+        if let Some(update) = update {
+            self.write_sized(op, &op.params.dst, width, update(base, mask));
+        }
+    }
+
+    /// setcc: writes 1 to the byte operand if `cond` holds, else 0
+    fn set_condition(&mut self, op: &Instruction, cond: bool) {
+        self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, u8::from(cond));
+    }
+
+    /// steps SI or DI by `size` bytes in the direction given by DF.
+    /// ESI or EDI is stepped when the address size is 32-bit
+    fn step_string_reg(&mut self, op: &Instruction, r: R, size: u16) {
+        match op.address_size {
+            AddressSize::_16bit => {
+                let v = self.cpu.get_r16(r);
+                let v = if !self.cpu.regs.flags.direction {
+                    v.wrapping_add(size)
+                } else {
+                    v.wrapping_sub(size)
+                };
+                self.cpu.set_r16(r, v);
+            }
+            AddressSize::_32bit => {
+                let r = match r {
+                    R::SI => R::ESI,
+                    R::DI => R::EDI,
+                    _ => unreachable!(),
+                };
+                let v = self.cpu.get_r32(r);
+                let v = if !self.cpu.regs.flags.direction {
+                    v.wrapping_add(u32::from(size))
+                } else {
+                    v.wrapping_sub(u32::from(size))
+                };
+                self.cpu.set_r32(r, v);
+            }
+        }
+    }
+}
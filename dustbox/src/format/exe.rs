@@ -2,23 +2,97 @@
 /// http://www.delorie.com/djgpp/doc/rbinter/id/51/29.html
 
 use std::fmt;
+use std::ops::Range;
 
 use bincode::deserialize;
+use log::{debug, warn};
+
+#[cfg(test)]
+#[path = "./exe_test.rs"]
+mod exe_test;
 
 pub struct ExeFile {
     pub header: ExeHeader,
     pub relocs: Vec<ExeRelocation>,
     pub program_data: Vec<u8>,
 
+    /// file offsets of data past the load module, such as overlays or appended resources
+    pub overlay: Option<Range<usize>>,
+
+    /// new-format executable following the MZ stub
+    pub new_executable: Option<NewExecutable>,
+
     /// total .EXE file size
     exe_size: usize,
 }
 
+#[derive(Debug)]
 pub enum ParseError {
     WrongMagic,
 }
 
-const DEBUG_PARSER: bool = false;
+/// kind of a new-format executable header
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NewExecutableKind {
+    /// 16-bit Windows and OS/2
+    NE,
+
+    /// Windows VxD, and DOS extenders such as DOS/4GW
+    LE,
+
+    /// 32-bit OS/2, and DOS extenders
+    LX,
+
+    /// Win32
+    PE,
+}
+
+/// a new-format executable header, located by the offset at 3Ch in the MZ header
+#[derive(Debug, PartialEq)]
+pub struct NewExecutable {
+    pub kind: NewExecutableKind,
+
+    /// file offset of the header
+    pub offset: usize,
+}
+
+impl NewExecutable {
+    /// file offset of the new header offset in the MZ header
+    const OFFSET_FIELD: usize = 0x3C;
+
+    /// looks for a new-format header after the MZ stub
+    fn from_data(header: &ExeHeader, data: &[u8]) -> Option<Self> {
+        // the new header offset is only valid with a relocation table at 40h or above
+        if header.reloc_table_offset < 0x40 || data.len() < Self::OFFSET_FIELD + 4 {
+            return None;
+        }
+        let field = &data[Self::OFFSET_FIELD..Self::OFFSET_FIELD + 4];
+        let offset = u32::from_le_bytes([field[0], field[1], field[2], field[3]]) as usize;
+        if offset < Self::OFFSET_FIELD + 4 || offset.checked_add(4)? > data.len() {
+            return None;
+        }
+        let kind = match &data[offset..offset + 4] {
+            [b'P', b'E', 0, 0] => NewExecutableKind::PE,
+            [b'N', b'E', ..] => NewExecutableKind::NE,
+            [b'L', b'E', ..] => NewExecutableKind::LE,
+            [b'L', b'X', ..] => NewExecutableKind::LX,
+            _ => return None,
+        };
+        Some(NewExecutable { kind, offset })
+    }
+}
+
+impl fmt::Display for NewExecutable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let desc = match self.kind {
+            NewExecutableKind::NE => "NE (16-bit Windows / OS/2)",
+            NewExecutableKind::LE => "LE (VxD / DOS extender)",
+            NewExecutableKind::LX => "LX (OS/2 / DOS extender)",
+            NewExecutableKind::PE => "PE (Win32)",
+        };
+        write!(f, "{} header at {:08X}", desc, self.offset)
+    }
+}
 
 /// Header pages is 512 bytes
 /// also sometimes called blocks
@@ -32,17 +106,26 @@ impl ExeFile {
         };
 
         if header.exe_data_end_offset() > data.len() {
-            println!("WARNING: program end = {:04X} but data len = {:04X}", header.exe_data_end_offset(), data.len());
+            warn!(target: "dos", "program end = {:04X} but data len = {:04X}", header.exe_data_end_offset(), data.len());
         }
         let program_start = header.exe_data_start_offset();
         let program_data = data[program_start..data.len()].to_vec();
         let relocs = header.parse_relocations(data);
-        println!("  program start in exe: {:04X}", program_start);
+        debug!(target: "dos", "  program start in exe: {:04X}", program_start);
+        let program_end = header.exe_data_end_offset();
+        let overlay = if program_end < data.len() {
+            Some(program_end..data.len())
+        } else {
+            None
+        };
+        let new_executable = NewExecutable::from_data(&header, data);
 
         Ok(ExeFile {
             header,
             relocs,
             program_data,
+            overlay,
+            new_executable,
             exe_size: data.len(),
         })
     }
@@ -51,6 +134,15 @@ impl ExeFile {
         println!("exe file size: {} bytes", self.exe_size);
         self.header.print_details();
 
+        if let Some(overlay) = &self.overlay {
+            println!("- overlay data from {:04X} to {:04X} ({} bytes)", overlay.start, overlay.end, overlay.end - overlay.start);
+        }
+        match &self.new_executable {
+            Some(ne) => println!("new-format executable: {}", ne),
+            None if self.header.reloc_table_offset >= 0x40 => println!("new-format executable: unrecognized header"),
+            None => {}
+        }
+
         if self.header.relocations > 0 {
             println!("relocations:");
             for (i, reloc) in self.relocs.iter().enumerate() {
@@ -143,15 +235,11 @@ impl ExeHeader {
         let mut relocs = Vec::new();
 
         if self.relocations > 0 {
-            if DEBUG_PARSER {
-                println!("relocations ({}):", self.relocations);
-            }
+            debug!(target: "dos", "relocations ({}):", self.relocations);
             let mut offset = self.reloc_table_offset as usize;
             for i in 0..self.relocations {
                 let reloc: ExeRelocation = deserialize(&data[offset..offset+4]).unwrap();
-                if DEBUG_PARSER {
-                    println!("  {}: {:?}", i, reloc);
-                }
+                debug!(target: "dos", "  {}: {:?}", i, reloc);
                 relocs.push(reloc);
                 offset += 4;
             }
@@ -172,10 +260,6 @@ impl ExeHeader {
             println!("overlay number: {}", self.overlay_number);
         }
 
-        if self.reloc_table_offset >= 0x40 {
-            println!("ERROR: unhandled new-format (NE,LE,LX,W3,PE,etc.) executable");
-        }
-
         if self.relocations > 0 {
             let reloc_start = self.reloc_table_offset as usize;
             let reloc_end   = (reloc_start) + (self.relocations as usize * 4);
@@ -0,0 +1,50 @@
+use crate::format::{ExeFile, NewExecutable, NewExecutableKind};
+
+/// returns a MZ header of `header_paragraphs`, with a load module ending at `end`
+fn exe_header(end: u16, relocations: u16, header_paragraphs: u16, reloc_table_offset: u16) -> Vec<u8> {
+    let fields = [
+        end % 512, end.div_ceil(512), relocations, header_paragraphs,
+        0, 0xFFFF, 0, 0x100, 0, 0, 0, reloc_table_offset, 0,
+    ];
+    let mut data = b"MZ".to_vec();
+    for field in &fields {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data.resize(header_paragraphs as usize * 16, 0);
+    data
+}
+
+#[test]
+fn can_parse_relocations_and_overlay() {
+    let mut data = exe_header(0x50, 2, 4, 0x1C);
+    data[0x1C..0x24].copy_from_slice(&[0x01, 0x00, 0x00, 0x00, 0x10, 0x00, 0x02, 0x00]);
+    data.extend_from_slice(&[0x90; 0x10]);
+    data.extend_from_slice(b"overlay!");
+
+    let exe = ExeFile::from_data(&data).unwrap();
+    assert_eq!(2, exe.relocs.len());
+    assert_eq!("0000:0001", format!("{}", exe.relocs[0]));
+    assert_eq!("0002:0010", format!("{}", exe.relocs[1]));
+    assert_eq!(Some(0x50..0x58), exe.overlay);
+    assert_eq!(None, exe.new_executable);
+}
+
+#[test]
+fn can_detect_new_executable() {
+    let mut data = exe_header(0x84, 0, 4, 0x40);
+    data[0x3C] = 0x80;
+    data.resize(0x80, 0);
+    data.extend_from_slice(b"PE\0\0");
+
+    let exe = ExeFile::from_data(&data).unwrap();
+    assert_eq!(None, exe.overlay);
+    assert_eq!(Some(NewExecutable{kind: NewExecutableKind::PE, offset: 0x80}), exe.new_executable);
+    assert_eq!("PE (Win32) header at 00000080", format!("{}", exe.new_executable.unwrap()));
+
+    // the offset at 3Ch is not used by old-format executables
+    let mut data = exe_header(0x84, 0, 4, 0x1C);
+    data[0x3C] = 0x80;
+    data.resize(0x80, 0);
+    data.extend_from_slice(b"LE\0\0");
+    assert_eq!(None, ExeFile::from_data(&data).unwrap().new_executable);
+}
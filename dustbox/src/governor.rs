@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+#[cfg(test)]
+#[path = "./governor_test.rs"]
+mod governor_test;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// paces the emulated cpu against host time, by handing out a budget of cycles to execute
+#[derive(Clone, Debug)]
+pub struct Governor {
+    /// emulated speed in percent of the cpu clock, 100 is authentic speed
+    percent: u32,
+
+    /// if set, the emulation runs as fast as the host allows
+    unlimited: bool,
+
+    /// cycles left to execute. negative when the last instruction overshot the budget
+    budget: i64,
+
+    /// fraction of a cycle carried between calls to `add_time`, so that rounding doesn't drift
+    remainder: u128,
+}
+
+impl Default for Governor {
+    fn default() -> Self {
+        Governor {
+            percent: 100,
+            unlimited: false,
+            budget: 0,
+            remainder: 0,
+        }
+    }
+}
+
+impl Governor {
+    /// sets the emulated speed in percent of the cpu clock, and leaves unlimited mode
+    pub fn set_speed_percent(&mut self, percent: u32) {
+        self.percent = percent.max(1);
+        self.unlimited = false;
+    }
+
+    pub fn speed_percent(&self) -> u32 {
+        self.percent
+    }
+
+    /// enables or disables running as fast as the host allows
+    pub fn set_unlimited(&mut self, unlimited: bool) {
+        self.unlimited = unlimited;
+        self.reset();
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.unlimited
+    }
+
+    /// adds the cycles a cpu running at `clock_hz` executes in `elapsed` host time to the budget
+    pub fn add_time(&mut self, elapsed: Duration, clock_hz: usize) {
+        let scaled = clock_hz as u128 * u128::from(self.percent) * elapsed.as_nanos() + self.remainder;
+        let divisor = 100 * NANOS_PER_SEC;
+        self.budget += (scaled / divisor) as i64;
+        self.remainder = scaled % divisor;
+    }
+
+    /// returns the number of cycles left to execute
+    pub fn budget(&self) -> i64 {
+        self.budget
+    }
+
+    /// subtracts `cycles` executed cycles from the budget
+    pub fn spend(&mut self, cycles: usize) {
+        self.budget -= cycles as i64;
+    }
+
+    /// forgets the budget, such as when resuming from pause
+    pub fn reset(&mut self) {
+        self.budget = 0;
+        self.remainder = 0;
+    }
+}
@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use crate::governor::Governor;
+use crate::machine::Machine;
+
+#[test]
+fn can_budget_cycles() {
+    let mut governor = Governor::default();
+    governor.add_time(Duration::from_millis(10), 5_000_000);
+    assert_eq!(50_000, governor.budget());
+
+    governor.spend(50_003);
+    assert_eq!(-3, governor.budget());
+
+    governor.set_speed_percent(50);
+    governor.add_time(Duration::from_millis(10), 5_000_000);
+    assert_eq!(24_997, governor.budget());
+}
+
+#[test]
+fn carries_fractional_cycles() {
+    let mut governor = Governor::default();
+    // 1 Hz clock gives a third of a cycle each call
+    for _ in 0..3 {
+        governor.add_time(Duration::from_millis(333), 1);
+    }
+    assert_eq!(0, governor.budget());
+    governor.add_time(Duration::from_millis(1), 1);
+    assert_eq!(1, governor.budget());
+}
+
+#[test]
+fn can_execute_for_host_time() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xEB, 0xFE,         // jmp short 0x100
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.cpu.clock_hz = 1_000_000;
+
+    let cycles = machine.execute_for(Duration::from_millis(1));
+    assert!(cycles >= 1_000, "{}", cycles);
+    let half = {
+        machine.set_speed_percent(50);
+        machine.execute_for(Duration::from_millis(1))
+    };
+    assert!(half < cycles, "{} < {}", half, cycles);
+
+    machine.set_unlimited(true);
+    assert!(machine.governor.is_unlimited());
+    assert!(machine.execute_for(Duration::from_millis(5)) > 0);
+}
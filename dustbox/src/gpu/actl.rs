@@ -0,0 +1,188 @@
+// EGA/VGA attribute controller, maps the 4-bit colors of text and 16 color
+// modes through the palette registers to an index in the DAC
+// http://www.osdever.net/FreeVGA/vga/attrreg.htm
+
+use log::{trace, warn};
+
+/// number of attribute controller registers, 00h-14h
+pub const ACTL_REGISTERS: usize = 0x15;
+
+/// default palette registers, the 16 text attribute colors in the 64 color EGA palette
+pub const DEFAULT_ACTL_PALETTE: [u8; 16] = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x14, 0x07, 0x38, 0x39, 0x3A, 0x3B, 0x3C, 0x3D, 0x3E, 0x3F];
+
+/// mode control register: graphics mode
+pub const MODE_GRAPHICS: u8 = 0x01;
+
+/// mode control register: line graphics characters repeat their 8th column in the 9th
+pub const MODE_LINE_GRAPHICS: u8 = 0x04;
+
+/// mode control register: attribute bit 7 blinks instead of selecting background intensity
+pub const MODE_BLINK: u8 = 0x08;
+
+/// mode control register: 8-bit color, used by mode 13h
+pub const MODE_8BIT_COLOR: u8 = 0x40;
+
+/// mode control register: bits 5-4 of the color come from the color select register
+pub const MODE_P54S: u8 = 0x80;
+
+#[derive(Clone, PartialEq)]
+pub struct ACTL {
+    /// registers 00h-0Fh
+    pub palette: [u8; 16],
+
+    /// register 10h
+    pub mode_control: u8,
+
+    /// register 11h, border color
+    pub overscan: u8,
+
+    /// register 12h, masks the 4 bits of each color
+    pub color_plane_enable: u8,
+
+    /// register 13h
+    pub horizontal_pel_panning: u8,
+
+    /// register 14h, bits 7-4 of the DAC index
+    pub color_select: u8,
+
+    pub index: u8,
+
+    /// palette address source (bit 5 of the index). when clear, the palette registers
+    /// can be written and the screen is blanked
+    pub palette_address_source: bool,
+
+    /// set when the next write to 03C0 is a data write, reset by reading 03DA
+    pub flip_flop: bool,
+}
+
+impl Default for ACTL {
+    fn default() -> Self {
+        ACTL {
+            palette: DEFAULT_ACTL_PALETTE,
+            mode_control: MODE_LINE_GRAPHICS | MODE_BLINK,
+            overscan: 0,
+            color_plane_enable: 0x0F,
+            horizontal_pel_panning: 0,
+            color_select: 0,
+            index: 0,
+            palette_address_source: true,
+            flip_flop: false,
+        }
+    }
+}
+
+impl ACTL {
+    /// returns the attribute controller as set by the BIOS for a mode.
+    /// `graphics` is set for graphics modes, `vga_256` for mode 13h
+    pub fn for_mode(graphics: bool, vga_256: bool) -> Self {
+        let mut actl = ACTL::default();
+        if vga_256 {
+            for (i, v) in actl.palette.iter_mut().enumerate() {
+                *v = i as u8;
+            }
+            actl.mode_control = MODE_GRAPHICS | MODE_8BIT_COLOR;
+        } else if graphics {
+            actl.mode_control = MODE_GRAPHICS;
+        }
+        actl
+    }
+
+    /// (EGA/VGA) attribute controller index and data register (0x03C0).
+    /// writes alternate between the index and the data of the selected register
+    pub fn write(&mut self, data: u8) {
+        if self.flip_flop {
+            self.write_register(self.index, data);
+        } else {
+            self.index = data & 0x1F;
+            self.palette_address_source = data & 0x20 != 0;
+        }
+        self.flip_flop = !self.flip_flop;
+    }
+
+    /// (VGA) reads the attribute controller index (0x03C0)
+    pub fn read_index(&self) -> u8 {
+        self.index | if self.palette_address_source { 0x20 } else { 0 }
+    }
+
+    /// (VGA) reads the selected attribute controller register (0x03C1)
+    pub fn read_data(&self) -> u8 {
+        self.read_register(self.index)
+    }
+
+    /// makes the next write to 0x03C0 an index write, done by reading the input status register
+    pub fn reset_flip_flop(&mut self) {
+        self.flip_flop = false;
+    }
+
+    pub fn write_register(&mut self, index: u8, data: u8) {
+        trace!(target: "gpu", "ACTL write {:02X} = {:02X}", index, data);
+        match index {
+            0x00..=0x0F => self.palette[index as usize] = data & 0x3F,
+            0x10 => self.mode_control = data,
+            0x11 => self.overscan = data,
+            0x12 => self.color_plane_enable = data & 0x0F,
+            0x13 => self.horizontal_pel_panning = data & 0x0F,
+            0x14 => self.color_select = data & 0x0F,
+            _ => warn!(target: "gpu", "ACTL write to unknown register {:02X} = {:02X}", index, data),
+        }
+    }
+
+    pub fn read_register(&self, index: u8) -> u8 {
+        match index {
+            0x00..=0x0F => self.palette[index as usize],
+            0x10 => self.mode_control,
+            0x11 => self.overscan,
+            0x12 => self.color_plane_enable,
+            0x13 => self.horizontal_pel_panning,
+            0x14 => self.color_select,
+            _ => 0,
+        }
+    }
+
+    /// returns registers 00h-14h
+    pub fn registers(&self) -> Vec<u8> {
+        (0..ACTL_REGISTERS as u8).map(|i| self.read_register(i)).collect()
+    }
+
+    /// writes registers 00h-14h from `regs`
+    pub fn set_registers(&mut self, regs: &[u8]) {
+        for (i, v) in regs.iter().take(ACTL_REGISTERS).enumerate() {
+            self.write_register(i as u8, *v);
+        }
+    }
+
+    /// returns the DAC index displayed for the 4-bit `color`
+    pub fn dac_index(&self, color: u8) -> u8 {
+        let entry = self.palette[(color & self.color_plane_enable & 0x0F) as usize];
+        let low = if self.mode_control & MODE_P54S != 0 {
+            (entry & 0x0F) | (self.color_select & 0x03) << 4
+        } else {
+            entry & 0x3F
+        };
+        low | (self.color_select & 0x0C) << 4
+    }
+
+    /// returns the DAC index of each of the 16 colors
+    pub fn color_map(&self) -> [u8; 16] {
+        let mut map = [0; 16];
+        for (i, v) in map.iter_mut().enumerate() {
+            *v = self.dac_index(i as u8);
+        }
+        map
+    }
+
+    /// returns true if attribute bit 7 blinks, else it selects background intensity
+    pub fn blink(&self) -> bool {
+        self.mode_control & MODE_BLINK != 0
+    }
+
+    /// returns true if line graphics characters repeat their 8th column in the 9th
+    pub fn line_graphics(&self) -> bool {
+        self.mode_control & MODE_LINE_GRAPHICS != 0
+    }
+
+    /// returns false while the palette registers are being written and the screen is blanked
+    pub fn display_enabled(&self) -> bool {
+        self.palette_address_source
+    }
+}
@@ -0,0 +1,90 @@
+use log::{debug, warn};
+
+use crate::gpu::font;
+
+/// number of character blocks available (VGA)
+pub const CHARGEN_BLOCKS: usize = 8;
+
+/// each character occupies 32 bytes of plane 2, regardless of character height
+const CHAR_SIZE: usize = 32;
+
+const BLOCK_SIZE: usize = 256 * CHAR_SIZE;
+
+/// EGA/VGA character generator RAM (plane 2)
+#[derive(Clone)]
+pub struct CharGen {
+    ram: Vec<u8>,
+
+    /// block used for characters with attribute bit 3 clear (character map select A)
+    pub block_a: u8,
+
+    /// block used for characters with attribute bit 3 set (character map select B)
+    pub block_b: u8,
+
+    /// incremented when glyphs are loaded
+    pub generation: u64,
+}
+
+impl Default for CharGen {
+    fn default() -> Self {
+        CharGen {
+            ram: vec![0; CHARGEN_BLOCKS * BLOCK_SIZE],
+            block_a: 0,
+            block_b: 0,
+            generation: 0,
+        }
+    }
+}
+
+impl CharGen {
+    /// loads `count` characters of `height` bytes each from `data`, starting at character `first` of `block`
+    pub fn load(&mut self, block: u8, first: u16, count: u16, height: u8, data: &[u8]) {
+        debug!(target: "gpu", "chargen: load block {}, first {:02X}, count {}, height {}", block, first, count, height);
+        self.generation += 1;
+        let base = (block as usize % CHARGEN_BLOCKS) * BLOCK_SIZE;
+        let height = height as usize;
+        for i in 0..count as usize {
+            let chr = first as usize + i;
+            if chr > 0xFF {
+                break;
+            }
+            let dst = base + chr * CHAR_SIZE;
+            for row in 0..CHAR_SIZE {
+                self.ram[dst + row] = if row < height {
+                    match data.get(i * height + row) {
+                        Some(b) => *b,
+                        None => 0,
+                    }
+                } else {
+                    0
+                };
+            }
+        }
+    }
+
+    /// loads one of the ROM fonts with given character height (8, 14 or 16) into `block`
+    pub fn load_rom_font(&mut self, block: u8, height: u8) {
+        match height {
+            8 => self.load(block, 0, 256, 8, &font::FONT_08),
+            14 => self.load(block, 0, 256, 14, &font::FONT_14),
+            16 => self.load(block, 0, 256, 16, &font::FONT_16),
+            _ => warn!(target: "gpu", "chargen error: no rom font of height {}", height),
+        }
+    }
+
+    /// character map select register (sequencer register 3)
+    pub fn set_map_select(&mut self, val: u8) {
+        self.block_a = ((val >> 2) & 3) | ((val >> 3) & 4);
+        self.block_b = (val & 3) | ((val >> 2) & 4);
+    }
+
+    /// returns one row of the glyph for `chr` using the block selected by `attr`
+    pub fn glyph_row(&self, chr: u8, attr: u8, row: usize) -> u8 {
+        let block = if attr & 0x08 != 0 {
+            self.block_b
+        } else {
+            self.block_a
+        };
+        self.ram[(block as usize % CHARGEN_BLOCKS) * BLOCK_SIZE + chr as usize * CHAR_SIZE + (row % CHAR_SIZE)]
+    }
+}
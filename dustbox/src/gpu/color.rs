@@ -0,0 +1,117 @@
+// post-processing of rendered frames, simulating the monitors of the period
+
+#[cfg(test)]
+#[path = "./color_test.rs"]
+mod color_test;
+
+/// the simulated monitor
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Monitor {
+    /// colors as rendered
+    #[default]
+    Color,
+
+    /// NTSC composite monitor, the CGA graphics modes show artifact colors
+    Composite,
+
+    /// monochrome monitors with amber, green or white phosphor
+    Amber,
+    Green,
+    White,
+}
+
+/// artifact colors of a CGA composite monitor, indexed by a group of 4 pixels in the
+/// 640x200 signal, leftmost pixel in bit 3
+pub const CGA_COMPOSITE_PALETTE: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00), (0x00, 0x6E, 0x31), (0x31, 0x09, 0xFF), (0x00, 0x8A, 0xFF),
+    (0xA7, 0x00, 0x31), (0x76, 0x76, 0x76), (0xEC, 0x11, 0xFF), (0xBB, 0x92, 0xFF),
+    (0x31, 0x5A, 0x00), (0x00, 0xDB, 0x00), (0x76, 0x76, 0x76), (0x45, 0xF7, 0xBB),
+    (0xEC, 0x63, 0x00), (0xBB, 0xE5, 0x00), (0xFF, 0x81, 0xBB), (0xFF, 0xFF, 0xFF),
+];
+
+/// color transform applied to rendered frames
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorEmulation {
+    pub monitor: Monitor,
+
+    /// added to each color component, in percent of full intensity (-100 to 100)
+    pub brightness: i16,
+
+    /// scales the distance of each color component from mid gray, in percent (0 to 400)
+    pub contrast: u16,
+}
+
+impl Default for ColorEmulation {
+    fn default() -> Self {
+        ColorEmulation {
+            monitor: Monitor::Color,
+            brightness: 0,
+            contrast: 100,
+        }
+    }
+}
+
+impl ColorEmulation {
+    /// returns an error message if brightness or contrast is out of range
+    pub fn validate(&self) -> Result<(), String> {
+        if !(-100..=100).contains(&self.brightness) {
+            return Err(format!("brightness {} is out of range -100 to 100", self.brightness));
+        }
+        if self.contrast > 400 {
+            return Err(format!("contrast {} is out of range 0 to 400", self.contrast));
+        }
+        Ok(())
+    }
+
+    /// returns true if rendered pixels are shown as is
+    pub fn is_identity(&self) -> bool {
+        self.brightness == 0 && self.contrast == 100 && self.tint().is_none()
+    }
+
+    /// returns the phosphor color of monochrome monitors
+    fn tint(&self) -> Option<(u8, u8, u8)> {
+        match self.monitor {
+            Monitor::Amber => Some((0xFF, 0xB0, 0x00)),
+            Monitor::Green => Some((0x33, 0xFF, 0x33)),
+            Monitor::White => Some((0xFF, 0xFF, 0xFF)),
+            Monitor::Color | Monitor::Composite => None,
+        }
+    }
+
+    /// returns the color displayed for the rendered color `rgb`
+    pub fn apply(&self, rgb: (u8, u8, u8)) -> (u8, u8, u8) {
+        let (r, g, b) = match self.tint() {
+            Some((tr, tg, tb)) => {
+                // ITU-R BT.601 luma
+                let luma = (299 * u32::from(rgb.0) + 587 * u32::from(rgb.1) + 114 * u32::from(rgb.2)) / 1000;
+                let shade = |c: u8| (u32::from(c) * luma / 255) as u8;
+                (shade(tr), shade(tg), shade(tb))
+            }
+            None => rgb,
+        };
+        let adjust = |c: u8| {
+            let v = (i32::from(c) - 128) * i32::from(self.contrast) / 100 + 128 + i32::from(self.brightness) * 255 / 100;
+            v.clamp(0, 255) as u8
+        };
+        (adjust(r), adjust(g), adjust(b))
+    }
+
+    /// applies the transform to `width` RGBA pixels of each of the `rows` rows of `buf`,
+    /// where rows are `pitch` bytes apart
+    pub fn apply_rgba(&self, buf: &mut [u8], width: usize, rows: usize, pitch: usize) {
+        if self.is_identity() {
+            return;
+        }
+        for row in 0..rows {
+            let start = row * pitch;
+            for px in buf[start..start + width * 4].chunks_exact_mut(4) {
+                let (r, g, b) = self.apply((px[0], px[1], px[2]));
+                px[0] = r;
+                px[1] = g;
+                px[2] = b;
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use crate::gpu::{ColorEmulation, Monitor};
+use crate::machine::Machine;
+
+#[test]
+fn can_leave_colors_unchanged() {
+    let color = ColorEmulation::default();
+    assert!(color.is_identity());
+    assert_eq!((0x12, 0x80, 0xFF), color.apply((0x12, 0x80, 0xFF)));
+}
+
+#[test]
+fn can_emulate_monochrome_monitors() {
+    let amber = ColorEmulation { monitor: Monitor::Amber, ..ColorEmulation::default() };
+    assert_eq!((0xFF, 0xB0, 0x00), amber.apply((0xFF, 0xFF, 0xFF)));
+    assert_eq!((0x00, 0x00, 0x00), amber.apply((0x00, 0x00, 0x00)));
+
+    let green = ColorEmulation { monitor: Monitor::Green, ..ColorEmulation::default() };
+    let (r, g, b) = green.apply((0xFF, 0x00, 0x00));
+    assert_eq!(r, b);
+    assert!(g > r && g < 0xFF);
+}
+
+#[test]
+fn can_adjust_brightness_and_contrast() {
+    let color = ColorEmulation { brightness: 10, ..ColorEmulation::default() };
+    assert_eq!((25, 153, 255), color.apply((0, 128, 250)));
+
+    let color = ColorEmulation { contrast: 200, ..ColorEmulation::default() };
+    assert_eq!((0, 128, 255), color.apply((32, 128, 200)));
+    assert!(ColorEmulation { contrast: 500, ..ColorEmulation::default() }.validate().is_err());
+}
+
+#[test]
+fn can_render_cga_composite_colors() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB8, 0x06, 0x00,   // mov ax,0x6
+        0xCD, 0x10,         // int 0x10
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x06, machine.gpu().mode.mode);
+
+    // pixel patterns 0101 and 1111
+    machine.mmu.write_u8(0xB800, 0x0000, 0x5F);
+
+    let frame = machine.gpu().render_frame(&machine.mmu);
+    let pal = machine.gpu().dac.frame_palette().to_vec();
+    assert_eq!(pal[0], frame.pixel(0, 0));
+    assert_eq!(pal[15], frame.pixel(1, 0));
+
+    machine.gpu_mut().color.monitor = Monitor::Composite;
+    let frame = machine.gpu().render_frame(&machine.mmu);
+    let artifact = frame.pixel(0, 0);
+    assert_eq!(artifact, frame.pixel(3, 0));
+    assert_ne!(artifact, frame.pixel(4, 0));
+    assert_eq!(frame.pixel(4, 0), frame.pixel(7, 0));
+    assert_eq!(pal[0], frame.pixel(8, 0));
+}
@@ -1,6 +1,9 @@
-const DEBUG_CRTC: bool = false;
+use log::{trace, warn};
 
-#[derive(Clone, Default)]
+/// number of CRTC registers, 00h-18h
+pub const CRTC_REGISTERS: usize = 0x19;
+
+#[derive(Clone)]
 pub struct CRTC {
     horizontal_total: u8,
     horizontal_display_end: u8,
@@ -32,17 +35,50 @@ pub struct CRTC {
     read_only: bool,
 }
 
+impl Default for CRTC {
+    fn default() -> Self {
+        CRTC {
+            horizontal_total: 0,
+            horizontal_display_end: 0,
+            start_horizontal_blanking: 0,
+            end_horizontal_blanking: 0,
+            start_horizontal_retrace: 0,
+            end_horizontal_retrace: 0,
+            vertical_total: 0,
+            overflow: 0x10,
+            preset_row_scan: 0,
+            maximum_scan_line: 0x40,
+            cursor_start: 0,
+            cursor_end: 0,
+            start_address_high: 0,
+            start_address_low: 0,
+            cursor_location_high: 0,
+            cursor_location_low: 0,
+            vertical_retrace_start: 0,
+            vertical_retrace_end: 0,
+            vertical_display_end: 0,
+            offset: 0,
+            underline_location: 0,
+            start_vertical_blanking: 0,
+            end_vertical_blanking: 0,
+            mode_control: 0,
+            // line compare 3FFh, no split screen
+            line_compare: 0xFF,
+            index: 0,
+            read_only: false,
+        }
+    }
+}
+
 impl CRTC {
     // 03D4  rW  CRT (6845) register index   (CGA/MCGA/color EGA/color VGA)
     // selects which register (0-11h) is to be accessed through 03D5
     // bit 7-6 =0: (VGA) reserved
     // bit 5   =0: (VGA) reserved for testage
     // bit 4-0   : selects which register is to be accessed through 03D5
+    // the full byte is kept, so that the SVGA registers above 18h don't alias the VGA ones
     pub fn set_index(&mut self, data: u8) {
-        if DEBUG_CRTC {
-            // println!("CRTC set_index {}", data & 0x1F);
-        }
-        self.index = data & 0x1F;
+        self.index = data;
     }
 
     // 03D5  -W  CRT (6845) data register   (CGA/MCGA/color EGA/color VGA) (see #P0708)
@@ -53,9 +89,7 @@ impl CRTC {
     // registers 32h-37h see PORT 03B5h (see #P0654)
     // registers 10h-11h on CGA, EGA, VGA and 12h-14h on EGA, VGA are conflictive with MCGA (see #P0710)
     pub fn write_current(&mut self, data: u8) {
-        if DEBUG_CRTC {
-            println!("CRTC write_current {:02X} = {:02X}", self.index, data);
-        }
+        trace!(target: "gpu", "CRTC write_current {:02X} = {:02X}", self.index, data);
         match self.index {
             0x00 => self.horizontal_total = data,
             0x01 => self.horizontal_display_end = data,
@@ -82,7 +116,53 @@ impl CRTC {
             0x16 => self.end_vertical_blanking = data,
             0x17 => self.mode_control = data,
             0x18 => self.line_compare = data,
-            _ => panic!(),
+            _ => warn!(target: "gpu", "CRTC: write to unknown register {:02X} = {:02X}", self.index, data),
+        }
+    }
+
+    /// returns the value of the register selected by `index`, 0 for unknown registers
+    pub fn read_current(&self) -> u8 {
+        self.registers().get(self.index as usize).cloned().unwrap_or(0)
+    }
+
+    /// returns registers 00h-18h, as saved by INT 10h AX=1C01h
+    pub fn registers(&self) -> [u8; CRTC_REGISTERS] {
+        [
+            self.horizontal_total, self.horizontal_display_end, self.start_horizontal_blanking,
+            self.end_horizontal_blanking, self.start_horizontal_retrace, self.end_horizontal_retrace,
+            self.vertical_total, self.overflow, self.preset_row_scan, self.maximum_scan_line,
+            self.cursor_start, self.cursor_end, self.start_address_high, self.start_address_low,
+            self.cursor_location_high, self.cursor_location_low, self.vertical_retrace_start,
+            self.vertical_retrace_end, self.vertical_display_end, self.offset, self.underline_location,
+            self.start_vertical_blanking, self.end_vertical_blanking, self.mode_control, self.line_compare,
+        ]
+    }
+
+    /// writes registers 00h-18h from `regs`, keeping the selected index
+    pub fn set_registers(&mut self, regs: &[u8]) {
+        let index = self.index;
+        for (i, v) in regs.iter().take(CRTC_REGISTERS).enumerate() {
+            self.index = i as u8;
+            self.write_current(*v);
         }
+        self.index = index;
+    }
+
+    /// returns the display start address (registers 0Ch and 0Dh)
+    pub fn start_address(&self) -> u16 {
+        u16::from(self.start_address_high) << 8 | u16::from(self.start_address_low)
+    }
+
+    /// returns the scanline after which the display restarts at address 0 (register 18h,
+    /// bit 8 in bit 4 of the overflow register and bit 9 in bit 6 of the maximum scan line register)
+    pub fn line_compare(&self) -> u16 {
+        u16::from(self.line_compare)
+            | u16::from(self.overflow & 0x10) << 4
+            | u16::from(self.maximum_scan_line & 0x40) << 3
+    }
+
+    /// returns the text mode cursor location (registers 0Eh and 0Fh)
+    pub fn cursor_location(&self) -> u16 {
+        u16::from(self.cursor_location_high) << 8 | u16::from(self.cursor_location_low)
     }
 }
@@ -1,8 +1,8 @@
+use log::trace;
+
 use crate::gpu::palette::{ColorSpace, text_palette};
 use crate::gpu::palette::ColorSpace::RGB;
 
-const DEBUG_DAC: bool = false;
-
 #[derive(Clone)]
 pub struct DAC {
     /// DAC bits, usually 6 or 8
@@ -30,6 +30,25 @@ pub struct DAC {
     pub hidac_counter: u8,
 
     reg02: u8,
+
+    /// set when the palette was written to since the last latch
+    dirty: bool,
+
+    /// palette as latched at the start of the current frame
+    frame_pal: Vec<ColorSpace>,
+
+    /// palette changes recorded by scanline during the frame in progress
+    pending: Vec<ScanlinePalette>,
+
+    /// palette changes recorded by scanline during the last complete frame
+    latched: Vec<ScanlinePalette>,
+}
+
+/// palette in effect from `scanline` until the next recorded change
+#[derive(Clone, PartialEq)]
+pub struct ScanlinePalette {
+    pub scanline: u32,
+    pub pal: Vec<ColorSpace>,
 }
 
 impl Default for DAC {
@@ -46,6 +65,10 @@ impl Default for DAC {
             pal: text_palette().to_vec(),
             hidac_counter: 0,
             reg02: 0,
+            dirty: false,
+            frame_pal: text_palette().to_vec(),
+            pending: Vec::new(),
+            latched: Vec::new(),
         }
     }
 }
@@ -55,9 +78,7 @@ impl DAC {
     pub fn get_state(&mut self) -> u8 {
         self.hidac_counter = 0;
         let res = self.state.register();
-        if DEBUG_DAC {
-            println!("read port 03C7: get_state = {:02X}", res);
-        }
+        trace!(target: "gpu", "read port 03C7: get_state = {:02X}", res);
         res
     }
 
@@ -77,17 +98,13 @@ impl DAC {
         self.write_index = val + 1;
         self.pel_index = 0;
         self.hidac_counter = 0;
-        if DEBUG_DAC {
-            println!("write port 03C7: set_pel_read_index = {:02X}", val);
-        }
+        trace!(target: "gpu", "write port 03C7: set_pel_read_index = {:02X}", val);
     }
 
     /// (VGA,MCGA) PEL address register (0x03C8)
     pub fn get_pel_write_index(&mut self) -> u8 {
         self.hidac_counter = 0;
-        if DEBUG_DAC {
-            println!("read port 03C8: get_pel_write_index = {:02X}", self.write_index);
-        }
+        trace!(target: "gpu", "read port 03C8: get_pel_write_index = {:02X}", self.write_index);
         self.write_index
     }
 
@@ -100,9 +117,7 @@ impl DAC {
         self.write_index = val;
         self.pel_index = 0;
         self.hidac_counter = 0;
-        if DEBUG_DAC {
-            println!("write port 03C8: set_pel_write_index = {:02X}", val);
-        }
+        trace!(target: "gpu", "write port 03C8: set_pel_write_index = {:02X}", val);
     }
 
     /// (VGA,MCGA) PEL data register (0x03C9)
@@ -131,9 +146,7 @@ impl DAC {
             }
             _ => unreachable!(),
         };
-        if DEBUG_DAC {
-            println!("read port 03C9: get_pel_data = {:02X}", ret);
-        }
+        trace!(target: "gpu", "read port 03C9: get_pel_data = {:02X}", ret);
         ret
     }
 
@@ -142,13 +155,12 @@ impl DAC {
     /// The internal DAC index is incremented on every 3rd access.
     pub fn set_pel_data(&mut self, mut val: u8) {
         val &= 0x3F;
-        if DEBUG_DAC {
-            println!("write port 03C9: set_pel_data = write index {:02X}, pel index {:02X} = {:02X}", self.write_index, self.pel_index, val);
-        }
+        trace!(target: "gpu", "write port 03C9: set_pel_data = write index {:02X}, pel index {:02X} = {:02X}", self.write_index, self.pel_index, val);
         // scale 6-bit color into 8 bits
         val <<= 2;
 
         self.hidac_counter = 0;
+        self.dirty = true;
         if let RGB(ref mut r, ref mut g, ref mut b) = self.pal[self.write_index as usize] {
             match self.pel_index {
                 0 => *r = val,
@@ -170,6 +182,53 @@ impl DAC {
             self.pel_index = 0;
         }
     }
+
+    /// called when the beam moved to `scanline`. records the current palette
+    /// if it was changed during the previous scanline
+    pub fn latch_scanline(&mut self, scanline: u32) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+        trace!(target: "gpu", "dac: palette changed before scanline {}", scanline);
+        self.pending.push(ScanlinePalette{scanline, pal: self.pal.clone()});
+    }
+
+    /// called on vertical retrace. the palette changes of the finished frame
+    /// are kept for rendering and the current palette is latched for the next frame
+    pub fn latch_frame(&mut self) {
+        self.dirty = false;
+        let mut frame = vec!(ScanlinePalette{scanline: 0, pal: self.frame_pal.clone()});
+        frame.append(&mut self.pending);
+        self.latched = frame;
+        self.frame_pal = self.pal.clone();
+    }
+
+    /// drops all latched state and uses the current palette directly, used on mode changes
+    pub fn reset_latch(&mut self) {
+        self.dirty = false;
+        self.pending.clear();
+        self.latched.clear();
+        self.frame_pal = self.pal.clone();
+    }
+
+    /// returns the palette latched at the start of the current frame
+    pub fn frame_palette(&self) -> &[ColorSpace] {
+        &self.frame_pal
+    }
+
+    /// returns the palette changes by scanline of the last complete frame
+    pub fn latched_palettes(&self) -> &[ScanlinePalette] {
+        &self.latched
+    }
+
+    /// returns the palette that was in effect for `scanline` during the last complete frame
+    pub fn scanline_palette(&self, scanline: u32) -> &[ColorSpace] {
+        match self.latched.iter().rev().find(|p| p.scanline <= scanline) {
+            Some(p) => &p.pal,
+            None => &self.frame_pal,
+        }
+    }
 }
 
 #[derive(Clone, PartialEq)]
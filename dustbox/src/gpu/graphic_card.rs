@@ -1,5 +1,10 @@
+#[cfg(test)]
+#[path = "./graphic_card_test.rs"]
+mod graphic_card_test;
+
 /// GraphicCard indicates the gfx card generation to emulate
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum GraphicCard {
     CGA, EGA, VGA, Tandy, PcJr,
 }
@@ -42,3 +47,185 @@ impl GraphicCard {
         }
     }
 }
+
+/// SVGA chipset emulated on top of VGA. programs detect the card by its extended registers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SvgaChipset {
+    /// plain VGA without extended registers
+    #[default]
+    None,
+
+    /// Tseng Labs ET4000
+    ET4000,
+
+    /// S3 Trio64
+    S3Trio,
+}
+
+/// size of the video memory of the SVGA chipsets
+pub const SVGA_MEMORY_SIZE: usize = 1024 * 1024;
+
+/// size of a bank of video memory, mapped at A000:0000
+pub const SVGA_BANK_SIZE: usize = 0x1_0000;
+
+/// state of the extended registers of a SVGA chipset, used for detection and bank switching
+#[derive(Clone)]
+pub struct Svga {
+    pub chipset: SvgaChipset,
+
+    /// CRTC index as written, including the extended registers above 18h
+    crtc_index: u8,
+
+    /// extended CRTC registers, at their index
+    crtc: Vec<u8>,
+
+    /// ET4000: the "key" sequence writing 03h to 03BFh and A0h to 03D8h enables the extensions
+    et4000_hercules: u8,
+    et4000_extensions: bool,
+
+    /// ET4000: segment select register (03CDh), bits 0-3 write bank, bits 4-7 read bank
+    et4000_segment: u8,
+
+    /// S3: bank for reads and writes, set by the low 4 bits of 35h or by 6Ah
+    s3_bank: u8,
+}
+
+impl Svga {
+    pub fn new(chipset: SvgaChipset) -> Self {
+        let mut crtc = vec![0; 0x100];
+        match chipset {
+            SvgaChipset::ET4000 => {
+                crtc[0x37] = 0x0F; // 1 MB of video memory, 32-bit bus
+            }
+            SvgaChipset::S3Trio => {
+                crtc[0x2D] = 0x88; // chip id high
+                crtc[0x2E] = 0x11; // chip id low, Trio64
+                crtc[0x2F] = 0x00; // revision
+                crtc[0x30] = 0xE1; // chip id / revision
+                crtc[0x36] = 0x8E; // 1 MB of fast page mode memory, PCI
+            }
+            SvgaChipset::None => {}
+        }
+        Svga {
+            chipset,
+            crtc_index: 0,
+            crtc,
+            et4000_hercules: 0,
+            et4000_extensions: false,
+            et4000_segment: 0,
+            s3_bank: 0,
+        }
+    }
+
+    /// handles writes to the CRTC index register (03B4h/03D4h)
+    pub fn set_crtc_index(&mut self, index: u8) {
+        self.crtc_index = index;
+    }
+
+    /// returns true if the selected CRTC register is not a standard VGA register
+    pub fn is_extended_crtc(&self) -> bool {
+        self.crtc_index > 0x18
+    }
+
+    /// writes the selected extended CRTC register, if unlocked
+    pub fn write_crtc(&mut self, data: u8) {
+        let index = self.crtc_index;
+        if !self.crtc_accessible(index) {
+            return;
+        }
+        match (self.chipset, index) {
+            // identification registers are read only
+            (SvgaChipset::S3Trio, 0x2D..=0x30) => {}
+            (SvgaChipset::ET4000, 0x37) => {}
+            (SvgaChipset::S3Trio, 0x35) => {
+                self.crtc[0x35] = data & 0xF0;
+                self.s3_bank = (self.s3_bank & 0xF0) | (data & 0x0F);
+            }
+            (SvgaChipset::S3Trio, 0x6A) => self.s3_bank = data & 0x7F,
+            _ => self.crtc[index as usize] = data,
+        }
+    }
+
+    /// reads the selected extended CRTC register, 0 if locked or not present
+    pub fn read_crtc(&self) -> u8 {
+        let index = self.crtc_index;
+        if !self.crtc_accessible(index) {
+            return 0;
+        }
+        match (self.chipset, index) {
+            (SvgaChipset::S3Trio, 0x35) => self.crtc[0x35] | (self.s3_bank & 0x0F),
+            (SvgaChipset::S3Trio, 0x6A) => self.s3_bank,
+            _ => self.crtc[index as usize],
+        }
+    }
+
+    fn crtc_accessible(&self, index: u8) -> bool {
+        match self.chipset {
+            SvgaChipset::None => false,
+            // 33h (extended start address) is always accessible, and used to tell ET4000 from ET3000
+            SvgaChipset::ET4000 => (0x30..=0x37).contains(&index) && (self.et4000_extensions || index == 0x33),
+            SvgaChipset::S3Trio => match index {
+                0x38 | 0x39 => true,
+                0x2D..=0x3F => self.crtc[0x38] == 0x48,
+                0x40..=0xFF => self.crtc[0x39] == 0xA5,
+                _ => false,
+            },
+        }
+    }
+
+    /// returns Some<u8> if the read of `port` was handled by the chipset
+    pub fn in_u8(&mut self, port: u16) -> Option<u8> {
+        match (self.chipset, port) {
+            (SvgaChipset::ET4000, 0x03CD) => Some(self.et4000_segment),
+            _ => None,
+        }
+    }
+
+    /// returns true if the write to `port` was handled by the chipset
+    pub fn out_u8(&mut self, port: u16, data: u8) -> bool {
+        match (self.chipset, port) {
+            (SvgaChipset::ET4000, 0x03BF) => {
+                self.et4000_hercules = data;
+                true
+            }
+            (SvgaChipset::ET4000, 0x03B8) | (SvgaChipset::ET4000, 0x03D8) => {
+                // the mode control register is shared with CGA/MDA, also handled by the caller
+                self.et4000_extensions = self.et4000_hercules == 0x03 && data == 0xA0;
+                false
+            }
+            (SvgaChipset::ET4000, 0x03CD) => {
+                self.et4000_segment = data;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// returns the bank mapped at A000:0000 for reads
+    pub fn read_bank(&self) -> usize {
+        match self.chipset {
+            SvgaChipset::None => 0,
+            SvgaChipset::ET4000 => usize::from(self.et4000_segment >> 4),
+            SvgaChipset::S3Trio => usize::from(self.s3_bank),
+        }
+    }
+
+    /// returns the bank mapped at A000:0000 for writes
+    pub fn write_bank(&self) -> usize {
+        match self.chipset {
+            SvgaChipset::None => 0,
+            SvgaChipset::ET4000 => usize::from(self.et4000_segment & 0x0F),
+            SvgaChipset::S3Trio => usize::from(self.s3_bank),
+        }
+    }
+
+    /// maps `bank` for reads and writes, as done by the VESA BIOS (INT 10h AX=4F05h)
+    pub fn set_bank(&mut self, bank: u8) {
+        match self.chipset {
+            SvgaChipset::None => {}
+            SvgaChipset::ET4000 => self.et4000_segment = (bank & 0x0F) << 4 | (bank & 0x0F),
+            SvgaChipset::S3Trio => self.s3_bank = bank & 0x7F,
+        }
+    }
+}
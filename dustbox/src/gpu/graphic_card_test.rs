@@ -0,0 +1,67 @@
+use crate::gpu::{Svga, SvgaChipset};
+
+/// writes `data` to the extended CRTC register `index`
+fn write_crtc(svga: &mut Svga, index: u8, data: u8) {
+    svga.set_crtc_index(index);
+    svga.write_crtc(data);
+}
+
+fn read_crtc(svga: &mut Svga, index: u8) -> u8 {
+    svga.set_crtc_index(index);
+    svga.read_crtc()
+}
+
+#[test]
+fn can_detect_et4000() {
+    let mut svga = Svga::new(SvgaChipset::ET4000);
+
+    // 33h is accessible without the key, the other extended registers are not
+    write_crtc(&mut svga, 0x33, 0x05);
+    assert_eq!(0x05, read_crtc(&mut svga, 0x33));
+    write_crtc(&mut svga, 0x34, 0x0A);
+    assert_eq!(0x00, read_crtc(&mut svga, 0x34));
+
+    // the key
+    assert!(svga.out_u8(0x03BF, 0x03));
+    assert!(!svga.out_u8(0x03D8, 0xA0));
+    write_crtc(&mut svga, 0x34, 0x0A);
+    assert_eq!(0x0A, read_crtc(&mut svga, 0x34));
+    assert_eq!(0x0F, read_crtc(&mut svga, 0x37));
+
+    // segment select
+    assert!(svga.out_u8(0x03CD, 0x32));
+    assert_eq!(Some(0x32), svga.in_u8(0x03CD));
+    assert_eq!(3, svga.read_bank());
+    assert_eq!(2, svga.write_bank());
+}
+
+#[test]
+fn can_detect_s3_trio() {
+    let mut svga = Svga::new(SvgaChipset::S3Trio);
+    assert_eq!(0x00, read_crtc(&mut svga, 0x2D));
+
+    write_crtc(&mut svga, 0x38, 0x48);
+    assert_eq!(0x88, read_crtc(&mut svga, 0x2D));
+    assert_eq!(0x11, read_crtc(&mut svga, 0x2E));
+
+    // the chip id is read only
+    write_crtc(&mut svga, 0x2E, 0x00);
+    assert_eq!(0x11, read_crtc(&mut svga, 0x2E));
+
+    // bank select through 6Ah needs the second unlock
+    write_crtc(&mut svga, 0x6A, 0x03);
+    assert_eq!(0, svga.write_bank());
+    write_crtc(&mut svga, 0x39, 0xA5);
+    write_crtc(&mut svga, 0x6A, 0x03);
+    assert_eq!(3, svga.write_bank());
+    assert_eq!(3, svga.read_bank());
+}
+
+#[test]
+fn can_ignore_extended_registers_without_chipset() {
+    let mut svga = Svga::new(SvgaChipset::None);
+    write_crtc(&mut svga, 0x38, 0x48);
+    assert_eq!(0x00, read_crtc(&mut svga, 0x38));
+    assert!(!svga.out_u8(0x03CD, 0x11));
+    assert_eq!(None, svga.in_u8(0x03CD));
+}
@@ -0,0 +1,159 @@
+use log::{trace, warn};
+
+/// number of graphics controller registers, 00h-08h
+pub const GRAPHICS_CONTROLLER_REGISTERS: usize = 0x09;
+
+/// (EGA/VGA) graphics controller, combines cpu data with the latches on writes to video memory
+/// and selects what reads of video memory return
+/// http://www.osdever.net/FreeVGA/vga/graphreg.htm
+#[derive(Clone)]
+pub struct GraphicsController {
+    /// register 00h, value written to the planes enabled in `enable_set_reset`
+    pub set_reset: u8,
+
+    /// register 01h
+    pub enable_set_reset: u8,
+
+    /// register 02h, color matched in read mode 1
+    pub color_compare: u8,
+
+    /// register 03h, bits 0-2 rotate count, bits 3-4 logical operation with the latches
+    pub data_rotate: u8,
+
+    /// register 04h, plane read in read mode 0
+    pub read_map_select: u8,
+
+    /// register 05h, bits 0-1 write mode, bit 3 read mode
+    pub mode: u8,
+
+    /// register 06h
+    pub misc: u8,
+
+    /// register 07h, planes compared in read mode 1
+    pub color_dont_care: u8,
+
+    /// register 08h, bits that are changed by writes, the others are taken from the latches
+    pub bit_mask: u8,
+
+    pub index: u8,
+}
+
+impl Default for GraphicsController {
+    fn default() -> Self {
+        GraphicsController {
+            set_reset: 0,
+            enable_set_reset: 0,
+            color_compare: 0,
+            data_rotate: 0,
+            read_map_select: 0,
+            mode: 0x10,
+            misc: 0x0E,
+            color_dont_care: 0x0F,
+            bit_mask: 0xFF,
+            index: 0,
+        }
+    }
+}
+
+impl GraphicsController {
+    // 03CE  -W  EGA graphics controller index register
+    pub fn set_index(&mut self, data: u8) {
+        self.index = data & 0x0F;
+    }
+
+    // 03CF  -W  EGA graphics controller data register
+    pub fn write_current(&mut self, data: u8) {
+        trace!(target: "gpu", "graphics controller write_current {:02X} = {:02X}", self.index, data);
+        match self.index {
+            0x00 => self.set_reset = data & 0x0F,
+            0x01 => self.enable_set_reset = data & 0x0F,
+            0x02 => self.color_compare = data & 0x0F,
+            0x03 => self.data_rotate = data & 0x1F,
+            0x04 => self.read_map_select = data & 0x03,
+            0x05 => self.mode = data & 0x7B,
+            0x06 => self.misc = data & 0x0F,
+            0x07 => self.color_dont_care = data & 0x0F,
+            0x08 => self.bit_mask = data,
+            _ => warn!(target: "gpu", "graphics controller: write to unknown register {:02X} = {:02X}", self.index, data),
+        }
+    }
+
+    /// returns the value of the register selected by `index`, 0 for unknown registers
+    pub fn read_current(&self) -> u8 {
+        self.registers().get(self.index as usize).cloned().unwrap_or(0)
+    }
+
+    /// returns registers 00h-08h
+    pub fn registers(&self) -> [u8; GRAPHICS_CONTROLLER_REGISTERS] {
+        [
+            self.set_reset, self.enable_set_reset, self.color_compare, self.data_rotate,
+            self.read_map_select, self.mode, self.misc, self.color_dont_care, self.bit_mask,
+        ]
+    }
+
+    /// writes registers 00h-08h from `regs`, keeping the selected index
+    pub fn set_registers(&mut self, regs: &[u8]) {
+        let index = self.index;
+        for (i, v) in regs.iter().take(GRAPHICS_CONTROLLER_REGISTERS).enumerate() {
+            self.index = i as u8;
+            self.write_current(*v);
+        }
+        self.index = index;
+    }
+
+    /// write mode 0-3
+    pub fn write_mode(&self) -> u8 {
+        self.mode & 0x03
+    }
+
+    /// read mode 0-1
+    pub fn read_mode(&self) -> u8 {
+        (self.mode >> 3) & 1
+    }
+
+    /// returns the byte written to plane `plane`, given the cpu `data` and the `latch` of the plane
+    pub fn combine(&self, plane: usize, data: u8, latch: u8) -> u8 {
+        let plane_bit = 1 << plane;
+        let expand = |set: bool| if set { 0xFF } else { 0x00 };
+        let (value, mask) = match self.write_mode() {
+            0 => {
+                let value = if self.enable_set_reset & plane_bit != 0 {
+                    expand(self.set_reset & plane_bit != 0)
+                } else {
+                    self.rotate(data)
+                };
+                (value, self.bit_mask)
+            }
+            1 => return latch,
+            2 => (expand(data & plane_bit != 0), self.bit_mask),
+            _ => (expand(self.set_reset & plane_bit != 0), self.rotate(data) & self.bit_mask),
+        };
+        let value = match (self.data_rotate >> 3) & 3 {
+            0 => value,
+            1 => value & latch,
+            2 => value | latch,
+            _ => value ^ latch,
+        };
+        (value & mask) | (latch & !mask)
+    }
+
+    /// returns the byte read by the cpu from the `latches` of the 4 planes
+    pub fn read(&self, latches: &[u8; 4]) -> u8 {
+        if self.read_mode() == 0 {
+            return latches[self.read_map_select as usize];
+        }
+        // read mode 1: set bits are pixels matching the compare color in the planes not ignored
+        let mut differ = 0;
+        for (plane, latch) in latches.iter().enumerate() {
+            if self.color_dont_care & (1 << plane) != 0 {
+                let compare = if self.color_compare & (1 << plane) != 0 { 0xFF } else { 0x00 };
+                differ |= latch ^ compare;
+            }
+        }
+        !differ
+    }
+
+    fn rotate(&self, data: u8) -> u8 {
+        data.rotate_right(u32::from(self.data_rotate & 7))
+    }
+}
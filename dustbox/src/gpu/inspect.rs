@@ -0,0 +1,87 @@
+use crate::bios::BIOS;
+use crate::codepage::cp437;
+use crate::gpu::modes::GFXMode;
+use crate::gpu::palette::ColorSpace;
+use crate::gpu::render::GPU;
+use crate::memory::MMU;
+
+#[cfg(test)]
+#[path = "./inspect_test.rs"]
+mod inspect_test;
+
+/// size of one VGA memory plane, as seen through chain-4 addressing
+const PLANE_SIZE: u32 = 0x4000;
+
+/// an image of video state besides the displayed frame, used by debugger views
+pub struct InspectImage {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<ColorSpace>,
+}
+
+impl GPU {
+    /// renders the 256 DAC palette entries as a 16x16 grid of `swatch` sized squares
+    pub fn render_palette(&self, swatch: u32) -> InspectImage {
+        let size = 16 * swatch;
+        let mut data = Vec::with_capacity((size * size) as usize);
+        for y in 0..size {
+            for x in 0..size {
+                let index = (y / swatch) * 16 + (x / swatch);
+                data.push(self.palette_color(index as usize));
+            }
+        }
+        InspectImage{width: size, height: size, data}
+    }
+
+    /// renders VGA memory plane `plane` (0-3) as mapped by chain-4 addressing (mode 13h),
+    /// where byte n of A000:0000 belongs to plane n & 3. each byte is one pixel, colored by the DAC
+    pub fn render_plane(&self, mmu: &MMU, plane: u8, width: u32) -> InspectImage {
+        let height = PLANE_SIZE / width;
+        let mut data = Vec::with_capacity((width * height) as usize);
+        for i in 0..width * height {
+            let addr = 0xA_0000 + i * 4 + u32::from(plane & 3);
+            let byte = mmu.memory.data[addr as usize];
+            data.push(self.palette_color(byte as usize));
+        }
+        InspectImage{width, height, data}
+    }
+
+    /// returns the characters of text page `page` as one string per row, or None in graphics modes
+    pub fn text_page(&self, mmu: &MMU, page: u8) -> Option<Vec<String>> {
+        if self.mode.kind != GFXMode::TEXT {
+            return None;
+        }
+        let start = self.mode.pstart as usize + usize::from(page) * self.mode.plength;
+        let mut rows = Vec::with_capacity(self.mode.theight);
+        for row in 0..self.mode.theight {
+            let line: Vec<u8> = (0..self.mode.twidth)
+                .map(|col| mmu.memory.data[(start + (row * self.mode.twidth + col) * 2) & 0xF_FFFF])
+                .collect();
+            rows.push(line.iter().map(|&b| text_char(b)).collect());
+        }
+        Some(rows)
+    }
+
+    /// returns the active display page, as selected by int 10h, ah = 05h
+    pub fn active_page(&self, mmu: &MMU) -> u8 {
+        mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_CURRENT_PAGE)
+    }
+
+    fn palette_color(&self, index: usize) -> ColorSpace {
+        match self.dac.pal.get(index) {
+            Some(c) => c.clone(),
+            None => ColorSpace::RGB(0, 0, 0),
+        }
+    }
+}
+
+/// returns the symbol displayed for `b`, with the control characters shown by their glyphs
+fn text_char(b: u8) -> char {
+    match b {
+        0x00 => ' ',
+        0x09 => '○',
+        0x0A => '◙',
+        0x0D => '♪',
+        _ => cp437::u8_as_char(b),
+    }
+}
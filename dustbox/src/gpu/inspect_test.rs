@@ -0,0 +1,41 @@
+use crate::gpu::ColorSpace;
+use crate::machine::Machine;
+
+#[test]
+fn can_render_palette_swatches() {
+    let mut machine = Machine::deterministic();
+    machine.gpu_mut().dac.pal[0x11] = ColorSpace::RGB(0x10, 0x20, 0x30);
+
+    let img = machine.gpu().render_palette(4);
+    assert_eq!(64, img.width);
+    assert_eq!(64, img.height);
+    // entry 0x11 is the second swatch of the second row
+    assert_eq!(ColorSpace::RGB(0x10, 0x20, 0x30), img.data[(4 * 64 + 4) as usize]);
+    assert_eq!(ColorSpace::RGB(0x10, 0x20, 0x30), img.data[(7 * 64 + 7) as usize]);
+    assert_ne!(ColorSpace::RGB(0x10, 0x20, 0x30), img.data[(8 * 64 + 4) as usize]);
+}
+
+#[test]
+fn can_render_chain4_planes() {
+    let mut machine = Machine::deterministic();
+    machine.gpu_mut().dac.pal[0x32] = ColorSpace::RGB(1, 2, 3);
+    // byte 6 of A000 is plane 2, offset 1
+    machine.mmu.write_u8(0xA000, 0x0006, 0x32);
+
+    let img = machine.gpu().render_plane(&machine.mmu, 2, 80);
+    assert_eq!(80, img.width);
+    assert_eq!(204, img.height);
+    assert_eq!(ColorSpace::RGB(1, 2, 3), img.data[1]);
+    assert_ne!(ColorSpace::RGB(1, 2, 3), machine.gpu().render_plane(&machine.mmu, 1, 80).data[1]);
+}
+
+#[test]
+fn can_read_text_page() {
+    let mut machine = Machine::deterministic();
+    machine.mmu.write(0xB800, 0x1000, &[b'H', 0x07, b'i', 0x07, 0x01, 0x07]);
+
+    let rows = machine.gpu().text_page(&machine.mmu, 1).unwrap();
+    assert_eq!(25, rows.len());
+    assert_eq!("Hi☺ ", &rows[0][..6]);
+    assert_eq!(0, machine.gpu().active_page(&machine.mmu));
+}
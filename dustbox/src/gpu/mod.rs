@@ -21,5 +21,35 @@ mod graphic_card;
 pub use self::crtc::*;
 mod crtc;
 
+pub use self::actl::*;
+mod actl;
+
+pub use self::sequencer::*;
+mod sequencer;
+
+pub use self::graphics_controller::*;
+mod graphics_controller;
+
+pub use self::planar::*;
+mod planar;
+
 pub use self::dac::*;
 mod dac;
+
+pub use self::raster::*;
+mod raster;
+
+pub use self::color::*;
+mod color;
+
+pub use self::chargen::*;
+mod chargen;
+
+pub use self::recorder::*;
+mod recorder;
+
+pub use self::inspect::*;
+mod inspect;
+
+pub use self::viewport::*;
+mod viewport;
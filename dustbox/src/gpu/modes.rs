@@ -70,6 +70,12 @@ pub struct VideoModeBlock {
     pub special: SpecialMode,
 }
 
+/// 256 color modes of the SVGA chipsets, in banked video memory
+pub fn svga_mode_block() -> [VideoModeBlock; 2] {[
+    VideoModeBlock{mode: 0x100, kind: GFXMode::LIN8, swidth: 640, sheight: 400, twidth: 80, theight: 25, cwidth: 8, cheight: 16, ptotal: 1, pstart: 0xA_0000, plength: 0x1_0000, htotal: 100, vtotal: 449, hdispend: 80, vdispend: 400, scale_x: 1., scale_y: 1., special: SpecialMode::default()},
+    VideoModeBlock{mode: 0x101, kind: GFXMode::LIN8, swidth: 640, sheight: 480, twidth: 80, theight: 30, cwidth: 8, cheight: 16, ptotal: 1, pstart: 0xA_0000, plength: 0x1_0000, htotal: 100, vtotal: 525, hdispend: 80, vdispend: 480, scale_x: 1., scale_y: 1., special: SpecialMode{vga_pixel_double: true, ..Default::default()}},
+]}
+
 impl VideoModeBlock {
     pub fn get_mode_block(card: &GraphicCard) -> Vec<VideoModeBlock> {
         match *card {
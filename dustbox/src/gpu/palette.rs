@@ -1,4 +1,4 @@
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum ColorSpace {
     RGB(u8, u8, u8), // 6 + 6 + 6 bit rgb color
     None,
@@ -0,0 +1,113 @@
+// the 4 planes of EGA/VGA video memory, accessed by the cpu through the
+// sequencer and graphics controller in the 16 color modes, and the linear
+// memory of the SVGA modes, accessed in banks
+
+use crate::gpu::graphic_card::SVGA_BANK_SIZE;
+use crate::gpu::graphics_controller::GraphicsController;
+use crate::gpu::sequencer::Sequencer;
+use crate::memory::MemoryHandler;
+
+#[cfg(test)]
+#[path = "./planar_test.rs"]
+mod planar_test;
+
+/// size of each plane
+pub const PLANE_SIZE: usize = 0x1_0000;
+
+/// physical address of the cpu window to video memory
+pub const PLANAR_BASE: u32 = 0xA_0000;
+
+#[derive(Clone)]
+pub struct PlanarMemory {
+    pub planes: [Vec<u8>; 4],
+
+    /// bytes of each plane loaded by the last cpu read
+    pub latches: [u8; 4],
+
+    pub sequencer: Sequencer,
+    pub graphics: GraphicsController,
+
+    /// video memory of the SVGA modes, empty without a SVGA chipset
+    pub linear: Vec<u8>,
+
+    /// set in the SVGA modes, where the cpu accesses `linear` through the banks
+    pub banked: bool,
+
+    /// banks of `linear` mapped at A000:0000, as selected by the SVGA chipset
+    pub read_bank: usize,
+    pub write_bank: usize,
+}
+
+impl Default for PlanarMemory {
+    fn default() -> Self {
+        PlanarMemory {
+            planes: [vec![0; PLANE_SIZE], vec![0; PLANE_SIZE], vec![0; PLANE_SIZE], vec![0; PLANE_SIZE]],
+            latches: [0; 4],
+            sequencer: Sequencer::default(),
+            graphics: GraphicsController::default(),
+            linear: Vec::new(),
+            banked: false,
+            read_bank: 0,
+            write_bank: 0,
+        }
+    }
+}
+
+impl PlanarMemory {
+    /// reads `offset` of the planes as the cpu does, loading the latches
+    pub fn read(&mut self, offset: usize) -> u8 {
+        let offset = offset % PLANE_SIZE;
+        for (latch, plane) in self.latches.iter_mut().zip(self.planes.iter()) {
+            *latch = plane[offset];
+        }
+        self.graphics.read(&self.latches)
+    }
+
+    /// writes `data` to `offset` of the planes enabled in the map mask, as the cpu does
+    pub fn write(&mut self, offset: usize, data: u8) {
+        let offset = offset % PLANE_SIZE;
+        for plane in 0..4 {
+            if self.sequencer.map_mask & (1 << plane) != 0 {
+                self.planes[plane][offset] = self.graphics.combine(plane, data, self.latches[plane]);
+            }
+        }
+    }
+
+    /// returns the offset in `linear` of `offset` in the bank `bank`
+    fn banked_offset(&self, bank: usize, offset: usize) -> usize {
+        (bank * SVGA_BANK_SIZE + offset % SVGA_BANK_SIZE) % self.linear.len()
+    }
+
+    /// returns the 4-bit color of pixel `bit` (7 = leftmost) of the byte at `offset`
+    pub fn pixel(&self, offset: usize, bit: u8) -> u8 {
+        let offset = offset % PLANE_SIZE;
+        self.planes.iter().enumerate().fold(0, |color, (plane, data)| {
+            color | ((data[offset] >> bit) & 1) << plane
+        })
+    }
+}
+
+impl MemoryHandler for PlanarMemory {
+    fn read_u8(&mut self, addr: u32) -> Option<u8> {
+        if self.banked && !self.linear.is_empty() {
+            return Some(self.linear[self.banked_offset(self.read_bank, (addr - PLANAR_BASE) as usize)]);
+        }
+        if !self.sequencer.is_planar() {
+            return None;
+        }
+        Some(self.read((addr - PLANAR_BASE) as usize))
+    }
+
+    fn write_u8(&mut self, addr: u32, data: u8) -> bool {
+        if self.banked && !self.linear.is_empty() {
+            let offset = self.banked_offset(self.write_bank, (addr - PLANAR_BASE) as usize);
+            self.linear[offset] = data;
+            return true;
+        }
+        if !self.sequencer.is_planar() {
+            return false;
+        }
+        self.write((addr - PLANAR_BASE) as usize, data);
+        true
+    }
+}
@@ -0,0 +1,155 @@
+use crate::cpu::R;
+use crate::gpu::PlanarMemory;
+use crate::machine::Machine;
+
+/// returns planar memory as set up for the 16 color modes
+fn planar_memory() -> PlanarMemory {
+    let mut planar = PlanarMemory::default();
+    planar.sequencer.memory_mode = 0x06;
+    planar.graphics.mode = 0x00;
+    planar
+}
+
+/// returns the byte at `offset` of each plane
+fn planes_at(planar: &PlanarMemory, offset: usize) -> Vec<u8> {
+    planar.planes.iter().map(|p| p[offset]).collect()
+}
+
+#[test]
+fn can_write_with_map_mask() {
+    let mut planar = planar_memory();
+    planar.sequencer.map_mask = 0b0101;
+    planar.write(10, 0xF0);
+    assert_eq!(vec![0xF0, 0x00, 0xF0, 0x00], planes_at(&planar, 10));
+    assert_eq!(0b0101, planar.pixel(10, 7));
+    assert_eq!(0, planar.pixel(10, 3));
+}
+
+#[test]
+fn can_write_mode_0_with_set_reset_and_bit_mask() {
+    let mut planar = planar_memory();
+    planar.planes[1][0] = 0xFF;
+    // plot a pixel of color 5 at the leftmost pixel, keeping the others
+    planar.graphics.set_reset = 5;
+    planar.graphics.enable_set_reset = 0x0F;
+    planar.graphics.bit_mask = 0x80;
+    planar.read(0);
+    planar.write(0, 0x00);
+    assert_eq!(vec![0x80, 0x7F, 0x80, 0x00], planes_at(&planar, 0));
+    assert_eq!(5, planar.pixel(0, 7));
+    assert_eq!(2, planar.pixel(0, 6));
+}
+
+#[test]
+fn can_write_mode_0_with_rotate_and_logical_operation() {
+    let mut planar = planar_memory();
+    planar.planes[0][0] = 0x0F;
+    planar.graphics.data_rotate = 0x18 | 4; // xor, rotate right by 4
+    planar.sequencer.map_mask = 0b0001;
+    planar.read(0);
+    planar.write(0, 0x3C);
+    assert_eq!(0x0F ^ 0xC3, planar.planes[0][0]);
+}
+
+#[test]
+fn can_copy_with_write_mode_1() {
+    let mut planar = planar_memory();
+    for (i, p) in planar.planes.iter_mut().enumerate() {
+        p[3] = 0x11 * (i as u8 + 1);
+    }
+    planar.graphics.mode = 0x01;
+    planar.read(3);
+    planar.write(83, 0xAA); // the cpu data is ignored
+    assert_eq!(vec![0x11, 0x22, 0x33, 0x44], planes_at(&planar, 83));
+}
+
+#[test]
+fn can_write_mode_2_colors() {
+    let mut planar = planar_memory();
+    planar.graphics.mode = 0x02;
+    planar.graphics.bit_mask = 0x0F;
+    planar.read(0);
+    planar.write(0, 0x0C);
+    assert_eq!(vec![0x00, 0x00, 0x0F, 0x0F], planes_at(&planar, 0));
+    assert_eq!(0x0C, planar.pixel(0, 0));
+    assert_eq!(0x00, planar.pixel(0, 4));
+}
+
+#[test]
+fn can_write_mode_3_masked_by_data() {
+    let mut planar = planar_memory();
+    planar.graphics.mode = 0x03;
+    planar.graphics.set_reset = 0x09;
+    planar.graphics.bit_mask = 0xF0;
+    planar.read(0);
+    planar.write(0, 0x3C);
+    assert_eq!(vec![0x30, 0x00, 0x00, 0x30], planes_at(&planar, 0));
+}
+
+#[test]
+fn can_read_mode_0_and_1() {
+    let mut planar = planar_memory();
+    planar.planes[0][0] = 0b1100_0000;
+    planar.planes[1][0] = 0b1010_0000;
+    planar.planes[2][0] = 0b0000_0000;
+    planar.planes[3][0] = 0b1111_1111;
+
+    planar.graphics.read_map_select = 1;
+    assert_eq!(0b1010_0000, planar.read(0));
+
+    // find the pixels of color 0x0B
+    planar.graphics.mode = 0x08;
+    planar.graphics.color_compare = 0x0B;
+    assert_eq!(0b1000_0000, planar.read(0));
+
+    // ignore plane 1
+    planar.graphics.color_dont_care = 0b1101;
+    assert_eq!(0b1100_0000, planar.read(0));
+    assert_eq!(vec![0xC0, 0xA0, 0x00, 0xFF], planar.latches.to_vec());
+}
+
+#[test]
+fn can_draw_in_mode_12h() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB8, 0x12, 0x00,   // mov ax,0x12
+        0xCD, 0x10,         // int 0x10
+        0xB8, 0x00, 0xA0,   // mov ax,0xa000
+        0x8E, 0xC0,         // mov es,ax
+        0xBA, 0xC4, 0x03,   // mov dx,0x3c4
+        0xB8, 0x02, 0x0E,   // mov ax,0xe02     ; map mask: planes 1-3
+        0xEF,               // out dx,ax
+        0xB2, 0xCE,         // mov dl,0xce
+        0xB8, 0x08, 0xC0,   // mov ax,0xc008    ; bit mask: 2 leftmost pixels
+        0xEF,               // out dx,ax
+        0x26, 0xA0, 0x00, 0x00, // mov al,[es:0x0]  ; load the latches
+        0xB0, 0xFF,         // mov al,0xff
+        0x26, 0xA2, 0x00, 0x00, // mov [es:0x0],al
+        0xB8, 0x05, 0x08,   // mov ax,0x805     ; read mode 1
+        0xEF,               // out dx,ax
+        0xB8, 0x02, 0x0E,   // mov ax,0xe02     ; color compare 0x0E
+        0xEF,               // out dx,ax
+        0x26, 0xA0, 0x00, 0x00, // mov al,[es:0x0]
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x12, machine.gpu().mode.mode);
+
+    machine.execute_instructions(11);
+    {
+        let planar = machine.gpu().planar.lock().unwrap();
+        assert_eq!(vec![0x00, 0xC0, 0xC0, 0xC0], planes_at(&planar, 0));
+    }
+    assert_eq!(0, machine.mmu.memory.data[0xA_0000]); // not written to the flat memory
+
+    let frame = machine.gpu().render_frame(&machine.mmu);
+    let pal = machine.gpu().dac.frame_palette();
+    assert_eq!(pal[0x3E], frame.pixel(0, 0)); // color 0x0E in the default palette
+    assert_eq!(pal[0x3E], frame.pixel(1, 0));
+    assert_eq!(pal[0x00], frame.pixel(2, 0));
+
+    machine.execute_instructions(5);
+    assert_eq!(0xC0, machine.cpu.get_r8(R::AL));
+}
@@ -0,0 +1,91 @@
+// per-scanline state of the video hardware, for effects done by programs changing
+// registers while the beam is drawing (raster bars, split screens)
+
+use std::sync::{Arc, Mutex};
+
+use crate::gpu::crtc::CRTC;
+use crate::gpu::dac::DAC;
+
+/// called when the beam starts drawing scanline `y`, with the CRTC and DAC to inspect or change
+pub type ScanlineHook = Arc<Mutex<dyn FnMut(u32, &mut CRTC, &mut DAC) + Send>>;
+
+/// CRTC state in effect from `scanline` until the next recorded change
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScanlineCrtc {
+    pub scanline: u32,
+
+    /// display start address, latched by the CRTC at the start of the frame
+    pub start_address: u16,
+
+    /// the display restarts at address 0 after this scanline (split screen)
+    pub line_compare: u16,
+}
+
+impl ScanlineCrtc {
+    fn new(scanline: u32, start_address: u16, crtc: &CRTC) -> Self {
+        ScanlineCrtc {
+            scanline,
+            start_address,
+            line_compare: crtc.line_compare(),
+        }
+    }
+}
+
+/// records the CRTC state by scanline during a frame, as the DAC does with the palette
+#[derive(Clone)]
+pub struct Raster {
+    /// state as latched at the start of the current frame
+    frame: ScanlineCrtc,
+
+    /// changes recorded by scanline during the frame in progress
+    pending: Vec<ScanlineCrtc>,
+
+    /// changes recorded by scanline during the last complete frame
+    latched: Vec<ScanlineCrtc>,
+}
+
+impl Raster {
+    pub fn new(crtc: &CRTC) -> Self {
+        Raster {
+            frame: ScanlineCrtc::new(0, crtc.start_address(), crtc),
+            pending: Vec::new(),
+            latched: Vec::new(),
+        }
+    }
+
+    /// called when the beam moved to `scanline`. records the CRTC state if it changed
+    pub fn latch_scanline(&mut self, scanline: u32, crtc: &CRTC) {
+        let last = self.pending.last().unwrap_or(&self.frame);
+        let state = ScanlineCrtc::new(scanline, self.frame.start_address, crtc);
+        if state.line_compare != last.line_compare {
+            self.pending.push(state);
+        }
+    }
+
+    /// called on vertical retrace. the changes of the finished frame are kept for rendering
+    /// and the current state is latched for the next frame
+    pub fn latch_frame(&mut self, crtc: &CRTC) {
+        let mut frame = vec!(self.frame);
+        frame.append(&mut self.pending);
+        self.latched = frame;
+        self.frame = ScanlineCrtc::new(0, crtc.start_address(), crtc);
+    }
+
+    /// drops all latched state and uses the current CRTC state, used on mode changes
+    pub fn reset(&mut self, crtc: &CRTC) {
+        *self = Raster::new(crtc);
+    }
+
+    /// returns the changes by scanline of the last complete frame
+    pub fn latched(&self) -> &[ScanlineCrtc] {
+        &self.latched
+    }
+
+    /// returns the state that was in effect for `scanline` during the last complete frame
+    pub fn scanline_crtc(&self, scanline: u32) -> ScanlineCrtc {
+        match self.latched.iter().rev().find(|c| c.scanline <= scanline) {
+            Some(c) => *c,
+            None => self.frame,
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use gif::SetParameter;
+use image::RgbaImage;
+use log::debug;
+
+#[cfg(test)]
+#[path = "./recorder_test.rs"]
+mod recorder_test;
+
+/// a captured video frame, displayed from `timestamp` (in milliseconds since capture started)
+pub struct RecordedFrame {
+    pub timestamp: u64,
+    pub image: RgbaImage,
+}
+
+/// accumulates rendered frames and writes them as an animated PNG if the path ends in .png,
+/// otherwise as an animated GIF
+pub struct VideoRecorder {
+    path: PathBuf,
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl VideoRecorder {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        VideoRecorder {
+            path: path.as_ref().to_path_buf(),
+            frames: Vec::new(),
+        }
+    }
+
+    /// adds a frame shown from `timestamp` ms. frames identical to the previous one are
+    /// merged into it, and frames of a different size than the first one (mode changes) are dropped
+    pub fn add_frame(&mut self, timestamp: u64, image: RgbaImage) {
+        if image.width() == 0 || image.height() == 0 {
+            return;
+        }
+        if let Some(first) = self.frames.first() {
+            if first.image.dimensions() != image.dimensions() {
+                debug!(target: "gpu", "recorder: dropping frame of size {:?}", image.dimensions());
+                return;
+            }
+        }
+        if let Some(last) = self.frames.last() {
+            if last.image.as_ref() as &[u8] == image.as_ref() as &[u8] {
+                return;
+            }
+        }
+        self.frames.push(RecordedFrame{timestamp, image});
+    }
+
+    /// writes the recorded frames to disk. the last frame is displayed for `last_duration` ms
+    pub fn save(&self, last_duration: u64) -> io::Result<()> {
+        if self.frames.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "no frames recorded"));
+        }
+        let is_png = self.path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+        let file = BufWriter::new(File::create(&self.path)?);
+        if is_png {
+            self.write_apng(file, last_duration)?;
+        } else {
+            self.write_gif(file, last_duration)?;
+        }
+        debug!(target: "gpu", "recorder: wrote {} frames to {}", self.frames.len(), self.path.display());
+        Ok(())
+    }
+
+    /// returns how long the frame at `index` is displayed, in ms
+    fn duration(&self, index: usize, last_duration: u64) -> u64 {
+        match self.frames.get(index + 1) {
+            Some(next) => next.timestamp.saturating_sub(self.frames[index].timestamp),
+            None => last_duration,
+        }
+    }
+
+    fn write_gif<W: Write>(&self, w: W, last_duration: u64) -> io::Result<()> {
+        let (width, height) = self.frames[0].image.dimensions();
+        let mut encoder = gif::Encoder::new(w, width as u16, height as u16, &[])?;
+        encoder.set(gif::Repeat::Infinite)?;
+
+        for (i, frame) in self.frames.iter().enumerate() {
+            let mut gif_frame = encode_frame(&frame.image);
+            // gif delays are in 1/100 s
+            gif_frame.delay = ((self.duration(i, last_duration) + 5) / 10).min(u64::from(u16::MAX)) as u16;
+            encoder.write_frame(&gif_frame)?;
+        }
+        Ok(())
+    }
+
+    /// writes an APNG. each frame is encoded as a PNG, whose image data is moved to the
+    /// fdAT chunks of the animation, the first frame is also the default image
+    fn write_apng<W: Write>(&self, mut w: W, last_duration: u64) -> io::Result<()> {
+        let (width, height) = self.frames[0].image.dimensions();
+        w.write_all(PNG_SIGNATURE)?;
+        let mut sequence = 0u32;
+        for (i, frame) in self.frames.iter().enumerate() {
+            let png = encode_png(&frame.image)?;
+            let chunks = png_chunks(&png)?;
+            if i == 0 {
+                let header = chunks.iter().find(|(kind, _)| kind == b"IHDR")
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing IHDR"))?;
+                write_chunk(&mut w, b"IHDR", header.1)?;
+                let mut actl = Vec::with_capacity(8);
+                actl.extend_from_slice(&(self.frames.len() as u32).to_be_bytes());
+                actl.extend_from_slice(&0u32.to_be_bytes()); // loop forever
+                write_chunk(&mut w, b"acTL", &actl)?;
+            }
+
+            let mut fctl = Vec::with_capacity(26);
+            fctl.extend_from_slice(&sequence.to_be_bytes());
+            fctl.extend_from_slice(&width.to_be_bytes());
+            fctl.extend_from_slice(&height.to_be_bytes());
+            fctl.extend_from_slice(&0u32.to_be_bytes()); // x offset
+            fctl.extend_from_slice(&0u32.to_be_bytes()); // y offset
+            // delay in ms, as numerator / denominator of seconds
+            let delay = self.duration(i, last_duration).min(u64::from(u16::MAX)) as u16;
+            fctl.extend_from_slice(&delay.to_be_bytes());
+            fctl.extend_from_slice(&1000u16.to_be_bytes());
+            fctl.push(0); // dispose op: none
+            fctl.push(0); // blend op: source
+            write_chunk(&mut w, b"fcTL", &fctl)?;
+            sequence += 1;
+
+            for (_, data) in chunks.iter().filter(|(kind, _)| kind == b"IDAT") {
+                if i == 0 {
+                    write_chunk(&mut w, b"IDAT", data)?;
+                } else {
+                    let mut fdat = Vec::with_capacity(4 + data.len());
+                    fdat.extend_from_slice(&sequence.to_be_bytes());
+                    fdat.extend_from_slice(data);
+                    write_chunk(&mut w, b"fdAT", &fdat)?;
+                    sequence += 1;
+                }
+            }
+        }
+        write_chunk(&mut w, b"IEND", &[])?;
+        w.flush()
+    }
+}
+
+const PNG_SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// encodes `image` as a PNG file
+fn encode_png(image: &RgbaImage) -> io::Result<Vec<u8>> {
+    let mut png = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png, image.width(), image.height());
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(image)?;
+    }
+    Ok(png)
+}
+
+/// splits the PNG file `png` into its chunks of type and data
+fn png_chunks(png: &[u8]) -> io::Result<Vec<([u8; 4], &[u8])>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed png");
+    if !png.starts_with(PNG_SIGNATURE) {
+        return Err(invalid());
+    }
+    let mut chunks = Vec::new();
+    let mut pos = PNG_SIGNATURE.len();
+    while pos < png.len() {
+        let header = png.get(pos..pos + 8).ok_or_else(invalid)?;
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let kind = [header[4], header[5], header[6], header[7]];
+        let data = png.get(pos + 8..pos + 8 + len).ok_or_else(invalid)?;
+        chunks.push((kind, data));
+        pos += 12 + len;
+    }
+    Ok(chunks)
+}
+
+/// writes a PNG chunk of type `kind`
+fn write_chunk<W: Write>(w: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    w.write_all(&crc.finalize().to_be_bytes())
+}
+
+/// converts `image` to a gif frame, using an exact palette when there is 256 colors or less
+fn encode_frame(image: &RgbaImage) -> gif::Frame<'static> {
+    let (width, height) = image.dimensions();
+    let mut palette = Vec::new();
+    let mut index = HashMap::new();
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for p in image.pixels() {
+        let rgb = (p[0], p[1], p[2]);
+        let idx = match index.get(&rgb) {
+            Some(idx) => *idx,
+            None => {
+                if index.len() == 256 {
+                    let mut raw = image.clone().into_raw();
+                    return gif::Frame::from_rgba_speed(width as u16, height as u16, &mut raw, 10);
+                }
+                let idx = index.len() as u8;
+                index.insert(rgb, idx);
+                palette.extend_from_slice(&[rgb.0, rgb.1, rgb.2]);
+                idx
+            }
+        };
+        pixels.push(idx);
+    }
+    gif::Frame::from_palette_pixels(width as u16, height as u16, &pixels, &palette, None)
+}
@@ -0,0 +1,97 @@
+use std::fs::File;
+
+use image::{Rgba, RgbaImage};
+use tempfile::tempdir;
+
+use crate::gpu::VideoRecorder;
+use crate::machine::Machine;
+
+#[test]
+fn can_merge_identical_frames() {
+    let mut recorder = VideoRecorder::new("unused.gif");
+    let black = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 0xFF]));
+    let white = RgbaImage::from_pixel(4, 4, Rgba([0xFF, 0xFF, 0xFF, 0xFF]));
+    recorder.add_frame(0, black.clone());
+    recorder.add_frame(14, black);
+    recorder.add_frame(28, white);
+    recorder.add_frame(42, RgbaImage::new(8, 8)); // size change is dropped
+
+    assert_eq!(2, recorder.frames.len());
+    assert_eq!(0, recorder.frames[0].timestamp);
+    assert_eq!(28, recorder.frames[1].timestamp);
+}
+
+#[test]
+fn can_record_video_to_gif() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("out.gif");
+
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB8, 0x13, 0x00,       // mov ax,0x13
+        0xCD, 0x10,             // int 0x10
+        0xB8, 0x00, 0xA0,       // mov ax,0xA000
+        0x8E, 0xC0,             // mov es,ax
+        0x26, 0xFE, 0x06, 0x00, 0x00, // inc byte [es:0x0]
+        0xEB, 0xF9,             // jmp short 0x10A
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    machine.execute_instructions(2);
+
+    machine.start_video_capture(&path);
+    // run for a few frames, changing the first pixel color constantly
+    let frame_cycles = machine.cpu.clock_hz / machine.gpu().refresh_rate;
+    machine.execute_instructions(frame_cycles * 3);
+    machine.stop_video_capture().unwrap();
+
+    let decoder = gif::Decoder::new(File::open(&path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    assert_eq!(320, reader.width());
+    assert_eq!(200, reader.height());
+    let mut frames = 0;
+    while let Some(frame) = reader.read_next_frame().unwrap() {
+        assert!(frame.delay <= 1); // 1000 / 70 ms per frame
+        frames += 1;
+    }
+    assert!(frames >= 3);
+}
+
+#[test]
+fn can_record_video_to_apng() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("out.png");
+
+    let mut recorder = VideoRecorder::new(&path);
+    let black = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 0xFF]));
+    let white = RgbaImage::from_pixel(4, 4, Rgba([0xFF, 0xFF, 0xFF, 0xFF]));
+    recorder.add_frame(0, black.clone());
+    recorder.add_frame(14, white);
+    recorder.add_frame(28, black.clone());
+    recorder.save(14).unwrap();
+
+    let data = std::fs::read(&path).unwrap();
+    // the default image is the first frame
+    let (info, mut reader) = png::Decoder::new(data.as_slice()).read_info().unwrap();
+    assert_eq!((4, 4), (info.width, info.height));
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf).unwrap();
+    assert_eq!(black.into_raw(), buf);
+
+    let mut kinds = Vec::new();
+    let mut pos = 8;
+    while pos < data.len() {
+        let len = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let kind = String::from_utf8_lossy(&data[pos + 4..pos + 8]).into_owned();
+        if kind == "acTL" {
+            assert_eq!(&[0, 0, 0, 3], &data[pos + 8..pos + 12]); // frames
+        }
+        if kind == "fcTL" {
+            assert_eq!(&[0, 14, 0x03, 0xE8], &data[pos + 28..pos + 32]); // 14 / 1000 s
+        }
+        kinds.push(kind);
+        pos += 12 + len;
+    }
+    assert_eq!(vec!["IHDR", "acTL", "fcTL", "IDAT", "fcTL", "fdAT", "fcTL", "fdAT", "IEND"], kinds);
+}
@@ -1,34 +1,50 @@
-use image::{ImageBuffer, Rgb};
+use std::ops::{Range, RangeInclusive};
+use std::sync::{Arc, Mutex};
+
+use image::{ImageBuffer, Rgb, RgbaImage};
+use log::{debug, trace, warn};
 
 use crate::cpu::{CPU, R};
 use crate::machine::Component;
-use crate::memory::{MMU, MemoryAddress};
+use crate::memory::{MMU, MemoryAddress, MemoryHandler};
 use crate::gpu::palette;
 use crate::gpu::palette::{ColorSpace};
 use crate::gpu::font;
 use crate::gpu::video_parameters;
 use crate::gpu::modes::GFXMode;
-use crate::gpu::modes::VideoModeBlock;
-use crate::gpu::graphic_card::GraphicCard;
+use crate::gpu::modes::{VideoModeBlock, svga_mode_block};
+use crate::gpu::graphic_card::{GraphicCard, Svga, SvgaChipset, SVGA_MEMORY_SIZE};
 use crate::bios::BIOS;
-use crate::gpu::crtc::CRTC;
-use crate::gpu::dac::DAC;
+use crate::gpu::actl::{ACTL, ACTL_REGISTERS, MODE_BLINK};
+use crate::gpu::crtc::{CRTC, CRTC_REGISTERS};
+use crate::gpu::dac::{DAC, ScanlinePalette};
+use crate::gpu::raster::{Raster, ScanlineCrtc, ScanlineHook};
+use crate::gpu::color::{ColorEmulation, Monitor, CGA_COMPOSITE_PALETTE};
+use crate::gpu::chargen::CharGen;
+use crate::gpu::graphics_controller::{GraphicsController, GRAPHICS_CONTROLLER_REGISTERS};
+use crate::gpu::planar::{PlanarMemory, PLANAR_BASE, PLANE_SIZE};
+use crate::gpu::sequencer::{Sequencer, SEQUENCER_REGISTERS};
 
 #[cfg(test)]
 #[path = "./render_test.rs"]
 mod render_test;
 
-const DEBUG_SET_MODE: bool = true;
-const DEBUG_FONT: bool = false;
-const DEBUG_INTERRUPTS: bool = false;
-
 /// if set, character output from DOS programs will be echoed to stdout
 const ECHO_TELETYPE: bool = false;
 
 const CGA_MASKS: [u8; 4]  = [0x3f, 0xcf, 0xf3, 0xfc];
 const CGA_MASKS2: [u8; 8] = [0x7f, 0xbf, 0xdf, 0xef, 0xf7, 0xfb, 0xfd, 0xfe];
 
-const ACTL_MAX_REG: u8 = 0x14;
+/// parts of the video state saved by INT 10h AX=1C01h, selected by CX
+const VIDEO_STATE_HARDWARE: u16 = 0x0001;
+const VIDEO_STATE_BIOS: u16     = 0x0002;
+const VIDEO_STATE_DAC: u16      = 0x0004;
+
+/// layout of the video state buffer: a header with the offsets of the saved parts, followed by the parts
+const VIDEO_STATE_HEADER_SIZE: u16   = 0x20;
+const VIDEO_STATE_HARDWARE_SIZE: u16 = 0x40;
+const VIDEO_STATE_BIOS_SIZE: u16     = 0x40;
+const VIDEO_STATE_DAC_SIZE: u16      = 0x303;
 
 pub static STATIC_FUNCTIONALITY: [u8; 0x10] = [
  /* 0 */ 0xff,  // All modes supported #1
@@ -47,28 +63,56 @@ pub static STATIC_FUNCTIONALITY: [u8; 0x10] = [
 ];
 
 impl Component for GPU {
+    fn io_ports(&self) -> Vec<RangeInclusive<u16>> {
+        vec![
+            0x02C6..=0x02C9, // DAC (alternate address)
+            0x03B0..=0x03BF, // MDA / mono CRTC
+            0x03C0..=0x03DF, // EGA/VGA, CGA
+        ]
+    }
+
+    fn interrupts(&self) -> Vec<u8> {
+        vec![0x10]
+    }
+
     fn in_u8(&mut self, port: u16) -> Option<u8> {
+        if let Some(v) = self.svga.in_u8(port) {
+            return Some(v);
+        }
         match port {
             0x03C7 => Some(self.dac.get_state()),
             0x03C8 => Some(self.dac.get_pel_write_index()),
             0x03C9 => Some(self.dac.get_pel_data()),
-            0x03D5 => {
-                // RW  CRT control register value
-                // XXX
-                Some(0)
-            },
-            0x03DA => Some(self.read_cga_status_register()),
+            0x03B5 | 0x03D5 => Some(self.read_crtc_data()), // RW  CRT control register value
+            0x03C0 => Some(self.actl.read_index()),
+            0x03C1 => Some(self.actl.read_data()),
+            0x03C4 => Some(self.planar.lock().unwrap().sequencer.index),
+            0x03C5 => Some(self.planar.lock().unwrap().sequencer.read_current()),
+            0x03CE => Some(self.planar.lock().unwrap().graphics.index),
+            0x03CF => Some(self.planar.lock().unwrap().graphics.read_current()),
+            0x03BA | 0x03DA => {
+                // reading the input status register resets the attribute controller flip-flop
+                self.actl.reset_flip_flop();
+                Some(self.read_cga_status_register())
+            }
             _ => None
         }
     }
 
     fn out_u8(&mut self, port: u16, data: u8) -> bool {
+        if self.svga.out_u8(port, data) {
+            self.update_banks();
+            return true;
+        }
         match port {
             // 02C6-02C9 - VGA/MCGA - DAC REGISTERS (alternate address)
             0x02C9 => self.dac.set_pel_data(data),
 
-            0x03B4 => self.crtc.set_index(data),           // NOTE: mirror of 03D4
-            0x03B5 => self.crtc.write_current(data),
+            0x03B4 => self.write_crtc_index(data),         // NOTE: mirror of 03D4
+            0x03B5 => self.write_crtc_data(data),
+
+            // PORT 03C0 - EGA/VGA - ATTRIBUTE CONTROLLER, index and data written alternately
+            0x03C0 => self.actl.write(data),
 
             // PORT 03C2-03CF - EGA/VGA - MISCELLANEOUS REGISTERS
             0x03C2 => {
@@ -77,8 +121,8 @@ impl Component for GPU {
             },
 
             // PORT 03C4-03C5 - EGA/VGA - SEQUENCER REGISTERS
-            //0x03C4 => // INDEX
-            //0x03C5 => // DATA
+            0x03C4 => self.planar.lock().unwrap().sequencer.set_index(data),
+            0x03C5 => self.planar.lock().unwrap().sequencer.write_current(data),
 
             // PORT 03C6-03C9 - EGA/VGA/MCGA - DAC REGISTERS
             0x03C6 => self.dac.set_pel_mask(data),
@@ -86,9 +130,13 @@ impl Component for GPU {
             0x03C8 => self.dac.set_pel_write_index(data),
             0x03C9 => self.dac.set_pel_data(data),
 
+            // PORT 03CE-03CF - EGA/VGA - GRAPHICS CONTROLLER REGISTERS
+            0x03CE => self.planar.lock().unwrap().graphics.set_index(data),
+            0x03CF => self.planar.lock().unwrap().graphics.write_current(data),
+
             // PORT 03D4-03D5 - COLOR VIDEO - CRT CONTROL REGISTERS
-            0x03D4 => self.crtc.set_index(data),
-            0x03D5 => self.crtc.write_current(data),
+            0x03D4 => self.write_crtc_index(data),
+            0x03D5 => self.write_crtc_data(data),
 
             0x03D8 => {
                 // RW  CGA mode control register  (except PCjr) (see #P0817)
@@ -114,6 +162,45 @@ impl Component for GPU {
         true
     }
 
+    fn in_u16(&mut self, port: u16) -> Option<u16> {
+        match port {
+            // index in the low byte, value of the selected register in the high byte
+            0x03B4 | 0x03D4 => Some(u16::from(self.read_crtc_data()) << 8 | u16::from(self.crtc.index)),
+            _ => {
+                let lo = self.in_u8(port)?;
+                let hi = self.in_u8(port + 1).unwrap_or(0);
+                Some(u16::from(hi) << 8 | u16::from(lo))
+            }
+        }
+    }
+
+    fn out_u16(&mut self, port: u16, data: u16) -> bool {
+        let lo = data as u8;
+        let hi = (data >> 8) as u8;
+        match port {
+            // index and register value written in one access
+            0x03B4 | 0x03D4 => {
+                self.write_crtc_index(lo);
+                self.write_crtc_data(hi);
+            }
+            // index and register value written in one access
+            0x03C0 => {
+                self.actl.write(lo);
+                self.actl.write(hi);
+            }
+            // start index and red component of the first color register
+            0x03C8 => {
+                self.dac.set_pel_write_index(lo);
+                self.dac.set_pel_data(hi);
+            }
+            _ => {
+                let handled = self.out_u8(port, lo);
+                return self.out_u8(port + 1, hi) || handled;
+            }
+        }
+        true
+    }
+
     fn int(&mut self, int: u8, cpu: &mut CPU, mmu: &mut MMU) -> bool {
         if int != 0x10 {
             return false;
@@ -132,9 +219,9 @@ impl Component for GPU {
 
                 // Return:
                 // Nothing
-                println!("XXX set text-mode cursor shape, start_options={:02X}, bottom_line={:02X}",
-                        cpu.get_r8(R::CH),
-                        cpu.get_r8(R::CL));
+                let start = cpu.get_r8(R::CH);
+                let end = cpu.get_r8(R::CL);
+                self.set_cursor_shape(mmu, start, end);
             }
             0x02 => {
                 // VIDEO - SET CURSOR POSITION
@@ -145,14 +232,17 @@ impl Component for GPU {
             }
             0x03 => {
                 // VIDEO - GET CURSOR POSITION AND SIZE
-                let page = cpu.get_r8(R::BH);
+                let page = cpu.get_r8(R::BH) & 7;
                 // Return:
                 // AX = 0000h (Phoenix BIOS)
                 // CH = start scan line
                 // CL = end scan line
                 // DH = row (00h is top)
                 // DL = column (00h is left)
-                println!("XXX GET CURSOR POSITION AND SIZE, page {}", page);
+                cpu.set_r16(R::AX, 0);
+                cpu.set_r16(R::CX, mmu.read_u16(BIOS::DATA_SEG, BIOS::DATA_CURSOR_TYPE));
+                cpu.set_r8(R::DH, cursor_pos_row(mmu, page));
+                cpu.set_r8(R::DL, cursor_pos_col(mmu, page));
             }
             0x05 => {
                 // VIDEO - SELECT ACTIVE DISPLAY PAGE
@@ -200,7 +290,7 @@ impl Component for GPU {
                 let y1 = cpu.get_r8(R::CH);
                 let x2 = cpu.get_r8(R::DL);
                 let y2 = cpu.get_r8(R::DH);
-                println!("XXX int10 - SCROLL UP WINDOW, lines {}, attr {}, upper left {},{}, lower right {},{}", lines, attr, x1, y1, x2, y2);
+                warn!(target: "gpu", "XXX int10 - SCROLL UP WINDOW, lines {}, attr {}, upper left {},{}, lower right {},{}", lines, attr, x1, y1, x2, y2);
             }
             0x07 => {
                 // VIDEO - SCROLL DOWN WINDOW
@@ -214,7 +304,7 @@ impl Component for GPU {
                 let y1 = cpu.get_r8(R::CH);
                 let x2 = cpu.get_r8(R::DL);
                 let y2 = cpu.get_r8(R::DH);
-                println!("XXX int10 - SCROLL DOWN WINDOW, lines {}, attr {}, upper left {},{}, lower right {},{}", lines, attr, x1, y1, x2, y2);
+                warn!(target: "gpu", "XXX int10 - SCROLL DOWN WINDOW, lines {}, attr {}, upper left {},{}, lower right {},{}", lines, attr, x1, y1, x2, y2);
             }
             0x08 => {
                 // VIDEO - READ CHARACTER AND ATTRIBUTE AT CURSOR POSITION
@@ -223,7 +313,9 @@ impl Component for GPU {
                 // AH = character's attribute (text mode only) (see #00014)
                 // AH = character's color (Tandy 2000 graphics mode only)
                 // AL = character
-                println!("XXX int10 - READ CHARACTER AND ATTRIBUTE AT CURSOR POSITION, page {}", page);
+                let (chr, attr) = self.read_char_attr(mmu, page);
+                cpu.set_r8(R::AL, chr);
+                cpu.set_r8(R::AH, attr);
             }
             0x09 => {
                 // VIDEO - WRITE CHARACTER AND ATTRIBUTE AT CURSOR POSITION
@@ -250,7 +342,7 @@ impl Component for GPU {
                         // VIDEO - SET BACKGROUND/BORDER COLOR
                         // BL = background/border color (border only in text modes)
                         // Return: Nothing
-                        println!("XXX set bg/border color, bl={:02X}", cpu.get_r8(R::BL));
+                        warn!(target: "gpu", "XXX set bg/border color, bl={:02X}", cpu.get_r8(R::BL));
                     }
                     0x01 => {
                         // VIDEO - SET PALETTE
@@ -262,10 +354,10 @@ impl Component for GPU {
                         // Note: This call was only valid in 320x200 graphics on
                         // the CGA, but newer cards support it in many or all
                         // graphics modes
-                        println!("XXX TODO set palette id, bl={:02X}", cpu.get_r8(R::BL));
+                        warn!(target: "gpu", "XXX TODO set palette id, bl={:02X}", cpu.get_r8(R::BL));
                     }
                     _ => {
-                        println!("video error: unknown int 10, ah=0B, bh={:02X}", cpu.get_r8(R::BH));
+                        warn!(target: "gpu", "video error: unknown int 10, ah=0B, bh={:02X}", cpu.get_r8(R::BH));
                     }
                 }
             }
@@ -297,15 +389,45 @@ impl Component for GPU {
                         // BL = palette register number (00h-0Fh)
                         //    = attribute register number (undocumented) (see #00017)
                         // BH = color or attribute register value
-                        println!("XXX VIDEO - SET SINGLE PALETTE REGISTER, bl={:02X}, bh={:02X}",
-                                cpu.get_r8(R::BL),
-                                cpu.get_r8(R::BH));
+                        let reg = cpu.get_r8(R::BL);
+                        let value = cpu.get_r8(R::BH);
+                        self.set_single_palette_register(reg, value);
+                    }
+                    0x01 => {
+                        // VIDEO - SET BORDER (OVERSCAN) COLOR (PCjr,Tandy,EGA,VGA)
+                        self.actl.overscan = cpu.get_r8(R::BH);
+                    }
+                    0x02 => {
+                        // VIDEO - SET ALL PALETTE REGISTERS (PCjr,Tandy,EGA,VGA)
+                        // ES:DX -> palette register list: 16 palette registers and the border color
+                        let seg = cpu.get_r16(R::ES);
+                        let off = cpu.get_r16(R::DX);
+                        let data = mmu.read(seg, off, 17);
+                        self.actl.palette.copy_from_slice(&data[..16]);
+                        self.actl.overscan = data[16];
+                    }
+                    0x03 => {
+                        // VIDEO - TOGGLE INTENSITY/BLINKING BIT (Jr, PS, TANDY 1000, EGA, VGA)
+                        // BL = 00h background intensity enabled, 01h blink enabled
+                        self.set_blink(mmu, cpu.get_r8(R::BL) != 0);
                     }
                     0x07 => {
                         // VIDEO - GET INDIVIDUAL PALETTE REGISTER (VGA,UltraVision v2+)
                         let reg = cpu.get_r8(R::BL);
                         cpu.set_r8(R::BH, self.get_individual_palette_register(reg));
                     }
+                    0x08 => {
+                        // VIDEO - READ OVERSCAN (BORDER COLOR) REGISTER (VGA,UltraVision v2+)
+                        cpu.set_r8(R::BH, self.actl.overscan);
+                    }
+                    0x09 => {
+                        // VIDEO - READ ALL PALETTE REGISTERS AND OVERSCAN REGISTER (VGA)
+                        // ES:DX -> 17-byte buffer
+                        let seg = cpu.get_r16(R::ES);
+                        let off = cpu.get_r16(R::DX);
+                        mmu.write(seg, off, &self.actl.palette);
+                        mmu.write_u8(seg, off + 16, self.actl.overscan);
+                    }
                     0x10 => {
                         // VIDEO - SET INDIVIDUAL DAC REGISTER (VGA/MCGA)
                         let index = cpu.get_r8(R::BL);
@@ -339,12 +461,89 @@ impl Component for GPU {
                         self.read_dac_block(mmu, index, count, seg, off);
                     }
                     _ => {
-                        println!("int10 error: unknown AH 10, al={:02X}", cpu.get_r8(R::AL));
+                        warn!(target: "gpu", "int10 error: unknown AH 10, al={:02X}", cpu.get_r8(R::AL));
                     }
                 }
             }
             0x11 => {
-                match cpu.get_r8(R::AL) {
+                let al = cpu.get_r8(R::AL);
+                match al {
+                    0x00 | 0x10 => {
+                        // VIDEO - TEXT-MODE CHARGEN - LOAD USER-SPECIFIED PATTERNS (PS,EGA,VGA)
+                        // AX = 1110h also reprograms the display for the new character height
+                        // ES:BP -> user table
+                        // CX = count of patterns to store
+                        // DX = character offset into map 2 block
+                        // BL = block to load in map 2
+                        // BH = number of bytes per character pattern
+                        let seg = cpu.get_r16(R::ES);
+                        let off = cpu.get_r16(R::BP);
+                        let request = UserFontRequest {
+                            count: cpu.get_r16(R::CX),
+                            first: cpu.get_r16(R::DX),
+                            block: cpu.get_r8(R::BL),
+                            height: cpu.get_r8(R::BH),
+                            reprogram: al == 0x10,
+                        };
+                        self.load_user_text_font(mmu, seg, off, &request);
+                    }
+                    0x01 | 0x11 => {
+                        // VIDEO - TEXT-MODE CHARGEN - LOAD ROM MONOCHROME PATTERNS (PS,EGA,VGA)
+                        // BL = block to load
+                        let block = cpu.get_r8(R::BL);
+                        self.load_rom_text_font(mmu, block, 14, al == 0x11);
+                    }
+                    0x02 | 0x12 => {
+                        // VIDEO - TEXT-MODE CHARGEN - LOAD ROM 8x8 DBL-DOT PATTERNS (PS,EGA,VGA)
+                        // BL = block to load
+                        // NOTE: AX = 1112h gives 43 lines on EGA and 50 lines on VGA in mode 03h
+                        let block = cpu.get_r8(R::BL);
+                        self.load_rom_text_font(mmu, block, 8, al == 0x12);
+                    }
+                    0x03 => {
+                        // VIDEO - TEXT-MODE CHARGEN - SET BLOCK SPECIFIER (PS,EGA,VGA)
+                        // BL = block specifier
+                        let bl = cpu.get_r8(R::BL);
+                        self.chargen.set_map_select(bl);
+                    }
+                    0x04 | 0x14 => {
+                        // VIDEO - TEXT-MODE CHARGEN - LOAD ROM 8x16 CHARACTER SET (VGA)
+                        // BL = block to load
+                        let block = cpu.get_r8(R::BL);
+                        self.load_rom_text_font(mmu, block, 16, al == 0x14);
+                    }
+                    0x20 => {
+                        // VIDEO - GRAPH-MODE CHARGEN - SET USER 8x8 GRAPHICS CHARS (PS,EGA,VGA)
+                        // ES:BP -> user table for characters 80h-FFh
+                        let seg = cpu.get_r16(R::ES);
+                        let off = cpu.get_r16(R::BP);
+                        mmu.write_vec(0x1F, MemoryAddress::RealSegmentOffset(seg, off));
+                    }
+                    0x21 => {
+                        // VIDEO - GRAPH-MODE CHARGEN - SET USER GRAPHICS CHARACTERS (PS,EGA,VGA)
+                        // ES:BP -> user font table
+                        // CX = bytes per character
+                        // BL = row specifier
+                        // DL = number of rows (if BL=00h)
+                        let seg = cpu.get_r16(R::ES);
+                        let off = cpu.get_r16(R::BP);
+                        let height = cpu.get_r16(R::CX);
+                        let bl = cpu.get_r8(R::BL);
+                        let dl = cpu.get_r8(R::DL);
+                        self.set_graphics_chars(mmu, MemoryAddress::RealSegmentOffset(seg, off), height, bl, dl);
+                    }
+                    0x22 => {
+                        // VIDEO - GRAPH-MODE CHARGEN - ROM 8x14 CHARACTER SET (PS,EGA,VGA)
+                        let bl = cpu.get_r8(R::BL);
+                        let dl = cpu.get_r8(R::DL);
+                        self.set_graphics_chars(mmu, self.font_14, 14, bl, dl);
+                    }
+                    0x23 => {
+                        // VIDEO - GRAPH-MODE CHARGEN - ROM 8x8 DBL-DOT CHARACTER SET (PS,EGA,VGA)
+                        let bl = cpu.get_r8(R::BL);
+                        let dl = cpu.get_r8(R::DL);
+                        self.set_graphics_chars(mmu, self.font_8_first, 8, bl, dl);
+                    }
                     0x24 => {
                         // VIDEO - GRAPH-MODE CHARGEN - LOAD 8x16 GRAPHICS CHARS (VGA,MCGA)
                         let bl = cpu.get_r8(R::BL);
@@ -358,13 +557,19 @@ impl Component for GPU {
                         // CX    = bytes/character of on-screen font (not the requested font!)
                         // DL    = highest character row on screen
                         let bh = cpu.get_r8(R::BH);
+                        cpu.set_r16(R::CX, u16::from(mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_CHAR_HEIGHT)));
+                        cpu.set_r8(R::DL, mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_NB_ROWS));
                         match bh { // BH = pointer specifier
                             0x00 => { // INT 1Fh pointer
                                 let (seg, off) = mmu.read_vec(0x1F);
                                 cpu.set_r16(R::ES, seg);
                                 cpu.set_r16(R::BP, off);
                             }
-                            // 01h INT 43h pointer
+                            0x01 => { // INT 43h pointer
+                                let (seg, off) = mmu.read_vec(0x43);
+                                cpu.set_r16(R::ES, seg);
+                                cpu.set_r16(R::BP, off);
+                            }
                             0x02 => {
                                 // ROM 8x14 character font pointer
                                 if let MemoryAddress::RealSegmentOffset(seg, off) = self.font_14 {
@@ -372,9 +577,27 @@ impl Component for GPU {
                                     cpu.set_r16(R::BP, off);
                                 }
                             }
-                            // 03h ROM 8x8 double dot font pointer
-                            // 04h ROM 8x8 double dot font (high 128 characters)
-                            // 05h ROM alpha alternate (9 by 14) pointer (EGA,VGA)
+                            0x03 => {
+                                // ROM 8x8 double dot font pointer
+                                if let MemoryAddress::RealSegmentOffset(seg, off) = self.font_8_first {
+                                    cpu.set_r16(R::ES, seg);
+                                    cpu.set_r16(R::BP, off);
+                                }
+                            }
+                            0x04 => {
+                                // ROM 8x8 double dot font (high 128 characters)
+                                if let MemoryAddress::RealSegmentOffset(seg, off) = self.font_8_second {
+                                    cpu.set_r16(R::ES, seg);
+                                    cpu.set_r16(R::BP, off);
+                                }
+                            }
+                            0x05 => {
+                                // ROM alpha alternate (9 by 14) pointer (EGA,VGA)
+                                if let MemoryAddress::RealSegmentOffset(seg, off) = self.font_14_alternate {
+                                    cpu.set_r16(R::ES, seg);
+                                    cpu.set_r16(R::BP, off);
+                                }
+                            }
                             0x06 => {
                                 // ROM 8x16 font (MCGA, VGA)
                                 if self.card.is_vga() {
@@ -384,17 +607,25 @@ impl Component for GPU {
                                     }
                                 }
                             }
-                            // 07h ROM alternate 9x16 font (VGA only) (see #00021)
+                            0x07 => {
+                                // ROM alternate 9x16 font (VGA only) (see #00021)
+                                if self.card.is_vga() {
+                                    if let MemoryAddress::RealSegmentOffset(seg, off) = self.font_16_alternate {
+                                        cpu.set_r16(R::ES, seg);
+                                        cpu.set_r16(R::BP, off);
+                                    }
+                                }
+                            }
                             // 11h (UltraVision v2+) 8x20 font (VGA) or 8x19 font (autosync EGA)
                             // 12h (UltraVision v2+) 8x10 font (VGA) or 8x11 font (autosync EGA)
                             _ => {
-                                println!("VIDEO - GET FONT INFORMATION (EGA, MCGA, VGA): unhandled bh={:02X}", bh);
+                                warn!(target: "gpu", "VIDEO - GET FONT INFORMATION (EGA, MCGA, VGA): unhandled bh={:02X}", bh);
                                 return false;
                             }
                         }
                     }
                     _ => {
-                        println!("int10 error: unknown ah=11, al={:02X}", cpu.get_r8(R::AL));
+                        warn!(target: "gpu", "int10 error: unknown ah=11, al={:02X}", cpu.get_r8(R::AL));
                         return false;
                     }
                 }
@@ -419,22 +650,24 @@ impl Component for GPU {
                         cpu.set_r8(R::CL, 9);
                     }
                     _ => {
-                        println!("int10 error: unknown ah=12, bl={:02X}", cpu.get_r8(R::BL));
+                        warn!(target: "gpu", "int10 error: unknown ah=12, bl={:02X}", cpu.get_r8(R::BL));
                         return false;
                     }
                 }
             }
             0x13 => {
                 // VIDEO - WRITE STRING (AT and later,EGA)
-                let row = cpu.get_r8(R::DH);
-                let col = cpu.get_r8(R::DL);
-                let flag = cpu.get_r8(R::AL);
-                let attr = cpu.get_r8(R::BL);
-                let str_seg = cpu.get_r16(R::ES);
-                let str_offs = cpu.get_r16(R::BP);
-                let count = cpu.get_r16(R::CX);
-                let page = cpu.get_r8(R::BH);
-                self.write_string(mmu, row, col, flag, attr, str_seg, str_offs, count, page);
+                let request = WriteStringRequest {
+                    row: cpu.get_r8(R::DH),
+                    col: cpu.get_r8(R::DL),
+                    flag: cpu.get_r8(R::AL),
+                    attr: cpu.get_r8(R::BL),
+                    page: cpu.get_r8(R::BH),
+                    seg: cpu.get_r16(R::ES),
+                    off: cpu.get_r16(R::BP),
+                    count: cpu.get_r16(R::CX),
+                };
+                self.write_string(mmu, &request);
             }
             0x1A => {
                 match cpu.get_r8(R::AL) {
@@ -449,10 +682,36 @@ impl Component for GPU {
                         cpu.set_r8(R::BH, 0x00); // 00 = no display
                     }
                     _ => {
-                        println!("int10 error: unknown ah=1a, al={:02X}", cpu.get_r8(R::AL));
+                        warn!(target: "gpu", "int10 error: unknown ah=1a, al={:02X}", cpu.get_r8(R::AL));
+                        return false;
+                    }
+                }
+            }
+            0x1C => {
+                // VIDEO - SAVE/RESTORE VIDEO STATE (PS50+,VGA)
+                // CX = requested states
+                //      bit 0: video hardware
+                //      bit 1: BIOS data areas
+                //      bit 2: color registers and DAC state
+                // AL = 00h return state buffer size
+                //      Return: BX = number of 64-byte blocks needed
+                // AL = 01h save video state
+                //      ES:BX -> buffer
+                // AL = 02h restore video state
+                //      ES:BX -> buffer containing previously saved state
+                // Return: AL = 1Ch if function supported
+                let states = cpu.get_r16(R::CX);
+                let (seg, off) = (cpu.get_r16(R::ES), cpu.get_r16(R::BX));
+                match cpu.get_r8(R::AL) {
+                    0x00 => cpu.set_r16(R::BX, video_state_blocks(states)),
+                    0x01 => self.save_video_state(mmu, states, seg, off),
+                    0x02 => self.restore_video_state(mmu, states, seg, off),
+                    _ => {
+                        warn!(target: "gpu", "int10 error: unknown ah=1c, al={:02X}", cpu.get_r8(R::AL));
                         return false;
                     }
                 }
+                cpu.set_r8(R::AL, 0x1C);
             }
             0x4F => {
                 // VESA
@@ -466,7 +725,7 @@ impl Component for GPU {
                         // AH = status:
                         //      00h successful, ES:DI buffer filled
                         //      01h failed
-                        println!("XXX VESA SuperVGA BIOS - GET SuperVGA MODE INFORMATION. cx={:04X}", cpu.get_r16(R::CX));
+                        warn!(target: "gpu", "XXX VESA SuperVGA BIOS - GET SuperVGA MODE INFORMATION. cx={:04X}", cpu.get_r16(R::CX));
                     }
                     0x02 => {
                         // VESA SuperVGA BIOS - SET SuperVGA VIDEO MODE
@@ -477,7 +736,12 @@ impl Component for GPU {
                         // AH = status
                         //      00h successful
                         //      01h failed
-                        println!("XXX VESA SuperVGA BIOS - SET SuperVGA VIDEO MODE. bx={:04X}", cpu.get_r16(R::BX));
+                        if self.svga.chipset == SvgaChipset::None {
+                            warn!(target: "gpu", "XXX VESA SuperVGA BIOS - SET SuperVGA VIDEO MODE. bx={:04X}", cpu.get_r16(R::BX));
+                        } else {
+                            let ok = self.set_vesa_mode(mmu, cpu.get_r16(R::BX));
+                            cpu.set_r16(R::AX, if ok { 0x004F } else { 0x014F });
+                        }
                     }
                     0x05 => {
                         // VESA SuperVGA BIOS - CPU VIDEO MEMORY CONTROL
@@ -491,16 +755,28 @@ impl Component for GPU {
                         //      00h window A
                         //      01h window B.
                         // ES = selector for memory-mapped registers (VBE 2.0+, when called from 32-bit protected mode)
-                        println!("XXX VESA SuperVGA BIOS - CPU VIDEO MEMORY CONTROL. bh={:02X}", cpu.get_r8(R::BH));
+                        if self.svga.chipset == SvgaChipset::None {
+                            warn!(target: "gpu", "XXX VESA SuperVGA BIOS - CPU VIDEO MEMORY CONTROL. bh={:02X}", cpu.get_r8(R::BH));
+                        } else {
+                            // window A and B both map the read and write bank, in 64K granularity
+                            match cpu.get_r8(R::BH) {
+                                0x00 => {
+                                    self.svga.set_bank(cpu.get_r16(R::DX) as u8);
+                                    self.update_banks();
+                                }
+                                _ => cpu.set_r16(R::DX, self.svga.write_bank() as u16),
+                            }
+                            cpu.set_r16(R::AX, 0x004F);
+                        }
                     }
                     _ => {
-                        println!("int10 error: unknown AH 4F (VESA), al={:02X}", cpu.get_r8(R::AL));
+                        warn!(target: "gpu", "int10 error: unknown AH 4F (VESA), al={:02X}", cpu.get_r8(R::AL));
                         return false;
                     }
                 }
             }
             _ => {
-                println!("int10 (video) error: unknown ah={:02X}, ax={:04X}, bx={:04X}",
+                warn!(target: "gpu", "int10 (video) error: unknown ah={:02X}, ax={:04X}, bx={:04X}",
                         cpu.get_r8(R::AH),
                         cpu.get_r16(R::AX),
                         cpu.get_r16(R::BX));
@@ -513,11 +789,88 @@ impl Component for GPU {
 }
 
 
+/// character patterns loaded by int 10h, ax = 1100h and 1110h
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserFontRequest {
+    /// number of character patterns
+    pub count: u16,
+
+    /// first character to replace
+    pub first: u16,
+
+    /// character generator block to load into
+    pub block: u8,
+
+    /// bytes per character pattern
+    pub height: u8,
+
+    /// reprograms the display for the new character height
+    pub reprogram: bool,
+}
+
+/// a string written by int 10h, ah = 13h
+#[derive(Clone, Debug, PartialEq)]
+pub struct WriteStringRequest {
+    /// cursor position of the first character, row 0xFF uses the current cursor position
+    pub row: u8,
+    pub col: u8,
+
+    /// bit 0 = update cursor after writing, bit 1 = string holds character and attribute pairs
+    pub flag: u8,
+
+    /// attribute of the characters, unless they are in the string
+    pub attr: u8,
+
+    pub page: u8,
+
+    /// address of the string
+    pub seg: u16,
+    pub off: u16,
+
+    /// number of characters
+    pub count: u16,
+}
+
 #[derive(Clone)]
 pub struct GPU {
     pub scanline: u32,
+
+    /// if set, palette and line compare changes done mid-frame are rendered from the scanline they
+    /// occured on (raster bars, split screens). otherwise the state at the start of the frame is used
+    pub scanline_palette: bool,
+
+    /// called when the beam starts drawing each scanline, see `add_scanline_hook`
+    scanline_hooks: Vec<ScanlineHook>,
+
+    /// vertical refresh rate in Hz, used for timing of the input status register (0x03DA)
+    pub refresh_rate: usize,
+
+    /// number of frames completed since power on, incremented at start of vertical retrace
+    pub frame_count: u64,
+
+    /// emulated cpu clock, set by `update`
+    clock_hz: usize,
+
+    /// emulated cpu cycles into the current frame
+    frame_cycle: usize,
+
     pub crtc: CRTC,
+    pub actl: ACTL,
+
+    /// CRTC state recorded by scanline
+    pub raster: Raster,
+
+    /// transform of the rendered colors, simulating the monitor
+    pub color: ColorEmulation,
+
+    /// video memory of the 16 color and SVGA modes, also accessed by the cpu through a memory hook
+    pub planar: Arc<Mutex<PlanarMemory>>,
+
+    /// extended registers of the emulated SVGA chipset
+    pub svga: Svga,
+
     pub dac: DAC,
+    pub chargen: CharGen,
     font_8_first: MemoryAddress,
     font_8_second: MemoryAddress,
     pub font_14: MemoryAddress,
@@ -530,37 +883,135 @@ pub struct GPU {
     pub card: GraphicCard,
     pub mode: VideoModeBlock,
     modes: Vec<VideoModeBlock>,
+
+    /// state the last frame updated by `changed_scanlines` was rendered with
+    last_render: Option<RenderState>,
+
+    /// front and back buffer of `present_frame`
+    frames: [VideoFrame; 2],
+    front: usize,
+
+    /// scanlines changed by the last `present_frame`, which are not yet in the back buffer
+    back_changed: Vec<u32>,
+}
+
+/// state besides video memory that a rendered frame depends on
+#[derive(Clone, PartialEq)]
+struct RenderState {
+    mode: u16,
+    color: ColorEmulation,
+    raster: Vec<ScanlineCrtc>,
+    actl: ACTL,
+    palettes: Vec<ScanlinePalette>,
+    chargen_blocks: (u8, u8),
+    chargen_generation: u64,
+}
+
+/// writes RGBA pixels left to right, top to bottom into a buffer with rows of `pitch` bytes,
+/// such as a locked texture
+struct FrameWriter<'a> {
+    buf: &'a mut [u8],
+    width: usize,
+    pitch: usize,
+    x: usize,
+    row: usize,
+}
+
+impl<'a> FrameWriter<'a> {
+    fn new(buf: &'a mut [u8], width: usize, pitch: usize) -> Self {
+        FrameWriter { buf, width, pitch, x: 0, row: 0 }
+    }
+
+    fn push(&mut self, color: &ColorSpace) {
+        let offset = self.row * self.pitch + self.x * FRAME_BYTES_PER_PIXEL;
+        let rgba = match color {
+            ColorSpace::RGB(r, g, b) => [*r, *g, *b, 0xFF],
+            ColorSpace::None => [0, 0, 0, 0xFF],
+        };
+        self.buf[offset..offset + FRAME_BYTES_PER_PIXEL].copy_from_slice(&rgba);
+        self.x += 1;
+        if self.x == self.width {
+            self.x = 0;
+            self.row += 1;
+        }
+    }
 }
 
+/// number of bytes of each pixel of a video frame
+pub const FRAME_BYTES_PER_PIXEL: usize = 4;
+
+#[derive(Clone)]
 pub struct VideoFrame {
-    pub data: Vec<ColorSpace>,
+    /// packed RGBA pixels, `stride` bytes per row. empty if the mode can't be rendered
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+
+    /// number of bytes of each row in `data`
+    pub stride: usize,
+
     pub mode: VideoModeBlock,
 }
 
 impl VideoFrame {
+    /// returns an empty frame of `mode`
+    pub fn new(mode: &VideoModeBlock) -> Self {
+        VideoFrame {
+            data: Vec::new(),
+            width: mode.swidth,
+            height: mode.sheight,
+            stride: mode.swidth as usize * FRAME_BYTES_PER_PIXEL,
+            mode: mode.clone(),
+        }
+    }
+
+    /// returns true if the frame has the size and mode of `mode`
+    fn is_mode(&self, mode: &VideoModeBlock) -> bool {
+        self.mode.mode == mode.mode && self.width == mode.swidth && self.height == mode.sheight
+            && self.data.len() == self.stride * self.height as usize
+    }
+
+    /// returns the color of the pixel at `x`, `y`
+    pub fn pixel(&self, x: u32, y: u32) -> ColorSpace {
+        let offset = y as usize * self.stride + x as usize * FRAME_BYTES_PER_PIXEL;
+        ColorSpace::RGB(self.data[offset], self.data[offset + 1], self.data[offset + 2])
+    }
+
     /// converts a video frame to a ImageBuffer, used for saving video frame to disk in gpu_test
     pub fn draw_image(&self) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
-        ImageBuffer::from_fn(self.mode.swidth, self.mode.sheight, |x, y| {
-            let offset = ((y * self.mode.swidth) + x) as usize;
-            if let ColorSpace::RGB(r, g, b) = self.data[offset] {
-                Rgb([r, g, b])
-            } else {
-                println!("error unhandled colorspace not RGB");
-                Rgb([0, 0, 0])
-            }
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            let offset = y as usize * self.stride + x as usize * FRAME_BYTES_PER_PIXEL;
+            Rgb([self.data[offset], self.data[offset + 1], self.data[offset + 2]])
         })
     }
 }
 
 impl GPU {
     pub fn default() -> Self {
-        let generation = GraphicCard::VGA;
+        Self::with_card(GraphicCard::VGA)
+    }
+
+    /// returns a GPU emulating `generation`. only VGA has video modes
+    pub fn with_card(generation: GraphicCard) -> Self {
         let modes = VideoModeBlock::get_mode_block(&generation);
         let mode = modes[3].clone();
+        let frames = [VideoFrame::new(&mode), VideoFrame::new(&mode)];
         GPU {
             scanline: 0,
+            scanline_palette: false,
+            scanline_hooks: Vec::new(),
+            refresh_rate: 70,
+            frame_count: 0,
+            clock_hz: 5_000_000,
+            frame_cycle: 0,
             crtc: CRTC::default(),
+            actl: ACTL::default(),
+            raster: Raster::new(&CRTC::default()),
+            color: ColorEmulation::default(),
+            planar: Arc::new(Mutex::new(PlanarMemory::default())),
+            svga: Svga::new(SvgaChipset::None),
             dac: DAC::default(),
+            chargen: CharGen::default(),
             font_8_first: MemoryAddress::Unset,
             font_8_second: MemoryAddress::Unset,
             font_14: MemoryAddress::Unset,
@@ -573,54 +1024,304 @@ impl GPU {
             card: generation,
             mode,
             modes,
+            last_render: None,
+            frames,
+            front: 0,
+            back_changed: Vec::new(),
         }
     }
 
+    /// emulates the extended registers and banked video memory of `chipset`,
+    /// and adds its 256 color modes to the VGA modes
+    pub fn set_svga(&mut self, chipset: SvgaChipset) {
+        self.svga = Svga::new(chipset);
+        self.modes.retain(|block| block.kind != GFXMode::LIN8);
+        let mut planar = self.planar.lock().unwrap();
+        if chipset == SvgaChipset::None || !self.card.is_vga() {
+            planar.linear = Vec::new();
+            return;
+        }
+        self.modes.extend_from_slice(&svga_mode_block());
+        planar.linear = vec![0; SVGA_MEMORY_SIZE];
+    }
+
+    // 03B4/03D4: CRTC index register, also selecting the extended registers of the SVGA chipset
+    fn write_crtc_index(&mut self, data: u8) {
+        self.crtc.set_index(data);
+        self.svga.set_crtc_index(data);
+    }
+
+    // 03B5/03D5: CRTC data register
+    fn write_crtc_data(&mut self, data: u8) {
+        if self.svga.is_extended_crtc() {
+            self.svga.write_crtc(data);
+            self.update_banks();
+        } else {
+            self.crtc.write_current(data);
+        }
+    }
+
+    fn read_crtc_data(&self) -> u8 {
+        if self.svga.is_extended_crtc() {
+            self.svga.read_crtc()
+        } else {
+            self.crtc.read_current()
+        }
+    }
+
+    /// maps the banks selected in the SVGA chipset at A000:0000
+    fn update_banks(&mut self) {
+        let mut planar = self.planar.lock().unwrap();
+        planar.read_bank = self.svga.read_bank();
+        planar.write_bank = self.svga.write_bank();
+    }
+
     pub fn render_frame(&self, mmu: &MMU) -> VideoFrame {
-        VideoFrame{
-            data: match self.mode.mode {
-                // 00: 40x25 Black and White text (CGA,EGA,MCGA,VGA)
-                // 01: 40x25 16 color text (CGA,EGA,MCGA,VGA)
-                // 02: 80x25 16 shades of gray text (CGA,EGA,MCGA,VGA)
-                //0x03 => self.render_mode03_frame(memory), // 80x25 16 color text (CGA,EGA,MCGA,VGA)
-                0x04 => self.render_mode04_frame(&mmu.memory.data),
-                // 05: 320x200 4 color graphics (CGA,EGA,MCGA,VGA)
-                //0x06 => self.render_mode06_frame(memory), // 640x200 B/W graphics (CGA,EGA,MCGA,VGA)
-                // 07: 80x25 Monochrome text (MDA,HERC,EGA,VGA)
-                // 08: 160x200 16 color graphics (PCjr)
-                // 09: 320x200 16 color graphics (PCjr)
-                // 0A: 640x200 4 color graphics (PCjr)
-                // 0D: 320x200 16 color graphics (EGA,VGA)
-                // 0E: 640x200 16 color graphics (EGA,VGA)
-                // 0F: 640x350 Monochrome graphics (EGA,VGA)
-                // 10: 640x350 16 color graphics (EGA or VGA with 128K)
-                //     640x350 4 color graphics (64K EGA)
-                0x11 => self.render_mode11_frame(&mmu.memory.data),
-                //0x12 => self.render_mode12_frame(&memory), // 640x480 16 color graphics (VGA)
-                0x13 => self.render_mode13_frame(&mmu.memory.data),
-                _ => {
-                    println!("XXX fixme render_frame for mode {:02x}", self.mode.mode);
-                    Vec::new()
-                }
+        let mut frame = VideoFrame::new(&self.mode);
+        frame.data = vec![0; frame.stride * frame.height as usize];
+        if !self.render_frame_into(mmu, &mut frame.data, frame.stride) {
+            warn!(target: "gpu", "XXX fixme render_frame for mode {:02x}", self.mode.mode);
+            frame.data.clear();
+        }
+        frame
+    }
+
+    /// renders the current frame as RGBA pixels into `buf`, with rows of `pitch` bytes.
+    /// returns false if the mode can't be rendered or `buf` is too small
+    pub fn render_frame_into(&self, mmu: &MMU, buf: &mut [u8], pitch: usize) -> bool {
+        self.render_scanlines_into(mmu, 0..self.mode.sheight, buf, pitch)
+    }
+
+    /// renders the scanlines `lines` as RGBA pixels into `buf`, with rows of `pitch` bytes
+    /// starting with the first line. returns false if the mode can't be rendered or `buf` is too small
+    pub fn render_scanlines_into(&self, mmu: &MMU, lines: Range<u32>, buf: &mut [u8], pitch: usize) -> bool {
+        let width = self.mode.swidth as usize;
+        let row_bytes = width * FRAME_BYTES_PER_PIXEL;
+        let count = lines.end.min(self.mode.sheight).saturating_sub(lines.start) as usize;
+        if count == 0 || pitch < row_bytes || buf.len() < (count - 1) * pitch + row_bytes {
+            return false;
+        }
+        let lines = lines.start..lines.start + count as u32;
+        if !self.render_lines(&mmu.memory.data, lines, &mut FrameWriter::new(buf, width, pitch)) {
+            return false;
+        }
+        self.color.apply_rgba(buf, width, count, pitch);
+        true
+    }
+
+    /// returns the scanlines that changed since the last call, where `dirty` is the memory written
+    /// to since then. all scanlines are changed if the mode, palette or other display state changed
+    pub fn changed_scanlines(&mut self, dirty: &[Range<u32>]) -> Vec<u32> {
+        let state = self.render_state();
+        let full = self.last_render.as_ref() != Some(&state);
+        self.last_render = Some(state);
+        if full {
+            return (0..self.mode.sheight).collect();
+        }
+        (0..self.mode.sheight)
+            .filter(|y| match self.scanline_memory(*y) {
+                Some(mem) => dirty.iter().any(|d| d.start < mem.end && mem.start < d.end),
+                None => false,
+            })
+            .collect()
+    }
+
+    /// re-renders the scanlines of `frame` that changed since it was rendered, where `dirty` is
+    /// the memory written to since then. returns the scanlines that was rendered
+    pub fn update_frame(&mut self, mmu: &MMU, frame: &mut VideoFrame, dirty: &[Range<u32>]) -> Vec<u32> {
+        let mut changed = self.changed_scanlines(dirty);
+        if !frame.is_mode(&self.mode) {
+            changed = (0..self.mode.sheight).collect();
+        }
+        if !self.render_changed(mmu, frame, &changed) {
+            return Vec::new();
+        }
+        changed
+    }
+
+    /// renders the scanlines changed since the last call into the back buffer, which then becomes
+    /// the front buffer returned by `front_frame`. `dirty` is the memory written to since the last call.
+    /// returns the scanlines that differ from the previous front buffer
+    pub fn present_frame(&mut self, mmu: &MMU, dirty: &[Range<u32>]) -> Vec<u32> {
+        let changed = self.changed_scanlines(dirty);
+        // the back buffer also lacks the changes of the previous front buffer
+        let mut lines = changed.clone();
+        lines.extend_from_slice(&self.back_changed);
+        lines.sort_unstable();
+        lines.dedup();
+
+        let back = 1 - self.front;
+        let mut frame = std::mem::replace(&mut self.frames[back], VideoFrame::new(&self.mode));
+        if !frame.is_mode(&self.mode) {
+            lines = (0..self.mode.sheight).collect();
+        }
+        let rendered = self.render_changed(mmu, &mut frame, &lines);
+        self.frames[back] = frame;
+        self.front = back;
+        self.back_changed = changed.clone();
+        if !rendered {
+            return Vec::new();
+        }
+        changed
+    }
+
+    /// returns the frame last rendered by `present_frame`
+    pub fn front_frame(&self) -> &VideoFrame {
+        &self.frames[self.front]
+    }
+
+    /// renders `lines` (in ascending order) of `frame`, resizing it to the current mode.
+    /// returns false if the mode can't be rendered
+    fn render_changed(&self, mmu: &MMU, frame: &mut VideoFrame, lines: &[u32]) -> bool {
+        if !frame.is_mode(&self.mode) {
+            let mut data = std::mem::take(&mut frame.data);
+            *frame = VideoFrame::new(&self.mode);
+            data.resize(frame.stride * frame.height as usize, 0);
+            frame.data = data;
+        }
+        let mut i = 0;
+        while i < lines.len() {
+            // render consecutive scanlines together
+            let first = lines[i];
+            let mut count = 1;
+            while i + count < lines.len() && lines[i + count] == first + count as u32 {
+                count += 1;
+            }
+            i += count;
+            let start = first as usize * frame.stride;
+            if !self.render_scanlines_into(mmu, first..first + count as u32, &mut frame.data[start..], frame.stride) {
+                frame.data.clear();
+                return false;
+            }
+        }
+        true
+    }
+
+    fn render_state(&self) -> RenderState {
+        let mut palettes = vec!(ScanlinePalette{scanline: 0, pal: self.dac.frame_palette().to_vec()});
+        if self.scanline_palette {
+            palettes.extend_from_slice(self.dac.latched_palettes());
+        }
+        RenderState {
+            mode: self.mode.mode,
+            color: self.color,
+            raster: if self.scanline_palette {
+                self.raster.latched().to_vec()
+            } else {
+                vec!(self.line_crtc(0))
             },
-            mode: self.mode.clone(),
+            actl: ACTL { index: 0, flip_flop: false, ..self.actl.clone() },
+            palettes,
+            chargen_blocks: (self.chargen.block_a, self.chargen.block_b),
+            chargen_generation: self.chargen.generation,
         }
     }
-/*
-    fn render_mode03_frame(&self, memory: &[u8]) -> Vec<u8> {
+
+    /// renders `lines` of the current mode as RGBA pixels to `buf`,
+    /// returns false if the mode can't be rendered
+    fn render_lines(&self, memory: &[u8], lines: Range<u32>, buf: &mut FrameWriter) -> bool {
+        match self.mode.mode {
+            // 00: 40x25 Black and White text (CGA,EGA,MCGA,VGA)
+            // 01: 40x25 16 color text (CGA,EGA,MCGA,VGA)
+            // 02: 80x25 16 shades of gray text (CGA,EGA,MCGA,VGA)
+            // 03: 80x25 16 color text (CGA,EGA,MCGA,VGA)
+            // 07: 80x25 Monochrome text (MDA,HERC,EGA,VGA)
+            0x00..=0x03 | 0x07 => self.render_text_frame(memory, lines, buf),
+            0x04..=0x06 if self.color.monitor == Monitor::Composite => self.render_composite_frame(memory, lines, buf),
+            // 05: 320x200 4 color graphics (CGA,EGA,MCGA,VGA)
+            0x04 | 0x05 => self.render_mode04_frame(memory, lines, buf),
+            // 06: 640x200 B/W graphics (CGA,EGA,MCGA,VGA)
+            0x06 => self.render_mode06_frame(memory, lines, buf),
+            // 08: 160x200 16 color graphics (PCjr)
+            // 09: 320x200 16 color graphics (PCjr)
+            // 0A: 640x200 4 color graphics (PCjr)
+            // 0D: 320x200 16 color graphics (EGA,VGA)
+            // 0E: 640x200 16 color graphics (EGA,VGA)
+            // 0F: 640x350 Monochrome graphics (EGA,VGA)
+            // 10: 640x350 16 color graphics (EGA or VGA with 128K)
+            //     640x350 4 color graphics (64K EGA)
+            // 12: 640x480 16 color graphics (VGA)
+            0x0D..=0x10 | 0x12 => self.render_planar_frame(lines, buf),
+            0x11 => self.render_mode11_frame(memory, lines, buf),
+            0x13 => self.render_mode13_frame(memory, lines, buf),
+            _ if self.mode.kind == GFXMode::LIN8 => self.render_linear_frame(lines, buf),
+            _ => return false,
+        }
+        true
+    }
+
+    /// returns the video memory displayed on scanline `y`, None if unknown
+    fn scanline_memory(&self, y: u32) -> Option<Range<u32>> {
+        let (start, len) = match self.mode.mode {
+            0x00..=0x03 | 0x07 => {
+                let (start, y) = self.display_start(y);
+                let row = y / self.mode.cheight.max(1) as u32;
+                let len = self.mode.twidth as u32 * 2;
+                (self.mode.pstart + u32::from(start) * 2 + row * len, len)
+            }
+            0x04..=0x06 => (0xB_8000 + ((y % 2) * 0x2000) + (80 * (y >> 1)), 80),
+            0x0D..=0x10 | 0x12 => {
+                let (start, y) = self.display_start(y);
+                let len = self.mode.swidth >> 3;
+                (PLANAR_BASE + u32::from(start) + y * len, len)
+            }
+            0x11 => (0xA_0000 + y * (self.mode.swidth >> 3), self.mode.swidth >> 3),
+            0x13 => (0xA_0000 + y * self.mode.swidth, self.mode.swidth),
+            // the banks written to are not tracked, so any write redraws the screen
+            _ if self.mode.kind == GFXMode::LIN8 => (PLANAR_BASE, self.mode.plength as u32),
+            _ => return None,
+        };
+        Some(start..start + len)
+    }
+
+    /// text modes, using the glyphs in character generator RAM
+    fn render_text_frame(&self, memory: &[u8], lines: Range<u32>, buf: &mut FrameWriter) {
         // 03h = T  80x25  8x8   640x200   16       4   B800 CGA,PCjr,Tandy
         //     = T  80x25  8x14  640x350   16/64    8   B800 EGA
         //     = T  80x25  8x16  640x400   16       8   B800 MCGA
         //     = T  80x25  9x16  720x400   16       8   B800 VGA
         //     = T  80x43  8x8   640x350   16       4   B800 EGA,VGA [17]
         //     = T  80x50  8x8   640x400   16       4   B800 VGA [17]
-        // XXX impl
-        Vec::new()
+        let cheight = self.mode.cheight.max(1);
+        let cwidth = self.mode.cwidth.max(1);
+        let colors = self.actl.color_map();
+        let (blink, line_graphics) = (self.actl.blink(), self.actl.line_graphics());
+        for y in lines {
+            let pal = self.line_palette(y);
+            let (start, line_y) = self.display_start(y);
+            let start = self.mode.pstart as usize + start as usize * 2;
+            let row = line_y as usize / cheight;
+            let line = line_y as usize % cheight;
+            for x in 0..self.mode.swidth {
+                let col = x as usize / cwidth;
+                let dot = x as usize % cwidth;
+                let offset = (start + (row * self.mode.twidth + col) * 2) & 0xF_FFFF;
+                let chr = memory[offset];
+                let attr = memory[offset + 1];
+                let bits = self.chargen.glyph_row(chr, attr, line);
+                let fg = if dot < 8 {
+                    bits & (0x80 >> dot) != 0
+                } else {
+                    // 9th column repeats the 8th for line graphics characters
+                    line_graphics && (0xC0..=0xDF).contains(&chr) && bits & 1 != 0
+                };
+                let color = if !self.actl.display_enabled() {
+                    // the screen is blanked while the palette registers are written
+                    self.actl.overscan
+                } else if fg {
+                    colors[(attr & 0xF) as usize]
+                } else if blink {
+                    colors[((attr >> 4) & 0x7) as usize]
+                } else {
+                    colors[(attr >> 4) as usize]
+                };
+                buf.push(&pal[color as usize]);
+            }
+        }
     }
-*/
+
     /// 320x200 4 color graphics (CGA,EGA,MCGA,VGA)
-    fn render_mode04_frame(&self, memory: &[u8]) -> Vec<ColorSpace> {
-        let mut buf: Vec<ColorSpace> = Vec::new();
+    fn render_mode04_frame(&self, memory: &[u8], lines: Range<u32>, buf: &mut FrameWriter) {
         // XXX palette selection is done by writes to cga registers
         // mappings to the cga palette
         let pal1_map: [usize; 4] = [0, 3, 5, 7];
@@ -629,35 +1330,74 @@ impl GPU {
         // let pal0_map: [u8; 4] = [0, 10, 12, 14];
 
         // 04h = G  40x25  8x8   320x200    4       .   B800 CGA,PCjr,EGA,MCGA,VGA
-        for y in 0..self.mode.sheight {
+        for y in lines {
             for x in 0..self.mode.swidth {
                 // divide Y by 2
                 // divide X by 4 (2 bits for each pixel)
                 // 80 bytes per line (80 * 4 = 320), 4 pixels per byte
                 let offset = (0xB_8000 + ((y%2) * 0x2000) + (80 * (y >> 1)) + (x >> 2)) as usize;
                 let bits = (memory[offset] >> ((3 - (x & 3)) * 2)) & 3; // 2 bits: cga palette to use
-                let pal = &self.dac.pal[pal1_map[bits as usize]];
-                buf.push(pal.clone());
+                let pal = &self.line_palette(y)[pal1_map[bits as usize]];
+                buf.push(pal);
             }
         }
-        buf
     }
-/*
-    fn render_mode06_frame(&self, memory: &[u8]) -> Vec<u8> {
+
+    /// 640x200 B/W graphics (CGA,EGA,MCGA,VGA)
+    fn render_mode06_frame(&self, memory: &[u8], lines: Range<u32>, buf: &mut FrameWriter) {
         // 06h = G  80x25  8x8   640x200    2       .   B800 CGA,PCjr,EGA,MCGA,VGA
         //     = G  80x25   .       .     mono      .   B000 HERCULES.COM on HGC [14]
-        // XXX impl
+        let mono_map: [usize; 2] = [0, 15];
+        for y in lines {
+            let pal = self.line_palette(y);
+            for x in 0..self.mode.swidth {
+                // 80 bytes per line, 8 pixels per byte
+                let offset = (0xB_8000 + ((y%2) * 0x2000) + (80 * (y >> 1)) + (x >> 3)) as usize;
+                let bit = (memory[offset] >> (7 - (x & 7))) & 1;
+                buf.push(&pal[mono_map[bit as usize]]);
+            }
+        }
     }
-*/
 
-    /// 640x480 B/W graphics (MCGA,VGA)
-    fn render_mode11_frame(&self, memory: &[u8]) -> Vec<ColorSpace> {
+    /// CGA graphics modes on a composite monitor, where each group of 4 pixels of the
+    /// 640x200 signal shows as one artifact color
+    fn render_composite_frame(&self, memory: &[u8], lines: Range<u32>, buf: &mut FrameWriter) {
+        // the 2 bit pixels of mode 04h/05h are 2 pixels of the signal
+        let pixels_per_group = if self.mode.mode == 0x06 { 4 } else { 2 };
+        for y in lines {
+            let line = (0xB_8000 + ((y%2) * 0x2000) + (80 * (y >> 1))) as usize;
+            for x in 0..self.mode.swidth as usize {
+                // each byte holds 2 groups of 4 bits
+                let group = x / pixels_per_group;
+                let bits = (memory[line + (group >> 1)] >> (4 - (group & 1) * 4)) & 0x0F;
+                let (r, g, b) = CGA_COMPOSITE_PALETTE[bits as usize];
+                buf.push(&ColorSpace::RGB(r, g, b));
+            }
+        }
+    }
 
-        let mut buf: Vec<ColorSpace> = Vec::new();
+    /// 16 color graphics (EGA,VGA), one bit of each pixel in each plane
+    fn render_planar_frame(&self, lines: Range<u32>, buf: &mut FrameWriter) {
+        let planar = self.planar.lock().unwrap();
+        let colors = self.actl.color_map();
+        let bytes_per_line = (self.mode.swidth >> 3) as usize;
+        for y in lines {
+            let pal = self.line_palette(y);
+            let (start, line_y) = self.display_start(y);
+            let line = start as usize + line_y as usize * bytes_per_line;
+            for x in 0..self.mode.swidth as usize {
+                let color = planar.pixel(line + (x >> 3), 7 - (x & 7) as u8);
+                buf.push(&pal[colors[color as usize] as usize]);
+            }
+        }
+    }
+
+    /// 640x480 B/W graphics (MCGA,VGA)
+    fn render_mode11_frame(&self, memory: &[u8], lines: Range<u32>, buf: &mut FrameWriter) {
         let pal = palette::mono_palette();
 
         // 11h = G  80x30  8x16  640x480  mono      .   A000 VGA,MCGA,ATI EGA,ATI VIP
-        for y in 0..self.mode.sheight {
+        for y in lines {
             let base_y = 0xA_0000 + (y * (self.mode.swidth >> 3));
             for x in 0..self.mode.swidth {
                 // 8 pixels in one byte, 640 pixels fit in 640/8 = 80 bytes (0x50 bytes)
@@ -669,10 +1409,9 @@ impl GPU {
                 let offset = (base_y + (x >> 3)) as usize;
                 let v = ((memory[offset] & (1 << (7-bit))) >> (7-bit)) & 1; // 1 bit
                 let pal = &pal[v as usize];
-                buf.push(pal.clone());
+                buf.push(pal);
             }
         }
-        buf
     }
 
 /*
@@ -707,17 +1446,86 @@ impl GPU {
 
     /// 320x200 256 color graphics (MCGA,VGA)
     /// linear mode
-    fn render_mode13_frame(&self, memory: &[u8]) -> Vec<ColorSpace> {
-        let mut buf: Vec<ColorSpace> = Vec::new();
-        for y in 0..self.mode.sheight {
+    fn render_mode13_frame(&self, memory: &[u8], lines: Range<u32>, buf: &mut FrameWriter) {
+        for y in lines {
+            let pal = self.line_palette(y);
             for x in 0..self.mode.swidth {
                 let offset = 0xA_0000 + ((y * self.mode.swidth) + x) as usize;
                 let byte = memory[offset];
-                let pal = &self.dac.pal[byte as usize];
-                buf.push(pal.clone());
+                buf.push(&pal[byte as usize]);
             }
         }
-        buf
+    }
+
+    /// 256 color graphics (SVGA), one byte per pixel in banked video memory
+    fn render_linear_frame(&self, lines: Range<u32>, buf: &mut FrameWriter) {
+        let planar = self.planar.lock().unwrap();
+        for y in lines {
+            let pal = self.line_palette(y);
+            let line = (y * self.mode.swidth) as usize;
+            for x in 0..self.mode.swidth as usize {
+                let byte = planar.linear[(line + x) % planar.linear.len()];
+                buf.push(&pal[byte as usize]);
+            }
+        }
+    }
+
+    /// renders the current frame into a RGBA image.
+    /// returns an empty image if the current video mode can't be rendered
+    pub fn screenshot(&self, mmu: &MMU) -> RgbaImage {
+        let frame = self.render_frame(mmu);
+        if frame.data.is_empty() {
+            return RgbaImage::new(0, 0);
+        }
+        RgbaImage::from_raw(frame.width, frame.height, frame.data).unwrap_or_else(|| RgbaImage::new(0, 0))
+    }
+
+    /// returns a hash of the current frame, used to quickly detect frame changes.
+    /// the hash is stable across runs and platforms (64-bit FNV-1a)
+    pub fn frame_hash(&self, mmu: &MMU) -> u64 {
+        let img = self.screenshot(mmu);
+        let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+        let dimensions = [img.width().to_le_bytes(), img.height().to_le_bytes()].concat();
+        for b in dimensions.iter().chain(img.into_raw().iter()) {
+            hash ^= u64::from(*b);
+            hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+        }
+        hash
+    }
+
+    /// returns the CRTC state to use when rendering scanline `y`
+    fn line_crtc(&self, y: u32) -> ScanlineCrtc {
+        if self.scanline_palette {
+            self.raster.scanline_crtc(y)
+        } else {
+            ScanlineCrtc {
+                scanline: 0,
+                start_address: self.crtc.start_address(),
+                line_compare: self.crtc.line_compare(),
+            }
+        }
+    }
+
+    /// returns the address displayed from for scanline `y`, and the scanline relative to it.
+    /// scanlines after the line compare are displayed from address 0 (split screen)
+    fn display_start(&self, y: u32) -> (u16, u32) {
+        let state = self.line_crtc(y);
+        // line compare counts CRTC scanlines, which are doubled in the 200 line modes
+        let split = (u32::from(state.line_compare) + 1) * self.mode.sheight / self.vdispend().max(1) as u32;
+        if y >= split {
+            (0, y - split)
+        } else {
+            (state.start_address, y)
+        }
+    }
+
+    /// returns the palette to use when rendering scanline `y`
+    fn line_palette(&self, y: u32) -> &[ColorSpace] {
+        if self.scanline_palette {
+            self.dac.scanline_palette(y)
+        } else {
+            self.dac.frame_palette()
+        }
     }
 
     /// stores video mode data in the BIOS Data Area (BDA)
@@ -746,42 +1554,75 @@ impl GPU {
         }
     }
 
+    /// returns the mode block of video mode `mode` for the emulated card
+    fn find_mode(&self, mode: u16) -> Option<VideoModeBlock> {
+        self.modes.iter().rev().find(|block| block.mode == mode).cloned()
+    }
+
     /// int 10h, ah = 00h
-    /// SET VIDEO MODE
+    /// SET VIDEO MODE. the video memory is cleared unless bit 7 of `mode` is set
     pub fn set_mode(&mut self, mmu: &mut MMU, mode: u8) {
-        let mut found = false;
-        for block in &self.modes {
-            if block.mode == u16::from(mode) {
-                self.mode = block.clone();
-                found = true;
+        self.set_mode_number(mmu, u16::from(mode & 0x7F), mode & 0x80 == 0);
+    }
+
+    /// int 10h, ax = 4F02h
+    /// SET SuperVGA VIDEO MODE. the video memory is cleared unless bit 15 of `mode` is set.
+    /// returns false if the mode is not supported
+    pub fn set_vesa_mode(&mut self, mmu: &mut MMU, mode: u16) -> bool {
+        self.set_mode_number(mmu, mode & 0x1FF, mode & 0x8000 == 0)
+    }
+
+    fn set_mode_number(&mut self, mmu: &mut MMU, mode: u16, clear_mem: bool) -> bool {
+        match self.find_mode(mode) {
+            Some(block) => self.mode = block,
+            None => {
+                warn!(target: "gpu", "ERROR: set_mode {:02X}: video mode not found for card {:?}", mode, self.card);
+                return false;
             }
         }
-        if !found {
-            println!("ERROR: set_mode {:02X}: video mode not found for card {:?}", mode, self.card);
-            return;
-        }
-        if DEBUG_SET_MODE {
-            println!("int 10h, ah = 00h: set_mode {:02X} {}x{}", mode, self.mode.swidth, self.mode.sheight);
-        }
+        debug!(target: "gpu", "int 10h, ah = 00h: set_mode {:02X} {}x{}", mode, self.mode.swidth, self.mode.sheight);
 
         match self.mode.kind {
             GFXMode::TEXT => self.dac.pal = palette::text_palette().to_vec(),
             GFXMode::CGA2 => self.dac.pal = palette::cga_palette_2().to_vec(),
             GFXMode::CGA4 => self.dac.pal = palette::cga_palette().to_vec(), // XXX is this the right cga pal for this mode?
             GFXMode::EGA => self.dac.pal = palette::ega_palette().to_vec(),
-            GFXMode::VGA => self.dac.pal = palette::vga_palette().to_vec(),
+            GFXMode::VGA | GFXMode::LIN8 => self.dac.pal = palette::vga_palette().to_vec(),
             _ => panic!("set_mode: unhandled palette for video mode {:?}", self.mode.kind),
         }
+        self.dac.pel_mask = 0xFF;
+        self.dac.reset_latch();
+        self.frame_cycle = 0;
+        self.scanline = 0;
+
+        // registers changed by the previous mode, such as the start address or character height, are reset
+        self.crtc = CRTC::default();
+        let vga_256 = self.mode.kind == GFXMode::VGA || self.mode.kind == GFXMode::LIN8;
+        self.actl = ACTL::for_mode(!self.mode.is_text(), vga_256);
+        self.reset_planar_registers();
+        if self.mode.is_text() {
+            self.chargen = CharGen::default();
+            self.chargen.load_rom_font(0, self.mode.cheight as u8);
+
+            let cheight = self.mode.cheight as u8;
+            self.crtc.set_index(0x09);
+            self.crtc.write_current(cheight - 1); // maximum scan line
+            self.crtc.set_index(0x0A);
+            self.crtc.write_current(cheight - 3); // cursor start
+            self.crtc.set_index(0x0B);
+            self.crtc.write_current(cheight - 2); // cursor end
+        }
+        self.raster.reset(&self.crtc);
 
-        let clear_mem = true;
         self.store_mode_in_bios(mmu, clear_mem);
+        if clear_mem {
+            self.clear_video_memory(mmu);
+        }
 
-        /*
         // Set cursor shape
-        if self.current_mode.kind == M_TEXT {
-            INT10_SetCursorShape(0x06, 07);
+        if self.mode.is_text() {
+            mmu.write_u16(BIOS::DATA_SEG, BIOS::DATA_CURSOR_TYPE, 0x0607);
         }
-        */
         // Set cursor pos for page 0..7
         for ct in 0..8 {
             self.set_cursor_pos(mmu, 0, 0, ct);
@@ -795,16 +1636,160 @@ impl GPU {
             16 => mmu.write_vec(0x43, self.font_16),
             _ => {},
         }
+        true
+    }
+
+    /// programs the sequencer and graphics controller for the current mode
+    fn reset_planar_registers(&mut self) {
+        let (memory_mode, mode) = match self.mode.kind {
+            GFXMode::TEXT => (0x02, 0x10),
+            GFXMode::CGA2 | GFXMode::CGA4 | GFXMode::TANDY16 => (0x02, 0x30),
+            GFXMode::VGA | GFXMode::LIN8 => (0x0E, 0x40),
+            _ => (0x06, 0x00),
+        };
+        self.svga.set_bank(0);
+        self.update_banks();
+        let mut planar = self.planar.lock().unwrap();
+        planar.banked = self.mode.kind == GFXMode::LIN8;
+        planar.sequencer = Sequencer::default();
+        planar.sequencer.memory_mode = memory_mode;
+        planar.graphics = GraphicsController::default();
+        planar.graphics.mode = mode;
+    }
+
+    /// clears the video memory of the current mode: text modes to blanks, graphic modes to color 0
+    fn clear_video_memory(&self, mmu: &mut MMU) {
+        match self.mode.kind {
+            GFXMode::TEXT => {
+                for i in 0..0x4000 {
+                    mmu.memory.write_u16(0xB_8000 + i * 2, 0x0720);
+                }
+            }
+            GFXMode::CGA2 | GFXMode::CGA4 | GFXMode::TANDY16 => {
+                for i in 0..0x4000 {
+                    mmu.memory.write_u16(0xB_8000 + i * 2, 0);
+                }
+            }
+            GFXMode::LIN8 => {
+                for v in self.planar.lock().unwrap().linear.iter_mut() {
+                    *v = 0;
+                }
+            }
+            _ => {
+                for i in 0..0x8000 {
+                    mmu.memory.write_u16(0xA_0000 + i * 2, 0);
+                }
+            }
+        }
+    }
+
+    /// int 10h, ax = 1C01h
+    /// SAVE VIDEO STATE. saves the `states` parts (see VIDEO_STATE_*) to the buffer at `seg`:`off`
+    pub fn save_video_state(&self, mmu: &mut MMU, states: u16, seg: u16, off: u16) {
+        let mut pos = VIDEO_STATE_HEADER_SIZE;
+        for i in 0..3 {
+            mmu.write_u16(seg, off + i * 2, 0);
+        }
+        if states & VIDEO_STATE_HARDWARE != 0 {
+            mmu.write_u16(seg, off, pos);
+            mmu.write_u16(seg, off + pos, self.mode.mode);
+            mmu.write_u8(seg, off + pos + 2, self.crtc.index);
+            mmu.write(seg, off + pos + 3, &self.crtc.registers());
+            mmu.write_u8(seg, off + pos + 0x1C, self.actl.read_index());
+            mmu.write(seg, off + pos + 0x1D, &self.actl.registers());
+            let planar = self.planar.lock().unwrap();
+            mmu.write(seg, off + pos + 0x32, &planar.sequencer.registers());
+            mmu.write(seg, off + pos + 0x37, &planar.graphics.registers());
+            pos += VIDEO_STATE_HARDWARE_SIZE;
+        }
+        if states & VIDEO_STATE_BIOS != 0 {
+            mmu.write_u16(seg, off + 2, pos);
+            let data = mmu.read(BIOS::DATA_SEG, BIOS::DATA_CURRENT_MODE, 0x1E);
+            mmu.write(seg, off + pos, &data);
+            let data = mmu.read(BIOS::DATA_SEG, BIOS::DATA_NB_ROWS, 7);
+            mmu.write(seg, off + pos + 0x20, &data);
+            // font vectors
+            let data = mmu.read(0, 0x1F * 4, 4);
+            mmu.write(seg, off + pos + 0x28, &data);
+            let data = mmu.read(0, 0x43 * 4, 4);
+            mmu.write(seg, off + pos + 0x2C, &data);
+            pos += VIDEO_STATE_BIOS_SIZE;
+        }
+        if states & VIDEO_STATE_DAC != 0 {
+            mmu.write_u16(seg, off + 4, pos);
+            mmu.write_u8(seg, off + pos, self.dac.pel_mask);
+            mmu.write_u16(seg, off + pos + 1, self.dac.pal.len() as u16);
+            for (i, color) in self.dac.pal.iter().enumerate() {
+                let rgb = match *color {
+                    ColorSpace::RGB(r, g, b) => [r, g, b],
+                    ColorSpace::None => [0, 0, 0],
+                };
+                mmu.write(seg, off + pos + 3 + i as u16 * 3, &rgb);
+            }
+        }
+    }
+
+    /// int 10h, ax = 1C02h
+    /// RESTORE VIDEO STATE. restores the `states` parts saved in the buffer at `seg`:`off`
+    pub fn restore_video_state(&mut self, mmu: &mut MMU, states: u16, seg: u16, off: u16) {
+        let part = |mmu: &MMU, state: u16, index: u16| -> Option<u16> {
+            let pos = mmu.read_u16(seg, off + index * 2);
+            if states & state != 0 && pos != 0 {
+                Some(off + pos)
+            } else {
+                None
+            }
+        };
+        if let Some(pos) = part(mmu, VIDEO_STATE_HARDWARE, 0) {
+            if let Some(block) = self.find_mode(mmu.read_u16(seg, pos)) {
+                self.mode = block;
+            }
+            self.crtc.set_registers(&mmu.read(seg, pos + 3, CRTC_REGISTERS));
+            self.crtc.set_index(mmu.read_u8(seg, pos + 2));
+            self.raster.reset(&self.crtc);
+            self.actl.set_registers(&mmu.read(seg, pos + 0x1D, ACTL_REGISTERS));
+            let index = mmu.read_u8(seg, pos + 0x1C);
+            self.actl.reset_flip_flop();
+            self.actl.write(index);
+            self.actl.reset_flip_flop();
+            let mut planar = self.planar.lock().unwrap();
+            planar.sequencer.set_registers(&mmu.read(seg, pos + 0x32, SEQUENCER_REGISTERS));
+            planar.graphics.set_registers(&mmu.read(seg, pos + 0x37, GRAPHICS_CONTROLLER_REGISTERS));
+        }
+        if let Some(pos) = part(mmu, VIDEO_STATE_BIOS, 1) {
+            let data = mmu.read(seg, pos, 0x1E);
+            mmu.write(BIOS::DATA_SEG, BIOS::DATA_CURRENT_MODE, &data);
+            let data = mmu.read(seg, pos + 0x20, 7);
+            mmu.write(BIOS::DATA_SEG, BIOS::DATA_NB_ROWS, &data);
+            let data = mmu.read(seg, pos + 0x28, 4);
+            mmu.write(0, 0x1F * 4, &data);
+            let data = mmu.read(seg, pos + 0x2C, 4);
+            mmu.write(0, 0x43 * 4, &data);
+            let mode = mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_CURRENT_MODE);
+            if let Some(block) = self.find_mode(u16::from(mode)) {
+                self.mode = block;
+            }
+        }
+        if let Some(pos) = part(mmu, VIDEO_STATE_DAC, 2) {
+            self.dac.pel_mask = mmu.read_u8(seg, pos);
+            let count = mmu.read_u16(seg, pos + 1).min(256);
+            self.dac.pal = (0..count)
+                .map(|i| {
+                    let rgb = mmu.read(seg, pos + 3 + i * 3, 3);
+                    ColorSpace::RGB(rgb[0], rgb[1], rgb[2])
+                })
+                .collect();
+            self.dac.reset_latch();
+        }
     }
 
     /// int 10h, ah = 05h
     /// SELECT ACTIVE DISPLAY PAGE
     pub fn set_active_page(&mut self, mmu: &mut MMU, page: u8) {
-        if DEBUG_INTERRUPTS {
-            println!("int 10h, ah = 05h: set_active_page");
-        }
-        if page > 7 {
-            println!("error: int10_set_active_page page {}", page);
+        debug!(target: "gpu", "int 10h, ah = 05h: set_active_page");
+        if page >= self.mode.ptotal.max(1) {
+            warn!(target: "gpu", "error: int10_set_active_page page {} of {}", page, self.mode.ptotal);
+            return;
         }
         /*
         if IS_EGAVGA_ARCH && (svgaCard == SVGA_S3Trio) {
@@ -841,12 +1826,39 @@ impl GPU {
         mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_CURRENT_PAGE)
     }
 
+    /// int 10h, ah = 01h
+    /// SET TEXT-MODE CURSOR SHAPE, shared by all pages
+    pub fn set_cursor_shape(&mut self, mmu: &mut MMU, start: u8, end: u8) {
+        debug!(target: "gpu", "int 10h, ah = 01h: set_cursor_shape");
+        mmu.write_u16(BIOS::DATA_SEG, BIOS::DATA_CURSOR_TYPE, u16::from(start) << 8 | u16::from(end));
+        if self.mode.is_text() {
+            self.crtc.set_index(0x0A);
+            self.crtc.write_current(start);
+            self.crtc.set_index(0x0B);
+            self.crtc.write_current(end);
+        }
+    }
+
+    /// int 10h, ah = 08h
+    /// READ CHARACTER AND ATTRIBUTE AT CURSOR POSITION of text mode page `page`
+    pub fn read_char_attr(&self, mmu: &MMU, page: u8) -> (u8, u8) {
+        if !self.mode.is_text() {
+            warn!(target: "gpu", "XXX int10 - READ CHARACTER AND ATTRIBUTE AT CURSOR POSITION in graphics mode {:02X}", self.mode.mode);
+            return (0, 0);
+        }
+        let page = page % self.mode.ptotal.max(1);
+        let row = u32::from(cursor_pos_row(mmu, page));
+        let col = u32::from(cursor_pos_col(mmu, page));
+        let address = self.mode.pstart
+            + u32::from(page) * u32::from(mmu.read_u16(BIOS::DATA_SEG, BIOS::DATA_PAGE_SIZE))
+            + (row * u32::from(mmu.read_u16(BIOS::DATA_SEG, BIOS::DATA_NB_COLS)) + col) * 2;
+        (mmu.memory.read_u8(address), mmu.memory.read_u8(address + 1))
+    }
+
     /// int 10h, ah = 02h
     /// SET CURSOR POSITION
     pub fn set_cursor_pos(&mut self, mmu: &mut MMU, row: u8, col: u8, page: u8) {
-        if DEBUG_INTERRUPTS {
-            println!("int 10h, ah = 02h: set_cursor_pos");
-        }
+        debug!(target: "gpu", "int 10h, ah = 02h: set_cursor_pos");
         // page = page number:
         //    0-3 in modes 2&3
         //    0-7 in modes 0&1
@@ -854,7 +1866,7 @@ impl GPU {
         // row = 0 is top
         // col = column (0 is left)
         if page > 7 {
-            println!("error: set_cursor_pos page {}", page);
+            warn!(target: "gpu", "error: set_cursor_pos page {}", page);
         }
         // BIOS cursor pos
         let cursor_ofs = u16::from(page) * 2;
@@ -878,9 +1890,7 @@ impl GPU {
     /// int 10h, ah = 0Ah
     /// WRITE CHARACTER ONLY AT CURSOR POSITION
     pub fn write_char(&mut self, mut mmu: &mut MMU, chr: u16, attr: u8, mut page: u8, mut count: u16, mut showattr: bool) {
-        if DEBUG_INTERRUPTS {
-            println!("int 10h, ah = 0Ah: write_char");
-        }
+        debug!(target: "gpu", "int 10h, ah = 0Ah: write_char");
         if !self.mode.is_text() {
             showattr = true;
             match self.card {
@@ -911,9 +1921,7 @@ impl GPU {
     /// and scrolling the screen as necessary
     pub fn teletype_output(&mut self, mmu: &mut MMU, chr: u8, page: u8, attr: u8) {
         // BL = foreground color (graphics modes only)
-        if DEBUG_INTERRUPTS {
-            println!("int 10h, ah = 0Eh: teletype_output");
-        }
+        debug!(target: "gpu", "int 10h, ah = 0Eh: teletype_output");
         if ECHO_TELETYPE {
             print!("{}", chr as char);
         }
@@ -923,20 +1931,20 @@ impl GPU {
 
     fn teletype_output_attr(&mut self, mmu: &mut MMU, chr: u8, attr: u8, page: u8, use_attr: bool) {
         let ncols = mmu.read_u16(BIOS::DATA_SEG, BIOS::DATA_NB_COLS);
-        let nrows = mmu.read_u16(BIOS::DATA_SEG, BIOS::DATA_NB_ROWS) + 1;
+        let nrows = u16::from(mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_NB_ROWS)) + 1;
         let mut cur_row = u16::from(cursor_pos_row(mmu, page));
         let mut cur_col = u16::from(cursor_pos_col(mmu, page));
         match chr {
-            /*
             7 => {
-                // enable speaker
+                // bell, the speaker is not sounded
+                /*
                 hw.out_u8(0x61, IO_Read(0x61) | 0x3);
                 for (Bitu i=0; i < 333; i++) {
                     CALLBACK_Idle();
                 }
                 hw.out_u8(0x61, IO_Read(0x61) & ~0x3);
+                */
             }
-            */
             8 => {
                 if cur_col > 0 {
                     cur_col -= 1;
@@ -970,21 +1978,62 @@ impl GPU {
         // Do we need to scroll ?
         if cur_row == nrows {
             // Fill with black on non-text modes and with 0x7 on textmode
-
-            // XXX in gpu branch:
-            /*
-            let fill = if self.mode.kind == GFXMode::TEXT {
-                7
+            if self.mode.kind == GFXMode::TEXT {
+                self.scroll_text_up(mmu, page, 0x07);
             } else {
-                0
-            };
-            int10_scroll_window(hw, 0, 0, (nrows-1) as u8, (ncols-1) as u8, -1, fill, page);
-            */
+                self.scroll_graphics_up(mmu);
+            }
             cur_row -= 1;
         }
         self.set_cursor_pos(mmu, cur_row as u8, cur_col as u8, page);
     }
 
+    /// scrolls text mode page `page` up by one line, filling the bottom line with blanks of attribute `attr`
+    fn scroll_text_up(&mut self, mmu: &mut MMU, page: u8, attr: u8) {
+        let ncols = u32::from(mmu.read_u16(BIOS::DATA_SEG, BIOS::DATA_NB_COLS));
+        let nrows = u32::from(mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_NB_ROWS)) + 1;
+        let base = self.mode.pstart + u32::from(page) * u32::from(mmu.read_u16(BIOS::DATA_SEG, BIOS::DATA_PAGE_SIZE));
+        let line = ncols * 2;
+        for row in 1..nrows {
+            for i in 0..line {
+                let v = mmu.memory.read_u8(base + row * line + i);
+                mmu.memory.write_u8(base + (row - 1) * line + i, v);
+            }
+        }
+        let last = base + (nrows - 1) * line;
+        for col in 0..ncols {
+            mmu.memory.write_u8(last + col * 2, b' ');
+            mmu.memory.write_u8(last + col * 2 + 1, attr);
+        }
+    }
+
+    /// scrolls a graphics mode screen up by one line of characters, filling the bottom line with black.
+    /// implemented for the memory layouts supported by `write_pixel`: CGA modes 04h-06h and mode 13h
+    fn scroll_graphics_up(&mut self, mmu: &mut MMU) {
+        let cheight = u32::from(mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_CHAR_HEIGHT));
+        // banks of pixel rows, bytes per pixel row, pixel rows per bank
+        let (banks, line, rows): (&[u32], u32, u32) = match self.mode.mode {
+            // even and odd pixel rows are interleaved in two 8k banks
+            0x04..=0x06 => (&[0, 0x2000], 80, 100),
+            0x13 => (&[0], 320, 200),
+            _ => {
+                warn!(target: "gpu", "XXX teletype: scrolling of graphics mode {:02X} is not implemented", self.mode.mode);
+                return;
+            }
+        };
+        let shift = cheight / banks.len() as u32;
+        for &bank in banks {
+            let base = self.mode.pstart + bank;
+            for i in 0..(rows - shift) * line {
+                let v = mmu.memory.read_u8(base + shift * line + i);
+                mmu.memory.write_u8(base + i, v);
+            }
+            for i in (rows - shift) * line..rows * line {
+                mmu.memory.write_u8(base + i, 0);
+            }
+        }
+    }
+
     fn write_char_internal(&mut self, mmu: &mut MMU, col: u16, row: u16, page: u8, mut chr: u16, mut attr: u8, use_attr: bool) {
         chr &= 0xFF;
         let cheight = mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_CHAR_HEIGHT);
@@ -1044,15 +2093,11 @@ impl GPU {
             hw.out_u8(0x3C5, 0xF);
         }
         */
-        if DEBUG_FONT {
-            println!("reading fontdata from {:04X}:{:04X}", fontdata_seg, fontdata_off);
-        }
+        trace!(target: "gpu", "reading fontdata from {:04X}:{:04X}", fontdata_seg, fontdata_off);
         for idx in 0..cheight {
             let mut bitsel = 128;
             let bitline = mmu.read_u8(fontdata_seg, fontdata_off);
-            if DEBUG_FONT {
-                println!("read fontdata {} = {:02x}", idx, bitline);
-            }
+            trace!(target: "gpu", "read fontdata {} = {:02x}", idx, bitline);
             fontdata_off += 1;
             let mut tx = x as u16;
             while bitsel != 0 {
@@ -1072,9 +2117,7 @@ impl GPU {
     /// WRITE GRAPHICS PIXEL
     /// color: if bit 7 is set, value is XOR'ed onto screen except in 256-color modes
     pub fn write_pixel(&mut self, mmu: &mut MMU, x: u16, y: u16, _page: u8, mut color: u8) {
-        if DEBUG_INTERRUPTS {
-            println!("int 10h, ah = 0Ch: write_pixel");
-        }
+        debug!(target: "gpu", "int 10h, ah = 0Ch: write_pixel");
         match self.mode.kind {
             GFXMode::TEXT => {}, // Valid only in graphics modes
             GFXMode::CGA4 => {
@@ -1116,16 +2159,14 @@ impl GPU {
                 }
             }
             GFXMode::VGA => mmu.write_u8(0xA000, y * 320 + x, color),
-            _ => println!("ERROR put_pixel TODO unimplemented for mode {:?}", self.mode.kind),
+            _ => warn!(target: "gpu", "ERROR put_pixel TODO unimplemented for mode {:?}", self.mode.kind),
         }
     }
 
     /// int 10h, ax = 1017h
     /// READ BLOCK OF DAC REGISTERS (VGA/MCGA)
     pub fn read_dac_block(&mut self, mmu: &mut MMU, index: u16, mut count: u16, seg: u16, mut off: u16) {
-        if DEBUG_INTERRUPTS {
-            println!("int 10h, ax = 1017h: read_dac_block");
-        }
+        debug!(target: "gpu", "int 10h, ax = 1017h: read_dac_block");
         // index = starting palette register
         // count = number of palette registers to read
         // seg:off -> buffer (3 * CX bytes in size) (see also AX=1012h)
@@ -1142,32 +2183,92 @@ impl GPU {
         }
     }
 
+    /// int 10h, ax = 1100h, 1110h
+    /// TEXT-MODE CHARGEN - LOAD USER-SPECIFIED PATTERNS (PS,EGA,VGA)
+    pub fn load_user_text_font(&mut self, mmu: &mut MMU, seg: u16, off: u16, request: &UserFontRequest) {
+        let UserFontRequest { count, first, block, height, reprogram } = *request;
+        debug!(target: "gpu", "int 10h, ax = 1100h: load_user_text_font: {} chars of height {} from {:04X}:{:04X}, first {:02X}, block {}",
+            count, height, seg, off, first, block);
+        let data = mmu.read(seg, off, count as usize * height as usize);
+        self.chargen.load(block, first, count, height, &data);
+        if reprogram {
+            self.set_text_char_height(mmu, height);
+        }
+    }
+
+    /// int 10h, ax = 1101h, 1102h, 1104h, 1111h, 1112h, 1114h
+    /// TEXT-MODE CHARGEN - LOAD ROM PATTERNS (PS,EGA,VGA)
+    pub fn load_rom_text_font(&mut self, mmu: &mut MMU, block: u8, height: u8, reprogram: bool) {
+        debug!(target: "gpu", "int 10h, ax = 11xxh: load_rom_text_font: height {}, block {}", height, block);
+        self.chargen.load_rom_font(block, height);
+        if reprogram {
+            self.set_text_char_height(mmu, height);
+        }
+    }
+
+    /// reprograms the current text mode for a new character height,
+    /// giving 43 (EGA) or 50 (VGA) rows with a 8x8 font in mode 03h
+    fn set_text_char_height(&mut self, mmu: &mut MMU, height: u8) {
+        if !self.mode.is_text() || height == 0 {
+            return;
+        }
+        let rows = self.mode.sheight as usize / height as usize;
+        self.mode.cheight = height as usize;
+        self.mode.theight = rows;
+        let page_size = ((rows * self.mode.twidth * 2) + 0xFF) & !0xFF;
+        self.mode.plength = page_size;
+        mmu.write_u8(BIOS::DATA_SEG, BIOS::DATA_NB_ROWS, (rows - 1) as u8);
+        mmu.write_u16(BIOS::DATA_SEG, BIOS::DATA_CHAR_HEIGHT, u16::from(height));
+        mmu.write_u16(BIOS::DATA_SEG, BIOS::DATA_PAGE_SIZE, page_size as u16);
+        self.crtc.set_index(0x09);
+        self.crtc.write_current(height - 1); // maximum scan line
+    }
+
     /// int 10h, ax = 1124h
     /// GRAPH-MODE CHARGEN - LOAD 8x16 GRAPHICS CHARS (VGA,MCGA)
     pub fn load_graphics_chars(&mut self, mmu: &mut MMU, row: u8, dl: u8) {
-        if DEBUG_INTERRUPTS {
-            println!("int 10h, ax = 1124h: load_graphics_chars");
-        }
+        debug!(target: "gpu", "int 10h, ax = 1124h: load_graphics_chars");
         if !self.card.is_vga() {
             return;
         }
-        mmu.write_vec(0x43, self.font_16);
-        mmu.write_u16(BIOS::DATA_SEG, BIOS::DATA_CHAR_HEIGHT, 16);
+        self.set_graphics_chars(mmu, self.font_16, 16, row, dl);
+    }
+
+    /// int 10h, ax = 1121h..1124h
+    /// points INT 43h to `font` and sets the number of character rows
+    fn set_graphics_chars(&mut self, mmu: &mut MMU, font: MemoryAddress, height: u16, row: u8, dl: u8) {
+        mmu.write_vec(0x43, font);
+        mmu.write_u16(BIOS::DATA_SEG, BIOS::DATA_CHAR_HEIGHT, height);
         let val = match row {
-            0x00 => dl - 1, // row 0 = user specified in DL
+            0x00 => dl.wrapping_sub(1), // row 0 = user specified in DL
             0x01 => 13,
             0x02 => 24,
             0x03 => 42,
-            _    => panic!(row),
+            _    => {
+                warn!(target: "gpu", "set_graphics_chars error: invalid row specifier {:02X}", row);
+                return;
+            }
         };
         mmu.write_u8(BIOS::DATA_SEG, BIOS::DATA_NB_ROWS, val);
     }
 
     /// int 10h, ah = 13h
     /// WRITE STRING (AT and later,EGA)
-    pub fn write_string(&mut self, mmu: &mut MMU, mut row: u8, mut col: u8, flag: u8, mut attr: u8, str_seg: u16, mut str_off: u16, mut count: u16, page: u8) {
-        if DEBUG_INTERRUPTS {
-            println!("int 10h, ah = 13h: write_string");
+    /// flag bit 0 = update cursor after writing, bit 1 = string holds character and attribute pairs.
+    /// bell, backspace, carriage return and line feed are handled as commands
+    pub fn write_string(&mut self, mmu: &mut MMU, request: &WriteStringRequest) {
+        debug!(target: "gpu", "int 10h, ah = 13h: write_string");
+        let WriteStringRequest { mut row, mut col, flag, mut attr, mut page, seg: str_seg, off: mut str_off, mut count } = *request;
+        if flag > 3 {
+            warn!(target: "gpu", "int10 error: write_string with invalid al={:02X}", flag);
+            return;
+        }
+        if !self.mode.is_text() {
+            match self.card {
+                GraphicCard::EGA | GraphicCard::VGA => page %= self.mode.ptotal,
+                GraphicCard::CGA => page = 0,
+                _ => {},
+            }
         }
         let cur_row = cursor_pos_row(mmu, page);
         let cur_col = cursor_pos_col(mmu, page);
@@ -1194,34 +2295,43 @@ impl GPU {
 
     /// int 10h, ax = 1007h
     /// GET INDIVIDUAL PALETTE REGISTER (VGA,UltraVision v2+)
-    pub fn get_individual_palette_register(&self, _reg: u8) -> u8 {
-        if DEBUG_INTERRUPTS {
-            println!("int 10h, ax = 1007h: get_individual_palette_register");
+    pub fn get_individual_palette_register(&self, reg: u8) -> u8 {
+        debug!(target: "gpu", "int 10h, ax = 1007h: get_individual_palette_register");
+        if (reg as usize) < ACTL_REGISTERS {
+            self.actl.read_register(reg)
+        } else {
+            0
         }
-        panic!("todo");
-        /*
-        const VGAREG_ACTL_ADDRESS: u16    = 0x3C0;
-        const VGAREG_ACTL_WRITE_DATA: u16 = 0x3C0;
-        const VGAREG_ACTL_READ_DATA: u16  = 0x3C1;
-
-        if reg <= ACTL_MAX_REG {
-            self.reset_actl();
-            IO_Write(VGAREG_ACTL_ADDRESS, reg + 32);
-            let ret = IO_Read(VGAREG_ACTL_READ_DATA);
-            IO_Write(VGAREG_ACTL_WRITE_DATA, ret);
-            ret
-        }
-        0
-        */
+    }
+
+    /// int 10h, ax = 1000h
+    /// SET SINGLE PALETTE REGISTER (PCjr,Tandy,EGA,MCGA,VGA)
+    pub fn set_single_palette_register(&mut self, reg: u8, value: u8) {
+        debug!(target: "gpu", "int 10h, ax = 1000h: set_single_palette_register {:02X} = {:02X}", reg, value);
+        if (reg as usize) < ACTL_REGISTERS {
+            self.actl.write_register(reg, value);
+        }
+    }
+
+    /// int 10h, ax = 1003h
+    /// TOGGLE INTENSITY/BLINKING BIT
+    pub fn set_blink(&mut self, mmu: &mut MMU, blink: bool) {
+        let mut msr = mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_CURRENT_MSR);
+        if blink {
+            self.actl.mode_control |= MODE_BLINK;
+            msr |= 0x20;
+        } else {
+            self.actl.mode_control &= !MODE_BLINK;
+            msr &= !0x20;
+        }
+        mmu.write_u8(BIOS::DATA_SEG, BIOS::DATA_CURRENT_MSR, msr);
     }
 
     /// int 10h, ax = 1010h
     /// SET INDIVIDUAL DAC REGISTER (VGA/MCGA)
     /// color components in 6-bit values (0-63)
     pub fn set_individual_dac_register(&mut self, mmu: &mut MMU, index: u8, r: u8, g: u8, b: u8) {
-        if DEBUG_INTERRUPTS {
-            println!("int 10h, ax = 1010h: set_individual_dac_register: index {:02X}, rgb = {:02X}, {:02X}, {:02X}", index, r, g, b);
-        }
+        debug!(target: "gpu", "int 10h, ax = 1010h: set_individual_dac_register: index {:02X}, rgb = {:02X}, {:02X}, {:02X}", index, r, g, b);
         self.dac.set_pel_write_index(index);
         if (mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_MODESET_CTL) & 0x06) == 0 {
             self.dac.set_pel_data(r);
@@ -1244,9 +2354,7 @@ impl GPU {
     /// int 10, ax = 1012h
     /// SET BLOCK OF DAC REGISTERS (VGA/MCGA)
     pub fn set_dac_block(&mut self, mmu: &mut MMU, index: u16, mut count: u16, seg: u16, mut off: u16) {
-        if DEBUG_INTERRUPTS {
-            println!("int 10h, ax = 1012h: set_dac_block: index {:04X}, count {} at {:04X}:{:04X}", index, count, seg, off);
-        }
+        debug!(target: "gpu", "int 10h, ax = 1012h: set_dac_block: index {:04X}, count {} at {:04X}:{:04X}", index, count, seg, off);
         // index = starting color register
         // count = number of registers to set
         // seg:off -> table of 3*CX bytes where each 3 byte group represents one byte each of red, green and blue (0-63)
@@ -1289,9 +2397,7 @@ impl GPU {
     /// int 10h, ax = 1015h
     /// READ INDIVIDUAL DAC REGISTER (VGA/MCGA)
     pub fn get_individual_dac_register(&mut self, reg: u8) -> (u8, u8, u8) {
-        if DEBUG_INTERRUPTS {
-            println!("int 10h, ax = 1015h: get_individual_dac_register: reg {:02X}", reg);
-        }
+        debug!(target: "gpu", "int 10h, ax = 1015h: get_individual_dac_register: reg {:02X}", reg);
         self.dac.set_pel_read_index(reg);
         let r = self.dac.get_pel_data();
         let g = self.dac.get_pel_data();
@@ -1299,23 +2405,114 @@ impl GPU {
         (r, g, b)
     }
 
-    fn reset_actl(&self) {
-        // 03BA  R-  CRT status register (see #P0656)
-        // 03DA  R-  CGA status register (see #P0818)
-        /*
-        match self.mode.crtc_address() + 6 {
-            0x3BA => panic!("xxx"),
-            0x3DA => self.read_cga_status_register(),
+    /// progress the video beam by `cycles` emulated cpu cycles
+    pub fn update(&mut self, cycles: usize, clock_hz: usize) {
+        self.clock_hz = clock_hz;
+        let frame_cycles = self.cycles_per_line() * self.vtotal();
+        self.frame_cycle += cycles;
+        let wrapped = self.frame_cycle >= frame_cycles;
+        if wrapped {
+            self.frame_cycle %= frame_cycles;
+        }
+
+        let line = self.frame_cycle / self.cycles_per_line();
+        let vdispend = self.vdispend();
+        let scanline = if line < vdispend {
+            (line * self.mode.sheight as usize / vdispend) as u32
+        } else {
+            self.mode.sheight
+        };
+        // walk the beam to the new scanline, so palette changes are latched
+        if wrapped {
+            while self.scanline != 0 {
+                self.progress_scanline();
+            }
+        }
+        while self.scanline < scanline {
+            self.progress_scanline();
         }
-        */
     }
 
-    /// HACK to have a source of info to toggle CGA status register
+    /// moves the video beam to the next scanline
     pub fn progress_scanline(&mut self) {
         self.scanline += 1;
+        if self.scanline == self.mode.sheight {
+            self.frame_count += 1;
+        }
         if self.scanline > self.mode.sheight {
             self.scanline = 0;
         }
+        if self.scanline < self.mode.sheight {
+            self.run_scanline_hooks();
+        }
+        if self.scanline == 0 {
+            self.dac.latch_frame();
+            self.raster.latch_frame(&self.crtc);
+        } else {
+            self.dac.latch_scanline(self.scanline);
+            self.raster.latch_scanline(self.scanline, &self.crtc);
+        }
+    }
+
+    /// registers `hook` to be called when the beam starts drawing each visible scanline,
+    /// with the CRTC and DAC to inspect or change, as done by programs syncing to the beam.
+    /// changes are rendered from that scanline if `scanline_palette` is set
+    pub fn add_scanline_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(u32, &mut CRTC, &mut DAC) + Send + 'static,
+    {
+        self.scanline_hooks.push(Arc::new(Mutex::new(hook)));
+    }
+
+    /// removes all hooks registered with `add_scanline_hook`
+    pub fn clear_scanline_hooks(&mut self) {
+        self.scanline_hooks.clear();
+    }
+
+    fn run_scanline_hooks(&mut self) {
+        for hook in &self.scanline_hooks {
+            (hook.lock().unwrap())(self.scanline, &mut self.crtc, &mut self.dac);
+        }
+    }
+
+    /// total number of scanlines in a frame, including the vertical blanking
+    fn vtotal(&self) -> usize {
+        if self.mode.vtotal > 0 {
+            self.mode.vtotal
+        } else {
+            self.mode.sheight as usize + 1
+        }
+    }
+
+    /// number of displayed scanlines in a frame
+    fn vdispend(&self) -> usize {
+        if self.mode.vdispend > 0 && self.mode.vdispend < self.vtotal() {
+            self.mode.vdispend
+        } else {
+            self.vtotal() - 1
+        }
+    }
+
+    /// number of emulated cpu cycles to draw one scanline, including horizontal retrace
+    fn cycles_per_line(&self) -> usize {
+        let n = self.clock_hz / (self.refresh_rate.max(1) * self.vtotal());
+        n.max(1)
+    }
+
+    /// returns true while the beam is in vertical retrace
+    pub fn in_vertical_retrace(&self) -> bool {
+        self.frame_cycle / self.cycles_per_line() >= self.vdispend()
+    }
+
+    /// returns true while the beam is in horizontal retrace
+    pub fn in_horizontal_retrace(&self) -> bool {
+        let cpl = self.cycles_per_line();
+        let display_cycles = if self.mode.htotal > 0 && self.mode.hdispend < self.mode.htotal as usize {
+            cpl * self.mode.hdispend / self.mode.htotal as usize
+        } else {
+            cpl
+        };
+        self.frame_cycle % cpl >= display_cycles
     }
 
     /// CGA status register (0x03DA)
@@ -1340,18 +2537,11 @@ impl GPU {
         //        (VGA,Genoa SuperEGA) horizontal or vertical retrace
         //    (C&T Wingine) display enabled (retrace/DE selected by XR14)
         let mut flags = 0;
-
-        // FIXME REMOVE THIS HACK: fake bit 0 and 3 (retrace in progress)
-        if self.scanline == 0 {
+        if self.in_vertical_retrace() {
+            flags |= 0b0000_1001; // set bit 0 and 3
+        } else if self.in_horizontal_retrace() {
             flags |= 0b0000_0001; // set bit 0
-            flags |= 0b0000_1000; // set bit 3
-        } else {
-            flags &= 0b1111_1110; // clear bit 0
-            flags &= 0b1111_0111; // clear bit 3
         }
-
-        // println!("read_cga_status_register: returns {:02X}", flags);
-
         flags
     }
 
@@ -1377,6 +2567,8 @@ impl GPU {
     }
 
     pub fn init(&mut self, mut mmu: &mut MMU) {
+        let planar: Arc<Mutex<dyn MemoryHandler>> = self.planar.clone();
+        mmu.register_hook(PLANAR_BASE..=PLANAR_BASE + PLANE_SIZE as u32 - 1, planar);
 
         let mut addr = MemoryAddress::RealSegmentOffset(0xC000, 3);
         //let seg = 0xC000;
@@ -1407,9 +2599,7 @@ impl GPU {
 
         // cga font
         self.font_8_first = addr;
-        if DEBUG_FONT {
-            println!("font_8_first = {:04X}:{:04X}", self.font_8_first.segment(), self.font_8_first.offset());
-        }
+        trace!(target: "gpu", "font_8_first = {:04X}:{:04X}", self.font_8_first.segment(), self.font_8_first.offset());
         for i in 0..(128 * 8) {
             mmu.write_u8_inc(&mut addr, font::FONT_08[i]);
         }
@@ -1417,9 +2607,7 @@ impl GPU {
         if self.card.is_ega_vga() {
             // cga second half
             self.font_8_second = addr;
-            if DEBUG_FONT {
-                println!("font_8_second = {:04X}:{:04X}", self.font_8_second.segment(), self.font_8_second.offset());
-            }
+            trace!(target: "gpu", "font_8_second = {:04X}:{:04X}", self.font_8_second.segment(), self.font_8_second.offset());
             for i in 0..(128 * 8) {
                 mmu.write_u8_inc(&mut addr, font::FONT_08[i + (128 * 8)]);
             }
@@ -1428,9 +2616,7 @@ impl GPU {
         if self.card.is_ega_vga() {
             // ega font
             self.font_14 = addr;
-            if DEBUG_FONT {
-                println!("font_14 = {:04X}:{:04X}", self.font_14.segment(), self.font_14.offset());
-            }
+            trace!(target: "gpu", "font_14 = {:04X}:{:04X}", self.font_14.segment(), self.font_14.offset());
             for i in 0..(256 * 14) {
                 mmu.write_u8_inc(&mut addr, font::FONT_14[i]);
             }
@@ -1439,9 +2625,7 @@ impl GPU {
         if self.card.is_vga() {
             // vga font
             self.font_16 = addr;
-            if DEBUG_FONT {
-                println!("font_16 = {:04X}:{:04X}", self.font_16.segment(), self.font_16.offset());
-            }
+            trace!(target: "gpu", "font_16 = {:04X}:{:04X}", self.font_16.segment(), self.font_16.offset());
             for i in 0..(256 * 16) {
                 mmu.write_u8_inc(&mut addr, font::FONT_16[i]);
             }
@@ -1518,6 +2702,21 @@ impl GPU {
     }
 }
 
+/// returns the number of 64-byte blocks needed to save the `states` parts of the video state
+fn video_state_blocks(states: u16) -> u16 {
+    let mut size = VIDEO_STATE_HEADER_SIZE;
+    if states & VIDEO_STATE_HARDWARE != 0 {
+        size += VIDEO_STATE_HARDWARE_SIZE;
+    }
+    if states & VIDEO_STATE_BIOS != 0 {
+        size += VIDEO_STATE_BIOS_SIZE;
+    }
+    if states & VIDEO_STATE_DAC != 0 {
+        size += VIDEO_STATE_DAC_SIZE;
+    }
+    size.div_ceil(64)
+}
+
 /// get the cursor x position
 fn cursor_pos_col(mmu: &MMU, page: u8) -> u8 {
     mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_CURSOR_POS + u16::from(page) * 2)
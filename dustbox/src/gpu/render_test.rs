@@ -2,10 +2,12 @@
 
 use std::panic;
 
-use image::{ImageBuffer, Rgb, Pixel, GenericImage};
+use image::{ImageBuffer, Rgb, Rgba, Pixel, GenericImage};
 
 use crate::cpu::R;
-use crate::machine::Machine;
+use crate::gpu::palette::ColorSpace::RGB;
+use crate::gpu::SvgaChipset;
+use crate::machine::{Machine, MachineConfig};
 
 #[test]
 fn can_get_palette_entry() {
@@ -136,6 +138,157 @@ let mut machine = Machine::deterministic();
 ", draw_ascii(&img));
 }
 
+#[test]
+fn can_latch_dac_palette_per_scanline() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB8, 0x13, 0x00,   // mov ax,0x13
+        0xCD, 0x10,         // int 0x10
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+
+    // fill the screen with color 1
+    for i in 0..(320 * 200) {
+        machine.mmu.write_u8(0xA000, i as u16, 1);
+    }
+
+    // color 1 = bright red, latched at the next vertical retrace
+    set_dac_color(&mut machine, 1, 0x3F, 0, 0);
+    run_to_next_frame(&mut machine);
+    for _ in 0..100 {
+        machine.gpu_mut().progress_scanline();
+    }
+    // color 1 = bright blue, written during scanline 100
+    set_dac_color(&mut machine, 1, 0, 0, 0x3F);
+    run_to_next_frame(&mut machine);
+
+    // the palette latched at the start of the frame is used by default
+    let frame = machine.gpu().render_frame(&machine.mmu);
+    let img = frame.draw_image();
+    assert_eq!(&Rgb([0, 0, 0xFC]), img.get_pixel(0, 0));
+    assert_eq!(&Rgb([0, 0, 0xFC]), img.get_pixel(0, 199));
+
+    // with per-scanline palette, the changes are shown where they occured
+    machine.gpu_mut().scanline_palette = true;
+    let frame = machine.gpu().render_frame(&machine.mmu);
+    let img = frame.draw_image();
+    assert_eq!(&Rgb([0xFC, 0, 0]), img.get_pixel(0, 0));
+    assert_eq!(&Rgb([0xFC, 0, 0]), img.get_pixel(0, 100));
+    assert_eq!(&Rgb([0, 0, 0xFC]), img.get_pixel(0, 101));
+    assert_eq!(&Rgb([0, 0, 0xFC]), img.get_pixel(0, 199));
+}
+
+#[test]
+fn can_write_vga_registers_with_word_io() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xBA, 0xD4, 0x03,   // mov dx,0x3d4
+        0xB8, 0x0E, 0x12,   // mov ax,0x120e
+        0xEF,               // out dx,ax
+        0xB0, 0x0F,         // mov al,0xf
+        0xEE,               // out dx,al
+        0xED,               // in ax,dx
+        0x89, 0xC3,         // mov bx,ax
+        0xBA, 0xC8, 0x03,   // mov dx,0x3c8
+        0xB8, 0x05, 0x3F,   // mov ax,0x3f05
+        0xEF,               // out dx,ax
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(10);
+    machine.out_u8(0x03C9, 0x10);
+    machine.out_u8(0x03C9, 0x20);
+
+    assert_eq!(0x12, machine.gpu().crtc.registers()[0x0E]);
+    assert_eq!(0x000F | u16::from(machine.gpu().crtc.registers()[0x0F]) << 8, machine.cpu.get_r16(R::BX));
+    assert_eq!(RGB(0xFC, 0x40, 0x80), machine.gpu().dac.pal[5]);
+}
+
+fn run_to_next_frame(machine: &mut Machine) {
+    machine.gpu_mut().progress_scanline();
+    while machine.gpu().scanline != 0 {
+        machine.gpu_mut().progress_scanline();
+    }
+}
+
+fn set_dac_color(machine: &mut Machine, index: u8, r: u8, g: u8, b: u8) {
+    machine.out_u8(0x03C8, index);
+    machine.out_u8(0x03C9, r);
+    machine.out_u8(0x03C9, g);
+    machine.out_u8(0x03C9, b);
+}
+
+#[test]
+fn can_load_user_text_font() {
+    let mut machine = Machine::deterministic();
+    let mut code: Vec<u8> = vec![
+        0xB8, 0x00, 0x11,   // mov ax,0x1100    ; load user-specified patterns
+        0xBB, 0x00, 0x10,   // mov bx,0x1000    ; 16 bytes per char, block 0
+        0xB9, 0x01, 0x00,   // mov cx,0x1       ; count
+        0xBA, 0x41, 0x00,   // mov dx,0x41      ; first char 'A'
+        0xBD, 0x11, 0x01,   // mov bp,0x111     ; es:bp = font data
+        0xCD, 0x10,         // int 0x10
+    ];
+    code.extend_from_slice(&[0b1010_0101; 16]);
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(6);
+    machine.execute_instruction(); // trigger the interrupt
+
+    machine.mmu.write_u8(0xB800, 0, b'A');
+    machine.mmu.write_u8(0xB800, 1, 0x07);
+
+    let frame = machine.gpu().render_frame(&machine.mmu);
+    let mut img = frame.draw_image();
+    let img = img.sub_image(0, 0, 9, 2).to_image();
+    assert_eq!("\
+6.6..6.6.
+6.6..6.6.
+", draw_ascii(&img));
+}
+
+#[test]
+fn can_set_50_line_text_mode() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB8, 0x12, 0x11,   // mov ax,0x1112    ; load rom 8x8 font and reprogram
+        0xB3, 0x00,         // mov bl,0x0       ; block 0
+        0xCD, 0x10,         // int 0x10
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+
+    assert_eq!(49, machine.mmu.read_u8(0x0040, 0x0084)); // rows - 1
+    assert_eq!(8, machine.mmu.read_u8(0x0040, 0x0085));  // char height
+    assert_eq!(50, machine.gpu().mode.theight);
+}
+
+#[test]
+fn can_hash_frame() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB8, 0x13, 0x00,   // mov ax,0x13
+        0xCD, 0x10,         // int 0x10
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+
+    let img = machine.screenshot();
+    assert_eq!(320, img.width());
+    assert_eq!(200, img.height());
+
+    let hash = machine.frame_hash();
+    assert_eq!(hash, machine.frame_hash());
+
+    machine.mmu.write_u8(0xA000, 0, 0x0F);
+    assert_ne!(hash, machine.frame_hash());
+    assert_eq!(&Rgba([0xFC, 0xFC, 0xFC, 0xFF]), machine.screenshot().get_pixel(0, 0));
+}
+
 fn draw_ascii(img: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> String {
     let mut res = String::new();
     for y in 0..img.height() {
@@ -161,3 +314,538 @@ fn pixel_256_to_ascii(v: &Rgb<u8>) -> char {
 fn scale(value_in:f64, base_min:f64, base_max:f64, limit_min:f64, limit_max:f64) -> f64 {
 	((limit_max - limit_min) * (value_in - base_min) / (base_max - base_min)) + limit_min
 }
+
+#[test]
+fn can_time_vertical_retrace() {
+    let mut machine = Machine::deterministic();
+    let clock_hz = machine.cpu.clock_hz;
+    let gpu = machine.gpu_mut();
+    gpu.refresh_rate = 70;
+
+    // mode 03h: 400 of 449 lines displayed
+    assert!(!gpu.in_vertical_retrace());
+    assert_eq!(0, gpu.read_cga_status_register() & 0b1000);
+
+    // 5_000_000 / (70 * 449) = 159 cycles per line
+    gpu.update(159 * 400, clock_hz);
+    assert!(gpu.in_vertical_retrace());
+    assert_eq!(0b1001, gpu.read_cga_status_register() & 0b1001);
+
+    // next frame starts after 449 lines
+    gpu.update(159 * 49, clock_hz);
+    assert!(!gpu.in_vertical_retrace());
+    assert_eq!(0, gpu.scanline);
+
+    // horizontal retrace at the end of the scanline
+    assert_eq!(0, gpu.read_cga_status_register() & 0b0001);
+    gpu.update(158, clock_hz);
+    assert!(gpu.in_horizontal_retrace());
+    assert_eq!(0b0001, gpu.read_cga_status_register() & 0b1001);
+}
+
+#[test]
+fn can_update_changed_scanlines() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB8, 0x13, 0x00,   // mov ax,0x13
+        0xCD, 0x10,         // int 0x10
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+
+    // first update renders the whole frame
+    let mut frame = machine.gpu().render_frame(&machine.mmu);
+    assert_eq!(200, machine.update_frame(&mut frame).len());
+    assert!(machine.update_frame(&mut frame).is_empty());
+
+    // a pixel on scanline 16, which starts on a page boundary
+    machine.mmu.write_u8(0xA000, 16 * 320 + 5, 1);
+    assert_eq!(vec![16], machine.update_frame(&mut frame));
+    let img = frame.draw_image();
+    assert_eq!(img.into_raw(), machine.gpu().render_frame(&machine.mmu).draw_image().into_raw());
+
+    // palette changes redraws the whole frame
+    set_dac_color(&mut machine, 1, 0x3F, 0, 0);
+    run_to_next_frame(&mut machine);
+    assert_eq!(200, machine.update_frame(&mut frame).len());
+    assert_eq!(&Rgb([0xFC, 0, 0]), frame.draw_image().get_pixel(5, 16));
+}
+
+#[test]
+fn can_scroll_graphics_mode_on_write_string() {
+    let mut machine = Machine::deterministic();
+    let mut code: Vec<u8> = vec![
+        0xB8, 0x13, 0x00,   // mov ax,0x13
+        0xCD, 0x10,         // int 0x10
+        0xB8, 0x01, 0x13,   // mov ax,0x1301    ; attribute in bl, cursor moved
+        0xBB, 0x0F, 0x00,   // mov bx,0xf       ; page 0, white
+        0xB9, 0x02, 0x00,   // mov cx,0x2
+        0xBA, 0x27, 0x18,   // mov dx,0x1827    ; row 24, column 39: the last cell of the screen
+        0xBD, 0x16, 0x01,   // mov bp,0x116
+        0xCD, 0x10,         // int 0x10
+    ];
+    code.extend_from_slice(b"AB");
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    machine.mmu.write_u8(0xA000, 0, 0x01);              // top line, scrolled out
+    machine.mmu.write_u8(0xA000, 8 * 320 + 5, 0x02);    // second line, scrolled to the top
+
+    machine.execute_instructions(6);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x00, machine.mmu.read_u8(0xA000, 0));
+    assert_eq!(0x02, machine.mmu.read_u8(0xA000, 5));
+    // "A" was written to the last cell and scrolled up one line, "B" is on the new bottom line
+    let cell = |machine: &Machine, row: u16, col: u16| -> Vec<u8> {
+        (0..8).flat_map(|y| machine.mmu.read(0xA000, (row * 8 + y) * 320 + col * 8, 8)).collect()
+    };
+    assert!(cell(&machine, 23, 39).contains(&0x0F));
+    assert!(cell(&machine, 24, 0).contains(&0x0F));
+    assert!(!cell(&machine, 24, 39).contains(&0x0F));
+    assert_ne!(cell(&machine, 23, 39), cell(&machine, 24, 0));
+    assert_eq!(vec![1, 24], machine.mmu.read(0x0040, 0x0050, 2));
+}
+
+#[test]
+fn can_write_string() {
+    let mut machine = Machine::deterministic();
+    let mut code: Vec<u8> = vec![
+        0xB8, 0x00, 0x13,   // mov ax,0x1300    ; attribute in bl, cursor not moved
+        0xBB, 0x1E, 0x00,   // mov bx,0x1e      ; page 0, attribute
+        0xB9, 0x02, 0x00,   // mov cx,0x2
+        0xBA, 0x00, 0x00,   // mov dx,0x0       ; row 0, column 0
+        0xBD, 0x2E, 0x01,   // mov bp,0x12e
+        0xCD, 0x10,         // int 0x10
+        0xB8, 0x01, 0x13,   // mov ax,0x1301    ; attribute in bl, cursor moved
+        0xBA, 0x00, 0x01,   // mov dx,0x100
+        0xCD, 0x10,         // int 0x10
+        0xB8, 0x02, 0x13,   // mov ax,0x1302    ; attributes in string, cursor not moved
+        0xBA, 0x00, 0x02,   // mov dx,0x200
+        0xBD, 0x30, 0x01,   // mov bp,0x130
+        0xCD, 0x10,         // int 0x10
+        0xB8, 0x03, 0x13,   // mov ax,0x1303    ; attributes in string, cursor moved
+        0xB7, 0x01,         // mov bh,0x1       ; inactive page 1
+        0xBA, 0x05, 0x03,   // mov dx,0x305
+        0xCD, 0x10,         // int 0x10
+    ];
+    code.extend_from_slice(b"ab");
+    code.extend_from_slice(&[b'c', 0x4F, b'd', 0x2A]);
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(6);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(vec![b'a', 0x1E, b'b', 0x1E], machine.mmu.read(0xB800, 0, 4));
+    assert_eq!(vec![0, 0], machine.mmu.read(0x0040, 0x0050, 2)); // page 0 cursor column, row
+
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(vec![b'a', 0x1E, b'b', 0x1E], machine.mmu.read(0xB800, 160, 4));
+    assert_eq!(vec![2, 1], machine.mmu.read(0x0040, 0x0050, 2));
+
+    machine.execute_instructions(4);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(vec![b'c', 0x4F, b'd', 0x2A], machine.mmu.read(0xB800, 2 * 160, 4));
+    assert_eq!(vec![2, 1], machine.mmu.read(0x0040, 0x0050, 2));
+
+    machine.execute_instructions(4);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(vec![b'c', 0x4F, b'd', 0x2A], machine.mmu.read(0xB800, 0x1000 + 3 * 160 + 5 * 2, 4));
+    assert_eq!(vec![b' ', 0x07], machine.mmu.read(0xB800, 3 * 160 + 5 * 2, 2)); // active page is unchanged
+    assert_eq!(vec![7, 3], machine.mmu.read(0x0040, 0x0052, 2)); // page 1 cursor
+    assert_eq!(vec![2, 1], machine.mmu.read(0x0040, 0x0050, 2));
+}
+
+#[test]
+fn can_flip_text_pages() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB4, 0x02,         // mov ah,0x2       ; set cursor position
+        0xB7, 0x01,         // mov bh,0x1       ; inactive page 1
+        0xBA, 0x03, 0x02,   // mov dx,0x203     ; row 2, column 3
+        0xCD, 0x10,         // int 0x10
+        0xB8, 0x41, 0x0E,   // mov ax,0xe41     ; teletype 'A' to page 1
+        0xCD, 0x10,         // int 0x10
+        0xB8, 0x01, 0x05,   // mov ax,0x501     ; select active page 1
+        0xCD, 0x10,         // int 0x10
+        0xB4, 0x03,         // mov ah,0x3       ; get cursor position of page 1
+        0xCD, 0x10,         // int 0x10
+        0xB4, 0x02,         // mov ah,0x2
+        0xBA, 0x03, 0x02,   // mov dx,0x203
+        0xCD, 0x10,         // int 0x10
+        0xB4, 0x08,         // mov ah,0x8       ; read character of page 1
+        0xCD, 0x10,         // int 0x10
+        0xB8, 0x08, 0x05,   // mov ax,0x508     ; past the last page of the mode
+        0xCD, 0x10,         // int 0x10
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(4);
+    machine.execute_instruction(); // trigger the interrupt
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(vec![b'A', 0x07], machine.mmu.read(0xB800, 0x1000 + 2 * 160 + 3 * 2, 2));
+    assert_eq!(vec![b' ', 0x07], machine.mmu.read(0xB800, 2 * 160 + 3 * 2, 2)); // active page is unchanged
+    assert_eq!(vec![0, 0, 4, 2], machine.mmu.read(0x0040, 0x0050, 4)); // cursor of page 0 and 1
+    assert_eq!(0, machine.gpu().crtc.start_address());
+
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(1, machine.mmu.read_u8(0x0040, 0x0062));
+    assert_eq!(0x1000 / 2, machine.gpu().crtc.start_address());
+    assert_eq!(0x1000 / 2 + 2 * 80 + 4, machine.gpu().crtc.cursor_location());
+
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x0204, machine.cpu.get_r16(R::DX));
+    assert_eq!(0x0607, machine.cpu.get_r16(R::CX));
+
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x0741, machine.cpu.get_r16(R::AX));
+
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(1, machine.mmu.read_u8(0x0040, 0x0062)); // ignored
+}
+
+#[test]
+fn can_write_string_with_control_characters() {
+    let mut machine = Machine::deterministic();
+    let mut code: Vec<u8> = vec![
+        0xB8, 0x01, 0x13,   // mov ax,0x1301
+        0xBB, 0x07, 0x00,   // mov bx,0x7
+        0xB9, 0x05, 0x00,   // mov cx,0x5
+        0xBA, 0x00, 0x18,   // mov dx,0x1800    ; bottom row
+        0xBD, 0x11, 0x01,   // mov bp,0x111
+        0xCD, 0x10,         // int 0x10
+    ];
+    code.extend_from_slice(b"x\r\n\x07y");
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(6);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(vec![b'x', 0x07], machine.mmu.read(0xB800, 23 * 160, 2)); // scrolled up
+    assert_eq!(vec![b'y', 0x07, b' ', 0x07], machine.mmu.read(0xB800, 24 * 160, 4));
+    assert_eq!(vec![1, 24], machine.mmu.read(0x0040, 0x0050, 2));
+}
+
+#[test]
+fn can_write_attribute_controller_palette() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xBA, 0xDA, 0x03,   // mov dx,0x3da
+        0xEC,               // in al,dx         ; reset the flip-flop
+        0xB2, 0xC0,         // mov dl,0xc0
+        0xB0, 0x01,         // mov al,0x1       ; palette register 1
+        0xEE,               // out dx,al
+        0xB0, 0x3F,         // mov al,0x3f      ; white
+        0xEE,               // out dx,al
+        0xB0, 0x21,         // mov al,0x21      ; palette register 1, display enabled
+        0xEE,               // out dx,al
+        0x42,               // inc dx
+        0xEC,               // in al,dx
+        0xB8, 0x03, 0x10,   // mov ax,0x1003    ; background intensity instead of blink
+        0xB3, 0x00,         // mov bl,0x0
+        0xCD, 0x10,         // int 0x10
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(7);
+    assert_eq!(0x3F, machine.gpu().actl.palette[1]);
+    assert!(!machine.gpu().actl.display_enabled());
+    machine.execute_instructions(4);
+    assert!(machine.gpu().actl.display_enabled());
+    assert_eq!(0x3F, machine.cpu.get_r8(R::AL));
+
+    machine.mmu.write(0xB800, 0, &[0xDB, 0x01, b' ', 0xA0]);
+    let frame = machine.gpu().render_frame(&machine.mmu);
+    let pal = machine.gpu().dac.frame_palette();
+    let cwidth = machine.gpu().mode.cwidth as u32;
+    assert_eq!(pal[0x3F], frame.pixel(0, 0));
+    assert_eq!(pal[0x02], frame.pixel(cwidth, 0)); // blinking, dark background
+
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    let frame = machine.gpu().render_frame(&machine.mmu);
+    assert_eq!(machine.gpu().dac.frame_palette()[0x3A], frame.pixel(cwidth, 0));
+}
+
+#[test]
+fn can_ignore_writes_to_unknown_crtc_registers() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xBA, 0xD4, 0x03,   // mov dx,0x3d4
+        0xB8, 0x38, 0x12,   // mov ax,0x1238    ; register 38h, not line compare (18h)
+        0xEF,               // out dx,ax
+        0xB8, 0x7F, 0x34,   // mov ax,0x347f
+        0xEF,               // out dx,ax
+    ];
+    machine.load_executable(&code, 0x085F);
+    let registers = machine.gpu().crtc.registers();
+
+    machine.execute_instructions(5);
+    assert_eq!(registers, machine.gpu().crtc.registers());
+
+    let mut crtc = machine.gpu().crtc.clone();
+    crtc.set_index(0x7F);
+    crtc.write_current(0x56);
+    assert_eq!(registers, crtc.registers());
+}
+
+#[test]
+fn can_save_and_restore_video_state() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xBA, 0xD4, 0x03,   // mov dx,0x3d4
+        0xB8, 0x13, 0x30,   // mov ax,0x3013    ; crtc offset register = 0x30
+        0xEF,               // out dx,ax
+        0xB8, 0x10, 0x10,   // mov ax,0x1010    ; set DAC register 1 to red
+        0xBB, 0x01, 0x00,   // mov bx,0x1
+        0xB6, 0x3F,         // mov dh,0x3f
+        0xB9, 0x00, 0x00,   // mov cx,0x0
+        0xCD, 0x10,         // int 0x10
+        0xB8, 0x00, 0x1C,   // mov ax,0x1c00    ; get buffer size
+        0xB9, 0x07, 0x00,   // mov cx,0x7       ; all states
+        0xCD, 0x10,         // int 0x10
+        0xB8, 0x01, 0x1C,   // mov ax,0x1c01    ; save state
+        0xBB, 0x00, 0x04,   // mov bx,0x400
+        0xCD, 0x10,         // int 0x10
+        0xB8, 0x13, 0x00,   // mov ax,0x13
+        0xCD, 0x10,         // int 0x10
+        0xB8, 0x02, 0x1C,   // mov ax,0x1c02    ; restore state
+        0xBB, 0x00, 0x04,   // mov bx,0x400
+        0xCD, 0x10,         // int 0x10
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(8);
+    machine.execute_instruction(); // trigger the interrupt
+    let pal = machine.gpu().dac.pal.clone();
+    assert_ne!(pal, crate::gpu::palette::text_palette().to_vec());
+
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x1C, machine.cpu.get_r8(R::AL));
+    assert_eq!(15, machine.cpu.get_r16(R::BX));
+
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x1C, machine.cpu.get_r8(R::AL));
+
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x13, machine.gpu().mode.mode);
+    assert_eq!(0, machine.gpu().crtc.registers()[0x13]);
+
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x03, machine.gpu().mode.mode);
+    assert_eq!(0x03, machine.mmu.read_u8(0x0040, 0x0049));
+    assert_eq!(0x30, machine.gpu().crtc.registers()[0x13]);
+    assert_eq!(pal, machine.gpu().dac.pal);
+}
+
+#[test]
+fn can_reinitialize_video_state_on_set_mode() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB8, 0x12, 0x11,   // mov ax,0x1112    ; load 8x8 font, 50 lines
+        0xB3, 0x00,         // mov bl,0x0
+        0xCD, 0x10,         // int 0x10
+        0xBA, 0xC6, 0x03,   // mov dx,0x3c6
+        0xB0, 0x0F,         // mov al,0xf       ; pel mask
+        0xEE,               // out dx,al
+        0xBA, 0x00, 0xB8,   // mov dx,0xb800
+        0x8E, 0xC2,         // mov es,dx
+        0x26, 0xC7, 0x06, 0x00, 0x00, 0x07, 0x00, // mov word [es:0x0],0x7
+        0xB8, 0x83, 0x00,   // mov ax,0x83      ; mode 3, keep video memory
+        0xCD, 0x10,         // int 0x10
+        0xB8, 0x03, 0x00,   // mov ax,0x3
+        0xCD, 0x10,         // int 0x10
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(7, machine.gpu().crtc.registers()[0x09]);
+
+    machine.execute_instructions(6);
+    assert_eq!(0x0F, machine.gpu().dac.pel_mask);
+
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(15, machine.gpu().crtc.registers()[0x09]); // maximum scan line
+    assert_eq!(0xFF, machine.gpu().dac.pel_mask);
+    assert_eq!(24, machine.mmu.read_u8(0x0040, 0x0084)); // rows - 1
+    assert_eq!(16, machine.mmu.read_u8(0x0040, 0x0085)); // character height
+    assert_eq!(0x0607, machine.mmu.read_u16(0x0040, 0x0060)); // cursor shape
+    assert_eq!(vec![0x07, 0x00], machine.mmu.read(0xB800, 0, 2));
+
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(vec![0x20, 0x07], machine.mmu.read(0xB800, 0, 2));
+}
+
+#[test]
+fn can_switch_banks_in_svga_mode() {
+    let mut machine = Machine::with_config(MachineConfig {
+        svga: SvgaChipset::S3Trio,
+        ..MachineConfig::default()
+    });
+    let code: Vec<u8> = vec![
+        0xB8, 0x02, 0x4F,   // mov ax,0x4f02
+        0xBB, 0x01, 0x01,   // mov bx,0x101
+        0xCD, 0x10,         // int 0x10
+        0xB8, 0x05, 0x4F,   // mov ax,0x4f05
+        0x31, 0xDB,         // xor bx,bx
+        0xBA, 0x01, 0x00,   // mov dx,0x1
+        0xCD, 0x10,         // int 0x10
+        0xB8, 0x00, 0xA0,   // mov ax,0xa000
+        0x8E, 0xC0,         // mov es,ax
+        0x26, 0xC6, 0x06, 0x00, 0x00, 0x2A, // mov byte [es:0x0],0x2a
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x004F, machine.cpu.get_r16(R::AX));
+    assert_eq!(0x101, machine.gpu().mode.mode);
+
+    machine.execute_instructions(4);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x004F, machine.cpu.get_r16(R::AX));
+
+    machine.execute_instructions(3);
+    assert_eq!(0x2A, machine.gpu().planar.lock().unwrap().linear[0x1_0000]);
+    assert_eq!(0, machine.mmu.memory.data[0xA_0000]); // not written to the flat memory
+
+    // offset 10000h is pixel 256 of line 102
+    let frame = machine.gpu().render_frame(&machine.mmu);
+    let pal = machine.gpu().dac.frame_palette();
+    assert_eq!((640, 480), (frame.width, frame.height));
+    assert_eq!(640 * 480 * 4, frame.data.len());
+    assert_eq!(pal[0x2A], frame.pixel(256, 102));
+    assert_eq!(pal[0x00], frame.pixel(0, 0));
+}
+
+#[test]
+fn can_change_palette_from_scanline_hook() {
+    let mut machine = Machine::deterministic();
+    machine.gpu_mut().scanline_palette = true;
+
+    // color 0 = red from scanline 100, black again from the top of the next frame
+    machine.gpu_mut().add_scanline_hook(|y, _crtc, dac| {
+        let rgb = match y {
+            0 => [0, 0, 0],
+            100 => [0x3F, 0, 0],
+            _ => return,
+        };
+        dac.set_pel_write_index(0);
+        for c in &rgb {
+            dac.set_pel_data(*c);
+        }
+    });
+    run_to_next_frame(&mut machine);
+    run_to_next_frame(&mut machine);
+
+    let frame = machine.gpu().render_frame(&machine.mmu);
+    let img = frame.draw_image();
+    assert_eq!(&Rgb([0, 0, 0]), img.get_pixel(0, 99));
+    assert_eq!(&Rgb([0xFC, 0, 0]), img.get_pixel(0, 100));
+    assert_eq!(&Rgb([0xFC, 0, 0]), img.get_pixel(0, 399));
+
+    // the palette set at the top of the frame is kept
+    machine.gpu_mut().clear_scanline_hooks();
+    run_to_next_frame(&mut machine);
+    let frame = machine.gpu().render_frame(&machine.mmu);
+    let img = frame.draw_image();
+    assert_eq!(&Rgb([0, 0, 0]), img.get_pixel(0, 100));
+}
+
+#[test]
+fn can_split_screen_with_line_compare() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB8, 0x12, 0x00,   // mov ax,0x12
+        0xCD, 0x10,         // int 0x10
+        0xBA, 0xD4, 0x03,   // mov dx,0x3d4
+        0xB8, 0x0C, 0x03,   // mov ax,0x30c     ; start address 0320h (line 10)
+        0xEF,               // out dx,ax
+        0xB8, 0x0D, 0x20,   // mov ax,0x200d
+        0xEF,               // out dx,ax
+        0xB8, 0x07, 0x00,   // mov ax,0x7       ; line compare 0EFh, bits 8-9 cleared
+        0xEF,               // out dx,ax
+        0xB8, 0x09, 0x00,   // mov ax,0x9
+        0xEF,               // out dx,ax
+        0xB8, 0x18, 0xEF,   // mov ax,0xef18
+        0xEF,               // out dx,ax
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    machine.execute_instructions(11);
+    assert_eq!(0xEF, machine.gpu().crtc.line_compare());
+    {
+        let mut planar = machine.gpu().planar.lock().unwrap();
+        planar.planes[0][0] = 0x80;         // color 1 at line 0
+        planar.planes[1][10 * 80] = 0x80;   // color 2 at line 10
+    }
+
+    let frame = machine.gpu().render_frame(&machine.mmu);
+    let pal = machine.gpu().dac.frame_palette().to_vec();
+    let colors = machine.gpu().actl.color_map();
+    assert_eq!(pal[colors[2] as usize], frame.pixel(0, 0));
+    assert_eq!(pal[colors[0] as usize], frame.pixel(0, 239));
+    assert_eq!(pal[colors[1] as usize], frame.pixel(0, 240));
+
+    // the line compare is latched per scanline
+    machine.gpu_mut().scanline_palette = true;
+    run_to_next_frame(&mut machine);
+    let frame = machine.gpu().render_frame(&machine.mmu);
+    assert_eq!(pal[colors[1] as usize], frame.pixel(0, 240));
+}
+
+#[test]
+fn can_render_frame_into_buffer_with_pitch() {
+    let mut machine = Machine::deterministic();
+    machine.mmu.write(0xB800, 0, &[b'A', 0x1E]);
+    let frame = machine.gpu().render_frame(&machine.mmu);
+
+    // rows padded to 3000 bytes, as a locked texture may be
+    let pitch = 3000;
+    let mut buf = vec![0xAA; pitch * 400];
+    assert!(machine.gpu().render_frame_into(&machine.mmu, &mut buf, pitch));
+    for y in 0..400 {
+        assert_eq!(&frame.data[y * frame.stride..(y + 1) * frame.stride], &buf[y * pitch..y * pitch + frame.stride]);
+    }
+    assert_eq!(0xAA, buf[frame.stride]);
+
+    // too small
+    assert!(!machine.gpu().render_frame_into(&machine.mmu, &mut buf[..pitch], pitch));
+}
+
+#[test]
+fn can_present_double_buffered_frames() {
+    let mut machine = Machine::deterministic();
+    assert_eq!(400, machine.present_frame().len());
+    // nothing changed, but the back buffer is rendered the first time it is presented
+    assert!(machine.present_frame().is_empty());
+    assert_eq!(720 * 400 * 4, machine.gpu().front_frame().data.len());
+
+    let blank = machine.gpu().front_frame().pixel(0, 0);
+    machine.mmu.write(0xB800, 0, &[0xDB, 0x0C]);
+    // writes are tracked in 256 byte pages, covering the first 2 text rows
+    assert_eq!((0..32).collect::<Vec<u32>>(), machine.present_frame());
+    let red = machine.gpu().front_frame().pixel(0, 0);
+    assert_ne!(blank, red);
+
+    // the change is rendered into the other buffer on the next present
+    assert!(machine.present_frame().is_empty());
+    assert_eq!(red, machine.gpu().front_frame().pixel(0, 0));
+    assert!(machine.present_frame().is_empty());
+    assert_eq!(red, machine.gpu().front_frame().pixel(0, 0));
+}
@@ -0,0 +1,84 @@
+use log::{trace, warn};
+
+/// number of sequencer registers, 00h-04h
+pub const SEQUENCER_REGISTERS: usize = 0x05;
+
+/// memory mode register: odd/even addressing is disabled
+pub const MEMORY_ODD_EVEN_DISABLE: u8 = 0x04;
+
+/// memory mode register: chain 4, the 2 low address bits select the plane (mode 13h)
+pub const MEMORY_CHAIN_4: u8 = 0x08;
+
+/// (EGA/VGA) sequencer, controls which planes of video memory the cpu writes to
+/// http://www.osdever.net/FreeVGA/vga/seqreg.htm
+#[derive(Clone)]
+pub struct Sequencer {
+    reset: u8,
+    clocking_mode: u8,
+
+    /// planes written to by the cpu, bits 0-3
+    pub map_mask: u8,
+
+    character_map_select: u8,
+    pub memory_mode: u8,
+
+    pub index: u8,
+}
+
+impl Default for Sequencer {
+    fn default() -> Self {
+        Sequencer {
+            reset: 0x03,
+            clocking_mode: 0x00,
+            map_mask: 0x0F,
+            character_map_select: 0x00,
+            memory_mode: 0x02,
+            index: 0,
+        }
+    }
+}
+
+impl Sequencer {
+    // 03C4  -W  EGA TS index register
+    pub fn set_index(&mut self, data: u8) {
+        self.index = data & 0x07;
+    }
+
+    // 03C5  -W  EGA TS data register
+    pub fn write_current(&mut self, data: u8) {
+        trace!(target: "gpu", "sequencer write_current {:02X} = {:02X}", self.index, data);
+        match self.index {
+            0x00 => self.reset = data & 0x03,
+            0x01 => self.clocking_mode = data,
+            0x02 => self.map_mask = data & 0x0F,
+            0x03 => self.character_map_select = data,
+            0x04 => self.memory_mode = data & 0x0E,
+            _ => warn!(target: "gpu", "sequencer: write to unknown register {:02X} = {:02X}", self.index, data),
+        }
+    }
+
+    /// returns the value of the register selected by `index`, 0 for unknown registers
+    pub fn read_current(&self) -> u8 {
+        self.registers().get(self.index as usize).cloned().unwrap_or(0)
+    }
+
+    /// returns registers 00h-04h
+    pub fn registers(&self) -> [u8; SEQUENCER_REGISTERS] {
+        [self.reset, self.clocking_mode, self.map_mask, self.character_map_select, self.memory_mode]
+    }
+
+    /// writes registers 00h-04h from `regs`, keeping the selected index
+    pub fn set_registers(&mut self, regs: &[u8]) {
+        let index = self.index;
+        for (i, v) in regs.iter().take(SEQUENCER_REGISTERS).enumerate() {
+            self.index = i as u8;
+            self.write_current(*v);
+        }
+        self.index = index;
+    }
+
+    /// returns true if the cpu accesses video memory one plane per bit, as in the 16 color modes
+    pub fn is_planar(&self) -> bool {
+        self.memory_mode & (MEMORY_CHAIN_4 | MEMORY_ODD_EVEN_DISABLE) == MEMORY_ODD_EVEN_DISABLE
+    }
+}
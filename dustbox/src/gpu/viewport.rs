@@ -0,0 +1,103 @@
+use crate::gpu::modes::VideoModeBlock;
+
+#[cfg(test)]
+#[path = "./viewport_test.rs"]
+mod viewport_test;
+
+/// how a video frame is scaled to fit the window
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scaling {
+    /// largest size keeping the aspect ratio
+    Fit,
+
+    /// nearest-neighbor scaling by whole pixels, the aspect ratio is approximated
+    Integer,
+}
+
+/// the window area a video frame is drawn to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+
+    /// frame size with non-square pixels stretched to square ones, the coordinate space of the mouse
+    pub logical_width: u32,
+    pub logical_height: u32,
+}
+
+impl Viewport {
+    /// returns the viewport of a `mode` frame centered in a `window_w` x `window_h` window.
+    /// if `aspect` is set, pixels are stretched by the scale_x and scale_y of the mode
+    pub fn for_mode(mode: &VideoModeBlock, window_w: u32, window_h: u32, scaling: Scaling, aspect: bool) -> Self {
+        let (scale_x, scale_y) = if aspect {
+            (mode.scale_x, mode.scale_y)
+        } else {
+            (1., 1.)
+        };
+        let logical_width = (mode.swidth as f32 * scale_x).round() as u32;
+        let logical_height = (mode.sheight as f32 * scale_y).round() as u32;
+
+        let (width, height) = match scaling {
+            Scaling::Fit => {
+                let factor = (window_w as f32 / logical_width as f32).min(window_h as f32 / logical_height as f32);
+                ((logical_width as f32 * factor) as u32, (logical_height as f32 * factor) as u32)
+            }
+            Scaling::Integer => {
+                // pick the largest horizontal factor where the matching vertical factor fits
+                let ratio = scale_y / scale_x;
+                let mut size = (mode.swidth, mode.sheight);
+                let mut fx = (window_w / mode.swidth.max(1)).max(1);
+                while fx > 1 {
+                    let fy = ((fx as f32 * ratio).round() as u32).max(1);
+                    if mode.swidth * fx <= window_w && mode.sheight * fy <= window_h {
+                        size = (mode.swidth * fx, mode.sheight * fy);
+                        break;
+                    }
+                    fx -= 1;
+                }
+                size
+            }
+        };
+
+        Viewport {
+            x: (window_w as i32 - width as i32) / 2,
+            y: (window_h as i32 - height as i32) / 2,
+            width,
+            height,
+            logical_width,
+            logical_height,
+        }
+    }
+
+    /// maps a window position to the logical frame coordinates, clamped to the frame
+    pub fn logical_position(&self, x: i32, y: i32) -> (i32, i32) {
+        let map = |v: i32, start: i32, size: u32, logical: u32| -> i32 {
+            if size == 0 {
+                return 0;
+            }
+            let v = (v - start).max(0).min(size as i32 - 1);
+            (i64::from(v) * i64::from(logical) / i64::from(size)) as i32
+        };
+        (map(x, self.x, self.width, self.logical_width), map(y, self.y, self.height, self.logical_height))
+    }
+
+    /// returns the window rows covered by frame scanline `line` of a frame with `lines` scanlines,
+    /// as (first row, number of rows)
+    pub fn scanline_rows(&self, line: u32, lines: u32) -> (i32, u32) {
+        let top = u64::from(line) * u64::from(self.height) / u64::from(lines.max(1));
+        let bottom = u64::from(line + 1) * u64::from(self.height) / u64::from(lines.max(1));
+        (self.y + top as i32, (bottom - top) as u32)
+    }
+}
+
+/// returns the window size for showing a `mode` frame at `scale` times its logical size
+pub fn window_size(mode: &VideoModeBlock, scale: f32, aspect: bool) -> (u32, u32) {
+    let (scale_x, scale_y) = if aspect {
+        (mode.scale_x, mode.scale_y)
+    } else {
+        (1., 1.)
+    };
+    ((mode.swidth as f32 * scale_x * scale) as u32, (mode.sheight as f32 * scale_y * scale) as u32)
+}
@@ -0,0 +1,34 @@
+use crate::gpu::{vga_mode_block, Scaling, Viewport, window_size};
+
+#[test]
+fn can_fit_with_aspect_correction() {
+    let mode = vga_mode_block().iter().find(|b| b.mode == 0x13).unwrap().clone();
+    assert_eq!((640, 480), window_size(&mode, 2., true));
+
+    // 320x200 stretched to 4:3 in a 16:9 window is pillarboxed
+    let vp = Viewport::for_mode(&mode, 1920, 1080, Scaling::Fit, true);
+    assert_eq!((240, 0, 1440, 1080), (vp.x, vp.y, vp.width, vp.height));
+    assert_eq!((320, 240), (vp.logical_width, vp.logical_height));
+    assert_eq!((0, 0), vp.logical_position(100, -5));
+    assert_eq!((160, 120), vp.logical_position(960, 540));
+    assert_eq!((319, 239), vp.logical_position(1900, 1080));
+
+    let vp = Viewport::for_mode(&mode, 1920, 1080, Scaling::Fit, false);
+    assert_eq!((1728, 1080), (vp.width, vp.height));
+}
+
+#[test]
+fn can_scale_by_whole_pixels() {
+    let mode = vga_mode_block().iter().find(|b| b.mode == 0x13).unwrap().clone();
+
+    // 4x horizontally gives 5x vertically, approximating the 1.2 pixel aspect
+    let vp = Viewport::for_mode(&mode, 1920, 1080, Scaling::Integer, true);
+    assert_eq!((1280, 1000), (vp.width, vp.height));
+    assert_eq!((320, 40), (vp.x, vp.y));
+    assert_eq!((40, 5), vp.scanline_rows(0, 200));
+    assert_eq!((1035, 5), vp.scanline_rows(199, 200));
+
+    // windows smaller than the frame fall back to 1x
+    let vp = Viewport::for_mode(&mode, 300, 150, Scaling::Integer, true);
+    assert_eq!((320, 200), (vp.width, vp.height));
+}
@@ -0,0 +1,103 @@
+use std::time::{Duration, Instant};
+
+use crate::governor::Governor;
+use crate::machine::Machine;
+
+#[cfg(test)]
+#[path = "./group_test.rs"]
+mod group_test;
+
+/// emulated time a machine runs before the next machine takes its turn
+const SLICE: Duration = Duration::from_millis(1);
+
+/// most host time caught up by one call to `sync`, so that a stalled host doesn't
+/// keep the machines busy catching up
+const MAX_CATCH_UP: Duration = Duration::from_millis(100);
+
+/// runs several machines in lockstep, such as the players of a network game or the two sides
+/// of an A/B comparison. the machines take turns executing short slices of emulated time,
+/// so they see the same virtual time pass regardless of their cpu speeds
+#[derive(Default)]
+pub struct MachineGroup {
+    machines: Vec<Machine>,
+
+    /// cycle budget of each machine
+    governors: Vec<Governor>,
+
+    /// emulated time executed by the group
+    elapsed: Duration,
+
+    /// host time of the last call to `sync`
+    last_sync: Option<Instant>,
+}
+
+impl MachineGroup {
+    /// adds `machine` to the group, returns its index
+    pub fn add(&mut self, machine: Machine) -> usize {
+        self.machines.push(machine);
+        self.governors.push(Governor::default());
+        self.machines.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.machines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.machines.is_empty()
+    }
+
+    pub fn machine(&self, index: usize) -> &Machine {
+        &self.machines[index]
+    }
+
+    pub fn machine_mut(&mut self, index: usize) -> &mut Machine {
+        &mut self.machines[index]
+    }
+
+    /// returns the emulated time executed by the group
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// returns true if all machines have stopped
+    pub fn stopped(&self) -> bool {
+        self.machines.iter().all(|m| m.cpu.fatal_error)
+    }
+
+    /// executes `duration` of emulated time on each machine, in turns of `SLICE`.
+    /// stopped machines are skipped and don't hold back the others
+    pub fn execute_for(&mut self, duration: Duration) {
+        let mut left = duration;
+        while left > Duration::from_secs(0) {
+            let slice = left.min(SLICE);
+            for (machine, governor) in self.machines.iter_mut().zip(&mut self.governors) {
+                if machine.cpu.fatal_error {
+                    continue;
+                }
+                governor.add_time(slice, machine.cpu.clock_hz);
+                while governor.budget() > 0 {
+                    let start = machine.cpu.cycle_count;
+                    machine.execute_instruction();
+                    governor.spend(machine.cpu.cycle_count.wrapping_sub(start));
+                    if machine.cpu.fatal_error {
+                        governor.reset();
+                        break;
+                    }
+                }
+            }
+            self.elapsed += slice;
+            left -= slice;
+        }
+    }
+
+    /// executes the host time passed since the last call, keeping the machines in step
+    /// with the wall clock. the first call starts the clock
+    pub fn sync(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last_sync {
+            self.execute_for((now - last).min(MAX_CATCH_UP));
+        }
+        self.last_sync = Some(now);
+    }
+}
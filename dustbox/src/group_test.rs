@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use crate::group::MachineGroup;
+use crate::machine::{Machine, MachineConfig};
+
+/// counts the loop iterations in the word at 0200h
+const COUNTER: [u8; 6] = [
+    0xFF, 0x06, 0x00, 0x02, // inc word [0x200]
+    0xEB, 0xFA,             // jmp short 0x100
+];
+
+fn machine(cpu_hz: usize, code: &[u8]) -> Machine {
+    let mut machine = Machine::with_config(MachineConfig::parse(&format!("cpu_hz = {}", cpu_hz)).unwrap());
+    machine.load_executable(code, 0x085F);
+    machine
+}
+
+#[test]
+fn machine_group_runs_machines_in_lockstep() {
+    let mut group = MachineGroup::default();
+    let slow = group.add(machine(1_000_000, &COUNTER));
+    let fast = group.add(machine(2_000_000, &COUNTER));
+    let start = (group.machine(slow).cpu.cycle_count, group.machine(fast).cpu.cycle_count);
+
+    group.execute_for(Duration::from_micros(10_500));
+    assert_eq!(Duration::from_micros(10_500), group.elapsed());
+    let slow_cycles = group.machine(slow).cpu.cycle_count - start.0;
+    let fast_cycles = group.machine(fast).cpu.cycle_count - start.1;
+    assert!((10_500..10_510).contains(&slow_cycles));
+    assert!((21_000..21_010).contains(&fast_cycles));
+
+    let slow_count = group.machine(slow).mmu.read_u16(0x085F, 0x0200);
+    let fast_count = group.machine(fast).mmu.read_u16(0x085F, 0x0200);
+    assert!(slow_count > 0);
+    assert!((2 * slow_count).abs_diff(fast_count) <= 2);
+}
+
+#[test]
+fn machine_group_skips_stopped_machines() {
+    let mut group = MachineGroup::default();
+    let stopping = group.add(machine(1_000_000, &[0xCD, 0x20])); // int 0x20
+    let running = group.add(machine(1_000_000, &COUNTER));
+
+    group.execute_for(Duration::from_millis(5));
+    assert!(group.machine(stopping).cpu.fatal_error);
+    assert!(!group.stopped());
+    assert!(group.machine(running).mmu.read_u16(0x085F, 0x0200) > 0);
+
+    group.machine_mut(running).cpu.fatal_error = true;
+    assert!(group.stopped());
+}
@@ -0,0 +1,502 @@
+// Experimental template JIT, translating hot basic blocks of 16-bit register
+// instructions into x86-64 host code.
+//
+// Guest registers and flags live in a `JitState` which is passed to the generated code
+// in RDI. Arithmetic is performed by the host using the same instruction, and the
+// resulting host flags are merged back into the guest flags.
+
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::ptr;
+
+use log::debug;
+
+use crate::cpu::{CPU, Decoder, Instruction, Op, Parameter, R, RegisterState, RepeatMode, OperandSize};
+use crate::memory::{MMU, MemoryAddress};
+
+#[cfg(not(all(target_arch = "x86_64", unix)))]
+compile_error!("the jit feature requires a x86_64 unix host");
+
+#[cfg(test)]
+#[path = "./jit_test.rs"]
+mod jit_test;
+
+/// number of times a branch target is reached before it is compiled
+pub const HOT_THRESHOLD: u32 = 50;
+
+/// max number of guest instructions in a block
+const MAX_BLOCK_INSTRUCTIONS: usize = 64;
+
+// FLAGS bits
+const CF: u32 = 0x0001;
+const PF: u32 = 0x0004;
+const AF: u32 = 0x0010;
+const ZF: u32 = 0x0040;
+const SF: u32 = 0x0080;
+const OF: u32 = 0x0800;
+
+/// arithmetic flags, the only flags transfered to and from the host
+pub const ARITHMETIC_FLAGS: u16 = (CF | PF | AF | ZF | SF | OF) as u16;
+
+// JitState field offsets
+const STATE_FLAGS: u8 = 16;
+const STATE_IP: u8 = 18;
+
+/// guest state shared with the generated code
+#[repr(C)]
+#[derive(Default)]
+pub struct JitState {
+    /// 16-bit general purpose registers, in encoding order (ax, cx, dx, bx, sp, bp, si, di)
+    pub regs: [u16; 8],
+    pub flags: u16,
+    pub ip: u16,
+}
+
+impl JitState {
+    fn from_cpu(cpu: &CPU) -> Self {
+        let mut state = JitState::default();
+        for (i, r) in [R::AX, R::CX, R::DX, R::BX, R::SP, R::BP, R::SI, R::DI].iter().enumerate() {
+            state.regs[i] = cpu.get_r16(*r);
+        }
+        state.flags = cpu.regs.flags.u16();
+        state.ip = cpu.regs.ip;
+        state
+    }
+
+    fn to_cpu(&self, cpu: &mut CPU) {
+        for (i, r) in [R::AX, R::CX, R::DX, R::BX, R::SP, R::BP, R::SI, R::DI].iter().enumerate() {
+            cpu.set_r16(*r, self.regs[i]);
+        }
+        let flags = &mut cpu.regs.flags;
+        flags.set_carry(self.flags & CF as u16 != 0);
+        flags.set_parity(self.flags & PF as u16 != 0);
+        flags.set_adjust(self.flags & AF as u16 != 0);
+        flags.set_zero(self.flags & ZF as u16 != 0);
+        flags.set_sign(self.flags & SF as u16 != 0);
+        flags.set_overflow(self.flags & OF as u16 != 0);
+        cpu.regs.ip = self.ip;
+    }
+}
+
+/// a page of executable host memory
+struct ExecutableBuffer {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// the buffer is never written to after creation
+unsafe impl Send for ExecutableBuffer {}
+
+impl ExecutableBuffer {
+    fn new(code: &[u8]) -> Option<Self> {
+        let page = 0x1000;
+        let len = code.len().div_ceil(page) * page;
+        unsafe {
+            let ptr = libc::mmap(ptr::null_mut(), len, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_PRIVATE | libc::MAP_ANON, -1, 0);
+            if ptr == libc::MAP_FAILED {
+                return None;
+            }
+            ptr::copy_nonoverlapping(code.as_ptr(), ptr as *mut u8, code.len());
+            if libc::mprotect(ptr, len, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                libc::munmap(ptr, len);
+                return None;
+            }
+            Some(ExecutableBuffer { ptr, len })
+        }
+    }
+
+    fn call(&self, state: &mut JitState) {
+        unsafe {
+            let f: extern "C" fn(*mut JitState) = mem::transmute(self.ptr);
+            f(state);
+        }
+    }
+}
+
+impl Drop for ExecutableBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+/// a translated basic block
+pub struct CompiledBlock {
+    segment: u16,
+    offset: u16,
+
+    /// guest code the block was translated from, used to detect self-modifying code
+    bytes: Vec<u8>,
+
+    /// number of guest instructions in the block
+    pub instructions: usize,
+
+    code: ExecutableBuffer,
+}
+
+impl CompiledBlock {
+    /// returns true if the guest code still matches the translated code
+    fn is_current(&self, mmu: &MMU) -> bool {
+        mmu.read(self.segment, self.offset, self.bytes.len()) == self.bytes
+    }
+
+    /// runs the block on the cpu state
+    pub fn run(&self, cpu: &mut CPU) {
+        let mut state = JitState::from_cpu(cpu);
+        self.code.call(&mut state);
+        state.to_cpu(cpu);
+        cpu.instruction_count += self.instructions;
+        // one cycle per instruction, as in the interpreter
+        cpu.cycle_count += self.instructions;
+    }
+}
+
+#[derive(Default)]
+pub struct Jit {
+    /// execution counts of branch targets, by physical address
+    counters: HashMap<u32, u32>,
+
+    /// compiled blocks, by physical address
+    blocks: HashMap<u32, CompiledBlock>,
+
+    /// addresses that failed to compile or validate
+    rejected: HashSet<u32>,
+
+    /// if set, each block execution is compared to the interpreter
+    pub validate: bool,
+
+    /// if set, all instructions are interpreted, as the debugger checks breakpoints after each one
+    pub disabled: bool,
+
+    /// number of blocks compiled
+    pub compiled: usize,
+
+    /// number of blocks dropped due to self-modifying code
+    pub invalidated: usize,
+
+    /// number of blocks that failed validation
+    pub mismatches: usize,
+}
+
+impl Jit {
+    /// counts a branch to seg:offset, compiling the target once it is hot
+    pub fn on_branch(&mut self, mmu: &mut MMU, decoder: &mut Decoder, seg: u16, offset: u16) {
+        let addr = MemoryAddress::RealSegmentOffset(seg, offset).value();
+        if self.blocks.contains_key(&addr) || self.rejected.contains(&addr) {
+            return;
+        }
+        let count = self.counters.entry(addr).or_insert(0);
+        *count += 1;
+        if *count < HOT_THRESHOLD {
+            return;
+        }
+        self.counters.remove(&addr);
+        match compile_block(mmu, decoder, seg, offset) {
+            Some(block) => {
+                debug!(target: "cpu", "jit: compiled {:04X}:{:04X}, {} instructions", seg, offset, block.instructions);
+                self.compiled += 1;
+                self.blocks.insert(addr, block);
+            }
+            None => {
+                self.rejected.insert(addr);
+            }
+        }
+    }
+
+    /// returns the compiled block at seg:offset, dropping it if the guest code was modified
+    pub fn block(&mut self, mmu: &MMU, seg: u16, offset: u16) -> Option<&CompiledBlock> {
+        let addr = MemoryAddress::RealSegmentOffset(seg, offset).value();
+        let current = match self.blocks.get(&addr) {
+            Some(block) => block.segment == seg && block.offset == offset && block.is_current(mmu),
+            None => return None,
+        };
+        if !current {
+            debug!(target: "cpu", "jit: dropping modified block at {:04X}:{:04X}", seg, offset);
+            self.blocks.remove(&addr);
+            self.invalidated += 1;
+            return None;
+        }
+        self.blocks.get(&addr)
+    }
+
+    /// runs the compiled block at seg:offset, returns the number of instructions executed.
+    /// the block is left to the interpreter if it takes more than `max_cycles`, so that
+    /// interrupts raised within it are taken at the right instruction
+    pub fn run(&mut self, mmu: &MMU, cpu: &mut CPU, seg: u16, offset: u16, max_cycles: usize) -> Option<usize> {
+        let block = self.block(mmu, seg, offset)?;
+        if block.instructions > max_cycles {
+            return None;
+        }
+        block.run(cpu);
+        Some(block.instructions)
+    }
+
+    /// drops the block at seg:offset and never compiles it again
+    pub fn reject(&mut self, seg: u16, offset: u16) {
+        let addr = MemoryAddress::RealSegmentOffset(seg, offset).value();
+        self.blocks.remove(&addr);
+        self.rejected.insert(addr);
+        self.mismatches += 1;
+    }
+}
+
+/// translates the block at seg:offset, returns None if the first instruction is not supported
+fn compile_block(mmu: &mut MMU, decoder: &mut Decoder, seg: u16, offset: u16) -> Option<CompiledBlock> {
+    let mut asm = Assembler::default();
+    let mut ip = offset;
+    let mut instructions = 0;
+    loop {
+        let op = decoder.get_instruction(mmu, seg, ip);
+        let next = ip.wrapping_add(u16::from(op.length));
+        if !asm.translate(&op, next) {
+            // end the block before the unsupported instruction
+            if instructions == 0 {
+                return None;
+            }
+            asm.set_ip(ip);
+            asm.ret();
+            break;
+        }
+        instructions += 1;
+        ip = next;
+        if is_branch(&op.command) {
+            break;
+        }
+        if instructions >= MAX_BLOCK_INSTRUCTIONS {
+            asm.set_ip(ip);
+            asm.ret();
+            break;
+        }
+    }
+
+    let length = ip.wrapping_sub(offset) as usize;
+    Some(CompiledBlock {
+        segment: seg,
+        offset,
+        bytes: mmu.read(seg, offset, length),
+        instructions,
+        code: ExecutableBuffer::new(&asm.code)?,
+    })
+}
+
+/// compares the registers and arithmetic flags touched by compiled code
+pub fn same_state(a: &RegisterState, b: &RegisterState) -> bool {
+    for r in &[R::AX, R::CX, R::DX, R::BX, R::SP, R::BP, R::SI, R::DI] {
+        if a.get_r16(*r) != b.get_r16(*r) {
+            return false;
+        }
+    }
+    a.ip == b.ip && a.flags.u16() & ARITHMETIC_FLAGS == b.flags.u16() & ARITHMETIC_FLAGS
+}
+
+fn is_branch(op: &Op) -> bool {
+    match op {
+        Op::JmpShort | Op::JmpNear | Op::Loop => true,
+        _ => condition_code(op).is_some(),
+    }
+}
+
+/// returns the host condition code for conditional jumps
+fn condition_code(op: &Op) -> Option<u8> {
+    match op {
+        Op::Jo => Some(0x0),
+        Op::Jno => Some(0x1),
+        Op::Jc => Some(0x2),
+        Op::Jnc => Some(0x3),
+        Op::Jz => Some(0x4),
+        Op::Jnz => Some(0x5),
+        Op::Jna => Some(0x6),
+        Op::Ja => Some(0x7),
+        Op::Js => Some(0x8),
+        Op::Jns => Some(0x9),
+        Op::Jpe => Some(0xA),
+        Op::Jpo => Some(0xB),
+        Op::Jl => Some(0xC),
+        Op::Jnl => Some(0xD),
+        Op::Jng => Some(0xE),
+        Op::Jg => Some(0xF),
+        _ => None,
+    }
+}
+
+/// returns the JitState offset of a 16-bit register
+fn reg_offset(p: &Parameter) -> Option<u8> {
+    match p {
+        Parameter::Reg16(r) => Some((r.index() * 2) as u8),
+        _ => None,
+    }
+}
+
+/// returns the value of a immediate parameter, sign extended to 16 bits
+fn imm16(p: &Parameter) -> Option<u16> {
+    match *p {
+        Parameter::Imm8(v) => Some(u16::from(v)),
+        Parameter::ImmS8(v) => Some(v as i16 as u16),
+        Parameter::Imm16(v) => Some(v),
+        _ => None,
+    }
+}
+
+/// emits x86-64 code operating on a JitState pointed to by RDI
+#[derive(Default)]
+struct Assembler {
+    code: Vec<u8>,
+}
+
+impl Assembler {
+    /// translates a instruction, returns false if it is not supported
+    fn translate(&mut self, op: &Instruction, next: u16) -> bool {
+        if op.repeat != RepeatMode::None || op.lock || op.op_size != OperandSize::_16bit {
+            return false;
+        }
+        // (/digit for imm16 form, opcode for r/m16,r16 form)
+        let alu = match op.command {
+            Op::Add16 => Some((0, 0x01)),
+            Op::Or16 => Some((1, 0x09)),
+            Op::And16 => Some((4, 0x21)),
+            Op::Sub16 => Some((5, 0x29)),
+            Op::Xor16 => Some((6, 0x31)),
+            Op::Cmp16 => Some((7, 0x39)),
+            _ => None,
+        };
+        if let Some((digit, opcode)) = alu {
+            let dst = match reg_offset(&op.params.dst) {
+                Some(dst) => dst,
+                None => return false,
+            };
+            if let Some(src) = reg_offset(&op.params.src) {
+                self.load_ax(src);
+                self.emit(&[0x66, opcode, 0x47, dst]);          // op [rdi+dst], ax
+            } else if let Some(imm) = imm16(&op.params.src) {
+                self.emit(&[0x66, 0x81, 0x47 | (digit << 3), dst]); // op word [rdi+dst], imm16
+                self.emit_u16(imm);
+            } else {
+                return false;
+            }
+            let mask = match op.command {
+                // AF is undefined for logical operations and left as is
+                Op::Or16 | Op::And16 | Op::Xor16 => CF | PF | ZF | SF | OF,
+                _ => CF | PF | AF | ZF | SF | OF,
+            };
+            self.store_flags(mask);
+            return true;
+        }
+
+        match op.command {
+            Op::Mov16 => {
+                let dst = match reg_offset(&op.params.dst) {
+                    Some(dst) => dst,
+                    None => return false,
+                };
+                if let Some(src) = reg_offset(&op.params.src) {
+                    self.load_ax(src);
+                    self.emit(&[0x66, 0x89, 0x47, dst]);        // mov [rdi+dst], ax
+                } else if let Some(imm) = imm16(&op.params.src) {
+                    self.emit(&[0x66, 0xC7, 0x47, dst]);        // mov word [rdi+dst], imm16
+                    self.emit_u16(imm);
+                } else {
+                    return false;
+                }
+            }
+            Op::Inc16 | Op::Dec16 => {
+                let dst = match reg_offset(&op.params.dst) {
+                    Some(dst) => dst,
+                    None => return false,
+                };
+                let modrm = if op.command == Op::Inc16 { 0x47 } else { 0x4F };
+                self.emit(&[0x66, 0xFF, modrm, dst]);           // inc/dec word [rdi+dst]
+                self.store_flags(PF | AF | ZF | SF | OF);
+            }
+            Op::JmpShort | Op::JmpNear => {
+                let target = match imm16(&op.params.dst) {
+                    Some(target) => target,
+                    None => return false,
+                };
+                self.set_ip(target);
+                self.ret();
+            }
+            Op::Loop => {
+                let target = match imm16(&op.params.dst) {
+                    Some(target) => target,
+                    None => return false,
+                };
+                self.emit(&[0x66, 0xFF, 0x4F, 0x02]);           // dec word [rdi+cx]
+                self.branch(0x5, target, next);                 // jnz
+            }
+            _ => {
+                let cc = match condition_code(&op.command) {
+                    Some(cc) => cc,
+                    None => return false,
+                };
+                let target = match imm16(&op.params.dst) {
+                    Some(target) => target,
+                    None => return false,
+                };
+                self.load_flags();
+                self.branch(cc, target, next);
+            }
+        }
+        true
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn emit_u16(&mut self, v: u16) {
+        self.code.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn emit_u32(&mut self, v: u32) {
+        self.code.extend_from_slice(&v.to_le_bytes());
+    }
+
+    /// mov ax, [rdi+offset]
+    fn load_ax(&mut self, offset: u8) {
+        self.emit(&[0x66, 0x8B, 0x47, offset]);
+    }
+
+    /// merges the host flags selected by `mask` into the guest flags
+    fn store_flags(&mut self, mask: u32) {
+        self.emit(&[0x9C]);                             // pushfq
+        self.emit(&[0x59]);                             // pop rcx
+        self.emit(&[0x81, 0xE1]);                       // and ecx, mask
+        self.emit_u32(mask);
+        self.emit(&[0x0F, 0xB7, 0x57, STATE_FLAGS]);    // movzx edx, word [rdi+flags]
+        self.emit(&[0x81, 0xE2]);                       // and edx, !mask
+        self.emit_u32(!mask);
+        self.emit(&[0x09, 0xCA]);                       // or edx, ecx
+        self.emit(&[0x66, 0x89, 0x57, STATE_FLAGS]);    // mov [rdi+flags], dx
+    }
+
+    /// loads the guest arithmetic flags into the host flags
+    fn load_flags(&mut self) {
+        self.emit(&[0x0F, 0xB7, 0x4F, STATE_FLAGS]);    // movzx ecx, word [rdi+flags]
+        self.emit(&[0x81, 0xE1]);                       // and ecx, ARITHMETIC_FLAGS
+        self.emit_u32(u32::from(ARITHMETIC_FLAGS));
+        self.emit(&[0x83, 0xC9, 0x02]);                 // or ecx, 2 (reserved bit)
+        self.emit(&[0x51]);                             // push rcx
+        self.emit(&[0x9D]);                             // popfq
+    }
+
+    /// ends the block with a jump to `target` if condition `cc` holds, else to `next`
+    fn branch(&mut self, cc: u8, target: u16, next: u16) {
+        self.emit(&[0x0F, 0x80 | cc]);                  // jcc taken
+        self.emit_u32(7);
+        self.set_ip(next);
+        self.ret();
+        self.set_ip(target);                            // taken:
+        self.ret();
+    }
+
+    /// mov word [rdi+ip], ip
+    fn set_ip(&mut self, ip: u16) {
+        self.emit(&[0x66, 0xC7, 0x47, STATE_IP]);
+        self.emit_u16(ip);
+    }
+
+    fn ret(&mut self) {
+        self.emit(&[0xC3]);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::cpu::R;
+use crate::machine::Machine;
+
+/// a register only loop, hot enough to be compiled
+const HOT_LOOP: [u8; 22] = [
+    0xB9, 0x00, 0x04,       // mov cx,0x400
+    0x31, 0xC0,             // xor ax,ax
+    0xBB, 0x34, 0x12,       // mov bx,0x1234
+    0x01, 0xC8,             // add ax,cx        <- loop
+    0x43,                   // inc bx
+    0x4A,                   // dec dx
+    0x31, 0xC6,             // xor si,ax
+    0x83, 0xEF, 0x03,       // sub di,byte +0x3
+    0x3D, 0x00, 0x80,       // cmp ax,0x8000
+    0xE2, 0xF2,             // loop 0x108
+];
+
+fn run_hot_loop(validate: bool) -> Machine {
+    let mut machine = Machine::deterministic();
+    machine.jit.validate = validate;
+    machine.load_executable(&HOT_LOOP, 0x085F);
+    while machine.cpu.regs.ip != 0x100 + HOT_LOOP.len() as u16 {
+        machine.execute_instruction();
+    }
+    machine
+}
+
+#[test]
+fn can_compile_hot_loop() {
+    let machine = run_hot_loop(true);
+    assert_eq!(1, machine.jit.compiled);
+    assert_eq!(0, machine.jit.mismatches);
+    assert_eq!(0, machine.cpu.get_r16(R::CX));
+
+    // same result without the interpreter double checking
+    let compiled = run_hot_loop(false);
+    assert_eq!(1, compiled.jit.compiled);
+    for r in &[R::AX, R::BX, R::CX, R::DX, R::SI, R::DI] {
+        assert_eq!(machine.cpu.get_r16(*r), compiled.cpu.get_r16(*r));
+    }
+    assert_eq!(machine.cpu.regs.flags.u16(), compiled.cpu.regs.flags.u16());
+    assert_eq!(machine.cpu.instruction_count, compiled.cpu.instruction_count);
+}
+
+#[test]
+fn drops_modified_block() {
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&HOT_LOOP, 0x085F);
+    while machine.jit.compiled == 0 || machine.cpu.regs.ip != 0x108 {
+        machine.execute_instruction();
+    }
+
+    // change "inc bx" into "dec bx"
+    machine.mmu.write_u8(0x085F, 0x10A, 0x4B);
+    let bx = machine.cpu.get_r16(R::BX);
+    let cx = machine.cpu.get_r16(R::CX);
+    while machine.cpu.regs.ip != 0x100 + HOT_LOOP.len() as u16 {
+        machine.execute_instruction();
+    }
+    assert_eq!(1, machine.jit.invalidated);
+    assert_eq!(0, machine.jit.mismatches);
+    assert_eq!(bx.wrapping_sub(cx), machine.cpu.get_r16(R::BX));
+}
+
+#[test]
+fn can_take_timer_interrupts_within_compiled_blocks() {
+    let code: Vec<u8> = vec![
+        0xB0, 0x36,             // mov al,0x36      ; timer 0, mode 3
+        0xE6, 0x43,             // out 0x43,al
+        0xB0, 0x00,             // mov al,0x0
+        0xE6, 0x40,             // out 0x40,al
+        0xB0, 0x01,             // mov al,0x1       ; count 0x100
+        0xE6, 0x40,             // out 0x40,al
+        0xFB,                   // sti
+        0xB9, 0x00, 0x10,       // mov cx,0x1000
+        0x31, 0xC0,             // xor ax,ax
+        0xBB, 0x34, 0x12,       // mov bx,0x1234
+        0x01, 0xC8,             // add ax,cx        <- loop
+        0x43,                   // inc bx
+        0x4A,                   // dec dx
+        0x31, 0xC6,             // xor si,ax
+        0x83, 0xEF, 0x03,       // sub di,byte +0x3
+        0x3D, 0x00, 0x80,       // cmp ax,0x8000
+        0xE2, 0xF2,             // loop 0x115
+        0xF4,                   // hlt
+        0x50,                   // push ax          <- int 8 handler
+        0xB0, 0x20,             // mov al,0x20
+        0xE6, 0x20,             // out 0x20,al
+        0x58,                   // pop ax
+        0xCF,                   // iret
+    ];
+    // returns the instruction counts when the timer interrupt was taken
+    let run = |disabled: bool| {
+        let mut machine = Machine::deterministic();
+        machine.jit.disabled = disabled;
+        machine.load_executable(&code, 0x085F);
+        machine.mmu.write_u16(0, 8 * 4, 0x0124);
+        machine.mmu.write_u16(0, 8 * 4 + 2, 0x085F);
+        let mut interrupts = Vec::new();
+        while machine.cpu.regs.ip != 0x0123 {
+            machine.execute_instruction();
+            if machine.cpu.regs.ip == 0x0124 {
+                interrupts.push(machine.cpu.instruction_count);
+            }
+        }
+        (machine.jit.compiled, interrupts)
+    };
+    let (compiled, interrupts) = run(false);
+    let (_, interpreted) = run(true);
+    assert_eq!(1, compiled);
+    assert!(interpreted.len() > 10);
+    assert_eq!(interpreted, interrupts);
+}
@@ -1,30 +1,187 @@
-// TODO later: dont depend on sdl2 in the core crate (process events with something else?)
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 
-use sdl2::keyboard::{Keycode, Mod};
+use log::{debug, warn};
 
 use crate::cpu::{CPU, R, FLAG_ZF};
 use crate::memory::MMU;
 use crate::machine::Component;
 
-const DEBUG_KEYBOARD: bool = false;
+/// controller command byte: keyboard interrupt enabled, system flag, scancode translation
+const DEFAULT_COMMAND_BYTE: u8 = 0b0100_0101;
+
+/// command byte: the keyboard interface is disabled
+const COMMAND_KEYBOARD_DISABLED: u8 = 0b0001_0000;
+
+/// command byte: the auxiliary (mouse) interface is disabled
+const COMMAND_AUX_DISABLED: u8 = 0b0010_0000;
+
+/// command byte: the system flag, reported in the status register
+const COMMAND_SYSTEM: u8 = 0b0000_0100;
+
+/// keyboard response: command acknowledged
+const ACK: u8 = 0xFA;
+
+/// keyboard response: unknown command, resend
+const RESEND: u8 = 0xFE;
 
 #[cfg(test)]
 #[path = "./keyboard_test.rs"]
 mod keyboard_test;
 
+/// declares `Keycode`, and its conversion from SDL key codes when the `sdl2` feature is enabled
+macro_rules! keycodes {
+    ($($key:ident),* $(,)?) => {
+        /// a key on the host keyboard
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        pub enum Keycode {
+            $($key,)*
+        }
+
+        #[cfg(feature = "sdl2")]
+        impl Keycode {
+            pub fn from_sdl(keycode: sdl2::keyboard::Keycode) -> Option<Self> {
+                match keycode {
+                    $(sdl2::keyboard::Keycode::$key => Some(Keycode::$key),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+keycodes!(
+    Escape, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9, Num0, Minus, Equals, Backspace, Tab,
+    Q, W, E, R, T, Y, U, I, O, P, LeftBracket, RightBracket, Return,
+    A, S, D, F, G, H, J, K, L, Colon, Quote, Caret, Backslash,
+    Z, X, C, V, B, N, M, Comma, Period, Slash, Asterisk, Space,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Home, Up, PageUp, KpMinus, Left, KpClearEntry, Right, KpPlus, End, Down, PageDown, Insert, Delete, Pause,
+    LShift, RShift, LCtrl, RCtrl, LAlt, RAlt, LGui, RGui,
+);
+
+/// national keyboard layout, as selected by KEYB.COM. decides the ascii code of each key
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyboardLayout {
+    #[default]
+    Us, Uk, De, Fr, It, Es, Sv, No, Dk,
+}
+
+impl KeyboardLayout {
+    /// returns an error for layouts without translation tables, only "us" is implemented
+    pub fn validate(self) -> Result<(), String> {
+        match self {
+            KeyboardLayout::Us => Ok(()),
+            _ => Err(format!("keyboard layout {:?} is not supported, only us is implemented", self).to_lowercase()),
+        }
+    }
+}
+
+impl FromStr for KeyboardLayout {
+    type Err = String;
+
+    /// parses "us", "uk", "de", "fr", "it", "es", "sv", "no" or "dk"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "us" => Ok(KeyboardLayout::Us),
+            "uk" => Ok(KeyboardLayout::Uk),
+            "de" => Ok(KeyboardLayout::De),
+            "fr" => Ok(KeyboardLayout::Fr),
+            "it" => Ok(KeyboardLayout::It),
+            "es" => Ok(KeyboardLayout::Es),
+            "sv" => Ok(KeyboardLayout::Sv),
+            "no" => Ok(KeyboardLayout::No),
+            "dk" => Ok(KeyboardLayout::Dk),
+            _ => Err(format!("unknown keyboard layout {}", s)),
+        }
+    }
+}
+
+/// modifier keys held down with a key press
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers { shift: false, ctrl: false, alt: false };
+    pub const SHIFT: Modifiers = Modifiers { shift: true, ctrl: false, alt: false };
+
+    #[cfg(feature = "sdl2")]
+    pub fn from_sdl(modifier: sdl2::keyboard::Mod) -> Self {
+        use sdl2::keyboard::Mod;
+        Modifiers {
+            shift: modifier.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+            ctrl: modifier.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD),
+            alt: modifier.intersects(Mod::LALTMOD | Mod::RALTMOD),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Keyboard {
     keypresses: Vec<Keypress>,
     status_register: StatusRegister,
+
+    /// controller command waiting for a data byte on port 0060
+    command: Option<u8>,
+
+    /// keyboard command waiting for its argument on port 0060
+    keyboard_command: Option<u8>,
+
+    /// controller and keyboard responses, read from port 0060 before any key press
+    responses: VecDeque<u8>,
+
+    /// controller command byte, read with command 20h and written with command 60h
+    command_byte: u8,
+
+    /// the keyboard sends scancodes, disabled by keyboard command F5h
+    scanning: bool,
+
+    /// typematic rate and delay, set by keyboard command F3h
+    pub typematic: u8,
+
+    /// keyboard LED state, set by keyboard command EDh. bit 0 = scroll lock, 1 = num lock, 2 = caps lock
+    pub leds: u8,
+
+    /// scancode set selected by keyboard command F0h
+    scancode_set: u8,
+
+    /// controller output port, bit 1 gates the A20 line
+    output_port: u8,
+
+    /// A20 line change requested by the controller, applied by the machine
+    a20_request: Option<bool>,
+
+    /// port 0061 control register
+    pub port_b: u8,
 }
 
 impl Component for Keyboard {
+    fn io_ports(&self) -> Vec<RangeInclusive<u16>> {
+        vec![0x0060..=0x0064]
+    }
+
+    fn interrupts(&self) -> Vec<u8> {
+        vec![0x16]
+    }
+
     fn in_u8(&mut self, port: u16) -> Option<u8> {
         // PORT 0060-006F - KEYBOARD CONTROLLER 804x (8041, 8042) (or PPI (8255) on PC,XT)
         // Note: XT uses ports 60h-63h, AT uses ports 60h-64h
         match port {
             0x0060 => {
                 // keyboard controller data output buffer
+                if let Some(data) = self.responses.pop_front() {
+                    return Some(data);
+                }
+                if !self.sends_keys() {
+                    return Some(0);
+                }
                 let (scancode, _, keypress) = self.peek_dos_standard_scancode_and_ascii();
                 if let Some(keypress) = keypress {
                     self.consume(&keypress);
@@ -33,9 +190,9 @@ impl Component for Keyboard {
             },
             0x0061 => {
                 // keyboard controller port b control register
-                let val = 0 as u8; // XXX
-                println!("XXX impl -- keyboard: read keyboard controller port b control register (current {:02X})", val);
-                Some(val)
+                // bit 4 toggles with each memory refresh, bit 5 is the timer 2 output, set by the machine
+                self.port_b ^= 0b0001_0000;
+                Some(self.port_b)
             }
             0x0064 => {
                 // keyboard controller read status
@@ -47,9 +204,22 @@ impl Component for Keyboard {
 
     fn out_u8(&mut self, port: u16, data: u8) -> bool {
         match port {
+            0x0060 => {
+                // keyboard controller data input buffer
+                self.status_register.mode = false;
+                match self.command.take() {
+                    Some(command) => self.controller_data(command, data),
+                    None => self.keyboard_data(data),
+                }
+            }
             0x0061 => {
-                // keyboard controller port b OR ppi programmable periphial interface (XT only) - which mode are we in?
-                println!("XXX impl -- keyboard: write keyboard controller port b {:02X}", data);
+                // keyboard controller port b control register
+                // bit 0 = timer 2 gate, bit 1 = speaker data enable, applied by the machine
+                self.port_b = (self.port_b & 0b0001_0000) | (data & 0b0000_1111);
+            }
+            0x0064 => {
+                self.status_register.mode = true;
+                self.controller_command(data);
             }
             _ => return false
         }
@@ -70,9 +240,7 @@ impl Component for Keyboard {
                 cpu.set_r8(R::AH, ah);
                 cpu.set_r8(R::AL, al);
 
-                if DEBUG_KEYBOARD {
-                    println!("KEYBOARD - GET KEYSTROKE, returns ah {:02x}, al {:02x}", ah, al);
-                }
+                debug!(target: "io", "KEYBOARD - GET KEYSTROKE, returns ah {:02x}, al {:02x}", ah, al);
             }
             0x01 => {
                 // read keyboard scancode (non-blocking)
@@ -85,11 +253,9 @@ impl Component for Keyboard {
 
                 // ZF set if no keystroke available
                 mmu.set_flag(FLAG_ZF, ah == 0);
-                //cpu.regs.flags.zero = ah == 0;
+                //cpu.regs.flags.set_zero(ah == 0);
 
-                if DEBUG_KEYBOARD {
-                    println!("KEYBOARD - CHECK FOR KEYSTROKE, returns ah {:02x}, al {:02x}", ah, al);
-                }
+                debug!(target: "io", "KEYBOARD - CHECK FOR KEYSTROKE, returns ah {:02x}, al {:02x}", ah, al);
             }
             0x05 => {
                 // KEYBOARD - STORE KEYSTROKE IN KEYBOARD BUFFER (AT/PS w enh keybd only)
@@ -101,7 +267,7 @@ impl Component for Keyboard {
                 // 01h if keyboard buffer full
                 let code = cpu.get_r8(R::CH);
                 let ascii = cpu.get_r8(R::CL);
-                println!("XXX impl KEYBOARD - STORE KEYSTROKE IN KEYBOARD BUFFER, code={:02X}, ascii={:02X}", code, ascii);
+                warn!(target: "io", "XXX impl KEYBOARD - STORE KEYSTROKE IN KEYBOARD BUFFER, code={:02X}, ascii={:02X}", code, ascii);
             }
             0x11 => {
                 // KEYBOARD - CHECK FOR ENHANCED KEYSTROKE (enh kbd support only)
@@ -110,9 +276,9 @@ impl Component for Keyboard {
                 // ZF clear if keystroke available
                 // AH = BIOS scan code
                 // AL = ASCII character
-                println!("XXX impl KEYBOARD - CHECK FOR ENHANCED KEYSTROKE");
+                warn!(target: "io", "XXX impl KEYBOARD - CHECK FOR ENHANCED KEYSTROKE");
                 mmu.set_flag(FLAG_ZF, true);
-                //cpu.regs.flags.zero = true;
+                //cpu.regs.flags.set_zero(true);
             }
             0x92 => {
                 // KEYB.COM KEYBOARD CAPABILITIES CHECK (not an actual function!)
@@ -137,22 +303,154 @@ impl Keyboard {
         Self {
             keypresses: Vec::new(),
             status_register: StatusRegister::default(),
+            command: None,
+            keyboard_command: None,
+            responses: VecDeque::new(),
+            command_byte: DEFAULT_COMMAND_BYTE,
+            scanning: true,
+            typematic: 0x2B, // 10.9 characters per second, 500 ms delay
+            leds: 0,
+            scancode_set: 2,
+            output_port: 0b0000_0001, // system reset line inactive, A20 disabled
+            a20_request: None,
+            port_b: 0,
+        }
+    }
+
+    /// port 0064: keyboard controller command
+    fn controller_command(&mut self, command: u8) {
+        debug!(target: "io", "keyboard: controller command {:02X}", command);
+        self.command = None;
+        match command {
+            0x20 => self.respond(&[self.command_byte]),         // read command byte
+            0x60 | 0xD1 | 0xD2 | 0xD3 | 0xD4 => {
+                // write command byte, output port, keyboard output buffer, aux output buffer
+                // or aux device, through port 0060
+                self.command = Some(command);
+            }
+            0xA7 => self.command_byte |= COMMAND_AUX_DISABLED,  // disable aux interface
+            0xA8 => self.command_byte &= !COMMAND_AUX_DISABLED, // enable aux interface
+            0xA9 | 0xAB => self.respond(&[0x00]),               // aux / keyboard interface test: no error
+            0xAA => self.respond(&[0x55]),                      // controller self test: passed
+            0xAD => self.command_byte |= COMMAND_KEYBOARD_DISABLED,  // disable keyboard interface
+            0xAE => self.command_byte &= !COMMAND_KEYBOARD_DISABLED, // enable keyboard interface
+            0xC0 => self.respond(&[0x80]),                      // read input port: keyboard not inhibited
+            0xD0 => self.respond(&[self.output_port]),          // read output port
+            0xDD => self.write_output_port(self.output_port & !0b10), // disable A20
+            0xDF => self.write_output_port(self.output_port | 0b10),  // enable A20
+            0xE0 => self.respond(&[0x00]),                      // read test inputs
+            0xF0..=0xFF => {} // pulse output port, no-op
+            _ => warn!(target: "io", "XXX impl -- keyboard: controller command {:02X}", command),
+        }
+    }
+
+    /// port 0060: data byte for controller `command`
+    fn controller_data(&mut self, command: u8, data: u8) {
+        match command {
+            0x60 => self.command_byte = data,
+            0xD1 => self.write_output_port(data),
+            0xD2 => self.respond(&[data]),
+            0xD3 | 0xD4 => {} // no aux device
+            _ => unreachable!(),
+        }
+    }
+
+    /// port 0060: command or argument sent to the keyboard
+    fn keyboard_data(&mut self, data: u8) {
+        debug!(target: "io", "keyboard: keyboard data {:02X}", data);
+        // writing to the keyboard enables the keyboard interface
+        self.command_byte &= !COMMAND_KEYBOARD_DISABLED;
+        if let Some(command) = self.keyboard_command.take() {
+            match command {
+                0xED => self.leds = data & 0b111,
+                0xF0 if data == 0 => {
+                    // get scancode set, reported translated
+                    let set = [0x43, 0x41, 0x3F][self.scancode_set as usize - 1];
+                    self.respond(&[ACK, set]);
+                    return;
+                }
+                0xF0 if data > 3 => {
+                    self.respond(&[RESEND]);
+                    return;
+                }
+                0xF0 => self.scancode_set = data,
+                0xF3 => self.typematic = data & 0x7F,
+                _ => unreachable!(),
+            }
+            self.respond(&[ACK]);
+            return;
+        }
+        match data {
+            0xED | 0xF0 | 0xF3 => {
+                // set LEDs, get / set scancode set, set typematic rate: argument follows
+                self.keyboard_command = Some(data);
+                self.respond(&[ACK]);
+            }
+            0xEE => self.respond(&[0xEE]),              // echo
+            0xF2 => self.respond(&[ACK, 0xAB, 0x41]),   // identify: MF2 keyboard, translated
+            0xF4 => {
+                self.scanning = true;
+                self.respond(&[ACK]);
+            }
+            0xF5 | 0xF6 | 0xFF => {
+                // disable scanning, set defaults, reset
+                self.scanning = data != 0xF5;
+                self.typematic = 0x2B;
+                self.leds = 0;
+                self.scancode_set = 2;
+                if data == 0xFF {
+                    self.respond(&[ACK, 0xAA]); // self test passed
+                } else {
+                    self.respond(&[ACK]);
+                }
+            }
+            0xF7..=0xFD => self.respond(&[ACK]), // typematic / make / break modes of the AT keyboard
+            _ => self.respond(&[RESEND]),
         }
     }
 
+    /// queues `data` for port 0060, replacing earlier responses not yet read
+    fn respond(&mut self, data: &[u8]) {
+        self.responses.clear();
+        self.responses.extend(data);
+    }
+
+    /// returns true if key presses are sent through port 0060
+    fn sends_keys(&self) -> bool {
+        self.scanning && self.command_byte & COMMAND_KEYBOARD_DISABLED == 0
+    }
+
+    fn write_output_port(&mut self, data: u8) {
+        self.output_port = data;
+        self.a20_request = Some(data & 0b10 != 0);
+    }
+
+    /// returns the A20 line state requested through the controller output port since last call
+    pub fn take_a20_request(&mut self) -> Option<bool> {
+        self.a20_request.take()
+    }
+
     pub fn has_queued_presses(&self) -> bool {
         !self.keypresses.is_empty()
     }
 
-    pub fn add_keypress(&mut self, keycode: Keycode, modifier: Mod) {
-        let keypress = Keypress{keycode, modifier};
-        if DEBUG_KEYBOARD {
-            println!("keyboard: add_keypress {:?}", keypress);
+    /// removes the first queued Ctrl-C or Ctrl-Break key press, as DOS does when checking for a break.
+    /// returns true if one was queued
+    pub fn take_break(&mut self) -> bool {
+        // the queue is consumed from the back
+        match self.keypresses.iter().rposition(Keypress::is_break) {
+            Some(idx) => {
+                self.keypresses.remove(idx);
+                true
+            }
+            None => false,
         }
-        self.keypresses.push(keypress);
+    }
 
-        // signal there is bytes to be read
-        self.status_register.output_buffer_status = true;
+    pub fn add_keypress(&mut self, keycode: Keycode, modifier: Modifiers) {
+        let keypress = Keypress{keycode, modifier};
+        debug!(target: "io", "keyboard: add_keypress {:?}", keypress);
+        self.keypresses.push(keypress);
     }
 
     fn consume_keypress(&mut self) -> Keypress {
@@ -170,10 +468,13 @@ impl Keyboard {
     }
 
     pub fn get_status_register_byte(&self) -> u8 {
-        let val = self.status_register.as_u8();
-        if DEBUG_KEYBOARD {
-            println!("keyboard: read keyboard controller read status (current {:02X})", val);
-        }
+        let mut status = self.status_register.clone();
+        // writes are handled at once, so the input buffer is always empty
+        status.input_buffer_status = false;
+        status.output_buffer_status = !self.responses.is_empty() || (self.sends_keys() && !self.keypresses.is_empty());
+        status.system = self.command_byte & COMMAND_SYSTEM != 0;
+        let val = status.as_u8();
+        debug!(target: "io", "keyboard: read keyboard controller read status (current {:02X})", val);
         val
     }
 
@@ -182,9 +483,7 @@ impl Keyboard {
     pub fn consume_dos_standard_scancode_and_ascii(&mut self) -> (u8, u8) {
         let (ah, al, keypress) = self.peek_dos_standard_scancode_and_ascii();
         if let Some(keypress) = keypress {
-            if DEBUG_KEYBOARD {
-                println!("keyboard: consume_dos_standard_scancode_and_ascii consumes {:?}", keypress);
-            }
+            debug!(target: "io", "keyboard: consume_dos_standard_scancode_and_ascii consumes {:?}", keypress);
             self.consume(&keypress);
         }
         (ah, al)
@@ -194,22 +493,17 @@ impl Keyboard {
     /// returns scancode, ascii, keypress
     pub fn peek_dos_standard_scancode_and_ascii(&self) -> (u8, u8, Option<Keypress>) {
         if let Some(keypress) = self.peek_keypress() {
-            let (ah, al) = map_sdl_to_dos_standard_codes(&keypress);
-            if DEBUG_KEYBOARD {
-                println!("keyboard: peek_dos_standard_scancode_and_ascii returns scancode {:02X}, ascii {:02X}, {:?}", ah, al, keypress);
-            }
+            let (ah, al) = map_to_dos_standard_codes(&keypress);
+            debug!(target: "io", "keyboard: peek_dos_standard_scancode_and_ascii returns scancode {:02X}, ascii {:02X}, {:?}", ah, al, keypress);
             (ah, al, Some(keypress))
         } else {
-            if DEBUG_KEYBOARD {
-                println!("keyboard: peek_dos_standard_scancode_and_ascii returns nothing");
-            }
+            debug!(target: "io", "keyboard: peek_dos_standard_scancode_and_ascii returns nothing");
             (0, 0, None)
         }
     }
 
     fn find_keypress_index(&self, keypress: &Keypress) -> Option<usize> {
         for (idx, x) in self.keypresses.iter().enumerate() {
-            println!("{}", x.keycode);
             if x == keypress {
                 return Some(idx);
             }
@@ -218,15 +512,13 @@ impl Keyboard {
     }
 
     pub fn consume(&mut self, keypress: &Keypress) {
-        if DEBUG_KEYBOARD {
-            println!("keyboard: consume {:?}", keypress);
-        }
+        debug!(target: "io", "keyboard: consume {:?}", keypress);
 
         if let Some(idx) = self.find_keypress_index(keypress) {
             self.keypresses.remove(idx);
         }
 
-        println!("ERROR failed to consume keypress {:?}", keypress);
+        warn!(target: "io", "ERROR failed to consume keypress {:?}", keypress);
     }
 }
 
@@ -310,7 +602,7 @@ impl StatusRegister {
 #[derive(Clone, Debug, PartialEq)]
 pub struct Keypress {
     keycode: Keycode,
-    modifier: Mod,
+    modifier: Modifiers,
 }
 
 /// returns keycodes as specified in https://sites.google.com/site/pcdosretro/scancodes
@@ -401,8 +693,9 @@ impl Keypress {
             Keycode::PageDown => (0x51, 0x00),
             Keycode::Insert => (0x52, 0x00),
             Keycode::Delete => (0x53, 0x00),
+            Keycode::Pause => (0x00, 0x00), // not stored in the keyboard buffer
             _ => {
-                println!("unhandled NORMAL keycode mapping for {:#?}", self.keycode);
+                warn!(target: "io", "unhandled NORMAL keycode mapping for {:#?}", self.keycode);
                 (0, 0)
             }
         }
@@ -494,7 +787,7 @@ impl Keypress {
             Keycode::Insert => (0x52, 0x30),
             Keycode::Delete => (0x53, 0x2E),
             _ => {
-                println!("unhandled SHIFT keycode mapping for {:#?}", self.keycode);
+                warn!(target: "io", "unhandled SHIFT keycode mapping for {:#?}", self.keycode);
                 (0, 0)
             }
         }
@@ -502,17 +795,29 @@ impl Keypress {
 
     pub fn to_std_ctrl(&self) -> (u8, u8) {
         match self.keycode {
+            Keycode::Pause => (0x00, 0x00), // Ctrl-Break
             _ => {
-                println!("unhandled CTRL keycode mapping for {:#?}", self.keycode);
-                (0, 0)
+                // Ctrl+letter gives the control character, such as 03h for Ctrl-C
+                let (scancode, ascii) = self.to_std_normal();
+                if ascii.is_ascii_lowercase() {
+                    (scancode, ascii & 0x1F)
+                } else {
+                    warn!(target: "io", "unhandled CTRL keycode mapping for {:#?}", self.keycode);
+                    (0, 0)
+                }
             }
         }
     }
 
+    /// returns true for Ctrl-C and Ctrl-Break
+    fn is_break(&self) -> bool {
+        self.modifier.ctrl && (self.keycode == Keycode::C || self.keycode == Keycode::Pause)
+    }
+
     pub fn to_std_alt(&self) -> (u8, u8) {
         match self.keycode {
             _ => {
-                println!("unhandled ALT keycode mapping for {:#?}", self.keycode);
+                warn!(target: "io", "unhandled ALT keycode mapping for {:#?}", self.keycode);
                 (0, 0)
             }
         }
@@ -520,18 +825,19 @@ impl Keypress {
 }
 
 // returns scancode, ascii
-fn map_sdl_to_dos_standard_codes(keypress: &Keypress) -> (u8, u8) {
+fn map_to_dos_standard_codes(keypress: &Keypress) -> (u8, u8) {
     match keypress.keycode {
         // misc mappings
-        Keycode::LGui => (0, 0),
-        Keycode::LShift => (0, 0),
-        Keycode::RShift => (0, 0),
+        Keycode::LGui | Keycode::RGui => (0, 0),
+        Keycode::LShift | Keycode::RShift => (0, 0),
+        Keycode::LCtrl | Keycode::RCtrl => (0, 0),
+        Keycode::LAlt | Keycode::RAlt => (0, 0),
         _ => {
-            if keypress.modifier == Mod::LSHIFTMOD || keypress.modifier == Mod::RSHIFTMOD {
+            if keypress.modifier.shift {
                 keypress.to_std_shift()
-            } else if keypress.modifier == Mod::LCTRLMOD || keypress.modifier == Mod::RCTRLMOD {
+            } else if keypress.modifier.ctrl {
                 keypress.to_std_ctrl()
-            } else if keypress.modifier == Mod::LALTMOD || keypress.modifier == Mod::RALTMOD {
+            } else if keypress.modifier.alt {
                 keypress.to_std_alt()
             } else {
                 keypress.to_std_normal()
@@ -1,6 +1,4 @@
-use sdl2::keyboard::{Keycode, Mod};
-
-use crate::keyboard::{Keyboard, StatusRegister};
+use crate::keyboard::{Keyboard, Keycode, Modifiers, StatusRegister};
 use crate::machine::Component;
 
 #[test]
@@ -17,7 +15,7 @@ fn can_read_keys_from_io_ports() {
     assert_eq!(Some(0x14), keyboard.in_u8(0x64));
 
     // inject key press
-    keyboard.add_keypress(Keycode::Escape, Mod::NOMOD);
+    keyboard.add_keypress(Keycode::Escape, Modifiers::NONE);
 
     // in al,0x64
     assert_eq!(Some(0x15), keyboard.in_u8(0x64));
@@ -35,8 +33,8 @@ fn consumes_keypress_queue() {
     assert_eq!(false, keyboard.has_queued_presses());
 
     // inject key press
-    keyboard.add_keypress(Keycode::Escape, Mod::NOMOD);
-    keyboard.add_keypress(Keycode::Escape, Mod::NOMOD);
+    keyboard.add_keypress(Keycode::Escape, Modifiers::NONE);
+    keyboard.add_keypress(Keycode::Escape, Modifiers::NONE);
     assert_eq!(true, keyboard.has_queued_presses());
 
     // read it
@@ -51,3 +49,91 @@ fn consumes_keypress_queue() {
     keyboard.consume(&keypress);
     assert_eq!(false, keyboard.has_queued_presses());
 }
+
+#[test]
+fn can_take_break_key_press() {
+    let mut keyboard = Keyboard::default();
+    let ctrl = Modifiers { ctrl: true, ..Modifiers::NONE };
+    keyboard.add_keypress(Keycode::A, Modifiers::NONE);
+    assert!(!keyboard.take_break());
+
+    keyboard.add_keypress(Keycode::C, ctrl);
+    let (scancode, ascii, _) = keyboard.peek_dos_standard_scancode_and_ascii();
+    assert_eq!((0x2E, 0x03), (scancode, ascii));
+    assert!(keyboard.take_break());
+    assert!(!keyboard.take_break());
+    assert_eq!(0x1E, keyboard.peek_dos_standard_scancode_and_ascii().0);
+}
+
+#[test]
+fn can_run_controller_commands() {
+    let mut keyboard = Keyboard::default();
+
+    // read command byte
+    assert!(keyboard.out_u8(0x64, 0x20));
+    assert_eq!(Some(0x1D), keyboard.in_u8(0x64)); // output buffer full, last write was a command
+    assert_eq!(Some(0x45), keyboard.in_u8(0x60));
+    assert_eq!(Some(0x1C), keyboard.in_u8(0x64));
+
+    // disable the keyboard interface through the command byte
+    keyboard.out_u8(0x64, 0x60);
+    keyboard.out_u8(0x60, 0x55);
+    keyboard.add_keypress(Keycode::Escape, Modifiers::NONE);
+    assert_eq!(Some(0x14), keyboard.in_u8(0x64));
+    assert_eq!(Some(0x00), keyboard.in_u8(0x60));
+    keyboard.out_u8(0x64, 0xAE);
+    assert_eq!(Some(0x01), keyboard.in_u8(0x60));
+
+    // self test
+    keyboard.out_u8(0x64, 0xAA);
+    assert_eq!(Some(0x55), keyboard.in_u8(0x60));
+
+    // write and read the output port
+    keyboard.out_u8(0x64, 0xD1);
+    keyboard.out_u8(0x60, 0xDF);
+    assert_eq!(Some(true), keyboard.take_a20_request());
+    keyboard.out_u8(0x64, 0xD0);
+    assert_eq!(Some(0xDF), keyboard.in_u8(0x60));
+}
+
+#[test]
+fn can_run_keyboard_commands() {
+    let mut keyboard = Keyboard::default();
+
+    // set typematic rate and delay
+    keyboard.out_u8(0x60, 0xF3);
+    assert_eq!(Some(0xFA), keyboard.in_u8(0x60));
+    keyboard.out_u8(0x60, 0x1F);
+    assert_eq!(Some(0xFA), keyboard.in_u8(0x60));
+    assert_eq!(0x1F, keyboard.typematic);
+
+    // set LEDs
+    keyboard.out_u8(0x60, 0xED);
+    keyboard.out_u8(0x60, 0x04);
+    assert_eq!(Some(0xFA), keyboard.in_u8(0x60));
+    assert_eq!(0x04, keyboard.leds);
+
+    // identify
+    keyboard.out_u8(0x60, 0xF2);
+    let id: Vec<_> = (0..3).map(|_| keyboard.in_u8(0x60).unwrap()).collect();
+    assert_eq!(vec![0xFA, 0xAB, 0x41], id);
+
+    // reset restores the defaults
+    keyboard.out_u8(0x60, 0xFF);
+    assert_eq!(Some(0xFA), keyboard.in_u8(0x60));
+    assert_eq!(Some(0xAA), keyboard.in_u8(0x60));
+    assert_eq!(0x2B, keyboard.typematic);
+
+    // disable scanning
+    keyboard.out_u8(0x60, 0xF5);
+    assert_eq!(Some(0xFA), keyboard.in_u8(0x60));
+    keyboard.add_keypress(Keycode::Escape, Modifiers::NONE);
+    assert_eq!(Some(0x00), keyboard.in_u8(0x60));
+    keyboard.out_u8(0x60, 0xF4);
+    assert_eq!(Some(0xFA), keyboard.in_u8(0x60));
+    assert_eq!(Some(0x01), keyboard.in_u8(0x60));
+
+    // unknown commands are answered with resend
+    keyboard.out_u8(0x60, 0x12);
+    assert_eq!(Some(0xFE), keyboard.in_u8(0x60));
+}
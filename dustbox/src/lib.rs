@@ -7,21 +7,32 @@ extern crate serde_derive;
 extern crate pretty_assertions;
 
 pub mod bios;
+pub mod clock;
 pub mod cmos;
 pub mod codepage;
 pub mod cpu;
 pub mod debug;
+pub mod dosbox_conf;
 pub mod format;
+pub mod governor;
 pub mod gpu;
+pub mod group;
 pub mod hex;
+#[cfg(feature = "jit")]
+pub mod jit;
 pub mod keyboard;
+pub mod logging;
 pub mod machine;
 pub mod memory;
 pub mod mouse;
+#[cfg(feature = "ndisasm")]
 pub mod ndisasm;
+pub mod net;
 pub mod pic;
 pub mod pit;
 pub mod dos;
 pub mod storage;
 pub mod string;
 pub mod tools;
+pub mod trace;
+pub mod trace_compare;
@@ -0,0 +1,108 @@
+// diagnostics of the emulated hardware, written with the `log` macros to one of the targets
+// "cpu", "gpu", "dos", "io" or "int", and filtered per target at runtime
+
+use std::sync::RwLock;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+#[cfg(test)]
+#[path = "./logging_test.rs"]
+mod logging_test;
+
+/// the log targets of the emulator
+pub const TARGETS: [&str; 5] = ["cpu", "gpu", "dos", "io", "int"];
+
+/// log level of each target, parsed from a list such as "dos=debug,io=warn".
+/// a bare level, such as "warn", sets the level of all targets
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogFilter {
+    pub default: LevelFilter,
+    pub targets: Vec<(String, LevelFilter)>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        LogFilter {
+            default: LevelFilter::Info,
+            targets: Vec::new(),
+        }
+    }
+}
+
+impl LogFilter {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut filter = LogFilter::default();
+        for part in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (target, level) = match part.find('=') {
+                Some(pos) => (Some(&part[..pos]), &part[pos + 1..]),
+                None => (None, part),
+            };
+            let level = level.parse().map_err(|_| format!("invalid log level {}", level))?;
+            match target {
+                Some(target) if TARGETS.contains(&target) => {
+                    filter.targets.retain(|(t, _)| t != target);
+                    filter.targets.push((target.to_owned(), level));
+                }
+                Some(target) => return Err(format!("unknown log target {}", target)),
+                None => filter.default = level,
+            }
+        }
+        Ok(filter)
+    }
+
+    /// returns the level of `target`
+    pub fn level(&self, target: &str) -> LevelFilter {
+        match self.targets.iter().find(|(t, _)| t == target) {
+            Some(&(_, level)) => level,
+            None => self.default,
+        }
+    }
+
+    /// returns the most verbose level of all targets
+    pub fn max_level(&self) -> LevelFilter {
+        self.targets.iter().map(|&(_, level)| level).fold(self.default, std::cmp::max)
+    }
+}
+
+struct Logger {
+    filter: RwLock<Option<LogFilter>>,
+}
+
+static LOGGER: Logger = Logger {
+    filter: RwLock::new(None),
+};
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        match *self.filter.read().unwrap() {
+            Some(ref filter) => metadata.level() <= filter.level(metadata.target()),
+            None => false,
+        }
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            println!("{}: {}", record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// sets the levels of the log targets. the filter is shared by all machines of the process,
+/// and has no effect if the host program has installed another logger
+pub fn set_filter(filter: &LogFilter) {
+    if log::set_logger(&LOGGER).is_err() && LOGGER.filter.read().unwrap().is_none() {
+        // another logger is installed
+        return;
+    }
+    log::set_max_level(filter.max_level());
+    *LOGGER.filter.write().unwrap() = Some(filter.clone());
+}
+
+/// sets the default levels, unless the levels were set before, such as by another machine
+pub fn set_default_filter() {
+    if LOGGER.filter.read().unwrap().is_none() {
+        set_filter(&LogFilter::default());
+    }
+}
@@ -0,0 +1,20 @@
+use log::LevelFilter;
+
+use crate::logging::LogFilter;
+
+#[test]
+fn can_parse_log_filter() {
+    let filter = LogFilter::parse("dos=debug, io=warn").unwrap();
+    assert_eq!(LevelFilter::Debug, filter.level("dos"));
+    assert_eq!(LevelFilter::Warn, filter.level("io"));
+    assert_eq!(LevelFilter::Info, filter.level("gpu"));
+    assert_eq!(LevelFilter::Debug, filter.max_level());
+
+    let filter = LogFilter::parse("off,int=TRACE").unwrap();
+    assert_eq!(LevelFilter::Off, filter.level("cpu"));
+    assert_eq!(LevelFilter::Trace, filter.level("int"));
+
+    assert_eq!(LogFilter::default(), LogFilter::parse("").unwrap());
+    assert!(LogFilter::parse("dos=loud").is_err());
+    assert!(LogFilter::parse("sound=debug").is_err());
+}
@@ -1,39 +1,55 @@
-use std::{mem, u8};
-use std::num::Wrapping;
-use std::fs::File;
-use std::path::Path;
+use std::u8;
+use std::any::Any;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::io::{BufWriter, Write};
 use std::io;
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+use image::RgbaImage;
+use log::{debug, error, info, trace, warn};
+use rand::{RngCore, SeedableRng};
+use rand_xorshift::XorShiftRng;
 
 use crate::bios::BIOS;
-use crate::cpu::{CPU, Op, Invalid, R, RegisterState};
-use crate::cpu::{Instruction, RepeatMode, Exception};
-use crate::cpu::{Parameter};
+use crate::codepage::{Codepage, CountryInfo};
+use crate::cpu::{CPU, CpuModel, Exception, Op, Invalid, R, RegisterState, FLAG_CF};
+use crate::cpu::{Instruction, RepeatMode, AddressSize, disasm_first_instr};
 use crate::format::ExeFile;
-use crate::gpu::GFXMode;
+use crate::gpu::{ColorEmulation, GFXMode, GraphicCard, SvgaChipset, VideoFrame, VideoRecorder};
 use crate::gpu::GPU as GPUComponent;
-use crate::dos::DOS;
+use crate::clock::Clock;
+use crate::governor::Governor;
+use crate::dos::{read_environment, write_environment, DeviceHeader, DosVersion, ExecRequest, Shell, ShellAction, DOS, ENV_SEG};
 use crate::hex::hex_bytes;
-use crate::keyboard::Keyboard as KeyboardComponent;
+#[cfg(feature = "jit")]
+use crate::jit::{self, Jit};
+use crate::keyboard::{Keyboard as KeyboardComponent, KeyboardLayout};
+use crate::logging::{self, LogFilter};
 use crate::memory::{MMU, MemoryAddress};
 use crate::mouse::Mouse as MouseComponent;
+use crate::net::{Ipx, IpxConfig, NetworkConfig, PacketDriver, Transport, IPX_IRQ};
+#[cfg(feature = "ndisasm")]
 use crate::ndisasm::ndisasm_first_instr;
+use crate::cmos::CMOS as CMOSComponent;
+use crate::debug::{Coverage, CoverageModule, IoBreakpoints, StackGuard, StackGuardAction, StackGuardConfig, SymbolTable};
 use crate::pic::PIC as PICComponent;
 use crate::pit::PIT as PITComponent;
+use crate::storage::Mount;
 use crate::storage::Storage as StorageComponent;
 use crate::tools::read_binary;
+use crate::trace::{BINARY_TRACE_MAGIC, TraceConfig, TraceFormat, TraceRecord};
 
 #[cfg(test)]
 #[path = "./machine_test.rs"]
 mod machine_test;
 
-const HANDLE_DEBUG_INTERRUPT: bool = false;
-
-/// prints each instruction as they are executed
-const DEBUG_EXEC: bool = false;
-
-/// prints access to I/O ports
-const DEBUG_IO: bool = false;
+#[path = "./execute.rs"]
+mod execute;
+use self::execute::OpHandler;
 
 /// DEBUG FEATURE: adds a 16-bit stack marker in order to end execution if it is found
 pub const DEBUG_MARK_STACK: bool = false;
@@ -41,16 +57,43 @@ pub const DEBUG_MARK_STACK: bool = false;
 /// value used to taint the stack, to notice on errors or small com apps just using "retn" to exit to DOS
 pub const STACK_MARKER: u16 = 0xDEAD;
 
-pub enum MachineComponent {
-    Storage(StorageComponent),
-    Keyboard(KeyboardComponent),
-    Mouse(MouseComponent),
-    PIC(PICComponent),
-    PIT(PITComponent),
-    GPU(GPUComponent),
+/// seed of the random number generator of deterministic machines
+pub const RNG_SEED: u64 = 0x0D05_B0C5;
+
+/// allows downcasting a registered component to its concrete type
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
 
-pub trait Component {
+pub trait Component: AsAny + Send {
+    /// i/o ports handled by the component, read on registration.
+    /// each port is dispatched to the first component registered for it
+    fn io_ports(&self) -> Vec<RangeInclusive<u16>> {
+        Vec::new()
+    }
+
+    /// interrupt vectors handled by the component, read on registration
+    fn interrupts(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// INT 2Fh multiplex ids (AH values) handled by the component, read on registration.
+    /// each id is dispatched to the first component registered for it, unclaimed ids report "not installed"
+    fn multiplex_ids(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
     /// returns Some<u8> if read was handled
     fn in_u8(&mut self, _port: u16) -> Option<u8> {
         None
@@ -61,12 +104,336 @@ pub trait Component {
         false
     }
 
+    /// returns Some<u16> if word read was handled. called when `port` and `port+1` belong to
+    /// the component, by default reads the low byte from `port` and the high byte from `port+1`
+    fn in_u16(&mut self, port: u16) -> Option<u16> {
+        let lo = self.in_u8(port)?;
+        let hi = self.in_u8(port.wrapping_add(1)).unwrap_or(0);
+        Some(u16::from(hi) << 8 | u16::from(lo))
+    }
+
+    /// returns true if word write was handled. called when `port` and `port+1` belong to
+    /// the component, by default writes the low byte to `port` and the high byte to `port+1`
+    fn out_u16(&mut self, port: u16, data: u16) -> bool {
+        let lo = self.out_u8(port, data as u8);
+        let hi = self.out_u8(port.wrapping_add(1), (data >> 8) as u8);
+        lo || hi
+    }
+
     /// returns true if interrupt was handled
     fn int(&mut self, _int: u8, _cpu: &mut CPU, _mmu: &mut MMU) -> bool {
         false
     }
 }
 
+/// sound hardware of the machine
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioDevice {
+    /// no sound, the pc speaker is silent
+    #[default]
+    None,
+
+    /// pc speaker, driven by counter 2 of the PIT
+    Speaker,
+
+    /// AdLib FM synthesizer at port 0388
+    AdLib,
+
+    /// Sound Blaster 16 at port 0220
+    SB16,
+}
+
+impl AudioDevice {
+    /// returns an error for devices that are not emulated, sound output is not implemented
+    pub fn validate(self) -> Result<(), String> {
+        match self {
+            AudioDevice::None => Ok(()),
+            _ => Err(format!("audio device {:?} is not supported, only none is implemented", self).to_lowercase()),
+        }
+    }
+}
+
+impl FromStr for AudioDevice {
+    type Err = String;
+
+    /// parses "none", "speaker", "adlib" or "sb16"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(AudioDevice::None),
+            "speaker" => Ok(AudioDevice::Speaker),
+            "adlib" => Ok(AudioDevice::AdLib),
+            "sb16" => Ok(AudioDevice::SB16),
+            _ => Err(format!("unknown audio device {}", s)),
+        }
+    }
+}
+
+/// what the machine does on an invalid opcode, or on an interrupt without a handler
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum UnknownPolicy {
+    /// stops execution
+    #[serde(rename = "stop")]
+    Stop,
+
+    /// logs a warning and continues with the next instruction
+    #[serde(rename = "skip")]
+    SkipAndLog,
+
+    /// raises the invalid opcode exception through INT 6, execution stops if the program has
+    /// not hooked it. interrupts without a handler are skipped
+    #[serde(rename = "ud")]
+    RaiseUD,
+}
+
+/// hardware configuration of a `Machine`, usually read from a dustbox.toml file
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MachineConfig {
+    /// emulated processor, decides the default clock speed
+    pub cpu: CpuModel,
+
+    /// cpu clock in Hz, overrides the speed of `cpu`
+    pub cpu_hz: Option<usize>,
+
+    /// decode opcodes exactly as `cpu` does, including the undocumented aliases of the 8086.
+    /// by default the opcodes of all models up to the 386 are decoded
+    pub strict_cpu: bool,
+
+    /// conventional memory in KB, at most 640
+    pub conventional_kb: u16,
+
+    /// extended memory above 1 MB in KB
+    pub extended_kb: u16,
+
+    /// emulated graphic card, only VGA is supported
+    pub graphic_card: GraphicCard,
+
+    /// SVGA chipset emulated on top of VGA: "none", "et4000" or "s3trio"
+    pub svga: SvgaChipset,
+
+    /// simulated monitor, brightness and contrast of the rendered frames
+    pub color: ColorEmulation,
+
+    /// sound hardware: "none", "speaker", "adlib" or "sb16". only "none" is supported
+    pub audio: AudioDevice,
+
+    /// keyboard layout, such as "us" or "de". only "us" is supported
+    pub keyboard_layout: KeyboardLayout,
+
+    /// host directories and disk images mounted as DOS drives
+    pub mounts: Vec<Mount>,
+
+    /// device drivers loaded at boot. "ANSI.SYS" is emulated, other names are .SYS files on the host
+    pub drivers: Vec<String>,
+
+    /// DOS code page: 437, 850 or 865
+    pub codepage: Codepage,
+
+    /// DOS country code, such as 1 (United States) or 49 (Germany)
+    pub country: u16,
+
+    /// DOS version reported to programs, such as "6.22"
+    pub dos_version: DosVersion,
+
+    /// versions reported to specific programs by file name, as set by SETVER.EXE
+    pub setver: HashMap<String, DosVersion>,
+
+    /// log level of each subsystem, such as "dos=debug,io=warn", see `LogFilter`
+    pub log: String,
+
+    /// on an invalid opcode: "stop", "skip" or "ud"
+    pub invalid_opcode: UnknownPolicy,
+
+    /// on an interrupt without a handler: "stop" or "skip"
+    pub unknown_interrupt: UnknownPolicy,
+
+    /// debug aid reporting stack underflows, overflows and collisions with the program
+    pub stack_guard: StackGuardConfig,
+
+    /// network card, used by programs through the packet driver
+    pub network: NetworkConfig,
+
+    /// IPX driver for multiplayer games, with packets carried over UDP
+    pub ipx: IpxConfig,
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        MachineConfig {
+            cpu: CpuModel::default(),
+            cpu_hz: None,
+            strict_cpu: false,
+            conventional_kb: 640,
+            extended_kb: 3072,
+            graphic_card: GraphicCard::VGA,
+            svga: SvgaChipset::None,
+            color: ColorEmulation::default(),
+            audio: AudioDevice::None,
+            keyboard_layout: KeyboardLayout::Us,
+            mounts: Vec::new(),
+            drivers: Vec::new(),
+            codepage: Codepage::default(),
+            country: 1,
+            dos_version: DosVersion::default(),
+            setver: HashMap::new(),
+            log: String::new(),
+            invalid_opcode: UnknownPolicy::Stop,
+            unknown_interrupt: UnknownPolicy::SkipAndLog,
+            stack_guard: StackGuardConfig::default(),
+            network: NetworkConfig::default(),
+            ipx: IpxConfig::default(),
+        }
+    }
+}
+
+impl MachineConfig {
+    /// reads a configuration in toml format
+    pub fn from_file(filename: &str) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(filename)?)
+    }
+
+    /// parses a configuration in toml format, such as:
+    ///
+    /// ```toml
+    /// cpu = "286"
+    /// extended_kb = 1024
+    /// svga = "et4000"
+    ///
+    /// drivers = ["ANSI.SYS"]
+    /// codepage = "850"
+    /// country = 49
+    /// dos_version = "6.22"
+    ///
+    /// audio = "none"
+    /// keyboard_layout = "us"
+    ///
+    /// [color]
+    /// monitor = "amber"       # color, composite, amber, green or white
+    /// brightness = 10         # -100 to 100
+    /// contrast = 120          # percent
+    ///
+    /// [setver]
+    /// "TC.EXE" = "3.30"
+    ///
+    /// [[mounts]]
+    /// drive = "C"
+    /// path = "/home/user/dos"
+    ///
+    /// [[mounts]]
+    /// drive = "D"
+    /// path = "/home/user/game.iso"
+    /// kind = "iso"            # directory, floppy or iso. guessed from the path if unset
+    /// ```
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let config: MachineConfig = toml::from_str(text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if config.graphic_card != GraphicCard::VGA {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("graphic card {:?} is not supported", config.graphic_card)));
+        }
+        config.color.validate().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        config.audio.validate().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        config.keyboard_layout.validate().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        LogFilter::parse(&config.log).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        config.network.validate().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        config.ipx.validate().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        for mount in &config.mounts {
+            mount.validate()?;
+        }
+        if CountryInfo::for_code(config.country).is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown country code {}", config.country)));
+        }
+        for driver in &config.drivers {
+            if !driver.eq_ignore_ascii_case("ANSI.SYS") && !Path::new(driver).is_file() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("driver {} not found", driver)));
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// number of instructions executed between checks of the host time in unlimited speed mode
+const UNLIMITED_BATCH: usize = 1000;
+
+/// marks a i/o port or multiplex id without a registered component in `Machine.port_map` and `Machine.multiplex_map`
+const NO_COMPONENT: u8 = 0xFF;
+
+/// ports handled by the machine when no component claims them, in `read_port_u8` and `out_u8`:
+/// DMA channel 1, system control port A (A20 gate), the joystick and the floppy controller DOR
+const BUILTIN_PORTS: [u16; 4] = [0x0002, 0x0092, 0x0201, 0x03F2];
+
+/// a component together with the interrupts it was registered for
+struct RegisteredComponent {
+    component: Box<dyn Component>,
+    interrupts: Vec<u8>,
+}
+
+/// indices in `Machine.components` of the components progressed on every step, or NO_COMPONENT
+struct TimedComponents {
+    gpu: u8,
+    pit: u8,
+    pic: u8,
+    cmos: u8,
+    packet_driver: u8,
+    ipx: u8,
+}
+
+impl Default for TimedComponents {
+    fn default() -> Self {
+        TimedComponents {
+            gpu: NO_COMPONENT,
+            pit: NO_COMPONENT,
+            pic: NO_COMPONENT,
+            cmos: NO_COMPONENT,
+            packet_driver: NO_COMPONENT,
+            ipx: NO_COMPONENT,
+        }
+    }
+}
+
+impl TimedComponents {
+    /// records `index` as the position of `component` if it is the first of its type
+    fn add(&mut self, component: &dyn Any, index: u8) {
+        let slot = if component.is::<GPUComponent>() {
+            &mut self.gpu
+        } else if component.is::<PITComponent>() {
+            &mut self.pit
+        } else if component.is::<PICComponent>() {
+            &mut self.pic
+        } else if component.is::<CMOSComponent>() {
+            &mut self.cmos
+        } else if component.is::<PacketDriver>() {
+            &mut self.packet_driver
+        } else if component.is::<Ipx>() {
+            &mut self.ipx
+        } else {
+            return;
+        };
+        if *slot == NO_COMPONENT {
+            *slot = index;
+        }
+    }
+}
+
+/// returns the component at `index` if it is of type `T`
+fn indexed_component<T: Component + 'static>(components: &[RegisteredComponent], index: u8) -> Option<&T> {
+    components.get(usize::from(index))?.component.as_ref().as_any().downcast_ref::<T>()
+}
+
+/// returns the component at `index` if it is of type `T`
+fn indexed_component_mut<T: Component + 'static>(components: &mut [RegisteredComponent], index: u8) -> Option<&mut T> {
+    components.get_mut(usize::from(index))?.component.as_mut().as_any_mut().downcast_mut::<T>()
+}
+
+/// returns the first component of type `T`
+fn find_component<T: Component + 'static>(components: &[RegisteredComponent]) -> Option<&T> {
+    components.iter().find_map(|c| c.component.as_ref().as_any().downcast_ref::<T>())
+}
+
+/// returns the first component of type `T`
+fn find_component_mut<T: Component + 'static>(components: &mut [RegisteredComponent]) -> Option<&mut T> {
+    components.iter_mut().find_map(|c| c.component.as_mut().as_any_mut().downcast_mut::<T>())
+}
+
 pub struct Machine {
     pub mmu: MMU,
     pub bios: BIOS,
@@ -79,56 +446,357 @@ pub struct Machine {
     /// length of loaded rom in bytes (used by disassembler)
     pub rom_length: usize,
 
+    /// names of addresses in the loaded rom, used by the trace log and debugging tools
+    pub symbols: SymbolTable,
+
+    /// debugger breakpoints on i/o ports and interrupts
+    pub io_breakpoints: IoBreakpoints,
+
+    /// stops execution on INT 3 reaching the default handler, set by the debugger
+    pub break_on_int3: bool,
+
+    /// watches the stack of the loaded program
+    pub stack_guard: StackGuard,
+
+    /// what to do on an invalid opcode
+    pub invalid_opcode: UnknownPolicy,
+
+    /// what to do on an interrupt without a handler
+    pub unknown_interrupt: UnknownPolicy,
+
     /// handlers for i/o ports and interrupts
-    components: Vec<MachineComponent>,
+    components: Vec<RegisteredComponent>,
+
+    /// dynamic recompiler for hot blocks
+    #[cfg(feature = "jit")]
+    pub jit: Jit,
+
+    /// address of the instruction following the last interpreted one, used to detect branches
+    #[cfg(feature = "jit")]
+    jit_fallthrough: Option<(u16, u16)>,
+
+    /// instruction handlers, indexed by `Op::index`
+    handlers: Vec<OpHandler>,
+
+    /// maps each i/o port to the index of the component handling it, or NO_COMPONENT
+    port_map: Vec<u8>,
+
+    /// where the components progressed on every step are found in `components`
+    timed: TimedComponents,
+
+    /// maps each INT 2Fh multiplex id to the index of the component handling it, or NO_COMPONENT
+    multiplex_map: Vec<u8>,
 
     /// if set, writes opcode trace to `trace_file`
     trace_file: Option<File>,
 
+    /// filters and format of the trace written to `trace_file`
+    trace_config: TraceConfig,
+
     /// if set, limits the execution to `trace_count` instructions
     trace_count: Option<usize>,
+
+    /// if set, records the executed instructions
+    coverage: Option<Coverage>,
+
+    /// if set, rendered frames are captured to a video file
+    video_recorder: Option<VideoRecorder>,
+
+    /// gpu frame count when video capture was started
+    video_start_frame: u64,
+
+    /// paces `execute_for` against host time
+    pub governor: Governor,
+
+    /// virtual time of day, progressed by the executed cycles
+    pub clock: Clock,
+
+    /// source of the random values handed to programs, such as temporary file names.
+    /// seeded with `RNG_SEED` for deterministic machines
+    rng: XorShiftRng,
+
+    /// called at the start of each vertical retrace
+    vblank_callback: Option<VblankCallback>,
+
+    /// IRQ 0 requests of timer 0 not yet passed on to the PIC
+    timer_irqs: u32,
+
+    /// the built-in shell, if started by `start_shell`
+    shell: Option<Shell>,
+
+    /// the programs waiting for the program they started with EXEC, the last one started the running program
+    parents: Vec<ParentProcess>,
+}
+
+/// a program suspended by EXEC, restored when the program it started terminates
+struct ParentProcess {
+    regs: RegisterState,
+
+    /// conventional memory from the environment segment up, as it was before EXEC
+    memory: Vec<u8>,
+
+    psp_segment: u16,
+    program_path: String,
+    rom_base: MemoryAddress,
+    rom_length: usize,
+    shell: Option<Shell>,
 }
 
+/// function called at the start of each vertical retrace
+type VblankCallback = Box<dyn FnMut(&Machine) + Send>;
+
 impl Machine {
      // returns a non-deterministic Machine instance
     pub fn default() -> Self {
-        let mut m = Self::deterministic();
-        m.pit_mut().init();
+        Self::from_config(MachineConfig::default())
+    }
+
+    /// returns a non-deterministic Machine instance with the hardware described by `config`
+    pub fn from_config(config: MachineConfig) -> Self {
+        let mut m = Self::with_config(config);
+        m.set_clock(Clock::local());
+        m.rng = XorShiftRng::from_entropy();
+        m.cpu.regs.flags.eager = false;
+        #[cfg(feature = "jit")]
+        {
+            m.jit.validate = false;
+        }
         m
     }
 
     pub fn deterministic() -> Self {
-        let mut mmu = MMU::default();
+        Self::with_config(MachineConfig::default())
+    }
+
+    /// sets the log level of each subsystem from a list such as "dos=debug,io=warn"
+    pub fn set_log_filter(&mut self, spec: &str) -> Result<(), String> {
+        logging::set_filter(&LogFilter::parse(spec)?);
+        Ok(())
+    }
+
+    /// returns a deterministic Machine instance with the hardware described by `config`
+    pub fn with_config(config: MachineConfig) -> Self {
+        // the log filter is shared by the machines of the process, and kept unless configured
+        if config.log.is_empty() {
+            logging::set_default_filter();
+        } else {
+            logging::set_filter(&LogFilter::parse(&config.log).unwrap_or_default());
+        }
+        let conventional_kb = config.conventional_kb.min(640);
+        let mut mmu = MMU::with_memory_size(0x10_0000 + usize::from(config.extended_kb) * 1024);
         let mut bios = BIOS::default();
         bios.init(&mut mmu);
+        mmu.write_u16(BIOS::DATA_SEG, BIOS::DATA_MEMORY_SIZE, conventional_kb);
+
+        let mut cpu = CPU::deterministic();
+        cpu.clock_hz = config.cpu_hz.unwrap_or_else(|| config.cpu.clock_hz());
+        cpu.model = config.cpu;
+        if config.strict_cpu {
+            cpu.decoder.set_model(Some(config.cpu));
+        }
+        let mut dos = DOS::default();
+        for mount in &config.mounts {
+            if let Err(e) = dos.drives.mount(mount) {
+                warn!(target: "dos", "error mounting drive {}: {}", mount.drive, e);
+            }
+        }
+        dos.init_drivers(&mut mmu);
+        dos.set_codepage(&mut mmu, config.codepage);
+        dos.country = config.country;
+        dos.version = config.dos_version;
+        dos.setver = config.setver;
+        for driver in &config.drivers {
+            if let Err(e) = dos.load_driver(&mut mmu, driver) {
+                warn!(target: "dos", "error loading driver {}: {}", driver, e);
+            }
+        }
 
         let mut m = Machine {
-            cpu: CPU::deterministic(),
+            cpu,
             mmu,
             bios,
-            dos: DOS::default(),
+            dos,
             rom_base: MemoryAddress::default_real(),
             rom_length: 0,
+            symbols: SymbolTable::default(),
+            io_breakpoints: IoBreakpoints::default(),
+            break_on_int3: false,
+            stack_guard: StackGuard::new(config.stack_guard),
+            invalid_opcode: config.invalid_opcode,
+            unknown_interrupt: config.unknown_interrupt,
             trace_file: None,
+            trace_config: TraceConfig::default(),
             trace_count: None,
+            coverage: None,
+            video_recorder: None,
+            video_start_frame: 0,
+            governor: Governor::default(),
+            clock: Clock::default(),
+            rng: XorShiftRng::seed_from_u64(RNG_SEED),
+            vblank_callback: None,
+            timer_irqs: 0,
+            shell: None,
+            parents: Vec::new(),
             components: Vec::new(),
+            port_map: vec![NO_COMPONENT; 0x1_0000],
+            timed: TimedComponents::default(),
+            multiplex_map: vec![NO_COMPONENT; 0x100],
+            handlers: execute::dispatch_table(),
+            #[cfg(feature = "jit")]
+            jit: Jit::default(),
+            #[cfg(feature = "jit")]
+            jit_fallthrough: None,
         };
 
-        m.register_components();
+        m.register_components(config.graphic_card, config.svga);
+        match config.network.open_transport() {
+            Ok(Some(transport)) => m.install_packet_driver(&config.network, transport),
+            Ok(None) => {}
+            Err(e) => warn!(target: "io", "error opening network: {}", e),
+        }
+        match config.ipx.open_transport() {
+            Ok(Some(transport)) => m.install_ipx(&config.ipx, transport),
+            Ok(None) => {}
+            Err(e) => warn!(target: "io", "error opening ipx network: {}", e),
+        }
+        m.gpu_mut().color = config.color;
+        if let Err(e) = config.audio.validate() {
+            warn!(target: "io", "{}", e);
+        }
+        if let Err(e) = config.keyboard_layout.validate() {
+            warn!(target: "io", "{}", e);
+        }
+        m.cmos_mut().set_memory_size(conventional_kb, config.extended_kb);
+        m.set_clock(Clock::default());
+        #[cfg(feature = "jit")]
+        {
+            // compare compiled blocks to the interpreter
+            m.jit.validate = true;
+        }
         m
     }
 
+    /// replaces the machine clock, and sets the BIOS tick count and the real-time clock to its time
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.bios.init_timer_ticks(&mut self.mmu, clock.now().time());
+        self.cmos_mut().reset_time(&clock);
+        self.clock = clock;
+    }
+
     /// Enables writing of opcode trace to file.
     /// The format tries to be similar to dosbox debugger "LOGS" format.
     pub fn write_trace_to(&mut self, filename: &str) {
+        self.write_trace_with(filename, TraceConfig::default());
+    }
+
+    /// Enables writing of opcode trace to file, filtered and formatted as described by `config`
+    pub fn write_trace_with(&mut self, filename: &str, config: TraceConfig) {
         let trace_path = Path::new(filename);
 
-        let file = match File::create(&trace_path) {
+        let mut file = match File::create(&trace_path) {
             Err(why) => panic!("couldn't create {:?}: {}", trace_path.display(), why),
             Ok(file) => file,
         };
+        if config.format == TraceFormat::Binary {
+            if let Err(why) = file.write_all(BINARY_TRACE_MAGIC) {
+                panic!("couldn't write {:?}: {}", trace_path.display(), why);
+            }
+        }
 
         self.trace_file = Some(file);
+        self.trace_config = config;
+    }
+
+    /// writes the state before executing `op` at `cs:ip` to the trace file
+    fn write_trace(&mut self, cs: u16, ip: u16, op: &Instruction) {
+        if !self.trace_config.should_trace(MemoryAddress::RealSegmentOffset(cs, ip).value(), op) {
+            return;
+        }
+        let bytes = self.mmu.read(cs, ip, op.length as usize);
+        let record = TraceRecord::from_cpu(&self.cpu, &bytes);
+        if let Some(file) = &self.trace_file {
+            let mut writer = BufWriter::new(file);
+            let _ = match self.trace_config.format {
+                TraceFormat::Text => {
+                    let disasm = self.symbols.instruction_text(op, cs);
+                    writeln!(&mut writer, "{}", record.text_line(&disasm, &self.trace_config.registers))
+                }
+                TraceFormat::Binary => record.write_to(&mut writer),
+            };
+        }
+    }
+
+    /// Starts recording the executed instructions, for code coverage reports
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::default());
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Writes the recorded coverage to `filename`. The drcov format is used if the name ends with .drcov,
+    /// otherwise it is written as one byte per byte of the loaded program, set to 1 if executed
+    pub fn save_coverage(&self, filename: &str) -> io::Result<()> {
+        let coverage = match &self.coverage {
+            Some(coverage) => coverage,
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "coverage not enabled")),
+        };
+        let rom_end = self.rom_base.value() + self.rom_length as u32;
+        let mut file = BufWriter::new(File::create(filename)?);
+        if filename.to_lowercase().ends_with(".drcov") {
+            let name = match Path::new(&self.dos.program_path).file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => "program".to_owned(),
+            };
+            // offsets in the program module are relative to the load segment, like in the disassembly
+            let modules = [
+                CoverageModule{name, base: u32::from(self.rom_base.segment()) << 4, end: rom_end},
+                CoverageModule{name: "memory".to_owned(), base: 0, end: self.mmu.memory.data.len() as u32},
+            ];
+            coverage.write_drcov(&mut file, &modules)
+        } else {
+            file.write_all(&coverage.byte_map(self.rom_base.value()..rom_end))
+        }
+    }
+
+    /// Starts capturing rendered frames, to be written by `stop_video_capture` to `path` as an
+    /// animated PNG if it ends in .png, otherwise as an animated GIF
+    pub fn start_video_capture<P: AsRef<Path>>(&mut self, path: P) {
+        let mut recorder = VideoRecorder::new(path);
+        self.video_start_frame = self.gpu().frame_count;
+        recorder.add_frame(0, self.screenshot());
+        self.video_recorder = Some(recorder);
+    }
+
+    /// Stops video capture and writes the recorded frames to disk
+    pub fn stop_video_capture(&mut self) -> io::Result<()> {
+        let frame_ms = self.video_frame_ms();
+        match self.video_recorder.take() {
+            Some(recorder) => recorder.save(frame_ms),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "video capture not started")),
+        }
+    }
+
+    /// duration of one video frame in milliseconds
+    fn video_frame_ms(&self) -> u64 {
+        1000 / self.gpu().refresh_rate.max(1) as u64
+    }
+
+    /// adds current frame to the video capture
+    fn capture_video_frame(&mut self) {
+        let frames = self.gpu().frame_count - self.video_start_frame;
+        let timestamp = frames * 1000 / self.gpu().refresh_rate.max(1) as u64;
+        let img = self.screenshot();
+        if let Some(recorder) = &mut self.video_recorder {
+            recorder.add_frame(timestamp, img);
+        }
+    }
+
+    /// Loads symbols for the loaded program from a .map or .toml file, returning the number of symbols
+    pub fn load_symbols(&mut self, filename: &str) -> io::Result<usize> {
+        self.symbols = SymbolTable::from_file(filename, self.rom_base.segment())?;
+        Ok(self.symbols.len())
     }
 
     /// Limits the instruction trace to `count` instructions
@@ -136,68 +804,199 @@ impl Machine {
         self.trace_count = Some(count);
     }
 
-    fn register_components(&mut self) {
-        self.components.push(MachineComponent::PIC(PICComponent::new(0x0020)));
-        self.components.push(MachineComponent::PIC(PICComponent::new(0x00A0)));
-        self.components.push(MachineComponent::PIT(PITComponent::default()));
-        self.components.push(MachineComponent::Keyboard(KeyboardComponent::default()));
-        self.components.push(MachineComponent::Mouse(MouseComponent::default()));
-        self.components.push(MachineComponent::Storage(StorageComponent::default()));
+    fn register_components(&mut self, card: GraphicCard, svga: SvgaChipset) {
+        self.register_component(Box::new(PICComponent::default()));
+        self.register_component(Box::new(PITComponent::default()));
+        self.register_component(Box::new(CMOSComponent::default()));
+        self.register_component(Box::new(KeyboardComponent::default()));
+        self.register_component(Box::new(MouseComponent::default()));
+        self.register_component(Box::new(StorageComponent::default()));
 
-        let mut gpu = GPUComponent::default();
+        let mut gpu = GPUComponent::with_card(card);
+        gpu.set_svga(svga);
         gpu.init(&mut self.mmu);
         gpu.set_mode(&mut self.mmu, GFXMode::MODE_TEXT_80_25 as u8);
-        self.components.push(MachineComponent::GPU(gpu));
+        self.register_component(Box::new(gpu));
     }
 
-    /// returns a mutable reference to the PIT component
-    pub fn pit_mut(&mut self) -> &mut PITComponent {
-        for component in &mut self.components {
-            if let MachineComponent::PIT(c) = component {
-                return c;
+    /// registers a component to handle the i/o ports, interrupts and multiplex ids it reports.
+    /// components registered first take precedence
+    pub fn register_component(&mut self, component: Box<dyn Component>) {
+        if self.components.len() >= NO_COMPONENT as usize {
+            panic!("too many components registered");
+        }
+        let ports = component.io_ports();
+        let interrupts = component.interrupts();
+        self.add_component(component, &ports, interrupts);
+    }
+
+    /// registers a device of the embedding application to handle the i/o `ports` and `interrupts`,
+    /// which replace those reported by the component. fails if a port, interrupt or multiplex id
+    /// is already handled by another component, or by the built-in BIOS, DOS and fallback ports
+    pub fn register_external_component(&mut self, component: Box<dyn Component>, ports: &[RangeInclusive<u16>], interrupts: &[u8]) -> Result<(), String> {
+        if self.components.len() >= NO_COMPONENT as usize {
+            return Err("too many components registered".to_owned());
+        }
+        for port in ports.iter().cloned().flatten() {
+            if self.port_map[usize::from(port)] != NO_COMPONENT || BUILTIN_PORTS.contains(&port) {
+                return Err(format!("port {:04X} is already handled", port));
+            }
+        }
+        for int in interrupts {
+            if BIOS::INTERRUPTS.contains(int) || DOS::INTERRUPTS.contains(int) || self.components.iter().any(|c| c.interrupts.contains(int)) {
+                return Err(format!("interrupt {:02X} is already handled", int));
             }
         }
-        unreachable!();
+        for id in component.multiplex_ids() {
+            if self.multiplex_map[usize::from(id)] != NO_COMPONENT {
+                return Err(format!("multiplex id {:02X} is already handled", id));
+            }
+        }
+        self.add_component(component, ports, interrupts.to_vec());
+        Ok(())
     }
 
-    /// returns a mutable reference to the Keyboard component
-    pub fn keyboard_mut(&mut self) -> &mut KeyboardComponent {
-        for component in &mut self.components {
-            if let MachineComponent::Keyboard(c) = component {
-                return c;
+    fn add_component(&mut self, component: Box<dyn Component>, ports: &[RangeInclusive<u16>], interrupts: Vec<u8>) {
+        let index = self.components.len();
+        for port in ports.iter().cloned().flatten() {
+            let entry = &mut self.port_map[usize::from(port)];
+            if *entry == NO_COMPONENT {
+                *entry = index as u8;
+            } else {
+                debug!(target: "io", "register_component: port {:04X} already handled by component {}", port, entry);
             }
         }
-        unreachable!();
+        for id in component.multiplex_ids() {
+            let entry = &mut self.multiplex_map[usize::from(id)];
+            if *entry == NO_COMPONENT {
+                *entry = index as u8;
+            } else {
+                debug!(target: "io", "register_component: multiplex id {:02X} already handled by component {}", id, entry);
+            }
+        }
+        self.timed.add(component.as_ref().as_any(), index as u8);
+        self.components.push(RegisteredComponent {
+            component,
+            interrupts,
+        });
+    }
+
+    /// installs the packet driver of a network card exchanging frames through `transport`
+    pub fn install_packet_driver(&mut self, config: &NetworkConfig, transport: Box<dyn Transport>) {
+        let driver = PacketDriver::new(config, transport);
+        driver.install(&mut self.mmu);
+        // the driver unmasks the IRQ of the card when loaded
+        let irq = driver.irq();
+        self.pic_mut().master.imr &= !(1 << irq);
+        self.register_component(Box::new(driver));
+    }
+
+    /// installs the IPX driver, exchanging packets through `transport`
+    pub fn install_ipx(&mut self, config: &IpxConfig, transport: Box<dyn Transport>) {
+        let ipx = Ipx::new(config, transport);
+        ipx.install(&mut self.mmu);
+        self.pic_mut().slave.imr &= !(1 << (IPX_IRQ - 8));
+        self.register_component(Box::new(ipx));
+    }
+
+    /// returns a reference to the first registered component of type `T`
+    pub fn component<T: Component + 'static>(&self) -> Option<&T> {
+        find_component(&self.components)
+    }
+
+    /// returns a mutable reference to the first registered component of type `T`
+    pub fn component_mut<T: Component + 'static>(&mut self) -> Option<&mut T> {
+        find_component_mut(&mut self.components)
+    }
+
+    /// returns a mutable reference to the PIT component
+    pub fn pit_mut(&mut self) -> &mut PITComponent {
+        indexed_component_mut(&mut self.components, self.timed.pit).unwrap()
+    }
+
+    /// returns a mutable reference to the PIC component
+    pub fn pic_mut(&mut self) -> &mut PICComponent {
+        indexed_component_mut(&mut self.components, self.timed.pic).unwrap()
+    }
+
+    /// returns a mutable reference to the CMOS component
+    pub fn cmos_mut(&mut self) -> &mut CMOSComponent {
+        self.component_mut().unwrap()
+    }
+
+    /// returns a mutable reference to the Keyboard component
+    pub fn keyboard_mut(&mut self) -> &mut KeyboardComponent {
+        self.component_mut().unwrap()
     }
 
     /// returns a mutable reference to the Mouse component
     pub fn mouse_mut(&mut self) -> &mut MouseComponent {
-        for component in &mut self.components {
-            if let MachineComponent::Mouse(c) = component {
-                return c;
-            }
-        }
-        unreachable!();
+        self.component_mut().unwrap()
     }
 
     /// returns a mutable reference to the GPU component
     pub fn gpu_mut(&mut self) -> &mut GPUComponent {
-        for component in &mut self.components {
-            if let MachineComponent::GPU(c) = component {
-                return c;
-            }
-        }
-        unreachable!();
+        indexed_component_mut(&mut self.components, self.timed.gpu).unwrap()
     }
 
     /// returns a reference to the GPU component
     pub fn gpu(&self) -> &GPUComponent {
-        for component in &self.components {
-            if let MachineComponent::GPU(c) = component {
-                return c;
-            }
+        indexed_component(&self.components, self.timed.gpu).unwrap()
+    }
+
+    /// renders the current video frame into a RGBA image
+    pub fn screenshot(&self) -> RgbaImage {
+        self.gpu().screenshot(&self.mmu)
+    }
+
+    /// returns a hash of the current video frame
+    pub fn frame_hash(&self) -> u64 {
+        self.gpu().frame_hash(&self.mmu)
+    }
+
+    /// returns `len` bytes of memory from `seg:off`, less at the end of memory. the video memory
+    /// of the planar and SVGA modes is not seen here, see `dump_video_memory`
+    pub fn dump_memory(&self, seg: u16, off: u16, len: usize) -> Vec<u8> {
+        let data = &self.mmu.memory.data;
+        let start = (MemoryAddress::RealSegmentOffset(seg, off).value() as usize).min(data.len());
+        let end = (start + len).min(data.len());
+        data[start..end].to_vec()
+    }
+
+    /// returns the video memory of the current mode: the 4 planes after each other in the planar
+    /// modes, the linear memory in the SVGA modes, otherwise A000:0000 to BFFF:000F
+    pub fn dump_video_memory(&self) -> Vec<u8> {
+        let planar = self.gpu().planar.lock().unwrap();
+        if planar.banked && !planar.linear.is_empty() {
+            return planar.linear.clone();
+        }
+        if planar.sequencer.is_planar() {
+            return planar.planes.concat();
         }
-        unreachable!();
+        self.dump_memory(0xA000, 0, 0x2_0000)
+    }
+
+    /// renders the scanlines of `frame` that changed since the last call, returns the changed scanlines
+    pub fn update_frame(&mut self, frame: &mut VideoFrame) -> Vec<u32> {
+        let dirty = self.mmu.take_dirty_ranges();
+        let gpu = indexed_component_mut::<GPUComponent>(&mut self.components, self.timed.gpu).unwrap();
+        gpu.update_frame(&self.mmu, frame, &dirty)
+    }
+
+    /// renders the scanlines that changed since the last call into the back buffer of the GPU and
+    /// swaps buffers, see `GPU::front_frame`. returns the changed scanlines
+    pub fn present_frame(&mut self) -> Vec<u32> {
+        let dirty = self.mmu.take_dirty_ranges();
+        let gpu = indexed_component_mut::<GPUComponent>(&mut self.components, self.timed.gpu).unwrap();
+        gpu.present_frame(&self.mmu, &dirty)
+    }
+
+    /// returns the scanlines that changed since the last call, to be rendered with
+    /// `GPU::render_scanlines_into`
+    pub fn changed_scanlines(&mut self) -> Vec<u32> {
+        let dirty = self.mmu.take_dirty_ranges();
+        let gpu = indexed_component_mut::<GPUComponent>(&mut self.components, self.timed.gpu).unwrap();
+        gpu.changed_scanlines(&dirty)
     }
 
     /// reset the CPU and memory
@@ -205,8 +1004,19 @@ impl Machine {
         self.cpu = CPU::default();
     }
 
-    /// Loads a program file
+    /// Loads a program file. a .BAT file is run by the built-in shell, which exits at its end
     pub fn load_executable_file(&mut self, filename: &str) -> Option<io::Error> {
+        if filename.to_ascii_lowercase().ends_with(".bat") {
+            let name = Path::new(filename).file_name().map_or(String::new(), |n| n.to_string_lossy().to_ascii_uppercase());
+            let mut shell = Shell::default();
+            if let Err(e) = shell.run_batch(Path::new(filename), &name, "") {
+                return Some(e);
+            }
+            self.shell = Some(shell);
+            self.load_executable(&Shell::PROGRAM, 0x0329);
+            self.dos.program_path = String::from(filename);
+            return None;
+        }
 
         match read_binary(filename) {
             Ok(data) => self.load_executable(&data, 0x0329),
@@ -218,26 +1028,183 @@ impl Machine {
         None
     }
 
-    /// loads a program file (.EXE or .COM) from data
-    pub fn load_executable(&mut self, data: &[u8], psp_segment: u16) {
-        self.init_psp(psp_segment);
-        if data[0] == b'M' && data[1] == b'Z' {
-            self.load_exe(data, psp_segment + 0x10);
-        } else {
-            self.load_com(data, psp_segment);
+    /// loads DOS program `name`, such as "C:\GAMES\DOOM.EXE" or "DOOM" in the current directory.
+    /// a name without extension is looked for as .COM, .EXE, then .BAT
+    pub fn load_dos_program(&mut self, name: &str) -> Option<io::Error> {
+        let has_extension = name.rsplit('\\').next().unwrap_or(name).contains('.');
+        let extensions: &[&str] = if has_extension { &[""] } else { &[".COM", ".EXE", ".BAT"] };
+        for extension in extensions {
+            let path = self.dos.host_path(&format!("{}{}", name, extension));
+            if path.is_file() {
+                return self.load_executable_file(&path.to_string_lossy());
+            }
         }
+        Some(io::Error::new(io::ErrorKind::NotFound, format!("program {} not found", name)))
     }
 
-    /// Writes the Program Segment Prefix (PSP) into given segment
-    ///
-    /// https://en.wikipedia.org/wiki/Program_Segment_Prefix
-    /// http://www.delorie.com/djgpp/doc/rbinter/it/78/13.html
-    fn init_psp(&mut self, segment: u16) {
-        let psp = vec![
-            0xCD, 0x20,             // int 0x20
-            0xFF, 0x9F,             // Segment of the first byte beyond the memory allocated to the program
-            0x00,                   // Reserved
-            0x9A,                   // CP/M CALL 5 service request (FAR CALL to absolute 000C0h)
+    /// sets the current DOS directory, such as "C:\GAMES"
+    pub fn set_current_dir(&mut self, dir: &str) {
+        self.dos.current_dir = dir.to_owned();
+    }
+
+    /// starts the built-in shell, which reads commands from the keyboard and runs programs until EXIT.
+    /// the host working directory is mounted as C: unless drive C: is mounted
+    pub fn start_shell(&mut self) {
+        if self.dos.drives.get('C').is_none() {
+            if let Err(e) = self.dos.drives.mount(&Mount::new('C', PathBuf::from("."))) {
+                warn!(target: "dos", "error mounting drive C: {}", e);
+            }
+        }
+        if self.dos.current_dir.is_empty() {
+            self.dos.current_dir = "C:\\".to_owned();
+        }
+        self.shell = Some(Shell::default());
+        self.load_executable(&Shell::PROGRAM, 0x0329);
+    }
+
+    /// runs a step of the built-in shell, and starts the program it asks for
+    fn shell_step(&mut self) {
+        let mut shell = match self.shell.take() {
+            Some(shell) => shell,
+            None => return,
+        };
+        let input = if shell.reads_input() { self.shell_input() } else { None };
+        match shell.step(&mut self.dos, &mut self.mmu, input) {
+            ShellAction::None => {}
+            ShellAction::Run { path, name, tail } => {
+                shell.write_environment(&mut self.mmu, &name);
+                match self.load_executable_file(&path.to_string_lossy()) {
+                    Some(e) => warn!(target: "dos", "error loading {}: {}", name, e),
+                    None => Shell::write_command_tail(&mut self.mmu, self.dos.psp_segment, &tail),
+                }
+            }
+            ShellAction::Exit(code) => {
+                self.dos.exit_code = Some(code);
+                self.cpu.fatal_error = true; // stops execution
+                return;
+            }
+        }
+        self.shell = Some(shell);
+    }
+
+    /// returns the next character of the redirected standard input, or of the keyboard
+    fn shell_input(&mut self) -> Option<u8> {
+        if let Some(b) = self.dos.read_stdin() {
+            return Some(b);
+        }
+        match self.keyboard_mut().consume_dos_standard_scancode_and_ascii() {
+            (_, 0) => None,
+            (_, ascii) => Some(ascii),
+        }
+    }
+
+    /// reloads the shell when the program started by it terminates, keeping its return code
+    fn return_to_shell(&mut self) {
+        if let Some(shell) = &mut self.shell {
+            shell.errorlevel = self.dos.exit_code.take().unwrap_or(0);
+            self.cpu.fatal_error = false;
+            self.load_executable(&Shell::PROGRAM, 0x0329);
+        }
+    }
+
+    /// starts the program asked for by EXEC, keeping the running program to return to when it terminates.
+    /// COMMAND.COM and batch files are run by the built-in shell
+    fn exec(&mut self, request: ExecRequest) {
+        let file_name = request.name.rsplit('\\').next().unwrap_or(&request.name).to_owned();
+        let command = file_name == "COMMAND.COM";
+        let data = if command || file_name.ends_with(".BAT") {
+            Vec::new()
+        } else {
+            match read_binary(&request.path.to_string_lossy()) {
+                Ok(data) => data,
+                Err(e) => {
+                    warn!(target: "dos", "error loading {}: {}", request.name, e);
+                    self.cpu.set_r16(R::AX, 0x0005); // access denied
+                    self.mmu.set_flag(FLAG_CF, true);
+                    return;
+                }
+            }
+        };
+        let env_segment = if request.env_segment == 0 { ENV_SEG } else { request.env_segment };
+        let vars = read_environment(&self.mmu, env_segment);
+        let start = u32::from(ENV_SEG) << 4;
+        let end = u32::from(self.mmu.read_u16(BIOS::DATA_SEG, BIOS::DATA_MEMORY_SIZE)) * 1024;
+        let parent_psp = self.dos.psp_segment;
+        self.parents.push(ParentProcess {
+            regs: self.cpu.regs.clone(),
+            memory: self.mmu.memory.read(start, (end - start) as usize).to_vec(),
+            psp_segment: parent_psp,
+            program_path: self.dos.program_path.clone(),
+            rom_base: self.rom_base,
+            rom_length: self.rom_length,
+            shell: self.shell.take(),
+        });
+
+        if data.is_empty() {
+            let mut shell = Shell::with_environment(&vars);
+            let tail = request.tail.trim_start();
+            if !command {
+                if let Err(e) = shell.run_batch(&request.path, &file_name, tail) {
+                    warn!(target: "dos", "error reading {}: {}", request.name, e);
+                    shell.run_command("EXIT");
+                }
+            } else if tail.len() >= 2 && tail[..2].eq_ignore_ascii_case("/C") {
+                shell.run_command(&tail[2..]);
+            }
+            self.shell = Some(shell);
+            self.load_executable(&Shell::PROGRAM, 0x0329);
+        } else {
+            write_environment(&mut self.mmu, &vars, &request.name);
+            self.load_executable(&data, 0x0329);
+            Shell::write_command_tail(&mut self.mmu, self.dos.psp_segment, &request.tail);
+        }
+        self.dos.program_path = request.path.to_string_lossy().into_owned();
+        self.mmu.write_u16(self.dos.psp_segment, 0x16, parent_psp);
+    }
+
+    /// resumes the program that started the terminated one with EXEC
+    fn return_to_parent(&mut self) {
+        let parent = match self.parents.pop() {
+            Some(parent) => parent,
+            None => return,
+        };
+        self.dos.child_exit_code = self.dos.exit_code.take().unwrap_or(0);
+        self.cpu.fatal_error = false;
+        self.mmu.memory.write(u32::from(ENV_SEG) << 4, &parent.memory);
+        self.cpu.regs = parent.regs;
+        self.dos.psp_segment = parent.psp_segment;
+        self.dos.program_path = parent.program_path;
+        self.rom_base = parent.rom_base;
+        self.rom_length = parent.rom_length;
+        self.shell = parent.shell;
+    }
+
+    /// loads a program file (.EXE or .COM) from data
+    pub fn load_executable(&mut self, data: &[u8], psp_segment: u16) {
+        self.init_psp(psp_segment);
+        let top = if data[0] == b'M' && data[1] == b'Z' {
+            self.load_exe(data, psp_segment + 0x10);
+            self.cpu.get_r16(R::SP)
+        } else {
+            self.load_com(data, psp_segment);
+            // the word at the initial SP of a .com program is the return address into the PSP
+            self.cpu.get_r16(R::SP).wrapping_add(2)
+        };
+        let ss = self.cpu.get_r16(R::SS);
+        let end = self.rom_base.value() + self.rom_length as u32;
+        self.stack_guard.arm(ss, top, u32::from(psp_segment) << 4..end);
+    }
+
+    /// Writes the Program Segment Prefix (PSP) into given segment
+    ///
+    /// https://en.wikipedia.org/wiki/Program_Segment_Prefix
+    /// http://www.delorie.com/djgpp/doc/rbinter/it/78/13.html
+    fn init_psp(&mut self, segment: u16) {
+        let psp = vec![
+            0xCD, 0x20,             // int 0x20
+            0xFF, 0x9F,             // Segment of the first byte beyond the memory allocated to the program
+            0x00,                   // Reserved
+            0x9A,                   // CP/M CALL 5 service request (FAR CALL to absolute 000C0h)
             0xF0, 0xFE,             // CP/M compatibility--size of first segment for .COM files
             0x1D, 0xF0,             // remainder of FAR JMP at 05h
             0x34, 0xF5, 0x00, 0xF0, // stored INT 22 termination address
@@ -308,6 +1275,13 @@ impl Machine {
 
         self.mmu.write(segment, 0, &exe.program_data);
 
+        // segment references in the program are relative to the load segment
+        for reloc in &exe.relocs {
+            let reloc_segment = segment.wrapping_add(reloc.segment);
+            let val = self.mmu.read_u16(reloc_segment, reloc.offset);
+            self.mmu.write_u16(reloc_segment, reloc.offset, val.wrapping_add(segment));
+        }
+
         let some_segment = 0x0329;
         self.cpu.set_r16(R::DS, self.dos.psp_segment); // ds points to PSP
         self.cpu.set_r16(R::ES, some_segment);
@@ -318,8 +1292,8 @@ impl Machine {
         self.cpu.set_r16(R::DI, 0xFFFE);
         self.cpu.regs.flags.interrupt = true;
 
-        self.rom_base = self.cpu.get_memory_address();
-        self.rom_length = data.len();
+        self.rom_base = MemoryAddress::RealSegmentOffset(segment, 0);
+        self.rom_length = exe.program_data.len();
 
         self.mark_stack();
     }
@@ -361,27 +1335,119 @@ impl Machine {
         }
     }
 
+    /// feeds the standard input of the program from `data` instead of the keyboard, for the
+    /// DOS character input functions and reads from handle 0
+    pub fn set_stdin(&mut self, data: Vec<u8>) {
+        self.dos.set_stdin(data);
+    }
+
+    /// returns the text written to standard output by the program
+    pub fn console_output(&self) -> String {
+        self.dos.codepage.to_utf8(&self.dos.stdout)
+    }
+
+    /// returns the return code of the program, if it has terminated
+    pub fn exit_code(&self) -> Option<u8> {
+        self.dos.exit_code
+    }
+
+    /// returns the segment, offset and header of each device driver in the DOS driver chain
+    pub fn device_drivers(&self) -> Vec<(u16, u16, DeviceHeader)> {
+        self.dos.drivers(&self.mmu)
+    }
+
     /// returns a copy of register values at a given time
     pub fn register_snapshot(&self) -> RegisterState {
         self.cpu.regs.clone()
     }
 
-    /// executes enough instructions that can run for 1 video frame
-    pub fn execute_frame(&mut self) {
-        let fps = 60;
-        let cycles = self.cpu.clock_hz / fps;
-        // println!("will execute {} cycles", cycles);
+    /// executes instructions until the video beam reaches the next vertical retrace, at most
+    /// for the cycles of one video frame. returns the number of executed cycles
+    pub fn execute_frame(&mut self) -> usize {
+        let budget = self.cpu.clock_hz / self.gpu().refresh_rate.max(1);
+        let frame_count = self.gpu().frame_count;
+        let mut executed = 0;
+        while executed < budget && self.gpu().frame_count == frame_count && !self.cpu.fatal_error {
+            executed += self.execute_counted();
+        }
+        executed
+    }
 
-        loop {
-            self.execute_instruction();
-            if self.cpu.fatal_error {
-                break;
+    /// executes instructions for at least `cycles` emulated cpu cycles, or until a fatal error.
+    /// returns the number of executed cycles
+    pub fn run_for_cycles(&mut self, cycles: usize) -> usize {
+        let mut executed = 0;
+        while executed < cycles && !self.cpu.fatal_error {
+            executed += self.execute_counted();
+        }
+        executed
+    }
+
+    /// sets a function called at the start of each vertical retrace, as timed by the executed cycles
+    pub fn set_vblank_callback<F: FnMut(&Machine) + Send + 'static>(&mut self, callback: F) {
+        self.vblank_callback = Some(Box::new(callback));
+    }
+
+    pub fn clear_vblank_callback(&mut self) {
+        self.vblank_callback = None;
+    }
+
+    /// reseeds the random number generator, making the random values handed to programs reproducible
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = XorShiftRng::seed_from_u64(seed);
+    }
+
+    /// returns the random number generator, for components and tools that need random values
+    pub fn rng_mut(&mut self) -> &mut dyn RngCore {
+        &mut self.rng
+    }
+
+    /// sets the emulated speed in percent of the cpu clock, 100 is authentic speed
+    pub fn set_speed_percent(&mut self, percent: u32) {
+        self.governor.set_speed_percent(percent);
+    }
+
+    /// enables or disables running as fast as the host allows
+    pub fn set_unlimited(&mut self, unlimited: bool) {
+        self.governor.set_unlimited(unlimited);
+    }
+
+    /// executes the cycles the emulated cpu runs in `elapsed` host time at the current speed.
+    /// in unlimited mode, executes as much as possible during `elapsed` instead.
+    /// returns the number of executed cycles
+    pub fn execute_for(&mut self, elapsed: Duration) -> usize {
+        let mut executed = 0;
+        if self.governor.is_unlimited() {
+            let start = Instant::now();
+            while start.elapsed() < elapsed {
+                for _ in 0..UNLIMITED_BATCH {
+                    executed += self.execute_counted();
+                    if self.cpu.fatal_error {
+                        return executed;
+                    }
+                }
             }
-            if self.cpu.cycle_count > cycles {
-                self.cpu.cycle_count = 0;
+            return executed;
+        }
+
+        self.governor.add_time(elapsed, self.cpu.clock_hz);
+        while self.governor.budget() > 0 {
+            let cycles = self.execute_counted();
+            self.governor.spend(cycles);
+            executed += cycles;
+            if self.cpu.fatal_error {
+                self.governor.reset();
                 break;
             }
         }
+        executed
+    }
+
+    /// executes one instruction, returns the number of cycles it took
+    fn execute_counted(&mut self) -> usize {
+        let start = self.cpu.cycle_count;
+        self.execute_instruction();
+        self.cpu.cycle_count.wrapping_sub(start)
     }
 
     /// executes n instructions of the cpu
@@ -394,34 +1460,73 @@ impl Machine {
         }
     }
 
-    /// returns first line of disassembly using nasm
-    fn external_disasm_of_bytes(&self, cs: u16, ip: u16) -> String {
+    /// logs the disassembly of the bytes at cs:ip, and the ndisasm disassembly when the feature is enabled
+    fn log_disasm_of_bytes(&self, cs: u16, ip: u16) {
         let bytes = self.mmu.read(cs, ip, 16);
-        ndisasm_first_instr(&bytes).unwrap()
+        error!(target: "cpu", "disasm: {}", disasm_first_instr(&bytes));
+        #[cfg(feature = "ndisasm")]
+        match ndisasm_first_instr(&bytes) {
+            Ok(s) => error!(target: "cpu", "ndisasm: {}", s),
+            Err(e) => error!(target: "cpu", "ndisasm: {}", e),
+        }
+    }
+
+    /// invokes the high level handler of a callback instruction in the BIOS
+    fn handle_callback(&mut self, number: u16) {
+        match number {
+            0x00..=0xFF => self.handle_interrupt(number as u8),
+            BIOS::CALLBACK_TIMER_TICK => self.bios.timer_tick(&mut self.mmu),
+            Shell::CALLBACK => self.shell_step(),
+            _ => warn!(target: "int", "error: unknown callback {:04X}", number),
+        }
+        if self.cpu.fatal_error && self.dos.exit_code.is_some() {
+            if self.shell.is_some() {
+                self.return_to_shell();
+            } else {
+                self.return_to_parent();
+            }
+        }
     }
 
     fn handle_interrupt(&mut self, int: u8) {
+        // the handler can also be entered by a far call or jump chaining to it, the flags
+        // of the interrupted code are in the frame on top of the stack
+        let (ss, sp) = (self.cpu.get_r16(R::SS), self.cpu.get_r16(R::SP));
+        self.mmu.flags_address = MemoryAddress::RealSegmentOffset(ss, sp.wrapping_add(4));
+
+        if !self.io_breakpoints.is_empty() {
+            self.io_breakpoints.interrupt(int, self.cpu.get_r16(R::AX));
+        }
+
+        if int == 0x70 {
+            // the BIOS handler of IRQ 8 ends the interrupt on both controllers
+            self.pic_mut().end_of_interrupt(8);
+        }
+
+        if int == 0x2F {
+            // the multiplex interrupt is dispatched on AH
+            let index = self.multiplex_map[usize::from(self.cpu.get_r8(R::AH))];
+            if index != NO_COMPONENT && self.components[usize::from(index)].component.int(int, &mut self.cpu, &mut self.mmu) {
+                return;
+            }
+        }
+
         // ask subsystems if they can handle the interrupt
-        for component in &mut self.components {
-            let handled = match component {
-                MachineComponent::PIC(c) => c.int(int, &mut self.cpu, &mut self.mmu),
-                MachineComponent::PIT(c) => c.int(int, &mut self.cpu, &mut self.mmu),
-                MachineComponent::Keyboard(c) => c.int(int, &mut self.cpu, &mut self.mmu),
-                MachineComponent::Mouse(c) => c.int(int, &mut self.cpu, &mut self.mmu),
-                MachineComponent::Storage(c) => c.int(int, &mut self.cpu, &mut self.mmu),
-                MachineComponent::GPU(c) => c.int(int, &mut self.cpu, &mut self.mmu),
-            };
-            if handled {
+        for c in &mut self.components {
+            if c.interrupts.contains(&int) && c.component.int(int, &mut self.cpu, &mut self.mmu) {
                 return;
             }
         }
 
         match int {
+            0x01 | 0x04 => {
+                // SINGLE-STEP and OVERFLOW, the default handlers do nothing
+            }
             0x03 => {
-                // debugger interrupt
+                // debugger interrupt, not hooked by the program
                 // http://www.ctyme.com/intr/int-03.htm
-                println!("INT 3 - debugger interrupt. AX={:04X}", self.cpu.get_r16(R::AX));
-                if HANDLE_DEBUG_INTERRUPT {
+                if self.break_on_int3 {
+                    info!(target: "int", "INT 3 - debugger interrupt. AX={:04X}", self.cpu.get_r16(R::AX));
                     self.cpu.fatal_error = true; // stops execution
                 }
             }
@@ -433,10 +1538,10 @@ impl Machine {
                         // DX = printer number (00h-02h)
                         // Return: AH = printer status (see #00631)
                         let dx = self.cpu.get_r16(R::DX);
-                        println!("XXX PRINTER - GET STATUS, printer {}", dx);
+                        warn!(target: "int", "XXX PRINTER - GET STATUS, printer {}", dx);
                     }
                     _ => {
-                        println!("int error: unknown printer interrupt, AH={:02X}, BX={:04X}, CX={:04X}, DX={:04X}",
+                        warn!(target: "int", "int error: unknown printer interrupt, AH={:02X}, BX={:04X}, CX={:04X}, DX={:04X}",
                             self.cpu.get_r8(R::AH),
                             self.cpu.get_r16(R::BX),
                             self.cpu.get_r16(R::CX),
@@ -444,79 +1549,92 @@ impl Machine {
                     }
                 }
             }
-            0x20 | 0x21 => {
-                self.dos.int(int, &mut self.cpu, &mut self.mmu);
+            0x12 | 0x15 => {
+                self.bios.int(int, &mut self.cpu, &mut self.mmu);
+            }
+            0x1C => {
+                // SYSTEM TIMER TICK, called by the INT 08 handler. programs hook it, the default handler does nothing
+            }
+            0x00 | 0x20 | 0x21 | 0x23 | 0x24 | 0x29 | 0x2F => {
+                if int == 0x21 && self.dos.checks_break(self.cpu.get_r8(R::AH)) && self.keyboard_mut().take_break() {
+                    self.dos.control_break(&mut self.cpu, &mut self.mmu);
+                    return;
+                }
+                if int != 0x21 || !(self.dos.clock_int(&mut self.cpu, &mut self.mmu, &mut self.clock) || self.dos.temp_file_int(&mut self.cpu, &mut self.mmu, &mut self.rng)) {
+                    self.dos.int(int, &mut self.cpu, &mut self.mmu);
+                }
+                if let Some(request) = self.dos.take_exec_request() {
+                    self.exec(request);
+                }
             },
             0x27 => {
                 // DOS 1+ - TERMINATE AND STAY RESIDENT
                 // DX = number of bytes to keep resident (max FFF0h)
                 // CS = segment of PSP
                 // Return: Never
-                println!("XXX DOS - TERMINATE AND STAY RESIDENT");
+                warn!(target: "dos", "XXX DOS - TERMINATE AND STAY RESIDENT");
                 self.cpu.fatal_error = true; // stops execution
             }
+            0x06 => {
+                // INVALID OPCODE, not hooked by the program
+                error!(target: "cpu", "[{}] invalid opcode exception without a handler", self.cpu.get_memory_address());
+                self.cpu.fatal_error = true;
+            }
             _ => {
-                println!("int error: unknown interrupt {:02X}, AX={:04X}, BX={:04X}, CX={:04X}, DX={:04X}",
+                warn!(target: "int", "int error: unknown interrupt {:02X}, AX={:04X}, BX={:04X}, CX={:04X}, DX={:04X}",
                         int,
                         self.cpu.get_r16(R::AX),
                         self.cpu.get_r16(R::BX),
                         self.cpu.get_r16(R::CX),
                         self.cpu.get_r16(R::DX));
+                if self.unknown_interrupt == UnknownPolicy::Stop {
+                    self.cpu.fatal_error = true;
+                }
             }
         }
     }
 
     /// executes the next CPU instruction
     pub fn execute_instruction(&mut self) {
-        let cs = self.cpu.get_r16(R::CS);
-        let ip = self.cpu.regs.ip;
-        if cs == 0xF000 {
-            // we are in interrupt vector code, execute high-level interrupt.
-            // the default interrupt vector table has a IRET
-            self.handle_interrupt(ip as u8);
+        let mut cs = self.cpu.get_r16(R::CS);
+        let mut ip = self.cpu.regs.ip;
+        let start_cycles = self.cpu.cycle_count;
+        // TF set before the instruction gives a single-step trap after it
+        let trap = self.cpu.regs.flags.trap;
+
+        #[cfg(feature = "jit")]
+        {
+            if self.execute_jit_block(cs, ip) {
+                self.check_stack_guard(cs, ip);
+                self.update_components(start_cycles);
+                return;
+            }
+        }
+
+        let mut op = self.cpu.decoder.get_instruction(&mut self.mmu, cs, ip);
+        if op.command == Op::Callback {
+            // high level handlers run as part of the instruction following the callback,
+            // the IRET of the default interrupt handlers
+            ip = ip.wrapping_add(u16::from(op.length));
+            self.cpu.regs.ip = ip;
+            self.handle_callback(self.cpu.read_parameter_imm(&op.params.dst) as u16);
+            // the handler may have started another program, such as the shell
+            cs = self.cpu.get_r16(R::CS);
+            ip = self.cpu.regs.ip;
+            op = self.cpu.decoder.get_instruction(&mut self.mmu, cs, ip);
         }
 
-        let op = self.cpu.decoder.get_instruction(&mut self.mmu, cs, ip);
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(MemoryAddress::RealSegmentOffset(cs, ip).value(), op.length);
+        }
 
         if self.trace_file.is_some() {
-            let ax = self.cpu.get_r16(R::AX);
-            let bx = self.cpu.get_r16(R::BX);
-            let cx = self.cpu.get_r16(R::CX);
-            let dx = self.cpu.get_r16(R::DX);
-
-            let si = self.cpu.get_r16(R::SI);
-            let di = self.cpu.get_r16(R::DI);
-            let bp = self.cpu.get_r16(R::BP);
-            let sp = self.cpu.get_r16(R::SP);
-
-            let ds = self.cpu.get_r16(R::DS);
-            let es = self.cpu.get_r16(R::ES);
-            //let fs = self.cpu.get_r16(R::FS);
-            //let gs = self.cpu.get_r16(R::GS);
-            let ss = self.cpu.get_r16(R::SS);
-
-            let cf = self.cpu.regs.flags.carry_numeric();
-            let zf = self.cpu.regs.flags.zero_numeric();
-            let sf = self.cpu.regs.flags.sign_numeric();
-            let of = self.cpu.regs.flags.overflow_numeric();
-            let iflag = self.cpu.regs.flags.interrupt_numeric();
-
-            // format similar to dosbox LOGS output
-            if let Some(file) = &self.trace_file {
-                let disasm = &format!("{:30}", format!("{}", op))[..30];
-                let mut writer = BufWriter::new(file);
-                let _ = write!(&mut writer, "{:04X}:{:04X}  {}", cs, ip, disasm);
-                let _ = write!(&mut writer, " EAX:{:08X} EBX:{:08X} ECX:{:08X} EDX:{:08X} ESI:{:08X} EDI:{:08X} EBP:{:08X} ESP:{:08X}", ax, bx, cx, dx, si, di, bp, sp);
-                let _ = write!(&mut writer, " DS:{:04X} ES:{:04X}", ds, es);
-                // let _ = write!(&mut writer, " FS:{:04X} GS:{:04X}", fs, g);
-                let _ = write!(&mut writer, " SS:{:04X}", ss);
-                let _ = writeln!(&mut writer, " C{} Z{} S{} O{} I{}", cf, zf, sf, of, iflag);
-            }
+            self.write_trace(cs, ip, &op);
         }
         if let Some(max) = self.trace_count {
             if self.cpu.instruction_count >= max {
                 self.cpu.fatal_error = true;
-                println!("[{:04X}:{:04X}] ending execution trace after {} instructions", cs, ip, self.cpu.instruction_count);
+                info!(target: "cpu", "[{:04X}:{:04X}] ending execution trace after {} instructions", cs, ip, self.cpu.instruction_count);
                 return;
             }
         }
@@ -524,67 +1642,208 @@ impl Machine {
         match op.command {
             Op::Uninitialized => {
                 self.cpu.fatal_error = true;
-                println!("[{:04X}:{:04X}] ERROR: uninitialized op. {} instructions executed",
+                error!(target: "cpu", "[{:04X}:{:04X}] ERROR: uninitialized op. {} instructions executed",
                          cs, ip, self.cpu.instruction_count);
             }
+            Op::Invalid(ref bytes, Invalid::Unsupported) => {
+                // the emulated cpus lack these, and raise #UD regardless of the policy
+                debug!(target: "cpu", "[{:04X}:{:04X}] {} unsupported opcode, raising #UD", cs, ip, hex_bytes(bytes));
+                self.cpu.regs.ip = ip.wrapping_add(u16::from(op.length));
+                self.cpu.exception(&mut self.mmu, &Exception::UD, op.length);
+            }
             Op::Invalid(bytes, reason) => {
                 let hex = hex_bytes(&bytes);
-                self.cpu.fatal_error = true;
                 match reason {
                     Invalid::Op => {
-                        println!("[{:04X}:{:04X}] {} ERROR: unhandled opcode", cs, ip, hex);
-                        println!("ndisasm: {}", self.external_disasm_of_bytes(cs, ip));
+                        error!(target: "cpu", "[{:04X}:{:04X}] {} ERROR: unhandled opcode", cs, ip, hex);
+                        self.log_disasm_of_bytes(cs, ip);
                     }
                     Invalid::FPUOp => {
-                        println!("[{:04X}:{:04X}] {} ERROR: unhandled FPU opcode", cs, ip, hex);
-                        println!("ndisasm: {}", self.external_disasm_of_bytes(cs, ip));
+                        error!(target: "cpu", "[{:04X}:{:04X}] {} ERROR: unhandled FPU opcode", cs, ip, hex);
+                        self.log_disasm_of_bytes(cs, ip);
                     }
                     Invalid::Reg(reg) => {
-                        println!("[{:04X}:{:04X}] {} ERROR: unhandled reg value {:02X}", cs, ip, hex, reg);
-                        println!("ndisasm: {}", self.external_disasm_of_bytes(cs, ip));
+                        error!(target: "cpu", "[{:04X}:{:04X}] {} ERROR: unhandled reg value {:02X}", cs, ip, hex, reg);
+                        self.log_disasm_of_bytes(cs, ip);
+                    }
+                    Invalid::Unsupported => unreachable!(),
+                }
+                match self.invalid_opcode {
+                    UnknownPolicy::Stop => self.cpu.fatal_error = true,
+                    UnknownPolicy::SkipAndLog => {
+                        self.cpu.regs.ip = ip.wrapping_add(u16::from(op.length.max(1)));
+                    }
+                    UnknownPolicy::RaiseUD => {
+                        self.cpu.regs.ip = ip.wrapping_add(u16::from(op.length));
+                        self.cpu.exception(&mut self.mmu, &Exception::UD, op.length);
                     }
                 }
             }
             _ => {
-                if DEBUG_EXEC {
-                    println!("[{:04X}:{:04X}] {}", cs, ip, op);
-                }
+                trace!(target: "cpu", "[{:04X}:{:04X}] {}", cs, ip, op);
                 self.execute(&op);
+                if trap && !op.inhibits_trap() {
+                    self.cpu.execute_interrupt(&mut self.mmu, 0x01);
+                }
             },
         }
 
-        if self.cpu.cycle_count % 100 == 0 {
-            // XXX need instruction timing to do this properly
-            self.gpu_mut().progress_scanline();
+        #[cfg(feature = "jit")]
+        {
+            self.jit_fallthrough = Some((cs, ip.wrapping_add(u16::from(op.length))));
         }
 
-        // HACK: pit should be updated regularry, but in a deterministic way
-        if self.cpu.cycle_count % 100 == 0 {
-            for component in &mut self.components {
-                if let MachineComponent::PIT(pit) = component {
-                    pit.update(&mut self.mmu);
-                }
+        self.check_stack_guard(cs, ip);
+        self.update_components(start_cycles);
+    }
+
+    /// reports stack conditions found by the stack guard after the instruction at `cs`:`ip`
+    fn check_stack_guard(&mut self, cs: u16, ip: u16) {
+        if !self.stack_guard.config.enabled {
+            return;
+        }
+        if let Some(event) = self.stack_guard.check(self.cpu.get_r16(R::SS), self.cpu.get_r16(R::SP)) {
+            warn!(target: "cpu", "[{:04X}:{:04X}] {}", cs, ip, event);
+            if self.stack_guard.config.action == StackGuardAction::Break {
+                self.cpu.fatal_error = true; // stops execution
+            }
+        }
+    }
+
+    /// progresses the timed components by the cycles executed since `start_cycles`
+    fn update_components(&mut self, start_cycles: usize) {
+        let cycles = self.cpu.cycle_count.wrapping_sub(start_cycles);
+        let clock_hz = self.cpu.clock_hz;
+        self.clock.advance(cycles, clock_hz);
+        let frame_count = self.gpu().frame_count;
+        self.gpu_mut().update(cycles, clock_hz);
+        if self.gpu().frame_count != frame_count {
+            if self.video_recorder.is_some() {
+                self.capture_video_frame();
+            }
+            if let Some(mut callback) = self.vblank_callback.take() {
+                callback(self);
+                self.vblank_callback = Some(callback);
+            }
+        }
+
+        if let Some(pit) = indexed_component_mut::<PITComponent>(&mut self.components, self.timed.pit) {
+            self.timer_irqs = self.timer_irqs.saturating_add(pit.update(cycles, clock_hz));
+        }
+        let mut rtc_irq = false;
+        if let Some(cmos) = indexed_component_mut::<CMOSComponent>(&mut self.components, self.timed.cmos) {
+            rtc_irq = cmos.update(&self.clock, cycles, clock_hz);
+        }
+        let mut network_irq = None;
+        if let Some(driver) = indexed_component_mut::<PacketDriver>(&mut self.components, self.timed.packet_driver) {
+            if driver.update(cycles, clock_hz) {
+                network_irq = Some(driver.irq());
+            }
+        }
+        let mut ipx_irq = false;
+        if let Some(ipx) = indexed_component_mut::<Ipx>(&mut self.components, self.timed.ipx) {
+            ipx_irq = ipx.update(cycles, clock_hz, &mut self.mmu);
+        }
+        let interrupt = self.cpu.regs.flags.interrupt;
+        let mut vector = None;
+        if let Some(pic) = indexed_component_mut::<PICComponent>(&mut self.components, self.timed.pic) {
+            // IRQ 0 - system timer. each request is passed on once the previous one was taken, so that
+            // INT 08h runs once per period of timer 0 even when many periods passed in one step.
+            // as the PIC holds only one request, the others are lost while IRQ 0 is masked
+            if self.timer_irqs > 0 && pic.master.irr & 1 == 0 {
+                pic.pulse_irq(0);
+                self.timer_irqs -= 1;
+            }
+            if pic.master.imr & 1 != 0 {
+                self.timer_irqs = 0;
+            }
+            if rtc_irq {
+                pic.pulse_irq(8); // IRQ 8 - real-time clock
+            }
+            if let Some(irq) = network_irq {
+                pic.pulse_irq(irq); // network card, a frame was received
             }
+            if ipx_irq {
+                pic.pulse_irq(IPX_IRQ); // IPX, ESRs of completed ECBs are called
+            }
+            if interrupt {
+                vector = pic.acknowledge();
+            }
+        }
+        if let Some(vector) = vector {
+            self.cpu.execute_interrupt(&mut self.mmu, vector);
+        }
+    }
+
+    /// runs the compiled block at cs:ip if there is one, returns true if it was executed.
+    /// in validation mode, the interpreter result is used and compared to the block result
+    #[cfg(feature = "jit")]
+    fn execute_jit_block(&mut self, cs: u16, ip: u16) -> bool {
+        let branched = self.jit_fallthrough != Some((cs, ip));
+        self.jit_fallthrough = None;
+        if !branched || self.jit.disabled || cs == 0xF000 || self.cpu.regs.flags.trap || self.trace_file.is_some() || self.trace_count.is_some() || self.coverage.is_some() {
+            return false;
+        }
+        self.jit.on_branch(&mut self.mmu, &mut self.cpu.decoder, cs, ip);
+        let max_cycles = self.cycles_until_irq();
+
+        if !self.jit.validate {
+            return self.jit.run(&self.mmu, &mut self.cpu, cs, ip, max_cycles).is_some();
         }
 
+        let before = self.cpu.regs.clone();
+        let instruction_count = self.cpu.instruction_count;
+        let cycle_count = self.cpu.cycle_count;
+        let instructions = match self.jit.run(&self.mmu, &mut self.cpu, cs, ip, max_cycles) {
+            Some(n) => n,
+            None => return false,
+        };
+        let compiled = std::mem::replace(&mut self.cpu.regs, before);
+        self.cpu.instruction_count = instruction_count;
+        self.cpu.cycle_count = cycle_count;
+
+        for _ in 0..instructions {
+            let ip = self.cpu.regs.ip;
+            let op = self.cpu.decoder.get_instruction(&mut self.mmu, cs, ip);
+            self.execute(&op);
+        }
+        if !jit::same_state(&compiled, &self.cpu.regs) {
+            warn!(target: "cpu", "[{:04X}:{:04X}] jit: compiled block differs from interpreter, rejecting it", cs, ip);
+            self.jit.reject(cs, ip);
+        }
+        true
+    }
+
+    /// returns the cpu cycles that pass before a timed component may raise an interrupt
+    #[cfg(feature = "jit")]
+    fn cycles_until_irq(&self) -> usize {
+        let clock_hz = self.cpu.clock_hz;
+        let pit = indexed_component::<PITComponent>(&self.components, self.timed.pit)
+            .and_then(|pit| pit.cycles_until_irq(clock_hz));
+        let cmos = indexed_component::<CMOSComponent>(&self.components, self.timed.cmos)
+            .and_then(|cmos| cmos.cycles_until_irq(clock_hz));
+        pit.into_iter().chain(cmos).min().unwrap_or(usize::MAX)
     }
 
     /// read byte from I/O port
     pub fn in_u8(&mut self, port: u16) -> u8 {
-        if DEBUG_IO {
-            println!("in_u8: read from {:04X}", port);
-        }
-
-        for component in &mut self.components {
-            let handled = match component {
-                MachineComponent::PIC(c) => c.in_u8(port),
-                MachineComponent::PIT(c) => c.in_u8(port),
-                MachineComponent::Keyboard(c) => c.in_u8(port),
-                MachineComponent::Mouse(c) => c.in_u8(port),
-                MachineComponent::Storage(c) => c.in_u8(port),
-                MachineComponent::GPU(c) => c.in_u8(port),
-            };
-            if let Some(v) = handled {
+        let data = self.read_port_u8(port);
+        if !self.io_breakpoints.is_empty() {
+            self.io_breakpoints.port_read(port, data);
+        }
+        data
+    }
+
+    fn read_port_u8(&mut self, port: u16) -> u8 {
+        trace!(target: "io", "in_u8: read from {:04X}", port);
+
+        let index = self.port_map[port as usize];
+        if index != NO_COMPONENT {
+            if let Some(v) = self.components[index as usize].component.in_u8(port) {
+                if port == 0x0061 && self.pit_mut().timer2.output {
+                    // the timer 2 output is readable in bit 5
+                    return v | 0b0010_0000;
+                }
                 return v;
             }
         }
@@ -593,10 +1852,18 @@ impl Machine {
             // PORT 0000-001F - DMA 1 - FIRST DIRECT MEMORY ACCESS CONTROLLER (8237)
             0x0002 => {
                 // DMA channel 1	current address		byte  0, then byte 1
-                println!("XXX fixme in_port read DMA channel 1 current address");
+                warn!(target: "io", "XXX fixme in_port read DMA channel 1 current address");
                 0
             }
 
+            0x0092 => {
+                // PORT 0092 - PS/2 system control port A
+                if self.mmu.memory.a20() {
+                    0b10
+                } else {
+                    0
+                }
+            }
             0x0201 => {
                 // read joystick position and status
                 // Bit(s)	Description	(Table P0542)
@@ -611,7 +1878,7 @@ impl Machine {
                 0 // XXX
             }
             _ => {
-                println!("in_u8: unhandled port {:04X}", port);
+                warn!(target: "io", "in_u8: unhandled port {:04X}", port);
                 0
             }
         }
@@ -619,31 +1886,63 @@ impl Machine {
 
     /// read word from I/O port
     pub fn in_u16(&mut self, port: u16) -> u16 {
-        println!("in_u16: unhandled read from {:04X}", port);
-        0
+        let data = self.read_port_u16(port);
+        if !self.io_breakpoints.is_empty() {
+            self.io_breakpoints.port_read(port, data as u8);
+            self.io_breakpoints.port_read(port.wrapping_add(1), (data >> 8) as u8);
+        }
+        data
+    }
+
+    fn read_port_u16(&mut self, port: u16) -> u16 {
+        trace!(target: "io", "in_u16: read from {:04X}", port);
+
+        if let Some(index) = self.word_port_component(port) {
+            if let Some(v) = self.components[index].component.in_u16(port) {
+                return v;
+            }
+        }
+        let lo = self.read_port_u8(port);
+        let hi = self.read_port_u8(port.wrapping_add(1));
+        u16::from(hi) << 8 | u16::from(lo)
+    }
+
+    /// returns the index of the component handling both `port` and `port+1`, if any
+    fn word_port_component(&self, port: u16) -> Option<usize> {
+        let index = self.port_map[port as usize];
+        if index != NO_COMPONENT && self.port_map[port.wrapping_add(1) as usize] == index {
+            Some(index as usize)
+        } else {
+            None
+        }
+    }
+
+    /// read dword from I/O port
+    pub fn in_u32(&mut self, port: u16) -> u32 {
+        let lo = self.in_u16(port);
+        let hi = self.in_u16(port+2);
+        u32::from(hi) << 16 | u32::from(lo)
     }
 
     /// write byte to I/O port
     pub fn out_u8(&mut self, port: u16, data: u8) {
-        if DEBUG_IO {
-            println!("out_u8: write to {:04X} = {:02X}", port, data);
-        }
-
-        for component in &mut self.components {
-            let b = match component {
-                MachineComponent::PIC(c) => c.out_u8(port, data),
-                MachineComponent::PIT(c) => c.out_u8(port, data),
-                MachineComponent::Keyboard(c) => c.out_u8(port, data),
-                MachineComponent::Mouse(c) => c.out_u8(port, data),
-                MachineComponent::Storage(c) => c.out_u8(port, data),
-                MachineComponent::GPU(c) => c.out_u8(port, data),
-            };
-            if b {
-                return;
-            }
+        trace!(target: "io", "out_u8: write to {:04X} = {:02X}", port, data);
+        if !self.io_breakpoints.is_empty() {
+            self.io_breakpoints.port_write(port, data);
+        }
+
+        let index = self.port_map[port as usize];
+        if index != NO_COMPONENT && self.components[index as usize].component.out_u8(port, data) {
+            self.port_written(port);
+            return;
         }
 
         match port {
+            0x0092 => {
+                // PORT 0092 - PS/2 system control port A
+                // bit 1 = A20 gate, bit 0 = fast reset (not emulated)
+                self.set_a20(data & 0b10 != 0);
+            }
             0x0201 => {
                 // W  fire joystick's four one-shots
             }
@@ -653,2092 +1952,104 @@ impl Machine {
 
                 // ../dos-software-decoding/games-com/Galaxian (1983)(Atari Inc)/galaxian.com writes 0x0C
             }
-            _ => println!("out_u8: unhandled port {:04X} = {:02X}", port, data),
+            _ => warn!(target: "io", "out_u8: unhandled port {:04X} = {:02X}", port, data),
         }
     }
 
     /// write word to I/O port
     pub fn out_u16(&mut self, port: u16, data: u16) {
-        if DEBUG_IO {
-            println!("out_u16: write to {:04X} = {:04X}", port, data);
+        trace!(target: "io", "out_u16: write to {:04X} = {:04X}", port, data);
+        if let Some(index) = self.word_port_component(port) {
+            if self.components[index].component.out_u16(port, data) {
+                if !self.io_breakpoints.is_empty() {
+                    self.io_breakpoints.port_write(port, data as u8);
+                    self.io_breakpoints.port_write(port.wrapping_add(1), (data >> 8) as u8);
+                }
+                self.port_written(port);
+                self.port_written(port.wrapping_add(1));
+                return;
+            }
+        }
+        self.out_u8(port, data as u8);
+        self.out_u8(port.wrapping_add(1), (data >> 8) as u8);
+    }
+
+    /// enables or disables the A20 gate. the cached instructions were decoded from memory at the old addresses
+    fn set_a20(&mut self, enabled: bool) {
+        if self.mmu.memory.a20() != enabled {
+            self.mmu.memory.set_a20(enabled);
+            self.cpu.decoder.clear_cache();
+        }
+    }
+
+    /// applies side effects of a write to `port` handled by a component
+    fn port_written(&mut self, port: u16) {
+        match port {
+            0x0060 | 0x0064 => {
+                // the keyboard controller output port gates the A20 line
+                if let Some(a20) = self.keyboard_mut().take_a20_request() {
+                    self.set_a20(a20);
+                }
+            }
+            0x0061 => {
+                // bit 0 of port b gates timer 2
+                let gate = self.keyboard_mut().port_b & 1 != 0;
+                self.pit_mut().set_gate(2, gate);
+            }
+            _ => {}
         }
-        let lo = data as u8;
-        let hi = (data >> 8) as u8;
-        self.out_u8(port, lo);
-        self.out_u8(port+1, hi);
     }
 
-    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cyclomatic_complexity))]
+    /// write dword to I/O port
+    pub fn out_u32(&mut self, port: u16, data: u32) {
+        self.out_u16(port, data as u16);
+        self.out_u16(port+2, (data >> 16) as u16);
+    }
+
     fn execute(&mut self, op: &Instruction) {
         let start_ip = self.cpu.regs.ip;
         self.cpu.regs.ip = self.cpu.regs.ip.wrapping_add(op.length as u16);
         self.cpu.instruction_count += 1;
-        self.cpu.cycle_count += 1; // XXX temp hack; we pretend each instruction takes 8 cycles due to lack of timing
-        match op.command {
-            Op::Aaa => {
-                let v = if self.cpu.get_r8(R::AL) > 0xf9 {
-                    2
-                 } else {
-                    1
-                };
-                self.cpu.adjb(6, v);
-            }
-            Op::Aad => {
-                // one parameter
-                let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-
-                let mut ax = u16::from(self.cpu.get_r8(R::AH)) * op1;
-                ax += u16::from(self.cpu.get_r8(R::AL));
-                let al = ax as u8;
-                self.cpu.set_r16(R::AX, al as u16);
-                self.cpu.regs.flags.sign = al >= 0x80;
-                self.cpu.regs.flags.zero = al == 0;
-                self.cpu.regs.flags.set_parity(al as usize);
-            }
-            Op::Aam => {
-                let imm8 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u8;
-                if imm8 == 0 {
-                    return self.cpu.exception(&Exception::DIV0, 0);
-                }
-                let al = self.cpu.get_r8(R::AL);
-                self.cpu.set_r8(R::AH, al / imm8);
-                self.cpu.set_r8(R::AL, al % imm8);
-                // The SF, ZF, and PF flags are set according to the resulting binary value in the AL register
-                let al = self.cpu.get_r8(R::AL);
-                self.cpu.regs.flags.sign = al & 0x80 != 0;
-                self.cpu.regs.flags.zero = al == 0;
-                self.cpu.regs.flags.set_parity(al as usize);
-            }
-            Op::Aas => {
-                let v = if self.cpu.get_r8(R::AL) < 6 {
-                    -2
-                } else {
-                    -1
-                };
-                self.cpu.adjb(-6, v);
-            }
-            Op::Adc8 => {
-                // two parameters (dst=reg)
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let carry = if self.cpu.regs.flags.carry { 1 } else { 0 };
-                let res = (Wrapping(dst) + Wrapping(src) + Wrapping(carry)).0;
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, (res & 0xFF) as u8);
-
-                // The OF, SF, ZF, AF, CF, and PF flags are set according to the result.
-                self.cpu.regs.flags.set_overflow_add_u8(res, src + carry, dst);
-                self.cpu.regs.flags.set_sign_u8(res);
-                self.cpu.regs.flags.set_zero_u8(res);
-                self.cpu.regs.flags.set_adjust(res, src + carry, dst);
-                self.cpu.regs.flags.set_carry_u8(res);
-                self.cpu.regs.flags.set_parity(res);
-            }
-            Op::Adc16 => {
-                // two parameters (dst=reg)
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let carry = if self.cpu.regs.flags.carry { 1 } else { 0 };
-                let res = (Wrapping(dst) + Wrapping(src) + Wrapping(carry)).0;
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, (res & 0xFFFF) as u16);
-
-                // The OF, SF, ZF, AF, CF, and PF flags are set according to the result.
-                self.cpu.regs.flags.set_overflow_add_u16(res, src + carry, dst);
-                self.cpu.regs.flags.set_sign_u16(res);
-                self.cpu.regs.flags.set_zero_u16(res);
-                self.cpu.regs.flags.set_adjust(res, src + carry, dst);
-                self.cpu.regs.flags.set_carry_u16(res);
-                self.cpu.regs.flags.set_parity(res);
-            }
-            Op::Add8 => {
-                // two parameters (dst=reg)
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u8;
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u8;
-                let res = src as usize + dst as usize;
-                self.cpu.regs.flags.set_carry_u8(res);
-                self.cpu.regs.flags.set_parity(res);
-                self.cpu.regs.flags.set_adjust(res, src as usize, dst as usize);
-                self.cpu.regs.flags.set_zero_u8(res);
-                self.cpu.regs.flags.set_sign_u8(res);
-                self.cpu.regs.flags.set_overflow_add_u8(res, src as usize, dst as usize);
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
-            }
-            Op::Add16 => {
-                // two parameters (dst=reg)
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u16;
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                let res = src as usize + dst as usize;
-                self.cpu.regs.flags.set_carry_u16(res);
-                self.cpu.regs.flags.set_parity(res);
-                self.cpu.regs.flags.set_adjust(res, src as usize, dst as usize);
-                self.cpu.regs.flags.set_zero_u16(res);
-                self.cpu.regs.flags.set_sign_u16(res);
-                self.cpu.regs.flags.set_overflow_add_u16(res, src as usize, dst as usize);
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
-            }
-            Op::Add32 => {
-                // two parameters (dst=reg)
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
-                let res = src as usize + dst as usize;
-                self.cpu.regs.flags.set_carry_u32(res);
-                self.cpu.regs.flags.set_parity(res);
-                self.cpu.regs.flags.set_adjust(res, src as usize, dst as usize);
-                self.cpu.regs.flags.set_zero_u32(res);
-                self.cpu.regs.flags.set_sign_u32(res);
-                self.cpu.regs.flags.set_overflow_add_u32(res, src as usize, dst as usize);
-                self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u32);
-            }
-            Op::And8 => {
-                // two parameters (dst=reg)
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let res = dst & src;
-
-                // The OF and CF flags are cleared; the SF, ZF, and PF flags
-                // are set according to the result.
-                self.cpu.regs.flags.overflow = false;
-                self.cpu.regs.flags.carry = false;
-                self.cpu.regs.flags.set_sign_u8(res);
-                self.cpu.regs.flags.set_zero_u8(res);
-                self.cpu.regs.flags.set_parity(res);
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
-            }
-            Op::And16 => {
-                // two parameters (dst=reg)
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let res = dst & src;
-
-                // The OF and CF flags are cleared; the SF, ZF, and PF flags
-                // are set according to the result.
-                self.cpu.regs.flags.overflow = false;
-                self.cpu.regs.flags.carry = false;
-                self.cpu.regs.flags.set_sign_u16(res);
-                self.cpu.regs.flags.set_zero_u16(res);
-                self.cpu.regs.flags.set_parity(res);
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
-            }
-            Op::Arpl => {
-                println!("XXX impl {}", op);
-                /*
-                // NOTE: RPL is the low two bits of the address
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let mut dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                if dst & 3 < src & 3 {
-                    self.cpu.regs.flags.zero = true;
-                    dst = (dst & 0xFFFC) + (src & 3);
-                    self.cpu.write_parameter_u16(&mut self.mmu, op.segment, &op.params.dst, (dst & 0xFFFF) as u16);
-                } else {
-                    self.cpu.regs.flags.zero = false;
-                }
-                */
-            }
-            Op::Bsf => {
-                let mut src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                if src == 0 {
-                    self.cpu.regs.flags.zero = true;
-                } else {
-                    let mut count = 0;
-                    while src & 1 == 0 {
-                        count += 1;
-                        src >>= 1;
-                    }
-                    self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, count);
-                    self.cpu.regs.flags.zero = false;
-                }
-            }
-            Op::Bt => {
-                let bit_base = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let bit_offset = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                self.cpu.regs.flags.carry = bit_base & (1 << (bit_offset & 15)) != 0;
-            }
-            Op::Bound => {
-                // XXX throw BR exception if out of bounds
-                println!("XXX impl {}", op);
-            }
-            Op::CallNear => {
-                let old_ip = self.cpu.regs.ip;
-                let temp_ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                self.cpu.push16(&mut self.mmu, old_ip);
-                self.cpu.regs.ip = temp_ip as u16;
-            }
-            Op::CallFar => {
-                let old_seg = self.cpu.regs.get_r16(R::CS);
-                let old_ip = self.cpu.regs.ip;
-                self.cpu.push16(&mut self.mmu, old_seg);
-                self.cpu.push16(&mut self.mmu, old_ip);
-                let (seg, offs) = match op.params.dst {
-                    Parameter::Ptr16Imm(seg, offs) =>
-                        (seg, offs),
-                    Parameter::Ptr16(seg, offs) =>
-                        (self.cpu.segment(seg), offs),
-                    Parameter::Ptr16Amode(seg, ref amode) =>
-                        (self.cpu.segment(seg), self.cpu.amode(amode) as u16),
-                    Parameter::Ptr16AmodeS8(seg, ref amode, imm) =>
-                        (self.cpu.segment(seg), (self.cpu.amode(amode) as isize + imm as isize) as u16),
-                    _ => panic!("CallFar unhandled type {:?}", op.params.dst),
-                };
-                self.cpu.regs.set_r16(R::CS, seg);
-                self.cpu.regs.ip = offs;
-            }
-            Op::Cbw => {
-                let ah = if self.cpu.get_r8(R::AL) & 0x80 != 0 {
-                    0xFF
-                } else {
-                    0x00
-                };
-                self.cpu.set_r8(R::AH, ah);
-            }
-            Op::Clc => {
-                self.cpu.regs.flags.carry = false;
-            }
-            Op::Cld => {
-                self.cpu.regs.flags.direction = false;
-            }
-            Op::Cli => {
-                self.cpu.regs.flags.interrupt = false;
-            }
-            Op::Cmc => {
-                self.cpu.regs.flags.carry = !self.cpu.regs.flags.carry;
-            }
-            Op::Cmp8 => {
-                // two parameters
-                // Modify status flags in the same manner as the SUB instruction
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                self.cpu.cmp8(dst, src);
-            }
-            Op::Cmp16 => {
-                // two parameters
-                // Modify status flags in the same manner as the SUB instruction
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                self.cpu.cmp16(dst, src);
-            }
-            Op::Cmp32 => {
-                // two parameters
-                // Modify status flags in the same manner as the SUB instruction
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                self.cpu.cmp32(dst, src);
-            }
-            Op::Cmpsb => {
-                // no parameters
-                // Compare byte at address DS:(E)SI with byte at address ES:(E)DI
-                // The DS segment may be overridden with a segment override prefix, but the ES segment cannot be overridden.
-                let src = self.mmu.read_u16(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI)) as usize;
-                let dst = self.mmu.read_u16(self.cpu.get_r16(R::ES), self.cpu.get_r16(R::DI)) as usize;
-                self.cpu.cmp8(dst, src);
-
-                let si = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::SI).wrapping_add(1)
-                } else {
-                    self.cpu.get_r16(R::SI).wrapping_sub(1)
-                };
-                self.cpu.set_r16(R::SI, si);
-                let di = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::DI).wrapping_add(1)
-                } else {
-                    self.cpu.get_r16(R::DI).wrapping_sub(1)
-                };
-                self.cpu.set_r16(R::DI, di);
-            }
-            Op::Cmpsw => {
-                // no parameters
-                // Compare word at address DS:(E)SI with word at address ES:(E)DI
-                // The DS segment may be overridden with a segment override prefix, but the ES segment cannot be overridden.
-                let src = self.mmu.read_u16(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI)) as usize;
-                let dst = self.mmu.read_u16(self.cpu.get_r16(R::ES), self.cpu.get_r16(R::DI)) as usize;
-                self.cpu.cmp16(dst, src);
-
-                let si = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::SI).wrapping_add(2)
-                } else {
-                    self.cpu.get_r16(R::SI).wrapping_sub(2)
-                };
-                self.cpu.set_r16(R::SI, si);
-                let di = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::DI).wrapping_add(2)
-                } else {
-                    self.cpu.get_r16(R::DI).wrapping_sub(2)
-                };
-                self.cpu.set_r16(R::DI, di);
-            }
-            Op::Cwd16 => {
-                // DX:AX ← sign-extend of AX.
-                let dx = if self.cpu.get_r16(R::AX) & 0x8000 != 0 {
-                    0xFFFF
-                } else {
-                    0
-                };
-                self.cpu.set_r16(R::DX, dx);
-            }
-            Op::Cwde32 => {
-                // EAX ← sign-extend of AX.
-                let ax = self.cpu.get_r16(R::AX) as i16;
-                self.cpu.set_r32(R::EAX, ax as u32);
-            }
-            Op::Daa => {
-                self.cpu.adj4(6, 0x60);
-            }
-            Op::Das => {
-                self.cpu.adj4(-6, -0x60);
-            }
-            Op::Dec8 => {
-                // single parameter (dst)
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let src = 1;
-                let res = dst.wrapping_sub(src);
-
-                // The CF flag is not affected. The OF, SF, ZF, AF,
-                // and PF flags are set according to the result.
-                self.cpu.regs.flags.set_overflow_sub_u8(res, src, dst);
-                self.cpu.regs.flags.set_sign_u8(res);
-                self.cpu.regs.flags.set_zero_u8(res);
-                self.cpu.regs.flags.set_adjust(res, src, dst);
-                self.cpu.regs.flags.set_parity(res);
-
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
-            }
-            Op::Dec16 => {
-                // single parameter (dst)
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let src = 1;
-                let res = dst.wrapping_sub(src);
-
-                // The CF flag is not affected. The OF, SF, ZF, AF,
-                // and PF flags are set according to the result.
-                self.cpu.regs.flags.set_overflow_sub_u16(res, src, dst);
-                self.cpu.regs.flags.set_sign_u16(res);
-                self.cpu.regs.flags.set_zero_u16(res);
-                self.cpu.regs.flags.set_adjust(res, src, dst);
-                self.cpu.regs.flags.set_parity(res);
-
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
-            }
-            Op::Dec32 => {
-                // single parameter (dst)
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let src = 1;
-                let res = dst.wrapping_sub(src);
-
-                // The CF flag is not affected. The OF, SF, ZF, AF,
-                // and PF flags are set according to the result.
-                self.cpu.regs.flags.set_overflow_sub_u32(res, src, dst);
-                self.cpu.regs.flags.set_sign_u32(res);
-                self.cpu.regs.flags.set_zero_u32(res);
-                self.cpu.regs.flags.set_adjust(res, src, dst);
-                self.cpu.regs.flags.set_parity(res);
-
-                self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u32);
-            }
-            Op::Div8 => {
-                // Unsigned divide AX by r/m8, with result stored in AL ← Quotient, AH ← Remainder.
-                let ax = self.cpu.get_r16(R::AX) as u16;
-                let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                if op1 == 0 {
-                    return self.cpu.exception(&Exception::DIV0, 0);
-                }
-                let quotient = ax / op1;
-                let remainder = (ax % op1) as u8;
-                let quo8 = (quotient & 0xFF) as u8;
-                if quotient > 0xFF {
-                    return self.cpu.exception(&Exception::DIV0, 0);
-                }
-                self.cpu.set_r8(R::AH, remainder);
-                self.cpu.set_r8(R::AL, quo8);
-            }
-            Op::Div16 => {
-                // Unsigned divide DX:AX by r/m16, with result stored in AX ← Quotient, DX ← Remainder.
-                let num = (u32::from(self.cpu.get_r16(R::DX)) << 16) + u32::from(self.cpu.get_r16(R::AX)); // DX:AX
-                let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
-                if op1 == 0 {
-                    return self.cpu.exception(&Exception::DIV0, 0);
-                }
-                let remainder = (num % op1) as u16;
-                let quotient = num / op1;
-                let quo16 = (quotient & 0xFFFF) as u16;
-                if quotient != u32::from(quo16) {
-                    return self.cpu.exception(&Exception::DIV0, 0);
-                }
-                self.cpu.set_r16(R::DX, remainder);
-                self.cpu.set_r16(R::AX, quo16);
-            }
-            Op::Div32 => {
-                // Unsigned divide EDX:EAX by r/m32, with result stored in EAX ← Quotient, EDX ← Remainder.
-                let num = (u64::from(self.cpu.get_r32(R::EDX)) << 32) + u64::from(self.cpu.get_r32(R::EAX)); // EDX:EAX
-                let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u64;
-                if op1 == 0 {
-                    return self.cpu.exception(&Exception::DIV0, 0);
-                }
-                let remainder = (num % op1) as u32;
-                let quotient = num / op1;
-                let quo32 = (quotient & 0xFFFF) as u32;
-                if quotient != u64::from(quo32) {
-                    return self.cpu.exception(&Exception::DIV0, 0);
-                }
-                self.cpu.set_r32(R::EDX, remainder);
-                self.cpu.set_r32(R::EAX, quo32);
-            }
-            Op::Enter => {
-                // Make Stack Frame for Procedure Parameters
-                // Create a stack frame with optional nested pointers for a procedure.
-                // XXX test this
-                let alloc_size = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                let mut nesting_level = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-
-                nesting_level &= 0x1F; // XXX "mod 32" says docs
-                let bp = self.cpu.get_r16(R::BP);
-                self.cpu.push16(&mut self.mmu, bp);
-                let frame_temp = self.cpu.get_r16(R::SP);
-
-                if nesting_level != 0 {
-                    for i in 0..nesting_level {
-                        let bp = self.cpu.get_r16(R::BP) - 2;
-                        self.cpu.set_r16(R::BP, bp);
-                        let val = self.mmu.read_u16(self.cpu.get_r16(R::SS), self.cpu.get_r16(R::BP));
-                        println!("XXX ENTER: pushing {} = {:04X}", i, val);
-                        self.cpu.push16(&mut self.mmu, val);
-                    }
-                    self.cpu.push16(&mut self.mmu, frame_temp);
-                }
-
-                self.cpu.set_r16(R::BP, frame_temp);
-                let sp = self.cpu.get_r16(R::SP) - alloc_size;
-                self.cpu.set_r16(R::SP, sp);
-            }
-            Op::Hlt => {
-                // println!("XXX impl {}", op);
-                // self.fatal_error = true;
-            }
-            Op::Idiv8 => {
-                let ax = self.cpu.get_r16(R::AX) as i16; // dividend
-                let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as i8;
-                if op1 == 0 {
-                    return self.cpu.exception(&Exception::DIV0, 0);
-                }
-                let rem = (ax % i16::from(op1)) as i8;
-                let quo = ax / i16::from(op1);
-                let quo8s = (quo & 0xFF) as i8;
-                if quo != i16::from(quo8s) {
-                    return self.cpu.exception(&Exception::DIV0, 0);
-                }
-                self.cpu.set_r8(R::AL, quo as u8);
-                self.cpu.set_r8(R::AH, rem as u8);
-                // The CF, OF, SF, ZF, AF, and PF flags are undefined.
-            }
-            Op::Idiv16 => {
-                let dividend = ((u32::from(self.cpu.get_r16(R::DX)) << 16) | u32::from(self.cpu.get_r16(R::AX))) as i32; // DX:AX
-                let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as i16;
-                if op1 == 0 {
-                    return self.cpu.exception(&Exception::DIV0, 0);
-                }
-                let quo = dividend / i32::from(op1);
-                let rem = (dividend % i32::from(op1)) as i16;
-                let quo16s = quo as i16;
-	            if quo != i32::from(quo16s) {
-                    return self.cpu.exception(&Exception::DIV0, 0);
-                }
-                self.cpu.set_r16(R::AX, quo16s as u16);
-                self.cpu.set_r16(R::DX, rem as u16);
-                // The CF, OF, SF, ZF, AF, and PF flags are undefined.
-            }
-            Op::Idiv32 => {
-                let dividend = ((u64::from(self.cpu.get_r32(R::EDX)) << 32) | u64::from(self.cpu.get_r32(R::EAX))) as i64; // EDX:EAX
-                let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as i32;
-                if op1 == 0 {
-                    return self.cpu.exception(&Exception::DIV0, 0);
-                }
-                let quo = dividend / i64::from(op1);
-                let rem = (dividend % i64::from(op1)) as i32;
-                let quo32s = quo as i32;
-	            if quo != i64::from(quo32s) {
-                    return self.cpu.exception(&Exception::DIV0, 0);
-                }
-                self.cpu.set_r32(R::EAX, quo32s as u32);
-                self.cpu.set_r32(R::EDX, rem as u32);
-                // The CF, OF, SF, ZF, AF, and PF flags are undefined.
-            }
-            Op::Imul8 => {
-                // NOTE: only 1-parameter imul8 instruction exists
-                // IMUL r/m8               : AX← AL ∗ r/m byte.
-
-                let f1 = self.cpu.get_r8(R::AL) as i8;
-                let f2 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as i8;
-                let ax = (i16::from(f1) * i16::from(f2)) as u16; // product
-                self.cpu.set_r16(R::AX, ax);
-
-                if (ax & 0xFF80) == 0xFF80 || (ax & 0xFF80) == 0x0000 {
-                    self.cpu.regs.flags.carry = false;
-                    self.cpu.regs.flags.overflow = false;
-                } else {
-                    self.cpu.regs.flags.carry = true;
-                    self.cpu.regs.flags.overflow = true;
-                }
-            }
-            Op::Imul16 => {
-                match op.params.count() {
-                    1 => {
-                        // IMUL r/m16               : DX:AX ← AX ∗ r/m word.
-                        let a = self.cpu.get_r16(R::AX);
-                        let b = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as i16;
-                        let temps = (a as i16) as isize * b as isize;
-                        self.cpu.set_r16(R::AX, temps as u16);
-                        self.cpu.set_r16(R::DX, (temps >> 16) as u16);
-
-                        let tempi = temps as u32;
-                        if (tempi & 0xFFFF_8000) == 0xFFFF_8000 || (tempi & 0xFFFF_8000) == 0x0000_0000 {
-                            self.cpu.regs.flags.carry = false;
-                            self.cpu.regs.flags.overflow = false;
-                        } else {
-                            self.cpu.regs.flags.carry = true;
-                            self.cpu.regs.flags.overflow = true;
-                        }
-                    }
-                    2 => {
-                        // IMUL r16, r/m16          : word register ← word register ∗ r/m16.
-                        let a = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                        let b = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as i16;
-                        let temps = (a as i16) as isize * b as isize;
-                        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, temps as u16);
-
-                        let tempi = temps as u32;
-                        if (tempi & 0xFFFF_8000) == 0xFFFF_8000 || (tempi & 0xFFFF_8000) == 0x0000_0000 {
-                            self.cpu.regs.flags.carry = false;
-                            self.cpu.regs.flags.overflow = false;
-                        } else {
-                            self.cpu.regs.flags.carry = true;
-                            self.cpu.regs.flags.overflow = true;
-                        }
-                    }
-                    3 => {
-                        // IMUL r16, r/m16, imm8    : word register ← r/m16 ∗ sign-extended immediate byte.
-                        // IMUL r16, r/m16, imm16   : word register ← r/m16 ∗ immediate word.
-                        let a = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                        let b = self.cpu.read_parameter_value(&self.mmu, &op.params.src2) as i16;
-                        let temps = (a as i16) as isize * b as isize;
-                        self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, temps as u16);
-
-                        let tempi = temps as u32;
-                        if (tempi & 0xFFFF_8000) == 0xFFFF_8000 || (tempi & 0xFFFF_8000) == 0x0000_0000 {
-                            self.cpu.regs.flags.carry = false;
-                            self.cpu.regs.flags.overflow = false;
-                        } else {
-                            self.cpu.regs.flags.carry = true;
-                            self.cpu.regs.flags.overflow = true;
-                        }
-                    }
-                    _ => unreachable!(),
-                }
-            }
-            Op::Imul32 => {
-                let tmp: isize;
-                match op.params.count() {
-                    1 => {
-                        // IMUL r/m32               : EDX:EAX ← EAX ∗ r/m32.
-                        let a = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as i32;
-                        tmp = (self.cpu.get_r32(R::EAX) as i32) as isize * a as isize;
-                        self.cpu.set_r32(R::EAX, tmp as u32);
-                        self.cpu.set_r32(R::EDX, (tmp >> 32) as u32);
-                    }
-                    2 => {
-                        // IMUL r32, r/m32          : doubleword register ← doubleword register ∗ r/m32.
-                        let a = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                        let b = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                        tmp = a as isize * b as isize;
-                        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, tmp as u32);
-                    }
-                    3 => {
-                        // IMUL r32, r/m32, imm8     : doubleword register ← r/m32 ∗ sign- extended immediate byte.
-                        // IMUL r32, r/m32, imm32    : doubleword register ← r/m32 ∗ immediate doubleword.
-                        let a = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                        let b = self.cpu.read_parameter_value(&self.mmu, &op.params.src2);
-                        tmp = b as isize * a as isize;
-                        self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, tmp as u32);
-                    }
-                    _ => unreachable!(),
-                }
-                if tmp != (tmp as i32) as isize {
-                    self.cpu.regs.flags.carry = true;
-                    self.cpu.regs.flags.overflow = true;
-                } else {
-                    self.cpu.regs.flags.carry = false;
-                    self.cpu.regs.flags.overflow = false;
-                }
-            }
-            Op::In8 => {
-                // two parameters (dst=AL)
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let data = self.in_u8(src as u16);
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, data);
-            }
-            Op::In16 => {
-                // two parameters (dst=AX)
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let data = self.in_u16(src as u16);
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, data);
-            }
-            Op::Inc8 => {
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let src = 1;
-                let res = dst.wrapping_add(src);
-
-                // The OF, SF, ZF, AF, and PF flags are set according to the result.
-                self.cpu.regs.flags.set_overflow_add_u8(res, src, dst);
-                self.cpu.regs.flags.set_sign_u8(res);
-                self.cpu.regs.flags.set_zero_u8(res);
-                self.cpu.regs.flags.set_adjust(res, src, dst);
-                self.cpu.regs.flags.set_parity(res);
-
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
-            }
-            Op::Inc16 => {
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let src = 1;
-                let res = dst.wrapping_add(src);
-
-                // The OF, SF, ZF, AF, and PF flags are set according to the result.
-                self.cpu.regs.flags.set_overflow_add_u16(res, src, dst);
-                self.cpu.regs.flags.set_sign_u16(res);
-                self.cpu.regs.flags.set_zero_u16(res);
-                self.cpu.regs.flags.set_adjust(res, src, dst);
-                self.cpu.regs.flags.set_parity(res);
-
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
-            }
-            Op::Inc32 => {
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let src = 1;
-                let res = dst.wrapping_add(src);
-
-                // The OF, SF, ZF, AF, and PF flags are set according to the result.
-                self.cpu.regs.flags.set_overflow_add_u32(res, src, dst);
-                self.cpu.regs.flags.set_sign_u32(res);
-                self.cpu.regs.flags.set_zero_u32(res);
-                self.cpu.regs.flags.set_adjust(res, src, dst);
-                self.cpu.regs.flags.set_parity(res);
-
-                self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u32);
-            }
-            Op::Insb => {
-                // Input byte from I/O port specified in DX into memory location specified in ES:DI.
-                // The ES segment cannot be overridden with a segment override prefix.
-                let dx = self.cpu.get_r16(R::DX);
-                let data = self.in_u8(dx);
-                self.mmu.write_u8(self.cpu.get_r16(R::ES), self.cpu.get_r16(R::DI), data);
-                let di = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::DI).wrapping_add(1)
-                } else {
-                    self.cpu.get_r16(R::DI).wrapping_sub(1)
-                };
-                self.cpu.set_r16(R::DI, di);
-            }
-            Op::Int => {
-                let int = self.cpu.read_parameter_imm(&op.params.dst);
-                self.cpu.execute_interrupt(&mut self.mmu, int as u8);
-            }
-            Op::Ja => {
-                if !self.cpu.regs.flags.carry & !self.cpu.regs.flags.zero {
-                    self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                }
-            }
-            Op::Jc => {
-                if self.cpu.regs.flags.carry {
-                    self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                }
-            }
-            Op::Jcxz => {
-                if self.cpu.get_r16(R::CX) == 0 {
-                    self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                }
-            }
-            Op::Jg => {
-                if !self.cpu.regs.flags.zero & self.cpu.regs.flags.sign == self.cpu.regs.flags.overflow {
-                    self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                }
-            }
-            Op::Jl => {
-                if self.cpu.regs.flags.sign != self.cpu.regs.flags.overflow {
-                    self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                }
-            }
-            Op::JmpFar => {
-                let (seg, offs) = match op.params.dst {
-                    Parameter::Ptr16(seg, imm) =>
-                        (self.cpu.segment(seg), imm),
-                    Parameter::Ptr16Imm(seg, imm) =>
-                        (seg, imm),
-                    Parameter::Ptr16Amode(seg, ref amode) =>
-                        (self.cpu.segment(seg), self.cpu.amode(amode) as u16),
-                    Parameter::Ptr16AmodeS8(seg, ref amode, imm) =>
-                        (self.cpu.segment(seg), (self.cpu.amode(amode) as isize + imm as isize) as u16),
-                    _ => panic!("[{}] JmpFar unhandled type {:?}",  self.cpu.get_memory_address(), op.params.dst),
-                };
-                self.cpu.set_r16(R::CS, seg);
-                self.cpu.regs.ip = offs;
-            }
-            Op::JmpNear | Op::JmpShort => {
-                self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-            }
-            Op::Jna => {
-                if self.cpu.regs.flags.carry | self.cpu.regs.flags.zero {
-                    self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                }
-            }
-            Op::Jnc => {
-                if !self.cpu.regs.flags.carry {
-                    self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                }
-            }
-            Op::Jng => {
-                if self.cpu.regs.flags.zero | self.cpu.regs.flags.sign != self.cpu.regs.flags.overflow {
-                    self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                }
-            }
-            Op::Jnl => {
-                if self.cpu.regs.flags.sign == self.cpu.regs.flags.overflow {
-                    self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                }
-            }
-            Op::Jno => {
-                if !self.cpu.regs.flags.overflow {
-                    self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                }
-            }
-            Op::Jns => {
-                if !self.cpu.regs.flags.sign {
-                    self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                }
-            }
-            Op::Jnz => {
-                if !self.cpu.regs.flags.zero {
-                    self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                }
-            }
-            Op::Jo => {
-                if self.cpu.regs.flags.overflow {
-                    self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                }
-            }
-            Op::Jpe => {
-                if self.cpu.regs.flags.parity {
-                    self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                }
-            }
-            Op::Jpo => {
-                 if !self.cpu.regs.flags.parity {
-                    self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                }
-            }
-            Op::Js => {
-                if self.cpu.regs.flags.sign {
-                    self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                }
-            }
-            Op::Jz => {
-                if self.cpu.regs.flags.zero {
-                    self.cpu.regs.ip = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                }
-            }
-            Op::Lahf => {
-                // Load: AH ← EFLAGS(SF:ZF:0:AF:0:PF:1:CF).
-                let mut val = 0 as u8;
-                if self.cpu.regs.flags.carry {
-                    val |= 1;
-                }
-                val |= 1 << 1;
-                if self.cpu.regs.flags.parity {
-                    val |= 1 << 2;
-                }
-                if self.cpu.regs.flags.adjust {
-                    val |= 1 << 4;
-                }
-                if self.cpu.regs.flags.zero {
-                    val |= 1 << 6;
-                }
-                if self.cpu.regs.flags.sign {
-                    val |= 1 << 7;
-                }
-                self.cpu.set_r8(R::AH, val);
-            }
-            Op::Lds => {
-                let (segment, offset) = self.cpu.read_segment_selector(&self.mmu, &op.params.src);
-                self.cpu.set_r16(R::DS, segment);
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, offset);
-            }
-            Op::Lea16 => {
-                let src = self.cpu.read_parameter_address(&op.params.src) as u16;
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, src);
-            }
-            Op::Leave => {
-                // High Level Procedure Exit
-                // Set SP to BP, then pop BP.
-                // XXX test this
-                let bp = self.cpu.get_r16(R::BP);
-                self.cpu.set_r16(R::SP, bp);
-                let bp = self.cpu.pop16(&mut self.mmu);
-                self.cpu.set_r16(R::BP, bp);
-            }
-            Op::Les => {
-                let (segment, offset) = self.cpu.read_segment_selector(&self.mmu, &op.params.src);
-                self.cpu.set_r16(R::ES, segment);
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, offset);
-            }
-            Op::Lodsb => {
-                // no arguments
-                // The DS segment may be over-ridden with a segment override prefix.
-                let val = self.mmu.read_u8(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI));
-
-                self.cpu.set_r8(R::AL, val);
-                let si = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::SI).wrapping_add(1)
-                } else {
-                    self.cpu.get_r16(R::SI).wrapping_sub(1)
-                };
-                self.cpu.set_r16(R::SI, si);
-            }
-            Op::Lodsw => {
-                // no arguments
-                // The DS segment may be over-ridden with a segment override prefix.
-                let val = self.mmu.read_u16(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI));
-
-                self.cpu.set_r16(R::AX, val);
-                let si = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::SI).wrapping_add(2)
-                } else {
-                    self.cpu.get_r16(R::SI).wrapping_sub(2)
-                };
-                self.cpu.set_r16(R::SI, si);
-            }
-            Op::Lodsd => {
-                // no arguments
-                // The DS segment may be over-ridden with a segment override prefix.
-                let val = self.mmu.read_u32(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI));
-
-                self.cpu.set_r32(R::EAX, val);
-                let si = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::SI).wrapping_add(4)
-                } else {
-                    self.cpu.get_r16(R::SI).wrapping_sub(4)
-                };
-                self.cpu.set_r16(R::SI, si);
-            }
-            Op::Loop => {
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                let cx = self.cpu.get_r16(R::CX).wrapping_sub(1);
-                self.cpu.set_r16(R::CX, cx);
-                if cx != 0 {
-                    self.cpu.regs.ip = dst;
-                }
-            }
-            Op::Loope => {
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                let cx = self.cpu.get_r16(R::CX).wrapping_sub(1);
-                self.cpu.set_r16(R::CX, cx);
-                if cx != 0 && self.cpu.regs.flags.zero {
-                    self.cpu.regs.ip = dst;
-                }
-            }
-            Op::Loopne => {
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                let cx = self.cpu.get_r16(R::CX).wrapping_sub(1);
-                self.cpu.set_r16(R::CX, cx);
-                if cx != 0 && !self.cpu.regs.flags.zero {
-                    self.cpu.regs.ip = dst;
-                }
-            }
-            Op::Mov8 => {
-                // two arguments (dst=reg)
-                let data = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u8;
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, data);
-            }
-            Op::Mov16 => {
-                // two arguments (dst=reg)
-                let data = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u16;
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, data);
-            }
-            Op::Mov32 => {
-                // two arguments (dst=reg)
-                let data = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
-                self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, data);
-            }
-            Op::Movsb => {
-                // move byte from address DS:(E)SI to ES:(E)DI.
-                // The DS segment may be overridden with a segment override prefix, but the ES segment cannot be overridden.
-                let val = self.mmu.read_u8(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI));
-                let si = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::SI).wrapping_add(1)
-                } else {
-                    self.cpu.get_r16(R::SI).wrapping_sub(1)
-                };
-                self.cpu.set_r16(R::SI, si);
-                let es = self.cpu.get_r16(R::ES);
-                let di = self.cpu.get_r16(R::DI);
-                self.mmu.write_u8(es, di, val);
-                let di = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::DI).wrapping_add(1)
-                } else {
-                    self.cpu.get_r16(R::DI).wrapping_sub(1)
-                };
-                self.cpu.set_r16(R::DI, di);
-            }
-            Op::Movsw => {
-                // move word from address DS:(E)SI to ES:(E)DI.
-                // The DS segment may be overridden with a segment override prefix, but the ES segment cannot be overridden.
-                let val = self.mmu.read_u16(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI));
-                let si = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::SI).wrapping_add(2)
-                } else {
-                    self.cpu.get_r16(R::SI).wrapping_sub(2)
-                };
-                self.cpu.set_r16(R::SI, si);
-                let es = self.cpu.get_r16(R::ES);
-                let di = self.cpu.get_r16(R::DI);
-                self.mmu.write_u16(es, di, val);
-                let di = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::DI).wrapping_add(2)
-                } else {
-                    self.cpu.get_r16(R::DI).wrapping_sub(2)
-                };
-                self.cpu.set_r16(R::DI, di);
-            }
-            Op::Movsd => {
-                // move dword from address DS:(E)SI to ES:(E)DI
-                // The DS segment may be overridden with a segment override prefix, but the ES segment cannot be overridden.
-                let val = self.mmu.read_u32(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI));
-                let si = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::SI).wrapping_add(4)
-                } else {
-                    self.cpu.get_r16(R::SI).wrapping_sub(4)
-                };
-                self.cpu.set_r16(R::SI, si);
-                let es = self.cpu.get_r16(R::ES);
-                let di = self.cpu.get_r16(R::DI);
-                self.mmu.write_u32(es, di, val);
-                let di = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::DI).wrapping_add(4)
-                } else {
-                    self.cpu.get_r16(R::DI).wrapping_sub(4)
-                };
-                self.cpu.set_r16(R::DI, di);
-            }
-            Op::Movsx16 => {
-                // 80386+
-                // moves a signed value into a register and sign-extends it with 1.
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u8;
-
-                let mut data = u16::from(src);
-                if src & 0x80 != 0 {
-                    data += 0xFF00;
-                }
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, data);
-            }
-            Op::Movsx32 => {
-                // 80386+
-                // moves a signed value into a register and sign-extends it with 1.
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u16;
-
-                let mut data = u32::from(src);
-                match op.params.src {
-                    Parameter::Reg8(_) | Parameter::Ptr8AmodeS8(_, _, _) | Parameter::Ptr8AmodeS16(_, _, _) =>
-                    if src & 0x80 != 0 {
-                        data += 0xFFFF_FF00;
-                    }
-                    Parameter::Reg16(_) => if src & 0x8000 != 0 {
-                        data += 0xFFFF_0000;
-                    }
-                    _ => panic!("unexpected Movsx32 src arg {:?}", op.params.src),
-                }
-                self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, data);
-            }
-            Op::Movzx16 => {
-                // 80386+
-                // moves an unsigned value into a register and zero-extends it.
-                let val = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u16;
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, val);
-            }
-            Op::Movzx32 => {
-                // 80386+
-                // moves an unsigned value into a register and zero-extends it.
-                let val = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
-                self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, val);
-            }
-            Op::Mul8 => {
-                // Unsigned multiply (AX ← AL ∗ r/m8).
-                let al = self.cpu.get_r8(R::AL) as usize;
-                let arg1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let ax = al.wrapping_mul(arg1) as u16;
-                self.cpu.set_r16(R::AX, ax);
-                // The OF and CF flags are set to 0 if the upper half of the
-                // result is 0; otherwise, they are set to 1.
-                // The SF, ZF, AF, and PF flags are undefined.
-                if ax & 0xFF00 != 0 {
-                    self.cpu.regs.flags.carry = true;
-                    self.cpu.regs.flags.overflow = true;
-                } else {
-                    self.cpu.regs.flags.carry = false;
-                    self.cpu.regs.flags.overflow = false;
-                }
-            }
-            Op::Mul16 => {
-                // Unsigned multiply (DX:AX ← AX ∗ r/m16).
-                let src = self.cpu.get_r16(R::AX) as usize;
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let res = dst.wrapping_mul(src);
-
-                self.cpu.set_r16(R::AX, res as u16);
-                let dx = (res >> 16) as u16;
-                self.cpu.set_r16(R::DX, dx);
-
-                self.cpu.regs.flags.carry = dx != 0;
-                self.cpu.regs.flags.overflow = dx != 0;
-            }
-            Op::Mul32 => {
-                // Unsigned multiply (EDX:EAX ← EAX ∗ r/m32)
-                let src = self.cpu.get_r32(R::EAX) as usize;
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let res = dst.wrapping_mul(src);
-
-                self.cpu.set_r32(R::EAX, res as u32);
-                let edx = (res >> 32) as u32;
-                self.cpu.set_r32(R::EDX, edx);
-
-                self.cpu.regs.flags.carry = edx != 0;
-                self.cpu.regs.flags.overflow = edx != 0;
-            }
-            Op::Neg8 => {
-                // Two's Complement Negation
-                // one argument
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let src: u8 = 0;
-                let res = src.wrapping_sub(dst as u8) as usize;
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
-
-                self.cpu.regs.flags.carry = dst != 0;
-                // The OF, SF, ZF, AF, and PF flags are set according to the result.
-                self.cpu.regs.flags.overflow = res == 0x80;
-                self.cpu.regs.flags.set_sign_u8(res);
-                self.cpu.regs.flags.set_zero_u8(res);
-                self.cpu.regs.flags.set_adjust(res, src as usize, dst);
-                self.cpu.regs.flags.set_parity(res);
-            }
-            Op::Neg16 => {
-                // one argument
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let src: u16 = 0;
-                let res = src.wrapping_sub(dst as u16) as usize;
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
-
-                self.cpu.regs.flags.carry = dst != 0;
-                // The OF, SF, ZF, AF, and PF flags are set according to the result.
-                self.cpu.regs.flags.overflow = res == 0x8000;
-                self.cpu.regs.flags.set_sign_u16(res);
-                self.cpu.regs.flags.set_zero_u16(res);
-                self.cpu.regs.flags.set_adjust(res, src as usize, dst);
-                self.cpu.regs.flags.set_parity(res);
-            }
-            Op::Neg32 => {
-                // one argument
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let src: u32 = 0;
-                let res = src.wrapping_sub(dst as u32) as usize;
-                self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u32);
-
-                self.cpu.regs.flags.carry = dst != 0;
-                // The OF, SF, ZF, AF, and PF flags are set according to the result.
-                self.cpu.regs.flags.overflow = res == 0x8000_0000;
-                self.cpu.regs.flags.set_sign_u32(res);
-                self.cpu.regs.flags.set_zero_u32(res);
-                self.cpu.regs.flags.set_adjust(res, src as usize, dst);
-                self.cpu.regs.flags.set_parity(res);
-            }
-            Op::Nop => {}
-            Op::Not8 => {
-                // one arguments (dst)
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let res = !dst;
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, (res & 0xFF) as u8);
-                // Flags Affected: None
-            }
-            Op::Not16 => {
-                // one arguments (dst)
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let res = !dst;
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, (res & 0xFFFF) as u16);
-                // Flags Affected: None
-            }
-            Op::Or8 => {
-                // two arguments (dst=AL)
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let res = dst | src;
-                // The OF and CF flags are cleared; the SF, ZF, and PF flags
-                // are set according to the result.
-                self.cpu.regs.flags.overflow = false;
-                self.cpu.regs.flags.carry = false;
-                self.cpu.regs.flags.set_sign_u8(res);
-                self.cpu.regs.flags.set_zero_u8(res);
-                self.cpu.regs.flags.set_parity(res);
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, (res & 0xFF) as u8);
-            }
-            Op::Or16 => {
-                // two arguments (dst=AX)
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let res = dst | src;
-                // The OF and CF flags are cleared; the SF, ZF, and PF flags
-                // are set according to the result.
-                self.cpu.regs.flags.overflow = false;
-                self.cpu.regs.flags.carry = false;
-                self.cpu.regs.flags.set_sign_u16(res);
-                self.cpu.regs.flags.set_zero_u16(res);
-                self.cpu.regs.flags.set_parity(res);
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, (res & 0xFFFF) as u16);
-            }
-            Op::Out8 => {
-                // two arguments
-                let addr = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                let val = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u8;
-                self.out_u8(addr, val);
-            }
-            Op::Out16 => {
-                // two arguments
-                let addr = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                let val = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u16;
-                self.out_u16(addr, val);
-            }
-            Op::Outsb => {
-                // Output byte from memory location specified in DS:(E)SI or RSI to I/O port specified in DX.
-                // no arguments
-                let val = self.mmu.read_u8(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI));
-                let port = self.cpu.get_r16(R::DX);
-                self.out_u8(port, val);
-                let si = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::SI).wrapping_add(1)
-                } else {
-                    self.cpu.get_r16(R::SI).wrapping_sub(1)
-                };
-                self.cpu.set_r16(R::SI, si);
-            }
-            Op::Outsw => {
-                // Output word from memory location specified in DS:(E)SI or RSI to I/O port specified in DX**.
-                // no arguments
-                let val = self.mmu.read_u16(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::SI));
-                let port = self.cpu.get_r16(R::DX);
-                self.out_u16(port, val);
-                let si = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::SI).wrapping_add(2)
-                } else {
-                    self.cpu.get_r16(R::SI).wrapping_sub(2)
-                };
-                self.cpu.set_r16(R::SI, si);
-            }
-            Op::Pop16 => {
-                // one arguments (dst)
-                let data = self.cpu.pop16(&mut self.mmu);
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, data);
-            }
-            Op::Pop32 => {
-                // one arguments (dst)
-                let data = self.cpu.pop32(&mut self.mmu);
-                self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, data);
-            }
-            Op::Popa16 => {
-                let di = self.cpu.pop16(&mut self.mmu);
-                self.cpu.set_r16(R::DI, di);
-                let si = self.cpu.pop16(&mut self.mmu);
-                self.cpu.set_r16(R::SI, si);
-                let bp = self.cpu.pop16(&mut self.mmu);
-                self.cpu.set_r16(R::BP, bp);
-                let sp = self.cpu.get_r16(R::SP) + 2; // skip next word of stack
-                self.cpu.set_r16(R::SP, sp);
-                let bx = self.cpu.pop16(&mut self.mmu);
-                self.cpu.set_r16(R::BX, bx);
-                let dx = self.cpu.pop16(&mut self.mmu);
-                self.cpu.set_r16(R::DX, dx);
-                let cx = self.cpu.pop16(&mut self.mmu);
-                self.cpu.set_r16(R::CX, cx);
-                let ax = self.cpu.pop16(&mut self.mmu);
-                self.cpu.set_r16(R::AX, ax);
-            }
-            Op::Popad32 => {
-                let edi = self.cpu.pop32(&mut self.mmu);
-                self.cpu.set_r32(R::EDI, edi);
-                let esi = self.cpu.pop32(&mut self.mmu);
-                self.cpu.set_r32(R::ESI, esi);
-                let ebp = self.cpu.pop32(&mut self.mmu);
-                self.cpu.set_r32(R::EBP, ebp);
-                let esp = self.cpu.get_r32(R::ESP) + 4; // skip next dword of stack
-                self.cpu.set_r32(R::ESP, esp);
-                let ebx = self.cpu.pop32(&mut self.mmu);
-                self.cpu.set_r32(R::EBX, ebx);
-                let edx = self.cpu.pop32(&mut self.mmu);
-                self.cpu.set_r32(R::EDX, edx);
-                let ecx = self.cpu.pop32(&mut self.mmu);
-                self.cpu.set_r32(R::ECX, ecx);
-                let eax = self.cpu.pop32(&mut self.mmu);
-                self.cpu.set_r32(R::EAX, eax);
-            }
-            Op::Popf => {
-                let data = self.cpu.pop16(&mut self.mmu);
-                self.cpu.regs.flags.set_u16(data);
-            }
-            Op::Push16 => {
-                // single parameter (dst)
-                let data = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                self.cpu.push16(&mut self.mmu, data);
-            }
-            Op::Push32 => {
-                // single parameter (dst)
-                let data = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
-                self.cpu.push32(&mut self.mmu, data);
-            }
-            Op::Pusha16 => {
-                let ax = self.cpu.get_r16(R::AX);
-                let cx = self.cpu.get_r16(R::CX);
-                let dx = self.cpu.get_r16(R::DX);
-                let bx = self.cpu.get_r16(R::BX);
-                let sp = self.cpu.get_r16(R::SP);
-                let bp = self.cpu.get_r16(R::BP);
-                let si = self.cpu.get_r16(R::SI);
-                let di = self.cpu.get_r16(R::DI);
-                self.cpu.push16(&mut self.mmu, ax);
-                self.cpu.push16(&mut self.mmu, cx);
-                self.cpu.push16(&mut self.mmu, dx);
-                self.cpu.push16(&mut self.mmu, bx);
-                self.cpu.push16(&mut self.mmu, sp);
-                self.cpu.push16(&mut self.mmu, bp);
-                self.cpu.push16(&mut self.mmu, si);
-                self.cpu.push16(&mut self.mmu, di);
-            }
-            Op::Pushad32 => {
-                let eax = self.cpu.get_r32(R::EAX);
-                let ecx = self.cpu.get_r32(R::ECX);
-                let edx = self.cpu.get_r32(R::EDX);
-                let ebx = self.cpu.get_r32(R::EBX);
-                let esp = self.cpu.get_r32(R::ESP);
-                let ebp = self.cpu.get_r32(R::EBP);
-                let esi = self.cpu.get_r32(R::ESI);
-                let edi = self.cpu.get_r32(R::EDI);
-                self.cpu.push32(&mut self.mmu, eax);
-                self.cpu.push32(&mut self.mmu, ecx);
-                self.cpu.push32(&mut self.mmu, edx);
-                self.cpu.push32(&mut self.mmu, ebx);
-                self.cpu.push32(&mut self.mmu, esp);
-                self.cpu.push32(&mut self.mmu, ebp);
-                self.cpu.push32(&mut self.mmu, esi);
-                self.cpu.push32(&mut self.mmu, edi);
-            }
-            Op::Pushf => {
-                let data = self.cpu.regs.flags.u16();
-                self.cpu.push16(&mut self.mmu, data);
-            }
-            Op::Rcl8 => {
-                // Rotate 9 bits (CF, r/m8) left imm8 times.
-                // two arguments
-                let count = (self.cpu.read_parameter_value(&self.mmu, &op.params.src) & 0x1F) % 9;
-                if count > 0 {
-                    let cf = self.cpu.regs.flags.carry_val() as u16;
-                    let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                    let res = if count == 1 {
-                        ((op1 << 1) | cf)
-                    } else {
-                        ((op1 << count) | (cf << (count - 1)) | (op1 >> (9 - count)))
-                    } as u8;
-                    self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res);
-                    let cf = (op1 >> (8 - count)) & 1;
-                    self.cpu.regs.flags.carry = cf != 0;
-                    // For left rotates, the OF flag is set to the exclusive OR of the CF bit
-                    // (after the rotate) and the most-significant bit of the result.
-                    self.cpu.regs.flags.overflow = cf ^ (u16::from(res) >> 7) != 0;
-                }
-            }
-            Op::Rcl16 => {
-                // Rotate 9 bits (CF, r/m8) left imm8 times.
-                // two arguments
-                let count = (self.cpu.read_parameter_value(&self.mmu, &op.params.src) & 0x1F) % 17;
-                if count > 0 {
-                    let cf = self.cpu.regs.flags.carry_val() as u16;
-                    let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                    let res = if count == 1 {
-                        (op1 << 1) | cf
-                    } else if count == 16 {
-                        (cf << 15) | (op1 >> 1)
-                    } else {
-                        (op1 << count) | (cf << (count - 1)) | (op1 >> (17 - count))
-                    };
-                    self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
-                    self.cpu.regs.flags.carry = (op1 >> (16 - count)) & 1 != 0;
-                    self.cpu.regs.flags.overflow = self.cpu.regs.flags.carry_val() as u16 ^ (op1 >> 15) != 0;
-                }
-            }
-            Op::Rcr8 => {
-                // two arguments
-                let count = ((self.cpu.read_parameter_value(&self.mmu, &op.params.src) & 0x1F) % 9) as u16;
-                if count != 0 {
-                    let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                    let cf = self.cpu.regs.flags.carry_val() as u16;
-
-                    let res = ((op1 >> count) | (cf << (8 - count)) | (op1 << (9 - count))) as u8;
-                    self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res);
-
-                    // NOTE: overflow is identical to bochs and dosbox, but differs in WinXP vm.
-                    let of = ((res ^ (res << 1)) & 0x80) >> 7;
-                    self.cpu.regs.flags.carry = (op1 >> (count - 1)) & 0x1 != 0;
-                    self.cpu.regs.flags.overflow = of != 0;
-                }
-            }
-            Op::Rcr16 => {
-                // two arguments
-                let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let count = (self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32 & 0x1F) % 17;
-                if count > 0 {
-                    let cf = self.cpu.regs.flags.carry_val();
-                    let res = (op1 >> count) | (cf << (16 - count)) | (op1 << (17 - count));
-                    self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
-                    self.cpu.regs.flags.carry = (op1 >> (count - 1)) & 1 != 0;
-                    let bit15 = (res >> 15) & 1;
-                    let bit14 = (res >> 14) & 1;
-                    self.cpu.regs.flags.overflow = bit15 ^ bit14 != 0;
-                }
-            }
-            Op::Rcr32 => {
-                // two arguments
-                let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32 & 0x1F;
-                if count > 0 {
-                    let cf = self.cpu.regs.flags.carry_val();
-                    let res = if count==1 {
-                        op1 >> 1 | cf << 31
-                    } else {
-                         (op1 >> count) | (cf << (32-count)) | (op1 << (33-count))
-                    };
-                    self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u32);
-                    self.cpu.regs.flags.carry = (op1 >> (count - 1)) & 1 != 0;
-                    self.cpu.regs.flags.overflow = (res ^ (res << 1)) & 0x8000_0000 != 0;
-                }
-            }
-            Op::Iret => {
-                self.cpu.regs.ip = self.cpu.pop16(&mut self.mmu);
-                let cs = self.cpu.pop16(&mut self.mmu);
-                self.cpu.set_r16(R::CS, cs);
-                let flags = self.cpu.pop16(&mut self.mmu);
-                self.cpu.regs.flags.set_u16(flags);
-                self.mmu.flags_address = MemoryAddress::Unset;
-            }
-            Op::Retf => {
-                if op.params.count() == 1 {
-                    // 1 argument: pop imm16 bytes from stack
-                    let imm16 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                    let sp = self.cpu.get_r16(R::SP) + imm16;
-                    self.cpu.set_r16(R::SP, sp);
-                }
-                self.cpu.regs.ip = self.cpu.pop16(&mut self.mmu);
-                let cs = self.cpu.pop16(&mut self.mmu);
-                self.cpu.set_r16(R::CS, cs);
-            }
-            Op::Retn => {
-                let val = self.cpu.pop16(&mut self.mmu);
-                if DEBUG_MARK_STACK && val == STACK_MARKER {
-                    println!("[{}] WARNING: stack marker was popped after {} instr. execution ended. (can be valid where small app just return to DOS with a 'ret', but can also indicate memory corruption)",
-                        self.cpu.get_memory_address(), self.cpu.instruction_count);
-                    self.cpu.fatal_error = true;
-                }
-                // println!("Retn, ip from {:04X} to {:04X}", self.cpu.regs.ip, val);
-                self.cpu.regs.ip = val;
-                if op.params.count() == 1 {
-                    // 1 argument: pop imm16 bytes from stack
-                    let imm16 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                    let sp = self.cpu.get_r16(R::SP).wrapping_add(imm16);
-                    self.cpu.set_r16(R::SP, sp);
-                }
-            }
-            Op::Rol8 => {
-                // Rotate 8 bits of 'dst' left for 'src' times.
-                // two arguments: op1, count
-                let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u8;
-                let mut count = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                if count & 0b0_0111 == 0 {
-                    if count & 0b1_1000 != 0 {
-                        let bit0 = op1 & 1;
-                        let bit7 = op1 >> 7;
-                        self.cpu.regs.flags.overflow = bit0 ^ bit7 != 0;
-                        self.cpu.regs.flags.carry = bit0 != 0;
-                    }
-                    // no-op if count is 0
-                    return;
-                }
-                count &= 0x7;
-                let res = (op1 << count) | (op1 >> (8 - count));
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res);
-                let bit0 = res & 1;
-                let bit7 = res >> 7;
-                self.cpu.regs.flags.overflow = bit0 ^ bit7 != 0;
-                self.cpu.regs.flags.carry = bit0 != 0;
-            }
-            Op::Rol16 => {
-                // Rotate 16 bits of 'dst' left for 'src' times.
-                // two arguments
-                let mut res = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) & 0x1F;
-                res = res.rotate_left(count as u32);
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res);
-                let bit0 = res & 1;
-                let bit15 = (res >> 15) & 1;
-                if count == 1 {
-                    self.cpu.regs.flags.overflow = bit0 ^ bit15 != 0;
-                }
-                self.cpu.regs.flags.carry = bit0 != 0;
-            }
-            Op::Ror8 => {
-                // Rotate 8 bits of 'dst' right for 'src' times.
-                // two arguments
-                let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u8;
-                let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) & 0x1F;
-
-                if count & 0b0_0111 == 0 {
-                    if count & 0b1_1000 != 0 {
-                        let bit6 = (op1 >> 6) & 1;
-                        let bit7 = op1 >> 7;
-                        self.cpu.regs.flags.overflow = bit6 ^ bit7 != 0;
-                        self.cpu.regs.flags.carry = bit7 != 0;
-                    }
-                    return;
-                }
-
-                let res = op1.rotate_right(count as u32);
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res);
-                let bit6 = (res >> 6) & 1;
-                let bit7 = res >> 7;
-                self.cpu.regs.flags.overflow = bit6 ^ bit7 != 0;
-                self.cpu.regs.flags.carry = bit7 != 0;
-            }
-            Op::Ror16 => {
-                // Rotate 16 bits of 'dst' right for 'src' times.
-                // two arguments
-                let mut res = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-                let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) & 0x1F;
-                res = res.rotate_right(count as u32);
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res);
-                let bit14 = (res >> 14) & 1;
-                let bit15 = (res >> 15) & 1;
-                if count == 1 {
-                    self.cpu.regs.flags.overflow = bit14 ^ bit15 != 0;
-                }
-                self.cpu.regs.flags.carry = bit15 != 0;
-            }
-            Op::Ror32 => {
-                // Rotate 32 bits of 'dst' right for 'src' times.
-                // two arguments
-                let mut res = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
-                let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) & 0x1F;
-                res = res.rotate_right(count as u32);
-                self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res);
-                // XXX flags
-            }
-            Op::Sahf => {
-                // Loads the SF, ZF, AF, PF, and CF flags of the EFLAGS register with values
-                // from the corresponding bits in the AH register (bits 7, 6, 4, 2, and 0, respectively).
-                let ah = self.cpu.get_r8(R::AH);
-                self.cpu.regs.flags.carry = ah & 0x1 != 0; // bit 0
-                self.cpu.regs.flags.parity = ah & 0x4 != 0; // bit 2
-                self.cpu.regs.flags.adjust = ah & 0x10 != 0; // bit 4
-                self.cpu.regs.flags.zero = ah & 0x40 != 0; // bit 6
-                self.cpu.regs.flags.sign = ah & 0x80 != 0; // bit 7
-            }
-            Op::Salc => {
-                let al = if self.cpu.regs.flags.carry {
-                    0xFF
-                } else {
-                    0
-                };
-                self.cpu.set_r8(R::AL, al);
-            }
-            Op::Sar8 => {
-                // Signed divide r/m8 by 2, imm8 times.
-                // two arguments
-                let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u8;
-                let mut count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) & 0x1F;
-                if count > 0 {
-                    if count > 8 {
-                        count = 8;
-                    }
-
-                    let res = if op1 & 0x80 != 0 {
-                        ((op1 as usize) >> count) | (0xFF << (8 - count))
-                    } else {
-                        ((op1 as usize) >> count)
-                    };
-
-                    self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
-                    self.cpu.regs.flags.carry = (op1 as isize >> (count - 1)) & 0x1 != 0;
-                    self.cpu.regs.flags.overflow = false;
-                    self.cpu.regs.flags.set_sign_u8(res as usize);
-                    self.cpu.regs.flags.set_zero_u8(res as usize);
-                    self.cpu.regs.flags.set_parity(res as usize);
-                }
-            }
-            Op::Sar16 => {
-                // Signed divide r/m8 by 2, imm8 times.
-                // two arguments
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let mut count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) & 0x1F; // use 5 lsb
-                if count > 0 {
-                    if count > 16 {
-                        count = 16;
-                    }
-                    let res = if (dst & 0x8000) != 0 {
-                        (dst >> count) | (0xFFFF << (16 - count))
-                    } else {
-                        dst >> count
-                    };
-                    self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
-
-                    self.cpu.regs.flags.carry = (dst as u16 >> (count - 1)) & 0x1 != 0;
-                    self.cpu.regs.flags.overflow = false;
-                    self.cpu.regs.flags.set_sign_u16(res);
-                    self.cpu.regs.flags.set_zero_u16(res);
-                    self.cpu.regs.flags.set_parity(res);
-                }
-            }
-            Op::Sar32 => {
-                // Signed divide r/m8 by 2, imm8 times.
-                // two arguments
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) & 0x1F; // use 5 lsb
-                if count > 0 {
-                    let res = if (dst & 0x8000_0000) != 0 {
-                        (dst >> count) | (0xFFFF_FFFF << (32 - count))
-                    } else {
-                        dst >> count
-                    };
-
-                    self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u32);
-                    self.cpu.regs.flags.carry = (dst as u32 >> (count - 1)) & 0x1 != 0;
-                    self.cpu.regs.flags.overflow = false;
-                    self.cpu.regs.flags.set_sign_u32(res);
-                    self.cpu.regs.flags.set_zero_u32(res);
-                    self.cpu.regs.flags.set_parity(res);
-                }
-            }
-            Op::Sbb8 => {
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let cf = if self.cpu.regs.flags.carry { 1 } else { 0 };
-                let res = (Wrapping(dst) - (Wrapping(src) + Wrapping(cf))).0;
-
-                // The OF, SF, ZF, AF, PF, and CF flags are set according to the result.
-                self.cpu.regs.flags.set_overflow_sub_u8(res, src, dst);
-                self.cpu.regs.flags.set_sign_u8(res);
-                self.cpu.regs.flags.set_zero_u8(res);
-                self.cpu.regs.flags.set_adjust(res, src, dst);
-                self.cpu.regs.flags.set_parity(res);
-                self.cpu.regs.flags.set_carry_u8(res);
-
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
-            }
-            Op::Sbb16 => {
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let cf = if self.cpu.regs.flags.carry { 1 } else { 0 };
-                let res = (Wrapping(dst) - (Wrapping(src) + Wrapping(cf))).0;
-
-                // The OF, SF, ZF, AF, PF, and CF flags are set according to the result.
-                self.cpu.regs.flags.set_overflow_sub_u16(res, src, dst);
-                self.cpu.regs.flags.set_sign_u16(res);
-                self.cpu.regs.flags.set_zero_u16(res);
-                self.cpu.regs.flags.set_adjust(res, src, dst);
-                self.cpu.regs.flags.set_parity(res);
-                self.cpu.regs.flags.set_carry_u16(res);
-
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
-            }
-            Op::Scasb => {
-                // Compare AL with byte at ES:(E)DI then set status flags.
-                // ES cannot be overridden with a segment override prefix.
-                let src = self.cpu.get_r8(R::AL);
-                let dst = self.mmu.read_u8(self.cpu.get_r16(R::ES), self.cpu.get_r16(R::DI));
-                self.cpu.cmp8(dst as usize, src as usize);
-                let di = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::DI).wrapping_add(1)
-                } else {
-                    self.cpu.get_r16(R::DI).wrapping_sub(1)
-                };
-                self.cpu.set_r16(R::DI, di);
-            }
-            Op::Scasw => {
-                // Compare AX with word at ES:(E)DI or RDI then set status flags.
-                // ES cannot be overridden with a segment override prefix.
-                let src = self.cpu.get_r16(R::AX);
-                let dst = self.mmu.read_u16(self.cpu.get_r16(R::ES), self.cpu.get_r16(R::DI));
-                self.cpu.cmp16(dst as usize, src as usize);
-                let di = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::DI).wrapping_add(2)
-                } else {
-                    self.cpu.get_r16(R::DI).wrapping_sub(2)
-                };
-                self.cpu.set_r16(R::DI, di);
-            }
-            Op::Setc => {
-                let val = if self.cpu.regs.flags.carry {
-                    1
-                } else {
-                    0
-                };
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, val);
-            }
-            Op::Setnz => {
-                let val = if !self.cpu.regs.flags.zero {
-                    1
-                } else {
-                    0
-                };
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, val);
-            }
-            Op::Shl8 => {
-                // two arguments
-                let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) & 0x1F;
-                if count > 0 {
-                    let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u16;
-
-                    let mut of: u16 = 0;
-                    let mut cf: u16 = 0;
-                    let res = if count <= 8 {
-                        let v = op1 << count;
-                        cf = (op1 >> (8 - count)) & 0x1;
-                        // NOTE: overflow is identical to bochs and dosbox, but differs in WinXP vm.
-                        of = cf ^ (v >> 7);
-                        v
-                    } else {
-                        0
-                    };
-                    self.cpu.regs.flags.set_sign_u8(res as usize);
-                    self.cpu.regs.flags.set_zero_u8(res as usize);
-                    self.cpu.regs.flags.set_parity(res as usize);
-                    self.cpu.regs.flags.carry = cf != 0;
-                    self.cpu.regs.flags.overflow = of != 0;
-
-                    self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
-                }
-            }
-            Op::Shl16 => {
-                // two arguments
-                let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) & 0x1F; // use only 5 LSB
-                if count > 0 {
-                    let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
-
-                    let mut of: u16 = 0;
-                    let mut cf: u16 = 0;
-                    let res = if count <= 16 {
-                        let v = op1 << count;
-                        cf = ((op1 as u16) >> (16 - count)) & 0x1;
-                        of = cf ^ ((v as u16) >> 15);
-                        v
-                    } else {
-                        0
-                    };
-
-                    self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
-
-                    self.cpu.regs.flags.set_sign_u16(res as usize);
-                    self.cpu.regs.flags.set_zero_u16(res as usize);
-                    self.cpu.regs.flags.set_parity(res as usize);
-                    self.cpu.regs.flags.carry = cf != 0;
-                    self.cpu.regs.flags.overflow = (of & 1) != 0;
-                }
-            }
-            Op::Shl32 => {
-                // Multiply `dst` by 2, `src` times.
-                // two arguments    (alias: sal)
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) & 0x1F; // XXX
-                if count > 0 {
-                    let res = dst.wrapping_shl(count as u32);
-                    self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u32);
-                    self.cpu.regs.flags.carry = (res & 0x8000_0000) != 0;
-                    if count == 1 {
-                        self.cpu.regs.flags.overflow = self.cpu.regs.flags.carry_val() ^ ((res & 0x8000) >> 15) != 0; // XXX
-                    }
-                    self.cpu.regs.flags.set_sign_u32(res);
-                    self.cpu.regs.flags.set_zero_u32(res);
-                    self.cpu.regs.flags.set_parity(res);
-                }
-            }
-            Op::Shld => {
-                // 3 arguments
-                let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src2) & 0x1F; // use 5 lsb
-                if count > 0 {
-                    let op1 = self.cpu.read_parameter_value(&self.mmu, &op.params.dst) as u32;
-                    let op2 = self.cpu.read_parameter_value(&self.mmu, &op.params.src) as u32;
-
-                    // count < 32, since only lower 5 bits used
-                    let temp_32 = (op1 << 16) | op2; // double formed by op1:op2
-                    let mut result_32 = temp_32 << count;
-
-                    // hack to act like x86 SHLD when count > 16
-                    if count > 16 {
-                        // for Pentium processor, when count > 16, actually shifting op1:op2:op2 << count,
-                        // it is the same as shifting op2:op2 by count-16
-                        // For P6 and later (CPU_LEVEL >= 6), when count > 16, actually shifting op1:op2:op1 << count,
-                        // which is the same as shifting op2:op1 by count-16
-                        // The behavior is undefined so both ways are correct, we prefer P6 way of implementation
-                        result_32 |= op1 << (count - 16);
-                     }
-
-                    let res16 = (result_32 >> 16) as u16;
-                    self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res16);
-
-                    let cf = (temp_32 >> (32 - count)) & 0x1;
-                    self.cpu.regs.flags.carry = cf != 0;
-
-                    let of = cf ^ (u32::from(res16 >> 15));
-                    self.cpu.regs.flags.overflow = of != 0;
-
-                    self.cpu.regs.flags.set_zero_u16(res16 as usize);
-                    self.cpu.regs.flags.set_sign_u16(res16 as usize);
-                    self.cpu.regs.flags.set_parity(res16 as usize);
-                }
-            }
-            Op::Shr8 => {
-                // Unsigned divide r/m8 by 2, `src` times.
-                // two arguments
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) & 0x1F;
-                if count > 0 {
-                    let res = dst.wrapping_shr(count as u32);
-                    self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
-                    self.cpu.regs.flags.carry = (dst.wrapping_shr((count - 1) as u32) & 0x1) != 0;
-                    self.cpu.regs.flags.overflow = dst & 0x80 != 0;
-                    self.cpu.regs.flags.set_sign_u8(res);
-                    self.cpu.regs.flags.set_zero_u8(res);
-                    self.cpu.regs.flags.set_parity(res);
-                    /*
-                    The CF flag contains the value of the last bit shifted out of the destination operand;
-                    it is undefined for SHL and SHR instructions where the count is greater than or equal to the size (in bits) of the destination operand.
-
-                    The OF flag is affected only for 1-bit shifts; otherwise, it is undefined.
-                    The SF, ZF, and PF flags are set according to the result. If the count is 0, the flags are not affected.
-                    For a non-zero count, the AF flag is undefined.
-                    */
-                }
-            }
-            Op::Shr16 => {
-                // two arguments
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) & 0x1F;
-                if count > 0 {
-                    let res = dst.wrapping_shr(count as u32);
-                    self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
-                    self.cpu.regs.flags.carry = (dst.wrapping_shr((count - 1) as u32) & 0x1) != 0;
-                    self.cpu.regs.flags.overflow = dst & 0x8000 != 0;
-                    self.cpu.regs.flags.set_sign_u16(res);
-                    self.cpu.regs.flags.set_zero_u16(res);
-                    self.cpu.regs.flags.set_parity(res);
-                }
-            }
-            Op::Shr32 => {
-                // two arguments
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src) & 0x1F; // XXX
-                if count > 0 {
-                    let res = dst.wrapping_shr(count as u32);
-                    self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u32);
-                    self.cpu.regs.flags.carry = (dst.wrapping_shr((count - 1) as u32) & 0x1) != 0; // XXX
-                    self.cpu.regs.flags.overflow = dst & 0x8000_0000 != 0;
-                    self.cpu.regs.flags.set_sign_u32(res);
-                    self.cpu.regs.flags.set_zero_u32(res);
-                    self.cpu.regs.flags.set_parity(res);
-                }
-            }
-            Op::Shrd => {
-                // 3 arguments
-
-                let count = self.cpu.read_parameter_value(&self.mmu, &op.params.src2) & 0x1F; // use 5 lsb
-                if count == 0 {
-                    return;
-                }
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-
-                // count < 32, since only lower 5 bits used
-                let temp_32 = (src << 16) | dst;
-                let mut result_32 = temp_32 >> count;
-
-                // hack to act like x86 SHRD when count > 16
-                if count > 16 {
-                    // for Pentium processor, when count > 16, actually shifting op2:op2:op1 >> count,
-                    // it is the same as shifting op2:op2 by count-16
-                    // For P6 and later (CPU_LEVEL >= 6), when count > 16, actually shifting op1:op2:op1 >> count,
-                    // which is the same as shifting op1:op2 by count-16
-                    // The behavior is undefined so both ways are correct, we prefer P6 way of implementation
-                    result_32 |= dst << (32 - count);
-                }
-
-                let result_16 = result_32 as u16;
-
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, result_16);
-
-                // SF, ZF, and PF flags are set according to the value of the result.
-                self.cpu.regs.flags.set_sign_u16(result_16 as usize);
-                self.cpu.regs.flags.set_zero_u16(result_16 as usize);
-                self.cpu.regs.flags.set_parity(result_16 as usize);
-
-                let mut cf = (dst >> (count - 1)) & 0x1;
-                let of = (((result_16 << 1) ^ result_16) >> 15) & 0x1; // of = result14 ^ result15
-                if count > 16 {
-                    // undefined flags behavior matching real HW
-                    cf = (src >> (count - 17)) & 0x1;
-                }
-                self.cpu.regs.flags.carry = cf != 0;
-                self.cpu.regs.flags.overflow = of != 0;
-            }
-            Op::Sldt => {
-                println!("XXX impl {}", op);
-            }
-            Op::Stc => {
-                self.cpu.regs.flags.carry = true;
-            }
-            Op::Std => {
-                self.cpu.regs.flags.direction = true;
-            }
-            Op::Sti => {
-                self.cpu.regs.flags.interrupt = true;
-            }
-            Op::Stosb => {
-                // no parameters
-                // store AL at ES:(E)DI
-                // The ES segment cannot be overridden with a segment override prefix.
-                let al = self.cpu.get_r8(R::AL);
-                let es = self.cpu.get_r16(R::ES);
-                let di = self.cpu.get_r16(R::DI);
-                self.mmu.write_u8(es, di, al);
-                let di = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::DI).wrapping_add(1)
-                } else {
-                    self.cpu.get_r16(R::DI).wrapping_sub(1)
-                };
-                self.cpu.set_r16(R::DI, di);
-            }
-            Op::Stosw => {
-                // no parameters
-                // store AX at address ES:(E)DI
-                // The ES segment cannot be overridden with a segment override prefix.
-                let ax = self.cpu.get_r16(R::AX);
-                let es = self.cpu.get_r16(R::ES);
-                let di = self.cpu.get_r16(R::DI);
-                self.mmu.write_u16(es, di, ax);
-                let di = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::DI).wrapping_add(2)
-                } else {
-                    self.cpu.get_r16(R::DI).wrapping_sub(2)
-                };
-                self.cpu.set_r16(R::DI, di);
-            }
-            Op::Stosd => {
-                // no parameters
-                // store EAX at address ES:(E)DI
-                // The ES segment cannot be overridden with a segment override prefix.
-                let eax = self.cpu.get_r32(R::EAX);
-                let es = self.cpu.get_r16(R::ES);
-                let di = self.cpu.get_r16(R::DI);
-                self.mmu.write_u32(es, di, eax);
-                // XXX adjust DI or EDI ?
-                let di = if !self.cpu.regs.flags.direction {
-                    self.cpu.get_r16(R::DI).wrapping_add(4)
-                } else {
-                    self.cpu.get_r16(R::DI).wrapping_sub(4)
-                };
-                self.cpu.set_r16(R::DI, di);
-            }
-            Op::Sub8 => {
-                // two parameters (dst=reg)
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let res = dst.wrapping_sub(src);
-
-                // The OF, SF, ZF, AF, PF, and CF flags are set according to the result.
-                self.cpu.regs.flags.set_overflow_sub_u8(res, src, dst);
-                self.cpu.regs.flags.set_sign_u8(res);
-                self.cpu.regs.flags.set_zero_u8(res);
-                self.cpu.regs.flags.set_adjust(res, src, dst);
-                self.cpu.regs.flags.set_parity(res);
-                self.cpu.regs.flags.set_carry_u8(res);
-
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
-            }
-            Op::Sub16 => {
-                // two parameters (dst=reg)
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let res = dst.wrapping_sub(src);
-
-                // The OF, SF, ZF, AF, PF, and CF flags are set according to the result.
-                self.cpu.regs.flags.set_overflow_sub_u16(res, src, dst);
-                self.cpu.regs.flags.set_sign_u16(res);
-                self.cpu.regs.flags.set_zero_u16(res);
-                self.cpu.regs.flags.set_adjust(res, src, dst);
-                self.cpu.regs.flags.set_parity(res);
-                self.cpu.regs.flags.set_carry_u16(res);
-
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
-            }
-            Op::Sub32 => {
-                // two parameters (dst=reg)
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let res = dst.wrapping_sub(src);
-
-                // The OF, SF, ZF, AF, PF, and CF flags are set according to the result.
-                self.cpu.regs.flags.set_overflow_sub_u32(res, src, dst);
-                self.cpu.regs.flags.set_sign_u32(res);
-                self.cpu.regs.flags.set_zero_u32(res);
-                self.cpu.regs.flags.set_adjust(res, src, dst);
-                self.cpu.regs.flags.set_parity(res);
-                self.cpu.regs.flags.set_carry_u32(res);
-
-                self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u32);
-            }
-            Op::Test8 => {
-                // two parameters
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let res = dst & src;
-                self.cpu.regs.flags.overflow = false;
-                self.cpu.regs.flags.carry = false;
-                // set SF, ZF, PF according to result.
-                self.cpu.regs.flags.set_sign_u8(res);
-                self.cpu.regs.flags.set_zero_u8(res);
-                self.cpu.regs.flags.set_parity(res);
-            }
-            Op::Test16 => {
-                // two parameters
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let res = dst & src;
-                self.cpu.regs.flags.overflow = false;
-                self.cpu.regs.flags.carry = false;
-                // set SF, ZF, PF according to result.
-                self.cpu.regs.flags.set_sign_u16(res);
-                self.cpu.regs.flags.set_zero_u16(res);
-                self.cpu.regs.flags.set_parity(res);
-            }
-            Op::Xchg8 => {
-                // two parameters (registers)
-                let mut src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let mut dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                mem::swap(&mut src, &mut dst);
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, dst as u8);
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.src, src as u8);
-            }
-            Op::Xchg16 => {
-                // two parameters (registers)
-                let mut src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let mut dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                mem::swap(&mut src, &mut dst);
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, dst as u16);
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.src, src as u16);
-            }
-            Op::Xchg32 => {
-                // two parameters (registers)
-                let mut src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let mut dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                mem::swap(&mut src, &mut dst);
-                self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, dst as u32);
-                self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.src, src as u32);
-            }
-            Op::Xlatb => {
-                // no parameters
-                // Set AL to memory byte DS:[(E)BX + unsigned AL].
-                // The DS segment may be overridden with a segment override prefix.
-                let al = self.mmu.read_u8(self.cpu.segment(op.segment_prefix), self.cpu.get_r16(R::BX) + u16::from(self.cpu.get_r8(R::AL)));
-                self.cpu.set_r8(R::AL, al);
-            }
-            Op::Xor8 => {
-                // two parameters (dst=reg)
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let res = dst ^ src;
-
-                // The OF and CF flags are cleared; the SF, ZF,
-                // and PF flags are set according to the result.
-                self.cpu.regs.flags.overflow = false;
-                self.cpu.regs.flags.carry = false;
-                self.cpu.regs.flags.set_sign_u8(res);
-                self.cpu.regs.flags.set_zero_u8(res);
-                self.cpu.regs.flags.set_parity(res);
-
-                self.cpu.write_parameter_u8(&mut self.mmu, &op.params.dst, res as u8);
-            }
-            Op::Xor16 => {
-                // two parameters (dst=reg)
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let res = dst ^ src;
-
-                // The OF and CF flags are cleared; the SF, ZF,
-                // and PF flags are set according to the result.
-                self.cpu.regs.flags.overflow = false;
-                self.cpu.regs.flags.carry = false;
-                self.cpu.regs.flags.set_sign_u16(res);
-                self.cpu.regs.flags.set_zero_u16(res);
-                self.cpu.regs.flags.set_parity(res);
-
-                self.cpu.write_parameter_u16(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u16);
-            }
-            Op::Xor32 => {
-                // two parameters (dst=reg)
-                let src = self.cpu.read_parameter_value(&self.mmu, &op.params.src);
-                let dst = self.cpu.read_parameter_value(&self.mmu, &op.params.dst);
-                let res = dst ^ src;
-
-                // The OF and CF flags are cleared; the SF, ZF,
-                // and PF flags are set according to the result.
-                self.cpu.regs.flags.overflow = false;
-                self.cpu.regs.flags.carry = false;
-                self.cpu.regs.flags.set_sign_u32(res);
-                self.cpu.regs.flags.set_zero_u32(res);
-                self.cpu.regs.flags.set_parity(res);
-
-                self.cpu.write_parameter_u32(&mut self.mmu, op.segment_prefix, &op.params.dst, res as u32);
-            }
-            _ => {
-                let (seg, off) = self.cpu.get_address_pair();
-                println!("execute error: unhandled '{}' at {:04X}:{:04X} (flat {:06X})",
-                         op,
-                         seg,
-                         off,
-                         self.cpu.get_address());
+        // there are no instruction timings, each instruction takes one cycle
+        self.cpu.cycle_count += 1;
+        if op.repeat != RepeatMode::None && self.rep_count(op) == 0 {
+            // a repeated string instruction with a zero count does nothing
+            return;
+        }
+        let handler = self.handlers[op.command.index()];
+        handler(self, op);
+
+        if op.repeat != RepeatMode::None {
+            let count = self.rep_count(op).wrapping_sub(1);
+            self.set_rep_count(op, count);
+            let repeat = match op.repeat {
+                RepeatMode::Repe => self.cpu.regs.flags.zero(),
+                RepeatMode::Repne => !self.cpu.regs.flags.zero(),
+                _ => true,
+            };
+            if count != 0 && repeat {
+                self.cpu.regs.ip = start_ip;
             }
         }
 
-        match op.repeat {
-            RepeatMode::Rep => {
-                let cx = self.cpu.get_r16(R::CX).wrapping_sub(1);
-                self.cpu.set_r16(R::CX, cx);
-                if cx != 0 {
-                    self.cpu.regs.ip = start_ip;
-                }
-            }
-            RepeatMode::Repe => {
-                let cx = self.cpu.get_r16(R::CX).wrapping_sub(1);
-                self.cpu.set_r16(R::CX, cx);
-                if cx != 0 && self.cpu.regs.flags.zero {
-                    self.cpu.regs.ip = start_ip;
-                }
-            }
-            RepeatMode::Repne => {
-                let cx = self.cpu.get_r16(R::CX).wrapping_sub(1);
-                self.cpu.set_r16(R::CX, cx);
-                if cx != 0 && !self.cpu.regs.flags.zero {
-                    self.cpu.regs.ip = start_ip;
-                }
-            }
-            RepeatMode::None => {}
-        }
-
         if op.lock {
             // TODO implement lock
             // println!("XXX FIXME: instruction has LOCK prefix: {}", op);
         }
     }
+
+    /// returns the repeat count, ECX when the address size is 32-bit
+    fn rep_count(&self, op: &Instruction) -> u32 {
+        match op.address_size {
+            AddressSize::_16bit => u32::from(self.cpu.get_r16(R::CX)),
+            AddressSize::_32bit => self.cpu.get_r32(R::ECX),
+        }
+    }
+
+    fn set_rep_count(&mut self, op: &Instruction, count: u32) {
+        match op.address_size {
+            AddressSize::_16bit => self.cpu.set_r16(R::CX, count as u16),
+            AddressSize::_32bit => self.cpu.set_r32(R::ECX, count),
+        }
+    }
 }
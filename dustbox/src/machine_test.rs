@@ -1,7 +1,19 @@
+use std::fs;
 use std::num::Wrapping;
+use std::ops::RangeInclusive;
 
-use crate::machine::Machine;
-use crate::cpu::R;
+use tempfile::tempdir;
+
+use crate::bios::BIOS;
+use crate::clock::Clock;
+use crate::machine::{AudioDevice, Machine, MachineConfig, Component, UnknownPolicy};
+use crate::cpu::{CPU, CpuModel, Invalid, Op, R};
+use crate::keyboard::{KeyboardLayout, Keycode, Modifiers};
+use crate::storage::Mount;
+use crate::memory::MMU;
+use crate::gpu::Monitor;
+use crate::dosbox_conf::DosboxConf;
+use crate::net::Pipe;
 
 // TODO TEST retn, retf, retn imm16
 // TODO lds, les - write tests and fix implementation - it is wrong?!
@@ -77,39 +89,39 @@ fn can_execute_add8() {
 
     machine.execute_instructions(2);
     assert_eq!(0x00, machine.cpu.get_r8(R::AH));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(true, machine.cpu.regs.flags.adjust);
-    assert_eq!(true, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(true, machine.cpu.regs.flags.adjust());
+    assert_eq!(true, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(2);
     assert_eq!(0x00, machine.cpu.get_r8(R::AH));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(true, machine.cpu.regs.flags.adjust);
-    assert_eq!(true, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(true, machine.cpu.regs.flags.adjust());
+    assert_eq!(true, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(2);
     assert_eq!(0xFF, machine.cpu.get_r8(R::AH));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(false, machine.cpu.regs.flags.adjust);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(true, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(false, machine.cpu.regs.flags.adjust());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(true, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(2);
     assert_eq!(0xFE, machine.cpu.get_r8(R::AH));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.parity);
-    assert_eq!(true, machine.cpu.regs.flags.adjust);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(true, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.parity());
+    assert_eq!(true, machine.cpu.regs.flags.adjust());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(true, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 }
 
 #[test]
@@ -132,39 +144,39 @@ fn can_execute_add16() {
 
     machine.execute_instructions(2);
     assert_eq!(0x0000, machine.cpu.get_r16(R::AX));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(true, machine.cpu.regs.flags.adjust);
-    assert_eq!(true, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(true, machine.cpu.regs.flags.adjust());
+    assert_eq!(true, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(2);
     assert_eq!(0x0000, machine.cpu.get_r16(R::AX));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(true, machine.cpu.regs.flags.adjust);
-    assert_eq!(true, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(true, machine.cpu.regs.flags.adjust());
+    assert_eq!(true, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(2);
     assert_eq!(0xFFFF, machine.cpu.get_r16(R::AX));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(false, machine.cpu.regs.flags.adjust);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(true, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(false, machine.cpu.regs.flags.adjust());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(true, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(2);
     assert_eq!(0xFFFE, machine.cpu.get_r16(R::AX));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.parity);
-    assert_eq!(true, machine.cpu.regs.flags.adjust);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(true, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.parity());
+    assert_eq!(true, machine.cpu.regs.flags.adjust());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(true, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 }
 
 #[test]
@@ -222,6 +234,28 @@ fn can_execute_mov_r8_rm8() {
     assert_eq!(0x99, machine.cpu.get_r8(R::AH));
 }
 
+#[test]
+fn can_execute_sib_addressing() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0x66, 0xB8, 0x00, 0x02, 0x00, 0x00,                         // mov eax,0x200
+        0x66, 0xB9, 0x04, 0x00, 0x00, 0x00,                         // mov ecx,0x4
+        0x67, 0xC6, 0x84, 0x88, 0x10, 0x00, 0x00, 0x00, 0x7F,       // mov byte [eax+ecx*4+0x10],0x7f
+        0x67, 0x8A, 0x5C, 0x08, 0x1C,                               // mov bl,[eax+ecx+0x1c]
+        0x66, 0x67, 0x8D, 0x54, 0x48, 0x02,                         // lea edx,[eax+ecx*2+0x2]
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(3);
+    assert_eq!(0x7F, machine.mmu.read_u8(0x085F, 0x220));
+
+    machine.execute_instruction();
+    assert_eq!(0x7F, machine.cpu.get_r8(R::BL));
+
+    machine.execute_instruction();
+    assert_eq!(0x20A, machine.cpu.get_r32(R::EDX));
+}
+
 #[test]
 fn can_execute_mv_r16() {
     let mut machine = Machine::deterministic();
@@ -395,22 +429,22 @@ fn can_execute_with_flags() {
     machine.execute_instruction();
     assert_eq!(0x102, machine.cpu.regs.ip);
     assert_eq!(0xFE, machine.cpu.get_r8(R::AH));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
-    assert_eq!(false, machine.cpu.regs.flags.adjust);
-    assert_eq!(false, machine.cpu.regs.flags.parity);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
+    assert_eq!(false, machine.cpu.regs.flags.adjust());
+    assert_eq!(false, machine.cpu.regs.flags.parity());
 
     machine.execute_instruction();
     assert_eq!(0x105, machine.cpu.regs.ip);
     assert_eq!(0x00, machine.cpu.get_r8(R::AH));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
-    assert_eq!(true, machine.cpu.regs.flags.adjust);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
+    assert_eq!(true, machine.cpu.regs.flags.adjust());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
 }
 
 #[test]
@@ -436,12 +470,12 @@ fn can_execute_cmp() {
     machine.execute_instruction();
     assert_eq!(0x109, machine.cpu.regs.ip);
 
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(true, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
-    assert_eq!(false, machine.cpu.regs.flags.adjust);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(true, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
+    assert_eq!(false, machine.cpu.regs.flags.adjust());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
 }
 
 #[test]
@@ -510,6 +544,135 @@ fn can_execute_rep_outsb() {
     assert_eq!(0x0, machine.cpu.get_r16(R::CX));
 }
 
+#[test]
+fn can_execute_rep_movsd_with_ecx() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0x66, 0xB9, 0x02, 0x00, 0x00, 0x00, // mov ecx,0x2
+        0x66, 0xBE, 0x00, 0x01, 0x01, 0x00, // mov esi,0x10100
+        0x66, 0xBF, 0x00, 0x02, 0x01, 0x00, // mov edi,0x10200
+        0x67, 0xF3, 0x66, 0xA5,             // a32 rep movsd
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(3);
+    machine.execute_instructions(2); // rep movsd
+    assert_eq!(0x0000_0000, machine.cpu.get_r32(R::ECX));
+    assert_eq!(0x0001_0108, machine.cpu.get_r32(R::ESI));
+    assert_eq!(0x0001_0208, machine.cpu.get_r32(R::EDI));
+    assert_eq!(0x0116, machine.cpu.regs.ip);
+    for i in 0x100..0x108 {
+        assert_eq!(machine.mmu.read_u8(0x085F, i), machine.mmu.read_u8(0x085F, i + 0x100));
+    }
+}
+
+#[test]
+fn can_execute_rep_stosb_with_cx() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0x66, 0xB9, 0x02, 0x00, 0x01, 0x00, // mov ecx,0x10002
+        0x66, 0xBF, 0xFF, 0xFF, 0x01, 0x00, // mov edi,0x1ffff
+        0xB0, 0x55,                         // mov al,0x55
+        0xF3, 0xAA,                         // rep stosb
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(3);
+    machine.execute_instructions(2); // rep stosb
+    // only the low words of ECX and EDI are used with 16-bit addressing
+    assert_eq!(0x0001_0000, machine.cpu.get_r32(R::ECX));
+    assert_eq!(0x0001_0001, machine.cpu.get_r32(R::EDI));
+    assert_eq!(0x55, machine.mmu.read_u8(0x085F, 0xFFFF));
+    assert_eq!(0x55, machine.mmu.read_u8(0x085F, 0x0000));
+    assert_eq!(0x0110, machine.cpu.regs.ip);
+}
+
+#[test]
+fn can_execute_rep_with_zero_count() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB9, 0x00, 0x00,   // mov cx,0x0
+        0xBF, 0x00, 0x02,   // mov di,0x200
+        0xB0, 0x55,         // mov al,0x55
+        0xF3, 0xAA,         // rep stosb
+        0xF3, 0xAE,         // repe scasb
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // rep stosb
+    assert_eq!(0x010A, machine.cpu.regs.ip);
+    assert_eq!(0x0000, machine.cpu.get_r16(R::CX));
+    assert_eq!(0x0200, machine.cpu.get_r16(R::DI));
+    assert_eq!(0x00, machine.mmu.read_u8(0x085F, 0x200));
+
+    machine.cpu.regs.flags.set_zero(false);
+    machine.execute_instruction(); // repe scasb
+    assert_eq!(0x010C, machine.cpu.regs.ip);
+    assert_eq!(0x0200, machine.cpu.get_r16(R::DI));
+    assert!(!machine.cpu.regs.flags.zero());
+}
+
+#[test]
+fn can_execute_std_rep_stosw() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xFD,               // std
+        0xBF, 0x06, 0x02,   // mov di,0x206
+        0xB9, 0x03, 0x00,   // mov cx,0x3
+        0xB8, 0x34, 0x12,   // mov ax,0x1234
+        0xF3, 0xAB,         // rep stosw
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(4);
+    machine.execute_instructions(3); // rep stosw
+    assert_eq!(0x0000, machine.cpu.get_r16(R::CX));
+    assert_eq!(0x0200, machine.cpu.get_r16(R::DI));
+    assert_eq!(0x0000, machine.mmu.read_u16(0x085F, 0x200));
+    assert_eq!(0x1234, machine.mmu.read_u16(0x085F, 0x202));
+    assert_eq!(0x1234, machine.mmu.read_u16(0x085F, 0x204));
+    assert_eq!(0x1234, machine.mmu.read_u16(0x085F, 0x206));
+}
+
+#[test]
+fn can_execute_repne_scasd() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xBF, 0x00, 0x02,                               // mov di,0x200
+        0x66, 0xC7, 0x45, 0x08, 0x78, 0x56, 0x34, 0x12, // mov dword [di+0x8],0x12345678
+        0x66, 0xB8, 0x78, 0x56, 0x34, 0x12,             // mov eax,0x12345678
+        0xB9, 0x10, 0x00,                               // mov cx,0x10
+        0xF2, 0x66, 0xAF,                               // repne scasd
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(4);
+    machine.execute_instructions(3); // repne scasd, stops on the match
+    assert!(machine.cpu.regs.flags.zero());
+    assert_eq!(0x020C, machine.cpu.get_r16(R::DI));
+    assert_eq!(0x000D, machine.cpu.get_r16(R::CX));
+    assert_eq!(0x0117, machine.cpu.regs.ip);
+}
+
+#[test]
+fn can_execute_std_repe_cmpsb() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xC6, 0x06, 0x01, 0x02, 0x11,   // mov byte [0x201],0x11
+        0xC6, 0x06, 0x01, 0x03, 0x11,   // mov byte [0x301],0x11
+        0xC6, 0x06, 0x00, 0x02, 0x22,   // mov byte [0x200],0x22
+        0xBE, 0x02, 0x02,               // mov si,0x202
+        0xBF, 0x02, 0x03,               // mov di,0x302
+        0xB9, 0x04, 0x00,               // mov cx,0x4
+        0xFD,                           // std
+        0xF3, 0xA6,                     // repe cmpsb
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(7);
+    machine.execute_instructions(3); // repe cmpsb, stops on the mismatch at 0x200
+    assert!(!machine.cpu.regs.flags.zero());
+    assert_eq!(0x01FF, machine.cpu.get_r16(R::SI));
+    assert_eq!(0x02FF, machine.cpu.get_r16(R::DI));
+    assert_eq!(0x0001, machine.cpu.get_r16(R::CX));
+    assert_eq!(0x011B, machine.cpu.regs.ip);
+}
+
 #[test]
 fn can_execute_es_outsb() {
     let mut machine = Machine::deterministic();
@@ -657,6 +820,30 @@ fn can_execute_32bit_addressing() {
     assert_eq!(0x0000_0200, machine.mmu.read_u32(ds, di - 0x140));
 }
 
+#[test]
+fn can_execute_32bit_addressing_relative_to_stack_segment() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB8, 0x00, 0x20,                           // mov ax,0x2000
+        0x8E, 0xD0,                                 // mov ss,ax
+        0x66, 0xBD, 0x10, 0x00, 0x00, 0x00,         // mov ebp,0x10
+        0x66, 0xBC, 0x20, 0x00, 0x00, 0x00,         // mov esp,0x20
+        0x66, 0x67, 0x8B, 0x45, 0x04,               // mov eax,[ebp+0x4]
+        0x66, 0x67, 0x8B, 0x5C, 0x24, 0x04,         // mov ebx,[esp+0x4]
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    machine.mmu.write_u32(0x2000, 0x14, 0x1122_3344);
+    machine.mmu.write_u32(0x2000, 0x24, 0x5566_7788);
+    machine.mmu.write_u32(0x085F, 0x14, 0xDEAD_BEEF);
+    machine.mmu.write_u32(0x085F, 0x24, 0xDEAD_BEEF);
+
+    machine.execute_instructions(6);
+    assert_ne!(machine.cpu.get_r16(R::SS), machine.cpu.get_r16(R::DS));
+    assert_eq!(0x1122_3344, machine.cpu.get_r32(R::EAX));
+    assert_eq!(0x5566_7788, machine.cpu.get_r32(R::EBX));
+}
+
 #[test]
 fn can_execute_math() {
     let mut machine = Machine::deterministic();
@@ -696,9 +883,9 @@ fn can_execute_and() {
 
     machine.execute_instruction();
     assert_eq!(0x10, machine.cpu.get_r8(R::AH));
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.parity);
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.parity());
 }
 
 #[test]
@@ -999,10 +1186,58 @@ fn can_execute_bts() {
     machine.load_executable(&code, 0x085F);
 
     let res = machine.cpu.decoder.disassemble_block_to_str(&mut machine.mmu, 0x85F, 0x100, 1);
-    assert_eq!("[085F:0100] 0FBA2EAE010F     Bts      word [ds:0x01AE], 0x0F", res);
+    assert_eq!("[085F:0100] 0FBA2EAE010F     Bts16    word [ds:0x01AE], 0x0F", res);
 
-    // XXX also test emulation
     machine.execute_instructions(1);
+    assert_eq!(0x8000, machine.mmu.read_u16(0x085F, 0x01AE));
+    assert!(!machine.cpu.regs.flags.carry());
+}
+
+#[test]
+fn can_execute_btr_btc() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB8, 0x0F, 0x00,                   // mov ax,0xf
+        0xBA, 0x11, 0x00,                   // mov dx,0x11
+        0x0F, 0xB3, 0xD0,                   // btr ax,dx
+        0x0F, 0xBA, 0xF8, 0x04,             // btc ax,0x4
+        0x66, 0xB8, 0x00, 0x00, 0x00, 0x80, // mov eax,0x80000000
+        0x66, 0x0F, 0xBA, 0xF0, 0x1F,       // btr eax,0x1f
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    // the bit offset is taken modulo the operand size
+    machine.execute_instructions(3);
+    assert_eq!(0x000D, machine.cpu.get_r16(R::AX));
+    assert!(machine.cpu.regs.flags.carry());
+
+    machine.execute_instruction();
+    assert_eq!(0x001D, machine.cpu.get_r16(R::AX));
+    assert!(!machine.cpu.regs.flags.carry());
+
+    machine.execute_instructions(2);
+    assert_eq!(0x0000_0000, machine.cpu.get_r32(R::EAX));
+    assert!(machine.cpu.regs.flags.carry());
+}
+
+#[test]
+fn can_execute_bsr() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB8, 0x14, 0x00,                   // mov ax,0x14
+        0x0F, 0xBD, 0xD0,                   // bsr dx,ax
+        0x66, 0xB8, 0x01, 0x00, 0x00, 0x80, // mov eax,0x80000001
+        0x66, 0x0F, 0xBD, 0xD0,             // bsr edx,eax
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(2);
+    assert_eq!(4, machine.cpu.get_r16(R::DX));
+    assert!(!machine.cpu.regs.flags.zero());
+
+    machine.execute_instructions(2);
+    assert_eq!(31, machine.cpu.get_r32(R::EDX));
+    assert!(!machine.cpu.regs.flags.zero());
 }
 
 #[test]
@@ -1020,15 +1255,15 @@ fn can_execute_bsf() {
 
     machine.execute_instructions(2);
     assert_eq!(2, machine.cpu.get_r16(R::DX));
-    assert_eq!(false, machine.cpu.regs.flags.zero);
+    assert_eq!(false, machine.cpu.regs.flags.zero());
 
     machine.execute_instructions(2);
     assert_eq!(4, machine.cpu.get_r16(R::DX));
-    assert_eq!(false, machine.cpu.regs.flags.zero);
+    assert_eq!(false, machine.cpu.regs.flags.zero());
 
     machine.execute_instructions(2);
     assert_eq!(4, machine.cpu.get_r16(R::DX)); // NOTE: if ax is 0, dx won't change
-    assert_eq!(true, machine.cpu.regs.flags.zero);
+    assert_eq!(true, machine.cpu.regs.flags.zero());
 }
 
 #[test]
@@ -1044,10 +1279,10 @@ fn can_execute_bt() {
     machine.load_executable(&code, 0x085F);
 
     machine.execute_instructions(3);
-    assert_eq!(false, machine.cpu.regs.flags.carry);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
 
     machine.execute_instructions(2);
-    assert_eq!(true, machine.cpu.regs.flags.carry);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
 }
 
 #[test]
@@ -1090,11 +1325,11 @@ fn can_execute_sahf() {
     machine.load_executable(&code, 0x085F);
 
     machine.execute_instructions(4);
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(true, machine.cpu.regs.flags.adjust);
-    assert_eq!(true, machine.cpu.regs.flags.zero);
-    assert_eq!(true, machine.cpu.regs.flags.sign);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(true, machine.cpu.regs.flags.adjust());
+    assert_eq!(true, machine.cpu.regs.flags.zero());
+    assert_eq!(true, machine.cpu.regs.flags.sign());
 }
 
 #[test]
@@ -1141,8 +1376,8 @@ fn can_execute_dec() {
 
     machine.execute_instruction();
     assert_eq!(0x1FF, machine.cpu.get_r16(R::BP));
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
 }
 
 #[test]
@@ -1159,12 +1394,12 @@ fn can_execute_neg() {
 
     machine.execute_instruction();
     assert_eq!(0xFEDD, machine.cpu.get_r16(R::BX));
-    // assert_eq!(true, machine.cpu.regs.flags.carry);  // XXX dosbox = TRUE
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(true, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
-    assert_eq!(true, machine.cpu.regs.flags.adjust);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
+    // assert_eq!(true, machine.cpu.regs.flags.carry());  // XXX dosbox = TRUE
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(true, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
+    assert_eq!(true, machine.cpu.regs.flags.adjust());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
 }
 
 #[test]
@@ -1181,12 +1416,12 @@ fn can_execute_sbb16() {
 
     // 3286 (xp)     =  0b11_0010_1000_0110
     // 7286 (dosbox) = 0b111_0010_1000_0110
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(false, machine.cpu.regs.flags.adjust);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(true, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(false, machine.cpu.regs.flags.adjust());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(true, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 }
 
 #[test]
@@ -1212,13 +1447,17 @@ fn can_execute_jmp_far_mem() {
         0x31, 0xC0,       // xor ax,ax
         0xBE, 0x88, 0x88, // mov si,0x8888
         0xBB, 0x22, 0x44, // mov bx,0x4422
-        0xC6, 0x00, 0x40, // mov byte [bx+si],0x40
+        0xC7, 0x00, 0x14, 0x01,         // mov word [bx+si],0x114
+        0xC7, 0x40, 0x02, 0x5F, 0x08,   // mov word [bx+si+0x2],0x85f
         0xFF, 0x28,       // jmp far [bx+si]
+        0x40,             // inc ax
+        0x40,             // inc ax
     ];
     machine.load_executable(&code, 0x085F);
 
-    machine.execute_instructions(6);
-    assert_eq!(0xCCAB, machine.cpu.regs.ip);
+    machine.execute_instructions(7);
+    assert_eq!(0x085F, machine.cpu.get_r16(R::CS));
+    assert_eq!(0x0115, machine.cpu.regs.ip);
     assert_eq!(0x0001, machine.cpu.get_r16(R::AX));
 }
 
@@ -1230,16 +1469,46 @@ fn can_execute_setc() {
     ];
 
     machine.load_executable(&code, 0x085F);
-    machine.cpu.regs.flags.carry = true;
+    machine.cpu.regs.flags.set_carry(true);
     machine.execute_instruction();
     assert_eq!(0x01, machine.cpu.get_r8(R::AL));
 
     machine.load_executable(&code, 0x085F);
-    machine.cpu.regs.flags.carry = false;
+    machine.cpu.regs.flags.set_carry(false);
     machine.execute_instruction();
     assert_eq!(0x00, machine.cpu.get_r8(R::AL));
 }
 
+#[test]
+fn can_execute_setcc() {
+    // zf set, sf set, of clear, cf clear, pf clear
+    let expected: [u8; 16] = [
+        0, 1, // seto, setno
+        0, 1, // setc, setnc
+        1, 0, // setz, setnz
+        1, 0, // setna, seta
+        1, 0, // sets, setns
+        0, 1, // setpe, setpo
+        1, 0, // setl, setnl
+        1, 0, // setng, setg
+    ];
+    let mut machine = Machine::deterministic();
+    for (i, &val) in expected.iter().enumerate() {
+        let code: Vec<u8> = vec![
+            0x0F, 0x90 + i as u8, 0xC0, // setcc al
+        ];
+        machine.load_executable(&code, 0x085F);
+        machine.cpu.set_r8(R::AL, 0xFF);
+        machine.cpu.regs.flags.set_zero(true);
+        machine.cpu.regs.flags.set_sign(true);
+        machine.cpu.regs.flags.set_overflow(false);
+        machine.cpu.regs.flags.set_carry(false);
+        machine.cpu.regs.flags.set_parity(false);
+        machine.execute_instruction();
+        assert_eq!(val, machine.cpu.get_r8(R::AL), "setcc 0F {:02X}", 0x90 + i);
+    }
+}
+
 #[test]
 fn can_execute_movzx() {
     let mut machine = Machine::deterministic();
@@ -1276,17 +1545,17 @@ fn can_execute_rol8() {
 
     machine.execute_instructions(2);
     assert_eq!(0xFD, machine.cpu.get_r8(R::AH));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(2);
     assert_eq!(0xFF, machine.cpu.get_r8(R::AH));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
     // overflow undefined with non-1 shift count
 
     machine.execute_instructions(2);
     assert_eq!(0x10,  machine.cpu.get_r8(R::AH));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
     // overflow undefined with non-1 shift count
 }
 
@@ -1305,17 +1574,17 @@ fn can_execute_rol16() {
 
     machine.execute_instructions(2);
     assert_eq!(0xFFFD, machine.cpu.get_r16(R::AX));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(2);
     assert_eq!(0xFFFF, machine.cpu.get_r16(R::AX));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
     // overflow undefined with non-1 shift count
 
     machine.execute_instructions(2);
     assert_eq!(0x0010, machine.cpu.get_r16(R::AX));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
     // overflow undefined with non-1 shift count
 }
 
@@ -1334,17 +1603,17 @@ fn can_execute_ror8() {
 
     machine.execute_instructions(2);
     assert_eq!(0x7F, machine.cpu.get_r8(R::AH));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(2);
     assert_eq!(0xFF, machine.cpu.get_r8(R::AH));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
     // overflow undefined with non-1 shift count
 
     machine.execute_instructions(2);
     assert_eq!(0x10, machine.cpu.get_r8(R::AH));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
     // overflow undefined with non-1 shift count
 }
 
@@ -1363,17 +1632,17 @@ fn can_execute_ror16() {
 
     machine.execute_instructions(2);
     assert_eq!(0x7FFF, machine.cpu.get_r16(R::AX));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(2);
     assert_eq!(0xFFFF, machine.cpu.get_r16(R::AX));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
     // overflow undefined with non-1 shift count
 
     machine.execute_instructions(2);
     assert_eq!(0x1000, machine.cpu.get_r16(R::AX));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
     // overflow undefined with non-1 shift count
 }
 
@@ -1395,18 +1664,18 @@ fn can_execute_rcl8() {
 
     machine.execute_instructions(3);
     assert_eq!(0xFD, machine.cpu.get_r8(R::AH));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(3);
     assert_eq!(0xFF, machine.cpu.get_r8(R::AH));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(3);
     assert_eq!(0x18, machine.cpu.get_r8(R::AH));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 }
 
 #[test]
@@ -1427,18 +1696,18 @@ fn can_execute_rcl16() {
 
     machine.execute_instructions(3);
     assert_eq!(0xFFFD, machine.cpu.get_r16(R::AX));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(3);
     assert_eq!(0xFFFF, machine.cpu.get_r16(R::AX));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(3);
     assert_eq!(0x0018, machine.cpu.get_r16(R::AX));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 }
 
 #[test]
@@ -1467,29 +1736,29 @@ fn can_execute_rcr8() {
     assert_eq!(0xFF,  machine.cpu.get_r8(R::AH));
     // 3002 = 0b11_0000_0000_0010 (xp)
     //        ____ O___ SZ_A _P_C
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(3);
     assert_eq!(0x7F,  machine.cpu.get_r8(R::AH));
     // 3802 = 0b11_1000_0000_0010 (xp)
     //        ____ O___ SZ_A _P_C
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(3);
     assert_eq!(0xFF,  machine.cpu.get_r8(R::AH));
     // 3703 = 0b11_0111_0000_0011 (xp)
     //        ____ O___ SZ_A _P_C
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(3);
     assert_eq!(0x30,  machine.cpu.get_r8(R::AH));
     // 3802 = 0b11_1000_0000_0010 (xp)
     //        ____ O___ SZ_A _P_C
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);   // XXX win-xp sets overflow here. seems wrong? verify on real hw
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.overflow());
 }
 
 #[test]
@@ -1518,29 +1787,29 @@ fn can_execute_rcr16() {
     assert_eq!(0xFFFF, machine.cpu.get_r16(R::AX));
     // 3002 = 0b11_0000_0000_0010 (xp)
     //        ____ O___ SZ_A _P_C
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(3);
     assert_eq!(0x7FFF, machine.cpu.get_r16(R::AX));
     // 3802 = 0b11_1000_0000_0010 (xp)
     //        ____ O___ SZ_A _P_C
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(3);
     assert_eq!(0xFFFF, machine.cpu.get_r16(R::AX));
     // 3003 = 0b11_0000_0000_0011 (xp)
     //        ____ O___ SZ_A _P_C
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(3);
     assert_eq!(0x3000, machine.cpu.get_r16(R::AX));
     // 3802 = 0b11_1000_0000_0010 (xp)
     //        ____ O___ SZ_A _P_C
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);  // XXX win-xp sets overflow here. seems wrong? verify on real hw
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.overflow());
 }
 
 #[test]
@@ -1559,19 +1828,19 @@ fn can_execute_shl8() {
 
     machine.execute_instructions(2);
     assert_eq!(0xFE, machine.cpu.get_r8(R::AH));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.parity);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(true, machine.cpu.regs.flags.sign);
-    //assert_eq!(false, machine.cpu.regs.flags.overflow); // XXX true in dustbox, false in dosbox?
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.parity());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(true, machine.cpu.regs.flags.sign());
+    //assert_eq!(false, machine.cpu.regs.flags.overflow()); // XXX true in dustbox, false in dosbox?
 
     machine.execute_instructions(2);
     assert_eq!(0x00, machine.cpu.get_r8(R::AH));
-    // assert_eq!(false, machine.cpu.regs.flags.carry); // XXX false in dosbox. true in dustbox!?
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(true, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow); // XXX true in dosbox
+    // assert_eq!(false, machine.cpu.regs.flags.carry()); // XXX false in dosbox. true in dustbox!?
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(true, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow()); // XXX true in dosbox
     // flag bug, reported at https://github.com/joncampbell123/dosbox-x/issues/469
     // win-xp:   flg 3046 = 0b11_0000_0100_0110       xp does not set aux or overflow
     // dosbox-x: flg 0856 =    0b1000_0101_0110       dosbox-x changes aux flag (bug?), and sets overflow (bug?)
@@ -1579,11 +1848,11 @@ fn can_execute_shl8() {
 
     machine.execute_instructions(2);
     assert_eq!(0x10, machine.cpu.get_r8(R::AH));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.parity);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.parity());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 }
 
 #[test]
@@ -1601,27 +1870,27 @@ fn can_execute_shl16() {
 
     machine.execute_instructions(2);
     assert_eq!(0xFFFE, machine.cpu.get_r16(R::AX));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.parity);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(true, machine.cpu.regs.flags.sign);
-    // assert_eq!(true, machine.cpu.regs.flags.overflow); // XXX buggy overflow
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.parity());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(true, machine.cpu.regs.flags.sign());
+    // assert_eq!(true, machine.cpu.regs.flags.overflow()); // XXX buggy overflow
 
     machine.execute_instructions(2);
     assert_eq!(0x0000, machine.cpu.get_r16(R::AX));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(true, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    // assert_eq!(true, machine.cpu.regs.flags.overflow); // XXX buggy overflow
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(true, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    // assert_eq!(true, machine.cpu.regs.flags.overflow()); // XXX buggy overflow
 
     machine.execute_instructions(2);
     assert_eq!(0x0010, machine.cpu.get_r16(R::AX));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.parity);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    // assert_eq!(true, machine.cpu.regs.flags.overflow); // XXX buggy overflow
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.parity());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    // assert_eq!(true, machine.cpu.regs.flags.overflow()); // XXX buggy overflow
 }
 
 #[test]
@@ -1639,27 +1908,27 @@ fn can_execute_shr8() {
 
     machine.execute_instructions(2);
     assert_eq!(0x7F, machine.cpu.get_r8(R::AH));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(false, machine.cpu.regs.flags.parity);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    assert_eq!(true, machine.cpu.regs.flags.overflow);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(false, machine.cpu.regs.flags.parity());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    assert_eq!(true, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(2);
     assert_eq!(0x00, machine.cpu.get_r8(R::AH));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(true, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    assert_eq!(true, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(true, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    assert_eq!(true, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(2);
     assert_eq!(0x00, machine.cpu.get_r8(R::AH));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(true, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(true, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 }
 
 #[test]
@@ -1677,27 +1946,27 @@ fn can_execute_shr16() {
 
     machine.execute_instructions(2);
     assert_eq!(0x7FFF, machine.cpu.get_r16(R::AX));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    assert_eq!(true, machine.cpu.regs.flags.overflow);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    assert_eq!(true, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(2);
     assert_eq!(0x0000, machine.cpu.get_r16(R::AX));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(true, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    assert_eq!(true, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(true, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    assert_eq!(true, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(2);
     assert_eq!(0x0000, machine.cpu.get_r16(R::AX));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(true, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(true, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 }
 
 #[test]
@@ -1715,27 +1984,27 @@ fn can_execute_sar8() {
 
     machine.execute_instructions(2);
     assert_eq!(0xFF, machine.cpu.get_r8(R::AH));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(true, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(true, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(2);
     assert_eq!(0xFF, machine.cpu.get_r8(R::AH));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(true, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(true, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(2);
     assert_eq!(0x00, machine.cpu.get_r8(R::AH));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(true, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(true, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 }
 
 #[test]
@@ -1753,27 +2022,27 @@ fn can_execute_sar16() {
 
     machine.execute_instructions(2);
     assert_eq!(0xFFFF, machine.cpu.get_r16(R::AX));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(true, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(true, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(2);
     assert_eq!(0xFFFF, machine.cpu.get_r16(R::AX));
-    assert_eq!(true, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(true, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(true, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(true, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 
     machine.execute_instructions(2);
     assert_eq!(0x0000, machine.cpu.get_r16(R::AX));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
-    assert_eq!(true, machine.cpu.regs.flags.zero);
-    assert_eq!(false, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
+    assert_eq!(true, machine.cpu.regs.flags.zero());
+    assert_eq!(false, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
 }
 
 #[test]
@@ -1957,13 +2226,227 @@ fn can_execute_int_iret() {
     assert_eq!(0x085F, machine.cpu.get_r16(R::CS));
     machine.execute_instruction();
     assert_eq!(0xF000, machine.cpu.get_r16(R::CS));
-    assert_eq!(0x0072, machine.cpu.regs.ip);
+    assert_eq!(BIOS::default_handler(0x72), machine.cpu.regs.ip);
 
     machine.execute_instruction(); // IRET
     assert_eq!(0x085F, machine.cpu.get_r16(R::CS));
     assert_eq!(0x0102, machine.cpu.regs.ip);
 }
 
+#[test]
+fn can_single_step_with_trap_flag() {
+    // installs a int 1 handler that counts the traps in DI, then traces a few instructions
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0x31, 0xC0,                                 // xor ax,ax
+        0x8E, 0xC0,                                 // mov es,ax
+        0x26, 0xC7, 0x06, 0x04, 0x00, 0x21, 0x01,   // mov word [es:0x4],0x121
+        0x26, 0x8C, 0x0E, 0x06, 0x00,               // mov [es:0x6],cs
+        0x9C,                                       // pushf
+        0x58,                                       // pop ax
+        0x0D, 0x00, 0x01,                           // or ax,0x100
+        0x50,                                       // push ax
+        0x9D,                                       // popf         ; sets TF
+        0x90,                                       // nop          ; traced
+        0x43,                                       // inc bx       ; traced
+        0x9C,                                       // pushf        ; traced
+        0x58,                                       // pop ax       ; traced
+        0x25, 0xFF, 0xFE,                           // and ax,0xfeff ; traced
+        0x50,                                       // push ax      ; traced
+        0x9D,                                       // popf         ; traced, clears TF
+        0x90,                                       // nop
+        0x47,                                       // inc di       ; int 1 handler
+        0xCF,                                       // iret
+    ];
+    machine.load_executable(&code, 0x085F);
+    let sp = machine.cpu.get_r16(R::SP);
+    let di = machine.cpu.get_r16(R::DI);
+    machine.execute_instructions(9);
+    assert!(machine.cpu.regs.flags.trap);
+
+    machine.execute_instruction(); // nop
+    assert_eq!(0x0121, machine.cpu.regs.ip);
+    assert!(!machine.cpu.regs.flags.trap);
+    machine.execute_instructions(2); // inc di, iret
+    assert_eq!(0x0118, machine.cpu.regs.ip);
+    assert!(machine.cpu.regs.flags.trap);
+
+    // 6 traced instructions with a 2 instruction handler, then the untraced nop
+    machine.execute_instructions(6 * 3 + 1);
+    assert_eq!(0x0121, machine.cpu.regs.ip);
+    assert!(!machine.cpu.regs.flags.trap);
+    assert_eq!(di.wrapping_add(7), machine.cpu.get_r16(R::DI));
+    assert_eq!(sp, machine.cpu.get_r16(R::SP));
+}
+
+#[test]
+fn can_hold_off_trap_after_mov_ss() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0x8C, 0xD0,     // mov ax,ss
+        0x8E, 0xD0,     // mov ss,ax
+        0x90,           // nop
+        0x16,           // push ss
+        0x17,           // pop ss
+        0x90,           // nop
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instruction(); // mov ax,ss
+    machine.cpu.regs.flags.trap = true;
+
+    machine.execute_instruction(); // mov ss,ax
+    assert_eq!(0x085F, machine.cpu.get_r16(R::CS));
+    assert_eq!(0x0104, machine.cpu.regs.ip);
+    machine.execute_instruction(); // nop, traps to the default int 1 handler
+    assert_eq!(0xF000, machine.cpu.get_r16(R::CS));
+    assert_eq!(BIOS::default_handler(0x01), machine.cpu.regs.ip);
+    machine.execute_instruction(); // iret
+    assert_eq!(0x085F, machine.cpu.get_r16(R::CS));
+    assert_eq!(0x0105, machine.cpu.regs.ip);
+
+    machine.execute_instruction(); // push ss, traps
+    assert_eq!(0xF000, machine.cpu.get_r16(R::CS));
+    machine.execute_instruction(); // iret
+    machine.execute_instruction(); // pop ss
+    assert_eq!(0x085F, machine.cpu.get_r16(R::CS));
+    assert_eq!(0x0107, machine.cpu.regs.ip);
+    machine.execute_instruction(); // nop, traps
+    assert_eq!(0xF000, machine.cpu.get_r16(R::CS));
+}
+
+#[test]
+fn can_chain_timer_tick_to_int1c() {
+    // hooks INT 1C, counting the ticks in DI
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0x31, 0xC0,                                 // xor ax,ax
+        0x8E, 0xC0,                                 // mov es,ax
+        0x26, 0xC7, 0x06, 0x70, 0x00, 0x21, 0x01,   // mov word [es:0x70],0x121
+        0x26, 0x8C, 0x0E, 0x72, 0x00,               // mov [es:0x72],cs
+        0xB0, 0x34,                                 // mov al,0x34
+        0xE6, 0x43,                                 // out 0x43,al      ; timer 0, rate generator
+        0xB0, 0x00,                                 // mov al,0x0
+        0xE6, 0x40,                                 // out 0x40,al
+        0xB0, 0x01,                                 // mov al,0x1
+        0xE6, 0x40,                                 // out 0x40,al      ; reload value 0x100
+        0x31, 0xFF,                                 // xor di,di
+        0xFB,                                       // sti
+        0xEB, 0xFE,                                 // jmp short 0x11f
+        0x47,                                       // inc di           ; int 1c handler
+        0xCF,                                       // iret
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(5000);
+
+    let ticks = machine.mmu.read_u32(BIOS::DATA_SEG, BIOS::DATA_TIMER_TICKS);
+    assert!(ticks >= 4);
+    assert_eq!(ticks, u32::from(machine.cpu.get_r16(R::DI)));
+}
+
+#[test]
+fn can_run_each_timer_interrupt_of_a_long_step() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xFB,       // sti
+        0xEB, 0xFE, // jmp short 0x101
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instruction();
+    let ticks = machine.mmu.read_u32(BIOS::DATA_SEG, BIOS::DATA_TIMER_TICKS);
+    // three periods of timer 0 pass in one step
+    let period = 0x1_0000 * machine.cpu.clock_hz / 1_193_182 + 1;
+    machine.update_components(machine.cpu.cycle_count.wrapping_sub(3 * period));
+    machine.execute_instructions(300);
+    assert_eq!(ticks + 3, machine.mmu.read_u32(BIOS::DATA_SEG, BIOS::DATA_TIMER_TICKS));
+}
+
+#[test]
+fn dos_and_rtc_time_follow_the_machine_clock() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB4, 0x2C,     // mov ah,0x2c
+        0xCD, 0x21,     // int 0x21
+        0x89, 0xCB,     // mov bx,cx
+        0xB4, 0x02,     // mov ah,0x2
+        0xCD, 0x1A,     // int 0x1a
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.set_clock(Clock::starting_at(chrono::NaiveDate::from_ymd(1994, 6, 1).and_hms(12, 30, 30)));
+
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    machine.execute_instructions(4);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x0C1E, machine.cpu.get_r16(R::BX)); // 12:30, from DOS
+    assert_eq!(0x1230, machine.cpu.get_r16(R::CX)); // 12:30, BCD
+    assert_eq!(0x30, machine.cpu.get_r8(R::DH));
+}
+
+#[test]
+fn can_set_dos_date_and_time() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB9, 0xCF, 0x07,   // mov cx,1999
+        0xBA, 0x1F, 0x0C,   // mov dx,0xc1f
+        0xB4, 0x2B,         // mov ah,0x2b
+        0xCD, 0x21,         // int 0x21
+        0xB9, 0x3B, 0x17,   // mov cx,0x173b
+        0xBA, 0x00, 0x3A,   // mov dx,0x3a00
+        0xB4, 0x2D,         // mov ah,0x2d
+        0xCD, 0x21,         // int 0x21
+        0xB4, 0x2A,         // mov ah,0x2a
+        0xCD, 0x21,         // int 0x21
+        0xB4, 0x04,         // mov ah,0x4
+        0xCD, 0x1A,         // int 0x1a
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(4);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x00, machine.cpu.get_r8(R::AL));
+    machine.execute_instructions(4);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x00, machine.cpu.get_r8(R::AL));
+    assert_eq!(BIOS::ticks_of_time(chrono::NaiveTime::from_hms(23, 59, 58)), machine.mmu.read_u32(BIOS::DATA_SEG, BIOS::DATA_TIMER_TICKS));
+
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(1999, machine.cpu.get_r16(R::CX));
+    assert_eq!(0x0C1F, machine.cpu.get_r16(R::DX));
+    assert_eq!(5, machine.cpu.get_r8(R::AL)); // friday
+
+    // the real-time clock follows
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x1999, machine.cpu.get_r16(R::CX));
+    assert_eq!(0x1231, machine.cpu.get_r16(R::DX));
+}
+
+#[test]
+fn can_roll_over_timer_ticks_at_midnight() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB4, 0x00,     // mov ah,0x0
+        0xCD, 0x1A,     // int 0x1a
+        0x88, 0xC3,     // mov bl,al
+        0xB4, 0x00,     // mov ah,0x0
+        0xCD, 0x1A,     // int 0x1a
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.mmu.write_u32(BIOS::DATA_SEG, BIOS::DATA_TIMER_TICKS, 0x0018_00AF);
+    machine.bios.timer_tick(&mut machine.mmu);
+    assert_eq!(0, machine.mmu.read_u32(BIOS::DATA_SEG, BIOS::DATA_TIMER_TICKS));
+
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x0000, machine.cpu.get_r16(R::CX));
+    assert_eq!(0x0000, machine.cpu.get_r16(R::DX));
+    assert_eq!(0x01, machine.cpu.get_r8(R::BL)); // midnight passed
+    assert_eq!(0x00, machine.cpu.get_r8(R::AL)); // cleared by the first read
+}
+
 #[test]
 fn can_execute_xlatb() {
     let mut machine = Machine::deterministic();
@@ -1991,12 +2474,12 @@ fn can_execute_cmpsw() {
     machine.execute_instructions(5);
     // xxx only results in regs ...
     // dosbox regs:
-    //assert_eq!(false, machine.cpu.regs.flags.carry); // XXX
-    //assert_eq!(false, machine.cpu.regs.flags.zero);
-    //assert_eq!(false, machine.cpu.regs.flags.sign);
-    //assert_eq!(true, machine.cpu.regs.flags.overflow);
-    //assert_eq!(false, machine.cpu.regs.flags.adjust);
-    //assert_eq!(true, machine.cpu.regs.flags.parity);
+    //assert_eq!(false, machine.cpu.regs.flags.carry()); // XXX
+    //assert_eq!(false, machine.cpu.regs.flags.zero());
+    //assert_eq!(false, machine.cpu.regs.flags.sign());
+    //assert_eq!(true, machine.cpu.regs.flags.overflow());
+    //assert_eq!(false, machine.cpu.regs.flags.adjust());
+    //assert_eq!(true, machine.cpu.regs.flags.parity());
 }
 
 #[test]
@@ -2010,12 +2493,12 @@ fn can_execute_shld() {
     machine.load_executable(&code, 0x085F);
     machine.execute_instructions(3);
     assert_eq!(0x8822, machine.cpu.get_r16(R::BX));
-    assert_eq!(false, machine.cpu.regs.flags.carry);
-    assert_eq!(true, machine.cpu.regs.flags.overflow);
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(true, machine.cpu.regs.flags.sign);
-    // assert_eq!(false, machine.cpu.regs.flags.adjust); // XXX dosbox: C0 Z0 S1 O1 A0 P1
-    assert_eq!(true, machine.cpu.regs.flags.parity);
+    assert_eq!(false, machine.cpu.regs.flags.carry());
+    assert_eq!(true, machine.cpu.regs.flags.overflow());
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(true, machine.cpu.regs.flags.sign());
+    // assert_eq!(false, machine.cpu.regs.flags.adjust()); // XXX dosbox: C0 Z0 S1 O1 A0 P1
+    assert_eq!(true, machine.cpu.regs.flags.parity());
 }
 
 #[test]
@@ -2133,12 +2616,12 @@ fn can_execute_shrd() {
     machine.execute_instruction();
     assert_eq!(0xFFFF, machine.cpu.get_r16(R::AX));
 
-    // assert_eq!(true, machine.cpu.regs.flags.carry); xxx should be set
-    assert_eq!(false, machine.cpu.regs.flags.zero);
-    assert_eq!(true, machine.cpu.regs.flags.sign);
-    assert_eq!(false, machine.cpu.regs.flags.overflow);
-    assert_eq!(false, machine.cpu.regs.flags.adjust);
-    assert_eq!(true, machine.cpu.regs.flags.parity);
+    // assert_eq!(true, machine.cpu.regs.flags.carry()); xxx should be set
+    assert_eq!(false, machine.cpu.regs.flags.zero());
+    assert_eq!(true, machine.cpu.regs.flags.sign());
+    assert_eq!(false, machine.cpu.regs.flags.overflow());
+    assert_eq!(false, machine.cpu.regs.flags.adjust());
+    assert_eq!(true, machine.cpu.regs.flags.parity());
 }
 
 #[test]
@@ -2228,28 +2711,1804 @@ fn can_execute_operand_and_address_prefix() {
 }
 
 #[test]
-fn estimate_mips() {
-    use std::time::Instant;
-
+fn can_execute_self_modifying_code() {
     let mut machine = Machine::deterministic();
     let code: Vec<u8> = vec![
-        0xB9, 0xFF, 0xFF, // mov cx,0xffff
-        0x49,             // dec cx
-        0xEB, 0xFA,       // jmp short 0x100
+        0xB8, 0x10, 0x00,               // mov ax,0x10
+        0x48,                           // dec ax
+        0xC6, 0x06, 0x03, 0x01, 0x40,   // mov byte [0x103],0x40
+        0xEB, 0xF8,                     // jmp short 0x103
     ];
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(4);
+    assert_eq!(0x0F, machine.cpu.get_r16(R::AX));
+    assert_eq!(0x103, machine.cpu.regs.ip);
+
+    // the cached "dec ax" was overwritten with "inc ax"
+    machine.execute_instruction();
+    assert_eq!(0x10, machine.cpu.get_r16(R::AX));
+}
+
+/// a device living outside of the core, used to test component registration
+#[derive(Default)]
+struct Latch {
+    value: u8,
+}
+
+impl Component for Latch {
+    fn io_ports(&self) -> Vec<RangeInclusive<u16>> {
+        vec![0x0300..=0x0300]
+    }
+
+    fn interrupts(&self) -> Vec<u8> {
+        vec![0x66]
+    }
+
+    fn in_u8(&mut self, _port: u16) -> Option<u8> {
+        Some(self.value)
+    }
 
+    fn out_u8(&mut self, _port: u16, data: u8) -> bool {
+        self.value = data;
+        true
+    }
+
+    fn int(&mut self, _int: u8, cpu: &mut CPU, _mmu: &mut MMU) -> bool {
+        cpu.set_r8(R::AL, self.value.wrapping_add(1));
+        true
+    }
+}
+
+#[test]
+fn can_register_component() {
+    let mut machine = Machine::deterministic();
+    machine.register_component(Box::new(Latch::default()));
+    let code: Vec<u8> = vec![
+        0xBA, 0x00, 0x03,   // mov dx,0x300
+        0xB0, 0x41,         // mov al,0x41
+        0xEE,               // out dx,al
+        0xEC,               // in al,dx
+        0x88, 0xC3,         // mov bl,al
+        0xCD, 0x66,         // int 0x66
+    ];
     machine.load_executable(&code, 0x085F);
 
-    // run for 1 sec
-    const RUN_SECONDS: u64 = 1;
-    let start = Instant::now();
-    loop {
-        machine.execute_instruction();
-        if  start.elapsed().as_secs() >= RUN_SECONDS {
-            break
-        }
+    machine.execute_instructions(6);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x41, machine.component::<Latch>().unwrap().value);
+    assert_eq!(0x41, machine.cpu.get_r8(R::BL));
+    assert_eq!(0x42, machine.cpu.get_r8(R::AL));
+}
+
+/// a device with a 16-bit register at ports 0310h-0311h
+#[derive(Default)]
+struct WordLatch {
+    value: u16,
+    byte_writes: usize,
+}
+
+impl Component for WordLatch {
+    fn io_ports(&self) -> Vec<RangeInclusive<u16>> {
+        vec![0x0310..=0x0311]
     }
 
-    let mips = (machine.cpu.instruction_count as f64) / 1_000_000.;
-    println!("MIPS: {}", mips);
+    fn in_u8(&mut self, port: u16) -> Option<u8> {
+        Some((self.value >> ((port & 1) * 8)) as u8)
+    }
+
+    fn out_u8(&mut self, _port: u16, _data: u8) -> bool {
+        self.byte_writes += 1;
+        true
+    }
+
+    fn in_u16(&mut self, _port: u16) -> Option<u16> {
+        Some(self.value)
+    }
+
+    fn out_u16(&mut self, _port: u16, data: u16) -> bool {
+        self.value = data;
+        true
+    }
+}
+
+#[test]
+fn can_use_word_io_ports() {
+    let mut machine = Machine::deterministic();
+    machine.register_component(Box::new(Latch::default()));
+    machine.register_component(Box::new(WordLatch::default()));
+    let code: Vec<u8> = vec![
+        0xBA, 0x10, 0x03,   // mov dx,0x310
+        0xB8, 0x34, 0x12,   // mov ax,0x1234
+        0xEF,               // out dx,ax
+        0x31, 0xC0,         // xor ax,ax
+        0xED,               // in ax,dx
+        0x89, 0xC3,         // mov bx,ax
+        0xBA, 0x00, 0x03,   // mov dx,0x300
+        0xB8, 0x41, 0x42,   // mov ax,0x4241
+        0xEF,               // out dx,ax
+        0xED,               // in ax,dx
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(10);
+    assert_eq!(0x1234, machine.component::<WordLatch>().unwrap().value);
+    assert_eq!(0, machine.component::<WordLatch>().unwrap().byte_writes);
+    assert_eq!(0x1234, machine.cpu.get_r16(R::BX));
+
+    // port 0301h is not handled by the byte-wide latch, so the access is split into bytes
+    assert_eq!(0x41, machine.component::<Latch>().unwrap().value);
+    assert_eq!(0x0041, machine.cpu.get_r16(R::AX));
+}
+
+#[test]
+fn can_use_country_info_and_codepage() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB8, 0x00, 0x38,           // mov ax,0x3800
+        0xBA, 0x00, 0x02,           // mov dx,0x200
+        0xCD, 0x21,                 // int 0x21
+        0xB0, 0x81,                 // mov al,0x81
+        0x9A, 0xF0, 0x00, 0x70, 0x00, // call 0x70:0xf0
+        0xB8, 0x02, 0x66,           // mov ax,0x6602
+        0xBB, 0x52, 0x03,           // mov bx,0x352
+        0xBA, 0x52, 0x03,           // mov dx,0x352
+        0xCD, 0x21,                 // int 0x21
+        0xB4, 0x02,                 // mov ah,0x2
+        0xB2, 0x9B,                 // mov dl,0x9b
+        0xCD, 0x21,                 // int 0x21
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(4);
+    assert_eq!(1, machine.cpu.get_r16(R::BX));
+    assert_eq!(b"$\0\0\0\0".to_vec(), machine.mmu.read(0x085F, 0x202, 5));
+    assert_eq!(vec![0xF0, 0x00, 0x70, 0x00], machine.mmu.read(0x085F, 0x212, 4)); // case map routine
+
+    // the case map routine upper cases ü
+    machine.execute_instructions(9);
+    assert_eq!(0x9A, machine.cpu.get_r8(R::AL));
+
+    machine.execute_instructions(9);
+    assert_eq!("ø", machine.console_output());
+}
+
+/// claims INT 2Fh multiplex id C0h
+struct MultiplexHandler;
+
+impl Component for MultiplexHandler {
+    fn multiplex_ids(&self) -> Vec<u8> {
+        vec![0xC0]
+    }
+
+    fn int(&mut self, _int: u8, cpu: &mut CPU, _mmu: &mut MMU) -> bool {
+        cpu.set_r8(R::AL, 0xFF);
+        true
+    }
+}
+
+#[test]
+fn can_register_multiplex_id() {
+    let mut machine = Machine::deterministic();
+    machine.register_component(Box::new(MultiplexHandler));
+    let code: Vec<u8> = vec![
+        0xB8, 0x00, 0xC0,   // mov ax,0xc000
+        0xCD, 0x2F,         // int 0x2f
+        0x88, 0xC3,         // mov bl,al
+        0xB8, 0x00, 0x43,   // mov ax,0x4300
+        0xCD, 0x2F,         // int 0x2f
+        0xBB, 0x34, 0x12,   // mov bx,0x1234
+        0xB8, 0x00, 0x15,   // mov ax,0x1500
+        0xCD, 0x2F,         // int 0x2f
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(4);
+    assert_eq!(0xFF, machine.cpu.get_r8(R::BL));
+
+    // unclaimed ids report not installed
+    machine.execute_instructions(3);
+    assert_eq!(0x00, machine.cpu.get_r8(R::AL));
+
+    machine.execute_instructions(4);
+    assert_eq!(0x0000, machine.cpu.get_r16(R::BX));
+}
+
+/// a debug "print port" collecting the bytes written to it, at ports chosen by the embedder
+#[derive(Default)]
+struct PrintPort {
+    text: Vec<u8>,
+}
+
+impl Component for PrintPort {
+    fn out_u8(&mut self, _port: u16, data: u8) -> bool {
+        self.text.push(data);
+        true
+    }
+
+    fn int(&mut self, _int: u8, cpu: &mut CPU, _mmu: &mut MMU) -> bool {
+        cpu.set_r16(R::AX, self.text.len() as u16);
+        true
+    }
+}
+
+#[test]
+fn can_register_external_component() {
+    let mut machine = Machine::deterministic();
+    machine.register_external_component(Box::new(PrintPort::default()), &[0x00E9..=0x00E9], &[0x65]).unwrap();
+    let code: Vec<u8> = vec![
+        0xB0, 0x68,         // mov al,0x68
+        0xE6, 0xE9,         // out 0xe9,al
+        0xB0, 0x69,         // mov al,0x69
+        0xE6, 0xE9,         // out 0xe9,al
+        0xCD, 0x65,         // int 0x65
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(5);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(b"hi".to_vec(), machine.component::<PrintPort>().unwrap().text);
+    assert_eq!(0x0002, machine.cpu.get_r16(R::AX));
+
+    // ports, interrupts and multiplex ids of other components are refused
+    assert_eq!(Err("port 00E9 is already handled".to_owned()), machine.register_external_component(Box::new(PrintPort::default()), &[0x00E8..=0x00E9], &[]));
+    assert_eq!(Err("port 0060 is already handled".to_owned()), machine.register_external_component(Box::new(PrintPort::default()), &[0x0060..=0x0060], &[]));
+    assert_eq!(Err("interrupt 10 is already handled".to_owned()), machine.register_external_component(Box::new(PrintPort::default()), &[], &[0x10]));
+
+    // so are the vectors of the built-in DOS and BIOS, and the ports handled by the machine
+    assert_eq!(Err("interrupt 21 is already handled".to_owned()), machine.register_external_component(Box::new(PrintPort::default()), &[], &[0x21]));
+    assert_eq!(Err("interrupt 15 is already handled".to_owned()), machine.register_external_component(Box::new(PrintPort::default()), &[], &[0x15]));
+    assert_eq!(Err("port 0092 is already handled".to_owned()), machine.register_external_component(Box::new(PrintPort::default()), &[0x0090..=0x0093], &[]));
+    assert_eq!(Err("port 0201 is already handled".to_owned()), machine.register_external_component(Box::new(PrintPort::default()), &[0x0201..=0x0201], &[]));
+    machine.register_component(Box::new(MultiplexHandler));
+    assert!(machine.register_external_component(Box::new(MultiplexHandler), &[], &[]).is_err());
+}
+
+#[test]
+fn estimate_mips() {
+    use std::time::Instant;
+
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB9, 0xFF, 0xFF, // mov cx,0xffff
+        0x49,             // dec cx
+        0xEB, 0xFA,       // jmp short 0x100
+    ];
+
+    machine.load_executable(&code, 0x085F);
+
+    // run for 1 sec
+    const RUN_SECONDS: u64 = 1;
+    let start = Instant::now();
+    loop {
+        machine.execute_instruction();
+        if  start.elapsed().as_secs() >= RUN_SECONDS {
+            break
+        }
+    }
+
+    let mips = (machine.cpu.instruction_count as f64) / 1_000_000.;
+    println!("MIPS: {}", mips);
+}
+
+#[test]
+fn can_get_extended_memory_size_and_system_config() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB4, 0x88,     // mov ah,0x88
+        0xCD, 0x15,     // int 0x15
+        0x89, 0xC1,     // mov cx,ax
+        0xB4, 0xC0,     // mov ah,0xc0
+        0xCD, 0x15,     // int 0x15
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x0C00, machine.cpu.get_r16(R::CX)); // 3 MB above 1 MB
+    assert_eq!(0x00, machine.cpu.get_r8(R::AH));
+    assert!(!machine.cpu.regs.flags.carry());
+
+    let es = machine.cpu.get_r16(R::ES);
+    let bx = machine.cpu.get_r16(R::BX);
+    assert_eq!(0xF000, es);
+    assert_eq!(8, machine.mmu.read_u16(es, bx)); // table length
+    assert_eq!(0xFC, machine.mmu.read_u8(es, bx + 2)); // model: AT
+}
+
+#[test]
+fn can_wait_microseconds() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB9, 0x01, 0x00,   // mov cx,0x1
+        0xBA, 0x00, 0x00,   // mov dx,0x0
+        0xB4, 0x86,         // mov ah,0x86
+        0xCD, 0x15,         // int 0x15     ; wait 65536 us
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(4);
+    let cycles = machine.cpu.cycle_count;
+    machine.execute_instruction(); // trigger the interrupt
+    while machine.cpu.regs.ip != 0x010A {
+        machine.execute_instruction();
+    }
+    let waited = (machine.cpu.cycle_count - cycles) as u64;
+    assert!(waited >= 65_536 * machine.cpu.clock_hz as u64 / 1_000_000);
+    assert_eq!(0x00, machine.cpu.get_r8(R::AH));
+    assert!(!machine.cpu.regs.flags.carry());
+}
+
+#[test]
+fn can_count_timer_ticks_while_waiting() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB9, 0x0F, 0x00,   // mov cx,0xf
+        0xBA, 0x40, 0x42,   // mov dx,0x4240
+        0xB4, 0x86,         // mov ah,0x86
+        0xCD, 0x15,         // int 0x15     ; wait 1 second
+    ];
+    machine.load_executable(&code, 0x085F);
+    let ticks = machine.mmu.read_u32(BIOS::DATA_SEG, BIOS::DATA_TIMER_TICKS);
+    machine.execute_instructions(4);
+    machine.execute_instruction();
+    while machine.cpu.regs.ip != 0x010A {
+        machine.execute_instruction();
+    }
+    // the timer ticks 18.2 times a second
+    let waited = machine.mmu.read_u32(BIOS::DATA_SEG, BIOS::DATA_TIMER_TICKS) - ticks;
+    assert!((17..=19).contains(&waited), "{} ticks", waited);
+}
+
+#[test]
+fn can_get_rtc_time_and_date() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB4, 0x02,     // mov ah,0x2
+        0xCD, 0x1A,     // int 0x1a
+        0x89, 0xCB,     // mov bx,cx
+        0x89, 0xD7,     // mov di,dx
+        0xB4, 0x04,     // mov ah,0x4
+        0xCD, 0x1A,     // int 0x1a
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    machine.execute_instructions(4);
+    machine.execute_instruction(); // trigger the interrupt
+
+    // deterministic mode starts at 2000-01-01 00:00:00
+    assert_eq!(0x0000, machine.cpu.get_r16(R::BX));
+    assert_eq!(0x0000, machine.cpu.get_r16(R::DI));
+    assert_eq!(0x2000, machine.cpu.get_r16(R::CX));
+    assert_eq!(0x0101, machine.cpu.get_r16(R::DX));
+    assert!(!machine.cpu.regs.flags.carry());
+}
+
+#[test]
+fn can_gate_a20_line() {
+    // the classic A20 detection, comparing 0000:0500 with its alias FFFF:0510
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0x31, 0xC0,                         // xor ax,ax
+        0x8E, 0xD8,                         // mov ds,ax
+        0x48,                               // dec ax
+        0x8E, 0xC0,                         // mov es,ax
+        0xC6, 0x06, 0x00, 0x05, 0x12,       // mov byte [0x500],0x12
+        0x26, 0xC6, 0x06, 0x10, 0x05, 0x34, // mov byte [es:0x510],0x34
+        0x8A, 0x0E, 0x00, 0x05,             // mov cl,[0x500]
+        0xE4, 0x92,                         // in al,0x92
+        0x0C, 0x02,                         // or al,0x2
+        0xE6, 0x92,                         // out 0x92,al      ; fast A20 enable
+        0xC6, 0x06, 0x00, 0x05, 0x12,       // mov byte [0x500],0x12
+        0x26, 0xC6, 0x06, 0x10, 0x05, 0x34, // mov byte [es:0x510],0x34
+        0x8A, 0x16, 0x00, 0x05,             // mov dl,[0x500]
+        0xB0, 0xD1,                         // mov al,0xd1
+        0xE6, 0x64,                         // out 0x64,al      ; write output port
+        0xB0, 0xDD,                         // mov al,0xdd
+        0xE6, 0x60,                         // out 0x60,al      ; A20 disabled
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(7);
+    assert_eq!(0x34, machine.cpu.get_r8(R::CL)); // wrapped around at 1 MB
+    assert!(!machine.mmu.memory.a20());
+
+    machine.execute_instructions(6);
+    assert!(machine.mmu.memory.a20());
+    assert_eq!(0x12, machine.cpu.get_r8(R::DL));
+    assert_eq!(0x34, machine.mmu.memory.read_u8(0x10_0500));
+
+    machine.execute_instructions(4);
+    assert!(!machine.mmu.memory.a20());
+}
+
+#[test]
+fn can_configure_memory_size() {
+    let mut machine = Machine::with_config(MachineConfig {
+        conventional_kb: 512,
+        extended_kb: 1024,
+        ..MachineConfig::default()
+    });
+    let code: Vec<u8> = vec![
+        0xCD, 0x12,     // int 0x12
+        0x89, 0xC3,     // mov bx,ax
+        0xB4, 0x88,     // mov ah,0x88
+        0xCD, 0x15,     // int 0x15
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instruction();
+    machine.execute_instruction(); // trigger the interrupt
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(512, machine.cpu.get_r16(R::BX));
+    assert_eq!(1024, machine.cpu.get_r16(R::AX));
+
+    // memory above the configured size is not backed
+    machine.mmu.memory.set_a20(true);
+    machine.mmu.memory.write_u8(0x20_0000, 0x12);
+    assert_eq!(0xFF, machine.mmu.memory.read_u8(0x20_0000));
+}
+
+#[test]
+fn can_chain_hooked_interrupt_to_default_handler() {
+    let mut machine = Machine::deterministic();
+    let mut code: Vec<u8> = vec![
+        0xB8, 0x21, 0x35,               // mov ax,0x3521
+        0xCD, 0x21,                     // int 0x21
+        0x89, 0x1E, 0x30, 0x01,         // mov [0x130],bx
+        0x8C, 0x06, 0x32, 0x01,         // mov [0x132],es
+        0xB8, 0x21, 0x25,               // mov ax,0x2521
+        0xBA, 0x20, 0x01,               // mov dx,0x120
+        0xCD, 0x21,                     // int 0x21
+        0xB4, 0x02,                     // mov ah,0x2
+        0xB2, 0x41,                     // mov dl,0x41
+        0xCD, 0x21,                     // int 0x21
+        0xF4,                           // hlt
+    ];
+    code.resize(0x20, 0x90);
+    code.extend(&[
+        0x2E, 0xFE, 0x06, 0x34, 0x01,   // inc byte [cs:0x134]
+        0x2E, 0xFF, 0x2E, 0x30, 0x01,   // jmp far [cs:0x130]
+    ]);
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(20);
+    assert_eq!(0xF000, machine.mmu.read_u16(0x085F, 0x132));
+    assert_eq!(BIOS::default_handler(0x21), machine.mmu.read_u16(0x085F, 0x130));
+    assert_eq!(1, machine.mmu.read_u8(0x085F, 0x134));
+    assert_eq!(b"A".to_vec(), machine.dos.stdout);
+}
+
+#[test]
+fn can_execute_hooked_int3_and_into() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xCC,               // int3
+        0xB0, 0x7F,         // mov al,0x7f
+        0xCE,               // into             ; no overflow
+        0x04, 0x01,         // add al,0x1
+        0xCE,               // into
+        0xF4,               // hlt
+        0xBB, 0x34, 0x12,   // mov bx,0x1234    ; int 3 handler
+        0xCF,               // iret
+        0xB9, 0x78, 0x56,   // mov cx,0x5678    ; int 4 handler
+        0xCF,               // iret
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.mmu.write_u16(0, 3 * 4, 0x0108);
+    machine.mmu.write_u16(0, 3 * 4 + 2, 0x085F);
+    machine.mmu.write_u16(0, 4 * 4, 0x010C);
+    machine.mmu.write_u16(0, 4 * 4 + 2, 0x085F);
+
+    machine.execute_instructions(3);
+    assert_eq!(0x1234, machine.cpu.get_r16(R::BX));
+    assert_eq!(0x0101, machine.cpu.regs.ip);
+
+    machine.execute_instructions(2);
+    assert_eq!(0x0104, machine.cpu.regs.ip);
+
+    machine.execute_instructions(4);
+    assert_eq!(0x5678, machine.cpu.get_r16(R::CX));
+    assert_eq!(0x0107, machine.cpu.regs.ip);
+}
+
+#[test]
+fn can_handle_invalid_opcodes_by_policy() {
+    let code: Vec<u8> = vec![
+        0x0F, 0xFF,         // invalid opcode
+        0xBB, 0x34, 0x12,   // mov bx,0x1234
+        0xF4,               // hlt
+        0x89, 0xE5,         // mov bp,sp        ; int 6 handler, skips the invalid opcode
+        0x83, 0x46, 0x00, 0x02, // add word [bp+0x0],byte +0x2
+        0xCF,               // iret
+    ];
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instruction();
+    assert!(machine.cpu.fatal_error);
+    assert_eq!(0x0100, machine.cpu.regs.ip);
+
+    let mut machine = Machine::with_config(MachineConfig {
+        invalid_opcode: UnknownPolicy::SkipAndLog,
+        ..MachineConfig::default()
+    });
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(2);
+    assert!(!machine.cpu.fatal_error);
+    assert_eq!(0x1234, machine.cpu.get_r16(R::BX));
+
+    let mut machine = Machine::deterministic();
+    machine.invalid_opcode = UnknownPolicy::RaiseUD;
+    machine.load_executable(&code, 0x085F);
+    machine.mmu.write_u16(0, 6 * 4, 0x0106);
+    machine.mmu.write_u16(0, 6 * 4 + 2, 0x085F);
+    machine.execute_instruction();
+    assert_eq!(0x0106, machine.cpu.regs.ip);
+    machine.execute_instructions(4);
+    assert!(!machine.cpu.fatal_error);
+    assert_eq!(0x1234, machine.cpu.get_r16(R::BX));
+
+    // without a handler, execution stops in the default INT 6 handler
+    let mut machine = Machine::deterministic();
+    machine.invalid_opcode = UnknownPolicy::RaiseUD;
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(2);
+    assert!(machine.cpu.fatal_error);
+}
+
+#[test]
+fn can_raise_ud_on_unsupported_opcodes() {
+    let code: Vec<u8> = vec![
+        0x0F, 0x44, 0xC3,   // cmovz ax,bx
+        0x0F, 0xA2,         // cpuid
+        0x90,               // nop
+        0xBB, 0x34, 0x12,   // mov bx,0x1234
+        0xF4,               // hlt
+        0x89, 0xE5,         // mov bp,sp        ; int 6 handler, skips 3 bytes
+        0x83, 0x46, 0x00, 0x03, // add word [bp+0x0],byte +0x3
+        0xCF,               // iret
+    ];
+    // raised regardless of the invalid opcode policy
+    let mut machine = Machine::with_config(MachineConfig {
+        invalid_opcode: UnknownPolicy::SkipAndLog,
+        ..MachineConfig::default()
+    });
+    machine.load_executable(&code, 0x085F);
+    machine.mmu.write_u16(0, 6 * 4, 0x010A);
+    machine.mmu.write_u16(0, 6 * 4 + 2, 0x085F);
+    machine.execute_instruction();
+    assert_eq!(0x010A, machine.cpu.regs.ip);
+
+    machine.execute_instructions(3);
+    assert_eq!(0x0103, machine.cpu.regs.ip);
+    machine.execute_instruction();
+    assert_eq!(0x010A, machine.cpu.regs.ip);
+    let sp = machine.cpu.get_r16(R::SP);
+    assert_eq!(0x0103, machine.mmu.read_u16(machine.cpu.get_r16(R::SS), sp));
+
+    machine.execute_instructions(4);
+    assert!(!machine.cpu.fatal_error);
+    assert_eq!(0x1234, machine.cpu.get_r16(R::BX));
+}
+
+#[test]
+fn can_stop_on_unknown_interrupt() {
+    let code: Vec<u8> = vec![
+        0xCD, 0x66,         // int 0x66
+        0xBB, 0x34, 0x12,   // mov bx,0x1234
+    ];
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(3);
+    assert_eq!(0x1234, machine.cpu.get_r16(R::BX));
+
+    let mut machine = Machine::with_config(MachineConfig::parse("unknown_interrupt = \"stop\"").unwrap());
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instruction();
+    machine.execute_instruction(); // trigger the interrupt
+    assert!(machine.cpu.fatal_error);
+    assert_eq!(0, machine.cpu.get_r16(R::BX));
+}
+
+#[test]
+fn can_deliver_divide_error() {
+    let code: Vec<u8> = vec![
+        0xB8, 0x0A, 0x00,   // mov ax,0xa
+        0xB3, 0x00,         // mov bl,0x0
+        0xF6, 0xF3,         // div bl
+        0xF4,               // hlt
+        0xB3, 0x02,         // mov bl,0x2       ; int 0 handler
+        0xCF,               // iret
+    ];
+    // the 8086 returns after the divide instruction
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&code, 0x085F);
+    machine.mmu.write_u16(0, 0, 0x0108);
+    machine.mmu.write_u16(0, 2, 0x085F);
+    machine.execute_instructions(3);
+    assert_eq!(0x0108, machine.cpu.regs.ip);
+    machine.execute_instructions(2);
+    assert_eq!(0x0107, machine.cpu.regs.ip);
+    assert_eq!(0x000A, machine.cpu.get_r16(R::AX));
+
+    // the 286 restarts it
+    let mut machine = Machine::with_config(MachineConfig {
+        cpu: CpuModel::I80286,
+        ..MachineConfig::default()
+    });
+    machine.load_executable(&code, 0x085F);
+    machine.mmu.write_u16(0, 0, 0x0108);
+    machine.mmu.write_u16(0, 2, 0x085F);
+    machine.execute_instructions(5);
+    assert_eq!(0x0105, machine.cpu.regs.ip);
+    machine.execute_instruction();
+    assert_eq!(0x0005, machine.cpu.get_r16(R::AX));
+
+    // the default handler of DOS terminates the program
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    assert!(machine.cpu.fatal_error);
+    assert_eq!(Some(0xFF), machine.exit_code());
+    assert_eq!(b"\r\nDivide overflow\r\n".to_vec(), machine.dos.stdout);
+}
+
+#[test]
+fn can_execute_8086_aliases() {
+    let mut machine = Machine::with_config(MachineConfig {
+        strict_cpu: true,
+        ..MachineConfig::default()
+    });
+    let code: Vec<u8> = vec![
+        0xB3, 0x34,     // mov bl,0x34
+        0xD0, 0xF0,     // setmo al
+        0x31, 0xC9,     // xor cx,cx
+        0xD2, 0xF3,     // setmo bl,cl      ; no change with cl = 0
+        0x6D, 0x01,     // jnl 0x10b        ; alias of 0x7D
+        0xF4,           // hlt
+        0xC7, 0xFA, 0x78, 0x56, // mov dx,0x5678 ; the reg field is ignored
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(2);
+    assert_eq!(0xFF, machine.cpu.get_r8(R::AL));
+    assert!(machine.cpu.regs.flags.sign());
+    assert!(!machine.cpu.regs.flags.carry());
+
+    machine.execute_instructions(2);
+    assert_eq!(0x34, machine.cpu.get_r8(R::BL));
+
+    machine.execute_instructions(2);
+    assert_eq!(0x010F, machine.cpu.regs.ip);
+    assert_eq!(0x5678, machine.cpu.get_r16(R::DX));
+}
+
+#[test]
+fn can_dump_memory() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB8, 0x13, 0x00,       // mov ax,0x13
+        0xCD, 0x10,             // int 0x10
+        0xB8, 0x00, 0xA0,       // mov ax,0xa000
+        0x8E, 0xC0,             // mov es,ax
+        0x26, 0xC6, 0x06, 0x01, 0x00, 0x2A, // mov byte [es:0x1],0x2a
+        0xB8, 0x12, 0x00,       // mov ax,0x12
+        0xCD, 0x10,             // int 0x10
+    ];
+    machine.load_executable(&code, 0x085F);
+    assert_eq!(vec![0xB8, 0x13, 0x00, 0xCD], machine.dump_memory(0x085F, 0x0100, 4));
+    assert_eq!(vec![0xB8, 0x13], machine.dump_memory(0x0860, 0x00F0, 2));
+
+    let small = Machine::with_config(MachineConfig {
+        extended_kb: 0,
+        ..MachineConfig::default()
+    });
+    assert_eq!(4, small.dump_memory(0xF000, 0xFFFC, 16).len()); // ends at the end of memory
+
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    machine.execute_instructions(3);
+    let video = machine.dump_video_memory();
+    assert_eq!(0x2_0000, video.len());
+    assert_eq!(0x2A, video[1]);
+
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(4 * 0x1_0000, machine.dump_video_memory().len());
+}
+
+#[test]
+fn can_detect_cpu_model_by_flags() {
+    // the classic cpu detection: bits 12-15 of FLAGS are always set on the 8086,
+    // always clear in real mode on the 286 and can be changed on the 386
+    let code: Vec<u8> = vec![
+        0xB2, 0x00,         // mov dl,0x0
+        0x9C,               // pushf
+        0x58,               // pop ax
+        0x25, 0xFF, 0x0F,   // and ax,0xfff
+        0x50,               // push ax
+        0x9D,               // popf
+        0x9C,               // pushf
+        0x58,               // pop ax
+        0x25, 0x00, 0xF0,   // and ax,0xf000
+        0x3D, 0x00, 0xF0,   // cmp ax,0xf000
+        0x74, 0x10,         // jz 0x123
+        0xB2, 0x02,         // mov dl,0x2
+        0xB8, 0x00, 0xF0,   // mov ax,0xf000
+        0x50,               // push ax
+        0x9D,               // popf
+        0x9C,               // pushf
+        0x58,               // pop ax
+        0x25, 0x00, 0xF0,   // and ax,0xf000
+        0x74, 0x02,         // jz 0x123
+        0xB2, 0x03,         // mov dl,0x3
+        0xEB, 0xFE,         // jmp short 0x123
+    ];
+    for &(cpu, expected) in &[(CpuModel::I8086, 0), (CpuModel::I80186, 0), (CpuModel::I80286, 2), (CpuModel::I80386, 3), (CpuModel::I80486, 3)] {
+        let mut machine = Machine::with_config(MachineConfig {
+            cpu,
+            ..MachineConfig::default()
+        });
+        machine.load_executable(&code, 0x085F);
+        machine.execute_instructions(20);
+        assert_eq!(0x0123, machine.cpu.regs.ip, "{:?}", cpu);
+        assert_eq!(expected, machine.cpu.get_r8(R::DL), "{:?}", cpu);
+    }
+}
+
+#[test]
+fn can_detect_cpu_model_by_quirks() {
+    let code: Vec<u8> = vec![
+        0x54,               // push sp
+        0x5B,               // pop bx
+        0x0F, 0x01, 0xE0,   // smsw ax
+        0x0F, 0x20, 0xC1,   // mov ecx,cr0
+    ];
+    for &(cpu, pushed_sp, msw, cr0) in &[
+        (CpuModel::I8086, 0xFFFC, 0x0000, 0x0000_00FF), // smsw and mov from cr0 are invalid
+        (CpuModel::I80186, 0xFFFC, 0x0000, 0x0000_00FF),
+        (CpuModel::I80286, 0xFFFE, 0xFFF0, 0x0000_00FF),
+        (CpuModel::I80386, 0xFFFE, 0x0010, 0x0000_0010),
+        (CpuModel::I80486, 0xFFFE, 0x0010, 0x0000_0010)] {
+        let mut machine = Machine::with_config(MachineConfig {
+            cpu,
+            ..MachineConfig::default()
+        });
+        machine.load_executable(&code, 0x085F);
+        assert_eq!(0xFFFE, machine.cpu.get_r16(R::SP));
+        machine.execute_instructions(4);
+        assert_eq!(pushed_sp, machine.cpu.get_r16(R::BX), "{:?}", cpu);
+        assert_eq!(msw, machine.cpu.get_r16(R::AX), "{:?}", cpu);
+        assert_eq!(cr0, machine.cpu.get_r32(R::ECX), "{:?}", cpu);
+    }
+
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&code, 0x085F);
+    let res = machine.cpu.decoder.disassemble_block_to_str(&mut machine.mmu, 0x85F, 0x102, 2);
+    assert_eq!("[085F:0102] 0F01E0           Smsw     ax
+[085F:0105] 0F20C1           Mov32    ecx, cr0", res);
+}
+
+#[test]
+fn can_execute_486_instructions() {
+    let code: Vec<u8> = vec![
+        0xB8, 0xFF, 0x00,   // mov ax,0xff
+        0xBB, 0x01, 0x00,   // mov bx,0x1
+        0x0F, 0xC0, 0xD8,   // xadd al,bl
+        0xB9, 0x34, 0x12,   // mov cx,0x1234
+        0x0F, 0xB1, 0xCB,   // cmpxchg bx,cx
+        0x0F, 0xB1, 0xCB,   // cmpxchg bx,cx
+        0x66, 0xBA, 0x78, 0x56, 0x34, 0x12, // mov edx,0x12345678
+        0x66, 0x0F, 0xCA,   // bswap edx
+    ];
+    let mut machine = Machine::with_config(MachineConfig {
+        cpu: CpuModel::I80486,
+        ..MachineConfig::default()
+    });
+    machine.load_executable(&code, 0x085F);
+
+    // xadd: al = 0xff + 0x01, bl = the old al, flags as for add
+    machine.execute_instructions(3);
+    assert_eq!(0x00, machine.cpu.get_r8(R::AL));
+    assert_eq!(0xFF, machine.cpu.get_r8(R::BL));
+    assert!(machine.cpu.regs.flags.carry());
+    assert!(machine.cpu.regs.flags.zero());
+
+    // cmpxchg: ax (0x0000) differs from bx (0x00FF), ax is loaded from bx
+    machine.execute_instructions(2);
+    assert_eq!(0x00FF, machine.cpu.get_r16(R::AX));
+    assert_eq!(0x00FF, machine.cpu.get_r16(R::BX));
+    assert!(!machine.cpu.regs.flags.zero());
+    assert!(machine.cpu.regs.flags.carry());
+
+    // ax equals bx, bx is loaded from cx
+    machine.execute_instruction();
+    assert_eq!(0x1234, machine.cpu.get_r16(R::BX));
+    assert!(machine.cpu.regs.flags.zero());
+
+    machine.execute_instructions(2);
+    assert_eq!(0x7856_3412, machine.cpu.get_r32(R::EDX));
+
+    // the 386 lacks them
+    let mut machine = Machine::with_config(MachineConfig {
+        cpu: CpuModel::I80386,
+        ..MachineConfig::default()
+    });
+    machine.load_executable(&code, 0x085F);
+    machine.mmu.write_u16(0, 6 * 4, 0x0200);
+    machine.mmu.write_u16(0, 6 * 4 + 2, 0x085F);
+    machine.execute_instructions(3);
+    assert_eq!(0x0200, machine.cpu.regs.ip);
+    assert_eq!(0x0106, machine.mmu.read_u16(machine.cpu.get_r16(R::SS), machine.cpu.get_r16(R::SP)));
+    assert_eq!(0x00FF, machine.cpu.get_r16(R::AX));
+}
+
+#[test]
+fn can_parse_machine_config() {
+    let dir = tempdir().unwrap();
+    let text = format!("cpu = \"286\"\nextended_kb = 1024\n\n[[mounts]]\ndrive = \"C\"\npath = {:?}\n", dir.path());
+    let config = MachineConfig::parse(&text).unwrap();
+    assert_eq!(CpuModel::I80286, config.cpu);
+    assert_eq!(640, config.conventional_kb);
+    assert_eq!(1024, config.extended_kb);
+    assert_eq!(vec![Mount::new('C', dir.path().to_path_buf())], config.mounts);
+    assert_eq!(12_000_000, Machine::with_config(config).cpu.clock_hz);
+
+    let config = MachineConfig::parse("cpu = \"386\"\ncpu_hz = 4770000").unwrap();
+    assert_eq!(4_770_000, Machine::with_config(config).cpu.clock_hz);
+
+    let config = MachineConfig::parse("cpu = \"186\"\nstrict_cpu = true").unwrap();
+    assert_eq!(Some(CpuModel::I80186), Machine::with_config(config).cpu.decoder.model());
+    assert_eq!(None, Machine::deterministic().cpu.decoder.model());
+
+    let config = MachineConfig::parse("[color]\nmonitor = \"amber\"\ncontrast = 120").unwrap();
+    assert_eq!(Monitor::Amber, config.color.monitor);
+    assert_eq!(Monitor::Amber, Machine::with_config(config).gpu().color.monitor);
+    assert!(MachineConfig::parse("[color]\nbrightness = 200").is_err());
+
+    assert!(MachineConfig::parse("log = \"dos=debug,io=warn\"").is_ok());
+    assert!(MachineConfig::parse("log = \"sound=debug\"").is_err());
+
+    let config = MachineConfig::parse("[stack_guard]\nenabled = true\nmax_depth = 512\naction = \"break\"").unwrap();
+    assert_eq!(Some(512), config.stack_guard.max_depth);
+    assert!(MachineConfig::parse("[stack_guard]\naction = \"panic\"").is_err());
+
+    let config = MachineConfig::parse("[network]\ntransport = \"udp\"\npeer = \"127.0.0.1:7655\"\nvector = 0x61\nirq = 5").unwrap();
+    assert_eq!(0x61, config.network.vector);
+    assert_eq!(5, config.network.irq);
+    assert!(MachineConfig::parse("[network]\nvector = 0x21").is_err());
+    assert!(MachineConfig::parse("[ipx]\ntransport = \"udp\"\nnode = \"02:49:50:58:00:02\"").is_ok());
+    assert!(MachineConfig::parse("[ipx]\nnode = \"02:49:50\"").is_err());
+
+    let config = MachineConfig::parse("audio = \"none\"\nkeyboard_layout = \"us\"").unwrap();
+    assert_eq!(AudioDevice::None, config.audio);
+    assert_eq!(KeyboardLayout::Us, config.keyboard_layout);
+    let err = MachineConfig::parse("audio = \"sb16\"").unwrap_err();
+    assert_eq!("audio device sb16 is not supported, only none is implemented", err.to_string());
+    let err = MachineConfig::parse("keyboard_layout = \"de\"").unwrap_err();
+    assert_eq!("keyboard layout de is not supported, only us is implemented", err.to_string());
+    assert!(MachineConfig::parse("audio = \"gus\"").is_err());
+    assert!(MachineConfig::parse("keyboard_layout = \"xx\"").is_err());
+    assert_eq!(Ok(AudioDevice::AdLib), "adlib".parse());
+    assert_eq!(Ok(KeyboardLayout::De), "DE".parse());
+    assert!("gus".parse::<AudioDevice>().is_err());
+
+    assert!(MachineConfig::parse("graphic_card = \"cga\"").is_err());
+    assert!(MachineConfig::parse("memory = 640").is_err());
+}
+
+#[test]
+fn can_open_file_on_mounted_drive() {
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join("DIR")).unwrap();
+    fs::write(dir.path().join("DIR").join("FILE.TXT"), b"hi").unwrap();
+
+    let mut machine = Machine::with_config(MachineConfig {
+        mounts: vec![Mount::new('C', dir.path().to_path_buf())],
+        ..MachineConfig::default()
+    });
+    let mut code: Vec<u8> = vec![
+        0xB4, 0x3D,         // mov ah,0x3d
+        0xB0, 0x00,         // mov al,0x0
+        0xBA, 0x15, 0x01,   // mov dx,0x115
+        0xCD, 0x21,         // int 0x21
+        0x89, 0xC3,         // mov bx,ax
+        0xB4, 0x3F,         // mov ah,0x3f
+        0xB9, 0x02, 0x00,   // mov cx,0x2
+        0xBA, 0x00, 0x02,   // mov dx,0x200
+        0xCD, 0x21,         // int 0x21
+    ];
+    code.extend_from_slice(b"c:\\DIR\\FILE.TXT\0");
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(5);
+    assert!(!machine.cpu.regs.flags.carry());
+    assert_eq!(0x0005, machine.cpu.get_r16(R::AX));
+
+    machine.execute_instructions(6);
+    assert_eq!(2, machine.cpu.get_r16(R::AX));
+    assert_eq!(b"hi".to_vec(), machine.mmu.read(0x085F, 0x0200, 2));
+}
+
+#[test]
+fn can_create_temporary_file() {
+    let dir = tempdir().unwrap();
+    let create = |seed| {
+        let mut machine = Machine::with_config(MachineConfig {
+            mounts: vec![Mount::new('C', dir.path().to_path_buf())],
+            ..MachineConfig::default()
+        });
+        machine.seed_rng(seed);
+        let mut code: Vec<u8> = vec![
+            0xB4, 0x5A,         // mov ah,0x5a
+            0x31, 0xC9,         // xor cx,cx
+            0xBA, 0x09, 0x01,   // mov dx,0x109
+            0xCD, 0x21,         // int 0x21
+        ];
+        code.extend_from_slice(b"c:\\\0");
+        code.extend_from_slice(&[0; 13]);
+        machine.load_executable(&code, 0x085F);
+        machine.execute_instructions(4);
+        machine.execute_instruction(); // trigger the interrupt
+        assert!(!machine.cpu.regs.flags.carry());
+        assert_eq!(0x0005, machine.cpu.get_r16(R::AX));
+        let path = machine.mmu.readz(0x085F, 0x0109);
+        assert_eq!(b"c:\\".to_vec(), path[..3].to_vec());
+        assert_eq!(11, path.len());
+        String::from_utf8(path[3..].to_vec()).unwrap()
+    };
+
+    let name = create(1);
+    assert!(dir.path().join(&name).exists());
+
+    // the same seed gives the same name, which is taken now
+    assert_ne!(name, create(1));
+}
+
+#[test]
+fn can_move_machine_to_another_thread() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB8, 0x34, 0x12,   // mov ax,0x1234
+    ];
+    machine.load_executable(&code, 0x085F);
+    let machine = std::thread::spawn(move || {
+        machine.execute_instruction();
+        machine
+    }).join().unwrap();
+    assert_eq!(0x1234, machine.cpu.get_r16(R::AX));
+}
+
+#[test]
+fn can_select_drive_and_get_free_disk_space() {
+    let dir = tempdir().unwrap();
+    let mut machine = Machine::with_config(MachineConfig {
+        mounts: vec![Mount::new('D', dir.path().to_path_buf())],
+        ..MachineConfig::default()
+    });
+    let code: Vec<u8> = vec![
+        0xB4, 0x0E,         // mov ah,0xe
+        0xB2, 0x03,         // mov dl,0x3
+        0xCD, 0x21,         // int 0x21
+        0xB4, 0x19,         // mov ah,0x19
+        0xCD, 0x21,         // int 0x21
+        0xB4, 0x36,         // mov ah,0x36
+        0xB2, 0x00,         // mov dl,0x0
+        0xCD, 0x21,         // int 0x21
+        0xB4, 0x36,         // mov ah,0x36
+        0xB2, 0x1A,         // mov dl,0x1a
+        0xCD, 0x21,         // int 0x21
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(4);
+    assert_eq!(5, machine.cpu.get_r8(R::AL));
+    assert_eq!("D:\\", machine.dos.current_dir);
+
+    machine.execute_instructions(3);
+    assert_eq!(3, machine.cpu.get_r8(R::AL));
+
+    machine.execute_instructions(4);
+    assert_eq!(64, machine.cpu.get_r16(R::AX));
+    assert_eq!(0x8000, machine.cpu.get_r16(R::BX));
+    assert_eq!(512, machine.cpu.get_r16(R::CX));
+    assert_eq!(0xFFFF, machine.cpu.get_r16(R::DX));
+
+    machine.execute_instructions(4);
+    assert_eq!(0xFFFF, machine.cpu.get_r16(R::AX));
+}
+
+#[test]
+fn can_get_allocation_information() {
+    let dir = tempdir().unwrap();
+    let mut machine = Machine::with_config(MachineConfig {
+        mounts: vec![Mount {
+            total_kb: Some(10 * 1024),
+            free_kb: Some(4096),
+            sectors_per_cluster: Some(8),
+            ..Mount::new('C', dir.path().to_path_buf())
+        }],
+        ..MachineConfig::default()
+    });
+    let code: Vec<u8> = vec![
+        0xB4, 0x1C,         // mov ah,0x1c
+        0xB2, 0x03,         // mov dl,0x3
+        0xCD, 0x21,         // int 0x21
+        0xB4, 0x36,         // mov ah,0x36
+        0xB2, 0x03,         // mov dl,0x3
+        0xCD, 0x21,         // int 0x21
+        0xB4, 0x1C,         // mov ah,0x1c
+        0xB2, 0x1A,         // mov dl,0x1a
+        0xCD, 0x21,         // int 0x21
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(4);
+    assert_eq!(8, machine.cpu.get_r8(R::AL));
+    assert_eq!(512, machine.cpu.get_r16(R::CX));
+    assert_eq!(2560, machine.cpu.get_r16(R::DX));
+    let (ds, bx) = (machine.cpu.get_r16(R::DS), machine.cpu.get_r16(R::BX));
+    assert_eq!(0xF8, machine.mmu.read_u8(ds, bx));
+
+    machine.execute_instructions(4);
+    assert_eq!(8, machine.cpu.get_r16(R::AX));
+    assert_eq!(1024, machine.cpu.get_r16(R::BX));
+    assert_eq!(2560, machine.cpu.get_r16(R::DX));
+
+    machine.execute_instructions(4);
+    assert_eq!(0xFF, machine.cpu.get_r8(R::AL));
+}
+
+#[test]
+fn can_report_dos_version() {
+    let config = MachineConfig::parse("dos_version = \"6.22\"\n[setver]\n\"tc.exe\" = \"3.30\"\n").unwrap();
+    let mut machine = Machine::with_config(config);
+    let code: Vec<u8> = vec![
+        0xB8, 0x00, 0x30,   // mov ax,0x3000
+        0xCD, 0x21,         // int 0x21
+        0x89, 0xC1,         // mov cx,ax
+        0xB8, 0x06, 0x33,   // mov ax,0x3306
+        0xCD, 0x21,         // int 0x21
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.dos.program_path = "/home/user/tc/TC.EXE".to_owned();
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x1E03, machine.cpu.get_r16(R::AX));
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x1606, machine.cpu.get_r16(R::BX));
+
+    assert!(MachineConfig::parse("dos_version = \"6.x\"").is_err());
+}
+
+#[test]
+fn can_get_and_set_device_information() {
+    let code: Vec<u8> = vec![
+        0xB8, 0x00, 0x44,   // mov ax,0x4400
+        0xBB, 0x01, 0x00,   // mov bx,0x1
+        0xCD, 0x21,         // int 0x21
+        0xB8, 0x01, 0x44,   // mov ax,0x4401
+        0xBA, 0x20, 0x00,   // mov dx,0x20
+        0xCD, 0x21,         // int 0x21
+        0xB8, 0x00, 0x44,   // mov ax,0x4400
+        0xCD, 0x21,         // int 0x21
+        0xB8, 0x00, 0x44,   // mov ax,0x4400
+        0xBB, 0x09, 0x00,   // mov bx,0x9
+        0xCD, 0x21,         // int 0x21
+        0xB8, 0x07, 0x44,   // mov ax,0x4407
+        0xBB, 0x01, 0x00,   // mov bx,0x1
+        0xCD, 0x21,         // int 0x21
+        0xB8, 0x08, 0x44,   // mov ax,0x4408
+        0xB3, 0x00,         // mov bl,0x0
+        0xCD, 0x21,         // int 0x21
+    ];
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    assert!(!machine.cpu.regs.flags.carry());
+    assert_eq!(0x00D3, machine.cpu.get_r16(R::DX));
+
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x00F3, machine.cpu.get_r16(R::DX));
+
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    assert!(machine.cpu.regs.flags.carry());
+    assert_eq!(0x0006, machine.cpu.get_r16(R::AX));
+
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    assert!(!machine.cpu.regs.flags.carry());
+    assert_eq!(0xFF, machine.cpu.get_r8(R::AL));
+
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0x0001, machine.cpu.get_r16(R::AX));
+}
+
+#[test]
+fn can_capture_console_output_and_exit_code() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xBA, 0x0C, 0x01,   // mov dx,0x10c
+        0xB4, 0x09,         // mov ah,0x9
+        0xCD, 0x21,         // int 0x21
+        0xB8, 0x03, 0x4C,   // mov ax,0x4c03
+        0xCD, 0x21,         // int 0x21
+        b'h', b'i', b'$',
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(4);
+    assert_eq!("hi", machine.console_output());
+    assert_eq!(None, machine.exit_code());
+
+    machine.execute_instructions(3);
+    assert_eq!(Some(3), machine.exit_code());
+}
+
+#[test]
+fn can_read_redirected_stdin() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB4, 0x01,         // mov ah,0x1
+        0xCD, 0x21,         // int 0x21
+        0xA2, 0x00, 0x02,   // mov [0x200],al
+        0xB4, 0x0A,         // mov ah,0xa
+        0xBA, 0x10, 0x02,   // mov dx,0x210
+        0xCD, 0x21,         // int 0x21
+        0xB4, 0x3F,         // mov ah,0x3f
+        0x31, 0xDB,         // xor bx,bx
+        0xB9, 0x10, 0x00,   // mov cx,0x10
+        0xBA, 0x30, 0x02,   // mov dx,0x230
+        0xCD, 0x21,         // int 0x21
+        0xA3, 0x02, 0x02,   // mov [0x202],ax
+        0xB4, 0x0B,         // mov ah,0xb
+        0xCD, 0x21,         // int 0x21
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.mmu.write_u8(0x085F, 0x210, 8);
+    machine.set_stdin(b"Yhello world\r\nrest\n".to_vec());
+    machine.execute_instructions(21);
+    assert_eq!(b'Y', machine.mmu.read_u8(0x085F, 0x200));
+    assert_eq!(7, machine.mmu.read_u8(0x085F, 0x211));
+    assert_eq!(b"hello w\r".to_vec(), machine.mmu.read(0x085F, 0x212, 8));
+    assert_eq!(5, machine.mmu.read_u16(0x085F, 0x202));
+    assert_eq!(b"rest\n".to_vec(), machine.mmu.read(0x085F, 0x230, 5));
+    assert_eq!(0x00, machine.cpu.get_r8(R::AL));
+    assert_eq!(b"Yhello w\r".to_vec(), machine.dos.stdout);
+}
+
+#[test]
+fn can_call_break_handler_on_ctrl_c() {
+    let ctrl = Modifiers { ctrl: true, ..Modifiers::NONE };
+    let code: Vec<u8> = vec![
+        0xB8, 0x23, 0x25,               // mov ax,0x2523
+        0xBA, 0x13, 0x01,               // mov dx,0x113
+        0xCD, 0x21,                     // int 0x21
+        0xB4, 0x02,                     // mov ah,0x2
+        0xB2, 0x41,                     // mov dl,0x41
+        0xCD, 0x21,                     // int 0x21
+        0xB8, 0x07, 0x4C,               // mov ax,0x4c07
+        0xCD, 0x21,                     // int 0x21
+        0xC6, 0x06, 0x00, 0x02, 0x01,   // mov byte [0x200],0x1
+        0xCF,                           // iret
+    ];
+
+    // the handler returns with IRET, the interrupted call is restarted
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&code, 0x085F);
+    machine.keyboard_mut().add_keypress(Keycode::C, ctrl);
+    machine.execute_instructions(100);
+    assert_eq!(b"^C\r\nA".to_vec(), machine.dos.stdout);
+    assert_eq!(0x01, machine.mmu.read_u8(0x085F, 0x200));
+    assert_eq!(Some(7), machine.exit_code());
+    assert!(!machine.keyboard_mut().has_queued_presses());
+
+    // the handler returns with RETF and CF set, the program is terminated
+    let mut code = code;
+    code[19..21].copy_from_slice(&[0xF9, 0xCB]); // stc, retf
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&code, 0x085F);
+    machine.keyboard_mut().add_keypress(Keycode::C, ctrl);
+    machine.execute_instructions(100);
+    assert_eq!(b"^C\r\n".to_vec(), machine.dos.stdout);
+    assert_eq!(Some(0), machine.exit_code());
+
+    // the default handler terminates the program
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&code[8..], 0x085F);
+    machine.keyboard_mut().add_keypress(Keycode::C, ctrl);
+    machine.execute_instructions(100);
+    assert_eq!(b"^C\r\n".to_vec(), machine.dos.stdout);
+    assert_eq!(Some(0), machine.exit_code());
+}
+
+#[test]
+fn can_call_critical_error_handler() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("FILE.TXT"), b"hi").unwrap();
+
+    let mut machine = Machine::with_config(MachineConfig {
+        mounts: vec![Mount::new('C', dir.path().to_path_buf())],
+        ..MachineConfig::default()
+    });
+    let mut code: Vec<u8> = vec![
+        0xB4, 0x3D,                     // mov ah,0x3d
+        0xB0, 0x00,                     // mov al,0x0
+        0xBA, 0x32, 0x01,               // mov dx,0x132
+        0xCD, 0x21,                     // int 0x21
+        0x89, 0xC3,                     // mov bx,ax
+        0xB8, 0x24, 0x25,               // mov ax,0x2524
+        0xBA, 0x2A, 0x01,               // mov dx,0x12a
+        0xCD, 0x21,                     // int 0x21
+        0xB4, 0x3F,                     // mov ah,0x3f
+        0xB9, 0x02, 0x00,               // mov cx,0x2
+        0xBA, 0x00, 0x02,               // mov dx,0x200
+        0xCD, 0x21,                     // int 0x21
+        0xA3, 0x04, 0x02,               // mov [0x204],ax
+        0x19, 0xC0,                     // sbb ax,ax
+        0xA3, 0x06, 0x02,               // mov [0x206],ax
+        0xB8, 0x00, 0x4C,               // mov ax,0x4c00
+        0xCD, 0x21,                     // int 0x21
+        0xC6, 0x06, 0x08, 0x02, 0x01,   // mov byte [0x208],0x1
+        0xB0, 0x03,                     // mov al,0x3
+        0xCF,                           // iret
+    ];
+    code.extend_from_slice(b"C:\\FILE.TXT\0");
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(4);
+    machine.execute_instruction(); // trigger the interrupt
+    assert!(!machine.cpu.regs.flags.carry());
+
+    // reading the file fails on the host, the handler fails the call
+    fs::remove_file(dir.path().join("FILE.TXT")).unwrap();
+    machine.execute_instructions(100);
+    assert_eq!(Some(0), machine.exit_code());
+    assert_eq!(0x01, machine.mmu.read_u8(0x085F, 0x208));
+    assert_eq!(0x0053, machine.mmu.read_u16(0x085F, 0x204));
+    assert_eq!(0xFFFF, machine.mmu.read_u16(0x085F, 0x206));
+}
+
+#[test]
+fn can_get_and_set_file_date_and_time() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("FILE.TXT"), b"hi").unwrap();
+
+    let mut machine = Machine::with_config(MachineConfig {
+        mounts: vec![Mount::new('C', dir.path().to_path_buf())],
+        ..MachineConfig::default()
+    });
+    let mut code: Vec<u8> = vec![
+        0xB4, 0x3D,         // mov ah,0x3d
+        0xB0, 0x00,         // mov al,0x0
+        0xBA, 0x1B, 0x01,   // mov dx,0x11b
+        0xCD, 0x21,         // int 0x21
+        0x89, 0xC3,         // mov bx,ax
+        0xB8, 0x01, 0x57,   // mov ax,0x5701
+        0xB9, 0x7D, 0xBF,   // mov cx,0xbf7d
+        0xBA, 0x9F, 0x1D,   // mov dx,0x1d9f
+        0xCD, 0x21,         // int 0x21
+        0xB8, 0x00, 0x57,   // mov ax,0x5700
+        0xCD, 0x21,         // int 0x21
+    ];
+    code.extend_from_slice(b"c:\\FILE.TXT\0");
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(5);
+    assert!(!machine.cpu.regs.flags.carry());
+
+    machine.execute_instructions(6);
+    assert!(!machine.cpu.regs.flags.carry());
+    let modified = fs::metadata(dir.path().join("FILE.TXT")).unwrap().modified().unwrap();
+    assert_eq!(chrono::NaiveDate::from_ymd(1994, 12, 31).and_hms(23, 59, 58), crate::dos::host_local_time(modified));
+
+    machine.execute_instructions(3);
+    assert!(!machine.cpu.regs.flags.carry());
+    assert_eq!(0xBF7D, machine.cpu.get_r16(R::CX));
+    assert_eq!(0x1D9F, machine.cpu.get_r16(R::DX));
+}
+
+#[test]
+fn can_use_long_filename_functions() {
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join("Saved Games")).unwrap();
+    fs::write(dir.path().join("Saved Games").join("Slot One.sav"), b"hi").unwrap();
+
+    let mut machine = Machine::with_config(MachineConfig {
+        mounts: vec![Mount::new('C', dir.path().to_path_buf())],
+        ..MachineConfig::default()
+    });
+    let mut code: Vec<u8> = vec![
+        0xB8, 0x3B, 0x71,   // mov ax,0x713b
+        0xBA, 0x31, 0x01,   // mov dx,0x131
+        0xCD, 0x21,         // int 0x21
+        0xB8, 0x4E, 0x71,   // mov ax,0x714e
+        0xB9, 0x00, 0x00,   // mov cx,0x0
+        0xBE, 0x01, 0x00,   // mov si,0x1
+        0xBA, 0x3D, 0x01,   // mov dx,0x13d
+        0xBF, 0x00, 0x03,   // mov di,0x300
+        0xCD, 0x21,         // int 0x21
+        0x89, 0xC3,         // mov bx,ax
+        0xB8, 0x4F, 0x71,   // mov ax,0x714f
+        0xCD, 0x21,         // int 0x21
+        0xB8, 0x6C, 0x71,   // mov ax,0x716c
+        0xBB, 0x02, 0x00,   // mov bx,0x2
+        0xB9, 0x00, 0x00,   // mov cx,0x0
+        0xBA, 0x10, 0x00,   // mov dx,0x10
+        0xBE, 0x43, 0x01,   // mov si,0x143
+        0xCD, 0x21,         // int 0x21
+    ];
+    code.extend_from_slice(b"c:\\savedg~1\0*.sav\0new file.txt\0");
+    machine.load_executable(&code, 0x085F);
+
+    machine.execute_instructions(4);
+    assert!(!machine.cpu.regs.flags.carry());
+    assert_eq!("c:\\savedg~1", machine.dos.current_dir);
+
+    machine.execute_instructions(7);
+    assert!(!machine.cpu.regs.flags.carry());
+    let es = machine.cpu.get_r16(R::ES);
+    assert_eq!(0x20, machine.mmu.read_u8(es, 0x0300)); // archive
+    assert_eq!(2, machine.mmu.read_u32(es, 0x0320));
+    assert_eq!(b"Slot One.sav".to_vec(), machine.mmu.readz(es, 0x032C));
+    assert_eq!(b"SLOTON~1.SAV".to_vec(), machine.mmu.readz(es, 0x0430));
+
+    machine.execute_instructions(4);
+    assert!(machine.cpu.regs.flags.carry());
+    assert_eq!(0x0012, machine.cpu.get_r16(R::AX)); // no more files
+
+    machine.execute_instructions(7);
+    assert!(!machine.cpu.regs.flags.carry());
+    assert_eq!(2, machine.cpu.get_r16(R::CX)); // created
+    assert!(dir.path().join("Saved Games").join("new file.txt").exists());
+}
+
+#[test]
+fn can_load_device_drivers() {
+    let dir = tempdir().unwrap();
+    let sys = dir.path().join("TEST.SYS");
+    let mut data = vec![
+        0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x80, 0x12, 0x00, 0x12, 0x00, b'T', b'E', b'S', b'T', b'$', b' ', b' ', b' ',
+    ];
+    data.push(0xCB); // retf
+    fs::write(&sys, &data).unwrap();
+
+    let mut machine = Machine::with_config(MachineConfig {
+        drivers: vec!["ansi.sys".to_owned(), sys.to_str().unwrap().to_owned()],
+        ..MachineConfig::default()
+    });
+    let names: Vec<String> = machine.device_drivers().iter().map(|(_, _, h)| h.name()).collect();
+    assert_eq!(vec!["NUL", "TEST$", "CON", "CON", "AUX", "PRN", "CLOCK$"], names);
+
+    let code: Vec<u8> = vec![
+        0xB4, 0x52,         // mov ah,0x52
+        0xCD, 0x21,         // int 0x21
+        0xB8, 0x00, 0x1A,   // mov ax,0x1a00
+        0xCD, 0x2F,         // int 0x2f
+        0xBA, 0x10, 0x01,   // mov dx,0x110
+        0xB4, 0x09,         // mov ah,0x9
+        0xCD, 0x21,         // int 0x21
+        0x1B, b'[', b'2', b'J', b'h', b'i', b'$',
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(3);
+    let (es, bx) = (machine.cpu.get_r16(R::ES), machine.cpu.get_r16(R::BX));
+    assert_eq!(b"NUL     ".to_vec(), machine.mmu.read(es, bx + 0x2C, 8));
+
+    machine.execute_instructions(3);
+    assert_eq!(0xFF, machine.cpu.get_r8(R::AL));
+
+    machine.execute_instructions(4);
+    assert_eq!("hi", machine.console_output());
+}
+
+#[test]
+fn can_detect_self_modifying_code() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB9, 0x02, 0x00,                   // mov cx,0x2
+        0xB0, 0x01,                         // mov al,0x1
+        0xC6, 0x06, 0x04, 0x01, 0x05,       // mov byte [0x104],0x5
+        0xE2, 0xF7,                         // loop 0x103
+    ];
+    machine.load_executable(&code, 0x085F);
+    machine.execute_instructions(7);
+    assert_eq!(0x05, machine.cpu.get_r8(R::AL));
+
+    // the second write does not change the byte
+    let writes = machine.mmu.take_code_writes();
+    assert_eq!(1, writes.len());
+    assert_eq!(0x86F4, writes[0].addr);
+    assert_eq!((0x01, 0x05), (writes[0].old, writes[0].new));
+}
+
+#[test]
+fn can_relocate_exe() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        // header
+        b'M', b'Z',
+        0x33, 0x00,             // bytes in last page
+        0x01, 0x00,             // pages
+        0x01, 0x00,             // relocations
+        0x02, 0x00,             // header paragraphs
+        0x00, 0x00,             // min extra paragraphs
+        0xFF, 0xFF,             // max extra paragraphs
+        0x00, 0x00,             // ss
+        0x00, 0x01,             // sp
+        0x00, 0x00,             // checksum
+        0x00, 0x00,             // ip
+        0x00, 0x00,             // cs
+        0x1C, 0x00,             // relocation table offset
+        0x00, 0x00,             // overlay number
+        0x03, 0x00, 0x00, 0x00, // relocation 0000:0003
+
+        // program
+        0x9A, 0x00, 0x00, 0x01, 0x00, // call 0x1:0x0
+        0xB4, 0x4C,             // mov ah,0x4c
+        0xCD, 0x21,             // int 0x21
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0xB0, 0x01,             // mov al,0x1
+        0xCB,                   // retf
+    ];
+    machine.load_executable(&code, 0x085F);
+    assert_eq!(0x086F, machine.cpu.get_r16(R::CS));
+    assert_eq!(0x0870, machine.mmu.read_u16(0x086F, 0x0003));
+
+    machine.execute_instructions(2);
+    assert_eq!(0x0870, machine.cpu.get_r16(R::CS));
+    assert_eq!(0x01, machine.cpu.get_r8(R::AL));
+}
+
+#[test]
+fn can_run_for_cycles_and_call_vblank_callback() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xEB, 0xFE, // jmp short 0x100
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    let frames = Arc::new(AtomicUsize::new(0));
+    let counter = frames.clone();
+    machine.set_vblank_callback(move |_| {
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+    let frame_cycles = machine.cpu.clock_hz / machine.gpu().refresh_rate;
+    assert!(machine.run_for_cycles(frame_cycles * 3) >= frame_cycles * 3);
+    let seen = frames.load(Ordering::SeqCst);
+    assert!((2..=3).contains(&seen));
+
+    // execute_frame stops at the next vertical retrace
+    assert!(machine.execute_frame() <= frame_cycles);
+    assert_eq!(seen + 1, frames.load(Ordering::SeqCst));
+    assert!(machine.execute_frame() > 0);
+    assert_eq!(seen + 2, frames.load(Ordering::SeqCst));
+
+    machine.clear_vblank_callback();
+    machine.execute_frame();
+    assert_eq!(seen + 2, frames.load(Ordering::SeqCst));
+}
+
+#[test]
+fn can_execute_far_transfers_with_operand_size() {
+    let code: Vec<u8> = vec![
+        0x66, 0x9A, 0x0B, 0x01, 0x00, 0x00, 0x5F, 0x08, // call dword 0x85f:0x10b
+        0xF4,                                           // hlt
+        0x90, 0x90,                                     // nop, nop
+        0x66, 0xCB,                                     // retf with 32-bit operand size
+    ];
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&code, 0x085F);
+
+    // ip and cs are pushed as dwords
+    machine.execute_instruction();
+    assert_eq!(0x010B, machine.cpu.regs.ip);
+    assert_eq!(0x085F, machine.cpu.get_r16(R::CS));
+    assert_eq!(0xFFF6, machine.cpu.get_r16(R::SP));
+    let ss = machine.cpu.get_r16(R::SS);
+    assert_eq!(0x0000_0108, machine.mmu.read_u32(ss, 0xFFF6));
+    assert_eq!(0x0000_085F, machine.mmu.read_u32(ss, 0xFFFA));
+
+    machine.execute_instruction();
+    assert_eq!(0x0108, machine.cpu.regs.ip);
+    assert_eq!(0x085F, machine.cpu.get_r16(R::CS));
+    assert_eq!(0xFFFE, machine.cpu.get_r16(R::SP));
+}
+
+#[test]
+fn can_execute_push_pop_sreg_with_operand_size() {
+    let code: Vec<u8> = vec![
+        0x66, 0x1E,         // push dword ds
+        0x66, 0x07,         // pop dword es
+        0xB8, 0x34, 0x12,   // mov ax,0x1234
+        0x8E, 0xD8,         // mov ds,ax
+        0x66, 0x1E,         // push dword ds
+        0x66, 0x58,         // pop eax
+    ];
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&code, 0x085F);
+    machine.cpu.set_r16(R::ES, 0);
+
+    machine.execute_instructions(2);
+    assert_eq!(machine.cpu.get_r16(R::DS), machine.cpu.get_r16(R::ES));
+    assert_eq!(0xFFFE, machine.cpu.get_r16(R::SP));
+
+    // the selector is pushed zero extended
+    machine.cpu.set_r32(R::EAX, 0xFFFF_FFFF);
+    machine.execute_instructions(4);
+    assert_eq!(0x0000_1234, machine.cpu.get_r32(R::EAX));
+    assert_eq!(0xFFFE, machine.cpu.get_r16(R::SP));
+}
+
+#[test]
+fn can_execute_popa_with_wrapping_sp() {
+    let code: Vec<u8> = vec![
+        0x61,               // popa
+    ];
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&code, 0x085F);
+    machine.cpu.set_r16(R::SP, 0xFFF8);
+
+    // the skipped sp word is at 0xFFFE and the pops wrap around the segment
+    machine.execute_instruction();
+    assert_eq!(0x0008, machine.cpu.get_r16(R::SP));
+}
+
+#[test]
+fn can_stop_on_stack_underflow() {
+    let code: Vec<u8> = vec![
+        0x58,               // pop ax
+        0x58,               // pop ax
+        0x90,               // nop
+    ];
+    let mut machine = Machine::with_config(MachineConfig::parse("[stack_guard]\nenabled = true\naction = \"break\"").unwrap());
+    machine.load_executable(&code, 0x085F);
+    assert_eq!(Some((0x085F, 0x0000)), machine.stack_guard.base());
+
+    // popping the return address of the .com program is not an underflow
+    machine.execute_instruction();
+    assert!(!machine.cpu.fatal_error);
+    machine.execute_instructions(2);
+    assert!(machine.cpu.fatal_error);
+    assert_eq!(0x0102, machine.cpu.regs.ip);
+}
+
+#[test]
+fn can_send_and_receive_through_packet_driver() {
+    let mut machine = Machine::with_config(MachineConfig::parse("[network]\ntransport = \"loopback\"").unwrap());
+    let mut code: Vec<u8> = vec![
+        0xCD, 0x60,         // int 0x60
+        0xCD, 0x60,         // int 0x60
+        0xEB, 0xFE,         // jmp short 0x104
+    ];
+    code.resize(0x10, 0x90);
+    code.extend_from_slice(&[
+        // receiver
+        0x85, 0xC0,         // test ax,ax
+        0x75, 0x06,         // jnz 0x11a
+        0x0E,               // push cs
+        0x07,               // pop es
+        0xBF, 0x00, 0x02,   // mov di,0x200
+        0xCB,               // retf
+        0x89, 0x0E, 0x00, 0x03, // mov [0x300],cx
+        0xCB,               // retf
+    ]);
+    machine.load_executable(&code, 0x085F);
+
+    // the driver is found by its signature
+    let (offset, seg) = (machine.mmu.read_u16(0, 0x60 * 4), machine.mmu.read_u16(0, 0x60 * 4 + 2));
+    assert_eq!(b"PKT DRVR\0".to_vec(), machine.mmu.read(seg, offset + 3, 9));
+
+    // access_type for IP frames, with the receiver at 0110h
+    machine.mmu.write(0x085F, 0x0400, &[0x08, 0x00]);
+    machine.cpu.set_r16(R::AX, 0x0201);
+    machine.cpu.set_r16(R::BX, 0xFFFF);
+    machine.cpu.set_r8(R::DL, 0);
+    machine.cpu.set_r16(R::SI, 0x0400);
+    machine.cpu.set_r16(R::CX, 2);
+    machine.cpu.set_r16(R::DI, 0x0110);
+    machine.execute_instructions(3);
+    assert_eq!(0x0102, machine.cpu.regs.ip);
+    assert!(!machine.cpu.regs.flags.carry());
+    assert_eq!(0, machine.cpu.get_r16(R::AX));
+
+    // send_pkt of a broadcast frame, which the loopback transport receives
+    let mut frame = vec![0xFF; 6];
+    frame.extend_from_slice(&[0x02, 0x44, 0x42, 0x00, 0x00, 0x01, 0x08, 0x00]);
+    frame.extend_from_slice(b"hello");
+    machine.mmu.write(0x085F, 0x0500, &frame);
+    machine.cpu.set_r16(R::AX, 0x0400);
+    machine.cpu.set_r16(R::SI, 0x0500);
+    machine.cpu.set_r16(R::CX, frame.len() as u16);
+    machine.execute_instructions(3);
+    assert_eq!(0x0104, machine.cpu.regs.ip);
+    assert!(!machine.cpu.regs.flags.carry());
+
+    // the frame is handed to the receiver by the IRQ handler
+    machine.cpu.set_r16(R::BX, 0x1234);
+    machine.cpu.set_r16(R::DI, 0x5678);
+    machine.execute_instructions(10_000);
+    assert_eq!(frame.len() as u16, machine.mmu.read_u16(0x085F, 0x0300));
+    assert_eq!(frame, machine.mmu.read(0x085F, 0x0200, frame.len()));
+    assert_eq!(0x0104, machine.cpu.regs.ip);
+    assert_eq!(0x1234, machine.cpu.get_r16(R::BX));
+    assert_eq!(0x5678, machine.cpu.get_r16(R::DI));
+    assert_eq!(0x085F, machine.cpu.get_r16(R::ES));
+}
+
+#[test]
+fn can_exchange_ipx_packets_between_machines() {
+    // finds the entry point, opens socket 4545h and sends or listens with the ECB at 0200h
+    let program = |function: u8| -> Vec<u8> {
+        let mut code: Vec<u8> = vec![
+            0xB8, 0x00, 0x7A,       // mov ax,0x7a00
+            0xCD, 0x2F,             // int 0x2f
+            0x89, 0x3E, 0x80, 0x01, // mov [0x180],di
+            0x8C, 0x06, 0x82, 0x01, // mov [0x182],es
+            0x0E,                   // push cs
+            0x07,                   // pop es
+            0x31, 0xDB,             // xor bx,bx
+            0xBA, 0x45, 0x45,       // mov dx,0x4545
+            0xFF, 0x1E, 0x80, 0x01, // call far [0x180]
+            0xBB, function, 0x00,   // mov bx,function
+            0xBE, 0x00, 0x02,       // mov si,0x200
+            0xFF, 0x1E, 0x80, 0x01, // call far [0x180]
+            0xEB, 0xFE,             // jmp short 0x122
+        ];
+        code.resize(0x30, 0x90);
+        code.extend_from_slice(&[
+            // esr
+            0x2E, 0xC6, 0x06, 0x90, 0x01, 0x01, // mov byte [cs:0x190],0x1
+            0xCB,                   // retf
+        ]);
+        code
+    };
+    let (pipe_a, pipe_b) = Pipe::pair();
+    let config_a = MachineConfig::default();
+    let config_b = MachineConfig::parse("[ipx]\nnode = \"02:49:50:58:00:02\"").unwrap();
+    let mut a = Machine::with_config(config_a.clone());
+    let mut b = Machine::with_config(config_b.clone());
+    a.install_ipx(&config_a.ipx, Box::new(pipe_a));
+    b.install_ipx(&config_b.ipx, Box::new(pipe_b));
+    a.load_executable(&program(0x03), 0x085F);
+    b.load_executable(&program(0x04), 0x085F);
+
+    // ECB with a 64 byte buffer at 0300h, the ESR of the listening ECB is at 0130h
+    for machine in &mut [&mut a, &mut b] {
+        machine.mmu.write(0x085F, 0x020A, &[0x45, 0x45]);
+        machine.mmu.write(0x085F, 0x0222, &[0x01, 0x00, 0x00, 0x03, 0x5F, 0x08, 0x40, 0x00]);
+    }
+    b.mmu.write(0x085F, 0x0204, &[0x30, 0x01, 0x5F, 0x08]);
+    b.execute_instructions(20);
+    assert_eq!(0x0122, b.cpu.regs.ip);
+    assert_eq!(0xFE, b.mmu.read_u8(0x085F, 0x0208));
+
+    // a broadcast packet with 5 bytes of data
+    let mut header = vec![0; 10];
+    header.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x45, 0x45]);
+    a.mmu.write(0x085F, 0x0300, &header);
+    a.mmu.write(0x085F, 0x031E, b"hello");
+    a.mmu.write_u16(0x085F, 0x0228, 35);
+    a.execute_instructions(20);
+    assert_eq!(0x0122, a.cpu.regs.ip);
+    assert_eq!(0x00, a.mmu.read_u8(0x085F, 0x0208));
+    assert_eq!(0x00, a.mmu.read_u8(0x085F, 0x0209));
+
+    // the packet is received by the other machine, which calls the ESR
+    b.cpu.set_r16(R::SI, 0x1234);
+    b.execute_instructions(10_000);
+    assert_eq!(0x00, b.mmu.read_u8(0x085F, 0x0208));
+    assert_eq!(0x00, b.mmu.read_u8(0x085F, 0x0209));
+    assert_eq!(0x01, b.mmu.read_u8(0x085F, 0x0190));
+    assert_eq!(vec![0x00, 0x23], b.mmu.read(0x085F, 0x0302, 2));
+    assert_eq!(vec![0x02, 0x49, 0x50, 0x58, 0x00, 0x01, 0x45, 0x45], b.mmu.read(0x085F, 0x0316, 8));
+    assert_eq!(b"hello".to_vec(), b.mmu.read(0x085F, 0x031E, 5));
+    assert_eq!(vec![0x02, 0x49, 0x50, 0x58, 0x00, 0x01], b.mmu.read(0x085F, 0x021C, 6));
+    assert_eq!(0x0122, b.cpu.regs.ip);
+    assert_eq!(0x1234, b.cpu.get_r16(R::SI));
+}
+
+#[test]
+fn can_load_dos_program_from_dosbox_conf() {
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join("games")).unwrap();
+    fs::write(dir.path().join("games").join("hello.com"), [0xCD, 0x20]).unwrap(); // int 0x20
+    let text = format!("[autoexec]\nmount c {:?}\nc:\ncd games\nhello\n", dir.path());
+    let mut config = MachineConfig::default();
+    let conf = DosboxConf::parse(&text, &mut config).unwrap();
+    assert_eq!(Some("C:\\GAMES\\HELLO".to_owned()), conf.program);
+
+    let mut machine = Machine::with_config(config);
+    machine.set_current_dir(conf.current_dir.as_ref().unwrap());
+    assert!(machine.load_dos_program(conf.program.as_ref().unwrap()).is_none());
+    let (cs, ip) = (machine.cpu.get_r16(R::CS), machine.cpu.regs.ip);
+    assert_eq!(vec![0xCD, 0x20], machine.mmu.read(cs, ip, 2));
+    assert!(machine.load_dos_program("HELLO.COM").is_none());
+    assert!(machine.load_dos_program("C:\\HELLO").is_some());
+}
+
+#[test]
+fn can_run_programs_from_shell() {
+    let dir = tempdir().unwrap();
+    let code = [
+        0xB4, 0x09,       // mov ah,0x9
+        0xBA, 0x0C, 0x01, // mov dx,0x10c
+        0xCD, 0x21,       // int 0x21
+        0xB8, 0x05, 0x4C, // mov ax,0x4c05
+        0xCD, 0x21,       // int 0x21
+        b'h', b'i', b'$',
+    ];
+    fs::write(dir.path().join("hi.com"), code).unwrap();
+    let mut config = MachineConfig::default();
+    config.mounts.push(Mount::new('c', dir.path().to_path_buf()));
+    let mut machine = Machine::with_config(config);
+    machine.set_stdin(b"hi\r\nHI.COM\r\nset x=1\r\nexit\r\n".to_vec());
+    machine.start_shell();
+    machine.execute_instructions(1000);
+    assert!(machine.cpu.fatal_error);
+    assert_eq!(Some(0), machine.exit_code());
+    // carriage returns are shown as blanks
+    let lines: Vec<String> = machine.console_output().lines().map(|l| l.trim_end().to_owned()).collect();
+    assert_eq!(vec!["", "C:\\>hi", "hi", "C:\\>HI.COM", "hi", "C:\\>set x=1", "", "C:\\>exit"], lines);
+}
+
+#[test]
+fn can_exec_batch_files() {
+    let dir = tempdir().unwrap();
+    let hi = [
+        0xB4, 0x09,       // mov ah,0x9
+        0xBA, 0x0C, 0x01, // mov dx,0x10c
+        0xCD, 0x21,       // int 0x21
+        0xB8, 0x05, 0x4C, // mov ax,0x4c05
+        0xCD, 0x21,       // int 0x21
+        b'h', b'i', b'$',
+    ];
+    fs::write(dir.path().join("hi.com"), hi).unwrap();
+    fs::write(dir.path().join("run.bat"), b"@echo off\r\necho %1\r\nhi\r\n").unwrap();
+    let code = [
+        0xBA, 0x26, 0x01, // mov dx,0x126
+        0xBB, 0x13, 0x01, // mov bx,0x113
+        0xB8, 0x00, 0x4B, // mov ax,0x4b00
+        0xCD, 0x21,       // int 0x21
+        0xB4, 0x4D,       // mov ah,0x4d
+        0xCD, 0x21,       // int 0x21
+        0xB4, 0x4C,       // mov ah,0x4c
+        0xCD, 0x21,       // int 0x21
+        // parameter block: environment of the parent, command tail at 0329:0121, no FCBs
+        0x00, 0x00, 0x21, 0x01, 0x29, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x03, b' ', b'a', b'b', 0x0D,
+        b'R', b'U', b'N', b'.', b'B', b'A', b'T', 0x00,
+    ];
+    let mut config = MachineConfig::default();
+    config.mounts.push(Mount::new('c', dir.path().to_path_buf()));
+    let mut machine = Machine::with_config(config);
+    machine.set_current_dir("C:\\");
+    machine.load_executable(&code, 0x0329);
+    machine.execute_instructions(1000);
+    assert!(machine.cpu.fatal_error);
+    // the parent resumes after EXEC and exits with the return code of the batch file
+    assert_eq!(Some(5), machine.exit_code());
+    let lines: Vec<String> = machine.console_output().lines().map(|l| l.trim_end().to_owned()).collect();
+    assert_eq!(vec!["ab", "hi"], lines);
+
+    let mut machine = Machine::deterministic();
+    machine.set_current_dir("C:\\");
+    machine.dos.drives.mount(&Mount::new('c', dir.path().to_path_buf())).unwrap();
+    assert!(machine.load_executable_file(&dir.path().join("run.bat").to_string_lossy()).is_none());
+    machine.execute_instructions(1000);
+    assert_eq!(Some(5), machine.exit_code());
+    let lines: Vec<String> = machine.console_output().lines().map(|l| l.trim_end().to_owned()).collect();
+    assert_eq!(vec!["ECHO is off", "hi"], lines);
+}
+
+#[test]
+fn can_index_dispatch_table_by_op() {
+    let table = super::execute::dispatch_table();
+    assert_eq!(Op::COUNT, table.len());
+    assert_eq!(0, Op::Aaa.index());
+    assert_eq!(Op::COUNT - 2, Op::Uninitialized.index());
+    assert_eq!(Op::COUNT - 1, Op::Invalid(vec![0x0F], Invalid::Op).index());
 }
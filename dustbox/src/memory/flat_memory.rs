@@ -1,22 +1,257 @@
+use std::mem;
+use std::ops::{Range, RangeInclusive};
+use std::sync::{Arc, Mutex};
+
+use log::trace;
+
 use crate::hex::hex_bytes_separated;
 
+/// granularity of the code tracking used to invalidate the instruction decode cache
+pub const CODE_PAGE_SIZE: u32 = 0x100;
+
+/// granularity of the write tracking returned by `take_dirty_ranges`
+pub const DIRTY_PAGE_SIZE: u32 = 0x100;
+
+/// handles accesses to a memory range registered with `FlatMemory::register_hook`,
+/// such as memory mapped devices
+pub trait MemoryHandler: Send {
+    /// returns Some<u8> if read was handled, otherwise the byte is read from memory
+    fn read_u8(&mut self, addr: u32) -> Option<u8>;
+
+    /// returns true if write was handled, otherwise the byte is written to memory
+    fn write_u8(&mut self, addr: u32, data: u8) -> bool;
+}
+
+/// observes accesses to a memory range registered with `FlatMemory::register_watch`,
+/// before they are dispatched to hooks or memory
+pub trait MemoryWatcher: Send {
+    fn read_u8(&mut self, addr: u32);
+
+    fn write_u8(&mut self, addr: u32, data: u8);
+}
+
+/// a write that changed a byte previously decoded as an instruction
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodeWrite {
+    pub addr: u32,
+    pub old: u8,
+    pub new: u8,
+}
+
+#[derive(Clone)]
+struct MemoryHook {
+    range: RangeInclusive<u32>,
+    handler: Arc<Mutex<dyn MemoryHandler>>,
+}
+
+#[derive(Clone)]
+struct MemoryWatch {
+    range: RangeInclusive<u32>,
+    watcher: Arc<Mutex<dyn MemoryWatcher>>,
+}
+
 #[derive(Clone, Default)]
 pub struct FlatMemory {
     pub data: Vec<u8>,
+
+    /// pages holding instructions in the decode cache
+    code_pages: Vec<bool>,
+
+    /// pages written to since last call to `take_invalidated_code_pages`
+    invalidated_code_pages: Vec<u32>,
+
+    /// applied to all addresses, clears bit 20 while the A20 line is disabled
+    a20_mask: u32,
+
+    /// handlers for memory mapped ranges, in registration order
+    hooks: Vec<MemoryHook>,
+
+    /// observers of memory ranges, such as debugger watchpoints
+    watches: Vec<MemoryWatch>,
+
+    /// pages written to since last call to `take_dirty_ranges`
+    dirty_pages: Vec<bool>,
+
+    /// one bit per byte, set for bytes decoded as instructions
+    code_bytes: Vec<u8>,
+
+    /// modified code bytes since last call to `take_code_writes`
+    code_writes: Vec<CodeWrite>,
 }
 
-const DEBUG_MEMORY: bool = false;
+/// address line 20, masked to emulate the 8086 wrap around at 1 MB
+const A20_BIT: u32 = 1 << 20;
+
+/// max number of code writes kept until `take_code_writes` is called
+const MAX_CODE_WRITES: usize = 1024;
 
 impl FlatMemory {
     pub fn new() -> Self {
-        FlatMemory { data: vec![0u8; 0x1_0000 * 64] }
+        Self::with_size(0x1_0000 * 64)
+    }
+
+    /// returns a memory of `size` bytes, with the A20 line disabled.
+    /// reads outside of it returns 0xFF and writes are ignored
+    pub fn with_size(size: usize) -> Self {
+        FlatMemory {
+            data: vec![0u8; size],
+            code_pages: vec![false; size / CODE_PAGE_SIZE as usize + 1],
+            invalidated_code_pages: Vec::new(),
+            a20_mask: !A20_BIT,
+            hooks: Vec::new(),
+            watches: Vec::new(),
+            dirty_pages: vec![true; size / DIRTY_PAGE_SIZE as usize + 1],
+            code_bytes: vec![0u8; size / 8 + 1],
+            code_writes: Vec::new(),
+        }
+    }
+
+    /// returns the address ranges written to since last call, in ascending order
+    pub fn take_dirty_ranges(&mut self) -> Vec<Range<u32>> {
+        let mut res: Vec<Range<u32>> = Vec::new();
+        for (page, dirty) in self.dirty_pages.iter_mut().enumerate() {
+            if !*dirty {
+                continue;
+            }
+            *dirty = false;
+            let start = page as u32 * DIRTY_PAGE_SIZE;
+            match res.last_mut() {
+                Some(last) if last.end == start => last.end += DIRTY_PAGE_SIZE,
+                _ => res.push(start..start + DIRTY_PAGE_SIZE),
+            }
+        }
+        res
+    }
+
+    /// registers `handler` for byte accesses in `range`. ranges registered first take precedence.
+    /// bulk reads with `read` are not dispatched to the handler
+    pub fn register_hook(&mut self, range: RangeInclusive<u32>, handler: Arc<Mutex<dyn MemoryHandler>>) {
+        trace!(target: "cpu", "register_hook {:06x}-{:06x}", range.start(), range.end());
+        self.hooks.push(MemoryHook{range, handler});
+    }
+
+    fn hook_at(&self, addr: u32) -> Option<&MemoryHook> {
+        self.hooks.iter().find(|h| h.range.contains(&addr))
+    }
+
+    /// registers `watcher` to observe byte accesses in `range`, in addition to any hook.
+    /// bulk reads with `read` are not dispatched to the watcher
+    pub fn register_watch(&mut self, range: RangeInclusive<u32>, watcher: Arc<Mutex<dyn MemoryWatcher>>) {
+        trace!(target: "cpu", "register_watch {:06x}-{:06x}", range.start(), range.end());
+        self.watches.push(MemoryWatch{range, watcher});
+    }
+
+    /// removes all registered watchers
+    pub fn clear_watches(&mut self) {
+        self.watches.clear();
+    }
+
+    fn is_watched(&self, start: u32, end: u32) -> bool {
+        self.watches.iter().any(|w| *w.range.start() < end && *w.range.end() >= start)
+    }
+
+    /// enables or disables the A20 line. while disabled, addresses wrap at 1 MB
+    pub fn set_a20(&mut self, enabled: bool) {
+        trace!(target: "cpu", "a20 {}", if enabled { "enabled" } else { "disabled" });
+        self.a20_mask = if enabled { !0 } else { !A20_BIT };
+    }
+
+    pub fn a20(&self) -> bool {
+        self.a20_mask & A20_BIT != 0
+    }
+
+    /// returns the mask applied to addresses by the A20 gate
+    pub fn a20_mask(&self) -> u32 {
+        self.a20_mask
+    }
+
+    /// marks `length` bytes at `addr` as holding decoded instructions
+    pub fn mark_code(&mut self, addr: u32, length: usize) {
+        let addr = addr & self.a20_mask;
+        let first = addr / CODE_PAGE_SIZE;
+        let last = (addr + length as u32).saturating_sub(1) / CODE_PAGE_SIZE;
+        for page in first..=last {
+            if let Some(p) = self.code_pages.get_mut(page as usize) {
+                *p = true;
+            }
+        }
+        self.watch_code(addr..addr + length as u32);
+    }
+
+    /// reports writes changing bytes in `range` from `take_code_writes`,
+    /// until they are decoded again
+    pub fn watch_code(&mut self, range: Range<u32>) {
+        for addr in range {
+            if let Some(b) = self.code_bytes.get_mut((addr >> 3) as usize) {
+                *b |= 1 << (addr & 7);
+            }
+        }
+    }
+
+    fn is_watched_code(&self, addr: u32) -> bool {
+        match self.code_bytes.get((addr >> 3) as usize) {
+            Some(b) => b & (1 << (addr & 7)) != 0,
+            None => false,
+        }
+    }
+
+    /// returns the self-modifying code writes since last call, in order of execution
+    pub fn take_code_writes(&mut self) -> Vec<CodeWrite> {
+        mem::take(&mut self.code_writes)
+    }
+
+    /// records a code write if `data` changes a watched byte at `addr`
+    fn track_code_write(&mut self, addr: u32, data: u8) {
+        if !self.is_watched_code(addr) {
+            return;
+        }
+        let old = self.data[addr as usize];
+        if old == data {
+            return;
+        }
+        self.code_bytes[(addr >> 3) as usize] &= !(1 << (addr & 7));
+        if self.code_writes.len() < MAX_CODE_WRITES {
+            self.code_writes.push(CodeWrite{addr, old, new: data});
+        }
+    }
+
+    /// returns the pages holding decoded instructions that was written to since last call
+    pub fn take_invalidated_code_pages(&mut self) -> Vec<u32> {
+        mem::take(&mut self.invalidated_code_pages)
+    }
+
+    fn invalidate_code(&mut self, addr: u32, length: usize) {
+        let first = addr / CODE_PAGE_SIZE;
+        let last = (addr + length as u32).saturating_sub(1) / CODE_PAGE_SIZE;
+        for page in first..=last {
+            if let Some(p) = self.code_pages.get_mut(page as usize) {
+                if *p {
+                    *p = false;
+                    self.invalidated_code_pages.push(page);
+                }
+            }
+        }
     }
 
     pub fn read_u8(&self, addr: u32) -> u8 {
-        let val = self.data[addr as usize];
-        if DEBUG_MEMORY {
-            println!("read_u8 from {:06x} = {:02x}", addr, val);
+        let addr = addr & self.a20_mask;
+        if !self.watches.is_empty() {
+            for w in self.watches.iter().filter(|w| w.range.contains(&addr)) {
+                w.watcher.lock().unwrap().read_u8(addr);
+            }
         }
+        if !self.hooks.is_empty() {
+            if let Some(hook) = self.hook_at(addr) {
+                if let Some(val) = hook.handler.lock().unwrap().read_u8(addr) {
+                    return val;
+                }
+            }
+        }
+        let val = match self.data.get(addr as usize) {
+            Some(v) => *v,
+            None => 0xFF,
+        };
+        trace!(target: "cpu", "read_u8 from {:06x} = {:02x}", addr, val);
         val
     }
 
@@ -25,9 +260,32 @@ impl FlatMemory {
     }
 
     pub fn write_u8(&mut self, addr: u32, data: u8) {
-        if DEBUG_MEMORY {
-            println!("write_u8 to {:06x} = {:02x}", addr, data);
+        let addr = addr & self.a20_mask;
+        trace!(target: "cpu", "write_u8 to {:06x} = {:02x}", addr, data);
+        if !self.watches.is_empty() {
+            for w in self.watches.iter().filter(|w| w.range.contains(&addr)) {
+                w.watcher.lock().unwrap().write_u8(addr, data);
+            }
+        }
+        if !self.hooks.is_empty() {
+            if let Some(hook) = self.hook_at(addr) {
+                if hook.handler.lock().unwrap().write_u8(addr, data) {
+                    // the device memory may be displayed, such as video memory
+                    if let Some(dirty) = self.dirty_pages.get_mut((addr / DIRTY_PAGE_SIZE) as usize) {
+                        *dirty = true;
+                    }
+                    return;
+                }
+            }
         }
+        if addr as usize >= self.data.len() {
+            return;
+        }
+        if self.code_pages[(addr / CODE_PAGE_SIZE) as usize] {
+            self.invalidate_code(addr, 1);
+        }
+        self.track_code_write(addr, data);
+        self.dirty_pages[(addr / DIRTY_PAGE_SIZE) as usize] = true;
         self.data[addr as usize] = data;
     }
 
@@ -46,14 +304,29 @@ impl FlatMemory {
     }
 
     pub fn read(&self, addr: u32, length: usize) -> &[u8] {
-        let addr = addr as usize;
+        let addr = (addr & self.a20_mask) as usize;
         &self.data[addr..addr+length]
     }
 
     pub fn write(&mut self, addr: u32, data: &[u8]) {
-        let addr = addr as usize;
-        if DEBUG_MEMORY {
-            println!("write to {:06x} in {} bytes: {}", addr, data.len(), hex_bytes_separated(data, ' '));
+        let addr = (addr & self.a20_mask) as usize;
+        trace!(target: "cpu", "write to {:06x} in {} bytes: {}", addr, data.len(), hex_bytes_separated(data, ' '));
+        let end = (addr + data.len()) as u32;
+        if self.hooks.iter().any(|h| *h.range.start() < end && *h.range.end() >= addr as u32) || self.is_watched(addr as u32, end) {
+            for (i, b) in data.iter().enumerate() {
+                self.write_u8((addr + i) as u32, *b);
+            }
+            return;
+        }
+        self.invalidate_code(addr as u32, data.len());
+        for (i, b) in data.iter().enumerate() {
+            self.track_code_write((addr + i) as u32, *b);
+        }
+        if !data.is_empty() {
+            let last = (addr + data.len() - 1) / DIRTY_PAGE_SIZE as usize;
+            for page in addr / DIRTY_PAGE_SIZE as usize..=last {
+                self.dirty_pages[page] = true;
+            }
         }
         self.data[addr..addr+data.len()].copy_from_slice(data);
     }
@@ -1,13 +1,15 @@
-use crate::memory::{FlatMemory, MemoryAddress};
+use std::ops::{Range, RangeInclusive};
+use std::sync::{Arc, Mutex};
+
+use log::trace;
+
+use crate::memory::{CodeWrite, FlatMemory, MemoryAddress, MemoryHandler, MemoryWatcher};
 use crate::codepage::cp437;
 
 #[cfg(test)]
 #[path = "./mmu_test.rs"]
 mod mmu_test;
 
-const DEBUG_MMU: bool = false;
-const DEBUG_VEC: bool = false;
-
 #[derive(Clone)]
 pub struct MMU {
     pub memory: FlatMemory,
@@ -24,6 +26,40 @@ impl MMU {
         }
     }
 
+    /// returns a MMU with `size` bytes of memory
+    pub fn with_memory_size(size: usize) -> Self {
+        MMU {
+            memory: FlatMemory::with_size(size),
+            flags_address: MemoryAddress::Unset,
+        }
+    }
+
+    /// registers `handler` for memory accesses to the physical addresses in `range`,
+    /// for example A_0000-B_FFFF for video memory
+    pub fn register_hook(&mut self, range: RangeInclusive<u32>, handler: Arc<Mutex<dyn MemoryHandler>>) {
+        self.memory.register_hook(range, handler);
+    }
+
+    /// registers `watcher` to observe memory accesses to the physical addresses in `range`
+    pub fn register_watch(&mut self, range: RangeInclusive<u32>, watcher: Arc<Mutex<dyn MemoryWatcher>>) {
+        self.memory.register_watch(range, watcher);
+    }
+
+    /// removes all registered watchers
+    pub fn clear_watches(&mut self) {
+        self.memory.clear_watches();
+    }
+
+    /// returns the physical address ranges written to since last call
+    pub fn take_dirty_ranges(&mut self) -> Vec<Range<u32>> {
+        self.memory.take_dirty_ranges()
+    }
+
+    /// returns the writes to previously decoded instructions since last call
+    pub fn take_code_writes(&mut self) -> Vec<CodeWrite> {
+        self.memory.take_code_writes()
+    }
+
     /// manipulates the FLAGS register on stack while in a interrupt
     pub fn set_flag(&mut self, flag_mask: u16, flag_value: bool) {
         if self.flags_address == MemoryAddress::Unset {
@@ -91,44 +127,34 @@ impl MMU {
 
     pub fn read_u8_addr(&self, addr: MemoryAddress) -> u8 {
         let v = self.memory.read_u8(addr.value());
-        if DEBUG_MMU {
-            println!("mmu.read_u8_addr from {} = {:02X}", addr, v);
-        }
+        trace!(target: "cpu", "mmu.read_u8_addr from {} = {:02X}", addr, v);
         v
     }
 
     pub fn read_u8(&self, seg: u16, offset: u16) -> u8 {
         let addr = MemoryAddress::RealSegmentOffset(seg, offset).value();
         let v = self.memory.read_u8(addr);
-        if DEBUG_MMU {
-            println!("mmu.read_u8 from ({:04X}:{:04X} == {:06X}) = {:02X}", seg, offset, addr, v);
-        }
+        trace!(target: "cpu", "mmu.read_u8 from ({:04X}:{:04X} == {:06X}) = {:02X}", seg, offset, addr, v);
         v
     }
 
     pub fn read_u16(&self, seg: u16, offset: u16) -> u16 {
         let addr = MemoryAddress::RealSegmentOffset(seg, offset).value();
         let v = self.memory.read_u16(addr);
-        if DEBUG_MMU {
-            println!("mmu.read_u16 from ({:04X}:{:04X} == {:06X}) = {:04X}", seg, offset, addr, v);
-        }
+        trace!(target: "cpu", "mmu.read_u16 from ({:04X}:{:04X} == {:06X}) = {:04X}", seg, offset, addr, v);
         v
     }
 
     pub fn write_u8(&mut self, seg: u16, offset: u16, data: u8) {
         let addr = MemoryAddress::RealSegmentOffset(seg, offset).value();
-        if DEBUG_MMU {
-            println!("mmu.write_u8 to ({:04X}:{:04X} == {:06X}) = {:02X}", seg, offset, addr, data);
-        }
+        trace!(target: "cpu", "mmu.write_u8 to ({:04X}:{:04X} == {:06X}) = {:02X}", seg, offset, addr, data);
         self.memory.write_u8(addr, data);
     }
 
     /// write data and increase addr
     pub fn write_u8_inc(&mut self, addr: &mut MemoryAddress, data: u8) {
         self.memory.write_u8(addr.value(), data);
-        if DEBUG_MMU {
-            println!("mmu.write_u8_inc to {:06X} = {:02X}", addr.value(), data);
-        }
+        trace!(target: "cpu", "mmu.write_u8_inc to {:06X} = {:02X}", addr.value(), data);
         addr.inc_u8();
     }
 
@@ -140,67 +166,53 @@ impl MMU {
 
     pub fn write_u16(&mut self, seg: u16, offset: u16, data: u16) {
         let addr = MemoryAddress::RealSegmentOffset(seg, offset).value();
-        if DEBUG_MMU {
-            println!("mmu.write_u16 to ({:04X}:{:04X} == {:06X}) = {:02X}", seg, offset, addr, data);
-        }
+        trace!(target: "cpu", "mmu.write_u16 to ({:04X}:{:04X} == {:06X}) = {:02X}", seg, offset, addr, data);
         self.memory.write_u16(addr, data);
     }
 
     /// write data and increase addr
     pub fn write_u16_inc(&mut self, addr: &mut MemoryAddress, data: u16) {
         self.memory.write_u16(addr.value(), data);
-        if DEBUG_MMU {
-            println!("mmu.write_u16_inc to {:06X} = {:08X}", addr.value(), data);
-        }
+        trace!(target: "cpu", "mmu.write_u16_inc to {:06X} = {:08X}", addr.value(), data);
         addr.inc_u16();
     }
 
     pub fn read_u32(&self, seg: u16, offset: u16) -> u32 {
         let addr = MemoryAddress::RealSegmentOffset(seg, offset).value();
         let v = self.memory.read_u32(addr);
-        if DEBUG_MMU {
-            println!("mmu.read_u32 from {:06X} = {:04X}", addr, v);
-        }
+        trace!(target: "cpu", "mmu.read_u32 from {:06X} = {:04X}", addr, v);
         v
     }
 
     pub fn write_u32(&mut self, seg: u16, offset: u16, data: u32) {
         // TODO take MemoryAddress parameter directly
         let addr = MemoryAddress::RealSegmentOffset(seg, offset).value();
-        if DEBUG_MMU {
-            println!("mmu.write_u32 to {:06X} = {:08X}", addr, data);
-        }
+        trace!(target: "cpu", "mmu.write_u32 to {:06X} = {:08X}", addr, data);
         self.memory.write_u32(addr, data);
     }
 
     /// write data and increase addr
     pub fn write_u32_inc(&mut self, addr: &mut MemoryAddress, data: u32) {
         self.memory.write_u32(addr.value(), data);
-        if DEBUG_MMU {
-            println!("mmu.write_u32_inc to {:06X} = {:08X}", addr.value(), data);
-        }
+        trace!(target: "cpu", "mmu.write_u32_inc to {:06X} = {:08X}", addr.value(), data);
         addr.inc_u32();
     }
 
     /// read interrupt vector, returns segment, offset
     pub fn read_vec(&self, v: u16) -> (u16, u16) {
-        // XXX better naming
+        // the vector table holds the offset, followed by the segment
         let v_abs = u32::from(v) << 2;
-        let seg = self.memory.read_u16(v_abs);
-        let off = self.memory.read_u16(v_abs + 2);
-        if DEBUG_VEC {
-            println!("mmu.read_vec: {:04X} = {:04X}:{:04X}", v, seg, off);
-        }
+        let off = self.memory.read_u16(v_abs);
+        let seg = self.memory.read_u16(v_abs + 2);
+        trace!(target: "cpu", "mmu.read_vec: {:04X} = {:04X}:{:04X}", v, seg, off);
         (seg, off)
     }
 
     /// write interrupt vector
     pub fn write_vec(&mut self, v: u16, data: MemoryAddress) {
         let v_abs = u32::from(v) << 2;
-        self.memory.write_u16(v_abs, data.segment());
-        self.memory.write_u16(v_abs + 2, data.offset());
-        if DEBUG_VEC {
-            println!("mmu.write_vec: {:04X} = {:04X}:{:04X}", v, data.segment(), data.offset());
-        }
+        self.memory.write_u16(v_abs, data.offset());
+        self.memory.write_u16(v_abs + 2, data.segment());
+        trace!(target: "cpu", "mmu.write_vec: {:04X} = {:04X}:{:04X}", v, data.segment(), data.offset());
     }
 }
@@ -1,4 +1,6 @@
-use crate::memory::mmu::MemoryAddress;
+use std::sync::{Arc, Mutex};
+
+use crate::memory::{CodeWrite, MMU, MemoryAddress, MemoryHandler};
 
 #[test]
 fn can_handle_real_mode_addressing() {
@@ -25,3 +27,71 @@ fn resolve_real_addressing() {
     let ma2 = MemoryAddress::RealSegmentOffset(0x0040, 0x006C);
     assert_eq!(ma1.value(), ma2.value());
 }
+
+/// counts writes and serves reads of a memory mapped register at 0xA_0000
+struct TestDevice {
+    writes: usize,
+    register: u8,
+}
+
+impl MemoryHandler for TestDevice {
+    fn read_u8(&mut self, addr: u32) -> Option<u8> {
+        if addr == 0xA_0000 {
+            Some(self.register)
+        } else {
+            None
+        }
+    }
+
+    fn write_u8(&mut self, addr: u32, data: u8) -> bool {
+        self.writes += 1;
+        if addr == 0xA_0000 {
+            self.register = data ^ 0xFF;
+            return true;
+        }
+        false
+    }
+}
+
+#[test]
+fn can_dispatch_memory_hooks() {
+    let mut mmu = MMU::default();
+    let device = Arc::new(Mutex::new(TestDevice{writes: 0, register: 0}));
+    mmu.register_hook(0xA_0000..=0xB_FFFF, device.clone());
+
+    mmu.write_u16(0xA000, 0x0000, 0x1234);
+    assert_eq!(0xCB, mmu.read_u8(0xA000, 0x0000)); // handled by the device
+    assert_eq!(0x12, mmu.read_u8(0xA000, 0x0001)); // passed through to memory
+    assert_eq!(0x00, mmu.memory.data[0xA_0000]);
+
+    mmu.write(0xB000, 0xFFFF, &[1, 2]);
+    assert_eq!(0x02, mmu.read_u8(0xC000, 0x0000)); // outside of the range
+    assert_eq!(3, device.lock().unwrap().writes);
+}
+
+#[test]
+fn can_track_dirty_ranges() {
+    let mut mmu = MMU::default();
+    mmu.take_dirty_ranges();
+    assert!(mmu.take_dirty_ranges().is_empty());
+
+    mmu.write_u8(0xA000, 0x0010, 1);
+    mmu.write_u16(0xA000, 0x01FF, 1); // spans two pages
+    mmu.write(0xB800, 0x0000, &[1, 2, 3]);
+    assert_eq!(vec![0xA_0000..0xA_0300, 0xB_8000..0xB_8100], mmu.take_dirty_ranges());
+    assert!(mmu.take_dirty_ranges().is_empty());
+}
+
+#[test]
+fn can_track_code_writes() {
+    let mut mmu = MMU::default();
+    mmu.write(0x0000, 0x1000, &[0x90, 0x90]);
+    mmu.memory.mark_code(0x1000, 2);
+
+    mmu.write_u8(0x0000, 0x1000, 0x90); // unchanged
+    mmu.write_u8(0x0000, 0x1002, 0xCC); // not code
+    mmu.write_u16(0x0000, 0x1001, 0xC3C3);
+    mmu.write_u8(0x0000, 0x1001, 0x90); // already reported until decoded again
+    assert_eq!(vec![CodeWrite{addr: 0x1001, old: 0x90, new: 0xC3}], mmu.take_code_writes());
+    assert!(mmu.take_code_writes().is_empty());
+}
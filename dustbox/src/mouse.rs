@@ -3,11 +3,15 @@
 ///
 /// https://wiki.osdev.org/Mouse_Input
 
+use log::debug;
+
 use crate::cpu::{CPU, R};
 use crate::machine::Component;
 use crate::memory::MMU;
 
-const DEBUG_MOUSE: bool = false;
+#[cfg(test)]
+#[path = "./mouse_test.rs"]
+mod mouse_test;
 
 #[derive(Debug)]
 pub enum MouseButton {
@@ -28,9 +32,21 @@ pub struct Mouse {
     max_x: u16,
     min_y: u16,
     max_y: u16,
+
+    /// motion since the last read of the motion counters, in mickeys
+    mickey_x: i32,
+    mickey_y: i32,
+
+    /// mickeys per 8 pixels of cursor movement, set by INT 33, ax=0F
+    mickeys_per_8x: u16,
+    mickeys_per_8y: u16,
 }
 
 impl Component for Mouse {
+    fn interrupts(&self) -> Vec<u8> {
+        vec![0x33]
+    }
+
     fn int(&mut self, int: u8, cpu: &mut CPU, _mmu: &mut MMU) -> bool {
         if int != 0x33 {
             return false;
@@ -47,9 +63,7 @@ impl Component for Mouse {
                 cpu.set_r16(R::BX, self.button_status());   // BX = button status
                 cpu.set_r16(R::CX, self.x as u16);          // CX = column
                 cpu.set_r16(R::DX, self.y as u16);          // DX = row
-                if DEBUG_MOUSE {
-                    println!("MOUSE - RETURN POSITION AND BUTTON STATUS");
-                }
+                debug!(target: "io", "MOUSE - RETURN POSITION AND BUTTON STATUS");
             }
             0x0007 => {
                 // MS MOUSE v1.0+ - DEFINE HORIZONTAL CURSOR RANGE
@@ -60,9 +74,7 @@ impl Component for Mouse {
                 let dx = cpu.get_r16(R::DX);
                 self.min_x = cx;
                 self.max_x = dx;
-                if DEBUG_MOUSE {
-                    println!("MOUSE - DEFINE HORIZONTAL CURSOR RANGE min {}, max {}", cx, dx);
-                }
+                debug!(target: "io", "MOUSE - DEFINE HORIZONTAL CURSOR RANGE min {}, max {}", cx, dx);
             }
             0x0008 => {
                 // MS MOUSE v1.0+ - DEFINE VERTICAL CURSOR RANGE
@@ -73,9 +85,28 @@ impl Component for Mouse {
                 let dx = cpu.get_r16(R::DX);
                 self.min_y = cx;
                 self.max_y = dx;
-                if DEBUG_MOUSE {
-                    println!("MOUSE - DEFINE VERTICAL CURSOR RANGE min {}, max {}", cx, dx);
+                debug!(target: "io", "MOUSE - DEFINE VERTICAL CURSOR RANGE min {}, max {}", cx, dx);
+            }
+            0x000B => {
+                // MS MOUSE v1.0+ - READ MOTION COUNTERS
+                // CX = number of mickeys mouse moved horizontally since last call
+                // DX = number of mickeys mouse moved vertically
+                let (x, y) = self.read_motion();
+                cpu.set_r16(R::CX, x as u16);
+                cpu.set_r16(R::DX, y as u16);
+                debug!(target: "io", "MOUSE - READ MOTION COUNTERS {}, {}", x, y);
+            }
+            0x000F => {
+                // MS MOUSE v1.0+ - DEFINE MICKEY/PIXEL RATIO
+                // CX = number of mickeys per 8 pixels horizontally (default 8)
+                // DX = number of mickeys per 8 pixels vertically (default 16)
+                let cx = cpu.get_r16(R::CX);
+                let dx = cpu.get_r16(R::DX);
+                if cx != 0 && dx != 0 {
+                    self.mickeys_per_8x = cx;
+                    self.mickeys_per_8y = dx;
                 }
+                debug!(target: "io", "MOUSE - DEFINE MICKEY/PIXEL RATIO {}, {}", cx, dx);
             }
             _ => return false
         }
@@ -83,6 +114,10 @@ impl Component for Mouse {
     }
 }
 
+fn clamp_i16(v: i32) -> i16 {
+    v.max(i32::from(i16::MIN)).min(i32::from(i16::MAX)) as i16
+}
+
 fn scale(value_in: f64, base_min: f64, base_max: f64, limit_min: f64, limit_max: f64) -> f64 {
     ((limit_max - limit_min) * (value_in - base_min) / (base_max - base_min)) + limit_min
 }
@@ -99,14 +134,15 @@ impl Mouse {
             max_x: 640,
             min_y: 0,
             max_y: 200,
+            mickey_x: 0,
+            mickey_y: 0,
+            mickeys_per_8x: 8,
+            mickeys_per_8y: 16,
         }
     }
 
     /// Sets the mouse absolute position
     pub fn set_position(&mut self, x: i32, y: i32) {
-        if DEBUG_MOUSE {
-            // println!("mouse.set_position {}, {}", x, y);
-        }
         // XXX In text modes, all coordinates are specified as multiples of the cell size, typically 8x8 pixels
 
         if x >= 0 && y >= 0 {
@@ -123,11 +159,35 @@ impl Mouse {
         }
     }
 
+    /// Adds relative motion in mickeys to the motion counters, without moving the cursor.
+    /// Used together with set_position when the host provides both
+    pub fn add_mickeys(&mut self, dx: i32, dy: i32) {
+        self.mickey_x = self.mickey_x.wrapping_add(dx);
+        self.mickey_y = self.mickey_y.wrapping_add(dy);
+    }
+
+    /// Moves the mouse by relative motion in mickeys, such as from a captured host mouse.
+    /// The cursor moves according to the mickey/pixel ratio, within the cursor range
+    pub fn move_relative(&mut self, dx: i32, dy: i32) {
+        debug!(target: "io", "mouse.move_relative {}, {}", dx, dy);
+        self.add_mickeys(dx, dy);
+        let x = self.x + dx * 8 / i32::from(self.mickeys_per_8x);
+        let y = self.y + dy * 8 / i32::from(self.mickeys_per_8y);
+        self.x = x.max(i32::from(self.min_x)).min(i32::from(self.max_x));
+        self.y = y.max(i32::from(self.min_y)).min(i32::from(self.max_y));
+    }
+
+    /// returns the motion counters and clears them, used by INT 33, ax=0B
+    fn read_motion(&mut self) -> (i16, i16) {
+        let res = (clamp_i16(self.mickey_x), clamp_i16(self.mickey_y));
+        self.mickey_x = 0;
+        self.mickey_y = 0;
+        res
+    }
+
     /// Sets the mouse button pressed state
     pub fn set_button(&mut self, button: MouseButton, pressed: bool) {
-        if DEBUG_MOUSE {
-            println!("mouse.set_button {:?}, {}", button, pressed);
-        }
+        debug!(target: "io", "mouse.set_button {:?}, {}", button, pressed);
         match button {
             MouseButton::Left => self.left = pressed,
             MouseButton::Right => self.right = pressed,
@@ -0,0 +1,41 @@
+use crate::cpu::R;
+use crate::machine::Machine;
+use crate::mouse::Mouse;
+
+#[test]
+fn moves_relative_within_range() {
+    let mut mouse = Mouse::default();
+    mouse.move_relative(10, 32);
+    assert_eq!((10, 16), (mouse.x, mouse.y));
+
+    mouse.move_relative(-50, 1000);
+    assert_eq!((0, 200), (mouse.x, mouse.y));
+}
+
+#[test]
+fn can_read_motion_counters() {
+    let mut machine = Machine::deterministic();
+    let code: Vec<u8> = vec![
+        0xB8, 0x0B, 0x00,   // mov ax,0xb
+        0xCD, 0x33,         // int 0x33
+        0xB8, 0x0B, 0x00,   // mov ax,0xb
+        0xCD, 0x33,         // int 0x33
+    ];
+    machine.load_executable(&code, 0x085F);
+
+    // absolute positioning keeps the counters separate from the cursor
+    machine.mouse_mut().set_position(100, 120);
+    machine.mouse_mut().add_mickeys(-3, 7);
+    machine.mouse_mut().move_relative(2, 0);
+
+    machine.execute_instructions(2);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0xFFFF, machine.cpu.get_r16(R::CX));
+    assert_eq!(0x0007, machine.cpu.get_r16(R::DX));
+
+    // counters are cleared on read
+    machine.execute_instructions(3);
+    machine.execute_instruction(); // trigger the interrupt
+    assert_eq!(0, machine.cpu.get_r16(R::CX));
+    assert_eq!(0, machine.cpu.get_r16(R::DX));
+}
@@ -1,5 +1,5 @@
 use std::io::{self, Write};
-use std::fs::File;
+use std::fs::{self, File};
 use std::process::Command;
 use std::str;
 
@@ -8,7 +8,7 @@ use tempfile::tempdir;
 use crate::cpu::{Encoder, Instruction};
 
 pub fn ndisasm_first_instr(bytes: &[u8]) -> Result<String, io::Error> {
-    let rows = ndisasm_bytes(bytes).unwrap();
+    let rows = ndisasm_bytes(bytes)?;
     // parse syntax "00000000  CD21              int 0x21", return third column
     let mut col = 0;
     let mut spacing = false;
@@ -55,6 +55,26 @@ pub fn ndisasm_bytes(bytes: &[u8]) -> Result<Vec<String>, io::Error> {
     Ok(res)
 }
 
+/// assembles nasm `source` with external nasm command, returning the flat binary
+pub fn nasm_assemble(source: &str) -> Result<Vec<u8>, io::Error> {
+    let tmp_dir = tempdir()?;
+    let src_path = tmp_dir.path().join("source.asm");
+    let bin_path = tmp_dir.path().join("binary.bin");
+    fs::write(&src_path, source)?;
+
+    let output = Command::new("nasm")
+        .args(["-f", "bin", "-o", bin_path.to_str().unwrap(), src_path.to_str().unwrap()])
+        .output()
+        .expect("failed to execute process");
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, stderr.into_owned()));
+    }
+    let res = fs::read(&bin_path)?;
+    tmp_dir.close()?;
+    Ok(res)
+}
+
 /// encodes an instruction and then disasms the resulting byte sequence with external ndisasm command
 fn ndisasm_instruction(op: &Instruction) -> Result<Vec<String>, io::Error> {
     let encoder = Encoder::new();
@@ -0,0 +1,510 @@
+// IPX, the network protocol of Novell NetWare, as used by DOS games for multiplayer.
+// http://www.ctyme.com/intr/int-7a.htm
+//
+// Programs find the far call entry point of the driver with INT 2Fh AX=7A00h, older ones
+// use INT 7Ah. Each IPX packet is carried in one datagram of the transport, between machines
+// over UDP, or through a `Pipe` between machines in one process.
+
+use std::collections::VecDeque;
+use std::io;
+
+use log::{debug, warn};
+
+use crate::bios::BIOS;
+use crate::cpu::{CPU, R};
+use crate::machine::Component;
+use crate::memory::MMU;
+use crate::net::{open_transport, parse_mac, parse_peer, Transport, TransportKind};
+
+#[cfg(test)]
+#[path = "./ipx_test.rs"]
+mod ipx_test;
+
+/// location of the driver code, in the BIOS rom segment after the packet driver
+const DRIVER_SEG: u16 = 0xF000;
+const DRIVER_OFFSET: u16 = 0x2100;
+
+/// offset of the ESR called for a completed ECB
+const ESR_SLOT: u16 = DRIVER_OFFSET + 0x05;
+
+/// offset of the IRQ handler
+const IRQ_HANDLER: u16 = DRIVER_OFFSET + 0x09;
+
+const IPX_VECTOR: u8 = 0x7A;
+const MULTIPLEX_ID: u8 = 0x7A;
+
+/// hardware interrupt used to call the ESRs of completed ECBs, on the slave PIC
+pub const IPX_IRQ: u8 = 11;
+const IRQ_VECTOR: u8 = 0x70 + IPX_IRQ - 8;
+
+/// length of the IPX header, at the start of each packet
+const HEADER_LEN: usize = 30;
+
+/// largest IPX packet, including the header
+const MAX_PACKET: usize = 576;
+
+const MAX_SOCKETS: usize = 150;
+
+/// sockets handed out when opening socket 0, in host byte order
+const DYNAMIC_SOCKETS: std::ops::Range<u16> = 0x4000..0x8000;
+
+/// duration of a timer tick, at 18.2065 Hz
+const NANOS_PER_TICK: u64 = 54_925_494;
+
+// offsets in the event control block (ECB)
+const ECB_ESR: u16 = 0x04;
+const ECB_IN_USE: u16 = 0x08;
+const ECB_COMPLETION: u16 = 0x09;
+const ECB_SOCKET: u16 = 0x0A;
+const ECB_IMMEDIATE_ADDRESS: u16 = 0x1C;
+const ECB_FRAGMENT_COUNT: u16 = 0x22;
+const ECB_FRAGMENTS: u16 = 0x24;
+
+// in-use flags of the ECB
+const SENDING: u8 = 0xFF;
+const LISTENING: u8 = 0xFE;
+const SCHEDULED: u8 = 0xFD;
+
+// completion codes of the ECB
+const SUCCESS: u8 = 0x00;
+const CANCELLED: u8 = 0xFC;
+const MALFORMED: u8 = 0xFD;
+const FAILED: u8 = 0xFF;
+
+/// settings of the IPX driver
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IpxConfig {
+    pub transport: TransportKind,
+
+    /// local address of the udp transport
+    pub listen: String,
+
+    /// address the udp transport sends to, the sender of the first received packet if unset
+    pub peer: Option<String>,
+
+    /// node address of the machine, must differ between the machines of a network
+    pub node: String,
+}
+
+impl Default for IpxConfig {
+    fn default() -> Self {
+        IpxConfig {
+            transport: TransportKind::None,
+            listen: "0.0.0.0:213".to_owned(),
+            peer: None,
+            node: "02:49:50:58:00:01".to_owned(),
+        }
+    }
+}
+
+impl IpxConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        parse_peer(&self.peer)?;
+        parse_mac(&self.node).map(|_| ())
+    }
+
+    /// opens the configured transport, returns None if IPX is disabled
+    pub fn open_transport(&self) -> io::Result<Option<Box<dyn Transport>>> {
+        open_transport(self.transport, &self.listen, &self.peer)
+    }
+}
+
+/// segment and offset of an event control block
+type Ecb = (u16, u16);
+
+/// step of the ESR calls by the IRQ handler
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EsrStep {
+    /// saves the registers of the interrupted program, and calls the first ESR
+    Start,
+
+    /// calls the next ESR
+    Next,
+
+    /// restores the registers of the interrupted program
+    Restore,
+}
+
+pub struct Ipx {
+    node: [u8; 6],
+
+    transport: Box<dyn Transport>,
+
+    /// open sockets, in network byte order as passed in DX
+    sockets: Vec<u16>,
+
+    /// ECBs waiting for a packet to their socket, in the order they were posted
+    listening: Vec<Ecb>,
+
+    /// ECBs of scheduled events, and the nanoseconds until they complete
+    scheduled: Vec<(Ecb, u64)>,
+
+    /// completed ECBs with an ESR to call
+    completed: VecDeque<Ecb>,
+
+    /// set while the IRQ is raised and the handler calls the ESRs
+    in_service: bool,
+    step: EsrStep,
+
+    /// registers of the program interrupted by the IRQ handler
+    saved: Vec<(R, u32)>,
+
+    /// cycles since the transport was polled
+    poll_cycles: usize,
+}
+
+impl Component for Ipx {
+    fn interrupts(&self) -> Vec<u8> {
+        vec![IPX_VECTOR, IRQ_VECTOR]
+    }
+
+    fn multiplex_ids(&self) -> Vec<u8> {
+        vec![MULTIPLEX_ID]
+    }
+
+    fn int(&mut self, int: u8, cpu: &mut CPU, mmu: &mut MMU) -> bool {
+        match int {
+            0x2F => {
+                // INT 2F AX=7A00h - IPX installation check
+                // Return: AL = FFh if installed, ES:DI = far call entry point
+                if cpu.get_r8(R::AL) != 0x00 {
+                    return false;
+                }
+                cpu.set_r8(R::AL, 0xFF);
+                cpu.set_r16(R::ES, DRIVER_SEG);
+                cpu.set_r16(R::DI, DRIVER_OFFSET);
+                true
+            }
+            IPX_VECTOR => {
+                self.api(cpu, mmu);
+                true
+            }
+            IRQ_VECTOR => {
+                self.call_esrs(cpu, mmu);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Ipx {
+    /// returns a driver configured by `config`, which must be valid, exchanging packets through `transport`
+    pub fn new(config: &IpxConfig, transport: Box<dyn Transport>) -> Self {
+        Ipx {
+            node: parse_mac(&config.node).unwrap(),
+            transport,
+            sockets: Vec::new(),
+            listening: Vec::new(),
+            scheduled: Vec::new(),
+            completed: VecDeque::new(),
+            in_service: false,
+            step: EsrStep::Start,
+            saved: Vec::new(),
+            poll_cycles: 0,
+        }
+    }
+
+    /// writes the far call entry point and IRQ handler to memory, and points the IRQ vector at the handler.
+    /// INT 7Ah reaches the driver through its default handler
+    pub fn install(&self, mmu: &mut MMU) {
+        let code = [
+            0xFE, 0x38, IPX_VECTOR, 0x00,   // callback to the api
+            0xCB,                           // retf
+            0x00, 0x00, 0x00, 0x00,         // esr
+            // irq handler
+            0xFE, 0x38, IRQ_VECTOR, 0x00,   // next: callback, takes the next ESR
+            0x72, 0x07,                     // jc done
+            0x2E, 0xFF, 0x1E, ESR_SLOT as u8, (ESR_SLOT >> 8) as u8, // call far [cs:esr]
+            0xEB, 0xF3,                     // jmp next
+            0xB0, 0x20,                     // done: mov al,0x20
+            0xE6, 0xA0,                     // out 0xa0,al  ; EOI
+            0xE6, 0x20,                     // out 0x20,al
+            0xFE, 0x38, IRQ_VECTOR, 0x00,   // callback, restores the registers
+            0xCF,                           // iret
+        ];
+        mmu.write(DRIVER_SEG, DRIVER_OFFSET, &code);
+        mmu.write_u16(0, u16::from(IRQ_VECTOR) * 4, IRQ_HANDLER);
+        mmu.write_u16(0, u16::from(IRQ_VECTOR) * 4 + 2, DRIVER_SEG);
+    }
+
+    /// polls the transport about once per millisecond of emulated time and completes due events,
+    /// returns true if the IRQ should be raised to call ESRs
+    pub fn update(&mut self, cycles: usize, clock_hz: usize, mmu: &mut MMU) -> bool {
+        self.poll_cycles += cycles;
+        if self.poll_cycles >= clock_hz / 1000 {
+            self.poll_cycles = 0;
+            self.poll(mmu);
+        }
+        if !self.scheduled.is_empty() {
+            let elapsed = cycles as u64 * 1_000_000_000 / clock_hz as u64;
+            let mut i = 0;
+            while i < self.scheduled.len() {
+                let (ecb, left) = self.scheduled[i];
+                if left <= elapsed {
+                    self.scheduled.remove(i);
+                    self.complete(ecb, SUCCESS, mmu);
+                } else {
+                    self.scheduled[i].1 = left - elapsed;
+                    i += 1;
+                }
+            }
+        }
+        if self.in_service || self.completed.is_empty() {
+            return false;
+        }
+        self.in_service = true;
+        true
+    }
+
+    fn poll(&mut self, mmu: &mut MMU) {
+        while let Some(packet) = self.transport.receive() {
+            self.receive(&packet, mmu);
+        }
+    }
+
+    /// hands a received packet to the first ECB listening on its socket
+    fn receive(&mut self, packet: &[u8], mmu: &mut MMU) {
+        if packet.len() < HEADER_LEN {
+            return;
+        }
+        let dest = &packet[10..16];
+        if dest != self.node && dest != [0xFF; 6] {
+            return;
+        }
+        let socket = u16::from_le_bytes([packet[16], packet[17]]);
+        let index = match self.listening.iter().position(|&(seg, offset)| mmu.read_u16(seg, offset.wrapping_add(ECB_SOCKET)) == socket) {
+            Some(index) => index,
+            None => {
+                debug!(target: "io", "ipx: no ECB listening on socket {:04X}, dropping packet", socket.swap_bytes());
+                return;
+            }
+        };
+        let ecb = self.listening.remove(index);
+        let mut rest = packet;
+        for (seg, offset, size) in fragments(ecb, mmu) {
+            let n = rest.len().min(usize::from(size));
+            mmu.write(seg, offset, &rest[..n]);
+            rest = &rest[n..];
+        }
+        // replies go to the sender
+        mmu.write(ecb.0, ecb.1.wrapping_add(ECB_IMMEDIATE_ADDRESS), &packet[22..28]);
+        self.complete(ecb, if rest.is_empty() { SUCCESS } else { MALFORMED }, mmu);
+    }
+
+    /// marks `ecb` done with completion code `code`, and queues its ESR
+    fn complete(&mut self, ecb: Ecb, code: u8, mmu: &mut MMU) {
+        let (seg, offset) = ecb;
+        mmu.write_u8(seg, offset.wrapping_add(ECB_COMPLETION), code);
+        mmu.write_u8(seg, offset.wrapping_add(ECB_IN_USE), 0);
+        if mmu.read_u32(seg, offset.wrapping_add(ECB_ESR)) != 0 {
+            self.completed.push_back(ecb);
+        }
+    }
+
+    /// marks `ecb` as cancelled, without calling its ESR
+    fn cancel(ecb: Ecb, mmu: &mut MMU) {
+        let (seg, offset) = ecb;
+        mmu.write_u8(seg, offset.wrapping_add(ECB_COMPLETION), CANCELLED);
+        mmu.write_u8(seg, offset.wrapping_add(ECB_IN_USE), 0);
+    }
+
+    /// IPX functions, selected by BX
+    fn api(&mut self, cpu: &mut CPU, mmu: &mut MMU) {
+        let ecb = (cpu.get_r16(R::ES), cpu.get_r16(R::SI));
+        match cpu.get_r16(R::BX) {
+            0x0000 => {
+                // OPEN SOCKET, AL = longevity, DX = socket in network byte order, 0 for a dynamic one
+                // Return: AL = 00h success, FFh socket already open, FEh socket table full. DX = socket
+                match self.open_socket(cpu.get_r16(R::DX)) {
+                    Ok(socket) => {
+                        cpu.set_r16(R::DX, socket);
+                        cpu.set_r8(R::AL, 0x00);
+                    }
+                    Err(code) => cpu.set_r8(R::AL, code),
+                }
+            }
+            0x0001 => {
+                // CLOSE SOCKET, DX = socket. pending ECBs of the socket are cancelled
+                let socket = cpu.get_r16(R::DX);
+                self.sockets.retain(|&s| s != socket);
+                let (closed, listening) = self.listening.iter().partition(|&&(seg, offset)| mmu.read_u16(seg, offset.wrapping_add(ECB_SOCKET)) == socket);
+                self.listening = listening;
+                for ecb in closed {
+                    Ipx::cancel(ecb, mmu);
+                }
+            }
+            0x0002 => {
+                // GET LOCAL TARGET, ES:SI = network, node and socket, ES:DI = buffer for the immediate address
+                // Return: AL = 00h, CX = transport time in ticks
+                let node = mmu.read(ecb.0, ecb.1.wrapping_add(4), 6);
+                mmu.write(cpu.get_r16(R::ES), cpu.get_r16(R::DI), &node);
+                cpu.set_r8(R::AL, 0x00);
+                cpu.set_r16(R::CX, 1);
+            }
+            0x0003 => self.send(ecb, mmu),
+            0x0004 => {
+                // LISTEN FOR PACKET, ES:SI = ECB
+                // Return: AL = 00h success, FFh socket not open
+                let socket = mmu.read_u16(ecb.0, ecb.1.wrapping_add(ECB_SOCKET));
+                if self.sockets.contains(&socket) {
+                    mmu.write_u8(ecb.0, ecb.1.wrapping_add(ECB_IN_USE), LISTENING);
+                    self.listening.retain(|&e| e != ecb);
+                    self.listening.push(ecb);
+                    cpu.set_r8(R::AL, 0x00);
+                } else {
+                    mmu.write_u8(ecb.0, ecb.1.wrapping_add(ECB_COMPLETION), FAILED);
+                    mmu.write_u8(ecb.0, ecb.1.wrapping_add(ECB_IN_USE), 0);
+                    cpu.set_r8(R::AL, 0xFF);
+                }
+            }
+            0x0005 | 0x0007 => {
+                // SCHEDULE IPX EVENT and SCHEDULE SPECIAL EVENT, AX = delay in ticks, ES:SI = ECB
+                let delay = u64::from(cpu.get_r16(R::AX)) * NANOS_PER_TICK;
+                mmu.write_u8(ecb.0, ecb.1.wrapping_add(ECB_IN_USE), SCHEDULED);
+                self.scheduled.retain(|&(e, _)| e != ecb);
+                self.scheduled.push((ecb, delay));
+            }
+            0x0006 => {
+                // CANCEL EVENT, ES:SI = ECB
+                // Return: AL = 00h success, F9h can't cancel, FFh not in use
+                let listening = self.listening.len();
+                let scheduled = self.scheduled.len();
+                self.listening.retain(|&e| e != ecb);
+                self.scheduled.retain(|&(e, _)| e != ecb);
+                if self.listening.len() != listening || self.scheduled.len() != scheduled {
+                    Ipx::cancel(ecb, mmu);
+                    cpu.set_r8(R::AL, 0x00);
+                } else if mmu.read_u8(ecb.0, ecb.1.wrapping_add(ECB_IN_USE)) == 0 {
+                    cpu.set_r8(R::AL, 0xFF);
+                } else {
+                    cpu.set_r8(R::AL, 0xF9);
+                }
+            }
+            0x0008 => {
+                // GET INTERVAL MARKER
+                // Return: AX = interval marker in ticks
+                let ticks = mmu.read_u16(BIOS::DATA_SEG, BIOS::DATA_TIMER_TICKS);
+                cpu.set_r16(R::AX, ticks);
+            }
+            0x0009 => {
+                // GET INTERNETWORK ADDRESS, ES:SI = 10 byte buffer for the network and node
+                mmu.write(ecb.0, ecb.1, &[0; 4]);
+                mmu.write(ecb.0, ecb.1.wrapping_add(4), &self.node);
+            }
+            0x000A => {
+                // RELINQUISH CONTROL, called by programs waiting for packets
+                self.poll(mmu);
+            }
+            0x000B => {
+                // DISCONNECT FROM TARGET, there are no connections to close
+            }
+            0x000D => {
+                // GET MAX PACKET SIZE
+                // Return: AX = max packet size, CX = retry count
+                cpu.set_r16(R::AX, MAX_PACKET as u16);
+                cpu.set_r16(R::CX, 0);
+            }
+            bx => debug!(target: "io", "ipx: unknown function {:04X}", bx),
+        }
+    }
+
+    fn open_socket(&mut self, socket: u16) -> Result<u16, u8> {
+        if self.sockets.len() >= MAX_SOCKETS {
+            return Err(0xFE);
+        }
+        let socket = if socket == 0 {
+            match DYNAMIC_SOCKETS.map(u16::swap_bytes).find(|s| !self.sockets.contains(s)) {
+                Some(socket) => socket,
+                None => return Err(0xFE),
+            }
+        } else if self.sockets.contains(&socket) {
+            return Err(0xFF);
+        } else {
+            socket
+        };
+        self.sockets.push(socket);
+        Ok(socket)
+    }
+
+    /// SEND PACKET, ES:SI = ECB. the header of the packet is completed by the driver
+    fn send(&mut self, ecb: Ecb, mmu: &mut MMU) {
+        mmu.write_u8(ecb.0, ecb.1.wrapping_add(ECB_IN_USE), SENDING);
+        let mut packet = Vec::new();
+        for (seg, offset, size) in fragments(ecb, mmu) {
+            packet.extend(mmu.read(seg, offset, usize::from(size)));
+        }
+        if packet.len() < HEADER_LEN || packet.len() > MAX_PACKET {
+            self.complete(ecb, MALFORMED, mmu);
+            return;
+        }
+        let len = packet.len() as u16;
+        packet[0..2].copy_from_slice(&[0xFF, 0xFF]);   // checksum
+        packet[2..4].copy_from_slice(&len.to_be_bytes());
+        packet[4] = 0;                                  // transport control
+        packet[18..22].copy_from_slice(&[0; 4]);        // source network
+        packet[22..28].copy_from_slice(&self.node);
+        packet[28..30].copy_from_slice(&mmu.read(ecb.0, ecb.1.wrapping_add(ECB_SOCKET), 2));
+        let code = match self.transport.send(&packet) {
+            Ok(()) => SUCCESS,
+            Err(e) => {
+                warn!(target: "io", "ipx: error sending packet: {}", e);
+                FAILED
+            }
+        };
+        self.complete(ecb, code, mmu);
+    }
+
+    /// called by the IRQ handler before each ESR call, and once more after the last.
+    /// sets CF when no ESR is left to call
+    fn call_esrs(&mut self, cpu: &mut CPU, mmu: &mut MMU) {
+        match self.step {
+            EsrStep::Start | EsrStep::Next => {
+                if self.step == EsrStep::Start {
+                    self.saved = [R::EAX, R::EBX, R::ECX, R::EDX, R::ESI, R::EDI, R::EBP].iter()
+                        .map(|&r| (r, cpu.get_r32(r)))
+                        .chain([R::DS, R::ES].iter().map(|&r| (r, u32::from(cpu.get_r16(r)))))
+                        .collect();
+                }
+                match self.completed.pop_front() {
+                    Some((seg, offset)) => {
+                        // ES:SI = ECB, AL = FFh when called by IPX
+                        let esr = mmu.read_u32(seg, offset.wrapping_add(ECB_ESR));
+                        mmu.write_u16(DRIVER_SEG, ESR_SLOT, esr as u16);
+                        mmu.write_u16(DRIVER_SEG, ESR_SLOT + 2, (esr >> 16) as u16);
+                        cpu.set_r16(R::ES, seg);
+                        cpu.set_r16(R::SI, offset);
+                        cpu.set_r8(R::AL, 0xFF);
+                        cpu.regs.flags.set_carry(false);
+                        self.step = EsrStep::Next;
+                    }
+                    None => {
+                        cpu.regs.flags.set_carry(true);
+                        self.step = EsrStep::Restore;
+                    }
+                }
+            }
+            EsrStep::Restore => {
+                for &(r, v) in &self.saved {
+                    match r {
+                        R::DS | R::ES => cpu.set_r16(r, v as u16),
+                        _ => cpu.set_r32(r, v),
+                    }
+                }
+                self.step = EsrStep::Start;
+                self.in_service = false;
+            }
+        }
+    }
+}
+
+/// returns the fragments of the packet buffer of `ecb`, as segment, offset and size
+fn fragments(ecb: Ecb, mmu: &MMU) -> Vec<(u16, u16, u16)> {
+    let (seg, offset) = ecb;
+    let count = mmu.read_u16(seg, offset.wrapping_add(ECB_FRAGMENT_COUNT));
+    (0..count).map(|i| {
+        let descriptor = offset.wrapping_add(ECB_FRAGMENTS).wrapping_add(i.wrapping_mul(6));
+        (mmu.read_u16(seg, descriptor.wrapping_add(2)), mmu.read_u16(seg, descriptor), mmu.read_u16(seg, descriptor.wrapping_add(4)))
+    }).collect()
+}
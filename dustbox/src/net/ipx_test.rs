@@ -0,0 +1,108 @@
+use crate::cpu::{CPU, R};
+use crate::memory::MMU;
+use crate::net::ipx::{Ipx, IpxConfig, CANCELLED, MALFORMED};
+use crate::net::{Pipe, Transport, TransportKind};
+
+/// calls IPX function `bx` with ES:SI = `si` and DX = `dx`, returns AL
+fn call(ipx: &mut Ipx, cpu: &mut CPU, mmu: &mut MMU, bx: u16, si: u16, dx: u16) -> u8 {
+    cpu.set_r16(R::BX, bx);
+    cpu.set_r16(R::ES, 0x1000);
+    cpu.set_r16(R::SI, si);
+    cpu.set_r16(R::DX, dx);
+    ipx.api(cpu, mmu);
+    cpu.get_r8(R::AL)
+}
+
+fn packet(node: [u8; 6], socket: [u8; 2], data: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0xFF, 0xFF, 0x00, 0x00, 0x00, 0x04, 0, 0, 0, 0];
+    packet.extend_from_slice(&node);
+    packet.extend_from_slice(&socket);
+    packet.extend_from_slice(&[0, 0, 0, 0, 0x02, 0x49, 0x50, 0x58, 0x00, 0x02, 0x45, 0x45]);
+    packet.extend_from_slice(data);
+    packet
+}
+
+#[test]
+fn can_validate_ipx_config() {
+    assert!(IpxConfig::default().validate().is_ok());
+    assert!(IpxConfig { node: "02:49:50:58".to_owned(), ..IpxConfig::default() }.validate().is_err());
+    assert!(IpxConfig { peer: Some("localhost".to_owned()), ..IpxConfig::default() }.validate().is_err());
+    assert!(IpxConfig::default().open_transport().unwrap().is_none());
+    assert!(IpxConfig { transport: TransportKind::Loopback, ..IpxConfig::default() }.open_transport().unwrap().is_some());
+}
+
+#[test]
+fn ipx_opens_sockets() {
+    let (_, pipe) = Pipe::pair();
+    let mut ipx = Ipx::new(&IpxConfig::default(), Box::new(pipe));
+    let mut cpu = CPU::default();
+    let mut mmu = MMU::default();
+    assert_eq!(0x00, call(&mut ipx, &mut cpu, &mut mmu, 0x0000, 0, 0x4545));
+    assert_eq!(0x4545, cpu.get_r16(R::DX));
+    assert_eq!(0xFF, call(&mut ipx, &mut cpu, &mut mmu, 0x0000, 0, 0x4545));
+
+    // dynamic sockets are returned in network byte order
+    assert_eq!(0x00, call(&mut ipx, &mut cpu, &mut mmu, 0x0000, 0, 0x0000));
+    assert_eq!(0x0040, cpu.get_r16(R::DX));
+
+    call(&mut ipx, &mut cpu, &mut mmu, 0x0001, 0, 0x4545);
+    assert_eq!(0x00, call(&mut ipx, &mut cpu, &mut mmu, 0x0000, 0, 0x4545));
+}
+
+#[test]
+fn ipx_delivers_packets_to_listening_ecbs() {
+    let (mut peer, pipe) = Pipe::pair();
+    let mut ipx = Ipx::new(&IpxConfig::default(), Box::new(pipe));
+    let mut cpu = CPU::default();
+    let mut mmu = MMU::default();
+    call(&mut ipx, &mut cpu, &mut mmu, 0x0000, 0, 0x4545);
+
+    // ECB with the header at 1000:0100 and 4 bytes of data at 1000:0200
+    mmu.write(0x1000, 0x000A, &[0x45, 0x45]);
+    mmu.write(0x1000, 0x0022, &[0x02, 0x00, 0x00, 0x01, 0x00, 0x10, 0x1E, 0x00, 0x00, 0x02, 0x00, 0x10, 0x04, 0x00]);
+    assert_eq!(0xFF, call(&mut ipx, &mut cpu, &mut mmu, 0x0004, 0x0040, 0x0000));
+    assert_eq!(0x00, call(&mut ipx, &mut cpu, &mut mmu, 0x0004, 0x0000, 0x0000));
+    assert_eq!(0xFE, mmu.read_u8(0x1000, 0x0008));
+
+    let other_node = packet([0x02, 0x49, 0x50, 0x58, 0x00, 0x09], [0x45, 0x45], b"skip");
+    let other_socket = packet([0xFF; 6], [0x45, 0x46], b"skip");
+    let broadcast = packet([0xFF; 6], [0x45, 0x45], b"hello!");
+    for p in &[&other_node, &other_socket, &broadcast] {
+        peer.send(p).unwrap();
+    }
+
+    // the transport is polled once per millisecond, without an ESR the IRQ is not raised
+    assert!(!ipx.update(999, 1_000_000, &mut mmu));
+    assert_eq!(0xFE, mmu.read_u8(0x1000, 0x0008));
+    assert!(!ipx.update(1, 1_000_000, &mut mmu));
+    assert_eq!(0x00, mmu.read_u8(0x1000, 0x0008));
+    assert_eq!(MALFORMED, mmu.read_u8(0x1000, 0x0009));
+    assert_eq!(broadcast[..30].to_vec(), mmu.read(0x1000, 0x0100, 30));
+    assert_eq!(b"hell".to_vec(), mmu.read(0x1000, 0x0200, 4));
+    assert_eq!(vec![0x02, 0x49, 0x50, 0x58, 0x00, 0x02], mmu.read(0x1000, 0x001C, 6));
+}
+
+#[test]
+fn ipx_completes_scheduled_events() {
+    let (_, pipe) = Pipe::pair();
+    let mut ipx = Ipx::new(&IpxConfig::default(), Box::new(pipe));
+    let mut cpu = CPU::default();
+    let mut mmu = MMU::default();
+
+    // an event 1 tick away, with an ESR
+    mmu.write_u16(0x1000, 0x0006, 0x2000);
+    cpu.set_r16(R::AX, 1);
+    call(&mut ipx, &mut cpu, &mut mmu, 0x0005, 0x0000, 0x0000);
+    assert_eq!(0xFD, mmu.read_u8(0x1000, 0x0008));
+    assert!(!ipx.update(54_925, 1_000_000, &mut mmu));
+    assert!(ipx.update(1, 1_000_000, &mut mmu));
+    assert_eq!(0x00, mmu.read_u8(0x1000, 0x0008));
+
+    // cancelled events complete without calling the ESR
+    cpu.set_r16(R::AX, 1);
+    call(&mut ipx, &mut cpu, &mut mmu, 0x0005, 0x0040, 0x0000);
+    assert_eq!(0x00, call(&mut ipx, &mut cpu, &mut mmu, 0x0006, 0x0040, 0x0000));
+    assert_eq!(CANCELLED, mmu.read_u8(0x1000, 0x0049));
+    assert_eq!(0xFF, call(&mut ipx, &mut cpu, &mut mmu, 0x0006, 0x0040, 0x0000));
+    assert_eq!(1, ipx.completed.len());
+}
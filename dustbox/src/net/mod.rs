@@ -0,0 +1,10 @@
+// these modules are re-exported as a single module
+
+pub use self::ipx::*;
+mod ipx;
+
+pub use self::packet_driver::*;
+mod packet_driver;
+
+pub use self::transport::*;
+mod transport;
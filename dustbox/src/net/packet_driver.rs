@@ -0,0 +1,477 @@
+// Packet driver interface (class 1, ethernet), as specified by FTP Software.
+// http://crynwr.com/packet_driver.html
+//
+// Programs find the driver by the "PKT DRVR" signature following the entry point of
+// one of the interrupts 60h-80h. Received frames are handed to the programs through
+// their receiver functions, which are called from the handler of the card's IRQ.
+
+use std::collections::VecDeque;
+use std::io;
+
+use log::{debug, warn};
+
+use crate::cpu::{CPU, R, FLAG_CF};
+use crate::machine::Component;
+use crate::memory::MMU;
+use crate::net::{open_transport, parse_peer, Transport, TransportKind, MAX_FRAME};
+
+#[cfg(test)]
+#[path = "./packet_driver_test.rs"]
+mod packet_driver_test;
+
+/// location of the driver code, in the BIOS rom segment above the default interrupt handlers
+const DRIVER_SEG: u16 = 0xF000;
+const DRIVER_OFFSET: u16 = 0x2000;
+
+/// offset of the receiver called for the frame being delivered
+const RECEIVER_SLOT: u16 = DRIVER_OFFSET + 0x11;
+
+/// offset of the IRQ handler
+const IRQ_HANDLER: u16 = DRIVER_OFFSET + 0x15;
+
+/// offset of the driver name
+const DRIVER_NAME: u16 = DRIVER_OFFSET + 0x34;
+
+/// reported interface type, programs usually ask for any type
+const IF_TYPE: u16 = 1;
+const ANY_TYPE: u16 = 0xFFFF;
+const CLASS_ETHERNET: u8 = 1;
+
+const MAX_HANDLES: usize = 16;
+
+/// received frames waiting for delivery
+const MAX_QUEUED: usize = 32;
+
+// error codes, returned in DH
+const BAD_HANDLE: u8 = 1;
+const NO_CLASS: u8 = 2;
+const NO_TYPE: u8 = 3;
+const NO_NUMBER: u8 = 4;
+const CANT_TERMINATE: u8 = 7;
+const BAD_MODE: u8 = 8;
+const NO_SPACE: u8 = 9;
+const TYPE_INUSE: u8 = 10;
+const BAD_COMMAND: u8 = 11;
+const CANT_SEND: u8 = 12;
+
+/// settings of the network card, used through the packet driver
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub transport: TransportKind,
+
+    /// local address of the udp transport
+    pub listen: String,
+
+    /// address the udp transport sends to, the sender of the first received frame if unset
+    pub peer: Option<String>,
+
+    /// software interrupt of the packet driver, 60h to 80h
+    pub vector: u8,
+
+    /// hardware interrupt signalling received frames, 3 to 7
+    pub irq: u8,
+
+    /// ethernet address of the card, such as "02:44:42:00:00:01"
+    pub mac: String,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            transport: TransportKind::None,
+            listen: "0.0.0.0:7654".to_owned(),
+            peer: None,
+            vector: 0x60,
+            irq: 3,
+            mac: "02:44:42:00:00:01".to_owned(),
+        }
+    }
+}
+
+impl NetworkConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.vector < 0x60 || self.vector > 0x80 {
+            return Err(format!("packet driver interrupt {:02X} is not in 60h-80h", self.vector));
+        }
+        if self.irq < 3 || self.irq > 7 {
+            return Err(format!("network irq {} is not in 3-7", self.irq));
+        }
+        parse_peer(&self.peer)?;
+        parse_mac(&self.mac).map(|_| ())
+    }
+
+    /// opens the configured transport, returns None if networking is disabled
+    pub fn open_transport(&self) -> io::Result<Option<Box<dyn Transport>>> {
+        open_transport(self.transport, &self.listen, &self.peer)
+    }
+}
+
+/// parses an ethernet address such as "02:44:42:00:00:01"
+pub fn parse_mac(s: &str) -> Result<[u8; 6], String> {
+    let parts: Vec<&str> = s.split([':', '-']).collect();
+    if parts.len() != 6 {
+        return Err(format!("invalid ethernet address {}", s));
+    }
+    let mut mac = [0; 6];
+    for (i, part) in parts.iter().enumerate() {
+        mac[i] = u8::from_str_radix(part, 16).map_err(|_| format!("invalid ethernet address {}", s))?;
+    }
+    Ok(mac)
+}
+
+/// a packet type registered by a program with access_type
+struct Handle {
+    /// leading bytes of the ethernet type field matched by the handle, any type if empty
+    packet_type: Vec<u8>,
+
+    /// segment and offset of the receiver function
+    receiver: (u16, u16),
+}
+
+/// step of the delivery of a received frame by the IRQ handler
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Delivery {
+    /// asks the receiver for a buffer
+    Request,
+
+    /// copies the frame to the buffer and hands it to the receiver
+    Copy,
+
+    /// restores the registers of the interrupted program
+    Restore,
+}
+
+pub struct PacketDriver {
+    vector: u8,
+    irq: u8,
+    mac: [u8; 6],
+
+    /// filter of received frames: 1 off, 2 own address, 3 and broadcasts,
+    /// 4 and 5 and multicasts, 6 all frames
+    receive_mode: u16,
+
+    transport: Box<dyn Transport>,
+
+    /// registered packet types, indexed by handle
+    handles: Vec<Option<Handle>>,
+
+    /// received frames and the handles they are delivered to
+    queue: VecDeque<(u16, Vec<u8>)>,
+
+    /// set while the IRQ is raised and the handler delivers a frame
+    in_service: bool,
+    delivery: Delivery,
+
+    /// registers of the program interrupted by the IRQ handler
+    saved: Vec<(R, u32)>,
+
+    /// cycles since the transport was polled
+    poll_cycles: usize,
+}
+
+impl Component for PacketDriver {
+    fn interrupts(&self) -> Vec<u8> {
+        vec![self.vector, self.irq_vector()]
+    }
+
+    fn int(&mut self, int: u8, cpu: &mut CPU, mmu: &mut MMU) -> bool {
+        if int == self.vector {
+            self.api(cpu, mmu);
+            true
+        } else if int == self.irq_vector() {
+            self.deliver(cpu, mmu);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl PacketDriver {
+    /// returns a driver configured by `config`, which must be valid, exchanging frames through `transport`
+    pub fn new(config: &NetworkConfig, transport: Box<dyn Transport>) -> Self {
+        PacketDriver {
+            vector: config.vector,
+            irq: config.irq,
+            mac: parse_mac(&config.mac).unwrap(),
+            receive_mode: 3,
+            transport,
+            handles: Vec::new(),
+            queue: VecDeque::new(),
+            in_service: false,
+            delivery: Delivery::Request,
+            saved: Vec::new(),
+            poll_cycles: 0,
+        }
+    }
+
+    pub fn irq(&self) -> u8 {
+        self.irq
+    }
+
+    fn irq_vector(&self) -> u8 {
+        0x08 + self.irq
+    }
+
+    /// writes the driver entry point and IRQ handler to memory, and points their interrupt vectors at them
+    pub fn install(&self, mmu: &mut MMU) {
+        let mut code = vec![
+            0xEB, 0x0A,                     // jmp short 0x0c
+            0x90,                           // nop
+        ];
+        code.extend_from_slice(b"PKT DRVR\0");
+        code.extend_from_slice(&[
+            0xFE, 0x38, self.vector, 0x00,  // callback to the api
+            0xCF,                           // iret
+            0x00, 0x00, 0x00, 0x00,         // receiver
+            // irq handler
+            0xFE, 0x38, self.irq_vector(), 0x00, // callback, asks for a buffer
+            0x72, 0x10,                     // jc done
+            0x2E, 0xFF, 0x1E, RECEIVER_SLOT as u8, (RECEIVER_SLOT >> 8) as u8, // call far [cs:receiver]
+            0xFE, 0x38, self.irq_vector(), 0x00, // callback, copies the frame
+            0x72, 0x05,                     // jc done
+            0x2E, 0xFF, 0x1E, RECEIVER_SLOT as u8, (RECEIVER_SLOT >> 8) as u8, // call far [cs:receiver]
+            0xB0, 0x20,                     // done: mov al,0x20
+            0xE6, 0x20,                     // out 0x20,al  ; EOI
+            0xFE, 0x38, self.irq_vector(), 0x00, // callback, restores the registers
+            0xCF,                           // iret
+        ]);
+        code.extend_from_slice(b"DUSTBOX\0");
+        mmu.write(DRIVER_SEG, DRIVER_OFFSET, &code);
+
+        for &(int, offset) in &[(self.vector, DRIVER_OFFSET), (self.irq_vector(), IRQ_HANDLER)] {
+            mmu.write_u16(0, u16::from(int) * 4, offset);
+            mmu.write_u16(0, u16::from(int) * 4 + 2, DRIVER_SEG);
+        }
+    }
+
+    /// polls the transport about once per millisecond of emulated time,
+    /// returns true if the IRQ should be raised to deliver a frame
+    pub fn update(&mut self, cycles: usize, clock_hz: usize) -> bool {
+        self.poll_cycles += cycles;
+        if self.poll_cycles >= clock_hz / 1000 {
+            self.poll_cycles = 0;
+            while let Some(frame) = self.transport.receive() {
+                self.accept(frame);
+            }
+        }
+        if self.in_service || self.queue.is_empty() {
+            return false;
+        }
+        self.in_service = true;
+        true
+    }
+
+    /// queues a received frame for the first handle matching its type
+    fn accept(&mut self, frame: Vec<u8>) {
+        if frame.len() < 14 || !self.wants_destination(&frame[0..6]) {
+            return;
+        }
+        let handle = self.handles.iter().position(|h| match h {
+            Some(h) => frame[12..].starts_with(&h.packet_type),
+            None => false,
+        });
+        match handle {
+            Some(handle) if self.queue.len() < MAX_QUEUED => self.queue.push_back((handle as u16, frame)),
+            Some(_) => debug!(target: "io", "packet driver: receive queue full, dropping frame"),
+            None => {}
+        }
+    }
+
+    fn wants_destination(&self, dest: &[u8]) -> bool {
+        let multicast = dest[0] & 1 != 0;
+        let broadcast = dest.iter().all(|b| *b == 0xFF);
+        match self.receive_mode {
+            1 => false,
+            2 => dest == self.mac,
+            3 => dest == self.mac || broadcast,
+            4 | 5 => dest == self.mac || multicast,
+            _ => true,
+        }
+    }
+
+    fn handle(&self, cpu: &CPU) -> Option<&Handle> {
+        self.handles.get(usize::from(cpu.get_r16(R::BX))).and_then(|h| h.as_ref())
+    }
+
+    /// INT 60h - packet driver functions, selected by AH
+    fn api(&mut self, cpu: &mut CPU, mmu: &mut MMU) {
+        let result = match cpu.get_r8(R::AH) {
+            0x01 => {
+                // driver_info
+                cpu.set_r16(R::BX, 1);              // version
+                cpu.set_r8(R::CH, CLASS_ETHERNET);
+                cpu.set_r16(R::DX, IF_TYPE);
+                cpu.set_r8(R::CL, 0);               // interface number
+                cpu.set_r16(R::DS, DRIVER_SEG);
+                cpu.set_r16(R::SI, DRIVER_NAME);
+                cpu.set_r8(R::AL, 1);               // basic functions
+                Ok(())
+            }
+            0x02 => self.access_type(cpu, mmu),
+            0x03 => {
+                // release_type
+                let bx = usize::from(cpu.get_r16(R::BX));
+                match self.handles.get_mut(bx) {
+                    Some(h) if h.is_some() => {
+                        *h = None;
+                        Ok(())
+                    }
+                    _ => Err(BAD_HANDLE),
+                }
+            }
+            0x04 => {
+                // send_pkt, DS:SI = buffer, CX = length
+                let len = usize::from(cpu.get_r16(R::CX));
+                let frame = mmu.read(cpu.get_r16(R::DS), cpu.get_r16(R::SI), len);
+                if len > MAX_FRAME {
+                    Err(CANT_SEND)
+                } else if let Err(e) = self.transport.send(&frame) {
+                    warn!(target: "io", "packet driver: error sending frame: {}", e);
+                    Err(CANT_SEND)
+                } else {
+                    Ok(())
+                }
+            }
+            0x05 => Err(CANT_TERMINATE),
+            0x06 => {
+                // get_address, ES:DI = buffer, CX = length
+                if self.handle(cpu).is_none() {
+                    Err(BAD_HANDLE)
+                } else if cpu.get_r16(R::CX) < 6 {
+                    Err(NO_SPACE)
+                } else {
+                    mmu.write(cpu.get_r16(R::ES), cpu.get_r16(R::DI), &self.mac);
+                    cpu.set_r16(R::CX, 6);
+                    Ok(())
+                }
+            }
+            0x07 => {
+                // reset_interface
+                self.queue.clear();
+                Ok(())
+            }
+            0x14 => {
+                // set_rcv_mode, CX = mode
+                match cpu.get_r16(R::CX) {
+                    mode @ 1..=6 => {
+                        self.receive_mode = mode;
+                        Ok(())
+                    }
+                    _ => Err(BAD_MODE),
+                }
+            }
+            0x15 => {
+                // get_rcv_mode
+                cpu.set_r16(R::AX, self.receive_mode);
+                Ok(())
+            }
+            ah => {
+                debug!(target: "io", "packet driver: unknown function {:02X}", ah);
+                Err(BAD_COMMAND)
+            }
+        };
+        if let Err(code) = result {
+            cpu.set_r8(R::DH, code);
+        }
+        mmu.set_flag(FLAG_CF, result.is_err());
+    }
+
+    /// access_type: AL = class, BX = type, DL = number, DS:SI = packet type, CX = its length, ES:DI = receiver
+    fn access_type(&mut self, cpu: &mut CPU, mmu: &MMU) -> Result<(), u8> {
+        if cpu.get_r8(R::AL) != CLASS_ETHERNET {
+            return Err(NO_CLASS);
+        }
+        let if_type = cpu.get_r16(R::BX);
+        if if_type != IF_TYPE && if_type != ANY_TYPE {
+            return Err(NO_TYPE);
+        }
+        if cpu.get_r8(R::DL) > 1 {
+            return Err(NO_NUMBER);
+        }
+        let packet_type = mmu.read(cpu.get_r16(R::DS), cpu.get_r16(R::SI), usize::from(cpu.get_r16(R::CX)));
+        if self.handles.iter().flatten().any(|h| h.packet_type == packet_type) {
+            return Err(TYPE_INUSE);
+        }
+        let handle = Handle {
+            packet_type,
+            receiver: (cpu.get_r16(R::ES), cpu.get_r16(R::DI)),
+        };
+        let index = match self.handles.iter().position(Option::is_none) {
+            Some(index) => index,
+            None if self.handles.len() < MAX_HANDLES => {
+                self.handles.push(None);
+                self.handles.len() - 1
+            }
+            None => return Err(NO_SPACE),
+        };
+        self.handles[index] = Some(handle);
+        cpu.set_r16(R::AX, index as u16);
+        Ok(())
+    }
+
+    /// called three times by the IRQ handler, which calls the receiver in between.
+    /// sets CF to skip the receiver calls
+    fn deliver(&mut self, cpu: &mut CPU, mmu: &mut MMU) {
+        match self.delivery {
+            Delivery::Request => {
+                self.saved = [R::EAX, R::EBX, R::ECX, R::EDX, R::ESI, R::EDI, R::EBP].iter()
+                    .map(|&r| (r, cpu.get_r32(r)))
+                    .chain([R::DS, R::ES].iter().map(|&r| (r, u32::from(cpu.get_r16(r)))))
+                    .collect();
+                // frames of released handles are dropped
+                while let Some(&(handle, _)) = self.queue.front() {
+                    if matches!(self.handles.get(usize::from(handle)), Some(Some(_))) {
+                        break;
+                    }
+                    self.queue.pop_front();
+                }
+                let (handle, len) = match self.queue.front() {
+                    Some((handle, frame)) => (*handle, frame.len() as u16),
+                    None => {
+                        cpu.regs.flags.set_carry(true);
+                        self.delivery = Delivery::Restore;
+                        return;
+                    }
+                };
+                let (seg, offset) = self.handles[usize::from(handle)].as_ref().unwrap().receiver;
+                mmu.write_u16(DRIVER_SEG, RECEIVER_SLOT, offset);
+                mmu.write_u16(DRIVER_SEG, RECEIVER_SLOT + 2, seg);
+                cpu.set_r16(R::AX, 0);
+                cpu.set_r16(R::BX, handle);
+                cpu.set_r16(R::CX, len);
+                cpu.regs.flags.set_carry(false);
+                self.delivery = Delivery::Copy;
+            }
+            Delivery::Copy => {
+                // ES:DI = buffer from the receiver, or 0:0 to drop the frame.
+                // the frame is gone if the receiver reset the interface
+                let (es, di) = (cpu.get_r16(R::ES), cpu.get_r16(R::DI));
+                let (handle, frame) = self.queue.pop_front().unwrap_or_default();
+                if frame.is_empty() || (es == 0 && di == 0) {
+                    cpu.regs.flags.set_carry(true);
+                } else {
+                    mmu.write(es, di, &frame);
+                    cpu.set_r16(R::DS, es);
+                    cpu.set_r16(R::SI, di);
+                    cpu.set_r16(R::AX, 1);
+                    cpu.set_r16(R::BX, handle);
+                    cpu.set_r16(R::CX, frame.len() as u16);
+                    cpu.regs.flags.set_carry(false);
+                }
+                self.delivery = Delivery::Restore;
+            }
+            Delivery::Restore => {
+                for &(r, v) in &self.saved {
+                    match r {
+                        R::DS | R::ES => cpu.set_r16(r, v as u16),
+                        _ => cpu.set_r32(r, v),
+                    }
+                }
+                self.delivery = Delivery::Request;
+                self.in_service = false;
+            }
+        }
+    }
+}
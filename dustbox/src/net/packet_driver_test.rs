@@ -0,0 +1,63 @@
+use crate::net::packet_driver::{parse_mac, Handle, NetworkConfig, PacketDriver};
+use crate::net::{Loopback, Transport, TransportKind, Udp};
+
+#[test]
+fn can_parse_mac() {
+    assert_eq!(Ok([0x02, 0x44, 0x42, 0x00, 0x00, 0x01]), parse_mac("02:44:42:00:00:01"));
+    assert_eq!(Ok([0x00, 0xA0, 0xCC, 0x12, 0x34, 0xFF]), parse_mac("00-a0-cc-12-34-ff"));
+    assert!(parse_mac("02:44:42:00:00").is_err());
+    assert!(parse_mac("02:44:42:00:00:GG").is_err());
+}
+
+#[test]
+fn can_validate_network_config() {
+    assert!(NetworkConfig::default().validate().is_ok());
+    assert!(NetworkConfig { vector: 0x21, ..NetworkConfig::default() }.validate().is_err());
+    assert!(NetworkConfig { irq: 9, ..NetworkConfig::default() }.validate().is_err());
+    assert!(NetworkConfig { peer: Some("localhost".to_owned()), ..NetworkConfig::default() }.validate().is_err());
+    assert!(NetworkConfig::default().open_transport().unwrap().is_none());
+    assert!(NetworkConfig { transport: TransportKind::Loopback, ..NetworkConfig::default() }.open_transport().unwrap().is_some());
+}
+
+#[test]
+fn can_exchange_frames_over_udp() {
+    let mut a = Udp::new("127.0.0.1:0", None).unwrap();
+    let mut b = Udp::new("127.0.0.1:0", Some(a.local_addr().unwrap())).unwrap();
+    assert!(a.send(b"unknown peer").is_err());
+
+    b.send(b"frame").unwrap();
+    let mut received = None;
+    for _ in 0..100 {
+        received = a.receive();
+        if received.is_some() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert_eq!(Some(b"frame".to_vec()), received);
+
+    // replies go to the sender
+    assert!(a.send(b"reply").is_ok());
+}
+
+#[test]
+fn packet_driver_filters_received_frames() {
+    let own = [0x02, 0x44, 0x42, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0, 0x08, 0x00, 0x45];
+    let other = [0x02, 0x44, 0x42, 0x00, 0x00, 0x02, 0, 0, 0, 0, 0, 0, 0x08, 0x00, 0x45];
+    let broadcast = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0, 0, 0x08, 0x06, 0x00];
+    let mut transport = Loopback::default();
+    for frame in &[&own[..], &other[..], &broadcast[..], &own[..10]] {
+        transport.send(frame).unwrap();
+    }
+    let mut driver = PacketDriver::new(&NetworkConfig::default(), Box::new(transport));
+    driver.handles.push(Some(Handle { packet_type: vec![0x08, 0x06], receiver: (0x085F, 0x0110) }));
+    driver.handles.push(Some(Handle { packet_type: vec![0x08], receiver: (0x085F, 0x0120) }));
+
+    // the transport is polled once per millisecond
+    assert!(!driver.update(999, 1_000_000));
+    assert!(driver.update(1, 1_000_000));
+    assert_eq!(vec![(1, own.to_vec()), (0, broadcast.to_vec())], driver.queue.iter().cloned().collect::<Vec<_>>());
+
+    // raised once until delivered
+    assert!(!driver.update(1000, 1_000_000));
+}
@@ -0,0 +1,137 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+
+/// largest ethernet frame, without the checksum
+pub const MAX_FRAME: usize = 1514;
+
+/// the host side of an emulated network
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum TransportKind {
+    /// no network
+    #[serde(rename = "none")]
+    None,
+
+    /// sent frames are received again
+    #[serde(rename = "loopback")]
+    Loopback,
+
+    /// frames are exchanged as UDP datagrams with a peer
+    #[serde(rename = "udp")]
+    Udp,
+}
+
+/// parses the address of a udp peer, such as "192.168.1.20:7654"
+pub fn parse_peer(peer: &Option<String>) -> Result<Option<SocketAddr>, String> {
+    match peer {
+        Some(peer) => peer.parse().map(Some).map_err(|_| format!("invalid udp peer {}", peer)),
+        None => Ok(None),
+    }
+}
+
+/// opens a transport of `kind`, returns None for TransportKind::None
+pub fn open_transport(kind: TransportKind, listen: &str, peer: &Option<String>) -> io::Result<Option<Box<dyn Transport>>> {
+    Ok(match kind {
+        TransportKind::None => None,
+        TransportKind::Loopback => Some(Box::new(Loopback::default())),
+        TransportKind::Udp => {
+            let peer = parse_peer(peer).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            Some(Box::new(Udp::new(listen, peer)?))
+        }
+    })
+}
+
+/// carries the ethernet frames of the emulated network card to the host
+pub trait Transport: Send {
+    fn send(&mut self, frame: &[u8]) -> io::Result<()>;
+
+    /// returns a received frame, without blocking
+    fn receive(&mut self) -> Option<Vec<u8>>;
+}
+
+/// returns each sent frame as received, for tests
+#[derive(Default)]
+pub struct Loopback {
+    frames: VecDeque<Vec<u8>>,
+}
+
+impl Transport for Loopback {
+    fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.frames.push_back(frame.to_vec());
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        self.frames.pop_front()
+    }
+}
+
+/// one end of an in-process link, such as between two machines in a test
+pub struct Pipe {
+    incoming: Arc<Mutex<VecDeque<Vec<u8>>>>,
+    outgoing: Arc<Mutex<VecDeque<Vec<u8>>>>,
+}
+
+impl Pipe {
+    /// returns both ends of a link, frames sent on one end are received on the other
+    pub fn pair() -> (Pipe, Pipe) {
+        let a = Arc::new(Mutex::new(VecDeque::new()));
+        let b = Arc::new(Mutex::new(VecDeque::new()));
+        (Pipe { incoming: a.clone(), outgoing: b.clone() }, Pipe { incoming: b, outgoing: a })
+    }
+}
+
+impl Transport for Pipe {
+    fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.outgoing.lock().unwrap().push_back(frame.to_vec());
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        self.incoming.lock().unwrap().pop_front()
+    }
+}
+
+/// sends each frame as one UDP datagram to a peer, such as another emulator
+pub struct Udp {
+    socket: UdpSocket,
+
+    /// where frames are sent, the sender of the last received datagram if unset
+    peer: Option<SocketAddr>,
+}
+
+impl Udp {
+    /// binds to `listen`, such as "0.0.0.0:7654"
+    pub fn new(listen: &str, peer: Option<SocketAddr>) -> io::Result<Self> {
+        let socket = UdpSocket::bind(listen)?;
+        socket.set_nonblocking(true)?;
+        Ok(Udp { socket, peer })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+}
+
+impl Transport for Udp {
+    fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+        match self.peer {
+            Some(peer) => self.socket.send_to(frame, peer).map(|_| ()),
+            None => Err(io::Error::new(io::ErrorKind::NotConnected, "no udp peer")),
+        }
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        let mut buf = [0u8; MAX_FRAME];
+        match self.socket.recv_from(&mut buf) {
+            Ok((len, from)) => {
+                if self.peer.is_none() {
+                    self.peer = Some(from);
+                }
+                Some(buf[..len].to_vec())
+            }
+            Err(_) => None,
+        }
+    }
+}
@@ -1,8 +1,16 @@
 // Programmable Interrupt Controller (8259A)
 // https://wiki.osdev.org/8259_PIC
+// https://pdos.csail.mit.edu/6.828/2010/readings/hardware/8259A.pdf
 
 // The 8259 PIC controls the CPU's interrupt mechanism, by accepting several
 // interrupt requests and feeding them to the processor in order.
+//
+// An AT has two controllers: the master at port 0020 handles IRQ 0-7, the slave
+// at port 00A0 handles IRQ 8-15 and is cascaded into IRQ 2 of the master.
+
+use std::ops::RangeInclusive;
+
+use log::{trace, warn};
 
 use crate::machine::Component;
 
@@ -10,9 +18,10 @@ use crate::machine::Component;
 #[path = "./pic_test.rs"]
 mod pic_test;
 
-const DEBUG_PIC: bool = false;
+/// the master input the slave is connected to
+const CASCADE_IRQ: u8 = 2;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 enum OperationMode {
     Clear,                              // 0 rotate in auto EOI mode (clear)
     NonspecificEOI,                     // 1 (WORD_A) nonspecific EOI
@@ -24,32 +33,51 @@ enum OperationMode {
     RotateOnSpecificEOICommand,         // 7 (WORD_D) rotate on specific EOI command
 }
 
+/// the initialization command word expected on the data port
+#[derive(Clone, Debug, PartialEq)]
+enum InitStep {
+    Ready,
+    Icw2,
+    Icw3,
+    Icw4,
+}
+
+/// the master and slave controllers of an AT
 #[derive(Clone)]
 pub struct PIC {
-    command: u8,
-    data: u8,
-
-    /// the base offset for I/O
-    io_base: u16,
-
-    operation: OperationMode,
+    pub master: Controller,
+    pub slave: Controller,
 }
 
 impl Component for PIC {
+    fn io_ports(&self) -> Vec<RangeInclusive<u16>> {
+        vec![
+            self.master.io_base..=self.master.io_base + 1,
+            self.slave.io_base..=self.slave.io_base + 1,
+        ]
+    }
+
     fn in_u8(&mut self, port: u16) -> Option<u8> {
         match port {
-            _ if port < self.io_base => None,
-            _ if port - self.io_base == 0x0000 => Some(self.get_register()),
-            _ if port - self.io_base == 0x0001 => Some(self.get_ocw1()),
+            0x0020 | 0x00A0 => {
+                let slave = port == 0x00A0;
+                if self.controller(slave).poll {
+                    return Some(self.poll(slave));
+                }
+                Some(self.get_register(slave))
+            }
+            0x0021 => Some(self.master.get_ocw1()),
+            0x00A1 => Some(self.slave.get_ocw1()),
             _ => None
         }
     }
 
     fn out_u8(&mut self, port: u16, data: u8) -> bool {
         match port {
-            _ if port < self.io_base => return false,
-            _ if port - self.io_base == 0x0000 => self.set_command(data),
-            _ if port - self.io_base == 0x0001 => self.set_data(data),
+            0x0020 => self.master.set_command(data),
+            0x0021 => self.master.set_data(data),
+            0x00A0 => self.slave.set_command(data),
+            0x00A1 => self.slave.set_data(data),
             _ => return false
         }
         true
@@ -57,29 +85,92 @@ impl Component for PIC {
 }
 
 impl PIC {
-    pub fn new(io_base: u16) -> Self {
+    /// returns the controllers as initialized by the BIOS: IRQ 0-7 at INT 08h-0Fh, IRQ 8-15 at INT 70h-77h
+    pub fn default() -> Self {
         PIC {
-            command: 0,
-            data: 0,
-            io_base,
-            operation: OperationMode::NoOperation, // XXX default?
+            master: Controller::initialized(0x0020, 0x08, 1 << CASCADE_IRQ, 0xB8),
+            slave: Controller::initialized(0x00A0, 0x70, CASCADE_IRQ, 0x8E),
         }
     }
 
-    /// io read of port 0021 (pic1) or 00A1 (pic2)
-    fn get_ocw1(&self) -> u8 {
-        // read: PIC master interrupt mask register OCW1
-        if DEBUG_PIC {
-            println!("PIC {:04x} get_ocw1", self.io_base);
+    fn controller(&mut self, slave: bool) -> &mut Controller {
+        if slave {
+            &mut self.slave
+        } else {
+            &mut self.master
         }
-        0 // XXX
     }
 
-    /// io read of port 0020 (pic1) or 00A0 (pic2)
-    fn get_register(&self) -> u8 {
-        if DEBUG_PIC {
-            println!("PIC {:04x} get_register", self.io_base);
+    /// sets the level of input line `irq`, 0-15
+    pub fn set_irq_line(&mut self, irq: u8, level: bool) {
+        trace!(target: "io", "PIC irq {} line {}", irq, level);
+        self.controller(irq >= 8).set_line(irq & 7, level);
+    }
+
+    /// raises and lowers input line `irq`, requesting an interrupt in edge triggered mode
+    pub fn pulse_irq(&mut self, irq: u8) {
+        self.set_irq_line(irq, true);
+        self.set_irq_line(irq, false);
+    }
+
+    /// returns the master request register, with the cascade input raised by a slave request
+    fn master_requests(&self) -> u8 {
+        if !self.master.single && self.slave.highest_request(self.slave.irr).is_some() {
+            self.master.irr | 1 << CASCADE_IRQ
+        } else {
+            self.master.irr
         }
+    }
+
+    /// returns true if an interrupt is requested from the cpu
+    pub fn pending(&self) -> bool {
+        self.master.highest_request(self.master_requests()).is_some()
+    }
+
+    /// interrupt acknowledge cycle: returns the vector of the highest priority request, if any,
+    /// and marks it in service
+    pub fn acknowledge(&mut self) -> Option<u8> {
+        let irq = self.master.highest_request(self.master_requests())?;
+        self.master.acknowledge(irq);
+        if irq == CASCADE_IRQ && !self.master.single {
+            return match self.slave.highest_request(self.slave.irr) {
+                Some(irq) => {
+                    self.slave.acknowledge(irq);
+                    Some(self.slave.vector_base + irq)
+                }
+                None => Some(self.slave.vector_base + 7), // spurious interrupt
+            };
+        }
+        Some(self.master.vector_base + irq)
+    }
+
+    /// specific EOI for `irq`, 0-15, as sent by the BIOS handlers
+    pub fn end_of_interrupt(&mut self, irq: u8) {
+        if irq >= 8 {
+            self.slave.isr &= !(1 << (irq & 7));
+            self.master.isr &= !(1 << CASCADE_IRQ);
+        } else {
+            self.master.isr &= !(1 << irq);
+        }
+    }
+
+    /// poll command: reads the highest priority request as 80h | irq and marks it in service,
+    /// or returns 0 if there is none
+    fn poll(&mut self, slave: bool) -> u8 {
+        let requests = if slave { self.slave.irr } else { self.master_requests() };
+        let pic = self.controller(slave);
+        pic.poll = false;
+        match pic.highest_request(requests) {
+            Some(irq) => {
+                pic.acknowledge(irq);
+                0x80 | irq
+            }
+            None => 0,
+        }
+    }
+
+    /// io read of port 0020 (pic1) or 00A0 (pic2)
+    fn get_register(&self, slave: bool) -> u8 {
         /*
         0020  R-  PIC  interrupt request/in-service registers after OCW3
         request register:
@@ -89,40 +180,211 @@ impl PIC {
             bit 7-0 = 0  corresponding line not currently being serviced
                 = 1  corresponding int. line currently being serviced
         */
-        0 // XXX
+        let pic = if slave { &self.slave } else { &self.master };
+        trace!(target: "io", "PIC {:04x} get_register", pic.io_base);
+        if pic.read_isr {
+            pic.isr
+        } else if slave {
+            pic.irr
+        } else {
+            self.master_requests()
+        }
+    }
+}
+
+/// a single 8259A controller
+#[derive(Clone)]
+pub struct Controller {
+    /// interrupt request register, the irqs waiting to be serviced
+    pub irr: u8,
+
+    /// in-service register, the irqs being serviced
+    pub isr: u8,
+
+    /// interrupt mask register, written with OCW1
+    pub imr: u8,
+
+    /// interrupt vector of irq 0 of the controller, written with ICW2
+    pub vector_base: u8,
+
+    /// the irq with the lowest priority, the others follow it in priority order
+    pub lowest_priority: u8,
+
+    /// the base offset for I/O
+    io_base: u16,
+
+    /// input line levels, for edge detection
+    lines: u8,
+
+    /// ICW1: requests are level triggered, else edge triggered
+    level_triggered: bool,
+
+    /// ICW1: single controller, no ICW3 is written
+    single: bool,
+
+    /// ICW1: ICW4 is written
+    icw4_needed: bool,
+
+    /// ICW3: the inputs with a slave on the master, the cascade id on a slave
+    cascade: u8,
+
+    /// ICW4: the in-service bit is not set on acknowledge, no EOI is needed
+    auto_eoi: bool,
+
+    /// OCW2: in auto EOI mode, the acknowledged irq gets the lowest priority
+    rotate_in_auto_eoi: bool,
+
+    /// OCW3: lower priority irqs are serviced while an irq is in service
+    special_mask: bool,
+
+    /// OCW3: the command port reads the in-service register, else the request register
+    read_isr: bool,
+
+    /// OCW3: the next read of the command port is a poll
+    poll: bool,
+
+    init_step: InitStep,
+}
+
+impl Controller {
+    pub fn new(io_base: u16) -> Self {
+        Controller {
+            irr: 0,
+            isr: 0,
+            imr: 0xFF,
+            vector_base: 0,
+            lowest_priority: 7,
+            io_base,
+            lines: 0,
+            level_triggered: false,
+            single: false,
+            icw4_needed: false,
+            cascade: 0,
+            auto_eoi: false,
+            rotate_in_auto_eoi: false,
+            special_mask: false,
+            read_isr: false,
+            poll: false,
+            init_step: InitStep::Ready,
+        }
+    }
+
+    /// returns a controller initialized in cascade mode with vectors from `vector_base`, as by the BIOS
+    fn initialized(io_base: u16, vector_base: u8, cascade: u8, mask: u8) -> Self {
+        let mut pic = Controller::new(io_base);
+        pic.set_command(0x11); // ICW1: edge triggered, cascade mode, ICW4 needed
+        pic.set_data(vector_base);
+        pic.set_data(cascade);
+        pic.set_data(0x01); // ICW4: 8086 mode
+        pic.set_data(mask);
+        pic
+    }
+
+    /// returns the irqs from highest to lowest priority
+    fn priority_order(&self) -> impl Iterator<Item = u8> {
+        let lowest = self.lowest_priority;
+        (1..=8).map(move |i| (lowest + i) & 7)
+    }
+
+    /// returns the highest priority unmasked request in `requests` that is not blocked
+    /// by an irq in service with a higher priority
+    fn highest_request(&self, requests: u8) -> Option<u8> {
+        for irq in self.priority_order() {
+            let bit = 1 << irq;
+            if self.isr & bit != 0 && !self.special_mask {
+                return None;
+            }
+            if requests & bit != 0 && self.imr & bit == 0 && self.isr & bit == 0 {
+                return Some(irq);
+            }
+        }
+        None
+    }
+
+    /// returns the highest priority irq in service
+    fn highest_in_service(&self) -> Option<u8> {
+        self.priority_order().find(|irq| self.isr & (1 << irq) != 0)
+    }
+
+    fn set_line(&mut self, irq: u8, level: bool) {
+        let bit = 1 << irq;
+        if level && (self.level_triggered || self.lines & bit == 0) {
+            self.irr |= bit;
+        }
+        if !level {
+            self.lines &= !bit;
+            if self.level_triggered {
+                self.irr &= !bit;
+            }
+        } else {
+            self.lines |= bit;
+        }
+    }
+
+    fn acknowledge(&mut self, irq: u8) {
+        let bit = 1 << irq;
+        self.irr &= !bit;
+        if self.auto_eoi {
+            if self.rotate_in_auto_eoi {
+                self.lowest_priority = irq;
+            }
+        } else {
+            self.isr |= bit;
+        }
+    }
+
+    /// io read of port 0021 (pic1) or 00A1 (pic2)
+    fn get_ocw1(&self) -> u8 {
+        // read: PIC master interrupt mask register OCW1
+        trace!(target: "io", "PIC {:04x} get_ocw1", self.io_base);
+        self.imr
     }
 
     /// PIC - Command register, port 0x0020
     fn set_command(&mut self, val: u8) {
-        if DEBUG_PIC {
-            println!("PIC {:04X} COMMAND: {:02x} == {:08b}", self.io_base, val, val);
+        trace!(target: "io", "PIC {:04X} COMMAND: {:02x} == {:08b}", self.io_base, val, val);
+
+        if val & 0x10 != 0 {
+            /*
+            0020  -W  PIC initialization command word ICW1 (see #P0010)
+            Bit(s)	Description	(Table P0010)
+            7-5	0 (only used in 8080/8085 mode)
+            4	ICW1 is being issued
+            3	(LTIM)
+                =0  edge triggered mode
+                =1  level triggered mode
+            2	interrupt vector size
+                =0 successive interrupt vectors use 8 bytes (8080/8085)
+                =1 successive interrupt vectors use 4 bytes (80x86)
+            1	(SNGL)
+                =0  cascade mode
+                =1  single mode, no ICW3 needed
+            0	ICW4 needed
+            SeeAlso: #P0011,#P0012,#P0013
+            */
+            self.level_triggered = val & 0b1000 != 0;
+            self.single = val & 0b10 != 0;
+            self.icw4_needed = val & 0b1 != 0;
+            self.irr = 0;
+            self.isr = 0;
+            self.imr = 0;
+            self.lines = 0;
+            self.lowest_priority = 7;
+            self.auto_eoi = false;
+            self.rotate_in_auto_eoi = false;
+            self.special_mask = false;
+            self.read_isr = false;
+            self.poll = false;
+            self.init_step = InitStep::Icw2;
+            return;
         }
-        // XXX 0x20 == 0b0010_0000 == EOI - End of interrrupt command code
-        self.command = val;
 
-        /*
-        0020  -W  PIC initialization command word ICW1 (see #P0010)
-        Bit(s)	Description	(Table P0010)
-        7-5	0 (only used in 8080/8085 mode)
-        4	ICW1 is being issued
-        3	(LTIM)
-            =0  edge triggered mode
-            =1  level triggered mode
-        2	interrupt vector size
-            =0 successive interrupt vectors use 8 bytes (8080/8085)
-            =1 successive interrupt vectors use 4 bytes (80x86)
-        1	(SNGL)
-            =0  cascade mode
-            =1  single mode, no ICW3 needed
-        0	ICW4 needed
-        SeeAlso: #P0011,#P0012,#P0013
-        */
         let kind = (val >> 3) & 0b11; // bits 4-3: reserved (00 - signals OCW2)
         match kind {
             0 => { // 0020  -W  PIC output control word OCW2
                 // SeeAlso: #P0014,#P0016
                 let operation = (val >> 5) & 0b111; // bits 7-5: operation
-                self.operation = match operation {
+                let operation = match operation {
                     0 => OperationMode::Clear,
                     1 => OperationMode::NonspecificEOI,
                     2 => OperationMode::NoOperation,
@@ -134,9 +396,27 @@ impl PIC {
                     _ => unreachable!(),
                 };
 
-                let data = val & 0b11; // bits 0-2: interrupt request to which the command applies
+                let irq = val & 0b111; // bits 0-2: interrupt request to which the command applies
                 //     (only used by WORD_B, WORD_D, and WORD_E)
-                println!("XXX: pic ocw2 operation {:?}, data {}", self.operation, data);
+                match operation {
+                    OperationMode::Clear => self.rotate_in_auto_eoi = false,
+                    OperationMode::Set => self.rotate_in_auto_eoi = true,
+                    OperationMode::NonspecificEOI | OperationMode::RotateOnNonspecificEOICommand => {
+                        if let Some(irq) = self.highest_in_service() {
+                            self.isr &= !(1 << irq);
+                            if operation == OperationMode::RotateOnNonspecificEOICommand {
+                                self.lowest_priority = irq;
+                            }
+                        }
+                    }
+                    OperationMode::SpecificEOI => self.isr &= !(1 << irq),
+                    OperationMode::RotateOnSpecificEOICommand => {
+                        self.isr &= !(1 << irq);
+                        self.lowest_priority = irq;
+                    }
+                    OperationMode::SetPriorityCommand => self.lowest_priority = irq,
+                    OperationMode::NoOperation => {}
+                }
             }
             1 => { // 0020  -W  PIC output control word OCW3 (see #P0016)
                 // Bit(s)	Description	(Table P0016)
@@ -154,24 +434,42 @@ impl PIC {
                 //     lower priority) to be processed while an interrupt is already in
                 //     service, but will not re-issue an interrupt for a particular IRQ
                 //     while it remains in service
+                if val & 0b0100_0000 != 0 {
+                    self.special_mask = val & 0b0010_0000 != 0;
+                }
+                self.poll = val & 0b100 != 0;
+                if val & 0b10 != 0 {
+                    self.read_isr = val & 0b1 != 0;
+                }
             }
-            _ => panic!("unhandled kind {}", kind),
+            _ => warn!(target: "io", "PIC {:04X}: invalid command {:02X}", self.io_base, val),
         }
     }
 
     /// Master PIC - Data register, port 0x0021
     fn set_data(&mut self, val: u8) {
-        if DEBUG_PIC {
-            println!("PIC {:04x} set_data = {:02x}", self.io_base, val);
+        trace!(target: "io", "PIC {:04x} set_data = {:02x}", self.io_base, val);
+        match self.init_step {
+            InitStep::Ready => self.imr = val, // OCW1: interrupt mask
+            InitStep::Icw2 => {
+                self.vector_base = val & 0xF8;
+                self.init_step = if !self.single {
+                    InitStep::Icw3
+                } else if self.icw4_needed {
+                    InitStep::Icw4
+                } else {
+                    InitStep::Ready
+                };
+            }
+            InitStep::Icw3 => {
+                self.cascade = val;
+                self.init_step = if self.icw4_needed { InitStep::Icw4 } else { InitStep::Ready };
+            }
+            InitStep::Icw4 => {
+                // bit 1 = automatic EOI, bit 0 = 8086 mode
+                self.auto_eoi = val & 0b10 != 0;
+                self.init_step = InitStep::Ready;
+            }
         }
-
-        // XXX: one value if written immediately after value to 0020, another otherwise....
-        self.data = val;
-
-        // XXX impl, from https://wiki.osdev.org/8259_PIC#Disabling
-        //If you are going to use the processor local APIC and the IOAPIC, you must first disable the PIC. This is done via:
-        //mov al, 0xff
-        //out 0xa1, al
-        //out 0x21, al
     }
 }
@@ -0,0 +1,154 @@
+use crate::machine::Component;
+use crate::pic::PIC;
+
+/// returns the controllers with all irqs unmasked
+fn unmasked_pic() -> PIC {
+    let mut pic = PIC::default();
+    pic.out_u8(0x21, 0x00);
+    pic.out_u8(0xA1, 0x00);
+    pic
+}
+
+#[test]
+fn can_nest_interrupts_by_priority() {
+    let mut pic = unmasked_pic();
+    pic.pulse_irq(3);
+    pic.pulse_irq(1);
+    assert!(pic.pending());
+    assert_eq!(Some(0x09), pic.acknowledge());
+
+    // irq 3 has a lower priority than irq 1 in service
+    assert_eq!(None, pic.acknowledge());
+
+    // irq 0 has a higher priority and nests
+    pic.pulse_irq(0);
+    assert_eq!(Some(0x08), pic.acknowledge());
+    assert_eq!(0b0000_1000, pic.master.irr);
+    assert_eq!(0b0000_0011, pic.master.isr);
+
+    // non-specific EOI ends the highest priority irq in service
+    pic.out_u8(0x20, 0x20);
+    assert_eq!(0b0000_0010, pic.master.isr);
+    assert_eq!(None, pic.acknowledge());
+    pic.out_u8(0x20, 0x20);
+    assert_eq!(Some(0x0B), pic.acknowledge());
+}
+
+#[test]
+fn can_mask_interrupts() {
+    let mut pic = PIC::default();
+    assert_eq!(Some(0xB8), pic.in_u8(0x21));
+
+    pic.pulse_irq(3);
+    assert!(!pic.pending());
+
+    // the request is kept while masked
+    pic.out_u8(0x21, 0xB0);
+    assert_eq!(Some(0x0B), pic.acknowledge());
+}
+
+#[test]
+fn can_send_specific_eoi_and_rotate_priority() {
+    let mut pic = unmasked_pic();
+    pic.pulse_irq(5);
+    pic.pulse_irq(6);
+    assert_eq!(Some(0x0D), pic.acknowledge());
+
+    // specific EOI for irq 5
+    pic.out_u8(0x20, 0x65);
+    assert_eq!(0, pic.master.isr);
+
+    // rotate on non-specific EOI: irq 6 gets the lowest priority
+    assert_eq!(Some(0x0E), pic.acknowledge());
+    pic.out_u8(0x20, 0xA0);
+    assert_eq!(6, pic.master.lowest_priority);
+    pic.pulse_irq(6);
+    pic.pulse_irq(0);
+    pic.pulse_irq(7);
+    assert_eq!(Some(0x0F), pic.acknowledge());
+    pic.out_u8(0x20, 0x67); // specific EOI for irq 7
+
+    // set priority command: irq 0 gets the lowest priority, irq 1 the highest
+    pic.out_u8(0x20, 0xC0);
+    pic.pulse_irq(1);
+    assert_eq!(Some(0x09), pic.acknowledge());
+
+    // rotate on specific EOI
+    pic.out_u8(0x20, 0xE1);
+    assert_eq!(1, pic.master.lowest_priority);
+    assert_eq!(Some(0x0E), pic.acknowledge());
+}
+
+#[test]
+fn can_cascade_slave_interrupts() {
+    let mut pic = unmasked_pic();
+    pic.pulse_irq(8);
+    pic.pulse_irq(12);
+    pic.pulse_irq(3);
+
+    // the slave is connected to irq 2, with a higher priority than irq 3
+    assert_eq!(Some(0x70), pic.acknowledge());
+    assert_eq!(0b0000_0100, pic.master.isr);
+    assert_eq!(0b0000_0001, pic.slave.isr);
+    assert_eq!(None, pic.acknowledge());
+
+    // both controllers need an EOI
+    pic.out_u8(0xA0, 0x20);
+    pic.out_u8(0x20, 0x20);
+    assert_eq!(Some(0x74), pic.acknowledge());
+    pic.end_of_interrupt(12);
+    assert_eq!(0, pic.master.isr);
+    assert_eq!(0, pic.slave.isr);
+    assert_eq!(Some(0x0B), pic.acknowledge());
+
+    // a masked slave irq is not seen by the master
+    pic.out_u8(0xA1, 0x01);
+    pic.pulse_irq(8);
+    assert!(!pic.pending());
+}
+
+#[test]
+fn can_read_registers_and_poll() {
+    let mut pic = unmasked_pic();
+    pic.pulse_irq(1);
+    pic.pulse_irq(4);
+
+    // OCW3: read request register, then in-service register
+    pic.out_u8(0x20, 0x0A);
+    assert_eq!(Some(0b0001_0010), pic.in_u8(0x20));
+    pic.out_u8(0x20, 0x0B);
+    assert_eq!(Some(0x00), pic.in_u8(0x20));
+
+    // OCW3: poll command, acknowledges the highest priority request
+    pic.out_u8(0x20, 0x0C);
+    assert_eq!(Some(0x81), pic.in_u8(0x20));
+    assert_eq!(Some(0b0000_0010), pic.in_u8(0x20));
+    pic.out_u8(0x20, 0x20);
+    pic.out_u8(0x20, 0x0C);
+    assert_eq!(Some(0x84), pic.in_u8(0x20));
+    pic.out_u8(0x20, 0x20);
+    pic.out_u8(0x20, 0x0C);
+    assert_eq!(Some(0x00), pic.in_u8(0x20));
+}
+
+#[test]
+fn can_initialize_with_auto_eoi() {
+    let mut pic = PIC::default();
+    pic.out_u8(0x20, 0x11); // ICW1: edge triggered, cascade mode, ICW4 needed
+    pic.out_u8(0x21, 0x20); // ICW2: vectors 20h-27h
+    pic.out_u8(0x21, 0x04); // ICW3: slave on irq 2
+    pic.out_u8(0x21, 0x03); // ICW4: auto EOI, 8086 mode
+    assert_eq!(Some(0x00), pic.in_u8(0x21));
+
+    pic.pulse_irq(5);
+    pic.pulse_irq(6);
+    assert_eq!(Some(0x25), pic.acknowledge());
+    assert_eq!(0, pic.master.isr);
+    assert_eq!(Some(0x26), pic.acknowledge());
+
+    // edge triggered: a line held high requests once
+    pic.set_irq_line(4, true);
+    assert_eq!(Some(0x24), pic.acknowledge());
+    pic.set_irq_line(4, true);
+    assert!(!pic.pending());
+}
@@ -4,7 +4,16 @@
 //
 // A 8253/8254 chip that runs at 18.2065 Hz (or an IRQ every 54.9254 ms)
 // with the default divisor of 0x1_0000
+//
+// Counter 0 raises IRQ 0 on the rising edge of its output, the output of counter 2
+// drives the speaker and is gated by bit 0 of port 0061
+// http://www.scs.stanford.edu/10wi-cs140/pintos/specs/8254.pdf
+
+use std::ops::RangeInclusive;
+
+use log::trace;
 
+use crate::bios::BIOS;
 use crate::cpu::{CPU, R};
 use crate::machine::Component;
 use crate::memory::MMU;
@@ -13,17 +22,28 @@ use crate::memory::MMU;
 #[path = "./pit_test.rs"]
 mod pit_test;
 
-const DEBUG_PIT: bool = false;
+/// input clock of the timers, in Hz
+const PIT_HZ: u64 = 1_193_182;
 
 #[derive(Clone)]
 pub struct PIT {
     pub timer0: Timer,
     pub timer1: Timer,
     pub timer2: Timer,
-    //divisor: u32, // XXX size?!?!
+
+    /// cpu cycles not yet converted to timer ticks, in units of 1 / `PIT_HZ` cycles
+    cycle_remainder: u64,
 }
 
 impl Component for PIT {
+    fn io_ports(&self) -> Vec<RangeInclusive<u16>> {
+        vec![0x0040..=0x0043]
+    }
+
+    fn interrupts(&self) -> Vec<u8> {
+        vec![0x1A]
+    }
+
     fn in_u8(&mut self, port: u16) -> Option<u8> {
         // PORT 0040-005F - PIT - PROGRAMMABLE INTERVAL TIMER (8253, 8254)
         match port {
@@ -45,7 +65,7 @@ impl Component for PIT {
         true
     }
 
-    fn int(&mut self, int: u8, cpu: &mut CPU, _mmu: &mut MMU) -> bool {
+    fn int(&mut self, int: u8, cpu: &mut CPU, mmu: &mut MMU) -> bool {
         if int != 0x1A {
             return false;
         }
@@ -55,18 +75,11 @@ impl Component for PIT {
                 // Return:
                 // CX:DX = number of clock ticks since midnight
                 // AL = midnight flag, nonzero if midnight passed since time last read
-                if cpu.deterministic {
-                    cpu.set_r16(R::CX, 0);
-                    cpu.set_r16(R::DX, 0);
-                    cpu.set_r8(R::AL, 0);
-                } else {
-                    // println!("INT 1A GET TIME: get number of clock ticks since midnight, ticks {}",  hw.pit.timer0.count);
-                    let cx = (self.timer0.count >> 16) as u16;
-                    let dx = (self.timer0.count & 0xFFFF) as u16;
-                    cpu.set_r16(R::CX, cx);
-                    cpu.set_r16(R::DX, dx);
-                    cpu.set_r8(R::AL, 0); // TODO implement midnight flag
-                }
+                let ticks = mmu.read_u32(BIOS::DATA_SEG, BIOS::DATA_TIMER_TICKS);
+                cpu.set_r16(R::CX, (ticks >> 16) as u16);
+                cpu.set_r16(R::DX, ticks as u16);
+                cpu.set_r8(R::AL, mmu.read_u8(BIOS::DATA_SEG, BIOS::DATA_MIDNIGHT));
+                mmu.write_u8(BIOS::DATA_SEG, BIOS::DATA_MIDNIGHT, 0);
             }
             0x01 => {
                 // TIME - SET SYSTEM TIME
@@ -74,9 +87,8 @@ impl Component for PIT {
                 let cx = cpu.get_r16(R::CX);
                 let dx = cpu.get_r16(R::DX);
                 let ticks = (u32::from(cx)) << 16 | u32::from(dx);
-
-                self.timer0.count = ticks;
-                // println!("SET SYSTEM TIME to {}", ticks);
+                mmu.write_u32(BIOS::DATA_SEG, BIOS::DATA_TIMER_TICKS, ticks);
+                mmu.write_u8(BIOS::DATA_SEG, BIOS::DATA_MIDNIGHT, 0);
             }
             _ => return false
         }
@@ -86,29 +98,51 @@ impl Component for PIT {
 
 impl PIT {
     pub fn default() -> Self {
+        // as programmed by the BIOS: the system timer, memory refresh and the speaker tone
+        let mut timer0 = Timer::new(0);
+        timer0.program(0b0011_0110);
+        timer0.write_reload_part(0x00);
+        timer0.write_reload_part(0x00);
+        let mut timer1 = Timer::new(1);
+        timer1.program(0b0101_0100);
+        timer1.write_reload_part(18);
+        let mut timer2 = Timer::new(2);
+        timer2.gate = false;
+        timer2.program(0b1011_0110);
+        timer2.write_reload_part(0x33);
+        timer2.write_reload_part(0x05);
         PIT {
-            timer0: Timer::new(0),
-            timer1: Timer::new(1),
-            timer2: Timer::new(2),
-            //divisor: 0x1_0000, // XXX
+            timer0,
+            timer1,
+            timer2,
+            // the first tick happens on the first cycle
+            cycle_remainder: PIT_HZ - 1,
         }
     }
 
-    /// initializes the PIT with current time of day
-    pub fn init(&mut self) {
-        // there is approximately 18.2 clock ticks per second, 0x18_00B0 per 24 hrs. one tick is generated every 54.9254ms
-        let midnight = chrono::Local::now().date().and_hms(0, 0, 0);
-        let duration = chrono::Local::now().signed_duration_since(midnight).to_std().unwrap();
-        self.timer0.count = (((duration.as_secs() as f64 * 1000.) + (f64::from(duration.subsec_nanos()) / 1_000_000.)) / 54.9254) as u32;
+    /// progresses the timers by `cycles` cpu cycles at `clock_hz`, returns the number of times timer 0 raised IRQ 0
+    pub fn update(&mut self, cycles: usize, clock_hz: usize) -> u32 {
+        let clock_hz = clock_hz as u64;
+        let total = self.cycle_remainder + cycles as u64 * PIT_HZ;
+        let ticks = total / clock_hz;
+        self.cycle_remainder = total % clock_hz;
+        if ticks == 0 {
+            return 0;
+        }
+        self.timer1.advance(ticks);
+        self.timer2.advance(ticks);
+        let irqs = self.timer0.advance(ticks);
+        if irqs > 0 {
+            trace!(target: "io", "pit irq 0, {} times", irqs);
+        }
+        irqs
     }
 
-    // updates PIT internal state
-    pub fn update(&mut self, mmu: &mut MMU) {
-        self.timer0.inc();
-        // MEM 0040:006C - TIMER TICKS SINCE MIDNIGHT
-        // Size:	DWORD
-        // Desc:	updated approximately every 55 milliseconds by the BIOS INT 08 handler
-        mmu.write_u32(0x0040, 0x006C, self.timer0.count);
+    /// returns the cpu cycles at `clock_hz` that pass before timer 0 may raise IRQ 0, None if it is stopped
+    pub fn cycles_until_irq(&self, clock_hz: usize) -> Option<usize> {
+        let ticks = self.timer0.ticks_until_change()?;
+        let needed = (ticks * clock_hz as u64).saturating_sub(self.cycle_remainder);
+        Some(needed.div_ceil(PIT_HZ) as usize)
     }
 
     fn counter(&mut self, n: u8) -> &mut Timer {
@@ -120,154 +154,421 @@ impl PIT {
         }
     }
 
+    /// sets the gate input of counter `n`. the gates of counter 0 and 1 are always high,
+    /// the gate of counter 2 is bit 0 of port 0061
+    pub fn set_gate(&mut self, n: u8, gate: bool) {
+        self.counter(n).set_gate(gate);
+    }
+
     /// port 0043: control word register for counters 0-2
     /// called "8253/8254 PIT mode control word" in the interrupt list
     pub fn set_mode_command(&mut self, val: u8) {
         let channel = (val >> 6) & 0b11; // bits 7-6
-        let access_mode = (val >> 4) & 0b11; // bits 5-4
-        let operating_mode = (val >> 1) & 0b111; // bits 3-1
-        let bcd_mode = val & 1; // bit 0
+        trace!(target: "io", "PIT set_mode_command {:02X}", val);
         if channel == 3 {
-            panic!("TODO channel == 3: Read-back command (8254 only)");
-        }
-        self.counter(channel).set_mode(access_mode, operating_mode, bcd_mode);
-        if DEBUG_PIT {
-            println!("PIT set_mode_command channel={}, access_mode={}, operating_mode={}, bcd_mode={}", channel, access_mode, operating_mode, bcd_mode);
+            // read-back command (8254 only)
+            // bit 5 = 0: latch count, bit 4 = 0: latch status, bits 3-1: counter 2-0
+            for n in 0..3 {
+                if val & (2 << n) != 0 {
+                    let counter = self.counter(n);
+                    if val & 0b0010_0000 == 0 {
+                        counter.latch_count();
+                    }
+                    if val & 0b0001_0000 == 0 {
+                        counter.latch_status();
+                    }
+                }
+            }
+            return;
         }
+        self.counter(channel).program(val);
     }
 }
 
 #[derive(Clone)]
 pub struct Timer {
+    /// counting element, 1 to 0x1_0000 (10000 in BCD mode). 0x1_0000 reads as 0
     pub count: u32,
+
+    /// count register, as written through the i/o port
     pub reload: u16,
-    latch: u32,
-    hi: bool,
+
     channel: u8, // 0-2, for debugging
 
-    // controlled by write to port 0040:
     access_mode: AccessMode,
     operating_mode: OperatingMode,
     bcd_mode: BcdMode,
+
+    /// counter output
+    pub output: bool,
+
+    /// gate input, counting is enabled or triggered by the gate
+    pub gate: bool,
+
+    /// the counting element is loaded and counting
+    running: bool,
+
+    /// modes 0, 1, 4 and 5: the terminal count of the current count is not reached yet
+    armed: bool,
+
+    /// modes 4 and 5: the output is low for one tick after the terminal count
+    strobe: bool,
+
+    /// a count was written, but not yet loaded into the counting element
+    null_count: bool,
+
+    /// a count was written since the mode was set
+    has_count: bool,
+
+    /// latched count, read before the counting element
+    latch: Option<u16>,
+
+    /// latched status byte, read before the latched count
+    status_latch: Option<u8>,
+
+    /// the next read of a low byte / high byte access returns the high byte
+    read_hi: bool,
+
+    /// the next write of a low byte / high byte access is the high byte
+    write_hi: bool,
 }
 
 impl Timer {
     pub fn new(channel: u8) -> Self {
         Timer {
-            count: 0,
+            count: 0x1_0000,
             reload: 0,
-            latch: 0,
-            hi: false,
             channel,
-            access_mode: AccessMode::LoByteHiByte, // XXX default?
-            operating_mode: OperatingMode::Mode0, // XXX default?
-            bcd_mode: BcdMode::SixteenBitBinary, // XXX default?
+            access_mode: AccessMode::LoByteHiByte,
+            operating_mode: OperatingMode::Mode0,
+            bcd_mode: BcdMode::SixteenBitBinary,
+            output: false,
+            gate: true,
+            running: false,
+            armed: false,
+            strobe: false,
+            null_count: true,
+            has_count: false,
+            latch: None,
+            status_latch: None,
+            read_hi: false,
+            write_hi: false,
         }
     }
 
-    pub fn inc(&mut self) {
-        // XXX channel 0 is connected to interrupt.
-        self.count += 1;
-        if DEBUG_PIT {
-            println!("pit timer inc {}: {:08x}", self.channel, self.count);
+    /// handles a control word for this counter: a counter latch command or a new mode
+    fn program(&mut self, val: u8) {
+        let access_mode = (val >> 4) & 0b11; // bits 5-4
+        let operating_mode = (val >> 1) & 0b111; // bits 3-1
+        let bcd_mode = val & 1; // bit 0
+        trace!(target: "io", "pit {}: access_mode={}, operating_mode={}, bcd_mode={}", self.channel, access_mode, operating_mode, bcd_mode);
+        self.access_mode = match access_mode {
+            0 => {
+                // counter latch command, the mode is unchanged
+                self.latch_count();
+                return;
+            }
+            1 => AccessMode::LoByteOnly,
+            2 => AccessMode::HiByteOnly,
+            3 => AccessMode::LoByteHiByte,
+            _ => unreachable!(),
+        };
+        self.operating_mode = match operating_mode {
+            0 => OperatingMode::Mode0,
+            1 => OperatingMode::Mode1,
+            2 | 6 => OperatingMode::Mode2,
+            3 | 7 => OperatingMode::Mode3,
+            4 => OperatingMode::Mode4,
+            5 => OperatingMode::Mode5,
+            _ => unreachable!(),
+        };
+        self.bcd_mode = match bcd_mode {
+            0 => BcdMode::SixteenBitBinary,
+            _ => BcdMode::FourDigitBCD,
+        };
+        // the output is low in mode 0 and high in the other modes until a count is written
+        self.output = self.operating_mode != OperatingMode::Mode0;
+        self.running = false;
+        self.armed = false;
+        self.strobe = false;
+        self.null_count = true;
+        self.has_count = false;
+        self.latch = None;
+        self.status_latch = None;
+        self.read_hi = false;
+        self.write_hi = false;
+    }
+
+    /// counter latch command: holds the current count until it is read
+    fn latch_count(&mut self) {
+        if self.latch.is_none() {
+            self.latch = Some(self.current_count());
+            self.read_hi = false;
         }
-        if self.count >= 0x0018_00B0 {
-            self.count = 0;
+    }
+
+    /// read-back command: holds the status byte until it is read
+    fn latch_status(&mut self) {
+        if self.status_latch.is_none() {
+            let access_mode = match self.access_mode {
+                AccessMode::LoByteOnly => 1,
+                AccessMode::HiByteOnly => 2,
+                AccessMode::LoByteHiByte => 3,
+            };
+            let mut status = access_mode << 4 | (self.operating_mode.clone() as u8) << 1;
+            if self.bcd_mode == BcdMode::FourDigitBCD {
+                status |= 0x01;
+            }
+            if self.null_count {
+                status |= 0x40;
+            }
+            if self.output {
+                status |= 0x80;
+            }
+            self.status_latch = Some(status);
+        }
+    }
+
+    /// returns the counting element as read through the i/o port
+    fn current_count(&self) -> u16 {
+        match self.bcd_mode {
+            BcdMode::SixteenBitBinary => self.count as u16,
+            BcdMode::FourDigitBCD => to_bcd(self.count % 10000),
+        }
+    }
+
+    /// returns the count register value as a count of ticks, where 0 is the largest count
+    fn initial_count(&self) -> u32 {
+        match (self.bcd_mode.clone(), self.reload) {
+            (BcdMode::SixteenBitBinary, 0) => 0x1_0000,
+            (BcdMode::SixteenBitBinary, n) => u32::from(n),
+            (BcdMode::FourDigitBCD, n) => match from_bcd(n) {
+                0 => 10000,
+                n => n,
+            },
+        }
+    }
+
+    /// returns the number of values of the counting element, after 0 the count wraps
+    fn modulus(&self) -> u32 {
+        match self.bcd_mode {
+            BcdMode::SixteenBitBinary => 0x1_0000,
+            BcdMode::FourDigitBCD => 10000,
         }
     }
 
     pub fn get_next_u8(&mut self) -> u8 {
-        match self.access_mode {
-            AccessMode::LatchCountValue => {
-                // Counter Latch Command
-                let res = if self.hi {
-                    (self.latch >> 8) as u8
-                } else {
-                    (self.latch & 0xFF) as u8
-                };
-                self.hi = !self.hi;
-                res
-            }
+        if let Some(status) = self.status_latch.take() {
+            return status;
+        }
+        let value = self.latch.unwrap_or_else(|| self.current_count());
+        let (res, done) = match self.access_mode {
+            AccessMode::LoByteOnly => (value as u8, true),
+            AccessMode::HiByteOnly => ((value >> 8) as u8, true),
             AccessMode::LoByteHiByte => {
-                let res = if self.hi {
-                    (self.count >> 8) as u8
+                self.read_hi = !self.read_hi;
+                if self.read_hi {
+                    (value as u8, false)
                 } else {
-                    (self.count & 0xFF) as u8
-                };
-                self.hi = !self.hi;
-                res
-            }
-            AccessMode::LoByteOnly => {
-                panic!("AccessMode::LoByteOnly");
-            }
-            AccessMode::HiByteOnly => {
-                panic!("AccessMode::HiByteOnly");
+                    ((value >> 8) as u8, true)
+                }
             }
+        };
+        if done {
+            self.latch = None;
         }
+        res
     }
 
     /// sets the reload value for the counter
     pub fn write_reload_part(&mut self, val: u8) {
         match self.access_mode {
-            AccessMode::LatchCountValue => {
-                panic!("AccessMode::LatchCountValue");
-            }
             AccessMode::LoByteHiByte => {
-                self.reload = if self.hi {
-                    (self.reload & 0x00FF) | (u16::from(val) << 8)
+                if self.write_hi {
+                    self.reload = (self.reload & 0x00FF) | (u16::from(val) << 8);
                 } else {
-                    (self.reload & 0xFF00) | u16::from(val)
-                };
-                self.hi = !self.hi;
+                    self.reload = u16::from(val);
+                    if self.operating_mode == OperatingMode::Mode0 {
+                        // writing the first byte stops the count
+                        self.running = false;
+                        self.output = false;
+                    }
+                }
+                self.write_hi = !self.write_hi;
+                if self.write_hi {
+                    return;
+                }
+            }
+            AccessMode::LoByteOnly => self.reload = u16::from(val),
+            AccessMode::HiByteOnly => self.reload = u16::from(val) << 8,
+        }
+        self.count_written();
+    }
+
+    /// a complete count was written to the count register
+    fn count_written(&mut self) {
+        self.null_count = true;
+        self.has_count = true;
+        match self.operating_mode {
+            OperatingMode::Mode0 => {
+                self.output = false;
+                self.load();
             }
-            AccessMode::LoByteOnly => {
-                self.reload = (self.reload & 0xFF00) | u16::from(val);
+            OperatingMode::Mode2 | OperatingMode::Mode3 => {
+                // a running counter loads the new count at the end of the current period
+                if !self.running {
+                    self.output = true;
+                    self.load();
+                }
             }
-            AccessMode::HiByteOnly => {
-                self.reload = (self.reload & 0x00FF) | (u16::from(val) << 8);
+            OperatingMode::Mode4 => {
+                self.output = true;
+                self.load();
             }
+            OperatingMode::Mode1 | OperatingMode::Mode5 => {} // waits for a trigger on the gate
         }
     }
 
-    pub fn set_mode(&mut self, access_mode: u8, operating_mode: u8, bcd_mode: u8) {
-        // println!("pit {}: set_mode_command access {:?}, operating {:?}, bcd {:?}", self.channel, access_mode, operating_mode, bcd_mode);
-        self.access_mode = match access_mode {
-            0 => {
-                // prepare current count value in the latch register
-                self.latch = self.count;
-                AccessMode::LatchCountValue
-            },
-            1 => AccessMode::LoByteOnly,
-            2 => AccessMode::HiByteOnly,
-            3 => AccessMode::LoByteHiByte,
-            _ => panic!("TODO Latch count value command"),
-        };
-        self.operating_mode = match operating_mode {
-            0 => OperatingMode::Mode0,
-            1 => OperatingMode::Mode1,
-            2 | 6 => OperatingMode::Mode2,
-            3 | 7 => OperatingMode::Mode3,
-            4 => OperatingMode::Mode4,
-            5 => OperatingMode::Mode5,
-            _ => unreachable!(),
-        };
-        self.bcd_mode = match bcd_mode {
-            0 => BcdMode::SixteenBitBinary,
-            //1 => BcdMode::FourDigitBCD,
-            _ => panic!("TODO BCD mode"),
+    /// loads the count register into the counting element
+    fn load(&mut self) {
+        self.count = self.initial_count();
+        if self.operating_mode == OperatingMode::Mode3 && self.count & 1 != 0 {
+            // with an odd count, the output is high for one tick longer than low
+            self.count += 1;
+        }
+        self.running = true;
+        self.armed = true;
+        self.strobe = false;
+        self.null_count = false;
+    }
+
+    pub fn set_gate(&mut self, gate: bool) {
+        let rising = gate && !self.gate;
+        self.gate = gate;
+        match self.operating_mode {
+            OperatingMode::Mode1 | OperatingMode::Mode5 if rising && self.has_count => {
+                // trigger: (re)load the count
+                if self.operating_mode == OperatingMode::Mode1 {
+                    self.output = false;
+                }
+                self.load();
+            }
+            OperatingMode::Mode2 | OperatingMode::Mode3 if !gate => self.output = true,
+            OperatingMode::Mode2 | OperatingMode::Mode3 if rising && self.has_count => {
+                self.output = true;
+                self.load();
+            }
+            _ => {}
+        }
+    }
+
+    /// returns the input clock pulses until the next change of the output or the count reaching 0,
+    /// None if the counter is stopped
+    fn ticks_until_change(&self) -> Option<u64> {
+        let gated = match self.operating_mode {
+            OperatingMode::Mode1 | OperatingMode::Mode5 => true,
+            _ => self.gate,
         };
+        if !self.running || !gated {
+            return None;
+        }
+        Some(match self.operating_mode {
+            _ if self.strobe => 1,
+            OperatingMode::Mode2 if self.output => u64::from(self.count - 1),
+            OperatingMode::Mode2 => 1,
+            OperatingMode::Mode3 => u64::from(self.count / 2),
+            _ => u64::from(self.count),
+        })
+    }
+
+    /// progresses the counter by `ticks` input clock pulses, returns the number of rising edges of the output
+    fn advance(&mut self, mut ticks: u64) -> u32 {
+        let mut edges = 0;
+        while ticks > 0 {
+            let until = match self.ticks_until_change() {
+                Some(until) => until,
+                None => break,
+            };
+            let mode3 = self.operating_mode == OperatingMode::Mode3;
+            if ticks < until {
+                let step = if mode3 { 2 * ticks } else { ticks };
+                self.count -= step as u32;
+                break;
+            }
+            ticks -= until;
+            match self.operating_mode {
+                _ if self.strobe => {
+                    // end of the one tick strobe of mode 4 and 5
+                    self.strobe = false;
+                    self.output = true;
+                    self.count -= 1;
+                    edges += 1;
+                }
+                OperatingMode::Mode2 if self.output => {
+                    // the output is low while the count is 1
+                    self.count = 1;
+                    self.output = false;
+                }
+                OperatingMode::Mode2 => {
+                    self.output = true;
+                    self.load();
+                    edges += 1;
+                }
+                OperatingMode::Mode3 => {
+                    self.output = !self.output;
+                    let initial = self.initial_count();
+                    self.count = match (initial & 1 != 0, self.output) {
+                        (true, true) => initial + 1,
+                        (true, false) => (initial - 1).max(2),
+                        (false, _) => initial,
+                    };
+                    self.null_count = false;
+                    if self.output {
+                        edges += 1;
+                    }
+                }
+                OperatingMode::Mode0 | OperatingMode::Mode1 => {
+                    // terminal count: the output goes high, the count wraps around
+                    if self.armed && !self.output {
+                        self.output = true;
+                        edges += 1;
+                    }
+                    self.armed = false;
+                    self.count = self.modulus();
+                }
+                OperatingMode::Mode4 | OperatingMode::Mode5 => {
+                    // terminal count: the output goes low for one tick
+                    self.count = self.modulus();
+                    if self.armed {
+                        self.armed = false;
+                        self.output = false;
+                        self.strobe = true;
+                    }
+                }
+            }
+        }
+        edges
     }
 }
 
-#[derive(Clone, Debug)]
+/// returns `val` as four BCD digits
+fn to_bcd(val: u32) -> u16 {
+    (0..4).fold(0, |acc, i| acc | ((val / 10u32.pow(i) % 10) as u16) << (i * 4))
+}
+
+/// returns the value of four BCD digits
+fn from_bcd(val: u16) -> u32 {
+    (0..4).fold(0, |acc, i| acc + u32::from(val >> (i * 4) & 0xF) * 10u32.pow(i))
+}
+
+#[derive(Clone, Debug, PartialEq)]
 enum AccessMode {
-    LatchCountValue,
     LoByteOnly,
     HiByteOnly,
     LoByteHiByte,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 enum OperatingMode {
     Mode0, // Mode 0 (interrupt on terminal count)
     Mode1, // Mode 1 (hardware re-triggerable one-shot)
@@ -277,7 +578,7 @@ enum OperatingMode {
     Mode5, // Mode 5 (hardware triggered strobe)
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 enum BcdMode {
     SixteenBitBinary,   // 16-bit binary
     FourDigitBCD,       // four-digit BCD
@@ -19,3 +19,135 @@ fn can_execute_pit_set_reload_value() {
 
     assert_eq!(0x2244, pit.timer0.reload);
 }
+
+#[test]
+fn can_raise_irq0_from_timer0() {
+    let mut pit = PIT::default();
+    let clock_hz = 5_000_000;
+
+    // default reload value of 0x1_0000 gives 18.2065 Hz
+    let period = 0x1_0000 * clock_hz / 1_193_182;
+    assert_eq!(0, pit.update(period - 1, clock_hz));
+    assert_eq!(1, pit.update(1, clock_hz));
+    assert_eq!(0, pit.update(period - 1, clock_hz));
+    assert_eq!(1, pit.update(1, clock_hz));
+
+    // reprogram timer 0 to 1193 Hz
+    pit.out_u8(0x43, 0b0011_0100);
+    pit.out_u8(0x40, 0xE8);
+    pit.out_u8(0x40, 0x03);
+    let period = 1000 * clock_hz / 1_193_182;
+    assert_eq!(0, pit.update(period - 1, clock_hz));
+    assert_eq!(1, pit.update(1, clock_hz));
+    // each expiration of a long step is counted
+    assert_eq!(5, pit.update(5 * (period + 1), clock_hz));
+}
+
+/// with the cpu running at the timer input clock, each cycle is one tick
+const TICK: usize = 1_193_182;
+
+#[test]
+fn can_interrupt_on_terminal_count() {
+    let mut pit = PIT::default();
+    pit.out_u8(0x43, 0b0011_0000); // channel 0, lobyte/hibyte, mode 0
+    assert!(!pit.timer0.output);
+    pit.out_u8(0x40, 100);
+    pit.out_u8(0x40, 0);
+    assert_eq!(0, pit.update(99, TICK));
+    assert!(!pit.timer0.output);
+    assert_eq!(1, pit.update(1, TICK));
+    assert!(pit.timer0.output);
+
+    // the count wraps around without further interrupts
+    assert_eq!(0, pit.update(0x1_0000, TICK));
+    pit.out_u8(0x43, 0b0000_0000); // latch counter 0
+    assert_eq!(0x00, pit.in_u8(0x40).unwrap());
+    assert_eq!(0x00, pit.in_u8(0x40).unwrap());
+}
+
+#[test]
+fn can_latch_count() {
+    let mut pit = PIT::default();
+    pit.out_u8(0x43, 0b0011_0100); // channel 0, lobyte/hibyte, rate generator
+    pit.out_u8(0x40, 0x00);
+    pit.out_u8(0x40, 0x10);
+    pit.update(0x100, TICK);
+
+    pit.out_u8(0x43, 0b0000_0000); // latch counter 0
+    pit.update(0x100, TICK);
+    assert_eq!(0x00, pit.in_u8(0x40).unwrap());
+    assert_eq!(0x0F, pit.in_u8(0x40).unwrap());
+
+    // without a latch, the counting element is read
+    assert_eq!(0x00, pit.in_u8(0x40).unwrap());
+    assert_eq!(0x0E, pit.in_u8(0x40).unwrap());
+}
+
+#[test]
+fn can_read_back_status_and_count() {
+    let mut pit = PIT::default();
+    pit.out_u8(0x43, 0b1001_0111); // channel 2, lobyte only, square wave, BCD
+    assert_eq!(0b1101_0111, read_back_status(&mut pit, 0b100));
+
+    pit.out_u8(0x42, 0x50);
+    pit.update(10, TICK);
+
+    // read-back of status and count of counter 2, status is read first
+    pit.out_u8(0x43, 0b1100_1000);
+    assert_eq!(0b1001_0111, pit.in_u8(0x42).unwrap());
+    assert_eq!(0x50, pit.in_u8(0x42).unwrap());
+
+    // counter 2 is gated off
+    pit.set_gate(2, true);
+    pit.update(10, TICK);
+    pit.out_u8(0x43, 0b1101_1000);
+    assert_eq!(0x30, pit.in_u8(0x42).unwrap());
+}
+
+fn read_back_status(pit: &mut PIT, counters: u8) -> u8 {
+    pit.out_u8(0x43, 0b1110_0000 | counters << 1);
+    pit.in_u8(0x40 + u16::from(counters.trailing_zeros() as u8)).unwrap()
+}
+
+#[test]
+fn can_generate_square_wave() {
+    let mut pit = PIT::default();
+    pit.out_u8(0x43, 0b0001_0110); // channel 0, lobyte only, square wave
+    pit.out_u8(0x40, 5);
+
+    // with an odd count, the output is high for 3 ticks and low for 2
+    assert!(pit.timer0.output);
+    pit.update(3, TICK);
+    assert!(!pit.timer0.output);
+    assert_eq!(0, pit.update(1, TICK));
+    assert_eq!(1, pit.update(1, TICK));
+    assert!(pit.timer0.output);
+    assert_eq!(0, pit.update(4, TICK));
+    assert_eq!(1, pit.update(1, TICK));
+}
+
+#[test]
+fn can_trigger_one_shot_and_strobe_from_gate() {
+    let mut pit = PIT::default();
+
+    // hardware re-triggerable one-shot
+    pit.out_u8(0x43, 0b1001_0010); // channel 2, lobyte only, mode 1
+    pit.out_u8(0x42, 10);
+    pit.update(20, TICK);
+    assert!(pit.timer2.output);
+    pit.set_gate(2, true);
+    assert!(!pit.timer2.output);
+    pit.update(9, TICK);
+    assert!(!pit.timer2.output);
+    pit.update(1, TICK);
+    assert!(pit.timer2.output);
+
+    // software triggered strobe, the output is low for one tick
+    pit.out_u8(0x43, 0b0001_1000); // channel 0, lobyte only, mode 4
+    pit.out_u8(0x40, 10);
+    assert_eq!(0, pit.update(10, TICK));
+    assert!(!pit.timer0.output);
+    assert_eq!(1, pit.update(1, TICK));
+    assert!(pit.timer0.output);
+    assert_eq!(0, pit.update(0x200, TICK));
+}
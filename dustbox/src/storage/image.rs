@@ -0,0 +1,274 @@
+// read-only access to disk images, unpacked to a host directory when mounted
+// FAT: http://www.maverick-os.dk/FileSystemFormats/FAT12_FileSystem.html
+// ISO 9660: https://wiki.osdev.org/ISO_9660
+
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+use chrono::prelude::*;
+
+use crate::dos::{local_host_time, unpack_dos_date_time};
+use crate::storage::DiskGeometry;
+
+#[cfg(test)]
+#[path = "./image_test.rs"]
+mod image_test;
+
+const FAT_DIR_ENTRY_SIZE: usize = 32;
+const FAT_ATTR_VOLUME_LABEL: u8 = 0x08;
+const FAT_ATTR_DIRECTORY: u8 = 0x10;
+
+/// volume layout read from the BIOS parameter block of a FAT disk image
+#[derive(Debug)]
+struct FatVolume<'a> {
+    data: &'a [u8],
+    bytes_per_sector: usize,
+    sectors_per_cluster: usize,
+    fat_start: usize,
+    root_start: usize,
+    root_entries: usize,
+    data_start: usize,
+    total_clusters: usize,
+    fat16: bool,
+    media_descriptor: u8,
+}
+
+impl<'a> FatVolume<'a> {
+    fn parse(data: &'a [u8]) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("not a FAT disk image: {}", msg));
+        if data.len() < 512 {
+            return Err(invalid("too small"));
+        }
+        let u16_at = |pos: usize| usize::from(u16::from(data[pos]) | u16::from(data[pos + 1]) << 8);
+        let bytes_per_sector = u16_at(11);
+        let sectors_per_cluster = usize::from(data[13]);
+        let reserved_sectors = u16_at(14);
+        let fats = usize::from(data[16]);
+        let root_entries = u16_at(17);
+        let mut total_sectors = u16_at(19);
+        let media_descriptor = data[21];
+        let sectors_per_fat = u16_at(22);
+        if total_sectors == 0 {
+            total_sectors = u16_at(32) | u16_at(34) << 16;
+        }
+        if ![512, 1024, 2048, 4096].contains(&bytes_per_sector) || sectors_per_cluster == 0 || fats == 0 || sectors_per_fat == 0 {
+            return Err(invalid("bad BIOS parameter block"));
+        }
+
+        let root_start = reserved_sectors + fats * sectors_per_fat;
+        let root_sectors = (root_entries * FAT_DIR_ENTRY_SIZE).div_ceil(bytes_per_sector);
+        let data_start = root_start + root_sectors;
+        if total_sectors <= data_start || (data_start * bytes_per_sector) > data.len() {
+            return Err(invalid("bad BIOS parameter block"));
+        }
+        let total_clusters = (total_sectors - data_start) / sectors_per_cluster;
+        Ok(FatVolume {
+            data,
+            bytes_per_sector,
+            sectors_per_cluster,
+            fat_start: reserved_sectors * bytes_per_sector,
+            root_start: root_start * bytes_per_sector,
+            root_entries,
+            data_start: data_start * bytes_per_sector,
+            total_clusters,
+            fat16: total_clusters >= 4085,
+            media_descriptor,
+        })
+    }
+
+    /// returns the FAT entry of `cluster`
+    fn fat_entry(&self, cluster: usize) -> usize {
+        let byte = |pos: usize| usize::from(*self.data.get(self.fat_start + pos).unwrap_or(&0xFF));
+        if self.fat16 {
+            byte(cluster * 2) | byte(cluster * 2 + 1) << 8
+        } else {
+            let pos = cluster * 3 / 2;
+            let v = byte(pos) | byte(pos + 1) << 8;
+            if cluster & 1 == 1 { v >> 4 } else { v & 0xFFF }
+        }
+    }
+
+    fn is_end_of_chain(&self, entry: usize) -> bool {
+        entry < 2 || entry >= if self.fat16 { 0xFFF8 } else { 0xFF8 }
+    }
+
+    fn free_clusters(&self) -> usize {
+        (2..self.total_clusters + 2).filter(|&c| self.fat_entry(c) == 0).count()
+    }
+
+    /// returns the data of the cluster chain starting at `cluster`
+    fn read_chain(&self, mut cluster: usize) -> Vec<u8> {
+        let cluster_size = self.bytes_per_sector * self.sectors_per_cluster;
+        let mut res = Vec::new();
+        // the chain can't be longer than the volume, guarding against loops in broken images
+        for _ in 0..self.total_clusters {
+            if self.is_end_of_chain(cluster) || cluster >= self.total_clusters + 2 {
+                break;
+            }
+            let start = self.data_start + (cluster - 2) * cluster_size;
+            let end = (start + cluster_size).min(self.data.len());
+            if start >= end {
+                break;
+            }
+            res.extend_from_slice(&self.data[start..end]);
+            cluster = self.fat_entry(cluster);
+        }
+        res
+    }
+
+    /// writes the files of the directory in `entries` to host directory `dest`
+    fn unpack_dir(&self, entries: &[u8], dest: &Path, depth: usize) -> io::Result<()> {
+        if depth > 16 {
+            return Ok(());
+        }
+        for entry in entries.chunks_exact(FAT_DIR_ENTRY_SIZE) {
+            match entry[0] {
+                0x00 => break,
+                0xE5 | b'.' => continue,
+                _ => {}
+            }
+            let attr = entry[11];
+            if attr & FAT_ATTR_VOLUME_LABEL != 0 {
+                // also skips the long filename entries
+                continue;
+            }
+            let name = fat_name(&entry[0..11]);
+            let cluster = usize::from(u16::from(entry[26]) | u16::from(entry[27]) << 8);
+            let path = dest.join(&name);
+            if attr & FAT_ATTR_DIRECTORY != 0 {
+                fs::create_dir_all(&path)?;
+                self.unpack_dir(&self.read_chain(cluster), &path, depth + 1)?;
+            } else {
+                let size = u32::from(entry[28]) | u32::from(entry[29]) << 8 | u32::from(entry[30]) << 16 | u32::from(entry[31]) << 24;
+                let mut data = self.read_chain(cluster);
+                data.truncate(size as usize);
+                fs::write(&path, &data)?;
+                let time = u16::from(entry[22]) | u16::from(entry[23]) << 8;
+                let date = u16::from(entry[24]) | u16::from(entry[25]) << 8;
+                if let Some(modified) = unpack_dos_date_time(date, time) {
+                    set_modified(&path, modified);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// returns the name of a FAT directory entry, such as "FILE.TXT"
+fn fat_name(raw: &[u8]) -> String {
+    let part = |b: &[u8]| -> String {
+        let s: String = b.iter().map(|&c| if c == 0x05 { 0xE5 as char } else { c as char }).collect();
+        s.trim_end().to_owned()
+    };
+    let base = part(&raw[0..8]);
+    let ext = part(&raw[8..11]);
+    if ext.is_empty() {
+        base
+    } else {
+        format!("{}.{}", base, ext)
+    }
+}
+
+fn set_modified(path: &Path, time: NaiveDateTime) {
+    if let (Ok(file), Some(time)) = (File::options().write(true).open(path), local_host_time(time)) {
+        let _ = file.set_modified(time);
+    }
+}
+
+/// unpacks the files of FAT12 or FAT16 disk image `data` to host directory `dest`
+pub fn unpack_fat_image(data: &[u8], dest: &Path) -> io::Result<DiskGeometry> {
+    let volume = FatVolume::parse(data)?;
+    let root_end = (volume.root_start + volume.root_entries * FAT_DIR_ENTRY_SIZE).min(data.len());
+    volume.unpack_dir(&data[volume.root_start..root_end], dest, 0)?;
+    Ok(DiskGeometry {
+        bytes_per_sector: volume.bytes_per_sector as u16,
+        sectors_per_cluster: volume.sectors_per_cluster as u16,
+        total_clusters: volume.total_clusters.min(0xFFFF) as u16,
+        free_clusters: volume.free_clusters().min(0xFFFF) as u16,
+        media_descriptor: volume.media_descriptor,
+    })
+}
+
+const ISO_SECTOR_SIZE: usize = 2048;
+
+/// unpacks the files of ISO 9660 CD-ROM image `data` to host directory `dest`
+pub fn unpack_iso_image(data: &[u8], dest: &Path) -> io::Result<DiskGeometry> {
+    let pvd = 16 * ISO_SECTOR_SIZE;
+    if data.len() < pvd + ISO_SECTOR_SIZE || data[pvd] != 1 || &data[pvd + 1..pvd + 6] != b"CD001" {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not an ISO 9660 image: no primary volume descriptor"));
+    }
+    let u32_at = |pos: usize| u32::from(data[pos]) | u32::from(data[pos + 1]) << 8 | u32::from(data[pos + 2]) << 16 | u32::from(data[pos + 3]) << 24;
+    let volume_blocks = u32_at(pvd + 80) as usize;
+    let root = &data[pvd + 156..pvd + 156 + 34];
+    unpack_iso_dir(data, root, dest, 0)?;
+    Ok(DiskGeometry {
+        bytes_per_sector: ISO_SECTOR_SIZE as u16,
+        sectors_per_cluster: 1,
+        total_clusters: volume_blocks.min(0xFFFF) as u16,
+        free_clusters: 0,
+        media_descriptor: 0xF8,
+    })
+}
+
+/// writes the files of the directory described by directory record `record` to host directory `dest`
+fn unpack_iso_dir(data: &[u8], record: &[u8], dest: &Path, depth: usize) -> io::Result<()> {
+    if depth > 16 {
+        return Ok(());
+    }
+    let extent = iso_extent(data, record);
+    let mut pos = 0;
+    while pos < extent.len() {
+        let len = usize::from(extent[pos]);
+        if len == 0 {
+            // records don't cross sector boundaries, the rest of the sector is padding
+            pos = (pos / ISO_SECTOR_SIZE + 1) * ISO_SECTOR_SIZE;
+            continue;
+        }
+        if pos + len > extent.len() || len < 34 {
+            break;
+        }
+        let entry = &extent[pos..pos + len];
+        pos += len;
+
+        let name_len = usize::from(entry[32]);
+        let raw_name = &entry[33..(33 + name_len).min(len)];
+        if raw_name == [0] || raw_name == [1] {
+            // "." and ".."
+            continue;
+        }
+        let name = iso_name(raw_name);
+        let path = dest.join(&name);
+        if entry[25] & 0x02 != 0 {
+            fs::create_dir_all(&path)?;
+            unpack_iso_dir(data, entry, &path, depth + 1)?;
+        } else {
+            fs::write(&path, iso_extent(data, entry))?;
+            let t = &entry[18..25];
+            let modified = NaiveDate::from_ymd_opt(1900 + i32::from(t[0]), u32::from(t[1]), u32::from(t[2]))
+                .and_then(|d| d.and_hms_opt(u32::from(t[3]), u32::from(t[4]), u32::from(t[5])));
+            if let Some(modified) = modified {
+                set_modified(&path, modified);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// returns the data described by directory record `record`
+fn iso_extent<'a>(data: &'a [u8], record: &[u8]) -> &'a [u8] {
+    let u32_at = |pos: usize| (u32::from(record[pos]) | u32::from(record[pos + 1]) << 8 | u32::from(record[pos + 2]) << 16 | u32::from(record[pos + 3]) << 24) as usize;
+    let start = (u32_at(2) * ISO_SECTOR_SIZE).min(data.len());
+    let end = (start + u32_at(10)).min(data.len());
+    &data[start..end]
+}
+
+/// returns the file name of an ISO 9660 identifier such as "FILE.TXT;1"
+fn iso_name(raw: &[u8]) -> String {
+    let name: String = raw.iter().map(|&c| c as char).collect();
+    let name = match name.rfind(';') {
+        Some(pos) => &name[..pos],
+        None => &name,
+    };
+    name.trim_end_matches('.').to_owned()
+}
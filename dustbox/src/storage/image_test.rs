@@ -0,0 +1,119 @@
+use std::fs;
+
+use tempfile::tempdir;
+
+use crate::storage::{unpack_fat_image, unpack_iso_image};
+
+/// sets FAT12 entry `cluster` of the FAT at `fat` to `value`
+fn set_fat12(fat: &mut [u8], cluster: usize, value: u16) {
+    let pos = cluster * 3 / 2;
+    if cluster & 1 == 1 {
+        fat[pos] = (fat[pos] & 0x0F) | ((value << 4) as u8);
+        fat[pos + 1] = (value >> 4) as u8;
+    } else {
+        fat[pos] = value as u8;
+        fat[pos + 1] = (fat[pos + 1] & 0xF0) | ((value >> 8) as u8 & 0x0F);
+    }
+}
+
+fn fat_dir_entry(name: &[u8; 11], attr: u8, cluster: u16, size: u32) -> Vec<u8> {
+    let mut entry = name.to_vec();
+    entry.push(attr);
+    entry.extend_from_slice(&[0; 10]);
+    entry.extend_from_slice(&0x6000_u16.to_le_bytes()); // 12:00:00
+    entry.extend_from_slice(&0x2821_u16.to_le_bytes()); // 2000-01-01
+    entry.extend_from_slice(&cluster.to_le_bytes());
+    entry.extend_from_slice(&size.to_le_bytes());
+    entry
+}
+
+#[test]
+fn can_unpack_fat12_image() {
+    // 20 sectors: boot sector, FAT, root directory and 17 data clusters of one sector
+    let mut data = vec![0; 20 * 512];
+    data[11..13].copy_from_slice(&512_u16.to_le_bytes());
+    data[13] = 1;   // sectors per cluster
+    data[14] = 1;   // reserved sectors
+    data[16] = 1;   // number of FATs
+    data[17] = 16;  // root directory entries
+    data[19..21].copy_from_slice(&20_u16.to_le_bytes());
+    data[21] = 0xF0;
+    data[22] = 1;   // sectors per FAT
+
+    let fat = &mut data[512..1024];
+    set_fat12(fat, 0, 0xFF0);
+    set_fat12(fat, 1, 0xFFF);
+    set_fat12(fat, 2, 0x003);
+    set_fat12(fat, 3, 0xFFF);
+    set_fat12(fat, 4, 0xFFF);
+    set_fat12(fat, 5, 0xFFF);
+
+    let mut root = fat_dir_entry(b"README  TXT", 0x20, 2, 600);
+    root.extend(fat_dir_entry(b"GAMES      ", 0x10, 4, 0));
+    data[1024..1024 + root.len()].copy_from_slice(&root);
+
+    let readme: Vec<u8> = (0..600).map(|i| (i % 251) as u8).collect();
+    data[1536..1536 + 600].copy_from_slice(&readme);
+
+    let mut games = fat_dir_entry(b".          ", 0x10, 4, 0);
+    games.extend(fat_dir_entry(b"PLAY    EXE", 0x20, 5, 3));
+    data[2560..2560 + games.len()].copy_from_slice(&games);
+    data[3072..3075].copy_from_slice(b"MZ!");
+
+    let dir = tempdir().unwrap();
+    let geometry = unpack_fat_image(&data, dir.path()).unwrap();
+    assert_eq!(512, geometry.bytes_per_sector);
+    assert_eq!(1, geometry.sectors_per_cluster);
+    assert_eq!(17, geometry.total_clusters);
+    assert_eq!(13, geometry.free_clusters);
+    assert_eq!(0xF0, geometry.media_descriptor);
+
+    assert_eq!(readme, fs::read(dir.path().join("README.TXT")).unwrap());
+    assert_eq!(b"MZ!".to_vec(), fs::read(dir.path().join("GAMES").join("PLAY.EXE")).unwrap());
+
+    assert!(unpack_fat_image(&[0; 100], dir.path()).is_err());
+}
+
+fn iso_record(extent: u32, size: u32, flags: u8, name: &[u8]) -> Vec<u8> {
+    let len = 33 + name.len() + (1 - name.len() % 2);
+    let mut record = vec![0; len];
+    record[0] = len as u8;
+    record[2..6].copy_from_slice(&extent.to_le_bytes());
+    record[10..14].copy_from_slice(&size.to_le_bytes());
+    record[18..25].copy_from_slice(&[100, 1, 1, 12, 0, 0, 0]); // 2000-01-01 12:00:00
+    record[25] = flags;
+    record[32] = name.len() as u8;
+    record[33..33 + name.len()].copy_from_slice(name);
+    record
+}
+
+#[test]
+fn can_unpack_iso_image() {
+    let mut data = vec![0; 21 * 2048];
+    let pvd = 16 * 2048;
+    data[pvd] = 1;
+    data[pvd + 1..pvd + 6].copy_from_slice(b"CD001");
+    data[pvd + 80..pvd + 84].copy_from_slice(&21_u32.to_le_bytes());
+    data[pvd + 156..pvd + 190].copy_from_slice(&iso_record(18, 2048, 0x02, &[0]));
+
+    let mut root = iso_record(18, 2048, 0x02, &[0]);
+    root.extend(iso_record(18, 2048, 0x02, &[1]));
+    root.extend(iso_record(20, 5, 0x00, b"HELLO.TXT;1"));
+    root.extend(iso_record(19, 2048, 0x02, b"DATA"));
+    data[18 * 2048..18 * 2048 + root.len()].copy_from_slice(&root);
+
+    let sub = iso_record(20, 2, 0x00, b"NOEXT.;1");
+    data[19 * 2048..19 * 2048 + sub.len()].copy_from_slice(&sub);
+    data[20 * 2048..20 * 2048 + 5].copy_from_slice(b"hello");
+
+    let dir = tempdir().unwrap();
+    let geometry = unpack_iso_image(&data, dir.path()).unwrap();
+    assert_eq!(2048, geometry.bytes_per_sector);
+    assert_eq!(21, geometry.total_clusters);
+    assert_eq!(0, geometry.free_clusters);
+
+    assert_eq!(b"hello".to_vec(), fs::read(dir.path().join("HELLO.TXT")).unwrap());
+    assert_eq!(b"he".to_vec(), fs::read(dir.path().join("DATA").join("NOEXT")).unwrap());
+
+    assert!(unpack_iso_image(&data[..16 * 2048], dir.path()).is_err());
+}
@@ -0,0 +1,10 @@
+// these modules are re-exported as a single module
+
+pub use self::storage::*;
+mod storage;
+
+pub use self::mount::*;
+mod mount;
+
+pub use self::image::*;
+mod image;
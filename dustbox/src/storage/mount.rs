@@ -0,0 +1,267 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::storage::image::{unpack_fat_image, unpack_iso_image};
+
+#[cfg(test)]
+#[path = "./mount_test.rs"]
+mod mount_test;
+
+/// the number of drive letters reported by DOS when fewer drives are mounted, as with LASTDRIVE=E
+const DEFAULT_LAST_DRIVE: u8 = 5;
+
+/// size and free space of directory drives, in KB
+const DEFAULT_TOTAL_KB: u32 = 2 * 1024 * 1024;
+const DEFAULT_FREE_KB: u32 = 1024 * 1024;
+
+/// largest cluster size of FAT16, 32 KB
+const MAX_SECTORS_PER_CLUSTER: u16 = 64;
+
+/// what a DOS drive is backed by
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MountKind {
+    /// a host directory
+    Directory,
+
+    /// a FAT12 or FAT16 disk image, such as a .img floppy image
+    Floppy,
+
+    /// an ISO 9660 CD-ROM image
+    Iso,
+}
+
+/// a host directory or disk image mounted as a DOS drive
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Mount {
+    pub drive: char,
+    pub path: PathBuf,
+
+    /// the kind of mount. if unset, it is guessed from the path
+    #[serde(default)]
+    pub kind: Option<MountKind>,
+
+    /// disk size reported for a directory, in KB. defaults to 2 GB
+    #[serde(default)]
+    pub total_kb: Option<u32>,
+
+    /// free disk space reported for a directory, in KB. defaults to 1 GB
+    #[serde(default)]
+    pub free_kb: Option<u32>,
+
+    /// sectors per cluster reported for a directory. if unset, the smallest cluster size
+    /// fitting the disk size in 65535 clusters is used, as when formatting a FAT16 drive
+    #[serde(default)]
+    pub sectors_per_cluster: Option<u16>,
+}
+
+impl Mount {
+    pub fn new(drive: char, path: PathBuf) -> Self {
+        Mount {
+            drive,
+            path,
+            kind: None,
+            total_kb: None,
+            free_kb: None,
+            sectors_per_cluster: None,
+        }
+    }
+
+    /// returns the kind of the mount: directories, .iso files, and other files as disk images
+    pub fn kind(&self) -> MountKind {
+        if let Some(kind) = self.kind {
+            return kind;
+        }
+        if self.path.is_dir() {
+            return MountKind::Directory;
+        }
+        match self.path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("iso") => MountKind::Iso,
+            _ => MountKind::Floppy,
+        }
+    }
+
+    /// returns an error if the mount can't be used
+    pub fn validate(&self) -> io::Result<()> {
+        if !self.drive.is_ascii_alphabetic() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid drive letter {}", self.drive)));
+        }
+        let ok = match self.kind() {
+            MountKind::Directory => self.path.is_dir(),
+            MountKind::Floppy | MountKind::Iso => self.path.is_file(),
+        };
+        if !ok {
+            let expected = if self.kind() == MountKind::Directory { "a directory" } else { "a disk image" };
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("mount {}: {} is not {}", self.drive, self.path.display(), expected)));
+        }
+        if let Some(n) = self.sectors_per_cluster {
+            if !n.is_power_of_two() || n > MAX_SECTORS_PER_CLUSTER {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("mount {}: invalid sectors per cluster {}", self.drive, n)));
+            }
+        }
+        if self.free_kb.unwrap_or(0) > self.total_kb.unwrap_or(DEFAULT_TOTAL_KB) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("mount {}: free space is larger than the disk", self.drive)));
+        }
+        Ok(())
+    }
+}
+
+/// disk layout reported by DOS for a drive, such as by GET FREE DISK SPACE
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DiskGeometry {
+    pub bytes_per_sector: u16,
+    pub sectors_per_cluster: u16,
+    pub total_clusters: u16,
+    pub free_clusters: u16,
+    pub media_descriptor: u8,
+}
+
+impl DiskGeometry {
+    /// geometry reported for host directories: 2 GB with 1 GB free in 32 KB clusters
+    pub fn directory() -> Self {
+        Self::with_size(DEFAULT_TOTAL_KB, DEFAULT_FREE_KB, None)
+    }
+
+    /// returns the geometry of a hard disk of `total_kb` with `free_kb` free. as with DOS on large drives,
+    /// at most 65535 clusters are reported
+    pub fn with_size(total_kb: u32, free_kb: u32, sectors_per_cluster: Option<u16>) -> Self {
+        let bytes_per_sector = 512;
+        let total_sectors = u64::from(total_kb) * 1024 / bytes_per_sector;
+        let sectors_per_cluster = sectors_per_cluster.unwrap_or_else(|| {
+            let mut n = 1;
+            while n < MAX_SECTORS_PER_CLUSTER && total_sectors / u64::from(n) > 0xFFFF {
+                n *= 2;
+            }
+            n
+        });
+        let clusters = |kb: u32| (u64::from(kb) * 1024 / bytes_per_sector / u64::from(sectors_per_cluster)).min(0xFFFF) as u16;
+        let total_clusters = clusters(total_kb);
+        DiskGeometry {
+            bytes_per_sector: bytes_per_sector as u16,
+            sectors_per_cluster,
+            total_clusters,
+            free_clusters: clusters(free_kb).min(total_clusters),
+            media_descriptor: 0xF8,
+        }
+    }
+
+    /// returns the geometry reported for directory `mount`
+    fn for_mount(mount: &Mount) -> Self {
+        let total_kb = mount.total_kb.unwrap_or(DEFAULT_TOTAL_KB);
+        let free_kb = mount.free_kb.unwrap_or_else(|| DEFAULT_FREE_KB.min(total_kb));
+        Self::with_size(total_kb, free_kb, mount.sectors_per_cluster)
+    }
+}
+
+/// a mounted DOS drive
+#[derive(Clone, Debug)]
+pub struct Drive {
+    pub mount: Mount,
+
+    /// host directory holding the files of the drive. disk images are unpacked to a scratch
+    /// directory, changes to their files are not written back to the image
+    pub root: PathBuf,
+
+    pub geometry: DiskGeometry,
+
+    scratch: Option<Arc<ScratchDir>>,
+}
+
+impl Drive {
+    /// returns the drive number, 0 = A:
+    pub fn number(&self) -> u8 {
+        self.mount.drive.to_ascii_uppercase() as u8 - b'A'
+    }
+}
+
+/// the drive letters known to DOS
+#[derive(Clone, Debug, Default)]
+pub struct MountTable {
+    drives: Vec<Drive>,
+}
+
+impl MountTable {
+    /// mounts `mount`, replacing an earlier mount of the same drive letter
+    pub fn mount(&mut self, mount: &Mount) -> io::Result<()> {
+        mount.validate()?;
+        let (geometry, scratch) = match mount.kind() {
+            MountKind::Directory => (DiskGeometry::for_mount(mount), None),
+            kind => {
+                let scratch = ScratchDir::create(mount.drive)?;
+                let data = fs::read(&mount.path)?;
+                let geometry = if kind == MountKind::Iso {
+                    unpack_iso_image(&data, &scratch.0)?
+                } else {
+                    unpack_fat_image(&data, &scratch.0)?
+                };
+                (geometry, Some(Arc::new(scratch)))
+            }
+        };
+        self.unmount(mount.drive);
+        self.drives.push(Drive {
+            mount: mount.clone(),
+            root: scratch.as_ref().map_or_else(|| mount.path.clone(), |s| s.0.clone()),
+            geometry,
+            scratch,
+        });
+        self.drives.sort_by_key(|d| d.number());
+        Ok(())
+    }
+
+    pub fn unmount(&mut self, drive: char) {
+        self.drives.retain(|d| !d.mount.drive.eq_ignore_ascii_case(&drive));
+    }
+
+    /// returns the drive with letter `drive`, without case
+    pub fn get(&self, drive: char) -> Option<&Drive> {
+        self.drives.iter().find(|d| d.mount.drive.eq_ignore_ascii_case(&drive))
+    }
+
+    /// returns the drive with number `number`, 0 = A:
+    pub fn get_number(&self, number: u8) -> Option<&Drive> {
+        self.drives.iter().find(|d| d.number() == number)
+    }
+
+    pub fn drives(&self) -> &[Drive] {
+        &self.drives
+    }
+
+    /// returns the number of drive letters, as returned by SELECT DEFAULT DRIVE
+    pub fn last_drive(&self) -> u8 {
+        self.drives.iter().map(|d| d.number() + 1).max().unwrap_or(0).max(DEFAULT_LAST_DRIVE)
+    }
+}
+
+/// a host directory holding an unpacked disk image, removed when the last drive using it is dropped
+#[derive(Debug)]
+struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    /// creates an empty host directory to unpack the image mounted as `drive` to
+    fn create(drive: char) -> io::Result<Self> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNT.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("dustbox-{}-{}-{}", std::process::id(), n, drive.to_ascii_uppercase()));
+        fs::create_dir_all(&dir)?;
+        Ok(ScratchDir(dir))
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// returns the drive letter of a DOS path such as "C:\FILE", if any
+pub fn path_drive(path: &str) -> Option<char> {
+    let bytes = path.as_bytes();
+    if bytes.len() >= 2 && bytes[1] == b':' && bytes[0].is_ascii_alphabetic() {
+        Some(bytes[0].to_ascii_uppercase() as char)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,74 @@
+use std::fs;
+
+use tempfile::tempdir;
+
+use crate::storage::{DiskGeometry, Mount, MountKind, MountTable};
+
+#[test]
+fn can_mount_drives() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("disk1.img"), b"").unwrap();
+    fs::write(dir.path().join("game.ISO"), b"").unwrap();
+
+    assert_eq!(MountKind::Directory, Mount::new('C', dir.path().to_path_buf()).kind());
+    assert_eq!(MountKind::Floppy, Mount::new('A', dir.path().join("disk1.img")).kind());
+    assert_eq!(MountKind::Iso, Mount::new('D', dir.path().join("game.ISO")).kind());
+
+    assert!(Mount::new('1', dir.path().to_path_buf()).validate().is_err());
+    assert!(Mount::new('A', dir.path().join("missing.img")).validate().is_err());
+    let mut mount = Mount::new('C', dir.path().join("disk1.img"));
+    mount.kind = Some(MountKind::Directory);
+    assert!(mount.validate().is_err());
+
+    let mut table = MountTable::default();
+    assert_eq!(5, table.last_drive());
+    assert!(table.mount(&Mount::new('A', dir.path().join("disk1.img"))).is_err());
+
+    table.mount(&Mount::new('g', dir.path().to_path_buf())).unwrap();
+    assert_eq!(7, table.last_drive());
+    assert_eq!(6, table.get('G').unwrap().number());
+    assert_eq!(dir.path(), table.get_number(6).unwrap().root);
+
+    table.unmount('G');
+    assert!(table.get('g').is_none());
+}
+
+#[test]
+fn can_configure_disk_geometry() {
+    let geometry = DiskGeometry::with_size(20 * 1024, 5 * 1024, None);
+    assert_eq!(512, geometry.bytes_per_sector);
+    assert_eq!(1, geometry.sectors_per_cluster);
+    assert_eq!(40960, geometry.total_clusters);
+    assert_eq!(10240, geometry.free_clusters);
+
+    let geometry = DiskGeometry::with_size(100 * 1024, 100 * 1024, None);
+    assert_eq!(4, geometry.sectors_per_cluster);
+    assert_eq!(51200, geometry.total_clusters);
+    assert_eq!(51200, geometry.free_clusters);
+
+    let geometry = DiskGeometry::with_size(8 * 1024 * 1024, 1024, Some(8));
+    assert_eq!(0xFFFF, geometry.total_clusters);
+    assert_eq!(256, geometry.free_clusters);
+
+    let dir = tempdir().unwrap();
+    let mut table = MountTable::default();
+    table.mount(&Mount {
+        free_kb: Some(512),
+        ..Mount::new('C', dir.path().to_path_buf())
+    }).unwrap();
+    let geometry = table.get('C').unwrap().geometry;
+    assert_eq!(64, geometry.sectors_per_cluster);
+    assert_eq!(16, geometry.free_clusters);
+
+    let bad = Mount {
+        sectors_per_cluster: Some(3),
+        ..Mount::new('C', dir.path().to_path_buf())
+    };
+    assert!(bad.validate().is_err());
+    let bad = Mount {
+        total_kb: Some(1024),
+        free_kb: Some(2048),
+        ..Mount::new('C', dir.path().to_path_buf())
+    };
+    assert!(bad.validate().is_err());
+}
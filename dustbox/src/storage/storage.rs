@@ -1,3 +1,5 @@
+use log::warn;
+
 use crate::cpu::{CPU, R};
 use crate::machine::Component;
 use crate::memory::MMU;
@@ -7,6 +9,10 @@ pub struct Storage {
 }
 
 impl Component for Storage {
+    fn interrupts(&self) -> Vec<u8> {
+        vec![0x13]
+    }
+
     fn int(&mut self, int: u8, cpu: &mut CPU, _mmu: &mut MMU) -> bool {
         if int != 0x13 {
             return false;
@@ -15,7 +21,7 @@ impl Component for Storage {
             0x00 => {
                 // DISK - RESET DISK DRIVES
                 // DL = drive (if bit 7 is set both hard disks and floppy disks reset)
-                println!("XXX DISK - RESET DISK SYSTEM, dl={:02X}", cpu.get_r8(R::DL))
+                warn!(target: "dos", "XXX DISK - RESET DISK SYSTEM, dl={:02X}", cpu.get_r8(R::DL))
                 // Return:
                 // AH = status (see #00234)
                 // CF clear if successful (returned AH=00h)
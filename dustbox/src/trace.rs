@@ -0,0 +1,213 @@
+use std::io::{self, Read, Write};
+use std::ops::Range;
+
+use crate::cpu::{CPU, Instruction, R};
+use crate::memory::MemoryAddress;
+
+#[cfg(test)]
+#[path = "./trace_test.rs"]
+mod trace_test;
+
+/// identifies a binary instruction trace
+pub const BINARY_TRACE_MAGIC: &[u8; 8] = b"DBTRACE1";
+
+/// max instruction length stored in a binary trace record
+const MAX_INSTRUCTION_BYTES: usize = 15;
+
+/// 32-bit gpr, in the order stored in a trace record
+const GPR32: [R; 8] = [R::EAX, R::ECX, R::EDX, R::EBX, R::ESP, R::EBP, R::ESI, R::EDI];
+
+/// 16-bit gpr, in the order stored in a trace record
+const GPR16: [R; 8] = [R::AX, R::CX, R::DX, R::BX, R::SP, R::BP, R::SI, R::DI];
+
+/// segment registers, in the order stored in a trace record
+const SREG: [R; 6] = [R::ES, R::CS, R::SS, R::DS, R::FS, R::GS];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceFormat {
+    /// one line per instruction, similar to the dosbox debugger "LOGS" format
+    Text,
+
+    /// fixed size records, starting with `BINARY_TRACE_MAGIC`. converted to text with dustbox-trace-convert
+    Binary,
+}
+
+/// configures the instruction trace written by `Machine::write_trace_with`
+#[derive(Clone, Debug)]
+pub struct TraceConfig {
+    /// if not empty, only instructions at flat addresses in these ranges are traced
+    pub ranges: Vec<Range<u32>>,
+
+    /// only trace instructions that may transfer control
+    pub only_branches: bool,
+
+    /// registers shown in text traces
+    pub registers: Vec<R>,
+
+    pub format: TraceFormat,
+}
+
+impl Default for TraceConfig {
+    fn default() -> Self {
+        TraceConfig {
+            ranges: Vec::new(),
+            only_branches: false,
+            registers: default_registers(),
+            format: TraceFormat::Text,
+        }
+    }
+}
+
+impl TraceConfig {
+    /// returns true if `op` at flat address `addr` should be traced
+    pub fn should_trace(&self, addr: u32, op: &Instruction) -> bool {
+        if !self.ranges.is_empty() && !self.ranges.iter().any(|r| r.contains(&addr)) {
+            return false;
+        }
+        !self.only_branches || op.is_branch()
+    }
+}
+
+/// the registers of the dosbox debugger "LOGS" format
+pub fn default_registers() -> Vec<R> {
+    vec![R::EAX, R::EBX, R::ECX, R::EDX, R::ESI, R::EDI, R::EBP, R::ESP, R::DS, R::ES, R::SS]
+}
+
+/// parses a comma separated list of register names, such as "ax,bx,ds"
+pub fn parse_registers(s: &str) -> Result<Vec<R>, String> {
+    let mut res = Vec::new();
+    for name in s.split(',') {
+        let name = name.trim().to_lowercase();
+        match GPR32.iter().chain(GPR16.iter()).chain(SREG.iter()).find(|r| format!("{}", r) == name) {
+            Some(r) => res.push(*r),
+            None => return Err(format!("unknown register {}", name)),
+        }
+    }
+    Ok(res)
+}
+
+/// parses an address range such as "085F:0100-085F:0200", or flat "8700-8800". the end is exclusive
+pub fn parse_range(s: &str) -> Result<Range<u32>, String> {
+    let pos = match s.find('-') {
+        Some(pos) => pos,
+        None => return Err(format!("invalid range {}", s)),
+    };
+    Ok(parse_address(&s[..pos])?..parse_address(&s[pos + 1..])?)
+}
+
+/// parses an address such as "085F:0100", or flat "8700"
+pub fn parse_address(s: &str) -> Result<u32, String> {
+    let hex = |s: &str| u32::from_str_radix(s.trim_start_matches("0x"), 16).map_err(|e| format!("{}: {}", s, e));
+    match s.find(':') {
+        Some(pos) => Ok(MemoryAddress::RealSegmentOffset(hex(&s[..pos])? as u16, hex(&s[pos + 1..])? as u16).value()),
+        None => hex(s),
+    }
+}
+
+/// the register state before a traced instruction
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceRecord {
+    pub ip: u16,
+    pub bytes: Vec<u8>,
+    pub gpr: [u32; 8],
+    pub sreg: [u16; 6],
+    pub flags: u16,
+}
+
+impl TraceRecord {
+    /// size of a record in a binary trace
+    pub const SIZE: usize = 2 + 1 + MAX_INSTRUCTION_BYTES + 8 * 4 + 6 * 2 + 2;
+
+    pub fn from_cpu(cpu: &CPU, bytes: &[u8]) -> Self {
+        let mut gpr = [0; 8];
+        for (i, r) in GPR32.iter().enumerate() {
+            gpr[i] = cpu.get_r32(*r);
+        }
+        let mut sreg = [0; 6];
+        for (i, r) in SREG.iter().enumerate() {
+            sreg[i] = cpu.get_r16(*r);
+        }
+        TraceRecord {
+            ip: cpu.regs.ip,
+            bytes: bytes[..bytes.len().min(MAX_INSTRUCTION_BYTES)].to_vec(),
+            gpr,
+            sreg,
+            flags: cpu.regs.flags.u16(),
+        }
+    }
+
+    pub fn cs(&self) -> u16 {
+        self.sreg[1]
+    }
+
+    /// returns the value of `r`, and its width in hex digits
+    fn register(&self, r: R) -> (u32, usize) {
+        if let Some(i) = GPR32.iter().position(|x| *x == r) {
+            (self.gpr[i], 8)
+        } else if let Some(i) = GPR16.iter().position(|x| *x == r) {
+            (self.gpr[i] & 0xFFFF, 4)
+        } else if let Some(i) = SREG.iter().position(|x| *x == r) {
+            (u32::from(self.sreg[i]), 4)
+        } else {
+            (0, 4)
+        }
+    }
+
+    /// formats the record similar to the dosbox debugger "LOGS" format
+    pub fn text_line(&self, disasm: &str, registers: &[R]) -> String {
+        let disasm = format!("{:30}", disasm);
+        let mut s = format!("{:04X}:{:04X}  {}", self.cs(), self.ip, &disasm[..30]);
+        for r in registers {
+            let (val, width) = self.register(*r);
+            s.push_str(&format!(" {}:{:0width$X}", format!("{}", r).to_uppercase(), val, width = width));
+        }
+        let bit = |mask: u16| if self.flags & mask != 0 { 1 } else { 0 };
+        s.push_str(&format!(" C{} Z{} S{} O{} I{}", bit(0x0001), bit(0x0040), bit(0x0080), bit(0x0800), bit(0x0200)));
+        s
+    }
+
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(Self::SIZE);
+        buf.extend_from_slice(&self.ip.to_le_bytes());
+        buf.push(self.bytes.len() as u8);
+        let mut bytes = [0u8; MAX_INSTRUCTION_BYTES];
+        bytes[..self.bytes.len()].copy_from_slice(&self.bytes);
+        buf.extend_from_slice(&bytes);
+        for v in &self.gpr {
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+        for v in &self.sreg {
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+        buf.extend_from_slice(&self.flags.to_le_bytes());
+        w.write_all(&buf)
+    }
+
+    /// reads the next record, or None at end of input
+    pub fn read_from<Rd: Read>(r: &mut Rd) -> io::Result<Option<Self>> {
+        let mut buf = [0u8; Self::SIZE];
+        match r.read_exact(&mut buf) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let u16_at = |pos: usize| u16::from_le_bytes([buf[pos], buf[pos + 1]]);
+        let len = (buf[2] as usize).min(MAX_INSTRUCTION_BYTES);
+        let mut gpr = [0; 8];
+        for (i, v) in gpr.iter_mut().enumerate() {
+            let pos = 18 + i * 4;
+            *v = u32::from_le_bytes([buf[pos], buf[pos + 1], buf[pos + 2], buf[pos + 3]]);
+        }
+        let mut sreg = [0; 6];
+        for (i, v) in sreg.iter_mut().enumerate() {
+            *v = u16_at(50 + i * 2);
+        }
+        Ok(Some(TraceRecord {
+            ip: u16_at(0),
+            bytes: buf[3..3 + len].to_vec(),
+            gpr,
+            sreg,
+            flags: u16_at(62),
+        }))
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead};
+
+use crate::cpu::{CPU, R};
+use crate::machine::Machine;
+use crate::trace::{default_registers, TraceRecord};
+
+#[cfg(test)]
+#[path = "./trace_compare_test.rs"]
+mod trace_compare_test;
+
+/// instructions in segments from here and up (video bios, bios) are skipped when comparing,
+/// because dosbox-x executes its interrupt handlers there while dustbox handles them natively
+const ROM_SEGMENT: u16 = 0xC000;
+
+/// flag names used in dosbox-x logs, and their bit in the flags register
+const FLAGS: [(&str, u16); 9] = [
+    ("CF", 0x0001), ("PF", 0x0004), ("AF", 0x0010), ("ZF", 0x0040), ("SF", 0x0080),
+    ("TF", 0x0100), ("IF", 0x0200), ("DF", 0x0400), ("OF", 0x0800),
+];
+
+/// registers that may appear in dosbox-x logs
+const REGISTERS: [R; 14] = [
+    R::EAX, R::EBX, R::ECX, R::EDX, R::ESI, R::EDI, R::EBP, R::ESP,
+    R::CS, R::DS, R::ES, R::FS, R::GS, R::SS,
+];
+
+/// a line of a dosbox-x debugger "LOGS" or "LOGL" trace, with the state before the instruction
+#[derive(Clone, Debug, PartialEq)]
+pub struct DosboxLogLine {
+    pub cs: u16,
+    pub ip: u16,
+    pub disasm: String,
+
+    /// register values present in the line
+    pub registers: Vec<(R, u32)>,
+
+    /// flag values present in the line
+    pub flags: Vec<(&'static str, bool)>,
+}
+
+impl DosboxLogLine {
+    /// parses a line such as "0192:00000100  mov  ax,1234   EAX:00000000 ... CF:0 ZF:0",
+    /// returns None for lines that don't describe an instruction
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut tokens = line.split_whitespace();
+        let addr = tokens.next()?;
+        let pos = addr.find(':')?;
+        let cs = u16::from_str_radix(&addr[..pos], 16).ok()?;
+        let ip = u32::from_str_radix(&addr[pos + 1..], 16).ok()? as u16;
+
+        let mut disasm = Vec::new();
+        let mut registers = Vec::new();
+        let mut flags = Vec::new();
+        for token in tokens {
+            let (name, value) = match token.find(':') {
+                Some(pos) => (&token[..pos], &token[pos + 1..]),
+                None => ("", ""),
+            };
+            let value = u32::from_str_radix(value, 16);
+            if let (Some(r), Ok(value)) = (REGISTERS.iter().find(|r| format!("{}", r).to_uppercase() == name), &value) {
+                registers.push((*r, *value));
+            } else if let (Some((flag, _)), Ok(value)) = (FLAGS.iter().find(|(flag, _)| *flag == name), &value) {
+                flags.push((*flag, *value != 0));
+            } else if registers.is_empty() && flags.is_empty() {
+                disasm.push(token);
+            }
+        }
+        if registers.is_empty() {
+            return None;
+        }
+        Some(DosboxLogLine {
+            cs,
+            ip,
+            disasm: disasm.join(" "),
+            registers,
+            flags,
+        })
+    }
+
+    /// returns the differences between the logged state and `cpu`
+    pub fn differences(&self, cpu: &CPU) -> Vec<String> {
+        let mut res = Vec::new();
+        if self.cs != cpu.get_r16(R::CS) || self.ip != cpu.regs.ip {
+            res.push(format!("CS:IP {:04X}:{:04X}, expected {:04X}:{:04X}", cpu.get_r16(R::CS), cpu.regs.ip, self.cs, self.ip));
+        }
+        for (r, expected) in &self.registers {
+            let (value, width) = if r.is_gpr() {
+                (cpu.get_r32(*r), 8)
+            } else {
+                (u32::from(cpu.get_r16(*r)), 4)
+            };
+            if value != *expected {
+                res.push(format!("{} {:0width$X}, expected {:0width$X}",
+                    format!("{}", r).to_uppercase(), value, expected, width = width));
+            }
+        }
+        let cpu_flags = cpu.regs.flags.u16();
+        for (name, expected) in &self.flags {
+            let mask = FLAGS.iter().find(|(flag, _)| flag == name).unwrap().1;
+            let value = cpu_flags & mask != 0;
+            if value != *expected {
+                res.push(format!("{} {}, expected {}", name, value as u8, *expected as u8));
+            }
+        }
+        res
+    }
+}
+
+/// the first instruction where dustbox and the log disagree
+pub struct Divergence {
+    /// number of compared instructions before the divergence
+    pub instruction: usize,
+
+    /// the latest matching instructions, as dustbox trace lines
+    pub context: Vec<String>,
+
+    /// the log line describing the expected state
+    pub expected: String,
+
+    /// the state of dustbox
+    pub actual: String,
+
+    pub differences: Vec<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "divergence after {} instructions", self.instruction)?;
+        for line in &self.context {
+            writeln!(f, "  {}", line)?;
+        }
+        writeln!(f, "dosbox-x: {}", self.expected)?;
+        writeln!(f, "dustbox:  {}", self.actual)?;
+        for diff in &self.differences {
+            writeln!(f, "  {}", diff)?;
+        }
+        Ok(())
+    }
+}
+
+/// runs `machine` in lockstep with a dosbox-x log, comparing the state before each logged instruction.
+/// returns the first divergence, or None if the whole log matched.
+/// `context` is the number of previous instructions to include in the result
+pub fn compare_with_log<B: BufRead>(machine: &mut Machine, log: B, context: usize) -> io::Result<Option<Divergence>> {
+    let registers = default_registers();
+    let mut history = VecDeque::with_capacity(context + 1);
+    let mut compared = 0;
+    for line in log.lines() {
+        let line = line?;
+        let expected = match DosboxLogLine::parse(&line) {
+            Some(expected) => expected,
+            None => continue,
+        };
+        if expected.cs >= ROM_SEGMENT {
+            continue;
+        }
+        while machine.cpu.get_r16(R::CS) >= ROM_SEGMENT && !machine.cpu.fatal_error {
+            machine.execute_instruction();
+        }
+
+        let cs = machine.cpu.get_r16(R::CS);
+        let ip = machine.cpu.regs.ip;
+        let op = machine.cpu.decoder.get_instruction(&mut machine.mmu, cs, ip);
+        let bytes = machine.mmu.read(cs, ip, op.length as usize);
+        let actual = TraceRecord::from_cpu(&machine.cpu, &bytes).text_line(&format!("{}", op), &registers);
+
+        let differences = expected.differences(&machine.cpu);
+        if !differences.is_empty() || machine.cpu.fatal_error {
+            return Ok(Some(Divergence {
+                instruction: compared,
+                context: history.into_iter().collect(),
+                expected: line.trim().to_owned(),
+                actual,
+                differences,
+            }));
+        }
+
+        history.push_back(actual);
+        if history.len() > context {
+            history.pop_front();
+        }
+        compared += 1;
+        machine.execute_instruction();
+    }
+    Ok(None)
+}
@@ -0,0 +1,45 @@
+use crate::cpu::R;
+use crate::machine::Machine;
+use crate::trace_compare::{compare_with_log, DosboxLogLine};
+
+fn compare_program() -> Vec<u8> {
+    vec![
+        0xB8, 0x34, 0x12,   // mov ax,0x1234
+        0x43,               // inc bx
+        0x43,               // inc bx
+    ]
+}
+
+fn log_line(ip: u16, disasm: &str, ax: u16, bx: u16) -> String {
+    format!("085F:{:08X}  {:40}EAX:{:08X} EBX:{:08X} ECX:000000FF EDX:0000085F ESI:00000100 EDI:0000FFFE EBP:0000091C ESP:0000FFFE DS:085F ES:085F FS:0000 GS:0000 SS:085F CF:0 ZF:0 SF:0 OF:0 IF:1\n",
+        ip, disasm, ax, bx)
+}
+
+#[test]
+fn can_parse_dosbox_log_line() {
+    let line = DosboxLogLine::parse("0192:00000105  mov  ax,[es:0010]          EAX:00001234 EBX:00000000 DS:0192 CF:1 ZF:0").unwrap();
+    assert_eq!(0x0192, line.cs);
+    assert_eq!(0x0105, line.ip);
+    assert_eq!("mov ax,[es:0010]", line.disasm);
+    assert_eq!(vec![(R::EAX, 0x1234), (R::EBX, 0), (R::DS, 0x0192)], line.registers);
+    assert_eq!(vec![("CF", true), ("ZF", false)], line.flags);
+
+    assert_eq!(None, DosboxLogLine::parse("LOG: dos: open file"));
+}
+
+#[test]
+fn can_find_divergence() {
+    let log = log_line(0x0100, "mov  ax,1234", 0, 0) + &log_line(0x0103, "inc  bx", 0x1234, 0) + &log_line(0x0104, "inc  bx", 0x1234, 1);
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&compare_program(), 0x085F);
+    assert!(compare_with_log(&mut machine, log.as_bytes(), 5).unwrap().is_none());
+
+    let log = log_line(0x0100, "mov  ax,1234", 0, 0) + &log_line(0x0103, "inc  bx", 0x1234, 0) + &log_line(0x0104, "inc  bx", 0x1234, 2);
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&compare_program(), 0x085F);
+    let divergence = compare_with_log(&mut machine, log.as_bytes(), 1).unwrap().unwrap();
+    assert_eq!(2, divergence.instruction);
+    assert_eq!(1, divergence.context.len());
+    assert!(divergence.context[0].starts_with("085F:0103  Inc16    bx"));
+    assert_eq!(vec!["EBX 00000001, expected 00000002"], divergence.differences);
+}
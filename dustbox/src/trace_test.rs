@@ -0,0 +1,79 @@
+use std::fs;
+
+use tempfile::tempdir;
+
+use crate::cpu::R;
+use crate::machine::Machine;
+use crate::trace::{parse_range, parse_registers, TraceConfig, TraceFormat, TraceRecord, BINARY_TRACE_MAGIC};
+
+fn trace_program() -> Vec<u8> {
+    vec![
+        0xB8, 0x34, 0x12,   // mov ax,0x1234
+        0xEB, 0x01,         // jmp short 0x106
+        0x90,               // nop
+        0x43,               // inc bx
+        0x75, 0x00,         // jnz 0x109
+        0x90,               // nop
+    ]
+}
+
+fn run_trace(config: TraceConfig) -> Vec<u8> {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("trace.log");
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&trace_program(), 0x085F);
+    machine.write_trace_with(path.to_str().unwrap(), config);
+    machine.execute_instructions(5);
+    fs::read(&path).unwrap()
+}
+
+#[test]
+fn can_parse_trace_options() {
+    assert_eq!(vec![R::AX, R::EBX, R::DS], parse_registers("ax, EBX,ds").unwrap());
+    assert!(parse_registers("ax,xx").is_err());
+    assert_eq!(0x8700..0x8710, parse_range("085F:0110-0x8710").unwrap());
+    assert!(parse_range("8700").is_err());
+}
+
+#[test]
+fn can_filter_trace() {
+    let config = TraceConfig {
+        only_branches: true,
+        registers: vec![R::AX, R::BX],
+        ..TraceConfig::default()
+    };
+    let trace = String::from_utf8(run_trace(config)).unwrap();
+    assert_eq!("085F:0103  JmpShort 0x0106                AX:1234 BX:0000 C0 Z0 S0 O0 I1
+085F:0107  Jnz      0x0109                AX:1234 BX:0001 C0 Z0 S0 O0 I1
+", trace);
+
+    let config = TraceConfig {
+        ranges: vec![parse_range("085F:0106-085F:0107").unwrap()],
+        ..TraceConfig::default()
+    };
+    let trace = String::from_utf8(run_trace(config)).unwrap();
+    assert_eq!("085F:0106  Inc16    bx                    EAX:00001234 EBX:00000000 ECX:000000FF EDX:0000085F ESI:00000100 EDI:0000FFFE EBP:0000091C ESP:0000FFFE DS:085F ES:085F SS:085F C0 Z0 S0 O0 I1
+", trace);
+}
+
+#[test]
+fn can_write_binary_trace() {
+    let config = TraceConfig {
+        format: TraceFormat::Binary,
+        ..TraceConfig::default()
+    };
+    let trace = run_trace(config);
+    assert_eq!(BINARY_TRACE_MAGIC, &trace[..8]);
+    assert_eq!(8 + 5 * TraceRecord::SIZE, trace.len());
+
+    let mut reader = &trace[8 + TraceRecord::SIZE..];
+    let record = TraceRecord::read_from(&mut reader).unwrap().unwrap();
+    assert_eq!(0x0103, record.ip);
+    assert_eq!(0x085F, record.cs());
+    assert_eq!(vec![0xEB, 0x01], record.bytes);
+    assert_eq!(0x1234, record.gpr[0]);
+
+    let mut out = Vec::new();
+    record.write_to(&mut out).unwrap();
+    assert_eq!(&trace[8 + TraceRecord::SIZE..8 + 2 * TraceRecord::SIZE], &out[..]);
+}
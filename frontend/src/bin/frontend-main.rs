@@ -1,25 +1,42 @@
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, SystemTime};
 use std::thread::sleep;
 
 use sdl2::event::Event;
+use sdl2::keyboard::Mod;
 use sdl2::pixels;
 use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Texture, WindowCanvas};
+use sdl2::video::FullscreenType;
 
 #[macro_use]
 extern crate clap;
 use clap::{Arg, App};
 
-use dustbox::machine::Machine;
+use dustbox::dosbox_conf::DosboxConf;
+use dustbox::gpu::{window_size, Scaling, VideoModeBlock, Viewport};
+use dustbox::keyboard::{Keycode, Modifiers};
+use dustbox::logging::LogFilter;
+use dustbox::storage::Mount;
+use dustbox::machine::{Machine, MachineConfig};
 use dustbox::mouse::MouseButton;
+use dustbox::trace::{parse_range, parse_registers, TraceConfig, TraceFormat};
 
 const DEBUG_PERFORMANCE: bool = true;
 
+/// speed change of the speed hotkeys, in percent
+const SPEED_STEP: u32 = 10;
+
+/// opacity of the dark half of each scanline, when the scanline filter is enabled
+const SCANLINE_ALPHA: u8 = 96;
+
 fn main() {
     let matches = App::new("dustbox-frontend")
         .version("0.1")
         .arg(Arg::with_name("INPUT")
-            .help("Sets the input file to use")
-            .required(true)
+            .help("Sets the input file to use. without one, the built-in shell is started")
             .index(1))
         .arg(Arg::with_name("SCALE")
             .help("Scale the window resolution")
@@ -28,6 +45,76 @@ fn main() {
         .arg(Arg::with_name("NOSQUARE")
             .help("Don't make pixels square by stretching (default)")
             .long("no-square"))
+        .arg(Arg::with_name("FULLSCREEN")
+            .help("Starts in fullscreen. Alt+Enter toggles it at runtime")
+            .long("fullscreen"))
+        .arg(Arg::with_name("INTEGERSCALE")
+            .help("Scales by whole pixels only, for sharp nearest-neighbor output")
+            .long("integer-scale"))
+        .arg(Arg::with_name("SCANLINES")
+            .help("Darkens every other display line, like a CRT")
+            .long("scanlines"))
+        .arg(Arg::with_name("CONFIG")
+            .help("Reads the machine configuration from a toml file (default dustbox.toml, if it exists)")
+            .takes_value(true)
+            .long("config"))
+        .arg(Arg::with_name("DOSBOXCONF")
+            .help("Reads the cpu speed, machine type, mounts and program to run from a dosbox .conf file")
+            .takes_value(true)
+            .long("dosbox-conf"))
+        .arg(Arg::with_name("CPU")
+            .help("Emulated cpu: 8086, 186, 286, 386 or 486")
+            .takes_value(true)
+            .long("cpu"))
+        .arg(Arg::with_name("CPUHZ")
+            .help("Cpu clock speed in Hz")
+            .takes_value(true)
+            .long("cpu-hz"))
+        .arg(Arg::with_name("SPEED")
+            .help("Emulated speed in percent of the cpu clock (default 100). Ctrl+F11/F12 adjusts it at runtime")
+            .takes_value(true)
+            .long("speed"))
+        .arg(Arg::with_name("TURBO")
+            .help("Runs as fast as possible. F12 toggles it at runtime")
+            .long("turbo"))
+        .arg(Arg::with_name("PAUSED")
+            .help("Starts paused. Pause toggles it at runtime")
+            .long("paused"))
+        .arg(Arg::with_name("CAPTUREMOUSE")
+            .help("Starts with the mouse captured, sending relative motion. Ctrl+F9 toggles it at runtime")
+            .long("capture-mouse"))
+        .arg(Arg::with_name("EXTENDEDKB")
+            .help("Extended memory in KB")
+            .takes_value(true)
+            .long("extended-kb"))
+        .arg(Arg::with_name("AUDIO")
+            .help("Sound hardware: none, speaker, adlib or sb16. only none is supported")
+            .takes_value(true)
+            .long("audio"))
+        .arg(Arg::with_name("KEYBOARDLAYOUT")
+            .help("Keyboard layout, such as us or de. only us is supported")
+            .takes_value(true)
+            .long("keyboard-layout"))
+        .arg(Arg::with_name("MOUNT")
+            .help("Mounts a host directory, floppy image or ISO image as a DOS drive, such as C=/home/user/dos or D=game.iso")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .long("mount"))
+        .arg(Arg::with_name("DRIVER")
+            .help("Loads a DOS device driver at boot, ANSI.SYS is emulated")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .long("driver"))
+        .arg(Arg::with_name("LOG")
+            .help("Log level of each subsystem (cpu, gpu, dos, io, int), such as dos=debug,io=warn or a bare level for all")
+            .takes_value(true)
+            .long("log"))
+        .arg(Arg::with_name("STDINFILE")
+            .help("Reads the standard input of the program from a file, instead of the keyboard")
+            .takes_value(true)
+            .long("stdin-file"))
         .arg(Arg::with_name("DETERMINISTIC")
             .help("Enables deterministic mode (debugging)")
             .long("deterministic"))
@@ -39,42 +126,194 @@ fn main() {
             .help("Limits the trace to a number of instructions (debugging)")
             .takes_value(true)
             .long("tracecount"))
+        .arg(Arg::with_name("TRACERANGE")
+            .help("Only trace instructions in address range, such as 085F:0100-085F:0200 (debugging)")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .long("trace-range"))
+        .arg(Arg::with_name("TRACEBRANCHES")
+            .help("Only trace instructions that may transfer control (debugging)")
+            .long("trace-branches"))
+        .arg(Arg::with_name("TRACEREGISTERS")
+            .help("Comma separated list of registers to show in the trace, such as ax,bx,ds (debugging)")
+            .takes_value(true)
+            .long("trace-registers"))
+        .arg(Arg::with_name("TRACEBINARY")
+            .help("Writes the trace in binary format, see dustbox-trace-convert (debugging)")
+            .long("trace-binary"))
+        .arg(Arg::with_name("SYMBOLS")
+            .help("Name addresses in the instruction trace using a .map or .toml file (debugging)")
+            .takes_value(true)
+            .long("symbols"))
+        .arg(Arg::with_name("COVERAGE")
+            .help("Writes the executed code to a coverage file, in drcov format if named *.drcov (debugging)")
+            .takes_value(true)
+            .long("coverage"))
+        .arg(Arg::with_name("DUMPMEMORY")
+            .help("Writes a memory range to a file on exit, such as 085F:0100-085F:0200=code.bin (debugging)")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .long("dump-memory"))
+        .arg(Arg::with_name("DUMPVIDEO")
+            .help("Writes the video memory of the current mode to a file on exit (debugging)")
+            .takes_value(true)
+            .long("dump-video"))
+        .arg(Arg::with_name("RECORDVIDEO")
+            .help("Records video output to an animated GIF, or APNG if the file name ends in .png")
+            .takes_value(true)
+            .long("record-video"))
         .get_matches();
 
-    let filename = matches.value_of("INPUT").unwrap();
+    let mut config = match matches.value_of("CONFIG") {
+        Some(name) => MachineConfig::from_file(name).unwrap_or_else(|e| panic!("error reading {}: {}", name, e)),
+        None if Path::new("dustbox.toml").exists() => {
+            MachineConfig::from_file("dustbox.toml").unwrap_or_else(|e| panic!("error reading dustbox.toml: {}", e))
+        }
+        None => MachineConfig::default(),
+    };
+    let dosbox = matches.value_of("DOSBOXCONF").map(|name| {
+        let dosbox = DosboxConf::from_file(name, &mut config).unwrap_or_else(|e| panic!("error reading {}: {}", name, e));
+        for setting in &dosbox.unsupported {
+            println!("{}: {} is not supported", name, setting);
+        }
+        dosbox
+    });
+    let filename = match (matches.value_of("INPUT"), &dosbox) {
+        (Some(filename), _) => filename.to_owned(),
+        (None, Some(DosboxConf { program: Some(program), .. })) => program.clone(),
+        (None, _) => "COMMAND.COM".to_owned(),
+    };
+    let filename = filename.as_str();
+    if let Some(cpu) = matches.value_of("CPU") {
+        config.cpu = cpu.parse().unwrap_or_else(|e| panic!("error {}", e));
+    }
+    if matches.is_present("CPUHZ") {
+        config.cpu_hz = Some(value_t!(matches, "CPUHZ", usize).unwrap());
+    }
+    if matches.is_present("EXTENDEDKB") {
+        config.extended_kb = value_t!(matches, "EXTENDEDKB", u16).unwrap();
+    }
+    if let Some(audio) = matches.value_of("AUDIO") {
+        config.audio = audio.parse().unwrap_or_else(|e| panic!("error {}", e));
+        config.audio.validate().unwrap_or_else(|e| panic!("error {}", e));
+    }
+    if let Some(layout) = matches.value_of("KEYBOARDLAYOUT") {
+        config.keyboard_layout = layout.parse().unwrap_or_else(|e| panic!("error {}", e));
+        config.keyboard_layout.validate().unwrap_or_else(|e| panic!("error {}", e));
+    }
+    if let Some(mounts) = matches.values_of("MOUNT") {
+        for mount in mounts {
+            let pos = mount.find('=').unwrap_or_else(|| panic!("invalid mount {}", mount));
+            let drive = mount[..pos].chars().next().unwrap_or_else(|| panic!("invalid mount {}", mount));
+            config.mounts.retain(|m| !m.drive.eq_ignore_ascii_case(&drive));
+            config.mounts.push(Mount::new(drive, PathBuf::from(&mount[pos + 1..])));
+        }
+    }
+    if let Some(drivers) = matches.values_of("DRIVER") {
+        config.drivers.extend(drivers.map(String::from));
+    }
+    if let Some(log) = matches.value_of("LOG") {
+        LogFilter::parse(log).unwrap_or_else(|e| panic!("error {}", e));
+        config.log = log.to_owned();
+    }
 
     let mut machine = if matches.is_present("DETERMINISTIC") {
-        Machine::deterministic()
+        Machine::with_config(config)
     } else {
-        Machine::default()
+        Machine::from_config(config)
     };
 
+    if matches.is_present("SPEED") {
+        machine.set_speed_percent(value_t!(matches, "SPEED", u32).unwrap());
+    }
+    if matches.is_present("TURBO") || dosbox.as_ref().is_some_and(|d| d.unlimited) {
+        machine.set_unlimited(true);
+    }
+
     if matches.is_present("TRACEFILE") {
         let tracename = matches.value_of("TRACEFILE").unwrap();
         println!("Instruction trace will be written to {}", tracename);
-        machine.write_trace_to(tracename);
+        let mut config = TraceConfig::default();
+        if let Some(ranges) = matches.values_of("TRACERANGE") {
+            for range in ranges {
+                config.ranges.push(parse_range(range).unwrap_or_else(|e| panic!("error {}", e)));
+            }
+        }
+        if let Some(registers) = matches.value_of("TRACEREGISTERS") {
+            config.registers = parse_registers(registers).unwrap_or_else(|e| panic!("error {}", e));
+        }
+        config.only_branches = matches.is_present("TRACEBRANCHES");
+        if matches.is_present("TRACEBINARY") {
+            config.format = TraceFormat::Binary;
+        }
+        machine.write_trace_with(tracename, config);
     }
     if matches.is_present("TRACECOUNT") {
         machine.set_trace_count(value_t!(matches, "TRACECOUNT", usize).unwrap());
     }
 
-    if let Some(e) = machine.load_executable_file(filename) {
+    let error = match &dosbox {
+        _ if matches.value_of("INPUT").is_some() => machine.load_executable_file(filename),
+        Some(DosboxConf { program: Some(_), current_dir, .. }) => {
+            if let Some(dir) = current_dir {
+                machine.set_current_dir(dir);
+            }
+            machine.load_dos_program(filename)
+        }
+        _ => {
+            machine.start_shell();
+            None
+        }
+    };
+    if let Some(e) = error {
         panic!("error {}", e);
     };
 
+    if let Some(name) = matches.value_of("STDINFILE") {
+        let input = fs::read(name).unwrap_or_else(|e| panic!("error reading {}: {}", name, e));
+        machine.set_stdin(input);
+    }
+
+    if let Some(symbols) = matches.value_of("SYMBOLS") {
+        match machine.load_symbols(symbols) {
+            Ok(n) => println!("Loaded {} symbols from {}", n, symbols),
+            Err(e) => panic!("error reading {}: {}", symbols, e),
+        }
+    }
+
+    if matches.is_present("COVERAGE") {
+        machine.enable_coverage();
+    }
+
+    if matches.is_present("RECORDVIDEO") {
+        let videoname = matches.value_of("RECORDVIDEO").unwrap();
+        println!("Video will be recorded to {}", videoname);
+        machine.start_video_capture(videoname);
+    }
+
     let sdl_context = sdl2::init().unwrap();
     let video_subsys = sdl_context.video().unwrap();
 
     let scale_factor = value_t!(matches, "SCALE", f32).unwrap_or(2.);
 
-    let initial_screen_width  = (320. * scale_factor) as u32;
-    let initial_screen_height = (200. * scale_factor) as u32;
-    let window = video_subsys.window(&format!("dustbox - {}", filename), initial_screen_width, initial_screen_height)
-        .position_centered()
-        .opengl()
-        .allow_highdpi()
-        .build()
-        .unwrap();
+    let square_pixels = !matches.is_present("NOSQUARE");
+    let scaling = if matches.is_present("INTEGERSCALE") {
+        Scaling::Integer
+    } else {
+        Scaling::Fit
+    };
+    let scanlines = matches.is_present("SCANLINES");
+
+    let mut mode = machine.gpu().mode.clone();
+    let (initial_screen_width, initial_screen_height) = window_size(&mode, scale_factor, square_pixels);
+    let mut window_builder = video_subsys.window(&format!("dustbox - {}", filename), initial_screen_width, initial_screen_height);
+    window_builder.position_centered().opengl().allow_highdpi().resizable();
+    if matches.is_present("FULLSCREEN") {
+        window_builder.fullscreen_desktop();
+    }
+    let window = window_builder.build().unwrap();
 
     let mut canvas = window.into_canvas().present_vsync().build().unwrap();
     // println!("renderer: sdl2 \"{}\"", canvas.info().name);
@@ -91,9 +330,17 @@ fn main() {
     let mut frame_exec_sum = Duration::new(0, 0);
     let mut frame_render_sum = Duration::new(0, 0);
     let mut frame_sleep_sum = Duration::new(0, 0);
-    let mut last_video_mode = 0;
+    let mut last_video_mode = mode.mode;
 
-    let square_pixels = !matches.is_present("NOSQUARE");
+    let mut texture = texture_creator.create_texture_streaming(PixelFormatEnum::RGBA32, mode.swidth, mode.sheight).unwrap();
+
+    let mut paused = matches.is_present("PAUSED");
+
+    // when captured, the host cursor is hidden and only relative motion is sent to the machine
+    let sdl_mouse = sdl_context.mouse();
+    let mut mouse_captured = matches.is_present("CAPTUREMOUSE");
+    sdl_mouse.set_relative_mouse_mode(mouse_captured);
+    let _ = canvas.window_mut().set_title(&window_title(filename, &machine, paused, mouse_captured));
 
     let mut frame_num = 0;
     'main: loop {
@@ -107,9 +354,36 @@ fn main() {
                         // break 'main
                     }
 
-                    machine.keyboard_mut().add_keypress(keycode, modifier);
+                    if keycode == sdl2::keyboard::Keycode::Return && modifier.intersects(Mod::LALTMOD | Mod::RALTMOD) {
+                        toggle_fullscreen(&mut canvas, &mode, scale_factor, square_pixels);
+                        continue;
+                    }
+
+                    if keycode == sdl2::keyboard::Keycode::F9 && modifier.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) {
+                        mouse_captured = !mouse_captured;
+                        sdl_mouse.set_relative_mouse_mode(mouse_captured);
+                        let _ = canvas.window_mut().set_title(&window_title(filename, &machine, paused, mouse_captured));
+                        continue;
+                    }
+
+                    if speed_hotkey(&mut machine, &mut paused, keycode, modifier) {
+                        let _ = canvas.window_mut().set_title(&window_title(filename, &machine, paused, mouse_captured));
+                        continue;
+                    }
+
+                    if let Some(keycode) = Keycode::from_sdl(keycode) {
+                        machine.keyboard_mut().add_keypress(keycode, Modifiers::from_sdl(modifier));
+                    }
+                }
+                Event::MouseMotion {x, y, xrel, yrel, ..} => {
+                    if mouse_captured {
+                        machine.mouse_mut().move_relative(xrel, yrel);
+                    } else {
+                        let (x, y) = mouse_position(&canvas, &mode, scaling, square_pixels, x, y);
+                        machine.mouse_mut().set_position(x, y);
+                        machine.mouse_mut().add_mickeys(xrel, yrel);
+                    }
                 }
-                Event::MouseMotion {x, y, ..} => machine.mouse_mut().set_position(x, y),
                 Event::MouseButtonDown {mouse_btn, ..} => {
                     match mouse_btn {
                         sdl2::mouse::MouseButton::Left => machine.mouse_mut().set_button(MouseButton::Left, true),
@@ -137,48 +411,15 @@ fn main() {
 
         let locked_fps = 60;
 
-        let frame = machine.gpu().render_frame(&machine.mmu);
-
-        let mut texture = texture_creator.create_texture_streaming(PixelFormatEnum::RGB24, frame.mode.swidth, frame.mode.sheight).unwrap();
-
         {
-            // resize window to current screen mode sizes
-            if frame.mode.mode != last_video_mode {
-                let (internal_scale_x, internal_scale_y) = if square_pixels {
-                    (scale_factor * frame.mode.scale_x, scale_factor * frame.mode.scale_y)
-                } else {
-                    (scale_factor, scale_factor)
-                };
-
-                // window size is the display size
-                let window_width = (frame.mode.swidth as f32 * internal_scale_x) as u32;
-                let window_height = (frame.mode.sheight as f32 * internal_scale_y) as u32;
-
-                println!("Resizing window for mode {:02x} to {}x{} pixels, {}x{} frame size, scale factor {}x, internal scale x:{}, y:{}",
-                    frame.mode.mode, window_width, window_height, frame.mode.swidth, frame.mode.sheight, scale_factor, internal_scale_x, internal_scale_y);
-
-                let window = canvas.window_mut();
-                window.set_size(window_width, window_height).unwrap();
-
-                // XXX logical size is needed for correct mouse coordinates without having to divide them by scale, but it gives black top+bottom bars on win10
-                let logical_w = (frame.mode.swidth as f32 * frame.mode.scale_x) as u32;
-                let logical_h = (frame.mode.sheight as f32 * frame.mode.scale_y) as u32;
-                canvas.set_logical_size(logical_w, logical_h).unwrap();
-
-                last_video_mode = frame.mode.mode;
-            }
-
-            // run some instructions and progress scanline until screen is drawn
-            for _ in 0..frame.mode.swidth {
-                // XXX calculate the number cycles to execute for (1/30th sec ) / scanlines
-                // XXX measure by instruction cycles
-                let num_instr = 400;
-                machine.execute_instructions(num_instr);
+            // run the cycles of one host frame at the current speed. the scanline is progressed by the machine
+            let turbo = machine.governor.is_unlimited();
+            if !paused {
+                machine.execute_for(Duration::new(0, 1_000_000_000 / locked_fps));
                 if machine.cpu.fatal_error {
                     println!("cpu fatal error occured. stopping execution after {} instructions executed", machine.cpu.instruction_count);
                     break 'main;
                 }
-                machine.gpu_mut().progress_scanline();
             }
             let exec_time = frame_start.elapsed().unwrap();
 
@@ -186,31 +427,32 @@ fn main() {
 
             let render_start = SystemTime::now();
 
-            let mut x: usize = 0;
-            let mut y: usize = 0;
-
-            texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
-                for pix in frame.data {
-                    if let dustbox::gpu::ColorSpace::RGB(r, g, b) = pix {
-                        let offset = y*pitch + x*3;
-                        buffer[offset] = r;
-                        buffer[offset + 1] = g;
-                        buffer[offset + 2] = b;
-                        x += 1;
-                        if x >= frame.mode.swidth as usize {
-                            x = 0;
-                            y += 1;
-                        }
-                    }
+            // only the scanlines changed since last frame are rendered, directly into the texture
+            let changed = machine.changed_scanlines();
+            if machine.gpu().mode.mode != last_video_mode {
+                mode = machine.gpu().mode.clone();
+
+                // resize window to current screen mode sizes, the frame is scaled to fit in fullscreen
+                if canvas.window().fullscreen_state() == FullscreenType::Off {
+                    let (window_width, window_height) = window_size(&mode, scale_factor, square_pixels);
+                    println!("Resizing window for mode {:02x} to {}x{} pixels, {}x{} frame size, scale factor {}x",
+                        mode.mode, window_width, window_height, mode.swidth, mode.sheight, scale_factor);
+                    canvas.window_mut().set_size(window_width, window_height).unwrap();
                 }
-            }).unwrap();
+
+                texture = texture_creator.create_texture_streaming(PixelFormatEnum::RGBA32, mode.swidth, mode.sheight).unwrap();
+                last_video_mode = mode.mode;
+            }
+            upload_scanlines(&mut texture, &machine, &changed);
 
             let render_time = render_start.elapsed().unwrap();
             frame_render_sum += render_time;
 
             // sleep for 1/60:th of a second, minus time it took to get here
             let mut sleep_time = Duration::new(0, 1_000_000_000 / locked_fps);
-            if sleep_time >= exec_time {
+            if turbo {
+                sleep_time = Duration::new(0, 0);
+            } else if sleep_time >= exec_time {
                 sleep_time -= exec_time;
             } else {
                 println!("WARN: exec is slow {:#?}", exec_time);
@@ -256,7 +498,156 @@ fn main() {
             frame_sleep_sum += sleep_time;
         }
 
-        canvas.copy(&texture, None, None).unwrap();
+        let viewport = window_viewport(&canvas, &mode, scaling, square_pixels);
+        canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
+        canvas.clear();
+        canvas.copy(&texture, None, Some(Rect::new(viewport.x, viewport.y, viewport.width, viewport.height))).unwrap();
+        if scanlines {
+            draw_scanlines(&mut canvas, &viewport, mode.sheight);
+        }
         canvas.present();
     }
+
+    if matches.is_present("RECORDVIDEO") {
+        if let Err(e) = machine.stop_video_capture() {
+            println!("error writing video: {}", e);
+        }
+    }
+
+    if let Some(coveragename) = matches.value_of("COVERAGE") {
+        match machine.save_coverage(coveragename) {
+            Ok(()) => println!("Coverage written to {}", coveragename),
+            Err(e) => println!("error writing coverage: {}", e),
+        }
+    }
+
+    if let Some(dumps) = matches.values_of("DUMPMEMORY") {
+        for dump in dumps {
+            if let Err(e) = dump_memory(&machine, dump) {
+                println!("error dumping memory: {}", e);
+            }
+        }
+    }
+
+    if let Some(videoname) = matches.value_of("DUMPVIDEO") {
+        match fs::write(videoname, machine.dump_video_memory()) {
+            Ok(()) => println!("Video memory written to {}", videoname),
+            Err(e) => println!("error writing video memory: {}", e),
+        }
+    }
+}
+
+/// writes the memory range of `arg`, such as "085F:0100-085F:0200=code.bin", to the named file
+fn dump_memory(machine: &Machine, arg: &str) -> Result<(), String> {
+    let pos = arg.find('=').ok_or_else(|| format!("expected range=file, got {}", arg))?;
+    let range = parse_range(&arg[..pos])?;
+    let filename = &arg[pos + 1..];
+    let len = range.end.saturating_sub(range.start) as usize;
+    let data = machine.dump_memory((range.start >> 4) as u16, (range.start & 0xF) as u16, len);
+    fs::write(filename, data).map_err(|e| format!("{}: {}", filename, e))?;
+    println!("Memory {} written to {}", &arg[..pos], filename);
+    Ok(())
+}
+
+/// handles the pause, turbo and speed hotkeys. returns false if `keycode` is not a hotkey
+fn speed_hotkey(machine: &mut Machine, paused: &mut bool, keycode: sdl2::keyboard::Keycode, modifier: Mod) -> bool {
+    use sdl2::keyboard::Keycode;
+    let ctrl = modifier.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+    match keycode {
+        // Ctrl+Pause is Ctrl-Break, passed to the machine
+        Keycode::Pause if !ctrl => *paused = !*paused,
+        Keycode::F10 if ctrl => machine.set_speed_percent(100),
+        Keycode::F11 if ctrl => {
+            let percent = machine.governor.speed_percent().saturating_sub(SPEED_STEP);
+            machine.set_speed_percent(percent.max(SPEED_STEP));
+        }
+        Keycode::F12 if ctrl => {
+            let percent = machine.governor.speed_percent() + SPEED_STEP;
+            machine.set_speed_percent(percent);
+        }
+        Keycode::F12 => {
+            let turbo = !machine.governor.is_unlimited();
+            machine.set_unlimited(turbo);
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// switches between windowed and fullscreen desktop mode
+fn toggle_fullscreen(canvas: &mut WindowCanvas, mode: &VideoModeBlock, scale_factor: f32, square_pixels: bool) {
+    let window = canvas.window_mut();
+    if window.fullscreen_state() == FullscreenType::Off {
+        let _ = window.set_fullscreen(FullscreenType::Desktop);
+    } else {
+        let _ = window.set_fullscreen(FullscreenType::Off);
+        let (width, height) = window_size(mode, scale_factor, square_pixels);
+        let _ = window.set_size(width, height);
+    }
+}
+
+/// returns the area of the canvas the frame is drawn to, in output pixels
+fn window_viewport(canvas: &WindowCanvas, mode: &VideoModeBlock, scaling: Scaling, square_pixels: bool) -> Viewport {
+    let (width, height) = canvas.output_size().unwrap();
+    Viewport::for_mode(mode, width, height, scaling, square_pixels)
+}
+
+/// maps a mouse position in window coordinates to the logical frame coordinates expected by the mouse driver
+fn mouse_position(canvas: &WindowCanvas, mode: &VideoModeBlock, scaling: Scaling, square_pixels: bool, x: i32, y: i32) -> (i32, i32) {
+    // with high dpi, the output has more pixels than the window has points
+    let (output_w, output_h) = canvas.output_size().unwrap();
+    let (window_w, window_h) = canvas.window().size();
+    let x = (i64::from(x) * i64::from(output_w) / i64::from(window_w.max(1))) as i32;
+    let y = (i64::from(y) * i64::from(output_h) / i64::from(window_h.max(1))) as i32;
+    window_viewport(canvas, mode, scaling, square_pixels).logical_position(x, y)
+}
+
+/// darkens the lower half of each frame scanline, when it covers at least two display lines
+fn draw_scanlines(canvas: &mut WindowCanvas, viewport: &Viewport, lines: u32) {
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(pixels::Color::RGBA(0, 0, 0, SCANLINE_ALPHA));
+    let rects: Vec<Rect> = (0..lines)
+        .map(|line| viewport.scanline_rows(line, lines))
+        .filter(|&(_, rows)| rows >= 2)
+        .map(|(y, rows)| Rect::new(viewport.x, y + (rows / 2) as i32, viewport.width, rows - rows / 2))
+        .collect();
+    let _ = canvas.fill_rects(&rects);
+    canvas.set_blend_mode(BlendMode::None);
+}
+
+/// returns the window title, showing the speed when not authentic and if the mouse is captured
+fn window_title(filename: &str, machine: &Machine, paused: bool, mouse_captured: bool) -> String {
+    let mut title = if paused {
+        format!("dustbox - {} [paused]", filename)
+    } else if machine.governor.is_unlimited() {
+        format!("dustbox - {} [turbo]", filename)
+    } else if machine.governor.speed_percent() != 100 {
+        format!("dustbox - {} [{}%]", filename, machine.governor.speed_percent())
+    } else {
+        format!("dustbox - {}", filename)
+    };
+    if mouse_captured {
+        title.push_str(" [mouse captured, Ctrl+F9 releases]");
+    }
+    title
+}
+
+/// renders the scanlines `lines` (in ascending order) of the current frame into `texture`
+fn upload_scanlines(texture: &mut Texture, machine: &Machine, lines: &[u32]) {
+    let gpu = machine.gpu();
+    let mut i = 0;
+    while i < lines.len() {
+        // lock consecutive scanlines together
+        let first = lines[i];
+        let mut count = 1;
+        while i + count < lines.len() && lines[i + count] == first + count as u32 {
+            count += 1;
+        }
+        i += count;
+
+        let rect = Rect::new(0, first as i32, gpu.mode.swidth, count as u32);
+        texture.with_lock(Some(rect), |buffer: &mut [u8], pitch: usize| {
+            gpu.render_scanlines_into(&machine.mmu, first..first + count as u32, buffer, pitch);
+        }).unwrap();
+    }
 }
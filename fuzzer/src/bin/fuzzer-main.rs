@@ -7,12 +7,13 @@ use rand_xorshift::XorShiftRng;
 
 use dustbox::cpu::Op;
 use fuzzer::fuzzer::{fuzz_ops, FuzzConfig, CodeRunner};
+use fuzzer::roundtrip::fuzz_round_trip;
 
 fn main() {
     let matches = App::new("dustbox-fuzzer")
         .version("0.1")
         .arg(Arg::with_name("RUNNER")
-            .help("Code runner to use (supersafe, vmrun, dosbox-x)")
+            .help("Code runner to use (supersafe, vmrun, dosbox-x), or roundtrip to check the encoder against the decoder")
             .required(true)
             .index(1)
             .long("runner"))
@@ -20,6 +21,10 @@ fn main() {
             .help("Number of mutations per instruction")
             .takes_value(true)
             .long("mutations"))
+        .arg(Arg::with_name("ITERATIONS")
+            .help("Number of random instructions to check (roundtrip)")
+            .takes_value(true)
+            .long("iterations"))
         .arg(Arg::with_name("HOST")
             .help("Remote host (supersafe)")
             .takes_value(true)
@@ -44,6 +49,12 @@ fn main() {
 
     let ops_to_fuzz = vec!(
         Op::Shl16,
+        Op::Bsf16, Op::Bsf32, Op::Bsr16, Op::Bsr32,
+        Op::Bt16, Op::Bt32, Op::Bts16, Op::Bts32, Op::Btr16, Op::Btr32, Op::Btc16, Op::Btc32,
+        Op::Seto, Op::Setno, Op::Setc, Op::Setnc, Op::Setz, Op::Setnz, Op::Setna, Op::Seta,
+        Op::Sets, Op::Setns, Op::Setpe, Op::Setpo, Op::Setl, Op::Setnl, Op::Setng, Op::Setg,
+        Op::Push32, Op::Pusha16, Op::Pushad32, Op::Popa16, Op::Popad32,
+        // NOTE: far call, jmp, retf and iret need absolute targets, they are covered by the machine tests
 
         //Op::Rol32, // Op::Rcl32,  // XXX not implemented in dustbox
         //Op::Ror32, // XXX carry flag diff vs WinXP
@@ -79,7 +90,6 @@ fn main() {
         Op::Movsx16, Op::Movsx32, Op::Movzx16, Op::Movzx32,
         Op::Shr8, Op::Sar8, Op::Sar16, // OK !
         //Op::Div8, Op::Div16, Op::Idiv8, Op::Idiv16, Op::Idiv32, // seems correct. NOTE that winxp crashes with "Divide overflow" on some input
-        Op::Bt, Op::Bsf,
         Op::Aaa, Op::Aad, Op::Aam, Op::Aas, Op::Daa, Op::Das,
 
         Op::Push16, // NOTE: also tests Op::Pop16
@@ -113,13 +123,6 @@ fn main() {
         password: matches.value_of("PASSWORD").unwrap_or("vmware").to_string(),
     };
 
-    let runner = match matches.value_of("RUNNER").unwrap() {
-        "supersafe" => CodeRunner::SuperSafe,
-        "dosbox-x"  => CodeRunner::DosboxX,
-        "vmrun"     => CodeRunner::Vmrun,
-        _ => panic!("unrecognized runner"),
-    };
-
     // seed prng if argument was given
     let mut rng: XorShiftRng;
     let seed_value = if matches.is_present("SEED") {
@@ -131,5 +134,18 @@ fn main() {
     rng = XorShiftRng::seed_from_u64(seed_value);
     println!("rng seed = {}", seed_value);
 
+    if matches.value_of("RUNNER").unwrap() == "roundtrip" {
+        let iterations = value_t!(matches, "ITERATIONS", usize).unwrap_or(100_000);
+        let failures = fuzz_round_trip(iterations, &mut rng);
+        std::process::exit(if failures > 0 { 1 } else { 0 });
+    }
+
+    let runner = match matches.value_of("RUNNER").unwrap() {
+        "supersafe" => CodeRunner::SuperSafe,
+        "dosbox-x"  => CodeRunner::DosboxX,
+        "vmrun"     => CodeRunner::Vmrun,
+        _ => panic!("unrecognized runner"),
+    };
+
     fuzz_ops(&runner, ops_to_fuzz, &cfg, &mut rng);
 }
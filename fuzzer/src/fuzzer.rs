@@ -217,13 +217,17 @@ impl AffectedFlags {
     pub fn for_op(op: &Op) -> u16 {
         match *op {
             Op::Nop | Op::Mov8 | Op::Mov16 | Op::Mov32 | Op::Movzx16 | Op::Movzx32 | Op::Movsx16 | Op::Movsx32 |
-            Op::Push16 | Op::Pop16 | Op::Not8 | Op::Not16 | Op::Not32 |
+            Op::Push16 | Op::Pop16 | Op::Push32 | Op::Pop32 |
+            Op::Pusha16 | Op::Popa16 | Op::Pushad32 | Op::Popad32 | Op::Not8 | Op::Not16 | Op::Not32 |
             Op::Div8 | Op::Div16 | Op::Div32 | Op::Idiv8 | Op::Idiv16 | Op::Idiv32 | Op::Xchg8 | Op::Xchg16 |
             Op::Salc | Op::Cbw | Op::Cwd16 | Op::Lahf | Op::Lea16 | Op::Xlatb |
-            Op::Loop | Op::Loope | Op::Loopne =>
+            Op::Loop | Op::Loope | Op::Loopne |
+            Op::Seto | Op::Setno | Op::Setc | Op::Setnc | Op::Setz | Op::Setnz | Op::Setna | Op::Seta |
+            Op::Sets | Op::Setns | Op::Setpe | Op::Setpo | Op::Setl | Op::Setnl | Op::Setng | Op::Setg =>
                 AffectedFlags{s:0, z:0, p:0, c:0, a:0, o:0, d:0, i:0}.mask(), // none
 
-            Op::Bt | Op::Clc | Op::Cmc | Op::Stc =>
+            Op::Bt16 | Op::Bt32 | Op::Bts16 | Op::Bts32 | Op::Btr16 | Op::Btr32 | Op::Btc16 | Op::Btc32 |
+            Op::Clc | Op::Cmc | Op::Stc =>
                 AffectedFlags{c:1, a:0, o:0, s:0, z:0, p:0, d:0, i:0}.mask(), // C
 
             Op::Cld | Op::Std =>
@@ -232,7 +236,7 @@ impl AffectedFlags {
             Op::Cli | Op::Sti =>
                 AffectedFlags{i:1, d:0, c:0, a:0, o:0, s:0, z:0, p:0}.mask(), // I
 
-            Op::Bsf =>
+            Op::Bsf16 | Op::Bsf32 | Op::Bsr16 | Op::Bsr32 =>
                 AffectedFlags{s:0, z:1, p:0, c:0, a:0, o:0, d:0, i:0}.mask(), // Z
 
             Op::Aaa | Op::Aas =>
@@ -539,6 +543,52 @@ fn get_mutator_snippet<RNG: Rng + ?Sized>(op: &Op, rng: &mut RNG) -> Vec<Instruc
             Instruction::new1(op.clone(), Parameter::Imm16(rng.gen())),
             Instruction::new1(Op::Pop16, Parameter::Reg16(R::BX)),
         )}
+        Op::Push32 => { vec!(
+            // push dword es, the upper word is pushed as zero.
+            // the stack dword is zeroed first, as later cpus only write the selector word
+            Instruction::new1(Op::Push16, Parameter::Imm16(0)),
+            Instruction::new1(Op::Push16, Parameter::Imm16(0)),
+            Instruction::new2(Op::Add16, Parameter::Reg16(R::SP), Parameter::Imm16(4)),
+            Instruction::new2(Op::Mov16, Parameter::Reg16(R::AX), Parameter::Imm16(rng.gen())),
+            Instruction::new2(Op::Mov16, Parameter::SReg16(R::ES), Parameter::Reg16(R::AX)),
+            Instruction::new1(op.clone(), Parameter::SReg16(R::ES)),
+            Instruction::new1(Op::Pop16, Parameter::Reg16(R::BX)),
+            Instruction::new1(Op::Pop16, Parameter::Reg16(R::CX)),
+        )}
+        Op::Pusha16 => { vec!(
+            // pusha, skip di, si, bp and sp, then pop the registers in another order
+            Instruction::new2(Op::Mov16, Parameter::Reg16(R::AX), Parameter::Imm16(rng.gen())),
+            Instruction::new2(Op::Mov16, Parameter::Reg16(R::BX), Parameter::Imm16(rng.gen())),
+            Instruction::new2(Op::Mov16, Parameter::Reg16(R::CX), Parameter::Imm16(rng.gen())),
+            Instruction::new2(Op::Mov16, Parameter::Reg16(R::DX), Parameter::Imm16(rng.gen())),
+            Instruction::new(op.clone()),
+            Instruction::new2(Op::Add16, Parameter::Reg16(R::SP), Parameter::Imm16(8)),
+            Instruction::new1(Op::Pop16, Parameter::Reg16(R::AX)),
+            Instruction::new1(Op::Pop16, Parameter::Reg16(R::BX)),
+            Instruction::new1(Op::Pop16, Parameter::Reg16(R::CX)),
+            Instruction::new1(Op::Pop16, Parameter::Reg16(R::DX)),
+        )}
+        Op::Pushad32 => { vec!(
+            Instruction::new2(Op::Mov32, Parameter::Reg32(R::EAX), Parameter::Imm32(rng.gen())),
+            Instruction::new2(Op::Mov32, Parameter::Reg32(R::EBX), Parameter::Imm32(rng.gen())),
+            Instruction::new2(Op::Mov32, Parameter::Reg32(R::ECX), Parameter::Imm32(rng.gen())),
+            Instruction::new2(Op::Mov32, Parameter::Reg32(R::EDX), Parameter::Imm32(rng.gen())),
+            Instruction::new(op.clone()),
+            Instruction::new2(Op::Add16, Parameter::Reg16(R::SP), Parameter::Imm16(16)),
+            Instruction::new1(Op::Pop32, Parameter::Reg32(R::EAX)),
+            Instruction::new1(Op::Pop32, Parameter::Reg32(R::EBX)),
+            Instruction::new1(Op::Pop32, Parameter::Reg32(R::ECX)),
+            Instruction::new1(Op::Pop32, Parameter::Reg32(R::EDX)),
+        )}
+        Op::Popa16 | Op::Popad32 => {
+            // popa, from random words on the stack
+            let words = if *op == Op::Popa16 { 8 } else { 16 };
+            let mut v: Vec<Instruction> = (0..words)
+                .map(|_| Instruction::new1(Op::Push16, Parameter::Imm16(rng.gen())))
+                .collect();
+            v.push(Instruction::new(op.clone()));
+            v
+        }
         Op::Mov8 => { vec!(
             Instruction::new2(op.clone(), Parameter::Reg8(R::AL), Parameter::Imm8(rng.gen())),
         )}
@@ -583,7 +633,7 @@ fn get_mutator_snippet<RNG: Rng + ?Sized>(op: &Op, rng: &mut RNG) -> Vec<Instruc
             Instruction::new2(Op::Mov32, Parameter::Reg32(R::EAX), Parameter::Imm32(rng.gen())),
             Instruction::new2(op.clone(), Parameter::Reg32(R::EAX), Parameter::Imm8(rng.gen())),
         )}
-        Op::Bt | Op::Bsf | Op::Xchg16 => { vec!(
+        Op::Bt16 | Op::Bts16 | Op::Btr16 | Op::Btc16 | Op::Bsf16 | Op::Bsr16 | Op::Xchg16 => { vec!(
             // bsf r16, r/m16
             // bt r/m16, r16
             // xchg r/m16, r16
@@ -591,6 +641,21 @@ fn get_mutator_snippet<RNG: Rng + ?Sized>(op: &Op, rng: &mut RNG) -> Vec<Instruc
             Instruction::new2(Op::Mov16, Parameter::Reg16(R::BX), Parameter::Imm16(rng.gen())),
             Instruction::new2(op.clone(), Parameter::Reg16(R::AX), Parameter::Reg16(R::BX)),
         )}
+        Op::Bt32 | Op::Bts32 | Op::Btr32 | Op::Btc32 | Op::Bsf32 | Op::Bsr32 => { vec!(
+            // bsf r32, r/m32
+            // bt r/m32, r32
+            Instruction::new2(Op::Mov32, Parameter::Reg32(R::EAX), Parameter::Imm32(rng.gen())),
+            Instruction::new2(Op::Mov32, Parameter::Reg32(R::EBX), Parameter::Imm32(rng.gen())),
+            Instruction::new2(op.clone(), Parameter::Reg32(R::EAX), Parameter::Reg32(R::EBX)),
+        )}
+        Op::Seto | Op::Setno | Op::Setc | Op::Setnc | Op::Setz | Op::Setnz | Op::Setna | Op::Seta |
+        Op::Sets | Op::Setns | Op::Setpe | Op::Setpo | Op::Setl | Op::Setnl | Op::Setng | Op::Setg => { vec!(
+            // setcc r/m8, from random flags
+            Instruction::new1(Op::Push16, Parameter::Imm16(rng.gen())),
+            Instruction::new(Op::Popf),
+            Instruction::new2(Op::Mov8, Parameter::Reg8(R::AL), Parameter::Imm8(rng.gen())),
+            Instruction::new1(op.clone(), Parameter::Reg8(R::AL)),
+        )}
         Op::Mul8 | Op::Imul8 => { vec!(
             // mul r/m8      ax = al * r/m
             // imul r/m8     ax = al * r/m
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+
+use rand::Rng;
+
+use dustbox::cpu::{Decoder, Encoder, Instruction, Op};
+use dustbox::memory::MMU;
+
+/// max length of a x86 instruction
+const MAX_INSTRUCTION_LENGTH: usize = 15;
+
+/// segment and offset where the random bytes are decoded
+const SEGMENT: u16 = 0x1000;
+const OFFSET: u16 = 0x0100;
+
+/// result of a round-trip
+pub enum RoundTrip {
+    /// decode, encode and decode again gave the same instruction
+    Ok,
+
+    /// the bytes don't decode to a valid instruction
+    Invalid,
+
+    /// the encoder can't encode the decoded instruction
+    Unencodable(Op),
+
+    /// the decoder and encoder disagree
+    Failed(String),
+}
+
+/// decodes, encodes and decodes random instruction bytes, without an external code runner
+pub struct RoundTripper {
+    mmu: MMU,
+    decoder: Decoder,
+    encoder: Encoder,
+}
+
+impl Default for RoundTripper {
+    fn default() -> Self {
+        RoundTripper {
+            mmu: MMU::default(),
+            decoder: Decoder::default(),
+            encoder: Encoder::new(),
+        }
+    }
+}
+
+impl RoundTripper {
+    fn decode(&mut self, data: &[u8]) -> Instruction {
+        self.mmu.write(SEGMENT, OFFSET, data);
+        self.decoder.get_instruction(&mut self.mmu, SEGMENT, OFFSET)
+    }
+
+    /// decodes `data` and encodes the instruction back. the encoding must decode to the same
+    /// instruction text and length, and encode to the same bytes again
+    pub fn round_trip(&mut self, data: &[u8]) -> RoundTrip {
+        let first = self.decode(data);
+        match first.command {
+            Op::Invalid(_, _) | Op::Uninitialized => return RoundTrip::Invalid,
+            _ => {}
+        }
+        let encoded = match self.encoder.encode_at(&first, OFFSET) {
+            Ok(encoded) => encoded,
+            Err(_) => return RoundTrip::Unencodable(first.command),
+        };
+
+        // pad with the original bytes, so a too short encoding decodes to something
+        let mut padded = encoded.clone();
+        padded.extend_from_slice(&data[encoded.len().min(data.len())..]);
+        let second = self.decode(&padded);
+        let (first_text, second_text) = (format!("{}", first), format!("{}", second));
+        if first_text != second_text {
+            return RoundTrip::Failed(format!("{:02X?} decoded as \"{}\", encoded as {:02X?} decoded as \"{}\"",
+                &data[..first.length as usize], first_text, encoded, second_text));
+        }
+        if second.length as usize != encoded.len() {
+            return RoundTrip::Failed(format!("\"{}\" encoded as {:02X?} decoded with length {}",
+                first_text, encoded, second.length));
+        }
+        match self.encoder.encode_at(&second, OFFSET) {
+            Ok(reencoded) if reencoded == encoded => RoundTrip::Ok,
+            Ok(reencoded) => RoundTrip::Failed(format!("\"{}\" encoded as {:02X?}, then as {:02X?}",
+                first_text, encoded, reencoded)),
+            Err(why) => RoundTrip::Failed(format!("\"{}\" encoded as {:02X?}, then failed: {}",
+                first_text, encoded, why)),
+        }
+    }
+}
+
+/// round-trips `iterations` random instructions through the decoder and encoder.
+/// returns the number of failures
+pub fn fuzz_round_trip<RNG: Rng + ?Sized>(iterations: usize, rng: &mut RNG) -> usize {
+    let mut tripper = RoundTripper::default();
+    let mut ok = 0;
+    let mut invalid = 0;
+    let mut failures = 0;
+    let mut unencodable: HashMap<String, usize> = HashMap::new();
+    let mut data = [0u8; MAX_INSTRUCTION_LENGTH];
+
+    // panics in the decoder or encoder are reported as failures
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    for _ in 0..iterations {
+        rng.fill(&mut data);
+        let res = match panic::catch_unwind(AssertUnwindSafe(|| tripper.round_trip(&data))) {
+            Ok(res) => res,
+            Err(e) => {
+                let msg = e.downcast_ref::<String>().cloned()
+                    .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+                    .unwrap_or_default();
+                tripper = RoundTripper::default();
+                RoundTrip::Failed(format!("{:02X?} panicked: {}", data, msg))
+            }
+        };
+        match res {
+            RoundTrip::Ok => ok += 1,
+            RoundTrip::Invalid => invalid += 1,
+            RoundTrip::Unencodable(op) => *unencodable.entry(format!("{:?}", op)).or_insert(0) += 1,
+            RoundTrip::Failed(msg) => {
+                println!("FAIL {}", msg);
+                failures += 1;
+            }
+        }
+    }
+    panic::set_hook(hook);
+
+    let mut unencodable: Vec<(String, usize)> = unencodable.into_iter().collect();
+    unencodable.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+    for (op, count) in &unencodable {
+        println!("not encodable: {} x {}", op, count);
+    }
+    println!("{} ok, {} failed, {} invalid, {} not encodable ({} ops)",
+        ok, failures, invalid, unencodable.iter().map(|(_, n)| n).sum::<usize>(), unencodable.len());
+    failures
+}
@@ -1,24 +1,123 @@
-use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
 
+#[macro_use]
 extern crate clap;
 use clap::{Arg, App};
 
 use colored::*;
+use rayon::prelude::*;
 use tera::{Tera, Context};
 use serde::{Serialize, Deserialize};
 
-use dustbox::machine::Machine;
+use dustbox::machine::{Machine, MachineConfig};
+use harness::golden::{compare_images, diff_image, ImageDiff};
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 struct SetDocument {
     name: String,
     default_instructions: usize,
     root: String,
-    set: Vec<String>,
+
+    /// perceptual color difference from 0.0 to 1.0 before a pixel counts as changed
+    #[serde(default = "default_color_threshold")]
+    color_threshold: f64,
+
+    /// number of changed pixels allowed before a frame is a regression
+    #[serde(default)]
+    max_diff_pixels: usize,
+
+    set: Vec<SetEntry>,
+}
+
+fn default_color_threshold() -> f64 {
+    0.1
+}
+
+/// a program in the set, either a table with expectations or a filename.
+/// the filename may be followed by instruction counts to capture frames at, such as "demo.com,500000|2000000"
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+enum SetEntry {
+    File(String),
+    Program(ProgramEntry),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct ProgramEntry {
+    file: String,
+
+    /// overrides `default_instructions` of the set
+    instructions: Option<usize>,
+
+    /// instruction counts to capture frames at, overrides `instructions`
+    #[serde(default)]
+    captures: Vec<usize>,
+
+    /// expected DOS return code
+    exit_code: Option<u8>,
+
+    /// text expected in the console output
+    output: Option<String>,
+
+    /// overrides `max_diff_pixels` of the set
+    max_diff_pixels: Option<usize>,
+}
+
+impl SetEntry {
+    fn program(&self) -> ProgramEntry {
+        match self {
+            SetEntry::File(file) => match file.find(',') {
+                Some(pos) => ProgramEntry {
+                    file: file[..pos].to_owned(),
+                    captures: file[pos + 1..].split('|')
+                        .map(|n| n.trim().parse().unwrap_or_else(|e| panic!("invalid instruction count in {}: {}", file, e)))
+                        .collect(),
+                    ..ProgramEntry::default()
+                },
+                None => ProgramEntry { file: file.clone(), ..ProgramEntry::default() },
+            },
+            SetEntry::Program(program) => program.clone(),
+        }
+    }
+}
+
+impl ProgramEntry {
+    /// returns the instruction counts to capture frames at, in ascending order
+    fn capture_points(&self, default_instructions: usize) -> Vec<usize> {
+        let mut points = if self.captures.is_empty() {
+            vec![self.instructions.unwrap_or(default_instructions)]
+        } else {
+            self.captures.clone()
+        };
+        points.sort_unstable();
+        points.dedup();
+        points
+    }
+}
+
+/// a frame captured after a number of instructions
+#[derive(Debug, Serialize)]
+struct FrameResult {
+    instructions: usize,
+
+    /// rendered frame, relative to docs/
+    image: String,
+}
+
+/// outcome of running a program of the set
+#[derive(Debug, Serialize)]
+struct ProgramResult {
+    file: String,
+
+    frames: Vec<FrameResult>,
+
+    /// "ok", "new" (no golden image) or "regression"
+    status: &'static str,
+
+    failures: Vec<String>,
 }
 
 fn main() {
@@ -28,6 +127,25 @@ fn main() {
             .help("Sets the test harness rom set file to use")
             .required(true)
             .index(1))
+        .arg(Arg::with_name("CONFIG")
+            .help("Reads the machine configuration from a toml file")
+            .takes_value(true)
+            .long("config"))
+        .arg(Arg::with_name("AUDIO")
+            .help("Sound hardware: none, speaker, adlib or sb16. only none is supported")
+            .takes_value(true)
+            .long("audio"))
+        .arg(Arg::with_name("KEYBOARDLAYOUT")
+            .help("Keyboard layout, such as us or de. only us is supported")
+            .takes_value(true)
+            .long("keyboard-layout"))
+        .arg(Arg::with_name("UPDATEGOLDEN")
+            .help("Saves the rendered frames as the new golden images")
+            .long("update-golden"))
+        .arg(Arg::with_name("JOBS")
+            .help("Number of programs to run in parallel (default: number of cpus)")
+            .takes_value(true)
+            .long("jobs"))
         .get_matches();
 
     let filename = matches.value_of("INPUT").unwrap();
@@ -35,51 +153,42 @@ fn main() {
     let data = fs::read_to_string(filename).expect("Unable to read file");
     let set: SetDocument = serde_yaml::from_str(&data).unwrap();
 
-    run_and_save_video_frames(&set);
-}
-
-fn run_and_save_video_frames(set: &SetDocument) {
-
-    let mut out_images = vec![];
-
-    for bin in &set.set {
-        println!("{}: {}", set.name.white(), bin.yellow());
-
-        let mut machine = Machine::deterministic();
-        let bin_path = format!("{}{}", set.root, bin);
-
-        if let Some(e) = machine.load_executable_file(&bin_path) {
-            panic!("error {}", e);
-        };
+    let mut config = match matches.value_of("CONFIG") {
+        Some(name) => MachineConfig::from_file(name).unwrap_or_else(|e| panic!("error reading {}: {}", name, e)),
+        None => MachineConfig::default(),
+    };
+    if let Some(audio) = matches.value_of("AUDIO") {
+        config.audio = audio.parse().unwrap_or_else(|e| panic!("error {}", e));
+        config.audio.validate().unwrap_or_else(|e| panic!("error {}", e));
+    }
+    if let Some(layout) = matches.value_of("KEYBOARDLAYOUT") {
+        config.keyboard_layout = layout.parse().unwrap_or_else(|e| panic!("error {}", e));
+        config.keyboard_layout.validate().unwrap_or_else(|e| panic!("error {}", e));
+    }
 
-        // XXX allow per-rom override + more properties on a rom basis
-        machine.execute_instructions(set.default_instructions);
+    if matches.is_present("JOBS") {
+        let jobs = value_t!(matches, "JOBS", usize).unwrap();
+        rayon::ThreadPoolBuilder::new().num_threads(jobs).build_global().unwrap();
+    }
 
-        if !Path::new(&format!("docs/render/{}", set.name)).exists() {
-            if let Err(e) = fs::create_dir(&format!("docs/render/{}", set.name)) {
-                panic!("create_dir failed {}", e);
-            }
-        }
+    let results = run_and_save_video_frames(&set, &config, matches.is_present("UPDATEGOLDEN"));
+    if print_summary(&set, &results) > 0 {
+        std::process::exit(1);
+    }
+}
 
-        let rel_path = Path::new(&bin);
-        let stem = rel_path.file_stem().unwrap_or_else(|| OsStr::new(""));
-        let mut filename = OsString::new(); // XXX base on dirname
-        let outname = &format!("render/{}/{:02x}_", set.name, machine.gpu_mut().mode.mode);
-        filename.push(format!("docs/{}", outname));
-        filename.push(stem.to_os_string());
-        filename.push(".png");
-
-        if write_video_frame_to_disk(&mut machine, filename.to_str().unwrap()) {
-            let mut pub_filename = String::new();
-            pub_filename.push_str(&outname);
-            pub_filename.push_str(stem.to_str().unwrap());
-            pub_filename.push_str(".png");
-            out_images.push(pub_filename);
-        } else {
-            println!("failed to write {} to disk", filename.to_str().unwrap());
+fn run_and_save_video_frames(set: &SetDocument, config: &MachineConfig, update_golden: bool) -> Vec<ProgramResult> {
+    for dir in &[format!("docs/render/{}", set.name), format!("harness/golden/{}", set.name)] {
+        if let Err(e) = fs::create_dir_all(dir) {
+            panic!("create_dir failed {}", e);
         }
     }
 
+    let mut results: Vec<ProgramResult> = set.set.par_iter()
+        .map(|entry| run_program(set, &entry.program(), config, update_golden))
+        .collect();
+    results.sort_by(|a, b| a.file.cmp(&b.file));
+
     let mut tera = match Tera::new("harness/templates/**/*") {
         Ok(t) => t,
         Err(e) => {
@@ -92,30 +201,147 @@ fn run_and_save_video_frames(set: &SetDocument) {
     tera.autoescape_on(vec![]);
 
     let mut context = Context::new();
-    out_images.sort();
-    context.insert("out_images", &out_images);
-    // add stuff to context
+    context.insert("results", &results);
     match tera.render("test_category.tpl.html", &context) {
         Ok(res) => {
             let mut f = File::create(format!("docs/{}.html", set.name)).expect("Unable to create file");
             f.write_all(res.as_bytes()).expect("Unable to write data");
         }
-        Err(why) => panic!(format!("{}", why)),
+        Err(why) => panic!("{}", why),
     }
+    results
 }
 
-// returns true on success
-fn write_video_frame_to_disk(machine: &mut Machine, pngfile: &str) -> bool {
-    let frame = machine.gpu().render_frame(&machine.mmu);
-    if frame.data.is_empty() {
-        println!("ERROR: no frame rendered");
-        return false;
+/// runs a program of the set, and checks the rendered frames and program state against expectations
+fn run_program(set: &SetDocument, program: &ProgramEntry, config: &MachineConfig, update_golden: bool) -> ProgramResult {
+    println!("{}: {}", set.name.white(), program.file.yellow());
+    let mut result = ProgramResult {
+        file: program.file.clone(),
+        frames: Vec::new(),
+        status: "ok",
+        failures: Vec::new(),
+    };
+
+    let mut machine = Machine::with_config(config.clone());
+    let bin_path = format!("{}{}", set.root, program.file);
+    if let Some(e) = machine.load_executable_file(&bin_path) {
+        result.status = "regression";
+        result.failures.push(format!("error loading {}: {}", bin_path, e));
+        return result;
+    };
+
+    let stem = Path::new(&program.file).file_stem().unwrap().to_str().unwrap();
+    let points = program.capture_points(set.default_instructions);
+    let mut executed = 0;
+    for &point in &points {
+        if !machine.cpu.fatal_error {
+            machine.execute_instructions(point - executed);
+        }
+        executed = point;
+
+        // a single capture keeps the plain program name
+        let name = if points.len() == 1 {
+            stem.to_owned()
+        } else {
+            format!("{}_{}", stem, point)
+        };
+        capture_frame(set, program, &mut machine, point, &name, update_golden, &mut result);
+    }
+
+    if let Some(expected) = program.exit_code {
+        if machine.exit_code() != Some(expected) {
+            result.failures.push(format!("exit code {:?}, expected {}", machine.exit_code(), expected));
+        }
     }
-    let img = frame.draw_image();
-    if let Err(why) = img.save(pngfile) {
-        println!("save err: {:?}", why);
-        return false;
+    if let Some(expected) = &program.output {
+        let output = machine.console_output();
+        if !output.contains(expected.as_str()) {
+            result.failures.push(format!("console output {:?} does not contain {:?}", output, expected));
+        }
+    }
+
+    if !result.failures.is_empty() {
+        result.status = "regression";
     }
-    true
+    result
 }
 
+/// saves the current frame as `name`, and compares it with the golden image
+fn capture_frame(set: &SetDocument, program: &ProgramEntry, machine: &mut Machine, instructions: usize, name: &str, update_golden: bool, result: &mut ProgramResult) {
+    let outname = format!("render/{}/{:02x}_{}", set.name, machine.gpu_mut().mode.mode, name);
+    let img = machine.screenshot();
+    if img.width() == 0 {
+        result.failures.push(format!("no frame rendered at {} instructions", instructions));
+        return;
+    }
+    if let Err(why) = img.save(format!("docs/{}.png", outname)) {
+        result.failures.push(format!("failed to write docs/{}.png: {:?}", outname, why));
+        return;
+    }
+    result.frames.push(FrameResult {
+        instructions,
+        image: format!("{}.png", outname),
+    });
+
+    let golden_path = format!("harness/golden/{}/{}.png", set.name, name);
+    if update_golden {
+        if let Err(why) = img.save(&golden_path) {
+            result.failures.push(format!("failed to write {}: {:?}", golden_path, why));
+        }
+        return;
+    }
+    if !Path::new(&golden_path).exists() {
+        result.status = "new";
+        return;
+    }
+    let golden = match image::open(&golden_path) {
+        Ok(golden) => golden.to_rgba(),
+        Err(why) => {
+            result.failures.push(format!("failed to read {}: {:?}", golden_path, why));
+            return;
+        }
+    };
+    let max_pixels = program.max_diff_pixels.unwrap_or(set.max_diff_pixels);
+    match compare_images(&golden, &img, set.color_threshold) {
+        ImageDiff::SizeMismatch { expected, actual } => {
+            result.failures.push(format!("frame at {} instructions is {}x{}, golden image is {}x{}",
+                instructions, actual.0, actual.1, expected.0, expected.1));
+        }
+        ImageDiff::Pixels(n) if n > max_pixels => {
+            result.failures.push(format!("{} pixels differ from the golden image at {} instructions", n, instructions));
+            if let Err(why) = diff_image(&golden, &img, set.color_threshold).save(format!("docs/{}.diff.png", outname)) {
+                println!("failed to write diff image: {:?}", why);
+            }
+        }
+        ImageDiff::Pixels(_) => {}
+    }
+}
+
+/// prints the outcome of each program, returns the number of regressions
+fn print_summary(set: &SetDocument, results: &[ProgramResult]) -> usize {
+    println!();
+    println!("{} results:", set.name.white());
+    let mut regressions = 0;
+    let mut new = 0;
+    for result in results {
+        match result.status {
+            "regression" => {
+                regressions += 1;
+                println!("{} {}: {}", "FAIL".red(), result.file, result.failures.join(", "));
+            }
+            "new" => {
+                new += 1;
+                println!("{}  {} (no golden image)", "NEW".yellow(), result.file);
+            }
+            _ => println!("{}   {}", "OK".green(), result.file),
+        }
+    }
+    let summary = format!("{} programs, {} ok, {} new, {} regressions",
+        results.len(), results.len() - regressions - new, new, regressions);
+    if regressions > 0 {
+        println!("{}", summary.red());
+    } else {
+        println!("{}", summary.green());
+    }
+    regressions
+}
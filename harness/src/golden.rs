@@ -0,0 +1,66 @@
+use image::{Rgba, RgbaImage};
+
+#[cfg(test)]
+#[path = "./golden_test.rs"]
+mod golden_test;
+
+/// largest possible YIQ distance between two colors
+const MAX_YIQ_DELTA: f64 = 35215.0;
+
+/// result of comparing a rendered frame with its golden image
+#[derive(Debug, PartialEq)]
+pub enum ImageDiff {
+    /// the images have different dimensions
+    SizeMismatch { expected: (u32, u32), actual: (u32, u32) },
+
+    /// number of pixels that differ more than the threshold
+    Pixels(usize),
+}
+
+impl ImageDiff {
+    /// returns true if the images differ in more than `max_pixels` pixels
+    pub fn exceeds(&self, max_pixels: usize) -> bool {
+        match self {
+            ImageDiff::SizeMismatch { .. } => true,
+            ImageDiff::Pixels(n) => *n > max_pixels,
+        }
+    }
+}
+
+/// compares `actual` with `expected`. a pixel differs if its perceptual color distance is
+/// above `threshold`, from 0.0 (any change) to 1.0 (black vs white)
+pub fn compare_images(expected: &RgbaImage, actual: &RgbaImage, threshold: f64) -> ImageDiff {
+    if expected.dimensions() != actual.dimensions() {
+        return ImageDiff::SizeMismatch { expected: expected.dimensions(), actual: actual.dimensions() };
+    }
+    let max_delta = threshold * threshold * MAX_YIQ_DELTA;
+    let n = expected.pixels().zip(actual.pixels())
+        .filter(|(a, b)| color_delta(a, b) > max_delta)
+        .count();
+    ImageDiff::Pixels(n)
+}
+
+/// returns a faded copy of `expected` with the differing pixels marked red
+pub fn diff_image(expected: &RgbaImage, actual: &RgbaImage, threshold: f64) -> RgbaImage {
+    let max_delta = threshold * threshold * MAX_YIQ_DELTA;
+    RgbaImage::from_fn(expected.width(), expected.height(), |x, y| {
+        let a = expected.get_pixel(x, y);
+        if x < actual.width() && y < actual.height() && color_delta(a, actual.get_pixel(x, y)) <= max_delta {
+            let luma = (0.299 * f64::from(a[0]) + 0.587 * f64::from(a[1]) + 0.114 * f64::from(a[2])) as u8;
+            let faded = 255 - (255 - luma) / 4;
+            Rgba([faded, faded, faded, 255])
+        } else {
+            Rgba([255, 0, 0, 255])
+        }
+    })
+}
+
+/// squared YIQ distance between two colors, as used by pixelmatch
+fn color_delta(a: &Rgba<u8>, b: &Rgba<u8>) -> f64 {
+    let (r1, g1, b1) = (f64::from(a[0]), f64::from(a[1]), f64::from(a[2]));
+    let (r2, g2, b2) = (f64::from(b[0]), f64::from(b[1]), f64::from(b[2]));
+    let y = (r1 - r2) * 0.298_895_31 + (g1 - g2) * 0.586_622_47 + (b1 - b2) * 0.114_482_23;
+    let i = (r1 - r2) * 0.595_977_99 - (g1 - g2) * 0.274_176_10 - (b1 - b2) * 0.321_801_89;
+    let q = (r1 - r2) * 0.211_470_17 - (g1 - g2) * 0.522_617_11 + (b1 - b2) * 0.311_146_94;
+    0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q
+}
@@ -0,0 +1,25 @@
+use image::{Rgba, RgbaImage};
+
+use crate::golden::{compare_images, diff_image, ImageDiff};
+
+#[test]
+fn can_compare_images() {
+    let expected = RgbaImage::from_pixel(4, 2, Rgba([0, 0, 170, 255]));
+    let mut actual = expected.clone();
+    assert_eq!(ImageDiff::Pixels(0), compare_images(&expected, &actual, 0.1));
+
+    // a slightly different shade is below the threshold
+    actual.put_pixel(0, 0, Rgba([0, 0, 172, 255]));
+    actual.put_pixel(3, 1, Rgba([255, 255, 85, 255]));
+    assert_eq!(ImageDiff::Pixels(1), compare_images(&expected, &actual, 0.1));
+    assert_eq!(ImageDiff::Pixels(2), compare_images(&expected, &actual, 0.0));
+    assert!(!compare_images(&expected, &actual, 0.1).exceeds(1));
+    assert!(compare_images(&expected, &actual, 0.0).exceeds(1));
+
+    let diff = diff_image(&expected, &actual, 0.1);
+    assert_eq!(&Rgba([255, 0, 0, 255]), diff.get_pixel(3, 1));
+    assert_ne!(&Rgba([255, 0, 0, 255]), diff.get_pixel(0, 0));
+
+    let smaller = RgbaImage::new(2, 2);
+    assert_eq!(ImageDiff::SizeMismatch { expected: (4, 2), actual: (2, 2) }, compare_images(&expected, &smaller, 0.1));
+}
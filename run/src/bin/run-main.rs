@@ -0,0 +1,195 @@
+use std::fs;
+use std::process;
+
+#[macro_use]
+extern crate clap;
+use clap::{Arg, App};
+
+use dustbox::cpu::{CPU, R};
+use dustbox::machine::{Machine, MachineConfig};
+use dustbox::memory::MemoryAddress;
+use dustbox::string::parse_number_string;
+use dustbox::trace::parse_address;
+
+/// process exit codes, besides the DOS return code of the program
+const EXIT_STOP_AT: i32 = 0;
+const EXIT_MAX_INSTRUCTIONS: i32 = 124;
+const EXIT_CPU_ERROR: i32 = 125;
+const EXIT_RUNNER_ERROR: i32 = 126;
+
+/// why the program stopped running
+enum Stop {
+    Terminated(u8),
+    Address(u16, u16),
+    MaxInstructions,
+    CpuError,
+}
+
+/// a --dump-mem argument
+struct MemoryDump {
+    address: u32,
+    length: usize,
+    filename: String,
+}
+
+impl MemoryDump {
+    /// parses "seg:off:len=file"
+    fn parse(s: &str) -> Result<Self, String> {
+        let (spec, filename) = match s.find('=') {
+            Some(pos) => (&s[..pos], &s[pos + 1..]),
+            None => return Err(format!("invalid memory dump {}, expected seg:off:len=file", s)),
+        };
+        let (address, length) = match spec.rfind(':') {
+            Some(pos) => (&spec[..pos], &spec[pos + 1..]),
+            None => return Err(format!("invalid memory dump {}, expected seg:off:len=file", s)),
+        };
+        Ok(MemoryDump {
+            address: parse_address(address)?,
+            length: parse_number_string(length).map_err(|e| format!("{}: {}", length, e))? as usize,
+            filename: filename.to_owned(),
+        })
+    }
+}
+
+fn main() {
+    let matches = App::new("dustbox-run")
+        .version("0.1")
+        .about("Runs a DOS program headlessly")
+        .arg(Arg::with_name("INPUT")
+            .help("Sets the program to run")
+            .required(true)
+            .index(1))
+        .arg(Arg::with_name("CONFIG")
+            .help("Reads the machine configuration from a toml file")
+            .takes_value(true)
+            .long("config"))
+        .arg(Arg::with_name("MAXINSTRUCTIONS")
+            .help("Stops after this many instructions (default 10000000)")
+            .takes_value(true)
+            .long("max-instructions"))
+        .arg(Arg::with_name("STOPAT")
+            .help("Stops before executing the instruction at address, such as 085F:0150")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .long("stop-at"))
+        .arg(Arg::with_name("DUMPREGS")
+            .help("Prints the registers when stopped")
+            .long("dump-regs"))
+        .arg(Arg::with_name("DUMPMEM")
+            .help("Writes memory to a file when stopped, such as B800:0000:4000=screen.bin")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .long("dump-mem"))
+        .arg(Arg::with_name("SCREENSHOT")
+            .help("Saves the video frame to a png file when stopped")
+            .takes_value(true)
+            .long("screenshot"))
+        .get_matches();
+
+    let max_instructions = if matches.is_present("MAXINSTRUCTIONS") {
+        value_t!(matches, "MAXINSTRUCTIONS", usize).unwrap_or_else(|e| e.exit())
+    } else {
+        10_000_000
+    };
+    let stop_at: Vec<u32> = matches.values_of("STOPAT").map_or_else(Vec::new, |values| values
+        .map(|s| parse_address(s).unwrap_or_else(|e| fail(&e)))
+        .collect());
+    let dumps: Vec<MemoryDump> = matches.values_of("DUMPMEM").map_or_else(Vec::new, |values| values
+        .map(|s| MemoryDump::parse(s).unwrap_or_else(|e| fail(&e)))
+        .collect());
+
+    let config = match matches.value_of("CONFIG") {
+        Some(name) => MachineConfig::from_file(name).unwrap_or_else(|e| fail(&format!("error reading {}: {}", name, e))),
+        None => MachineConfig::default(),
+    };
+    let mut machine = Machine::with_config(config);
+    let filename = matches.value_of("INPUT").unwrap();
+    if let Some(e) = machine.load_executable_file(filename) {
+        fail(&format!("error loading {}: {}", filename, e));
+    }
+
+    let (stop, executed) = run(&mut machine, max_instructions, &stop_at);
+
+    if matches.is_present("DUMPREGS") {
+        print_registers(&machine.cpu);
+    }
+    let mut output_error = false;
+    for dump in &dumps {
+        if let Err(e) = fs::write(&dump.filename, machine.mmu.memory.read(dump.address, dump.length)) {
+            println!("error writing {}: {}", dump.filename, e);
+            output_error = true;
+        }
+    }
+    if let Some(filename) = matches.value_of("SCREENSHOT") {
+        if let Err(e) = machine.screenshot().save(filename) {
+            println!("error writing {}: {}", filename, e);
+            output_error = true;
+        }
+    }
+
+    let code = match stop {
+        Stop::Terminated(code) => {
+            println!("program terminated with return code {} after {} instructions", code, executed);
+            i32::from(code)
+        }
+        Stop::Address(cs, ip) => {
+            println!("stopped at {:04X}:{:04X} after {} instructions", cs, ip, executed);
+            EXIT_STOP_AT
+        }
+        Stop::MaxInstructions => {
+            println!("stopped after {} instructions", executed);
+            EXIT_MAX_INSTRUCTIONS
+        }
+        Stop::CpuError => {
+            println!("cpu error at {:04X}:{:04X} after {} instructions", machine.cpu.get_r16(R::CS), machine.cpu.regs.ip, executed);
+            EXIT_CPU_ERROR
+        }
+    };
+    process::exit(if output_error { EXIT_RUNNER_ERROR } else { code });
+}
+
+fn fail(msg: &str) -> ! {
+    println!("{}", msg);
+    process::exit(EXIT_RUNNER_ERROR);
+}
+
+/// runs the program until it stops, returns the reason and the number of executed instructions
+fn run(machine: &mut Machine, max_instructions: usize, stop_at: &[u32]) -> (Stop, usize) {
+    let mut executed = 0;
+    loop {
+        if let Some(code) = machine.exit_code() {
+            return (Stop::Terminated(code), executed);
+        }
+        if machine.cpu.fatal_error {
+            return (Stop::CpuError, executed);
+        }
+        let (cs, ip) = (machine.cpu.get_r16(R::CS), machine.cpu.regs.ip);
+        if stop_at.contains(&MemoryAddress::RealSegmentOffset(cs, ip).value()) {
+            return (Stop::Address(cs, ip), executed);
+        }
+        if executed >= max_instructions {
+            return (Stop::MaxInstructions, executed);
+        }
+        machine.execute_instruction();
+        executed += 1;
+    }
+}
+
+/// prints the registers in the format of MS-DOS DEBUG
+fn print_registers(cpu: &CPU) {
+    let r16 = |regs: &[R]| regs.iter()
+        .map(|r| format!("{}={:04X}", format!("{}", r).to_uppercase(), cpu.get_r16(*r)))
+        .collect::<Vec<String>>()
+        .join("  ");
+    let flags = &cpu.regs.flags;
+    let flag = |set: bool, on: &'static str, off: &'static str| if set { on } else { off };
+    println!("{}", r16(&[R::AX, R::BX, R::CX, R::DX, R::SP, R::BP, R::SI, R::DI]));
+    println!("{}  IP={:04X}   {} {} {} {} {} {} {} {}",
+        r16(&[R::DS, R::ES, R::SS, R::CS]), cpu.regs.ip,
+        flag(flags.overflow(), "OV", "NV"), flag(flags.direction, "DN", "UP"),
+        flag(flags.interrupt, "EI", "DI"), flag(flags.sign(), "NG", "PL"),
+        flag(flags.zero(), "ZR", "NZ"), flag(flags.adjust(), "AC", "NA"),
+        flag(flags.parity(), "PE", "PO"), flag(flags.carry(), "CY", "NC"));
+}
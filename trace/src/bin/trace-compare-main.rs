@@ -0,0 +1,68 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+#[macro_use]
+extern crate clap;
+use clap::{Arg, App};
+
+use dustbox::machine::Machine;
+use dustbox::tools::read_binary;
+use dustbox::trace_compare::{compare_with_log, DosboxLogLine};
+
+fn main() {
+    let matches = App::new("dustbox-trace-compare")
+            .version("0.1")
+            .arg(Arg::with_name("INPUT")
+                .help("Sets the program to run")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("LOG")
+                .help("Sets the dosbox-x LOGS file to compare with")
+                .required(true)
+                .index(2))
+            .arg(Arg::with_name("psp")
+                .long("psp")
+                .takes_value(true)
+                .help("PSP segment of the program in the log, in hex"))
+            .arg(Arg::with_name("context")
+                .long("context")
+                .takes_value(true)
+                .help("Number of matching instructions to show before the divergence (default 10)"))
+            .get_matches();
+
+    let filename = matches.value_of("INPUT").unwrap();
+    let logname = matches.value_of("LOG").unwrap();
+    let context = value_t!(matches, "context", usize).unwrap_or(10);
+
+    let data = match read_binary(filename) {
+        Ok(data) => data,
+        Err(e) => panic!("failed to read {}: {}", filename, e),
+    };
+    let open_log = || match File::open(logname) {
+        Ok(file) => BufReader::new(file),
+        Err(e) => panic!("failed to open {}: {}", logname, e),
+    };
+
+    let psp = match matches.value_of("psp") {
+        Some(s) => u16::from_str_radix(s.trim_start_matches("0x"), 16).unwrap_or_else(|e| panic!("invalid psp {}: {}", s, e)),
+        None => {
+            // a .COM starts with CS at the PSP
+            match open_log().lines().map_while(Result::ok).find_map(|l| DosboxLogLine::parse(&l)) {
+                Some(line) => line.cs,
+                None => panic!("no instructions found in {}", logname),
+            }
+        }
+    };
+
+    let mut machine = Machine::deterministic();
+    machine.load_executable(&data, psp);
+
+    match compare_with_log(&mut machine, open_log(), context) {
+        Ok(Some(divergence)) => {
+            print!("{}", divergence);
+            std::process::exit(1);
+        }
+        Ok(None) => println!("no divergence found in {}", logname),
+        Err(e) => panic!("failed to read {}: {}", logname, e),
+    }
+}
@@ -0,0 +1,54 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+
+use clap::{Arg, App};
+
+use dustbox::cpu::Decoder;
+use dustbox::memory::MMU;
+use dustbox::trace::{default_registers, parse_registers, TraceRecord, BINARY_TRACE_MAGIC};
+
+fn main() {
+    let matches = App::new("dustbox-trace-convert")
+            .version("0.1")
+            .arg(Arg::with_name("INPUT")
+                .help("Sets the binary trace to convert")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("registers")
+                .long("registers")
+                .takes_value(true)
+                .help("Comma separated list of registers to show, such as ax,bx,ds"))
+            .get_matches();
+
+    let filename = matches.value_of("INPUT").unwrap();
+    let registers = match matches.value_of("registers") {
+        Some(s) => match parse_registers(s) {
+            Ok(registers) => registers,
+            Err(e) => panic!("{}", e),
+        },
+        None => default_registers(),
+    };
+
+    let mut reader = match File::open(filename) {
+        Ok(file) => BufReader::new(file),
+        Err(e) => panic!("failed to open {}: {}", filename, e),
+    };
+    let mut magic = [0u8; 8];
+    if reader.read_exact(&mut magic).is_err() || &magic != BINARY_TRACE_MAGIC {
+        panic!("{} is not a binary trace", filename);
+    }
+
+    // instructions are decoded from their recorded bytes
+    let mut mmu = MMU::default();
+    let mut decoder = Decoder::default();
+    loop {
+        let record = match TraceRecord::read_from(&mut reader) {
+            Ok(Some(record)) => record,
+            Ok(None) => break,
+            Err(e) => panic!("failed to read {}: {}", filename, e),
+        };
+        mmu.write(record.cs(), record.ip, &record.bytes);
+        let op = decoder.get_instruction(&mut mmu, record.cs(), record.ip);
+        println!("{}", record.text_line(&format!("{}", op), &registers));
+    }
+}
@@ -0,0 +1,167 @@
+//! WebAssembly bindings, exported as plain C functions so the module can be used
+//! from javascript without generated glue code. See www/index.html for an example page.
+
+use std::cell::RefCell;
+
+use dustbox::gpu::VideoFrame;
+use dustbox::keyboard::{Keycode, Modifiers};
+use dustbox::machine::Machine;
+
+/// segment where the program is loaded, as by the frontend
+const PSP_SEGMENT: u16 = 0x0329;
+
+struct Emulator {
+    machine: Machine,
+
+    /// last rendered frame, only the changed scanlines are rendered by `tick`
+    frame: VideoFrame,
+}
+
+thread_local! {
+    static EMULATOR: RefCell<Option<Emulator>> = const { RefCell::new(None) };
+    static UPLOAD: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+fn with_emulator<T>(default: T, f: impl FnOnce(&mut Emulator) -> T) -> T {
+    EMULATOR.with(|emulator| match emulator.borrow_mut().as_mut() {
+        Some(emulator) => f(emulator),
+        None => default,
+    })
+}
+
+/// returns a buffer of `len` bytes where the host writes the program before calling `load`
+#[no_mangle]
+pub extern "C" fn alloc(len: usize) -> *mut u8 {
+    UPLOAD.with(|upload| {
+        let mut upload = upload.borrow_mut();
+        upload.clear();
+        upload.resize(len, 0);
+        upload.as_mut_ptr()
+    })
+}
+
+/// starts a new machine running the .com or .exe program in the `alloc` buffer
+#[no_mangle]
+pub extern "C" fn load() {
+    // deterministic, as there is no clock to read the local time from
+    let mut machine = Machine::deterministic();
+    UPLOAD.with(|upload| machine.load_executable(&upload.borrow(), PSP_SEGMENT));
+    let frame = VideoFrame::new(&machine.gpu().mode);
+    let emulator = Emulator { machine, frame };
+    EMULATOR.with(|e| *e.borrow_mut() = Some(emulator));
+    render();
+}
+
+/// runs the machine for one video frame (1/60th second) and renders the frame.
+/// returns false when the program has stopped
+#[no_mangle]
+pub extern "C" fn tick() -> bool {
+    let running = with_emulator(false, |emulator| {
+        if emulator.machine.cpu.fatal_error {
+            return false;
+        }
+        emulator.machine.execute_frame();
+        !emulator.machine.cpu.fatal_error
+    });
+    render();
+    running
+}
+
+fn render() {
+    with_emulator((), |emulator| {
+        emulator.machine.update_frame(&mut emulator.frame);
+    });
+}
+
+/// returns the RGBA pixels of the frame rendered by the last `tick`
+#[no_mangle]
+pub extern "C" fn framebuffer() -> *const u8 {
+    with_emulator(std::ptr::null(), |emulator| emulator.frame.data.as_ptr())
+}
+
+#[no_mangle]
+pub extern "C" fn framebuffer_width() -> u32 {
+    with_emulator(0, |emulator| if emulator.frame.data.is_empty() { 0 } else { emulator.frame.width })
+}
+
+#[no_mangle]
+pub extern "C" fn framebuffer_height() -> u32 {
+    with_emulator(0, |emulator| if emulator.frame.data.is_empty() { 0 } else { emulator.frame.height })
+}
+
+/// injects a key press, `key_code` is the KeyboardEvent.keyCode of the browser.
+/// returns false if the key is not mapped
+#[no_mangle]
+pub extern "C" fn key_down(key_code: u32, shift: bool, ctrl: bool, alt: bool) -> bool {
+    let keycode = match map_key_code(key_code) {
+        Some(keycode) => keycode,
+        None => return false,
+    };
+    with_emulator(false, |emulator| {
+        emulator.machine.keyboard_mut().add_keypress(keycode, Modifiers { shift, ctrl, alt });
+        true
+    })
+}
+
+const DIGITS: [Keycode; 10] = [
+    Keycode::Num0, Keycode::Num1, Keycode::Num2, Keycode::Num3, Keycode::Num4,
+    Keycode::Num5, Keycode::Num6, Keycode::Num7, Keycode::Num8, Keycode::Num9,
+];
+
+const LETTERS: [Keycode; 26] = [
+    Keycode::A, Keycode::B, Keycode::C, Keycode::D, Keycode::E, Keycode::F, Keycode::G,
+    Keycode::H, Keycode::I, Keycode::J, Keycode::K, Keycode::L, Keycode::M, Keycode::N,
+    Keycode::O, Keycode::P, Keycode::Q, Keycode::R, Keycode::S, Keycode::T, Keycode::U,
+    Keycode::V, Keycode::W, Keycode::X, Keycode::Y, Keycode::Z,
+];
+
+const FUNCTION_KEYS: [Keycode; 12] = [
+    Keycode::F1, Keycode::F2, Keycode::F3, Keycode::F4, Keycode::F5, Keycode::F6,
+    Keycode::F7, Keycode::F8, Keycode::F9, Keycode::F10, Keycode::F11, Keycode::F12,
+];
+
+/// maps a browser key code to a Keycode
+/// https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/keyCode
+pub fn map_key_code(key_code: u32) -> Option<Keycode> {
+    let keycode = match key_code {
+        8 => Keycode::Backspace,
+        9 => Keycode::Tab,
+        12 => Keycode::KpClearEntry,
+        13 => Keycode::Return,
+        16 => Keycode::LShift,
+        17 => Keycode::LCtrl,
+        18 => Keycode::LAlt,
+        27 => Keycode::Escape,
+        32 => Keycode::Space,
+        33 => Keycode::PageUp,
+        34 => Keycode::PageDown,
+        35 => Keycode::End,
+        36 => Keycode::Home,
+        37 => Keycode::Left,
+        38 => Keycode::Up,
+        39 => Keycode::Right,
+        40 => Keycode::Down,
+        45 => Keycode::Insert,
+        46 => Keycode::Delete,
+        48..=57 => DIGITS[(key_code - 48) as usize],
+        59 | 186 => Keycode::Colon,
+        61 | 187 => Keycode::Equals,
+        65..=90 => LETTERS[(key_code - 65) as usize],
+        91 => Keycode::LGui,
+        106 => Keycode::Asterisk,
+        107 => Keycode::KpPlus,
+        109 => Keycode::KpMinus,
+        112..=123 => FUNCTION_KEYS[(key_code - 112) as usize],
+        173 | 189 => Keycode::Minus,
+        188 => Keycode::Comma,
+        190 => Keycode::Period,
+        191 => Keycode::Slash,
+        192 => Keycode::Caret,
+        219 => Keycode::LeftBracket,
+        220 => Keycode::Backslash,
+        221 => Keycode::RightBracket,
+        222 => Keycode::Quote,
+        _ => return None,
+    };
+    Some(keycode)
+}